@@ -0,0 +1,270 @@
+/*
+ * Bakes the diffuse irradiance of an equirectangular radiance map without a surface or a
+ * swapchain: projects the map onto a cube with its mips, uploads it, runs
+ * Renderer::bake_ibl and reads the +X face of the irradiance cube back as a binary PPM.
+ * The renderer is headless and render never gets called, everything goes through
+ * Renderer::flush_gpu_work with validation on. examples/asset_baker.json is the bare
+ * minimum a renderer needs.
+ *
+ * Arguments, all optional: --input=PATH of tightly packed RGBA8 texels with --width=N
+ * and --height=N, a procedural sky otherwise. --output=PATH, irradiance.ppm by default.
+ * --size=N for the side of the irradiance cube.
+ *
+ * There's no GPU mip generation in the renderer, the mips get box filtered here. The
+ * process exits with an error if validation reported any, objects still alive when the
+ * device goes away included.
+ */
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::format::Format;
+use rend_vk::handle::TextureHandle;
+use rend_vk::ibl::IblBakeDesc;
+use rend_vk::renderer::{self, Renderer};
+use rend_vk::texture::MipMap;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Faces of the cube the map gets projected onto
+const SOURCE_SIZE: u32 = 128;
+
+static VALIDATION_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        let message = record.args().to_string();
+        // The debug callback logs the severity first
+        if message.starts_with("ERROR") {
+            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", message);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+fn arg(name: &str) -> Option<String> {
+    let prefix = format!("--{}=", name);
+    std::env::args().find_map(|e| e.strip_prefix(&prefix).map(str::to_owned))
+}
+
+fn number_arg(name: &str, default: u32) -> u32 {
+    arg(name)
+        .map(|e| {
+            e.parse()
+                .unwrap_or_else(|_| panic!("--{} takes a number!", name))
+        })
+        .unwrap_or(default)
+}
+
+// Sky over ground with a sun, bright enough to show up in the irradiance
+fn procedural_sky(width: u32, height: u32) -> Vec<u8> {
+    let mut texels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let v = y as f32 / height as f32;
+            let sun = (x as f32 - width as f32 * 0.25).hypot(y as f32 - height as f32 * 0.2);
+            let rgb = if sun < width as f32 / 64.0 {
+                [255, 250, 230]
+            } else if v < 0.5 {
+                let t = v * 2.0;
+                [
+                    (60.0 + 120.0 * t) as u8,
+                    (110.0 + 100.0 * t) as u8,
+                    (220.0 + 20.0 * t) as u8,
+                ]
+            } else {
+                [90, 70, 50]
+            };
+            texels.extend([rgb[0], rgb[1], rgb[2], 255]);
+        }
+    }
+    texels
+}
+
+// Direction through the texel of the face, +X, -X, +Y, -Y, +Z, -Z with +Y up
+fn cube_direction(face: usize, x: u32, y: u32, size: u32) -> [f32; 3] {
+    let u = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+    let v = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
+
+// Nearest texel of the equirectangular map for every texel of the six faces
+fn project_to_cube(texels: &[u8], width: u32, height: u32, size: u32) -> Vec<[u8; 4]> {
+    let mut faces = Vec::with_capacity((6 * size * size) as usize);
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                let [dx, dy, dz] = cube_direction(face, x, y, size);
+                let longitude = dz.atan2(dx);
+                let latitude = (dy / (dx * dx + dy * dy + dz * dz).sqrt()).asin();
+                let u = longitude / std::f32::consts::TAU + 0.5;
+                let v = 0.5 - latitude / std::f32::consts::PI;
+                let sx = ((u * width as f32) as u32).min(width - 1);
+                let sy = ((v * height as f32) as u32).min(height - 1);
+                let i = ((sy * width + sx) * 4) as usize;
+                faces.push([texels[i], texels[i + 1], texels[i + 2], texels[i + 3]]);
+            }
+        }
+    }
+    faces
+}
+
+// Full chain down to 1x1, every mip holds the six faces one after the other
+fn box_filtered_mips(faces: Vec<[u8; 4]>, size: u32) -> (Vec<MipMap>, Vec<u8>) {
+    let mut mips = Vec::new();
+    let mut bytes = Vec::new();
+    let (mut level, mut side) = (faces, size);
+    loop {
+        let offset = bytes.len() as u32;
+        bytes.extend(level.iter().flatten());
+        mips.push(MipMap {
+            index: mips.len() as u32,
+            width: side,
+            height: side,
+            size: bytes.len() as u32 - offset,
+            offset,
+        });
+        if side == 1 {
+            return (mips, bytes);
+        }
+        let half = side / 2;
+        let texel = |face: u32, x: u32, y: u32| level[(face * side * side + y * side + x) as usize];
+        let mut next = Vec::with_capacity((6 * half * half) as usize);
+        for face in 0..6 {
+            for y in 0..half {
+                for x in 0..half {
+                    let quad = [
+                        texel(face, 2 * x, 2 * y),
+                        texel(face, 2 * x + 1, 2 * y),
+                        texel(face, 2 * x, 2 * y + 1),
+                        texel(face, 2 * x + 1, 2 * y + 1),
+                    ];
+                    next.push(std::array::from_fn(|c| {
+                        (quad.iter().map(|e| e[c] as u32).sum::<u32>() / 4) as u8
+                    }));
+                }
+            }
+        }
+        (level, side) = (next, half);
+    }
+}
+
+fn upload_radiance(
+    renderer: &mut Renderer,
+    texels: Vec<u8>,
+    width: u32,
+    height: u32,
+    size: u32,
+) -> TextureHandle {
+    let faces = project_to_cube(&texels, width, height, size);
+    let (mips, bytes) = box_filtered_mips(faces, size);
+    let texture = renderer.gen_texture_cube(
+        "radiance".to_string(),
+        Format::R8G8B8A8_UNORM,
+        &mips,
+        bytes.len() as u32,
+    );
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), staging.addr as *mut u8, bytes.len()) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    texture
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32 / 1024.0;
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-14),
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Texels of ibl::FORMAT, clamped to 8 bits
+fn write_ppm(path: &str, texels: &[u8], width: u32, height: u32) {
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for texel in texels.chunks_exact(8) {
+        for c in 0..3 {
+            let half = u16::from_le_bytes([texel[2 * c], texel[2 * c + 1]]);
+            ppm.push((half_to_f32(half).clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    std::fs::write(path, ppm).unwrap_or_else(|e| panic!("can't write {}: {}", path, e));
+}
+
+fn main() {
+    let _ = log::set_logger(&LOGGER).map(|_| log::set_max_level(log::LevelFilter::Debug));
+    let (texels, width, height) = match arg("input") {
+        Some(path) => {
+            let width = number_arg("width", 0);
+            let height = number_arg("height", 0);
+            let texels =
+                std::fs::read(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path, e));
+            if width == 0 || height == 0 || texels.len() != (width * height * 4) as usize {
+                panic!("{} isn't --width by --height RGBA8 texels!", path);
+            }
+            (texels, width, height)
+        }
+        None => (procedural_sky(512, 256), 512, 256),
+    };
+    let output = arg("output").unwrap_or_else(|| "irradiance.ppm".to_string());
+    let size = number_arg("size", 32);
+
+    // No surface, no instance extensions for one either
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, true, true, &[]);
+    let mut renderer =
+        Renderer::with_core_headless(core, config, "examples/asset_baker.json", 1, 1);
+
+    let radiance = upload_radiance(&mut renderer, texels, width, height, SOURCE_SIZE);
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    let desc = IblBakeDesc {
+        irradiance_size: size,
+        ..IblBakeDesc::default()
+    };
+    let maps = renderer.bake_ibl(radiance.index, desc);
+    // The flush frame waits for the bake
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert!(maps.is_ready(&renderer));
+    let mut request = renderer.read_texture(maps.irradiance).unwrap();
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    let bytes = request
+        .try_resolve(&renderer)
+        .expect("readback not done after the flush!");
+    write_ppm(&output, &bytes, size, size);
+    for texture in [radiance, maps.specular, maps.irradiance, maps.brdf_lut] {
+        renderer.free_texture(texture).unwrap();
+    }
+    renderer.destroy();
+
+    let errors = VALIDATION_ERRORS.load(Ordering::Relaxed);
+    if errors > 0 {
+        eprintln!("{} validation errors!", errors);
+        std::process::exit(1);
+    }
+    println!("wrote {}", output);
+}
@@ -0,0 +1,183 @@
+/*
+ * Renders a synthetic scene headless with pipeline.json and prints a JSON report of the
+ * measured frames to stdout. Scenes come from rend_vk::bench, the same arguments give
+ * the same workload and the same workloadHash in the report, so reports of two versions
+ * of the crate compare as long as the hashes match. There's no replaying of captured
+ * frames, captures are RenderDoc's and nothing the crate can read back.
+ *
+ * Arguments, all optional: --seed=N --meshes=N --triangles=N --textures=N
+ * --tasks=N (per kind) --warmup=N --frames=N --width=N --height=N --lights
+ * --no-state-cache
+ *
+ * Times are in milliseconds, sceneCreationMs is the time it took to make the meshes and
+ * textures of the scene before the warmup frames. Ring, allocation, descriptor and state command numbers are
+ * per frame. Runs with and without the state cache record the same workload, their
+ * cpuRecordMs compare like the ones of two versions.
+ */
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use ash::{extensions::ext::HeadlessSurface, vk};
+use serde::Serialize;
+
+use rend_vk::bench::{Summary, SyntheticScene, SyntheticSceneParams};
+use rend_vk::config::RendererConfig;
+use rend_vk::events::{RenderEvent, RingBufferSink};
+use rend_vk::render_task::TaskKind;
+use rend_vk::renderer::{self, Renderer};
+
+// Frames rendered after the measured ones so the timings of those reach the sink
+const TRAILING_FRAMES: u32 = 4;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    params: SyntheticSceneParams,
+    workload_hash: String,
+    width: u32,
+    height: u32,
+    warmup_frames: u32,
+    measured_frames: u32,
+    tasks_per_frame: usize,
+    scene_creation_ms: f64,
+    cpu_record_ms: Summary,
+    gpu_stage_ms: BTreeMap<String, Summary>,
+    ring_frame_bytes: Summary,
+    ring_peak_frame_bytes: u64,
+    allocations: Summary,
+    descriptor_flush_bytes: Summary,
+    is_state_cache_enabled: bool,
+    state_commands_requested: Summary,
+    state_commands_emitted: Summary,
+}
+
+fn arg(name: &str, default: u64) -> u64 {
+    let prefix = format!("--{}=", name);
+    std::env::args()
+        .find_map(|e| e.strip_prefix(&prefix).map(str::to_owned))
+        .map(|e| {
+            e.parse()
+                .unwrap_or_else(|_| panic!("--{} takes a number!", name))
+        })
+        .unwrap_or(default)
+}
+
+fn millis(d: std::time::Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let defaults = SyntheticSceneParams::default();
+    let mut kinds = vec![TaskKind::MeshStatic];
+    if std::env::args().any(|e| e == "--lights") {
+        kinds.push(TaskKind::LightDir);
+    }
+    let params = SyntheticSceneParams {
+        seed: arg("seed", defaults.seed),
+        meshes: arg("meshes", defaults.meshes as u64) as u32,
+        triangles_per_mesh: arg("triangles", defaults.triangles_per_mesh as u64) as u32,
+        textures: arg("textures", defaults.textures as u64) as u32,
+        texture_size: defaults.texture_size,
+        tasks_per_kind: arg("tasks", defaults.tasks_per_kind as u64) as u32,
+        kinds,
+    };
+    let warmup_frames = arg("warmup", 60) as u32;
+    let measured_frames = arg("frames", 600) as u32;
+    let width = arg("width", 1280) as u32;
+    let height = arg("height", 720) as u32;
+
+    let extensions = [
+        vk::KhrSurfaceFn::name().as_ptr(),
+        HeadlessSurface::name().as_ptr(),
+    ];
+    let config = RendererConfig {
+        is_state_cache_enabled: !std::env::args().any(|e| e == "--no-state-cache"),
+        ..Default::default()
+    };
+    let is_state_cache_enabled = config.is_state_cache_enabled;
+    let core = renderer::make_render_core(&config, false, true, &extensions);
+    let mut renderer = Renderer::with_core(core, config, "pipeline.json", false, |entry, e| {
+        let info = vk::HeadlessSurfaceCreateInfoEXT::default();
+        unsafe { HeadlessSurface::new(entry, e).create_headless_surface(&info, None) }
+    });
+    renderer.resize(width, height);
+    renderer.set_event_sink(Box::new(RingBufferSink::new(1 << 16)));
+
+    let creation_start = Instant::now();
+    let scene = SyntheticScene::generate(&params, &mut renderer);
+    let scene_creation_ms = millis(creation_start.elapsed());
+    for _ in 0..warmup_frames {
+        scene.queue_frame(&mut renderer);
+        renderer.render();
+    }
+    renderer.drain_events();
+
+    let mut measured = Vec::new();
+    let mut record_ms = Vec::new();
+    let mut ring_bytes = Vec::new();
+    let mut ring_peak = 0;
+    let mut allocations = Vec::new();
+    let mut descriptor_bytes = Vec::new();
+    let mut state_requested = Vec::new();
+    let mut state_emitted = Vec::new();
+    let mut gpu_ms: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    let mut collect_timings = |renderer: &mut Renderer, measured: &[u64]| {
+        for event in renderer.drain_events() {
+            if let RenderEvent::StageExecuted {
+                frame,
+                stage,
+                gpu_time,
+            } = event
+            {
+                if measured.contains(&frame) {
+                    gpu_ms.entry(stage).or_default().push(millis(gpu_time));
+                }
+            }
+        }
+    };
+    for _ in 0..measured_frames {
+        scene.queue_frame(&mut renderer);
+        renderer.render();
+        let stats = renderer.frame_stats();
+        measured.push(stats.frame);
+        record_ms.push(millis(stats.bench.record_time));
+        ring_bytes.push(stats.frame_ring.frame_bytes as f64);
+        ring_peak = ring_peak.max(stats.frame_ring.peak_frame_bytes);
+        allocations.push(stats.bench.allocations as f64);
+        descriptor_bytes.push(stats.bench.descriptor_flush_bytes as f64);
+        state_requested.push(stats.state_commands.requested.iter().sum::<u64>() as f64);
+        state_emitted.push(stats.state_commands.emitted.iter().sum::<u64>() as f64);
+        collect_timings(&mut renderer, &measured);
+    }
+    for _ in 0..TRAILING_FRAMES {
+        renderer.render();
+        collect_timings(&mut renderer, &measured);
+    }
+
+    let report = Report {
+        workload_hash: format!("{:016x}", scene.workload_hash()),
+        tasks_per_frame: scene.task_count(),
+        scene_creation_ms,
+        params,
+        width,
+        height,
+        warmup_frames,
+        measured_frames,
+        cpu_record_ms: Summary::of(&record_ms),
+        gpu_stage_ms: gpu_ms
+            .into_iter()
+            .map(|(stage, samples)| (stage, Summary::of(&samples)))
+            .collect(),
+        ring_frame_bytes: Summary::of(&ring_bytes),
+        ring_peak_frame_bytes: ring_peak,
+        allocations: Summary::of(&allocations),
+        descriptor_flush_bytes: Summary::of(&descriptor_bytes),
+        is_state_cache_enabled,
+        state_commands_requested: Summary::of(&state_requested),
+        state_commands_emitted: Summary::of(&state_emitted),
+    };
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+    scene.free(&mut renderer);
+    renderer.destroy();
+}
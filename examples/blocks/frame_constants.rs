@@ -0,0 +1,23 @@
+/*
+ * Per frame constants of examples/frame_constants.rs, included with #[path] by it and by
+ * tests/shader_block.rs, which checks the checked in GLSL is still what this generates.
+ */
+use glam::{Mat4, UVec2, Vec2};
+
+rend_vk::shader_block! {
+    pub struct FrameConstants {
+        pub view_proj: Mat4,
+        pub resolution: Vec2,
+        pub jitter: Vec2,
+        pub time: f32,
+        // Filled in by the renderer, see per_draw::LAYOUT_VERSION and noise::FrameNoise
+        pub frame_index: u32,
+        pub per_draw_layout_version: u32,
+        pub noise_seed: u32,
+        pub noise_offset: UVec2,
+        // Mat4 aligns the struct to 16 bytes, the GLSL side has no trailing padding
+        pub padding: [u32; 2],
+    }
+}
+
+pub const GLSL_PATH: &str = "shader/generated/frame_constants.glsl";
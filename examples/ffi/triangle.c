@@ -0,0 +1,74 @@
+/*
+ * The triangle example through the C ABI, with GLFW for the window. Build the cdylib with
+ * cargo build --features ffi, then from the root of the repo:
+ *
+ *     cc examples/ffi/triangle.c -Iinclude -Ltarget/debug -lrend_vk -lglfw -o triangle
+ *     LD_LIBRARY_PATH=target/debug ./triangle
+ */
+#define GLFW_INCLUDE_VULKAN
+#include <GLFW/glfw3.h>
+#include <stdio.h>
+
+#include "rend_vk.h"
+
+/* Same values as TaskKind */
+#define TASK_KIND_MESH_STATIC 0
+#define TASK_KIND_FULLSCREEN 17
+
+static VkResult create_surface(VkInstance instance, void *user_data, VkSurfaceKHR *surface) {
+    return glfwCreateWindowSurface(instance, (GLFWwindow *)user_data, NULL, surface);
+}
+
+static int check(RendVkResult result, const char *call) {
+    if (result != REND_VK_RESULT_OK) {
+        fprintf(stderr, "%s failed with %d: %s\n", call, result, rendvk_last_error_message());
+        return 0;
+    }
+    return 1;
+}
+
+int main(void) {
+    if (!glfwInit()) {
+        return 1;
+    }
+    glfwWindowHint(GLFW_CLIENT_API, GLFW_NO_API);
+    GLFWwindow *window = glfwCreateWindow(1280, 720, "rend-vk ffi triangle", NULL, NULL);
+    uint32_t extensions_len = 0;
+    const char **extensions = glfwGetRequiredInstanceExtensions(&extensions_len);
+    RendVkConfig config = {
+        .is_vsync_enabled = true,
+        .is_debug_enabled = false,
+        .is_validation_layer_enabled = false,
+        .instance_extensions = extensions,
+        .instance_extensions_len = extensions_len,
+    };
+    RendVkRenderer *renderer = NULL;
+    if (!check(rendvk_make_renderer(&config, create_surface, window, &renderer),
+               "rendvk_make_renderer")) {
+        return 1;
+    }
+    RendVkTask task = {
+        .mesh = rendvk_test_triangle(),
+        .instance_count = 1,
+        .variant = RENDVK_NO_VARIANT,
+    };
+    int is_running = 1;
+    while (is_running && !glfwWindowShouldClose(window)) {
+        glfwPollEvents();
+        int width, height;
+        glfwGetFramebufferSize(window, &width, &height);
+        if (width == 0 || height == 0) {
+            /* Minimized, nothing to present to */
+            continue;
+        }
+        task.kind = TASK_KIND_MESH_STATIC;
+        is_running = check(rendvk_add_task(renderer, &task), "rendvk_add_task");
+        task.kind = TASK_KIND_FULLSCREEN;
+        is_running = is_running && check(rendvk_add_task(renderer, &task), "rendvk_add_task");
+        is_running = is_running && check(rendvk_render(renderer), "rendvk_render");
+    }
+    check(rendvk_destroy(renderer), "rendvk_destroy");
+    glfwDestroyWindow(window);
+    glfwTerminate();
+    return 0;
+}
@@ -0,0 +1,72 @@
+/*
+ * Per frame constants declared once in Rust with shader_block! and read by
+ * shader/frame_constants.frag through the GLSL struct generated from it. Run with --glsl
+ * to write that struct to shader/generated/frame_constants.glsl, then compile the
+ * shaders again. Renders with the pipeline in examples/frame_constants.json. The jitter
+ * member gets filled in by the renderer from its TAA jitter sequence, the
+ * perDrawLayoutVersion one with the version of the per draw layout and the frame index
+ * and noise ones with the values of the frame, see noise.rs.
+ */
+use std::time::Instant;
+
+use glam::{Mat4, UVec2, Vec2};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::motion::JitterSequence;
+use rend_vk::render_core::RenderCore;
+use rend_vk::renderer::Renderer;
+use rend_vk::shader_block::ShaderBlock;
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+#[path = "blocks/frame_constants.rs"]
+mod frame_constants_block;
+
+use frame_constants_block::{FrameConstants, GLSL_PATH};
+
+fn main() {
+    if std::env::args().any(|e| e == "--glsl") {
+        std::fs::write(GLSL_PATH, FrameConstants::glsl_source()).unwrap();
+        println!("wrote {}", GLSL_PATH);
+        return;
+    }
+    let window_context = WindowContext::new(1280, 720);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, false, false, instance_extensions);
+    let mut renderer = Renderer::with_core(
+        core.clone(),
+        config,
+        "examples/frame_constants.json",
+        true,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    renderer.set_taa_jitter_sequence(Some(JitterSequence::halton_2_3(8)));
+    let start = Instant::now();
+    window_context.event_loop(|| {
+        let size = window_context.window.inner_size();
+        let resolution = Vec2::new(size.width as f32, size.height as f32);
+        renderer.set_frame_constants(&FrameConstants {
+            view_proj: Mat4::IDENTITY,
+            resolution,
+            // Replaced with the one of the jitter sequence
+            jitter: Vec2::ZERO,
+            time: start.elapsed().as_secs_f32(),
+            frame_index: 0,
+            per_draw_layout_version: 0,
+            noise_seed: 0,
+            noise_offset: UVec2::ZERO,
+            padding: [0; 2],
+        });
+        renderer.add_task_to_queue(render_task::RenderTask {
+            bounds: render_task::TaskBounds::None,
+            ..render_task::RenderTask::new(
+                Renderer::TEST_TRIANGLE,
+                render_task::TaskKind::Fullscreen,
+            )
+        });
+        renderer.render();
+    });
+    renderer.destroy();
+    RenderCore::destroy(core);
+}
@@ -0,0 +1,92 @@
+/*
+ * Logs the time from sampling input to the GPU finishing the frame built from it.
+ * Run with --wait to block on Renderer::wait_for_frame_slot before sampling input, and
+ * without it to see the latency of sampling input right after the previous render.
+ *
+ * Run with --stutter to make every 30th frame take longer than a refresh. Where the
+ * device has VK_GOOGLE_display_timing the missed vsync count of the frame stats goes up
+ * with each of them, a few frames late, along with the timing of the late present.
+ */
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use rend_vk::renderer::Renderer;
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+const REPORT_EVERY: u32 = 120;
+const STUTTER_EVERY: u64 = 30;
+
+fn main() {
+    let is_waiting = std::env::args().any(|e| e == "--wait");
+    let is_stuttering = std::env::args().any(|e| e == "--stutter");
+    let window_context = WindowContext::new(1280, 720);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let mut renderer = renderer::make_renderer(
+        true,
+        false,
+        false,
+        instance_extensions,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    let mut input_times = VecDeque::new();
+    let mut total_latency = Duration::ZERO;
+    let mut frames = 0u32;
+    let mut total_frames = 0u64;
+    let mut missed_vsyncs = 0;
+    if is_stuttering && !renderer.capabilities().is_display_timing_enabled {
+        println!("no VK_GOOGLE_display_timing, missed vsyncs can't be counted");
+    }
+    window_context.event_loop(|| {
+        if is_waiting {
+            renderer
+                .wait_for_frame_slot(0, Duration::from_secs(1))
+                .expect("GPU took too long!");
+        }
+        // Stand-in for polling input and building the frame out of it
+        let input_time = Instant::now();
+        renderer.add_task_to_queue(render_task::RenderTask {
+            bounds: render_task::TaskBounds::None,
+            ..render_task::RenderTask::new(
+                Renderer::TEST_TRIANGLE,
+                render_task::TaskKind::Fullscreen,
+            )
+        });
+        total_frames += 1;
+        if is_stuttering && total_frames.is_multiple_of(STUTTER_EVERY) {
+            // Longer than a refresh of any display this runs on
+            let refresh = renderer.refresh_duration().unwrap_or(16_666_667);
+            std::thread::sleep(Duration::from_nanos(refresh * 2));
+        }
+        renderer.render();
+        let stats = renderer.frame_stats();
+        if stats.missed_vsyncs > missed_vsyncs {
+            missed_vsyncs = stats.missed_vsyncs;
+            let late = renderer
+                .present_timing_history()
+                .and_then(|e| e.iter().rev().find(|e| e.is_missed_vsync).copied());
+            println!(
+                "missed vsyncs: {}, last late present {:?}",
+                missed_vsyncs, late
+            );
+        }
+        input_times.push_back(input_time);
+        // Rendering waits for the previous frame, so it's done by now
+        if input_times.len() > 1 {
+            total_latency += input_times.pop_front().unwrap().elapsed();
+            frames += 1;
+        }
+        if frames == REPORT_EVERY {
+            println!(
+                "input to GPU done, {} frame slot wait: {:?} on average",
+                if is_waiting { "with" } else { "without" },
+                total_latency / frames
+            );
+            total_latency = Duration::ZERO;
+            frames = 0;
+        }
+    });
+    renderer.destroy();
+}
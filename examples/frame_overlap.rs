@@ -0,0 +1,100 @@
+/*
+ * Logs the CPU time per frame with a stand-in for building the tasks of a frame.
+ * Without arguments the tasks get built and then rendered, one after the other. Run
+ * with --overlap to build the tasks of the next frame on another thread, sending them
+ * through a TaskSender while the current frame gets submitted.
+ */
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::Renderer;
+use rend_vk::task_sender::TaskSender;
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+const REPORT_EVERY: u32 = 120;
+const BUILD_TIME: Duration = Duration::from_millis(4);
+
+fn build_tasks() -> Vec<RenderTask> {
+    // Busy like culling and sorting a scene would be
+    let start = Instant::now();
+    while start.elapsed() < BUILD_TIME {
+        std::hint::spin_loop();
+    }
+    vec![RenderTask::new(
+        Renderer::TEST_TRIANGLE,
+        TaskKind::Fullscreen,
+    )]
+}
+
+fn spawn_builder(sender: TaskSender) -> (mpsc::Sender<()>, mpsc::Receiver<()>) {
+    let (start_tx, start_rx) = mpsc::channel::<()>();
+    let (done_tx, done_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for _ in start_rx {
+            sender.send_all(build_tasks());
+            if done_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    (start_tx, done_rx)
+}
+
+fn main() {
+    let is_overlapping = std::env::args().any(|e| e == "--overlap");
+    let window_context = WindowContext::new(1280, 720);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let mut renderer = renderer::make_renderer(
+        true,
+        false,
+        false,
+        instance_extensions,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    let (start_build, build_done) = spawn_builder(renderer.task_sender());
+    if is_overlapping {
+        // Tasks of the first frame
+        start_build.send(()).unwrap();
+        build_done.recv().unwrap();
+    }
+    let mut total_time = Duration::ZERO;
+    let mut frames = 0u32;
+    window_context.event_loop(|| {
+        let frame_start = Instant::now();
+        if is_overlapping {
+            let frame = renderer.prepare_frame();
+            // Tasks sent from now on go to the next frame
+            start_build.send(()).unwrap();
+            if let Some(frame) = frame {
+                renderer.submit_frame(frame);
+            }
+            build_done.recv().unwrap();
+        } else {
+            for task in build_tasks() {
+                renderer.add_task_to_queue(task);
+            }
+            renderer.render();
+        }
+        total_time += frame_start.elapsed();
+        frames += 1;
+        if frames == REPORT_EVERY {
+            println!(
+                "CPU frame time {}: {:?} on average",
+                if is_overlapping {
+                    "building while submitting"
+                } else {
+                    "building then rendering"
+                },
+                total_time / frames
+            );
+            total_time = Duration::ZERO;
+            frames = 0;
+        }
+    });
+    drop(start_build);
+    renderer.destroy();
+}
@@ -0,0 +1,234 @@
+/*
+ * Live memory overlay built from Renderer::memory_snapshot. Keeps generating and freeing
+ * meshes and textures of random sizes under a small texture budget, and draws a bar per
+ * buffer allocator with its allocated ranges, one per texture pool with how much of it is
+ * taken, the resident texture bytes against the budget, a cell per texture shaded by its
+ * residency and sized by its memory, and a square per resource waiting to be destroyed.
+ * Bars are fills of the test triangle clipped by per task scissors, see
+ * examples/memdash.json. The geometry only gets rebuilt when the snapshot sequence moves.
+ */
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::format::Format;
+use rend_vk::handle::{MeshHandle, TextureHandle};
+use rend_vk::memory::{AllocatorSnapshot, MemorySnapshot};
+use rend_vk::render_core::RenderCore;
+use rend_vk::render_task::{RenderTask, ScissorRect, TaskKind};
+use rend_vk::renderer::Renderer;
+use rend_vk::texture::{MipMap, Residency};
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 720;
+const MARGIN: i32 = 16;
+const ROW_HEIGHT: i32 = 24;
+const CELL_WIDTH: i32 = 12;
+const MAX_MESHES: usize = 48;
+const MAX_TEXTURES: usize = 32;
+const TEXTURE_BUDGET: u64 = 4 << 20;
+const FRAMES_PER_CHANGE: u32 = 10;
+
+// Gray levels of the fill stage
+const BACKGROUND: f32 = 0.15;
+const USED: f32 = 0.6;
+const OVER_BUDGET: f32 = 1.0;
+
+struct Bar {
+    gray: f32,
+    rect: ScissorRect,
+}
+
+fn bar(gray: f32, x: i32, y: i32, width: u32, height: u32) -> Bar {
+    Bar {
+        gray,
+        rect: ScissorRect {
+            x,
+            y,
+            width,
+            height,
+        },
+    }
+}
+
+fn fill(bar: &Bar) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: bar.gray,
+        scissor: Some(bar.rect),
+        ..RenderTask::new(Renderer::TEST_TRIANGLE, TaskKind::Fullscreen)
+    }
+}
+
+/*
+ * Lays out rows top to bottom, spans of each row scaled from bytes to its width.
+ */
+struct Layout {
+    bars: Vec<Bar>,
+    y: i32,
+}
+
+impl Layout {
+    fn width() -> u32 {
+        WIDTH - 2 * MARGIN as u32
+    }
+
+    fn row(&mut self, total: u64, spans: &[(f32, u64, u64)]) {
+        let scale = |v: u64| (v as f64 / total.max(1) as f64 * Self::width() as f64) as i32;
+        let height = ROW_HEIGHT as u32;
+        self.bars
+            .push(bar(BACKGROUND, MARGIN, self.y, Self::width(), height));
+        for (gray, offset, size) in spans {
+            let x = MARGIN + scale(*offset);
+            // At least a pixel, so small allocations still show up
+            let width = scale(*size).max(1) as u32;
+            self.bars.push(bar(*gray, x, self.y, width, height));
+        }
+        self.y += ROW_HEIGHT + MARGIN / 2;
+    }
+
+    fn allocator(&mut self, allocator: &AllocatorSnapshot) {
+        let used: Vec<_> = allocator
+            .used_ranges()
+            .iter()
+            .map(|e| (USED, e[0], e[1]))
+            .collect();
+        self.row(allocator.size, &used);
+    }
+
+    fn cells(&mut self, cells: impl Iterator<Item = (f32, f32)>) {
+        let mut x = MARGIN;
+        for (gray, fraction) in cells {
+            let height = ((ROW_HEIGHT as f32 * fraction) as u32).max(2);
+            let y = self.y + ROW_HEIGHT - height as i32;
+            self.bars
+                .push(bar(gray, x, y, CELL_WIDTH as u32 - 2, height));
+            x += CELL_WIDTH;
+        }
+        self.y += ROW_HEIGHT + MARGIN / 2;
+    }
+}
+
+fn layout_of(snapshot: &MemorySnapshot) -> Vec<Bar> {
+    let mut layout = Layout {
+        bars: Vec::new(),
+        y: MARGIN,
+    };
+    layout.allocator(&snapshot.general);
+    for allocator in snapshot.descriptor.iter().chain(&snapshot.readback) {
+        layout.allocator(allocator);
+    }
+    for pool in &snapshot.image_pools {
+        layout.row(pool.size, &[(USED, 0, pool.size - pool.available)]);
+    }
+    let budget = snapshot
+        .texture_memory_budget
+        .unwrap_or(snapshot.texture_bytes);
+    let total = budget.max(snapshot.texture_bytes);
+    let mut spans = vec![(USED, 0, snapshot.texture_bytes.min(budget))];
+    if snapshot.texture_bytes > budget {
+        spans.push((OVER_BUDGET, budget, snapshot.texture_bytes - budget));
+    }
+    layout.row(total, &spans);
+    let largest = snapshot.textures.iter().map(|e| e.size).max().unwrap_or(1);
+    layout.cells(snapshot.textures.iter().map(|e| {
+        let gray = match e.residency {
+            Residency::Resident => 0.8,
+            Residency::Uploading => 0.5,
+            Residency::Evicted => 0.25,
+        };
+        (gray, e.size as f32 / largest.max(1) as f32)
+    }));
+    layout.cells((0..snapshot.pending_destroys).map(|_| (OVER_BUDGET, 0.5)));
+    layout.bars
+}
+
+fn gen_random_mesh(renderer: &mut Renderer, rng: &mut impl Rng) -> MeshHandle {
+    let count = rng.gen_range(3..4096) * 3;
+    renderer.gen_mesh(count * 12, count * 12, count * 8, count * 4, count)
+}
+
+fn gen_random_texture(renderer: &mut Renderer, rng: &mut impl Rng) -> TextureHandle {
+    let side = 1 << rng.gen_range(5..10);
+    let size = side * side * 4;
+    let mip_maps = [MipMap {
+        index: 0,
+        width: side,
+        height: side,
+        size,
+        offset: 0,
+    }];
+    let name = format!("memdash_{}", side);
+    let texture = renderer.gen_texture(name, Format::R8G8B8A8_UNORM, &mip_maps, size);
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    texture
+}
+
+fn main() {
+    let window_context = WindowContext::new(WIDTH, HEIGHT);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, false, false, instance_extensions);
+    let mut renderer = Renderer::with_core(
+        core.clone(),
+        config,
+        "examples/memdash.json",
+        false,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    renderer.set_texture_memory_budget(Some(TEXTURE_BUDGET));
+
+    let mut rng = rand::thread_rng();
+    let mut meshes = VecDeque::new();
+    let mut textures = VecDeque::new();
+    let mut frames = 0u32;
+    let mut sequence = 0;
+    let mut bars = Vec::new();
+    window_context.event_loop(|| {
+        frames += 1;
+        if frames.is_multiple_of(FRAMES_PER_CHANGE) {
+            meshes.push_back(gen_random_mesh(&mut renderer, &mut rng));
+            textures.push_back(gen_random_texture(&mut renderer, &mut rng));
+            // Free a random one instead of the oldest, so the allocators fragment
+            if meshes.len() > MAX_MESHES {
+                let mesh = meshes.swap_remove_back(rng.gen_range(0..meshes.len()));
+                renderer.free_mesh(mesh.unwrap()).unwrap();
+            }
+            if textures.len() > MAX_TEXTURES {
+                let texture = textures.pop_front().unwrap();
+                renderer.free_texture(texture).unwrap();
+            }
+        }
+        let snapshot = renderer.memory_snapshot();
+        if snapshot.sequence != sequence {
+            sequence = snapshot.sequence;
+            bars = layout_of(&snapshot);
+            println!(
+                "snapshot {}: general {:.2} fragmented, {} of {} texture bytes, {} pending destroys",
+                sequence,
+                snapshot.general.fragmentation(),
+                snapshot.texture_bytes,
+                TEXTURE_BUDGET,
+                snapshot.pending_destroys
+            );
+        }
+        // Unclipped and first, the present stage copies the ui target within it
+        let mut clear = fill(&bar(0.0, 0, 0, WIDTH, HEIGHT));
+        clear.scissor = None;
+        renderer.add_task_to_queue(clear);
+        for bar in &bars {
+            renderer.add_task_to_queue(fill(bar));
+        }
+        renderer.render();
+    });
+    for mesh in meshes {
+        renderer.free_mesh(mesh).unwrap();
+    }
+    for texture in textures {
+        renderer.free_texture(texture).unwrap();
+    }
+    renderer.destroy();
+    RenderCore::destroy(core);
+}
@@ -0,0 +1,181 @@
+/*
+ * Host cost of per instance data uploaded every frame against data kept in scene slots.
+ * Draws the test triangle for each of 50k objects, a few hundred of which move every
+ * frame, first with every resource inline in the tasks and then with each object's
+ * resources in scene slots, where only the moved objects get their slot updated. Prints
+ * the average time spent queueing and submitting a frame of each every few hundred
+ * frames, run it in release.
+ */
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::handle::SceneSlotId;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{self, Renderer};
+use rend_vk::shader_resource::{
+    bytes_of, Material, MultiResource, ResourceKind, Transform, TransformExtra,
+};
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+const OBJECTS: usize = 50_000;
+const MOVED_PER_FRAME: usize = 500;
+const FRAMES_PER_MODE: u32 = 300;
+
+struct Object {
+    transform: Transform,
+    material: Material,
+    extra: TransformExtra,
+    // Transform, Material and TransformExtra, only while drawing from scene slots
+    slots: Option<[SceneSlotId; 3]>,
+}
+
+fn object(i: usize) -> Object {
+    // A grid of tiny triangles filling the view
+    let side = (OBJECTS as f32).sqrt().ceil() as usize;
+    let x = (i % side) as f32 / side as f32 * 2.0 - 1.0;
+    let y = (i / side) as f32 / side as f32 * 2.0 - 1.0;
+    let mv = Mat4::from_translation(Vec3::new(x, y, 0.0)) * Mat4::from_scale(Vec3::splat(0.005));
+    Object {
+        transform: Transform { mvp: mv, mv },
+        material: Material {
+            shininess: 0.0,
+            scaling: 1.0,
+            diffuse_handle: 0,
+            normal_handle: 0,
+            glow_handle: 0,
+            diffuse_sampler: 0,
+            normal_sampler: 0,
+            glow_sampler: 0,
+            padding: 0,
+        },
+        extra: TransformExtra { prev_mvp: mv },
+        slots: None,
+    }
+}
+
+fn task_of(object: &Object) -> RenderTask {
+    let mut resources = HashMap::new();
+    match object.slots {
+        Some([transform, material, extra]) => {
+            resources.insert(ResourceKind::Transform, MultiResource::SceneSlot(transform));
+            resources.insert(ResourceKind::Material, MultiResource::SceneSlot(material));
+            resources.insert(
+                ResourceKind::TransformExtra,
+                MultiResource::SceneSlot(extra),
+            );
+        }
+        None => {
+            resources.insert(
+                ResourceKind::Transform,
+                MultiResource::Transform(vec![object.transform.clone()]),
+            );
+            resources.insert(
+                ResourceKind::Material,
+                MultiResource::Material(vec![object.material.clone()]),
+            );
+            resources.insert(
+                ResourceKind::TransformExtra,
+                MultiResource::TransformExtra(vec![object.extra.clone()]),
+            );
+        }
+    }
+    RenderTask {
+        resources,
+        is_two_sided: true,
+        ..RenderTask::new(Renderer::TEST_TRIANGLE, TaskKind::MeshStatic)
+    }
+}
+
+fn create_slots(renderer: &mut Renderer, objects: &mut [Object]) {
+    for e in objects {
+        e.slots = Some([
+            renderer.create_scene_slot(bytes_of(std::slice::from_ref(&e.transform))),
+            renderer.create_scene_slot(bytes_of(std::slice::from_ref(&e.material))),
+            renderer.create_scene_slot(bytes_of(std::slice::from_ref(&e.extra))),
+        ]);
+    }
+}
+
+fn free_slots(renderer: &mut Renderer, objects: &mut [Object]) {
+    for e in objects {
+        for slot in e.slots.take().into_iter().flatten() {
+            renderer.free_scene_slot(slot).unwrap();
+        }
+    }
+}
+
+/*
+ * Nudges a window of the objects along, only their slots get rewritten.
+ */
+fn move_objects(renderer: &mut Renderer, objects: &mut [Object], frame: u32) {
+    let start = frame as usize * MOVED_PER_FRAME % OBJECTS;
+    let offset = Mat4::from_translation(Vec3::new(0.0, 0.001, 0.0));
+    for e in objects[start..].iter_mut().take(MOVED_PER_FRAME) {
+        e.extra.prev_mvp = e.transform.mvp;
+        e.transform.mv = offset * e.transform.mv;
+        e.transform.mvp = e.transform.mv;
+        if let Some([transform, _, extra]) = e.slots {
+            let bytes = bytes_of(std::slice::from_ref(&e.transform));
+            renderer.update_scene_slot(transform, bytes).unwrap();
+            let bytes = bytes_of(std::slice::from_ref(&e.extra));
+            renderer.update_scene_slot(extra, bytes).unwrap();
+        }
+    }
+}
+
+fn main() {
+    let window_context = WindowContext::new(1280, 720);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let mut renderer = renderer::make_renderer(
+        false,
+        false,
+        false,
+        instance_extensions,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    let mut objects: Vec<_> = (0..OBJECTS).map(object).collect();
+
+    let mut frames = 0u32;
+    let mut host_time = Duration::ZERO;
+    window_context.event_loop(|| {
+        let start = Instant::now();
+        move_objects(&mut renderer, &mut objects, frames);
+        for e in &objects {
+            renderer.add_task_to_queue(task_of(e));
+        }
+        renderer.add_task_to_queue(RenderTask {
+            kind: TaskKind::Fullscreen,
+            resources: HashMap::new(),
+            ..task_of(&objects[0])
+        });
+        renderer.render();
+        host_time += start.elapsed();
+        // Only host time counts, not how far ahead of the GPU it got
+        renderer
+            .wait_for_frame_slot(0, Duration::from_secs(1))
+            .expect("GPU took too long!");
+        frames += 1;
+        if frames == FRAMES_PER_MODE {
+            let is_slotted = objects[0].slots.is_some();
+            println!(
+                "{}: {:?} per frame",
+                if is_slotted { "scene slots" } else { "inline" },
+                host_time / frames
+            );
+            if is_slotted {
+                free_slots(&mut renderer, &mut objects);
+            } else {
+                create_slots(&mut renderer, &mut objects);
+            }
+            frames = 0;
+            host_time = Duration::ZERO;
+        }
+    });
+    free_slots(&mut renderer, &mut objects);
+    renderer.destroy();
+}
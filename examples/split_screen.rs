@@ -0,0 +1,168 @@
+/*
+ * Local co-op style split screen: the test triangle seen by two cameras, one viewport
+ * each, side by side in the same window. Each camera gets its own task, routed to its
+ * viewport by the mask, and its own Frustum for the stages reading one per pass. Press
+ * 2 to turn the right viewport off and on, escape to quit. With trace logging on, the
+ * GPU time of every stage in each viewport gets logged.
+ */
+use std::collections::HashMap;
+
+use ash::vk;
+use glam::{Mat4, Vec3};
+
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::WindowBuilder,
+};
+
+use rend_vk::render_task::{RenderTask, TaskBounds, TaskKind};
+use rend_vk::renderer::{self, Renderer};
+use rend_vk::shader_resource::{Frustum, MultiResource, ResourceKind, SingleResource, Transform};
+use rend_vk::split_screen::{self, ViewportDesc};
+use rend_vk::surface;
+
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
+
+fn test_triangle_task(kind: TaskKind, viewport_mask: u8) -> RenderTask {
+    RenderTask {
+        mesh: Renderer::TEST_TRIANGLE,
+        instance_count: 1,
+        kind,
+        resources: HashMap::new(),
+        variant: None,
+        alpha_cutoff: 0.0,
+        is_two_sided: true,
+        view_depth: 0.0,
+        object_id: None,
+        bounds: TaskBounds::None,
+        scissor: None,
+        viewport_mask,
+    }
+}
+
+fn frustum_of(region: vk::Rect2D) -> Frustum {
+    let (width, height) = (region.extent.width as f32, region.extent.height as f32);
+    Frustum {
+        width,
+        height,
+        inv_width: 1.0 / width,
+        inv_height: 1.0 / height,
+        near_plane: NEAR,
+        far_plane: FAR,
+    }
+}
+
+// Left and right halves of the window, each with the frustum of its size
+fn viewports_of(width: u32, height: u32) -> Vec<ViewportDesc> {
+    let area = vk::Rect2D {
+        offset: vk::Offset2D::default(),
+        extent: vk::Extent2D { width, height },
+    };
+    split_screen::side_by_side(area, 2)
+        .into_iter()
+        .map(|region| {
+            ViewportDesc::new(region).with_resource(
+                ResourceKind::Frustum,
+                SingleResource::Frustum(frustum_of(region)),
+            )
+        })
+        .collect()
+}
+
+// The triangle as seen by the camera of the viewport, the first one in front of it
+fn camera_task(viewport: usize, aspect: f32) -> RenderTask {
+    let eye = match viewport {
+        0 => Vec3::new(0.0, 0.0, 2.0),
+        _ => Vec3::new(1.5, 1.0, 1.0),
+    };
+    let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_3, aspect, NEAR, FAR);
+    let mv = Mat4::look_at_rh(eye, Vec3::ZERO, Vec3::Y);
+    let mut task = test_triangle_task(TaskKind::MeshStatic, 1 << viewport);
+    task.resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform { mvp: proj * mv, mv }]),
+    );
+    task
+}
+
+fn main() {
+    let mut event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("rend-vk split screen")
+        .with_inner_size(LogicalSize::new(1280.0, 720.0))
+        .build(&event_loop)
+        .unwrap();
+    let instance_extensions = surface::required_extensions(&window).unwrap();
+    let mut renderer =
+        renderer::make_renderer(true, true, true, instance_extensions, |entry, instance| {
+            surface::create_for_window(entry, instance, &window)
+        });
+    // The render area keeps the size the renderer was made with, so do the regions
+    let size = window.inner_size();
+    renderer.set_viewports(&viewports_of(size.width, size.height));
+    let mut is_right_enabled = true;
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Key2),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Its region keeps what the shared stages and clears leave in it
+                is_right_enabled = !is_right_enabled;
+                renderer.set_viewport_enabled(1, is_right_enabled);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => renderer.resize(size.width, size.height),
+            Event::MainEventsCleared => {
+                let size = window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    // Minimized, nothing to present to
+                    return;
+                }
+                let aspects: Vec<_> = renderer
+                    .viewports()
+                    .iter()
+                    .map(|e| e.region.extent.width as f32 / e.region.extent.height as f32)
+                    .collect();
+                for (i, aspect) in aspects.into_iter().enumerate() {
+                    renderer.add_task_to_queue(camera_task(i, aspect));
+                }
+                // Composed in every viewport
+                renderer.add_task_to_queue(test_triangle_task(TaskKind::Fullscreen, u8::MAX));
+                renderer.render();
+            }
+            _ => (),
+        }
+    });
+    renderer.destroy();
+}
@@ -0,0 +1,90 @@
+/*
+ * Smallest complete program: opens a window, renders the built-in test triangle and
+ * keeps the swapchain in step with the window size. Close it or press escape to quit.
+ * A second, translucent triangle gets blended over the lit result in front of it.
+ */
+
+use glam::{Mat4, Vec3};
+
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::WindowBuilder,
+};
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{self, Renderer};
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+use rend_vk::surface;
+
+fn test_triangle_task(kind: TaskKind) -> RenderTask {
+    RenderTask::new(Renderer::TEST_TRIANGLE, kind)
+}
+
+// Needs a Transform, its view depth is what translucent tasks get sorted by
+fn translucent_task(aspect: f32) -> RenderTask {
+    let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_3, aspect, 0.1, 100.0);
+    let mv = Mat4::from_translation(Vec3::new(0.25, 0.25, -1.5));
+    let mut task = test_triangle_task(TaskKind::Translucent);
+    task.resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform { mvp: proj * mv, mv }]),
+    );
+    task
+}
+
+fn main() {
+    let mut event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("rend-vk triangle")
+        .with_inner_size(LogicalSize::new(1280.0, 720.0))
+        .build(&event_loop)
+        .unwrap();
+    let instance_extensions = surface::required_extensions(&window).unwrap();
+    let mut renderer = renderer::make_renderer(
+        true,
+        false,
+        false,
+        instance_extensions,
+        |entry, instance| surface::create_for_window(entry, instance, &window),
+    );
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => renderer.resize(size.width, size.height),
+            Event::MainEventsCleared => {
+                let size = window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    // Minimized, nothing to present to
+                    return;
+                }
+                renderer.add_task_to_queue(test_triangle_task(TaskKind::MeshStatic));
+                renderer
+                    .add_task_to_queue(translucent_task(size.width as f32 / size.height as f32));
+                renderer.add_task_to_queue(test_triangle_task(TaskKind::Fullscreen));
+                renderer.render();
+            }
+            _ => (),
+        }
+    });
+    renderer.destroy();
+}
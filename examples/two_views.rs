@@ -0,0 +1,52 @@
+/*
+ * Two renderers sharing one device: the main window and a small preview window, each
+ * with its own swapchain, pipeline and frame pacing on top of the same RenderCore.
+ */
+
+use winit::{dpi::LogicalSize, window::WindowBuilder};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::render_core::RenderCore;
+use rend_vk::renderer::Renderer;
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+fn fullscreen_task() -> render_task::RenderTask {
+    render_task::RenderTask::new(Renderer::TEST_TRIANGLE, render_task::TaskKind::Fullscreen)
+}
+
+fn main() {
+    let window_context = WindowContext::new(1280, 720);
+    let preview_window = WindowBuilder::new()
+        .with_title("rend-vk preview")
+        .with_inner_size(LogicalSize::new(256.0, 256.0))
+        .build(&window_context.event_loop.borrow())
+        .unwrap();
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, false, false, instance_extensions);
+    let mut main_view = Renderer::with_core(
+        core.clone(),
+        config.clone(),
+        "pipeline.json",
+        true,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    let mut preview_view = Renderer::with_core(
+        core.clone(),
+        config,
+        "pipeline.json",
+        false,
+        |entry, instance| surface::create_for_window(entry, instance, &preview_window),
+    );
+    window_context.event_loop(|| {
+        main_view.add_task_to_queue(fullscreen_task());
+        main_view.render();
+        preview_view.add_task_to_queue(fullscreen_task());
+        preview_view.render();
+    });
+    // Views go first, the core panics otherwise
+    main_view.destroy();
+    preview_view.destroy();
+    RenderCore::destroy(core);
+}
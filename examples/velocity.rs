@@ -0,0 +1,67 @@
+/*
+ * Motion vectors from the previousTransform per draw field. The test triangle slides
+ * back and forth, the stage in examples/velocity.json writes its screen space velocity
+ * into an RG16F target and a fullscreen stage shows it, red and green where it moved.
+ * The still triangle next to it has no object id, so it gets zero velocity.
+ */
+use std::{collections::HashMap, time::Instant};
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::render_core::RenderCore;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::Renderer;
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+const MOVING_OBJECT: u64 = 1;
+
+fn triangle_task(kind: TaskKind, mv: Mat4, proj: Mat4, object_id: Option<u64>) -> RenderTask {
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform { mvp: proj * mv, mv }]),
+    );
+    RenderTask {
+        resources,
+        object_id,
+        ..RenderTask::new(Renderer::TEST_TRIANGLE, kind)
+    }
+}
+
+fn main() {
+    let window_context = WindowContext::new(1280, 720);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, false, false, instance_extensions);
+    let mut renderer = Renderer::with_core(
+        core.clone(),
+        config,
+        "examples/velocity.json",
+        true,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    let start = Instant::now();
+    window_context.event_loop(|| {
+        let size = window_context.window.inner_size();
+        let aspect = size.width as f32 / size.height as f32;
+        let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_3, aspect, 0.1, 100.0);
+        let x = (start.elapsed().as_secs_f32() * 2.0).sin() - 0.6;
+        let moving = Mat4::from_translation(Vec3::new(x, 0.0, -2.0));
+        let still = Mat4::from_translation(Vec3::new(0.6, 0.0, -2.0));
+        renderer.add_task_to_queue(triangle_task(
+            TaskKind::MeshStatic,
+            moving,
+            proj,
+            Some(MOVING_OBJECT),
+        ));
+        renderer.add_task_to_queue(triangle_task(TaskKind::MeshStatic, still, proj, None));
+        // Fullscreen stages ignore the transform
+        renderer.add_task_to_queue(triangle_task(TaskKind::Fullscreen, still, proj, None));
+        renderer.render();
+    });
+    renderer.destroy();
+    RenderCore::destroy(core);
+}
@@ -0,0 +1,148 @@
+/*
+ * Vertex fetch cost of separate attribute buffers against interleaved ones. Draws a dense
+ * grid many times over a few pixels, so the frames are bound by vertex processing, first
+ * from a mesh with separate attributes and then from the same data interleaved. The
+ * stages in examples/vertex_fetch.json read the separate mesh for MeshStatic tasks and
+ * the interleaved one for MeshAnimated tasks. Prints the average frame time of each
+ * every few hundred frames, run it in release.
+ */
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::handle::MeshHandle;
+use rend_vk::mesh_opt::{MeshData, MeshIndices, MeshOptFlags, VertexStream};
+use rend_vk::render_core::RenderCore;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::Renderer;
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+use rend_vk::vertex_layout::VertexLayout;
+use rend_vk::window::WindowContext;
+use rend_vk::*;
+
+// Vertices per side of the grid
+const GRID_SIZE: u32 = 256;
+const INSTANCES: u32 = 64;
+const FRAMES_PER_LAYOUT: u32 = 300;
+
+/*
+ * Interleaved position, normal and tex coord of each vertex, and the indices of the grid.
+ */
+fn grid() -> (Vec<u8>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    for y in 0..GRID_SIZE {
+        for x in 0..GRID_SIZE {
+            let u = x as f32 / (GRID_SIZE - 1) as f32;
+            let v = y as f32 / (GRID_SIZE - 1) as f32;
+            let vertex = [u * 2.0 - 1.0, v * 2.0 - 1.0, 0.0, 0.0, 0.0, 1.0, u, v];
+            vertices.extend(vertex.iter().flat_map(|e| e.to_ne_bytes()));
+        }
+    }
+    let mut indices = Vec::new();
+    for y in 0..GRID_SIZE - 1 {
+        for x in 0..GRID_SIZE - 1 {
+            let i = y * GRID_SIZE + x;
+            indices.extend([
+                i,
+                i + 1,
+                i + GRID_SIZE,
+                i + 1,
+                i + GRID_SIZE + 1,
+                i + GRID_SIZE,
+            ]);
+        }
+    }
+    (vertices, indices)
+}
+
+fn grid_task(mesh: MeshHandle, kind: TaskKind) -> RenderTask {
+    // A handful of pixels, vertices are all that costs anything
+    let mv = Mat4::from_scale(Vec3::splat(0.01));
+    let transforms = (0..INSTANCES).map(|_| Transform { mvp: mv, mv }).collect();
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(transforms),
+    );
+    RenderTask {
+        instance_count: INSTANCES,
+        resources,
+        ..RenderTask::new(mesh, kind)
+    }
+}
+
+fn main() {
+    let window_context = WindowContext::new(1280, 720);
+    let instance_extensions = surface::required_extensions(&window_context.window).unwrap();
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, false, false, instance_extensions);
+    let mut renderer = Renderer::with_core(
+        core.clone(),
+        config,
+        "examples/vertex_fetch.json",
+        false,
+        |entry, instance| surface::create_for_window(entry, instance, &window_context.window),
+    );
+    let (vertices, indices) = grid();
+    let layout = VertexLayout::packed();
+    let stream = |offset: usize| VertexStream {
+        data: &vertices[offset..],
+        stride: layout.stride(),
+    };
+    let (separate, _) = renderer.gen_mesh_with_data(
+        &MeshData {
+            vertex_count: GRID_SIZE * GRID_SIZE,
+            positions: stream(0),
+            normals: Some(stream(12)),
+            tex_coords: Some(stream(24)),
+            indices: MeshIndices::U32(&indices),
+        },
+        MeshOptFlags::default(),
+    );
+    let index_bytes: Vec<u8> = indices.iter().flat_map(|e| e.to_ne_bytes()).collect();
+    let interleaved =
+        renderer.gen_mesh_interleaved(layout, &vertices, &index_bytes, indices.len() as u32);
+
+    let mut frames = 0u32;
+    let mut is_interleaved = false;
+    let mut start = Instant::now();
+    window_context.event_loop(|| {
+        let task = if is_interleaved {
+            grid_task(interleaved, TaskKind::MeshAnimated)
+        } else {
+            grid_task(separate, TaskKind::MeshStatic)
+        };
+        renderer.add_task_to_queue(task);
+        let mut copy = grid_task(Renderer::TEST_TRIANGLE, TaskKind::Fullscreen);
+        copy.instance_count = 1;
+        renderer.add_task_to_queue(copy);
+        renderer.render();
+        // Only GPU time counts, not how far ahead the CPU got
+        renderer
+            .wait_for_frame_slot(0, Duration::from_secs(1))
+            .expect("GPU took too long!");
+        frames += 1;
+        if frames == FRAMES_PER_LAYOUT {
+            println!(
+                "{}: {:?} per frame",
+                if is_interleaved {
+                    "interleaved"
+                } else {
+                    "separate"
+                },
+                start.elapsed() / frames
+            );
+            frames = 0;
+            is_interleaved = !is_interleaved;
+            start = Instant::now();
+        }
+    });
+    renderer.free_mesh(separate).unwrap();
+    renderer.free_mesh(interleaved).unwrap();
+    renderer.destroy();
+    RenderCore::destroy(core);
+}
@@ -0,0 +1,271 @@
+/* C ABI of rend-vk, built with the ffi feature. See src/ffi.rs. */
+
+#ifndef REND_VK_H
+#define REND_VK_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <vulkan/vulkan.h>
+
+#define RENDVK_NO_VARIANT UINT32_MAX
+
+/**
+ *
+ * Outcome of every call. The handle and task errors match StaleHandle and the variants
+ * of TaskRejected.
+ *
+ */
+typedef enum RendVkResult {
+  REND_VK_RESULT_OK = 0,
+  REND_VK_RESULT_NULL_ARGUMENT = 1,
+  REND_VK_RESULT_INVALID_ARGUMENT = 2,
+  REND_VK_RESULT_STALE_HANDLE = 3,
+  REND_VK_RESULT_TASK_KIND_BUDGET = 4,
+  REND_VK_RESULT_TASK_TOTAL_BUDGET = 5,
+  REND_VK_RESULT_TASK_STALE_MESH = 6,
+  REND_VK_RESULT_TASK_MESH_UPLOADING = 7,
+  REND_VK_RESULT_PANIC = 8,
+  REND_VK_RESULT_TASK_MISSING_RESOURCE = 9,
+  REND_VK_RESULT_TASK_VERTEX_LAYOUT = 10,
+  REND_VK_RESULT_TASK_STALE_SCENE_SLOT = 11,
+  REND_VK_RESULT_TASK_SCENE_SLOT_SIZE = 12,
+  REND_VK_RESULT_TASK_VERTEX_FORMAT = 13,
+} RendVkResult;
+
+/**
+ *
+ * Opaque to C, only ever handled through a pointer.
+ *
+ */
+typedef struct RendVkRenderer RendVkRenderer;
+
+typedef struct RendVkConfig {
+  bool is_vsync_enabled;
+  bool is_debug_enabled;
+  bool is_validation_layer_enabled;
+  const char *const *instance_extensions;
+  uint32_t instance_extensions_len;
+} RendVkConfig;
+
+/**
+ *
+ * Creates the surface for the instance and writes it to the last argument, called once
+ * while making the renderer.
+ *
+ */
+typedef VkResult (*RendVkSurfaceCallback)(VkInstance, void*, VkSurfaceKHR*);
+
+/**
+ *
+ * Mesh buffers the caller writes the mesh data into, mapped for the lifetime of the mesh.
+ *
+ */
+typedef struct RendVkMesh {
+  uint64_t handle;
+  void *vertices;
+  void *normals;
+  void *tex_coords;
+  void *indices;
+} RendVkMesh;
+
+typedef struct RendVkMipMap {
+  uint32_t width;
+  uint32_t height;
+  uint32_t size;
+  uint32_t offset;
+} RendVkMipMap;
+
+/**
+ *
+ * Texture with its staging buffer mapped, write the mip maps there and queue it for
+ * uploading. The staging buffer is gone once the upload completes.
+ *
+ */
+typedef struct RendVkTexture {
+  uint64_t handle;
+  void *staging;
+  uint32_t staging_len;
+} RendVkTexture;
+
+/**
+ *
+ * Render task. The resources are instance_count items of each kind set in
+ * resource_bits, lowest bit first, every kind starting at an offset from resources
+ * aligned to its own alignment. resources itself can be at any address. Sizes and
+ * alignments come from rendvk_resource_size_of and rendvk_resource_align_of. Tasks
+ * carry transforms, materials, directional and point lights and transform extras, other
+ * kinds are invalid arguments.
+ *
+ */
+typedef struct RendVkTask {
+  uint32_t kind;
+  uint64_t mesh;
+  uint32_t instance_count;
+  uint32_t variant;
+  float alpha_cutoff;
+  bool is_two_sided;
+  uint32_t resource_bits;
+  const uint8_t *resources;
+  uint32_t resources_len;
+} RendVkTask;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ *
+ * Message of the last call on this thread that didn't return REND_VK_RESULT_OK, null if
+ * it did. Valid until the next call on this thread.
+ *
+ */
+const char *rendvk_last_error_message(void);
+
+/**
+ *
+ * Makes a renderer with the pipeline.json of the working directory and writes it to
+ * out. user_data is passed on to the surface callback.
+ *
+ * # Safety
+ *
+ * config has to be null or point to a RendVkConfig whose instance_extensions holds
+ * instance_extensions_len nul terminated strings. out has to be null or valid for
+ * writing a pointer.
+ *
+ */
+RendVkResult rendvk_make_renderer(const RendVkConfig *config,
+                                  RendVkSurfaceCallback surface_callback,
+                                  void *user_data,
+                                  RendVkRenderer **out);
+
+/**
+ *
+ * Waits for the GPU and frees everything. Null does nothing.
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed. It can't be used after this.
+ *
+ */
+RendVkResult rendvk_destroy(RendVkRenderer *renderer);
+
+/**
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call.
+ *
+ */
+RendVkResult rendvk_render(RendVkRenderer *renderer);
+
+/**
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call.
+ *
+ */
+RendVkResult rendvk_resize(RendVkRenderer *renderer, uint32_t width, uint32_t height);
+
+/**
+ *
+ * Mesh id of the built-in test triangle, drawable without generating any mesh.
+ *
+ */
+uint64_t rendvk_test_triangle(void);
+
+/**
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call. out has to be null or valid
+ * for writing a RendVkMesh.
+ *
+ */
+RendVkResult rendvk_gen_mesh(RendVkRenderer *renderer,
+                             uint32_t vertices_size,
+                             uint32_t normals_size,
+                             uint32_t tex_coords_size,
+                             uint32_t indices_size,
+                             uint32_t count,
+                             RendVkMesh *out);
+
+/**
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call.
+ *
+ */
+RendVkResult rendvk_free_mesh(RendVkRenderer *renderer, uint64_t mesh);
+
+/**
+ *
+ * name can be null. format is the index of the format in the Format enum.
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call. name has to be null or a nul
+ * terminated string, mip_maps has to point to mip_maps_len RendVkMipMap and out has to
+ * be null or valid for writing a RendVkTexture.
+ *
+ */
+RendVkResult rendvk_gen_texture(RendVkRenderer *renderer,
+                                const char *name,
+                                uint32_t format,
+                                const RendVkMipMap *mip_maps,
+                                uint32_t mip_maps_len,
+                                uint32_t staging_size,
+                                RendVkTexture *out);
+
+/**
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call.
+ *
+ */
+RendVkResult rendvk_queue_texture_for_uploading(RendVkRenderer *renderer, uint64_t texture);
+
+/**
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call.
+ *
+ */
+RendVkResult rendvk_free_texture(RendVkRenderer *renderer, uint64_t texture);
+
+uint32_t rendvk_resource_size_of(uint32_t kind);
+
+uint32_t rendvk_resource_align_of(uint32_t kind);
+
+/**
+ *
+ * Queues the task for the next frame, rejected tasks return the TaskRejected codes.
+ *
+ * # Safety
+ *
+ * renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+ * destroyed, not used from another thread during the call. task has to be null or
+ * point to a RendVkTask whose resources holds resources_len bytes, at any alignment.
+ *
+ */
+RendVkResult rendvk_add_task(RendVkRenderer *renderer, const RendVkTask *task);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* REND_VK_H */
@@ -0,0 +1,31 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer StageConstants
+{
+	vec2 spot;
+	float intensity;
+};
+
+INPUTS_BEGIN
+	StageConstants constants;
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+/*
+ * Source of tests/downsample_chain.json, black but for the pixel at the spot, which
+ * gets moved around to see how the chains follow it.
+ */
+void main() {
+	ivec2 pixel = ivec2(gl_FragCoord.xy);
+	bool isSpot = pixel == ivec2(registers.constants.spot);
+	outColor = isSpot ? vec3(registers.constants.intensity) : vec3(0.0);
+}
@@ -0,0 +1,25 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(bloom, SMP_RT, 2D, 0)
+
+// Gets blended additively onto the output.
+layout (constant_id = 0) const float INTENSITY = 0.05;
+
+void main() {
+	outColor = texture(bloom, passTexCoord).xyz * INTENSITY;
+}
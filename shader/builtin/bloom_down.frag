@@ -0,0 +1,42 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(higherMip, SMP_RT, 2D, 0)
+
+void main() {
+	// 13 tap filter, from 4 bilinear 2x2 boxes around the center plus the center one.
+	vec2 texel = 1.0 / vec2(textureSize(higherMip, 0));
+	vec2 tc = passTexCoord;
+	vec3 a = texture(higherMip, tc + texel * vec2(-2, -2)).xyz;
+	vec3 b = texture(higherMip, tc + texel * vec2(0, -2)).xyz;
+	vec3 c = texture(higherMip, tc + texel * vec2(2, -2)).xyz;
+	vec3 d = texture(higherMip, tc + texel * vec2(-2, 0)).xyz;
+	vec3 e = texture(higherMip, tc).xyz;
+	vec3 f = texture(higherMip, tc + texel * vec2(2, 0)).xyz;
+	vec3 g = texture(higherMip, tc + texel * vec2(-2, 2)).xyz;
+	vec3 h = texture(higherMip, tc + texel * vec2(0, 2)).xyz;
+	vec3 i = texture(higherMip, tc + texel * vec2(2, 2)).xyz;
+	vec3 j = texture(higherMip, tc + texel * vec2(-1, -1)).xyz;
+	vec3 k = texture(higherMip, tc + texel * vec2(1, -1)).xyz;
+	vec3 l = texture(higherMip, tc + texel * vec2(-1, 1)).xyz;
+	vec3 m = texture(higherMip, tc + texel * vec2(1, 1)).xyz;
+	vec3 color = e * 0.125;
+	color += (a + c + g + i) * 0.03125;
+	color += (b + d + f + h) * 0.0625;
+	color += (j + k + l + m) * 0.125;
+	outColor = color;
+}
@@ -0,0 +1,36 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(inputColor, SMP_RT, 2D, 0)
+
+// Luminance below this doesn't bloom.
+layout (constant_id = 0) const float THRESHOLD = 1.0;
+
+void main() {
+	// 4 bilinear taps around the center cover the 4x4 texels under this one.
+	vec2 texel = 1.0 / vec2(textureSize(inputColor, 0));
+	vec3 color = texture(inputColor, passTexCoord + texel * vec2(-1, -1)).xyz;
+	color += texture(inputColor, passTexCoord + texel * vec2(1, -1)).xyz;
+	color += texture(inputColor, passTexCoord + texel * vec2(-1, 1)).xyz;
+	color += texture(inputColor, passTexCoord + texel * vec2(1, 1)).xyz;
+	color *= 0.25;
+	// Soft knee so the cut doesn't show.
+	float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+	float knee = clamp(luma - THRESHOLD * 0.5, 0.0, THRESHOLD) / max(THRESHOLD, 0.0001);
+	float weight = max(luma - THRESHOLD, knee * knee * THRESHOLD * 0.5) / max(luma, 0.0001);
+	outColor = color * weight;
+}
@@ -0,0 +1,35 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(lowerMip, SMP_RT, 2D, 0)
+SAMPLING(sameMip, SMP_RT, 2D, 1)
+
+void main() {
+	// 3x3 tent filter over the lower mip, added onto the downsampled mip of the same size.
+	vec2 texel = 1.0 / vec2(textureSize(lowerMip, 0));
+	vec2 tc = passTexCoord;
+	vec3 color = texture(lowerMip, tc).xyz * 4.0;
+	color += texture(lowerMip, tc + texel * vec2(0, -1)).xyz * 2.0;
+	color += texture(lowerMip, tc + texel * vec2(-1, 0)).xyz * 2.0;
+	color += texture(lowerMip, tc + texel * vec2(1, 0)).xyz * 2.0;
+	color += texture(lowerMip, tc + texel * vec2(0, 1)).xyz * 2.0;
+	color += texture(lowerMip, tc + texel * vec2(-1, -1)).xyz;
+	color += texture(lowerMip, tc + texel * vec2(1, -1)).xyz;
+	color += texture(lowerMip, tc + texel * vec2(-1, 1)).xyz;
+	color += texture(lowerMip, tc + texel * vec2(1, 1)).xyz;
+	outColor = color / 16.0 + texture(sameMip, tc).xyz;
+}
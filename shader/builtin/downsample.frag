@@ -0,0 +1,47 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Stage constants of the level, see downsample_chain.rs.
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer StageConstants
+{
+	float maxLuminance;
+};
+
+INPUTS_BEGIN
+	StageConstants constants;
+INPUTS_END
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(higherMip, SMP_RT, 2D, 0)
+
+// Scaled down so its luminance doesn't go over the max, fireflies don't spread as squares.
+vec3 clampLuminance(vec3 color, float maxLuminance) {
+	float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+	return color * min(1.0, maxLuminance / max(luma, 0.0001));
+}
+
+void main() {
+	// Dual filter, the center and the 4 diagonal bilinear taps half a texel away.
+	float maxLuminance = registers.constants.maxLuminance;
+	vec2 halfTexel = 0.5 / vec2(textureSize(higherMip, 0));
+	vec2 tc = passTexCoord;
+	vec3 color = clampLuminance(texture(higherMip, tc).xyz, maxLuminance) * 4.0;
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(-1, -1)).xyz, maxLuminance);
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(1, -1)).xyz, maxLuminance);
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(-1, 1)).xyz, maxLuminance);
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(1, 1)).xyz, maxLuminance);
+	outColor = color * 0.125;
+}
@@ -0,0 +1,60 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Stage constants of the first level, see downsample_chain.rs.
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer StageConstants
+{
+	float threshold;
+	float knee;
+	float maxLuminance;
+};
+
+INPUTS_BEGIN
+	StageConstants constants;
+INPUTS_END
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(higherMip, SMP_RT, 2D, 0)
+
+float luminance(vec3 color) {
+	return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Scaled down so its luminance doesn't go over the max, fireflies don't spread as squares.
+vec3 clampLuminance(vec3 color, float maxLuminance) {
+	return color * min(1.0, maxLuminance / max(luminance(color), 0.0001));
+}
+
+void main() {
+	StageConstants constants = registers.constants;
+	// Dual filter, the center and the 4 diagonal bilinear taps half a texel away.
+	vec2 halfTexel = 0.5 / vec2(textureSize(higherMip, 0));
+	vec2 tc = passTexCoord;
+	float maxLuminance = constants.maxLuminance;
+	vec3 color = clampLuminance(texture(higherMip, tc).xyz, maxLuminance) * 4.0;
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(-1, -1)).xyz, maxLuminance);
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(1, -1)).xyz, maxLuminance);
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(-1, 1)).xyz, maxLuminance);
+	color += clampLuminance(texture(higherMip, tc + halfTexel * vec2(1, 1)).xyz, maxLuminance);
+	color *= 0.125;
+	// Quadratic soft knee around the threshold, so the cut doesn't show.
+	float luma = luminance(color);
+	float knee = max(constants.knee, 0.0001);
+	float soft = clamp(luma - constants.threshold + knee, 0.0, 2.0 * knee);
+	soft = soft * soft / (4.0 * knee);
+	float weight = max(soft, luma - constants.threshold) / max(luma, 0.0001);
+	outColor = color * weight;
+}
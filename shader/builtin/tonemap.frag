@@ -0,0 +1,35 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "../shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+SAMPLING(hdrColor, SMP_RT, 2D, 0)
+
+// 0 is Reinhard, 1 is the ACES fit by Krzysztof Narkowicz.
+layout (constant_id = 0) const int OPERATOR = 1;
+layout (constant_id = 1) const float EXPOSURE = 1.0;
+
+vec3 reinhard(vec3 color) {
+	return color / (color + 1.0);
+}
+
+vec3 aces(vec3 color) {
+	return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+	vec3 color = texture(hdrColor, passTexCoord).xyz * EXPOSURE;
+	outColor = OPERATOR == 0 ? reinhard(color) : aces(color);
+}
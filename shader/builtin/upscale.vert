@@ -0,0 +1,13 @@
+#version 460
+
+/*
+ * Fullscreen triangle of the scaling stage, see src/scaling.rs. The viewport is the
+ * letterboxed rect, so the texture coordinates span the whole source inside it.
+ */
+
+layout (location = 0) out vec2 texCoord;
+
+void main() {
+  texCoord = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+  gl_Position = vec4(texCoord * 2.0 - 1.0, 0.0, 1.0);
+}
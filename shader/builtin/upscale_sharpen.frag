@@ -0,0 +1,64 @@
+#version 460
+
+/*
+ * Sharpening filter of the scaling stage, see src/scaling.rs. Upscales the internal
+ * target with a Catmull-Rom bicubic, then sharpens it with the robust contrast adaptive
+ * sharpening of FSR1 over the source texels around it, which backs off where that
+ * would overshoot the local minimum or maximum.
+ */
+
+layout (set = 0, binding = 0) uniform sampler2D source;
+
+layout (location = 0) in vec2 texCoord;
+
+layout (location = 0) out vec4 outColor;
+
+// In stops, 0 sharpens the most
+const float SHARPNESS = 0.2;
+// Strongest negative lobe RCAS allows, above it ringing shows
+const float MAX_LOBE = 0.1875;
+// Keeps the ratios finite on black and white neighborhoods
+const float EPSILON = 1.0 / 32768.0;
+
+vec3 texel(ivec2 at, ivec2 size) {
+  return texelFetch(source, clamp(at, ivec2(0), size - 1), 0).rgb;
+}
+
+vec3 catmullRom(vec2 uv) {
+  ivec2 size = textureSize(source, 0);
+  vec2 pos = uv * vec2(size) - 0.5;
+  vec2 base = floor(pos);
+  vec2 f = pos - base;
+  vec2 weights[4] = vec2[](
+    f * (-0.5 + f * (1.0 - 0.5 * f)),
+    1.0 + f * f * (-2.5 + 1.5 * f),
+    f * (0.5 + f * (2.0 - 1.5 * f)),
+    f * f * (-0.5 + 0.5 * f)
+  );
+  vec3 sum = vec3(0.0);
+  for (int y = 0; y < 4; ++y) {
+    for (int x = 0; x < 4; ++x) {
+      ivec2 at = ivec2(base) + ivec2(x - 1, y - 1);
+      sum += texel(at, size) * weights[x].x * weights[y].y;
+    }
+  }
+  return sum;
+}
+
+void main() {
+  vec2 texelStep = 1.0 / vec2(textureSize(source, 0));
+  vec3 center = catmullRom(texCoord);
+  vec3 north = textureLod(source, texCoord - vec2(0.0, texelStep.y), 0.0).rgb;
+  vec3 south = textureLod(source, texCoord + vec2(0.0, texelStep.y), 0.0).rgb;
+  vec3 west = textureLod(source, texCoord - vec2(texelStep.x, 0.0), 0.0).rgb;
+  vec3 east = textureLod(source, texCoord + vec2(texelStep.x, 0.0), 0.0).rgb;
+  vec3 ringMin = min(min(north, south), min(west, east));
+  vec3 ringMax = max(max(north, south), max(west, east));
+  // How far the negative lobe can go before the result leaves [0, 1]
+  vec3 hitMin = min(ringMin, center) / (4.0 * max(ringMax, center) + EPSILON);
+  vec3 hitMax = (1.0 - max(ringMax, center)) / (4.0 * min(ringMin, center) - 4.0 - EPSILON);
+  vec3 lobes = max(-hitMin, hitMax);
+  float lobe = max(-MAX_LOBE, min(max(lobes.r, max(lobes.g, lobes.b)), 0.0)) * exp2(-SHARPNESS);
+  vec3 sharpened = (lobe * (north + south + west + east) + center) / (4.0 * lobe + 1.0);
+  outColor = vec4(clamp(sharpened, 0.0, 1.0), 1.0);
+}
@@ -0,0 +1,29 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Nothing but the debug flags, "debugFlags": true on the pass
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer StageConstants
+{
+	uint64_t debugFlags;
+};
+
+INPUTS_BEGIN
+	StageConstants constants;
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec4, 0);
+
+/*
+ * Source of tests/debug_flags.json, the low 32 bits of the stage's debug flags as the
+ * bytes of every pixel.
+ */
+void main() {
+	outColor = unpackUnorm4x8(uint(registers.constants.debugFlags));
+}
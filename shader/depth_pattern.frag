@@ -0,0 +1,18 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+/*
+ * Depth of tests/depth_pyramid.json, hashed from the pixel so no 2x2 block reduces to
+ * the right value by accident.
+ */
+void main() {
+	uvec2 pixel = uvec2(gl_FragCoord.xy);
+	uint hash = (pixel.x * 73856093u) ^ (pixel.y * 19349663u);
+	gl_FragDepth = float(hash % 1021u) / 1021.0;
+}
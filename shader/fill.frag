@@ -0,0 +1,24 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+INPUTS_BEGIN
+	// "perDrawFields": ["alphaCutoff"], the gray level of the fill
+	USING(DRAW, ALPHA_CUTOFF)
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec4, 0);
+
+void main() {
+	outColor = vec4(vec3(READ(DRAW, ALPHA_CUTOFF)), 1.0);
+}
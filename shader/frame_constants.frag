@@ -0,0 +1,35 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+// Written by the frame_constants example, run it with --glsl
+#include "generated/frame_constants.glsl"
+
+FRAME_CONSTANTS_BLOCK(FrameConstants)
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+PASS_DATA_BEGIN
+	USING(PASS, FRAME_CONSTANTS)
+PASS_DATA_END
+
+INPUTS_BEGIN
+	USING(PASS, DATA)
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+void main() {
+	FrameConstants constants = READ(PASS, FRAME_CONSTANTS);
+	// Jitter is in clip space, half of it in uv units
+	vec2 uv = passTexCoord + constants.jitter * 0.5;
+	float pulse = 0.5 + 0.5 * sin(constants.time);
+	outColor = vec3(uv, pulse);
+}
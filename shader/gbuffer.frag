@@ -22,7 +22,9 @@ INPUTS_BEGIN
     UNUSED_INPUT(3)
     USING(INST, MATERIAL)
     UNUSED_INPUT(4)
-    UNUSED_INPUT(5)
+    // "perDrawFields": ["alphaCutoff", "flags"] when ALPHA_TESTED
+    USING(DRAW, ALPHA_CUTOFF)
+    USING(DRAW, FLAGS)
 INPUTS_END
 
 // Output parameters.
@@ -37,6 +39,9 @@ DESCRIPTOR(TEXTURE, DEFAULT, 1)
 SAMPLING(matDiffuse, SMP_TEX, 2D, 0)
 SAMPLING(matNormal, SMP_TEX, 2D, 1)
 
+// Discards texels with diffuse alpha under the cutoff, for foliage and the like.
+layout (constant_id = 0) const bool ALPHA_TESTED = false;
+
 void main() {
 	Material mat = READ(INST, MATERIAL);
 	// Compute flipped Y axis tex coord.
@@ -50,7 +55,12 @@ void main() {
 	// // Get specular map factor.
 	float fspec = txNormal.w;
 	// // Perturbed normal.
-	vec3 pertNormal = perturbNormal(passNormal.xyz, txNormal.xyz, passViewPos, texCoord);
+	vec3 normal = passNormal.xyz;
+	if (ALPHA_TESTED && (READ(DRAW, FLAGS) & DRAW_FLAG_TWO_SIDED) != 0u && !gl_FrontFacing) {
+		// Back faces of two sided geometry face the other way.
+		normal = -normal;
+	}
+	vec3 pertNormal = perturbNormal(normal, txNormal.xyz, passViewPos, texCoord);
 	// Output to gbuffer.
 	outAlbedo = vec4(pow(txDiffuse.xyz, vec3(2.2)), fspec);
 	outNormal = encodeNormal(pertNormal);
@@ -58,4 +68,8 @@ void main() {
 	outMisc.x = storeShininess(shininess);
 	// // Velocity buffer for motion blur
 	outVelocity = ((passPrevProjPos.xy / passPrevProjPos.z) - (passProjPos.xy / passProjPos.z)).xy;
+	// Discard last, the implicit lod fetches and perturbNormal need derivatives.
+	if (ALPHA_TESTED && txDiffuse.w < READ(DRAW, ALPHA_CUTOFF)) {
+		discard;
+	}
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+// Generated from the Rust struct FrameConstants, don't edit
+struct FrameConstants
+{
+  mat4 viewProj;
+  vec2 resolution;
+  vec2 jitter;
+  float time;
+  uint frameIndex;
+  uint perDrawLayoutVersion;
+  uint noiseSeed;
+  uvec2 noiseOffset;
+  uint padding[2];
+};
@@ -0,0 +1,52 @@
+/*
+ * Helpers of the IBL bake compute shaders, see src/ibl.rs. Faces come in the order of
+ * the cube layers, +X, -X, +Y, -Y, +Z, -Z.
+ */
+
+#define PI 3.14159265359
+
+// Same for every pass, the ones not needing a member ignore it
+layout (push_constant) uniform Registers {
+  uint size;
+  uint sampleCount;
+  float roughness;
+};
+
+// Direction through the center of the texel of the face, size texels along each side
+vec3 cubeDirection(uvec3 texel) {
+  vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+  switch (texel.z) {
+    case 0: return normalize(vec3(1.0, -uv.y, -uv.x));
+    case 1: return normalize(vec3(-1.0, -uv.y, uv.x));
+    case 2: return normalize(vec3(uv.x, 1.0, uv.y));
+    case 3: return normalize(vec3(uv.x, -1.0, -uv.y));
+    case 4: return normalize(vec3(uv.x, -uv.y, 1.0));
+    default: return normalize(vec3(-uv.x, -uv.y, -1.0));
+  }
+}
+
+vec2 hammersley(uint i, uint count) {
+  uint bits = bitfieldReverse(i);
+  return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+// Orthonormal basis around the normal, z along it
+mat3 tangentFrame(vec3 n) {
+  vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+  vec3 tangent = normalize(cross(up, n));
+  return mat3(tangent, cross(n, tangent), n);
+}
+
+// GGX distributed half vector around z
+vec3 importanceSampleGgx(vec2 xi, float alpha) {
+  float phi = 2.0 * PI * xi.x;
+  float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+  float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+  return vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+}
+
+float distributionGgx(float nDotH, float alpha) {
+  float a2 = alpha * alpha;
+  float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
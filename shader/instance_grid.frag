@@ -0,0 +1,28 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Width of the grid target of tests/large_resources.json
+#define GRID_WIDTH 64u
+
+INPUTS_BEGIN
+	USING(INST, TRANSFORM)
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec4, 0);
+
+/*
+ * Each pixel reads the transform of its own instance, whichever instance gets drawn, so
+ * the whole per instance buffer ends up in the target. The color is the last column of
+ * the mvp.
+ */
+void main() {
+	uvec2 pixel = uvec2(gl_FragCoord.xy);
+	outColor = registers.transforms.items[pixel.y * GRID_WIDTH + pixel.x].mvp[3];
+}
@@ -0,0 +1,40 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+INPUTS_BEGIN
+    UNUSED_INPUT(0)
+    UNUSED_INPUT(1)
+    UNUSED_INPUT(2)
+    UNUSED_INPUT(3)
+    // "perDrawFields": ["albedoTexture", "alphaCutoff"]
+    USING(DRAW, ALBEDO_TEXTURE)
+    USING(DRAW, ALPHA_CUTOFF)
+INPUTS_END
+
+// Textures
+DESCRIPTOR(SAMPLER, DEFAULT, 0)
+DESCRIPTOR(TEXTURE, DEFAULT, 1)
+SAMPLING(albedo, SMP_TEX, 2D, 0)
+
+/*
+ * Depth only, alpha tested so foliage casts the shadow of its leaves instead of its
+ * quads. Cull mode comes from each task, two sided ones get drawn without culling.
+ */
+void main() {
+	vec2 texCoord = flipTexCoord(passTexCoord);
+	// Regular implicit lod fetch, derivatives are still fine before discarding.
+	float alpha = texture(SAMPLER_FOR(albedo, 2D, READ(DRAW, ALBEDO_TEXTURE), READ(DRAW, ALBEDO_SAMPLER)), texCoord).w;
+	if (alpha < READ(DRAW, ALPHA_CUTOFF)) {
+		discard;
+	}
+}
@@ -0,0 +1,27 @@
+#version 330 core
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+INPUTS_BEGIN
+    USING(ATTR, POSITION)
+    USING(ATTR, NORMAL)
+    USING(ATTR, TEXCOORD)
+    USING(INST, TRANSFORM)
+    // Always last
+    USING(INST, INSTANCE_ID)
+INPUTS_END
+
+// Output parameters.
+ATTR_LOC(0) out vec2 passTexCoord;
+ATTR_LOC(1) flat out int passInstanceId;
+
+void main() {
+    // Instance index. Mandatory first line of main.
+    passInstanceId = READ(INST, INSTANCE_ID);
+    passTexCoord = READ(ATTR, TEXCOORD);
+    // Transform holds the light's mvp for shadow tasks.
+    gl_Position = READ(INST, TRANSFORM).mvp * vec4(READ(ATTR, POSITION), 1.0);
+}
@@ -11,6 +11,8 @@
 #define NUM_INV_PI 0.31830987
 #define NUM_INV_TAU 0.15915494
 #define NUM_SQRT2 1.4142135
+// Bits of the flags per-draw field.
+#define DRAW_FLAG_TWO_SIDED 1u
 /* Various struct definitions. */
 
 struct Frustum
@@ -115,6 +117,20 @@ struct Sky
   vec4 EMTPY;
 };
 
+// Matrix of each view of a multiview stage, indexed by gl_ViewIndex.
+#define MAX_VIEWS 6
+struct ViewMatrices
+{
+  mat4 items[MAX_VIEWS];
+};
+
+// Bounding sphere of a light for cluster binning, in world space.
+struct GpuLight
+{
+  vec3 position;
+  float radius;
+};
+
 /* Basic utility functions.  */
 
 float rand(vec2 co)
@@ -273,6 +289,20 @@ vec2 apiTexCoord ( vec2 texCoord )
 #endif
 }
 
+/* Temporal utility functions. */
+
+// Offsets a projected position by a clip space TAA jitter, like the frame constants one.
+vec4 jitterClip ( vec4 clipPos, vec2 jitter )
+{
+  return vec4(clipPos.xy + jitter * clipPos.w, clipPos.zw);
+}
+
+// Screen space motion between two projected positions, in uv units.
+vec2 velocityOf ( vec4 clipPos, vec4 prevClipPos )
+{
+  return (clipPos.xy / clipPos.w - prevClipPos.xy / prevClipPos.w) * 0.5;
+}
+
 /* Misc. */
 
 // Returns color if any of the 'val' components are NaN.
@@ -125,12 +125,31 @@
 // Per pass data
 #define READ_PASS_FRUSTUM_MACRO frustum
 #define READ_PASS_VIEWRAY_MACRO viewRay
+// Per draw data
+#define READ_DRAW_ALBEDO_TEXTURE_MACRO albedoTexture
+#define READ_DRAW_ALBEDO_SAMPLER_MACRO albedoSampler
+#define READ_DRAW_ALPHA_CUTOFF_MACRO alphaCutoff
+#define READ_DRAW_FLAGS_MACRO drawFlags
+#define READ_DRAW_VIEW_DEPTH_MACRO viewDepth
+#define READ_DRAW_PREVIOUS_TRANSFORM_MACRO previousTransform
+#define READ_DRAW_INTERPOLATED_TRANSFORM_MACRO interpolatedTransform
+#define READ_DRAW_TEXTURE_FEEDBACK_MACRO textureFeedback
+// No feedback buffer in OpenGL
+#define WRITE_TEXTURE_FEEDBACK(TIDX, UV)
 // Per-attribute data
 #define READ_ATTR_POSITION_MACRO inPosition
 #define READ_ATTR_NORMAL_MACRO inNormal
 #define READ_ATTR_COLOR_MACRO inColor
 #define READ_ATTR_TEXCOORD_MACRO inTexCoord
 #define READ_ATTR_JOINT_WEIGHT_MACRO inJointWeight
+// Interleaving is up to the vertex array setup here
+#define READ_IATTR_POSITION_MACRO inPosition
+#define READ_IATTR_NORMAL_MACRO inNormal
+#define READ_IATTR_TEXCOORD_MACRO inTexCoord
+// So is decoding quantized ones
+#define READ_QATTR_POSITION_MACRO inPosition
+#define READ_QATTR_NORMAL_MACRO inNormal
+#define READ_QATTR_TEXCOORD_MACRO inTexCoord
 // Base attribute/instance read macro expansion
 #define READ(TYPE,NAME) READ_##TYPE##_##NAME##_MACRO
 
@@ -149,6 +168,14 @@
 // UBO macro expansions for per-pass data
 #define USING_PASS_FRUSTUM_MACRO layout ( std140, binding = BIND_UBO_FRUSTUM ) uniform UBO_FRUSTUM_NAME {  Frustum frustum; };
 #define USING_PASS_VIEWRAY_MACRO layout ( std140, binding = BIND_UBO_VIEWRAY ) uniform UBO_VIEWRAY_NAME {  ViewRay viewRay; };
+// Plain uniforms for per-draw data
+#define USING_DRAW_ALBEDO_TEXTURE_MACRO uniform uint albedoTexture; uniform uint albedoSampler;
+#define USING_DRAW_ALPHA_CUTOFF_MACRO uniform float alphaCutoff;
+#define USING_DRAW_FLAGS_MACRO uniform uint drawFlags;
+#define USING_DRAW_VIEW_DEPTH_MACRO uniform float viewDepth;
+#define USING_DRAW_PREVIOUS_TRANSFORM_MACRO uniform mat4 previousTransform;
+#define USING_DRAW_INTERPOLATED_TRANSFORM_MACRO uniform mat4 interpolatedTransform;
+#define USING_DRAW_TEXTURE_FEEDBACK_MACRO uniform uint textureFeedback;
 
 // Input attribute macro expansions.
 #define USING_ATTR_POSITION_MACRO layout ( location = ATTRIB_LOC_POSITION ) in vec3 inPosition;
@@ -156,6 +183,9 @@
 #define USING_ATTR_COLOR_MACRO layout ( location = ATTRIB_LOC_COLOR ) in vec3 inColor;
 #define USING_ATTR_TEXCOORD_MACRO layout ( location = ATTRIB_LOC_TEXCOORD ) in vec2 inTexCoord;
 #define USING_ATTR_JOINT_WEIGHT_MACRO layout ( location = ATTRIB_LOC_JOINT_WEIGHT ) in uvec3 inJointWeight;
+#define USING_ATTR_POSITION_F16_MACRO USING_ATTR_POSITION_MACRO
+#define USING_ATTR_NORMAL_SNORM10_MACRO USING_ATTR_NORMAL_MACRO
+#define USING_ATTR_TEXCOORD_UNORM16_MACRO USING_ATTR_TEXCOORD_MACRO
 
 // On GL this is an attribute, but use it as conceptually "per instance" data
 #define READ_INST_INSTANCE_ID_MACRO inInstanceId
@@ -26,6 +26,37 @@ layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer Tex
 {
     vec2 items[];
 };
+// Every attribute of each vertex next to each other, read at the offsets of the layout
+layout(scalar, buffer_reference, buffer_reference_align = 4) readonly buffer Interleaved
+{
+    float items[];
+};
+// Quantized per vertex data, see StreamFormats::QUANTIZED
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer PositionsF16
+{
+    // Two half floats each, the fourth component is always 1
+    uvec2 items[];
+};
+layout(scalar, buffer_reference, buffer_reference_align = 4) readonly buffer NormalsSnorm10
+{
+    uint items[];
+};
+layout(scalar, buffer_reference, buffer_reference_align = 4) readonly buffer TexCoordsUnorm16
+{
+    uint items[];
+};
+
+vec3 decodePositionF16(uvec2 v)
+{
+    return vec3(unpackHalf2x16(v.x), unpackHalf2x16(v.y).x);
+}
+
+vec3 decodeNormalSnorm10(uint v)
+{
+    // Sign extended from the top of the word, -512 reads as -1 like -511
+    ivec3 c = ivec3(int(v << 22), int(v << 12), int(v << 2)) >> 22;
+    return max(vec3(c) / 511.0, -1.0);
+}
 // Per instance data
 layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer Transforms
 {
@@ -47,18 +78,143 @@ layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer Tra
 {
     TransformExtra items[];
 };
+// Simulation states of every object, see Renderer::set_frame_transforms_interpolated
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer SimulationStates
+{
+    // First 3 rows of each matrix, or real and dual parts of each dual quaternion
+    vec4 items[];
+};
+struct SimulationBlend
+{
+    uint baseIndex;
+    float alpha;
+};
+
+#ifdef DUAL_QUATERNION_INTERPOLATION
+vec4 quatMul(vec4 a, vec4 b)
+{
+    return vec4(a.w * b.xyz + b.w * a.xyz + cross(a.xyz, b.xyz), a.w * b.w - dot(a.xyz, b.xyz));
+}
+
+// Normalized linear blend of the dual quaternions at i, as a matrix, see dual_quat.rs
+mat4 interpolatedTransform(SimulationStates previous, SimulationStates current, SimulationBlend blend, int instance)
+{
+    uint i = (blend.baseIndex + uint(instance)) * 2;
+    vec4 a = previous.items[i];
+    vec4 b = current.items[i];
+    // Shortest path, q and -q are the same rotation
+    float s = dot(a, b) < 0.0 ? -1.0 : 1.0;
+    vec4 real = mix(a, s * b, blend.alpha);
+    vec4 dual = mix(previous.items[i + 1], s * current.items[i + 1], blend.alpha);
+    float len = length(real);
+    real /= len;
+    dual /= len;
+    vec3 t = 2.0 * quatMul(dual, vec4(-real.xyz, real.w)).xyz;
+    vec3 q = real.xyz;
+    float w = real.w;
+    mat3 r = mat3(
+        1.0 - 2.0 * (q.y * q.y + q.z * q.z), 2.0 * (q.x * q.y + w * q.z), 2.0 * (q.x * q.z - w * q.y),
+        2.0 * (q.x * q.y - w * q.z), 1.0 - 2.0 * (q.x * q.x + q.z * q.z), 2.0 * (q.y * q.z + w * q.x),
+        2.0 * (q.x * q.z + w * q.y), 2.0 * (q.y * q.z - w * q.x), 1.0 - 2.0 * (q.x * q.x + q.y * q.y));
+    return mat4(vec4(r[0], 0.0), vec4(r[1], 0.0), vec4(r[2], 0.0), vec4(t, 1.0));
+}
+#else
+// Component wise lerp of the matrices at i, see motion::lerp_affine
+mat4 interpolatedTransform(SimulationStates previous, SimulationStates current, SimulationBlend blend, int instance)
+{
+    uint i = (blend.baseIndex + uint(instance)) * 3;
+    vec4 r0 = mix(previous.items[i], current.items[i], blend.alpha);
+    vec4 r1 = mix(previous.items[i + 1], current.items[i + 1], blend.alpha);
+    vec4 r2 = mix(previous.items[i + 2], current.items[i + 2], blend.alpha);
+    return transpose(mat4(r0, r1, r2, vec4(0.0, 0.0, 0.0, 1.0)));
+}
+#endif
+// Smallest footprint sampled of every texture id this frame, see texture_feedback.rs
+layout(scalar, buffer_reference, buffer_reference_align = 4) buffer TextureFeedback
+{
+    uint footprints[];
+};
+// Keeps the footprints of anything short of magnifying 2^32 times positive
+#define TEXTURE_FEEDBACK_BIAS 32.0
+
+// Derivatives only exist in fragment shaders, see cotangentFrame in shared.glsl.frag
+#if IS_FRAGMENT_SHADER
+// log2 of the texels a pixel covers at the coordinates, as if the image was 1x1
+float textureFeedbackFootprint(vec2 uv)
+{
+    vec2 dx = dFdx(uv);
+    vec2 dy = dFdy(uv);
+    return 0.5 * log2(max(max(dot(dx, dx), dot(dy, dy)), 1e-30));
+}
+#endif /* IS_FRAGMENT_SHADER */
+
+void writeTextureFeedback(TextureFeedback feedback, uint id, float footprint)
+{
+    // Renderer without a feedback buffer
+    if (uint64_t(feedback) == 0ul)
+    {
+        return;
+    }
+    atomicMin(feedback.footprints[id], uint(max(floor(footprint) + TEXTURE_FEEDBACK_BIAS, 0.0)));
+}
+// Clustered light lists, see light_cluster.rs
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer ClusterRanges
+{
+    // Offset and count in the light indices
+    uvec2 items[];
+};
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer LightIndices
+{
+    uint items[];
+};
+struct LightClusters
+{
+  ClusterRanges ranges;
+  LightIndices lightIndices;
+  uvec3 dims;
+  uint isExponential;
+  float zScale;
+  float zBias;
+};
+
+uint clusterIndexOf ( LightClusters clusters, vec2 fragCoord, vec2 viewportSize, float viewDepth )
+{
+  float d = clusters.isExponential != 0u ? log(viewDepth) : viewDepth;
+  uint z = uint(clamp(d * clusters.zScale - clusters.zBias, 0.0, float(clusters.dims.z - 1u)));
+  uvec2 xy = min(uvec2(fragCoord / viewportSize * vec2(clusters.dims.xy)), clusters.dims.xy - 1u);
+  return xy.x + clusters.dims.x * (xy.y + clusters.dims.y * z);
+}
+/*
+* Block set with Renderer::set_frame_constants, TYPE is the struct generated
+* from its shader_block! declaration. Declare it before the pass data.
+*/
+#define FRAME_CONSTANTS_BLOCK(TYPE) \
+layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer FrameConstantsRef { TYPE value; };
 // Per pass data
 
 #define DESC_SET_SAMPLER 0
 #define DESC_SET_TEXTURE 1
 #define DESC_SET_ATTACHMENT 2
 
+// Reserved ids of the image table, Renderer::ID_BLUE_NOISE and ID_BLUE_NOISE_DUAL
+#define BLUE_NOISE_TEXTURE 1
+#define BLUE_NOISE_DUAL_TEXTURE 2
+
 // Per vertex attributes
 #define READ_ATTR_POSITION_MACRO registers.positions.items[gl_VertexIndex]
 #define READ_ATTR_NORMAL_MACRO registers.normals.items[gl_VertexIndex]
 #define READ_ATTR_COLOR_MACRO registers.colors.items[gl_VertexIndex]
 #define READ_ATTR_TEXCOORD_MACRO registers.texCoords.items[gl_VertexIndex]
 #define READ_ATTR_JOINT_WEIGHT_MACRO registers.joints.items[gl_VertexIndex]
+// Interleaved vertex attributes, offsets are in bytes within the vertex
+#define INTERLEAVED_AT(OFFSET, I) registers.interleaved.items[(uint(gl_VertexIndex) * registers.vertexStride + registers.OFFSET) / 4 + I]
+#define READ_IATTR_POSITION_MACRO vec3(INTERLEAVED_AT(positionOffset, 0), INTERLEAVED_AT(positionOffset, 1), INTERLEAVED_AT(positionOffset, 2))
+#define READ_IATTR_NORMAL_MACRO vec3(INTERLEAVED_AT(normalOffset, 0), INTERLEAVED_AT(normalOffset, 1), INTERLEAVED_AT(normalOffset, 2))
+#define READ_IATTR_TEXCOORD_MACRO vec2(INTERLEAVED_AT(texCoordOffset, 0), INTERLEAVED_AT(texCoordOffset, 1))
+// Quantized vertex attributes, decoded to what the ATTR ones read
+#define READ_QATTR_POSITION_MACRO decodePositionF16(registers.positions.items[gl_VertexIndex])
+#define READ_QATTR_NORMAL_MACRO decodeNormalSnorm10(registers.normals.items[gl_VertexIndex])
+#define READ_QATTR_TEXCOORD_MACRO unpackUnorm2x16(registers.texCoords.items[gl_VertexIndex])
 // Per instance data
 #define READ_INST_INSTANCE_ID_MACRO gl_InstanceIndex
 #define READ_INST_TRANSFORM_MACRO registers.transforms.items[passInstanceId]
@@ -84,6 +240,23 @@ layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer Tra
 #define READ_PASS_SKY_MACRO registers.pass.sky
 #define READ_PASS_STATIC_SHADOW_MACRO registers.pass.staticShadow
 #define READ_PASS_TRANSFORM_EXTRA_MACRO registers.pass.transformExtra
+#define READ_PASS_LIGHT_CLUSTERS_MACRO registers.pass.lightClusters
+#define READ_PASS_VIEW_MATRICES_MACRO registers.pass.viewMatrices
+#define READ_PASS_FRAME_CONSTANTS_MACRO registers.pass.frameConstants.value
+// Per-draw data
+#define READ_DRAW_ALBEDO_TEXTURE_MACRO registers.albedoTexture
+#define READ_DRAW_ALBEDO_SAMPLER_MACRO registers.albedoSampler
+#define READ_DRAW_ALPHA_CUTOFF_MACRO registers.alphaCutoff
+#define READ_DRAW_FLAGS_MACRO registers.flags
+#define READ_DRAW_VIEW_DEPTH_MACRO registers.viewDepth
+#define READ_DRAW_PREVIOUS_TRANSFORM_MACRO registers.previousTransform.items[passInstanceId].prevMvp
+// Model matrix of the instance in the rendered frame, the task's mvp is the view projection
+#define READ_DRAW_INTERPOLATED_TRANSFORM_MACRO interpolatedTransform(registers.previousSimulation, registers.currentSimulation, registers.simulationBlend, passInstanceId)
+#define READ_DRAW_TEXTURE_FEEDBACK_MACRO registers.textureFeedback
+#if IS_FRAGMENT_SHADER
+// Reports sampling the texture id at the coordinates
+#define WRITE_TEXTURE_FEEDBACK(TIDX, UV) writeTextureFeedback(registers.textureFeedback, TIDX, textureFeedbackFootprint(UV))
+#endif /* IS_FRAGMENT_SHADER */
 // Base attribute/instance read macro expansion
 #define READ(TYPE,NAME) READ_##TYPE##_##NAME##_MACRO
 
@@ -106,6 +279,12 @@ layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer Tra
 #define USING_ATTR_POSITION_MACRO Positions positions;
 #define USING_ATTR_NORMAL_MACRO Normals normals;
 #define USING_ATTR_TEXCOORD_MACRO TexCoords texCoords;
+// In place of the three above for interleaved meshes, same size
+#define USING_ATTR_INTERLEAVED_MACRO Interleaved interleaved; uint vertexStride; uint positionOffset; uint normalOffset; uint texCoordOffset;
+// In place of the separate ones for quantized meshes, read through QATTR
+#define USING_ATTR_POSITION_F16_MACRO PositionsF16 positions;
+#define USING_ATTR_NORMAL_SNORM10_MACRO NormalsSnorm10 normals;
+#define USING_ATTR_TEXCOORD_UNORM16_MACRO TexCoordsUnorm16 texCoords;
 // Per-instance data definitions
 #define USING_INST_TRANSFORM_MACRO Transforms transforms;
 #define USING_INST_MATERIAL_MACRO Materials materials;
@@ -118,6 +297,20 @@ layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer Tra
 #define USING_PASS_FRUSTUM_MACRO Frustum frustum;
 #define USING_PASS_VIEWRAY_MACRO ViewRay viewRay;
 #define USING_PASS_TRANSFORM_EXTRA_MACRO TransformExtra transformExtra;
+#define USING_PASS_LIGHT_CLUSTERS_MACRO LightClusters lightClusters;
+#define USING_PASS_VIEW_MATRICES_MACRO ViewMatrices viewMatrices;
+#define USING_PASS_FRAME_CONSTANTS_MACRO FrameConstantsRef frameConstants;
+// Per-draw data definitions, after all the addresses in the order of perDrawFields
+#define USING_DRAW_ALBEDO_TEXTURE_MACRO uint albedoTexture; uint albedoSampler;
+#define USING_DRAW_ALPHA_CUTOFF_MACRO float alphaCutoff;
+#define USING_DRAW_FLAGS_MACRO uint flags;
+#define USING_DRAW_VIEW_DEPTH_MACRO float viewDepth;
+// An address, list it where the offset is 8 byte aligned
+#define USING_DRAW_PREVIOUS_TRANSFORM_MACRO TransformExtras previousTransform;
+// Two addresses and the blend, list it where the offset is 8 byte aligned
+#define USING_DRAW_INTERPOLATED_TRANSFORM_MACRO SimulationStates previousSimulation; SimulationStates currentSimulation; SimulationBlend simulationBlend;
+// An address, list it where the offset is 8 byte aligned
+#define USING_DRAW_TEXTURE_FEEDBACK_MACRO TextureFeedback textureFeedback;
 // This struct will hold all the per-pass data together
 #define USING_PASS_DATA_MACRO PassData pass;
 // Using pre-defined gl_InstanceIndex in vulkan
@@ -5,6 +5,14 @@
 layout (location = 0) in vec3 inColor;
 layout (location = 0) out vec4 outColor;
 
+/*
+ * Set from the pipeline file, eg, on the pass using this program:
+ *   "specialization": { "0": false },
+ *   "variants": { "swapped": { "0": true } }
+ * Tasks with the "swapped" variant id get drawn with red and blue swapped.
+ */
+layout (constant_id = 0) const bool SWAP_COLORS = false;
+
 void main() {
-    outColor = vec4(inColor, 1 );
+    outColor = vec4(SWAP_COLORS ? inColor.bgr : inColor, 1 );
 }
\ No newline at end of file
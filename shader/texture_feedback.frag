@@ -0,0 +1,43 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Input parameters, the ones gbuffer.vert writes.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) in vec3 passNormal;
+ATTR_LOC(2) in vec3 passViewPos;
+ATTR_LOC(3) in vec3 passProjPos;
+ATTR_LOC(4) in vec3 passPrevProjPos;
+ATTR_LOC(5) flat in int passInstanceId;
+
+INPUTS_BEGIN
+    UNUSED_INPUT(0)
+    UNUSED_INPUT(1)
+    UNUSED_INPUT(2)
+    UNUSED_INPUT(3)
+    USING(INST, MATERIAL)
+    UNUSED_INPUT(4)
+    // "perDrawFields": ["textureFeedback"]
+    USING(DRAW, TEXTURE_FEEDBACK)
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec4, 0);
+
+// Textures
+DESCRIPTOR(SAMPLER, DEFAULT, 0)
+DESCRIPTOR(TEXTURE, DEFAULT, 1)
+SAMPLING(matDiffuse, SMP_TEX, 2D, 0)
+
+// Diffuse of the material, reporting the mip it sampled to the texture feedback buffer.
+void main() {
+	Material mat = READ(INST, MATERIAL);
+	vec2 texCoord = flipTexCoord(passTexCoord) * mat.scaling;
+	outColor = texture(SAMPLER_FOR(matDiffuse, 2D, mat.diffuseId, mat.diffuseSamplerId), texCoord);
+	WRITE_TEXTURE_FEEDBACK(mat.diffuseId, texCoord);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+INPUTS_BEGIN
+	USING(ATTR, POSITION)
+	UNUSED_INPUT(1) // normals
+	UNUSED_INPUT(2) // tex coords
+	USING(INST, TRANSFORM)
+  // Always last
+  USING(INST, INSTANCE_ID)
+INPUTS_END
+
+void main() {
+  // Instance index. Mandatory first line of main.
+  int passInstanceId = READ(INST, INSTANCE_ID);
+  vec3 inPosition = READ(ATTR, POSITION);
+  Transform trns = READ(INST, TRANSFORM);
+  gl_Position = trns.mvp * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,33 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec3 passColor;
+ATTR_LOC(1) flat in int passInstanceId;
+
+INPUTS_BEGIN
+    UNUSED_INPUT(0)
+    UNUSED_INPUT(1)
+    UNUSED_INPUT(2)
+    UNUSED_INPUT(3)
+    // "perDrawFields": ["viewDepth"]
+    USING(DRAW, VIEW_DEPTH)
+INPUTS_END
+
+// Output parameters.
+WRITING(outColor, vec4, 0);
+
+/*
+ * Blended over the lit result with depth testing but no depth writes, tasks come
+ * sorted back to front. Fades out a bit with distance to show off the sort key.
+ */
+void main() {
+	float alpha = mix(0.6, 0.3, clamp(READ(DRAW, VIEW_DEPTH) / 100.0, 0.0, 1.0));
+	outColor = vec4(passColor, alpha);
+}
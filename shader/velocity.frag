@@ -0,0 +1,20 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec4 passProjPos;
+ATTR_LOC(1) in vec4 passPrevProjPos;
+ATTR_LOC(2) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outVelocity, vec2, 0);
+
+void main() {
+	outVelocity = velocityOf(passProjPos, passPrevProjPos);
+}
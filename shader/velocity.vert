@@ -0,0 +1,32 @@
+#version 330 core
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+INPUTS_BEGIN
+    USING(ATTR, POSITION)
+    USING(ATTR, NORMAL)
+    USING(ATTR, TEXCOORD)
+    USING(INST, TRANSFORM)
+    // "perDrawFields": ["previousTransform"]
+    USING(DRAW, PREVIOUS_TRANSFORM)
+    // Always last
+    USING(INST, INSTANCE_ID)
+INPUTS_END
+
+// Output parameters.
+ATTR_LOC(0) out vec4 passProjPos;
+ATTR_LOC(1) out vec4 passPrevProjPos;
+ATTR_LOC(2) flat out int passInstanceId;
+
+void main() {
+    // Instance index. Mandatory first line of main.
+    passInstanceId = READ(INST, INSTANCE_ID);
+    vec4 inPosition = vec4(READ(ATTR, POSITION), 1.0);
+    // Last frame mvp of the object, the current one for tasks without an object id
+    passPrevProjPos = READ(DRAW, PREVIOUS_TRANSFORM) * inPosition;
+    passProjPos = READ(INST, TRANSFORM).mvp * inPosition;
+    gl_Position = passProjPos;
+}
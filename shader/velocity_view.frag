@@ -0,0 +1,28 @@
+#version 330 core
+
+#define IS_FRAGMENT_SHADER 1
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+
+#include "shared_wrapper.glsl.frag"
+
+// Input parameters.
+ATTR_LOC(0) in vec2 passTexCoord;
+ATTR_LOC(1) flat in int passInstanceId;
+
+// Output parameters.
+WRITING(outColor, vec3, 0);
+
+// Textures
+DESCRIPTOR(SAMPLER, DEFAULT, 0)
+SAMPLING(velocity, SMP_RT, 2D, 0)
+
+/*
+ * Direction of the motion as red and green, black where nothing moved. Velocities are
+ * small fractions of the screen, scaled up to be visible.
+ */
+void main() {
+	vec2 v = texture(velocity, passTexCoord).xy;
+	outColor = vec3(saturate(abs(v) * 50.0), 0.0);
+}
@@ -0,0 +1,30 @@
+#version 330 core
+
+#extension GL_GOOGLE_include_directive : enable 
+#extension GL_ARB_shading_language_include : enable 
+#extension GL_EXT_multiview : enable
+
+#include "shared_wrapper.glsl.frag"
+
+PASS_DATA_BEGIN
+	USING(PASS, VIEW_MATRICES)
+PASS_DATA_END
+
+INPUTS_BEGIN
+	USING(PASS, DATA)
+	USING(ATTR, POSITION)
+	UNUSED_INPUT(2) // normals
+	UNUSED_INPUT(3) // tex coords
+	USING(INST, TRANSFORM)
+  // Always last
+  USING(INST, INSTANCE_ID)
+INPUTS_END
+
+void main() {
+  // Instance index. Mandatory first line of main.
+  int passInstanceId = READ(INST, INSTANCE_ID);
+  vec3 inPosition = READ(ATTR, POSITION);
+  Transform trns = READ(INST, TRANSFORM);
+  mat4 view = READ(PASS, VIEW_MATRICES).items[gl_ViewIndex];
+  gl_Position = view * trns.mvp * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,385 @@
+/*
+ * Physical devices as tooling sees them before any device exists, and the choice of
+ * which one a render core gets made on. Enumerating needs no window, presentation is only
+ * checked against a probe surface when one is passed.
+ */
+use std::ffi::CStr;
+
+use ash::{extensions::ext, extensions::khr, vk, Entry};
+use serde::{Deserialize, Serialize};
+
+use crate::config::DescriptorMode;
+use crate::renderer;
+
+///
+/// What a device lacks of what every render core needs, named like the Vulkan features.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MissingFeature {
+    // Below Vulkan 1.3, none of the features after it got checked
+    ApiVersion,
+    GraphicsQueue,
+    // No graphics queue family presents to the probe surface
+    Present,
+    Swapchain,
+    ShaderClipDistance,
+    Multiview,
+    DescriptorIndexing,
+    TimelineSemaphore,
+    BufferDeviceAddress,
+    ScalarBlockLayout,
+    RuntimeDescriptorArray,
+    SampledImageArrayNonUniformIndexing,
+    DynamicRendering,
+    Synchronization2,
+    // Needed by DescriptorMode::DescriptorBuffer
+    DescriptorBuffer,
+    // Needed by DescriptorMode::DescriptorSets, partially bound and update after bind
+    DescriptorSetUpdateAfterBind,
+}
+
+impl std::fmt::Display for MissingFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::ApiVersion => "Vulkan 1.3",
+            Self::GraphicsQueue => "graphics queue",
+            Self::Present => "presentation to the surface",
+            Self::Swapchain => "VK_KHR_swapchain",
+            Self::ShaderClipDistance => "shaderClipDistance",
+            Self::Multiview => "multiview",
+            Self::DescriptorIndexing => "descriptorIndexing",
+            Self::TimelineSemaphore => "timelineSemaphore",
+            Self::BufferDeviceAddress => "bufferDeviceAddress",
+            Self::ScalarBlockLayout => "scalarBlockLayout",
+            Self::RuntimeDescriptorArray => "runtimeDescriptorArray",
+            Self::SampledImageArrayNonUniformIndexing => {
+                "shaderSampledImageArrayNonUniformIndexing"
+            }
+            Self::DynamicRendering => "dynamicRendering",
+            Self::Synchronization2 => "synchronization2",
+            Self::DescriptorBuffer => "VK_EXT_descriptor_buffer",
+            Self::DescriptorSetUpdateAfterBind => {
+                "descriptorBindingPartiallyBound and descriptorBindingSampledImageUpdateAfterBind"
+            }
+        };
+        write!(f, "{}", name)
+    }
+}
+
+///
+/// A physical device as enumerate_adapters found it. Missing lists what it lacks
+/// regardless of the descriptor mode, see missing_features for a given one.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdapterInfo {
+    // Position in the enumeration, only stable as long as the drivers and devices are
+    pub index: usize,
+    pub physical_device: vk::PhysicalDevice,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    // Encoded the way the vendor likes
+    pub driver_version: u32,
+    pub api_version: u32,
+    // Stable across runs, what launchers should persist
+    pub uuid: [u8; 16],
+    // Only on Windows
+    pub luid: Option<[u8; 8]>,
+    // Sizes of the device local heaps
+    pub vram_heap_sizes: Vec<u64>,
+    // One that presents to the probe surface when there is one and any does
+    pub graphics_family: Option<u32>,
+    pub is_descriptor_buffer_supported: bool,
+    pub is_descriptor_set_fallback_supported: bool,
+    pub missing: Vec<MissingFeature>,
+}
+
+impl AdapterInfo {
+    pub fn vram(&self) -> u64 {
+        self.vram_heap_sizes.iter().sum()
+    }
+
+    ///
+    /// Everything the device lacks to make a render core with the descriptor mode. Auto
+    /// only misses descriptors when neither mode works.
+    ///
+    pub fn missing_features(&self, mode: DescriptorMode) -> Vec<MissingFeature> {
+        let mut missing = self.missing.clone();
+        let is_buffer_missing = !self.is_descriptor_buffer_supported;
+        let is_sets_missing = !self.is_descriptor_set_fallback_supported;
+        let is_auto_missing = is_buffer_missing && is_sets_missing;
+        if is_buffer_missing && (mode == DescriptorMode::DescriptorBuffer || is_auto_missing) {
+            missing.push(MissingFeature::DescriptorBuffer);
+        }
+        if is_sets_missing && (mode == DescriptorMode::DescriptorSets || is_auto_missing) {
+            missing.push(MissingFeature::DescriptorSetUpdateAfterBind);
+        }
+        missing
+    }
+
+    pub fn is_usable(&self, mode: DescriptorMode) -> bool {
+        self.missing_features(mode).is_empty()
+    }
+}
+
+///
+/// Which adapter RendererConfig::adapter asks for. Auto picks the first discrete one with
+/// everything needed, then any other with it. The rest refer to enumerate_adapters
+/// results, by uuid or luid to find the same one again in later runs.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AdapterSelector {
+    #[default]
+    Auto,
+    ByIndex(usize),
+    ByUuid([u8; 16]),
+    ByLuid([u8; 8]),
+}
+
+impl AdapterSelector {
+    pub fn select<'a>(
+        &self,
+        adapters: &'a [AdapterInfo],
+        mode: DescriptorMode,
+    ) -> Result<&'a AdapterInfo, AdapterError> {
+        let found = match *self {
+            Self::Auto => {
+                let usable = |e: &&AdapterInfo| e.is_usable(mode);
+                let is_discrete =
+                    |e: &&AdapterInfo| e.device_type == vk::PhysicalDeviceType::DISCRETE_GPU;
+                return adapters
+                    .iter()
+                    .filter(usable)
+                    .find(is_discrete)
+                    .or_else(|| adapters.iter().find(usable))
+                    .ok_or_else(|| AdapterError::NoneSuitable {
+                        rejected: adapters
+                            .iter()
+                            .map(|e| (e.name.clone(), e.missing_features(mode)))
+                            .collect(),
+                    });
+            }
+            Self::ByIndex(index) => adapters.iter().find(|e| e.index == index),
+            Self::ByUuid(uuid) => adapters.iter().find(|e| e.uuid == uuid),
+            Self::ByLuid(luid) => adapters.iter().find(|e| e.luid == Some(luid)),
+        };
+        let adapter = found.ok_or(AdapterError::NotFound(*self))?;
+        let missing = adapter.missing_features(mode);
+        if !missing.is_empty() {
+            return Err(AdapterError::Unsupported {
+                name: adapter.name.clone(),
+                missing,
+            });
+        }
+        Ok(adapter)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdapterError {
+    // No adapter the selector refers to
+    NotFound(AdapterSelector),
+    // The selected adapter can't make a render core
+    Unsupported {
+        name: String,
+        missing: Vec<MissingFeature>,
+    },
+    // Auto found nothing usable, with what each adapter lacks
+    NoneSuitable {
+        rejected: Vec<(String, Vec<MissingFeature>)>,
+    },
+}
+
+impl std::fmt::Display for AdapterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |missing: &[MissingFeature]| {
+            missing
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::NotFound(selector) => write!(f, "no adapter matches {:?}", selector),
+            Self::Unsupported { name, missing } => {
+                write!(f, "adapter {} lacks {}", name, list(missing))
+            }
+            Self::NoneSuitable { rejected } if rejected.is_empty() => {
+                write!(f, "no adapters found")
+            }
+            Self::NoneSuitable { rejected } => {
+                write!(f, "no adapter has everything needed")?;
+                for (name, missing) in rejected {
+                    write!(f, ", {} lacks {}", name, list(missing))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AdapterError {}
+
+///
+/// Every adapter a temporary instance with the extensions sees, without a surface to
+/// check presentation against. No device gets made.
+///
+pub fn enumerate_adapters(instance_extensions: &[*const i8]) -> Vec<AdapterInfo> {
+    let entry = Entry::linked();
+    let instance = renderer::make_instance(&entry, instance_extensions, false, false);
+    let adapters = adapters_of(&instance, None);
+    unsafe { instance.destroy_instance(None) };
+    adapters
+}
+
+///
+/// Same as enumerate_adapters on an existing instance, graphics families also have to
+/// present to the probe surface if one is passed.
+///
+pub fn adapters_of(
+    instance: &ash::Instance,
+    surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+) -> Vec<AdapterInfo> {
+    let devices = unsafe {
+        instance
+            .enumerate_physical_devices()
+            .expect("couldn't enumerate the physical devices!")
+    };
+    devices
+        .into_iter()
+        .enumerate()
+        .map(|(index, e)| info_of(instance, surface, index, e))
+        .collect()
+}
+
+fn info_of(
+    instance: &ash::Instance,
+    surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+    index: usize,
+    physical_device: vk::PhysicalDevice,
+) -> AdapterInfo {
+    let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::builder()
+        .push_next(&mut id_properties)
+        .build();
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+    let properties = properties2.properties;
+    let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
+    let memory = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let vram_heap_sizes = memory.memory_heaps[..memory.memory_heap_count as usize]
+        .iter()
+        .filter(|e| e.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|e| e.size)
+        .collect();
+
+    let mut missing = Vec::new();
+    let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    let graphics: Vec<u32> = (0..families.len() as u32)
+        .filter(|&i| {
+            families[i as usize]
+                .queue_flags
+                .contains(vk::QueueFlags::GRAPHICS)
+        })
+        .collect();
+    let presents = |family: u32| {
+        surface.is_none_or(|(ext, surface)| unsafe {
+            ext.get_physical_device_surface_support(physical_device, family, surface)
+                .unwrap_or(false)
+        })
+    };
+    let graphics_family = graphics.iter().copied().find(|&e| presents(e));
+    if graphics.is_empty() {
+        missing.push(MissingFeature::GraphicsQueue);
+    } else if graphics_family.is_none() {
+        missing.push(MissingFeature::Present);
+    }
+    let is_supported =
+        |name| renderer::is_device_extension_supported(instance, physical_device, name);
+    if !is_supported(khr::Swapchain::name()) {
+        missing.push(MissingFeature::Swapchain);
+    }
+    let is_descriptor_buffer_supported = is_supported(ext::DescriptorBuffer::name());
+    let mut is_descriptor_set_fallback_supported = false;
+    if properties.api_version < vk::API_VERSION_1_3 {
+        missing.push(MissingFeature::ApiVersion);
+    } else {
+        let mut features11 = vk::PhysicalDeviceVulkan11Features::default();
+        let mut features12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features13 = vk::PhysicalDeviceVulkan13Features::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut features11)
+            .push_next(&mut features12)
+            .push_next(&mut features13)
+            .build();
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+        // Same as make_device enables
+        let required = [
+            (
+                features2.features.shader_clip_distance,
+                MissingFeature::ShaderClipDistance,
+            ),
+            (features11.multiview, MissingFeature::Multiview),
+            (
+                features12.descriptor_indexing,
+                MissingFeature::DescriptorIndexing,
+            ),
+            (
+                features12.timeline_semaphore,
+                MissingFeature::TimelineSemaphore,
+            ),
+            (
+                features12.buffer_device_address,
+                MissingFeature::BufferDeviceAddress,
+            ),
+            (
+                features12.scalar_block_layout,
+                MissingFeature::ScalarBlockLayout,
+            ),
+            (
+                features12.runtime_descriptor_array,
+                MissingFeature::RuntimeDescriptorArray,
+            ),
+            (
+                features12.shader_sampled_image_array_non_uniform_indexing,
+                MissingFeature::SampledImageArrayNonUniformIndexing,
+            ),
+            (
+                features13.dynamic_rendering,
+                MissingFeature::DynamicRendering,
+            ),
+            (
+                features13.synchronization2,
+                MissingFeature::Synchronization2,
+            ),
+        ];
+        missing.extend(
+            required
+                .into_iter()
+                .filter(|e| e.0 != vk::TRUE)
+                .map(|e| e.1),
+        );
+        is_descriptor_set_fallback_supported = features12.descriptor_binding_partially_bound
+            == vk::TRUE
+            && features12.descriptor_binding_sampled_image_update_after_bind == vk::TRUE;
+    }
+
+    AdapterInfo {
+        index,
+        physical_device,
+        name: name.to_string_lossy().into_owned(),
+        device_type: properties.device_type,
+        vendor_id: properties.vendor_id,
+        device_id: properties.device_id,
+        driver_version: properties.driver_version,
+        api_version: properties.api_version,
+        uuid: id_properties.device_uuid,
+        luid: (id_properties.device_luid_valid == vk::TRUE).then_some(id_properties.device_luid),
+        vram_heap_sizes,
+        graphics_family: graphics_family.or(graphics.first().copied()),
+        is_descriptor_buffer_supported,
+        is_descriptor_set_fallback_supported,
+        missing,
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use crate::memory::ScopeSnapshot;
+
+/*
+ * Budget bookkeeping of the scopes of a buffer allocator, see
+ * DeviceAllocator::create_scope. Scopes only count bytes, the ranges themselves stay in
+ * the RangeAllocator of the buffer. Allocations are looked up by offset when freed, so
+ * it doesn't matter through which handle of the buffer they get freed. Nested scopes
+ * count against the budget of every scope above them.
+ */
+
+///
+/// Allocation that would take the scope, or one it's nested in, past its budget.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScopeBudgetExceeded {
+    pub scope: String,
+    pub budget: u64,
+    pub used: u64,
+    pub size: u64,
+}
+
+impl std::fmt::Display for ScopeBudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "scope '{}' can't fit {} more bytes, {} of its {} are used",
+            self.scope, self.size, self.used, self.budget
+        )
+    }
+}
+
+impl std::error::Error for ScopeBudgetExceeded {}
+
+#[derive(Clone, Debug)]
+struct Scope {
+    name: String,
+    budget: u64,
+    parent: Option<usize>,
+    // Of the scope and every scope nested in it
+    used: u64,
+    peak: u64,
+    // Made in the scope itself
+    allocation_count: u32,
+    failed_count: u32,
+    is_live: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScopeTable {
+    // By id, destroyed ones stay so ids never get reused
+    scopes: Vec<Scope>,
+    // Scope and size of each allocation made in a scope, by offset
+    owners: HashMap<u64, (usize, u64)>,
+}
+
+impl ScopeTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Id of a new scope, nested in the parent one if any.
+    ///
+    pub fn create(&mut self, name: &str, budget: u64, parent: Option<usize>) -> usize {
+        if let Some(parent) = parent {
+            self.expect_live(parent);
+        }
+        self.scopes.push(Scope {
+            name: name.to_string(),
+            budget,
+            parent,
+            used: 0,
+            peak: 0,
+            allocation_count: 0,
+            failed_count: 0,
+            is_live: true,
+        });
+        self.scopes.len() - 1
+    }
+
+    pub fn is_live(&self, id: usize) -> bool {
+        self.scopes[id].is_live
+    }
+
+    fn expect_live(&self, id: usize) -> &Scope {
+        let scope = &self.scopes[id];
+        if !scope.is_live {
+            panic!("scope '{}' was destroyed already!", scope.name);
+        }
+        scope
+    }
+
+    /*
+     * The scope and the ones it's nested in, innermost first.
+     */
+    fn chain(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(id), |e| self.scopes[*e].parent)
+    }
+
+    ///
+    /// Fails for the innermost scope whose budget can't fit the size, counting the
+    /// failure on the scope allocated in.
+    ///
+    pub fn check(&mut self, id: usize, size: u64) -> Result<(), ScopeBudgetExceeded> {
+        self.expect_live(id);
+        let exceeded = self
+            .chain(id)
+            .map(|e| &self.scopes[e])
+            .find(|e| e.used + size > e.budget)
+            .map(|e| ScopeBudgetExceeded {
+                scope: e.name.clone(),
+                budget: e.budget,
+                used: e.used,
+                size,
+            });
+        match exceeded {
+            Some(e) => {
+                self.scopes[id].failed_count += 1;
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// Counts an allocation that passed check against the scope.
+    ///
+    pub fn record(&mut self, id: usize, offset: u64, size: u64) {
+        let chain: Vec<_> = self.chain(id).collect();
+        for e in chain {
+            let scope = &mut self.scopes[e];
+            scope.used += size;
+            scope.peak = scope.peak.max(scope.used);
+        }
+        self.scopes[id].allocation_count += 1;
+        self.owners.insert(offset, (id, size));
+    }
+
+    ///
+    /// Uncounts the allocation at the offset, false if it wasn't made in a scope.
+    ///
+    pub fn release(&mut self, offset: u64) -> bool {
+        let (id, size) = match self.owners.remove(&offset) {
+            Some(e) => e,
+            None => return false,
+        };
+        let chain: Vec<_> = self.chain(id).collect();
+        for e in chain {
+            self.scopes[e].used -= size;
+        }
+        self.scopes[id].allocation_count -= 1;
+        true
+    }
+
+    ///
+    /// Destroys the scope and every scope nested in it, returning offset and size of
+    /// each allocation still made in them for the caller to free.
+    ///
+    pub fn destroy(&mut self, id: usize) -> Vec<(u64, u64)> {
+        self.expect_live(id);
+        let doomed: Vec<_> = (0..self.scopes.len())
+            .filter(|e| self.scopes[*e].is_live && self.chain(*e).any(|e| e == id))
+            .collect();
+        let mut outstanding: Vec<_> = self
+            .owners
+            .iter()
+            .filter(|(_, (owner, _))| doomed.contains(owner))
+            .map(|(offset, (_, size))| (*offset, *size))
+            .collect();
+        outstanding.sort_unstable();
+        for (offset, _) in &outstanding {
+            self.release(*offset);
+        }
+        for e in doomed {
+            self.scopes[e].is_live = false;
+        }
+        outstanding
+    }
+
+    ///
+    /// Bytes the scope can still allocate before it or a scope it's nested in runs out
+    /// of budget.
+    ///
+    pub fn remaining(&self, id: usize) -> u64 {
+        self.chain(id)
+            .map(|e| &self.scopes[e])
+            .map(|e| e.budget.saturating_sub(e.used))
+            .min()
+            .unwrap()
+    }
+
+    pub fn snapshot_of(&self, id: usize) -> ScopeSnapshot {
+        let scope = &self.scopes[id];
+        ScopeSnapshot {
+            id: id as u32,
+            name: scope.name.clone(),
+            parent: scope.parent.map(|e| e as u32),
+            budget: scope.budget,
+            used: scope.used,
+            peak: scope.peak,
+            allocation_count: scope.allocation_count,
+            failed_count: scope.failed_count,
+        }
+    }
+
+    ///
+    /// Every live scope, by id.
+    ///
+    pub fn snapshots(&self) -> Vec<ScopeSnapshot> {
+        (0..self.scopes.len())
+            .filter(|e| self.scopes[*e].is_live)
+            .map(|e| self.snapshot_of(e))
+            .collect()
+    }
+}
@@ -0,0 +1,467 @@
+/*
+ * Workloads and statistics for examples/benchmark.rs, only built with the bench-metrics
+ * feature. A scene is planned from its params alone, with a generator of its own
+ * instead of rand, so the same seed gives the same meshes, texels and tasks whatever
+ * version of the crate or its dependencies runs it. The plan hash tells two runs apart
+ * when they didn't.
+ */
+use std::collections::HashMap;
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+use serde::Serialize;
+
+use crate::{
+    format::Format,
+    handle::{MeshHandle, TextureHandle},
+    mesh_opt::{MeshData, MeshIndices, MeshOptFlags, VertexStream},
+    render_task::{RenderTask, TaskKind},
+    renderer::Renderer,
+    shader_resource::{
+        DirLight, Material, MultiResource, PointLight, ResourceKind, SpotLight, Transform,
+        TransformExtra,
+    },
+    texture::MipMap,
+};
+
+///
+/// What SyntheticScene::generate makes. Every kind gets tasks_per_kind tasks, fullscreen
+/// and directional light ones draw the test triangle, the rest one of the meshes each.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntheticSceneParams {
+    pub seed: u64,
+    pub meshes: u32,
+    pub triangles_per_mesh: u32,
+    pub textures: u32,
+    // Width and height of the RGBA8 textures, single mip
+    pub texture_size: u32,
+    pub tasks_per_kind: u32,
+    #[serde(serialize_with = "serialize_kinds")]
+    pub kinds: Vec<TaskKind>,
+}
+
+impl Default for SyntheticSceneParams {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            meshes: 64,
+            triangles_per_mesh: 256,
+            textures: 16,
+            texture_size: 64,
+            tasks_per_kind: 256,
+            kinds: vec![TaskKind::MeshStatic],
+        }
+    }
+}
+
+fn serialize_kinds<S: serde::Serializer>(kinds: &[TaskKind], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(kinds.iter().map(|e| e.to_string()))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedMesh {
+    // 3 floats per vertex for positions and normals, 2 for tex coords
+    pub positions: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub tex_coords: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedTask {
+    pub kind: TaskKind,
+    // Index into the meshes of the plan, None for the test triangle
+    pub mesh: Option<u32>,
+    pub texture: u32,
+    pub model: Mat4,
+}
+
+///
+/// Everything a scene is made of, derived from the params alone.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenePlan {
+    pub meshes: Vec<PlannedMesh>,
+    pub textures: Vec<Vec<u8>>,
+    pub tasks: Vec<PlannedTask>,
+}
+
+impl ScenePlan {
+    ///
+    /// Panics for kinds drawing meshes without any mesh or any texture to draw with.
+    ///
+    pub fn of(params: &SyntheticSceneParams) -> Self {
+        let mut rng = SplitMix::new(params.seed);
+        let meshes = (0..params.meshes)
+            .map(|_| plan_mesh(&mut rng, params.triangles_per_mesh))
+            .collect();
+        let texture_bytes = params.texture_size * params.texture_size * 4;
+        let textures = (0..params.textures)
+            .map(|_| (0..texture_bytes).map(|_| rng.next() as u8).collect())
+            .collect();
+        let mut tasks = Vec::new();
+        for kind in &params.kinds {
+            let mesh_count = match is_fullscreen(*kind) {
+                true => None,
+                false if params.meshes == 0 || params.textures == 0 => {
+                    panic!("{} tasks need meshes and textures to draw with!", kind)
+                }
+                false => Some(params.meshes),
+            };
+            for _ in 0..params.tasks_per_kind {
+                let mesh = mesh_count.map(|e| rng.below(e));
+                let texture = rng.below(params.textures.max(1));
+                let translation = Vec3::new(
+                    rng.signed() * 4.0,
+                    rng.signed() * 4.0,
+                    -4.0 - rng.unit() * 16.0,
+                );
+                let rotation = Quat::from_rotation_y(rng.unit() * std::f32::consts::TAU);
+                let scale = Vec3::splat(0.5 + rng.unit());
+                tasks.push(PlannedTask {
+                    kind: *kind,
+                    mesh,
+                    texture,
+                    model: Mat4::from_scale_rotation_translation(scale, rotation, translation),
+                });
+            }
+        }
+        Self {
+            meshes,
+            textures,
+            tasks,
+        }
+    }
+
+    ///
+    /// FNV-1a of every byte and float of the plan, equal for equal plans on any platform.
+    ///
+    pub fn hash(&self) -> u64 {
+        let mut hash = Fnv::default();
+        for mesh in &self.meshes {
+            for floats in [&mesh.positions, &mesh.normals, &mesh.tex_coords] {
+                floats.iter().for_each(|e| hash.write(&e.to_le_bytes()));
+            }
+            mesh.indices
+                .iter()
+                .for_each(|e| hash.write(&e.to_le_bytes()));
+        }
+        for texels in &self.textures {
+            hash.write(texels);
+        }
+        for task in &self.tasks {
+            hash.write(&[task.kind as u8]);
+            hash.write(&task.mesh.map_or(u32::MAX, |e| e).to_le_bytes());
+            hash.write(&task.texture.to_le_bytes());
+            for e in task.model.to_cols_array() {
+                hash.write(&e.to_le_bytes());
+            }
+        }
+        hash.0
+    }
+}
+
+// Kinds whose stages draw over the whole target instead of reading vertices
+fn is_fullscreen(kind: TaskKind) -> bool {
+    matches!(kind, TaskKind::Fullscreen | TaskKind::LightDir)
+}
+
+fn plan_mesh(rng: &mut SplitMix, triangles: u32) -> PlannedMesh {
+    let vertices = triangles as usize * 3;
+    let mut positions = Vec::with_capacity(vertices * 3);
+    let mut normals = Vec::with_capacity(vertices * 3);
+    let mut tex_coords = Vec::with_capacity(vertices * 2);
+    for _ in 0..vertices {
+        positions.extend([rng.signed(), rng.signed(), rng.signed()]);
+        let normal = Vec3::new(rng.signed(), rng.signed(), rng.signed()).normalize_or_zero();
+        normals.extend(normal.to_array());
+        tex_coords.extend([rng.unit(), rng.unit()]);
+    }
+    PlannedMesh {
+        positions,
+        normals,
+        tex_coords,
+        indices: (0..vertices as u32).collect(),
+    }
+}
+
+///
+/// Meshes, textures and tasks of a plan, made on the renderer. The textures take a
+/// frame to upload and the meshes may take more, so benchmarks render some warmup
+/// frames first.
+///
+pub struct SyntheticScene {
+    pub meshes: Vec<MeshHandle>,
+    pub textures: Vec<TextureHandle>,
+    tasks: Vec<PlannedTask>,
+    hash: u64,
+}
+
+impl SyntheticScene {
+    ///
+    /// Panics if some stage drawing one of the kinds reads per instance resources other
+    /// than transforms, materials and lights, there's no telling what to fill them with.
+    ///
+    pub fn generate(params: &SyntheticSceneParams, renderer: &mut Renderer) -> Self {
+        for kind in &params.kinds {
+            if let Some(resource) = renderer
+                .instance_resources_of(*kind)
+                .into_iter()
+                .find(|e| resource_of(*e, Mat4::IDENTITY, Mat4::IDENTITY, 0).is_none())
+            {
+                panic!(
+                    "synthetic {} tasks can't fill {} resources!",
+                    kind, resource
+                );
+            }
+        }
+        let plan = ScenePlan::of(params);
+        let hash = plan.hash();
+        let meshes = plan
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let as_bytes = |floats: &[f32]| -> Vec<u8> {
+                    floats.iter().flat_map(|e| e.to_le_bytes()).collect()
+                };
+                let (positions, normals, tex_coords) = (
+                    as_bytes(&mesh.positions),
+                    as_bytes(&mesh.normals),
+                    as_bytes(&mesh.tex_coords),
+                );
+                let data = MeshData {
+                    vertex_count: mesh.indices.len() as u32,
+                    positions: VertexStream {
+                        data: &positions,
+                        stride: MeshData::POSITION_SIZE as u32,
+                    },
+                    normals: Some(VertexStream {
+                        data: &normals,
+                        stride: MeshData::NORMAL_SIZE as u32,
+                    }),
+                    tex_coords: Some(VertexStream {
+                        data: &tex_coords,
+                        stride: MeshData::TEX_COORD_SIZE as u32,
+                    }),
+                    indices: MeshIndices::U32(&mesh.indices),
+                };
+                // Optimizing would only add noise to the frames measured
+                renderer
+                    .gen_mesh_with_data(&data, MeshOptFlags::default())
+                    .0
+            })
+            .collect();
+        let textures = plan
+            .textures
+            .iter()
+            .enumerate()
+            .map(|(i, texels)| {
+                let size = texels.len() as u32;
+                let mip = MipMap {
+                    index: 0,
+                    width: params.texture_size,
+                    height: params.texture_size,
+                    size,
+                    offset: 0,
+                };
+                let name = format!("synthetic_{}", i);
+                let handle = renderer.gen_texture(name, Format::R8G8B8A8_UNORM, &[mip], size);
+                let staging = renderer
+                    .fetch_texture(handle)
+                    .and_then(|e| e.staging.as_ref())
+                    .unwrap();
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        texels.as_ptr(),
+                        staging.addr as *mut u8,
+                        texels.len(),
+                    )
+                };
+                renderer.queue_texture_for_uploading(handle).unwrap();
+                handle
+            })
+            .collect();
+        Self {
+            meshes,
+            textures,
+            tasks: plan.tasks,
+            hash,
+        }
+    }
+
+    ///
+    /// Hash of the plan the scene was made from, see ScenePlan::hash.
+    ///
+    pub fn workload_hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    ///
+    /// Queues every task of the scene for the next frame, seen by a camera at the origin
+    /// looking down -Z with the aspect of the render extent.
+    ///
+    pub fn queue_frame(&self, renderer: &mut Renderer) {
+        let extent = renderer.render_extent();
+        let aspect = extent.width as f32 / extent.height.max(1) as f32;
+        let proj = Mat4::perspective_rh(std::f32::consts::FRAC_PI_3, aspect, 0.1, 100.0);
+        for task in &self.tasks {
+            let mesh = task
+                .mesh
+                .map_or(Renderer::TEST_TRIANGLE, |e| self.meshes[e as usize]);
+            let texture = self
+                .textures
+                .get(task.texture as usize)
+                .map_or(0, |e| e.index);
+            let resources: HashMap<_, _> = renderer
+                .instance_resources_of(task.kind)
+                .into_iter()
+                .filter_map(|kind| resource_of(kind, proj, task.model, texture).map(|e| (kind, e)))
+                .collect();
+            renderer.add_task_to_queue(RenderTask {
+                resources,
+                ..RenderTask::new(mesh, task.kind)
+            });
+        }
+    }
+
+    pub fn free(self, renderer: &mut Renderer) {
+        for mesh in self.meshes {
+            let _ = renderer.free_mesh(mesh);
+        }
+        for texture in self.textures {
+            let _ = renderer.free_texture(texture);
+        }
+    }
+}
+
+/*
+ * Resource of a single instance, None for the kinds synthetic tasks don't know how to
+ * fill. The view is the identity, the camera sits at the origin.
+ */
+fn resource_of(kind: ResourceKind, proj: Mat4, model: Mat4, texture: u32) -> Option<MultiResource> {
+    let mvp = proj * model;
+    Some(match kind {
+        ResourceKind::Transform => MultiResource::Transform(vec![Transform { mvp, mv: model }]),
+        ResourceKind::TransformExtra => {
+            // Nothing moves, the previous frame saw the same
+            MultiResource::TransformExtra(vec![TransformExtra { prev_mvp: mvp }])
+        }
+        ResourceKind::Material => MultiResource::Material(vec![Material {
+            shininess: 16.0,
+            scaling: 1.0,
+            diffuse_handle: texture,
+            normal_handle: texture,
+            glow_handle: texture,
+            diffuse_sampler: 0,
+            normal_sampler: 0,
+            glow_sampler: 0,
+            padding: 0,
+        }]),
+        ResourceKind::DirLight => MultiResource::DirLight(vec![DirLight {
+            view_dir: Vec4::new(-0.5, -1.0, -0.5, 0.0).normalize(),
+            color: Vec4::ONE,
+            sky_color: Vec4::new(0.5, 0.6, 0.8, 1.0),
+            ground_color: Vec4::new(0.3, 0.25, 0.2, 1.0),
+            inv_view_shadow_proj: Mat4::IDENTITY,
+        }]),
+        ResourceKind::PointLight => MultiResource::PointLight(vec![PointLight {
+            color: Vec3::ONE,
+            radius: 4.0,
+        }]),
+        ResourceKind::SpotLight => MultiResource::SpotLight(vec![SpotLight {
+            cos_cutoff_rad: 0.9,
+            sin_cutoff_rad: 0.9f32.acos().sin(),
+            range: 8.0,
+            inv_range: 1.0 / 8.0,
+            intensity: 1.0,
+            color: Vec3::ONE,
+        }]),
+        _ => return None,
+    })
+}
+
+///
+/// Mean and nearest rank percentiles of the samples, all zero without any.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn of(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let rank = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Self {
+            count: sorted.len(),
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p95: rank(0.95),
+            p99: rank(0.99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+// SplitMix64, fixed here so plans don't change with the rand crate
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Within [0, 1)
+    fn unit(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // Within [-1, 1)
+    fn signed(&mut self) -> f32 {
+        self.unit() * 2.0 - 1.0
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next() % n as u64) as u32
+    }
+}
+
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
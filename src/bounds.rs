@@ -0,0 +1,238 @@
+use glam::{DMat3, DVec3, Mat4, Vec3, Vec4};
+
+///
+/// Axis aligned box and bounding sphere of the positions of a mesh, in its object
+/// space, see Renderer::mesh_bounds. The sphere is the smallest one holding every
+/// position, give or take float rounding.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl MeshBounds {
+    ///
+    /// Bounds of the positions, None without any.
+    ///
+    pub fn of_positions(positions: &[Vec3]) -> Option<Self> {
+        let first = *positions.first()?;
+        let (min, max) = positions
+            .iter()
+            .fold((first, first), |(min, max), e| (min.min(*e), max.max(*e)));
+        let center = min_sphere_center(positions);
+        // Measured again in f32, so every position is inside as the shaders compute it
+        let radius = positions
+            .iter()
+            .map(|e| e.distance(center))
+            .fold(0.0, f32::max);
+        Some(Self {
+            min,
+            max,
+            center,
+            radius,
+        })
+    }
+
+    ///
+    /// Bounds of tightly packed positions of 3 floats, the way vertex buffers hold them.
+    ///
+    pub fn of_position_bytes(bytes: &[u8]) -> Option<Self> {
+        Self::of_strided_position_bytes(bytes, 12, 0)
+    }
+
+    ///
+    /// Same as of_position_bytes for positions at the offset of each vertex of the
+    /// stride, the way interleaved vertex buffers hold them.
+    ///
+    pub fn of_strided_position_bytes(bytes: &[u8], stride: u32, offset: u32) -> Option<Self> {
+        let positions: Vec<_> = bytes
+            .chunks_exact(stride as usize)
+            .map(|e| {
+                let e = &e[offset as usize..];
+                let component =
+                    |i: usize| f32::from_ne_bytes(e[i * 4..i * 4 + 4].try_into().unwrap());
+                Vec3::new(component(0), component(1), component(2))
+            })
+            .collect();
+        Self::of_positions(&positions)
+    }
+
+    ///
+    /// Box and sphere scaled around their centers. Bind pose bounds of skinned meshes
+    /// miss what the animations reach, a factor above 1 makes up for it.
+    ///
+    pub fn inflated(&self, factor: f32) -> Self {
+        let box_center = (self.min + self.max) * 0.5;
+        let half_extent = (self.max - self.min) * 0.5 * factor;
+        Self {
+            min: box_center - half_extent,
+            max: box_center + half_extent,
+            center: self.center,
+            radius: self.radius * factor,
+        }
+    }
+
+    ///
+    /// Sphere moved by the transform, center in xyz and radius in w. Non uniform scales
+    /// grow the radius by the largest of them.
+    ///
+    pub fn transformed_sphere(&self, transform: &Mat4) -> Vec4 {
+        let center = transform.transform_point3(self.center);
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        center.extend(self.radius * scale)
+    }
+}
+
+///
+/// Smallest sphere holding both spheres, center in xyz and radius in w.
+///
+pub fn merge_spheres(a: Vec4, b: Vec4) -> Vec4 {
+    let offset = b.truncate() - a.truncate();
+    let distance = offset.length();
+    if distance + b.w <= a.w {
+        return a;
+    }
+    if distance + a.w <= b.w {
+        return b;
+    }
+    let radius = (distance + a.w + b.w) * 0.5;
+    let center = a.truncate() + offset * ((radius - a.w) / distance);
+    center.extend(radius)
+}
+
+/*
+ * Welzl's minimal enclosing sphere, the iterative move-to-front form. Points get visited
+ * in a fixed pseudo random order, its expected time is linear on any input. Computed in
+ * f64, degenerate support sets fall back to growing the sphere that failed to hold the
+ * point, which only ever makes it slightly larger than minimal.
+ */
+#[derive(Clone, Copy)]
+struct Sphere {
+    center: DVec3,
+    radius_sq: f64,
+}
+
+impl Sphere {
+    fn contains(&self, p: DVec3) -> bool {
+        p.distance_squared(self.center) <= self.radius_sq * (1.0 + 1e-12) + 1e-18
+    }
+
+    fn grown_to(&self, p: DVec3) -> Self {
+        let radius = self.radius_sq.sqrt();
+        let distance = p.distance(self.center);
+        let new_radius = (radius + distance) * 0.5;
+        Self {
+            center: self.center + (p - self.center) * ((new_radius - radius) / distance),
+            radius_sq: new_radius * new_radius,
+        }
+    }
+}
+
+fn min_sphere_center(positions: &[Vec3]) -> Vec3 {
+    let mut points: Vec<DVec3> = positions.iter().map(|e| e.as_dvec3()).collect();
+    shuffle(&mut points);
+    let mut sphere = Sphere {
+        center: points[0],
+        radius_sq: 0.0,
+    };
+    for i in 1..points.len() {
+        if sphere.contains(points[i]) {
+            continue;
+        }
+        let p = points[i];
+        sphere = Sphere {
+            center: p,
+            radius_sq: 0.0,
+        };
+        for j in 0..i {
+            if sphere.contains(points[j]) {
+                continue;
+            }
+            let q = points[j];
+            sphere = diameter_sphere(p, q);
+            for k in 0..j {
+                if sphere.contains(points[k]) {
+                    continue;
+                }
+                let r = points[k];
+                sphere = circle_sphere(p, q, r).unwrap_or_else(|| sphere.grown_to(r));
+                for &s in &points[..k] {
+                    if !sphere.contains(s) {
+                        sphere =
+                            tetrahedron_sphere(p, q, r, s).unwrap_or_else(|| sphere.grown_to(s));
+                    }
+                }
+            }
+        }
+    }
+    sphere.center.as_vec3()
+}
+
+fn diameter_sphere(a: DVec3, b: DVec3) -> Sphere {
+    Sphere {
+        center: (a + b) * 0.5,
+        radius_sq: a.distance_squared(b) * 0.25,
+    }
+}
+
+/*
+ * Circumscribed circle of the triangle, None if its points are collinear.
+ */
+fn circle_sphere(a: DVec3, b: DVec3, c: DVec3) -> Option<Sphere> {
+    let ab = b - a;
+    let ac = c - a;
+    let normal = ab.cross(ac);
+    let denominator = 2.0 * normal.length_squared();
+    if denominator <= f64::EPSILON * ab.length_squared() * ac.length_squared() {
+        return None;
+    }
+    let offset = (normal.cross(ab) * ac.length_squared() + ac.cross(normal) * ab.length_squared())
+        / denominator;
+    Some(Sphere {
+        center: a + offset,
+        radius_sq: offset.length_squared(),
+    })
+}
+
+/*
+ * Circumscribed sphere of the tetrahedron, None if its points are coplanar.
+ */
+fn tetrahedron_sphere(a: DVec3, b: DVec3, c: DVec3, d: DVec3) -> Option<Sphere> {
+    let (ab, ac, ad) = (b - a, c - a, d - a);
+    let rows = DMat3::from_cols(ab, ac, ad).transpose();
+    let det = rows.determinant();
+    if det.abs() <= f64::EPSILON * ab.length() * ac.length() * ad.length() {
+        return None;
+    }
+    let rhs = DVec3::new(
+        ab.length_squared(),
+        ac.length_squared(),
+        ad.length_squared(),
+    ) * 0.5;
+    let offset = rows.inverse() * rhs;
+    Some(Sphere {
+        center: a + offset,
+        radius_sq: offset.length_squared(),
+    })
+}
+
+/*
+ * Same order for the same positions, the bounds of a mesh don't change between runs.
+ */
+fn shuffle(points: &mut [DVec3]) {
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    for i in (1..points.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        points.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
@@ -1,18 +1,76 @@
 use ash::vk;
-use std::cell::RefCell;
 use std::clone::Clone;
 use std::marker::Copy;
 use std::os::raw::c_void;
-use std::rc::Rc;
-
-use crate::context::VulkanContext;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[cfg(feature = "alloc-canaries")]
+use crate::canary::{CanaryError, CanaryTable};
+#[cfg(feature = "fault-injection")]
+use crate::fault::{Fault, FaultInjector};
+use crate::{
+    alloc_scope::{ScopeBudgetExceeded, ScopeTable},
+    context::VulkanContext,
+    memory::{AllocatorSnapshot, ScopeSnapshot},
+    range_allocator::RangeAllocator,
+};
 
 #[derive(Clone)]
 pub struct DeviceAllocator {
-    inner: Rc<RefCell<InnerDeviceAllocator>>,
+    inner: Arc<Mutex<InnerDeviceAllocator>>,
     pub buffer: DeviceBuffer,
+    // None for the allocator of the whole buffer, see create_scope
+    scope: Option<Arc<ScopeGuard>>,
+}
+
+/*
+ * Shared by the clones of a scope, destroys it along with the last of them unless
+ * destroy_scope did already.
+ */
+struct ScopeGuard {
+    id: usize,
+    inner: Arc<Mutex<InnerDeviceAllocator>>,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        // Nothing to clean up in a poisoned allocator
+        let mut inner = match self.inner.lock() {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+        if !inner.scopes.is_live(self.id) {
+            return;
+        }
+        let name = inner.scopes.snapshot_of(self.id).name;
+        let leaked = inner.destroy_scope(self.id);
+        if leaked > 0 {
+            log::warn!(
+                "scope '{}' dropped with {} allocations still made in it, freeing them",
+                name,
+                leaked
+            );
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AllocError {
+    OutOfMemory,
+    ScopeBudgetExceeded(ScopeBudgetExceeded),
+}
+
+impl std::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfMemory => write!(f, "no free range is big enough"),
+            Self::ScopeBudgetExceeded(e) => e.fmt(f),
+        }
+    }
 }
 
+impl std::error::Error for AllocError {}
+
 #[derive(Copy, Clone)]
 pub struct DeviceSlice {
     pub buffer: vk::Buffer,
@@ -24,6 +82,13 @@ pub struct DeviceSlice {
     pub kind: BufferKind,
 }
 
+/*
+ * SAFETY: addr points into persistently mapped, host coherent memory owned by the
+ * DeviceBuffer it was sliced from. The mapping stays valid from any thread until the
+ * buffer is destroyed, and nothing about it is tied to the thread that mapped it.
+ */
+unsafe impl Send for DeviceSlice {}
+
 impl DeviceSlice {
     pub fn empty() -> Self {
         Self {
@@ -60,46 +125,179 @@ impl DeviceAllocator {
     pub fn new(ctx: &VulkanContext, size: u64, kind: BufferKind) -> Self {
         let inner = InnerDeviceAllocator::new(ctx, size, kind);
         let buffer = inner.buffer.clone();
-        let refc = Rc::new(RefCell::new(inner));
+        let refc = Arc::new(Mutex::new(inner));
         Self {
             buffer,
             inner: refc,
+            scope: None,
         }
     }
 
+    fn lock(&self) -> MutexGuard<'_, InnerDeviceAllocator> {
+        self.inner.lock().expect("device allocator lock poisoned!")
+    }
+
+    fn scope_id(&self) -> Option<usize> {
+        self.scope.as_ref().map(|e| e.id)
+    }
+
+    #[cfg_attr(feature = "alloc-canaries", track_caller)]
     pub fn alloc(&self, size: u64) -> Option<DeviceSlice> {
-        self.inner.borrow_mut().alloc(size)
+        self.try_alloc(size).ok()
+    }
+
+    ///
+    /// Same as alloc, telling apart running out of budget in a scope from running out of
+    /// free ranges.
+    ///
+    #[cfg_attr(feature = "alloc-canaries", track_caller)]
+    pub fn try_alloc(&self, size: u64) -> Result<DeviceSlice, AllocError> {
+        let mut inner = self.lock();
+        #[cfg(feature = "fault-injection")]
+        if inner
+            .faults
+            .as_ref()
+            .is_some_and(|e| e.take(Fault::AllocFailed))
+        {
+            return Err(AllocError::OutOfMemory);
+        }
+        let slice = inner.alloc(size, self.scope_id())?;
+        #[cfg(feature = "alloc-canaries")]
+        {
+            // Owners are where the allocations were made
+            let at = std::panic::Location::caller();
+            let owner = match self.scope_id() {
+                Some(id) => format!("{} in scope '{}'", at, inner.scopes.snapshot_of(id).name),
+                None => at.to_string(),
+            };
+            let inner = &mut *inner;
+            let memory = unsafe { mapped(&inner.buffer) };
+            inner
+                .canaries
+                .allocated(memory, slice.offset, slice.size, owner);
+        }
+        Ok(slice)
+    }
+
+    ///
+    /// Allocator drawing from this one that can't allocate more than the budget, nested
+    /// allocations counting against the budgets of the scopes above too. Allocations can
+    /// be freed through any allocator of the buffer. Whatever is still allocated in the
+    /// scope gets freed by destroy_scope, or with a warning once the last clone of it is
+    /// dropped, slices of it mustn't be freed again after that.
+    ///
+    pub fn create_scope(&self, name: &str, budget: u64) -> DeviceAllocator {
+        let id = self.lock().scopes.create(name, budget, self.scope_id());
+        DeviceAllocator {
+            inner: self.inner.clone(),
+            buffer: self.buffer.clone(),
+            scope: Some(Arc::new(ScopeGuard {
+                id,
+                inner: self.inner.clone(),
+            })),
+        }
+    }
+
+    ///
+    /// Frees everything still allocated in the scope and in the scopes nested in it,
+    /// clones of them panic when allocating from then on. Returns how many allocations
+    /// were freed.
+    ///
+    pub fn destroy_scope(self) -> usize {
+        let id = self.scope_id().expect("not a scope!");
+        self.lock().destroy_scope(id)
+    }
+
+    ///
+    /// Usage and budget of the scope, None for the allocator of the whole buffer.
+    ///
+    pub fn scope_snapshot(&self) -> Option<ScopeSnapshot> {
+        let id = self.scope_id()?;
+        Some(self.lock().scopes.snapshot_of(id))
+    }
+
+    ///
+    /// Whether both allocate from the same buffer, scoped or not.
+    ///
+    pub fn shares_buffer_with(&self, other: &DeviceAllocator) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    ///
+    /// Shared by every clone of the allocator.
+    ///
+    #[cfg(feature = "fault-injection")]
+    pub fn set_fault_injector(&self, faults: Option<FaultInjector>) {
+        self.lock().faults = faults;
+    }
+
+    ///
+    /// Allocations made through any clone of the allocator since the last call.
+    ///
+    #[cfg(feature = "bench-metrics")]
+    pub fn take_allocation_count(&self) -> u64 {
+        std::mem::take(&mut self.lock().allocations)
     }
 
     pub fn free(&self, slice: DeviceSlice) {
-        self.inner.borrow_mut().free(slice)
+        self.lock().free(slice)
+    }
+
+    ///
+    /// Guards of every allocation of the buffer and the ranges freed since, see canary.
+    /// Each overwrite gets reported once.
+    ///
+    #[cfg(feature = "alloc-canaries")]
+    pub fn check_canaries(&self) -> Vec<CanaryError> {
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let memory = unsafe { mapped(&inner.buffer) };
+        inner.canaries.check(memory)
     }
 
     pub fn destroy(&self, device: &ash::Device) {
-        self.inner.borrow().destroy(device)
+        self.lock().destroy(device)
     }
 
+    ///
+    /// Free bytes of the buffer, at most what's left of the budget for a scope.
+    ///
     pub fn available(&self) -> u64 {
-        self.inner.borrow().available()
+        let inner = self.lock();
+        match self.scope_id() {
+            Some(id) => inner.available().min(inner.scopes.remaining(id)),
+            None => inner.available(),
+        }
+    }
+
+    ///
+    /// Of the whole buffer with every scope of it, same for all allocators of the buffer.
+    ///
+    pub fn snapshot(&self) -> AllocatorSnapshot {
+        let inner = self.lock();
+        AllocatorSnapshot {
+            scopes: inner.scopes.snapshots(),
+            ..AllocatorSnapshot::of(&inner.ranges)
+        }
     }
 
     pub fn alignment(&self) -> u64 {
-        self.inner.borrow().buffer.alignment
+        self.lock().buffer.alignment
     }
 
     pub fn size(&self) -> u64 {
-        self.inner.borrow().buffer.size
+        self.lock().buffer.size
     }
 
     pub fn kind(&self) -> BufferKind {
-        self.inner.borrow().buffer.kind
+        self.lock().buffer.kind
     }
 
     ///
     /// Just go to town with it if you want
     ///
     pub fn buffer(&self) -> DeviceBuffer {
-        self.inner.borrow().buffer.clone()
+        self.lock().buffer.clone()
     }
 }
 
@@ -108,6 +306,8 @@ pub enum BufferKind {
     Undefined,
     General,
     Descriptor,
+    // Copy destination read by the host, see Renderer::read_buffer
+    Readback,
 }
 
 impl BufferKind {
@@ -128,26 +328,41 @@ impl BufferKind {
                     | Buf::RESOURCE_DESCRIPTOR_BUFFER_EXT
                     | Buf::SAMPLER_DESCRIPTOR_BUFFER_EXT
             }
+            BufferKind::Readback => Buf::SHADER_DEVICE_ADDRESS | Buf::TRANSFER_DST,
             _ => unreachable!(),
         }
     }
-}
 
-#[derive(Copy, Clone, Debug)]
-struct Range {
-    start: u64,
-    end: u64,
-}
-
-impl Range {
-    fn size(&self) -> u64 {
-        self.end - self.start
+    ///
+    /// Memory properties to look for in order, the first one a memory type has wins.
+    ///
+    pub fn preferred_memory_flags(&self) -> &'static [vk::MemoryPropertyFlags] {
+        use vk::MemoryPropertyFlags as Mpf;
+        const HOST: u32 = Mpf::HOST_VISIBLE.as_raw() | Mpf::HOST_COHERENT.as_raw();
+        // Cached memory makes host reads way faster than write combined one
+        const READBACK: &[Mpf] = &[
+            Mpf::from_raw(HOST | Mpf::HOST_CACHED.as_raw()),
+            Mpf::from_raw(HOST),
+        ];
+        const MAPPED_DEVICE_LOCAL: &[Mpf] = &[Mpf::from_raw(HOST | Mpf::DEVICE_LOCAL.as_raw())];
+        match self {
+            BufferKind::Readback => READBACK,
+            _ => MAPPED_DEVICE_LOCAL,
+        }
     }
 }
 
 struct InnerDeviceAllocator {
     buffer: DeviceBuffer,
-    ranges: Vec<Range>,
+    ranges: RangeAllocator,
+    scopes: ScopeTable,
+    #[cfg(feature = "alloc-canaries")]
+    canaries: CanaryTable,
+    #[cfg(feature = "fault-injection")]
+    faults: Option<FaultInjector>,
+    // Made since last taken, see take_allocation_count
+    #[cfg(feature = "bench-metrics")]
+    allocations: u64,
 }
 
 #[derive(Clone)]
@@ -162,14 +377,15 @@ pub struct DeviceBuffer {
     pub kind: BufferKind,
 }
 
+// SAFETY: Same as DeviceSlice, addr is a persistent mapping valid on any thread.
+unsafe impl Send for DeviceBuffer {}
+
 impl DeviceBuffer {
     // Max alignment a buffer of any type can have
     const MAX_ALIGNMENT: u64 = 256;
 
     pub fn new(ctx: &VulkanContext, size: u64, kind: BufferKind) -> Self {
-        use vk::MemoryPropertyFlags as Mpf;
         let usage_flags = kind.to_vk_usage_flags();
-        let mem_flags = Mpf::DEVICE_LOCAL | Mpf::HOST_VISIBLE | Mpf::HOST_COHERENT;
         let buffer_info = vk::BufferCreateInfo {
             size: Self::next_size(size, Self::MAX_ALIGNMENT),
             usage: usage_flags,
@@ -195,8 +411,8 @@ impl DeviceBuffer {
             mem_reqs.alignment
         };
 
-        let memi = ctx
-            .memory_type_index_for(mem_reqs.memory_type_bits, mem_flags)
+        let (memi, _) = ctx
+            .memory_type_index_for_any(mem_reqs.memory_type_bits, kind.preferred_memory_flags())
             .expect("Unable to find suitable memorytype for the buffer");
         let mut mem_flags = vk::MemoryAllocateFlagsInfo {
             flags: vk::MemoryAllocateFlags::DEVICE_ADDRESS,
@@ -267,103 +483,84 @@ impl InnerDeviceAllocator {
     }
 
     fn wrap(buffer: DeviceBuffer) -> Self {
-        let ranges = vec![Range {
-            start: 0,
-            end: buffer.size,
-        }];
-        return Self { buffer, ranges };
+        let ranges = RangeAllocator::new(buffer.size);
+        Self {
+            buffer,
+            ranges,
+            scopes: ScopeTable::new(),
+            #[cfg(feature = "alloc-canaries")]
+            canaries: CanaryTable::default(),
+            #[cfg(feature = "fault-injection")]
+            faults: None,
+            #[cfg(feature = "bench-metrics")]
+            allocations: 0,
+        }
     }
 
-    fn alloc(&mut self, size: u64) -> Option<DeviceSlice> {
+    fn alloc(&mut self, size: u64, scope: Option<usize>) -> Result<DeviceSlice, AllocError> {
         let size = DeviceBuffer::next_size(size, self.buffer.alignment);
-        let ranges = &mut self.ranges;
-        for i in 0..ranges.len() {
-            let range = &ranges[i];
-            let range_size = range.size();
-            if range_size < size {
-                continue;
-            }
-            let old_start = range.start;
-            let new_start = old_start + size;
-            if new_start == range.end {
-                // Took the entire range
-                ranges.remove(i);
-            } else {
-                // Update the existing range
-                let range = &mut ranges[i];
-                range.start = new_start;
-            }
-            let mut addr = self.buffer.addr;
-            let offset;
-            unsafe {
-                addr = addr.offset(old_start as isize);
-                offset = addr.offset_from(self.buffer.addr) as u64;
-            }
-            let device_addr = self.buffer.device_addr + offset;
-            return Some(DeviceSlice {
-                buffer: self.buffer.buffer,
-                addr,
-                size,
-                offset,
-                alignment: self.buffer.alignment,
-                device_addr,
-                kind: self.buffer.kind,
-            });
+        if let Some(scope) = scope {
+            self.scopes
+                .check(scope, size)
+                .map_err(AllocError::ScopeBudgetExceeded)?;
         }
-        return None;
+        let offset = self
+            .ranges
+            .alloc(Self::reserved_size(size), self.buffer.alignment)
+            .ok_or(AllocError::OutOfMemory)?;
+        if let Some(scope) = scope {
+            self.scopes.record(scope, offset, size);
+        }
+        #[cfg(feature = "bench-metrics")]
+        {
+            self.allocations += 1;
+        }
+        let addr = unsafe { self.buffer.addr.offset(offset as isize) };
+        let device_addr = self.buffer.device_addr + offset;
+        Ok(DeviceSlice {
+            buffer: self.buffer.buffer,
+            addr,
+            size,
+            offset,
+            alignment: self.buffer.alignment,
+            device_addr,
+            kind: self.buffer.kind,
+        })
     }
 
     fn free(&mut self, slice: DeviceSlice) {
-        // | | | | | |
-        let slice_start = unsafe { slice.addr.offset(-(self.buffer.addr as isize)) as u64 };
-        let slice_end = slice_start + slice.size;
-        let mut idx = 0;
-        for i in 0..self.ranges.len() {
-            idx = i;
-            let range = self.ranges[i];
-            if range.start <= slice_start {
-                continue;
-            }
-            if range.start == slice_end {
-                let mut new_start = slice_start;
-                if i > 0 {
-                    let prev_range = self.ranges[i - 1];
-                    if prev_range.end == slice_start {
-                        //  . <- remove
-                        // |f|f|o|o|
-                        new_start = prev_range.start;
-                        idx = i - 1;
-                        self.ranges.remove(idx);
-                    }
-                }
-                //  . <- extend backwards
-                // |f|o|o|
-                let range = &mut self.ranges[idx];
-                range.start = new_start;
-                return;
-            }
-            if i != 0 {
-                let prev_range = &mut self.ranges[i - 1];
-                if prev_range.end == slice_start {
-                    //  . <- extend forwards
-                    // |f|o|o|
-                    prev_range.end = slice_end;
-                    return;
-                }
-                //    . <- insert
-                // |o|f|o|
+        self.scopes.release(slice.offset);
+        self.free_range(slice.offset, slice.size);
+    }
+
+    fn destroy_scope(&mut self, id: usize) -> usize {
+        let outstanding = self.scopes.destroy(id);
+        for (offset, size) in &outstanding {
+            self.free_range(*offset, *size);
+        }
+        outstanding.len()
+    }
+
+    /*
+     * Bytes kept from other allocations for one of the size, the guard comes on top
+     * with the alloc-canaries feature.
+     */
+    fn reserved_size(size: u64) -> u64 {
+        #[cfg(feature = "alloc-canaries")]
+        return CanaryTable::reserved_size(size);
+        #[cfg(not(feature = "alloc-canaries"))]
+        size
+    }
+
+    fn free_range(&mut self, offset: u64, size: u64) {
+        #[cfg(feature = "alloc-canaries")]
+        {
+            let memory = unsafe { mapped(&self.buffer) };
+            if let Err(e) = self.canaries.freed(memory, offset) {
+                log::error!("{}", e);
             }
-            //  . <- insert
-            // |f|o|o|
-            break;
         }
-        self.ranges.insert(
-            idx,
-            Range {
-                start: slice_start,
-                end: slice_end,
-            },
-        );
+        self.ranges.free(offset, Self::reserved_size(size));
     }
 
     fn destroy(&self, device: &ash::Device) {
@@ -374,6 +571,15 @@ impl InnerDeviceAllocator {
     }
 
     fn available(&self) -> u64 {
-        self.ranges.iter().map(|r| r.size()).sum()
+        self.ranges.available()
     }
 }
+
+/*
+ * SAFETY: The whole mapping of the buffer, callers mustn't keep it past the buffer or
+ * hold two of them at once.
+ */
+#[cfg(feature = "alloc-canaries")]
+unsafe fn mapped<'a>(buffer: &DeviceBuffer) -> &'a mut [u8] {
+    std::slice::from_raw_parts_mut(buffer.addr as *mut u8, buffer.size as usize)
+}
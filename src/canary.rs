@@ -0,0 +1,227 @@
+/*
+ * Guard bytes after every allocation of a DeviceAllocator, to catch host code writing
+ * past the end of a DeviceSlice. Only with the alloc-canaries feature. The guard takes
+ * GUARD_SIZE bytes right after the aligned size of the slice, outside of it: the size,
+ * offset and alignment of the slice stay what they would be without the feature, only
+ * the range kept from other allocations grows.
+ *
+ * Guards get checked when their allocation is freed and by
+ * DeviceAllocator::check_canaries, see Renderer::check_canaries. Trampled ones get
+ * reported once and armed again. Fresh allocations get filled with FRESH and freed ones
+ * with FREED, both read as magenta-ish vec4s, so reading memory nothing wrote or that
+ * got freed shows up on screen. Freed ranges get checked too until something gets
+ * allocated over them, catching writes through slices kept after freeing them.
+ */
+use std::collections::BTreeMap;
+
+pub const GUARD_SIZE: u64 = 16;
+pub const GUARD: [u8; GUARD_SIZE as usize] = [
+    0xca, 0xfe, 0xf0, 0x0d, 0xde, 0xad, 0xbe, 0xef, 0xca, 0xfe, 0xf0, 0x0d, 0xde, 0xad, 0xbe, 0xef,
+];
+// vec4(1, 0, 1, 1)
+pub const FRESH: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+// vec4(0.5, 0, 1, 1)
+pub const FREED: [f32; 4] = [0.5, 0.0, 1.0, 1.0];
+
+///
+/// What a check found, owners being where the allocations were made.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CanaryError {
+    // Something wrote past the end of the allocation, likely through its slice. next is
+    // the allocation right after the guard, hit too if the write went on
+    Trampled {
+        owner: String,
+        offset: u64,
+        size: u64,
+        // First overwritten byte of the guard
+        byte: u64,
+        next: Option<String>,
+    },
+    // Something wrote into the allocation after it was freed
+    WrittenAfterFree {
+        owner: String,
+        offset: u64,
+        size: u64,
+        // First overwritten byte of the allocation
+        byte: u64,
+    },
+}
+
+impl std::fmt::Display for CanaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CanaryError::Trampled {
+                owner,
+                offset,
+                size,
+                byte,
+                next,
+            } => {
+                write!(
+                    f,
+                    "guard of the {} bytes at {} from {} overwritten at byte {}, \
+                    written past the end of it",
+                    size, offset, owner, byte
+                )?;
+                match next {
+                    Some(next) => write!(f, " and maybe into the one from {}", next),
+                    None => Ok(()),
+                }
+            }
+            CanaryError::WrittenAfterFree {
+                owner,
+                offset,
+                size,
+                byte,
+            } => write!(
+                f,
+                "the {} bytes at {} from {} were written at byte {} after being freed",
+                size, offset, owner, byte
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CanaryError {}
+
+struct Guarded {
+    size: u64,
+    owner: String,
+}
+
+///
+/// Guards and freed ranges of a buffer, by offset. Takes the mapped memory of the whole
+/// buffer on every call.
+///
+#[derive(Default)]
+pub struct CanaryTable {
+    live: BTreeMap<u64, Guarded>,
+    freed: BTreeMap<u64, Guarded>,
+}
+
+impl CanaryTable {
+    ///
+    /// Bytes to keep for an allocation of the size, guard included.
+    ///
+    pub fn reserved_size(size: u64) -> u64 {
+        size + GUARD_SIZE
+    }
+
+    ///
+    /// Fills the freshly allocated range with FRESH and arms its guard. Freed ranges it
+    /// overlaps aren't checked anymore.
+    ///
+    pub fn allocated(&mut self, memory: &mut [u8], offset: u64, size: u64, owner: String) {
+        let end = offset + Self::reserved_size(size);
+        let overlapping: Vec<_> = self
+            .freed
+            .range(..end)
+            .filter(|(start, e)| **start + e.size > offset)
+            .map(|(start, _)| *start)
+            .collect();
+        for start in overlapping {
+            self.freed.remove(&start);
+        }
+        fill(&mut memory[range(offset, size)], &FRESH);
+        memory[range(offset + size, GUARD_SIZE)].copy_from_slice(&GUARD);
+        self.live.insert(offset, Guarded { size, owner });
+    }
+
+    ///
+    /// Checks the guard of the allocation and fills it, guard included, with FREED.
+    /// Panics if nothing was allocated at the offset.
+    ///
+    pub fn freed(&mut self, memory: &mut [u8], offset: u64) -> Result<(), CanaryError> {
+        let checked = self.check_guard(memory, offset);
+        let guarded = self
+            .live
+            .remove(&offset)
+            .unwrap_or_else(|| panic!("no allocation at {} to free!", offset));
+        let reserved = Self::reserved_size(guarded.size);
+        fill(&mut memory[range(offset, reserved)], &FREED);
+        self.freed.insert(
+            offset,
+            Guarded {
+                size: reserved,
+                owner: guarded.owner,
+            },
+        );
+        checked
+    }
+
+    ///
+    /// Checks every guard and every freed range, arming trampled guards and poisoning
+    /// written freed ranges again so each write gets reported once.
+    ///
+    pub fn check(&mut self, memory: &mut [u8]) -> Vec<CanaryError> {
+        let mut errors = Vec::new();
+        let offsets: Vec<_> = self.live.keys().copied().collect();
+        for offset in offsets {
+            if let Err(e) = self.check_guard(memory, offset) {
+                let size = self.live[&offset].size;
+                memory[range(offset + size, GUARD_SIZE)].copy_from_slice(&GUARD);
+                errors.push(e);
+            }
+        }
+        for (offset, freed) in &self.freed {
+            let bytes = &mut memory[range(*offset, freed.size)];
+            if let Some(byte) = first_mismatch(bytes, &FREED) {
+                fill(bytes, &FREED);
+                errors.push(CanaryError::WrittenAfterFree {
+                    owner: freed.owner.clone(),
+                    offset: *offset,
+                    size: freed.size,
+                    byte,
+                });
+            }
+        }
+        errors
+    }
+
+    fn check_guard(&self, memory: &[u8], offset: u64) -> Result<(), CanaryError> {
+        let guarded = match self.live.get(&offset) {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+        let guard = &memory[range(offset + guarded.size, GUARD_SIZE)];
+        let byte = match guard.iter().zip(GUARD).position(|(a, b)| *a != b) {
+            Some(e) => e as u64,
+            None => return Ok(()),
+        };
+        let next = self
+            .live
+            .range(offset + 1..)
+            .next()
+            .map(|(_, e)| e.owner.clone());
+        Err(CanaryError::Trampled {
+            owner: guarded.owner.clone(),
+            offset,
+            size: guarded.size,
+            byte,
+            next,
+        })
+    }
+}
+
+fn range(offset: u64, size: u64) -> std::ops::Range<usize> {
+    offset as usize..(offset + size) as usize
+}
+
+fn pattern_byte(pattern: &[f32; 4], i: usize) -> u8 {
+    pattern[i / 4 % 4].to_ne_bytes()[i % 4]
+}
+
+fn fill(bytes: &mut [u8], pattern: &[f32; 4]) {
+    for (i, e) in bytes.iter_mut().enumerate() {
+        *e = pattern_byte(pattern, i);
+    }
+}
+
+fn first_mismatch(bytes: &[u8], pattern: &[f32; 4]) -> Option<u64> {
+    bytes
+        .iter()
+        .enumerate()
+        .position(|(i, e)| *e != pattern_byte(pattern, i))
+        .map(|e| e as u64)
+}
@@ -0,0 +1,142 @@
+/*
+ * Optional device features get enabled when the device supports them, pipeline files
+ * list the ones their stages can't do without in "requires". Matching both when loading
+ * fails with every missing feature at once, instead of at whichever vkCreate call first
+ * trips over one.
+ */
+use ash::vk;
+use serde::{Deserialize, Serialize};
+
+///
+/// Optional features a pipeline file can require, named like DeviceCapabilities
+/// serializes them.
+///
+#[derive(
+    Deserialize, Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, strum_macros::Display,
+)]
+#[serde(rename_all = "camelCase")]
+#[strum(serialize_all = "camelCase")]
+pub enum DeviceFeature {
+    Multiview,
+    FragmentShadingRate,
+    ShadingRateAttachment,
+    DepthBounds,
+    DepthClamp,
+    WideLines,
+    SamplerAnisotropy,
+    DescriptorBuffer,
+    SwapchainMutableFormat,
+    NullDescriptor,
+    RobustImageAccess,
+    ExternalSemaphore,
+    SparseResidency,
+    DisplayTiming,
+}
+
+///
+/// Optional features and extensions the device was created with, see
+/// Renderer::capabilities. Serializes to JSON for bug reports, under the names pipeline
+/// files require them by.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCapabilities {
+    #[serde(rename = "multiview")]
+    pub is_multiview_enabled: bool,
+    // Without it stages declaring 2x2 or attachment rates shade at 1x1
+    #[serde(rename = "fragmentShadingRate")]
+    pub is_shading_rate_enabled: bool,
+    // Pixels each texel of a shading rate image covers, None if attachment rates shade at 1x1
+    #[serde(serialize_with = "serialize_extent")]
+    pub shading_rate_texel_size: Option<vk::Extent2D>,
+    #[serde(rename = "depthBounds")]
+    pub is_depth_bounds_enabled: bool,
+    #[serde(rename = "depthClamp")]
+    pub is_depth_clamp_enabled: bool,
+    #[serde(rename = "wideLines")]
+    pub is_wide_lines_enabled: bool,
+    #[serde(rename = "samplerAnisotropy")]
+    pub is_sampler_anisotropy_enabled: bool,
+    #[serde(rename = "descriptorBuffer")]
+    pub is_descriptor_buffer_enabled: bool,
+    #[serde(rename = "swapchainMutableFormat")]
+    pub is_swapchain_mutable_format_enabled: bool,
+    #[serde(rename = "nullDescriptor")]
+    pub is_null_descriptor_enabled: bool,
+    #[serde(rename = "robustImageAccess")]
+    pub is_robust_image_access_enabled: bool,
+    // Timeline semaphores exportable as an fd, or a Win32 handle on Windows
+    #[serde(rename = "externalSemaphore")]
+    pub is_external_semaphore_enabled: bool,
+    // Partially resident 2D textures whose unbound pages read as zeros
+    #[serde(rename = "sparseResidency")]
+    pub is_sparse_residency_enabled: bool,
+    // Presentation timestamps and target present times through VK_GOOGLE_display_timing
+    #[serde(rename = "displayTiming")]
+    pub is_display_timing_enabled: bool,
+}
+
+fn serialize_extent<S: serde::Serializer>(
+    extent: &Option<vk::Extent2D>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    extent.map(|e| [e.width, e.height]).serialize(serializer)
+}
+
+impl DeviceCapabilities {
+    pub fn has(&self, feature: DeviceFeature) -> bool {
+        match feature {
+            DeviceFeature::Multiview => self.is_multiview_enabled,
+            DeviceFeature::FragmentShadingRate => self.is_shading_rate_enabled,
+            DeviceFeature::ShadingRateAttachment => self.shading_rate_texel_size.is_some(),
+            DeviceFeature::DepthBounds => self.is_depth_bounds_enabled,
+            DeviceFeature::DepthClamp => self.is_depth_clamp_enabled,
+            DeviceFeature::WideLines => self.is_wide_lines_enabled,
+            DeviceFeature::SamplerAnisotropy => self.is_sampler_anisotropy_enabled,
+            DeviceFeature::DescriptorBuffer => self.is_descriptor_buffer_enabled,
+            DeviceFeature::SwapchainMutableFormat => self.is_swapchain_mutable_format_enabled,
+            DeviceFeature::NullDescriptor => self.is_null_descriptor_enabled,
+            DeviceFeature::RobustImageAccess => self.is_robust_image_access_enabled,
+            DeviceFeature::ExternalSemaphore => self.is_external_semaphore_enabled,
+            DeviceFeature::SparseResidency => self.is_sparse_residency_enabled,
+            DeviceFeature::DisplayTiming => self.is_display_timing_enabled,
+        }
+    }
+}
+
+///
+/// Features a pipeline file requires that the device lacks, each with the stages needing
+/// it. Requirements of the whole pipeline are listed as needed by "the pipeline".
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingFeatures {
+    pub missing: Vec<(DeviceFeature, Vec<String>)>,
+}
+
+impl std::fmt::Display for MissingFeatures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the device lacks features the pipeline requires:")?;
+        for (feature, stages) in &self.missing {
+            write!(f, " {} for {};", feature, stages.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingFeatures {}
+
+///
+/// Returned by calls needing an optional feature the device was created without.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CapabilityError {
+    pub feature: DeviceFeature,
+}
+
+impl std::fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the device was created without {}", self.feature)
+    }
+}
+
+impl std::error::Error for CapabilityError {}
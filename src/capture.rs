@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+///
+/// Returned when a capture is requested but RenderDoc isn't loaded into the process,
+/// or the crate was built without the renderdoc feature.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureUnavailable;
+
+impl std::fmt::Display for CaptureUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "frame capture unavailable, RenderDoc isn't loaded")
+    }
+}
+
+impl std::error::Error for CaptureUnavailable {}
+
+///
+/// Renderer state a replay of captured frames has to set again to render them the same,
+/// which RenderDoc captures don't hold. Textures go by id, the replay has to make them
+/// at the same ids first, see Renderer::gen_texture_with_id. Stored as JSON.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapturedState {
+    pub version: u32,
+    // Frame being prepared when it got captured
+    pub frame: u64,
+    // Texture id and the sampler its override picks, by texture id
+    pub sampler_overrides: Vec<(u32, u8)>,
+    pub inspected_texture: Option<u32>,
+}
+
+impl CapturedState {
+    pub const VERSION: u32 = 1;
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CaptureFormatError> {
+        let state: Self =
+            serde_json::from_str(json).map_err(|e| CaptureFormatError::Syntax(e.to_string()))?;
+        if state.version != Self::VERSION {
+            return Err(CaptureFormatError::Version(state.version));
+        }
+        Ok(state)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureFormatError {
+    Syntax(String),
+    // Captured by a version of the crate writing another one
+    Version(u32),
+}
+
+impl std::fmt::Display for CaptureFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureFormatError::Syntax(e) => write!(f, "invalid captured state: {}", e),
+            CaptureFormatError::Version(version) => write!(
+                f,
+                "captured state of version {}, only {} is supported",
+                version,
+                CapturedState::VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CaptureFormatError {}
+
+pub struct FrameCapture {
+    #[cfg(feature = "renderdoc")]
+    renderdoc: Option<renderdoc::RenderDoc>,
+}
+
+impl Default for FrameCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "renderdoc")]
+            renderdoc: renderdoc::RenderDoc::find(),
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.renderdoc.is_some();
+        #[cfg(not(feature = "renderdoc"))]
+        return false;
+    }
+
+    ///
+    /// Captures the next num_frames frames.
+    ///
+    pub fn trigger(&self, num_frames: u32) -> Result<(), CaptureUnavailable> {
+        #[cfg(feature = "renderdoc")]
+        if let Some(renderdoc) = &self.renderdoc {
+            renderdoc.trigger(num_frames);
+            return Ok(());
+        }
+        let _ = num_frames;
+        Err(CaptureUnavailable)
+    }
+}
+
+#[cfg(feature = "renderdoc")]
+mod renderdoc {
+    use std::ffi::c_void;
+
+    // eRENDERDOC_API_Version_1_1_2, first one with TriggerMultiFrameCapture
+    const API_VERSION: i32 = 10102;
+    // Entry points in the API table before TriggerMultiFrameCapture
+    const ENTRIES_BEFORE_TRIGGER: usize = 22;
+
+    #[cfg(unix)]
+    const LIB_NAME: &str = "librenderdoc.so";
+    #[cfg(windows)]
+    const LIB_NAME: &str = "renderdoc.dll";
+
+    type GetApiFn = unsafe extern "C" fn(version: i32, out_api: *mut *mut c_void) -> i32;
+
+    #[repr(C)]
+    struct Api {
+        _unused: [*const c_void; ENTRIES_BEFORE_TRIGGER],
+        trigger_multi_frame_capture: unsafe extern "C" fn(num_frames: u32),
+    }
+
+    pub struct RenderDoc {
+        // Keeps the module referenced while the API table is in use
+        _lib: libloading::Library,
+        api: *const Api,
+    }
+
+    /*
+     * SAFETY: The RenderDoc in-application API can be called from any thread.
+     */
+    unsafe impl Send for RenderDoc {}
+
+    impl RenderDoc {
+        ///
+        /// Looks for an already injected RenderDoc, it's never loaded here since it
+        /// has to hook the Vulkan loader before the instance is created.
+        ///
+        pub fn find() -> Option<Self> {
+            let lib = match open_already_loaded() {
+                Ok(lib) => lib,
+                Err(_) => {
+                    log::debug!("{} isn't loaded, frame capture unavailable", LIB_NAME);
+                    return None;
+                }
+            };
+            let mut api: *mut c_void = std::ptr::null_mut();
+            let is_found = unsafe {
+                let get_api = lib.get::<GetApiFn>(b"RENDERDOC_GetAPI\0").ok()?;
+                get_api(API_VERSION, &mut api) == 1
+            };
+            if !is_found || api.is_null() {
+                log::warn!("{} is loaded but didn't provide its API!", LIB_NAME);
+                return None;
+            }
+            log::info!("RenderDoc found, frame capture available");
+            Some(Self {
+                _lib: lib,
+                api: api as *const Api,
+            })
+        }
+
+        pub fn trigger(&self, num_frames: u32) {
+            unsafe { ((*self.api).trigger_multi_frame_capture)(num_frames) }
+        }
+    }
+
+    #[cfg(unix)]
+    fn open_already_loaded() -> Result<libloading::Library, libloading::Error> {
+        // Linux and Android value, the only unix platforms RenderDoc runs on
+        const RTLD_NOLOAD: i32 = 0x4;
+        unsafe {
+            libloading::os::unix::Library::open(
+                Some(LIB_NAME),
+                libloading::os::unix::RTLD_NOW | RTLD_NOLOAD,
+            )
+        }
+        .map(Into::into)
+    }
+
+    #[cfg(windows)]
+    fn open_already_loaded() -> Result<libloading::Library, libloading::Error> {
+        libloading::os::windows::Library::open_already_loaded(LIB_NAME).map(Into::into)
+    }
+}
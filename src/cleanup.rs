@@ -0,0 +1,44 @@
+/*
+ * Objects created while making a render core or a renderer, destroyed in reverse order
+ * if making it panics half way through. Disarmed once whatever owns them from then on
+ * exists, its destroy takes them down after that.
+ */
+pub struct Cleanup {
+    steps: Vec<Box<dyn FnOnce()>>,
+}
+
+impl Cleanup {
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    ///
+    /// Runs the step if the cleanup gets dropped before being disarmed, before every step
+    /// deferred earlier. Steps run while unwinding, they can't panic.
+    ///
+    pub fn defer(&mut self, step: impl FnOnce() + 'static) {
+        self.steps.push(Box::new(step));
+    }
+
+    pub fn disarm(mut self) {
+        self.steps.clear();
+    }
+}
+
+impl Default for Cleanup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        if self.steps.is_empty() {
+            return;
+        }
+        log::warn!("destroying {} partially made objects", self.steps.len());
+        while let Some(step) = self.steps.pop() {
+            step();
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::time::Duration;
+
+use crate::{
+    adapter::AdapterSelector, deterministic::Deterministic, format::Format,
+    handle::{MeshHandle, SceneSlotId}, noise::NoiseConfig, render_task::TaskKind,
+    scaling::UpscaleFilter, shader_resource::ResourceKind,
+    vertex_layout::{VertexAttributeKind, VertexLayoutKind}, UsedAsIndex,
+};
+
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    // Tasks queued past these limits in a single frame get rejected
+    pub max_tasks_per_kind: [u32; TaskKind::MAX_LEN],
+    pub max_tasks_total: u32,
+    // Tasks per kind frame regions get sized for in the pipeline budget, only read on creation
+    pub expected_tasks_per_kind: [u32; TaskKind::MAX_LEN],
+    // Applies to textures generated from now on
+    pub upload_queue: UploadQueue,
+    // Only read by make_renderer_with_config, changing it afterwards does nothing
+    pub descriptor_mode: DescriptorMode,
+    // Same as descriptor_mode
+    pub adapter: AdapterSelector,
+    // Bytes of texture and mesh uploads recorded on the graphics queue per frame
+    pub upload_bytes_per_frame: Option<u64>,
+    // Mesh upload data staged but not copied yet, writes past it wait for a frame
+    pub mesh_staging_bytes: u64,
+    // Draw data of the frames in flight, out of the general buffer and only read on creation
+    pub frame_ring_bytes: u64,
+    pub idle_frames: IdleFrames,
+    // Frames an object id goes unseen before its previous transforms are dropped
+    pub previous_transform_lifetime: u64,
+    // How long render waits for a swapchain image before skipping the frame
+    pub acquire_timeout: Duration,
+    // Bounds of meshes generated with data or uploaded in chunks, see Renderer::mesh_bounds
+    pub is_mesh_bounds_computed: bool,
+    // Size of the readback buffer, only read by the first Renderer::read_buffer making it
+    pub readback_bytes: u64,
+    // Staging buffer of texture loaders, only read by the first Renderer::texture_loader
+    pub loader_staging_bytes: u64,
+    // Suboptimal frames in a row after which the swapchain gets rebuilt before the next
+    pub suboptimal_frames_before_rebuild: u32,
+    // Only reports suboptimal frames, the host calls Renderer::rebuild_swapchain itself
+    pub is_manual_swapchain_rebuild: bool,
+    // Stages render at this size and get scaled into the swapchain image, only read on creation
+    pub internal_resolution: Option<(u32, u32)>,
+    pub upscale_filter: UpscaleFilter,
+    // Around the scaled image when its aspect differs from the one of the window
+    pub letterbox_color: [f32; 4],
+    // Attempts after the first that queued work out of room gets before failing for good
+    pub retry_limit: u32,
+    // Retried work taken back per queue each frame, ahead of the work queued fresh
+    pub retried_work_per_frame: u32,
+    // Blue noise textures at the reserved ids, only read on creation
+    pub noise: NoiseConfig,
+    // Widest and tallest render_to_texture renders at, larger passes get rejected
+    pub max_offscreen_extent: u32,
+    // Time each frame spends stepping operations, see Renderer::start_operation
+    pub operation_budget: Duration,
+    // Shared presentable swapchain image without vsync where supported, a retained copy of
+    // the last frame otherwise, see low_latency
+    pub low_latency_overlay: bool,
+    // Stages with a budget priority get skipped on frames predicted to take longer, see
+    // frame_budget
+    pub gpu_frame_budget: Option<Duration>,
+    // Drops state commands setting what's already set, off only to compare, see state_cache
+    pub is_state_cache_enabled: bool,
+    // Keeps a buffer shaders report the mips they sample into, the streaming and eviction
+    // of textures go by it, see texture_feedback
+    pub is_texture_feedback_enabled: bool,
+    // Reads of attachments and named buffers before anything wrote them, only read on
+    // creation, see UninitializedReads
+    pub uninitialized_reads: UninitializedReads,
+    // Same frames for the same calls whatever the timing, see deterministic
+    pub deterministic: Option<Deterministic>,
+    // Blends simulation transforms as dual quaternions, shaders get them packed that way
+    #[cfg(feature = "dual-quaternion")]
+    pub is_dual_quaternion_interpolation: bool,
+}
+
+///
+/// Where texture uploads get recorded. The async ones fall back to the graphics queue
+/// when the device has no compute queue family separate from graphics.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UploadQueue {
+    Graphics,
+    // Textures stay exclusive and change owner to graphics once uploaded
+    #[default]
+    AsyncExclusive,
+    // Textures are shared by both queues, no ownership transfers
+    AsyncConcurrent,
+}
+
+///
+/// What render does with frames without any queued task or pending texture upload. Keep
+/// presents the swapchain image with what it held the last time it was presented, which
+/// is some frames ago with more than one image, and clears images never presented.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IdleFrames {
+    // Records every stage like any other frame
+    #[default]
+    Render,
+    Clear([f32; 4]),
+    Keep,
+}
+
+///
+/// Where descriptor tables live. Auto uses descriptor buffers when the device supports
+/// VK_EXT_descriptor_buffer and classic descriptor sets otherwise.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DescriptorMode {
+    #[default]
+    Auto,
+    // Panics on devices without the extension
+    DescriptorBuffer,
+    DescriptorSets,
+}
+
+///
+/// What creating a renderer does about passes reading attachments or named buffers
+/// without an initialState before any pass of the frame wrote them, see
+/// file::Pipeline::uninitialized_reads.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UninitializedReads {
+    // Panics listing all of them
+    #[default]
+    Error,
+    // Zeroes them before the first frame, warning about each
+    Zero,
+}
+
+impl RendererConfig {
+    pub const DEFAULT_MAX_TASKS_PER_KIND: u32 = 64 * 1024;
+    pub const DEFAULT_MAX_TASKS_TOTAL: u32 = 256 * 1024;
+    pub const DEFAULT_EXPECTED_TASKS_PER_KIND: u32 = 1024;
+    pub const DEFAULT_MESH_STAGING_BYTES: u64 = 16 * 1024 * 1024;
+    pub const DEFAULT_FRAME_RING_BYTES: u64 = 16 * 1024 * 1024;
+    pub const DEFAULT_PREVIOUS_TRANSFORM_LIFETIME: u64 = 8;
+    pub const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_millis(100);
+    pub const DEFAULT_READBACK_BYTES: u64 = 4 * 1024 * 1024;
+    pub const DEFAULT_LOADER_STAGING_BYTES: u64 = 64 * 1024 * 1024;
+    pub const DEFAULT_SUBOPTIMAL_FRAMES_BEFORE_REBUILD: u32 = 3;
+    pub const DEFAULT_RETRY_LIMIT: u32 = 8;
+    pub const DEFAULT_RETRIED_WORK_PER_FRAME: u32 = 16;
+    pub const DEFAULT_MAX_OFFSCREEN_EXTENT: u32 = 4096;
+    pub const DEFAULT_OPERATION_BUDGET: Duration = Duration::from_millis(2);
+
+    pub fn max_tasks_for(&self, kind: TaskKind) -> u32 {
+        self.max_tasks_per_kind[kind.to_usize()]
+    }
+
+    pub fn set_max_tasks_for(&mut self, kind: TaskKind, max: u32) {
+        self.max_tasks_per_kind[kind.to_usize()] = max;
+    }
+
+    ///
+    /// Whether one more task of the kind fits the budgets, with queued tasks of the kind
+    /// and queued_total of every kind already in the frame.
+    ///
+    pub fn check_task_budget(
+        &self,
+        kind: TaskKind,
+        queued: usize,
+        queued_total: usize,
+    ) -> Result<(), TaskRejected> {
+        if queued >= self.max_tasks_for(kind) as usize {
+            return Err(TaskRejected::KindBudget {
+                kind,
+                limit: self.max_tasks_for(kind),
+            });
+        }
+        if queued_total >= self.max_tasks_total as usize {
+            return Err(TaskRejected::TotalBudget {
+                limit: self.max_tasks_total,
+            });
+        }
+        Ok(())
+    }
+}
+
+///
+/// Tasks to drop from the end of each batch so the draw data of the frame fits in the
+/// budget, given the bytes of every queued task by kind and the bytes taken regardless
+/// of the tasks. The kind taking the most bytes loses its newest task first.
+///
+pub fn tasks_to_trim(task_sizes: &[Vec<u64>], fixed_size: u64, budget: u64) -> Vec<usize> {
+    let mut sizes_by_kind: Vec<u64> = task_sizes.iter().map(|e| e.iter().sum()).collect();
+    let mut trimmed = vec![0; task_sizes.len()];
+    let mut total_size = fixed_size + sizes_by_kind.iter().sum::<u64>();
+    while total_size > budget {
+        let kind = match sizes_by_kind
+            .iter()
+            .enumerate()
+            .filter(|e| *e.1 > 0)
+            .max_by_key(|e| *e.1)
+        {
+            Some((kind, _)) => kind,
+            None => break,
+        };
+        let sizes = &task_sizes[kind];
+        let size = sizes[sizes.len() - 1 - trimmed[kind]];
+        sizes_by_kind[kind] -= size;
+        total_size -= size;
+        trimmed[kind] += 1;
+    }
+    trimmed
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            max_tasks_per_kind: [Self::DEFAULT_MAX_TASKS_PER_KIND; TaskKind::MAX_LEN],
+            max_tasks_total: Self::DEFAULT_MAX_TASKS_TOTAL,
+            expected_tasks_per_kind: [Self::DEFAULT_EXPECTED_TASKS_PER_KIND; TaskKind::MAX_LEN],
+            upload_queue: UploadQueue::default(),
+            descriptor_mode: DescriptorMode::default(),
+            adapter: AdapterSelector::default(),
+            upload_bytes_per_frame: None,
+            mesh_staging_bytes: Self::DEFAULT_MESH_STAGING_BYTES,
+            frame_ring_bytes: Self::DEFAULT_FRAME_RING_BYTES,
+            idle_frames: IdleFrames::default(),
+            previous_transform_lifetime: Self::DEFAULT_PREVIOUS_TRANSFORM_LIFETIME,
+            acquire_timeout: Self::DEFAULT_ACQUIRE_TIMEOUT,
+            is_mesh_bounds_computed: true,
+            readback_bytes: Self::DEFAULT_READBACK_BYTES,
+            loader_staging_bytes: Self::DEFAULT_LOADER_STAGING_BYTES,
+            suboptimal_frames_before_rebuild: Self::DEFAULT_SUBOPTIMAL_FRAMES_BEFORE_REBUILD,
+            is_manual_swapchain_rebuild: false,
+            internal_resolution: None,
+            upscale_filter: UpscaleFilter::default(),
+            letterbox_color: [0.0, 0.0, 0.0, 1.0],
+            retry_limit: Self::DEFAULT_RETRY_LIMIT,
+            retried_work_per_frame: Self::DEFAULT_RETRIED_WORK_PER_FRAME,
+            noise: NoiseConfig::default(),
+            max_offscreen_extent: Self::DEFAULT_MAX_OFFSCREEN_EXTENT,
+            operation_budget: Self::DEFAULT_OPERATION_BUDGET,
+            low_latency_overlay: false,
+            gpu_frame_budget: None,
+            is_state_cache_enabled: true,
+            is_texture_feedback_enabled: false,
+            uninitialized_reads: UninitializedReads::default(),
+            deterministic: None,
+            #[cfg(feature = "dual-quaternion")]
+            is_dual_quaternion_interpolation: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskRejected {
+    // Too many tasks of this kind queued for the frame
+    KindBudget {
+        kind: TaskKind,
+        limit: u32,
+    },
+    // Too many tasks queued for the frame overall
+    TotalBudget {
+        limit: u32,
+    },
+    // Mesh was freed before the task got queued
+    StaleMesh {
+        mesh: MeshHandle,
+    },
+    // Mesh has uploads that aren't complete yet
+    MeshUploading {
+        mesh: MeshHandle,
+    },
+    // Tasks of this kind can't be drawn without the resource
+    MissingResource {
+        kind: TaskKind,
+        resource: ResourceKind,
+    },
+    // Some stage drawing tasks of this kind doesn't accept the layout of the mesh
+    VertexLayout {
+        kind: TaskKind,
+        mesh: MeshHandle,
+        layout: VertexLayoutKind,
+    },
+    // Some stage drawing tasks of this kind doesn't read the attribute in the format the
+    // mesh holds it in
+    VertexFormat {
+        kind: TaskKind,
+        mesh: MeshHandle,
+        attribute: VertexAttributeKind,
+        format: Format,
+    },
+    // Scene slot was freed before the task got queued
+    StaleSceneSlot {
+        slot: SceneSlotId,
+    },
+    // Scene slot doesn't hold the resource for exactly the instances of the task
+    SceneSlotSize {
+        resource: ResourceKind,
+        slot: SceneSlotId,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl std::fmt::Display for TaskRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskRejected::KindBudget { kind, limit } => {
+                write!(f, "task budget of {} for {} tasks exceeded", limit, kind)
+            }
+            TaskRejected::TotalBudget { limit } => {
+                write!(f, "total task budget of {} exceeded", limit)
+            }
+            TaskRejected::StaleMesh { mesh } => {
+                write!(f, "mesh {} was freed", mesh)
+            }
+            TaskRejected::MeshUploading { mesh } => {
+                write!(f, "mesh {} is still uploading", mesh)
+            }
+            TaskRejected::MissingResource { kind, resource } => {
+                write!(f, "{} tasks need a {} resource", kind, resource)
+            }
+            TaskRejected::VertexLayout { kind, mesh, layout } => {
+                write!(
+                    f,
+                    "mesh {} is {}, not every stage drawing {} tasks accepts that layout",
+                    mesh, layout, kind
+                )
+            }
+            TaskRejected::VertexFormat {
+                kind,
+                mesh,
+                attribute,
+                format,
+            } => {
+                write!(
+                    f,
+                    "mesh {} holds its {} as {}, not every stage drawing {} tasks reads that format",
+                    mesh, attribute, format, kind
+                )
+            }
+            TaskRejected::StaleSceneSlot { slot } => {
+                write!(f, "scene slot {} was freed", slot)
+            }
+            TaskRejected::SceneSlotSize {
+                resource,
+                slot,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "scene slot {} holds {} bytes, {} for every instance takes {}",
+                    slot, found, resource, expected
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TaskRejected {}
@@ -2,6 +2,11 @@ use std::{any::TypeId, collections::HashMap};
 
 use ash::vk;
 
+use crate::{
+    capabilities::DeviceCapabilities, interop::ExternalSemaphoreFns,
+    pipeline::descriptor::DescriptorSizes,
+};
+
 #[derive(Clone)]
 pub struct VulkanContext {
     pub entry: ash::Entry,
@@ -10,14 +15,34 @@ pub struct VulkanContext {
     pub physical_device: ash::vk::PhysicalDevice,
     pub memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub extension: ExtensionContext,
+    // Optional features and extensions the device was created with
+    pub capabilities: DeviceCapabilities,
 }
 
 #[derive(Clone)]
 pub struct ExtensionContext {
-    pub descriptor_buffer: ash::extensions::ext::DescriptorBuffer,
+    // None when descriptors go into classic descriptor sets
+    pub descriptor_buffer: Option<ash::extensions::ext::DescriptorBuffer>,
+    // Queried along with it, None just the same
+    pub descriptor_sizes: Option<DescriptorSizes>,
     pub debug_utils: Option<ash::extensions::ext::DebugUtils>,
     pub swapchain: ash::extensions::khr::Swapchain,
     pub surface: ash::extensions::khr::Surface,
+    // VK_KHR_swapchain_mutable_format has no functions to load
+    pub is_swapchain_mutable_format_enabled: bool,
+    // Not an extension but a core feature, optional all the same
+    pub is_depth_bounds_enabled: bool,
+    // None when every stage shades at 1x1
+    pub fragment_shading_rate: Option<vk::KhrFragmentShadingRateFn>,
+    // Pixels per texel of shading rate images, None if attachment rates aren't supported
+    pub shading_rate_texel_size: Option<vk::Extent2D>,
+    // None when timeline semaphores can't be exported on this platform
+    pub external_semaphore: Option<ExternalSemaphoreFns>,
+    // None without VK_GOOGLE_display_timing, presents carry no ids nor target times then
+    pub display_timing: Option<vk::GoogleDisplayTimingFn>,
+    // Only loaded for low latency overlays, see low_latency::shared_present_usage
+    pub surface_capabilities2: Option<vk::KhrGetSurfaceCapabilities2Fn>,
+    pub is_shared_presentable_image_enabled: bool,
 }
 
 impl VulkanContext {
@@ -43,9 +68,68 @@ impl VulkanContext {
             })
             .map(|(index, _memory_type)| index as _)
     }
+
+    ///
+    /// Tries each set of property flags in order and returns the first memory type index
+    /// that matches, along with the full property flags of that memory type.
+    ///
+    pub fn memory_type_index_for_any(
+        &self,
+        requirement_bits: u32,
+        preferred_property_flags: &[vk::MemoryPropertyFlags],
+    ) -> Option<(u32, vk::MemoryPropertyFlags)> {
+        preferred_property_flags.iter().find_map(|&flags| {
+            self.memory_type_index_for(requirement_bits, flags)
+                .map(|index| {
+                    (
+                        index,
+                        self.memory_properties.memory_types[index as usize].property_flags,
+                    )
+                })
+        })
+    }
 }
 
 impl ExtensionContext {
+    ///
+    /// Opens a debug label region in the command buffer, visible in tools like RenderDoc.
+    /// Does nothing without debug utils.
+    ///
+    pub fn try_begin_label(&self, command_buffer: vk::CommandBuffer, name: &str) -> bool {
+        let dbg = match &self.debug_utils {
+            Some(dbg) => dbg,
+            None => return false,
+        };
+        let c_name = std::ffi::CString::new(name).unwrap();
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(&c_name)
+            .build();
+        unsafe { dbg.cmd_begin_debug_utils_label(command_buffer, &label) };
+        true
+    }
+
+    pub fn try_end_label(&self, command_buffer: vk::CommandBuffer) -> bool {
+        let dbg = match &self.debug_utils {
+            Some(dbg) => dbg,
+            None => return false,
+        };
+        unsafe { dbg.cmd_end_debug_utils_label(command_buffer) };
+        true
+    }
+
+    pub fn try_insert_label(&self, command_buffer: vk::CommandBuffer, name: &str) -> bool {
+        let dbg = match &self.debug_utils {
+            Some(dbg) => dbg,
+            None => return false,
+        };
+        let c_name = std::ffi::CString::new(name).unwrap();
+        let label = vk::DebugUtilsLabelEXT::builder()
+            .label_name(&c_name)
+            .build();
+        unsafe { dbg.cmd_insert_debug_utils_label(command_buffer, &label) };
+        true
+    }
+
     pub fn try_set_debug_name<T: 'static>(&self, device: &ash::Device, name: &str, obj: T) -> bool
     where
         T: vk::Handle,
@@ -0,0 +1,82 @@
+/*
+ * Words of debug flags shaders test bits of, for debug visualizations like showing
+ * normals or turning shadows off, without plumbing a resource through for each. There's
+ * one global word and one per stage, both set with Renderer::set_debug_flags:
+ *
+ *   uint64_t debugFlags;   in the frame constants, when the block has it, the global word
+ *   uint64_t debugFlags;   last in the StageConstants block, 8 byte aligned, the stage's
+ *
+ * Stages declaring constants get the StageConstants block anyway, others get it with
+ * "debugFlags": true on their pass. Both words get copied every frame like the rest of
+ * their blocks, so a frame sees the flags set before it was prepared and no pipeline
+ * gets rebuilt for a change. Bits can get names with Renderer::register_debug_flag for
+ * tools to list and toggle them by, the renderer itself doesn't look at any of them.
+ */
+use serde::Serialize;
+
+pub const MEMBER: &str = "debugFlags";
+pub const MAX_FLAGS: u32 = u64::BITS;
+
+///
+/// Named bit, see Renderer::list_debug_flags.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugFlag {
+    pub name: String,
+    pub bit: u32,
+    pub is_set_globally: bool,
+}
+
+///
+/// Names of the bits, in the order they were registered, and the global word.
+///
+#[derive(Clone, Debug, Default)]
+pub struct DebugFlags {
+    names: Vec<String>,
+    pub global: u64,
+}
+
+impl DebugFlags {
+    ///
+    /// Bit of the name, the next free one the first time the name comes up. Panics once
+    /// all MAX_FLAGS bits are taken.
+    ///
+    pub fn register(&mut self, name: &str) -> u32 {
+        if let Some(bit) = self.bit(name) {
+            return bit;
+        }
+        if self.names.len() as u32 == MAX_FLAGS {
+            panic!(
+                "can't register debug flag {}, all {} are taken!",
+                name, MAX_FLAGS
+            );
+        }
+        self.names.push(name.to_string());
+        self.names.len() as u32 - 1
+    }
+
+    pub fn bit(&self, name: &str) -> Option<u32> {
+        self.names.iter().position(|e| e == name).map(|e| e as u32)
+    }
+
+    pub fn list(&self) -> Vec<DebugFlag> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(bit, name)| DebugFlag {
+                name: name.clone(),
+                bit: bit as u32,
+                is_set_globally: self.global & (1 << bit) != 0,
+            })
+            .collect()
+    }
+}
+
+///
+/// Offset of the stage's word in a StageConstants block whose constants take size
+/// bytes.
+///
+pub fn offset_after(size: u32) -> u32 {
+    (size + 7) & !7
+}
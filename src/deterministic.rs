@@ -0,0 +1,46 @@
+/*
+ * Deterministic mode, for lockstep networking and replays, see
+ * RendererConfig::deterministic. Frames come out the same for the same calls, however
+ * long anything took to record, run or present:
+ *
+ *   Frame timing is fixed. The frame constants get the frame times frame_time, in
+ *   seconds, in a float time member when the block has one, over what the host wrote.
+ *   Nothing branches on timestamps. The quality governor and the frame budget get no
+ *   GPU timings and keep what they decided last, operations take a single turn each per
+ *   pump whatever the budget, and render waits for the swapchain image instead of
+ *   skipping the frame after RendererConfig::acquire_timeout.
+ *   Tasks of a stage draw in a stable order, the one they were queued in grouped by the
+ *   variant they draw with, see plan::order_tasks. That holds outside of deterministic
+ *   mode too.
+ *
+ * Ids of meshes and textures depend on internal allocations either way, hosts needing
+ * them stable take them with Renderer::gen_mesh_with_id and gen_texture_with_id.
+ */
+use std::time::Duration;
+
+pub const TIME_MEMBER: &str = "time";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deterministic {
+    // Time between one frame and the next, whatever it really was
+    pub frame_time: Duration,
+}
+
+impl Deterministic {
+    pub const DEFAULT_FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+
+    ///
+    /// Seconds since the first frame the time member of the frame gets.
+    ///
+    pub fn time_of(&self, frame: u64) -> f32 {
+        (self.frame_time.as_secs_f64() * frame as f64) as f32
+    }
+}
+
+impl Default for Deterministic {
+    fn default() -> Self {
+        Self {
+            frame_time: Self::DEFAULT_FRAME_TIME,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+/*
+ * Dual quaternion blending of simulation transforms, for users who can't live with the
+ * shrinking of rotating objects the affine lerp of motion::SimulationTransforms gives
+ * in between two states far apart. Rigid transforms only: the scale of each axis gets
+ * normalized away when converting, interpolated objects come out with a scale of 1.
+ *
+ * Shaders blending themselves read them packed with pack, real part then dual part,
+ * xyzw each. GLSL, matching to_affine and blend:
+ *
+ *   struct DualQuat { vec4 real; vec4 dual; };
+ *
+ *   DualQuat blendDualQuat(DualQuat a, DualQuat b, float t) {
+ *       // Shortest path, q and -q are the same rotation
+ *       float s = dot(a.real, b.real) < 0.0 ? -1.0 : 1.0;
+ *       vec4 real = mix(a.real, s * b.real, t);
+ *       vec4 dual = mix(a.dual, s * b.dual, t);
+ *       float len = length(real);
+ *       return DualQuat(real / len, dual / len);
+ *   }
+ *
+ *   vec4 quatMul(vec4 a, vec4 b) {
+ *       return vec4(a.w * b.xyz + b.w * a.xyz + cross(a.xyz, b.xyz),
+ *                   a.w * b.w - dot(a.xyz, b.xyz));
+ *   }
+ *
+ *   vec3 dualQuatTransform(DualQuat q, vec3 p) {
+ *       vec3 r = q.real.xyz;
+ *       vec3 rotated = p + 2.0 * cross(r, cross(r, p) + q.real.w * p);
+ *       vec4 conj = vec4(-q.real.xyz, q.real.w);
+ *       return rotated + 2.0 * quatMul(q.dual, conj).xyz;
+ *   }
+ */
+use glam::{Mat3, Quat, Vec3};
+
+use crate::motion::Affine;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DualQuat {
+    pub real: Quat,
+    pub dual: Quat,
+}
+
+impl DualQuat {
+    ///
+    /// Rotation and translation of the transform, its scale gets dropped.
+    ///
+    pub fn from_affine(m: &Affine) -> Self {
+        let axis = |c: usize| Vec3::new(m[c], m[4 + c], m[8 + c]).normalize();
+        let real = Quat::from_mat3(&Mat3::from_cols(axis(0), axis(1), axis(2))).normalize();
+        let t = Quat::from_xyzw(m[3], m[7], m[11], 0.0);
+        Self {
+            real,
+            dual: (t * real) * 0.5,
+        }
+    }
+
+    pub fn to_affine(&self) -> Affine {
+        let r = Mat3::from_quat(self.real);
+        let t = (self.dual * 2.0) * self.real.conjugate();
+        let (x, y, z) = (r.x_axis, r.y_axis, r.z_axis);
+        [
+            x.x, y.x, z.x, t.x, //
+            x.y, y.y, z.y, t.y, //
+            x.z, y.z, z.z, t.z,
+        ]
+    }
+
+    ///
+    /// Normalized linear blend from a to b, t past 0 and 1 extrapolates.
+    ///
+    pub fn blend(a: &Self, b: &Self, t: f32) -> Self {
+        // Shortest path, q and -q are the same rotation
+        let sign = if a.real.dot(b.real) < 0.0 { -1.0 } else { 1.0 };
+        let real = a.real * (1.0 - t) + b.real * (sign * t);
+        let dual = a.dual * (1.0 - t) + b.dual * (sign * t);
+        let len = real.length();
+        Self {
+            real: real * (1.0 / len),
+            dual: dual * (1.0 / len),
+        }
+    }
+
+    ///
+    /// Real part then dual part, xyzw each, the way shaders read them.
+    ///
+    pub fn pack(&self) -> [f32; 8] {
+        let (r, d) = (self.real, self.dual);
+        [r.x, r.y, r.z, r.w, d.x, d.y, d.z, d.w]
+    }
+}
+
+///
+/// Packed dual quaternions of the transforms, in order.
+///
+pub fn pack_all(transforms: &[Affine]) -> Vec<[f32; 8]> {
+    transforms
+        .iter()
+        .map(|e| DualQuat::from_affine(e).pack())
+        .collect()
+}
@@ -0,0 +1,494 @@
+use std::{collections::VecDeque, time::Duration};
+
+use ash::vk;
+use serde::Serialize;
+
+use crate::{
+    context::VulkanContext,
+    retry::{RetriedWork, RetryReason},
+    split_screen::MAX_VIEWPORTS,
+    stats::FramePath,
+};
+
+///
+/// Something that happened while rendering, handed to the event sink of the renderer
+/// as it happens. More kinds of events can show up in later versions.
+///
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum RenderEvent {
+    FrameStarted {
+        frame: u64,
+    },
+    // From the start of prepare_frame to the end of submit_frame
+    FrameEnded {
+        frame: u64,
+        path: FramePath,
+        cpu_time: Duration,
+    },
+    // Only with stage timing enabled in the sink
+    StageRecorded {
+        frame: u64,
+        stage: String,
+        cpu_time: Duration,
+    },
+    // Reported once the GPU is done with the frame, some frames after it was recorded
+    StageExecuted {
+        frame: u64,
+        stage: String,
+        gpu_time: Duration,
+    },
+    // Share of a StageExecuted drawing a split screen viewport, see split_screen
+    StageViewportExecuted {
+        frame: u64,
+        stage: String,
+        viewport: u32,
+        gpu_time: Duration,
+    },
+    // Stage left out over RendererConfig::gpu_frame_budget from this frame on, predicted
+    // is its GPU time, see frame_budget
+    StageSkipped {
+        frame: u64,
+        stage: String,
+        predicted: Duration,
+    },
+    StageRestored {
+        frame: u64,
+        stage: String,
+    },
+    AllocationFailed {
+        purpose: String,
+        size: u64,
+        available: u64,
+    },
+    TextureUploaded {
+        texture: u32,
+        name: String,
+    },
+    SwapchainRecreated {
+        width: u32,
+        height: u32,
+    },
+    // Made again at the new size of the swapchain without the contents of the previous
+    // image, applications accumulating into it start over, see file::PreserveMode
+    TargetReset {
+        target: String,
+        width: u32,
+        height: u32,
+    },
+    SwapchainOutOfDate {
+        frame: u64,
+    },
+    // No swapchain image within RendererConfig::acquire_timeout, the frame was skipped
+    AcquireTimedOut {
+        frame: u64,
+        consecutive: u32,
+    },
+    // Acquisition or presentation of the frame reported the swapchain as suboptimal
+    SwapchainSuboptimal {
+        frame: u64,
+        consecutive: u32,
+    },
+    DeviceLost {
+        frame: u64,
+    },
+    // Image memory is what went back to the driver, see Renderer::hibernate
+    Hibernated {
+        evicted_textures: u32,
+        released_image_memory: u64,
+    },
+    Resumed {
+        width: u32,
+        height: u32,
+        restored_textures: u32,
+    },
+    // Out of room, tried again on a later frame as attempt
+    WorkRetried {
+        work: RetriedWork,
+        reason: RetryReason,
+        attempt: u32,
+    },
+    // Out of room on every attempt, dropped, see Renderer::take_failed_work
+    WorkFailed {
+        work: RetriedWork,
+        reason: RetryReason,
+        attempts: u32,
+    },
+    // Ids are the ones of the OperationHandle, see Renderer::start_operation
+    OperationStarted {
+        operation: u64,
+        name: String,
+    },
+    OperationProgressed {
+        operation: u64,
+        progress: f32,
+    },
+    OperationCompleted {
+        operation: u64,
+    },
+    OperationCancelled {
+        operation: u64,
+    },
+}
+
+///
+/// Receives the events of a renderer, see Renderer::set_event_sink. Called right from
+/// the render path while it runs, so whatever a sink does adds to the frame time.
+///
+pub trait RenderEventSink: Send {
+    fn emit(&mut self, event: RenderEvent);
+
+    ///
+    /// Whether stages get timed on the CPU and the GPU. Timing costs two timestamps and
+    /// two events per stage every frame, so it's off unless a sink wants it.
+    ///
+    fn is_stage_timing_enabled(&self) -> bool {
+        false
+    }
+
+    ///
+    /// Events kept by the sink so far, oldest first. Sinks that don't keep any return
+    /// none.
+    ///
+    fn drain(&mut self) -> Vec<RenderEvent> {
+        Vec::new()
+    }
+}
+
+///
+/// Forwards every event to log, the default sink. Stages are timed only when trace
+/// logging is on.
+///
+#[derive(Default)]
+pub struct LogSink;
+
+impl RenderEventSink for LogSink {
+    fn emit(&mut self, event: RenderEvent) {
+        match event {
+            RenderEvent::FrameStarted { frame } => log::trace!("frame {} started", frame),
+            RenderEvent::FrameEnded {
+                frame,
+                path,
+                cpu_time,
+            } => log::trace!("frame {} ended on {:?} after {:?}", frame, path, cpu_time),
+            RenderEvent::StageRecorded {
+                frame,
+                stage,
+                cpu_time,
+            } => log::trace!("frame {} stage {} recorded in {:?}", frame, stage, cpu_time),
+            RenderEvent::StageExecuted {
+                frame,
+                stage,
+                gpu_time,
+            } => log::trace!("frame {} stage {} ran for {:?}", frame, stage, gpu_time),
+            RenderEvent::StageViewportExecuted {
+                frame,
+                stage,
+                viewport,
+                gpu_time,
+            } => log::trace!(
+                "frame {} stage {} ran for {:?} in viewport {}",
+                frame,
+                stage,
+                gpu_time,
+                viewport
+            ),
+            RenderEvent::StageSkipped {
+                frame,
+                stage,
+                predicted,
+            } => log::debug!(
+                "frame {} skips stage {}, predicted to run for {:?}",
+                frame,
+                stage,
+                predicted
+            ),
+            RenderEvent::StageRestored { frame, stage } => {
+                log::debug!("frame {} records stage {} again", frame, stage)
+            }
+            RenderEvent::AllocationFailed {
+                purpose,
+                size,
+                available,
+            } => log::error!(
+                "couldn't allocate '{}' buffer of size {}, {} available",
+                purpose,
+                size,
+                available
+            ),
+            RenderEvent::TextureUploaded { texture, name } => {
+                log::debug!("texture {} {} uploaded", texture, name)
+            }
+            RenderEvent::SwapchainRecreated { width, height } => {
+                log::debug!("swapchain recreated at {}x{}", width, height)
+            }
+            RenderEvent::TargetReset {
+                target,
+                width,
+                height,
+            } => log::debug!("target {} reset at {}x{}", target, width, height),
+            RenderEvent::SwapchainOutOfDate { frame } => {
+                log::debug!("swapchain out of date on frame {}", frame)
+            }
+            RenderEvent::AcquireTimedOut { frame, consecutive } => log::debug!(
+                "swapchain image acquisition timed out on frame {}, {} in a row",
+                frame,
+                consecutive
+            ),
+            RenderEvent::SwapchainSuboptimal { frame, consecutive } => log::debug!(
+                "swapchain suboptimal on frame {}, {} in a row",
+                frame,
+                consecutive
+            ),
+            RenderEvent::DeviceLost { frame } => log::error!("device lost on frame {}", frame),
+            RenderEvent::Hibernated {
+                evicted_textures,
+                released_image_memory,
+            } => log::info!(
+                "hibernated, evicted {} textures and released {} bytes of image memory",
+                evicted_textures,
+                released_image_memory
+            ),
+            RenderEvent::Resumed {
+                width,
+                height,
+                restored_textures,
+            } => log::info!(
+                "resumed at {}x{}, restoring {} textures",
+                width,
+                height,
+                restored_textures
+            ),
+            RenderEvent::WorkRetried {
+                work,
+                reason,
+                attempt,
+            } => log::debug!("{:?} retried, {}, attempt {}", work, reason, attempt),
+            RenderEvent::WorkFailed {
+                work,
+                reason,
+                attempts,
+            } => log::warn!("{:?} failed after {} attempts, {}", work, attempts, reason),
+            RenderEvent::OperationStarted { operation, name } => {
+                log::debug!("operation {} {} started", operation, name)
+            }
+            RenderEvent::OperationProgressed {
+                operation,
+                progress,
+            } => log::trace!("operation {} at {:.0}%", operation, progress * 100.0),
+            RenderEvent::OperationCompleted { operation } => {
+                log::debug!("operation {} completed", operation)
+            }
+            RenderEvent::OperationCancelled { operation } => {
+                log::debug!("operation {} cancelled", operation)
+            }
+        }
+    }
+
+    fn is_stage_timing_enabled(&self) -> bool {
+        log::log_enabled!(log::Level::Trace)
+    }
+}
+
+///
+/// Keeps the last capacity events for Renderer::drain_events, dropping the oldest ones
+/// once full. Times every stage.
+///
+pub struct RingBufferSink {
+    events: VecDeque<RenderEvent>,
+    capacity: usize,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize) -> Self {
+        if capacity == 0 {
+            panic!("ring buffer sink can't keep zero events!");
+        }
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+}
+
+impl RenderEventSink for RingBufferSink {
+    fn emit(&mut self, event: RenderEvent) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    fn is_stage_timing_enabled(&self) -> bool {
+        true
+    }
+
+    fn drain(&mut self) -> Vec<RenderEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+///
+/// GPU times of a frame read back by a StageTimer.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameTimings {
+    pub frame: u64,
+    // By stage
+    pub stages: Vec<Duration>,
+    // Of the split screen viewports of the stages drawing once per viewport, as stage,
+    // viewport and GPU time
+    pub viewports: Vec<(u32, u32, Duration)>,
+}
+
+///
+/// Timestamps around every stage of a frame and every split screen viewport drawn in
+/// it, read back once the frame is done.
+///
+pub struct StageTimer {
+    pool: vk::QueryPool,
+    // Nanoseconds per timestamp tick
+    period: f64,
+    total_stages: u32,
+    // Frame the queries were last written in and not read yet
+    pub pending_frame: Option<u64>,
+    // Stage and viewport of the viewport queries written in it, the others stay unwritten
+    written_viewports: Vec<(u32, u32)>,
+}
+
+impl StageTimer {
+    ///
+    /// None when the queue family can't write timestamps.
+    ///
+    pub fn new(ctx: &VulkanContext, queue_family_index: u32, total_stages: u32) -> Option<Self> {
+        let families = unsafe {
+            ctx.instance
+                .get_physical_device_queue_family_properties(ctx.physical_device)
+        };
+        if families[queue_family_index as usize].timestamp_valid_bits == 0 || total_stages == 0 {
+            return None;
+        }
+        let limits = unsafe {
+            ctx.instance
+                .get_physical_device_properties(ctx.physical_device)
+                .limits
+        };
+        let info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(Self::total_queries(total_stages));
+        let pool = unsafe { ctx.device.create_query_pool(&info, None) }.unwrap();
+        ctx.try_set_debug_name("stage_timestamps", pool);
+        Some(Self {
+            pool,
+            period: limits.timestamp_period as f64,
+            total_stages,
+            pending_frame: None,
+            written_viewports: Vec::new(),
+        })
+    }
+
+    /*
+     * A pair per stage, then a pair per stage and viewport.
+     */
+    fn total_queries(total_stages: u32) -> u32 {
+        total_stages * 2 * (1 + MAX_VIEWPORTS as u32)
+    }
+
+    fn viewport_query(&self, stage: u32, viewport: u32) -> u32 {
+        self.total_stages * 2 + (stage * MAX_VIEWPORTS as u32 + viewport) * 2
+    }
+
+    fn duration_of(&self, ticks: &[u64]) -> Duration {
+        let nanos = ticks[1].saturating_sub(ticks[0]) as f64 * self.period;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_query_pool(self.pool, None) }
+    }
+
+    pub fn reset(&mut self, device: &ash::Device, cmd: vk::CommandBuffer, frame: u64) {
+        let count = Self::total_queries(self.total_stages);
+        unsafe { device.cmd_reset_query_pool(cmd, self.pool, 0, count) };
+        self.pending_frame = Some(frame);
+        self.written_viewports.clear();
+    }
+
+    pub fn write(&self, device: &ash::Device, cmd: vk::CommandBuffer, stage: u32, is_end: bool) {
+        let stage_mask = if is_end {
+            vk::PipelineStageFlags2::BOTTOM_OF_PIPE
+        } else {
+            vk::PipelineStageFlags2::TOP_OF_PIPE
+        };
+        let query = stage * 2 + is_end as u32;
+        unsafe { device.cmd_write_timestamp2(cmd, stage_mask, self.pool, query) };
+    }
+
+    ///
+    /// Same as write, around the draws of a split screen viewport of the stage. Unlike
+    /// the ones of the stages, these don't all have to be written.
+    ///
+    pub fn write_viewport(
+        &mut self,
+        device: &ash::Device,
+        cmd: vk::CommandBuffer,
+        stage: u32,
+        viewport: u32,
+        is_end: bool,
+    ) {
+        assert!(
+            (viewport as usize) < MAX_VIEWPORTS,
+            "viewport {} is past the last one timed!",
+            viewport
+        );
+        let stage_mask = if is_end {
+            vk::PipelineStageFlags2::BOTTOM_OF_PIPE
+        } else {
+            self.written_viewports.push((stage, viewport));
+            vk::PipelineStageFlags2::TOP_OF_PIPE
+        };
+        let query = self.viewport_query(stage, viewport) + is_end as u32;
+        unsafe { device.cmd_write_timestamp2(cmd, stage_mask, self.pool, query) };
+    }
+
+    ///
+    /// GPU times of the pending frame, the frame has to be done by now. None if nothing
+    /// is pending.
+    ///
+    pub fn read(&mut self, device: &ash::Device) -> Option<FrameTimings> {
+        let frame = self.pending_frame.take()?;
+        let mut ticks = vec![0u64; self.total_stages as usize * 2];
+        let read = unsafe {
+            device.get_query_pool_results(
+                self.pool,
+                0,
+                self.total_stages * 2,
+                &mut ticks,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        read.ok()?;
+        let stages = ticks.chunks_exact(2).map(|e| self.duration_of(e)).collect();
+        let mut viewports = Vec::with_capacity(self.written_viewports.len());
+        for (stage, viewport) in std::mem::take(&mut self.written_viewports) {
+            let mut ticks = [0u64; 2];
+            let read = unsafe {
+                device.get_query_pool_results(
+                    self.pool,
+                    self.viewport_query(stage, viewport),
+                    2,
+                    &mut ticks,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+            };
+            read.ok()?;
+            viewports.push((stage, viewport, self.duration_of(&ticks)));
+        }
+        Some(FrameTimings {
+            frame,
+            stages,
+            viewports,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+/*
+ * Failures forced on purpose, to exercise the error paths of the renderer that real
+ * devices hardly ever take. Only built with the fault-injection feature, without it the
+ * call sites the injector hooks into are plain calls.
+ */
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Fault {
+    // DeviceAllocator::alloc returns None
+    AllocFailed,
+    // Acquiring the swapchain image returns ERROR_OUT_OF_DATE_KHR without acquiring
+    AcquireOutOfDate,
+    // Acquires the image, reporting the swapchain as suboptimal
+    AcquireSuboptimal,
+    // Acquiring the swapchain image returns TIMEOUT without acquiring
+    AcquireTimeout,
+    // Submitting the frame returns ERROR_DEVICE_LOST without submitting
+    SubmitDeviceLost,
+    // No free descriptor slot to generate a texture in
+    DescriptorsFull,
+    // Texture uploads wait for the next frame as if over the upload budget
+    UploadBudget,
+    // Readbacks find no room in the readback buffer
+    ReadbackBusy,
+}
+
+#[derive(Default)]
+struct Inner {
+    // Calls to let through first and calls left to fail after them
+    pending: HashMap<Fault, (u32, u32)>,
+    // Calls made to fail so far
+    injected: HashMap<Fault, u64>,
+}
+
+///
+/// Makes the next calls of the hooked operations fail, see Renderer::set_fault_injector.
+/// Clones share the same counts, so tests can keep one to arm faults while the renderer
+/// runs.
+///
+#[derive(Clone, Default)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("fault injector lock poisoned!")
+    }
+
+    ///
+    /// Fails the next count calls hooked for the fault, on top of the ones still pending.
+    ///
+    pub fn fail_next(&self, fault: Fault, count: u32) {
+        self.lock().pending.entry(fault).or_default().1 += count;
+    }
+
+    ///
+    /// Lets the next passing calls hooked for the fault through, then fails count of
+    /// them. Replaces what was pending for the fault, for failing the middle of a
+    /// sequence of the same call.
+    ///
+    pub fn fail_after(&self, fault: Fault, passing: u32, count: u32) {
+        self.lock().pending.insert(fault, (passing, count));
+    }
+
+    pub fn pending(&self, fault: Fault) -> u32 {
+        self.lock()
+            .pending
+            .get(&fault)
+            .map(|e| e.1)
+            .unwrap_or_default()
+    }
+
+    pub fn injected(&self, fault: Fault) -> u64 {
+        self.lock()
+            .injected
+            .get(&fault)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        self.lock().pending.clear();
+    }
+
+    ///
+    /// Whether the call hooked for the fault has to fail, counting it as injected if so.
+    ///
+    pub fn take(&self, fault: Fault) -> bool {
+        let mut inner = self.lock();
+        match inner.pending.get_mut(&fault) {
+            Some((passing, _)) if *passing > 0 => {
+                *passing -= 1;
+                false
+            }
+            Some((_, count)) if *count > 0 => {
+                *count -= 1;
+                *inner.injected.entry(fault).or_default() += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
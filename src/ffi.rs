@@ -0,0 +1,578 @@
+/*
+ * C ABI for embedding the renderer from other languages, include/rend_vk.h declares it.
+ * Regenerate the header with cbindgen after changing anything here:
+ *
+ *     cbindgen --config cbindgen.toml --output include/rend_vk.h
+ *
+ * Every function catches panics and returns them as REND_VK_RESULT_PANIC, nothing unwinds
+ * into the caller. Failing calls leave a message for rendvk_last_error_message.
+ */
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_void, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+};
+
+use ash::vk;
+
+use crate::{
+    config::TaskRejected,
+    format::Format,
+    handle::{MeshHandle, StaleHandle, TextureHandle},
+    java_api,
+    render_task::{RenderTask, TaskBounds, TaskKind},
+    renderer::{self, Renderer},
+    shader_resource::ResourceKind,
+    texture::MipMap,
+    UsedAsIndex,
+};
+
+///
+/// Opaque to C, only ever handled through a pointer.
+///
+pub struct RendVkRenderer {
+    renderer: Renderer,
+}
+
+///
+/// Outcome of every call. The handle and task errors match StaleHandle and the variants
+/// of TaskRejected.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RendVkResult {
+    Ok = 0,
+    NullArgument = 1,
+    InvalidArgument = 2,
+    StaleHandle = 3,
+    TaskKindBudget = 4,
+    TaskTotalBudget = 5,
+    TaskStaleMesh = 6,
+    TaskMeshUploading = 7,
+    // The renderer can't be used after this, other than to destroy it
+    Panic = 8,
+    TaskMissingResource = 9,
+    TaskVertexLayout = 10,
+    TaskStaleSceneSlot = 11,
+    TaskSceneSlotSize = 12,
+    TaskVertexFormat = 13,
+}
+
+impl From<StaleHandle> for RendVkResult {
+    fn from(_: StaleHandle) -> Self {
+        Self::StaleHandle
+    }
+}
+
+impl From<TaskRejected> for RendVkResult {
+    fn from(e: TaskRejected) -> Self {
+        match e {
+            TaskRejected::KindBudget { .. } => Self::TaskKindBudget,
+            TaskRejected::TotalBudget { .. } => Self::TaskTotalBudget,
+            TaskRejected::StaleMesh { .. } => Self::TaskStaleMesh,
+            TaskRejected::MeshUploading { .. } => Self::TaskMeshUploading,
+            TaskRejected::MissingResource { .. } => Self::TaskMissingResource,
+            TaskRejected::VertexLayout { .. } => Self::TaskVertexLayout,
+            TaskRejected::StaleSceneSlot { .. } => Self::TaskStaleSceneSlot,
+            TaskRejected::SceneSlotSize { .. } => Self::TaskSceneSlotSize,
+            TaskRejected::VertexFormat { .. } => Self::TaskVertexFormat,
+        }
+    }
+}
+
+///
+/// Creates the surface for the instance and writes it to the last argument, called once
+/// while making the renderer.
+///
+pub type RendVkSurfaceCallback =
+    extern "C" fn(vk::Instance, *mut c_void, *mut vk::SurfaceKHR) -> vk::Result;
+
+#[repr(C)]
+pub struct RendVkConfig {
+    pub is_vsync_enabled: bool,
+    pub is_debug_enabled: bool,
+    pub is_validation_layer_enabled: bool,
+    // Instance extensions the surface needs, from the windowing library
+    pub instance_extensions: *const *const c_char,
+    pub instance_extensions_len: u32,
+}
+
+///
+/// Mesh buffers the caller writes the mesh data into, mapped for the lifetime of the mesh.
+///
+#[repr(C)]
+pub struct RendVkMesh {
+    pub handle: u64,
+    pub vertices: *mut c_void,
+    pub normals: *mut c_void,
+    pub tex_coords: *mut c_void,
+    pub indices: *mut c_void,
+}
+
+#[repr(C)]
+pub struct RendVkMipMap {
+    pub width: u32,
+    pub height: u32,
+    pub size: u32,
+    // Offset into the staging buffer
+    pub offset: u32,
+}
+
+///
+/// Texture with its staging buffer mapped, write the mip maps there and queue it for
+/// uploading. The staging buffer is gone once the upload completes.
+///
+#[repr(C)]
+pub struct RendVkTexture {
+    pub handle: u64,
+    pub staging: *mut c_void,
+    pub staging_len: u32,
+}
+
+// Task drawn with the base pipeline of its stage
+pub const RENDVK_NO_VARIANT: u32 = u32::MAX;
+
+///
+/// Render task. The resources are instance_count items of each kind set in
+/// resource_bits, lowest bit first, every kind starting at an offset from resources
+/// aligned to its own alignment. resources itself can be at any address. Sizes and
+/// alignments come from rendvk_resource_size_of and rendvk_resource_align_of. Tasks
+/// carry transforms, materials, directional and point lights and transform extras, other
+/// kinds are invalid arguments.
+///
+#[repr(C)]
+pub struct RendVkTask {
+    pub kind: u32,
+    pub mesh: u64,
+    pub instance_count: u32,
+    pub variant: u32,
+    pub alpha_cutoff: f32,
+    pub is_two_sided: bool,
+    pub resource_bits: u32,
+    pub resources: *const u8,
+    pub resources_len: u32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior nul bytes would cut the message short anyway
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/*
+ * Runs the call with panics caught. Messages of errors are set here too so the calls
+ * only need to return the code.
+ */
+fn guard<F>(f: F) -> RendVkResult
+where
+    F: FnOnce() -> Result<(), (RendVkResult, String)>,
+{
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => RendVkResult::Ok,
+        Ok(Err((result, message))) => {
+            set_last_error(message);
+            result
+        }
+        Err(payload) => {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(e) => e.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(e) => e.clone(),
+                    None => "unknown panic".to_string(),
+                },
+            };
+            set_last_error(format!("renderer panicked: {}", message));
+            RendVkResult::Panic
+        }
+    }
+}
+
+fn error<E: std::fmt::Display + Into<RendVkResult>>(e: E) -> (RendVkResult, String) {
+    let message = e.to_string();
+    (e.into(), message)
+}
+
+fn null_argument(name: &str) -> (RendVkResult, String) {
+    (RendVkResult::NullArgument, format!("{} is null", name))
+}
+
+fn invalid_argument(message: String) -> (RendVkResult, String) {
+    (RendVkResult::InvalidArgument, message)
+}
+
+/*
+ * The pointer has to be null or one rendvk_make_renderer wrote and not yet destroyed.
+ */
+unsafe fn renderer_mut<'a>(
+    ptr: *mut RendVkRenderer,
+) -> Result<&'a mut Renderer, (RendVkResult, String)> {
+    match unsafe { ptr.as_mut() } {
+        Some(e) => Ok(&mut e.renderer),
+        None => Err(null_argument("renderer")),
+    }
+}
+
+///
+/// Message of the last call on this thread that didn't return REND_VK_RESULT_OK, null if
+/// it did. Valid until the next call on this thread.
+///
+#[no_mangle]
+pub extern "C" fn rendvk_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |e| e.as_ptr()))
+}
+
+///
+/// Makes a renderer with the pipeline.json of the working directory and writes it to
+/// out. user_data is passed on to the surface callback.
+///
+/// # Safety
+///
+/// config has to be null or point to a RendVkConfig whose instance_extensions holds
+/// instance_extensions_len nul terminated strings. out has to be null or valid for
+/// writing a pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_make_renderer(
+    config: *const RendVkConfig,
+    surface_callback: Option<RendVkSurfaceCallback>,
+    user_data: *mut c_void,
+    out: *mut *mut RendVkRenderer,
+) -> RendVkResult {
+    guard(|| {
+        let config = unsafe { config.as_ref() }.ok_or_else(|| null_argument("config"))?;
+        let surface_callback = surface_callback.ok_or_else(|| null_argument("surface_callback"))?;
+        if out.is_null() {
+            return Err(null_argument("out"));
+        }
+        let instance_extensions: &[*const c_char] = if config.instance_extensions_len == 0 {
+            &[]
+        } else if config.instance_extensions.is_null() {
+            return Err(null_argument("config.instance_extensions"));
+        } else {
+            unsafe {
+                std::slice::from_raw_parts(
+                    config.instance_extensions,
+                    config.instance_extensions_len as usize,
+                )
+            }
+        };
+        let renderer = renderer::make_renderer(
+            config.is_vsync_enabled,
+            config.is_debug_enabled,
+            config.is_validation_layer_enabled,
+            instance_extensions,
+            |_, instance| {
+                let mut surface = vk::SurfaceKHR::null();
+                match surface_callback(instance.handle(), user_data, &mut surface) {
+                    vk::Result::SUCCESS => Ok(surface),
+                    e => Err(e),
+                }
+            },
+        );
+        let boxed = Box::new(RendVkRenderer { renderer });
+        unsafe { *out = Box::into_raw(boxed) };
+        Ok(())
+    })
+}
+
+///
+/// Waits for the GPU and frees everything. Null does nothing.
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed. It can't be used after this.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_destroy(renderer: *mut RendVkRenderer) -> RendVkResult {
+    guard(|| {
+        if renderer.is_null() {
+            return Ok(());
+        }
+        let mut boxed = unsafe { Box::from_raw(renderer) };
+        boxed.renderer.destroy();
+        Ok(())
+    })
+}
+
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_render(renderer: *mut RendVkRenderer) -> RendVkResult {
+    guard(|| {
+        renderer_mut(renderer)?.render();
+        Ok(())
+    })
+}
+
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_resize(
+    renderer: *mut RendVkRenderer,
+    width: u32,
+    height: u32,
+) -> RendVkResult {
+    guard(|| {
+        renderer_mut(renderer)?.resize(width, height);
+        Ok(())
+    })
+}
+
+///
+/// Mesh id of the built-in test triangle, drawable without generating any mesh.
+///
+#[no_mangle]
+pub extern "C" fn rendvk_test_triangle() -> u64 {
+    Renderer::TEST_TRIANGLE.to_raw()
+}
+
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call. out has to be null or valid
+/// for writing a RendVkMesh.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_gen_mesh(
+    renderer: *mut RendVkRenderer,
+    vertices_size: u32,
+    normals_size: u32,
+    tex_coords_size: u32,
+    indices_size: u32,
+    count: u32,
+    out: *mut RendVkMesh,
+) -> RendVkResult {
+    guard(|| {
+        let renderer = renderer_mut(renderer)?;
+        let out = unsafe { out.as_mut() }.ok_or_else(|| null_argument("out"))?;
+        let handle = renderer.gen_mesh(
+            vertices_size,
+            normals_size,
+            tex_coords_size,
+            indices_size,
+            count,
+        );
+        let mesh = renderer.fetch_mesh_or_fail(handle);
+        *out = RendVkMesh {
+            handle: handle.to_raw(),
+            vertices: mesh.vertices.addr,
+            normals: mesh.normals.addr,
+            tex_coords: mesh.tex_coords.addr,
+            indices: mesh.indices.addr,
+        };
+        Ok(())
+    })
+}
+
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_free_mesh(renderer: *mut RendVkRenderer, mesh: u64) -> RendVkResult {
+    guard(|| {
+        renderer_mut(renderer)?
+            .free_mesh(MeshHandle::from_raw(mesh))
+            .map_err(error)
+    })
+}
+
+///
+/// name can be null. format is the index of the format in the Format enum.
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call. name has to be null or a nul
+/// terminated string, mip_maps has to point to mip_maps_len RendVkMipMap and out has to
+/// be null or valid for writing a RendVkTexture.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_gen_texture(
+    renderer: *mut RendVkRenderer,
+    name: *const c_char,
+    format: u32,
+    mip_maps: *const RendVkMipMap,
+    mip_maps_len: u32,
+    staging_size: u32,
+    out: *mut RendVkTexture,
+) -> RendVkResult {
+    guard(|| {
+        let renderer = renderer_mut(renderer)?;
+        let out = unsafe { out.as_mut() }.ok_or_else(|| null_argument("out"))?;
+        if mip_maps.is_null() || mip_maps_len == 0 {
+            return Err(invalid_argument("a texture needs mip maps".to_string()));
+        }
+        if format > Format::MAX_VALUE as u32 {
+            return Err(invalid_argument(format!("unknown format {}", format)));
+        }
+        let name = if name.is_null() {
+            "ffi_texture".to_string()
+        } else {
+            unsafe { CStr::from_ptr(name) }
+                .to_str()
+                .map_err(|_| invalid_argument("name isn't valid utf8".to_string()))?
+                .to_string()
+        };
+        let mip_maps: Vec<_> =
+            unsafe { std::slice::from_raw_parts(mip_maps, mip_maps_len as usize) }
+                .iter()
+                .enumerate()
+                .map(|(i, e)| MipMap {
+                    index: i as u32,
+                    width: e.width,
+                    height: e.height,
+                    size: e.size,
+                    offset: e.offset,
+                })
+                .collect();
+        let handle = renderer.gen_texture(name, Format::of_u32(format), &mip_maps, staging_size);
+        let texture = renderer.fetch_texture(handle).unwrap();
+        *out = match &texture.staging {
+            Some(e) => RendVkTexture {
+                handle: handle.to_raw(),
+                staging: e.addr,
+                staging_len: e.size as u32,
+            },
+            None => RendVkTexture {
+                handle: handle.to_raw(),
+                staging: std::ptr::null_mut(),
+                staging_len: 0,
+            },
+        };
+        Ok(())
+    })
+}
+
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_queue_texture_for_uploading(
+    renderer: *mut RendVkRenderer,
+    texture: u64,
+) -> RendVkResult {
+    guard(|| {
+        renderer_mut(renderer)?
+            .queue_texture_for_uploading(TextureHandle::from_raw(texture))
+            .map_err(error)
+    })
+}
+
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_free_texture(renderer: *mut RendVkRenderer, texture: u64) -> RendVkResult {
+    guard(|| {
+        renderer_mut(renderer)?
+            .free_texture(TextureHandle::from_raw(texture))
+            .map_err(error)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rendvk_resource_size_of(kind: u32) -> u32 {
+    if kind > ResourceKind::MAX_VALUE as u32 {
+        return 0;
+    }
+    ResourceKind::of_u32(kind).resource_size() as u32
+}
+
+#[no_mangle]
+pub extern "C" fn rendvk_resource_align_of(kind: u32) -> u32 {
+    if kind > ResourceKind::MAX_VALUE as u32 {
+        return 0;
+    }
+    ResourceKind::of_u32(kind).resource_align() as u32
+}
+
+///
+/// Queues the task for the next frame, rejected tasks return the TaskRejected codes.
+///
+/// # Safety
+///
+/// renderer has to be null or a renderer made by rendvk_make_renderer and not yet
+/// destroyed, not used from another thread during the call. task has to be null or
+/// point to a RendVkTask whose resources holds resources_len bytes, at any alignment.
+///
+#[no_mangle]
+pub unsafe extern "C" fn rendvk_add_task(
+    renderer: *mut RendVkRenderer,
+    task: *const RendVkTask,
+) -> RendVkResult {
+    guard(|| {
+        // Checked and unpacked before the renderer is looked at
+        let task = unsafe { task.as_ref() }.ok_or_else(|| null_argument("task"))?;
+        if task.kind > TaskKind::MAX_VALUE as u32 {
+            return Err(invalid_argument(format!("unknown task kind {}", task.kind)));
+        }
+        let supported_bits = java_api::TASK_RESOURCE_KINDS
+            .iter()
+            .fold(0u32, |bits, e| bits | 1 << e.to_usize());
+        if task.resource_bits & !supported_bits != 0 {
+            return Err(invalid_argument(format!(
+                "unsupported resource kinds in bits {:#x}",
+                task.resource_bits & !supported_bits
+            )));
+        }
+        let variant =
+            match task.variant {
+                RENDVK_NO_VARIANT => None,
+                e => Some(u16::try_from(e).map_err(|_| {
+                    invalid_argument(format!("variant {} out of range", task.variant))
+                })?),
+            };
+        let data: &[u8] = if task.resources_len == 0 {
+            &[]
+        } else if task.resources.is_null() {
+            return Err(null_argument("task.resources"));
+        } else {
+            unsafe { std::slice::from_raw_parts(task.resources, task.resources_len as usize) }
+        };
+        let size = java_api::render_task_resources_size(task.resource_bits, task.instance_count);
+        if data.len() < size {
+            return Err(invalid_argument(format!(
+                "resources take {} bytes, got only {}",
+                size,
+                data.len()
+            )));
+        }
+        let resources =
+            java_api::unpack_render_task_resources(data, task.resource_bits, task.instance_count);
+        let task = RenderTask {
+            instance_count: task.instance_count,
+            resources,
+            variant,
+            alpha_cutoff: task.alpha_cutoff,
+            is_two_sided: task.is_two_sided,
+            ..RenderTask::new(MeshHandle::from_raw(task.mesh), TaskKind::of_u32(task.kind))
+        };
+        renderer_mut(renderer)?
+            .try_add_task_to_queue(task)
+            .map_err(error)
+    })
+}
@@ -43,6 +43,75 @@ impl Format {
             || self == Self::R8_SRGB
     }
 
+    ///
+    /// Other format of the UNORM and sRGB pair with the same layout, None for formats
+    /// without one. Images of either can be viewed as both, see gen_texture_dual_view.
+    ///
+    pub fn srgb_sibling(self) -> Option<Self> {
+        let sibling = match self {
+            Self::A8B8G8R8_SRGB_PACK32 => Self::A8B8G8R8_UNORM_PACK32,
+            Self::A8B8G8R8_UNORM_PACK32 => Self::A8B8G8R8_SRGB_PACK32,
+            Self::ASTC_10X10_SRGB_BLOCK => Self::ASTC_10X10_UNORM_BLOCK,
+            Self::ASTC_10X10_UNORM_BLOCK => Self::ASTC_10X10_SRGB_BLOCK,
+            Self::ASTC_10X5_SRGB_BLOCK => Self::ASTC_10X5_UNORM_BLOCK,
+            Self::ASTC_10X5_UNORM_BLOCK => Self::ASTC_10X5_SRGB_BLOCK,
+            Self::ASTC_10X6_SRGB_BLOCK => Self::ASTC_10X6_UNORM_BLOCK,
+            Self::ASTC_10X6_UNORM_BLOCK => Self::ASTC_10X6_SRGB_BLOCK,
+            Self::ASTC_10X8_SRGB_BLOCK => Self::ASTC_10X8_UNORM_BLOCK,
+            Self::ASTC_10X8_UNORM_BLOCK => Self::ASTC_10X8_SRGB_BLOCK,
+            Self::ASTC_12X10_SRGB_BLOCK => Self::ASTC_12X10_UNORM_BLOCK,
+            Self::ASTC_12X10_UNORM_BLOCK => Self::ASTC_12X10_SRGB_BLOCK,
+            Self::ASTC_12X12_SRGB_BLOCK => Self::ASTC_12X12_UNORM_BLOCK,
+            Self::ASTC_12X12_UNORM_BLOCK => Self::ASTC_12X12_SRGB_BLOCK,
+            Self::ASTC_4X4_SRGB_BLOCK => Self::ASTC_4X4_UNORM_BLOCK,
+            Self::ASTC_4X4_UNORM_BLOCK => Self::ASTC_4X4_SRGB_BLOCK,
+            Self::ASTC_5X4_SRGB_BLOCK => Self::ASTC_5X4_UNORM_BLOCK,
+            Self::ASTC_5X4_UNORM_BLOCK => Self::ASTC_5X4_SRGB_BLOCK,
+            Self::ASTC_5X5_SRGB_BLOCK => Self::ASTC_5X5_UNORM_BLOCK,
+            Self::ASTC_5X5_UNORM_BLOCK => Self::ASTC_5X5_SRGB_BLOCK,
+            Self::ASTC_6X5_SRGB_BLOCK => Self::ASTC_6X5_UNORM_BLOCK,
+            Self::ASTC_6X5_UNORM_BLOCK => Self::ASTC_6X5_SRGB_BLOCK,
+            Self::ASTC_6X6_SRGB_BLOCK => Self::ASTC_6X6_UNORM_BLOCK,
+            Self::ASTC_6X6_UNORM_BLOCK => Self::ASTC_6X6_SRGB_BLOCK,
+            Self::ASTC_8X5_SRGB_BLOCK => Self::ASTC_8X5_UNORM_BLOCK,
+            Self::ASTC_8X5_UNORM_BLOCK => Self::ASTC_8X5_SRGB_BLOCK,
+            Self::ASTC_8X6_SRGB_BLOCK => Self::ASTC_8X6_UNORM_BLOCK,
+            Self::ASTC_8X6_UNORM_BLOCK => Self::ASTC_8X6_SRGB_BLOCK,
+            Self::ASTC_8X8_SRGB_BLOCK => Self::ASTC_8X8_UNORM_BLOCK,
+            Self::ASTC_8X8_UNORM_BLOCK => Self::ASTC_8X8_SRGB_BLOCK,
+            Self::B8G8R8A8_SRGB => Self::B8G8R8A8_UNORM,
+            Self::B8G8R8A8_UNORM => Self::B8G8R8A8_SRGB,
+            Self::B8G8R8_SRGB => Self::B8G8R8_UNORM,
+            Self::B8G8R8_UNORM => Self::B8G8R8_SRGB,
+            Self::BC1_RGBA_SRGB_BLOCK => Self::BC1_RGBA_UNORM_BLOCK,
+            Self::BC1_RGBA_UNORM_BLOCK => Self::BC1_RGBA_SRGB_BLOCK,
+            Self::BC1_RGB_SRGB_BLOCK => Self::BC1_RGB_UNORM_BLOCK,
+            Self::BC1_RGB_UNORM_BLOCK => Self::BC1_RGB_SRGB_BLOCK,
+            Self::BC2_SRGB_BLOCK => Self::BC2_UNORM_BLOCK,
+            Self::BC2_UNORM_BLOCK => Self::BC2_SRGB_BLOCK,
+            Self::BC3_SRGB_BLOCK => Self::BC3_UNORM_BLOCK,
+            Self::BC3_UNORM_BLOCK => Self::BC3_SRGB_BLOCK,
+            Self::BC7_SRGB_BLOCK => Self::BC7_UNORM_BLOCK,
+            Self::BC7_UNORM_BLOCK => Self::BC7_SRGB_BLOCK,
+            Self::ETC2_R8G8B8A1_SRGB_BLOCK => Self::ETC2_R8G8B8A1_UNORM_BLOCK,
+            Self::ETC2_R8G8B8A1_UNORM_BLOCK => Self::ETC2_R8G8B8A1_SRGB_BLOCK,
+            Self::ETC2_R8G8B8A8_SRGB_BLOCK => Self::ETC2_R8G8B8A8_UNORM_BLOCK,
+            Self::ETC2_R8G8B8A8_UNORM_BLOCK => Self::ETC2_R8G8B8A8_SRGB_BLOCK,
+            Self::ETC2_R8G8B8_SRGB_BLOCK => Self::ETC2_R8G8B8_UNORM_BLOCK,
+            Self::ETC2_R8G8B8_UNORM_BLOCK => Self::ETC2_R8G8B8_SRGB_BLOCK,
+            Self::R8G8B8A8_SRGB => Self::R8G8B8A8_UNORM,
+            Self::R8G8B8A8_UNORM => Self::R8G8B8A8_SRGB,
+            Self::R8G8B8_SRGB => Self::R8G8B8_UNORM,
+            Self::R8G8B8_UNORM => Self::R8G8B8_SRGB,
+            Self::R8G8_SRGB => Self::R8G8_UNORM,
+            Self::R8G8_UNORM => Self::R8G8_SRGB,
+            Self::R8_SRGB => Self::R8_UNORM,
+            Self::R8_UNORM => Self::R8_SRGB,
+            _ => return None,
+        };
+        Some(sibling)
+    }
+
     pub fn has_color(self) -> bool {
         !self.has_depth_or_stencil()
     }
@@ -104,6 +173,34 @@ impl Format {
         }
     }
 
+    ///
+    /// Bytes per texel of uncompressed formats copied out of an image, only the depth of
+    /// depth stencil formats. None for the ones attachments rarely use.
+    ///
+    pub fn texel_size(self) -> Option<u32> {
+        match self {
+            Self::R8_UNORM | Self::R8_UINT | Self::S8_UINT => Some(1),
+            Self::R8G8_UNORM | Self::R16_SFLOAT | Self::R16_UINT | Self::D16_UNORM => Some(2),
+            Self::R8G8B8A8_UNORM
+            | Self::R8G8B8A8_SRGB
+            | Self::B8G8R8A8_UNORM
+            | Self::B8G8R8A8_SRGB
+            | Self::A2B10G10R10_UNORM_PACK32
+            | Self::B10G11R11_UFLOAT_PACK32
+            | Self::R16G16_SNORM
+            | Self::R16G16_SFLOAT
+            | Self::R32_SFLOAT
+            | Self::R32_UINT
+            | Self::D24_UNORM_S8_UINT
+            | Self::X8_D24_UNORM_PACK32
+            | Self::D32_SFLOAT
+            | Self::D32_SFLOAT_S8_UINT => Some(4),
+            Self::R16G16B16A16_SFLOAT | Self::R32G32_SFLOAT | Self::R32G32_UINT => Some(8),
+            Self::R32G32B32A32_SFLOAT => Some(16),
+            _ => None,
+        }
+    }
+
     pub fn size_for_extent(self, extent: vk::Extent2D) -> u32 {
         self.size_for(extent.width, extent.height)
     }
@@ -145,7 +242,7 @@ impl Format {
     }
 }
 
-#[derive(Deserialize, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, strum_macros::Display)]
+#[derive(Deserialize, Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, strum_macros::Display)]
 /* Preserve these as-is since the serde screaming case renaming wouldn't work */
 #[allow(non_camel_case_types)]
 pub enum Format {
@@ -0,0 +1,175 @@
+/*
+ * Soft GPU budget per frame, see RendererConfig::gpu_frame_budget. Passes given a
+ * "budgetPriority" can get skipped on frames the GPU time of the stages recorded is
+ * predicted to go over the budget, lowest priority first and later stages first among
+ * equal ones. The rest always get recorded.
+ *
+ * The prediction of each stage is the average of its last WINDOW GPU times from frames
+ * it got recorded in, skipped stages keep the one they had. Stages get skipped right
+ * away once the prediction goes over the budget, but only come back after MIN_SKIPPED
+ * frames and once the prediction with them back stays under RESTORE_RATIO of the
+ * budget, one stage per frame, so they don't flap on and off around the budget.
+ *
+ * Skipped stages record no draws but still begin and end their rendering, so they clear
+ * or keep their outputs as their clearing state says: clearing ones leave their clear
+ * values, like no bloom, the others keep what the last frame left. Blit stages don't
+ * copy anything when skipped.
+ */
+use std::{collections::VecDeque, time::Duration};
+
+// Frames of GPU times each prediction averages over
+pub const WINDOW: usize = 16;
+// Frames a stage stays skipped at least
+pub const MIN_SKIPPED: u32 = 30;
+// Of the budget, the prediction with a skipped stage back has to stay under it
+pub const RESTORE_RATIO: f64 = 0.85;
+
+///
+/// Stages skipped or brought back by a FrameBudget::update, by index.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BudgetChanges {
+    pub skipped: Vec<usize>,
+    pub restored: Vec<usize>,
+}
+
+impl BudgetChanges {
+    pub fn is_empty(&self) -> bool {
+        self.skipped.is_empty() && self.restored.is_empty()
+    }
+}
+
+///
+/// Which optional stages get skipped, out of the GPU times of the stages. Doesn't touch
+/// the GPU itself.
+///
+pub struct FrameBudget {
+    budget: Duration,
+    // By stage, None for the ones never skipped
+    priorities: Vec<Option<u32>>,
+    timings: Vec<VecDeque<Duration>>,
+    // Frames each skipped stage has been skipped for, None for the ones recorded
+    skipped: Vec<Option<u32>>,
+}
+
+impl FrameBudget {
+    pub fn new(budget: Duration, priorities: Vec<Option<u32>>) -> Self {
+        let stages = priorities.len();
+        Self {
+            budget,
+            priorities,
+            timings: vec![VecDeque::with_capacity(WINDOW); stages],
+            skipped: vec![None; stages],
+        }
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    pub fn priorities(&self) -> &[Option<u32>] {
+        &self.priorities
+    }
+
+    ///
+    /// Takes the GPU time of every stage in a frame recorded with the stages skipped
+    /// now. Skipped stages and zero times, of stages not recorded at all, are left out.
+    ///
+    pub fn record(&mut self, timings: &[Duration]) {
+        for (i, gpu_time) in timings.iter().enumerate().take(self.timings.len()) {
+            if self.skipped[i].is_some() || gpu_time.is_zero() {
+                continue;
+            }
+            let history = &mut self.timings[i];
+            if history.len() == WINDOW {
+                history.pop_front();
+            }
+            history.push_back(*gpu_time);
+        }
+    }
+
+    ///
+    /// Predicted GPU time of the stage, zero until it was timed.
+    ///
+    pub fn estimate(&self, stage: usize) -> Duration {
+        let history = &self.timings[stage];
+        if history.is_empty() {
+            return Duration::ZERO;
+        }
+        history.iter().sum::<Duration>() / history.len() as u32
+    }
+
+    ///
+    /// Predicted GPU time of the stages recorded with the ones skipped now.
+    ///
+    pub fn predicted(&self) -> Duration {
+        (0..self.skipped.len())
+            .filter(|e| self.skipped[*e].is_none())
+            .map(|e| self.estimate(e))
+            .sum()
+    }
+
+    pub fn is_skipped(&self, stage: usize) -> bool {
+        self.skipped[stage].is_some()
+    }
+
+    pub fn skipped(&self) -> Vec<usize> {
+        (0..self.skipped.len())
+            .filter(|e| self.is_skipped(*e))
+            .collect()
+    }
+
+    ///
+    /// Decides the stages skipped in the next frame.
+    ///
+    pub fn update(&mut self) -> BudgetChanges {
+        let mut changes = BudgetChanges::default();
+        for frames in self.skipped.iter_mut().flatten() {
+            *frames = frames.saturating_add(1);
+        }
+        let mut predicted = self.predicted();
+        while predicted > self.budget {
+            let next = self.next_to_skip();
+            let stage = match next {
+                Some(e) => e,
+                None => break,
+            };
+            predicted -= self.estimate(stage);
+            self.skipped[stage] = Some(0);
+            changes.skipped.push(stage);
+        }
+        if !changes.skipped.is_empty() {
+            return changes;
+        }
+        let restore_limit = self.budget.mul_f64(RESTORE_RATIO);
+        if let Some(stage) = self.next_to_restore() {
+            if predicted + self.estimate(stage) <= restore_limit {
+                self.skipped[stage] = None;
+                changes.restored.push(stage);
+            }
+        }
+        changes
+    }
+
+    /*
+     * Recorded optional stage of the lowest priority, the later one among equal ones.
+     */
+    fn next_to_skip(&self) -> Option<usize> {
+        (0..self.priorities.len())
+            .filter(|e| self.skipped[*e].is_none())
+            .filter_map(|e| self.priorities[e].map(|priority| (priority, e)))
+            .min_by_key(|(priority, e)| (*priority, std::cmp::Reverse(*e)))
+            .map(|e| e.1)
+    }
+
+    /*
+     * Skipped stage of the highest priority, the earlier one among equal ones, once it
+     * was skipped long enough. Waits for that one rather than bringing back lower ones.
+     */
+    fn next_to_restore(&self) -> Option<usize> {
+        let (stage, frames) = (0..self.priorities.len())
+            .filter_map(|e| self.skipped[e].map(|frames| (e, frames)))
+            .max_by_key(|(e, _)| (self.priorities[*e], std::cmp::Reverse(*e)))?;
+        (frames >= MIN_SKIPPED).then_some(stage)
+    }
+}
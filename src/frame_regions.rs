@@ -0,0 +1,166 @@
+use ash::vk;
+
+use crate::{context::VulkanContext, frame_ring::FrameRing};
+
+///
+/// One region of the frame ring per frame in flight, frames use them round robin. A
+/// region only gets reset once the frame timeline semaphore says the GPU finished the
+/// last frame recorded with it, frame N signals N + 1.
+///
+pub struct FrameRegions {
+    ring: FrameRing,
+    // Last frame recorded with each region
+    last_frames: Vec<Option<u64>>,
+}
+
+impl FrameRegions {
+    pub fn new(ring: FrameRing) -> Self {
+        let last_frames = vec![None; ring.frames_in_flight() as usize];
+        Self { ring, last_frames }
+    }
+
+    pub fn frames_in_flight(&self) -> u32 {
+        self.last_frames.len() as u32
+    }
+
+    pub fn region_index_of(&self, frame: u64) -> usize {
+        (frame % self.last_frames.len() as u64) as usize
+    }
+
+    ///
+    /// Timeline value the GPU has to reach before the region of the frame can be
+    /// rewritten, None if it was never used.
+    ///
+    pub fn wait_value_for(&self, frame: u64) -> Option<u64> {
+        let index = self.region_index_of(frame);
+        self.last_frames[index].map(|e| {
+            assert!(
+                e < frame,
+                "frame {} recorded after frame {} in region {}!",
+                e,
+                frame,
+                index
+            );
+            e + 1
+        })
+    }
+
+    ///
+    /// Blocks until the GPU is done with the region of the frame, then resets it in the
+    /// ring for the frame to allocate from.
+    ///
+    pub fn begin(
+        &mut self,
+        ctx: &VulkanContext,
+        semaphore: vk::Semaphore,
+        frame: u64,
+    ) -> &mut FrameRing {
+        if let Some(wait_value) = self.wait_value_for(frame) {
+            let completed = unsafe { ctx.device.get_semaphore_counter_value(semaphore) }
+                .expect("couldn't read the frame timeline semaphore!");
+            if completed < wait_value {
+                log::warn!(
+                    "waiting on GPU for frame data region {}",
+                    self.region_index_of(frame)
+                );
+                let semaphores = [semaphore];
+                let values = [wait_value];
+                let wait_info = vk::SemaphoreWaitInfo::builder()
+                    .semaphores(&semaphores)
+                    .values(&values)
+                    .build();
+                unsafe { ctx.device.wait_semaphores(&wait_info, u64::MAX) }
+                    .expect("frame data region wait failed!");
+            }
+        }
+        self.mark_begun(frame);
+        &mut self.ring
+    }
+
+    /* Resets the region of the frame, once the GPU is done with it */
+    fn mark_begun(&mut self, frame: u64) {
+        let index = self.region_index_of(frame);
+        self.ring.reset(index);
+        self.last_frames[index] = Some(frame);
+    }
+
+    ///
+    /// Releases the data of every region. The GPU has to be done with all of them.
+    ///
+    pub fn release_all(&mut self) {
+        self.ring.clear();
+    }
+
+    ///
+    /// Ring allocating for a frame already passed to begin.
+    ///
+    pub fn region_mut(&mut self, frame: u64) -> &mut FrameRing {
+        let index = self.region_index_of(frame);
+        assert!(
+            self.last_frames[index] == Some(frame),
+            "frame data region {} wasn't begun for frame {}!",
+            index,
+            frame
+        );
+        &mut self.ring
+    }
+
+    pub fn ring(&self) -> &FrameRing {
+        &self.ring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameRegions;
+    use crate::{buffer::DeviceSlice, frame_ring::FrameRing};
+
+    // Over a made up slice, nothing in it is ever written
+    fn regions(frames_in_flight: u32) -> FrameRegions {
+        let slice = DeviceSlice {
+            size: 4096,
+            alignment: 256,
+            ..DeviceSlice::empty()
+        };
+        FrameRegions::new(FrameRing::new(slice, frames_in_flight, 4))
+    }
+
+    #[test]
+    fn frames_wrap_around_the_regions() {
+        let regions = regions(3);
+        assert_eq!(regions.frames_in_flight(), 3);
+        let indices: Vec<_> = (0..7).map(|e| regions.region_index_of(e)).collect();
+        assert_eq!(indices, [0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(regions.region_index_of(u64::MAX), (u64::MAX % 3) as usize);
+    }
+
+    #[test]
+    fn first_frames_wait_for_nothing() {
+        let mut regions = regions(3);
+        for frame in 0..3 {
+            assert_eq!(regions.wait_value_for(frame), None);
+            regions.mark_begun(frame);
+        }
+        // Frame N signals N + 1, the frame before in the region
+        for frame in 3..9 {
+            assert_eq!(regions.wait_value_for(frame), Some(frame - 2));
+            regions.mark_begun(frame);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "frame 5 recorded after frame 2 in region 2!")]
+    fn frames_out_of_order_panic() {
+        let mut regions = regions(3);
+        regions.mark_begun(5);
+        regions.wait_value_for(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "frame 4 recorded after frame 4 in region 1!")]
+    fn frames_begun_twice_panic() {
+        let mut regions = regions(3);
+        regions.mark_begun(4);
+        regions.wait_value_for(4);
+    }
+}
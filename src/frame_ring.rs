@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+use std::ffi::c_void;
+
+use crate::buffer::DeviceSlice;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RingError {
+    // Larger than the share of the ring one frame gets, it could never fit
+    TooLarge { size: u64, region_size: u64 },
+    // Runs into data of frames still in flight
+    Full { size: u64, available: u64 },
+}
+
+impl std::fmt::Display for RingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { size, region_size } => write!(
+                f,
+                "frame data of {} bytes is larger than the per-frame region of {} bytes",
+                size, region_size
+            ),
+            Self::Full { size, available } => write!(
+                f,
+                "frame data of {} bytes doesn't fit in the {} bytes left in the frame ring",
+                size, available
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RingError {}
+
+///
+/// How full the ring got, to size it. Frame bytes count alignment padding and skipped
+/// tails, everything the frame kept from other frames.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RingWatermarks {
+    pub size: u64,
+    pub region_size: u64,
+    // Taken by the frame allocating now
+    pub frame_bytes: u64,
+    // Most taken by a single frame, this one or one of the history_len before it
+    pub peak_frame_bytes: u64,
+    // Allocations that skipped the end of the ring over those frames, and the bytes skipped
+    pub tail_skips: u32,
+    pub tail_waste_bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FrameUsage {
+    bytes: u64,
+    tail_skips: u32,
+    tail_waste_bytes: u64,
+}
+
+///
+/// Draw data of the frames in flight, allocated linearly out of a single slice. Frames
+/// take what follows the data of the frame before, going back to the start of the slice
+/// once an allocation doesn't fit before its end. Allocations never straddle the end,
+/// the tail they skip stays taken until the frame skipping it gets reset.
+///
+/// Each frame index reclaims what it allocated last time round on reset, which has to
+/// wait until the GPU is done with it. A single allocation can't be larger than the
+/// ring split evenly between the frames in flight.
+///
+pub struct FrameRing {
+    slice: DeviceSlice,
+    region_size: u64,
+    /*
+     * Positions only ever grow, the offset in the slice is the position modulo its size.
+     * Everything from the oldest start of a frame up to the head is taken.
+     */
+    head: u64,
+    starts: Vec<Option<u64>>,
+    current: Option<usize>,
+    usage: FrameUsage,
+    history: VecDeque<FrameUsage>,
+    history_len: usize,
+}
+
+impl FrameRing {
+    pub fn new(slice: DeviceSlice, frames_in_flight: u32, history_len: usize) -> Self {
+        assert!(frames_in_flight > 0, "need at least one frame in the ring!");
+        assert!(slice.size > 0, "frame ring over an empty slice!");
+        Self {
+            slice,
+            region_size: slice.size / frames_in_flight as u64,
+            head: 0,
+            starts: vec![None; frames_in_flight as usize],
+            current: None,
+            usage: FrameUsage::default(),
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+        }
+    }
+
+    ///
+    /// Allocates for the frame index last reset. Alignment has to be a power of two no
+    /// larger than the one of the slice.
+    ///
+    pub fn alloc(&mut self, size: u64, alignment: u64) -> Result<DeviceSlice, RingError> {
+        assert!(
+            alignment.is_power_of_two() && alignment <= self.slice.alignment.max(1),
+            "frame ring alignment {} isn't a power of two up to {}!",
+            alignment,
+            self.slice.alignment
+        );
+        assert!(
+            self.current.is_some(),
+            "frame ring allocation before resetting any frame!"
+        );
+        if size > self.region_size {
+            return Err(RingError::TooLarge {
+                size,
+                region_size: self.region_size,
+            });
+        }
+        let lap_start = self.lap_start();
+        let lap_end = lap_start + self.slice.size;
+        let mut start = lap_start + (self.head - lap_start).next_multiple_of(alignment);
+        let mut waste = 0;
+        if start + size > lap_end {
+            waste = lap_end - self.head;
+            start = lap_end;
+        }
+        let end = start + size;
+        if end > self.oldest_start() + self.slice.size {
+            return Err(RingError::Full {
+                size,
+                available: self.available(),
+            });
+        }
+        if waste > 0 {
+            self.usage.tail_skips += 1;
+            self.usage.tail_waste_bytes += waste;
+        }
+        self.usage.bytes += end - self.head;
+        self.head = end;
+        let offset = start % self.slice.size;
+        Ok(DeviceSlice {
+            size,
+            offset: self.slice.offset + offset,
+            alignment,
+            addr: (self.slice.addr as *mut u8).wrapping_add(offset as usize) as *mut c_void,
+            device_addr: self.slice.device_addr + offset,
+            ..self.slice
+        })
+    }
+
+    ///
+    /// Reclaims what the frame index allocated last time round and makes it the one
+    /// allocating from now on. The GPU has to be done with the data of the frame.
+    ///
+    pub fn reset(&mut self, frame_index: usize) {
+        assert!(
+            frame_index < self.starts.len(),
+            "frame index {} out of the {} frames of the ring!",
+            frame_index,
+            self.starts.len()
+        );
+        self.end_frame();
+        self.starts[frame_index] = None;
+        self.restart_if_empty();
+        self.starts[frame_index] = Some(self.head);
+        self.current = Some(frame_index);
+    }
+
+    ///
+    /// Reclaims the data of every frame. The GPU has to be done with all of them, the
+    /// next allocation needs a reset first.
+    ///
+    pub fn clear(&mut self) {
+        self.end_frame();
+        self.starts.fill(None);
+        self.restart_if_empty();
+    }
+
+    ///
+    /// Bytes not taken by any frame, skipped tails count as taken.
+    ///
+    pub fn available(&self) -> u64 {
+        self.slice.size - (self.head - self.oldest_start())
+    }
+
+    ///
+    /// Bytes the current frame can allocate whatever their sizes, as long as each fits
+    /// in the per-frame region: the larger of the free space before the end of the ring
+    /// and the one after its start, at most one tail gets skipped on the way.
+    ///
+    pub fn guaranteed_available(&self) -> u64 {
+        let limit = self.oldest_start() + self.slice.size;
+        let lap_end = self.lap_start() + self.slice.size;
+        let tail = limit.min(lap_end) - self.head;
+        let front = limit.saturating_sub(lap_end);
+        tail.max(front)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.slice.size
+    }
+
+    pub fn region_size(&self) -> u64 {
+        self.region_size
+    }
+
+    pub fn alignment(&self) -> u64 {
+        self.slice.alignment
+    }
+
+    pub fn frames_in_flight(&self) -> u32 {
+        self.starts.len() as u32
+    }
+
+    ///
+    /// Slice the ring allocates from, to free it once the ring is gone.
+    ///
+    pub fn slice(&self) -> DeviceSlice {
+        self.slice
+    }
+
+    pub fn watermarks(&self) -> RingWatermarks {
+        let frames = || self.history.iter().chain(std::iter::once(&self.usage));
+        RingWatermarks {
+            size: self.slice.size,
+            region_size: self.region_size,
+            frame_bytes: self.usage.bytes,
+            peak_frame_bytes: frames().map(|e| e.bytes).max().unwrap_or(0),
+            tail_skips: frames().map(|e| e.tail_skips).sum(),
+            tail_waste_bytes: frames().map(|e| e.tail_waste_bytes).sum(),
+        }
+    }
+
+    fn lap_start(&self) -> u64 {
+        self.head / self.slice.size * self.slice.size
+    }
+
+    fn oldest_start(&self) -> u64 {
+        self.starts
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+            .unwrap_or(self.head)
+    }
+
+    fn end_frame(&mut self) {
+        if self.current.take().is_none() {
+            return;
+        }
+        if self.history_len > 0 {
+            if self.history.len() == self.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.usage);
+        }
+        self.usage = FrameUsage::default();
+    }
+
+    /*
+     * With no frame holding any data the next one may as well start at the beginning,
+     * rather than wherever the last one stopped.
+     */
+    fn restart_if_empty(&mut self) {
+        if self.oldest_start() != self.head || self.head == self.lap_start() {
+            return;
+        }
+        self.head = self.lap_start() + self.slice.size;
+        for start in self.starts.iter_mut().flatten() {
+            *start = self.head;
+        }
+    }
+}
@@ -0,0 +1,514 @@
+/*
+ * Atlas of small images in one texture, glyphs mostly, kept up to date through
+ * Renderer::update_texture_region. Rasterizing is up to the caller, SDF or plain coverage
+ * alike, the atlas only packs the rectangles, uploads their texels and hands out the UVs.
+ *
+ * Every slot is packed with a texel of padding to its right and below, uploaded as
+ * zeros, so sampling its edges never blends in its neighbours. Slots keep a copy of their
+ * texels, which is what lets a full atlas grow into a larger texture, or repack what's
+ * left after evicting the least recently used slots, without the caller inserting them
+ * again.
+ */
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use glam::Vec2;
+
+use crate::format::Format;
+use crate::handle::TextureHandle;
+use crate::renderer::Renderer;
+use crate::texture::{MipMap, TextureRegion};
+
+// Texels left empty to the right and below each slot
+const PADDING: u32 = 1;
+// Share of the slots evicted at once when nothing else makes room
+const EVICTED_FRACTION: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+///
+/// Bottom left skyline packer. Each rectangle goes wherever its top ends up lowest, on
+/// the leftmost spot on ties, and the space it leaves below the skyline is gone.
+///
+#[derive(Clone, Debug)]
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    // Left to right, covering the whole width
+    skyline: Vec<Segment>,
+    used_area: u64,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            skyline: vec![Segment { x: 0, y: 0, width }],
+            used_area: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    ///
+    /// Top left corner of the rectangle, None if it doesn't fit anywhere. Empty ones take
+    /// no space.
+    ///
+    pub fn pack(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        if width == 0 || height == 0 {
+            return Some([0, 0]);
+        }
+        let (index, y) = (0..self.skyline.len())
+            .filter_map(|i| self.fit(i, width, height).map(|y| (i, y)))
+            .min_by_key(|&(i, y)| (y + height, self.skyline[i].x))?;
+        let x = self.skyline[index].x;
+        self.raise(index, x, y + height, width);
+        self.used_area += width as u64 * height as u64;
+        Some([x, y])
+    }
+
+    ///
+    /// Share of the area covered by packed rectangles.
+    ///
+    pub fn occupancy(&self) -> f32 {
+        self.used_area as f32 / (self.width as f32 * self.height as f32)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.width, self.height);
+    }
+
+    /*
+     * Lowest y the rectangle fits at with its left edge on the segment, if any.
+     */
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[index].x;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut covered = 0;
+        for segment in &self.skyline[index..] {
+            if covered >= width {
+                break;
+            }
+            y = y.max(segment.y);
+            if y + height > self.height {
+                return None;
+            }
+            covered += segment.width;
+        }
+        Some(y)
+    }
+
+    fn raise(&mut self, index: usize, x: u32, y: u32, width: u32) {
+        let end = x + width;
+        self.skyline.insert(index, Segment { x, y, width });
+        // Segments the new one covers go, the last one it overlaps gets cut
+        let next = index + 1;
+        while next < self.skyline.len() {
+            let segment = &mut self.skyline[next];
+            if segment.x >= end {
+                break;
+            }
+            let segment_end = segment.x + segment.width;
+            if segment_end <= end {
+                self.skyline.remove(next);
+            } else {
+                segment.width = segment_end - end;
+                segment.x = end;
+                break;
+            }
+        }
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].width += self.skyline[i + 1].width;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+///
+/// Where an inserted image ended up in the atlas texture, in texels and in UVs.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasSlot {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+impl AtlasSlot {
+    fn new(position: [u32; 2], width: u32, height: u32, atlas_size: u32) -> Self {
+        let [x, y] = position;
+        let size = atlas_size as f32;
+        Self {
+            x,
+            y,
+            width,
+            height,
+            uv_min: Vec2::new(x as f32, y as f32) / size,
+            uv_max: Vec2::new((x + width) as f32, (y + height) as f32) / size,
+        }
+    }
+}
+
+///
+/// The image didn't fit, not even after growing the atlas to its max size or evicting
+/// whatever eviction allows.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasFull {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::fmt::Display for AtlasFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no room for {}x{} texels in the atlas",
+            self.width, self.height
+        )
+    }
+}
+
+impl std::error::Error for AtlasFull {}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    slot: AtlasSlot,
+    pixels: Vec<u8>,
+    last_use: u64,
+}
+
+///
+/// Square texture of images looked up by key. When an insert doesn't fit, the atlas
+/// first doubles its size up to the max size, set_max_size, repacking every slot into a
+/// new texture. At the max size it evicts the least recently used slots if
+/// set_lru_eviction allows, and repacks the rest. Either way slots move, so revision
+/// changes and UVs from before have to be looked up again.
+///
+#[derive(Debug)]
+pub struct GlyphAtlas<K> {
+    texture: TextureHandle,
+    format: Format,
+    texel_size: u32,
+    size: u32,
+    max_size: u32,
+    is_lru_eviction: bool,
+    packer: SkylinePacker,
+    entries: HashMap<K, Entry>,
+    clock: u64,
+    revision: u64,
+}
+
+impl<K: Hash + Eq + Clone> GlyphAtlas<K> {
+    ///
+    /// Empty atlas of size by size texels, R8_UNORM being the usual format for glyphs.
+    /// Panics on block compressed formats.
+    ///
+    pub fn new(renderer: &mut Renderer, size: u32, format: Format) -> Self {
+        let texel_size = format
+            .texel_size()
+            .unwrap_or_else(|| panic!("can't pack {} images into an atlas!", format));
+        Self {
+            texture: make_texture(renderer, size, format, texel_size),
+            format,
+            texel_size,
+            size,
+            max_size: size,
+            is_lru_eviction: false,
+            packer: SkylinePacker::new(size, size),
+            entries: HashMap::new(),
+            clock: 0,
+            revision: 0,
+        }
+    }
+
+    ///
+    /// Changes whenever the atlas gets repacked, the old one is freed.
+    ///
+    pub fn texture(&self) -> TextureHandle {
+        self.texture
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    ///
+    /// Bumped whenever slots move, by growing or evicting.
+    ///
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn occupancy(&self) -> f32 {
+        self.packer.occupancy()
+    }
+
+    ///
+    /// Size the atlas can grow to, rounded down to a power of two multiple of its size.
+    ///
+    pub fn set_max_size(&mut self, max_size: u32) {
+        self.max_size = max_size;
+    }
+
+    pub fn set_lru_eviction(&mut self, is_lru_eviction: bool) {
+        self.is_lru_eviction = is_lru_eviction;
+    }
+
+    ///
+    /// Packs the image and queues its texels for uploading, width by height texels of the
+    /// atlas format, row by row. Inserting a key again replaces its image. Panics if the
+    /// pixels don't add up to the image.
+    ///
+    pub fn insert(
+        &mut self,
+        renderer: &mut Renderer,
+        key: K,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<AtlasSlot, AtlasFull> {
+        let size = (width * height * self.texel_size) as usize;
+        if pixels.len() != size {
+            panic!(
+                "{} bytes for a {}x{} image of {}!",
+                pixels.len(),
+                width,
+                height,
+                self.format
+            );
+        }
+        let texture = renderer
+            .fetch_texture(self.texture)
+            .expect("atlas texture got freed!");
+        if texture.is_evicted() {
+            // Whatever a restorer would write isn't the slots
+            self.repack(renderer, self.size, None);
+        }
+        self.clock += 1;
+        // Its old space stays taken until the next repack
+        self.entries.remove(&key);
+        let full = AtlasFull { width, height };
+        let padded = [width + PADDING, height + PADDING];
+        if padded[0] > self.max_size || padded[1] > self.max_size {
+            return Err(full);
+        }
+        let position = if width == 0 || height == 0 {
+            Some([0, 0])
+        } else {
+            self.packer.pack(padded[0], padded[1])
+        };
+        let slot = match position {
+            Some(e) => AtlasSlot::new(e, width, height, self.size),
+            None => self.make_room(renderer, padded).ok_or(full)?,
+        };
+        let entry = Entry {
+            slot,
+            pixels: pixels.to_vec(),
+            last_use: self.clock,
+        };
+        self.upload(renderer, &entry);
+        self.entries.insert(key, entry);
+        Ok(slot)
+    }
+
+    ///
+    /// Slot of the key, counting as a use of it for eviction.
+    ///
+    pub fn get(&mut self, key: &K) -> Option<AtlasSlot> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_use = self.clock;
+        Some(entry.slot)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    ///
+    /// Drops the slot, its space is reused once the atlas gets repacked.
+    ///
+    pub fn remove(&mut self, key: &K) -> Option<AtlasSlot> {
+        self.entries.remove(key).map(|e| e.slot)
+    }
+
+    pub fn destroy(self, renderer: &mut Renderer) {
+        renderer
+            .free_texture(self.texture)
+            .expect("atlas texture got freed!");
+    }
+
+    /*
+     * Grows the atlas until the padded image fits along with every slot, then evicts
+     * down from the least recently used if allowed. Returns the slot of the image.
+     */
+    fn make_room(&mut self, renderer: &mut Renderer, padded: [u32; 2]) -> Option<AtlasSlot> {
+        let mut size = self.size;
+        while size * 2 <= self.max_size {
+            size *= 2;
+            if let Some(slot) = self.repack(renderer, size, Some(padded)) {
+                return Some(slot);
+            }
+        }
+        if !self.is_lru_eviction {
+            return None;
+        }
+        while !self.entries.is_empty() {
+            let mut uses: Vec<_> = self
+                .entries
+                .iter()
+                .map(|(k, e)| (e.last_use, k.clone()))
+                .collect();
+            uses.sort_unstable_by_key(|e| e.0);
+            let evicted = (uses.len() / EVICTED_FRACTION).max(1);
+            for (_, key) in uses.into_iter().take(evicted) {
+                self.entries.remove(&key);
+            }
+            if let Some(slot) = self.repack(renderer, self.size, Some(padded)) {
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    /*
+     * Packs every slot, tallest first, and the extra padded image last into an atlas of
+     * the size. Nothing changes if they don't fit. Otherwise the slots get uploaded again
+     * at their new spots into a new zeroed texture, so nothing of evicted slots is left
+     * next to them, and the slot of the extra image is returned.
+     */
+    fn repack(
+        &mut self,
+        renderer: &mut Renderer,
+        size: u32,
+        extra: Option<[u32; 2]>,
+    ) -> Option<AtlasSlot> {
+        let mut packer = SkylinePacker::new(size, size);
+        let mut keys: Vec<K> = self.entries.keys().cloned().collect();
+        keys.sort_by_key(|e| std::cmp::Reverse(self.entries[e].slot.height));
+        let mut positions = Vec::with_capacity(keys.len());
+        for key in &keys {
+            let slot = self.entries[key].slot;
+            if slot.width == 0 || slot.height == 0 {
+                positions.push([0, 0]);
+                continue;
+            }
+            positions.push(packer.pack(slot.width + PADDING, slot.height + PADDING)?);
+        }
+        let extra = match extra {
+            Some([width, height]) => Some((packer.pack(width, height)?, width, height)),
+            None => None,
+        };
+        let texture = make_texture(renderer, size, self.format, self.texel_size);
+        renderer
+            .free_texture(self.texture)
+            .expect("atlas texture got freed!");
+        self.texture = texture;
+        self.size = size;
+        self.packer = packer;
+        self.revision += 1;
+        for (key, position) in keys.iter().zip(positions) {
+            let entry = self.entries.get_mut(key).unwrap();
+            entry.slot = AtlasSlot::new(position, entry.slot.width, entry.slot.height, size);
+        }
+        for entry in self.entries.values() {
+            self.upload(renderer, entry);
+        }
+        extra.map(|(position, width, height)| {
+            AtlasSlot::new(position, width - PADDING, height - PADDING, size)
+        })
+    }
+
+    /*
+     * The slot along with its padding, zeroed.
+     */
+    fn upload(&self, renderer: &mut Renderer, entry: &Entry) {
+        let slot = entry.slot;
+        if slot.width == 0 || slot.height == 0 {
+            return;
+        }
+        let row = (slot.width * self.texel_size) as usize;
+        let padded_row = row + (PADDING * self.texel_size) as usize;
+        let mut bytes = vec![0; padded_row * (slot.height + PADDING) as usize];
+        for (src, dst) in entry
+            .pixels
+            .chunks_exact(row)
+            .zip(bytes.chunks_exact_mut(padded_row))
+        {
+            dst[..row].copy_from_slice(src);
+        }
+        let region = TextureRegion {
+            mip: 0,
+            offset: [slot.x, slot.y],
+            extent: [slot.width + PADDING, slot.height + PADDING],
+        };
+        renderer
+            .update_texture_region(self.texture, region, &bytes)
+            .expect("atlas texture got freed!");
+    }
+}
+
+/*
+ * Zeroed texture queued for uploading, regions written before it's uploaded wait for it.
+ */
+fn make_texture(
+    renderer: &mut Renderer,
+    size: u32,
+    format: Format,
+    texel_size: u32,
+) -> TextureHandle {
+    let bytes = size * size * texel_size;
+    let mip_maps = [MipMap {
+        index: 0,
+        width: size,
+        height: size,
+        size: bytes,
+        offset: 0,
+    }];
+    let texture = renderer.gen_texture("glyph atlas".to_string(), format, &mip_maps, bytes);
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::write_bytes(staging.addr as *mut u8, 0, bytes as usize) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    texture
+}
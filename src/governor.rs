@@ -0,0 +1,222 @@
+use std::{collections::VecDeque, time::Duration};
+
+///
+/// Bounds and thresholds of the quality governor, see Renderer::set_quality_governor.
+/// Quality degrades once the average GPU time over window frames is above
+/// target_gpu_time * degrade_ratio, or the texture memory headroom drops below
+/// min_memory_headroom. It recovers once the average is below
+/// target_gpu_time * recover_ratio with enough headroom. Between the two ratios nothing
+/// changes, and at most one step happens every cooldown_frames frames.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityGovernorConfig {
+    pub target_gpu_time: Duration,
+    pub window: u32,
+    pub degrade_ratio: f32,
+    pub recover_ratio: f32,
+    pub cooldown_frames: u32,
+    // Fraction of the texture memory budget left, ignored without a budget
+    pub min_memory_headroom: f32,
+    pub min_lod_bias: f32,
+    pub max_lod_bias: f32,
+    pub lod_bias_step: f32,
+    // Render scale only drops once the lod bias is at its max
+    pub is_render_scale_enabled: bool,
+    pub min_render_scale: f32,
+    pub max_render_scale: f32,
+    pub render_scale_step: f32,
+}
+
+impl Default for QualityGovernorConfig {
+    fn default() -> Self {
+        Self {
+            target_gpu_time: Duration::from_micros(16_667),
+            window: 30,
+            degrade_ratio: 1.1,
+            recover_ratio: 0.8,
+            cooldown_frames: 60,
+            min_memory_headroom: 0.05,
+            min_lod_bias: 0.0,
+            max_lod_bias: 2.0,
+            lod_bias_step: 0.5,
+            is_render_scale_enabled: false,
+            min_render_scale: 0.5,
+            max_render_scale: 1.0,
+            render_scale_step: 0.125,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Pressure {
+    #[default]
+    None,
+    GpuTime,
+    Memory,
+    // Comfortably under the target, quality can go back up
+    Relaxed,
+}
+
+///
+/// Host provided values for the next frame only, replacing what the governor decided.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct QualityOverride {
+    pub lod_bias: Option<f32>,
+    pub render_scale: Option<f32>,
+}
+
+///
+/// What the governor decided and the inputs it decided on.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GovernorState {
+    pub lod_bias: f32,
+    // The renderer has no dynamic resolution, hosts scale what they render by this
+    pub render_scale: f32,
+    // Average over the window, None until the window fills up
+    pub average_gpu_time: Option<Duration>,
+    pub memory_headroom: Option<f32>,
+    pub pressure: Pressure,
+    pub is_overridden: bool,
+}
+
+///
+/// Control loop behind Renderer::set_quality_governor. Fed the GPU time and memory
+/// headroom of every frame, doesn't touch the GPU itself.
+///
+pub struct QualityGovernor {
+    config: QualityGovernorConfig,
+    gpu_times: VecDeque<Duration>,
+    frames_since_step: u32,
+    lod_bias: f32,
+    render_scale: f32,
+    state: GovernorState,
+}
+
+impl QualityGovernor {
+    pub fn new(config: QualityGovernorConfig) -> Self {
+        if config.window == 0 {
+            panic!("quality governor window can't be empty!");
+        }
+        if config.min_lod_bias > config.max_lod_bias
+            || config.min_render_scale > config.max_render_scale
+        {
+            panic!("quality governor bounds have min over max!");
+        }
+        let lod_bias = config.min_lod_bias;
+        let render_scale = config.max_render_scale;
+        Self {
+            gpu_times: VecDeque::with_capacity(config.window as usize),
+            frames_since_step: config.cooldown_frames,
+            lod_bias,
+            render_scale,
+            state: GovernorState {
+                lod_bias,
+                render_scale,
+                average_gpu_time: None,
+                memory_headroom: None,
+                pressure: Pressure::None,
+                is_overridden: false,
+            },
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &QualityGovernorConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> &GovernorState {
+        &self.state
+    }
+
+    ///
+    /// Takes the inputs of a frame and decides the values for the next one. Overrides
+    /// show up in the state but don't move what the governor would do on its own.
+    ///
+    pub fn update(
+        &mut self,
+        gpu_time: Option<Duration>,
+        memory_headroom: Option<f32>,
+        overrides: QualityOverride,
+    ) -> &GovernorState {
+        if let Some(gpu_time) = gpu_time {
+            if self.gpu_times.len() == self.config.window as usize {
+                self.gpu_times.pop_front();
+            }
+            self.gpu_times.push_back(gpu_time);
+        }
+        self.frames_since_step = self.frames_since_step.saturating_add(1);
+        let average_gpu_time = (self.gpu_times.len() == self.config.window as usize)
+            .then(|| self.gpu_times.iter().sum::<Duration>() / self.config.window);
+        let pressure = self.pressure_of(average_gpu_time, memory_headroom);
+        if self.frames_since_step >= self.config.cooldown_frames {
+            let has_stepped = match pressure {
+                Pressure::GpuTime | Pressure::Memory => self.degrade(),
+                Pressure::Relaxed => self.recover(),
+                Pressure::None => false,
+            };
+            if has_stepped {
+                // Frames timed before the step don't tell anything about the new values
+                self.gpu_times.clear();
+                self.frames_since_step = 0;
+            }
+        }
+        self.state = GovernorState {
+            lod_bias: overrides.lod_bias.unwrap_or(self.lod_bias),
+            render_scale: overrides.render_scale.unwrap_or(self.render_scale),
+            average_gpu_time,
+            memory_headroom,
+            pressure,
+            is_overridden: overrides != QualityOverride::default(),
+        };
+        &self.state
+    }
+
+    fn pressure_of(&self, average_gpu_time: Option<Duration>, headroom: Option<f32>) -> Pressure {
+        let is_memory_low = headroom.is_some_and(|e| e < self.config.min_memory_headroom);
+        if is_memory_low {
+            return Pressure::Memory;
+        }
+        let average = match average_gpu_time {
+            Some(e) => e.as_secs_f32(),
+            None => return Pressure::None,
+        };
+        let target = self.config.target_gpu_time.as_secs_f32();
+        if average > target * self.config.degrade_ratio {
+            Pressure::GpuTime
+        } else if average < target * self.config.recover_ratio {
+            Pressure::Relaxed
+        } else {
+            Pressure::None
+        }
+    }
+
+    fn degrade(&mut self) -> bool {
+        let c = &self.config;
+        if self.lod_bias < c.max_lod_bias {
+            self.lod_bias = (self.lod_bias + c.lod_bias_step).min(c.max_lod_bias);
+            true
+        } else if c.is_render_scale_enabled && self.render_scale > c.min_render_scale {
+            self.render_scale = (self.render_scale - c.render_scale_step).max(c.min_render_scale);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Undoes the steps of degrade in reverse
+    fn recover(&mut self) -> bool {
+        let c = &self.config;
+        if self.render_scale < c.max_render_scale {
+            self.render_scale = (self.render_scale + c.render_scale_step).min(c.max_render_scale);
+            true
+        } else if self.lod_bias > c.min_lod_bias {
+            self.lod_bias = (self.lod_bias - c.lod_bias_step).max(c.min_lod_bias);
+            true
+        } else {
+            false
+        }
+    }
+}
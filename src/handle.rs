@@ -0,0 +1,113 @@
+///
+/// Returned when a handle refers to a slot that has been freed since the handle was
+/// made, the slot may hold something else by now.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaleHandle;
+
+impl std::fmt::Display for StaleHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stale handle, the resource it referred to was freed")
+    }
+}
+
+impl std::error::Error for StaleHandle {}
+
+///
+/// Returned when an id asked for explicitly is out of range or still in use. Freed
+/// texture slots stay in use until released at the start of the next frame.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdUnavailable {
+    pub id: u32,
+}
+
+impl std::fmt::Display for IdUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "id {} is out of range or already in use", self.id)
+    }
+}
+
+impl std::error::Error for IdUnavailable {}
+
+macro_rules! handle {
+    ($name:ident) => {
+        ///
+        /// Index of the slot plus the generation of the slot at the time it was handed
+        /// out. Only the index ever reaches the GPU.
+        ///
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub struct $name {
+            pub index: u32,
+            pub generation: u32,
+        }
+
+        impl $name {
+            ///
+            /// Unpacks a handle from to_raw, for crossing FFI boundaries.
+            ///
+            pub const fn from_raw(raw: u64) -> Self {
+                Self {
+                    index: raw as u32,
+                    generation: (raw >> 32) as u32,
+                }
+            }
+
+            ///
+            /// Generation in the high 32 bits, index in the low 32 bits.
+            ///
+            pub const fn to_raw(self) -> u64 {
+                ((self.generation as u64) << 32) | self.index as u64
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}v{}", self.index, self.generation)
+            }
+        }
+    };
+}
+
+handle!(MeshHandle);
+handle!(TextureHandle);
+handle!(SceneSlotId);
+
+///
+/// Host side metadata of a texture or mesh, only kept for tooling to tell which asset a
+/// resource came from. Never reaches the GPU.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceMeta {
+    pub source_path: Option<String>,
+    // Of the source data, whatever hash the host uses, see Renderer::find_texture_by_hash
+    pub content_hash: Option<u64>,
+    pub user_tag: u64,
+}
+
+///
+/// Current generation of every slot, bumped each time a slot is freed so the handles
+/// handed out for it before stop matching.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Generations {
+    by_index: Vec<u32>,
+}
+
+impl Generations {
+    pub fn of(&self, index: u32) -> u32 {
+        self.by_index.get(index as usize).copied().unwrap_or(0)
+    }
+
+    pub fn is_current(&self, index: u32, generation: u32) -> bool {
+        self.of(index) == generation
+    }
+
+    pub fn bump(&mut self, index: u32) {
+        let index = index as usize;
+        if index >= self.by_index.len() {
+            self.by_index.resize(index + 1, 0);
+        }
+        self.by_index[index] = self.by_index[index].wrapping_add(1);
+    }
+}
@@ -0,0 +1,472 @@
+use ash::vk;
+
+use crate::context::VulkanContext;
+use crate::handle::TextureHandle;
+use crate::renderer::Renderer;
+use crate::shader;
+use crate::texture::{MipMap, Texture};
+
+/*
+ * Image based lighting maps baked out of an environment cube by compute dispatches: a
+ * specular cube prefiltered with a rougher GGX lobe every mip, an irradiance cube and the
+ * split sum BRDF table. The shaders in shader/ibl get compiled with glslangValidator like
+ * the pipeline ones, the bake runs on the setup command buffer and signals a timeline
+ * semaphore of its own. The views and
+ * descriptors of a bake stay around until the GPU is done with it.
+ */
+
+// GLSL of the bake, compiled next to it when a baker gets made
+const SHADER_DIR: &str = "shader/ibl";
+// Pipeline names and the shaders they run, in the order of IblBaker::pipelines
+const SHADERS: [(&str, &str); 3] = [
+    ("ibl_prefilter", "prefilter.comp"),
+    ("ibl_irradiance", "irradiance.comp"),
+    ("ibl_brdf_lut", "brdf_lut.comp"),
+];
+// Invocations along each side of a workgroup, local_size of the shaders
+const GROUP_SIZE: u32 = 8;
+// Filterable and storable everywhere
+pub const FORMAT: crate::format::Format = crate::format::Format::R16G16B16A16_SFLOAT;
+
+///
+/// Sizes of the baked maps in texels along a side, and how many samples every texel
+/// takes. Mip 0 of the specular cube is the mirror reflection, the last one fully rough.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IblBakeDesc {
+    pub specular_size: u32,
+    pub specular_mips: u32,
+    pub irradiance_size: u32,
+    pub brdf_lut_size: u32,
+    pub sample_count: u32,
+}
+
+impl Default for IblBakeDesc {
+    fn default() -> Self {
+        Self {
+            specular_size: 256,
+            specular_mips: 6,
+            irradiance_size: 32,
+            brdf_lut_size: 128,
+            sample_count: 512,
+        }
+    }
+}
+
+///
+/// Textures made by Renderer::bake_ibl. They can be handed to tasks right away, frames
+/// submitted after the bake wait for it. Freed like any other texture.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IblMaps {
+    pub specular: TextureHandle,
+    pub irradiance: TextureHandle,
+    pub brdf_lut: TextureHandle,
+    // Bake timeline value signaled once done
+    pub(crate) value: u64,
+}
+
+impl IblMaps {
+    ///
+    /// Whether the GPU is done baking them, doesn't block.
+    ///
+    pub fn is_ready(&self, renderer: &Renderer) -> bool {
+        renderer.is_ibl_bake_done(self.value)
+    }
+}
+
+///
+/// Mips of a map of the size along a side, halving down to the count given. The size of
+/// every mip covers all the layers.
+///
+pub fn mip_maps(size: u32, count: u32, layers: u32) -> Vec<MipMap> {
+    assert!(
+        count > 0 && size >> (count - 1) > 0,
+        "{} mips don't fit a size of {}!",
+        count,
+        size
+    );
+    let mut offset = 0;
+    (0..count)
+        .map(|index| {
+            let extent = size >> index;
+            let mip = MipMap {
+                index,
+                width: extent,
+                height: extent,
+                size: FORMAT.size_for(extent, extent) * layers,
+                offset,
+            };
+            offset += mip.size;
+            mip
+        })
+        .collect()
+}
+
+///
+/// SPIR-V of the bake shaders with the names of their pipelines, compiled from the GLSL
+/// in shader/ibl. Panics if glslangValidator fails on them.
+///
+pub fn compile_shaders() -> [(&'static str, Vec<u8>); 3] {
+    SHADERS.map(|(name, file)| {
+        let src = format!("{SHADER_DIR}/{file}");
+        let out = format!("{src}.spv");
+        shader::compile_glsl(file, &[&src, "-V", "-o", &out]);
+        let spirv =
+            std::fs::read(&out).unwrap_or_else(|e| panic!("failed reading {}: {}", out, e));
+        (name, spirv)
+    })
+}
+
+/*
+ * Views and descriptors one bake reads and writes through.
+ */
+struct BakeResources {
+    pool: vk::DescriptorPool,
+    views: Vec<vk::ImageView>,
+}
+
+pub struct IblBaker {
+    // Prefilter, irradiance and BRDF table, in that order
+    pipelines: [vk::Pipeline; 3],
+    layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    pub semaphore: vk::Semaphore,
+    // Signaled by the last bake submitted
+    pub value: u64,
+    in_flight: Vec<(u64, BakeResources)>,
+}
+
+impl IblBaker {
+    pub fn new(ctx: &VulkanContext) -> Self {
+        let device = &ctx.device;
+        // Source cube gets read from blurrier mips the wider the lobe
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None) }.unwrap();
+        let samplers = [sampler];
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .immutable_samplers(&samplers)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let set_layout =
+            unsafe { device.create_descriptor_set_layout(&set_layout_info, None) }.unwrap();
+        let set_layouts = [set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<[u32; 3]>() as u32,
+        }];
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None) }.unwrap();
+        let pipelines = compile_shaders()
+            .map(|(name, spirv)| Self::compute_pipeline(ctx, layout, name, &spirv));
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0)
+            .build();
+        let semaphore_info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut type_info)
+            .build();
+        let semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }.unwrap();
+        ctx.try_set_debug_name("ibl_bake_timeline_semaphore", semaphore);
+        Self {
+            pipelines,
+            layout,
+            set_layout,
+            sampler,
+            semaphore,
+            value: 0,
+            in_flight: Vec::new(),
+        }
+    }
+
+    /*
+     * Makes the pipeline of the compiled shader.
+     */
+    fn compute_pipeline(
+        ctx: &VulkanContext,
+        layout: vk::PipelineLayout,
+        name: &str,
+        spirv: &[u8],
+    ) -> vk::Pipeline {
+        let device = &ctx.device;
+        // Copies into words, the bytes carry no alignment
+        let code = ash::util::read_spv(&mut std::io::Cursor::new(spirv))
+            .unwrap_or_else(|e| panic!("failed to load {}: {}", name, e));
+        let module_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+        let module = unsafe { device.create_shader_module(&module_info, None) }
+            .unwrap_or_else(|e| panic!("{} shader module error: {}", name, e));
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .module(module)
+                    .name(c"main")
+                    .build(),
+            )
+            .layout(layout)
+            .build();
+        let pipeline = unsafe {
+            device.create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+        }
+        .map_err(|e| e.1)
+        .unwrap_or_else(|e| panic!("failed creating the {} pipeline: {}", name, e))[0];
+        unsafe { device.destroy_shader_module(module, None) };
+        ctx.try_set_debug_name(&format!("{}_pipeline", name), pipeline);
+        pipeline
+    }
+
+    ///
+    /// Last value a bake signaled on the semaphore.
+    ///
+    pub fn completed(&self, device: &ash::Device) -> u64 {
+        unsafe { device.get_semaphore_counter_value(self.semaphore) }.unwrap()
+    }
+
+    ///
+    /// Destroys the views and descriptors of the bakes the GPU is done with.
+    ///
+    pub fn release_finished(&mut self, device: &ash::Device) {
+        let completed = self.completed(device);
+        self.in_flight.retain(|(value, resources)| {
+            if *value > completed {
+                return true;
+            }
+            Self::destroy_resources(device, resources);
+            false
+        });
+    }
+
+    fn destroy_resources(device: &ash::Device, resources: &BakeResources) {
+        unsafe {
+            device.destroy_descriptor_pool(resources.pool, None);
+            for view in &resources.views {
+                device.destroy_image_view(*view, None);
+            }
+        }
+    }
+
+    ///
+    /// Records the whole bake, the source has to be in the read only layout and the maps
+    /// fresh out of texture::make. Leaves the maps in the read only layout. Their views
+    /// and descriptors go once the value after the current one is signaled.
+    ///
+    pub fn record(
+        &mut self,
+        ctx: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+        source: &Texture,
+        maps: [&Texture; 3],
+        sample_count: u32,
+    ) {
+        let device = &ctx.device;
+        let [specular, irradiance, brdf_lut] = maps;
+        // Every specular mip, then the irradiance cube and the table, with their pipelines
+        let targets: Vec<_> = (0..specular.mip_map_count())
+            .map(|mip| (specular, mip, 0))
+            .chain([(irradiance, 0, 1), (brdf_lut, 0, 2)])
+            .collect();
+        let views: Vec<_> = targets
+            .iter()
+            .map(|(texture, mip, _)| {
+                let info = vk::ImageViewCreateInfo::builder()
+                    .image(texture.image)
+                    .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                    .format(texture.format.to_vk())
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: *mip,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: texture.layers,
+                    });
+                unsafe { device.create_image_view(&info, None) }.unwrap()
+            })
+            .collect();
+        let set_count = views.len() as u32;
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: set_count,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: set_count,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(set_count)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { device.create_descriptor_pool(&pool_info, None) }.unwrap();
+        let set_layouts = vec![self.set_layout; views.len()];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let sets = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+            .expect("couldn't allocate the IBL bake descriptor sets!");
+        let read = [vk::DescriptorImageInfo {
+            image_view: source.view,
+            image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+            ..Default::default()
+        }];
+        let writes: Vec<_> = views
+            .iter()
+            .map(|view| {
+                [vk::DescriptorImageInfo {
+                    image_view: *view,
+                    image_layout: vk::ImageLayout::GENERAL,
+                    ..Default::default()
+                }]
+            })
+            .collect();
+        let descriptor_writes: Vec<_> = sets
+            .iter()
+            .zip(&writes)
+            .flat_map(|(set, write)| {
+                [
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(*set)
+                        .dst_binding(0)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(&read)
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(*set)
+                        .dst_binding(1)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(write)
+                        .build(),
+                ]
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        // Whatever wrote the source before, uploads included, is done before sampling it
+        let source_barrier = vk::ImageMemoryBarrier2::builder()
+            .image(source.image)
+            .old_layout(vk::ImageLayout::READ_ONLY_OPTIMAL)
+            .new_layout(vk::ImageLayout::READ_ONLY_OPTIMAL)
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+            .subresource_range(source.subresource_range())
+            .build();
+        let to_general = maps.map(|e| {
+            vk::ImageMemoryBarrier2::builder()
+                .image(e.image)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                .subresource_range(e.subresource_range())
+                .build()
+        });
+        let to_read_only = maps.map(|e| {
+            vk::ImageMemoryBarrier2::builder()
+                .image(e.image)
+                .old_layout(vk::ImageLayout::GENERAL)
+                .new_layout(vk::ImageLayout::READ_ONLY_OPTIMAL)
+                .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+                .subresource_range(e.subresource_range())
+                .build()
+        });
+        let mut before = vec![source_barrier];
+        before.extend(to_general);
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before),
+            );
+        }
+        let last_mip = specular.mip_map_count().saturating_sub(1).max(1);
+        for ((texture, mip, pipeline), set) in targets.iter().zip(&sets) {
+            // Only the specular mips get rougher, the others ignore it
+            let roughness = *mip as f32 / last_mip as f32;
+            let size = texture.width() >> mip;
+            let registers = [size, sample_count, roughness.to_bits()];
+            unsafe {
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipelines[*pipeline],
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.layout,
+                    0,
+                    &[*set],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    registers.align_to::<u8>().1,
+                );
+                device.cmd_dispatch(
+                    command_buffer,
+                    size.div_ceil(GROUP_SIZE),
+                    size.div_ceil(GROUP_SIZE),
+                    texture.layers,
+                );
+            }
+        }
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&to_read_only),
+            );
+        }
+        for ((texture, mip, _), view) in targets.iter().zip(&views) {
+            let name = format!("{}_mip_{}_storage_view", texture.name, mip);
+            ctx.try_set_debug_name(&name, *view);
+        }
+        self.in_flight
+            .push((self.value + 1, BakeResources { pool, views }));
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for (_, resources) in self.in_flight.drain(..) {
+            Self::destroy_resources(device, &resources);
+        }
+        unsafe {
+            for pipeline in self.pipelines {
+                device.destroy_pipeline(pipeline, None);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_semaphore(self.semaphore, None);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::{context::VulkanContext, memory::PoolMemoryReport, range_allocator::RangeAllocator};
+
+#[derive(Copy, Clone, Debug)]
+pub struct ImageAllocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: u64,
+    pub size: u64,
+    pub memory_type_index: u32,
+    pub memory_flags: vk::MemoryPropertyFlags,
+    pub is_dedicated: bool,
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    ranges: RangeAllocator,
+}
+
+#[derive(Copy, Clone, Default)]
+struct DedicatedUsage {
+    count: u32,
+    size: u64,
+}
+
+///
+/// Suballocates image memory from big blocks, grouped by memory type, to stay away from
+/// the driver's allocation count limit. Only optimal tiling images are placed in here,
+/// so buffer-image granularity never comes into play.
+///
+pub struct ImagePool {
+    block_size: u64,
+    blocks_by_type: HashMap<u32, Vec<MemoryBlock>>,
+    dedicated_by_type: HashMap<u32, DedicatedUsage>,
+}
+
+impl ImagePool {
+    pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+    pub fn new(block_size: u64) -> Self {
+        Self {
+            block_size,
+            blocks_by_type: HashMap::new(),
+            dedicated_by_type: HashMap::new(),
+        }
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    ///
+    /// Only affects blocks allocated from now on.
+    ///
+    pub fn set_block_size(&mut self, block_size: u64) {
+        self.block_size = block_size;
+    }
+
+    ///
+    /// Finds memory for the image and binds it. Very large images, lazily allocated memory
+    /// and images the driver would rather have on their own get a dedicated allocation.
+    ///
+    pub fn alloc(
+        &mut self,
+        ctx: &VulkanContext,
+        image: vk::Image,
+        preferred_memory_flags: &[vk::MemoryPropertyFlags],
+    ) -> ImageAllocation {
+        let (requirements, is_dedicated_preferred) = requirements_of(ctx, image);
+        let (memory_type_index, memory_flags) = ctx
+            .memory_type_index_for_any(requirements.memory_type_bits, preferred_memory_flags)
+            .expect("no suitable memory type for the image!");
+        let is_dedicated = is_dedicated_preferred
+            || requirements.size > self.block_size / 2
+            || memory_flags.contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED);
+        if is_dedicated {
+            let usage = self.dedicated_by_type.entry(memory_type_index).or_default();
+            usage.count += 1;
+            usage.size += requirements.size;
+            return alloc_dedicated(ctx, image, &requirements, memory_type_index, memory_flags);
+        }
+        let blocks = self.blocks_by_type.entry(memory_type_index).or_default();
+        let found = blocks.iter_mut().find_map(|block| {
+            block
+                .ranges
+                .alloc(requirements.size, requirements.alignment)
+                .map(|offset| (block.memory, offset))
+        });
+        let (memory, offset) = match found {
+            Some(found) => found,
+            None => {
+                let memory_allocate_info = vk::MemoryAllocateInfo::builder()
+                    .allocation_size(self.block_size)
+                    .memory_type_index(memory_type_index)
+                    .build();
+                let memory = unsafe {
+                    ctx.device
+                        .allocate_memory(&memory_allocate_info, None)
+                        .expect("failed image pool block alloc")
+                };
+                ctx.try_set_debug_name(
+                    &format!("image_pool_{}_{}", memory_type_index, blocks.len()),
+                    memory,
+                );
+                let mut ranges = RangeAllocator::new(self.block_size);
+                let offset = ranges
+                    .alloc(requirements.size, requirements.alignment)
+                    .expect("image doesn't fit in an empty image pool block!");
+                blocks.push(MemoryBlock { memory, ranges });
+                (memory, offset)
+            }
+        };
+        unsafe {
+            ctx.device
+                .bind_image_memory(image, memory, offset)
+                .expect("failed image memory bind")
+        };
+        ImageAllocation {
+            memory,
+            offset,
+            size: requirements.size,
+            memory_type_index,
+            memory_flags,
+            is_dedicated: false,
+        }
+    }
+
+    pub fn free(&mut self, device: &ash::Device, allocation: ImageAllocation) {
+        if allocation.is_dedicated {
+            if let Some(usage) = self
+                .dedicated_by_type
+                .get_mut(&allocation.memory_type_index)
+            {
+                usage.count -= 1;
+                usage.size -= allocation.size;
+            }
+            unsafe { device.free_memory(allocation.memory, None) };
+            return;
+        }
+        let block = self
+            .blocks_by_type
+            .get_mut(&allocation.memory_type_index)
+            .and_then(|e| e.iter_mut().find(|e| e.memory == allocation.memory))
+            .expect("image allocation doesn't belong to the pool!");
+        block.ranges.free(allocation.offset, allocation.size);
+    }
+
+    ///
+    /// Gives the blocks nothing is allocated from back to the driver, returns how many
+    /// bytes that freed.
+    ///
+    pub fn release_unused_blocks(&mut self, device: &ash::Device) -> u64 {
+        let mut released = 0;
+        for blocks in self.blocks_by_type.values_mut() {
+            blocks.retain(|block| {
+                if !block.ranges.is_unused() {
+                    return true;
+                }
+                unsafe { device.free_memory(block.memory, None) };
+                released += block.ranges.size();
+                false
+            });
+        }
+        self.blocks_by_type.retain(|_, e| !e.is_empty());
+        released
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for block in self.blocks_by_type.values().flatten() {
+            unsafe { device.free_memory(block.memory, None) };
+        }
+        self.blocks_by_type.clear();
+    }
+
+    pub fn report(&self) -> Vec<PoolMemoryReport> {
+        let mut types: Vec<_> = self
+            .blocks_by_type
+            .keys()
+            .chain(self.dedicated_by_type.keys())
+            .copied()
+            .collect();
+        types.sort();
+        types.dedup();
+        types
+            .into_iter()
+            .map(|memory_type_index| {
+                let blocks = self
+                    .blocks_by_type
+                    .get(&memory_type_index)
+                    .map_or(&[][..], |e| e.as_slice());
+                let dedicated = self
+                    .dedicated_by_type
+                    .get(&memory_type_index)
+                    .copied()
+                    .unwrap_or_default();
+                PoolMemoryReport {
+                    memory_type_index,
+                    block_count: blocks.len() as u32,
+                    size: blocks.iter().map(|e| e.ranges.size()).sum(),
+                    available: blocks.iter().map(|e| e.ranges.available()).sum(),
+                    largest_free: blocks
+                        .iter()
+                        .map(|e| e.ranges.largest_free())
+                        .max()
+                        .unwrap_or(0),
+                    free_range_count: blocks.iter().map(|e| e.ranges.free_range_count()).sum(),
+                    dedicated_count: dedicated.count,
+                    dedicated_size: dedicated.size,
+                }
+            })
+            .collect()
+    }
+}
+
+fn requirements_of(ctx: &VulkanContext, image: vk::Image) -> (vk::MemoryRequirements, bool) {
+    let mut dedicated_req = vk::MemoryDedicatedRequirements {
+        ..Default::default()
+    };
+    let mut memory_req = vk::MemoryRequirements2::builder()
+        .push_next(&mut dedicated_req)
+        .build();
+    let requirements_info = vk::ImageMemoryRequirementsInfo2::builder()
+        .image(image)
+        .build();
+    unsafe {
+        ctx.device
+            .get_image_memory_requirements2(&requirements_info, &mut memory_req)
+    };
+    let is_dedicated_preferred = dedicated_req.prefers_dedicated_allocation == vk::TRUE
+        || dedicated_req.requires_dedicated_allocation == vk::TRUE;
+    (memory_req.memory_requirements, is_dedicated_preferred)
+}
+
+///
+/// Allocates memory just for this image, outside of any pool.
+///
+pub fn alloc_dedicated_for(
+    ctx: &VulkanContext,
+    image: vk::Image,
+    preferred_memory_flags: &[vk::MemoryPropertyFlags],
+) -> ImageAllocation {
+    let (requirements, _) = requirements_of(ctx, image);
+    let (memory_type_index, memory_flags) = ctx
+        .memory_type_index_for_any(requirements.memory_type_bits, preferred_memory_flags)
+        .expect("no suitable memory type for the image!");
+    alloc_dedicated(ctx, image, &requirements, memory_type_index, memory_flags)
+}
+
+fn alloc_dedicated(
+    ctx: &VulkanContext,
+    image: vk::Image,
+    requirements: &vk::MemoryRequirements,
+    memory_type_index: u32,
+    memory_flags: vk::MemoryPropertyFlags,
+) -> ImageAllocation {
+    let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::builder()
+        .image(image)
+        .build();
+    let memory_allocate_info = vk::MemoryAllocateInfo::builder()
+        .push_next(&mut dedicated_info)
+        .allocation_size(requirements.size)
+        .memory_type_index(memory_type_index)
+        .build();
+    let memory = unsafe {
+        ctx.device
+            .allocate_memory(&memory_allocate_info, None)
+            .expect("failed image memory alloc")
+    };
+    unsafe {
+        ctx.device
+            .bind_image_memory(image, memory, 0)
+            .expect("failed image memory bind")
+    };
+    ImageAllocation {
+        memory,
+        offset: 0,
+        size: requirements.size,
+        memory_type_index,
+        memory_flags,
+        is_dedicated: true,
+    }
+}
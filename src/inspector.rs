@@ -0,0 +1,160 @@
+use ash::vk;
+
+use crate::{context::VulkanContext, pipeline::attachment::Attachment, texture::Texture};
+
+/*
+ * Texture inspector overlay, copies every mip of a texture onto the presented image at
+ * 1:1 pixels. Mip 0 sits at the top left corner, the rest of the chain stacked to its
+ * right, all of it clipped to the image.
+ */
+
+const MARGIN: i32 = 16;
+const GAP: i32 = 4;
+
+///
+/// Whether the texture format can be copied into the presented image, compressed
+/// formats for one can't.
+///
+pub fn can_inspect(ctx: &VulkanContext, texture: &Texture, target: &Attachment) -> bool {
+    let features_of = |format: vk::Format| unsafe {
+        ctx.instance
+            .get_physical_device_format_properties(ctx.physical_device, format)
+            .optimal_tiling_features
+    };
+    features_of(texture.format.to_vk()).contains(vk::FormatFeatureFlags::BLIT_SRC)
+        && features_of(target.vk_format).contains(vk::FormatFeatureFlags::BLIT_DST)
+}
+
+pub fn mip_chain_blits(texture: &Texture, target: vk::Extent2D) -> Vec<vk::ImageBlit> {
+    let mut blits = Vec::with_capacity(texture.mip_maps.len());
+    // Top of the next mip in the column right of mip 0
+    let mut chain_y = MARGIN;
+    for mip in &texture.mip_maps {
+        let (w, h) = (mip.width as i32, mip.height as i32);
+        let (x, y) = match mip.index {
+            0 => (MARGIN, MARGIN),
+            _ => {
+                chain_y += h + GAP;
+                (MARGIN + texture.width() as i32 + GAP, chain_y - h - GAP)
+            }
+        };
+        // 1:1, the source gets clipped the same as the destination
+        let dst_end = [
+            (x + w).min(target.width as i32),
+            (y + h).min(target.height as i32),
+        ];
+        if dst_end[0] > x && dst_end[1] > y {
+            let layers = vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: mip.index,
+                base_array_layer: 0,
+                layer_count: 1,
+            };
+            blits.push(vk::ImageBlit {
+                src_subresource: layers,
+                src_offsets: [
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: dst_end[0] - x,
+                        y: dst_end[1] - y,
+                        z: 1,
+                    },
+                ],
+                dst_subresource: vk::ImageSubresourceLayers {
+                    mip_level: 0,
+                    ..layers
+                },
+                dst_offsets: [
+                    vk::Offset3D { x, y, z: 0 },
+                    vk::Offset3D {
+                        x: dst_end[0],
+                        y: dst_end[1],
+                        z: 1,
+                    },
+                ],
+            });
+        }
+    }
+    blits
+}
+
+///
+/// Has to be recorded after the final stage, the target is expected to be ready for
+/// presenting and the texture to be uploaded.
+///
+pub fn record(
+    ctx: &VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    texture: &Texture,
+    target: &Attachment,
+) {
+    let blits = mip_chain_blits(texture, target.extent);
+    if blits.is_empty() {
+        return;
+    }
+    let barrier =
+        |image: vk::Image,
+         range: vk::ImageSubresourceRange,
+         from: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2),
+         to: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2)| {
+            vk::ImageMemoryBarrier2::builder()
+                .image(image)
+                .subresource_range(range)
+                .old_layout(from.0)
+                .src_access_mask(from.1)
+                .src_stage_mask(from.2)
+                .new_layout(to.0)
+                .dst_access_mask(to.1)
+                .dst_stage_mask(to.2)
+                .build()
+        };
+    use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+    let sampled = (
+        Il::SHADER_READ_ONLY_OPTIMAL,
+        Af::SHADER_READ,
+        Ps::FRAGMENT_SHADER,
+    );
+    let copy_src = (Il::TRANSFER_SRC_OPTIMAL, Af::TRANSFER_READ, Ps::BLIT);
+    // Keeps the contents of the frame, the overlay goes on top of it
+    let presented = (
+        Il::PRESENT_SRC_KHR,
+        Af::MEMORY_WRITE,
+        Ps::COLOR_ATTACHMENT_OUTPUT,
+    );
+    let copy_dst = (Il::TRANSFER_DST_OPTIMAL, Af::TRANSFER_WRITE, Ps::BLIT);
+    let texture_range = texture.subresource_range();
+    let target_range = Attachment::color_subresource_range();
+    let before = [
+        barrier(texture.image, texture_range, sampled, copy_src),
+        target.adapt_barrier(barrier(target.image, target_range, presented, copy_dst)),
+    ];
+    let after = [
+        barrier(texture.image, texture_range, copy_src, sampled),
+        target.adapt_barrier(barrier(
+            target.image,
+            target_range,
+            copy_dst,
+            (Il::PRESENT_SRC_KHR, Af::NONE, Ps::BOTTOM_OF_PIPE),
+        )),
+    ];
+    unsafe {
+        ctx.device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::builder().image_memory_barriers(&before),
+        );
+        // Linear like the default sampler, irrelevant at 1:1 anyway
+        ctx.device.cmd_blit_image(
+            command_buffer,
+            texture.image,
+            Il::TRANSFER_SRC_OPTIMAL,
+            target.image,
+            target.layout_for(Il::TRANSFER_DST_OPTIMAL),
+            &blits,
+            vk::Filter::LINEAR,
+        );
+        ctx.device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::builder().image_memory_barriers(&after),
+        );
+    }
+}
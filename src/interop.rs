@@ -0,0 +1,152 @@
+use ash::{extensions::khr, vk};
+
+use crate::context::VulkanContext;
+
+/*
+ * Timeline semaphore shared with a producer outside of the renderer, like a compute
+ * library writing vertex data the frames read, so neither side waits on the host.
+ *
+ * The handshake, with the exported handle imported by the producer on its own device:
+ *
+ * 1. The producer writes its buffers and signals some value V on its queue, V greater
+ *    than anything signaled on the semaphore before.
+ * 2. The host calls Renderer::wait_external_value_before_frame(V), the next frame
+ *    submitted waits on the GPU for V before any of its commands run.
+ * 3. Renderer::signal_value_after_frame() returns the value that frame signals once its
+ *    commands are done, V + 1. The producer waits on it before overwriting the buffers
+ *    and signals something greater than it next.
+ *
+ * Frames without a wait neither wait nor signal, so the value only moves forward.
+ */
+
+///
+/// Handle of the exported semaphore, owned by the caller. An fd gets closed by importing
+/// it, a Win32 handle has to be closed by the caller.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExternalSemaphoreHandle {
+    Fd(i32),
+    Win32(vk::HANDLE),
+}
+
+///
+/// Functions of the extension for the handle type of the platform.
+///
+#[derive(Clone)]
+pub enum ExternalSemaphoreFns {
+    Fd(khr::ExternalSemaphoreFd),
+    Win32(khr::ExternalSemaphoreWin32),
+}
+
+impl ExternalSemaphoreFns {
+    pub fn load(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+    ) -> Self {
+        if handle_type == vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_WIN32 {
+            Self::Win32(khr::ExternalSemaphoreWin32::new(instance, device))
+        } else {
+            Self::Fd(khr::ExternalSemaphoreFd::new(instance, device))
+        }
+    }
+
+    pub fn handle_type(&self) -> vk::ExternalSemaphoreHandleTypeFlags {
+        match self {
+            Self::Fd(_) => vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD,
+            Self::Win32(_) => vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_WIN32,
+        }
+    }
+}
+
+///
+/// Extension and handle type timeline semaphores of the device can be exported with on
+/// this platform, None if they can't.
+///
+pub fn export_support(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Option<(
+    &'static std::ffi::CStr,
+    vk::ExternalSemaphoreHandleTypeFlags,
+)> {
+    let (name, handle_type) = if cfg!(windows) {
+        (
+            khr::ExternalSemaphoreWin32::name(),
+            vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_WIN32,
+        )
+    } else {
+        (
+            khr::ExternalSemaphoreFd::name(),
+            vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD,
+        )
+    };
+    if !crate::renderer::is_device_extension_supported(instance, physical_device, name) {
+        return None;
+    }
+    let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+        .semaphore_type(vk::SemaphoreType::TIMELINE)
+        .build();
+    let info = vk::PhysicalDeviceExternalSemaphoreInfo::builder()
+        .handle_type(handle_type)
+        .push_next(&mut type_info)
+        .build();
+    let mut properties = vk::ExternalSemaphoreProperties::default();
+    unsafe {
+        instance.get_physical_device_external_semaphore_properties(
+            physical_device,
+            &info,
+            &mut properties,
+        )
+    };
+    properties
+        .external_semaphore_features
+        .contains(vk::ExternalSemaphoreFeatureFlags::EXPORTABLE)
+        .then_some((name, handle_type))
+}
+
+///
+/// Exportable timeline semaphore starting at 0.
+///
+pub fn make_semaphore(ctx: &VulkanContext, fns: &ExternalSemaphoreFns) -> vk::Semaphore {
+    let mut export_info = vk::ExportSemaphoreCreateInfo::builder()
+        .handle_types(fns.handle_type())
+        .build();
+    let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+        .semaphore_type(vk::SemaphoreType::TIMELINE)
+        .initial_value(0)
+        .build();
+    let create_info = vk::SemaphoreCreateInfo::builder()
+        .push_next(&mut type_info)
+        .push_next(&mut export_info)
+        .build();
+    let semaphore = unsafe { ctx.device.create_semaphore(&create_info, None) }.unwrap();
+    ctx.try_set_debug_name("interop_timeline_semaphore", semaphore);
+    semaphore
+}
+
+///
+/// New handle to the semaphore, every call exports another one.
+///
+pub fn export(
+    fns: &ExternalSemaphoreFns,
+    semaphore: vk::Semaphore,
+) -> Result<ExternalSemaphoreHandle, vk::Result> {
+    unsafe {
+        match fns {
+            ExternalSemaphoreFns::Fd(e) => {
+                let info = vk::SemaphoreGetFdInfoKHR::builder()
+                    .semaphore(semaphore)
+                    .handle_type(fns.handle_type());
+                e.get_semaphore_fd(&info).map(ExternalSemaphoreHandle::Fd)
+            }
+            ExternalSemaphoreFns::Win32(e) => {
+                let info = vk::SemaphoreGetWin32HandleInfoKHR::builder()
+                    .semaphore(semaphore)
+                    .handle_type(fns.handle_type());
+                e.get_semaphore_win32_handle(&info)
+                    .map(ExternalSemaphoreHandle::Win32)
+            }
+        }
+    }
+}
@@ -9,6 +9,7 @@ use bitvec::view::BitView;
 
 use crate::{
     format::Format,
+    handle::{MeshHandle, TextureHandle},
     pipeline::{
         file::{Filtering, WrapMode},
         sampler::SamplerKey,
@@ -115,6 +116,13 @@ fn to_renderer(addr: u64) -> Box<Renderer> {
     unsafe { Box::from_raw(addr as *mut Renderer) }
 }
 
+fn fetch_texture_or_fail(renderer: &Renderer, texture: u64) -> &Texture {
+    let handle = TextureHandle::from_raw(texture);
+    renderer
+        .fetch_texture(handle)
+        .unwrap_or_else(|| panic!("couldn't find texture with handle {}", handle))
+}
+
 #[no_mangle]
 pub extern "C" fn Java_game_render_vulkan_RendVkApi_init(
     _unused_jnienv: usize,
@@ -153,7 +161,7 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_makeRenderer(
     let glfw_create_window_surface = unsafe {
         std::mem::transmute::<
             _,
-            extern "C" fn(vk::Instance, u64, u64, *const vk::SurfaceKHR) -> vk::Result,
+            extern "C" fn(vk::Instance, u64, u64, *mut vk::SurfaceKHR) -> vk::Result,
         >(glfw_create_window_surface as *const ())
     };
     let instance_extensions: &[*const i8] = if instance_extensions_len == 0 {
@@ -171,7 +179,13 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_makeRenderer(
         is_debug_enabled == JNI_TRUE,
         is_validation_layer_enabled == JNI_TRUE,
         instance_extensions,
-        |_, instance, surface| glfw_create_window_surface(instance.handle(), window, 0, surface),
+        |_, instance| {
+            let mut surface = vk::SurfaceKHR::null();
+            match glfw_create_window_surface(instance.handle(), window, 0, &mut surface) {
+                vk::Result::SUCCESS => Ok(surface),
+                e => Err(e),
+            }
+        },
     );
     let boxed = Box::from(renderer);
     let ptr = Box::into_raw(boxed) as u64;
@@ -224,6 +238,8 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_tryGetSampler(
         filter: Filtering::of_u8(filter),
         wrap_mode: WrapMode::of_u8(wrap_mode),
         anisotropy,
+        compare: None,
+        is_exact: false,
     });
     Box::leak(renderer);
     match sampler {
@@ -246,11 +262,25 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_getSampler(
         filter: Filtering::of_u8(filter),
         wrap_mode: WrapMode::of_u8(wrap_mode),
         anisotropy,
+        compare: None,
+        is_exact: false,
     });
     Box::leak(renderer);
-    sampler.try_into().unwrap()
+    match sampler {
+        Ok(id) => id,
+        Err(e) => {
+            log::warn!("{}", e);
+            MISSING_SAMPLER_ID
+        }
+    }
 }
 
+/*
+ * Meshes and textures cross as the to_raw of their handles, a Java long. Before handles
+ * these were ints holding the bare id, so the Java declarations of genMesh, fetchMesh,
+ * genTexture and the texture functions taking an id have to use long now. The low 32
+ * bits are still the id, which is what materials and descriptors reference.
+ */
 #[no_mangle]
 pub extern "C" fn Java_game_render_vulkan_RendVkApi_genMesh(
     _unused_jnienv: usize,
@@ -261,9 +291,9 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_genMesh(
     tex_coords_size: u32,
     indices_size: u32,
     count: u32,
-) -> u32 {
+) -> u64 {
     let mut renderer = to_renderer(renderer);
-    let mesh_id = renderer.gen_mesh(
+    let mesh = renderer.gen_mesh(
         vertices_size,
         normals_size,
         tex_coords_size,
@@ -271,7 +301,7 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_genMesh(
         count,
     );
     Box::leak(renderer);
-    return mesh_id;
+    mesh.to_raw()
 }
 
 #[no_mangle]
@@ -279,13 +309,11 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_fetchMesh(
     _unused_jnienv: usize,
     _unused_jclazz: usize,
     renderer: u64,
-    id: u32,
+    mesh: u64,
     dest: u64,
 ) {
     let renderer = to_renderer(renderer);
-    let mesh = renderer
-        .fetch_mesh(id)
-        .unwrap_or_else(|| panic!("couldn't find mesh with id {}", id));
+    let mesh = renderer.fetch_mesh_or_fail(MeshHandle::from_raw(mesh));
     let dest = unsafe { std::slice::from_raw_parts_mut(dest as *mut JavaMesh, 1) };
     dest[0] = mesh.to_java();
     Box::leak(renderer);
@@ -302,7 +330,7 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_genTexture(
     name: u64,
     name_len: u32,
     staging_size: u32,
-) -> u32 {
+) -> u64 {
     let mut renderer = to_renderer(renderer);
     let mip_map_count = mip_maps_len / size_of::<JavaMipMap>() as u32;
     let expected_mip_map_size = size_of::<JavaMipMap>() as u32 * mip_map_count;
@@ -329,14 +357,14 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_genTexture(
         size: e.size,
     })
     .collect();
-    let texture_id = renderer.gen_texture(
+    let texture = renderer.gen_texture(
         name.to_string(),
         Format::of_u32(format),
         &mip_maps,
         staging_size,
     );
     Box::leak(renderer);
-    return texture_id;
+    texture.to_raw()
 }
 
 #[no_mangle]
@@ -344,13 +372,11 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_fetchTexture(
     _unused_jnienv: usize,
     _unused_jclazz: usize,
     renderer: u64,
-    id: u32,
+    texture: u64,
     dest: u64,
 ) {
     let renderer = to_renderer(renderer);
-    let texture = renderer
-        .fetch_texture(id)
-        .unwrap_or_else(|| panic!("couldn't find texture with id {}", id));
+    let texture = fetch_texture_or_fail(&renderer, texture);
     let dest = unsafe { std::slice::from_raw_parts_mut(dest as *mut JavaTexture, 1) };
     dest[0] = texture.to_java();
     Box::leak(renderer);
@@ -361,13 +387,11 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_fetchTextureMipMaps(
     _unused_jnienv: usize,
     _unused_jclazz: usize,
     renderer: u64,
-    id: u32,
+    texture: u64,
     dest: u64,
 ) {
     let renderer = to_renderer(renderer);
-    let texture = renderer
-        .fetch_texture(id)
-        .unwrap_or_else(|| panic!("couldn't find texture with id {}", id));
+    let texture = fetch_texture_or_fail(&renderer, texture);
     let dest = unsafe {
         std::slice::from_raw_parts_mut(
             dest as *mut JavaMipMap,
@@ -385,10 +409,13 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_queueTextureForUploading(
     _unused_jnienv: usize,
     _unused_jclazz: usize,
     renderer: u64,
-    id: u32,
+    texture: u64,
 ) {
     let mut renderer = to_renderer(renderer);
-    renderer.queue_texture_for_uploading(id);
+    let handle = TextureHandle::from_raw(texture);
+    renderer
+        .queue_texture_for_uploading(handle)
+        .unwrap_or_else(|_| panic!("couldn't find texture with handle {}", handle));
     Box::leak(renderer);
 }
 
@@ -397,14 +424,47 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_isTextureUploaded(
     _unused_jnienv: usize,
     _unused_jclazz: usize,
     renderer: u64,
-    id: u32,
+    texture: u64,
 ) -> u8 {
     let renderer = to_renderer(renderer);
-    let is_uploaded = renderer.is_texture_uploaded(id);
+    let handle = TextureHandle::from_raw(texture);
+    let is_uploaded = renderer
+        .is_texture_uploaded(handle)
+        .unwrap_or_else(|_| panic!("couldn't find texture with handle {}", handle));
     Box::leak(renderer);
     return if is_uploaded { JNI_TRUE } else { JNI_FALSE };
 }
 
+#[no_mangle]
+pub extern "C" fn Java_game_render_vulkan_RendVkApi_getTextureResidency(
+    _unused_jnienv: usize,
+    _unused_jclazz: usize,
+    renderer: u64,
+    texture: u64,
+) -> i32 {
+    let renderer = to_renderer(renderer);
+    let handle = TextureHandle::from_raw(texture);
+    let residency = renderer
+        .texture_residency(handle)
+        .unwrap_or_else(|_| panic!("couldn't find texture with handle {}", handle));
+    Box::leak(renderer);
+    // Same order as the Java enum
+    residency as i32
+}
+
+#[no_mangle]
+pub extern "C" fn Java_game_render_vulkan_RendVkApi_setTextureMemoryBudget(
+    _unused_jnienv: usize,
+    _unused_jclazz: usize,
+    renderer: u64,
+    bytes: i64,
+) {
+    let mut renderer = to_renderer(renderer);
+    // Negative turns eviction off
+    renderer.set_texture_memory_budget(u64::try_from(bytes).ok());
+    Box::leak(renderer);
+}
+
 #[no_mangle]
 pub extern "C" fn Java_game_render_vulkan_RendVkApi_placeShaderResource(
     _unused_jnienv: usize,
@@ -416,6 +476,7 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_placeShaderResource(
 ) {
     let mut renderer = to_renderer(renderer);
     let kind = ResourceKind::of_u32(kind);
+    renderer.check_shader_resource_size(kind, resource_len as usize);
     let data = unsafe { std::slice::from_raw_parts(resource as *const u8, resource_len as usize) };
     let (resource, _) = match kind {
         ResourceKind::Transform => unpack_single_resource::<Transform>(data),
@@ -429,6 +490,9 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_placeShaderResource(
         ResourceKind::Sky => unpack_single_resource::<Sky>(data),
         ResourceKind::StaticShadow => unpack_single_resource::<StaticShadow>(data),
         ResourceKind::TransformExtra => unpack_single_resource::<TransformExtra>(data),
+        ResourceKind::LightClusters => unpack_single_resource::<LightClusters>(data),
+        ResourceKind::ViewMatrices => unpack_single_resource::<ViewMatrices>(data),
+        ResourceKind::FrameConstants => unpack_single_resource::<FrameConstants>(data),
     };
     renderer.place_shader_resource(kind, resource);
     Box::leak(renderer);
@@ -440,7 +504,7 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_addTaskToQueue(
     _unused_jclazz: usize,
     renderer: u64,
     kind: u32,
-    mesh_id: u32,
+    mesh: u64,
     instance_count: u32,
     resource_bits: u32,
     resources: u64,
@@ -452,16 +516,40 @@ pub extern "C" fn Java_game_render_vulkan_RendVkApi_addTaskToQueue(
         unsafe { std::slice::from_raw_parts(resources as *const u8, resources_len as usize) };
     let resources = unpack_render_task_resources(data, resource_bits, instance_count);
     let task = render_task::RenderTask {
-        kind,
-        resources,
         instance_count,
-        mesh_buffer_id: mesh_id,
+        resources,
+        ..render_task::RenderTask::new(MeshHandle::from_raw(mesh), kind)
     };
     renderer.add_task_to_queue(task);
     Box::leak(renderer);
 }
 
-fn unpack_render_task_resources(
+// Resource kinds tasks can carry, unpack_render_task_resources panics on the others
+#[cfg(feature = "ffi")]
+pub(crate) const TASK_RESOURCE_KINDS: [ResourceKind; 5] = [
+    ResourceKind::Transform,
+    ResourceKind::Material,
+    ResourceKind::DirLight,
+    ResourceKind::PointLight,
+    ResourceKind::TransformExtra,
+];
+
+/*
+ * Bytes the resources of a task take packed the way unpack_render_task_resources reads
+ * them, the bits are expected to only hold TASK_RESOURCE_KINDS.
+ */
+#[cfg(feature = "ffi")]
+pub(crate) fn render_task_resources_size(resource_bits: u32, instances: u32) -> usize {
+    resource_bits
+        .view_bits::<bitvec::order::Lsb0>()
+        .iter_ones()
+        .map(ResourceKind::of_usize)
+        .fold(0, |end, kind| {
+            pos_mul(kind.resource_align(), end) + kind.resource_size() * instances as usize
+        })
+}
+
+pub(crate) fn unpack_render_task_resources(
     data: &[u8],
     resource_bits: u32,
     instances: u32,
@@ -501,7 +589,7 @@ where
     T: WrapResource<T>,
 {
     let (res, next_end) = unpack_resource::<T>(0, 1, data);
-    (T::single_wrapper_for(res), next_end)
+    (T::single_wrapper_for(&res), next_end)
 }
 
 fn unpack_multi_resource<T>(start: usize, count: usize, data: &[u8]) -> (MultiResource, usize)
@@ -509,25 +597,34 @@ where
     T: WrapResource<T>,
 {
     let (res, next_end) = unpack_resource::<T>(start, count, data);
-    (T::multi_wrapper_for(res), next_end)
+    (T::multi_wrapper_for(&res), next_end)
 }
 
-fn unpack_resource<T>(start: usize, count: usize, data: &[u8]) -> (&[T], usize)
+/*
+ * Offsets get aligned from the start of the data, the data itself can be at any address,
+ * so the items get copied out instead of read in place.
+ */
+fn unpack_resource<T>(start: usize, count: usize, data: &[u8]) -> (Vec<T>, usize)
 where
     T: WrapResource<T>,
 {
     let start_aligned = pos_mul(core::mem::align_of::<T>(), start);
-    let slice_aligned = &data[start_aligned..];
+    let available = data.len().saturating_sub(start_aligned);
 
     assert!(
-        slice_aligned.len() >= count * std::mem::size_of::<T>(),
+        available >= count * std::mem::size_of::<T>(),
         "unexpected resource {} count! expected {count}, got only {}",
         std::any::type_name::<T>(),
-        slice_aligned.len() / std::mem::size_of::<T>()
+        available / std::mem::size_of::<T>()
     );
 
-    let items = unsafe { std::slice::from_raw_parts(slice_aligned.as_ptr().cast::<T>(), count) };
+    let items = (0..count)
+        .map(|i| {
+            let offset = start_aligned + i * std::mem::size_of::<T>();
+            unsafe { data.as_ptr().add(offset).cast::<T>().read_unaligned() }
+        })
+        .collect();
 
-    let next_end = items.as_ptr_range().end as usize - data.as_ptr() as usize;
+    let next_end = start_aligned + count * std::mem::size_of::<T>();
     return (items, next_end);
 }
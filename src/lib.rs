@@ -1,21 +1,86 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod adapter;
+pub mod alloc_scope;
+#[cfg(feature = "bench-metrics")]
+pub mod bench;
+pub mod bounds;
 pub mod buffer;
+#[cfg(feature = "alloc-canaries")]
+pub mod canary;
+pub mod capabilities;
+pub mod capture;
+pub mod cleanup;
+pub mod config;
 pub mod context;
 pub mod debug;
+pub mod debug_flags;
+pub mod deterministic;
+#[cfg(feature = "dual-quaternion")]
+pub mod dual_quat;
+pub mod events;
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod format;
+pub mod frame_budget;
+pub mod frame_regions;
+pub mod frame_ring;
+pub mod glyph_atlas;
+pub mod governor;
+pub mod handle;
+pub mod ibl;
+pub mod image_pool;
+pub mod inspector;
+pub mod interop;
+pub mod light_cluster;
+pub mod low_latency;
 pub mod java_api;
+pub mod memory;
+pub mod mesh_opt;
+pub mod mesh_upload;
+pub mod motion;
+pub mod noise;
+pub mod offscreen;
+pub mod operation;
 pub mod pipeline;
+pub mod present_timing;
+pub mod publisher;
+pub mod range_allocator;
+pub mod readback;
+pub mod reflection;
+pub mod render_core;
 pub mod render_task;
 pub mod renderer;
+pub mod retry;
+pub mod scaling;
+pub mod scene_slot;
 pub mod shader;
+pub mod shader_block;
 pub mod shader_resource;
+pub mod sparse;
+pub mod spirv_patch;
+pub mod split_screen;
+pub mod stage_constants;
+pub mod stats;
+#[cfg(feature = "winit")]
+pub mod surface;
 pub mod swapchain;
+pub mod task_sender;
 pub mod texture;
+pub mod texture_feedback;
+pub mod texture_loader;
 pub mod updater;
+pub mod upload;
+pub mod vertex;
+pub mod vertex_layout;
+#[cfg(feature = "winit")]
 pub mod window;
 
+pub use adapter::enumerate_adapters;
+
 pub trait UsedAsIndex<const T: u8> {
     const MAX_VALUE: u8 = T;
     const MAX_SIZE: usize = Self::MAX_VALUE as usize;
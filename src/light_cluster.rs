@@ -0,0 +1,271 @@
+use glam::{Mat4, UVec3, Vec3};
+
+use crate::shader_resource::LightClusters;
+
+/*
+ * CPU side light binning for clustered forward lighting. The view frustum between
+ * z_near and z_far gets split in a froxel grid, every cluster gets the range of the
+ * light index list holding the lights whose bounding sphere touches it.
+ *
+ * GLSL side, as declared in shared.glsl.frag and shared_vulkan.glsl.frag:
+ *
+ *  struct GpuLight { vec3 position; float radius; };
+ *  buffer ClusterRanges { uvec2 items[]; }; // offset, count
+ *  buffer LightIndices { uint items[]; };
+ *  struct LightClusters
+ *  {
+ *    ClusterRanges ranges;
+ *    LightIndices lightIndices;
+ *    uvec3 dims;
+ *    uint isExponential;
+ *    float zScale;
+ *    float zBias;
+ *  };
+ *
+ * Clusters are laid out x first, then y, then z. Tile (0, 0) sits at NDC (-1, -1),
+ * the top left corner with a Vulkan projection, same as gl_FragCoord.
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZDistribution {
+    Linear,
+    // Slices get deeper the further away they are
+    Exponential,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClusterGridConfig {
+    pub dims: (u32, u32, u32),
+    pub z_near: f32,
+    pub z_far: f32,
+    pub z_distribution: ZDistribution,
+}
+
+impl ClusterGridConfig {
+    pub const fn cluster_count(&self) -> usize {
+        (self.dims.0 * self.dims.1 * self.dims.2) as usize
+    }
+
+    ///
+    /// View depth range covered by the z slice, bounds included.
+    ///
+    pub fn slice_bounds(&self, z: u32) -> (f32, f32) {
+        let at = |z: u32| {
+            let t = z as f32 / self.dims.2 as f32;
+            match self.z_distribution {
+                ZDistribution::Linear => self.z_near + (self.z_far - self.z_near) * t,
+                ZDistribution::Exponential => self.z_near * (self.z_far / self.z_near).powf(t),
+            }
+        };
+        (at(z), at(z + 1))
+    }
+
+    ///
+    /// Scale and bias so that slice = d * scale - bias, with ln(d) for exponential.
+    ///
+    pub fn depth_scale_and_bias(&self) -> (f32, f32) {
+        let slices = self.dims.2 as f32;
+        match self.z_distribution {
+            ZDistribution::Linear => {
+                let scale = slices / (self.z_far - self.z_near);
+                (scale, self.z_near * scale)
+            }
+            ZDistribution::Exponential => {
+                let scale = slices / (self.z_far / self.z_near).ln();
+                (scale, self.z_near.ln() * scale)
+            }
+        }
+    }
+}
+
+///
+/// Bounding sphere of a light in world space. Its index in the slice passed to
+/// ClusterBuilder::build is what ends up in the light index list.
+///
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct GpuLight {
+    pub position: Vec3,
+    pub radius: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ClusterRange {
+    pub offset: u32,
+    pub count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClusterData {
+    pub config: ClusterGridConfig,
+    pub ranges: Vec<ClusterRange>,
+    pub light_indices: Vec<u32>,
+}
+
+impl ClusterData {
+    pub const fn cluster_index(&self, x: u32, y: u32, z: u32) -> usize {
+        let (dx, dy, _) = self.config.dims;
+        (x + dx * (y + dy * z)) as usize
+    }
+
+    pub fn lights_in(&self, x: u32, y: u32, z: u32) -> &[u32] {
+        let range = self.ranges[self.cluster_index(x, y, z)];
+        let start = range.offset as usize;
+        &self.light_indices[start..start + range.count as usize]
+    }
+
+    ///
+    /// Shader side description of the grid, with the addresses of the uploaded arrays.
+    ///
+    pub fn to_resource(&self, ranges: u64, light_indices: u64) -> LightClusters {
+        let (z_scale, z_bias) = self.config.depth_scale_and_bias();
+        let (x, y, z) = self.config.dims;
+        LightClusters {
+            ranges,
+            light_indices,
+            dims: UVec3::new(x, y, z),
+            is_exponential: (self.config.z_distribution == ZDistribution::Exponential) as u32,
+            z_scale,
+            z_bias,
+        }
+    }
+}
+
+pub struct ClusterBuilder {
+    pub config: ClusterGridConfig,
+}
+
+impl ClusterBuilder {
+    pub fn new(config: ClusterGridConfig) -> Self {
+        assert!(
+            config.dims.0 > 0 && config.dims.1 > 0 && config.dims.2 > 0,
+            "cluster grid dims can't be zero!"
+        );
+        assert!(
+            config.z_near > 0.0 && config.z_far > config.z_near,
+            "cluster grid needs 0 < z_near < z_far!"
+        );
+        Self { config }
+    }
+
+    ///
+    /// Bins the lights into the grid. View is expected to look down -Z, the z slices
+    /// get binned in parallel.
+    ///
+    pub fn build(&self, lights: &[GpuLight], view: Mat4, proj: Mat4) -> ClusterData {
+        let spheres: Vec<_> = lights
+            .iter()
+            .map(|e| (view.transform_point3(e.position), e.radius))
+            .collect();
+        let tile_rays = self.tile_corner_rays(proj);
+        let slices = self.config.dims.2;
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |e| e.get() as u32)
+            .min(slices);
+        let per_thread = slices.div_ceil(threads);
+        let binned: Vec<(Vec<ClusterRange>, Vec<u32>)> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let (spheres, tile_rays) = (&spheres, &tile_rays);
+                    let z_range = (t * per_thread)..((t + 1) * per_thread).min(slices);
+                    s.spawn(move || {
+                        z_range
+                            .map(|z| self.bin_slice(z, spheres, tile_rays))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|e| e.join().expect("cluster binning thread panicked!"))
+                .collect()
+        });
+        // Slices got their own index lists, offsets need to be made global
+        let mut ranges = Vec::with_capacity(self.config.cluster_count());
+        let mut light_indices = Vec::new();
+        for (slice_ranges, slice_indices) in binned {
+            let base = light_indices.len() as u32;
+            ranges.extend(slice_ranges.into_iter().map(|e| ClusterRange {
+                offset: e.offset + base,
+                count: e.count,
+            }));
+            light_indices.extend(slice_indices);
+        }
+        ClusterData {
+            config: self.config,
+            ranges,
+            light_indices,
+        }
+    }
+
+    ///
+    /// View space rays through the tile corners, scaled to depth 1.
+    ///
+    fn tile_corner_rays(&self, proj: Mat4) -> Vec<Vec3> {
+        let (dx, dy, _) = self.config.dims;
+        let inv_proj = proj.inverse();
+        let mut rays = Vec::with_capacity(((dx + 1) * (dy + 1)) as usize);
+        for y in 0..=dy {
+            for x in 0..=dx {
+                let ndc_x = -1.0 + 2.0 * x as f32 / dx as f32;
+                let ndc_y = -1.0 + 2.0 * y as f32 / dy as f32;
+                // Any depth between the planes works, the point is on the ray either way
+                let p = inv_proj.project_point3(Vec3::new(ndc_x, ndc_y, 0.5));
+                rays.push(p / -p.z);
+            }
+        }
+        rays
+    }
+
+    fn bin_slice(
+        &self,
+        z: u32,
+        spheres: &[(Vec3, f32)],
+        tile_rays: &[Vec3],
+    ) -> (Vec<ClusterRange>, Vec<u32>) {
+        let (dx, dy, _) = self.config.dims;
+        let (near, far) = self.config.slice_bounds(z);
+        // Lights behind the camera never reach past z_near
+        let candidates: Vec<u32> = (0..spheres.len() as u32)
+            .filter(|i| {
+                let (center, radius) = spheres[*i as usize];
+                let depth = -center.z;
+                depth + radius >= near && depth - radius <= far
+            })
+            .collect();
+        let mut ranges = Vec::with_capacity((dx * dy) as usize);
+        let mut indices = Vec::new();
+        for y in 0..dy {
+            for x in 0..dx {
+                let corner = |cx: u32, cy: u32| tile_rays[(cx + cy * (dx + 1)) as usize];
+                let corners = [
+                    corner(x, y),
+                    corner(x + 1, y),
+                    corner(x, y + 1),
+                    corner(x + 1, y + 1),
+                ];
+                let mut min = Vec3::splat(f32::MAX);
+                let mut max = Vec3::splat(f32::MIN);
+                for ray in corners {
+                    for p in [ray * near, ray * far] {
+                        min = min.min(p);
+                        max = max.max(p);
+                    }
+                }
+                let offset = indices.len() as u32;
+                // Touching counts, a light on a boundary lands in both neighbors
+                indices.extend(candidates.iter().copied().filter(|i| {
+                    let (center, radius) = spheres[*i as usize];
+                    let closest = center.clamp(min, max);
+                    closest.distance_squared(center) <= radius * radius
+                }));
+                ranges.push(ClusterRange {
+                    offset,
+                    count: indices.len() as u32 - offset,
+                });
+            }
+        }
+        (ranges, indices)
+    }
+}
@@ -0,0 +1,426 @@
+use std::time::{Duration, Instant};
+
+use ash::vk;
+
+use crate::{
+    context::VulkanContext, image_pool, pipeline::attachment::Attachment, render_task::ScissorRect,
+    stats::FramePath,
+};
+
+/*
+ * Low latency overlay, see RendererConfig::low_latency_overlay. The swapchain gets a
+ * single shared presentable image (VK_KHR_shared_presentable_image, demand refresh) the
+ * display reads from directly. Frames with nothing queued but overlay tasks only record
+ * the overlay stage, into the dirty region on top of what the image holds, and present
+ * right away. Frames with anything else queued go through every stage like always.
+ *
+ * Shared presentable images stay in the SHARED_PRESENT_KHR layout whatever they're used
+ * for, see Attachment::layout_for. They're acquired once, every later frame renders into
+ * them without waiting on an acquisition.
+ *
+ * Without shared presentable images, swapchains presenting right away (immediate or
+ * mailbox) with images that can be copied from and to get a RetainedFrame instead. Every
+ * frame leaves a copy of what it presented in it. Overlay frames acquire the next image
+ * like any other frame, restore the copy into it, redraw the dirty region on top and copy
+ * that region back.
+ */
+
+///
+/// Usage the shared presentable images of the surface support, None if the device or the
+/// surface can't do shared presentation in demand refresh mode.
+///
+pub fn shared_present_usage(
+    ctx: &VulkanContext,
+    surface: vk::SurfaceKHR,
+) -> Option<vk::ImageUsageFlags> {
+    let fns = ctx
+        .extension
+        .surface_capabilities2
+        .as_ref()
+        .filter(|_| ctx.extension.is_shared_presentable_image_enabled)?;
+    let modes = unsafe {
+        ctx.extension
+            .surface
+            .get_physical_device_surface_present_modes(ctx.physical_device, surface)
+            .ok()?
+    };
+    if !modes.contains(&vk::PresentModeKHR::SHARED_DEMAND_REFRESH) {
+        return None;
+    }
+    let mut shared = vk::SharedPresentSurfaceCapabilitiesKHR::default();
+    let mut caps = vk::SurfaceCapabilities2KHR::builder()
+        .push_next(&mut shared)
+        .build();
+    let info = vk::PhysicalDeviceSurfaceInfo2KHR::builder()
+        .surface(surface)
+        .build();
+    let res = unsafe {
+        (fns.get_physical_device_surface_capabilities2_khr)(ctx.physical_device, &info, &mut caps)
+    };
+    if res != vk::Result::SUCCESS {
+        return None;
+    }
+    Some(shared.shared_present_supported_usage_flags)
+}
+
+///
+/// Low latency bookkeeping of a renderer: whether the shared image got acquired and holds
+/// a whole frame, the region the overlay changed and the oldest input not shown yet.
+///
+#[derive(Debug, Default)]
+pub struct OverlayState {
+    is_acquired: bool,
+    // Some frame went through every stage and got presented since the swapchain was made
+    is_frame_complete: bool,
+    // Union of the regions marked since the last frame
+    dirty: Option<vk::Rect2D>,
+    input_at: Option<Instant>,
+}
+
+impl OverlayState {
+    ///
+    /// For a new swapchain, its image neither is acquired nor holds anything.
+    ///
+    pub fn reset(&mut self) {
+        self.is_acquired = false;
+        self.is_frame_complete = false;
+        self.dirty = None;
+    }
+
+    pub fn is_acquired(&self) -> bool {
+        self.is_acquired
+    }
+
+    pub fn set_acquired(&mut self) {
+        self.is_acquired = true;
+    }
+
+    pub fn is_frame_complete(&self) -> bool {
+        self.is_frame_complete
+    }
+
+    ///
+    /// Adds the region to the one the next overlay frame redraws. Regions are unioned
+    /// into their bounding rectangle. Nothing is marked for regions of no area.
+    ///
+    pub fn mark_dirty(&mut self, rect: ScissorRect) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let rect = vk::Rect2D {
+            offset: vk::Offset2D {
+                x: rect.x,
+                y: rect.y,
+            },
+            extent: vk::Extent2D {
+                width: rect.width,
+                height: rect.height,
+            },
+        };
+        self.dirty = Some(match self.dirty {
+            Some(e) => union_of(e, rect),
+            None => rect,
+        });
+    }
+
+    ///
+    /// Keeps the oldest input not presented yet, what input_to_present measures from.
+    ///
+    pub fn mark_input(&mut self, at: Instant) {
+        if self.input_at.is_none_or(|e| at < e) {
+            self.input_at = Some(at);
+        }
+    }
+
+    ///
+    /// Whether the frame can skip every stage but the overlay one. Needs a whole frame in
+    /// the image to draw on top of, overlay tasks to draw and nothing else changed.
+    ///
+    pub fn is_overlay_frame(&self, is_scene_changed: bool, has_overlay_tasks: bool) -> bool {
+        self.is_frame_complete && !is_scene_changed && has_overlay_tasks
+    }
+
+    ///
+    /// Region the overlay frame redraws within the area, the whole area if nothing got
+    /// marked. None if what got marked lies outside it. Starts over from nothing marked.
+    ///
+    pub fn take_dirty(&mut self, area: vk::Rect2D) -> Option<vk::Rect2D> {
+        match self.dirty.take() {
+            Some(e) => ScissorRect::from(e).clamped_to(area),
+            None => Some(area),
+        }
+    }
+
+    ///
+    /// Records a frame handed to the presentation engine at the given time. Frames of
+    /// every other path redraw the whole image, so the marked region starts over. Returns
+    /// the time since the oldest input marked, if any.
+    ///
+    pub fn presented(&mut self, path: FramePath, at: Instant) -> Option<Duration> {
+        if path != FramePath::Overlay {
+            self.is_frame_complete = true;
+            self.dirty = None;
+        }
+        self.input_at
+            .take()
+            .map(|e| at.saturating_duration_since(e))
+    }
+}
+
+///
+/// Whether a swapchain without shared presentable images can keep the low latency
+/// overlay going through a RetainedFrame. Needs a present mode that doesn't wait for
+/// vertical blanks and images that can be copied from and to.
+///
+pub fn is_retainable(present_mode: vk::PresentModeKHR, usage: vk::ImageUsageFlags) -> bool {
+    let needed = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+    matches!(
+        present_mode,
+        vk::PresentModeKHR::IMMEDIATE | vk::PresentModeKHR::MAILBOX
+    ) && usage.contains(needed)
+}
+
+///
+/// Copy of the last presented swapchain image, in the TRANSFER_SRC_OPTIMAL layout between
+/// frames. Overlay frames restore it into the image they acquired.
+///
+pub struct RetainedFrame {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    extent: vk::Extent2D,
+    // Some frame copied all of its image in
+    is_filled: bool,
+    // Of the overlay frame being recorded, the rest copy the whole image
+    redrawn: Option<vk::Rect2D>,
+}
+
+impl RetainedFrame {
+    pub fn make(ctx: &VulkanContext, vk_format: vk::Format, extent: vk::Extent2D) -> Self {
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(vk_format)
+            .extent(extent.into())
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST);
+        let image = unsafe { ctx.device.create_image(&create_info, None) }.unwrap();
+        let allocation =
+            image_pool::alloc_dedicated_for(ctx, image, &[vk::MemoryPropertyFlags::DEVICE_LOCAL]);
+        ctx.try_set_debug_name("retained_frame_image", image);
+        ctx.try_set_debug_name("retained_frame_memory", allocation.memory);
+        Self {
+            image,
+            memory: allocation.memory,
+            extent,
+            is_filled: false,
+            redrawn: None,
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+
+    pub fn is_filled(&self) -> bool {
+        self.is_filled
+    }
+
+    ///
+    /// Region the overlay frame being recorded redraws, record_retain only copies that
+    /// much back.
+    ///
+    pub fn set_redrawn(&mut self, region: vk::Rect2D) {
+        self.redrawn = Some(region);
+    }
+
+    ///
+    /// Copies the retained frame into the acquired image and leaves it ready for the
+    /// overlay stage to load, in the ATTACHMENT_OPTIMAL layout.
+    ///
+    pub fn record_restore(
+        &self,
+        ctx: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+        target: &Attachment,
+    ) {
+        assert!(self.is_filled, "nothing retained to restore!");
+        use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+        let copy_dst = (Il::TRANSFER_DST_OPTIMAL, Af::TRANSFER_WRITE, Ps::COPY);
+        // Chains with the wait on the acquired image
+        let before = [barrier(
+            target.image,
+            (Il::UNDEFINED, Af::NONE, Ps::COLOR_ATTACHMENT_OUTPUT),
+            copy_dst,
+        )];
+        let after = [barrier(
+            target.image,
+            copy_dst,
+            (
+                Il::ATTACHMENT_OPTIMAL,
+                Af::COLOR_ATTACHMENT_READ | Af::COLOR_ATTACHMENT_WRITE,
+                Ps::COLOR_ATTACHMENT_OUTPUT,
+            ),
+        )];
+        let whole = vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent: self.extent,
+        };
+        let device = &ctx.device;
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before),
+            );
+            device.cmd_copy_image(
+                command_buffer,
+                self.image,
+                Il::TRANSFER_SRC_OPTIMAL,
+                target.image,
+                Il::TRANSFER_DST_OPTIMAL,
+                &[copy_of(whole)],
+            );
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after),
+            );
+        }
+    }
+
+    ///
+    /// Copies what the frame leaves in the image it presents into the retained frame, the
+    /// region set_redrawn set or the whole image. The image has to be in the present
+    /// layout and goes back to it.
+    ///
+    pub fn record_retain(
+        &mut self,
+        ctx: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+        source: &Attachment,
+    ) {
+        let whole = vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent: self.extent,
+        };
+        let region = match self.redrawn.take() {
+            Some(e) => {
+                assert!(self.is_filled, "nothing retained to redraw on top of!");
+                e
+            }
+            None => whole,
+        };
+        if region.extent.width == 0 || region.extent.height == 0 {
+            // Overlay frame that redrew nothing
+            return;
+        }
+        use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+        let copy_src = (Il::TRANSFER_SRC_OPTIMAL, Af::TRANSFER_READ, Ps::COPY);
+        let copy_dst = (Il::TRANSFER_DST_OPTIMAL, Af::TRANSFER_WRITE, Ps::COPY);
+        // Copies of only a region keep the rest
+        let retained = if region == whole {
+            Il::UNDEFINED
+        } else {
+            Il::TRANSFER_SRC_OPTIMAL
+        };
+        let before = [
+            // Whatever wrote it before it went to the present layout
+            barrier(
+                source.image,
+                (Il::PRESENT_SRC_KHR, Af::MEMORY_WRITE, Ps::ALL_COMMANDS),
+                copy_src,
+            ),
+            barrier(self.image, (retained, Af::NONE, Ps::COPY), copy_dst),
+        ];
+        let after = [
+            barrier(
+                source.image,
+                copy_src,
+                (Il::PRESENT_SRC_KHR, Af::NONE, Ps::BOTTOM_OF_PIPE),
+            ),
+            // Restores and copies of later frames
+            barrier(self.image, copy_dst, copy_src),
+        ];
+        let device = &ctx.device;
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before),
+            );
+            device.cmd_copy_image(
+                command_buffer,
+                source.image,
+                Il::TRANSFER_SRC_OPTIMAL,
+                self.image,
+                Il::TRANSFER_DST_OPTIMAL,
+                &[copy_of(region)],
+            );
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after),
+            );
+        }
+        self.is_filled = true;
+    }
+}
+
+fn barrier(
+    image: vk::Image,
+    from: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2),
+    to: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2),
+) -> vk::ImageMemoryBarrier2 {
+    vk::ImageMemoryBarrier2::builder()
+        .image(image)
+        .subresource_range(Attachment::color_subresource_range())
+        .old_layout(from.0)
+        .src_access_mask(from.1)
+        .src_stage_mask(from.2)
+        .new_layout(to.0)
+        .dst_access_mask(to.1)
+        .dst_stage_mask(to.2)
+        .build()
+}
+
+// Same region in both images
+fn copy_of(region: vk::Rect2D) -> vk::ImageCopy {
+    let layers = vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let offset = vk::Offset3D {
+        x: region.offset.x,
+        y: region.offset.y,
+        z: 0,
+    };
+    vk::ImageCopy {
+        src_subresource: layers,
+        src_offset: offset,
+        dst_subresource: layers,
+        dst_offset: offset,
+        extent: region.extent.into(),
+    }
+}
+
+fn union_of(a: vk::Rect2D, b: vk::Rect2D) -> vk::Rect2D {
+    let end = |e: vk::Rect2D| {
+        (
+            e.offset.x as i64 + e.extent.width as i64,
+            e.offset.y as i64 + e.extent.height as i64,
+        )
+    };
+    let (a_end, b_end) = (end(a), end(b));
+    let x = a.offset.x.min(b.offset.x);
+    let y = a.offset.y.min(b.offset.y);
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D {
+            width: (a_end.0.max(b_end.0) - x as i64) as u32,
+            height: (a_end.1.max(b_end.1) - y as i64) as u32,
+        },
+    }
+}
@@ -0,0 +1,225 @@
+use serde::Serialize;
+
+use crate::{handle::ResourceMeta, range_allocator::RangeAllocator, texture::Residency};
+
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub general: AllocatorReport,
+    // None when descriptors go into classic descriptor sets
+    pub descriptor: Option<AllocatorReport>,
+    // None until the first Renderer::read_buffer
+    pub readback: Option<AllocatorReport>,
+    pub attachments: Vec<AttachmentMemoryReport>,
+    pub image_pools: Vec<PoolMemoryReport>,
+    // Image memory of the resident textures
+    pub texture_bytes: u64,
+    pub texture_memory_budget: Option<u64>,
+    // Since the renderer was made
+    pub texture_evictions: u64,
+    pub texture_restores: u64,
+    // Every texture by id, the default one included
+    pub textures: Vec<TextureMemoryReport>,
+}
+
+///
+/// Image memory of a single texture, 0 while evicted.
+///
+#[derive(Clone, Debug)]
+pub struct TextureMemoryReport {
+    pub id: u32,
+    pub name: String,
+    pub size: u64,
+    pub residency: Residency,
+    pub meta: Option<ResourceMeta>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AllocatorReport {
+    pub size: u64,
+    pub available: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct AttachmentMemoryReport {
+    pub name: String,
+    pub is_memoryless: bool,
+    pub is_lazily_allocated: bool,
+    // Bytes the implementation actually backed, only tracked for lazily allocated memory.
+    pub committed: u64,
+}
+
+///
+/// Texture memory of a single memory type, pooled blocks plus dedicated allocations.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolMemoryReport {
+    pub memory_type_index: u32,
+    pub block_count: u32,
+    pub size: u64,
+    pub available: u64,
+    pub largest_free: u64,
+    pub free_range_count: usize,
+    pub dedicated_count: u32,
+    pub dedicated_size: u64,
+}
+
+impl PoolMemoryReport {
+    ///
+    /// 0 when all the free memory is in a single range, approaching 1 the more it's
+    /// split into small ranges.
+    ///
+    pub fn fragmentation(&self) -> f32 {
+        if self.available == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free as f32 / self.available as f32
+    }
+}
+
+///
+/// Everything a memory overlay draws, cheap enough to take every frame, see
+/// Renderer::memory_snapshot. Serializes to JSON under the field names.
+///
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemorySnapshot {
+    // Bumped each time anything else differs from the previous snapshot, from 1 on
+    pub sequence: u64,
+    pub general: AllocatorSnapshot,
+    // None when descriptors go into classic descriptor sets
+    pub descriptor: Option<AllocatorSnapshot>,
+    // None until the first Renderer::read_buffer
+    pub readback: Option<AllocatorSnapshot>,
+    pub image_pools: Vec<PoolMemoryReport>,
+    // Every texture by id, the default one included
+    pub textures: Vec<TextureSnapshot>,
+    pub texture_bytes: u64,
+    pub texture_memory_budget: Option<u64>,
+    // Freed textures, evicted images and frame buffers waiting on the GPU to be destroyed
+    pub pending_destroys: u32,
+}
+
+///
+/// Free ranges of a buffer allocator, the memory between them is allocated.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocatorSnapshot {
+    pub size: u64,
+    pub available: u64,
+    // Offset and size of each, by offset
+    pub free_ranges: Vec<[u64; 2]>,
+    // Live scopes of the allocator by id, see DeviceAllocator::create_scope
+    pub scopes: Vec<ScopeSnapshot>,
+}
+
+impl AllocatorSnapshot {
+    pub fn of(ranges: &RangeAllocator) -> Self {
+        Self {
+            size: ranges.size(),
+            available: ranges.available(),
+            free_ranges: ranges.free_ranges(),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn largest_free(&self) -> u64 {
+        self.free_ranges.iter().map(|e| e[1]).max().unwrap_or(0)
+    }
+
+    ///
+    /// Like PoolMemoryReport::fragmentation, 0 when all the free memory is in a single
+    /// range.
+    ///
+    pub fn fragmentation(&self) -> f32 {
+        if self.available == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free() as f32 / self.available as f32
+    }
+
+    ///
+    /// Offset and size of every allocated range between the free ones, by offset.
+    ///
+    pub fn used_ranges(&self) -> Vec<[u64; 2]> {
+        let mut used = Vec::new();
+        let mut offset = 0;
+        for [start, size] in &self.free_ranges {
+            if *start > offset {
+                used.push([offset, start - offset]);
+            }
+            offset = start + size;
+        }
+        if self.size > offset {
+            used.push([offset, self.size - offset]);
+        }
+        used
+    }
+}
+
+///
+/// Usage of a scope against its budget, counting the scopes nested in it.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeSnapshot {
+    pub id: u32,
+    pub name: String,
+    pub parent: Option<u32>,
+    pub budget: u64,
+    pub used: u64,
+    // Highest used since the scope was made
+    pub peak: u64,
+    // Live allocations made in the scope itself
+    pub allocation_count: u32,
+    pub failed_count: u32,
+}
+
+impl ScopeSnapshot {
+    pub fn usage(&self) -> f32 {
+        if self.budget == 0 {
+            return 1.0;
+        }
+        self.used as f32 / self.budget as f32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureSnapshot {
+    pub id: u32,
+    // Image memory, 0 while evicted
+    pub size: u64,
+    pub residency: Residency,
+}
+
+///
+/// Hands out the sequence numbers of memory snapshots, keeping the previous one to tell
+/// whether anything changed.
+///
+#[derive(Clone, Debug, Default)]
+pub struct MemorySnapshotTracker {
+    previous: Option<MemorySnapshot>,
+}
+
+impl MemorySnapshotTracker {
+    ///
+    /// Sets the sequence of the snapshot to the one of the previous snapshot, bumped
+    /// unless both are the same otherwise.
+    ///
+    pub fn track(&mut self, mut snapshot: MemorySnapshot) -> MemorySnapshot {
+        snapshot.sequence = match &self.previous {
+            None => 1,
+            Some(previous) => {
+                snapshot.sequence = previous.sequence;
+                if *previous == snapshot {
+                    return snapshot;
+                }
+                previous.sequence + 1
+            }
+        };
+        self.previous = Some(snapshot.clone());
+        snapshot
+    }
+}
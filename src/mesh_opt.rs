@@ -0,0 +1,554 @@
+/*
+ * Mesh optimizations run on the host before a mesh gets copied to the GPU, see
+ * Renderer::gen_mesh_with_data. In the order they run:
+ *
+ *   - deduplicate_vertices merges vertices with bit identical attributes, generating
+ *     indices for non indexed meshes.
+ *   - vertex_cache_reorder reorders the triangles for the post transform cache, with the
+ *     linear speed optimizer of Tom Forsyth.
+ *   - overdraw_reorder splits that order into clusters where the cache flushes anyway
+ *     and sorts the clusters to draw the outward facing ones first, like Sander et al.
+ *   - fetch_reorder reorders the vertices by first use in the indices.
+ *   - quantize rewrites the attributes in smaller formats, see vertex.
+ *
+ * Triangles keep the winding of their vertices, and vertices keep their attributes bit
+ * for bit, only the order of either changes. Quantizing is the one lossy step.
+ */
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::{
+    vertex::{self, QuantizationError},
+    vertex_layout::StreamFormats,
+};
+
+///
+/// Optimizations for gen_mesh_with_data to run, none by default.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshOptFlags {
+    pub vertex_cache_reorder: bool,
+    pub overdraw_reorder: bool,
+    pub fetch_reorder: bool,
+    pub deduplicate_vertices: bool,
+    // Formats to quantize the attributes into after every other optimization ran
+    pub quantize: Option<StreamFormats>,
+}
+
+impl MeshOptFlags {
+    ///
+    /// Every optimization that keeps the attributes as they are.
+    ///
+    pub const ALL: Self = Self {
+        vertex_cache_reorder: true,
+        overdraw_reorder: true,
+        fetch_reorder: true,
+        deduplicate_vertices: true,
+        quantize: None,
+    };
+
+    pub fn is_any(self) -> bool {
+        self != Self::default()
+    }
+}
+
+///
+/// What the optimizations did. ACMR is the average cache miss ratio, vertices
+/// transformed per triangle with a FIFO cache of ACMR_CACHE_SIZE entries, 3 at worst.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshOptReport {
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+    pub vertices_deduplicated: u32,
+    // None unless quantized
+    pub quantization_error: Option<QuantizationError>,
+}
+
+///
+/// Attribute of each vertex, stride bytes apart, so interleaved attributes can point into
+/// the same data.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct VertexStream<'a> {
+    pub data: &'a [u8],
+    pub stride: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MeshIndices<'a> {
+    None,
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+///
+/// Mesh data to upload, see Renderer::gen_mesh_with_data. Positions are 3 floats,
+/// normals 3 floats and tex coords 2 floats per vertex, at the start of each stride.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct MeshData<'a> {
+    pub vertex_count: u32,
+    pub positions: VertexStream<'a>,
+    pub normals: Option<VertexStream<'a>>,
+    pub tex_coords: Option<VertexStream<'a>>,
+    pub indices: MeshIndices<'a>,
+}
+
+impl MeshData<'_> {
+    pub const POSITION_SIZE: usize = 12;
+    pub const NORMAL_SIZE: usize = 12;
+    pub const TEX_COORD_SIZE: usize = 8;
+
+    ///
+    /// Copies the attributes tightly packed, the way the shaders read them.
+    ///
+    pub fn pack(&self) -> PackedMesh {
+        let count = self.vertex_count as usize;
+        let pack_or_empty = |stream: Option<VertexStream>, size: usize| match stream {
+            Some(e) => pack_stream(e, size, count),
+            None => Vec::new(),
+        };
+        let indices = match self.indices {
+            MeshIndices::None => None,
+            MeshIndices::U16(e) => Some(e.iter().map(|e| *e as u32).collect()),
+            MeshIndices::U32(e) => Some(e.to_vec()),
+        };
+        PackedMesh {
+            vertex_count: self.vertex_count,
+            streams: vec![
+                (
+                    pack_stream(self.positions, Self::POSITION_SIZE, count),
+                    Self::POSITION_SIZE,
+                ),
+                (
+                    pack_or_empty(self.normals, Self::NORMAL_SIZE),
+                    Self::NORMAL_SIZE,
+                ),
+                (
+                    pack_or_empty(self.tex_coords, Self::TEX_COORD_SIZE),
+                    Self::TEX_COORD_SIZE,
+                ),
+            ],
+            indices,
+            formats: StreamFormats::default(),
+        }
+    }
+}
+
+fn pack_stream(stream: VertexStream, size: usize, count: usize) -> Vec<u8> {
+    let stride = stream.stride as usize;
+    if stride < size {
+        panic!(
+            "vertex stride {} is smaller than its {} bytes!",
+            stride, size
+        );
+    }
+    if count > 0 && stream.data.len() < (count - 1) * stride + size {
+        panic!(
+            "vertex stream of {} bytes is too short for {} vertices with stride {}!",
+            stream.data.len(),
+            count,
+            stride
+        );
+    }
+    let mut dst = Vec::with_capacity(count * size);
+    for i in 0..count {
+        dst.extend_from_slice(&stream.data[i * stride..i * stride + size]);
+    }
+    dst
+}
+
+///
+/// Vertex attributes packed one stream after another, each stream with the size of its
+/// elements. Streams left empty are missing from the mesh. The first one holds the
+/// positions, the second the normals and the last the tex coords, in the formats.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedMesh {
+    pub vertex_count: u32,
+    pub streams: Vec<(Vec<u8>, usize)>,
+    pub indices: Option<Vec<u32>>,
+    pub formats: StreamFormats,
+}
+
+impl PackedMesh {
+    ///
+    /// Indices, or vertices without them, the mesh gets drawn with.
+    ///
+    pub fn count(&self) -> u32 {
+        self.indices
+            .as_ref()
+            .map_or(self.vertex_count, |e| e.len() as u32)
+    }
+
+    ///
+    /// Position of the vertex, only while the positions are unquantized.
+    ///
+    pub fn position(&self, vertex: u32) -> Vec3 {
+        let (data, _) = &self.streams[0];
+        let at = vertex as usize * 12;
+        let component = |i: usize| {
+            let bytes = data[at + i * 4..at + i * 4 + 4].try_into().unwrap();
+            f32::from_ne_bytes(bytes)
+        };
+        Vec3::new(component(0), component(1), component(2))
+    }
+
+    fn vertex_key(&self, vertex: usize) -> Vec<u8> {
+        let mut key = Vec::new();
+        for (data, size) in &self.streams {
+            if !data.is_empty() {
+                key.extend_from_slice(&data[vertex * size..(vertex + 1) * size]);
+            }
+        }
+        key
+    }
+
+    /*
+     * Moves vertex i to remap[i], with vertex_count vertices left. Vertices mapped to
+     * the same place have to be identical.
+     */
+    fn remap_vertices(&mut self, remap: &[u32], vertex_count: u32) {
+        for (data, size) in &mut self.streams {
+            if data.is_empty() {
+                continue;
+            }
+            let size = *size;
+            let mut dst = vec![0u8; vertex_count as usize * size];
+            for (src, dst_index) in remap.iter().enumerate() {
+                let dst_at = *dst_index as usize * size;
+                dst[dst_at..dst_at + size].copy_from_slice(&data[src * size..(src + 1) * size]);
+            }
+            *data = dst;
+        }
+        if let Some(indices) = &mut self.indices {
+            for e in indices.iter_mut() {
+                *e = remap[*e as usize];
+            }
+        }
+        self.vertex_count = vertex_count;
+    }
+}
+
+pub const ACMR_CACHE_SIZE: u32 = 16;
+
+///
+/// Average cache miss ratio of the triangles with a FIFO cache of cache_size entries,
+/// 3 for meshes without indices.
+///
+pub fn acmr(indices: Option<&[u32]>, vertex_count: u32, cache_size: u32) -> f32 {
+    let indices = match indices {
+        Some(e) if e.len() >= 3 => e,
+        _ => return 3.0,
+    };
+    let mut timestamps = vec![0u32; vertex_count as usize];
+    let mut timestamp = cache_size + 1;
+    let mut misses = 0;
+    for e in indices {
+        let last = &mut timestamps[*e as usize];
+        if timestamp - *last > cache_size {
+            *last = timestamp;
+            timestamp += 1;
+            misses += 1;
+        }
+    }
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+///
+/// Runs the optimizations of the flags on the mesh in place.
+///
+pub fn optimize(mesh: &mut PackedMesh, flags: MeshOptFlags) -> MeshOptReport {
+    if let Some(e) = mesh
+        .indices
+        .iter()
+        .flatten()
+        .find(|e| **e >= mesh.vertex_count)
+    {
+        panic!("index {} out of {} vertices!", e, mesh.vertex_count);
+    }
+    let acmr_before = acmr(mesh.indices.as_deref(), mesh.vertex_count, ACMR_CACHE_SIZE);
+    let mut vertices_deduplicated = 0;
+    // Quantizing doesn't care about the order
+    let is_reordered = MeshOptFlags {
+        quantize: None,
+        ..flags
+    }
+    .is_any();
+    if is_reordered && mesh.indices.is_none() {
+        // Reordering triangles needs indices, deduplicate makes them useful too
+        mesh.indices = Some((0..mesh.vertex_count).collect());
+    }
+    if flags.deduplicate_vertices {
+        let (remap, unique) = deduplicate(mesh);
+        vertices_deduplicated = mesh.vertex_count - unique;
+        mesh.remap_vertices(&remap, unique);
+    }
+    let is_reordering = flags.vertex_cache_reorder || flags.overdraw_reorder;
+    if let Some(indices) = mesh.indices.as_mut().filter(|_| is_reordering) {
+        if indices.len() % 3 != 0 {
+            panic!(
+                "{} indices aren't whole triangles, they can't be reordered!",
+                indices.len()
+            );
+        }
+        if flags.vertex_cache_reorder {
+            *indices = optimize_vertex_cache(indices, mesh.vertex_count);
+        }
+    }
+    if flags.overdraw_reorder {
+        let indices = mesh.indices.take().unwrap();
+        mesh.indices = Some(optimize_overdraw(&indices, mesh));
+    }
+    if flags.fetch_reorder {
+        let remap = fetch_remap(mesh.indices.as_deref().unwrap(), mesh.vertex_count);
+        mesh.remap_vertices(&remap, mesh.vertex_count);
+    }
+    MeshOptReport {
+        acmr_before,
+        acmr_after: acmr(mesh.indices.as_deref(), mesh.vertex_count, ACMR_CACHE_SIZE),
+        vertices_deduplicated,
+        quantization_error: flags.quantize.map(|e| vertex::quantize(mesh, e)),
+    }
+}
+
+// Where each vertex goes and how many are left, unique vertices keep their order
+fn deduplicate(mesh: &PackedMesh) -> (Vec<u32>, u32) {
+    let mut unique_by_key: HashMap<Vec<u8>, u32> = HashMap::new();
+    let remap = (0..mesh.vertex_count as usize)
+        .map(|i| {
+            let next = unique_by_key.len() as u32;
+            *unique_by_key.entry(mesh.vertex_key(i)).or_insert(next)
+        })
+        .collect();
+    (remap, unique_by_key.len() as u32)
+}
+
+// Vertices in order of first use, unused ones after them in their order
+fn fetch_remap(indices: &[u32], vertex_count: u32) -> Vec<u32> {
+    let mut remap = vec![u32::MAX; vertex_count as usize];
+    let mut next = 0;
+    for e in indices.iter().copied().chain(0..vertex_count) {
+        let slot = &mut remap[e as usize];
+        if *slot == u32::MAX {
+            *slot = next;
+            next += 1;
+        }
+    }
+    remap
+}
+
+const FORSYTH_CACHE_SIZE: usize = 32;
+
+fn forsyth_score(cache_position: Option<usize>, live_triangles: u32) -> f32 {
+    if live_triangles == 0 {
+        // Nothing left to draw with it
+        return -1.0;
+    }
+    let position_score = match cache_position {
+        None => 0.0,
+        // Used by the last triangle, whichever order it was drawn in
+        Some(e) if e < 3 => 0.75,
+        Some(e) => {
+            let scale = 1.0 / (FORSYTH_CACHE_SIZE - 3) as f32;
+            (1.0 - (e - 3) as f32 * scale).powf(1.5)
+        }
+    };
+    // Vertices with few triangles left get them out of the way
+    position_score + 2.0 / (live_triangles as f32).sqrt()
+}
+
+///
+/// Triangle order of the indices for the post transform cache, picking the triangle with
+/// the best score among the ones using cached vertices each step.
+///
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: u32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let vertex_count = vertex_count as usize;
+    // Triangles of each vertex, the live ones first
+    let mut live_triangles = vec![0u32; vertex_count];
+    for e in indices {
+        live_triangles[*e as usize] += 1;
+    }
+    let mut triangle_offsets = vec![0usize; vertex_count + 1];
+    for v in 0..vertex_count {
+        triangle_offsets[v + 1] = triangle_offsets[v] + live_triangles[v] as usize;
+    }
+    let mut vertex_triangles = vec![0u32; indices.len()];
+    let mut filled = triangle_offsets.clone();
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        for v in triangle {
+            vertex_triangles[filled[*v as usize]] = t as u32;
+            filled[*v as usize] += 1;
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = (0..vertex_count)
+        .map(|v| forsyth_score(None, live_triangles[v]))
+        .collect();
+    let mut triangle_scores: Vec<f32> = indices
+        .chunks_exact(3)
+        .map(|e| e.iter().map(|v| vertex_scores[*v as usize]).sum())
+        .collect();
+    let mut is_emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+    let mut dst = Vec::with_capacity(indices.len());
+    let mut next_unemitted = 0;
+    let mut best = None;
+
+    while dst.len() < indices.len() {
+        let triangle = match best {
+            Some(e) => e,
+            None => {
+                // Cache ran dry, go on with the next triangle in input order
+                while is_emitted[next_unemitted] {
+                    next_unemitted += 1;
+                }
+                next_unemitted
+            }
+        };
+        is_emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        dst.extend_from_slice(corners);
+        for v in corners {
+            let v = *v as usize;
+            let start = triangle_offsets[v];
+            let live = &mut vertex_triangles[start..start + live_triangles[v] as usize];
+            let at = live.iter().position(|e| *e == triangle as u32).unwrap();
+            let last = live.len() - 1;
+            live.swap(at, last);
+            live_triangles[v] -= 1;
+        }
+
+        // Corners go to the front, whatever falls off the end leaves the cache
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|e| !corners.contains(e)));
+        let evicted: Vec<u32> = new_cache.split_off(FORSYTH_CACHE_SIZE.min(new_cache.len()));
+        for e in evicted {
+            cache_positions[e as usize] = None;
+            vertex_scores[e as usize] = forsyth_score(None, live_triangles[e as usize]);
+            update_triangle_scores(
+                e as usize,
+                indices,
+                &triangle_offsets,
+                &live_triangles,
+                &vertex_triangles,
+                &vertex_scores,
+                &mut triangle_scores,
+            );
+        }
+        for (i, e) in new_cache.iter().enumerate() {
+            cache_positions[*e as usize] = Some(i);
+            vertex_scores[*e as usize] = forsyth_score(Some(i), live_triangles[*e as usize]);
+        }
+        for e in &new_cache {
+            update_triangle_scores(
+                *e as usize,
+                indices,
+                &triangle_offsets,
+                &live_triangles,
+                &vertex_triangles,
+                &vertex_scores,
+                &mut triangle_scores,
+            );
+        }
+        best = None;
+        let mut best_score = f32::MIN;
+        for e in &new_cache {
+            let v = *e as usize;
+            let start = triangle_offsets[v];
+            for t in &vertex_triangles[start..start + live_triangles[v] as usize] {
+                let t = *t as usize;
+                if triangle_scores[t] > best_score {
+                    best_score = triangle_scores[t];
+                    best = Some(t);
+                }
+            }
+        }
+        cache = new_cache;
+    }
+    dst
+}
+
+fn update_triangle_scores(
+    vertex: usize,
+    indices: &[u32],
+    triangle_offsets: &[usize],
+    live_triangles: &[u32],
+    vertex_triangles: &[u32],
+    vertex_scores: &[f32],
+    triangle_scores: &mut [f32],
+) {
+    let start = triangle_offsets[vertex];
+    for t in &vertex_triangles[start..start + live_triangles[vertex] as usize] {
+        let t = *t as usize;
+        triangle_scores[t] = indices[t * 3..t * 3 + 3]
+            .iter()
+            .map(|e| vertex_scores[*e as usize])
+            .sum();
+    }
+}
+
+///
+/// Keeps the triangles of the indices in clusters that start where the cache misses all
+/// three vertices, and draws the clusters facing away from the center of the mesh first
+/// so they occlude the rest.
+///
+pub fn optimize_overdraw(indices: &[u32], mesh: &PackedMesh) -> Vec<u32> {
+    let mut cluster_starts = vec![0];
+    let mut timestamps = vec![0u32; mesh.vertex_count as usize];
+    let mut timestamp = ACMR_CACHE_SIZE + 1;
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        let mut misses = 0;
+        for e in triangle {
+            let last = &mut timestamps[*e as usize];
+            if timestamp - *last > ACMR_CACHE_SIZE {
+                *last = timestamp;
+                timestamp += 1;
+                misses += 1;
+            }
+        }
+        if misses == 3 && t > 0 {
+            cluster_starts.push(t * 3);
+        }
+    }
+    cluster_starts.push(indices.len());
+
+    // Area weighted centroids and normals
+    let mut mesh_centroid = Vec3::ZERO;
+    let mut mesh_area = 0.0;
+    let mut clusters: Vec<(Vec3, Vec3, &[u32])> = cluster_starts
+        .windows(2)
+        .map(|e| {
+            let triangles = &indices[e[0]..e[1]];
+            let mut centroid = Vec3::ZERO;
+            let mut normal = Vec3::ZERO;
+            let mut area = 0.0;
+            for triangle in triangles.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.position(triangle[i]));
+                let cross = (b - a).cross(c - a);
+                let triangle_area = cross.length();
+                centroid += (a + b + c) / 3.0 * triangle_area;
+                normal += cross;
+                area += triangle_area;
+            }
+            mesh_centroid += centroid;
+            mesh_area += area;
+            let centroid = if area > 0.0 {
+                centroid / area
+            } else {
+                centroid
+            };
+            (centroid, normal.normalize_or_zero(), triangles)
+        })
+        .collect();
+    if mesh_area > 0.0 {
+        mesh_centroid /= mesh_area;
+    }
+    let key = |e: &(Vec3, Vec3, &[u32])| (e.0 - mesh_centroid).dot(e.1);
+    clusters.sort_by(|a, b| key(b).total_cmp(&key(a)));
+    clusters.into_iter().flat_map(|e| e.2).copied().collect()
+}
@@ -0,0 +1,642 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use ash::vk;
+
+use crate::{
+    bounds::MeshBounds,
+    buffer::{DeviceAllocator, DeviceSlice},
+    context::VulkanContext,
+    format::Format,
+    handle::MeshHandle,
+    operation::{Operation, Step},
+    renderer::{MeshBuffer, Renderer},
+    retry::{RetryAfter, RetryError, RetryQueue, RetryReason},
+    vertex,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MeshAttribute {
+    Vertices,
+    Normals,
+    TexCoords,
+    Indices,
+}
+
+impl MeshAttribute {
+    pub fn of(self, mesh: &MeshBuffer) -> DeviceSlice {
+        match self {
+            Self::Vertices => mesh.vertices,
+            Self::Normals => mesh.normals,
+            Self::TexCoords => mesh.tex_coords,
+            Self::Indices => mesh.indices,
+        }
+    }
+}
+
+struct Chunk {
+    mesh_id: u32,
+    upload: u64,
+    staging: DeviceSlice,
+    // Staging allocations get rounded up
+    size: u64,
+    dst: vk::Buffer,
+    dst_offset: u64,
+}
+
+// Written while staging was full, staged on a later frame
+struct Deferred {
+    mesh_id: u32,
+    upload: u64,
+    data: Vec<u8>,
+    dst: vk::Buffer,
+    dst_offset: u64,
+}
+
+#[derive(Default)]
+struct UploadState {
+    // Tells apart uploads of the same attribute
+    upload: u64,
+    // Staged chunks not recorded yet, deferred ones included
+    pending: u32,
+    is_finished: bool,
+    is_cancelled: bool,
+    // Frame the last chunk got recorded in
+    last_frame: Option<u64>,
+}
+
+impl UploadState {
+    fn is_complete(&self, completed_frames: u64) -> bool {
+        !self.is_cancelled
+            && self.is_finished
+            && self.pending == 0
+            && self.last_frame.is_none_or(|e| e < completed_frames)
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    chunks: VecDeque<Chunk>,
+    staged_bytes: u64,
+    staging_capacity: u64,
+    uploads: HashMap<(u32, MeshAttribute), UploadState>,
+    next_upload: u64,
+    // Staging of recorded chunks, with the frame that copies from it
+    in_flight: Vec<(u64, DeviceSlice)>,
+    // Left for the current frame, None for no limit
+    frame_budget: Option<u64>,
+    frame_spent: u64,
+    is_bounds_computed: bool,
+    // Of vertex uploads finished since the renderer last took them
+    bounds: HashMap<u32, MeshBounds>,
+    deferred: RetryQueue<Deferred>,
+    retry_limit: u32,
+    // Deferrals since the renderer last took them, by mesh id
+    retries: Vec<(u32, Result<u32, RetryError>)>,
+}
+
+///
+/// Spreads the uploads recorded on the graphics queue over several frames. Texture
+/// transitions and mesh chunks spend from the same per frame byte budget, whatever goes
+/// over it waits for the next frame. The first upload of a frame always goes so big
+/// ones can't get stuck.
+///
+#[derive(Clone)]
+pub struct UploadScheduler {
+    inner: Arc<Mutex<Inner>>,
+    allocator: DeviceAllocator,
+}
+
+impl UploadScheduler {
+    pub fn new(allocator: DeviceAllocator, staging_capacity: u64) -> Self {
+        let inner = Inner {
+            staging_capacity,
+            ..Default::default()
+        };
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            allocator,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("upload scheduler lock poisoned!")
+    }
+
+    pub fn set_staging_capacity(&self, bytes: u64) {
+        self.lock().staging_capacity = bytes;
+    }
+
+    pub fn set_retry_limit(&self, limit: u32) {
+        self.lock().retry_limit = limit;
+    }
+
+    ///
+    /// Whether vertex uploads begun from now on keep their positions on the host to
+    /// compute the bounds of the mesh once finished.
+    ///
+    pub fn set_bounds_computed(&self, is_computed: bool) {
+        self.lock().is_bounds_computed = is_computed;
+    }
+
+    ///
+    /// Bounds of the last vertex upload finished for the mesh, until taken.
+    ///
+    pub fn bounds_of(&self, mesh_id: u32) -> Option<MeshBounds> {
+        self.lock().bounds.get(&mesh_id).copied()
+    }
+
+    pub fn take_bounds(&self) -> HashMap<u32, MeshBounds> {
+        std::mem::take(&mut self.lock().bounds)
+    }
+
+    ///
+    /// Starts over a previous upload of the same attribute, dropping its staged chunks.
+    /// Positions are read for the bounds at the stride and offset given, in the format
+    /// given, (12, 0, R32G32B32_SFLOAT) unless the mesh is interleaved.
+    ///
+    pub fn begin(
+        &self,
+        mesh_id: u32,
+        attribute: MeshAttribute,
+        dst: DeviceSlice,
+        position_stride: (u32, u32, Format),
+    ) -> MeshUploadCursor {
+        let mut inner = self.lock();
+        if let Some(previous) = inner.uploads.get(&(mesh_id, attribute)) {
+            let previous = previous.upload;
+            self.drop_chunks(&mut inner, |_, upload| upload == previous);
+        }
+        inner.next_upload += 1;
+        let upload = inner.next_upload;
+        let state = UploadState {
+            upload,
+            ..Default::default()
+        };
+        inner.uploads.insert((mesh_id, attribute), state);
+        let positions = (attribute == MeshAttribute::Vertices && inner.is_bounds_computed)
+            .then(|| Vec::with_capacity(dst.size as usize));
+        MeshUploadCursor {
+            scheduler: self.clone(),
+            mesh_id,
+            attribute,
+            upload,
+            dst,
+            written: 0,
+            positions,
+            position_stride,
+            is_finished: false,
+        }
+    }
+
+    /*
+     * Cancelled uploads keep the mesh from drawing until another upload of the attribute
+     * completes, its contents are only partly written.
+     */
+    fn cancel(&self, mesh_id: u32, attribute: MeshAttribute, upload: u64) {
+        let mut inner = self.lock();
+        match inner.uploads.get_mut(&(mesh_id, attribute)) {
+            // Replaced by a newer upload or the mesh got freed
+            Some(state) if state.upload == upload => {
+                state.pending = 0;
+                state.is_cancelled = true;
+            }
+            _ => return,
+        }
+        self.drop_chunks(&mut inner, |_, e| e == upload);
+    }
+
+    ///
+    /// Forgets everything about the mesh, for when it gets freed.
+    ///
+    pub fn remove_mesh(&self, mesh_id: u32) {
+        let mut inner = self.lock();
+        self.drop_chunks(&mut inner, |id, _| id == mesh_id);
+        inner.uploads.retain(|e, _| e.0 != mesh_id);
+        inner.bounds.remove(&mesh_id);
+    }
+
+    // Filtered by mesh id and upload, deferred ones along
+    fn drop_chunks(&self, inner: &mut Inner, filter: impl Fn(u32, u64) -> bool) {
+        let (dropped, kept) = std::mem::take(&mut inner.chunks)
+            .into_iter()
+            .partition::<Vec<_>, _>(|e| filter(e.mesh_id, e.upload));
+        inner.chunks = kept.into();
+        for chunk in dropped {
+            inner.staged_bytes -= chunk.staging.size;
+            self.allocator.free(chunk.staging);
+        }
+        inner.deferred.retain(|e| !filter(e.mesh_id, e.upload));
+    }
+
+    /*
+     * Copies the data into a new staged chunk if it fits, both in the staging capacity
+     * and the allocator.
+     */
+    fn stage_locked(
+        &self,
+        inner: &mut Inner,
+        (mesh_id, upload): (u32, u64),
+        data: &[u8],
+        (dst, dst_offset): (vk::Buffer, u64),
+    ) -> bool {
+        let staging = match self.allocator.alloc(data.len() as u64) {
+            Some(e) if inner.staged_bytes + e.size <= inner.staging_capacity => e,
+            Some(e) => {
+                self.allocator.free(e);
+                return false;
+            }
+            None => return false,
+        };
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), staging.addr as *mut u8, data.len())
+        };
+        inner.staged_bytes += staging.size;
+        inner.chunks.push_back(Chunk {
+            mesh_id,
+            upload,
+            staging,
+            size: data.len() as u64,
+            dst,
+            dst_offset,
+        });
+        true
+    }
+
+    /*
+     * Puts the data back for a later frame, cancelling the upload once it failed more
+     * often than the retry limit allows.
+     */
+    fn defer_locked(&self, inner: &mut Inner, deferred: Deferred, attempts: u32) {
+        let (mesh_id, upload) = (deferred.mesh_id, deferred.upload);
+        let limit = inner.retry_limit;
+        let result = inner
+            .deferred
+            .defer(deferred, RetryReason::StagingFull, attempts, limit);
+        if result.is_err() {
+            if let Some(state) = inner.uploads.values_mut().find(|e| e.upload == upload) {
+                state.pending = 0;
+                state.is_cancelled = true;
+            }
+            self.drop_chunks(inner, |_, e| e == upload);
+        }
+        inner.retries.push((mesh_id, result));
+    }
+
+    ///
+    /// Stages up to cap of the chunks deferred by MeshUploadCursor::write_all, oldest
+    /// first. Ones that still don't fit go back for the next frame.
+    ///
+    pub fn stage_deferred(&self, cap: u32) {
+        let mut inner = self.lock();
+        for (deferred, attempts) in inner.deferred.take(cap) {
+            let key = (deferred.mesh_id, deferred.upload);
+            let dst = (deferred.dst, deferred.dst_offset);
+            if !self.stage_locked(&mut inner, key, &deferred.data, dst) {
+                self.defer_locked(&mut inner, deferred, attempts + 1);
+            }
+        }
+    }
+
+    ///
+    /// Chunks deferred or retried since the last call by mesh id, with the attempt they
+    /// get next or the error they failed with for good.
+    ///
+    pub fn take_retries(&self) -> Vec<(u32, Result<u32, RetryError>)> {
+        std::mem::take(&mut self.lock().retries)
+    }
+
+    ///
+    /// Staged chunks not recorded yet and, of them, deferred ones not staged yet.
+    ///
+    pub fn pending_chunks(&self) -> (u32, u32) {
+        let inner = self.lock();
+        let deferred = inner.deferred.len() as u32;
+        (inner.chunks.len() as u32 + deferred, deferred)
+    }
+
+    ///
+    /// Whether the mesh has uploads that aren't on the GPU yet, completed_frames being
+    /// the frame timeline value.
+    ///
+    pub fn is_uploading(&self, mesh_id: u32, completed_frames: u64) -> bool {
+        self.lock()
+            .uploads
+            .iter()
+            .any(|(k, v)| k.0 == mesh_id && !v.is_complete(completed_frames))
+    }
+
+    ///
+    /// Frees the staging the GPU is done copying from and drops completed uploads.
+    ///
+    pub fn release(&self, completed_frames: u64) {
+        let mut inner = self.lock();
+        let in_flight = std::mem::take(&mut inner.in_flight);
+        for (frame, staging) in in_flight {
+            if frame < completed_frames {
+                self.allocator.free(staging);
+            } else {
+                inner.in_flight.push((frame, staging));
+            }
+        }
+        inner
+            .uploads
+            .retain(|_, e| !e.is_complete(completed_frames));
+    }
+
+    pub fn begin_frame(&self, bytes_per_frame: Option<u64>) {
+        let mut inner = self.lock();
+        inner.frame_budget = bytes_per_frame;
+        inner.frame_spent = 0;
+    }
+
+    ///
+    /// Takes the bytes out of the budget of the frame if they fit.
+    ///
+    pub fn try_spend(&self, bytes: u64) -> bool {
+        Self::try_spend_locked(&mut self.lock(), bytes)
+    }
+
+    fn try_spend_locked(inner: &mut Inner, bytes: u64) -> bool {
+        let fits = match inner.frame_budget {
+            Some(e) => inner.frame_spent == 0 || inner.frame_spent + bytes <= e,
+            None => true,
+        };
+        if fits {
+            inner.frame_spent += bytes;
+        }
+        fits
+    }
+
+    ///
+    /// Records the copies of the staged chunks that fit in what's left of the frame budget.
+    /// Returns the bytes recorded.
+    ///
+    pub fn record(&self, ctx: &VulkanContext, cmd: vk::CommandBuffer, frame: u64) -> u64 {
+        let mut inner = self.lock();
+        let mut recorded = 0;
+        while let Some(chunk) = inner.chunks.front() {
+            let size = chunk.size;
+            if !Self::try_spend_locked(&mut inner, size) {
+                break;
+            }
+            let chunk = inner.chunks.pop_front().unwrap();
+            let region = vk::BufferCopy {
+                src_offset: chunk.staging.offset,
+                dst_offset: chunk.dst_offset,
+                size: chunk.size,
+            };
+            unsafe {
+                ctx.device
+                    .cmd_copy_buffer(cmd, chunk.staging.buffer, chunk.dst, &[region])
+            };
+            let state = inner
+                .uploads
+                .values_mut()
+                .find(|e| e.upload == chunk.upload)
+                .unwrap();
+            state.pending -= 1;
+            state.last_frame = Some(frame);
+            inner.staged_bytes -= chunk.staging.size;
+            inner.in_flight.push((frame, chunk.staging));
+            recorded += chunk.size;
+        }
+        if recorded > 0 {
+            // Meshes are read through vertex/index bindings and buffer addresses
+            let barriers = [vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
+                .build()];
+            let dep_info = vk::DependencyInfo::builder()
+                .memory_barriers(&barriers)
+                .build();
+            unsafe { ctx.device.cmd_pipeline_barrier2(cmd, &dep_info) };
+        }
+        recorded
+    }
+}
+
+///
+/// Sequential writer into one attribute of a mesh, for meshes too big to write in one
+/// go. Data gets staged in chunks and copied over the next frames, tasks with the mesh
+/// are rejected until every upload of it is complete. Dropping it without calling
+/// finish cancels the upload.
+///
+pub struct MeshUploadCursor {
+    scheduler: UploadScheduler,
+    mesh_id: u32,
+    attribute: MeshAttribute,
+    upload: u64,
+    dst: DeviceSlice,
+    written: u64,
+    // Written so far, only for vertex uploads computing the bounds
+    positions: Option<Vec<u8>>,
+    // Stride, offset and format of the positions within the vertices written
+    position_stride: (u32, u32, Format),
+    is_finished: bool,
+}
+
+impl MeshUploadCursor {
+    // Largest single copy recorded
+    pub const CHUNK_SIZE: u64 = 1024 * 1024;
+
+    ///
+    /// Stages as much of the data as the staging capacity allows and returns how many
+    /// bytes it took. Zero means staging is full, render a frame and try again with the
+    /// rest.
+    ///
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        self.try_write(data).unwrap_or(0)
+    }
+
+    ///
+    /// Same as write, with staging full being RetryError::Transient instead of zero
+    /// bytes taken.
+    ///
+    pub fn try_write(&mut self, data: &[u8]) -> Result<usize, RetryError> {
+        if data.len() as u64 > self.remaining() {
+            panic!(
+                "writing {} bytes to {:?} of mesh {} but only {} are left!",
+                data.len(),
+                self.attribute,
+                self.mesh_id,
+                self.remaining()
+            );
+        }
+        let mut inner = self.scheduler.lock();
+        let key = (self.mesh_id, self.attribute);
+        if inner.uploads.get(&key).map(|e| e.upload) != Some(self.upload) {
+            panic!(
+                "upload of {:?} of mesh {} was replaced or the mesh freed!",
+                self.attribute, self.mesh_id
+            );
+        }
+        let mut taken = 0;
+        while taken < data.len() {
+            let size = (Self::CHUNK_SIZE as usize).min(data.len() - taken);
+            let chunk = (self.mesh_id, self.upload);
+            let dst = (self.dst.buffer, self.dst.offset + self.written);
+            let bytes = &data[taken..taken + size];
+            if !self.scheduler.stage_locked(&mut inner, chunk, bytes, dst) {
+                break;
+            }
+            inner.uploads.get_mut(&key).unwrap().pending += 1;
+            self.written += size as u64;
+            taken += size;
+        }
+        if let Some(positions) = &mut self.positions {
+            positions.extend_from_slice(&data[..taken]);
+        }
+        if taken == 0 && !data.is_empty() {
+            return Err(RetryError::Transient(RetryAfter::NextFrame));
+        }
+        Ok(taken)
+    }
+
+    ///
+    /// Same as write, what doesn't fit gets kept and staged by the renderer on later
+    /// frames. Once a part fails to stage RendererConfig::retry_limit more times the
+    /// upload gets cancelled, see Renderer::take_failed_work.
+    ///
+    pub fn write_all(&mut self, data: &[u8]) {
+        let taken = self.write(data);
+        let mut inner = self.scheduler.lock();
+        let key = (self.mesh_id, self.attribute);
+        for part in data[taken..].chunks(Self::CHUNK_SIZE as usize) {
+            match inner.uploads.get_mut(&key) {
+                // Cancelled by an earlier part
+                Some(state) if state.upload == self.upload && !state.is_cancelled => {
+                    state.pending += 1
+                }
+                _ => break,
+            }
+            let deferred = Deferred {
+                mesh_id: self.mesh_id,
+                upload: self.upload,
+                data: part.to_vec(),
+                dst: self.dst.buffer,
+                dst_offset: self.dst.offset + self.written,
+            };
+            self.written += part.len() as u64;
+            self.scheduler.defer_locked(&mut inner, deferred, 1);
+        }
+        if let Some(positions) = &mut self.positions {
+            positions.extend_from_slice(&data[taken..]);
+        }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.dst.size - self.written
+    }
+
+    ///
+    /// No more writes, the upload completes once the staged chunks are on the GPU. Poll
+    /// Renderer::is_upload_complete to know when.
+    ///
+    pub fn finish(mut self) {
+        self.is_finished = true;
+        let bounds = self.positions.take().and_then(|e| {
+            let (stride, offset, format) = self.position_stride;
+            MeshBounds::of_positions(&vertex::decode_positions(&e, stride, offset, format))
+        });
+        let mut inner = self.scheduler.lock();
+        match inner.uploads.get_mut(&(self.mesh_id, self.attribute)) {
+            // Deferred parts may have failed for good already
+            Some(state) if state.upload == self.upload && !state.is_cancelled => {
+                state.is_finished = true
+            }
+            _ => return,
+        }
+        if let Some(bounds) = bounds {
+            inner.bounds.insert(self.mesh_id, bounds);
+        }
+    }
+}
+
+impl Drop for MeshUploadCursor {
+    fn drop(&mut self) {
+        if !self.is_finished {
+            self.scheduler
+                .cancel(self.mesh_id, self.attribute, self.upload);
+        }
+    }
+}
+
+///
+/// Writes the data into one attribute of a mesh a chunk per step, see
+/// Renderer::upload_mesh_in_steps. Done once the GPU copied all of it. Cancelling it
+/// cancels the upload like dropping a cursor does, the chunks staged so far get
+/// dropped. Abandoned if the mesh gets freed meanwhile.
+///
+pub struct MeshUploadOperation {
+    name: String,
+    mesh: MeshHandle,
+    data: Vec<u8>,
+    written: usize,
+    // None once every chunk is staged
+    cursor: Option<MeshUploadCursor>,
+}
+
+impl MeshUploadOperation {
+    pub fn new(mesh: MeshHandle, cursor: MeshUploadCursor, data: Vec<u8>) -> Self {
+        if data.len() as u64 != cursor.remaining() {
+            panic!(
+                "uploading {} bytes to {:?} of mesh {} but it takes {}!",
+                data.len(),
+                cursor.attribute,
+                mesh.index,
+                cursor.remaining()
+            );
+        }
+        Self {
+            name: format!("upload {:?} of mesh {}", cursor.attribute, mesh.index),
+            mesh,
+            data,
+            written: 0,
+            cursor: Some(cursor),
+        }
+    }
+}
+
+impl Operation<Renderer> for MeshUploadOperation {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn progress(&self) -> f32 {
+        self.written as f32 / self.data.len().max(1) as f32
+    }
+
+    fn step(&mut self, renderer: &mut Renderer) -> Step {
+        if renderer.fetch_mesh(self.mesh).is_none() {
+            return Step::Abandoned;
+        }
+        let cursor = match &mut self.cursor {
+            Some(cursor) => cursor,
+            None if renderer.is_upload_complete(self.mesh) == Ok(true) => return Step::Done,
+            None => return Step::Blocked,
+        };
+        let end = (self.written + MeshUploadCursor::CHUNK_SIZE as usize).min(self.data.len());
+        let taken = cursor.write(&self.data[self.written..end]);
+        if taken == 0 && end > self.written {
+            return Step::Blocked;
+        }
+        self.written += taken;
+        if self.written == self.data.len() {
+            self.cursor.take().unwrap().finish();
+        }
+        Step::Continue
+    }
+
+    fn unwind(&mut self, _: &mut Renderer) {
+        self.cursor = None;
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec2, Vec4};
+
+use crate::render_task::TaskKind;
+
+///
+/// Sub-pixel offsets cycled through frame after frame, see
+/// Renderer::set_taa_jitter_sequence. Offsets are in pixels, within half a pixel of the
+/// center.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct JitterSequence {
+    samples: Vec<Vec2>,
+}
+
+impl JitterSequence {
+    pub fn new(samples: Vec<Vec2>) -> Self {
+        if samples.is_empty() {
+            panic!("jitter sequence can't be empty!");
+        }
+        Self { samples }
+    }
+
+    ///
+    /// First len points of the Halton sequence in bases 2 and 3, the usual TAA pattern.
+    /// Starts at index 1, index 0 would be the corner.
+    ///
+    pub fn halton_2_3(len: u32) -> Self {
+        let samples = (1..=len)
+            .map(|i| Vec2::new(halton(i, 2), halton(i, 3)) - 0.5)
+            .collect();
+        Self::new(samples)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn samples(&self) -> &[Vec2] {
+        &self.samples
+    }
+
+    ///
+    /// Offset in pixels for the frame.
+    ///
+    pub fn at(&self, frame: u64) -> Vec2 {
+        self.samples[(frame % self.samples.len() as u64) as usize]
+    }
+
+    ///
+    /// Offset in clip space for the frame on a target of the extent, what gets added to
+    /// the projected xy before the divide by w.
+    ///
+    pub fn clip_space_at(&self, frame: u64, width: u32, height: u32) -> Vec2 {
+        self.at(frame) * 2.0 / Vec2::new(width as f32, height as f32)
+    }
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+    r
+}
+
+///
+/// Transforms of the previous frames by object identity, what previousTransform per draw
+/// fields read. Keyed by task kind as well, the same object gets different mvps in
+/// shadow and camera stages.
+///
+#[derive(Default)]
+pub struct PreviousTransforms {
+    // Frame last seen in and the mvp of each instance back then
+    entries: HashMap<(TaskKind, u64), (u64, Vec<Mat4>)>,
+}
+
+impl PreviousTransforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, kind: TaskKind, object_id: u64) -> Option<&[Mat4]> {
+        self.entries.get(&(kind, object_id)).map(|e| e.1.as_slice())
+    }
+
+    pub fn record(&mut self, kind: TaskKind, object_id: u64, frame: u64, mvps: Vec<Mat4>) {
+        self.entries.insert((kind, object_id), (frame, mvps));
+    }
+
+    ///
+    /// Drops the entries last seen more than lifetime frames before the frame.
+    ///
+    pub fn evict(&mut self, frame: u64, lifetime: u64) {
+        self.entries
+            .retain(|_, e| frame.saturating_sub(e.0) <= lifetime);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+///
+/// Affine transform of a simulation object, the first 3 rows of its 4x4 matrix in row
+/// major order, what Renderer::set_frame_transforms_interpolated takes.
+///
+pub type Affine = [f32; 12];
+
+pub const IDENTITY_AFFINE: Affine = [
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0,
+];
+
+pub fn affine_to_mat4(m: &Affine) -> Mat4 {
+    Mat4::from_cols(
+        Vec4::new(m[0], m[4], m[8], 0.0),
+        Vec4::new(m[1], m[5], m[9], 0.0),
+        Vec4::new(m[2], m[6], m[10], 0.0),
+        Vec4::new(m[3], m[7], m[11], 1.0),
+    )
+}
+
+///
+/// Drops the last row, which has to be 0, 0, 0, 1 for the matrix to be affine.
+///
+pub fn mat4_to_affine(m: &Mat4) -> Affine {
+    let rows = [m.row(0), m.row(1), m.row(2)];
+    let mut affine = [0.0; 12];
+    for (dst, row) in affine.chunks_exact_mut(4).zip(rows) {
+        dst.copy_from_slice(&row.to_array());
+    }
+    affine
+}
+
+///
+/// Component wise lerp of the matrices, t past 0 and 1 extrapolates. Rotations come out
+/// scaled down in between, by cos(angle / 2) halfway through a turn of angle, fine for
+/// the few degrees objects turn over a simulation step. See dual_quat for the exact way.
+///
+pub fn lerp_affine(a: &Affine, b: &Affine, t: f32) -> Affine {
+    let mut lerped = [0.0; 12];
+    for (dst, (a, b)) in lerped.iter_mut().zip(a.iter().zip(b)) {
+        *dst = a + (b - a) * t;
+    }
+    lerped
+}
+
+///
+/// Previous and current state of every simulation object, indexed by
+/// RenderTask::object_id with the instances of a task at the indices after it, and how
+/// far the rendered frame is from the previous state to the current one.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationTransforms {
+    previous: Vec<Affine>,
+    current: Vec<Affine>,
+    alpha: f32,
+    // Blends with dual quaternions instead of lerping the matrices
+    #[cfg(feature = "dual-quaternion")]
+    is_dual_quaternion: bool,
+}
+
+impl SimulationTransforms {
+    pub fn new(previous: &[Affine], current: &[Affine], alpha: f32) -> Self {
+        if previous.len() != current.len() {
+            panic!(
+                "{} previous simulation transforms but {} current ones!",
+                previous.len(),
+                current.len()
+            );
+        }
+        if !(0.0..=1.0).contains(&alpha) {
+            panic!("interpolation alpha {} isn't within 0 and 1!", alpha);
+        }
+        Self {
+            previous: previous.to_vec(),
+            current: current.to_vec(),
+            alpha,
+            #[cfg(feature = "dual-quaternion")]
+            is_dual_quaternion: false,
+        }
+    }
+
+    #[cfg(feature = "dual-quaternion")]
+    pub fn with_dual_quaternions(mut self, is_dual_quaternion: bool) -> Self {
+        self.is_dual_quaternion = is_dual_quaternion;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    pub fn previous(&self) -> &[Affine] {
+        &self.previous
+    }
+
+    pub fn current(&self) -> &[Affine] {
+        &self.current
+    }
+
+    ///
+    /// Whether shaders get dual quaternions instead of matrices, see dual_quat.
+    ///
+    pub fn is_dual_quaternion(&self) -> bool {
+        #[cfg(feature = "dual-quaternion")]
+        return self.is_dual_quaternion;
+        #[cfg(not(feature = "dual-quaternion"))]
+        false
+    }
+
+    ///
+    /// Transform of the object at the index in the rendered frame.
+    ///
+    pub fn interpolated(&self, index: usize) -> Affine {
+        self.blend(index, self.alpha)
+    }
+
+    ///
+    /// What the object gets moved from for its velocity: the interpolated transform one
+    /// whole simulation step earlier. The motion over the frame is the one between the
+    /// previous and current states whatever alpha is, frames interpolated at alphas
+    /// apart by some irregular amount don't make velocities jump around.
+    ///
+    pub fn velocity_origin(&self, index: usize) -> Affine {
+        self.blend(index, self.alpha - 1.0)
+    }
+
+    ///
+    /// Bytes of the previous and current states, the way shaders read them: matrices
+    /// row by row, or packed dual quaternions.
+    ///
+    pub fn to_bytes(&self) -> [Vec<u8>; 2] {
+        [self.encode(&self.previous), self.encode(&self.current)]
+    }
+
+    ///
+    /// Bytes of the interpolated transforms of count objects from the index, the same
+    /// way as to_bytes.
+    ///
+    pub fn interpolated_bytes(&self, index: usize, count: usize) -> Vec<u8> {
+        let interpolated: Vec<_> = (index..index + count)
+            .map(|i| self.interpolated(i))
+            .collect();
+        self.encode(&interpolated)
+    }
+
+    fn encode(&self, transforms: &[Affine]) -> Vec<u8> {
+        #[cfg(feature = "dual-quaternion")]
+        if self.is_dual_quaternion {
+            return crate::dual_quat::pack_all(transforms)
+                .iter()
+                .flatten()
+                .flat_map(|e| e.to_ne_bytes())
+                .collect();
+        }
+        transforms
+            .iter()
+            .flatten()
+            .flat_map(|e| e.to_ne_bytes())
+            .collect()
+    }
+
+    fn blend(&self, index: usize, t: f32) -> Affine {
+        let (previous, current) = (&self.previous[index], &self.current[index]);
+        #[cfg(feature = "dual-quaternion")]
+        if self.is_dual_quaternion {
+            use crate::dual_quat::DualQuat;
+            let (a, b) = (
+                DualQuat::from_affine(previous),
+                DualQuat::from_affine(current),
+            );
+            return DualQuat::blend(&a, &b, t).to_affine();
+        }
+        lerp_affine(previous, current, t)
+    }
+}
+
+///
+/// What the motion related per draw fields of a frame read.
+///
+#[derive(Clone, Copy)]
+pub struct FrameMotion<'a> {
+    pub previous: &'a PreviousTransforms,
+    pub simulation: Option<&'a SimulationTransforms>,
+    // Device addresses of the previous and current states, when a stage interpolates
+    // in its shaders
+    pub uploaded_simulation: Option<[u64; 2]>,
+}
@@ -0,0 +1,144 @@
+/*
+ * Noise every frame gets without the host asking, for dithering, stochastic
+ * transparency and the like. Two blue noise textures sit at reserved texture ids,
+ * Renderer::BLUE_NOISE with a single R8_UNORM channel and Renderer::BLUE_NOISE_DUAL with
+ * two decorrelated R8G8_UNORM ones. The embedded ones are 64x64, made with void and
+ * cluster over a gaussian of sigma 1.5 on the torus so they tile. Stages sample them
+ * through the image table like any other texture.
+ *
+ * The frame constants get the values of FrameNoise::of the current frame in the members
+ * with these names, when the block has them:
+ *
+ *   uint frameIndex;     low 32 bits of the frame
+ *   uint noiseSeed;      hash of the frame, decorrelated from one frame to the next
+ *   uvec2 noiseOffset;   texels to offset the noise textures by, cycling every 64 frames
+ *
+ * Every stage of a frame reads the same values.
+ */
+use glam::UVec2;
+
+use crate::format::Format;
+
+// Frames after which the noise offset comes back around
+pub const NOISE_PERIOD: u64 = 64;
+// Side of the embedded textures, the offsets stay below it
+pub const NOISE_SIZE: u32 = 64;
+pub const FRAME_INDEX_MEMBER: &str = "frameIndex";
+pub const SEED_MEMBER: &str = "noiseSeed";
+pub const OFFSET_MEMBER: &str = "noiseOffset";
+
+const EMBEDDED_SINGLE: &[u8] = include_bytes!("../shader/builtin/blue_noise_r8.bin");
+const EMBEDDED_DUAL: &[u8] = include_bytes!("../shader/builtin/blue_noise_rg8.bin");
+// Plastic constant based R2 sequence, evenly spread for any number of frames
+const R2_ALPHA: [f64; 2] = [0.754_877_666_246_692_7, 0.569_840_290_998_053_2];
+
+///
+/// Noise textures the renderer registers at creation. Disabled leaves the reserved ids
+/// to the host and saves the memory, custom ones take the place of the embedded ones.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum NoiseConfig {
+    #[default]
+    Embedded,
+    Disabled,
+    Custom {
+        // R8_UNORM
+        single: NoiseTexture,
+        // R8G8_UNORM
+        dual: NoiseTexture,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseTexture {
+    pub width: u32,
+    pub height: u32,
+    // Rows of texels, tightly packed
+    pub texels: Vec<u8>,
+}
+
+impl NoiseTexture {
+    pub fn embedded_single() -> Self {
+        Self {
+            width: NOISE_SIZE,
+            height: NOISE_SIZE,
+            texels: EMBEDDED_SINGLE.to_vec(),
+        }
+    }
+
+    pub fn embedded_dual() -> Self {
+        Self {
+            width: NOISE_SIZE,
+            height: NOISE_SIZE,
+            texels: EMBEDDED_DUAL.to_vec(),
+        }
+    }
+
+    pub fn check(&self, format: Format) {
+        let texel_size = format.texel_size().expect("noise formats are uncompressed");
+        let expected = self.width as usize * self.height as usize * texel_size as usize;
+        if self.width == 0 || self.height == 0 || self.texels.len() != expected {
+            panic!(
+                "{}x{} {:?} noise texture needs {} bytes, got {}!",
+                self.width,
+                self.height,
+                format,
+                expected,
+                self.texels.len()
+            );
+        }
+    }
+}
+
+impl NoiseConfig {
+    ///
+    /// Single and dual channel textures to register, None if disabled.
+    ///
+    pub fn textures(&self) -> Option<(NoiseTexture, NoiseTexture)> {
+        match self {
+            Self::Embedded => Some((
+                NoiseTexture::embedded_single(),
+                NoiseTexture::embedded_dual(),
+            )),
+            Self::Disabled => None,
+            Self::Custom { single, dual } => Some((single.clone(), dual.clone())),
+        }
+    }
+}
+
+///
+/// Per frame noise values written into the frame constants, only depending on the
+/// frame so the same frame always gets the same ones.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameNoise {
+    pub frame_index: u32,
+    pub seed: u32,
+    pub offset: UVec2,
+}
+
+impl FrameNoise {
+    pub fn of(frame: u64) -> Self {
+        let n = (frame % NOISE_PERIOD) as f64;
+        let offset = R2_ALPHA.map(|e| ((0.5 + n * e).fract() * NOISE_SIZE as f64) as u32);
+        Self {
+            frame_index: frame as u32,
+            seed: hash(frame),
+            offset: UVec2::from(offset),
+        }
+    }
+}
+
+/*
+ * Murmur3 finalizer over both halves of the frame, consecutive frames end up with
+ * unrelated seeds. Offset so frame 0 doesn't get a seed of 0.
+ */
+fn hash(frame: u64) -> u32 {
+    let mut h = (frame as u32 ^ 0x9E37_79B9) ^ ((frame >> 32) as u32).wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2_AE35);
+    h ^= h >> 16;
+    h
+}
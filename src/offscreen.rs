@@ -0,0 +1,210 @@
+/*
+ * Offscreen passes draw tasks with the pipelines of a stage into a texture of their own,
+ * for previews and the like the host samples later on, without a stage of their own in
+ * the pipeline. See Renderer::render_to_texture. They get recorded after the stages of
+ * the frame, sharing its per pass data, and go through barriers of their own so the
+ * attachments of the stage stay as the stages of the frame left them.
+ *
+ * Their images come out of a pool keyed by size and format. A texture handed out stays
+ * the one of its key until freed, rendering with the same key again renders into it,
+ * so a live preview doesn't allocate every frame. Freed ones go back to the pool for
+ * the next pass of their key, they're only destroyed along with the renderer.
+ */
+use ash::vk;
+
+use crate::{
+    config::TaskRejected,
+    format::Format,
+    pipeline::stage::{PreparedStage, Stage},
+    render_task::RenderTask,
+    texture::Texture,
+};
+
+///
+/// What Renderer::render_to_texture renders and where. The format has to be the one the
+/// stage renders its output in. Stages with depth get a depth image of the format of
+/// theirs along with the texture.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffscreenPassDesc {
+    pub stage: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffscreenKey {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub depth_format: Option<Format>,
+}
+
+///
+/// Part of the textures made by render_to_texture, see Texture::offscreen.
+///
+#[derive(Clone)]
+pub struct OffscreenImage {
+    pub key: OffscreenKey,
+    pub depth: Option<Box<Texture>>,
+    // The frame of the first pass rendering it is done, until then it samples the
+    // default texture
+    pub is_rendered: bool,
+}
+
+///
+/// Images a prepared offscreen pass renders into, the depth one with its aspect and
+/// what it gets cleared to if the stage loads depth instead.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct OffscreenTarget {
+    pub color: vk::Image,
+    pub color_view: vk::ImageView,
+    pub depth: Option<(vk::Image, vk::ImageView, vk::ImageAspectFlags)>,
+    pub depth_clear_value: f32,
+    pub extent: vk::Extent2D,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OffscreenError {
+    UnknownStage(String),
+    // Blits, multiview stages, ones with inputs or more than the one output
+    UnsupportedStage {
+        stage: String,
+        reason: &'static str,
+    },
+    FormatMismatch {
+        stage: String,
+        expected: vk::Format,
+        requested: Format,
+    },
+    // Over RendererConfig::max_offscreen_extent on either side
+    TooLarge {
+        width: u32,
+        height: u32,
+        max: u32,
+    },
+    // No texture id left for a new image
+    TexturesFull,
+    Rejected(TaskRejected),
+}
+
+impl std::fmt::Display for OffscreenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownStage(stage) => write!(f, "no stage {}", stage),
+            Self::UnsupportedStage { stage, reason } => {
+                write!(f, "stage {} can't render offscreen, it {}", stage, reason)
+            }
+            Self::FormatMismatch {
+                stage,
+                expected,
+                requested,
+            } => write!(
+                f,
+                "stage {} renders in {:?}, not {}",
+                stage, expected, requested
+            ),
+            Self::TooLarge { width, height, max } => write!(
+                f,
+                "offscreen passes render at up to {}x{}, not {}x{}",
+                max, max, width, height
+            ),
+            Self::TexturesFull => write!(f, "no texture id left for an offscreen image"),
+            Self::Rejected(e) => write!(f, "task rejected, {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OffscreenError {}
+
+impl From<TaskRejected> for OffscreenError {
+    fn from(e: TaskRejected) -> Self {
+        Self::Rejected(e)
+    }
+}
+
+///
+/// Pass waiting for the next frame to get prepared.
+///
+pub(crate) struct PendingOffscreenPass {
+    pub texture: u32,
+    pub stage: String,
+    pub tasks: Vec<RenderTask>,
+}
+
+///
+/// Pass prepared along with the frame, recorded after its stages.
+///
+pub(crate) struct PreparedOffscreenPass {
+    pub stage: usize,
+    pub texture: u32,
+    pub prepared: PreparedStage,
+    pub target: OffscreenTarget,
+}
+
+///
+/// Images of an offscreen texture to render into, panics for other textures.
+///
+pub fn target_of(texture: &Texture, depth_clear_value: f32) -> OffscreenTarget {
+    let offscreen = texture
+        .offscreen
+        .as_ref()
+        .unwrap_or_else(|| panic!("texture {} isn't an offscreen one!", texture.name));
+    OffscreenTarget {
+        color: texture.image,
+        color_view: texture.view,
+        depth: offscreen
+            .depth
+            .as_ref()
+            .map(|e| (e.image, e.view, e.format.aspect())),
+        depth_clear_value,
+        extent: texture.extent(),
+    }
+}
+
+///
+/// Why the stage can't draw offscreen passes, None if it can.
+///
+pub fn unsupported_reason(stage: &Stage) -> Option<&'static str> {
+    if stage.blit.is_some() {
+        Some("copies instead of drawing")
+    } else if stage.view_mask != 0 {
+        Some("renders multiview")
+    } else if !stage.inputs.is_empty() {
+        Some("samples attachments")
+    } else if stage.outputs.len() != 1 {
+        Some("doesn't render into exactly one color output")
+    } else if stage.rendering.default_attachment_index.is_some() {
+        Some("renders into the default attachment")
+    } else {
+        None
+    }
+}
+
+///
+/// Pool key of the images a pass of the stage renders into.
+///
+pub fn key_of(stage: &Stage, desc: &OffscreenPassDesc) -> Result<OffscreenKey, OffscreenError> {
+    if let Some(reason) = unsupported_reason(stage) {
+        return Err(OffscreenError::UnsupportedStage {
+            stage: stage.name.clone(),
+            reason,
+        });
+    }
+    let expected = stage.outputs[0].vk_format;
+    if desc.format.to_vk() != expected {
+        return Err(OffscreenError::FormatMismatch {
+            stage: stage.name.clone(),
+            expected,
+            requested: desc.format,
+        });
+    }
+    Ok(OffscreenKey {
+        width: desc.width,
+        height: desc.height,
+        format: desc.format,
+        depth_format: stage.depth_stencil.as_ref().map(|e| e.format),
+    })
+}
@@ -0,0 +1,337 @@
+/*
+ * Work too long for a single call, split into bounded steps. Operations get stepped in
+ * turns by Operations::pump until its time budget is spent, the renderer pumps them at
+ * the start of every frame and on Renderer::pump_operations. Cancelling is cooperative:
+ * the handle only raises a flag, the next pump unwinds the operation in between two
+ * steps instead of stepping it, so there's never a half done step to undo.
+ */
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::events::RenderEvent;
+
+// Ids are unique across renderers, events of different ones can't be mixed up
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+pub enum OperationState {
+    Running,
+    Completed,
+    Cancelled,
+}
+
+impl OperationState {
+    pub fn is_terminal(self) -> bool {
+        self != OperationState::Running
+    }
+}
+
+///
+/// What an operation says after a step.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    // More to do, step it again within the same budget
+    Continue,
+    // Waiting on the GPU or on room, step it again on the next pump
+    Blocked,
+    Done,
+    // Gave up on its own, say because what it worked on went away. Gets unwound
+    Abandoned,
+}
+
+///
+/// Resumable state machine stepped by Operations::pump. A step does a bounded amount of
+/// work, like a mip, a stage or a chunk. Once cancelled or abandoned the operation gets
+/// unwound instead, releasing whatever its steps made so far. Neither gets called again
+/// after that or after it's done.
+///
+pub trait Operation<C>: Send {
+    fn name(&self) -> &str;
+
+    ///
+    /// Fraction of the work done, from 0 to 1.
+    ///
+    fn progress(&self) -> f32;
+
+    fn step(&mut self, ctx: &mut C) -> Step;
+
+    fn unwind(&mut self, ctx: &mut C);
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: OperationState,
+    progress: f32,
+    is_cancel_requested: bool,
+}
+
+///
+/// Follows an operation from any thread. Clones follow the same one.
+///
+#[derive(Clone, Debug)]
+pub struct OperationHandle {
+    id: u64,
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+}
+
+impl OperationHandle {
+    fn new(id: u64) -> Self {
+        let shared = Shared {
+            state: OperationState::Running,
+            progress: 0.0,
+            is_cancel_requested: false,
+        };
+        Self {
+            id,
+            shared: Arc::new((Mutex::new(shared), Condvar::new())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    ///
+    /// Progress as of the last pump, 1 once completed.
+    ///
+    pub fn progress(&self) -> f32 {
+        self.lock().progress
+    }
+
+    pub fn state(&self) -> OperationState {
+        self.lock().state
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state() == OperationState::Completed
+    }
+
+    ///
+    /// Asks for the operation to stop, it gets unwound on the next pump. Does nothing once
+    /// it's completed or cancelled.
+    ///
+    pub fn cancel(&self) {
+        let mut shared = self.lock();
+        if !shared.state.is_terminal() {
+            shared.is_cancel_requested = true;
+        }
+    }
+
+    ///
+    /// Blocks until the operation is completed or cancelled, or the timeout elapses, and
+    /// returns its state by then. Operations only advance when pumped, so waiting on the
+    /// thread pumping them just times out.
+    ///
+    pub fn wait(&self, timeout: Duration) -> OperationState {
+        let deadline = Instant::now() + timeout;
+        let mut shared = self.lock();
+        while !shared.state.is_terminal() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            shared = self
+                .shared
+                .1
+                .wait_timeout(shared, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        shared.state
+    }
+
+    fn is_cancel_requested(&self) -> bool {
+        self.lock().is_cancel_requested
+    }
+
+    fn set_progress(&self, progress: f32) {
+        self.lock().progress = progress;
+    }
+
+    fn end(&self, state: OperationState) {
+        let mut shared = self.lock();
+        shared.state = state;
+        if state == OperationState::Completed {
+            shared.progress = 1.0;
+        }
+        self.shared.1.notify_all();
+    }
+}
+
+struct Running<C> {
+    handle: OperationHandle,
+    operation: Box<dyn Operation<C>>,
+    // Last progress an event went out for
+    reported: f32,
+}
+
+///
+/// Operations being stepped, along with the events about them not taken yet. Started,
+/// completed and cancelled ones get an event each, running ones one per pump their
+/// progress moved in.
+///
+pub struct Operations<C> {
+    running: Vec<Running<C>>,
+    events: Vec<RenderEvent>,
+}
+
+impl<C> Default for Operations<C> {
+    fn default() -> Self {
+        Self {
+            running: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl<C> Operations<C> {
+    pub fn start(&mut self, operation: Box<dyn Operation<C>>) -> OperationHandle {
+        let handle = OperationHandle::new(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        self.events.push(RenderEvent::OperationStarted {
+            operation: handle.id,
+            name: operation.name().to_string(),
+        });
+        self.running.push(Running {
+            handle: handle.clone(),
+            operation,
+            reported: 0.0,
+        });
+        handle
+    }
+
+    ///
+    /// Moves the operations and events of the other ones after these.
+    ///
+    pub fn append(&mut self, mut other: Operations<C>) {
+        self.running.append(&mut other.running);
+        self.events.append(&mut other.events);
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    ///
+    /// Steps the operations in turns until every one is done or blocked, or the budget is
+    /// spent. At least one step gets taken whatever the budget, so a zero budget still
+    /// moves things along. Cancelled operations get unwound first, whatever the budget.
+    /// Operations started by the steps wait for the next pump.
+    ///
+    pub fn pump(&mut self, ctx: &mut C, budget: Duration) {
+        let started = Instant::now();
+        let mut is_idle = vec![false; self.running.len()];
+        let mut is_stepped = false;
+        while is_idle.iter().any(|e| !e) && (!is_stepped || started.elapsed() < budget) {
+            for (i, e) in self.running.iter_mut().enumerate() {
+                if is_idle[i] {
+                    continue;
+                }
+                is_stepped |= !e.handle.is_cancel_requested();
+                is_idle[i] = Self::step(e, ctx, &mut self.events);
+                if started.elapsed() >= budget {
+                    break;
+                }
+            }
+        }
+        self.end_pump();
+    }
+
+    ///
+    /// Same as pump, a single turn each however long the steps take, for deterministic
+    /// mode. Cancelled operations get unwound instead.
+    ///
+    pub fn pump_turn(&mut self, ctx: &mut C) {
+        for e in &mut self.running {
+            Self::step(e, ctx, &mut self.events);
+        }
+        self.end_pump();
+    }
+
+    /*
+     * Unwinds the operation if cancelled, steps it otherwise. Returns whether it's
+     * done or blocked.
+     */
+    fn step(e: &mut Running<C>, ctx: &mut C, events: &mut Vec<RenderEvent>) -> bool {
+        if e.handle.is_cancel_requested() {
+            Self::unwind(e, ctx, events);
+            return true;
+        }
+        match e.operation.step(ctx) {
+            Step::Continue => false,
+            Step::Blocked => true,
+            Step::Done => {
+                e.handle.end(OperationState::Completed);
+                events.push(RenderEvent::OperationCompleted {
+                    operation: e.handle.id,
+                });
+                true
+            }
+            Step::Abandoned => {
+                Self::unwind(e, ctx, events);
+                true
+            }
+        }
+    }
+
+    fn end_pump(&mut self) {
+        self.report_progress();
+        self.running.retain(|e| !e.handle.state().is_terminal());
+        // Whoever went first this time goes last the next, tight budgets reach them all
+        if !self.running.is_empty() {
+            self.running.rotate_left(1);
+        }
+    }
+
+    ///
+    /// Unwinds every operation still running, for when whatever they work on goes away.
+    ///
+    pub fn cancel_all(&mut self, ctx: &mut C) {
+        for mut e in self.running.drain(..) {
+            Self::unwind(&mut e, ctx, &mut self.events);
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<RenderEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn unwind(e: &mut Running<C>, ctx: &mut C, events: &mut Vec<RenderEvent>) {
+        e.operation.unwind(ctx);
+        e.handle.end(OperationState::Cancelled);
+        events.push(RenderEvent::OperationCancelled {
+            operation: e.handle.id,
+        });
+    }
+
+    fn report_progress(&mut self) {
+        for e in self
+            .running
+            .iter_mut()
+            .filter(|e| !e.handle.state().is_terminal())
+        {
+            let progress = e.operation.progress().clamp(0.0, 1.0);
+            e.handle.set_progress(progress);
+            if progress != e.reported {
+                e.reported = progress;
+                self.events.push(RenderEvent::OperationProgressed {
+                    operation: e.handle.id,
+                    progress,
+                });
+            }
+        }
+    }
+}
@@ -1,5 +1,10 @@
 use ash::vk;
 
+use super::{
+    file::{ExtraUsage, ViewFormat},
+    plan::Transition,
+};
+
 #[derive(Clone)]
 pub struct Attachment {
     pub name: String,
@@ -12,6 +17,19 @@ pub struct Attachment {
     pub extent: vk::Extent2D,
     pub descriptor_offset: usize,
     pub descriptor_index: u32,
+    pub is_memoryless: bool,
+    pub memory_flags: vk::MemoryPropertyFlags,
+    pub layers: u32,
+    // Only depth pyramids have more than the one, their view covers them all
+    pub mips: u32,
+    // Format and view writing raw values into an sRGB swapchain image
+    pub unorm_view: Option<(vk::Format, vk::ImageView)>,
+    // What the image was created for, see Pipeline::attachment_usages
+    pub usage: vk::ImageUsageFlags,
+    // Shared presentable swapchain image, see layout_for
+    pub is_shared_present: bool,
+    // Single layer views of targets allocated per split screen viewport, by viewport
+    pub viewport_views: Vec<vk::ImageView>,
 }
 
 impl Attachment {
@@ -22,6 +40,7 @@ impl Attachment {
         vk_format: vk::Format,
         image: vk::Image,
         image_view: vk::ImageView,
+        unorm_view: Option<(vk::Format, vk::ImageView)>,
         extent: vk::Extent2D,
     ) -> Attachment {
         Attachment {
@@ -34,6 +53,82 @@ impl Attachment {
             extent,
             descriptor_offset: 0,
             descriptor_index: 0,
+            is_memoryless: false,
+            memory_flags: vk::MemoryPropertyFlags::empty(),
+            layers: 1,
+            mips: 1,
+            unorm_view,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            is_shared_present: false,
+            viewport_views: Vec::new(),
+        }
+    }
+
+    ///
+    /// Panics telling what to add to the target of the attachment when its image wasn't
+    /// created with the usage, the purpose says what needed it.
+    ///
+    pub fn require_usage(&self, usage: ExtraUsage, purpose: &str) {
+        if !self.usage.contains(usage.to_vk()) {
+            panic!(
+                "attachment {} wasn't created with {:?} usage, add \"extraUsage\": [\"{}\"] to its target to {}!",
+                self.name,
+                usage.to_vk(),
+                usage,
+                purpose
+            );
+        }
+    }
+
+    ///
+    /// Format and view a pass renders through, the regular ones when there's no UNORM
+    /// view.
+    ///
+    pub fn view_for(&self, view_format: ViewFormat) -> (vk::Format, vk::ImageView) {
+        match (view_format, self.unorm_view) {
+            (ViewFormat::Unorm, Some(e)) => e,
+            _ => (self.vk_format, self.view),
+        }
+    }
+
+    ///
+    /// Layout the image is used in for what would go in the given one. Shared presentable
+    /// images stay in SHARED_PRESENT_KHR whatever they're used for.
+    ///
+    pub fn layout_for(&self, layout: vk::ImageLayout) -> vk::ImageLayout {
+        if self.is_shared_present {
+            vk::ImageLayout::SHARED_PRESENT_KHR
+        } else {
+            layout
+        }
+    }
+
+    ///
+    /// The barrier with its layouts through layout_for if it's one of this image.
+    /// Undefined old layouts stay, they still discard the contents.
+    ///
+    pub fn adapt_barrier(&self, mut barrier: vk::ImageMemoryBarrier2) -> vk::ImageMemoryBarrier2 {
+        if barrier.image != self.image {
+            return barrier;
+        }
+        if barrier.old_layout != vk::ImageLayout::UNDEFINED {
+            barrier.old_layout = self.layout_for(barrier.old_layout);
+        }
+        barrier.new_layout = self.layout_for(barrier.new_layout);
+        barrier
+    }
+
+    ///
+    /// Same attachment through the view of the layer of the split screen viewport, for
+    /// stages rendering into or sampling it. Others only have the one view.
+    ///
+    pub fn of_viewport(&self, viewport: usize) -> Attachment {
+        match self.viewport_views.get(viewport) {
+            Some(view) => Attachment {
+                view: *view,
+                ..self.clone()
+            },
+            None => self.clone(),
         }
     }
 
@@ -41,6 +136,19 @@ impl Attachment {
         self.name == Attachment::DEFAULT_NAME
     }
 
+    ///
+    /// Image destroyed by Pipeline::release_attachments, until the pipeline gets loaded
+    /// again.
+    ///
+    pub fn is_released(&self) -> bool {
+        self.image == vk::Image::null()
+    }
+
+    pub fn is_lazily_allocated(&self) -> bool {
+        self.memory_flags
+            .contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED)
+    }
+
     pub fn render_area_no_offset(&self) -> vk::Rect2D {
         vk::Rect2D {
             extent: self.extent,
@@ -49,26 +157,45 @@ impl Attachment {
     }
 
     pub fn default_attachment_write_barrier(image: vk::Image) -> vk::ImageMemoryBarrier2 {
+        Transition::DEFAULT_WRITE.to_barrier(image, Self::color_subresource_range())
+    }
+
+    ///
+    /// For passes writing to the default attachment after another one already did.
+    ///
+    pub fn default_attachment_rewrite_barrier(image: vk::Image) -> vk::ImageMemoryBarrier2 {
+        Transition::DEFAULT_REWRITE.to_barrier(image, Self::color_subresource_range())
+    }
+
+    pub fn default_attachment_present_barrier(image: vk::Image) -> vk::ImageMemoryBarrier2 {
+        Transition::DEFAULT_PRESENT.to_barrier(image, Self::color_subresource_range())
+    }
+
+    ///
+    /// Idle frames clear the swapchain image with a transfer instead of a pass.
+    ///
+    pub fn default_attachment_clear_barrier(image: vk::Image) -> vk::ImageMemoryBarrier2 {
         vk::ImageMemoryBarrier2::builder()
             .image(image)
-            .src_access_mask(vk::AccessFlags2::MEMORY_READ)
-            .dst_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .src_access_mask(vk::AccessFlags2::NONE)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
             .old_layout(vk::ImageLayout::UNDEFINED)
-            .new_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
-            .src_stage_mask(vk::PipelineStageFlags2::NONE)
-            .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            // Chains with the wait on the acquired image
+            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .dst_stage_mask(vk::PipelineStageFlags2::CLEAR)
             .subresource_range(Self::color_subresource_range())
             .build()
     }
 
-    pub fn default_attachment_present_barrier(image: vk::Image) -> vk::ImageMemoryBarrier2 {
+    pub fn default_attachment_cleared_present_barrier(image: vk::Image) -> vk::ImageMemoryBarrier2 {
         vk::ImageMemoryBarrier2::builder()
             .image(image)
-            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
             .dst_access_mask(vk::AccessFlags2::NONE)
-            .old_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
             .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
             .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE)
             .subresource_range(Self::color_subresource_range())
             .build()
@@ -86,6 +213,17 @@ impl Attachment {
         }
     }
 
+    ///
+    /// Covers all the layers and mips of the attachment, with the aspect of its format.
+    ///
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            layer_count: self.layers,
+            level_count: self.mips,
+            ..Self::default_subresource_range(self.format.aspect())
+        }
+    }
+
     pub fn color_subresource_range() -> vk::ImageSubresourceRange {
         Self::default_subresource_range(vk::ImageAspectFlags::COLOR)
     }
@@ -0,0 +1,567 @@
+/*
+ * What a pipeline file needs from the device, added up from the file alone so loading it
+ * can tell whether it fits before allocating anything. Image memory comes from format,
+ * extent and layers of the targets, descriptor bytes from the descriptor sizes of the
+ * device and frame region bytes from what the stages reserve for the expected number of
+ * tasks. All of them are estimates, drivers pad images and descriptor set layouts as
+ * they see fit.
+ *
+ * The image and sampler tables get as many slots as fit their share of the descriptor
+ * allocator with the descriptor sizes of the device, up to what they hold with smaller
+ * descriptors. Devices with large descriptors get fewer texture and sampler ids instead
+ * of running out of descriptor memory, down to a floor below which the pipeline doesn't
+ * fit.
+ */
+use ash::vk;
+use serde::Serialize;
+
+use super::{
+    depth_pyramid,
+    descriptor::DescriptorSizes,
+    file::{self, PassKind, PerDrawField, TransformInterpolation},
+    load::IMAGE_CAPACITY,
+    sampler::Sampler,
+};
+use crate::{
+    buffer::DeviceAllocator, config::RendererConfig, context::VulkanContext, format::Format,
+    motion::Affine, render_task::TaskKind, renderer::Renderer, shader_resource::TransformExtra,
+    stage_constants::StageConstants, UsedAsIndex,
+};
+
+///
+/// What the budget of a pipeline gets checked against, see BudgetLimits::of. Made up
+/// ones work just as well, for dry runs and tests.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetLimits {
+    // Descriptor sizes of VK_EXT_descriptor_buffer, ignored without a descriptor allocator
+    pub descriptor_sizes: DescriptorSizes,
+    // None when descriptors go into classic descriptor sets
+    pub descriptor_allocator: Option<AllocatorLimits>,
+    pub general_allocator: AllocatorLimits,
+    // Frame ring out of the general allocator the frame regions share, see FrameRing
+    pub frame_ring_bytes: u64,
+    // Largest device local heap
+    pub device_local_bytes: u64,
+    pub max_per_stage_descriptor_samplers: u32,
+    pub max_descriptor_set_samplers: u32,
+    // Frames whose regions are alive at once, the one being prepared included
+    pub frames_alive: u32,
+    pub expected_tasks_per_kind: [u32; TaskKind::MAX_LEN],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocatorLimits {
+    pub size: u64,
+    pub alignment: u64,
+}
+
+impl BudgetLimits {
+    ///
+    /// Limits of the device and sizes of the allocators the renderer made.
+    ///
+    pub fn of(
+        ctx: &VulkanContext,
+        general: &DeviceAllocator,
+        descriptor: Option<&DeviceAllocator>,
+        frame_ring_bytes: u64,
+        frames_alive: u32,
+        expected_tasks_per_kind: [u32; TaskKind::MAX_LEN],
+    ) -> Self {
+        let limits = unsafe {
+            ctx.instance
+                .get_physical_device_properties(ctx.physical_device)
+                .limits
+        };
+        let heaps =
+            &ctx.memory_properties.memory_heaps[..ctx.memory_properties.memory_heap_count as usize];
+        let device_local_bytes = heaps
+            .iter()
+            .filter(|e| e.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|e| e.size)
+            .max()
+            .unwrap_or(0);
+        let allocator_limits = |e: &DeviceAllocator| AllocatorLimits {
+            size: e.size(),
+            alignment: e.alignment(),
+        };
+        Self {
+            // Only queried with the extension enabled
+            descriptor_sizes: ctx.extension.descriptor_sizes.unwrap_or_default(),
+            descriptor_allocator: descriptor.map(allocator_limits),
+            general_allocator: allocator_limits(general),
+            frame_ring_bytes,
+            device_local_bytes,
+            max_per_stage_descriptor_samplers: limits.max_per_stage_descriptor_samplers,
+            max_descriptor_set_samplers: limits.max_descriptor_set_samplers,
+            frames_alive,
+            expected_tasks_per_kind,
+        }
+    }
+}
+
+///
+/// Made up limits of a desktop device with descriptor buffers and the allocators the
+/// renderer makes, what dry runs check against unless told otherwise. Descriptor sizes
+/// are on the large side of what drivers report.
+///
+impl Default for BudgetLimits {
+    fn default() -> Self {
+        Self {
+            descriptor_sizes: DescriptorSizes {
+                sampled_image: 64,
+                combined_image_sampler: 64,
+                sampler: 32,
+                uniform_buffer: 64,
+                storage_buffer: 64,
+            },
+            descriptor_allocator: Some(AllocatorLimits {
+                size: Renderer::DESCRIPTOR_ALLOCATOR_BYTES,
+                alignment: 256,
+            }),
+            general_allocator: AllocatorLimits {
+                size: Renderer::GENERAL_ALLOCATOR_BYTES,
+                alignment: 256,
+            },
+            frame_ring_bytes: RendererConfig::DEFAULT_FRAME_RING_BYTES,
+            device_local_bytes: 4 * 1024 * 1024 * 1024,
+            max_per_stage_descriptor_samplers: 1024 * 1024,
+            max_descriptor_set_samplers: 1024 * 1024,
+            frames_alive: Renderer::FRAMES_IN_FLIGHT,
+            expected_tasks_per_kind: [RendererConfig::DEFAULT_EXPECTED_TASKS_PER_KIND;
+                TaskKind::MAX_LEN],
+        }
+    }
+}
+
+///
+/// Bytes a loaded pipeline takes, see file::Pipeline::budget. Serializes to JSON along
+/// with the rest of what the renderer reports.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineBudget {
+    pub attachments: Vec<AttachmentBudget>,
+    pub stages: Vec<StageBudget>,
+    // Image and sampler tables every stage shares, both of them
+    pub table_descriptor_bytes: u64,
+    pub image_table_bytes: u64,
+    pub sampler_table_bytes: u64,
+    // Texture and sampler ids the tables have room for
+    pub image_capacity: u32,
+    pub sampler_capacity: u32,
+    pub descriptor_bytes: u64,
+    pub descriptor_allocator_bytes: Option<u64>,
+    // Regions of every frame alive at once
+    pub frame_region_bytes: u64,
+    pub frame_ring_bytes: u64,
+    pub general_allocator_bytes: u64,
+    pub attachment_bytes: u64,
+    // Attachments and both allocators
+    pub device_bytes: u64,
+    pub device_local_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentBudget {
+    pub name: String,
+    pub extent: [u32; 2],
+    pub layers: u32,
+    // 0 for memoryless ones
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageBudget {
+    pub name: String,
+    // Attachment descriptors of its inputs
+    pub descriptor_bytes: u64,
+    // Reserved each frame for the expected tasks of its kind
+    pub frame_bytes: u64,
+}
+
+impl std::fmt::Display for PipelineBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pipeline budget: {} bytes of attachments, {} of descriptors ({} for {} image ids, {} for {} sampler ids), {} of frame regions out of a ring of {};",
+            self.attachment_bytes,
+            self.descriptor_bytes,
+            self.image_table_bytes,
+            self.image_capacity,
+            self.sampler_table_bytes,
+            self.sampler_capacity,
+            self.frame_region_bytes,
+            self.frame_ring_bytes
+        )?;
+        for e in &self.attachments {
+            let [width, height] = e.extent;
+            write!(
+                f,
+                " attachment {} {}x{}x{} {};",
+                e.name, width, height, e.layers, e.bytes
+            )?;
+        }
+        for e in &self.stages {
+            write!(
+                f,
+                " stage {} descriptors {} frame {};",
+                e.name, e.descriptor_bytes, e.frame_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Totals past what there is, each with the attachment, stage or table taking the most.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Overage {
+    Descriptors {
+        bytes: u64,
+        available: u64,
+        largest: String,
+    },
+    FrameRegions {
+        bytes: u64,
+        available: u64,
+        largest: String,
+    },
+    DeviceMemory {
+        bytes: u64,
+        available: u64,
+        largest: String,
+    },
+}
+
+impl std::fmt::Display for Overage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (what, bytes, available, largest) = match self {
+            Overage::Descriptors {
+                bytes,
+                available,
+                largest,
+            } => ("descriptors", bytes, available, largest),
+            Overage::FrameRegions {
+                bytes,
+                available,
+                largest,
+            } => ("frame regions", bytes, available, largest),
+            Overage::DeviceMemory {
+                bytes,
+                available,
+                largest,
+            } => ("device memory", bytes, available, largest),
+        };
+        write!(
+            f,
+            "{} take {} bytes of {}, most of them {}",
+            what, bytes, available, largest
+        )
+    }
+}
+
+///
+/// Pipeline that doesn't fit the device, with every overage at once and the budget that
+/// didn't fit.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    pub overages: Vec<Overage>,
+    pub budget: Box<PipelineBudget>,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the pipeline doesn't fit the device:")?;
+        for e in &self.overages {
+            write!(f, " {};", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+fn aligned(bytes: u64, alignment: u64) -> u64 {
+    bytes.next_multiple_of(alignment.max(1))
+}
+
+// Largest part of the descriptor allocator each table may take, the stages share the rest
+const IMAGE_TABLE_SHARE: u64 = 2;
+const SAMPLER_TABLE_SHARE: u64 = 8;
+
+///
+/// Bytes the image and sampler tables can take at most of a descriptor allocator of the
+/// size, what the render core sets aside for them.
+///
+pub fn table_share_of(allocator_bytes: u64) -> u64 {
+    allocator_bytes / IMAGE_TABLE_SHARE + allocator_bytes / SAMPLER_TABLE_SHARE
+}
+
+// Ids the tables get at least, pipelines that can't fit them are over budget
+pub const MIN_IMAGE_CAPACITY: u32 = 256;
+pub const MIN_SAMPLER_CAPACITY: u32 = 16;
+
+///
+/// Slots of descriptor_size bytes a table of up to capacity slots gets out of bytes,
+/// with its size aligned to alignment. Never below min, nor above capacity. Descriptors
+/// of size 0, like ones in classic descriptor sets, take nothing.
+///
+pub fn table_capacity(
+    capacity: u32,
+    min: u32,
+    descriptor_size: u32,
+    bytes: u64,
+    alignment: u64,
+) -> u32 {
+    if descriptor_size == 0 {
+        return capacity;
+    }
+    let alignment = alignment.max(1);
+    let fitting = (bytes / alignment * alignment) / descriptor_size as u64;
+    fitting.min(capacity as u64).max(min.min(capacity) as u64) as u32
+}
+
+/*
+ * Bytes per texel the image of an attachment takes. Depth stencil formats get the
+ * stencil on top, formats texel_size doesn't know get the largest size there is.
+ */
+fn attachment_texel_size(format: Format) -> u64 {
+    match format {
+        Format::D32_SFLOAT_S8_UINT => 8,
+        _ => format.texel_size().unwrap_or(16) as u64,
+    }
+}
+
+fn largest_of<'a>(items: impl Iterator<Item = (&'a str, u64)>) -> String {
+    items
+        .max_by_key(|e| e.1)
+        .map_or_else(String::new, |e| e.0.to_string())
+}
+
+impl file::Pipeline {
+    ///
+    /// Bytes the enabled passes and the targets take with the default attachment at the
+    /// extent, or every way they don't fit the limits.
+    ///
+    pub fn budget(
+        &self,
+        extent: vk::Extent2D,
+        limits: &BudgetLimits,
+    ) -> Result<PipelineBudget, BudgetExceeded> {
+        let attachments: Vec<_> = self
+            .targets
+            .iter()
+            .map(|e| {
+                let target_extent =
+                    Self::extent_of(e.width, e.height, extent.width as f32, extent.height as f32);
+                let texels = if e.is_mip_chained {
+                    depth_pyramid::mip_extents(target_extent.width, target_extent.height)
+                        .iter()
+                        .map(|e| e[0] as u64 * e[1] as u64)
+                        .sum()
+                } else {
+                    target_extent.width as u64 * target_extent.height as u64
+                };
+                AttachmentBudget {
+                    name: e.name.clone(),
+                    extent: [target_extent.width, target_extent.height],
+                    layers: e.layers,
+                    bytes: if e.is_memoryless {
+                        0
+                    } else {
+                        texels * e.layers as u64 * attachment_texel_size(e.format)
+                    },
+                }
+            })
+            .collect();
+        let passes: Vec<_> = self.passes.iter().filter(|e| !e.is_disabled).collect();
+        let descriptor_alignment = limits.descriptor_allocator.map_or(1, |e| e.alignment);
+        let is_descriptor_buffer = limits.descriptor_allocator.is_some();
+        let general_alignment = limits.general_allocator.alignment;
+        let stages: Vec<_> = passes
+            .iter()
+            .map(|pass| {
+                let descriptor_bytes = if is_descriptor_buffer && !pass.inputs.is_empty() {
+                    let size = limits.descriptor_sizes.combined_image_sampler as u64;
+                    // One table per layer of the targets allocated per viewport it uses
+                    let tables = Self::per_viewport_target_of(pass, &self.targets)
+                        .map_or(1, |e| e.layers as u64);
+                    tables * aligned(pass.inputs.len() as u64 * size, descriptor_alignment)
+                } else {
+                    0
+                };
+                let frame_bytes = match (pass.kind, pass.batch) {
+                    (PassKind::Blit, _) | (_, None) => 0,
+                    (_, Some(kind)) => {
+                        let per_pass: usize = pass
+                            .per_pass_updaters
+                            .iter()
+                            .map(|e| e.to_resource_kind().resource_size())
+                            .sum();
+                        let constants = StageConstants::of_pass(pass);
+                        let constants_size = if constants.is_empty() {
+                            0
+                        } else {
+                            constants.size()
+                        };
+                        // Each task reserves its own, a single instance each
+                        let mut per_task: u64 = pass
+                            .per_instance_updaters
+                            .iter()
+                            .map(|e| {
+                                let size = e.to_resource_kind().resource_size() as u64;
+                                aligned(size, general_alignment)
+                            })
+                            .sum();
+                        if pass
+                            .per_draw_fields
+                            .contains(&PerDrawField::PreviousTransform)
+                        {
+                            let size = std::mem::size_of::<TransformExtra>() as u64;
+                            per_task += aligned(size, general_alignment);
+                        }
+                        if pass.transform_interpolation == TransformInterpolation::Cpu {
+                            let size = std::mem::size_of::<Affine>() as u64;
+                            per_task += aligned(size, general_alignment);
+                        }
+                        let tasks = limits.expected_tasks_per_kind[kind.to_usize()] as u64;
+                        let mut bytes = per_task * tasks;
+                        if per_pass > 0 {
+                            bytes += aligned(per_pass as u64, general_alignment);
+                        }
+                        if constants_size > 0 {
+                            bytes += aligned(constants_size as u64, general_alignment);
+                        }
+                        bytes
+                    }
+                };
+                StageBudget {
+                    name: pass.name.clone(),
+                    descriptor_bytes,
+                    frame_bytes,
+                }
+            })
+            .collect();
+        let max_inputs = passes.iter().map(|e| e.inputs.len() as u32).max();
+        let max_sampler_capacity = Sampler::capacity_within(
+            limits.max_per_stage_descriptor_samplers,
+            limits.max_descriptor_set_samplers,
+            max_inputs.unwrap_or(0),
+        );
+        let sizes = &limits.descriptor_sizes;
+        let (image_capacity, sampler_capacity, image_table_bytes, sampler_table_bytes) =
+            match limits.descriptor_allocator {
+                Some(allocator) => {
+                    let image_capacity = table_capacity(
+                        IMAGE_CAPACITY,
+                        MIN_IMAGE_CAPACITY,
+                        sizes.sampled_image,
+                        allocator.size / IMAGE_TABLE_SHARE,
+                        descriptor_alignment,
+                    );
+                    let sampler_capacity = table_capacity(
+                        max_sampler_capacity,
+                        MIN_SAMPLER_CAPACITY,
+                        sizes.sampler,
+                        allocator.size / SAMPLER_TABLE_SHARE,
+                        descriptor_alignment,
+                    );
+                    (
+                        image_capacity,
+                        sampler_capacity,
+                        aligned(
+                            image_capacity as u64 * sizes.sampled_image as u64,
+                            descriptor_alignment,
+                        ),
+                        aligned(
+                            sampler_capacity as u64 * sizes.sampler as u64,
+                            descriptor_alignment,
+                        ),
+                    )
+                }
+                None => (IMAGE_CAPACITY, max_sampler_capacity, 0, 0),
+            };
+        let table_descriptor_bytes = image_table_bytes + sampler_table_bytes;
+        let descriptor_bytes =
+            table_descriptor_bytes + stages.iter().map(|e| e.descriptor_bytes).sum::<u64>();
+        let frame_region_bytes =
+            stages.iter().map(|e| e.frame_bytes).sum::<u64>() * limits.frames_alive as u64;
+        let attachment_bytes = attachments.iter().map(|e| e.bytes).sum::<u64>();
+        let descriptor_allocator_bytes = limits.descriptor_allocator.map(|e| e.size);
+        let device_bytes = attachment_bytes
+            + limits.general_allocator.size
+            + descriptor_allocator_bytes.unwrap_or(0);
+        let budget = PipelineBudget {
+            attachments,
+            stages,
+            table_descriptor_bytes,
+            image_table_bytes,
+            sampler_table_bytes,
+            image_capacity,
+            sampler_capacity,
+            descriptor_bytes,
+            descriptor_allocator_bytes,
+            frame_region_bytes,
+            frame_ring_bytes: limits.frame_ring_bytes,
+            general_allocator_bytes: limits.general_allocator.size,
+            attachment_bytes,
+            device_bytes,
+            device_local_bytes: limits.device_local_bytes,
+        };
+
+        let mut overages = Vec::new();
+        match descriptor_allocator_bytes {
+            Some(available) if descriptor_bytes > available => {
+                let tables = [
+                    ("the image table", image_table_bytes),
+                    ("the sampler table", sampler_table_bytes),
+                ];
+                let stages = budget
+                    .stages
+                    .iter()
+                    .map(|e| (e.name.as_str(), e.descriptor_bytes));
+                overages.push(Overage::Descriptors {
+                    bytes: descriptor_bytes,
+                    available,
+                    largest: largest_of(tables.into_iter().chain(stages)),
+                });
+            }
+            _ => {}
+        }
+        if frame_region_bytes > budget.frame_ring_bytes {
+            let stages = budget
+                .stages
+                .iter()
+                .map(|e| (e.name.as_str(), e.frame_bytes));
+            overages.push(Overage::FrameRegions {
+                bytes: frame_region_bytes,
+                available: budget.frame_ring_bytes,
+                largest: largest_of(stages),
+            });
+        }
+        if device_bytes > budget.device_local_bytes {
+            let attachments = budget
+                .attachments
+                .iter()
+                .map(|e| (e.name.as_str(), e.bytes));
+            overages.push(Overage::DeviceMemory {
+                bytes: device_bytes,
+                available: budget.device_local_bytes,
+                largest: largest_of(attachments),
+            });
+        }
+        if overages.is_empty() {
+            Ok(budget)
+        } else {
+            Err(BudgetExceeded {
+                overages,
+                budget: Box::new(budget),
+            })
+        }
+    }
+}
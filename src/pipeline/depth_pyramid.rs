@@ -0,0 +1,338 @@
+use ash::vk;
+
+use super::{attachment::Attachment, file::Reduction};
+use crate::context::VulkanContext;
+
+/*
+ * Hierarchical depth, built by a chain of compute dispatches. The first one copies the
+ * depth attachment into mip 0, every later one reduces the mip above into the next. The
+ * whole target sits in the general layout meanwhile, each mip gets a barrier between
+ * the dispatch writing it and the one reading it.
+ */
+
+pub const SHADER_NAME: &str = "depth_pyramid.comp";
+// Invocations along each side of a workgroup, local_size of the shader
+const GROUP_SIZE: u32 = 8;
+
+///
+/// Extents of every mip of a pyramid down to 1x1, the first one the extent given. Odd
+/// sizes round down, the last texel of a row or column covers the three left over.
+///
+pub fn mip_extents(width: u32, height: u32) -> Vec<[u32; 2]> {
+    assert!(width > 0 && height > 0, "pyramid of an empty extent!");
+    let mut extents = vec![[width, height]];
+    while let Some(&[width, height]) = extents.last().filter(|e| e[0] > 1 || e[1] > 1) {
+        extents.push([(width / 2).max(1), (height / 2).max(1)]);
+    }
+    extents
+}
+
+pub struct DepthPyramid {
+    pub reduction: Reduction,
+    pub extents: Vec<[u32; 2]>,
+    image: vk::Image,
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    // One per mip, reading the one above or the depth
+    sets: Vec<vk::DescriptorSet>,
+    sampler: vk::Sampler,
+    // Depth aspect alone, the view of the attachment may have stencil too
+    depth_view: vk::ImageView,
+    mip_views: Vec<vk::ImageView>,
+}
+
+impl DepthPyramid {
+    pub fn new(
+        ctx: &VulkanContext,
+        name: &str,
+        source: &Attachment,
+        destination: &Attachment,
+        reduction: Reduction,
+        shader_path: &str,
+    ) -> Self {
+        let device = &ctx.device;
+        let extents = mip_extents(destination.extent.width, destination.extent.height);
+        assert_eq!(extents.len() as u32, destination.mips, "mips of {}", name);
+        let mut file = std::fs::File::open(shader_path)
+            .unwrap_or_else(|e| panic!("failed opening {}: {}", shader_path, e));
+        let code = ash::util::read_spv(&mut file).expect("failed to load depth pyramid shader");
+        let module_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+        let module = unsafe { device.create_shader_module(&module_info, None) }
+            .expect("depth pyramid shader module error");
+        // Only ever fetched from, the filtering doesn't matter
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None) }.unwrap();
+        let samplers = [sampler];
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .immutable_samplers(&samplers)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let set_layout =
+            unsafe { device.create_descriptor_set_layout(&set_layout_info, None) }.unwrap();
+        let set_layouts = [set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<[u32; 6]>() as u32,
+        }];
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None) }.unwrap();
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .module(module)
+                    .name(c"main")
+                    .build(),
+            )
+            .layout(layout)
+            .build();
+        let pipeline = unsafe {
+            device.create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+        }
+        .map_err(|e| e.1)
+        .expect("failed creating the depth pyramid pipeline")[0];
+        unsafe { device.destroy_shader_module(module, None) };
+
+        let view_of = |image, format, aspect_mask, base_mip_level| {
+            let info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            unsafe { device.create_image_view(&info, None) }.unwrap()
+        };
+        let depth_view = view_of(
+            source.image,
+            source.vk_format,
+            vk::ImageAspectFlags::DEPTH,
+            0,
+        );
+        let mip_views: Vec<_> = (0..destination.mips)
+            .map(|mip| {
+                view_of(
+                    destination.image,
+                    destination.vk_format,
+                    vk::ImageAspectFlags::COLOR,
+                    mip,
+                )
+            })
+            .collect();
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: destination.mips,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: destination.mips,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(destination.mips)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { device.create_descriptor_pool(&pool_info, None) }.unwrap();
+        let set_layouts = vec![set_layout; destination.mips as usize];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let sets = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+            .expect("couldn't allocate the depth pyramid descriptor sets!");
+        let reads: Vec<_> = (0..mip_views.len())
+            .map(|mip| match mip {
+                0 => [vk::DescriptorImageInfo {
+                    image_view: depth_view,
+                    image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                    ..Default::default()
+                }],
+                _ => [vk::DescriptorImageInfo {
+                    image_view: mip_views[mip - 1],
+                    image_layout: vk::ImageLayout::GENERAL,
+                    ..Default::default()
+                }],
+            })
+            .collect();
+        let writes: Vec<_> = mip_views
+            .iter()
+            .map(|view| {
+                [vk::DescriptorImageInfo {
+                    image_view: *view,
+                    image_layout: vk::ImageLayout::GENERAL,
+                    ..Default::default()
+                }]
+            })
+            .collect();
+        let descriptor_writes: Vec<_> = sets
+            .iter()
+            .enumerate()
+            .flat_map(|(mip, set)| {
+                [
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(*set)
+                        .dst_binding(0)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(&reads[mip])
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(*set)
+                        .dst_binding(1)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(&writes[mip])
+                        .build(),
+                ]
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        ctx.try_set_debug_name(&format!("{}_pipeline", name), pipeline);
+        ctx.try_set_debug_name(&format!("{}_depth_view", name), depth_view);
+        for (mip, view) in mip_views.iter().enumerate() {
+            ctx.try_set_debug_name(&format!("{}_mip_{}_view", name, mip), *view);
+        }
+        Self {
+            reduction,
+            extents,
+            image: destination.image,
+            pipeline,
+            layout,
+            set_layout,
+            pool,
+            sets,
+            sampler,
+            depth_view,
+            mip_views,
+        }
+    }
+
+    ///
+    /// Records the dispatches of every mip, the depth has to be in the read only layout
+    /// and the pyramid in the general one.
+    ///
+    pub fn record(&self, ctx: &VulkanContext, command_buffer: vk::CommandBuffer) {
+        let device = &ctx.device;
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+        }
+        for (mip, extent) in self.extents.iter().enumerate() {
+            // Mip 0 copies the depth, its extent is the same
+            let source = if mip == 0 {
+                *extent
+            } else {
+                self.extents[mip - 1]
+            };
+            if mip > 0 {
+                let barrier = vk::ImageMemoryBarrier2::builder()
+                    .image(self.image)
+                    .old_layout(vk::ImageLayout::GENERAL)
+                    .new_layout(vk::ImageLayout::GENERAL)
+                    .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        base_mip_level: mip as u32 - 1,
+                        ..Attachment::color_subresource_range()
+                    })
+                    .build();
+                unsafe {
+                    device.cmd_pipeline_barrier2(
+                        command_buffer,
+                        &vk::DependencyInfo::builder().image_memory_barriers(&[barrier]),
+                    );
+                }
+            }
+            let registers = [
+                source[0],
+                source[1],
+                extent[0],
+                extent[1],
+                (self.reduction == Reduction::Min) as u32,
+                (mip == 0) as u32,
+            ];
+            unsafe {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.layout,
+                    0,
+                    &[self.sets[mip]],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    registers.align_to::<u8>().1,
+                );
+                device.cmd_dispatch(
+                    command_buffer,
+                    extent[0].div_ceil(GROUP_SIZE),
+                    extent[1].div_ceil(GROUP_SIZE),
+                    1,
+                );
+            }
+        }
+    }
+
+    ///
+    /// Destroys the views of the depth and pyramid images ahead of the images, destroy
+    /// leaves them alone after.
+    ///
+    pub fn release_views(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.depth_view, None);
+            for view in self.mip_views.drain(..) {
+                device.destroy_image_view(view, None);
+            }
+        }
+        self.depth_view = vk::ImageView::null();
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.depth_view, None);
+            for view in &self.mip_views {
+                device.destroy_image_view(*view, None);
+            }
+        }
+    }
+}
@@ -4,6 +4,276 @@ use bitvec::vec::BitVec;
 
 use crate::context::VulkanContext;
 
+// Sets the tables get bound to, same as DESC_SET_* in the shaders
+pub const SET_SAMPLERS: u32 = 0;
+pub const SET_IMAGES: u32 = 1;
+pub const SET_ATTACHMENTS: u32 = 2;
+
+///
+/// Where the slots of a table are in its set layout. Arrays have every slot in binding
+/// 0, otherwise each slot is the binding with its number and only the listed ones exist.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SlotLayout {
+    Array(u32),
+    Bindings(Vec<u32>),
+}
+
+impl SlotLayout {
+    ///
+    /// Slots the table has room for, gaps between bindings included.
+    ///
+    pub fn count(&self) -> u32 {
+        match self {
+            Self::Array(count) => *count,
+            Self::Bindings(bindings) => bindings.iter().max().map_or(0, |e| e + 1),
+        }
+    }
+
+    pub fn has_slot(&self, slot: u32) -> bool {
+        match self {
+            Self::Array(count) => slot < *count,
+            Self::Bindings(bindings) => bindings.contains(&slot),
+        }
+    }
+
+    ///
+    /// Binding and array element of the slot.
+    ///
+    pub fn binding_of(&self, slot: u32) -> (u32, u32) {
+        match self {
+            Self::Array(_) => (0, slot),
+            Self::Bindings(_) => (slot, 0),
+        }
+    }
+
+    pub fn layout_bindings(
+        &self,
+        descriptor_type: vk::DescriptorType,
+    ) -> Vec<vk::DescriptorSetLayoutBinding> {
+        let binding_of = |binding: u32, count: u32| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(count)
+                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                .build()
+        };
+        match self {
+            Self::Array(count) => vec![binding_of(0, *count)],
+            Self::Bindings(bindings) => bindings.iter().map(|e| binding_of(*e, 1)).collect(),
+        }
+    }
+
+    ///
+    /// Occupancy of a new table, the gaps between bindings count as taken so they never
+    /// get handed out.
+    ///
+    pub fn occupancy(&self) -> BitVec {
+        (0..self.count()).map(|e| !self.has_slot(e)).collect()
+    }
+}
+
+///
+/// Table of descriptors the stages bind, either in a descriptor buffer or in a classic
+/// descriptor set. Placing only touches host memory until flushed.
+///
+pub trait DescriptorBackend: Send {
+    fn layout(&self) -> vk::DescriptorSetLayout;
+
+    fn capacity(&self) -> u32;
+
+    ///
+    /// First free slot, None once all are taken.
+    ///
+    fn next_free(&self) -> Option<usize>;
+
+    fn is_free(&self, index: u32) -> bool;
+
+    fn remove_at(&mut self, index: u32);
+
+    fn place_sampler_at(
+        &mut self,
+        ctx: &VulkanContext,
+        index: u32,
+        desc: vk::Sampler,
+    ) -> (usize, u32);
+
+    fn place_image_at(
+        &mut self,
+        ctx: &VulkanContext,
+        index: u32,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32);
+
+    fn place_image_sampler_at(
+        &mut self,
+        ctx: &VulkanContext,
+        index: u32,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32);
+
+    fn place_image_sampler(
+        &mut self,
+        ctx: &VulkanContext,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32) {
+        let index = self.next_free().expect("ran out of descriptor slots!") as u32;
+        self.place_image_sampler_at(ctx, index, desc)
+    }
+
+    ///
+    /// Points the slot at the image and frees it, so free slots never refer to destroyed
+    /// or never written images.
+    ///
+    fn reset_image_at(&mut self, ctx: &VulkanContext, index: u32, desc: vk::DescriptorImageInfo) {
+        self.place_image_at(ctx, index, desc);
+        self.remove_at(index);
+    }
+
+    fn flush(&mut self, ctx: &VulkanContext);
+
+    fn flush_single(&mut self, ctx: &VulkanContext, index: u32);
+
+    ///
+    /// Bytes flushed since the last call, the descriptors copied into the buffer or the
+    /// image infos handed to vkUpdateDescriptorSets.
+    ///
+    #[cfg(feature = "bench-metrics")]
+    fn take_flushed_bytes(&mut self) -> u64;
+
+    fn binding(&self) -> DescriptorBinding;
+
+    fn destroy(&self, device: &ash::Device);
+
+    ///
+    /// Gives the descriptor memory back to the allocator it came from, for backends
+    /// destroyed while the allocator lives on. Sets go with their pool on destroy.
+    ///
+    fn free(&self, _mem: &DeviceAllocator) {}
+}
+
+#[derive(Clone, Copy)]
+pub enum DescriptorBinding {
+    Buffer(vk::DescriptorBufferBindingInfoEXT),
+    Set(vk::DescriptorSet),
+}
+
+/*
+ * SAFETY: Binding infos are built without any p_next chain, the only raw pointer
+ * they hold is null.
+ */
+unsafe impl Send for DescriptorBinding {}
+
+impl DescriptorBinding {
+    ///
+    /// Device address of the buffer or handle of the set, the same for the same table.
+    ///
+    pub fn to_raw(&self) -> u64 {
+        match self {
+            Self::Buffer(e) => e.address,
+            Self::Set(e) => vk::Handle::as_raw(*e),
+        }
+    }
+}
+
+///
+/// Binds the tables to the sets starting at 0, in order. They all have to come from
+/// the same kind of backend.
+///
+pub fn bind(
+    ctx: &VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    bindings: &[DescriptorBinding],
+) {
+    let mut buffers = Vec::with_capacity(bindings.len());
+    let mut sets = Vec::with_capacity(bindings.len());
+    for binding in bindings {
+        match binding {
+            DescriptorBinding::Buffer(e) => buffers.push(*e),
+            DescriptorBinding::Set(e) => sets.push(*e),
+        }
+    }
+    assert!(
+        buffers.is_empty() || sets.is_empty(),
+        "can't mix descriptor buffers and descriptor sets!"
+    );
+    unsafe {
+        if !buffers.is_empty() {
+            let ext = descriptor_buffer_ext(ctx);
+            let indices: Vec<u32> = (0..buffers.len() as u32).collect();
+            let offsets = vec![0; buffers.len()];
+            ext.cmd_bind_descriptor_buffers(command_buffer, &buffers);
+            ext.cmd_set_descriptor_buffer_offsets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &indices,
+                &offsets,
+            );
+        } else if !sets.is_empty() {
+            ctx.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &sets,
+                &[],
+            );
+        }
+    }
+}
+
+fn descriptor_buffer_ext(ctx: &VulkanContext) -> &ash::extensions::ext::DescriptorBuffer {
+    ctx.extension
+        .descriptor_buffer
+        .as_ref()
+        .expect("descriptor_buffer extension isn't enabled!")
+}
+
+///
+/// Bytes a descriptor of each type takes in a descriptor buffer, as the device reports
+/// them. Queried once with the device, see ExtensionContext::descriptor_sizes.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DescriptorSizes {
+    pub sampled_image: u32,
+    pub combined_image_sampler: u32,
+    pub sampler: u32,
+    pub uniform_buffer: u32,
+    pub storage_buffer: u32,
+}
+
+impl DescriptorSizes {
+    pub fn of(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut props = vk::PhysicalDeviceDescriptorBufferPropertiesEXT::default();
+        let mut device_props = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut props)
+            .build();
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut device_props) };
+        Self {
+            sampled_image: props.sampled_image_descriptor_size as u32,
+            combined_image_sampler: props.combined_image_sampler_descriptor_size as u32,
+            sampler: props.sampler_descriptor_size as u32,
+            uniform_buffer: props.uniform_buffer_descriptor_size as u32,
+            storage_buffer: props.storage_buffer_descriptor_size as u32,
+        }
+    }
+
+    pub fn size_of(&self, descriptor_type: vk::DescriptorType) -> u32 {
+        match descriptor_type {
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER => self.combined_image_sampler,
+            vk::DescriptorType::SAMPLED_IMAGE => self.sampled_image,
+            vk::DescriptorType::UNIFORM_BUFFER => self.uniform_buffer,
+            vk::DescriptorType::STORAGE_BUFFER => self.storage_buffer,
+            vk::DescriptorType::SAMPLER => self.sampler,
+            _ => panic!("unsupported descriptor type {:?}", descriptor_type),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct DescriptorBuffer {
     pub name: String,
@@ -14,8 +284,12 @@ pub struct DescriptorBuffer {
     pub count: u32,
     pub subsets: u32,
     subset_size: u32,
+    // Where each slot is within a subset, as the layout says
+    slot_offsets: Vec<usize>,
     occupancy: BitVec,
     host: Box<[u8]>,
+    #[cfg(feature = "bench-metrics")]
+    flushed_bytes: u64,
 }
 
 fn next_mul_u32(v: u32, mul: u32) -> u32 {
@@ -32,10 +306,10 @@ impl DescriptorBuffer {
         mem: &mut DeviceAllocator,
         name: String,
         descriptor_type: vk::DescriptorType,
-        count: u32,
+        slots: &SlotLayout,
         subsets: u32,
-        is_array: bool,
     ) -> Self {
+        let count = slots.count();
         assert!(count > 0, "cant have zero sized descriptor buffers!");
         assert!(
             BufferKind::Descriptor == mem.buffer.kind,
@@ -43,28 +317,13 @@ impl DescriptorBuffer {
             mem.buffer.kind,
             BufferKind::Descriptor
         );
-        let descriptor_size = Self::size_of(descriptor_type, &ctx.instance, &ctx.physical_device);
+        let descriptor_size = ctx
+            .extension
+            .descriptor_sizes
+            .expect("descriptor_buffer extension isn't enabled!")
+            .size_of(descriptor_type) as usize;
         let subsets = subsets.max(1);
-        let bindings: Vec<_> = if is_array {
-            vec![vk::DescriptorSetLayoutBinding::builder()
-                .binding(0)
-                .descriptor_type(descriptor_type)
-                .descriptor_count(count)
-                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
-                .build()]
-        } else {
-            (0..count)
-                .into_iter()
-                .map(|e| {
-                    vk::DescriptorSetLayoutBinding::builder()
-                        .binding(e)
-                        .descriptor_type(descriptor_type)
-                        .descriptor_count(1)
-                        .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
-                        .build()
-                })
-                .collect()
-        };
+        let bindings = slots.layout_bindings(descriptor_type);
         let info = vk::DescriptorSetLayoutCreateInfo::builder()
             .bindings(&bindings)
             .flags(vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT)
@@ -76,6 +335,17 @@ impl DescriptorBuffer {
          * descriptor buffer.
          */
         let subset_size = next_mul_u64(Self::layout_size_of(ctx, layout), mem.alignment()) as u32;
+        // Gaps get no offset, nothing is ever placed there
+        let slot_offsets = (0..count)
+            .map(|slot| {
+                if !slots.has_slot(slot) {
+                    return 0;
+                }
+                let (binding, element) = slots.binding_of(slot);
+                let offset = Self::binding_offset_of(ctx, layout, binding) as usize;
+                offset + element as usize * descriptor_size
+            })
+            .collect();
         let buffer_size = subset_size as u64 * subsets as u64;
         let host = vec![0u8; subset_size as usize].into_boxed_slice();
         let device = if let Some(buffer) = mem.alloc(buffer_size) {
@@ -90,51 +360,37 @@ impl DescriptorBuffer {
         // Clear descriptor memory initially *just in case*. Should be a pretty small write.
         unsafe { std::ptr::write_bytes(device.addr as *mut u8, 0, device.size as usize) };
         // Every descriptor is initially unoccupied
-        let occupancy = BitVec::repeat(false, count as usize);
+        let occupancy = slots.occupancy();
         ctx.try_set_debug_name(&name, layout);
         Self {
             name,
             layout,
             device,
             subset_size,
+            slot_offsets,
             host,
             descriptor_type,
             descriptor_size,
             occupancy,
             count,
             subsets,
+            #[cfg(feature = "bench-metrics")]
+            flushed_bytes: 0,
         }
     }
 
     fn layout_size_of(vulkan_context: &VulkanContext, layout: vk::DescriptorSetLayout) -> u64 {
-        unsafe {
-            vulkan_context
-                .extension
-                .descriptor_buffer
-                .get_descriptor_set_layout_size(layout)
-        }
+        unsafe { descriptor_buffer_ext(vulkan_context).get_descriptor_set_layout_size(layout) }
     }
 
-    fn size_of(
-        descriptor_type: vk::DescriptorType,
-        instance: &ash::Instance,
-        physical_device: &vk::PhysicalDevice,
-    ) -> usize {
-        let mut props = vk::PhysicalDeviceDescriptorBufferPropertiesEXT {
-            ..Default::default()
-        };
-        let mut device_props = vk::PhysicalDeviceProperties2::builder()
-            .push_next(&mut props)
-            .build();
-        unsafe { instance.get_physical_device_properties2(*physical_device, &mut device_props) };
-        match descriptor_type {
-            vk::DescriptorType::COMBINED_IMAGE_SAMPLER => {
-                props.combined_image_sampler_descriptor_size
-            }
-            vk::DescriptorType::SAMPLED_IMAGE => props.sampled_image_descriptor_size,
-            vk::DescriptorType::UNIFORM_BUFFER => props.uniform_buffer_descriptor_size,
-            vk::DescriptorType::SAMPLER => props.sampler_descriptor_size,
-            _ => panic!("unsupported descriptor type {:?}", descriptor_type),
+    fn binding_offset_of(
+        vulkan_context: &VulkanContext,
+        layout: vk::DescriptorSetLayout,
+        binding: u32,
+    ) -> u64 {
+        unsafe {
+            descriptor_buffer_ext(vulkan_context)
+                .get_descriptor_set_layout_binding_offset(layout, binding)
         }
     }
 
@@ -152,20 +408,35 @@ impl DescriptorBuffer {
     pub fn offsets(&self) -> Vec<u64> {
         self.occupancy
             .iter_ones()
-            .map(|i| self.device.device_addr + (self.descriptor_size * i) as u64)
+            .map(|i| self.device.device_addr + self.slot_offsets[i] as u64)
             .collect()
     }
 
     pub fn place(&mut self, subset: u32, data: &[u8]) -> (usize, u32) {
-        self.place_at(self.next_free() as u32, subset, data)
+        self.place_at(self.next_free_or_fail(), subset, data)
+    }
+
+    pub fn next_free(&self) -> Option<usize> {
+        self.occupancy.first_zero()
+    }
+
+    fn next_free_or_fail(&self) -> u32 {
+        self.next_free().expect("ran out of descriptor slots!") as u32
     }
 
-    pub fn next_free(&self) -> usize {
-        self.occupancy.first_zero().unwrap()
+    ///
+    /// Whether the slot at index exists and nothing is placed there, subset 0 only.
+    ///
+    pub fn is_free(&self, index: u32) -> bool {
+        index < self.count && !self.occupancy[index as usize]
     }
 
     pub fn offset_at(&self, index: u32, subset: u32) -> usize {
-        (subset as usize * self.subset_size as usize) + (index as usize * self.descriptor_size)
+        self.subset_offset(subset) + self.slot_offsets[index as usize]
+    }
+
+    fn subset_offset(&self, subset: u32) -> usize {
+        subset as usize * self.subset_size as usize
     }
 
     pub fn remove_at(&mut self, index: u32) {
@@ -178,7 +449,7 @@ impl DescriptorBuffer {
         desc: vk::Sampler,
         desc_buffer_instance: &ash::extensions::ext::DescriptorBuffer,
     ) -> (usize, u32) {
-        self.place_sampler_at(self.next_free() as u32, subset, desc, desc_buffer_instance)
+        self.place_sampler_at(self.next_free_or_fail(), subset, desc, desc_buffer_instance)
     }
 
     pub fn place_sampler_at(
@@ -204,7 +475,7 @@ impl DescriptorBuffer {
         desc: vk::DescriptorImageInfo,
         desc_buffer_instance: &ash::extensions::ext::DescriptorBuffer,
     ) -> (usize, u32) {
-        self.place_image_at(self.next_free() as u32, subset, desc, desc_buffer_instance)
+        self.place_image_at(self.next_free_or_fail(), subset, desc, desc_buffer_instance)
     }
 
     pub fn place_image_at(
@@ -230,7 +501,7 @@ impl DescriptorBuffer {
         desc: vk::DescriptorImageInfo,
         desc_buffer_instance: &ash::extensions::ext::DescriptorBuffer,
     ) -> (usize, u32) {
-        self.place_image_sampler_at(self.next_free() as u32, subset, desc, desc_buffer_instance)
+        self.place_image_sampler_at(self.next_free_or_fail(), subset, desc, desc_buffer_instance)
     }
 
     pub fn place_image_sampler_at(
@@ -258,7 +529,7 @@ impl DescriptorBuffer {
         desc: vk::DescriptorAddressInfoEXT,
         desc_buffer_instance: &ash::extensions::ext::DescriptorBuffer,
     ) -> (usize, u32) {
-        self.place_ubo_at(self.next_free() as u32, subset, desc, desc_buffer_instance)
+        self.place_ubo_at(self.next_free_or_fail(), subset, desc, desc_buffer_instance)
     }
 
     pub fn place_ubo_at(
@@ -334,13 +605,17 @@ impl DescriptorBuffer {
             subset,
             self.subset_size
         );
-        let offset = self.offset_at(0, subset);
+        let offset = self.subset_offset(subset);
         unsafe {
             let src = self.host.as_ptr();
             let dst = self.device.addr.add(offset) as *mut u8;
             let len = self.host.len();
             std::ptr::copy_nonoverlapping(src, dst, len)
         };
+        #[cfg(feature = "bench-metrics")]
+        {
+            self.flushed_bytes += self.host.len() as u64;
+        }
     }
 
     pub fn into_device_single(&mut self, index: u32) {
@@ -368,6 +643,10 @@ impl DescriptorBuffer {
             let len = self.descriptor_size;
             std::ptr::copy_nonoverlapping(src, dst, len)
         };
+        #[cfg(feature = "bench-metrics")]
+        {
+            self.flushed_bytes += self.descriptor_size as u64;
+        }
     }
 
     pub fn binding_info(&self) -> vk::DescriptorBufferBindingInfoEXT {
@@ -375,7 +654,7 @@ impl DescriptorBuffer {
     }
 
     pub fn binding_info_at(&self, subset: u32) -> vk::DescriptorBufferBindingInfoEXT {
-        let offset = self.offset_at(0, subset) as u64;
+        let offset = self.subset_offset(subset) as u64;
         vk::DescriptorBufferBindingInfoEXT::builder()
             .address(self.device.device_addr + offset)
             .usage(self.device.kind.to_vk_usage_flags())
@@ -388,3 +667,77 @@ impl DescriptorBuffer {
         }
     }
 }
+
+impl DescriptorBackend for DescriptorBuffer {
+    fn layout(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    fn capacity(&self) -> u32 {
+        self.count
+    }
+
+    fn next_free(&self) -> Option<usize> {
+        DescriptorBuffer::next_free(self)
+    }
+
+    fn is_free(&self, index: u32) -> bool {
+        DescriptorBuffer::is_free(self, index)
+    }
+
+    fn remove_at(&mut self, index: u32) {
+        DescriptorBuffer::remove_at(self, index)
+    }
+
+    fn place_sampler_at(
+        &mut self,
+        ctx: &VulkanContext,
+        index: u32,
+        desc: vk::Sampler,
+    ) -> (usize, u32) {
+        DescriptorBuffer::place_sampler_at(self, index, 0, desc, descriptor_buffer_ext(ctx))
+    }
+
+    fn place_image_at(
+        &mut self,
+        ctx: &VulkanContext,
+        index: u32,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32) {
+        DescriptorBuffer::place_image_at(self, index, 0, desc, descriptor_buffer_ext(ctx))
+    }
+
+    fn place_image_sampler_at(
+        &mut self,
+        ctx: &VulkanContext,
+        index: u32,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32) {
+        DescriptorBuffer::place_image_sampler_at(self, index, 0, desc, descriptor_buffer_ext(ctx))
+    }
+
+    fn flush(&mut self, _: &VulkanContext) {
+        self.into_device()
+    }
+
+    fn flush_single(&mut self, _: &VulkanContext, index: u32) {
+        self.into_device_single(index)
+    }
+
+    #[cfg(feature = "bench-metrics")]
+    fn take_flushed_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.flushed_bytes)
+    }
+
+    fn binding(&self) -> DescriptorBinding {
+        DescriptorBinding::Buffer(self.binding_info())
+    }
+
+    fn destroy(&self, device: &ash::Device) {
+        DescriptorBuffer::destroy(self, device)
+    }
+
+    fn free(&self, mem: &DeviceAllocator) {
+        mem.free(self.device)
+    }
+}
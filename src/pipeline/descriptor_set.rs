@@ -0,0 +1,234 @@
+use ash::vk;
+use bitvec::vec::BitVec;
+
+use crate::context::VulkanContext;
+use crate::pipeline::descriptor::{DescriptorBackend, DescriptorBinding, SlotLayout};
+
+///
+/// Descriptor table in a classic descriptor set, for devices without descriptor buffers.
+/// Every binding is update after bind, so slots can be written while a frame using the
+/// set is still in flight, as long as that frame doesn't access them.
+///
+pub struct DescriptorSets {
+    pub name: String,
+    pub layout: vk::DescriptorSetLayout,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    slots: SlotLayout,
+    occupancy: BitVec,
+    // Host copy of the descriptors, written into the set on flush
+    infos: Vec<vk::DescriptorImageInfo>,
+    dirty: Vec<u32>,
+    #[cfg(feature = "bench-metrics")]
+    flushed_bytes: u64,
+}
+
+impl DescriptorSets {
+    pub fn of(
+        ctx: &VulkanContext,
+        name: String,
+        descriptor_type: vk::DescriptorType,
+        slots: SlotLayout,
+    ) -> Self {
+        let count = slots.count();
+        assert!(count > 0, "cant have zero sized descriptor sets!");
+        let bindings = slots.layout_bindings(descriptor_type);
+        let binding_flags = vec![
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND;
+            bindings.len()
+        ];
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+            .binding_flags(&binding_flags)
+            .build();
+        let info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings)
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .push_next(&mut binding_flags_info)
+            .build();
+        let layout = unsafe { ctx.device.create_descriptor_set_layout(&info, None) }.unwrap();
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: descriptor_type,
+            descriptor_count: count,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes)
+            .build();
+        let pool = unsafe { ctx.device.create_descriptor_pool(&pool_info, None) }.unwrap();
+        let layouts = [layout];
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts)
+            .build();
+        let set = unsafe { ctx.device.allocate_descriptor_sets(&alloc_info) }
+            .expect("couldn't allocate the descriptor set!")[0];
+        ctx.try_set_debug_name(&name, layout);
+        ctx.try_set_debug_name(&name, pool);
+        ctx.try_set_debug_name(&name, set);
+        Self {
+            name,
+            layout,
+            descriptor_type,
+            count,
+            pool,
+            set,
+            occupancy: slots.occupancy(),
+            slots,
+            infos: vec![vk::DescriptorImageInfo::default(); count as usize],
+            dirty: Vec::new(),
+            #[cfg(feature = "bench-metrics")]
+            flushed_bytes: 0,
+        }
+    }
+
+    // Image infos of that many writes, what flushes count for bench-metrics
+    #[cfg(feature = "bench-metrics")]
+    fn info_bytes(writes: usize) -> u64 {
+        (writes * std::mem::size_of::<vk::DescriptorImageInfo>()) as u64
+    }
+
+    fn place_at(
+        &mut self,
+        index: u32,
+        desc_type: vk::DescriptorType,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32) {
+        assert!(
+            desc_type == self.descriptor_type,
+            "Can't place a {:?} on a {:?} set!",
+            desc_type,
+            self.descriptor_type
+        );
+        self.infos[index as usize] = desc;
+        self.occupancy.set(index as usize, true);
+        self.dirty.push(index);
+        // No offsets in a set, only the index means anything
+        (0, index)
+    }
+
+    fn write_of(&self, index: u32) -> vk::WriteDescriptorSet {
+        let (binding, element) = self.slots.binding_of(index);
+        vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(binding)
+            .dst_array_element(element)
+            .descriptor_type(self.descriptor_type)
+            .image_info(std::slice::from_ref(&self.infos[index as usize]))
+            .build()
+    }
+}
+
+impl DescriptorBackend for DescriptorSets {
+    fn layout(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    fn capacity(&self) -> u32 {
+        self.count
+    }
+
+    fn next_free(&self) -> Option<usize> {
+        self.occupancy.first_zero()
+    }
+
+    fn is_free(&self, index: u32) -> bool {
+        index < self.count && !self.occupancy[index as usize]
+    }
+
+    fn remove_at(&mut self, index: u32) {
+        self.occupancy.set(index as usize, false);
+        // Slots removed before getting flushed may point to destroyed views
+        self.dirty.retain(|e| *e != index);
+    }
+
+    fn reset_image_at(&mut self, _: &VulkanContext, index: u32, desc: vk::DescriptorImageInfo) {
+        // Stays dirty, unlike a removed slot it points to something valid
+        self.place_at(index, vk::DescriptorType::SAMPLED_IMAGE, desc);
+        self.occupancy.set(index as usize, false);
+    }
+
+    fn place_sampler_at(
+        &mut self,
+        _: &VulkanContext,
+        index: u32,
+        desc: vk::Sampler,
+    ) -> (usize, u32) {
+        let desc = vk::DescriptorImageInfo {
+            sampler: desc,
+            ..Default::default()
+        };
+        self.place_at(index, vk::DescriptorType::SAMPLER, desc)
+    }
+
+    fn place_image_at(
+        &mut self,
+        _: &VulkanContext,
+        index: u32,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32) {
+        self.place_at(index, vk::DescriptorType::SAMPLED_IMAGE, desc)
+    }
+
+    fn place_image_sampler_at(
+        &mut self,
+        _: &VulkanContext,
+        index: u32,
+        desc: vk::DescriptorImageInfo,
+    ) -> (usize, u32) {
+        self.place_at(index, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, desc)
+    }
+
+    fn flush(&mut self, ctx: &VulkanContext) {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        dirty.sort_unstable();
+        dirty.dedup();
+        let writes: Vec<_> = dirty.into_iter().map(|e| self.write_of(e)).collect();
+        if !writes.is_empty() {
+            unsafe { ctx.device.update_descriptor_sets(&writes, &[]) };
+        }
+        #[cfg(feature = "bench-metrics")]
+        {
+            self.flushed_bytes += Self::info_bytes(writes.len());
+        }
+    }
+
+    fn flush_single(&mut self, ctx: &VulkanContext, index: u32) {
+        assert!(
+            index < self.count,
+            "index {} out of bounds! total {}",
+            index,
+            self.count
+        );
+        let is_dirty = self.dirty.contains(&index);
+        self.dirty.retain(|e| *e != index);
+        if is_dirty || self.occupancy[index as usize] {
+            let writes = [self.write_of(index)];
+            unsafe { ctx.device.update_descriptor_sets(&writes, &[]) };
+            #[cfg(feature = "bench-metrics")]
+            {
+                self.flushed_bytes += Self::info_bytes(1);
+            }
+        }
+    }
+
+    #[cfg(feature = "bench-metrics")]
+    fn take_flushed_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.flushed_bytes)
+    }
+
+    fn binding(&self) -> DescriptorBinding {
+        DescriptorBinding::Set(self.set)
+    }
+
+    fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            // Sets go away with their pool
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.layout, None);
+        }
+    }
+}
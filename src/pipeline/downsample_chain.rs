@@ -0,0 +1,266 @@
+use serde_json::{json, Value};
+
+/*
+ * Downsample chain passes get replaced by one fullscreen pass per level before the
+ * pipeline gets parsed, so each level is an attachment like any other as far as layout
+ * tracking and sampling go:
+ *
+ *   { "name": "bloomDown", "type": "downsampleChain", "source": "hdr",
+ *     "destination": "bloom", "levels": 5, "threshold": 1.0, "knee": 0.5,
+ *     "maxLuminance": [64, 16] }
+ *
+ * Level i renders into the target "{destination}_{i}", declared here at half the size
+ * of the level above it, the first one at half the source. They take the format and
+ * group of the source unless the pass says otherwise, a source that isn't a declared
+ * target needs the format given. The level passes are named "{name}_{i}" and filter
+ * with the dual filter kernel of builtin/downsample.frag, the first one through
+ * builtin/downsample_prefilter.frag extracting what is over the threshold first. Threshold and knee are stage constants of the first
+ * level, maxLuminance one of every level, see Renderer::set_stage_constant. A single
+ * maxLuminance applies to every level, a shorter list repeats its last value. An
+ * extraUsage list goes to every level target, see Target::extra_usage.
+ */
+
+const TYPE_NAME: &str = "downsampleChain";
+// Largest half float, no clamping short of the level formats overflowing
+const DEFAULT_MAX_LUMINANCE: f64 = 65504.0;
+const DEFAULT_THRESHOLD: f64 = 1.0;
+const DEFAULT_KNEE: f64 = 0.5;
+
+///
+/// Replaces every downsample chain among the passes of the pipeline with its levels,
+/// appending their targets and programs to the ones of the pipeline.
+///
+pub fn expand(pipeline: &mut Value) {
+    let passes = match pipeline.get_mut("passes").and_then(|e| e.as_array_mut()) {
+        Some(passes) => std::mem::take(passes),
+        None => return,
+    };
+    let declared_targets = pipeline
+        .get("targets")
+        .and_then(|e| e.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let mut expanded_passes = Vec::with_capacity(passes.len());
+    let mut targets = Vec::new();
+    let mut programs = Vec::new();
+    for pass in passes {
+        if pass.get("type").and_then(|e| e.as_str()) != Some(TYPE_NAME) {
+            expanded_passes.push(pass);
+            continue;
+        }
+        let chain = Chain::of(&pass, &declared_targets);
+        targets.extend(chain.targets());
+        programs.extend(chain.programs());
+        expanded_passes.extend(chain.passes());
+    }
+    pipeline["passes"] = Value::Array(expanded_passes);
+    for (name, items) in [("targets", targets), ("programs", programs)] {
+        match pipeline.get_mut(name).and_then(|e| e.as_array_mut()) {
+            Some(existing) => existing.extend(items),
+            None => pipeline[name] = Value::Array(items),
+        }
+    }
+}
+
+struct Chain {
+    name: String,
+    source: String,
+    destination: String,
+    format: Value,
+    group: Value,
+    // Of the source, either in pixels or as a fraction of the window
+    width: Value,
+    height: Value,
+    threshold: f64,
+    knee: f64,
+    max_luminance: Vec<f64>,
+    extra_usage: Value,
+    is_disabled: bool,
+}
+
+impl Chain {
+    fn of(pass: &Value, declared_targets: &[Value]) -> Self {
+        let name = pass["name"]
+            .as_str()
+            .expect("downsample chain without a name!")
+            .to_string();
+        let field = |field: &str| -> String {
+            pass[field]
+                .as_str()
+                .unwrap_or_else(|| panic!("downsample chain {} needs a {}!", name, field))
+                .to_string()
+        };
+        let source = field("source");
+        let destination = field("destination");
+        let levels = pass["levels"]
+            .as_u64()
+            .filter(|e| *e > 0)
+            .unwrap_or_else(|| panic!("downsample chain {} needs at least 1 level!", name));
+        let source_target = declared_targets
+            .iter()
+            .find(|e| e["name"].as_str() == Some(&source));
+        let format = match (pass.get("format"), source_target) {
+            (Some(format), _) => format.clone(),
+            (None, Some(target)) => target["format"].clone(),
+            (None, None) => panic!(
+                "downsample chain {} needs a format, its source {} isn't a declared target!",
+                name, source
+            ),
+        };
+        let (group, width, height) = match source_target {
+            Some(target) => (
+                target["group"].clone(),
+                target["width"].clone(),
+                target["height"].clone(),
+            ),
+            None => (Value::String(name.clone()), json!(1.0), json!(1.0)),
+        };
+        let number = |field: &str, default: f64| -> f64 {
+            match pass.get(field) {
+                Some(v) => v.as_f64().unwrap_or_else(|| {
+                    panic!("{} of downsample chain {} has to be a number", field, name)
+                }),
+                None => default,
+            }
+        };
+        let (threshold, knee) = (
+            number("threshold", DEFAULT_THRESHOLD),
+            number("knee", DEFAULT_KNEE),
+        );
+        let listed: Vec<f64> = match pass.get("maxLuminance") {
+            None => vec![DEFAULT_MAX_LUMINANCE],
+            Some(Value::Array(items)) => items.iter().filter_map(|e| e.as_f64()).collect(),
+            Some(v) => v.as_f64().into_iter().collect(),
+        };
+        if listed.is_empty() || listed.len() > levels as usize {
+            panic!(
+                "maxLuminance of downsample chain {} takes a number or up to {} of them",
+                name, levels
+            );
+        }
+        let max_luminance = (0..levels as usize)
+            .map(|i| listed[i.min(listed.len() - 1)])
+            .collect();
+        Self {
+            is_disabled: pass["isDisabled"].as_bool().unwrap_or(false),
+            name,
+            source,
+            destination,
+            format,
+            group,
+            width,
+            height,
+            threshold,
+            knee,
+            max_luminance,
+            extra_usage: pass.get("extraUsage").cloned().unwrap_or(json!([])),
+        }
+    }
+
+    fn level_name(&self, level: usize) -> String {
+        format!("{}_{}", self.destination, level)
+    }
+
+    /*
+     * Pixel sizes halve rounding down but never below a pixel, window fractions just
+     * halve.
+     */
+    fn level_size(size: &Value, level: usize) -> Value {
+        match size.as_u64() {
+            Some(pixels) => json!((pixels >> (level + 1)).max(1)),
+            None => {
+                let fraction = size.as_f64().expect("target sizes are numbers");
+                json!(fraction * 0.5f64.powi(level as i32 + 1))
+            }
+        }
+    }
+
+    fn targets(&self) -> Vec<Value> {
+        (0..self.max_luminance.len())
+            .map(|level| {
+                json!({
+                    "name": self.level_name(level),
+                    "group": self.group,
+                    "format": self.format,
+                    "width": Self::level_size(&self.width, level),
+                    "height": Self::level_size(&self.height, level),
+                    "extraUsage": self.extra_usage,
+                })
+            })
+            .collect()
+    }
+
+    fn programs(&self) -> Vec<Value> {
+        [
+            ("prefilter", "downsample_prefilter"),
+            ("down", "downsample"),
+        ]
+        .iter()
+        .map(|(kind, shader)| {
+            json!({
+                "name": format!("{}_{}", self.name, kind),
+                "vertex": "fullscreen.vert",
+                "fragment": format!("builtin/{}.frag", shader),
+            })
+        })
+        .collect()
+    }
+
+    fn passes(&self) -> Vec<Value> {
+        self.max_luminance
+            .iter()
+            .enumerate()
+            .map(|(level, max_luminance)| {
+                let input = match level {
+                    0 => self.source.clone(),
+                    _ => self.level_name(level - 1),
+                };
+                let mut constants = Vec::new();
+                if level == 0 {
+                    constants.push(Self::constant("threshold", self.threshold));
+                    constants.push(Self::constant("knee", self.knee));
+                }
+                constants.push(Self::constant("maxLuminance", *max_luminance));
+                let viewport = json!({
+                    "x": 0,
+                    "y": 0,
+                    "width": Self::level_size(&self.width, level),
+                    "height": Self::level_size(&self.height, level),
+                });
+                json!({
+                    "name": format!("{}_{}", self.name, level),
+                    "program": format!(
+                        "{}_{}",
+                        self.name,
+                        if level == 0 { "prefilter" } else { "down" }
+                    ),
+                    "batch": "FULLSCREEN",
+                    "outputs": [self.level_name(level)],
+                    "inputs": [{ "name": input, "sampler": "LINEAR", "uniform": "higherMip" }],
+                    "constants": constants,
+                    "isDisabled": self.is_disabled,
+                    // Kept around so the chain shows up in the frame graph
+                    "template": format!("{} as {}", TYPE_NAME, self.name),
+                    "state": {
+                        "writing": "COLOR",
+                        "depth": "NO",
+                        "scissor": viewport,
+                        "viewport": viewport,
+                        "stencil": "NO",
+                        "triangle": {
+                            "frontFace": "CCW",
+                            "cullFace": "NONE",
+                            "polygonMode": "FILL"
+                        },
+                        "blending": "NO",
+                        "clearing": "NO"
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn constant(name: &str, default: f64) -> Value {
+        json!({ "name": name, "type": "float", "default": default, "min": 0.0 })
+    }
+}
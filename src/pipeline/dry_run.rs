@@ -0,0 +1,1150 @@
+use std::collections::{HashMap, HashSet};
+
+use ash::vk;
+
+use super::{
+    attachment::Attachment,
+    budget::{BudgetLimits, PipelineBudget},
+    depth_pyramid,
+    file::{
+        self, DescHandler, Pass, PassKind, PerDrawField, ShadingRate, TransformInterpolation,
+        ViewFormat,
+    },
+    graph::{FrameGraph, GraphAttachment, GraphStage, StageKind},
+    plan::{self, DrawSink, DrawState, ImageTransition, ScopeShape, Transition},
+    specialization::Specialization,
+    stage::BufferAccess,
+};
+use crate::{
+    capabilities::DeviceCapabilities,
+    format::Format,
+    handle::MeshHandle,
+    motion::Affine,
+    render_task::{RenderTask, ScissorRect, TaskKind},
+    shader_resource::{MultiResource, ResourceKind, TransformExtra},
+    stage_constants::StageConstants,
+};
+
+/*
+ * Walks a frame of a pipeline file the way the renderer records it, without a device.
+ * Barriers, rendering scopes, draw order and state changes come out of the same plan
+ * functions the renderer goes through, so a change to any of them shows up in the
+ * trace of a test instead of as a validation error on somebody's GPU.
+ *
+ * What needs a device isn't traced: descriptor offsets depend on the reflected bindings
+ * and the descriptor sizes of the device, so bound inputs are listed in the order the
+ * pass declares them. Blit format support of the device isn't checked either, nor whether
+ * the shaders declare the specialization constants of a stage, and the frame graph has no
+ * reflected blocks. Stages group their
+ * draws by pipeline handle, which the trace can't know, it groups them by variant id,
+ * base pipeline first.
+ */
+
+///
+/// Mesh drawn by the synthetic tasks of a dry run.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DryMesh {
+    pub count: u32,
+    pub is_indexed: bool,
+}
+
+///
+/// What a reservation in the frame region is for.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reservation {
+    PerPass,
+    Constants,
+    PerInstance(ResourceKind),
+    PreviousTransforms,
+    // Blended on the cpu, the states uploaded for shaders to blend aren't traced
+    InterpolatedTransforms,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TracedAttachment {
+    pub name: String,
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+}
+
+///
+/// Operation of a frame, in the order the renderer does them. Reservations come first,
+/// every stage gets prepared before any gets recorded.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceOp {
+    Reserve {
+        stage: String,
+        purpose: Reservation,
+        bytes: u64,
+    },
+    // Host wait on the stage of the previous frame
+    Wait {
+        stage: String,
+        value: u64,
+    },
+    Transition {
+        stage: String,
+        transition: ImageTransition,
+    },
+    BufferBarrier {
+        stage: String,
+        buffer: String,
+        src: BufferAccess,
+        dst: BufferAccess,
+    },
+    BeginRendering {
+        stage: String,
+        area: vk::Extent2D,
+        view_mask: u32,
+        attachments: Vec<TracedAttachment>,
+    },
+    // Inputs sampling the default texture in its place are None
+    BindDescriptors {
+        stage: String,
+        inputs: Vec<Option<String>>,
+    },
+    SetDepthBounds([f32; 2]),
+    SetShadingRate(ShadingRate),
+    // Base pipeline if the variant is None
+    BindPipeline {
+        stage: String,
+        variant: Option<String>,
+    },
+    SetCullMode(vk::CullModeFlags),
+    SetScissor(vk::Rect2D),
+    Draw {
+        mesh: MeshHandle,
+        count: u32,
+        instance_count: u32,
+        is_indexed: bool,
+    },
+    EndRendering {
+        stage: String,
+    },
+    // Only barriers if nothing wrote the source yet
+    Blit {
+        stage: String,
+        source: String,
+        destination: String,
+        is_copy: bool,
+        is_skipped: bool,
+    },
+    // Same, one dispatch per mip
+    DepthPyramid {
+        stage: String,
+        source: String,
+        destination: String,
+        mips: u32,
+        is_skipped: bool,
+    },
+    Present {
+        stage: String,
+    },
+    Signal {
+        stage: String,
+        value: u64,
+    },
+    // Internal target scaled into the swapchain image
+    Upscale,
+}
+
+impl std::fmt::Display for TraceOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let access = |e: &BufferAccess| {
+            if *e == BufferAccess::WRITE {
+                "write"
+            } else {
+                "read"
+            }
+        };
+        match self {
+            Self::Reserve {
+                stage,
+                purpose,
+                bytes,
+            } => match purpose {
+                Reservation::PerInstance(kind) => write!(f, "{stage}: reserve {kind} {bytes}"),
+                _ => write!(f, "{stage}: reserve {purpose:?} {bytes}"),
+            },
+            Self::Wait { stage, value } => write!(f, "{stage}: wait {value}"),
+            Self::Transition { stage, transition } => write!(
+                f,
+                "{stage}: transition {} {:?} -> {:?}",
+                transition.attachment,
+                transition.transition.old_layout,
+                transition.transition.new_layout
+            ),
+            Self::BufferBarrier {
+                stage,
+                buffer,
+                src,
+                dst,
+            } => write!(
+                f,
+                "{stage}: buffer barrier {buffer} {} -> {}",
+                access(src),
+                access(dst)
+            ),
+            Self::BeginRendering {
+                stage,
+                area,
+                view_mask,
+                attachments,
+            } => {
+                write!(f, "{stage}: begin rendering {}x{}", area.width, area.height)?;
+                if *view_mask != 0 {
+                    write!(f, " views {view_mask:#b}")?;
+                }
+                for e in attachments {
+                    write!(f, " {} {:?}/{:?}", e.name, e.load_op, e.store_op)?;
+                }
+                Ok(())
+            }
+            Self::BindDescriptors { stage, inputs } => {
+                write!(f, "{stage}: bind descriptors")?;
+                for e in inputs {
+                    write!(f, " {}", e.as_deref().unwrap_or("(default texture)"))?;
+                }
+                Ok(())
+            }
+            Self::SetDepthBounds([min, max]) => write!(f, "  depth bounds {min} {max}"),
+            Self::SetShadingRate(rate) => write!(f, "  shading rate {rate}"),
+            Self::BindPipeline { stage, variant } => write!(
+                f,
+                "  bind pipeline {stage}{}",
+                variant.as_ref().map_or(String::new(), |e| format!(" {e}"))
+            ),
+            // Debug of the flags leaves NONE empty
+            Self::SetCullMode(mode) => match *mode {
+                vk::CullModeFlags::NONE => write!(f, "  cull mode NONE"),
+                vk::CullModeFlags::FRONT_AND_BACK => write!(f, "  cull mode FRONT_AND_BACK"),
+                _ => write!(f, "  cull mode {mode:?}"),
+            },
+            Self::SetScissor(e) => write!(
+                f,
+                "  scissor {},{} {}x{}",
+                e.offset.x, e.offset.y, e.extent.width, e.extent.height
+            ),
+            Self::Draw {
+                mesh,
+                count,
+                instance_count,
+                is_indexed,
+            } => write!(
+                f,
+                "  draw mesh {mesh} {} {count} x{instance_count}",
+                if *is_indexed { "indices" } else { "vertices" }
+            ),
+            Self::EndRendering { stage } => write!(f, "{stage}: end rendering"),
+            Self::Blit {
+                stage,
+                source,
+                destination,
+                is_copy,
+                is_skipped,
+            } => write!(
+                f,
+                "{stage}: {}{} {source} -> {destination}",
+                if *is_skipped { "skip " } else { "" },
+                if *is_copy { "copy" } else { "blit" }
+            ),
+            Self::DepthPyramid {
+                stage,
+                source,
+                destination,
+                mips,
+                is_skipped,
+            } => write!(
+                f,
+                "{stage}: {}depth pyramid {source} -> {destination} {mips} mips",
+                if *is_skipped { "skip " } else { "" },
+            ),
+            Self::Present { stage } => write!(f, "{stage}: present"),
+            Self::Signal { stage, value } => write!(f, "{stage}: signal {value}"),
+            Self::Upscale => write!(f, "upscale"),
+        }
+    }
+}
+
+///
+/// Operations of one frame, along with what the renderer would warn about recording it.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameTrace {
+    pub ops: Vec<TraceOp>,
+    pub warnings: Vec<String>,
+}
+
+impl FrameTrace {
+    ///
+    /// One line per operation, draw state indented under the stage it's recorded in.
+    ///
+    pub fn lines(&self) -> Vec<String> {
+        self.ops.iter().map(|e| e.to_string()).collect()
+    }
+}
+
+impl std::fmt::Display for FrameTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for op in &self.ops {
+            writeln!(f, "{op}")?;
+        }
+        Ok(())
+    }
+}
+
+struct DryBlit {
+    source: String,
+    destination: String,
+    is_copy: bool,
+    // Depth pyramids only
+    mips: Option<u32>,
+    after: Vec<ImageTransition>,
+}
+
+/*
+ * What a Stage keeps for recording, attachments by name instead of images and views.
+ */
+struct DryStage {
+    name: String,
+    index: u32,
+    template: Option<String>,
+    task_kind: TaskKind,
+    inputs: Vec<String>,
+    depth_stencil: Option<String>,
+    // Outputs first, then the depth stencil attachment if written
+    written: Vec<String>,
+    // Colors, depth and stencil, with their ops merged like the rendering of a Stage
+    rendering: Vec<TracedAttachment>,
+    is_default_written: bool,
+    is_first_default_write: bool,
+    is_final: bool,
+    transitions: Vec<ImageTransition>,
+    buffer_dependencies: Vec<super::stage::BufferDependency>,
+    area: vk::Extent2D,
+    view_mask: u32,
+    cull_mode: vk::CullModeFlags,
+    depth_bounds: Option<[f32; 2]>,
+    shading_rate: ShadingRate,
+    dynamic_scissor: Option<vk::Rect2D>,
+    // Indexed by variant id, whether the pass declares it
+    variants: Vec<bool>,
+    per_pass_updaters: Vec<ResourceKind>,
+    constants_size: u32,
+    per_instance_updaters: Vec<ResourceKind>,
+    per_draw_fields: Vec<PerDrawField>,
+    transform_interpolation: TransformInterpolation,
+    blit: Option<DryBlit>,
+    is_scope_continued: bool,
+    is_scope_kept_open: bool,
+}
+
+impl DryStage {
+    fn graph_stage(&self) -> GraphStage {
+        let kind = match &self.blit {
+            Some(e) if e.mips.is_some() => StageKind::Compute,
+            Some(_) => StageKind::Blit,
+            None if self.task_kind == TaskKind::Fullscreen => StageKind::Fullscreen,
+            None => StageKind::Graphics,
+        };
+        let barriers = self
+            .transitions
+            .iter()
+            .map(|e| {
+                let transition = &e.transition;
+                (
+                    e.attachment.clone(),
+                    transition.old_layout,
+                    transition.new_layout,
+                )
+            })
+            .collect();
+        // Depth and stencil come after the outputs, only the depth ops get shown
+        let output_count = self.rendering.len()
+            - self
+                .rendering
+                .iter()
+                .filter(|e| Some(&e.name) == self.depth_stencil.as_ref())
+                .count();
+        let outputs = self.rendering[..output_count]
+            .iter()
+            .map(|e| (e.name.clone(), (e.load_op, e.store_op)))
+            .collect();
+        let depth_stencil = self.depth_stencil.as_ref().map(|name| {
+            let ops = self
+                .rendering
+                .get(output_count)
+                .map(|e| (e.load_op, e.store_op));
+            (name.clone(), ops.filter(|_| self.written.contains(name)))
+        });
+        GraphStage {
+            index: self.index,
+            name: self.name.clone(),
+            kind,
+            task_kind: self.task_kind,
+            template: self.template.clone(),
+            view_mask: self.view_mask,
+            barriers,
+            blocks: Vec::new(),
+            is_scope_continued: self.is_scope_continued,
+            is_final: self.is_final,
+            outputs,
+            depth_stencil,
+            inputs: self.inputs.clone(),
+            blit: self.blit.as_ref().map(|e| {
+                let verb = match (e.mips, e.is_copy) {
+                    (Some(_), _) => "depth pyramid",
+                    (None, true) => "copy",
+                    (None, false) => "blit",
+                };
+                (verb, e.source.clone(), e.destination.clone())
+            }),
+        }
+    }
+
+    fn pipeline_for(&self, variant: Option<u16>) -> Option<u16> {
+        variant.filter(|e| self.variants.get(*e as usize).copied().unwrap_or(false))
+    }
+}
+
+///
+/// Frames of a pipeline file recorded without a device, see FrameTrace. Like the
+/// renderer it remembers across frames which attachments were written, so the first
+/// frame can differ from the rest. Attachments with an initial state count as written
+/// from the start. Reads of ones without aren't rejected the way creating a renderer
+/// does, file::Pipeline::uninitialized_reads lists them.
+///
+pub struct DryRun {
+    stages: Vec<DryStage>,
+    variant_names: Vec<String>,
+    budget: PipelineBudget,
+    capabilities: DeviceCapabilities,
+    is_scaled: bool,
+    written: HashSet<String>,
+    frame: u64,
+    attachments: Vec<GraphAttachment>,
+}
+
+impl DryRun {
+    ///
+    /// Checks the pipeline the way Pipeline::load does and lays out its stages. The
+    /// extent is the one of the default attachment, the internal resolution when scaled.
+    /// Panics where loading it would, with the default budget limits.
+    ///
+    pub fn new(
+        pip: file::Pipeline,
+        capabilities: &DeviceCapabilities,
+        extent: vk::Extent2D,
+        is_scaled: bool,
+    ) -> Self {
+        Self::with_limits(
+            pip,
+            capabilities,
+            extent,
+            is_scaled,
+            &BudgetLimits::default(),
+        )
+    }
+
+    ///
+    /// Same as new, checking the budget of the pipeline against the limits.
+    ///
+    pub fn with_limits(
+        mut pip: file::Pipeline,
+        capabilities: &DeviceCapabilities,
+        extent: vk::Extent2D,
+        is_scaled: bool,
+        limits: &BudgetLimits,
+    ) -> Self {
+        pip.match_requirements(capabilities)
+            .unwrap_or_else(|e| panic!("{}", e));
+        let budget = pip
+            .budget(extent, limits)
+            .unwrap_or_else(|e| panic!("{}", e));
+        // Extent, format and layers of every attachment, by name
+        let mut targets: HashMap<&str, (vk::Extent2D, Format, u32, bool)> = pip
+            .targets
+            .iter()
+            .map(|e| {
+                let target_extent = file::Pipeline::extent_of(
+                    e.width,
+                    e.height,
+                    extent.width as f32,
+                    extent.height as f32,
+                );
+                (
+                    e.name.as_str(),
+                    (target_extent, e.format, e.layers, e.is_memoryless),
+                )
+            })
+            .collect();
+        // Format doesn't matter, the default attachment can't be blitted nor sampled
+        targets.insert(
+            Attachment::DEFAULT_NAME,
+            (extent, Format::R8G8B8A8_SRGB, 1, false),
+        );
+        let target_of = |pass: &Pass, name: &str| {
+            *targets
+                .get(name)
+                .unwrap_or_else(|| panic!("attachment {} missing for pass {}!", name, pass.name))
+        };
+        // The default attachment is the one of a headless renderer
+        let attachments = pip
+            .targets
+            .iter()
+            .zip(pip.attachment_usages())
+            .map(|(e, (_, usage))| GraphAttachment {
+                name: e.name.clone(),
+                format: e.format.to_vk(),
+                extent: targets[e.name.as_str()].0,
+                layers: e.layers,
+                usage,
+            })
+            .chain(std::iter::once(GraphAttachment {
+                name: Attachment::DEFAULT_NAME.to_string(),
+                format: crate::swapchain::HEADLESS_FORMAT,
+                extent,
+                layers: 1,
+                usage: crate::swapchain::HEADLESS_USAGE,
+            }))
+            .collect();
+        let convention = pip.depth_convention;
+        let passes: Vec<_> = pip.passes.into_iter().filter(|e| !e.is_disabled).collect();
+        file::Pipeline::validate_memoryless_targets(&pip.targets, &passes);
+        file::Pipeline::validate_blit_attachments(&passes);
+        file::Pipeline::validate_mip_chained_targets(&pip.targets, &passes);
+        file::Pipeline::validate_preserved_targets(&pip.targets);
+        file::Pipeline::validate_per_viewport_targets(&pip.targets, &passes);
+        file::Pipeline::validate_vertex_formats(&passes);
+        file::Pipeline::validate_initial_states(&pip.targets, &pip.buffers, &passes);
+        // Cleared before the first frame, see Pipeline::record_initial_states
+        let initialized: HashSet<String> = pip
+            .targets
+            .iter()
+            .filter(|e| e.initial_state.is_some())
+            .map(|e| e.name.clone())
+            .collect();
+        let mut variant_names: Vec<String> = passes
+            .iter()
+            .flat_map(|e| e.variants.keys().cloned())
+            .collect();
+        variant_names.sort();
+        variant_names.dedup();
+        let default_passes: Vec<_> = passes
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.has_output(Attachment::DEFAULT_NAME))
+            .map(|(i, _)| i)
+            .collect();
+        file::Pipeline::validate_present(&passes, &default_passes);
+        let mut stages = Vec::with_capacity(passes.len());
+        for (passi, pass) in passes.iter().enumerate() {
+            let mut stage = DryStage {
+                name: pass.name.clone(),
+                index: passi as u32,
+                template: pass.template.clone(),
+                task_kind: TaskKind::Fullscreen,
+                inputs: Vec::new(),
+                depth_stencil: None,
+                written: Vec::new(),
+                rendering: Vec::new(),
+                is_default_written: false,
+                is_first_default_write: Some(&passi) == default_passes.first(),
+                is_final: !is_scaled && Some(&passi) == default_passes.last(),
+                transitions: Vec::new(),
+                buffer_dependencies: Vec::new(),
+                area: extent,
+                view_mask: 0,
+                cull_mode: vk::CullModeFlags::NONE,
+                depth_bounds: None,
+                shading_rate: ShadingRate::Full,
+                dynamic_scissor: None,
+                variants: variant_names
+                    .iter()
+                    .map(|e| pass.variants.contains_key(e))
+                    .collect(),
+                per_pass_updaters: Vec::new(),
+                constants_size: 0,
+                per_instance_updaters: Vec::new(),
+                per_draw_fields: Vec::new(),
+                transform_interpolation: pass.transform_interpolation,
+                blit: None,
+                is_scope_continued: false,
+                is_scope_kept_open: false,
+            };
+            if pass.kind == PassKind::Blit {
+                let source_desc = pass.source.as_ref().unwrap();
+                let destination = pass.destination.as_ref().unwrap();
+                let (source_extent, source_format, ..) = target_of(pass, &source_desc.name);
+                let (destination_extent, destination_format, ..) = target_of(pass, destination);
+                file::Pipeline::validate_blit_aspects(pass, source_format, destination_format);
+                let source_offsets = file::Pipeline::blit_offsets_of(
+                    pass,
+                    &source_desc.name,
+                    source_extent,
+                    pass.source_rect,
+                );
+                let destination_offsets = file::Pipeline::blit_offsets_of(
+                    pass,
+                    destination,
+                    destination_extent,
+                    pass.destination_rect,
+                );
+                let size_of = |e: [vk::Offset3D; 2]| (e[1].x - e[0].x, e[1].y - e[0].y);
+                let is_destination_partial = size_of(destination_offsets)
+                    != (
+                        destination_extent.width as i32,
+                        destination_extent.height as i32,
+                    );
+                let (before, after) =
+                    plan::blit_transitions(passi, &passes, is_destination_partial);
+                stage.transitions = before;
+                stage.blit = Some(DryBlit {
+                    source: source_desc.name.clone(),
+                    destination: destination.clone(),
+                    is_copy: source_format == destination_format
+                        && size_of(source_offsets) == size_of(destination_offsets),
+                    mips: None,
+                    after,
+                });
+                stages.push(stage);
+                continue;
+            }
+            if pass.kind == PassKind::DepthPyramid {
+                let destination = pass.destination.as_ref().unwrap();
+                let (extent, ..) = target_of(pass, destination);
+                let (before, after) = plan::depth_pyramid_transitions(passi, &passes);
+                stage.transitions = before;
+                stage.blit = Some(DryBlit {
+                    source: pass.source.as_ref().unwrap().name.clone(),
+                    destination: destination.clone(),
+                    is_copy: false,
+                    mips: Some(
+                        depth_pyramid::mip_extents(extent.width, extent.height).len() as u32,
+                    ),
+                    after,
+                });
+                stages.push(stage);
+                continue;
+            }
+            stage.task_kind = pass
+                .batch
+                .unwrap_or_else(|| panic!("pass {} has no batch!", pass.name));
+            Specialization::validate_ids(pass, None);
+            let writing = file::Pipeline::handle_option(pass.state.writing.clone());
+            let stencil = file::Pipeline::handle_option(pass.state.stencil.clone());
+            let scissor = file::Pipeline::handle_option(pass.state.scissor.clone());
+            let triangle = file::Pipeline::handle_option(pass.state.triangle.clone());
+            let clearing = file::Pipeline::clearing_of(pass, convention);
+            let is_depth_written = writing.depth || writing.stencil;
+            let depth = pass.depth_stencil.as_ref().map(|e| {
+                let (_, format, layers, is_memoryless) = target_of(pass, &e.name);
+                (e, format, layers, is_memoryless)
+            });
+            stage.inputs = pass.inputs.iter().map(|e| e.name.clone()).collect();
+            for input in &stage.inputs {
+                target_of(pass, input);
+            }
+            stage.view_mask = file::Pipeline::validate_views(
+                pass,
+                pass.outputs
+                    .iter()
+                    .map(|e| (e.name.as_str(), target_of(pass, &e.name).2))
+                    .chain(depth.map(|(e, _, layers, _)| (e.name.as_str(), layers))),
+            );
+            let mut rendered: Vec<(&str, bool)> = Vec::new();
+            for output in &pass.outputs {
+                let (_, _, _, is_memoryless) = target_of(pass, &output.name);
+                let (load_op, store_op) = plan::attachment_ops(
+                    is_memoryless,
+                    clearing.to_vk_color().is_some(),
+                    output.is_stored,
+                );
+                stage.rendering.push(TracedAttachment {
+                    name: output.name.clone(),
+                    load_op,
+                    store_op,
+                });
+                rendered.push((output.name.as_str(), false));
+            }
+            if let Some((output, _, _, is_memoryless)) = depth {
+                stage.depth_stencil = Some(output.name.clone());
+                let is_cleared = clearing.to_vk_depth_stencil().is_some();
+                let mut stored = vec![output.is_stored];
+                // Only bound when the pipeline has a stencil format
+                if writing.stencil || !stencil.disabled {
+                    stored.push(output.is_stencil_stored);
+                }
+                for is_stored in stored {
+                    let (load_op, store_op) =
+                        plan::attachment_ops(is_memoryless, is_cleared, is_stored);
+                    stage.rendering.push(TracedAttachment {
+                        name: output.name.clone(),
+                        load_op,
+                        store_op,
+                    });
+                }
+                if is_depth_written {
+                    rendered.push((output.name.as_str(), true));
+                }
+            }
+            let inputs: Vec<_> = stage.inputs.iter().map(|e| e.as_str()).collect();
+            stage.transitions = plan::image_transitions_for(passi, &inputs, &rendered, &passes);
+            stage.buffer_dependencies = plan::buffer_dependencies_for(passi, &passes);
+            stage.written = rendered
+                .iter()
+                .map(|e| e.0)
+                .filter(|e| Attachment::DEFAULT_NAME != *e)
+                .map(|e| e.to_string())
+                .collect();
+            stage.is_default_written = pass.has_output(Attachment::DEFAULT_NAME);
+            stage.area = match pass.outputs.first() {
+                Some(e) => target_of(pass, &e.name).0,
+                None => extent,
+            };
+            stage.cull_mode = triangle.cull_face.to_vk();
+            stage.depth_bounds = file::Pipeline::depth_bounds_of(capabilities, pass);
+            stage.shading_rate = file::Pipeline::shading_rate_of(capabilities, pass);
+            if pass.is_scissor_dynamic {
+                let scissor = scissor.to_vk(extent.width as f32, extent.height as f32);
+                let area = vk::Rect2D {
+                    offset: vk::Offset2D::default(),
+                    extent: stage.area,
+                };
+                stage.dynamic_scissor = Some(
+                    ScissorRect {
+                        x: scissor.offset.x,
+                        y: scissor.offset.y,
+                        width: scissor.extent.width,
+                        height: scissor.extent.height,
+                    }
+                    .clamped_to(area)
+                    .unwrap_or_else(|| {
+                        panic!("scissor of pass {} misses its render area!", pass.name)
+                    }),
+                );
+            }
+            stage.per_pass_updaters = pass
+                .per_pass_updaters
+                .iter()
+                .map(|e| e.to_resource_kind())
+                .collect();
+            stage.per_instance_updaters = pass
+                .per_instance_updaters
+                .iter()
+                .map(|e| e.to_resource_kind())
+                .collect();
+            stage.per_draw_fields = pass.per_draw_fields.clone();
+            let constants = StageConstants::of_pass(pass);
+            stage.constants_size = if constants.is_empty() {
+                0
+            } else {
+                constants.size()
+            };
+            stages.push(stage);
+        }
+        Self::group_render_scopes(&mut stages, &passes);
+        Self {
+            stages,
+            variant_names,
+            budget,
+            capabilities: *capabilities,
+            is_scaled,
+            written: initialized,
+            frame: 0,
+            attachments,
+        }
+    }
+
+    /*
+     * Same grouping as the one of Pipeline::load, attachments told apart by name and
+     * the view rendered through.
+     */
+    fn group_render_scopes(stages: &mut [DryStage], passes: &[Pass]) {
+        let shapes: Vec<_> = stages
+            .iter()
+            .zip(passes)
+            .map(|(e, pass)| ScopeShape {
+                is_blit: e.blit.is_some(),
+                is_final: e.is_final,
+                is_isolated: pass.is_isolated,
+                view_mask: e.view_mask,
+                is_rate_attachment: e.shading_rate == ShadingRate::Attachment,
+                is_waiting: !e.transitions.is_empty() || !e.buffer_dependencies.is_empty(),
+                is_clearing: e
+                    .rendering
+                    .iter()
+                    .any(|e| e.load_op == vk::AttachmentLoadOp::CLEAR),
+                views: e
+                    .rendering
+                    .iter()
+                    .map(|e| (e.name.clone(), Self::view_format_of(pass, &e.name)))
+                    .collect::<Vec<_>>(),
+                rendered: pass
+                    .outputs
+                    .iter()
+                    .map(|e| e.name.clone())
+                    .chain(pass.depth_stencil.as_ref().map(|e| e.name.clone()))
+                    .collect(),
+                inputs: e.inputs.clone(),
+            })
+            .collect();
+        let starts = plan::scope_starts(&shapes);
+        for i in 1..stages.len() {
+            let first = starts[i];
+            if first == i {
+                continue;
+            }
+            stages[i].is_scope_continued = true;
+            stages[i - 1].is_scope_kept_open = true;
+            let (scope, rest) = stages.split_at_mut(i);
+            for (b, e) in scope[first].rendering.iter_mut().zip(&rest[0].rendering) {
+                b.store_op = e.store_op;
+            }
+        }
+    }
+
+    fn view_format_of(pass: &Pass, attachment: &str) -> ViewFormat {
+        pass.outputs
+            .iter()
+            .find(|e| e.name == attachment)
+            .map_or(ViewFormat::Srgb, |e| e.view_format)
+    }
+
+    ///
+    /// Graphviz DOT graph of the stages and attachments, the one Pipeline::dump_frame_graph
+    /// gives for a headless renderer of the same extent, minus the reflected blocks.
+    ///
+    pub fn dump_frame_graph(&self) -> String {
+        let stages = self.stages.iter().map(|e| e.graph_stage()).collect();
+        let graph = FrameGraph {
+            attachments: self.attachments.clone(),
+            stages,
+        };
+        graph.to_dot()
+    }
+
+    pub fn budget(&self) -> &PipelineBudget {
+        &self.budget
+    }
+
+    pub fn variant_id(&self, name: &str) -> Option<u16> {
+        self.variant_names
+            .iter()
+            .position(|e| e == name)
+            .map(|e| e as u16)
+    }
+
+    ///
+    /// Next frame, drawing the tasks the way the renderer would after they got queued.
+    /// Panics where preparing the frame would, like on tasks without the resources their
+    /// stage needs or meshes that aren't in the table.
+    ///
+    pub fn frame(&mut self, tasks: Vec<RenderTask>, meshes: &HashMap<u32, DryMesh>) -> FrameTrace {
+        let mut trace = FrameTrace::default();
+        let mut batches: HashMap<TaskKind, Vec<RenderTask>> = HashMap::new();
+        for task in tasks {
+            batches.entry(task.kind).or_default().push(task);
+        }
+        if let Some(batch) = batches.get_mut(&TaskKind::Translucent) {
+            plan::sort_back_to_front(batch);
+        }
+        let prepared: Vec<_> = self
+            .stages
+            .iter()
+            .map(|stage| {
+                let tasks = batches.get(&stage.task_kind).map_or(&[][..], |e| e);
+                Self::prepare(stage, tasks, meshes, &mut trace)
+            })
+            .collect();
+        let total_stages = self.stages.len() as u32;
+        for (i, draws) in prepared.iter().enumerate() {
+            let stage = &self.stages[i];
+            trace.ops.push(TraceOp::Wait {
+                stage: stage.name.clone(),
+                value: super::signal_value_for(self.frame, total_stages, stage.index),
+            });
+            self.record(i, draws, &mut trace);
+            self.mark_written(i);
+            let stage = &self.stages[i];
+            trace.ops.push(TraceOp::Signal {
+                stage: stage.name.clone(),
+                value: super::signal_value_for(self.frame + 1, total_stages, stage.index),
+            });
+        }
+        if self.is_scaled {
+            trace.ops.push(TraceOp::Upscale);
+        }
+        self.frame += 1;
+        trace
+    }
+
+    /*
+     * Mirrors Stage::prepare, reserving what it would in the frame region.
+     */
+    fn prepare(
+        stage: &DryStage,
+        tasks: &[RenderTask],
+        meshes: &HashMap<u32, DryMesh>,
+        trace: &mut FrameTrace,
+    ) -> Vec<(DrawState<Option<u16>>, TraceOp)> {
+        if stage.blit.is_some() {
+            return Vec::new();
+        }
+        let tasks = plan::order_tasks(tasks, stage.dynamic_scissor, |e| {
+            (stage.pipeline_for(e.variant), e.is_two_sided)
+        });
+        let mut reserve = |purpose: Reservation, bytes: u64| {
+            trace.ops.push(TraceOp::Reserve {
+                stage: stage.name.clone(),
+                purpose,
+                bytes,
+            })
+        };
+        if !stage.per_pass_updaters.is_empty() {
+            let bytes = stage.per_pass_updaters.iter().map(|e| e.resource_size());
+            reserve(Reservation::PerPass, bytes.sum::<usize>() as u64);
+        }
+        if stage.constants_size > 0 {
+            reserve(Reservation::Constants, stage.constants_size as u64);
+        }
+        let mut draws = Vec::with_capacity(tasks.len());
+        for (task, scissor) in tasks {
+            let mesh = meshes.get(&task.mesh.index).unwrap_or_else(|| {
+                panic!(
+                    "stage {} draws mesh {}, it has none!",
+                    stage.name, task.mesh
+                )
+            });
+            for kind in &stage.per_instance_updaters {
+                match task.resources.get(kind) {
+                    // Already on the GPU
+                    Some(MultiResource::SceneSlot(_)) => {}
+                    Some(_) => reserve(
+                        Reservation::PerInstance(*kind),
+                        (kind.resource_size() * task.instance_count as usize) as u64,
+                    ),
+                    None => panic!("unavailable resource kind {}", kind),
+                }
+            }
+            if stage
+                .per_draw_fields
+                .contains(&PerDrawField::PreviousTransform)
+            {
+                if !matches!(
+                    task.resources.get(&ResourceKind::Transform),
+                    Some(MultiResource::Transform(_))
+                ) {
+                    panic!(
+                        "stage {} writes {}, but the task has no {}!",
+                        stage.name,
+                        PerDrawField::PreviousTransform,
+                        ResourceKind::Transform
+                    );
+                }
+                let size = std::mem::size_of::<TransformExtra>() * task.instance_count as usize;
+                reserve(Reservation::PreviousTransforms, size as u64);
+            }
+            if stage.transform_interpolation == TransformInterpolation::Cpu {
+                let size = std::mem::size_of::<Affine>() * task.instance_count as usize;
+                reserve(Reservation::InterpolatedTransforms, size as u64);
+            }
+            let state = DrawState {
+                pipeline: stage.pipeline_for(task.variant),
+                cull_mode: plan::cull_mode_of(task, stage.cull_mode),
+                scissor,
+            };
+            let draw = TraceOp::Draw {
+                mesh: task.mesh,
+                count: mesh.count,
+                instance_count: task.instance_count,
+                is_indexed: mesh.is_indexed,
+            };
+            draws.push((state, draw));
+        }
+        draws
+    }
+
+    /*
+     * Mirrors Stage::substitute_unwritten_inputs and Stage::render.
+     */
+    fn record(
+        &self,
+        i: usize,
+        draws: &[(DrawState<Option<u16>>, TraceOp)],
+        trace: &mut FrameTrace,
+    ) {
+        let stage = &self.stages[i];
+        let name = || stage.name.clone();
+        if let Some(blit) = &stage.blit {
+            let is_source_written = self.written.contains(&blit.source);
+            if !is_source_written {
+                trace.warnings.push(format!(
+                    "stage {} blits {} before anything wrote it, it skips the blit until then",
+                    stage.name, blit.source
+                ));
+            }
+            let is_destination_written = self.written.contains(&blit.destination);
+            let is_kept =
+                |e: &&ImageTransition| is_source_written || e.attachment == blit.destination;
+            for transition in stage.transitions.iter().filter(is_kept) {
+                let mut transition = transition.clone();
+                if !is_destination_written && transition.attachment == blit.destination {
+                    transition.transition.old_layout = vk::ImageLayout::UNDEFINED;
+                }
+                trace.ops.push(TraceOp::Transition {
+                    stage: name(),
+                    transition,
+                });
+            }
+            trace.ops.push(match blit.mips {
+                Some(mips) => TraceOp::DepthPyramid {
+                    stage: name(),
+                    source: blit.source.clone(),
+                    destination: blit.destination.clone(),
+                    mips,
+                    is_skipped: !is_source_written,
+                },
+                None => TraceOp::Blit {
+                    stage: name(),
+                    source: blit.source.clone(),
+                    destination: blit.destination.clone(),
+                    is_copy: blit.is_copy,
+                    is_skipped: !is_source_written,
+                },
+            });
+            for transition in blit.after.iter().filter(is_kept) {
+                trace.ops.push(TraceOp::Transition {
+                    stage: name(),
+                    transition: transition.clone(),
+                });
+            }
+            return;
+        }
+        let inputs: Vec<_> = stage
+            .inputs
+            .iter()
+            .map(|e| self.written.contains(e).then(|| e.clone()))
+            .collect();
+        for (input, bound) in stage.inputs.iter().zip(&inputs) {
+            if bound.is_none() {
+                trace.warnings.push(format!(
+                    "stage {} samples {} before anything wrote it, it gets the default texture until then",
+                    stage.name, input
+                ));
+            }
+        }
+        if !stage.is_scope_continued {
+            let substituted: Vec<_> = stage
+                .inputs
+                .iter()
+                .zip(&inputs)
+                .filter(|e| e.1.is_none())
+                .map(|e| e.0)
+                .collect();
+            for transition in &stage.transitions {
+                if substituted.contains(&&transition.attachment) {
+                    continue;
+                }
+                trace.ops.push(TraceOp::Transition {
+                    stage: name(),
+                    transition: transition.clone(),
+                });
+            }
+            if stage.is_default_written {
+                trace.ops.push(TraceOp::Transition {
+                    stage: name(),
+                    transition: ImageTransition {
+                        attachment: Attachment::DEFAULT_NAME.to_string(),
+                        transition: if stage.is_first_default_write {
+                            Transition::DEFAULT_WRITE
+                        } else {
+                            Transition::DEFAULT_REWRITE
+                        },
+                    },
+                });
+            }
+            for dependency in &stage.buffer_dependencies {
+                trace.ops.push(TraceOp::BufferBarrier {
+                    stage: name(),
+                    buffer: dependency.name.clone(),
+                    src: dependency.src,
+                    dst: dependency.dst,
+                });
+            }
+            trace.ops.push(TraceOp::BeginRendering {
+                stage: name(),
+                area: stage.area,
+                view_mask: stage.view_mask,
+                attachments: stage.rendering.clone(),
+            });
+        }
+        trace.ops.push(TraceOp::BindDescriptors {
+            stage: name(),
+            inputs,
+        });
+        if let Some(bounds) = stage.depth_bounds {
+            trace.ops.push(TraceOp::SetDepthBounds(bounds));
+        }
+        if self.capabilities.is_shading_rate_enabled {
+            trace.ops.push(TraceOp::SetShadingRate(stage.shading_rate));
+        }
+        let mut sink = TraceSink {
+            stage: &stage.name,
+            variant_names: &self.variant_names,
+            ops: &mut trace.ops,
+        };
+        plan::record_draws(&mut sink, draws.iter().map(|e| (e.0, &e.1)));
+        if !stage.is_scope_kept_open {
+            trace.ops.push(TraceOp::EndRendering { stage: name() });
+        }
+        if stage.is_final {
+            trace.ops.push(TraceOp::Present { stage: name() });
+        }
+    }
+
+    /*
+     * Mirrors Stage::mark_written.
+     */
+    fn mark_written(&mut self, i: usize) {
+        let stage = &self.stages[i];
+        match &stage.blit {
+            Some(blit) => {
+                if self.written.contains(&blit.source) {
+                    self.written.insert(blit.destination.clone());
+                }
+            }
+            None => self.written.extend(stage.written.iter().cloned()),
+        }
+    }
+}
+
+struct TraceSink<'a> {
+    stage: &'a str,
+    variant_names: &'a [String],
+    ops: &'a mut Vec<TraceOp>,
+}
+
+impl DrawSink<Option<u16>, TraceOp> for TraceSink<'_> {
+    fn bind_pipeline(&mut self, variant: Option<u16>) {
+        self.ops.push(TraceOp::BindPipeline {
+            stage: self.stage.to_string(),
+            variant: variant.map(|e| self.variant_names[e as usize].clone()),
+        });
+    }
+
+    fn set_cull_mode(&mut self, cull_mode: vk::CullModeFlags) {
+        self.ops.push(TraceOp::SetCullMode(cull_mode));
+    }
+
+    fn set_scissor(&mut self, scissor: vk::Rect2D) {
+        self.ops.push(TraceOp::SetScissor(scissor));
+    }
+
+    fn draw(&mut self, draw: &TraceOp) {
+        self.ops.push(draw.clone());
+    }
+}
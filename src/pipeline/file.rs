@@ -1,45 +1,516 @@
+use std::{collections::BTreeMap, fmt};
+
 use ash::vk;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
 
 use super::state::*;
-use crate::{format, shader_resource::ResourceKind, UsedAsIndex};
+use crate::{
+    capabilities::DeviceFeature,
+    format,
+    shader_resource::ResourceKind,
+    spirv_patch::ShaderPatch,
+    stage_constants::ConstantType,
+    vertex_layout::{AcceptedFormats, VertexLayoutKind},
+    UsedAsIndex,
+};
 
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Pipeline {
+    // Always PIPELINE_VERSION once read, older files get migrated first
+    #[serde(default = "latest_version")]
+    pub version: u32,
     pub targets: Vec<Target>,
     pub programs: Vec<Program>,
     pub passes: Vec<Pass>,
+    // Stages inherit its compare op and depth clear value unless they set their own
+    #[serde(default)]
+    pub depth_convention: DepthConvention,
+    // Device features every stage needs, see DeviceCapabilities
+    #[serde(default)]
+    pub requires: Vec<DeviceFeature>,
+    // Patches applied to the shader files named like the keys, see spirv_patch
+    #[serde(default)]
+    pub shaders: BTreeMap<String, ShaderPatch>,
+    // Where includes not found next to the including file get looked up, in order
+    #[serde(default)]
+    pub include_paths: Vec<String>,
+    // Named buffers the renderer writes before any stage reads them, see InitialState
+    #[serde(default)]
+    pub buffers: Vec<BufferDecl>,
+}
+///
+/// Version of the pipeline files this crate reads. Files without a "version" are
+/// version 1, older ones get migrated when read, see migrate_to_latest.
+///
+pub const PIPELINE_VERSION: u32 = 2;
+fn latest_version() -> u32 {
+    PIPELINE_VERSION
 }
+///
+/// Which end of the depth range is near. Reverse puts near at 1 and far at 0, which
+/// spreads the float precision more evenly over the distance.
+///
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum DepthConvention {
+    #[default]
+    Standard,
+    Reverse,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Target {
     pub name: String,
     pub group: String,
     pub format: format::Format,
     pub width: U32OrF32,
     pub height: U32OrF32,
+    #[serde(default, rename = "memoryless")]
+    pub is_memoryless: bool,
+    // Array layers, multiview passes render one view per layer
+    #[serde(default = "default_layers")]
+    pub layers: u32,
+    // Every mip down to 1x1, only depth pyramid passes can write these
+    #[serde(default, rename = "fullMips")]
+    pub is_mip_chained: bool,
+    // Usage on top of the inferred one, for accesses the passes don't declare
+    #[serde(default)]
+    pub extra_usage: Vec<ExtraUsage>,
+    // Contents before the first frame, see InitialState
+    #[serde(default)]
+    pub initial_state: Option<InitialState>,
+    // Contents carried over into the image made for a new swapchain size, see PreserveMode
+    #[serde(default, rename = "preserveOnRecreate")]
+    pub is_preserved_on_recreate: bool,
+    #[serde(default)]
+    pub preserve_mode: PreserveMode,
+    // Of preserveMode blit, the same as the filter of blit passes
+    #[serde(default = "default_blit_filter")]
+    pub preserve_filter: Filtering,
+    // A layer per split screen viewport instead of one image they share, see split_screen
+    #[serde(default, rename = "perViewport")]
+    pub is_per_viewport: bool,
+}
+///
+/// How a target declared with preserveOnRecreate gets its contents into the image made
+/// when the swapchain changes size. Blit scales them with the preserveFilter of the
+/// target, copyCentered copies what both sizes share into the middle of the new image,
+/// for ids and depth that mean nothing scaled, clearing the rest to the initialState or
+/// zero. Discard leaves it empty like targets without preserveOnRecreate, which get a
+/// RenderEvent::TargetReset. Targets that keep their size get copied with either of the
+/// first two.
+///
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum PreserveMode {
+    #[default]
+    Blit,
+    CopyCentered,
+    Discard,
+}
+///
+/// What an attachment or named buffer holds before the first stage touching it, for
+/// ones some pass reads before any pass of the frame wrote them, like history
+/// attachments. Attachments get cleared before the first frame records its stages and
+/// left in the layout the last stage of a frame leaves them in, named buffers get filled
+/// when registered. Color attachments take clearColor, depth ones clearDepth and named
+/// buffers fill, a u32 repeated over the whole buffer.
+///
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InitialState {
+    ClearColor([f32; 4]),
+    ClearDepth(f32),
+    Fill(u32),
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BufferDecl {
+    pub name: String,
+    pub initial_state: InitialState,
 }
+///
+/// Usage an attachment can get on top of what the passes touching it need, for
+/// accesses from outside of the pipeline: reading it back, sampling it by texture id,
+/// writing it from compute or copying it out. See Pipeline::attachment_usages.
+///
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum ExtraUsage {
+    Storage,
+    Sampled,
+    TransferSrc,
+    TransferDst,
+}
+fn default_layers() -> u32 {
+    1
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct AttachmentInput {
     pub name: String,
     pub sampler: Filtering,
+    // Sampled with a comparison sampler, for shadow lookups
+    #[serde(default)]
+    pub compare: Option<CompareFunc>,
+    // Sampler the shaders read it through, the one named like the attachment if missing
+    #[serde(default)]
+    pub uniform: Option<String>,
 }
+///
+/// Attachment a pass renders into, either just its name or an object that also says
+/// whether its contents are kept once the pass ends. Stored unless told otherwise.
+///
+#[derive(Deserialize)]
+#[serde(from = "AttachmentOutputDesc")]
+pub struct AttachmentOutput {
+    pub name: String,
+    pub is_stored: bool,
+    pub is_stencil_stored: bool,
+    // Only means anything for the default attachment
+    pub view_format: ViewFormat,
+}
+///
+/// View of the swapchain image a pass writes through. Srgb is the view in the swapchain
+/// format, Unorm writes raw values into an sRGB swapchain.
+///
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ViewFormat {
+    #[default]
+    Srgb,
+    Unorm,
+}
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum AttachmentOutputDesc {
+    Name(String),
+    #[serde(rename_all = "camelCase")]
+    Configured {
+        name: String,
+        #[serde(default = "default_store")]
+        store: bool,
+        // Same as store if missing
+        stencil_store: Option<bool>,
+        #[serde(default)]
+        view_format: ViewFormat,
+    },
+}
+fn default_store() -> bool {
+    true
+}
+impl From<AttachmentOutputDesc> for AttachmentOutput {
+    fn from(desc: AttachmentOutputDesc) -> Self {
+        match desc {
+            AttachmentOutputDesc::Name(name) => Self {
+                name,
+                is_stored: true,
+                is_stencil_stored: true,
+                view_format: ViewFormat::Srgb,
+            },
+            AttachmentOutputDesc::Configured {
+                name,
+                store,
+                stencil_store,
+                view_format,
+            } => Self {
+                name,
+                is_stored: store,
+                is_stencil_stored: stencil_store.unwrap_or(store),
+                view_format,
+            },
+        }
+    }
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Pass {
     pub name: String,
+    #[serde(default, rename = "type")]
+    pub kind: PassKind,
+    // Draw passes need the program and batch, blit passes have none
+    #[serde(default)]
     pub program: String,
-    pub depth_stencil: Option<String>,
-    pub batch: crate::render_task::TaskKind,
-    pub outputs: Vec<String>,
+    pub depth_stencil: Option<AttachmentOutput>,
+    #[serde(default)]
+    pub batch: Option<crate::render_task::TaskKind>,
+    #[serde(default)]
+    pub outputs: Vec<AttachmentOutput>,
+    #[serde(default)]
     pub inputs: Vec<AttachmentInput>,
+    #[serde(default)]
     pub per_pass_updaters: Vec<UpdaterKind>,
+    #[serde(default)]
     pub per_instance_updaters: Vec<UpdaterKind>,
+    #[serde(default)]
     pub state: State,
     #[serde(default)]
     pub is_disabled: bool,
+    // StageConstants block even without constants, for the stage's debug flags
+    #[serde(default, rename = "debugFlags")]
+    pub has_debug_flags: bool,
+    // Skippable over RendererConfig::gpu_frame_budget, lower priorities first, see
+    // frame_budget
+    #[serde(default)]
+    pub budget_priority: Option<u32>,
+    // Draws once over the whole render area with split screen viewports, for passes
+    // like post processing. The others draw once per viewport, see split_screen
+    #[serde(default, rename = "sharedAcrossViewports")]
+    pub is_shared_across_viewports: bool,
+    // Where the interpolatedTransform per draw field gets blended, see motion
+    #[serde(default)]
+    pub transform_interpolation: TransformInterpolation,
+    // Specialization constant id to value, for all the shaders of the program
+    #[serde(default)]
+    pub specialization: BTreeMap<String, SpecValue>,
+    // Named permutations, each overriding some of the constants above
+    #[serde(default)]
+    pub variants: BTreeMap<String, BTreeMap<String, SpecValue>>,
+    // Template use this pass was expanded from, if any
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub per_draw_fields: Vec<PerDrawField>,
+    // Values tweakable at runtime, see stage_constants
+    #[serde(default)]
+    pub constants: Vec<ConstantDecl>,
+    // Multiview, rendered once into each of the first views layers of the targets
+    #[serde(default = "default_views")]
+    pub views: u32,
+    // Fragments outside of the stored depth range get discarded, needs depthBounds
+    #[serde(default)]
+    pub depth_bounds: Option<[f32; 2]>,
+    // Pixels each fragment shader invocation covers, 1x1 without fragment shading rate
+    #[serde(default)]
+    pub shading_rate: ShadingRate,
+    // Buffers registered with Renderer::register_named_buffer, written or read by shaders
+    #[serde(default)]
+    pub writes_buffers: Vec<String>,
+    #[serde(default)]
+    pub reads_buffers: Vec<String>,
+    // Only writer of the default attachment, straight into the swapchain image
+    #[serde(default, rename = "present")]
+    pub is_present: bool,
+    // Never shares its rendering scope with the passes next to it
+    #[serde(default, rename = "isolate")]
+    pub is_isolated: bool,
+    // Blit and depth pyramid passes only, source gets copied into destination
+    #[serde(default)]
+    pub source: Option<BlitSource>,
+    #[serde(default)]
+    pub destination: Option<String>,
+    #[serde(default = "default_blit_filter")]
+    pub filter: Filtering,
+    // Whole attachments when missing
+    #[serde(default)]
+    pub source_rect: Option<Rect>,
+    #[serde(default)]
+    pub destination_rect: Option<Rect>,
+    // Depth pyramid passes only, the farthest depth of the convention if missing
+    #[serde(default)]
+    pub reduction: Option<Reduction>,
+    // Layouts of the meshes its shaders read, tasks with meshes of others get rejected
+    #[serde(default = "default_vertex_layouts")]
+    pub vertex_layouts: Vec<VertexLayoutKind>,
+    // Formats of the attributes its shaders read, tasks with meshes in others get rejected
+    #[serde(default)]
+    pub vertex_formats: AcceptedFormats,
+    // Scissor set per task within the one of the state, see RenderTask::scissor
+    #[serde(default, rename = "dynamicScissor")]
+    pub is_scissor_dynamic: bool,
+    // Device features only this pass needs
+    #[serde(default)]
+    pub requires: Vec<DeviceFeature>,
+    // Disabled rather than failing the load when the device lacks what it requires
+    #[serde(default, rename = "optional")]
+    pub is_optional: bool,
+}
+fn default_views() -> u32 {
+    1
+}
+fn default_vertex_layouts() -> Vec<VertexLayoutKind> {
+    vec![VertexLayoutKind::Separate]
+}
+fn default_blit_filter() -> Filtering {
+    Filtering::Nearest
+}
+///
+/// What a pass does. Blit passes copy one attachment into another instead of drawing,
+/// scaling and converting the format if they have to:
+///
+///   { "name": "history", "type": "blit", "source": "lightAcc", "destination": "history" }
+///
+/// They are a plain copy when both regions have the same size and the attachments the
+/// same format. Depth pyramid passes reduce a depth attachment into every mip of a
+/// R32_SFLOAT target declared with fullMips, for occlusion culling and the like:
+///
+///   { "name": "hiZ", "type": "depthPyramid", "source": "depth", "destination": "hiZ" }
+///
+/// Downsample chains never get this far, Pipeline::read replaces them with a draw pass
+/// per level, see downsample_chain.rs.
+///
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum PassKind {
+    #[default]
+    Draw,
+    Blit,
+    DepthPyramid,
+}
+///
+/// Where the transforms set with Renderer::set_frame_transforms_interpolated get
+/// blended for the interpolatedTransform per draw field. Shader passes the addresses of
+/// both states of every object and the alpha for the shaders to blend, uploaded once a
+/// frame for all the stages. Cpu blends each instance of the task into the frame ring,
+/// the shaders read the same field with an alpha of 0. None doesn't interpolate, the
+/// stage can't write the field then.
+///
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum TransformInterpolation {
+    Shader,
+    Cpu,
+    #[default]
+    None,
+}
+///
+/// What each texel of a depth pyramid mip keeps of the texels it covers in the mip
+/// above. Culling wants the farthest depth, the max with the standard convention and
+/// the min with the reverse one.
+///
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum Reduction {
+    Min,
+    Max,
+}
+///
+/// Attachment a blit reads, either just its name or an object that also picks the mip
+/// level and array layer.
+///
+#[derive(Deserialize)]
+#[serde(from = "BlitSourceDesc")]
+pub struct BlitSource {
+    pub name: String,
+    pub mip: u32,
+    pub layer: u32,
+}
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum BlitSourceDesc {
+    Name(String),
+    Configured {
+        name: String,
+        #[serde(default)]
+        mip: u32,
+        #[serde(default)]
+        layer: u32,
+    },
+}
+impl From<BlitSourceDesc> for BlitSource {
+    fn from(desc: BlitSourceDesc) -> Self {
+        match desc {
+            BlitSourceDesc::Name(name) => Self {
+                name,
+                mip: 0,
+                layer: 0,
+            },
+            BlitSourceDesc::Configured { name, mip, layer } => Self { name, mip, layer },
+        }
+    }
+}
+///
+/// Region of an attachment in pixels.
+///
+#[derive(Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+///
+/// Shading rate of a pass. Attachment takes the rate of each region from the R8_UINT
+/// image set with Renderer::set_shading_rate_image, 1x1 wherever none is set.
+///
+#[derive(Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq, strum_macros::Display)]
+pub enum ShadingRate {
+    #[default]
+    #[serde(rename = "1x1")]
+    #[strum(serialize = "1x1")]
+    Full,
+    #[serde(rename = "2x2")]
+    #[strum(serialize = "2x2")]
+    Quarter,
+    #[serde(rename = "attachment")]
+    #[strum(serialize = "attachment")]
+    Attachment,
+}
+///
+/// Optional per task values, pushed right after the buffer addresses in the order the
+/// pass lists them.
+///
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, strum_macros::Display)]
+#[strum(serialize_all = "camelCase")]
+pub enum PerDrawField {
+    // Texture and sampler ids of the diffuse texture of the first material
+    AlbedoTexture,
+    AlphaCutoff,
+    Flags,
+    // RenderTask::view_depth, the sort key of translucent tasks
+    ViewDepth,
+    // Address of the last frame mvp of each instance, by RenderTask::object_id
+    PreviousTransform,
+    // Addresses of the previous and current simulation states and where the task's
+    // instances start in them with the alpha, see TransformInterpolation
+    InterpolatedTransform,
+    // Address of the texture feedback buffer, 0 unless the renderer keeps one
+    TextureFeedback,
+}
+///
+/// Stage constant, min and max are only passed on for building sliders:
+///
+///   { "name": "tint", "type": "vec3", "default": [1, 1, 1], "min": 0, "max": 1 }
+///
+/// Scalars take a number, vectors an array of as many numbers as components. Min and
+/// max take either for vectors, a number applies to every component.
+///
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ConstantDecl {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ConstantType,
+    pub default: ConstantComponents,
+    #[serde(default)]
+    pub min: Option<ConstantComponents>,
+    #[serde(default)]
+    pub max: Option<ConstantComponents>,
+}
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ConstantComponents {
+    Scalar(f64),
+    Vector(Vec<f64>),
 }
 #[derive(Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -56,9 +527,12 @@ pub enum UpdaterKind {
     Sky = 8,
     StaticShadow = 9,
     TransformExtra = 10,
+    LightClusters = 11,
+    ViewMatrices = 12,
+    FrameConstants = 13,
 }
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct State {
     pub writing: DescOption<WriteDesc>,
     pub depth: DescOption<DepthDesc>,
@@ -70,7 +544,7 @@ pub struct State {
     pub clearing: DescOption<ClearDesc>,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Program {
     pub name: String,
     #[serde(default)]
@@ -81,7 +555,7 @@ pub struct Program {
     pub geometry: String,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct StencilDesc {
     pub func: CompareFunc,
@@ -94,7 +568,7 @@ pub struct StencilDesc {
     pub disabled: bool,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct ScissorDesc {
     pub x: U32OrF32,
@@ -103,7 +577,7 @@ pub struct ScissorDesc {
     pub height: U32OrF32,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct ViewportDesc {
     pub x: U32OrF32,
@@ -112,7 +586,7 @@ pub struct ViewportDesc {
     pub height: U32OrF32,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct DepthDesc {
     pub func: CompareFunc,
@@ -123,7 +597,7 @@ pub struct DepthDesc {
 }
 
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct TriangleDesc {
     pub front_face: WindingOrder,
@@ -131,7 +605,7 @@ pub struct TriangleDesc {
     pub polygon_mode: PolygonMode,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct WriteDesc {
     pub color_mask: u32,
@@ -139,7 +613,7 @@ pub struct WriteDesc {
     pub stencil: bool,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct BlendDesc {
     #[serde(skip)]
@@ -148,7 +622,7 @@ pub struct BlendDesc {
     pub dst_factor: BlendFactor,
 }
 #[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 #[derive(Copy, Clone)]
 pub struct ClearDesc {
     pub color: Option<u32>,
@@ -167,6 +641,17 @@ pub enum Filtering {
 const MAX_FILTERING: u8 = Filtering::Nearest.to_u8();
 impl UsedAsIndex<MAX_FILTERING> for Filtering {}
 
+impl ExtraUsage {
+    pub fn to_vk(self) -> vk::ImageUsageFlags {
+        match self {
+            ExtraUsage::Storage => vk::ImageUsageFlags::STORAGE,
+            ExtraUsage::Sampled => vk::ImageUsageFlags::SAMPLED,
+            ExtraUsage::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            ExtraUsage::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
 impl Filtering {
     pub fn to_vk(self) -> vk::Filter {
         match self {
@@ -245,6 +730,30 @@ impl WrapMode {
     }
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+#[derive(Copy, Clone, Debug)]
+pub enum SpecValue {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+}
+
+impl SpecValue {
+    ///
+    /// All supported constant types are 4 bytes wide, booleans included (VkBool32).
+    ///
+    pub fn to_bytes(self) -> [u8; 4] {
+        match self {
+            Self::Bool(v) => (v as u32).to_ne_bytes(),
+            Self::Int(v) => v.to_ne_bytes(),
+            Self::UInt(v) => v.to_ne_bytes(),
+            Self::Float(v) => v.to_ne_bytes(),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 #[derive(Copy, Clone)]
@@ -257,6 +766,112 @@ const DEFAULT_DEPTH_CLEAR_VALUE: f32 = 1.0;
 const DEFAULT_STENCIL_CLEAR_VALUE: u32 = 0;
 const DEFAULT_COLOR_CLEAR_VALUE: u32 = 0;
 
+impl DepthConvention {
+    pub const fn clear_value(self) -> f32 {
+        match self {
+            Self::Standard => DEFAULT_DEPTH_CLEAR_VALUE,
+            Self::Reverse => 0.0,
+        }
+    }
+
+    pub const fn farthest(self) -> Reduction {
+        match self {
+            Self::Standard => Reduction::Max,
+            Self::Reverse => Reduction::Min,
+        }
+    }
+
+    pub const fn default_func(self) -> CompareFunc {
+        match self {
+            Self::Standard => CompareFunc::LessOrEqual,
+            Self::Reverse => CompareFunc::GreaterOrEqual,
+        }
+    }
+
+    ///
+    /// Whether the compare op keeps the farther fragment under this convention.
+    ///
+    pub const fn contradicts(self, func: CompareFunc) -> bool {
+        match self {
+            Self::Standard => matches!(func, CompareFunc::Greater | CompareFunc::GreaterOrEqual),
+            Self::Reverse => matches!(func, CompareFunc::Less | CompareFunc::LessOrEqual),
+        }
+    }
+}
+
+impl ShadingRate {
+    ///
+    /// Fragment size and combiner ops, the attachment replaces the rate of the pipeline.
+    ///
+    pub const fn to_vk(self) -> (vk::Extent2D, [vk::FragmentShadingRateCombinerOpKHR; 2]) {
+        use vk::FragmentShadingRateCombinerOpKHR as Op;
+        let size = match self {
+            Self::Quarter => 2,
+            Self::Full | Self::Attachment => 1,
+        };
+        let op = match self {
+            Self::Attachment => Op::REPLACE,
+            Self::Full | Self::Quarter => Op::KEEP,
+        };
+        let fragment_size = vk::Extent2D {
+            width: size,
+            height: size,
+        };
+        (fragment_size, [Op::KEEP, op])
+    }
+}
+
+impl Pass {
+    // Blit and depth pyramid destinations count as outputs and sources as inputs
+    pub fn has_output(&self, name: &str) -> bool {
+        self.outputs.iter().any(|e| e.name == name)
+            || self.destination.as_ref().is_some_and(|e| e == name)
+    }
+
+    pub fn has_input(&self, name: &str) -> bool {
+        self.inputs.iter().any(|e| e.name == name)
+            || self.source.as_ref().is_some_and(|e| e.name == name)
+    }
+
+    pub fn writes_buffer(&self, name: &str) -> bool {
+        self.writes_buffers.iter().any(|e| e == name)
+    }
+
+    pub fn reads_buffer(&self, name: &str) -> bool {
+        self.reads_buffers.iter().any(|e| e == name)
+    }
+}
+
+impl PerDrawField {
+    ///
+    /// Push constant members the field gets written into, member_size bytes each.
+    ///
+    pub const fn member_names(self) -> &'static [&'static str] {
+        match self {
+            Self::AlbedoTexture => &["albedoTexture", "albedoSampler"],
+            Self::AlphaCutoff => &["alphaCutoff"],
+            Self::Flags => &["flags"],
+            Self::ViewDepth => &["viewDepth"],
+            Self::PreviousTransform => &["previousTransform"],
+            Self::InterpolatedTransform => {
+                &["previousSimulation", "currentSimulation", "simulationBlend"]
+            }
+            Self::TextureFeedback => &["textureFeedback"],
+        }
+    }
+
+    pub const fn member_size(self) -> u32 {
+        match self {
+            Self::PreviousTransform | Self::InterpolatedTransform | Self::TextureFeedback => 8,
+            _ => 4,
+        }
+    }
+
+    pub const fn size(self) -> u32 {
+        self.member_names().len() as u32 * self.member_size()
+    }
+}
+
 impl UpdaterKind {
     pub const fn to_resource_kind(self) -> ResourceKind {
         ResourceKind::of_u32(self as u32)
@@ -417,6 +1032,12 @@ pub enum DescOption<T> {
     Configured(T),
 }
 
+impl<T> Default for DescOption<T> {
+    fn default() -> Self {
+        Self::Predefined(OptionPredefined::Default)
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[derive(Copy, Clone)]
@@ -673,3 +1294,306 @@ impl TriangleDesc {
         }
     }
 }
+
+/*
+ * Migrations from the version before each one, in order. Each only touches what changed
+ * between the two versions:
+ *
+ * 2: Attachments used to get sampled, transfer src and transfer dst usage whatever the
+ *    passes did with them, now they only get what the passes need and their
+ *    "extraUsage". Targets that aren't memoryless get those three added to their
+ *    "extraUsage", so they can still be read back, sampled by id and copied.
+ */
+type Migration = fn(&mut Map<String, Value>);
+
+const MIGRATIONS: &[(u32, Migration)] = &[(2, add_former_usage)];
+
+fn add_former_usage(file: &mut Map<String, Value>) {
+    let targets = match file.get_mut("targets").and_then(|e| e.as_array_mut()) {
+        Some(e) => e,
+        None => return,
+    };
+    for target in targets.iter_mut().filter_map(|e| e.as_object_mut()) {
+        if target.get("memoryless") == Some(&Value::Bool(true)) {
+            continue;
+        }
+        let usage = target
+            .entry("extraUsage")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Some(usage) = usage.as_array_mut() {
+            for e in ["sampled", "transferSrc", "transferDst"] {
+                if !usage.iter().any(|v| v == e) {
+                    usage.push(e.into());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MigrationError {
+    // Not JSON, with the line and column
+    Syntax(String),
+    NotAnObject,
+    // Anything but a positive integer, as written
+    InvalidVersion(String),
+    // Written for a later version of the crate
+    Unsupported { version: u32, latest: u32 },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Syntax(e) => write!(f, "pipeline file isn't valid JSON: {}", e),
+            MigrationError::NotAnObject => write!(f, "pipeline file isn't a JSON object"),
+            MigrationError::InvalidVersion(e) => {
+                write!(f, "pipeline version {} isn't a positive integer", e)
+            }
+            MigrationError::Unsupported { version, latest } => write!(
+                f,
+                "pipeline version {} is newer than {}, the latest this crate reads",
+                version, latest
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+///
+/// Pass reading an attachment or named buffer that declares no initialState before any
+/// pass of the frame wrote it, see Pipeline::uninitialized_reads.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UninitializedRead {
+    pub pass: String,
+    pub resource: String,
+    pub is_buffer: bool,
+}
+
+impl fmt::Display for UninitializedRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.is_buffer {
+            "buffer"
+        } else {
+            "attachment"
+        };
+        write!(
+            f,
+            "pass {} reads {} {} before anything wrote it, it needs an initialState",
+            self.pass, kind, self.resource
+        )
+    }
+}
+
+impl std::error::Error for UninitializedRead {}
+
+///
+/// Part of a pipeline file that doesn't match the schema. The path leads to it like
+/// passes[3].state.depth, as far in as the error can be told apart.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaError {
+    pub path: String,
+    // Of the target, program, pass or buffer the path starts at
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, self.path.is_empty()) {
+            (Some(name), _) => write!(f, "{} ({}): {}", self.path, name, self.message),
+            (None, false) => write!(f, "{}: {}", self.path, self.message),
+            (None, true) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+///
+/// Why a pipeline file couldn't be read, see Pipeline::parse.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileError {
+    Migration(MigrationError),
+    Schema(SchemaError),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Migration(e) => e.fmt(f),
+            FileError::Schema(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<MigrationError> for FileError {
+    fn from(e: MigrationError) -> Self {
+        FileError::Migration(e)
+    }
+}
+
+impl From<SchemaError> for FileError {
+    fn from(e: SchemaError) -> Self {
+        FileError::Schema(e)
+    }
+}
+
+///
+/// The pipeline file taken to PIPELINE_VERSION, for upgrading files on disk. Keys keep
+/// their order, the JSON gets indented by two spaces.
+///
+pub fn migrate_to_latest(json: &str) -> Result<String, MigrationError> {
+    let mut file: Value =
+        serde_json::from_str(json).map_err(|e| MigrationError::Syntax(e.to_string()))?;
+    migrate(&mut file)?;
+    let mut migrated = serde_json::to_string_pretty(&file).unwrap();
+    migrated.push('\n');
+    Ok(migrated)
+}
+
+/*
+ * Takes the file to PIPELINE_VERSION in place, returns the version it was written for.
+ */
+pub(super) fn migrate(file: &mut Value) -> Result<u32, MigrationError> {
+    let file = match file.as_object_mut() {
+        Some(e) => e,
+        None => return Err(MigrationError::NotAnObject),
+    };
+    let version = match file.get("version") {
+        None => 1,
+        Some(e) => match e.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(v) if v > 0 => v,
+            _ => return Err(MigrationError::InvalidVersion(e.to_string())),
+        },
+    };
+    if version > PIPELINE_VERSION {
+        return Err(MigrationError::Unsupported {
+            version,
+            latest: PIPELINE_VERSION,
+        });
+    }
+    for (_, apply) in MIGRATIONS.iter().filter(|e| e.0 > version) {
+        apply(file);
+    }
+    match file.get_mut("version") {
+        Some(e) => *e = PIPELINE_VERSION.into(),
+        None => {
+            file.shift_insert(0, "version".to_string(), PIPELINE_VERSION.into());
+        }
+    }
+    Ok(version)
+}
+
+impl Pipeline {
+    ///
+    /// Pipeline out of a file at PIPELINE_VERSION with its templates and chains expanded.
+    /// Targets, programs and passes get checked one by one so errors can name them.
+    ///
+    pub fn from_value(file: Value) -> Result<Self, SchemaError> {
+        for section in ["targets", "programs", "passes", "buffers"] {
+            let items = match file.get(section).and_then(|e| e.as_array()) {
+                Some(e) => e,
+                None => continue,
+            };
+            for (i, item) in items.iter().enumerate() {
+                let checked = match section {
+                    "targets" => check::<Target>(item),
+                    "programs" => check::<Program>(item),
+                    "buffers" => check::<BufferDecl>(item),
+                    _ => check::<Pass>(item),
+                };
+                if let Err((path, message)) = checked {
+                    return Err(SchemaError {
+                        path: format!("{}[{}]{}", section, i, path),
+                        name: item.get("name").and_then(|e| e.as_str()).map(String::from),
+                        message,
+                    });
+                }
+            }
+        }
+        let path_start = |path: String| path.trim_start_matches('.').to_string();
+        match check::<Self>(&file) {
+            Ok(_) => Ok(serde_json::from_value(file).unwrap()),
+            Err((path, message)) => Err(SchemaError {
+                path: path_start(path),
+                name: None,
+                message,
+            }),
+        }
+    }
+}
+
+/*
+ * Narrows the error down to the innermost field or element whose removal makes it go
+ * away, or turns it into that field missing. Within objects that fail as a whole, like
+ * state descriptions matching none of their forms, nothing further in can be told
+ * apart. The path comes out like .state.depth or .outputs[1].
+ */
+fn check<T: DeserializeOwned>(value: &Value) -> Result<(), (String, String)> {
+    let message = match serde_json::from_value::<T>(value.clone()) {
+        Ok(_) => return Ok(()),
+        Err(e) => e.to_string(),
+    };
+    let mut path: Vec<Segment> = Vec::new();
+    loop {
+        let children: Vec<_> = match at(value, &path) {
+            Value::Object(e) => e.keys().cloned().map(Segment::Key).collect(),
+            Value::Array(e) => (0..e.len()).map(Segment::Index).collect(),
+            _ => break,
+        };
+        let culprit = children.into_iter().find(|child| {
+            let mut trial = value.clone();
+            match (at_mut(&mut trial, &path), child) {
+                (Value::Object(e), Segment::Key(key)) => {
+                    e.shift_remove(key);
+                }
+                (Value::Array(e), Segment::Index(i)) => {
+                    e.remove(*i);
+                }
+                _ => unreachable!(),
+            }
+            match (serde_json::from_value::<T>(trial), child) {
+                (Ok(_), _) => true,
+                (Err(e), Segment::Key(key)) => e.to_string() == format!("missing field `{}`", key),
+                (Err(_), Segment::Index(_)) => false,
+            }
+        });
+        match culprit {
+            Some(e) => path.push(e),
+            None => break,
+        }
+    }
+    let path = path
+        .iter()
+        .map(|e| match e {
+            Segment::Key(key) => format!(".{}", key),
+            Segment::Index(i) => format!("[{}]", i),
+        })
+        .collect();
+    Err((path, message))
+}
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn at<'a>(value: &'a Value, path: &[Segment]) -> &'a Value {
+    path.iter().fold(value, |value, e| match e {
+        Segment::Key(key) => &value[key.as_str()],
+        Segment::Index(i) => &value[*i],
+    })
+}
+
+fn at_mut<'a>(value: &'a mut Value, path: &[Segment]) -> &'a mut Value {
+    path.iter().fold(value, |value, e| match e {
+        Segment::Key(key) => &mut value[key.as_str()],
+        Segment::Index(i) => &mut value[*i],
+    })
+}
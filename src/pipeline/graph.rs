@@ -0,0 +1,322 @@
+use std::fmt::Write;
+
+use ash::vk;
+
+use super::{attachment::Attachment, stage::Stage, Pipeline};
+use crate::render_task::TaskKind;
+
+///
+/// Stages and attachments of a frame, what dump_frame_graph writes out. Filled in from
+/// a loaded pipeline or from a dry run of a pipeline file.
+///
+pub(crate) struct FrameGraph {
+    pub attachments: Vec<GraphAttachment>,
+    pub stages: Vec<GraphStage>,
+}
+
+#[derive(Clone)]
+pub(crate) struct GraphAttachment {
+    pub name: String,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub layers: u32,
+    pub usage: vk::ImageUsageFlags,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StageKind {
+    Graphics,
+    Fullscreen,
+    Blit,
+    // Depth pyramids, dispatched on the graphics queue
+    Compute,
+}
+
+impl StageKind {
+    fn label_and_color(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Graphics => ("graphics", "lightblue"),
+            Self::Fullscreen => ("fullscreen", "lightgoldenrod"),
+            Self::Blit => ("blit", "lightsalmon"),
+            Self::Compute => ("compute", "palegreen"),
+        }
+    }
+}
+
+// Load and store op of an attachment
+pub(crate) type Ops = (vk::AttachmentLoadOp, vk::AttachmentStoreOp);
+
+pub(crate) struct GraphStage {
+    pub index: u32,
+    pub name: String,
+    pub kind: StageKind,
+    pub task_kind: TaskKind,
+    pub template: Option<String>,
+    pub view_mask: u32,
+    // Attachment name, old and new layout
+    pub barriers: Vec<(String, vk::ImageLayout, vk::ImageLayout)>,
+    // Reflected block name and size
+    pub blocks: Vec<(String, u32)>,
+    pub is_scope_continued: bool,
+    pub is_final: bool,
+    pub outputs: Vec<(String, Ops)>,
+    // Ops only if the stage writes it, tested only otherwise
+    pub depth_stencil: Option<(String, Option<Ops>)>,
+    pub inputs: Vec<String>,
+    // Verb, source and destination
+    pub blit: Option<(&'static str, String, String)>,
+}
+
+impl FrameGraph {
+    ///
+    /// Graphviz DOT graph of the frame. Nodes and edges are emitted in a stable order so
+    /// the output can be diffed between runs.
+    ///
+    pub fn to_dot(&self) -> String {
+        let mut attachments: Vec<_> = self.attachments.iter().collect();
+        attachments.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut out = String::new();
+        writeln!(out, "digraph frame {{").unwrap();
+        writeln!(out, "    rankdir=LR;").unwrap();
+        writeln!(out, "    node [fontname=\"monospace\", fontsize=10];").unwrap();
+        writeln!(out, "    edge [fontname=\"monospace\", fontsize=9];").unwrap();
+
+        for att in &attachments {
+            let layers = if att.layers > 1 {
+                format!("x{}", att.layers)
+            } else {
+                String::new()
+            };
+            writeln!(
+                out,
+                "    \"{}\" [shape=box, style=rounded, label=\"{}\\n{:?}\\n{}x{}{}\\n{:?}\"];",
+                attachment_node_id(&att.name),
+                escape(&att.name),
+                att.format,
+                att.extent.width,
+                att.extent.height,
+                layers,
+                att.usage,
+            )
+            .unwrap();
+        }
+
+        let mut scope_of = "";
+        for stage in &self.stages {
+            let (kind, color) = stage.kind.label_and_color();
+            let mut label = format!("{}: {}\\n{}", stage.index, escape(&stage.name), kind);
+            if stage.blit.is_none() {
+                label.push_str(&format!("\\nbatch: {}", stage.task_kind));
+            }
+            if let Some(template) = &stage.template {
+                label.push_str(&format!("\\nfrom: {}", escape(template)));
+            }
+            if stage.view_mask != 0 {
+                label.push_str(&format!("\\nviews: {}", stage.view_mask.count_ones()));
+            }
+            for (name, old_layout, new_layout) in &stage.barriers {
+                label.push_str(&format!(
+                    "\\n{}: {:?} -> {:?}",
+                    escape(name),
+                    old_layout,
+                    new_layout
+                ));
+            }
+            for (name, size) in &stage.blocks {
+                label.push_str(&format!("\\n{}: {} bytes", escape(name), size));
+            }
+            if stage.is_scope_continued {
+                label.push_str(&format!("\\nin scope of: {}", escape(scope_of)));
+            } else {
+                scope_of = &stage.name;
+            }
+            if stage.is_final {
+                label.push_str("\\npresents");
+            }
+            writeln!(
+                out,
+                "    \"{}\" [shape=ellipse, style=filled, fillcolor=\"{}\", label=\"{}\"];",
+                stage_node_id(stage.index),
+                color,
+                label
+            )
+            .unwrap();
+        }
+
+        for stage in &self.stages {
+            let stage_id = stage_node_id(stage.index);
+            for (name, (load_op, store_op)) in &stage.outputs {
+                writeln!(
+                    out,
+                    "    \"{}\" -> \"{}\" [label=\"write\\n{:?}/{:?}\"];",
+                    stage_id,
+                    attachment_node_id(name),
+                    load_op,
+                    store_op
+                )
+                .unwrap();
+            }
+            match &stage.depth_stencil {
+                Some((name, Some((load_op, store_op)))) => writeln!(
+                    out,
+                    "    \"{}\" -> \"{}\" [label=\"write depth\\n{:?}/{:?}\"];",
+                    stage_id,
+                    attachment_node_id(name),
+                    load_op,
+                    store_op
+                )
+                .unwrap(),
+                Some((name, None)) => writeln!(
+                    out,
+                    "    \"{}\" -> \"{}\" [style=dashed, label=\"test depth\"];",
+                    attachment_node_id(name),
+                    stage_id,
+                )
+                .unwrap(),
+                None => {}
+            }
+            for name in &stage.inputs {
+                writeln!(
+                    out,
+                    "    \"{}\" -> \"{}\" [label=\"read\"];",
+                    attachment_node_id(name),
+                    stage_id,
+                )
+                .unwrap();
+            }
+            if let Some((verb, source, destination)) = &stage.blit {
+                writeln!(
+                    out,
+                    "    \"{}\" -> \"{}\" [label=\"{} from\"];",
+                    attachment_node_id(source),
+                    stage_id,
+                    verb
+                )
+                .unwrap();
+                writeln!(
+                    out,
+                    "    \"{}\" -> \"{}\" [label=\"{} into\"];",
+                    stage_id,
+                    attachment_node_id(destination),
+                    verb
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl Pipeline {
+    ///
+    /// Describes the stages and attachments of the pipeline as a Graphviz DOT
+    /// graph. Nodes and edges are emitted in a stable order so the output can
+    /// be diffed between runs.
+    ///
+    pub fn dump_frame_graph(&self) -> String {
+        self.frame_graph().to_dot()
+    }
+
+    pub fn dump_frame_graph_to_file<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.dump_frame_graph())
+    }
+
+    fn frame_graph(&self) -> FrameGraph {
+        let attachments = self
+            .attachments
+            .iter()
+            .map(|e| GraphAttachment {
+                name: e.name.clone(),
+                format: e.vk_format,
+                extent: e.extent,
+                layers: e.layers,
+                usage: e.usage,
+            })
+            .collect();
+        let stages = self.stages.iter().map(|e| self.graph_stage_of(e)).collect();
+        FrameGraph {
+            attachments,
+            stages,
+        }
+    }
+
+    fn graph_stage_of(&self, stage: &Stage) -> GraphStage {
+        let barriers = stage
+            .image_barriers
+            .iter()
+            .map(|e| {
+                let name = self
+                    .attachment_by_image(e.image)
+                    .map_or("?", |e| e.name.as_str());
+                (name.to_string(), e.old_layout, e.new_layout)
+            })
+            .collect();
+        let blocks = stage
+            .reflection
+            .blocks
+            .iter()
+            .map(|e| (e.name.clone(), e.size))
+            .collect();
+        let outputs = stage
+            .outputs
+            .iter()
+            .zip(&stage.rendering.attachments)
+            .map(|(att, info)| (att.name.clone(), (info.load_op, info.store_op)))
+            .collect();
+        let depth_stencil = match (&stage.depth_stencil, &stage.rendering.depth_stencil) {
+            (Some(att), Some(info)) => Some((
+                att.name.clone(),
+                stage
+                    .is_depth_stencil_written
+                    .then_some((info.load_op, info.store_op)),
+            )),
+            _ => None,
+        };
+        let kind = match (&stage.blit, stage.task_kind) {
+            (Some(blit), _) if blit.pyramid.is_some() => StageKind::Compute,
+            (Some(_), _) => StageKind::Blit,
+            (None, TaskKind::Fullscreen) => StageKind::Fullscreen,
+            (None, _) => StageKind::Graphics,
+        };
+        GraphStage {
+            index: stage.index,
+            name: stage.name.clone(),
+            kind,
+            task_kind: stage.task_kind,
+            template: stage.template.clone(),
+            view_mask: stage.view_mask,
+            barriers,
+            blocks,
+            is_scope_continued: stage.is_scope_continued,
+            is_final: stage.is_final,
+            outputs,
+            depth_stencil,
+            inputs: stage.inputs.iter().map(|e| e.name.clone()).collect(),
+            blit: stage
+                .blit
+                .as_ref()
+                .map(|e| (e.verb(), e.source.name.clone(), e.destination.name.clone())),
+        }
+    }
+
+    fn attachment_by_image(&self, image: vk::Image) -> Option<&Attachment> {
+        self.attachments.iter().find(|e| e.image == image)
+    }
+}
+
+fn stage_node_id(index: u32) -> String {
+    format!("stage_{}", index)
+}
+
+fn attachment_node_id(name: &str) -> String {
+    format!("attachment_{}", escape(name))
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,58 @@
+///
+/// Things in the pipeline description that work as they are but cost more than they
+/// need to, or likely aren't what was meant.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptimizationHint {
+    // Stage stores an attachment nothing loads or samples before it's overwritten
+    UnreadStore {
+        stage: String,
+        attachment: String,
+        is_stencil: bool,
+    },
+    // Stage depth test keeps the farther fragments under the pipeline depth convention
+    DepthConventionMismatch {
+        stage: String,
+        func: String,
+        convention: String,
+    },
+    // Optional stage disabled since the device lacks features it requires
+    MissingFeatures {
+        stage: String,
+        features: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for OptimizationHint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptimizationHint::UnreadStore {
+                stage,
+                attachment,
+                is_stencil,
+            } => write!(
+                f,
+                "stage {} stores the {} of attachment {} but nothing reads it, \"{}\": false would discard it",
+                stage,
+                if *is_stencil { "stencil" } else { "contents" },
+                attachment,
+                if *is_stencil { "stencilStore" } else { "store" }
+            ),
+            OptimizationHint::DepthConventionMismatch {
+                stage,
+                func,
+                convention,
+            } => write!(
+                f,
+                "stage {} compares depth with {} but the pipeline uses the {} depth convention",
+                stage, func, convention
+            ),
+            OptimizationHint::MissingFeatures { stage, features } => write!(
+                f,
+                "optional stage {} is disabled, the device lacks {}",
+                stage,
+                features.join(", ")
+            ),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+/*
+ * Shaders get their includes pasted in before compiling, so the pipeline knows which
+ * files each shader is made of. A line like
+ *   #include "relative/path.glsl"
+ * gets replaced by the file, looked up next to the including file first and then in the
+ * include paths of the pipeline, in order. The <path.glsl> form skips the first look up.
+ * Conditionals aren't evaluated, includes in branches not taken still get pasted in and
+ * count as dependencies.
+ *
+ * Files guarded by #pragma once get pasted in only the first time. Files with a classic
+ * guard, an #ifndef and #define of the same macro before anything else, get pasted in
+ * every time and the compiler skips them, unless they are being included already. Files
+ * without a guard being included already are a cycle.
+ */
+
+const PRAGMA_ONCE: &str = "#pragma once";
+
+///
+/// Shader source with its includes pasted in.
+///
+#[derive(Clone, Debug)]
+pub struct Flattened {
+    pub source: String,
+    // The shader first, then every file it included in the order they were found
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IncludeError {
+    // Chain of includes leading to the one that wasn't found
+    Missing {
+        include: String,
+        chain: Vec<PathBuf>,
+    },
+    // Ends with the file the chain started from
+    Cycle {
+        chain: Vec<PathBuf>,
+    },
+    Unreadable {
+        path: PathBuf,
+        error: String,
+    },
+    Malformed {
+        path: PathBuf,
+        line: usize,
+    },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IncludeError::Missing { include, chain } => write!(
+                f,
+                "couldn't find include \"{}\" of {}",
+                include,
+                chain_of(chain)
+            ),
+            IncludeError::Cycle { chain } => write!(f, "include cycle {}", chain_of(chain)),
+            IncludeError::Unreadable { path, error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            }
+            IncludeError::Malformed { path, line } => {
+                write!(f, "malformed #include at {}:{}", path.display(), line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+fn chain_of(chain: &[PathBuf]) -> String {
+    let files: Vec<_> = chain.iter().map(|e| e.display().to_string()).collect();
+    files.join(" -> ")
+}
+
+///
+/// Reads the shader at the path and pastes its includes in, see the top of this file.
+///
+pub fn flatten(path: &Path, search_paths: &[PathBuf]) -> Result<Flattened, IncludeError> {
+    let mut flattener = Flattener {
+        search_paths,
+        chain: Vec::new(),
+        pasted_once: HashSet::new(),
+        files: Vec::new(),
+    };
+    let mut source = String::new();
+    flattener.paste(&normalize(path), &mut source)?;
+    Ok(Flattened {
+        source,
+        files: flattener.files,
+    })
+}
+
+///
+/// Drops the "." and resolves the ".." components of the path without touching the
+/// file system, so the same file reached through different includes gets the same path.
+///
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                let is_popped = matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) && normalized.pop();
+                if !is_popped {
+                    normalized.push("..");
+                }
+            }
+            e => normalized.push(e),
+        }
+    }
+    normalized
+}
+
+struct Flattener<'a> {
+    search_paths: &'a [PathBuf],
+    // Files being pasted in, outermost first
+    chain: Vec<PathBuf>,
+    pasted_once: HashSet<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Flattener<'_> {
+    fn paste(&mut self, path: &Path, out: &mut String) -> Result<(), IncludeError> {
+        let source = std::fs::read_to_string(path).map_err(|e| IncludeError::Unreadable {
+            path: path.to_path_buf(),
+            error: e.to_string(),
+        })?;
+        let guard = guard_of(&source);
+        if self.chain.iter().any(|e| e == path) {
+            if guard != Guard::None {
+                return Ok(());
+            }
+            let mut chain = self.chain.clone();
+            chain.push(path.to_path_buf());
+            return Err(IncludeError::Cycle { chain });
+        }
+        if guard == Guard::PragmaOnce && !self.pasted_once.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        if !self.files.iter().any(|e| e == path) {
+            self.files.push(path.to_path_buf());
+        }
+        self.chain.push(path.to_path_buf());
+        for (i, line) in source.lines().enumerate() {
+            let directive = line.trim();
+            if directive == PRAGMA_ONCE {
+                continue;
+            }
+            match include_of(directive) {
+                None => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                Some(None) => {
+                    return Err(IncludeError::Malformed {
+                        path: path.to_path_buf(),
+                        line: i + 1,
+                    })
+                }
+                Some(Some((name, is_relative))) => {
+                    let found = self.find(path, name, is_relative).ok_or_else(|| {
+                        IncludeError::Missing {
+                            include: name.to_string(),
+                            chain: self.chain.clone(),
+                        }
+                    })?;
+                    self.paste(&found, out)?;
+                }
+            }
+        }
+        self.chain.pop();
+        Ok(())
+    }
+
+    fn find(&self, includer: &Path, name: &str, is_relative: bool) -> Option<PathBuf> {
+        let dir = includer.parent().unwrap_or(Path::new(""));
+        is_relative
+            .then(|| dir.to_path_buf())
+            .into_iter()
+            .chain(self.search_paths.iter().cloned())
+            .map(|e| normalize(&e.join(name)))
+            .find(|e| e.is_file())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Guard {
+    None,
+    PragmaOnce,
+    Macro,
+}
+
+fn guard_of(source: &str) -> Guard {
+    if source.lines().any(|e| e.trim() == PRAGMA_ONCE) {
+        return Guard::PragmaOnce;
+    }
+    // First two directives, skipping blank lines and comments
+    let mut directives = source
+        .lines()
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && !e.starts_with("//"));
+    let ifndef = directives.next().and_then(|e| macro_of(e, "ifndef"));
+    let define = directives.next().and_then(|e| macro_of(e, "define"));
+    match (ifndef, define) {
+        (Some(a), Some(b)) if a == b => Guard::Macro,
+        _ => Guard::None,
+    }
+}
+
+fn macro_of<'a>(line: &'a str, directive: &str) -> Option<&'a str> {
+    let rest = line
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix(directive)?;
+    let mut words = rest.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(name), None) if rest.starts_with(char::is_whitespace) => Some(name),
+        _ => None,
+    }
+}
+
+/*
+ * None for lines that aren't includes, Some(None) for malformed ones, otherwise the
+ * name and whether it's looked up next to the including file first.
+ */
+fn include_of(line: &str) -> Option<Option<(&str, bool)>> {
+    let rest = line
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("include")?;
+    if !rest.starts_with(|e: char| e.is_whitespace() || e == '"' || e == '<') {
+        // Some other directive starting like it
+        return None;
+    }
+    // Paths can't have two slashes in a row, whatever follows them is a comment
+    let rest = rest.split("//").next().unwrap_or("").trim();
+    let name = if let Some(e) = rest.strip_prefix('"') {
+        e.strip_suffix('"').map(|e| (e, true))
+    } else if let Some(e) = rest.strip_prefix('<') {
+        e.strip_suffix('>').map(|e| (e, false))
+    } else {
+        None
+    };
+    Some(name.filter(|e| !e.0.is_empty() && !e.0.contains(['"', '<', '>'])))
+}
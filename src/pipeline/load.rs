@@ -1,53 +1,150 @@
 use ash::vk::{self, DescriptorType, ShaderStageFlags};
 
 use std::{
-    collections::{HashMap, HashSet},
-    process::Command,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
 };
 
 use super::{
-    descriptor::DescriptorBuffer,
+    budget::{table_share_of, BudgetLimits},
+    depth_pyramid::{self, DepthPyramid},
+    descriptor::{
+        DescriptorBackend, DescriptorBuffer, SlotLayout, SET_ATTACHMENTS, SET_IMAGES, SET_SAMPLERS,
+    },
+    descriptor_set::DescriptorSets,
+    downsample_chain,
     file::*,
-    sampler::{Sampler, SamplerKey},
+    hints::OptimizationHint,
+    include::{self, Flattened, IncludeError},
+    per_draw::{Condition, PerDrawLayout},
+    plan::{self, ScopeShape},
+    preserve::PreservedTarget,
+    sampler::{Sampler, SamplerKey, SamplerPolicy},
+    specialization::Specialization,
+    stage::{Blit, Stage},
+    template,
 };
+use crate::capabilities::{DeviceCapabilities, DeviceFeature, MissingFeatures};
+use crate::debug_flags;
+use crate::format::Format;
+use crate::reflection::{BindingKind, BindingMismatch, DescriptorBinding, ShaderReflection};
+use crate::render_core::{CoreTables, RenderCore};
+use crate::render_task::{ScissorRect, TaskKind};
 use crate::shader;
-use crate::texture::MipMap;
-use crate::{buffer::DeviceAllocator, pipeline::attachment::Attachment, renderer::Renderer};
+use crate::shader_resource::ViewMatrices;
+use crate::stage_constants::{self, StageConstants};
+use crate::texture::{MipMap, TextureDesc};
+use crate::vertex_layout::{AcceptedFormats, VertexAttributeKind};
+use crate::{buffer::DeviceAllocator, pipeline::attachment::Attachment};
 use crate::{context::VulkanContext, texture};
 
+pub const MAX_PUSH_CONSTANTS_SIZE: u32 = 128;
+// Push constant block the shaders declare through INPUTS_BEGIN
+const REGISTERS_BLOCK: &str = "Registers";
+// Slots of the image table, one per texture id, fewer if their descriptors don't fit
+pub(super) const IMAGE_CAPACITY: u32 = 1024;
+// Where shaders are looked up by the name programs give them
+const SHADER_DIR: &str = "shader";
+// Shaders with their includes pasted in get compiled from here
+const FLATTENED_DIR: &str = "shader/generated/flat";
+
+/*
+ * Where a blit or depth pyramid stage goes in the pipeline.
+ */
+struct TransferSlot {
+    total_variants: usize,
+    index: u32,
+    is_validation_layer_enabled: bool,
+}
+
 impl Pipeline {
     pub fn read(name: Option<&str>) -> Self {
         let name = name.unwrap_or("pipeline.json");
-        let file = std::fs::File::open(name)
+        let json = std::fs::read_to_string(name)
             .expect(format!("failed opening the pipeline at {}", name).as_str());
-        return serde_json::from_reader(file)
-            .expect(format!("couldn't parse the pipeline at {}", name).as_str());
+        let base_dir = std::path::Path::new(name)
+            .parent()
+            .unwrap_or(std::path::Path::new(""));
+        match Self::parse(&json, base_dir) {
+            Ok(e) => e,
+            Err(e) => panic!("couldn't load the pipeline at {}: {}", name, e),
+        }
+    }
+
+    ///
+    /// Pipeline out of the contents of a pipeline file, templates get looked up relative
+    /// to base_dir. Files written for an older version get migrated on the way, with a
+    /// warning.
+    ///
+    pub fn parse(json: &str, base_dir: &Path) -> Result<Self, FileError> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| MigrationError::Syntax(e.to_string()))?;
+        let version = migrate(&mut value)?;
+        if version < PIPELINE_VERSION {
+            log::warn!(
+                "pipeline file at version {} migrated to {}, migrate_to_latest upgrades it for good",
+                version,
+                PIPELINE_VERSION
+            );
+        }
+        template::expand(&mut value, base_dir);
+        // After the templates, their passes can be chains too
+        downsample_chain::expand(&mut value);
+        Ok(Self::from_value(value)?)
     }
 
+    ///
+    /// Makes the pipeline of the file at name, the default one without. Targets declared
+    /// perViewport get viewport_layers layers, one per split screen viewport.
+    ///
+    #[allow(clippy::too_many_arguments)]
     pub fn load(
-        ctx: &VulkanContext,
-        descriptor_mem: &mut DeviceAllocator,
+        core: &RenderCore,
+        mut descriptor_mem: Option<&mut DeviceAllocator>,
         default_attachment: Attachment,
         is_validation_layer_enabled: bool,
         name: Option<&str>,
+        is_scaled: bool,
+        viewport_layers: u32,
+        limits: &BudgetLimits,
     ) -> crate::pipeline::Pipeline {
-        let pip = Self::read(name);
-        let shaders_by_name: HashMap<_, _> = pip
-            .programs
-            .iter()
-            .map(|p| vec![&p.fragment, &p.vertex, &p.geometry])
-            .flatten()
-            .filter(|f| !f.is_empty())
-            // Same shader could be used in multiple programs.
-            .collect::<HashSet<_>>()
-            .iter()
+        let ctx = &core.vulkan_context;
+        let mut pip = Self::read(name);
+        Self::validate_per_viewport_targets(&pip.targets, &pip.passes);
+        for target in pip.targets.iter_mut().filter(|e| e.is_per_viewport) {
+            target.layers = viewport_layers;
+        }
+        let requirement_hints = pip
+            .match_requirements(&ctx.capabilities)
+            .unwrap_or_else(|e| panic!("{}", e));
+        // Before compiling or allocating anything
+        let budget = pip
+            .budget(default_attachment.extent, limits)
+            .unwrap_or_else(|e| panic!("{}", e));
+        let flattened = pip.flatten_shaders().unwrap_or_else(|e| panic!("{}", e));
+        let shader_dependents = pip.dependents_of(&flattened);
+        let shaders_by_name: HashMap<_, _> = flattened
+            .keys()
             .map(|f| (f.clone(), format!("shader/{f}.spv")))
             .collect();
         for src_out in &shaders_by_name {
             let name = src_out.0;
+            // Includes are pasted in already, keeps the extension telling the stage apart
+            let src = format!("{FLATTENED_DIR}/{name}");
+            // Same source as the last compile that went through, like when loaded again on resize
+            let modified = |path: &str| std::fs::metadata(path).and_then(|e| e.modified()).ok();
+            let is_compiled = modified(src_out.1) >= modified(&src)
+                && std::fs::read_to_string(&src).is_ok_and(|e| e == flattened[name].source);
+            if is_compiled {
+                continue;
+            }
+            let src_dir = std::path::Path::new(&src).parent().unwrap();
+            std::fs::create_dir_all(src_dir)
+                .and_then(|_| std::fs::write(&src, &flattened[name].source))
+                .unwrap_or_else(|e| panic!("failed writing {}, error {}", src, e));
             // Some flags so the various macros work
             let args = [
-                &format!("shader/{}", name),
+                &src,
                 "-V",
                 "-DIS_VULKAN=1",
                 "-DIS_EXTERNAL_COMPILER=1",
@@ -56,36 +153,19 @@ impl Pipeline {
                 "-o",
                 &src_out.1,
             ];
-            log::info!("compiling shader {} with args {:?}...", name, args);
-            let res = Command::new("glslangValidator")
-                .args(args)
-                .spawn()
-                .expect(format!("Failed to start {}", &name).as_str())
-                // TODO: Could launch all of these these concurrently and wait for them all.
-                .wait();
-            if res.is_err() {
-                panic!(
-                    "Error compiling shader {}, error {}",
-                    name,
-                    res.unwrap_err()
-                )
-            }
-            match res {
-                Err(e) => {
-                    panic!("Error compiling shader {}, error {}", name, e)
-                }
-                Ok(e) if !e.success() => {
-                    panic!("Failed compiling shader {}, error {}", name, e);
-                }
-                _ => {}
+            shader::compile_glsl(name, &args);
+        }
+        for name in pip.shaders.keys() {
+            if !shaders_by_name.contains_key(name) {
+                log::warn!("patch for shader {} that no program uses", name);
             }
-            log::info!("shader {} compiled!", name);
         }
         let load_shader = |name: &String| {
             shaders_by_name.get(name).map(|v| {
                 (
                     v.clone(),
                     std::fs::File::open(v).expect(format!("failed opening {v}").as_str()),
+                    pip.shaders.get(name),
                 )
             })
         };
@@ -107,29 +187,64 @@ impl Pipeline {
             .collect();
         let window_width = default_attachment.extent.width;
         let window_height = default_attachment.extent.height;
+        let usages = pip.attachment_usages();
+        let uninitialized_reads = pip.uninitialized_reads();
         let mut attachments_by_name: HashMap<_, _> = pip
             .targets
             .iter()
-            .map(|f| {
+            .zip(usages)
+            .map(|(f, (_, usage))| {
                 let extent =
                     Self::extent_of(f.width, f.height, window_width as f32, window_height as f32);
+                let mip_extents = if f.is_mip_chained {
+                    depth_pyramid::mip_extents(extent.width, extent.height)
+                } else {
+                    vec![[extent.width, extent.height]]
+                };
+                let mip_maps: Vec<_> = mip_extents
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| MipMap {
+                        index: i as u32,
+                        width: e[0],
+                        height: e[1],
+                        ..Default::default()
+                    })
+                    .collect();
+                // Attachments are few and big, they get their own allocations
                 let texture = texture::make(
                     &ctx,
-                    0,
-                    f.name.clone(),
-                    &[MipMap {
-                        width: extent.width,
-                        height: extent.height,
-                        ..Default::default()
-                    }],
-                    f.format,
-                    true,
+                    None,
+                    TextureDesc {
+                        id: 0,
+                        name: f.name.clone(),
+                        mip_maps: &mip_maps,
+                        layers: f.layers,
+                        is_cube: false,
+                        format: f.format,
+                        attachment_usage: Some(usage),
+                        is_storage: false,
+                        is_transient: f.is_memoryless,
+                        shared_with: &[],
+                        srgb_id: None,
+                    },
                     None,
                 );
 
                 ctx.try_set_debug_name(&format!("{}_{}", f.name, "image"), texture.image);
-                ctx.try_set_debug_name(&format!("{}_{}", f.name, "memory"), texture.memory);
+                ctx.try_set_debug_name(
+                    &format!("{}_{}", f.name, "memory"),
+                    texture.allocation.memory,
+                );
                 ctx.try_set_debug_name(&format!("{}_{}", f.name, "view"), texture.view);
+                let viewport_views: Vec<_> = (0..f.layers)
+                    .filter(|_| f.is_per_viewport)
+                    .map(|layer| {
+                        let view = Self::layer_view_of(ctx, texture.image, f.format, layer);
+                        ctx.try_set_debug_name(&format!("{}_view_{}", f.name, layer), view);
+                        view
+                    })
+                    .collect();
                 return (
                     &f.name,
                     Attachment {
@@ -137,11 +252,19 @@ impl Pipeline {
                         format: f.format,
                         vk_format: f.format.to_vk(),
                         image: texture.image,
-                        memory: texture.memory,
+                        memory: texture.allocation.memory,
                         view: texture.view,
                         extent,
                         descriptor_offset: 0,
                         descriptor_index: 0,
+                        is_memoryless: f.is_memoryless,
+                        memory_flags: texture.allocation.memory_flags,
+                        layers: f.layers,
+                        mips: mip_maps.len() as u32,
+                        unorm_view: None,
+                        usage,
+                        is_shared_present: false,
+                        viewport_views,
                     },
                 );
             })
@@ -149,40 +272,191 @@ impl Pipeline {
         let default_attachment_name = Attachment::DEFAULT_NAME.to_string();
         // Default attachment is provided by the caller since it depends on the swapchain.
         attachments_by_name.insert(&default_attachment_name, default_attachment);
+        for target in pip.targets.iter().filter_map(PreservedTarget::of) {
+            Self::validate_preserve_format(ctx, &target, &attachments_by_name[&target.name]);
+        }
         // If there are no inputs whatsoever, just use a dummy one sized buffer.
+        let depth_convention = pip.depth_convention;
         let enabled_passes: Vec<_> = pip.passes.into_iter().filter(|e| !e.is_disabled).collect();
-        let image_descriptors = Self::image_desc_buffer(ctx, descriptor_mem);
-        let mut sampler_descriptors =
-            Self::sampler_desc_buffer(ctx, descriptor_mem, Renderer::MAX_SAMPLERS);
-
-        let mut samplers_by_key: HashMap<SamplerKey, Sampler> = HashMap::new();
+        Self::validate_initial_states(&pip.targets, &pip.buffers, &enabled_passes);
+        Self::validate_memoryless_targets(&pip.targets, &enabled_passes);
+        Self::validate_blit_attachments(&enabled_passes);
+        Self::validate_mip_chained_targets(&pip.targets, &enabled_passes);
+        Self::validate_preserved_targets(&pip.targets);
+        Self::validate_vertex_formats(&enabled_passes);
+        let mut optimization_hints = requirement_hints;
+        optimization_hints.extend(Self::validate_store_ops(
+            &pip.targets,
+            &enabled_passes.iter().collect::<Vec<_>>(),
+        ));
+        optimization_hints.extend(Self::validate_depth_convention(
+            depth_convention,
+            &enabled_passes.iter().collect::<Vec<_>>(),
+        ));
+        for hint in &optimization_hints {
+            log::info!("{}", hint);
+        }
+        // Without a descriptor allocator the tables go into classic descriptor sets
+        let pipeline_flags = if descriptor_mem.is_some() {
+            vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT
+        } else {
+            vk::PipelineCreateFlags::empty()
+        };
+        // Made by the first pipeline of the core, with as many ids as its budget found room for
+        let tables = core.tables_or_make(|| {
+            let (image_capacity, sampler_capacity) =
+                (budget.image_capacity, budget.sampler_capacity);
+            if image_capacity < IMAGE_CAPACITY {
+                log::warn!(
+                    "image table holds {} of {} texture ids, descriptors of {} bytes take too much",
+                    image_capacity,
+                    IMAGE_CAPACITY,
+                    limits.descriptor_sizes.sampled_image
+                );
+            }
+            // In descriptor memory of the core, as much as the tables could take of the allocator
+            let descriptor_bytes = limits.descriptor_allocator.map(|e| table_share_of(e.size));
+            CoreTables::new(ctx, descriptor_bytes, image_capacity, sampler_capacity)
+        });
+        let image_capacity = tables.image_descriptors.capacity();
+        let sampler_capacity = tables.sampler_capacity;
+        let table_layouts = [
+            tables.sampler_descriptors.layout(),
+            tables.image_descriptors.layout(),
+        ];
+        // Not held while loading, a pipeline that fails to load would poison it
+        drop(tables);
 
+        // Variant ids are shared by all stages, a stage without some variant uses its base pipeline
+        let mut variant_names: Vec<String> = enabled_passes
+            .iter()
+            .flat_map(|e| e.variants.keys().cloned())
+            .collect();
+        variant_names.sort();
+        variant_names.dedup();
+        if variant_names.len() > u16::MAX as usize {
+            panic!("too many variants, {} declared!", variant_names.len());
+        }
+        // Several passes can write to the default attachment, the last one presents
+        let default_passes: Vec<_> = enabled_passes
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.has_output(Attachment::DEFAULT_NAME))
+            .map(|(i, _)| i)
+            .collect();
+        Self::validate_present(&enabled_passes, &default_passes);
+        let first_default_pass = default_passes.first().copied();
+        let last_default_pass = default_passes.last().copied();
         let mut stages = Vec::<_>::with_capacity(enabled_passes.len());
         let mut stage_index = 0u32;
+        let slot_of = |index| TransferSlot {
+            total_variants: variant_names.len(),
+            index,
+            is_validation_layer_enabled,
+        };
         for (passi, pass) in enabled_passes.iter().enumerate() {
+            if pass.kind == PassKind::Blit {
+                stages.push(Self::blit_stage_of(
+                    ctx,
+                    passi,
+                    &enabled_passes,
+                    &attachments_by_name,
+                    &slot_of(stage_index),
+                ));
+                stage_index += 1;
+                continue;
+            }
+            if pass.kind == PassKind::DepthPyramid {
+                stages.push(Self::depth_pyramid_stage_of(
+                    ctx,
+                    passi,
+                    &enabled_passes,
+                    &attachments_by_name,
+                    depth_convention,
+                    &shaders_by_name[depth_pyramid::SHADER_NAME],
+                    &slot_of(stage_index),
+                ));
+                stage_index += 1;
+                continue;
+            }
+            let batch = pass
+                .batch
+                .unwrap_or_else(|| panic!("pass {} has no batch!", pass.name));
             let writing = Self::handle_option(pass.state.writing.clone());
-            let depth = Self::handle_option(pass.state.depth.clone());
+            let depth = Self::depth_of(pass, depth_convention);
             let blending = Self::handle_option(pass.state.blending.clone());
             let stencil = Self::handle_option(pass.state.stencil.clone());
             let viewport = Self::handle_option(pass.state.viewport.clone());
             let scissor = Self::handle_option(pass.state.scissor.clone());
             let triangle = Self::handle_option(pass.state.triangle.clone());
-            let clearing = Self::handle_option(pass.state.clearing.clone());
+            let clearing = Self::clearing_of(pass, depth_convention);
             let stencil_op_state = stencil.to_vk();
-            let depth_stencil_state = depth.to_vk(stencil_op_state, &writing);
+            let mut depth_stencil_state = depth.to_vk(stencil_op_state, &writing);
+            let depth_bounds = Self::depth_bounds_of(&ctx.capabilities, pass);
+            // Viewport and scissor too, offscreen passes draw at the size of their target
+            let mut dynamic_states = vec![
+                vk::DynamicState::CULL_MODE,
+                vk::DynamicState::VIEWPORT,
+                vk::DynamicState::SCISSOR,
+            ];
+            if let Some([min, max]) = depth_bounds {
+                // Bounds can be changed at runtime, these are only the initial ones
+                depth_stencil_state.depth_bounds_test_enable = 1;
+                depth_stencil_state.min_depth_bounds = min;
+                depth_stencil_state.max_depth_bounds = max;
+                dynamic_states.push(vk::DynamicState::DEPTH_BOUNDS);
+            }
+            let shading_rate = Self::shading_rate_of(&ctx.capabilities, pass);
+            let mut shading_rate_state = ctx.extension.fragment_shading_rate.is_some().then(|| {
+                dynamic_states.push(vk::DynamicState::FRAGMENT_SHADING_RATE_KHR);
+                let (fragment_size, combiner_ops) = shading_rate.to_vk();
+                vk::PipelineFragmentShadingRateStateCreateInfoKHR {
+                    fragment_size,
+                    combiner_ops,
+                    ..Default::default()
+                }
+            });
+            let stage_pipeline_flags = if shading_rate == ShadingRate::Attachment {
+                pipeline_flags
+                    | vk::PipelineCreateFlags::RENDERING_FRAGMENT_SHADING_RATE_ATTACHMENT_KHR
+            } else {
+                pipeline_flags
+            };
             let viewports = [viewport.to_vk(&depth, window_width as f32, window_height as f32)];
             let scissors = [scissor.to_vk(window_width as f32, window_height as f32)];
+            // Task scissors get clamped to the one of the state within the render area
+            let dynamic_scissor = pass.is_scissor_dynamic.then(|| {
+                // Same render area the stage begins rendering with
+                let area_name = pass
+                    .outputs
+                    .first()
+                    .map_or(&default_attachment_name, |e| &e.name);
+                let area = attachments_by_name[area_name].render_area_no_offset();
+                let [scissor] = scissors;
+                ScissorRect {
+                    x: scissor.offset.x,
+                    y: scissor.offset.y,
+                    width: scissor.extent.width,
+                    height: scissor.extent.height,
+                }
+                .clamped_to(area)
+                .unwrap_or_else(|| panic!("scissor of pass {} misses its render area!", pass.name))
+            });
             let viewport_scissor_state = vk::PipelineViewportStateCreateInfo::builder()
                 .scissors(&scissors)
                 .viewports(&viewports);
             let rasterization_state = triangle.to_vk();
             let depth_stencil_attachment = match &pass.depth_stencil {
-                Some(name) => Some(attachments_by_name.get(&name.to_string()).expect(&format!(
-                    "depth stencil attachment {} missing for pass {}!",
-                    name, pass.name
-                ))),
+                Some(output) => Some(attachments_by_name.get(&output.name).unwrap_or_else(|| {
+                    panic!(
+                        "depth stencil attachment {} missing for pass {}!",
+                        output.name, pass.name
+                    )
+                })),
                 _ => None,
-            };
+            }
+            .map(|e| e.of_viewport(0));
+            let depth_stencil_attachment = depth_stencil_attachment.as_ref();
             let binding_descs = [];
             let attrib_descs = [];
             let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::builder()
@@ -193,19 +467,28 @@ impl Pipeline {
                 ..Default::default()
             };
 
+            // Two sided tasks draw without culling
             let dynamic_state_info =
-                vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&[]);
+                vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
             // TODO: Check why if depth output isn't placed last, VVL errors get reported
             let attachment_outputs: Vec<_> = pass
                 .outputs
                 .iter()
                 .map(|e| {
-                    attachments_by_name
-                        .get(e)
-                        .expect(&format!("output attachment {e} missing!"))
-                        .clone()
+                    let att = attachments_by_name
+                        .get(&e.name)
+                        .unwrap_or_else(|| panic!("output attachment {} missing!", e.name))
+                        .of_viewport(0);
+                    // Pipeline format has to match the view the pass renders through
+                    let (vk_format, view) = att.view_for(e.view_format);
+                    Attachment {
+                        vk_format,
+                        view,
+                        ..att
+                    }
                 })
                 .collect();
+            let default_view_format = Self::validate_view_formats(pass, &attachments_by_name);
             let attachment_inputs: Vec<_> = pass
                 .inputs
                 .iter()
@@ -213,29 +496,53 @@ impl Pipeline {
                     attachments_by_name
                         .get(&e.name)
                         .expect(&format!("input attachment {} missing!", e.name))
-                        .clone()
+                        .of_viewport(0)
                 })
                 .collect();
             let attachment_samplers: Vec<_> = pass
                 .inputs
                 .iter()
                 .map(|i| {
+                    // Attachments are always sampled the same, whatever the policy
                     let key = SamplerKey {
                         filter: i.sampler,
                         wrap_mode: WrapMode::ClampToEdge,
                         anisotropy: 1u8,
+                        compare: i.compare,
+                        is_exact: true,
                     };
+                    let mut tables = core.lock_tables();
+                    let samplers_by_key = &mut tables.samplers_by_key;
                     match samplers_by_key.get(&key) {
                         Some(s) => s.clone(),
                         None => {
+                            if samplers_by_key.len() as u32 >= sampler_capacity {
+                                panic!(
+                                    "attachment inputs need more than {} samplers!",
+                                    sampler_capacity
+                                );
+                            }
                             let name = format!("sampler_{}", i.name);
-                            let smp = Sampler::of_key(ctx, name, key, samplers_by_key.len() as u8);
+                            let smp = Sampler::of_key(
+                                ctx,
+                                name,
+                                key,
+                                &SamplerPolicy::default(),
+                                samplers_by_key.len() as u8,
+                            );
                             samplers_by_key.insert(key, smp.clone());
                             smp
                         }
                     }
                 })
                 .collect();
+            let view_mask = Self::validate_views(
+                pass,
+                attachment_outputs
+                    .iter()
+                    .chain(depth_stencil_attachment)
+                    .map(|e| (e.name.as_str(), e.layers)),
+            );
             let attachment_output_formats: Vec<_> =
                 attachment_outputs.iter().map(|e| e.vk_format).collect();
             // We only need blend state for color attachments, ignoring depth/stencil
@@ -244,7 +551,8 @@ impl Pipeline {
 
             let mut rendering_pipeline_info = {
                 let mut b = vk::PipelineRenderingCreateInfo::builder()
-                    .color_attachment_formats(&attachment_output_formats);
+                    .color_attachment_formats(&attachment_output_formats)
+                    .view_mask(view_mask);
                 if writing.stencil || !stencil.disabled {
                     let att = depth_stencil_attachment.expect(&format!(
                         "stencil attachment for writing/testing not set for pass {}!",
@@ -266,26 +574,43 @@ impl Pipeline {
                 rasterization_samples: vk::SampleCountFlags::TYPE_1,
                 ..Default::default()
             };
-            let shader_stages = shader_programs_by_name
+            let program = shader_programs_by_name
                 .get(&pass.program)
-                .expect(&format!("program {} missing!", pass.program))
+                .unwrap_or_else(|| panic!("program {} missing!", pass.program));
+            let shader_stages = program.shaders.iter().map(|e| e.info).collect::<Vec<_>>();
+            let reflection = program.reflection();
+            Self::validate_table_bindings(pass, program, image_capacity, sampler_capacity);
+            let shaders: Vec<_> = program
                 .shaders
                 .iter()
-                .map(|e| e.info)
-                .collect::<Vec<_>>();
+                .map(|e| (e.name.as_str(), &e.reflection))
+                .collect();
+            // Targets allocated per viewport get sampled a layer at a time
+            let layers: Vec<_> = attachment_inputs
+                .iter()
+                .map(|e| {
+                    if e.viewport_views.is_empty() {
+                        e.layers
+                    } else {
+                        1
+                    }
+                })
+                .collect();
+            let input_bindings = plan::input_bindings(pass, &program.name, &shaders, &layers)
+                .unwrap_or_else(|e| panic!("{}!", e));
 
             let mut attachment_descriptors = (pass.inputs.len() > 0).then(|| {
-                Box::new(Self::attachment_image_desc_buffer(
+                Self::attachment_image_desc_buffer(
                     ctx,
-                    descriptor_mem,
+                    descriptor_mem.as_deref_mut(),
                     &pass.name,
-                    pass.inputs.len() as u32,
-                ))
+                    input_bindings.clone(),
+                )
             });
 
             let clear_color_value = clearing.to_vk_color();
             let clear_depth_stencil_value = clearing.to_vk_depth_stencil();
-            let make_attachment_descriptor = |e: (&Attachment, &Sampler)| -> Attachment {
+            let make_attachment_descriptor = |(e, binding): ((&Attachment, &Sampler), &u32)| {
                 let desc = vk::DescriptorImageInfo::builder()
                     .image_layout(vk::ImageLayout::READ_ONLY_OPTIMAL)
                     .image_view(e.0.view)
@@ -294,52 +619,63 @@ impl Pipeline {
                 let (descriptor_offset, descriptor_index) = attachment_descriptors
                     .as_mut()
                     .unwrap()
-                    .place_image_sampler(0, desc, &ctx.extension.descriptor_buffer);
-                Attachment {
+                    .place_image_sampler_at(ctx, *binding, desc);
+                let attachment = Attachment {
                     descriptor_offset,
                     descriptor_index,
                     ..e.0.clone()
-                }
+                };
+                (attachment, desc)
             };
             // Final passes have special rendering attachment info hanlding on render.
             let default_attachment_index = pass
                 .outputs
                 .iter()
-                .position(|e| Attachment::DEFAULT_NAME == e);
+                .position(|e| Attachment::DEFAULT_NAME == e.name);
 
             // Generate attachment structs with the proper descriptor index/offset
-            let inputs: Vec<_> = attachment_inputs
+            let (inputs, input_descriptors): (Vec<_>, Vec<_>) = attachment_inputs
                 .iter()
                 .zip(attachment_samplers.iter())
+                .zip(&input_bindings)
                 .map(make_attachment_descriptor)
-                .collect();
+                .unzip();
 
-            let make_rendering_attachment_info = |e: &Attachment| vk::RenderingAttachmentInfo {
-                image_view: e.view,
-                image_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
-                load_op: if e.format.has_depth_or_stencil() {
+            let make_rendering_attachment_info = |e: &Attachment, is_stored: bool| {
+                let clear_value = if e.format.has_depth_or_stencil() {
                     clear_depth_stencil_value
-                        .map_or(vk::AttachmentLoadOp::LOAD, |_| vk::AttachmentLoadOp::CLEAR)
                 } else {
                     clear_color_value
-                        .map_or(vk::AttachmentLoadOp::LOAD, |_| vk::AttachmentLoadOp::CLEAR)
-                },
-                clear_value: if e.format.has_depth_or_stencil() {
-                    clear_depth_stencil_value.unwrap_or_default()
-                } else {
-                    clear_color_value.unwrap_or_default()
-                },
-                store_op: vk::AttachmentStoreOp::STORE,
-                ..Default::default()
+                };
+                let (load_op, store_op) =
+                    plan::attachment_ops(e.is_memoryless, clear_value.is_some(), is_stored);
+                vk::RenderingAttachmentInfo {
+                    image_view: e.view,
+                    image_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+                    load_op,
+                    clear_value: clear_value.unwrap_or_default(),
+                    store_op,
+                    ..Default::default()
+                }
             };
 
             let attachment_rendering: Vec<_> = attachment_outputs
                 .iter()
-                .map(make_rendering_attachment_info)
+                .zip(&pass.outputs)
+                .map(|(att, output)| make_rendering_attachment_info(att, output.is_stored))
                 .collect();
-            let depth_stencil_rendering = match depth_stencil_attachment {
-                Some(att) => Some(make_rendering_attachment_info(att)),
-                None => None,
+            let depth_stencil_rendering = match (depth_stencil_attachment, &pass.depth_stencil) {
+                (Some(att), Some(output)) => {
+                    Some(make_rendering_attachment_info(att, output.is_stored))
+                }
+                _ => None,
+            };
+            // Only bound when the pipeline has a stencil format, same as above
+            let stencil_rendering = match (depth_stencil_attachment, &pass.depth_stencil) {
+                (Some(att), Some(output)) if writing.stencil || !stencil.disabled => Some(
+                    make_rendering_attachment_info(att, output.is_stencil_stored),
+                ),
+                _ => None,
             };
             /*
              * Add the depth-stencil attachment to the output list if present,
@@ -358,14 +694,14 @@ impl Pipeline {
                 &outputs_for_barriers,
                 &enabled_passes,
             );
-            let mut set_layouts = vec![sampler_descriptors.layout, image_descriptors.layout];
+            let mut set_layouts = table_layouts.to_vec();
             if let Some(d) = &attachment_descriptors {
-                set_layouts.push(d.layout)
+                set_layouts.push(d.layout())
             }
             let pipeline_layout = unsafe {
                 let push_constant_ranges = [vk::PushConstantRange::builder()
                     .offset(0)
-                    .size(128)
+                    .size(MAX_PUSH_CONSTANTS_SIZE)
                     .stage_flags(ShaderStageFlags::ALL_GRAPHICS)
                     .build()];
                 let info = vk::PipelineLayoutCreateInfo::builder()
@@ -375,48 +711,166 @@ impl Pipeline {
                 ctx.device.create_pipeline_layout(&info, None)
             }
             .unwrap();
-            let graphic_pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
-                .flags(vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT)
-                .stages(&shader_stages)
-                .vertex_input_state(&vertex_input_state_info)
-                .input_assembly_state(&vertex_input_assembly_state_info)
-                .viewport_state(&viewport_scissor_state)
-                .rasterization_state(&rasterization_state)
-                .multisample_state(&multisample_state)
-                .depth_stencil_state(&depth_stencil_state)
-                .color_blend_state(&blend_state)
-                .dynamic_state(&dynamic_state_info)
-                .layout(pipeline_layout)
-                .push_next(&mut rendering_pipeline_info)
-                .build();
+            /*
+             * Base permutation first, then one per variant declared on this pass. All of them
+             * share the layout, only the specialization constants differ.
+             */
+            let base_constants = Specialization::parse(&pass.name, &pass.specialization);
+            let mut permutations = vec![(None, Specialization::of(&base_constants))];
+            for (variant_name, overrides) in &pass.variants {
+                let mut constants = base_constants.clone();
+                constants.extend(Specialization::parse(&pass.name, overrides));
+                let variant_id = variant_names
+                    .iter()
+                    .position(|e| e == variant_name)
+                    .unwrap();
+                permutations.push((Some(variant_id), Specialization::of(&constants)));
+            }
+            Specialization::validate_ids(pass, Some(&reflection));
+            Self::validate_per_draw_fields(pass, &reflection);
+            let constants = StageConstants::of_pass(pass);
+            Self::validate_constants(pass, &constants, &reflection);
+            let specialization_infos: Vec<_> = permutations.iter().map(|e| e.1.to_vk()).collect();
+            let stages_per_permutation: Vec<Vec<_>> = specialization_infos
+                .iter()
+                .map(|info| {
+                    shader_stages
+                        .iter()
+                        .map(|stage| vk::PipelineShaderStageCreateInfo {
+                            p_specialization_info: info
+                                .as_ref()
+                                .map_or(std::ptr::null(), |e| e as *const _),
+                            ..*stage
+                        })
+                        .collect()
+                })
+                .collect();
+            let graphic_pipeline_infos: Vec<_> = stages_per_permutation
+                .iter()
+                .map(|stages| {
+                    let mut b = vk::GraphicsPipelineCreateInfo::builder()
+                        .flags(stage_pipeline_flags)
+                        .stages(stages)
+                        .vertex_input_state(&vertex_input_state_info)
+                        .input_assembly_state(&vertex_input_assembly_state_info)
+                        .viewport_state(&viewport_scissor_state)
+                        .rasterization_state(&rasterization_state)
+                        .multisample_state(&multisample_state)
+                        .depth_stencil_state(&depth_stencil_state)
+                        .color_blend_state(&blend_state)
+                        .dynamic_state(&dynamic_state_info)
+                        .layout(pipeline_layout)
+                        .push_next(&mut rendering_pipeline_info);
+                    if let Some(state) = &mut shading_rate_state {
+                        b = b.push_next(state);
+                    }
+                    b.build()
+                })
+                .collect();
 
             let graphics_pipelines = unsafe {
                 ctx.device.create_graphics_pipelines(
                     vk::PipelineCache::null(),
-                    &[graphic_pipeline_info],
+                    &graphic_pipeline_infos,
                     None,
                 )
             }
             .expect("Unable to create graphics pipeline");
             let graphics_pipeline = graphics_pipelines[0];
+            let mut variant_pipelines = vec![None; variant_names.len()];
+            for (permutation, pipeline) in permutations.iter().zip(&graphics_pipelines).skip(1) {
+                let variant_id = permutation.0.unwrap();
+                ctx.try_set_debug_name(
+                    &format!("{}_{}", pass.name, variant_names[variant_id]),
+                    *pipeline,
+                );
+                variant_pipelines[variant_id] = Some(*pipeline);
+            }
 
             ctx.try_set_debug_name(&pass.name, graphics_pipeline);
             ctx.try_set_debug_name(&pass.name, pipeline_layout);
 
             if let Some(d) = &mut attachment_descriptors {
                 // If there are any input descriptors, write them into device memory
-                d.into_device()
+                d.flush(ctx)
             }
-            stages.push(crate::pipeline::stage::Stage {
+            let rendering = super::stage::Rendering {
+                attachments: attachment_rendering,
+                depth_stencil: depth_stencil_rendering,
+                stencil: stencil_rendering,
+                default_attachment_index,
+                default_view_format,
+            };
+            // The above renders into and samples the layers of the first viewport
+            let is_layered = Self::per_viewport_target_of(pass, &pip.targets).is_some();
+            let viewport_layers = (1..viewport_layers as usize)
+                .filter(|_| is_layered)
+                .map(|viewport| {
+                    let view_of = |att: &Attachment, view: vk::ImageView| {
+                        att.viewport_views.get(viewport).copied().unwrap_or(view)
+                    };
+                    let mut attachment_descriptors = (!pass.inputs.is_empty()).then(|| {
+                        Self::attachment_image_desc_buffer(
+                            ctx,
+                            descriptor_mem.as_deref_mut(),
+                            &pass.name,
+                            input_bindings.clone(),
+                        )
+                    });
+                    let input_descriptors: Vec<_> = inputs
+                        .iter()
+                        .zip(&input_descriptors)
+                        .zip(&input_bindings)
+                        .map(|((att, desc), binding)| {
+                            let desc = vk::DescriptorImageInfo {
+                                image_view: view_of(att, desc.image_view),
+                                ..*desc
+                            };
+                            let descriptors = attachment_descriptors.as_mut().unwrap();
+                            descriptors.place_image_sampler_at(ctx, *binding, desc);
+                            desc
+                        })
+                        .collect();
+                    if let Some(d) = &mut attachment_descriptors {
+                        d.flush(ctx)
+                    }
+                    let layer_of = |info: &vk::RenderingAttachmentInfo, att: &Attachment| {
+                        vk::RenderingAttachmentInfo {
+                            image_view: view_of(att, info.image_view),
+                            ..*info
+                        }
+                    };
+                    let depth_of = |info: &Option<vk::RenderingAttachmentInfo>| {
+                        info.as_ref()
+                            .zip(depth_stencil_attachment)
+                            .map(|(info, att)| layer_of(info, att))
+                    };
+                    super::stage::ViewportLayer {
+                        rendering: super::stage::Rendering {
+                            attachments: rendering
+                                .attachments
+                                .iter()
+                                .zip(&attachment_outputs)
+                                .map(|(info, att)| layer_of(info, att))
+                                .collect(),
+                            depth_stencil: depth_of(&rendering.depth_stencil),
+                            stencil: depth_of(&rendering.stencil),
+                            ..rendering.clone()
+                        },
+                        attachment_descriptors,
+                        input_descriptors,
+                    }
+                })
+                .collect();
+            let stage = crate::pipeline::stage::Stage {
                 name: pass.name.clone(),
+                template: pass.template.clone(),
                 is_validation_layer_enabled,
-                rendering: super::stage::Rendering {
-                    attachments: attachment_rendering,
-                    depth_stencil: depth_stencil_rendering,
-                    default_attachment_index,
-                },
-                task_kind: pass.batch,
+                rendering,
+                viewport_layers,
+                task_kind: batch,
                 pipeline: graphics_pipeline,
+                variant_pipelines,
                 layout: pipeline_layout,
                 per_instance_updaters: pass
                     .per_instance_updaters
@@ -428,17 +882,49 @@ impl Pipeline {
                     .iter()
                     .map(|e| e.to_resource_kind())
                     .collect(),
+                per_draw_fields: pass.per_draw_fields.clone(),
+                constants,
+                cull_mode: triangle.cull_face.to_vk(),
+                depth_bounds,
+                shading_rate,
+                shading_rate_texture: None,
+                shading_rate_image: None,
+                view_mask,
+                is_input_substituted: vec![false; inputs.len()],
+                input_descriptors,
                 inputs,
                 outputs: attachment_outputs,
+                depth_stencil: depth_stencil_attachment.cloned(),
+                is_depth_stencil_written: writing.depth || writing.stencil,
                 index: stage_index,
-                is_final: default_attachment_index.is_some(),
+                // Scaled default attachments get presented by the renderer
+                is_final: !is_scaled && Some(passi) == last_default_pass,
+                is_first_default_write: Some(passi) == first_default_pass,
                 image_barriers,
+                buffer_dependencies: plan::buffer_dependencies_for(passi, &enabled_passes),
                 attachment_descriptors,
-                reserved_buffers: Vec::new(),
-            });
+                reflection,
+                blit: None,
+                is_scope_continued: false,
+                is_scope_kept_open: false,
+                vertex_layouts: pass.vertex_layouts.clone(),
+                vertex_formats: pass.vertex_formats.clone(),
+                dynamic_scissor,
+                budget_priority: pass.budget_priority,
+                is_shared_across_viewports: pass.is_shared_across_viewports,
+                transform_interpolation: pass.transform_interpolation,
+                texture_feedback: 0,
+                viewport: viewports[0],
+                scissor: scissors[0],
+            };
+            for mismatch in stage.resource_layout_mismatches() {
+                log::warn!("{}", mismatch);
+            }
+            stages.push(stage);
             // Increment for next stage
             stage_index += 1;
         }
+        Self::group_render_scopes(&mut stages, &enabled_passes);
         for shader in shader_programs_by_name
             .into_values()
             .flat_map(|e| e.shaders)
@@ -447,189 +933,1671 @@ impl Pipeline {
             unsafe { ctx.device.destroy_shader_module(shader.info.module, None) };
         }
 
+        // Materials naming samplers not published yet sample with this one meanwhile
+        let fallback_key = SamplerKey {
+            filter: Filtering::Linear,
+            wrap_mode: WrapMode::Repeat,
+            anisotropy: 1,
+            compare: None,
+            is_exact: false,
+        };
+        let mut tables = core.lock_tables();
+        let CoreTables {
+            samplers_by_key,
+            sampler_descriptors,
+            ..
+        } = &mut *tables;
+        if !samplers_by_key.contains_key(&fallback_key) {
+            if samplers_by_key.len() as u32 >= sampler_capacity {
+                panic!(
+                    "attachment inputs leave no room for the fallback sampler in {}!",
+                    sampler_capacity
+                );
+            }
+            let position = samplers_by_key.len() as u8;
+            let name = "sampler_fallback".to_string();
+            let sampler =
+                Sampler::of_key(ctx, name, fallback_key, &SamplerPolicy::default(), position);
+            samplers_by_key.insert(fallback_key, sampler);
+        }
+        let fallback_sampler = samplers_by_key[&fallback_key].position;
         //  Place all sampler descriptors into the descriptor buffer and write to the GPU
         let mut positioned_samplers = samplers_by_key.values().collect::<Vec<_>>();
         positioned_samplers.sort_by(|a, b| a.position.cmp(&b.position));
         for sampler in positioned_samplers {
-            sampler_descriptors.place_sampler_at(
-                0,
-                sampler.position as u32,
-                sampler.sampler,
-                &ctx.extension.descriptor_buffer,
-            );
+            sampler_descriptors.place_sampler_at(ctx, sampler.position as u32, sampler.sampler);
         }
-        sampler_descriptors.into_device();
-        // image_descriptors.into_device();
+        sampler_descriptors.flush(ctx);
+        drop(tables);
 
         return crate::pipeline::Pipeline {
             stages,
+            variant_names,
             attachments: attachments_by_name.into_values().collect(),
-            image_descriptors,
-            sampler_descriptors,
-            samplers_by_key,
+            fallback_sampler,
+            optimization_hints,
+            budget,
+            written_attachments: HashSet::new(),
+            uninitialized_attachments: pip
+                .targets
+                .iter()
+                .filter_map(|e| e.initial_state.map(|state| (e.name.clone(), state)))
+                .collect(),
+            released_attachments: Vec::new(),
+            preserved_targets: pip.targets.iter().filter_map(PreservedTarget::of).collect(),
+            buffer_fills: pip
+                .buffers
+                .iter()
+                .filter_map(|e| match e.initial_state {
+                    InitialState::Fill(value) => Some((e.name.clone(), value)),
+                    _ => None,
+                })
+                .collect(),
+            uninitialized_reads,
+            declared_buffers: enabled_passes
+                .iter()
+                .flat_map(|e| e.writes_buffers.iter().chain(&e.reads_buffers))
+                .cloned()
+                .collect(),
+            named_buffers: HashMap::new(),
+            is_present_declared: enabled_passes.iter().any(|e| e.is_present),
+            depth_convention,
+            shader_dependents,
         };
     }
 
-    pub fn image_desc_buffer(ctx: &VulkanContext, mem: &mut DeviceAllocator) -> DescriptorBuffer {
-        let desc_buffer = DescriptorBuffer::of(
-            ctx,
-            mem,
-            "images".to_string(),
-            DescriptorType::SAMPLED_IMAGE,
-            1024,
-            1,
-            true,
-        );
-        desc_buffer
+    /*
+     * Consecutive stages rendering into the same attachments share a single rendering
+     * scope, the later ones only bind their pipelines and draw. The scope begins with
+     * the load ops of its first stage and ends with the store ops of its last one.
+     */
+    fn group_render_scopes(stages: &mut [Stage], passes: &[Pass]) {
+        let shapes: Vec<_> = stages
+            .iter()
+            .zip(passes)
+            .map(|(stage, pass)| Self::scope_shape_of(stage, pass))
+            .collect();
+        let starts = plan::scope_starts(&shapes);
+        for i in 1..stages.len() {
+            let first = starts[i];
+            if first == i {
+                continue;
+            }
+            log::debug!(
+                "stage {} continues the rendering scope of {}",
+                stages[i].name,
+                stages[first].name
+            );
+            stages[i].is_scope_continued = true;
+            stages[i - 1].is_scope_kept_open = true;
+            let (scope, rest) = stages.split_at_mut(i);
+            let (begin, end) = (&mut scope[first].rendering, &rest[0].rendering);
+            for (b, e) in begin.attachments.iter_mut().zip(&end.attachments) {
+                b.store_op = e.store_op;
+            }
+            for (b, e) in [
+                (&mut begin.depth_stencil, &end.depth_stencil),
+                (&mut begin.stencil, &end.stencil),
+            ] {
+                if let (Some(b), Some(e)) = (b, e) {
+                    b.store_op = e.store_op;
+                }
+            }
+        }
     }
 
-    pub fn attachment_image_desc_buffer(
-        ctx: &VulkanContext,
-        mem: &mut DeviceAllocator,
-        prefix: &str,
-        size: u32,
-    ) -> DescriptorBuffer {
-        let name = format!("{}_attachments", prefix);
-        let desc_buffer = DescriptorBuffer::of(
-            ctx,
-            mem,
-            name,
-            DescriptorType::COMBINED_IMAGE_SAMPLER,
-            size,
-            1,
-            false,
-        );
-        desc_buffer
+    #[allow(clippy::type_complexity)]
+    fn scope_shape_of(
+        stage: &Stage,
+        pass: &Pass,
+    ) -> ScopeShape<
+        (
+            Vec<vk::ImageView>,
+            Option<vk::ImageView>,
+            Option<vk::ImageView>,
+            Option<usize>,
+            ViewFormat,
+        ),
+        vk::Image,
+    > {
+        let rendering = &stage.rendering;
+        ScopeShape {
+            is_blit: stage.blit.is_some(),
+            is_final: stage.is_final,
+            // Layered ones begin rendering once per viewport
+            is_isolated: pass.is_isolated || !stage.viewport_layers.is_empty(),
+            view_mask: stage.view_mask,
+            is_rate_attachment: stage.shading_rate == ShadingRate::Attachment,
+            is_waiting: !stage.image_barriers.is_empty() || !stage.buffer_dependencies.is_empty(),
+            is_clearing: rendering
+                .attachments
+                .iter()
+                .chain(&rendering.depth_stencil)
+                .chain(&rendering.stencil)
+                .any(|e| e.load_op == vk::AttachmentLoadOp::CLEAR),
+            views: (
+                rendering.attachments.iter().map(|e| e.image_view).collect(),
+                rendering.depth_stencil.map(|e| e.image_view),
+                rendering.stencil.map(|e| e.image_view),
+                rendering.default_attachment_index,
+                rendering.default_view_format,
+            ),
+            rendered: stage
+                .outputs
+                .iter()
+                .chain(&stage.depth_stencil)
+                .map(|e| e.image)
+                .collect(),
+            inputs: stage.inputs.iter().map(|e| e.image).collect(),
+        }
     }
 
-    pub fn sampler_desc_buffer(
-        ctx: &VulkanContext,
-        mem: &mut DeviceAllocator,
-        size: u32,
-    ) -> DescriptorBuffer {
-        let desc_buffer = DescriptorBuffer::of(
-            ctx,
-            mem,
-            "samplers".to_string(),
-            DescriptorType::SAMPLER,
-            size,
-            1,
-            false,
-        );
-        desc_buffer
+    /*
+     * Offscreen only pipelines are fine, the renderer presents what idle frames would.
+     * Only passes declared present have to write to the default attachment alone.
+     */
+    pub(super) fn validate_present(passes: &[Pass], default_passes: &[usize]) {
+        if default_passes.is_empty() && !passes.iter().any(|e| e.is_present) {
+            log::warn!("no pass writes to the default attachment, presenting what idle frames do");
+            return;
+        }
+        let present_passes: Vec<_> = passes.iter().filter(|e| e.is_present).collect();
+        let pass = match present_passes[..] {
+            [] => return,
+            [pass] => pass,
+            _ => panic!(
+                "only one pass can be declared present, got {}!",
+                present_passes.len()
+            ),
+        };
+        if !pass.has_output(Attachment::DEFAULT_NAME) {
+            panic!(
+                "pass {} is declared present but doesn't write to the default attachment!",
+                pass.name
+            );
+        }
+        if default_passes.len() > 1 {
+            let others: Vec<_> = default_passes
+                .iter()
+                .map(|i| passes[*i].name.as_str())
+                .filter(|e| *e != pass.name)
+                .collect();
+            panic!(
+                "pass {} is declared present, but {} also write to the default attachment!",
+                pass.name,
+                others.join(", ")
+            );
+        }
     }
 
-    pub fn extent_of(
-        opt_width: U32OrF32,
-        opt_height: U32OrF32,
-        ref_width: f32,
-        ref_height: f32,
-    ) -> vk::Extent2D {
-        vk::Extent2D {
-            width: match opt_width {
-                U32OrF32::U32(v) => v,
-                U32OrF32::F32(v) => (ref_width * v).ceil() as u32,
-            },
-            height: match opt_height {
-                U32OrF32::U32(v) => v,
-                U32OrF32::F32(v) => (ref_height * v).ceil() as u32,
-            },
+    /*
+     * Samplers and images are tables shared by every stage, shaders have to declare them
+     * where they get bound and no bigger than they are. Attachments are checked along
+     * with the inputs, nothing else can be bound.
+     */
+    fn validate_table_bindings(
+        pass: &Pass,
+        program: &shader::ShaderProgram,
+        image_capacity: u32,
+        sampler_capacity: u32,
+    ) {
+        for shader in &program.shaders {
+            for binding in &shader.reflection.bindings {
+                let (kind, capacity) = match binding.set {
+                    SET_SAMPLERS => (BindingKind::Sampler, sampler_capacity),
+                    SET_IMAGES => (BindingKind::Image, image_capacity),
+                    SET_ATTACHMENTS => continue,
+                    _ => Self::binding_mismatch(pass, shader, binding, "no table is bound there"),
+                };
+                if binding.binding != 0 {
+                    Self::binding_mismatch(
+                        pass,
+                        shader,
+                        binding,
+                        &format!("the {} table is binding 0 only", kind),
+                    );
+                }
+                if binding.kind != kind || binding.is_arrayed {
+                    Self::binding_mismatch(
+                        pass,
+                        shader,
+                        binding,
+                        &format!("declared as {}, the table holds {}", binding.kind, kind),
+                    );
+                }
+                if binding.count > capacity {
+                    Self::binding_mismatch(
+                        pass,
+                        shader,
+                        binding,
+                        &format!("array of {}, the table holds {}", binding.count, capacity),
+                    );
+                }
+            }
         }
     }
 
-    fn gen_image_barriers_for(
-        currenti: usize,
-        inputs: &Vec<Attachment>,
-        outputs: &Vec<Attachment>,
-        passes: &Vec<Pass>,
-    ) -> Vec<vk::ImageMemoryBarrier2> {
-        let mut i = currenti;
-        let mut barriers: Vec<vk::ImageMemoryBarrier2> = Vec::new();
-        fn wrap_around(index: usize, length: usize) -> usize {
-            if index == 0 {
-                length - 1
-            } else {
-                index - 1
+    fn binding_mismatch(
+        pass: &Pass,
+        shader: &shader::Shader,
+        binding: &DescriptorBinding,
+        problem: &str,
+    ) -> ! {
+        let mismatch = BindingMismatch {
+            stage: pass.name.clone(),
+            shader: shader.name.clone(),
+            set: binding.set,
+            binding: binding.binding,
+            name: binding.name.clone(),
+            problem: problem.to_string(),
+        };
+        panic!("{}!", mismatch)
+    }
+
+    /*
+     * Per draw fields go right after the buffer addresses, the shaders have to declare
+     * them there. Members of the per draw layout the shaders declare by name have to sit
+     * at its offsets. Checked only against shaders that could be reflected.
+     */
+    fn validate_per_draw_fields(pass: &Pass, reflection: &ShaderReflection) {
+        let is_interpolated = pass
+            .per_draw_fields
+            .contains(&PerDrawField::InterpolatedTransform);
+        match (pass.transform_interpolation, is_interpolated) {
+            (TransformInterpolation::None, true) => panic!(
+                "stage {} writes per draw field {}, but its transformInterpolation is none!",
+                pass.name,
+                PerDrawField::InterpolatedTransform
+            ),
+            (mode, false) if mode != TransformInterpolation::None => panic!(
+                "stage {} interpolates transforms on the {}, but doesn't write per draw field {}!",
+                pass.name,
+                mode,
+                PerDrawField::InterpolatedTransform
+            ),
+            _ => {}
+        }
+        let layout = PerDrawLayout::of_pass(pass);
+        if layout.size() > MAX_PUSH_CONSTANTS_SIZE {
+            panic!(
+                "stage {} needs {} bytes of push constants, only {} available!",
+                pass.name,
+                layout.size(),
+                MAX_PUSH_CONSTANTS_SIZE
+            );
+        }
+        // Addresses go into 8 byte aligned members, even in the scalar layout
+        for member in layout.members() {
+            if let Condition::Field(field) = member.condition {
+                if !member.offset.is_multiple_of(member.size) {
+                    panic!(
+                        "stage {} writes per draw field {} at offset {}, it has to be {} byte aligned, list it earlier!",
+                        pass.name, field, member.offset, member.size
+                    );
+                }
             }
         }
-        for input in inputs {
-            if Attachment::DEFAULT_NAME == input.name {
-                panic!("Can't read from the default attachment!")
+        if reflection.is_empty() {
+            return;
+        }
+        let registers = reflection.block(REGISTERS_BLOCK);
+        for member in layout.members() {
+            let read = registers.and_then(|b| b.members.iter().find(|e| e.name == member.name));
+            match (read, member.condition) {
+                (None, Condition::Field(field)) => panic!(
+                    "stage {} writes per draw field {}, but its shaders don't read {}!",
+                    pass.name, field, member.name
+                ),
+                (Some(read), _) if read.offset != member.offset => panic!(
+                    "stage {} writes {} at offset {}, but its shaders read it at {}!",
+                    pass.name, member.name, member.offset, read.offset
+                ),
+                _ => {}
             }
-            loop {
-                i = wrap_around(i, passes.len());
-                if i == currenti {
-                    // Looped back to current pass, nothing to check
-                    break;
-                }
-                let prev = &passes[i];
-                if prev.inputs.iter().any(|e| e.name.eq(&input.name)) {
-                    // Already issued barrier before
-                    break;
-                }
-                if !prev.outputs.contains(&input.name) {
-                    // Continue to previous pass
-                    continue;
+        }
+    }
+
+    /*
+     * Shaders declaring the constants block have to lay it out like the declaration
+     * does. Checked only against shaders that could be reflected.
+     */
+    fn validate_constants(pass: &Pass, constants: &StageConstants, reflection: &ShaderReflection) {
+        let block = match reflection.block(stage_constants::BLOCK_NAME) {
+            Some(block) => block,
+            None => return,
+        };
+        let declared = constants.constants();
+        // Debug flags can be left out, but read where they are when they aren't
+        let members = match block.members.split_last() {
+            Some((last, rest)) if last.name == debug_flags::MEMBER => {
+                if last.offset != constants.debug_flags_offset() || last.size != 8 {
+                    panic!(
+                        "shaders of stage {} read the debug flags at offset {} of {} bytes, but they're at offset {} of 8 bytes!",
+                        pass.name,
+                        last.offset,
+                        last.size,
+                        constants.debug_flags_offset()
+                    );
                 }
-                // Image was written to before, barrier for reading
-                let barrier = vk::ImageMemoryBarrier2::builder()
-                    .image(input.image)
-                    .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
-                    .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
-                    .old_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
-                    .new_layout(vk::ImageLayout::READ_ONLY_OPTIMAL)
-                    .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                    .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
-                    .subresource_range(Attachment::default_subresource_range(input.format.aspect()))
-                    .build();
-                barriers.push(barrier);
-                break;
+                rest
+            }
+            _ => &block.members,
+        };
+        for index in 0..declared.len().max(members.len()) {
+            match (declared.get(index), members.get(index)) {
+                (Some(constant), Some(member))
+                    if constant.offset == member.offset && constant.kind.size() == member.size => {}
+                (Some(constant), Some(member)) => panic!(
+                    "stage {} declares constant {} at offset {} of {} bytes, but its shaders read {} at offset {} of {} bytes!",
+                    pass.name,
+                    constant.name,
+                    constant.offset,
+                    constant.kind.size(),
+                    member.name,
+                    member.offset,
+                    member.size
+                ),
+                (Some(constant), None) => panic!(
+                    "stage {} declares constant {}, but its shaders don't read it!",
+                    pass.name, constant.name
+                ),
+                (None, Some(member)) => panic!(
+                    "shaders of stage {} read constant {}, but the stage doesn't declare it!",
+                    pass.name, member.name
+                ),
+                (None, None) => unreachable!(),
             }
         }
-        for output in outputs {
-            if Attachment::DEFAULT_NAME == output.name {
-                /*
-                 * Handled in the rendering loop, since the swapchain
-                 * changes which image this barrier refers to.
-                 */
-                continue;
+    }
+
+    ///
+    /// Store hints for the enabled passes, without loading anything.
+    ///
+    pub fn optimization_hints(&self) -> Vec<OptimizationHint> {
+        let enabled_passes: Vec<_> = self.passes.iter().filter(|e| !e.is_disabled).collect();
+        let mut hints = Self::validate_store_ops(&self.targets, &enabled_passes);
+        hints.extend(Self::validate_depth_convention(
+            self.depth_convention,
+            &enabled_passes,
+        ));
+        hints
+    }
+
+    ///
+    /// Shaders of the pipeline, under shader/, with their includes pasted in. Those of
+    /// every program, and the one of depth pyramids when an enabled pass builds one.
+    ///
+    pub fn flatten_shaders(&self) -> Result<BTreeMap<String, Flattened>, IncludeError> {
+        let mut names: BTreeSet<_> = self
+            .programs
+            .iter()
+            .flat_map(|p| [&p.vertex, &p.fragment, &p.geometry])
+            .filter(|f| !f.is_empty())
+            .cloned()
+            .collect();
+        // Compiled along with the rest, it's no program of the pipeline
+        let is_depth_pyramid_used = self
+            .passes
+            .iter()
+            .any(|e| !e.is_disabled && e.kind == PassKind::DepthPyramid);
+        if is_depth_pyramid_used {
+            names.insert(depth_pyramid::SHADER_NAME.to_string());
+        }
+        let search_paths: Vec<_> = self.include_paths.iter().map(PathBuf::from).collect();
+        names
+            .into_iter()
+            .map(|name| {
+                let path = Path::new(SHADER_DIR).join(&name);
+                include::flatten(&path, &search_paths).map(|e| (name, e))
+            })
+            .collect()
+    }
+
+    ///
+    /// Files the shaders of every enabled pass are made of, includes included, along
+    /// with the names of the stages using them. Paths start with shader/, or with the
+    /// include path they were found in.
+    ///
+    pub fn shader_dependencies(&self) -> Result<BTreeMap<PathBuf, BTreeSet<String>>, IncludeError> {
+        Ok(self.dependents_of(&self.flatten_shaders()?))
+    }
+
+    fn dependents_of(
+        &self,
+        flattened: &BTreeMap<String, Flattened>,
+    ) -> BTreeMap<PathBuf, BTreeSet<String>> {
+        let mut dependents: BTreeMap<PathBuf, BTreeSet<String>> = BTreeMap::new();
+        for pass in self.passes.iter().filter(|e| !e.is_disabled) {
+            let shaders = match pass.kind {
+                PassKind::Draw => self
+                    .programs
+                    .iter()
+                    .filter(|e| e.name == pass.program)
+                    .flat_map(|p| [&p.vertex, &p.fragment, &p.geometry])
+                    .cloned()
+                    .collect(),
+                PassKind::DepthPyramid => vec![depth_pyramid::SHADER_NAME.to_string()],
+                PassKind::Blit => Vec::new(),
+            };
+            let files = shaders
+                .iter()
+                .filter_map(|e| flattened.get(e))
+                .flat_map(|e| &e.files);
+            for file in files {
+                dependents
+                    .entry(file.clone())
+                    .or_default()
+                    .insert(pass.name.clone());
             }
-            loop {
-                i = wrap_around(i, passes.len());
-                if i == currenti {
-                    // Looped back to current pass, nothing to check
-                    break;
+        }
+        dependents
+    }
+
+    ///
+    /// Usage every target gets created with, in declaration order. Only what the enabled
+    /// passes need: rendered to as color or depth stencil, sampled as input or by depth
+    /// pyramids, copied from and into by blits, written by depth pyramids. Depth
+    /// pyramids stay sampled, shaders reach them through Renderer::attachment_texture_id.
+    /// The extra usage of the target goes on top of that. Targets no pass touches get the
+    /// attachment usage of their format alone. Targets with an initialState or read before
+    /// written get cleared by a transfer, see uninitialized_reads. Preserved targets get
+    /// copied from and into by transfers, see PreserveMode.
+    ///
+    pub fn attachment_usages(&self) -> Vec<(String, vk::ImageUsageFlags)> {
+        let enabled_passes: Vec<_> = self.passes.iter().filter(|e| !e.is_disabled).collect();
+        let uninitialized = self.uninitialized_reads();
+        self.targets
+            .iter()
+            .map(|e| {
+                let mut usage = Self::usage_of(e, &enabled_passes);
+                let is_cleared = e.initial_state.is_some()
+                    || uninitialized.iter().any(|read| read.resource == e.name);
+                if is_cleared && !e.is_memoryless {
+                    usage |= vk::ImageUsageFlags::TRANSFER_DST;
                 }
-                let prev = &passes[i];
-                if prev.outputs.contains(&output.name) {
-                    // Already issued barrier before
-                    break;
+                if PreservedTarget::of(e).is_some() {
+                    usage |= vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
                 }
-                if !prev.inputs.iter().any(|e| e.name.eq(&output.name)) {
-                    // Continue to previous pass
-                    continue;
+                (e.name.clone(), usage)
+            })
+            .collect()
+    }
+
+    ///
+    /// Reads by the enabled passes, in pass order, of attachments and named buffers
+    /// without an initialState that no earlier pass of the frame wrote. Sampled inputs,
+    /// blit and depth pyramid sources and read buffers count, loading an output doesn't.
+    /// On the first frame nothing wrote them yet, they hold whatever the memory did.
+    ///
+    pub fn uninitialized_reads(&self) -> Vec<UninitializedRead> {
+        let mut written: HashSet<&str> = self
+            .targets
+            .iter()
+            .filter(|e| e.initial_state.is_some())
+            .map(|e| e.name.as_str())
+            .chain(self.buffers.iter().map(|e| e.name.as_str()))
+            .collect();
+        let mut reads = Vec::new();
+        for pass in self.passes.iter().filter(|e| !e.is_disabled) {
+            let inputs = pass.inputs.iter().map(|e| &e.name);
+            for name in inputs.chain(pass.source.as_ref().map(|e| &e.name)) {
+                if !written.contains(name.as_str()) {
+                    reads.push(UninitializedRead {
+                        pass: pass.name.clone(),
+                        resource: name.clone(),
+                        is_buffer: false,
+                    });
                 }
-                // Image was read before, issue barrier for writing
-                let barrier = vk::ImageMemoryBarrier2::builder()
-                    .image(output.image)
-                    .src_access_mask(vk::AccessFlags2::MEMORY_READ)
-                    .dst_access_mask(vk::AccessFlags2::MEMORY_WRITE)
-                    .old_layout(vk::ImageLayout::UNDEFINED)
-                    .new_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
-                    .src_stage_mask(vk::PipelineStageFlags2::NONE)
-                    .dst_stage_mask(if output.format.has_depth_or_stencil() {
-                        vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
-                    } else {
-                        vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
-                    })
-                    .subresource_range(Attachment::default_subresource_range(
-                        output.format.aspect(),
-                    ))
-                    .build();
-                barriers.push(barrier);
-                break;
             }
-        }
-        return barriers;
+            for name in &pass.reads_buffers {
+                if !written.contains(name.as_str()) {
+                    reads.push(UninitializedRead {
+                        pass: pass.name.clone(),
+                        resource: name.clone(),
+                        is_buffer: true,
+                    });
+                }
+            }
+            let writing = Self::handle_option(pass.state.writing.clone());
+            let depth_stencil = pass
+                .depth_stencil
+                .as_ref()
+                .filter(|_| writing.depth || writing.stencil);
+            written.extend(
+                pass.outputs
+                    .iter()
+                    .chain(depth_stencil)
+                    .map(|e| e.name.as_str()),
+            );
+            written.extend(pass.destination.as_deref());
+            written.extend(pass.writes_buffers.iter().map(String::as_str));
+        }
+        reads
+    }
+
+    fn usage_of(target: &Target, passes: &[&Pass]) -> vk::ImageUsageFlags {
+        use vk::ImageUsageFlags as Iu;
+        let name = target.name.as_str();
+        let mut usage = Iu::empty();
+        for pass in passes {
+            let is_source = pass.source.as_ref().is_some_and(|e| e.name == name);
+            let is_destination = pass.destination.as_ref().is_some_and(|e| e == name);
+            match pass.kind {
+                PassKind::Draw => {
+                    if pass.outputs.iter().any(|e| e.name == name) {
+                        usage |= Iu::COLOR_ATTACHMENT;
+                    }
+                    if pass.depth_stencil.as_ref().is_some_and(|e| e.name == name) {
+                        usage |= Iu::DEPTH_STENCIL_ATTACHMENT;
+                    }
+                    if pass.inputs.iter().any(|e| e.name == name) {
+                        usage |= Iu::SAMPLED;
+                    }
+                }
+                PassKind::Blit => {
+                    if is_source {
+                        usage |= Iu::TRANSFER_SRC;
+                    }
+                    if is_destination {
+                        usage |= Iu::TRANSFER_DST;
+                    }
+                }
+                PassKind::DepthPyramid => {
+                    if is_source {
+                        usage |= Iu::SAMPLED;
+                    }
+                    if is_destination {
+                        usage |= Iu::STORAGE | Iu::SAMPLED;
+                    }
+                }
+            }
+        }
+        if usage.is_empty() {
+            usage = if target.format.has_depth() {
+                Iu::DEPTH_STENCIL_ATTACHMENT
+            } else {
+                Iu::COLOR_ATTACHMENT
+            };
+        }
+        if target.is_memoryless {
+            /*
+             * Never leaves tile memory, anything but rendering to it needs memory.
+             * validate_memoryless_targets tells apart the passes sampling it.
+             */
+            if let Some(extra) = target.extra_usage.first() {
+                panic!(
+                    "memoryless attachment {} can't have {} usage!",
+                    target.name, extra
+                );
+            }
+            let rendered = usage & (Iu::COLOR_ATTACHMENT | Iu::DEPTH_STENCIL_ATTACHMENT);
+            return rendered | Iu::TRANSIENT_ATTACHMENT;
+        }
+        target
+            .extra_usage
+            .iter()
+            .fold(usage, |usage, e| usage | e.to_vk())
+    }
+
+    ///
+    /// Matches what the pipeline and its enabled passes require against the capabilities.
+    /// Optional passes missing some feature get disabled, with a hint saying so. Any other
+    /// missing feature fails with all of them and the passes needing each.
+    ///
+    pub fn match_requirements(
+        &mut self,
+        capabilities: &DeviceCapabilities,
+    ) -> Result<Vec<OptimizationHint>, MissingFeatures> {
+        let mut needed_by: BTreeMap<DeviceFeature, Vec<String>> = BTreeMap::new();
+        for feature in &self.requires {
+            if !capabilities.has(*feature) {
+                needed_by
+                    .entry(*feature)
+                    .or_default()
+                    .push("the pipeline".to_string());
+            }
+        }
+        let mut hints = Vec::new();
+        for pass in self.passes.iter_mut().filter(|e| !e.is_disabled) {
+            let mut missing: Vec<_> = pass
+                .requires
+                .iter()
+                .copied()
+                .filter(|e| !capabilities.has(*e))
+                .collect();
+            missing.sort();
+            missing.dedup();
+            if missing.is_empty() {
+                continue;
+            }
+            if pass.is_optional {
+                pass.is_disabled = true;
+                hints.push(OptimizationHint::MissingFeatures {
+                    stage: pass.name.clone(),
+                    features: missing.iter().map(|e| e.to_string()).collect(),
+                });
+                continue;
+            }
+            for feature in missing {
+                needed_by
+                    .entry(feature)
+                    .or_default()
+                    .push(pass.name.clone());
+            }
+        }
+        if !needed_by.is_empty() {
+            return Err(MissingFeatures {
+                missing: needed_by.into_iter().collect(),
+            });
+        }
+        Ok(hints)
+    }
+
+    ///
+    /// Depth state of the pass, predefined ones compare the way the convention says.
+    ///
+    pub(super) fn depth_of(pass: &Pass, convention: DepthConvention) -> DepthDesc {
+        let mut depth = Self::handle_option(pass.state.depth.clone());
+        if !matches!(pass.state.depth, DescOption::Configured(_)) {
+            depth.func = convention.default_func();
+        }
+        depth
+    }
+
+    ///
+    /// Clear values of the pass, predefined ones clear depth to the far end of the
+    /// convention.
+    ///
+    pub(super) fn clearing_of(pass: &Pass, convention: DepthConvention) -> ClearDesc {
+        let mut clearing = Self::handle_option(pass.state.clearing.clone());
+        if !matches!(pass.state.clearing, DescOption::Configured(_)) {
+            clearing.depth = clearing.depth.map(|_| convention.clear_value());
+        }
+        clearing
+    }
+
+    pub(super) fn depth_bounds_of(
+        capabilities: &DeviceCapabilities,
+        pass: &Pass,
+    ) -> Option<[f32; 2]> {
+        let [min, max] = pass.depth_bounds?;
+        if !(0.0..=1.0).contains(&min) || !(0.0..=1.0).contains(&max) || min > max {
+            panic!(
+                "depth bounds [{}, {}] of pass {} have to be within 0 and 1, lowest first!",
+                min, max, pass.name
+            );
+        }
+        if pass.depth_stencil.is_none() {
+            panic!(
+                "pass {} has depth bounds but no depth attachment!",
+                pass.name
+            );
+        }
+        if !capabilities.is_depth_bounds_enabled {
+            log::warn!(
+                "device can't test depth bounds, pass {} draws without them",
+                pass.name
+            );
+            return None;
+        }
+        Some([min, max])
+    }
+
+    pub(super) fn shading_rate_of(capabilities: &DeviceCapabilities, pass: &Pass) -> ShadingRate {
+        let is_supported = match pass.shading_rate {
+            ShadingRate::Full => true,
+            ShadingRate::Quarter => capabilities.is_shading_rate_enabled,
+            ShadingRate::Attachment => capabilities.shading_rate_texel_size.is_some(),
+        };
+        if is_supported {
+            pass.shading_rate
+        } else {
+            log::debug!(
+                "device can't shade pass {} at {}, it shades at 1x1",
+                pass.name,
+                pass.shading_rate
+            );
+            ShadingRate::Full
+        }
+    }
+
+    fn validate_depth_convention(
+        convention: DepthConvention,
+        passes: &[&Pass],
+    ) -> Vec<OptimizationHint> {
+        passes
+            .iter()
+            .filter_map(|pass| {
+                let depth = Self::depth_of(pass, convention);
+                (depth.testing && convention.contradicts(depth.func)).then(|| {
+                    OptimizationHint::DepthConventionMismatch {
+                        stage: pass.name.clone(),
+                        func: format!("{:?}", depth.func),
+                        convention: convention.to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /*
+     * Follows each store of an attachment to the next pass that touches it, wrapping
+     * around into the next frame. If that pass loads or samples the contents they have to
+     * be stored, otherwise storing them is wasted bandwidth.
+     */
+    fn validate_store_ops(targets: &[Target], passes: &[&Pass]) -> Vec<OptimizationHint> {
+        let mut hints = Vec::new();
+        for target in targets.iter().filter(|e| !e.is_memoryless) {
+            let name = target.name.as_str();
+            let is_depth_stencil_of =
+                |pass: &Pass| pass.depth_stencil.as_ref().is_some_and(|e| e.name == name);
+            let loads = |pass: &Pass| {
+                if pass.kind != PassKind::Draw {
+                    // Blits overwrite the whole destination unless given a region of it
+                    return pass.has_input(name)
+                        || (pass.has_output(name) && pass.destination_rect.is_some());
+                }
+                let clearing = Self::handle_option(pass.state.clearing.clone());
+                pass.has_input(name)
+                    || (pass.has_output(name) && clearing.to_vk_color().is_none())
+                    || (is_depth_stencil_of(pass) && clearing.to_vk_depth_stencil().is_none())
+            };
+            for (i, pass) in passes.iter().enumerate() {
+                // Test only depth attachments have a store op all the same, blits always store
+                if pass.kind != PassKind::Draw
+                    || (!pass.has_output(name) && !is_depth_stencil_of(pass))
+                {
+                    continue;
+                }
+                // Next pass touching the attachment, this same one at the latest
+                let next = (1..=passes.len())
+                    .map(|offset| passes[(i + offset) % passes.len()])
+                    .find(|e| e.has_input(name) || e.has_output(name) || is_depth_stencil_of(e))
+                    .unwrap();
+                let is_needed = loads(next);
+                let output = pass
+                    .outputs
+                    .iter()
+                    .chain(pass.depth_stencil.as_ref())
+                    .find(|e| e.name == name)
+                    .unwrap();
+                let mut stores = vec![(false, output.is_stored)];
+                if is_depth_stencil_of(pass) && target.format.has_stencil() {
+                    stores.push((true, output.is_stencil_stored));
+                }
+                for (is_stencil, is_stored) in stores {
+                    if is_needed && !is_stored {
+                        log::warn!(
+                            "stage {} discards the {} of attachment {}, but stage {} reads it afterwards!",
+                            pass.name,
+                            if is_stencil { "stencil" } else { "contents" },
+                            name,
+                            next.name
+                        );
+                    } else if !is_needed && is_stored {
+                        hints.push(OptimizationHint::UnreadStore {
+                            stage: pass.name.clone(),
+                            attachment: name.to_string(),
+                            is_stencil,
+                        });
+                    }
+                }
+            }
+        }
+        hints
+    }
+
+    ///
+    /// View mask of the pass, 0 unless it renders several views. Every target of a
+    /// multiview pass needs a layer per view.
+    ///
+    pub(super) fn validate_views<'a>(
+        pass: &Pass,
+        targets: impl Iterator<Item = (&'a str, u32)>,
+    ) -> u32 {
+        if pass.views == 0 || pass.views > ViewMatrices::MAX_VIEWS {
+            panic!(
+                "pass {} renders {} views, it has to be between 1 and {}!",
+                pass.name,
+                pass.views,
+                ViewMatrices::MAX_VIEWS
+            );
+        }
+        if pass.views == 1 {
+            return 0;
+        }
+        for (name, layers) in targets {
+            if layers < pass.views {
+                panic!(
+                    "pass {} renders {} views, but target {} only has {} layers!",
+                    pass.name, pass.views, name, layers
+                );
+            }
+        }
+        !(u32::MAX << pass.views)
+    }
+
+    /*
+     * Only the default attachment has views to pick from. Returns the view format the
+     * pass writes to the default attachment through.
+     */
+    fn validate_view_formats(
+        pass: &Pass,
+        attachments_by_name: &HashMap<&String, Attachment>,
+    ) -> ViewFormat {
+        let mut default_view_format = ViewFormat::Srgb;
+        for output in &pass.outputs {
+            if output.view_format == ViewFormat::Srgb {
+                continue;
+            }
+            if Attachment::DEFAULT_NAME != output.name {
+                panic!(
+                    "pass {} picks a {:?} view of {}, only the default attachment has one!",
+                    pass.name, output.view_format, output.name
+                );
+            }
+            let att = &attachments_by_name[&output.name];
+            if att.unorm_view.is_none() {
+                log::warn!(
+                    "pass {} wants a {:?} view of the swapchain but there is none, it renders through the {:?} one",
+                    pass.name,
+                    output.view_format,
+                    att.vk_format
+                );
+            }
+            default_view_format = output.view_format;
+        }
+        default_view_format
+    }
+
+    /*
+     * Clearing memoryless attachments would have nothing to keep the contents in, and
+     * initial states have to fit what they initialize.
+     */
+    pub(super) fn validate_initial_states(
+        targets: &[Target],
+        buffers: &[BufferDecl],
+        passes: &[Pass],
+    ) {
+        for target in targets {
+            let state = match target.initial_state {
+                Some(e) => e,
+                None => continue,
+            };
+            if target.is_memoryless {
+                panic!(
+                    "memoryless attachment {} can't have an initial state!",
+                    target.name
+                );
+            }
+            let is_fitting = match state {
+                InitialState::ClearColor(_) => target.format.has_color(),
+                InitialState::ClearDepth(_) => target.format.has_depth(),
+                InitialState::Fill(_) => false,
+            };
+            if !is_fitting {
+                panic!(
+                    "attachment {} of format {} can't start out as {:?}!",
+                    target.name, target.format, state
+                );
+            }
+        }
+        for buffer in buffers {
+            if !matches!(buffer.initial_state, InitialState::Fill(_)) {
+                panic!(
+                    "buffer {} can only start out filled, not as {:?}!",
+                    buffer.name, buffer.initial_state
+                );
+            }
+            let is_declared = passes
+                .iter()
+                .any(|e| e.writes_buffer(&buffer.name) || e.reads_buffer(&buffer.name));
+            if !is_declared {
+                log::warn!(
+                    "buffer {} has an initial state, but no pass reads or writes it",
+                    buffer.name
+                );
+            }
+        }
+    }
+
+    pub(super) fn validate_memoryless_targets(targets: &[Target], passes: &[Pass]) {
+        for target in targets.iter().filter(|e| e.is_memoryless) {
+            /*
+             * Memoryless attachments live only in tile memory during a pass, they are never
+             * stored so there is nothing for a later stage to sample from.
+             */
+            if let Some(pass) = passes.iter().find(|p| p.has_input(&target.name)) {
+                panic!(
+                    "memoryless attachment {} can't be sampled, but pass {} reads from it!",
+                    target.name, pass.name
+                );
+            }
+        }
+    }
+
+    /*
+     * Draws render into mip 0 alone and blits only copy one, only depth pyramids fill
+     * all of them.
+     */
+    pub(super) fn validate_mip_chained_targets(targets: &[Target], passes: &[Pass]) {
+        for target in targets.iter().filter(|e| e.is_mip_chained) {
+            if target.format != crate::format::Format::R32_SFLOAT
+                || target.layers != 1
+                || target.is_memoryless
+            {
+                panic!(
+                    "attachment {} has full mips, it has to be a single layer R32_SFLOAT one with memory!",
+                    target.name
+                );
+            }
+            let is_written_by = |pass: &Pass| {
+                pass.has_output(&target.name)
+                    || pass
+                        .depth_stencil
+                        .as_ref()
+                        .is_some_and(|e| e.name == target.name)
+            };
+            if let Some(pass) = passes
+                .iter()
+                .find(|e| e.kind != PassKind::DepthPyramid && is_written_by(e))
+            {
+                panic!(
+                    "attachment {} has full mips, only depth pyramids can write it but pass {} does!",
+                    target.name, pass.name
+                );
+            }
+        }
+        for pass in passes.iter().filter(|e| e.kind == PassKind::DepthPyramid) {
+            let destination = pass.destination.as_ref().unwrap();
+            let is_mip_chained = targets
+                .iter()
+                .any(|e| e.name == *destination && e.is_mip_chained);
+            if !is_mip_chained {
+                panic!(
+                    "depth pyramid pass {} writes {}, it has to be declared with full mips!",
+                    pass.name, destination
+                );
+            }
+        }
+    }
+
+    /*
+     * Memoryless attachments have nothing to carry over, full mips only get the first
+     * one carried over, and depth stencil blits can't filter.
+     */
+    pub(super) fn validate_preserved_targets(targets: &[Target]) {
+        for target in targets.iter().filter(|e| PreservedTarget::of(e).is_some()) {
+            if target.is_memoryless || target.is_mip_chained {
+                panic!(
+                    "attachment {} is preserved on recreate, it can't be memoryless or have full mips!",
+                    target.name
+                );
+            }
+            if target.format.has_depth_or_stencil()
+                && target.preserve_mode == PreserveMode::Blit
+                && target.preserve_filter == Filtering::Linear
+            {
+                panic!(
+                    "depth stencil attachment {} is preserved with linear filtering, only nearest is supported!",
+                    target.name
+                );
+            }
+        }
+    }
+
+    /*
+     * Every viewport renders into its layer in a rendering scope of its own, with
+     * viewport_layers layers in place of the declared ones. Only passes drawing once per
+     * viewport know which layer is theirs.
+     */
+    pub(super) fn validate_per_viewport_targets(targets: &[Target], passes: &[Pass]) {
+        for target in targets.iter().filter(|e| e.is_per_viewport) {
+            if target.layers != 1 || target.is_memoryless || target.is_mip_chained {
+                panic!(
+                    "attachment {} is allocated per viewport, it has to be a single layer one with memory and without full mips!",
+                    target.name
+                );
+            }
+        }
+        for pass in passes.iter().filter(|e| !e.is_disabled) {
+            let target = match Self::per_viewport_target_of(pass, targets) {
+                Some(e) => e,
+                None => continue,
+            };
+            if pass.kind != PassKind::Draw || pass.is_shared_across_viewports || pass.views > 1 {
+                panic!(
+                    "pass {} uses attachment {} allocated per viewport, it has to be a draw pass drawing once per viewport!",
+                    pass.name, target.name
+                );
+            }
+        }
+    }
+
+    /*
+     * First target allocated per viewport the pass renders into or samples, it begins
+     * rendering once per viewport with any.
+     */
+    pub(super) fn per_viewport_target_of<'a>(
+        pass: &Pass,
+        targets: &'a [Target],
+    ) -> Option<&'a Target> {
+        targets.iter().filter(|e| e.is_per_viewport).find(|e| {
+            pass.has_input(&e.name)
+                || pass.has_output(&e.name)
+                || pass
+                    .depth_stencil
+                    .as_ref()
+                    .is_some_and(|d| d.name == e.name)
+        })
+    }
+
+    pub(super) fn validate_vertex_formats(passes: &[Pass]) {
+        for pass in passes {
+            for kind in VertexAttributeKind::ALL {
+                let formats = pass.vertex_formats.of(kind);
+                if let Some(e) = formats.iter().find(|e| !kind.is_format_supported(**e)) {
+                    panic!(
+                        "pass {} reads {} as {}, it can't be held in that format!",
+                        pass.name, kind, e
+                    );
+                }
+            }
+        }
+    }
+
+    /*
+     * Checked before anything else looks at the passes, has_input and has_output count
+     * the source and destination of blits and depth pyramids.
+     */
+    pub(super) fn validate_blit_attachments(passes: &[Pass]) {
+        for pass in passes {
+            if pass.kind != PassKind::DepthPyramid && pass.reduction.is_some() {
+                panic!(
+                    "pass {} has a reduction, but isn't a depth pyramid!",
+                    pass.name
+                );
+            }
+            if pass.kind == PassKind::Draw {
+                if pass.source.is_some() || pass.destination.is_some() {
+                    panic!(
+                        "pass {} has a blit source or destination, but isn't a blit!",
+                        pass.name
+                    );
+                }
+                continue;
+            }
+            let (source, destination) = match (&pass.source, &pass.destination) {
+                (Some(source), Some(destination)) => (source, destination),
+                _ => panic!(
+                    "{} pass {} needs a source and a destination!",
+                    pass.kind, pass.name
+                ),
+            };
+            if pass.kind == PassKind::DepthPyramid
+                && (pass.source_rect.is_some() || pass.destination_rect.is_some())
+            {
+                panic!(
+                    "depth pyramid pass {} can't have regions, it reduces the whole source!",
+                    pass.name
+                );
+            }
+            if Attachment::DEFAULT_NAME == source.name || Attachment::DEFAULT_NAME == destination {
+                panic!(
+                    "{} pass {} can't read from or write into the default attachment!",
+                    pass.kind, pass.name
+                );
+            }
+            if source.name == *destination {
+                panic!(
+                    "{} pass {} writes {} into itself!",
+                    pass.kind, pass.name, destination
+                );
+            }
+            if !pass.program.is_empty()
+                || !pass.inputs.is_empty()
+                || !pass.outputs.is_empty()
+                || pass.depth_stencil.is_some()
+            {
+                panic!(
+                    "{} pass {} can't have a program or attachments besides its source and destination!",
+                    pass.kind, pass.name
+                );
+            }
+        }
+    }
+
+    ///
+    /// Stage of a blit pass. Copies when the attachments have the same format and the
+    /// regions the same size, blits otherwise.
+    ///
+    fn blit_stage_of(
+        ctx: &VulkanContext,
+        passi: usize,
+        passes: &[Pass],
+        attachments_by_name: &HashMap<&String, Attachment>,
+        slot: &TransferSlot,
+    ) -> crate::pipeline::stage::Stage {
+        let pass = &passes[passi];
+        let source_desc = pass.source.as_ref().unwrap();
+        let attachment_of = |name: &String| {
+            attachments_by_name
+                .get(name)
+                .unwrap_or_else(|| {
+                    panic!("blit attachment {} missing for pass {}!", name, pass.name)
+                })
+                .clone()
+        };
+        let source = attachment_of(&source_desc.name);
+        let destination = attachment_of(pass.destination.as_ref().unwrap());
+        if let Some(att) = [&source, &destination]
+            .into_iter()
+            .find(|e| e.is_memoryless)
+        {
+            panic!(
+                "memoryless attachment {} can't be blitted, but pass {} does!",
+                att.name, pass.name
+            );
+        }
+        if source_desc.mip >= source.mips {
+            panic!(
+                "pass {} blits mip {} of {}, it only has {} mips!",
+                pass.name, source_desc.mip, source.name, source.mips
+            );
+        }
+        if source_desc.layer >= source.layers {
+            panic!(
+                "pass {} blits layer {} of {}, it only has {} layers!",
+                pass.name, source_desc.layer, source.name, source.layers
+            );
+        }
+        Self::validate_blit_aspects(pass, source.format, destination.format);
+        let aspect = source.format.aspect();
+        let source_offsets =
+            Self::blit_offsets_of(pass, &source.name, source.extent, pass.source_rect);
+        let destination_offsets = Self::blit_offsets_of(
+            pass,
+            &destination.name,
+            destination.extent,
+            pass.destination_rect,
+        );
+        let size_of = |e: [vk::Offset3D; 2]| (e[1].x - e[0].x, e[1].y - e[0].y);
+        let is_copy = source.vk_format == destination.vk_format
+            && size_of(source_offsets) == size_of(destination_offsets);
+        if !is_copy {
+            Self::validate_blit_formats(ctx, pass, &source, &destination);
+        }
+        // Regions are clipped to the attachment, only a smaller one can start elsewhere
+        let is_destination_partial = size_of(destination_offsets)
+            != (
+                destination.extent.width as i32,
+                destination.extent.height as i32,
+            );
+        let (before, after) = plan::blit_transitions(passi, passes, is_destination_partial);
+        let to_barriers = |transitions: Vec<plan::ImageTransition>| {
+            transitions
+                .iter()
+                .map(|e| {
+                    let att = if e.attachment == source.name {
+                        &source
+                    } else {
+                        &destination
+                    };
+                    e.to_barrier(att)
+                })
+                .collect::<Vec<_>>()
+        };
+        let image_barriers = to_barriers(before);
+        let after_barriers = to_barriers(after);
+        let blit = Blit {
+            source_layers: vk::ImageSubresourceLayers {
+                aspect_mask: aspect,
+                mip_level: source_desc.mip,
+                base_array_layer: source_desc.layer,
+                layer_count: 1,
+            },
+            destination_layers: vk::ImageSubresourceLayers {
+                aspect_mask: aspect,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            source,
+            destination,
+            source_offsets,
+            destination_offsets,
+            filter: pass.filter.to_vk(),
+            is_copy,
+            after_barriers,
+            // Until the first frame says otherwise, so a missing source gets logged
+            is_source_written: true,
+            is_destination_written: false,
+            pyramid: None,
+        };
+        Self::transfer_stage_of(pass, blit, image_barriers, slot)
+    }
+
+    ///
+    /// Stage of a depth pyramid pass, recorded like a blit but dispatching the reduction
+    /// of every mip instead of copying.
+    ///
+    fn depth_pyramid_stage_of(
+        ctx: &VulkanContext,
+        passi: usize,
+        passes: &[Pass],
+        attachments_by_name: &HashMap<&String, Attachment>,
+        convention: DepthConvention,
+        shader_path: &str,
+        slot: &TransferSlot,
+    ) -> crate::pipeline::stage::Stage {
+        let pass = &passes[passi];
+        let attachment_of = |name: &String| {
+            attachments_by_name
+                .get(name)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "depth pyramid attachment {} missing for pass {}!",
+                        name, pass.name
+                    )
+                })
+                .clone()
+        };
+        let source = attachment_of(&pass.source.as_ref().unwrap().name);
+        let destination = attachment_of(pass.destination.as_ref().unwrap());
+        if !source.format.has_depth() || source.is_memoryless {
+            panic!(
+                "depth pyramid pass {} reduces {}, it has to be a depth attachment with memory!",
+                pass.name, source.name
+            );
+        }
+        if source.extent != destination.extent {
+            panic!(
+                "depth pyramid pass {} reduces {} into {}, their extents {:?} and {:?} don't match!",
+                pass.name, source.name, destination.name, source.extent, destination.extent
+            );
+        }
+        let reduction = pass.reduction.unwrap_or(convention.farthest());
+        let pyramid = DepthPyramid::new(
+            ctx,
+            &pass.name,
+            &source,
+            &destination,
+            reduction,
+            shader_path,
+        );
+        let (before, after) = plan::depth_pyramid_transitions(passi, passes);
+        let to_barriers = |transitions: Vec<plan::ImageTransition>| {
+            transitions
+                .iter()
+                .map(|e| {
+                    let att = if e.attachment == source.name {
+                        &source
+                    } else {
+                        &destination
+                    };
+                    e.to_barrier(att)
+                })
+                .collect::<Vec<_>>()
+        };
+        let image_barriers = to_barriers(before);
+        let after_barriers = to_barriers(after);
+        let layers_of = |aspect_mask| vk::ImageSubresourceLayers {
+            aspect_mask,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let offsets_of = |extent: vk::Extent2D| {
+            [
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: extent.width as i32,
+                    y: extent.height as i32,
+                    z: 1,
+                },
+            ]
+        };
+        let blit = Blit {
+            source_layers: layers_of(vk::ImageAspectFlags::DEPTH),
+            destination_layers: layers_of(vk::ImageAspectFlags::COLOR),
+            source_offsets: offsets_of(source.extent),
+            destination_offsets: offsets_of(destination.extent),
+            filter: vk::Filter::NEAREST,
+            is_copy: false,
+            after_barriers,
+            is_source_written: true,
+            is_destination_written: false,
+            pyramid: Some(pyramid),
+            source,
+            destination,
+        };
+        Self::transfer_stage_of(pass, blit, image_barriers, slot)
+    }
+
+    /*
+     * Blits and depth pyramids have no pipeline, attachments nor tasks of their own.
+     */
+    fn transfer_stage_of(
+        pass: &Pass,
+        blit: Blit,
+        image_barriers: Vec<vk::ImageMemoryBarrier2>,
+        slot: &TransferSlot,
+    ) -> crate::pipeline::stage::Stage {
+        crate::pipeline::stage::Stage {
+            name: pass.name.clone(),
+            template: pass.template.clone(),
+            rendering: super::stage::Rendering {
+                attachments: Vec::new(),
+                depth_stencil: None,
+                stencil: None,
+                default_attachment_index: None,
+                default_view_format: ViewFormat::Srgb,
+            },
+            viewport_layers: Vec::new(),
+            pipeline: vk::Pipeline::null(),
+            variant_pipelines: vec![None; slot.total_variants],
+            layout: vk::PipelineLayout::null(),
+            outputs: Vec::new(),
+            inputs: Vec::new(),
+            input_descriptors: Vec::new(),
+            is_input_substituted: Vec::new(),
+            depth_stencil: None,
+            is_depth_stencil_written: false,
+            per_instance_updaters: Vec::new(),
+            per_pass_updaters: Vec::new(),
+            per_draw_fields: Vec::new(),
+            constants: StageConstants::default(),
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_bounds: None,
+            shading_rate: ShadingRate::Full,
+            shading_rate_texture: None,
+            shading_rate_image: None,
+            view_mask: 0,
+            attachment_descriptors: None,
+            // Never drawn, tasks don't get matched to blit stages
+            task_kind: TaskKind::Fullscreen,
+            index: slot.index,
+            is_final: false,
+            is_first_default_write: false,
+            image_barriers,
+            buffer_dependencies: Vec::new(),
+            is_validation_layer_enabled: slot.is_validation_layer_enabled,
+            reflection: ShaderReflection::default(),
+            blit: Some(blit),
+            is_scope_continued: false,
+            is_scope_kept_open: false,
+            vertex_layouts: Vec::new(),
+            vertex_formats: AcceptedFormats::default(),
+            dynamic_scissor: None,
+            budget_priority: pass.budget_priority,
+            // Blits copy whole attachments, once
+            is_shared_across_viewports: true,
+            transform_interpolation: TransformInterpolation::None,
+            texture_feedback: 0,
+            viewport: vk::Viewport::default(),
+            scissor: vk::Rect2D::default(),
+        }
+    }
+
+    ///
+    /// Corners of the region of the attachment, clipped to it since attachments sized
+    /// relative to the window shrink along with it.
+    ///
+    pub(super) fn blit_offsets_of(
+        pass: &Pass,
+        name: &str,
+        extent: vk::Extent2D,
+        rect: Option<Rect>,
+    ) -> [vk::Offset3D; 2] {
+        let rect = rect.unwrap_or(Rect {
+            x: 0,
+            y: 0,
+            width: extent.width,
+            height: extent.height,
+        });
+        let end = [
+            (rect.x + rect.width).min(extent.width),
+            (rect.y + rect.height).min(extent.height),
+        ];
+        if end[0] <= rect.x || end[1] <= rect.y {
+            panic!(
+                "pass {} blits {:?} of {}, nothing of it is within {}x{}!",
+                pass.name, rect, name, extent.width, extent.height
+            );
+        }
+        [
+            vk::Offset3D {
+                x: rect.x as i32,
+                y: rect.y as i32,
+                z: 0,
+            },
+            vk::Offset3D {
+                x: end[0] as i32,
+                y: end[1] as i32,
+                z: 1,
+            },
+        ]
+    }
+
+    ///
+    /// What a blit pass can't do on any device: mixing color with depth or stencil, and
+    /// filtering or converting depth stencil attachments.
+    ///
+    pub fn validate_blit_aspects(pass: &Pass, source_format: Format, destination_format: Format) {
+        let source = &pass.source.as_ref().unwrap().name;
+        let destination = pass.destination.as_ref().unwrap();
+        let aspect = source_format.aspect();
+        if aspect != destination_format.aspect() {
+            panic!(
+                "pass {} blits {} into {}, their aspects {:?} and {:?} don't match!",
+                pass.name,
+                source,
+                destination,
+                aspect,
+                destination_format.aspect()
+            );
+        }
+        if source_format.has_depth_or_stencil() && pass.filter == Filtering::Linear {
+            panic!(
+                "pass {} blits depth stencil attachment {} with linear filtering, only nearest is supported!",
+                pass.name, source
+            );
+        }
+        if source_format.has_depth_or_stencil() && source_format != destination_format {
+            panic!(
+                "pass {} blits {} into {}, depth stencil blits can't convert between formats!",
+                pass.name, source, destination
+            );
+        }
+    }
+
+    fn validate_blit_formats(
+        ctx: &VulkanContext,
+        pass: &Pass,
+        source: &Attachment,
+        destination: &Attachment,
+    ) {
+        let features_of = |format: vk::Format| unsafe {
+            ctx.instance
+                .get_physical_device_format_properties(ctx.physical_device, format)
+                .optimal_tiling_features
+        };
+        let source_features = features_of(source.vk_format);
+        if !source_features.contains(vk::FormatFeatureFlags::BLIT_SRC)
+            || !features_of(destination.vk_format).contains(vk::FormatFeatureFlags::BLIT_DST)
+        {
+            panic!(
+                "device can't blit {:?} into {:?} for pass {}!",
+                source.vk_format, destination.vk_format, pass.name
+            );
+        }
+        if pass.filter == Filtering::Linear
+            && !source_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            panic!(
+                "device can't filter {:?} linearly for blit pass {}!",
+                source.vk_format, pass.name
+            );
+        }
+    }
+
+    fn validate_preserve_format(
+        ctx: &VulkanContext,
+        target: &PreservedTarget,
+        attachment: &Attachment,
+    ) {
+        if target.mode != PreserveMode::Blit {
+            return;
+        }
+        let features = unsafe {
+            ctx.instance
+                .get_physical_device_format_properties(ctx.physical_device, attachment.vk_format)
+                .optimal_tiling_features
+        };
+        let blit = vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST;
+        if !features.contains(blit) {
+            panic!(
+                "device can't blit {:?} to preserve attachment {}, preserve it with copyCentered!",
+                attachment.vk_format, target.name
+            );
+        }
+        if target.filter == Filtering::Linear
+            && !features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            panic!(
+                "device can't filter {:?} linearly to preserve attachment {}!",
+                attachment.vk_format, target.name
+            );
+        }
+    }
+
+    pub fn image_desc_buffer(
+        ctx: &VulkanContext,
+        mem: Option<&mut DeviceAllocator>,
+        size: u32,
+    ) -> Box<dyn DescriptorBackend> {
+        Self::descriptors_of(
+            ctx,
+            mem,
+            "images".to_string(),
+            DescriptorType::SAMPLED_IMAGE,
+            SlotLayout::Array(size),
+        )
+    }
+
+    /*
+     * Single layer view of a target allocated per viewport, what the viewport of the
+     * layer renders into and samples.
+     */
+    fn layer_view_of(
+        ctx: &VulkanContext,
+        image: vk::Image,
+        format: crate::format::Format,
+        layer: u32,
+    ) -> vk::ImageView {
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format.to_vk())
+            .subresource_range(vk::ImageSubresourceRange {
+                base_array_layer: layer,
+                ..Attachment::default_subresource_range(format.aspect())
+            });
+        unsafe { ctx.device.create_image_view(&info, None) }.expect("failed image view")
+    }
+
+    pub fn attachment_image_desc_buffer(
+        ctx: &VulkanContext,
+        mem: Option<&mut DeviceAllocator>,
+        prefix: &str,
+        bindings: Vec<u32>,
+    ) -> Box<dyn DescriptorBackend> {
+        let name = format!("{}_attachments", prefix);
+        Self::descriptors_of(
+            ctx,
+            mem,
+            name,
+            DescriptorType::COMBINED_IMAGE_SAMPLER,
+            SlotLayout::Bindings(bindings),
+        )
+    }
+
+    pub fn sampler_desc_buffer(
+        ctx: &VulkanContext,
+        mem: Option<&mut DeviceAllocator>,
+        size: u32,
+    ) -> Box<dyn DescriptorBackend> {
+        Self::descriptors_of(
+            ctx,
+            mem,
+            "samplers".to_string(),
+            DescriptorType::SAMPLER,
+            SlotLayout::Array(size),
+        )
+    }
+
+    ///
+    /// Descriptor buffer when there is an allocator for it, descriptor set otherwise.
+    ///
+    fn descriptors_of(
+        ctx: &VulkanContext,
+        mem: Option<&mut DeviceAllocator>,
+        name: String,
+        descriptor_type: DescriptorType,
+        slots: SlotLayout,
+    ) -> Box<dyn DescriptorBackend> {
+        match mem {
+            Some(mem) => Box::new(DescriptorBuffer::of(
+                ctx,
+                mem,
+                name,
+                descriptor_type,
+                &slots,
+                1,
+            )),
+            None => Box::new(DescriptorSets::of(ctx, name, descriptor_type, slots)),
+        }
+    }
+
+    pub fn extent_of(
+        opt_width: U32OrF32,
+        opt_height: U32OrF32,
+        ref_width: f32,
+        ref_height: f32,
+    ) -> vk::Extent2D {
+        vk::Extent2D {
+            width: match opt_width {
+                U32OrF32::U32(v) => v,
+                U32OrF32::F32(v) => (ref_width * v).ceil() as u32,
+            },
+            height: match opt_height {
+                U32OrF32::U32(v) => v,
+                U32OrF32::F32(v) => (ref_height * v).ceil() as u32,
+            },
+        }
+    }
+
+    fn gen_image_barriers_for(
+        currenti: usize,
+        inputs: &[Attachment],
+        outputs: &[Attachment],
+        passes: &[Pass],
+    ) -> Vec<vk::ImageMemoryBarrier2> {
+        let input_names: Vec<_> = inputs.iter().map(|e| e.name.as_str()).collect();
+        let output_names: Vec<_> = outputs
+            .iter()
+            .map(|e| (e.name.as_str(), e.format.has_depth_or_stencil()))
+            .collect();
+        plan::image_transitions_for(currenti, &input_names, &output_names, passes)
+            .iter()
+            .map(|e| {
+                let att = inputs
+                    .iter()
+                    .chain(outputs)
+                    .find(|att| att.name == e.attachment)
+                    .unwrap();
+                e.to_barrier(att)
+            })
+            .collect()
     }
 }
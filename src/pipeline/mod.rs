@@ -1,19 +1,41 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use self::descriptor::DescriptorBuffer;
-use self::sampler::SamplerKey;
+use ash::vk;
 
+use crate::buffer::{DeviceAllocator, DeviceSlice};
+use crate::format::Format;
 use crate::pipeline::attachment::Attachment;
-use crate::pipeline::sampler::Sampler;
+use crate::pipeline::budget::PipelineBudget;
+use crate::config::UninitializedReads;
+use crate::pipeline::file::{DepthConvention, InitialState, UninitializedRead};
+use crate::pipeline::hints::OptimizationHint;
+use crate::pipeline::preserve::PreservedTarget;
 use crate::pipeline::stage::Stage;
+use crate::render_task::TaskKind;
+use crate::vertex_layout::{StreamFormats, VertexAttributeKind, VertexLayoutKind};
 
 pub mod attachment;
+pub mod budget;
 pub mod descriptor;
+pub mod descriptor_set;
+pub mod depth_pyramid;
+mod downsample_chain;
+pub mod dry_run;
 pub mod file;
+mod graph;
+pub mod hints;
+pub mod include;
 mod load;
+pub mod per_draw;
+pub mod plan;
+pub mod preserve;
 pub mod sampler;
+pub mod specialization;
 pub mod stage;
 mod state;
+pub mod state_cache;
+pub mod template;
 
 // Fixed descriptor set indices
 pub const DESCRIPTOR_SET_SAMPLER: u32 = 0;
@@ -22,10 +44,34 @@ pub const DESCRIPTOR_SET_TARGET_IMAGE: u32 = 2;
 
 pub struct Pipeline {
     pub stages: Vec<Stage>,
+    pub variant_names: Vec<String>,
     pub attachments: Vec<Attachment>,
-    pub image_descriptors: DescriptorBuffer,
-    pub sampler_descriptors: DescriptorBuffer,
-    pub samplers_by_key: HashMap<SamplerKey, Sampler>,
+    // Linear sampler tasks sample with while the samplers they name aren't published yet
+    pub fallback_sampler: u8,
+    pub optimization_hints: Vec<OptimizationHint>,
+    pub budget: PipelineBudget,
+    // Attachments some stage rendered into, the rest were never laid out for sampling
+    pub written_attachments: HashSet<vk::Image>,
+    // Cleared by record_initial_states before the first frame records its stages
+    pub uninitialized_attachments: Vec<(String, InitialState)>,
+    // Written attachments release_attachments destroyed, reset once loaded again
+    pub released_attachments: Vec<String>,
+    // Carried over into the pipeline loaded again for a new swapchain size
+    pub preserved_targets: Vec<PreservedTarget>,
+    // Value named buffers get filled with when registered
+    pub buffer_fills: HashMap<String, u32>,
+    // See file::Pipeline::uninitialized_reads, handled by resolve_uninitialized_reads
+    pub uninitialized_reads: Vec<UninitializedRead>,
+    // Buffers some stage reads or writes
+    pub declared_buffers: HashSet<String>,
+    // Registered with Renderer::register_named_buffer, unregistered ones get global barriers
+    pub named_buffers: HashMap<String, DeviceSlice>,
+    // Some pass is declared present, the only one writing the default attachment
+    pub is_present_declared: bool,
+    // Offscreen passes of stages loading depth clear it to the far end of this one
+    pub depth_convention: DepthConvention,
+    // Source files to the stages using them, see file::Pipeline::shader_dependencies
+    pub shader_dependents: BTreeMap<PathBuf, BTreeSet<String>>,
 }
 
 pub fn signal_value_for(current_frame: u64, total_stages: u32, stage_index: u32) -> u64 {
@@ -41,20 +87,235 @@ impl Pipeline {
         signal_value_for(current_frame, self.total_stages(), stage_index)
     }
 
-    pub fn destroy(&self, device: &ash::Device) {
-        unsafe {
-            for e in [&self.image_descriptors, &self.sampler_descriptors] {
-                e.destroy(device);
+    pub fn variant_id(&self, name: &str) -> Option<u16> {
+        self.variant_names
+            .iter()
+            .position(|e| e == name)
+            .map(|e| e as u16)
+    }
+
+    pub fn optimization_hints(&self) -> &[OptimizationHint] {
+        &self.optimization_hints
+    }
+
+    pub fn budget(&self) -> &PipelineBudget {
+        &self.budget
+    }
+
+    ///
+    /// Names of the stages whose shaders are made of the file, in pass order. Relative
+    /// paths go from the working directory, like the shader paths of the pipeline do.
+    ///
+    pub fn stages_depending_on(&self, path: &Path) -> Vec<String> {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let path = include::normalize(path.strip_prefix(&cwd).unwrap_or(path));
+        let names = match self.shader_dependents.get(&path) {
+            Some(e) => e,
+            None => return Vec::new(),
+        };
+        self.stages
+            .iter()
+            .filter(|e| names.contains(&e.name))
+            .map(|e| e.name.clone())
+            .collect()
+    }
+
+    ///
+    /// Whether every stage drawing tasks of the kind reads meshes of the layout.
+    ///
+    pub fn accepts_vertex_layout(&self, kind: TaskKind, layout: VertexLayoutKind) -> bool {
+        self.stages
+            .iter()
+            .filter(|e| e.blit.is_none() && e.task_kind == kind)
+            .all(|e| e.vertex_layouts.contains(&layout))
+    }
+
+    ///
+    /// Attribute and format of the first attribute some stage drawing tasks of the kind
+    /// doesn't read in the format the mesh holds it in, None if every stage does.
+    ///
+    pub fn rejected_vertex_format(
+        &self,
+        kind: TaskKind,
+        formats: &StreamFormats,
+    ) -> Option<(VertexAttributeKind, Format)> {
+        self.stages
+            .iter()
+            .filter(|e| e.blit.is_none() && e.task_kind == kind)
+            .find_map(|e| e.vertex_formats.rejected_of(formats))
+    }
+
+    ///
+    /// Layout the attachment is left in after the last stage touching it, None if no
+    /// stage does.
+    ///
+    pub fn end_of_frame_layout(&self, attachment: &Attachment) -> Option<vk::ImageLayout> {
+        let image = attachment.image;
+        self.stages.iter().rev().find_map(|stage| match &stage.blit {
+            // Pyramids are left sampleable, for the texture id handed out for them
+            Some(blit) if blit.destination.image == image && blit.pyramid.is_some() => {
+                Some(vk::ImageLayout::READ_ONLY_OPTIMAL)
+            }
+            Some(blit) if blit.destination.image == image => {
+                Some(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+            }
+            Some(blit) if blit.source.image == image => Some(vk::ImageLayout::READ_ONLY_OPTIMAL),
+            Some(_) => None,
+            None if stage
+                .outputs
+                .iter()
+                .chain(&stage.depth_stencil)
+                .any(|e| e.image == image) =>
+            {
+                Some(vk::ImageLayout::ATTACHMENT_OPTIMAL)
             }
-            for e in self.samplers_by_key.values() {
-                e.destroy(device);
+            None if stage.inputs.iter().any(|e| e.image == image) => {
+                Some(vk::ImageLayout::READ_ONLY_OPTIMAL)
             }
+            None => None,
+        })
+    }
+
+    ///
+    /// Panics listing the uninitialized reads with Error, gives what they read a zero
+    /// initial state with a warning for each with Zero.
+    ///
+    pub fn resolve_uninitialized_reads(&mut self, policy: UninitializedReads) {
+        let reads = std::mem::take(&mut self.uninitialized_reads);
+        if reads.is_empty() {
+            return;
+        }
+        if policy == UninitializedReads::Error {
+            let reads: Vec<_> = reads.iter().map(|e| e.to_string()).collect();
+            panic!("{}!", reads.join("; "));
+        }
+        for read in reads {
+            log::warn!("{}, it starts out zeroed", read);
+            if read.is_buffer {
+                self.buffer_fills.entry(read.resource).or_insert(0);
+                continue;
+            }
+            if self.uninitialized_attachments.iter().any(|e| e.0 == read.resource) {
+                continue;
+            }
+            let attachment = self.attachments.iter().find(|e| e.name == read.resource);
+            let state = match attachment {
+                Some(e) if e.format.has_depth() => InitialState::ClearDepth(0.0),
+                _ => InitialState::ClearColor([0.0; 4]),
+            };
+            self.uninitialized_attachments.push((read.resource, state));
+        }
+    }
+
+    ///
+    /// Clears the attachments with an initial state and leaves them in the layout the
+    /// frame ends them in, as if an earlier frame wrote them. Stages sample them and
+    /// blit from them right away then. Only the first call records anything.
+    ///
+    pub fn record_initial_states(
+        &mut self,
+        ctx: &crate::context::VulkanContext,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let uninitialized = std::mem::take(&mut self.uninitialized_attachments);
+        let cleared: Vec<_> = uninitialized
+            .into_iter()
+            .filter_map(|(name, state)| {
+                let attachment = self.attachments.iter().find(|e| e.name == name).unwrap();
+                // Nothing waits for it in any layout
+                let layout = self.end_of_frame_layout(attachment)?;
+                Some((attachment, state, layout))
+            })
+            .collect();
+        if cleared.is_empty() {
+            return;
+        }
+        let barrier = |attachment: &Attachment, from, to, src_stage, dst_stage| {
+            vk::ImageMemoryBarrier2::builder()
+                .image(attachment.image)
+                .subresource_range(attachment.subresource_range())
+                .old_layout(from)
+                .new_layout(to)
+                .src_stage_mask(src_stage)
+                .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+                .dst_stage_mask(dst_stage)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .build()
+        };
+        let transfer = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        let (all, clear) = (
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::PipelineStageFlags2::CLEAR,
+        );
+        let before: Vec<_> = cleared
+            .iter()
+            .map(|e| barrier(e.0, vk::ImageLayout::UNDEFINED, transfer, all, clear))
+            .collect();
+        let after: Vec<_> = cleared
+            .iter()
+            .map(|e| barrier(e.0, transfer, e.2, clear, all))
+            .collect();
+        ctx.extension
+            .try_begin_label(command_buffer, "initial states");
+        unsafe {
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before),
+            );
+            for (attachment, state, _) in &cleared {
+                let range = attachment.subresource_range();
+                match state {
+                    InitialState::ClearColor(color) => ctx.device.cmd_clear_color_image(
+                        command_buffer,
+                        attachment.image,
+                        transfer,
+                        &vk::ClearColorValue { float32: *color },
+                        &[range],
+                    ),
+                    InitialState::ClearDepth(depth) => {
+                        ctx.device.cmd_clear_depth_stencil_image(
+                            command_buffer,
+                            attachment.image,
+                            transfer,
+                            &vk::ClearDepthStencilValue {
+                                depth: *depth,
+                                stencil: 0,
+                            },
+                            &[range],
+                        )
+                    }
+                    InitialState::Fill(_) => unreachable!(),
+                }
+            }
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after),
+            );
+        }
+        ctx.extension.try_end_label(command_buffer);
+        let images: Vec<_> = cleared.iter().map(|e| e.0.image).collect();
+        self.written_attachments.extend(images);
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
             for stage in &self.stages {
                 device.destroy_pipeline(stage.pipeline, None);
+                for pipeline in stage.variant_pipelines.iter().flatten() {
+                    device.destroy_pipeline(*pipeline, None);
+                }
                 device.destroy_pipeline_layout(stage.layout, None);
-                if let Some(desc) = &stage.attachment_descriptors {
+                let layers = stage.viewport_layers.iter();
+                for desc in stage
+                    .attachment_descriptors
+                    .iter()
+                    .chain(layers.flat_map(|e| &e.attachment_descriptors))
+                {
                     desc.destroy(device)
                 }
+                if let Some(pyramid) = stage.blit.as_ref().and_then(|e| e.pyramid.as_ref()) {
+                    pyramid.destroy(device);
+                }
             }
             for attachment in &self.attachments {
                 if attachment.is_default() {
@@ -63,8 +324,115 @@ impl Pipeline {
                 }
                 device.free_memory(attachment.memory, None);
                 device.destroy_image_view(attachment.view, None);
+                for view in &attachment.viewport_views {
+                    device.destroy_image_view(*view, None);
+                }
                 device.destroy_image(attachment.image, None);
             }
         }
     }
+
+    ///
+    /// Gives the descriptor memory of the stages back, for a pipeline replaced while the
+    /// renderer lives on. destroy leaves it to the allocator going away.
+    ///
+    pub fn free_descriptors(&self, mem: &DeviceAllocator) {
+        for stage in &self.stages {
+            let layers = stage.viewport_layers.iter();
+            for desc in stage
+                .attachment_descriptors
+                .iter()
+                .chain(layers.flat_map(|e| &e.attachment_descriptors))
+            {
+                desc.free(mem);
+            }
+        }
+    }
+
+    ///
+    /// Destroys the images of the targets without preserveOnRecreate for a pipeline that
+    /// doesn't render again until loaded again, like while hibernated. Their attachments
+    /// stay, released. Returns the bytes of memory they had bound.
+    ///
+    pub fn release_attachments(&mut self, device: &ash::Device) -> u64 {
+        let is_released = |e: &Attachment| {
+            !e.is_default()
+                && !e.is_released()
+                && !self.preserved_targets.iter().any(|p| p.name == e.name)
+        };
+        let images: HashSet<_> = self
+            .attachments
+            .iter()
+            .filter(|e| is_released(e))
+            .map(|e| e.image)
+            .collect();
+        // Views into them go first
+        for blit in self.stages.iter_mut().flat_map(|e| &mut e.blit) {
+            let is_viewed =
+                images.contains(&blit.source.image) || images.contains(&blit.destination.image);
+            if let Some(pyramid) = blit.pyramid.as_mut().filter(|_| is_viewed) {
+                pyramid.release_views(device);
+            }
+        }
+        let mut released_bytes = 0;
+        for attachment in &mut self.attachments {
+            if !images.contains(&attachment.image) {
+                continue;
+            }
+            if self.written_attachments.remove(&attachment.image) {
+                self.released_attachments.push(attachment.name.clone());
+            }
+            unsafe {
+                released_bytes += device.get_image_memory_requirements(attachment.image).size;
+                device.destroy_image_view(attachment.view, None);
+                for view in attachment.viewport_views.drain(..) {
+                    device.destroy_image_view(view, None);
+                }
+                device.destroy_image(attachment.image, None);
+                device.free_memory(attachment.memory, None);
+            }
+            attachment.image = vk::Image::null();
+            attachment.view = vk::ImageView::null();
+            attachment.memory = vk::DeviceMemory::null();
+        }
+        released_bytes
+    }
+
+    pub fn has_released_attachments(&self) -> bool {
+        self.attachments.iter().any(|e| e.is_released())
+    }
+
+    ///
+    /// Layers of the targets allocated per split screen viewport, None without any.
+    ///
+    pub fn viewport_layers(&self) -> Option<u32> {
+        self.attachments
+            .iter()
+            .find(|e| !e.viewport_views.is_empty())
+            .map(|e| e.layers)
+    }
+
+    ///
+    /// Takes over what was set at runtime on the pipeline it replaces: the depth bounds,
+    /// shading rate images, constants and debug flags of the stages of the same name, and
+    /// the registered named buffers.
+    ///
+    pub fn carry_over(&mut self, old: &Pipeline) {
+        for stage in &mut self.stages {
+            let previous = match old.stages.iter().find(|e| e.name == stage.name) {
+                Some(e) => e,
+                None => continue,
+            };
+            if let Some(bounds) = &mut stage.depth_bounds {
+                *bounds = previous.depth_bounds.unwrap_or(*bounds);
+            }
+            stage.shading_rate_texture = previous.shading_rate_texture;
+            stage.constants = previous.constants.clone();
+        }
+        for (name, slice) in &old.named_buffers {
+            if self.declared_buffers.contains(name) {
+                self.named_buffers.insert(name.clone(), *slice);
+            }
+        }
+    }
 }
@@ -0,0 +1,256 @@
+/*
+ * Layout of the push constants every draw of a stage gets, the Registers block shaders
+ * declare between INPUTS_BEGIN and INPUTS_END. Stage::prepare writes it in this order,
+ * each member only when its condition holds:
+ *
+ *   pass                   per pass data address, if the stage has perPassUpdaters
+ *   constants              stage constants address, if the stage declares constants
+ *   positions, normals,    vertex addresses, unless the stage batch is FULLSCREEN
+ *   texCoords
+ *   one per updater        per instance addresses, in perInstanceUpdaters order
+ *   per draw fields        in perDrawFields order, see PerDrawField
+ *
+ * Meshes with an interleaved layout fill the 24 bytes of the vertex addresses with the
+ * vertices address, the stride and the offsets instead, see USING(ATTR, INTERLEAVED).
+ *
+ * LAYOUT_VERSION changes whenever any of this does. The renderer writes it into the
+ * perDrawLayoutVersion member of the frame constants when they have one, so shaders can
+ * compare it against the PER_DRAW_LAYOUT_VERSION define of the generated block. The
+ * frameIndex, noiseSeed and noiseOffset members get filled in the same way, see noise.rs,
+ * the noise textures themselves are in the image table at BLUE_NOISE_TEXTURE and
+ * BLUE_NOISE_DUAL_TEXTURE.
+ */
+use crate::format::Format;
+use crate::pipeline::file::{Pass, PerDrawField};
+use crate::render_task::TaskKind;
+use crate::shader_resource::ResourceKind;
+use crate::vertex_layout::{AcceptedFormats, VertexAttributeKind};
+
+pub const LAYOUT_VERSION: u32 = 1;
+// Frame constants member the version gets written into, a uint
+pub const VERSION_MEMBER: &str = "perDrawLayoutVersion";
+
+const ADDRESS_SIZE: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    PerPassData,
+    Constants,
+    NotFullscreen,
+    PerInstance(ResourceKind),
+    Field(PerDrawField),
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PerPassData => write!(f, "perPassUpdaters isn't empty"),
+            Self::Constants => write!(f, "constants isn't empty"),
+            Self::NotFullscreen => write!(f, "batch isn't FULLSCREEN"),
+            Self::PerInstance(kind) => write!(f, "perInstanceUpdaters lists {}", kind),
+            Self::Field(field) => write!(f, "perDrawFields lists {}", field),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PerDrawMember {
+    pub name: &'static str,
+    pub glsl_type: &'static str,
+    pub offset: u32,
+    pub size: u32,
+    // What makes the stage have it
+    pub condition: Condition,
+}
+
+///
+/// Push constants of the draws of one stage, member by member.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PerDrawLayout {
+    members: Vec<PerDrawMember>,
+}
+
+impl PerDrawLayout {
+    ///
+    /// Vertex address types follow the first format the stage accepts for each
+    /// attribute.
+    ///
+    pub fn new(
+        has_pass_data: bool,
+        has_constants: bool,
+        is_fullscreen: bool,
+        per_instance_updaters: &[ResourceKind],
+        vertex_formats: &AcceptedFormats,
+        per_draw_fields: &[PerDrawField],
+    ) -> Self {
+        let mut layout = Self::default();
+        if has_pass_data {
+            layout.push("pass", "PassData", ADDRESS_SIZE, Condition::PerPassData);
+        }
+        if has_constants {
+            let glsl_type = crate::stage_constants::BLOCK_NAME;
+            layout.push("constants", glsl_type, ADDRESS_SIZE, Condition::Constants);
+        }
+        if !is_fullscreen {
+            for kind in VertexAttributeKind::ALL {
+                let format = vertex_formats
+                    .of(kind)
+                    .first()
+                    .copied()
+                    .unwrap_or(kind.format());
+                let (glsl_type, name) = vertex_member(kind, format);
+                layout.push(name, glsl_type, ADDRESS_SIZE, Condition::NotFullscreen);
+            }
+        }
+        for &kind in per_instance_updaters {
+            let (glsl_type, name) = instance_member(kind);
+            layout.push(name, glsl_type, ADDRESS_SIZE, Condition::PerInstance(kind));
+        }
+        for &field in per_draw_fields {
+            for (&name, &glsl_type) in field.member_names().iter().zip(field_types(field)) {
+                layout.push(
+                    name,
+                    glsl_type,
+                    field.member_size(),
+                    Condition::Field(field),
+                );
+            }
+        }
+        layout
+    }
+
+    pub fn of_pass(pass: &Pass) -> Self {
+        let per_instance_updaters: Vec<_> = pass
+            .per_instance_updaters
+            .iter()
+            .map(|e| e.to_resource_kind())
+            .collect();
+        Self::new(
+            !pass.per_pass_updaters.is_empty(),
+            !pass.constants.is_empty() || pass.has_debug_flags,
+            pass.batch == Some(TaskKind::Fullscreen),
+            &per_instance_updaters,
+            &pass.vertex_formats,
+            &pass.per_draw_fields,
+        )
+    }
+
+    pub fn members(&self) -> &[PerDrawMember] {
+        &self.members
+    }
+
+    pub fn member(&self, name: &str) -> Option<&PerDrawMember> {
+        self.members.iter().find(|e| e.name == name)
+    }
+
+    ///
+    /// Bytes pushed per draw.
+    ///
+    pub fn size(&self) -> u32 {
+        self.members.last().map_or(0, |e| e.offset + e.size)
+    }
+
+    ///
+    /// Registers block the stage's shaders declare, along with the version define. The
+    /// buffer reference types it names have to be declared before it, PassData with
+    /// PASS_DATA_BEGIN and StageConstants with StageConstants::glsl_source.
+    ///
+    pub fn glsl_struct(&self, stage: &str) -> String {
+        let mut src = format!(
+            "// Per draw layout of stage {}, generated by Renderer::per_draw_glsl_struct, don't edit\n\
+             #define PER_DRAW_LAYOUT_VERSION {}\n\
+             layout(scalar, push_constant) uniform Registers\n{{\n",
+            stage, LAYOUT_VERSION
+        );
+        for member in &self.members {
+            src += &format!("  {} {};\n", member.glsl_type, member.name);
+        }
+        src += "}\nregisters;\n";
+        src
+    }
+
+    ///
+    /// Members as a markdown table, one row each.
+    ///
+    pub fn markdown_table(&self) -> String {
+        let mut doc = "| Offset | Size | Member | GLSL type | Present when |\n\
+                       |-------:|-----:|--------|-----------|--------------|\n"
+            .to_string();
+        for e in &self.members {
+            doc += &format!(
+                "| {} | {} | `{}` | `{}` | {} |\n",
+                e.offset, e.size, e.name, e.glsl_type, e.condition
+            );
+        }
+        doc
+    }
+
+    fn push(
+        &mut self,
+        name: &'static str,
+        glsl_type: &'static str,
+        size: u32,
+        condition: Condition,
+    ) {
+        let offset = self.size();
+        self.members.push(PerDrawMember {
+            name,
+            glsl_type,
+            offset,
+            size,
+            condition,
+        });
+    }
+}
+
+/*
+ * Names the USING macros of shared_vulkan.glsl.frag give them. Kinds it has no buffer
+ * reference type for are plain addresses.
+ */
+const fn vertex_member(kind: VertexAttributeKind, format: Format) -> (&'static str, &'static str) {
+    match (kind, format) {
+        (VertexAttributeKind::Position, Format::R16G16B16A16_SFLOAT) => {
+            ("PositionsF16", "positions")
+        }
+        (VertexAttributeKind::Position, _) => ("Positions", "positions"),
+        (VertexAttributeKind::Normal, Format::A2B10G10R10_SNORM_PACK32) => {
+            ("NormalsSnorm10", "normals")
+        }
+        (VertexAttributeKind::Normal, _) => ("Normals", "normals"),
+        (VertexAttributeKind::TexCoord, Format::R16G16_UNORM) => ("TexCoordsUnorm16", "texCoords"),
+        (VertexAttributeKind::TexCoord, _) => ("TexCoords", "texCoords"),
+    }
+}
+
+const fn instance_member(kind: ResourceKind) -> (&'static str, &'static str) {
+    match kind {
+        ResourceKind::Transform => ("Transforms", "transforms"),
+        ResourceKind::Material => ("Materials", "materials"),
+        ResourceKind::DirLight => ("DirLights", "dirLights"),
+        ResourceKind::PointLight => ("PointLights", "pointLights"),
+        ResourceKind::TransformExtra => ("TransformExtras", "transformExtras"),
+        ResourceKind::Frustum => ("uint64_t", "frustums"),
+        ResourceKind::ViewRay => ("uint64_t", "viewRays"),
+        ResourceKind::SpotLight => ("uint64_t", "spotLight"),
+        ResourceKind::Joint => ("uint64_t", "joints"),
+        ResourceKind::Sky => ("uint64_t", "skies"),
+        ResourceKind::StaticShadow => ("uint64_t", "staticShadows"),
+        ResourceKind::LightClusters => ("uint64_t", "lightClusters"),
+        ResourceKind::ViewMatrices => ("uint64_t", "viewMatrices"),
+        ResourceKind::FrameConstants => ("uint64_t", "frameConstants"),
+    }
+}
+
+const fn field_types(field: PerDrawField) -> &'static [&'static str] {
+    match field {
+        PerDrawField::AlbedoTexture => &["uint", "uint"],
+        PerDrawField::AlphaCutoff | PerDrawField::ViewDepth => &["float"],
+        PerDrawField::Flags => &["uint"],
+        PerDrawField::PreviousTransform => &["TransformExtras"],
+        PerDrawField::InterpolatedTransform => {
+            &["SimulationStates", "SimulationStates", "SimulationBlend"]
+        }
+        PerDrawField::TextureFeedback => &["TextureFeedback"],
+    }
+}
@@ -0,0 +1,686 @@
+use ash::vk;
+
+use super::{
+    attachment::Attachment,
+    descriptor::SET_ATTACHMENTS,
+    file::{Pass, PassKind},
+    stage::{BufferAccess, BufferDependency},
+};
+use crate::reflection::{BindingKind, BindingMismatch, DescriptorBinding, ShaderReflection};
+use crate::render_task::{RenderTask, ScissorRect};
+
+/*
+ * Decisions about a frame that don't need a device: what a stage waits on before it
+ * renders, which stages share a rendering scope, where their inputs get bound, the order
+ * tasks get drawn in and the state changes between draws. Pipeline::load and
+ * Stage::render go through these, and so does the DryRun, so the trace it writes is the
+ * frame the device records.
+ */
+
+///
+/// Layout change of an attachment along with the accesses it waits on and blocks.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    pub dst_stage: vk::PipelineStageFlags2,
+    pub dst_access: vk::AccessFlags2,
+}
+
+impl Transition {
+    // Written by an earlier stage, sampled by this one
+    pub const SAMPLE_WRITTEN: Self = Self {
+        old_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+        new_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+        src_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        src_access: vk::AccessFlags2::MEMORY_WRITE,
+        dst_stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        dst_access: vk::AccessFlags2::MEMORY_READ,
+    };
+    // Chains with the wait on the acquired image, or the scaling of the last frame
+    pub const DEFAULT_WRITE: Self = Self {
+        old_layout: vk::ImageLayout::UNDEFINED,
+        new_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+        src_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        src_access: vk::AccessFlags2::MEMORY_READ,
+        dst_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_access: vk::AccessFlags2::MEMORY_WRITE,
+    };
+    pub const DEFAULT_REWRITE: Self = Self {
+        old_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+        new_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+        src_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        src_access: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_access: vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw(),
+        ),
+    };
+    pub const DEFAULT_PRESENT: Self = Self {
+        old_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+        new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        src_stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        src_access: vk::AccessFlags2::MEMORY_WRITE,
+        dst_stage: vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+        dst_access: vk::AccessFlags2::NONE,
+    };
+
+    ///
+    /// Sampled by an earlier stage, written by this one. The contents get overwritten,
+    /// so they're discarded.
+    ///
+    pub fn write_sampled(is_depth: bool) -> Self {
+        Self {
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+            src_stage: vk::PipelineStageFlags2::NONE,
+            src_access: vk::AccessFlags2::MEMORY_READ,
+            dst_stage: if is_depth {
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+            } else {
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+            },
+            dst_access: vk::AccessFlags2::MEMORY_WRITE,
+        }
+    }
+
+    pub fn to_barrier(
+        &self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+    ) -> vk::ImageMemoryBarrier2 {
+        vk::ImageMemoryBarrier2::builder()
+            .image(image)
+            .old_layout(self.old_layout)
+            .new_layout(self.new_layout)
+            .src_stage_mask(self.src_stage)
+            .src_access_mask(self.src_access)
+            .dst_stage_mask(self.dst_stage)
+            .dst_access_mask(self.dst_access)
+            .subresource_range(range)
+            .build()
+    }
+}
+
+///
+/// Transition of an attachment of the pipeline, by name.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageTransition {
+    pub attachment: String,
+    pub transition: Transition,
+}
+
+impl ImageTransition {
+    pub fn to_barrier(&self, att: &Attachment) -> vk::ImageMemoryBarrier2 {
+        self.transition
+            .to_barrier(att.image, att.subresource_range())
+    }
+}
+
+///
+/// Transitions a stage needs before sampling the inputs and rendering into the
+/// outputs, given as name and whether it's a depth stencil attachment. Each one waits
+/// on the closest earlier pass that used the attachment the other way, wrapping around
+/// into the previous frame. The default attachment is left out, which of the swapchain
+/// images it is changes every frame.
+///
+pub fn image_transitions_for(
+    currenti: usize,
+    inputs: &[&str],
+    outputs: &[(&str, bool)],
+    passes: &[Pass],
+) -> Vec<ImageTransition> {
+    let mut transitions = Vec::new();
+    let wrap_around = |index: usize| {
+        if index == 0 {
+            passes.len() - 1
+        } else {
+            index - 1
+        }
+    };
+    for input in inputs {
+        if Attachment::DEFAULT_NAME == *input {
+            panic!("Can't read from the default attachment!")
+        }
+        let mut i = currenti;
+        loop {
+            i = wrap_around(i);
+            if i == currenti {
+                // Looped back to current pass, nothing to check
+                break;
+            }
+            let prev = &passes[i];
+            if prev.has_input(input) {
+                // Already issued barrier before
+                break;
+            }
+            if !prev.has_output(input) {
+                continue;
+            }
+            if prev.kind == PassKind::DepthPyramid {
+                // Left sampleable already, see depth_pyramid_transitions
+                break;
+            }
+            transitions.push(ImageTransition {
+                attachment: input.to_string(),
+                transition: Transition::SAMPLE_WRITTEN,
+            });
+            break;
+        }
+    }
+    for (output, is_depth) in outputs {
+        if Attachment::DEFAULT_NAME == *output {
+            // Handled while rendering, see Stage::render
+            continue;
+        }
+        let mut i = currenti;
+        loop {
+            i = wrap_around(i);
+            if i == currenti {
+                break;
+            }
+            let prev = &passes[i];
+            if prev.has_output(output) {
+                break;
+            }
+            if !prev.has_input(output) {
+                continue;
+            }
+            transitions.push(ImageTransition {
+                attachment: output.to_string(),
+                transition: Transition::write_sampled(*is_depth),
+            });
+            break;
+        }
+    }
+    transitions
+}
+
+///
+/// Transitions into the transfer layouts before a blit and out of them after it.
+/// Between stages attachments are left in the layout of their last use, sampled or
+/// rendered to. Whichever the source and destination are in before the blit, they go
+/// back to the layout of a read and a write after it.
+///
+pub fn blit_transitions(
+    passi: usize,
+    passes: &[Pass],
+    is_destination_partial: bool,
+) -> (Vec<ImageTransition>, Vec<ImageTransition>) {
+    let pass = &passes[passi];
+    let source = pass.source.as_ref().unwrap().name.as_str();
+    let destination = pass.destination.as_ref().unwrap().as_str();
+    let is_source_rendered_to = !image_transitions_for(passi, &[source], &[], passes).is_empty();
+    let is_destination_sampled =
+        !image_transitions_for(passi, &[], &[(destination, false)], passes).is_empty();
+    use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+    let transition = |name: &str, from: (Il, Af, Ps), to: (Il, Af, Ps)| ImageTransition {
+        attachment: name.to_string(),
+        transition: Transition {
+            old_layout: from.0,
+            src_access: from.1,
+            src_stage: from.2,
+            new_layout: to.0,
+            dst_access: to.1,
+            dst_stage: to.2,
+        },
+    };
+    // Neighbouring stages can be draws or other blits
+    let earlier = |layout| (layout, Af::MEMORY_WRITE, Ps::ALL_COMMANDS);
+    let later = |layout| (layout, Af::MEMORY_READ | Af::MEMORY_WRITE, Ps::ALL_COMMANDS);
+    let copy_src = (
+        Il::TRANSFER_SRC_OPTIMAL,
+        Af::TRANSFER_READ,
+        Ps::ALL_TRANSFER,
+    );
+    let copy_dst = (
+        Il::TRANSFER_DST_OPTIMAL,
+        Af::TRANSFER_WRITE,
+        Ps::ALL_TRANSFER,
+    );
+    let source_layout = if is_source_rendered_to {
+        Il::ATTACHMENT_OPTIMAL
+    } else {
+        Il::READ_ONLY_OPTIMAL
+    };
+    // Contents outside of the region are kept, the rest gets overwritten anyway
+    let destination_layout = match (is_destination_partial, is_destination_sampled) {
+        (false, _) => Il::UNDEFINED,
+        (true, true) => Il::READ_ONLY_OPTIMAL,
+        (true, false) => Il::ATTACHMENT_OPTIMAL,
+    };
+    let before = vec![
+        transition(source, earlier(source_layout), copy_src),
+        transition(destination, earlier(destination_layout), copy_dst),
+    ];
+    let after = vec![
+        transition(source, copy_src, later(Il::READ_ONLY_OPTIMAL)),
+        transition(destination, copy_dst, later(Il::ATTACHMENT_OPTIMAL)),
+    ];
+    (before, after)
+}
+
+///
+/// Transitions before and after a depth pyramid, the source gets sampled by compute and
+/// the destination written by it one mip at a time. The destination is overwritten, its
+/// earlier contents are discarded. Afterwards it's left sampleable, for later stages
+/// and for shaders reaching it through Renderer::attachment_texture_id alike.
+///
+pub fn depth_pyramid_transitions(
+    passi: usize,
+    passes: &[Pass],
+) -> (Vec<ImageTransition>, Vec<ImageTransition>) {
+    let pass = &passes[passi];
+    let source = pass.source.as_ref().unwrap().name.as_str();
+    let destination = pass.destination.as_ref().unwrap().as_str();
+    // Depth gets rendered to as the depth stencil, which doesn't count as an output
+    let is_source_rendered_to = (1..passes.len())
+        .map(|back| &passes[(passi + passes.len() - back) % passes.len()])
+        .find(|e| {
+            e.has_input(source)
+                || e.has_output(source)
+                || e.depth_stencil.as_ref().is_some_and(|e| e.name == source)
+        })
+        .is_some_and(|e| !e.has_input(source));
+    use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+    let transition = |name: &str, from: (Il, Af, Ps), to: (Il, Af, Ps)| ImageTransition {
+        attachment: name.to_string(),
+        transition: Transition {
+            old_layout: from.0,
+            src_access: from.1,
+            src_stage: from.2,
+            new_layout: to.0,
+            dst_access: to.1,
+            dst_stage: to.2,
+        },
+    };
+    let earlier = |layout| (layout, Af::MEMORY_WRITE, Ps::ALL_COMMANDS);
+    let later = |layout| (layout, Af::MEMORY_READ | Af::MEMORY_WRITE, Ps::ALL_COMMANDS);
+    let sampled = (
+        Il::READ_ONLY_OPTIMAL,
+        Af::SHADER_SAMPLED_READ,
+        Ps::COMPUTE_SHADER,
+    );
+    let stored = (Il::GENERAL, Af::SHADER_STORAGE_WRITE, Ps::COMPUTE_SHADER);
+    let source_layout = if is_source_rendered_to {
+        Il::ATTACHMENT_OPTIMAL
+    } else {
+        Il::READ_ONLY_OPTIMAL
+    };
+    let before = vec![
+        transition(source, earlier(source_layout), sampled),
+        transition(destination, earlier(Il::UNDEFINED), stored),
+    ];
+    let after = vec![
+        transition(source, sampled, later(Il::READ_ONLY_OPTIMAL)),
+        transition(destination, stored, later(Il::READ_ONLY_OPTIMAL)),
+    ];
+    (before, after)
+}
+
+///
+/// Mirrors image_transitions_for: every buffer the pass touches waits on the closest
+/// earlier pass that touched it, wrapping around into the previous frame. Reads after
+/// reads need nothing, the barrier before the first reader covers them.
+///
+pub fn buffer_dependencies_for(currenti: usize, passes: &[Pass]) -> Vec<BufferDependency> {
+    let pass = &passes[currenti];
+    let mut names: Vec<_> = pass
+        .writes_buffers
+        .iter()
+        .chain(&pass.reads_buffers)
+        .collect();
+    names.sort();
+    names.dedup();
+    let access_of = |pass: &Pass, name: &str| {
+        if pass.writes_buffer(name) {
+            BufferAccess::WRITE
+        } else {
+            BufferAccess::READ
+        }
+    };
+    let mut dependencies = Vec::new();
+    for name in names {
+        let dst = access_of(pass, name);
+        // Walks back from the previous pass, ending on this one of the previous frame
+        let prev = (1..=passes.len())
+            .map(|e| &passes[(currenti + passes.len() - e) % passes.len()])
+            .find(|e| e.writes_buffer(name) || e.reads_buffer(name))
+            .unwrap();
+        let src = access_of(prev, name);
+        if src == BufferAccess::READ && dst == BufferAccess::READ {
+            continue;
+        }
+        dependencies.push(BufferDependency {
+            name: name.clone(),
+            src,
+            dst,
+        });
+    }
+    dependencies
+}
+
+///
+/// Load and store op of an attachment the stage renders into. Memoryless attachments
+/// never have contents to load or store.
+///
+pub fn attachment_ops(
+    is_memoryless: bool,
+    is_cleared: bool,
+    is_stored: bool,
+) -> (vk::AttachmentLoadOp, vk::AttachmentStoreOp) {
+    let load_op = if is_cleared {
+        vk::AttachmentLoadOp::CLEAR
+    } else if is_memoryless {
+        vk::AttachmentLoadOp::DONT_CARE
+    } else {
+        vk::AttachmentLoadOp::LOAD
+    };
+    let store_op = if is_memoryless || !is_stored {
+        vk::AttachmentStoreOp::DONT_CARE
+    } else {
+        vk::AttachmentStoreOp::STORE
+    };
+    (load_op, store_op)
+}
+
+///
+/// What decides whether a stage can draw inside the rendering scope of the previous
+/// one. Views can be anything comparable that tells the attachments rendered through
+/// apart, images anything naming them.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeShape<V, I> {
+    pub is_blit: bool,
+    pub is_final: bool,
+    pub is_isolated: bool,
+    pub view_mask: u32,
+    // Rate image is bound per stage and transitioned back after each one
+    pub is_rate_attachment: bool,
+    // Has image barriers or buffer dependencies
+    pub is_waiting: bool,
+    // Some attachment gets cleared when the stage begins rendering
+    pub is_clearing: bool,
+    pub views: V,
+    // Outputs and depth stencil attachment
+    pub rendered: Vec<I>,
+    pub inputs: Vec<I>,
+}
+
+pub fn continues_scope<V: PartialEq, I: PartialEq>(
+    first: &ScopeShape<V, I>,
+    prev: &ScopeShape<V, I>,
+    next: &ScopeShape<V, I>,
+) -> bool {
+    if prev.is_blit || next.is_blit || prev.is_final {
+        return false;
+    }
+    if prev.is_isolated || next.is_isolated {
+        return false;
+    }
+    // Timestamps between the stages would take one query per view
+    if prev.view_mask != 0 || next.view_mask != 0 {
+        return false;
+    }
+    if prev.is_rate_attachment || next.is_rate_attachment {
+        return false;
+    }
+    // Nothing can be waited on inside the scope
+    if next.is_waiting {
+        return false;
+    }
+    if prev.views != next.views {
+        return false;
+    }
+    // Memoryless attachments don't care, their contents carry over inside the scope
+    if next.is_clearing {
+        return false;
+    }
+    // What the scope renders to can only be sampled once it ends
+    !next.inputs.iter().any(|e| first.rendered.contains(e))
+}
+
+///
+/// Index of the stage that begins the rendering scope of each stage, its own unless it
+/// continues the one of an earlier stage.
+///
+pub fn scope_starts<V: PartialEq, I: PartialEq>(shapes: &[ScopeShape<V, I>]) -> Vec<usize> {
+    let mut starts = Vec::with_capacity(shapes.len());
+    let mut first = 0;
+    for i in 0..shapes.len() {
+        if i > 0 && !continues_scope(&shapes[first], &shapes[i - 1], &shapes[i]) {
+            first = i;
+        }
+        starts.push(first);
+    }
+    starts
+}
+
+///
+/// Binding of the attachment table each input goes into, given the name and reflection
+/// of each shader of the program and the layers of each input. Inputs naming a uniform,
+/// or named like one of the shader samplers, go where the shaders declare it. The rest
+/// fall back to the binding of their position, the old convention. Every sampler the
+/// shaders declare in the table needs an input, inputs the shaders don't sample only
+/// take part in the barriers and get some free binding.
+///
+/// Panics for inputs naming a uniform the shaders don't declare.
+///
+pub fn input_bindings(
+    pass: &Pass,
+    program: &str,
+    shaders: &[(&str, &ShaderReflection)],
+    input_layers: &[u32],
+) -> Result<Vec<u32>, BindingMismatch> {
+    let mismatch = |shader: &str, binding: &DescriptorBinding, problem: String| BindingMismatch {
+        stage: pass.name.clone(),
+        shader: shader.to_string(),
+        set: binding.set,
+        binding: binding.binding,
+        name: binding.name.clone(),
+        problem,
+    };
+    let mut declared: Vec<(&str, &DescriptorBinding)> = Vec::new();
+    for (shader, reflection) in shaders {
+        for binding in &reflection.bindings {
+            if binding.set != SET_ATTACHMENTS {
+                continue;
+            }
+            let is_same = |e: &DescriptorBinding| {
+                (e.kind, e.count, e.is_arrayed) == (binding.kind, binding.count, binding.is_arrayed)
+            };
+            match declared.iter().find(|e| e.1.binding == binding.binding) {
+                Some(e) if !is_same(e.1) => {
+                    let problem = format!("declared differently as {} in {}", e.1.name, e.0);
+                    return Err(mismatch(shader, binding, problem));
+                }
+                Some(_) => {}
+                None => declared.push((shader, binding)),
+            }
+        }
+    }
+    let mut bindings: Vec<Option<u32>> = pass
+        .inputs
+        .iter()
+        .map(|input| {
+            let uniform = input.uniform.as_ref().unwrap_or(&input.name);
+            let found = declared.iter().find(|e| &e.1.name == uniform);
+            if found.is_none() && input.uniform.is_some() {
+                panic!(
+                    "input {} of pass {} is sampled as {}, program {} declares no such attachment sampler!",
+                    input.name, pass.name, uniform, program
+                );
+            }
+            found.map(|e| e.1.binding)
+        })
+        .collect();
+    let mut positional = Vec::new();
+    for i in 0..bindings.len() {
+        let binding = i as u32;
+        let is_declared = declared.iter().any(|e| e.1.binding == binding);
+        if bindings[i].is_none() && is_declared && !bindings.contains(&Some(binding)) {
+            bindings[i] = Some(binding);
+            positional.push(pass.inputs[i].name.as_str());
+        }
+    }
+    if positional.len() > 1 {
+        log::warn!(
+            "pass {} maps inputs {:?} to its samplers by position, name them with uniform!",
+            pass.name,
+            positional
+        );
+    }
+    for (shader, binding) in &declared {
+        let users: Vec<_> = (0..bindings.len())
+            .filter(|i| bindings[*i] == Some(binding.binding))
+            .collect();
+        let i = match users[..] {
+            [i] => i,
+            [] => return Err(mismatch(shader, binding, "no input goes there".to_string())),
+            _ => {
+                let problem = "more than one input goes there".to_string();
+                return Err(mismatch(shader, binding, problem));
+            }
+        };
+        if binding.kind != BindingKind::CombinedImageSampler || binding.count != 1 {
+            let problem = format!(
+                "input {} needs a single CombinedImageSampler, got {} x{}",
+                pass.inputs[i].name, binding.kind, binding.count
+            );
+            return Err(mismatch(shader, binding, problem));
+        }
+        let is_layered = input_layers[i] > 1;
+        if binding.is_arrayed != is_layered {
+            let problem = format!(
+                "input {} has {} layers, sampled as {}",
+                pass.inputs[i].name,
+                input_layers[i],
+                if binding.is_arrayed {
+                    "an array"
+                } else {
+                    "a single layer"
+                }
+            );
+            return Err(mismatch(shader, binding, problem));
+        }
+    }
+    // Nothing reads them, any binding the shaders leave alone does
+    let mut free = (0..).filter(|e| !declared.iter().any(|d| d.1.binding == *e));
+    let mut taken: Vec<u32> = bindings.iter().flatten().copied().collect();
+    Ok(bindings
+        .into_iter()
+        .map(|e| {
+            e.unwrap_or_else(|| {
+                let binding = free.find(|e| !taken.contains(e)).unwrap();
+                taken.push(binding);
+                binding
+            })
+        })
+        .collect())
+}
+
+///
+/// Tasks of a stage in the order they get drawn, with the scissor of each one. Grouped
+/// by the key to change state as little as possible, the sort is stable so overlapping
+/// UI keeps its order. Scissors clamped to nothing skip the task rather than draw with
+/// an empty one.
+///
+pub fn order_tasks<K: Ord>(
+    tasks: &[RenderTask],
+    dynamic_scissor: Option<vk::Rect2D>,
+    key_of: impl Fn(&RenderTask) -> K,
+) -> Vec<(&RenderTask, Option<vk::Rect2D>)> {
+    let scissor_of = |task: &RenderTask| match (dynamic_scissor, task.scissor) {
+        (Some(area), Some(scissor)) => scissor.clamped_to(area).map(Some),
+        (area, _) => Some(area),
+    };
+    let mut tasks: Vec<_> = tasks
+        .iter()
+        .filter_map(|e| Some((e, scissor_of(e)?)))
+        .collect();
+    tasks.sort_by_key(|(e, _)| key_of(e));
+    tasks
+}
+
+///
+/// Scissor of a draw clipped to a region, the one of the stage for draws without their
+/// own. None if nothing of it is left.
+///
+pub fn clip_scissor(
+    scissor: Option<vk::Rect2D>,
+    stage_scissor: vk::Rect2D,
+    region: vk::Rect2D,
+) -> Option<vk::Rect2D> {
+    ScissorRect::from(scissor.unwrap_or(stage_scissor)).clamped_to(region)
+}
+
+///
+/// Back to front, so each one blends over what's behind it.
+///
+pub fn sort_back_to_front(tasks: &mut [RenderTask]) {
+    tasks.sort_by(|a, b| b.view_depth.total_cmp(&a.view_depth));
+}
+
+///
+/// Two sided tasks draw without culling, the rest with the cull mode of the stage.
+///
+pub fn cull_mode_of(task: &RenderTask, stage_cull_mode: vk::CullModeFlags) -> vk::CullModeFlags {
+    if task.is_two_sided {
+        vk::CullModeFlags::NONE
+    } else {
+        stage_cull_mode
+    }
+}
+
+///
+/// Dynamic state a draw needs bound, the pipeline being anything telling them apart.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawState<P> {
+    pub pipeline: P,
+    pub cull_mode: vk::CullModeFlags,
+    // Only for stages with a dynamic scissor
+    pub scissor: Option<vk::Rect2D>,
+}
+
+///
+/// Where the draws of a stage get recorded, a command buffer or a trace.
+///
+pub trait DrawSink<P, D> {
+    fn bind_pipeline(&mut self, pipeline: P);
+    fn set_cull_mode(&mut self, cull_mode: vk::CullModeFlags);
+    fn set_scissor(&mut self, scissor: vk::Rect2D);
+    fn draw(&mut self, draw: &D);
+}
+
+///
+/// Records the draws in order, only setting the state that changed since the last one.
+///
+pub fn record_draws<'a, P: Copy + PartialEq, D: 'a>(
+    sink: &mut impl DrawSink<P, D>,
+    draws: impl IntoIterator<Item = (DrawState<P>, &'a D)>,
+) {
+    let mut bound_pipeline = None;
+    let mut bound_cull_mode = None;
+    let mut bound_scissor = None;
+    for (state, draw) in draws {
+        if bound_pipeline != Some(state.pipeline) {
+            sink.bind_pipeline(state.pipeline);
+            bound_pipeline = Some(state.pipeline);
+        }
+        if bound_cull_mode != Some(state.cull_mode) {
+            sink.set_cull_mode(state.cull_mode);
+            bound_cull_mode = Some(state.cull_mode);
+        }
+        if let Some(scissor) = state.scissor.filter(|e| bound_scissor != Some(*e)) {
+            sink.set_scissor(scissor);
+            bound_scissor = Some(scissor);
+        }
+        sink.draw(draw);
+    }
+}
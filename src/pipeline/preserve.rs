@@ -0,0 +1,313 @@
+/*
+ * Contents of targets declared with preserveOnRecreate, carried over from the pipeline
+ * made for the previous swapchain size into the one loaded again for the new size.
+ */
+use ash::vk;
+
+use super::attachment::Attachment;
+use super::file::{Filtering, InitialState, PreserveMode, Target};
+use super::Pipeline;
+use crate::context::VulkanContext;
+
+///
+/// Target whose contents get carried over into the image of the new size, how is up to
+/// the mode. Discarded ones have none.
+///
+#[derive(Clone)]
+pub struct PreservedTarget {
+    pub name: String,
+    pub mode: PreserveMode,
+    pub filter: Filtering,
+    // Clears what copyCentered leaves out, zero without one
+    pub initial_state: Option<InitialState>,
+}
+
+impl PreservedTarget {
+    pub fn of(target: &Target) -> Option<Self> {
+        if !target.is_preserved_on_recreate || target.preserve_mode == PreserveMode::Discard {
+            return None;
+        }
+        Some(Self {
+            name: target.name.clone(),
+            mode: target.preserve_mode,
+            filter: target.preserve_filter,
+            initial_state: target.initial_state,
+        })
+    }
+}
+
+///
+/// Offset into the previous image, offset into the new one and extent of the region
+/// copyCentered copies: what both sizes share, centered on each.
+///
+pub fn centered_region(
+    from: vk::Extent2D,
+    to: vk::Extent2D,
+) -> (vk::Offset2D, vk::Offset2D, vk::Extent2D) {
+    let margin = |from: u32, to: u32| (from.saturating_sub(to) / 2) as i32;
+    let source = vk::Offset2D {
+        x: margin(from.width, to.width),
+        y: margin(from.height, to.height),
+    };
+    let destination = vk::Offset2D {
+        x: margin(to.width, from.width),
+        y: margin(to.height, from.height),
+    };
+    let extent = vk::Extent2D {
+        width: from.width.min(to.width),
+        height: from.height.min(to.height),
+    };
+    (source, destination, extent)
+}
+
+// Between the layouts of the stages and the transfer ones
+fn barrier(
+    attachment: &Attachment,
+    from: vk::ImageLayout,
+    to: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+) -> vk::ImageMemoryBarrier2 {
+    vk::ImageMemoryBarrier2::builder()
+        .image(attachment.image)
+        .subresource_range(attachment.subresource_range())
+        .old_layout(from)
+        .new_layout(to)
+        .src_stage_mask(src_stage)
+        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(dst_stage)
+        .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+        .build()
+}
+
+// Previous and new attachment of a target carried over, with the layout the new one ends in
+struct Carried<'a> {
+    target: &'a PreservedTarget,
+    from: &'a Attachment,
+    to: &'a Attachment,
+    from_layout: vk::ImageLayout,
+    to_layout: vk::ImageLayout,
+}
+
+impl Pipeline {
+    ///
+    /// Copies the preserved targets the previous pipeline wrote into the images of this
+    /// one, leaving them in the layout the frame ends them in. Neither pipeline may be in
+    /// use by the GPU until the commands ran. Returns the names of the targets carried
+    /// over, they count as written and skip their initial state.
+    ///
+    pub fn record_preserved(
+        &mut self,
+        ctx: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+        previous: &Pipeline,
+    ) -> Vec<String> {
+        let carried: Vec<_> = self
+            .preserved_targets
+            .iter()
+            .filter_map(|target| {
+                let from = previous
+                    .attachments
+                    .iter()
+                    .find(|e| e.name == target.name)?;
+                let to = self.attachments.iter().find(|e| e.name == target.name)?;
+                // Nothing rendered into it yet, or declared differently since
+                if !previous.written_attachments.contains(&from.image)
+                    || from.format != to.format
+                    || from.layers != to.layers
+                {
+                    return None;
+                }
+                Some(Carried {
+                    target,
+                    from,
+                    to,
+                    from_layout: previous.end_of_frame_layout(from)?,
+                    to_layout: self.end_of_frame_layout(to)?,
+                })
+            })
+            .collect();
+        if carried.is_empty() {
+            return Vec::new();
+        }
+        let (src, dst) = (
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        let (all, transfer) = (
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::PipelineStageFlags2::ALL_TRANSFER,
+        );
+        let before: Vec<_> = carried
+            .iter()
+            .flat_map(|e| {
+                [
+                    barrier(e.from, e.from_layout, src, all, transfer),
+                    barrier(e.to, vk::ImageLayout::UNDEFINED, dst, all, transfer),
+                ]
+            })
+            .collect();
+        let after: Vec<_> = carried
+            .iter()
+            .map(|e| barrier(e.to, dst, e.to_layout, transfer, all))
+            .collect();
+        ctx.extension
+            .try_begin_label(command_buffer, "preserved targets");
+        unsafe {
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before),
+            );
+        }
+        for e in &carried {
+            Self::record_carried(ctx, command_buffer, e);
+        }
+        unsafe {
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after),
+            );
+        }
+        ctx.extension.try_end_label(command_buffer);
+        let names: Vec<_> = carried.iter().map(|e| e.target.name.clone()).collect();
+        let images: Vec<_> = carried.iter().map(|e| e.to.image).collect();
+        self.written_attachments.extend(images);
+        self.uninitialized_attachments
+            .retain(|e| !names.contains(&e.0));
+        names
+    }
+
+    /*
+     * Records the copy of a single target, both images in the transfer layouts.
+     */
+    fn record_carried(ctx: &VulkanContext, command_buffer: vk::CommandBuffer, carried: &Carried) {
+        let (from, to) = (carried.from, carried.to);
+        let layers = |attachment: &Attachment| vk::ImageSubresourceLayers {
+            aspect_mask: attachment.format.aspect(),
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: attachment.layers,
+        };
+        let corner = |extent: vk::Extent2D| vk::Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: 1,
+        };
+        let (src, dst) = (
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        let is_centered = carried.target.mode == PreserveMode::CopyCentered;
+        let (source, destination, extent) = centered_region(from.extent, to.extent);
+        if is_centered && extent != to.extent {
+            // The part the previous image doesn't cover
+            let range = to.subresource_range();
+            let transfer = vk::PipelineStageFlags2::ALL_TRANSFER;
+            unsafe {
+                match carried.target.initial_state {
+                    Some(InitialState::ClearDepth(depth)) => {
+                        ctx.device.cmd_clear_depth_stencil_image(
+                            command_buffer,
+                            to.image,
+                            dst,
+                            &vk::ClearDepthStencilValue { depth, stencil: 0 },
+                            &[range],
+                        )
+                    }
+                    _ if to.format.has_depth_or_stencil() => {
+                        ctx.device.cmd_clear_depth_stencil_image(
+                            command_buffer,
+                            to.image,
+                            dst,
+                            &vk::ClearDepthStencilValue::default(),
+                            &[range],
+                        )
+                    }
+                    state => {
+                        let color = match state {
+                            Some(InitialState::ClearColor(color)) => color,
+                            _ => [0.0; 4],
+                        };
+                        ctx.device.cmd_clear_color_image(
+                            command_buffer,
+                            to.image,
+                            dst,
+                            &vk::ClearColorValue { float32: color },
+                            &[range],
+                        )
+                    }
+                }
+                let cleared = [barrier(to, dst, dst, transfer, transfer)];
+                ctx.device.cmd_pipeline_barrier2(
+                    command_buffer,
+                    &vk::DependencyInfo::builder().image_memory_barriers(&cleared),
+                );
+            }
+        }
+        unsafe {
+            if is_centered || from.extent == to.extent {
+                let regions = [vk::ImageCopy2::builder()
+                    .src_subresource(layers(from))
+                    .src_offset(vk::Offset3D {
+                        x: source.x,
+                        y: source.y,
+                        z: 0,
+                    })
+                    .dst_subresource(layers(to))
+                    .dst_offset(vk::Offset3D {
+                        x: destination.x,
+                        y: destination.y,
+                        z: 0,
+                    })
+                    .extent(vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    })
+                    .build()];
+                let info = vk::CopyImageInfo2::builder()
+                    .src_image(from.image)
+                    .src_image_layout(src)
+                    .dst_image(to.image)
+                    .dst_image_layout(dst)
+                    .regions(&regions);
+                ctx.device.cmd_copy_image2(command_buffer, &info);
+            } else {
+                let regions = [vk::ImageBlit2::builder()
+                    .src_subresource(layers(from))
+                    .src_offsets([vk::Offset3D::default(), corner(from.extent)])
+                    .dst_subresource(layers(to))
+                    .dst_offsets([vk::Offset3D::default(), corner(to.extent)])
+                    .build()];
+                let info = vk::BlitImageInfo2::builder()
+                    .src_image(from.image)
+                    .src_image_layout(src)
+                    .dst_image(to.image)
+                    .dst_image_layout(dst)
+                    .regions(&regions)
+                    .filter(carried.target.filter.to_vk());
+                ctx.device.cmd_blit_image2(command_buffer, &info);
+            }
+        }
+    }
+
+    ///
+    /// Attachments of this pipeline that replaced ones the previous pipeline wrote or
+    /// released without getting their contents, by name. What a RenderEvent::TargetReset reports.
+    ///
+    pub fn reset_attachments(&self, previous: &Pipeline, carried: &[String]) -> Vec<&Attachment> {
+        let mut reset: Vec<_> = self
+            .attachments
+            .iter()
+            .filter(|e| !e.is_default() && !carried.contains(&e.name))
+            .filter(|e| {
+                previous.released_attachments.contains(&e.name)
+                    || previous.attachments.iter().any(|old| {
+                        old.name == e.name && previous.written_attachments.contains(&old.image)
+                    })
+            })
+            .collect();
+        reset.sort_by(|a, b| a.name.cmp(&b.name));
+        reset
+    }
+}
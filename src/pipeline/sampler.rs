@@ -2,15 +2,69 @@ use ash::vk::{self};
 
 use crate::context::VulkanContext;
 
-use super::file::{Filtering, WrapMode};
+use super::{
+    file::{Filtering, WrapMode},
+    state::CompareFunc,
+};
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub struct SamplerKey {
     pub filter: Filtering,
     pub wrap_mode: WrapMode,
     pub anisotropy: u8,
+    // Comparison sampler when set, depth attachments only
+    pub compare: Option<CompareFunc>,
+    // Made exactly as described, the sampler policy doesn't apply
+    pub is_exact: bool,
 }
 
+impl SamplerKey {
+    pub const fn exact(self) -> Self {
+        Self {
+            is_exact: true,
+            ..self
+        }
+    }
+}
+
+///
+/// Applied on top of every sampler key that isn't exact, see Renderer::set_sampler_policy.
+/// The default leaves the keys as they are.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerPolicy {
+    // Caps the anisotropy of the keys
+    pub max_anisotropy: u8,
+    pub lod_bias: f32,
+    pub force_nearest_min_filter: bool,
+}
+
+impl Default for SamplerPolicy {
+    fn default() -> Self {
+        Self {
+            max_anisotropy: u8::MAX,
+            lod_bias: 0.0,
+            force_nearest_min_filter: false,
+        }
+    }
+}
+
+///
+/// Returned when every sampler id is taken, ids of samplers are never reused.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SamplersExhausted {
+    pub capacity: u32,
+}
+
+impl std::fmt::Display for SamplersExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "all {} sampler ids are taken", self.capacity)
+    }
+}
+
+impl std::error::Error for SamplersExhausted {}
+
 #[derive(Clone)]
 pub struct Sampler {
     pub name: String,
@@ -20,26 +74,81 @@ pub struct Sampler {
 }
 
 impl Sampler {
-    pub fn of_key(ctx: &VulkanContext, name: String, key: SamplerKey, position: u8) -> Self {
-        Self::of(ctx, name, key.filter, key.wrap_mode, key.anisotropy, position)
+    // Ids are u8 and the Java side takes u8::MAX as no sampler
+    pub const MAX_IDS: u32 = u8::MAX as u32;
+
+    ///
+    /// Samplers the device can bind next to the given amount of combined image samplers
+    /// per stage, the attachment inputs of a pass. Never more than MAX_IDS.
+    ///
+    pub fn capacity_of(ctx: &VulkanContext, attachment_samplers: u32) -> u32 {
+        let limits = unsafe {
+            ctx.instance
+                .get_physical_device_properties(ctx.physical_device)
+                .limits
+        };
+        Self::capacity_within(
+            limits.max_per_stage_descriptor_samplers,
+            limits.max_descriptor_set_samplers,
+            attachment_samplers,
+        )
+    }
+
+    ///
+    /// Same as capacity_of, with the limits of the device passed in.
+    ///
+    pub fn capacity_within(
+        max_per_stage_samplers: u32,
+        max_set_samplers: u32,
+        attachment_samplers: u32,
+    ) -> u32 {
+        max_per_stage_samplers
+            .saturating_sub(attachment_samplers)
+            .min(max_set_samplers)
+            .min(Self::MAX_IDS)
     }
 
-    pub fn of(
+    ///
+    /// Id of the next sampler made with the amount of samplers there are already. Fails
+    /// once the capacity or MAX_IDS is reached, the id never wraps around.
+    ///
+    pub fn next_id(taken: usize, capacity: u32) -> Result<u8, SamplersExhausted> {
+        let capacity = capacity.min(Self::MAX_IDS);
+        if taken >= capacity as usize {
+            return Err(SamplersExhausted { capacity });
+        }
+        Ok(taken as u8)
+    }
+
+    pub fn of_key(
         ctx: &VulkanContext,
         name: String,
-        filter: Filtering,
-        wrap_mode: WrapMode,
-        anisotropy: u8,
+        key: SamplerKey,
+        policy: &SamplerPolicy,
         position: u8,
     ) -> Self {
-        if position as u32 >= crate::renderer::Renderer::MAX_SAMPLERS {
-            panic!(
-                "can't allocate more samplers than {}!",
-                crate::renderer::Renderer::MAX_SAMPLERS
-            );
-        }
-        let info = Self::info_of(filter, wrap_mode, anisotropy);
-        let sampler = unsafe { ctx.device.create_sampler(&info, None) }.unwrap();
+        Self::made_with(ctx, name, &Self::info_of_key(key, policy), position)
+    }
+
+    ///
+    /// What the sampler of the key gets made with under the policy, exact keys ignore it.
+    ///
+    pub fn info_of_key(key: SamplerKey, policy: &SamplerPolicy) -> vk::SamplerCreateInfo {
+        let policy = if key.is_exact {
+            SamplerPolicy::default()
+        } else {
+            *policy
+        };
+        Self::info_of(key, &policy)
+    }
+
+    fn made_with(
+        ctx: &VulkanContext,
+        name: String,
+        info: &vk::SamplerCreateInfo,
+        position: u8,
+    ) -> Self {
+        let sampler = unsafe { ctx.device.create_sampler(info, None) }.unwrap();
         ctx.try_set_debug_name(&name, sampler);
         Self {
             name,
@@ -49,17 +158,32 @@ impl Sampler {
         }
     }
 
-    fn info_of(filter: Filtering, wrap_mode: WrapMode, anisotropy: u8) -> vk::SamplerCreateInfo {
+    fn info_of(key: SamplerKey, policy: &SamplerPolicy) -> vk::SamplerCreateInfo {
+        let SamplerKey {
+            filter,
+            wrap_mode,
+            anisotropy,
+            compare,
+            ..
+        } = key;
+        let anisotropy = anisotropy.min(policy.max_anisotropy);
+        let min_filter = if policy.force_nearest_min_filter {
+            vk::Filter::NEAREST
+        } else {
+            filter.to_vk()
+        };
         vk::SamplerCreateInfo::builder()
             .address_mode_u(wrap_mode.to_vk())
             .address_mode_v(wrap_mode.to_vk())
             .address_mode_w(wrap_mode.to_vk())
             .anisotropy_enable(if anisotropy > 1 { true } else { false })
-            .compare_enable(false)
+            .compare_enable(compare.is_some())
+            .compare_op(compare.map_or(vk::CompareOp::NEVER, |e| e.to_vk()))
             .mipmap_mode(filter.to_vk_mip_map())
-            .min_filter(filter.to_vk())
+            .min_filter(min_filter)
             .mag_filter(filter.to_vk())
             .max_anisotropy(anisotropy as f32)
+            .mip_lod_bias(policy.lod_bias)
             .max_lod(vk::LOD_CLAMP_NONE)
             .build()
     }
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+use ash::vk;
+
+use super::file::{Pass, SpecValue};
+use crate::reflection::ShaderReflection;
+
+///
+/// Packed specialization constants for one permutation of a stage.
+///
+pub struct Specialization {
+    entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl Specialization {
+    ///
+    /// Parses the constant ids of the pipeline file, panics if any isn't a number.
+    ///
+    pub fn parse(stage: &str, constants: &BTreeMap<String, SpecValue>) -> BTreeMap<u32, SpecValue> {
+        constants
+            .iter()
+            .map(|(id, value)| {
+                let id = id.parse::<u32>().unwrap_or_else(|_| {
+                    panic!(
+                        "stage {} has an invalid specialization constant id {}!",
+                        stage, id
+                    )
+                });
+                (id, *value)
+            })
+            .collect()
+    }
+
+    ///
+    /// Panics if a constant id of the stage or of one of its variants isn't a number, or
+    /// if the reflection of its shaders doesn't declare it. Without a reflection only the
+    /// ids themselves get checked, the dry run has none.
+    ///
+    pub fn validate_ids(pass: &Pass, reflection: Option<&ShaderReflection>) {
+        let declared = std::iter::once(&pass.specialization).chain(pass.variants.values());
+        for constants in declared {
+            let reflection = match reflection {
+                Some(e) => e,
+                None => {
+                    Self::parse(&pass.name, constants);
+                    continue;
+                }
+            };
+            for id in Self::parse(&pass.name, constants).into_keys() {
+                if !reflection.specialization_ids.contains(&id) {
+                    panic!(
+                        "stage {} specializes constant {}, but its shaders don't declare it!",
+                        pass.name, id
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn of(constants: &BTreeMap<u32, SpecValue>) -> Self {
+        let mut entries = Vec::with_capacity(constants.len());
+        let mut data = Vec::with_capacity(constants.len() * 4);
+        for (&constant_id, value) in constants {
+            let bytes = value.to_bytes();
+            entries.push(vk::SpecializationMapEntry {
+                constant_id,
+                offset: data.len() as u32,
+                size: bytes.len(),
+            });
+            data.extend_from_slice(&bytes);
+        }
+        Self { entries, data }
+    }
+
+    ///
+    /// Returned info points into self, it can't outlive it.
+    ///
+    pub fn to_vk(&self) -> Option<vk::SpecializationInfo> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        Some(
+            vk::SpecializationInfo::builder()
+                .map_entries(&self.entries)
+                .data(&self.data)
+                .build(),
+        )
+    }
+}
@@ -1,70 +1,1235 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
-    buffer::{DeviceAllocator, DeviceSlice},
-    pipeline::{attachment::Attachment, descriptor::DescriptorBuffer},
-    render_task::{RenderTask, TaskKind},
+    buffer::DeviceSlice,
+    events::StageTimer,
+    frame_ring::FrameRing,
+    handle::{MeshHandle, TextureHandle},
+    motion::{self, FrameMotion},
+    offscreen::OffscreenTarget,
+    pipeline::{
+        attachment::Attachment,
+        depth_pyramid::DepthPyramid,
+        descriptor::{self, DescriptorBackend, DescriptorBinding},
+        file::{PerDrawField, ShadingRate, TransformInterpolation, ViewFormat},
+        per_draw::PerDrawLayout,
+        plan::{self, DrawSink, DrawState},
+        state_cache::StateCache,
+    },
+    reflection::{HostMember, LayoutMismatch, ShaderReflection},
+    render_task::{RenderTask, ScissorRect, TaskKind},
     renderer::MeshBuffer,
-    shader_resource::{ResourceKind, SingleResource},
+    scene_slot::SceneSlot,
+    shader_resource::{MultiResource, ResourceKind, SingleResource, TransformExtra},
+    split_screen::{self, ViewportDesc},
+    stage_constants::StageConstants,
     updater,
+    vertex_layout::{AcceptedFormats, VertexLayoutKind},
 };
 use ash::vk::{self, ShaderStageFlags};
 
 pub struct Stage {
     pub name: String,
+    pub template: Option<String>,
     pub rendering: Rendering,
+    // Of the split screen viewports after the first, which renders through the above.
+    // Empty unless the stage uses targets allocated per viewport
+    pub viewport_layers: Vec<ViewportLayer>,
     pub pipeline: vk::Pipeline,
+    // Indexed by variant id, None where this stage doesn't declare the variant
+    pub variant_pipelines: Vec<Option<vk::Pipeline>>,
     pub layout: vk::PipelineLayout,
     pub outputs: Vec<Attachment>,
     pub inputs: Vec<Attachment>,
+    // Descriptors of the inputs, to put them back once they stop being substituted
+    pub input_descriptors: Vec<vk::DescriptorImageInfo>,
+    // Inputs sampling the default texture because nothing wrote them yet
+    pub is_input_substituted: Vec<bool>,
+    pub depth_stencil: Option<Attachment>,
+    pub is_depth_stencil_written: bool,
     pub per_instance_updaters: Vec<ResourceKind>,
     pub per_pass_updaters: Vec<ResourceKind>,
-    pub attachment_descriptors: Option<Box<DescriptorBuffer>>,
+    pub per_draw_fields: Vec<PerDrawField>,
+    pub constants: StageConstants,
+    // Cull mode of tasks that aren't two sided, it's dynamic state
+    pub cull_mode: vk::CullModeFlags,
+    // Depth bounds test range, dynamic state. None if the stage doesn't test them
+    pub depth_bounds: Option<[f32; 2]>,
+    // Rate the device ended up supporting, dynamic state when it supports any
+    pub shading_rate: ShadingRate,
+    // Set through Renderer::set_shading_rate_image, for the attachment rate
+    pub shading_rate_texture: Option<TextureHandle>,
+    // Image and view of the texture for this frame, None while it isn't uploaded
+    pub shading_rate_image: Option<(vk::Image, vk::ImageView)>,
+    // Non zero for multiview stages, one bit per layer rendered to
+    pub view_mask: u32,
+    pub attachment_descriptors: Option<Box<dyn DescriptorBackend>>,
     pub task_kind: TaskKind,
     pub index: u32,
+    // Last stage writing to the default attachment, transitions it for presenting
     pub is_final: bool,
+    pub is_first_default_write: bool,
     pub image_barriers: Vec<vk::ImageMemoryBarrier2>,
-    pub reserved_buffers: Vec<DeviceSlice>,
+    // Earlier accesses to named buffers this stage has to wait for
+    pub buffer_dependencies: Vec<BufferDependency>,
     pub is_validation_layer_enabled: bool,
+    pub reflection: ShaderReflection,
+    // Blit and depth pyramid stages copy instead of drawing, they have no pipeline nor
+    // attachments of their own
+    pub blit: Option<Blit>,
+    // Draws inside the rendering scope the previous stage began, without barriers
+    pub is_scope_continued: bool,
+    // Leaves the rendering scope open for the next stage to draw in
+    pub is_scope_kept_open: bool,
+    // Layouts of the meshes its shaders read, see Pipeline::accepts_vertex_layout
+    pub vertex_layouts: Vec<VertexLayoutKind>,
+    // Formats of their attributes, see Pipeline::rejected_vertex_format
+    pub vertex_formats: AcceptedFormats,
+    // Area task scissors get clamped to, None unless the stage sets them per task
+    pub dynamic_scissor: Option<vk::Rect2D>,
+    // Skippable over the GPU frame budget at this priority, see frame_budget
+    pub budget_priority: Option<u32>,
+    // Drawn once over the render area rather than once per split screen viewport
+    pub is_shared_across_viewports: bool,
+    // Where its interpolatedTransform per draw field gets blended
+    pub transform_interpolation: TransformInterpolation,
+    // Device address of the texture feedback buffer of the frame, 0 without one
+    pub texture_feedback: u64,
+    // Of the state, set every time the stage draws since they're dynamic state
+    pub viewport: vk::Viewport,
+    pub scissor: vk::Rect2D,
+}
+
+///
+/// Copy of a blit stage from one attachment into another. The barriers leave both in the
+/// layouts the other stages expect, the source as if it was sampled and the destination
+/// as if it was rendered to. Depth pyramids are left sampled instead.
+///
+pub struct Blit {
+    pub source: Attachment,
+    pub destination: Attachment,
+    pub source_layers: vk::ImageSubresourceLayers,
+    pub destination_layers: vk::ImageSubresourceLayers,
+    // Corners of the regions, the second one exclusive
+    pub source_offsets: [vk::Offset3D; 2],
+    pub destination_offsets: [vk::Offset3D; 2],
+    pub filter: vk::Filter,
+    // Same format and region size, copied without blitting
+    pub is_copy: bool,
+    // The image barriers of the stage go into the transfer layouts, these out of them
+    pub after_barriers: Vec<vk::ImageMemoryBarrier2>,
+    // Nothing gets copied until the source is written, like history buffers on the first frame
+    pub is_source_written: bool,
+    pub is_destination_written: bool,
+    // Depth pyramids dispatch their reduction instead, in the general layout
+    pub pyramid: Option<DepthPyramid>,
+}
+
+impl Blit {
+    pub fn verb(&self) -> &'static str {
+        match (&self.pyramid, self.is_copy) {
+            (Some(_), _) => "depth pyramid",
+            (None, true) => "copy",
+            (None, false) => "blit",
+        }
+    }
+
+    ///
+    /// Records the copy alone, the images have to be in the transfer layouts.
+    ///
+    fn record(&self, ctx: &crate::context::VulkanContext, command_buffer: vk::CommandBuffer) {
+        if let Some(pyramid) = &self.pyramid {
+            pyramid.record(ctx, command_buffer);
+            return;
+        }
+        unsafe {
+            if self.is_copy {
+                let regions = [vk::ImageCopy2::builder()
+                    .src_subresource(self.source_layers)
+                    .src_offset(self.source_offsets[0])
+                    .dst_subresource(self.destination_layers)
+                    .dst_offset(self.destination_offsets[0])
+                    .extent(vk::Extent3D {
+                        width: (self.source_offsets[1].x - self.source_offsets[0].x) as u32,
+                        height: (self.source_offsets[1].y - self.source_offsets[0].y) as u32,
+                        depth: 1,
+                    })
+                    .build()];
+                let info = vk::CopyImageInfo2::builder()
+                    .src_image(self.source.image)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_image(self.destination.image)
+                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .regions(&regions);
+                ctx.device.cmd_copy_image2(command_buffer, &info);
+            } else {
+                let regions = [vk::ImageBlit2::builder()
+                    .src_subresource(self.source_layers)
+                    .src_offsets(self.source_offsets)
+                    .dst_subresource(self.destination_layers)
+                    .dst_offsets(self.destination_offsets)
+                    .build()];
+                let info = vk::BlitImageInfo2::builder()
+                    .src_image(self.source.image)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_image(self.destination.image)
+                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .regions(&regions)
+                    .filter(self.filter);
+                ctx.device.cmd_blit_image2(command_buffer, &info);
+            }
+        }
+    }
+}
+
+/*
+ * SAFETY: The vulkan structs kept here (rendering infos, barriers) are built without
+ * any p_next chain, so the only raw pointers they hold are null.
+ */
+unsafe impl Send for Stage {}
+
+///
+/// Access of a stage to a named buffer, declared with writesBuffers and readsBuffers.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferAccess {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+impl BufferAccess {
+    // Storage writes from any of the shaders of the stage
+    pub const WRITE: Self = Self {
+        stage: vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::VERTEX_SHADER.as_raw()
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw(),
+        ),
+        access: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+    };
+    // Vertex and index fetches, uniform and storage reads
+    pub const READ: Self = Self {
+        stage: vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::VERTEX_INPUT.as_raw()
+                | vk::PipelineStageFlags2::VERTEX_SHADER.as_raw()
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw(),
+        ),
+        access: vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::VERTEX_ATTRIBUTE_READ.as_raw()
+                | vk::AccessFlags2::INDEX_READ.as_raw()
+                | vk::AccessFlags2::UNIFORM_READ.as_raw()
+                | vk::AccessFlags2::SHADER_STORAGE_READ.as_raw(),
+        ),
+    };
+}
+
+///
+/// Barrier between an earlier access to a named buffer and the one of a stage. The
+/// earlier access can be from the previous frame.
+///
+#[derive(Clone, Debug)]
+pub struct BufferDependency {
+    pub name: String,
+    pub src: BufferAccess,
+    pub dst: BufferAccess,
+}
+
+impl BufferDependency {
+    pub fn to_buffer_barrier(&self, slice: &DeviceSlice) -> vk::BufferMemoryBarrier2 {
+        vk::BufferMemoryBarrier2::builder()
+            .buffer(slice.buffer)
+            .offset(slice.offset)
+            .size(slice.size)
+            .src_stage_mask(self.src.stage)
+            .src_access_mask(self.src.access)
+            .dst_stage_mask(self.dst.stage)
+            .dst_access_mask(self.dst.access)
+            .build()
+    }
+
+    pub fn to_memory_barrier(&self) -> vk::MemoryBarrier2 {
+        vk::MemoryBarrier2::builder()
+            .src_stage_mask(self.src.stage)
+            .src_access_mask(self.src.access)
+            .dst_stage_mask(self.dst.stage)
+            .dst_access_mask(self.dst.access)
+            .build()
+    }
+}
+
+///
+/// Draws of a stage for one frame, with their per pass and per instance data already
+/// written into the frame ring.
+///
+#[derive(Default)]
+pub struct PreparedStage {
+    draws: Vec<PreparedDraw>,
+    // Drawn after the draws above, one viewport after the other, see split_screen
+    viewports: Vec<PreparedViewport>,
+    // Dirty region the stage redraws alone, see clip_prepared
+    region: Option<vk::Rect2D>,
+}
+
+struct PreparedViewport {
+    index: u32,
+    region: vk::Rect2D,
+    // Clipped to the region
+    draws: Vec<PreparedDraw>,
+}
+
+struct PreparedDraw {
+    pipeline: vk::Pipeline,
+    cull_mode: vk::CullModeFlags,
+    mesh: MeshHandle,
+    // None for meshes drawn without indices
+    indices: Option<DeviceSlice>,
+    count: u32,
+    instance_count: u32,
+    push_constants: Vec<u8>,
+    // Only for stages with a dynamic scissor
+    scissor: Option<vk::Rect2D>,
+}
+
+impl PreparedDraw {
+    fn state(&self) -> DrawState<vk::Pipeline> {
+        DrawState {
+            pipeline: self.pipeline,
+            cull_mode: self.cull_mode,
+            scissor: self.scissor,
+        }
+    }
+}
+
+/*
+ * Records the draws of a stage into its command buffer, see plan::record_draws. State
+ * goes through the cache.
+ */
+struct CommandSink<'a> {
+    ctx: &'a crate::context::VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    cache: &'a mut StateCache,
+    // Labels every draw with it when verbose labels are enabled
+    labeled_kind: Option<TaskKind>,
+}
+
+impl DrawSink<vk::Pipeline, PreparedDraw> for CommandSink<'_> {
+    fn bind_pipeline(&mut self, pipeline: vk::Pipeline) {
+        if !self.cache.set_pipeline(pipeline) {
+            return;
+        }
+        unsafe {
+            self.ctx.device.cmd_bind_pipeline(
+                self.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            )
+        };
+    }
+
+    fn set_cull_mode(&mut self, cull_mode: vk::CullModeFlags) {
+        if !self.cache.set_cull_mode(cull_mode) {
+            return;
+        }
+        unsafe {
+            self.ctx
+                .device
+                .cmd_set_cull_mode(self.command_buffer, cull_mode)
+        };
+    }
+
+    fn set_scissor(&mut self, scissor: vk::Rect2D) {
+        if !self.cache.set_scissor(scissor) {
+            return;
+        }
+        unsafe {
+            self.ctx
+                .device
+                .cmd_set_scissor(self.command_buffer, 0, &[scissor])
+        };
+    }
+
+    fn draw(&mut self, draw: &PreparedDraw) {
+        let (ctx, command_buffer) = (self.ctx, self.command_buffer);
+        // What got dropped has to be what the draw needs
+        debug_assert_eq!(self.cache.pipeline(), Some(draw.pipeline));
+        debug_assert_eq!(self.cache.cull_mode(), Some(draw.cull_mode));
+        debug_assert!(draw.scissor.is_none() || self.cache.scissor() == draw.scissor);
+        if let Some(kind) = self.labeled_kind {
+            ctx.extension.try_insert_label(
+                command_buffer,
+                &format!("mesh {} {} x{}", draw.mesh, kind, draw.instance_count),
+            );
+        }
+        let is_pushed = !draw.push_constants.is_empty()
+            && self
+                .cache
+                .set_push_constants(self.layout, &draw.push_constants);
+        let indices = draw
+            .indices
+            .filter(|e| self.cache.set_index_buffer(e.buffer, e.offset));
+        // Now we push the data into the command stream and issue the draws
+        unsafe {
+            if is_pushed {
+                ctx.device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    ShaderStageFlags::ALL_GRAPHICS,
+                    0u32,
+                    &draw.push_constants,
+                );
+            }
+            if let Some(indices) = indices {
+                ctx.device.cmd_bind_index_buffer(
+                    command_buffer,
+                    indices.buffer,
+                    indices.offset,
+                    vk::IndexType::UINT32,
+                );
+            }
+            if draw.indices.is_some() {
+                ctx.device.cmd_draw_indexed(
+                    command_buffer,
+                    draw.count,
+                    draw.instance_count,
+                    0,
+                    0,
+                    0,
+                );
+            } else {
+                ctx.device
+                    .cmd_draw(command_buffer, draw.count, draw.instance_count, 0, 0)
+            }
+        }
+    }
+}
+
+///
+/// What every stage of a frame records with, besides its own prepared draws.
+///
+pub struct StageRecording<'a> {
+    pub command_buffer: vk::CommandBuffer,
+    pub sampler_descriptors: DescriptorBinding,
+    pub image_descriptors: DescriptorBinding,
+    pub default_attachment: &'a Attachment,
+    pub named_buffers: &'a HashMap<String, DeviceSlice>,
+    pub is_verbose_labels_enabled: bool,
+}
+
+///
+/// What every stage of a frame prepares its draws from, besides its own tasks.
+///
+pub struct StagePreparation<'a> {
+    pub mesh_buffers_by_id: &'a HashMap<u32, MeshBuffer>,
+    pub shader_resources_by_kind: &'a HashMap<ResourceKind, SingleResource>,
+    pub motion: &'a FrameMotion<'a>,
+    pub scene_slots: &'a HashMap<u32, SceneSlot>,
+}
+
+///
+/// What a stage renders into and samples for a split screen viewport, the layers of
+/// the targets allocated per viewport belonging to it in place of the first ones.
+///
+pub struct ViewportLayer {
+    pub rendering: Rendering,
+    pub attachment_descriptors: Option<Box<dyn DescriptorBackend>>,
+    // Of the inputs, to put them back once they stop being substituted
+    pub input_descriptors: Vec<vk::DescriptorImageInfo>,
 }
 
 #[derive(Clone)]
 pub struct Rendering {
     pub attachments: Vec<vk::RenderingAttachmentInfo>,
     pub depth_stencil: Option<vk::RenderingAttachmentInfo>,
+    pub stencil: Option<vk::RenderingAttachmentInfo>,
     pub default_attachment_index: Option<usize>,
+    pub default_view_format: ViewFormat,
 }
 
 impl Stage {
+    ///
+    /// Writes the per pass and per instance data of the tasks into the frame ring and
+    /// builds the push constants of every draw, without touching any command buffer.
+    ///
+    pub fn prepare(
+        &self,
+        tasks: &[RenderTask],
+        frame: &StagePreparation,
+        ring: &mut FrameRing,
+    ) -> PreparedStage {
+        if self.blit.is_some() {
+            return PreparedStage::default();
+        }
+        let draws = self.prepare_draws(self.order_tasks(tasks), frame, &HashMap::new(), ring);
+        PreparedStage {
+            draws,
+            viewports: Vec::new(),
+            region: None,
+        }
+    }
+
+    ///
+    /// Same as prepare, once per enabled split screen viewport with the tasks drawn in
+    /// it and its resources over the shared ones. The draws of each one get clipped to
+    /// its region.
+    ///
+    pub fn prepare_viewports(
+        &self,
+        tasks: &[RenderTask],
+        viewports: &[ViewportDesc],
+        frame: &StagePreparation,
+        ring: &mut FrameRing,
+    ) -> PreparedStage {
+        if self.blit.is_some() {
+            return PreparedStage::default();
+        }
+        let tasks = self.order_tasks(tasks);
+        let viewports = viewports
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.is_enabled)
+            .map(|(i, viewport)| {
+                let tasks = tasks
+                    .iter()
+                    .filter(|(task, _)| split_screen::is_drawn_in(task, i))
+                    .copied()
+                    .collect();
+                let mut draws = self.prepare_draws(tasks, frame, &viewport.resources, ring);
+                self.clip_draws(&mut draws, viewport.region);
+                PreparedViewport {
+                    index: i as u32,
+                    region: viewport.region,
+                    draws,
+                }
+            })
+            .collect();
+        PreparedStage {
+            draws: Vec::new(),
+            viewports,
+            region: None,
+        }
+    }
+
+    fn order_tasks<'a>(
+        &self,
+        tasks: &'a [RenderTask],
+    ) -> Vec<(&'a RenderTask, Option<vk::Rect2D>)> {
+        // By variant rather than by the handles the driver picked, same order every run
+        plan::order_tasks(tasks, self.dynamic_scissor, |e| {
+            (self.drawn_variant_of(e.variant), e.is_two_sided)
+        })
+    }
+
+    /*
+     * Resources of the kinds in overrides get taken from there instead of the shared
+     * ones, for split screen viewports.
+     */
+    fn prepare_draws(
+        &self,
+        tasks: Vec<(&RenderTask, Option<vk::Rect2D>)>,
+        frame: &StagePreparation,
+        overrides: &HashMap<ResourceKind, SingleResource>,
+        ring: &mut FrameRing,
+    ) -> Vec<PreparedDraw> {
+        let per_pass_buffers =
+            self.reserve_pass_buffers(ring, frame.shader_resources_by_kind, overrides);
+        tasks
+            .into_iter()
+            .map(|(task, scissor)| {
+                let mesh_buffer = frame.mesh_buffers_by_id.get(&task.mesh.index).unwrap();
+                // Most of the time it's nowehere near going to be close to 32 addresses
+                let mut push_constants: Vec<u64> = Vec::with_capacity(32);
+                // First appearing, the per-pass data, uploaded once and repeated for all tasks
+                push_constants.extend(&per_pass_buffers);
+                // Second, the addresses pointing to the already uploaded vertex data
+                if self.task_kind != TaskKind::Fullscreen {
+                    match &mesh_buffer.layout {
+                        // Same size as the separate addresses, the stride and offsets packed
+                        Some(layout) => {
+                            push_constants.push(mesh_buffer.vertices.device_addr);
+                            push_constants.extend(&layout.push_constants());
+                        }
+                        None => push_constants.extend(&[
+                            mesh_buffer.vertices.device_addr,
+                            mesh_buffer.normals.device_addr,
+                            mesh_buffer.tex_coords.device_addr,
+                        ]),
+                    }
+                }
+                // Third, the per-instance date for the task, uploaded per task
+                push_constants.extend(&self.reserve_instance_buffers(
+                    ring,
+                    frame.scene_slots,
+                    task,
+                ));
+                // Last, the per-draw fields the stage asked for
+                let mut push_constants = unsafe { push_constants.align_to::<u8>().1 }.to_vec();
+                self.write_per_draw_fields(task, frame.motion, ring, &mut push_constants);
+                PreparedDraw {
+                    pipeline: self.pipeline_for(task.variant),
+                    cull_mode: plan::cull_mode_of(task, self.cull_mode),
+                    mesh: task.mesh,
+                    indices: (!mesh_buffer.indices.is_empty()).then_some(mesh_buffer.indices),
+                    count: mesh_buffer.count,
+                    instance_count: task.instance_count,
+                    push_constants,
+                    scissor,
+                }
+            })
+            .collect()
+    }
+
+    ///
+    /// Points the inputs nothing wrote yet, like history buffers on the first frame or
+    /// outputs of disabled stages, at the fallback view, and back at the attachment once
+    /// written. Their barriers get skipped meanwhile, the images never were in the layout
+    /// those transition from.
+    ///
+    pub fn substitute_unwritten_inputs(
+        &mut self,
+        ctx: &crate::context::VulkanContext,
+        written: &HashSet<vk::Image>,
+        fallback_view: vk::ImageView,
+    ) {
+        if let Some(blit) = &mut self.blit {
+            let is_source_written = written.contains(&blit.source.image);
+            if !is_source_written && blit.is_source_written {
+                log::warn!(
+                    "stage {} blits {} before anything wrote it, it skips the blit until then",
+                    self.name,
+                    blit.source.name
+                );
+            }
+            blit.is_source_written = is_source_written;
+            blit.is_destination_written = written.contains(&blit.destination.image);
+            return;
+        }
+        if self.attachment_descriptors.is_none() {
+            return;
+        }
+        for (i, input) in self.inputs.iter().enumerate() {
+            let is_unwritten = !written.contains(&input.image);
+            if is_unwritten == self.is_input_substituted[i] {
+                continue;
+            }
+            if is_unwritten {
+                log::warn!(
+                    "stage {} samples {} before anything wrote it, it gets the default texture until then",
+                    self.name,
+                    input.name
+                );
+            }
+            // Each split screen viewport samples its own layer of it
+            let layers = self
+                .viewport_layers
+                .iter_mut()
+                .map(|e| (&mut e.attachment_descriptors, &e.input_descriptors));
+            let tables =
+                std::iter::once((&mut self.attachment_descriptors, &self.input_descriptors));
+            for (descriptors, input_descriptors) in tables.chain(layers) {
+                let desc = if is_unwritten {
+                    vk::DescriptorImageInfo {
+                        image_view: fallback_view,
+                        ..input_descriptors[i]
+                    }
+                } else {
+                    input_descriptors[i]
+                };
+                let descriptors = descriptors.as_mut().unwrap();
+                descriptors.place_image_sampler_at(ctx, input.descriptor_index, desc);
+                descriptors.flush_single(ctx, input.descriptor_index);
+            }
+            self.is_input_substituted[i] = is_unwritten;
+        }
+    }
+
+    ///
+    /// Adds the attachments the stage renders into to the written ones.
+    ///
+    pub fn mark_written(&self, written: &mut HashSet<vk::Image>) {
+        let outputs = self
+            .outputs
+            .iter()
+            .filter(|e| Attachment::DEFAULT_NAME != e.name);
+        written.extend(outputs.map(|e| e.image));
+        if self.is_depth_stencil_written {
+            written.extend(self.depth_stencil.as_ref().map(|e| e.image));
+        }
+        if let Some(blit) = self.blit.as_ref().filter(|e| e.is_source_written) {
+            written.insert(blit.destination.image);
+        }
+    }
+
     pub fn render(
         &mut self,
         ctx: &crate::context::VulkanContext,
-        batches_by_task_type: &Vec<Vec<RenderTask>>,
-        mesh_buffers_by_id: &HashMap<u32, MeshBuffer>,
-        shader_resources_by_kind: &HashMap<ResourceKind, SingleResource>,
-        sampler_descriptors: &DescriptorBuffer,
-        image_descriptors: &DescriptorBuffer,
-        buffer_allocator: &DeviceAllocator,
+        prepared: &PreparedStage,
+        recording: StageRecording,
+        timer: Option<&mut StageTimer>,
+        cache: &mut StateCache,
+    ) {
+        let command_buffer = recording.command_buffer;
+        if let Some(blit) = &self.blit {
+            self.render_blit(ctx, blit, command_buffer);
+            return;
+        }
+        // Dirty region outside of the target, nothing to redraw
+        let is_clipped_away = prepared
+            .region
+            .is_some_and(|e| e.extent.width == 0 || e.extent.height == 0);
+        // Stages with a layer per viewport begin rendering into each one on its own
+        if !self.viewport_layers.is_empty() && !prepared.viewports.is_empty() {
+            self.render_viewport_layers(ctx, prepared, &recording, timer, cache);
+        } else if !is_clipped_away {
+            self.render_scope(ctx, prepared, &recording, timer, cache);
+        }
+        if let Some((image, _)) = self.shading_rate_image {
+            // Back to where textures are kept, for sampling and later uploads
+            let barriers = [Self::shading_rate_barrier(image, false)];
+            let dep_info = vk::DependencyInfo::builder()
+                .image_memory_barriers(&barriers)
+                .build();
+            unsafe { ctx.device.cmd_pipeline_barrier2(command_buffer, &dep_info) };
+        }
+        if !self.is_final {
+            // Nothing else to do
+            return;
+        }
+        // Need to transition for presenting
+        let default_attachment = recording.default_attachment;
+        let present_image_barriers =
+            vec![
+                default_attachment.adapt_barrier(Attachment::default_attachment_present_barrier(
+                    default_attachment.image,
+                )),
+            ];
+        let barrier_dep_info = vk::DependencyInfo::builder()
+            .image_memory_barriers(&present_image_barriers)
+            .build();
+        unsafe {
+            ctx.device
+                .cmd_pipeline_barrier2(command_buffer, &barrier_dep_info);
+        }
+    }
+
+    /*
+     * Draws in the rendering scope of the stage, split screen viewports one after the
+     * other. Stages continuing the scope of the previous one skip beginning it.
+     */
+    fn render_scope(
+        &self,
+        ctx: &crate::context::VulkanContext,
+        prepared: &PreparedStage,
+        recording: &StageRecording,
+        mut timer: Option<&mut StageTimer>,
+        cache: &mut StateCache,
+    ) {
+        let command_buffer = recording.command_buffer;
+        if !self.is_scope_continued {
+            self.record_barriers(ctx, recording);
+            // Clipped ones load and store nothing past their region
+            let area = prepared
+                .region
+                .unwrap_or_else(|| self.render_area(recording.default_attachment));
+            self.begin_rendering(ctx, recording, &self.rendering, area);
+            cache.invalidate();
+        }
+        self.bind_descriptors(
+            ctx,
+            recording,
+            self.attachment_descriptors.as_deref(),
+            cache,
+        );
+        let total_draws = prepared.draws.len()
+            + prepared
+                .viewports
+                .iter()
+                .map(|e| e.draws.len())
+                .sum::<usize>();
+        ctx.extension.try_begin_label(
+            command_buffer,
+            &format!("stage: {} / draws ({} tasks)", self.name, total_draws),
+        );
+        // Stages with a dynamic scissor set the one of each task instead
+        let scissor = self.dynamic_scissor.is_none().then_some(self.scissor);
+        self.set_dynamic_state(ctx, command_buffer, cache, self.viewport, scissor);
+        let mut sink = CommandSink {
+            ctx,
+            command_buffer,
+            layout: self.layout,
+            cache,
+            labeled_kind: recording
+                .is_verbose_labels_enabled
+                .then_some(self.task_kind),
+        };
+        plan::record_draws(&mut sink, prepared.draws.iter().map(|e| (e.state(), e)));
+        for viewport in &prepared.viewports {
+            let (index, region) = (viewport.index, viewport.region);
+            if let Some(timer) = &mut timer {
+                timer.write_viewport(&ctx.device, command_buffer, self.index, index, false);
+            }
+            // Every draw has a scissor within the region already
+            let area = self.viewport_of(region);
+            if sink.cache.set_viewport(area) {
+                unsafe { ctx.device.cmd_set_viewport(command_buffer, 0, &[area]) };
+            }
+            plan::record_draws(&mut sink, viewport.draws.iter().map(|e| (e.state(), e)));
+            if let Some(timer) = &mut timer {
+                timer.write_viewport(&ctx.device, command_buffer, self.index, index, true);
+            }
+        }
+        // End drawing this stage, unless the next one draws in the same scope
+        if !self.is_scope_kept_open {
+            unsafe { ctx.device.cmd_end_rendering(command_buffer) }
+            sink.cache.invalidate();
+        }
+        ctx.extension.try_end_label(command_buffer);
+    }
+
+    /*
+     * Draws every split screen viewport in a rendering scope of its own covering its
+     * region, clears included, through the layers of the targets allocated per viewport
+     * belonging to it. Their costs include beginning and ending the scopes.
+     */
+    fn render_viewport_layers(
+        &self,
+        ctx: &crate::context::VulkanContext,
+        prepared: &PreparedStage,
+        recording: &StageRecording,
+        mut timer: Option<&mut StageTimer>,
+        cache: &mut StateCache,
+    ) {
+        let command_buffer = recording.command_buffer;
+        self.record_barriers(ctx, recording);
+        let render_area = self.render_area(recording.default_attachment);
+        for viewport in &prepared.viewports {
+            let index = viewport.index;
+            let region = match ScissorRect::from(viewport.region).clamped_to(render_area) {
+                Some(e) => e,
+                None => continue,
+            };
+            let (rendering, descriptors) = match index as usize {
+                0 => (&self.rendering, self.attachment_descriptors.as_deref()),
+                i => {
+                    let layer = &self.viewport_layers[i - 1];
+                    (&layer.rendering, layer.attachment_descriptors.as_deref())
+                }
+            };
+            if let Some(timer) = &mut timer {
+                timer.write_viewport(&ctx.device, command_buffer, self.index, index, false);
+            }
+            ctx.extension.try_begin_label(
+                command_buffer,
+                &format!(
+                    "stage: {} / viewport {} draws ({} tasks)",
+                    self.name,
+                    index,
+                    viewport.draws.len()
+                ),
+            );
+            self.begin_rendering(ctx, recording, rendering, region);
+            cache.invalidate();
+            self.bind_descriptors(ctx, recording, descriptors, cache);
+            // Every draw has a scissor within the region already
+            let area = self.viewport_of(region);
+            self.set_dynamic_state(ctx, command_buffer, cache, area, None);
+            let mut sink = CommandSink {
+                ctx,
+                command_buffer,
+                layout: self.layout,
+                cache,
+                labeled_kind: recording
+                    .is_verbose_labels_enabled
+                    .then_some(self.task_kind),
+            };
+            plan::record_draws(&mut sink, viewport.draws.iter().map(|e| (e.state(), e)));
+            unsafe { ctx.device.cmd_end_rendering(command_buffer) }
+            ctx.extension.try_end_label(command_buffer);
+            if let Some(timer) = &mut timer {
+                timer.write_viewport(&ctx.device, command_buffer, self.index, index, true);
+            }
+        }
+        cache.invalidate();
+    }
+
+    // Viewport state of the stage moved over the region
+    fn viewport_of(&self, region: vk::Rect2D) -> vk::Viewport {
+        vk::Viewport {
+            x: region.offset.x as f32,
+            y: region.offset.y as f32,
+            width: region.extent.width as f32,
+            height: region.extent.height as f32,
+            ..self.viewport
+        }
+    }
+
+    fn bind_descriptors(
+        &self,
+        ctx: &crate::context::VulkanContext,
+        recording: &StageRecording,
+        attachment_descriptors: Option<&dyn DescriptorBackend>,
+        cache: &mut StateCache,
+    ) {
+        let mut descriptor_bindings =
+            vec![recording.sampler_descriptors, recording.image_descriptors];
+        if let Some(desc) = attachment_descriptors {
+            descriptor_bindings.push(desc.binding());
+        }
+        let tables: Vec<_> = descriptor_bindings.iter().map(|e| e.to_raw()).collect();
+        if cache.set_descriptors(self.layout, &tables) {
+            descriptor::bind(
+                ctx,
+                recording.command_buffer,
+                self.layout,
+                &descriptor_bindings,
+            );
+        }
+    }
+
+    /*
+     * State that stays set across the pipelines of the stage, all of them have it
+     * dynamic.
+     */
+    fn set_dynamic_state(
+        &self,
+        ctx: &crate::context::VulkanContext,
         command_buffer: vk::CommandBuffer,
-        default_attachment: &Attachment,
+        cache: &mut StateCache,
+        viewport: vk::Viewport,
+        scissor: Option<vk::Rect2D>,
     ) {
-        let mut image_barriers = self.image_barriers.clone();
-        if self.is_final {
-            image_barriers.push(Attachment::default_attachment_write_barrier(
-                default_attachment.image,
+        unsafe {
+            if cache.set_viewport(viewport) {
+                ctx.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            }
+            if let Some(scissor) = scissor.filter(|e| cache.set_scissor(*e)) {
+                ctx.device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+            }
+            if let Some([min, max]) = self.depth_bounds {
+                if cache.set_depth_bounds(min, max) {
+                    ctx.device.cmd_set_depth_bounds(command_buffer, min, max);
+                }
+            }
+            if let Some(fsr) = &ctx.extension.fragment_shading_rate {
+                let (fragment_size, combiner_ops) = self.shading_rate.to_vk();
+                if cache.set_shading_rate(fragment_size, combiner_ops) {
+                    (fsr.cmd_set_fragment_shading_rate_khr)(
+                        command_buffer,
+                        &fragment_size,
+                        &combiner_ops,
+                    );
+                }
+            }
+        }
+    }
+
+    ///
+    /// Whether the stage draws once per split screen viewport rather than once over the
+    /// render area. Multiview stages draw all their layers at once, they're shared too.
+    ///
+    pub fn is_drawn_per_viewport(&self) -> bool {
+        !self.is_shared_across_viewports && self.blit.is_none() && self.view_mask == 0
+    }
+
+    ///
+    /// Whether the stage can draw the low latency overlay on its own, see low_latency.
+    /// Only final Nuklear stages loading the default attachment as their one attachment,
+    /// with nothing else to read or wait on, can.
+    ///
+    pub fn is_overlay_capable(&self) -> bool {
+        self.task_kind == TaskKind::Nuklear
+            && self.is_final
+            && !self.is_first_default_write
+            && self.blit.is_none()
+            && self.rendering.default_attachment_index == Some(0)
+            && self.rendering.attachments.len() == 1
+            && self.rendering.attachments[0].load_op == vk::AttachmentLoadOp::LOAD
+            && self.rendering.depth_stencil.is_none()
+            && self.rendering.stencil.is_none()
+            && self.shading_rate_texture.is_none()
+            && self.inputs.is_empty()
+            && self.image_barriers.is_empty()
+            && self.buffer_dependencies.is_empty()
+    }
+
+    ///
+    /// Clips the prepared draws to the region, dropping the ones left with nothing to
+    /// draw. Draws without a scissor of their own get the one of the stage clipped. The
+    /// stage begins rendering over the region alone, nothing is recorded for an empty one.
+    ///
+    pub fn clip_prepared(&self, prepared: &mut PreparedStage, region: vk::Rect2D) {
+        prepared.region = Some(region);
+        self.clip_draws(&mut prepared.draws, region);
+        for viewport in &mut prepared.viewports {
+            self.clip_draws(&mut viewport.draws, region);
+        }
+    }
+
+    fn clip_draws(&self, draws: &mut Vec<PreparedDraw>, region: vk::Rect2D) {
+        draws.retain_mut(|draw| {
+            draw.scissor = plan::clip_scissor(draw.scissor, self.scissor, region);
+            draw.scissor.is_some()
+        });
+    }
+
+    ///
+    /// Same as prepare, for an offscreen pass. Task scissors don't apply, the pass draws
+    /// over the whole target.
+    ///
+    pub fn prepare_offscreen(
+        &self,
+        tasks: &[RenderTask],
+        frame: &StagePreparation,
+        ring: &mut FrameRing,
+    ) -> PreparedStage {
+        let mut prepared = self.prepare(tasks, frame, ring);
+        for draw in &mut prepared.draws {
+            draw.scissor = None;
+        }
+        prepared
+    }
+
+    ///
+    /// Draws the prepared tasks into the target of an offscreen pass instead of the
+    /// attachments of the stage, with its pipelines, descriptors and clear values. The
+    /// target gets cleared, stages that load their outputs clear to zeros and to the
+    /// depth of the target. Only the target goes through barriers, the attachments of the stage
+    /// stay as the stages of the frame left them. The color image ends up ready for
+    /// sampling.
+    ///
+    pub fn render_offscreen(
+        &self,
+        ctx: &crate::context::VulkanContext,
+        prepared: &PreparedStage,
+        sampler_descriptors: DescriptorBinding,
+        image_descriptors: DescriptorBinding,
+        command_buffer: vk::CommandBuffer,
+        target: &OffscreenTarget,
+    ) {
+        let color_range = Attachment::color_subresource_range();
+        let attachment_barrier = |image, range, stage, access| {
+            vk::ImageMemoryBarrier2::builder()
+                .image(image)
+                .subresource_range(range)
+                // Whatever sampled or read back its last contents
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_stage_mask(stage)
+                .dst_access_mask(access)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+                .build()
+        };
+        let mut before_barriers = vec![attachment_barrier(
+            target.color,
+            color_range,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        )];
+        if let Some((image, _, aspect)) = target.depth {
+            before_barriers.push(attachment_barrier(
+                image,
+                vk::ImageSubresourceRange {
+                    aspect_mask: aspect,
+                    ..color_range
+                },
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
             ));
         }
+        let after_barriers = [vk::ImageMemoryBarrier2::builder()
+            .image(target.color)
+            .subresource_range(color_range)
+            .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ)
+            .old_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build()];
+        let cleared = |info: &vk::RenderingAttachmentInfo, view, store_op, fallback| {
+            vk::RenderingAttachmentInfo {
+                image_view: view,
+                image_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op,
+                clear_value: if info.load_op == vk::AttachmentLoadOp::CLEAR {
+                    info.clear_value
+                } else {
+                    fallback
+                },
+                ..Default::default()
+            }
+        };
+        let color_attachments = [cleared(
+            &self.rendering.attachments[0],
+            target.color_view,
+            vk::AttachmentStoreOp::STORE,
+            vk::ClearValue::default(),
+        )];
+        let depth_view = target.depth.map_or(vk::ImageView::null(), |e| e.1);
+        let depth_clear = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: target.depth_clear_value,
+                stencil: 0,
+            },
+        };
+        // Only the color image outlives the pass
+        let depth_attachment = self
+            .rendering
+            .depth_stencil
+            .as_ref()
+            .map(|e| cleared(e, depth_view, vk::AttachmentStoreOp::DONT_CARE, depth_clear));
+        let stencil_attachment = self
+            .rendering
+            .stencil
+            .as_ref()
+            .map(|e| cleared(e, depth_view, vk::AttachmentStoreOp::DONT_CARE, depth_clear));
+        let area = vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent: target.extent,
+        };
+        let mut rendering_info = vk::RenderingInfo::builder()
+            .color_attachments(&color_attachments)
+            .render_area(area)
+            .layer_count(1);
+        if let Some(att) = &depth_attachment {
+            rendering_info = rendering_info.depth_attachment(att);
+        }
+        if let Some(att) = &stencil_attachment {
+            rendering_info = rendering_info.stencil_attachment(att);
+        }
+        ctx.extension.try_begin_label(
+            command_buffer,
+            &format!(
+                "offscreen: {} / draws ({} tasks) into {}x{}",
+                self.name,
+                prepared.draws.len(),
+                target.extent.width,
+                target.extent.height
+            ),
+        );
+        unsafe {
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before_barriers),
+            );
+            ctx.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+        let mut descriptor_bindings = vec![sampler_descriptors, image_descriptors];
+        if let Some(desc) = &self.attachment_descriptors {
+            descriptor_bindings.push(desc.binding());
+        }
+        descriptor::bind(ctx, command_buffer, self.layout, &descriptor_bindings);
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: target.extent.width as f32,
+            height: target.extent.height as f32,
+            ..self.viewport
+        };
+        // Nothing known of the state in its own scope, only redundant draw state gets dropped
+        let mut cache = StateCache::new(true);
+        self.set_dynamic_state(ctx, command_buffer, &mut cache, viewport, Some(area));
+        let mut sink = CommandSink {
+            ctx,
+            command_buffer,
+            layout: self.layout,
+            cache: &mut cache,
+            labeled_kind: None,
+        };
+        plan::record_draws(&mut sink, prepared.draws.iter().map(|e| (e.state(), e)));
+        unsafe {
+            ctx.device.cmd_end_rendering(command_buffer);
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after_barriers),
+            );
+        }
+        ctx.extension.try_end_label(command_buffer);
+    }
+
+    /*
+     * Barriers the stage waits on before it begins rendering, stages continuing the
+     * scope of the previous one skip them.
+     */
+    fn record_barriers(&self, ctx: &crate::context::VulkanContext, recording: &StageRecording) {
+        let default_attachment = recording.default_attachment;
+        let substituted: Vec<_> = self
+            .inputs
+            .iter()
+            .zip(&self.is_input_substituted)
+            .filter(|e| *e.1)
+            .map(|e| e.0.image)
+            .collect();
+        let mut image_barriers = self.image_barriers.clone();
+        image_barriers.retain(|e| !substituted.contains(&e.image));
+        if let Some((image, _)) = self.shading_rate_image {
+            image_barriers.push(Self::shading_rate_barrier(image, true));
+        }
+        if self.rendering.default_attachment_index.is_some() {
+            let barrier = if self.is_first_default_write {
+                Attachment::default_attachment_write_barrier(default_attachment.image)
+            } else {
+                Attachment::default_attachment_rewrite_barrier(default_attachment.image)
+            };
+            image_barriers.push(default_attachment.adapt_barrier(barrier));
+        }
+        let mut buffer_barriers = Vec::new();
+        let mut memory_barriers = Vec::new();
+        for dependency in &self.buffer_dependencies {
+            match recording.named_buffers.get(&dependency.name) {
+                Some(slice) => buffer_barriers.push(dependency.to_buffer_barrier(slice)),
+                // Range isn't known, wait on every access of the kind
+                None => memory_barriers.push(dependency.to_memory_barrier()),
+            }
+        }
         let barrier_dep_info = vk::DependencyInfo::builder()
             .image_memory_barriers(&image_barriers)
+            .buffer_memory_barriers(&buffer_barriers)
+            .memory_barriers(&memory_barriers)
             .build();
-        let mut rendering_attachments = self.rendering.attachments.clone();
-        if let Some(dai) = self.rendering.default_attachment_index {
+        let command_buffer = recording.command_buffer;
+        ctx.extension
+            .try_begin_label(command_buffer, &format!("stage: {} / barriers", self.name));
+        unsafe {
+            ctx.device
+                .cmd_pipeline_barrier2(command_buffer, &barrier_dep_info);
+        }
+        ctx.extension.try_end_label(command_buffer);
+    }
+
+    // Whole first output, the default attachment without any
+    fn render_area(&self, default_attachment: &Attachment) -> vk::Rect2D {
+        if let Some(att) = self.outputs.first() {
+            att.render_area_no_offset()
+        } else {
+            default_attachment.render_area_no_offset()
+        }
+    }
+
+    /*
+     * Begins the rendering scope over the area, with the attachments of the rendering.
+     */
+    fn begin_rendering(
+        &self,
+        ctx: &crate::context::VulkanContext,
+        recording: &StageRecording,
+        rendering: &Rendering,
+        area: vk::Rect2D,
+    ) {
+        let default_attachment = recording.default_attachment;
+        let mut rendering_attachments = rendering.attachments.clone();
+        if let Some(dai) = rendering.default_attachment_index {
             /*
              * If default attachment is present, override
              * the view with the current swapchain target
              */
             rendering_attachments[dai] = vk::RenderingAttachmentInfo {
-                image_view: default_attachment.view,
+                image_view: default_attachment.view_for(rendering.default_view_format).1,
+                image_layout: default_attachment
+                    .layout_for(rendering_attachments[dai].image_layout),
                 ..rendering_attachments[dai]
             };
         };
@@ -74,135 +1239,184 @@ impl Stage {
          */
         let mut rendering_info_builder = vk::RenderingInfo::builder()
             .color_attachments(&rendering_attachments)
-            .render_area(if let Some(att) = self.outputs.first() {
-                att.render_area_no_offset()
-            } else {
-                default_attachment.render_area_no_offset()
-            })
-            .layer_count(1);
-        if let Some(att) = &self.rendering.depth_stencil {
+            .render_area(area)
+            .layer_count(1)
+            .view_mask(self.view_mask);
+        if let Some(att) = &rendering.depth_stencil {
             rendering_info_builder = rendering_info_builder.depth_attachment(att);
         }
+        if let Some(att) = &rendering.stencil {
+            rendering_info_builder = rendering_info_builder.stencil_attachment(att);
+        }
+        let mut shading_rate_attachment = self.shading_rate_image.map(|(_, view)| {
+            vk::RenderingFragmentShadingRateAttachmentInfoKHR {
+                image_view: view,
+                image_layout: vk::ImageLayout::FRAGMENT_SHADING_RATE_ATTACHMENT_OPTIMAL_KHR,
+                shading_rate_attachment_texel_size: ctx
+                    .extension
+                    .shading_rate_texel_size
+                    .expect("shading rate image set without attachment support!"),
+                ..Default::default()
+            }
+        });
+        if let Some(att) = &mut shading_rate_attachment {
+            rendering_info_builder = rendering_info_builder.push_next(att);
+        }
         let rendering_info = rendering_info_builder.build();
+        unsafe {
+            ctx.device
+                .cmd_begin_rendering(recording.command_buffer, &rendering_info);
+        }
+    }
+
+    fn render_blit(
+        &self,
+        ctx: &crate::context::VulkanContext,
+        blit: &Blit,
+        command_buffer: vk::CommandBuffer,
+    ) {
         /*
-         *  At this point we already waited for the previous stage invocation to finish,
-         *  we can free the buffers used back then.
+         * Without a written source the destination still goes through its transitions,
+         * later stages expect it in the layout the blit leaves it in.
          */
-        self.release_reserved_buffers(&buffer_allocator);
-        let mut desc_buffer_info = vec![
-            sampler_descriptors.binding_info(),
-            image_descriptors.binding_info(),
-        ];
-        let mut desc_buffer_indices = vec![0, 1];
-        let mut desc_buffer_offsets = vec![0, 0];
-        if let Some(desc) = &self.attachment_descriptors {
-            desc_buffer_info.push(desc.binding_info());
-            desc_buffer_indices.push(2);
-            desc_buffer_offsets.push(0);
-        }
-        unsafe {
-            ctx.extension
-                .descriptor_buffer
-                .cmd_bind_descriptor_buffers(command_buffer, &desc_buffer_info);
-            ctx.extension
-                .descriptor_buffer
-                .cmd_set_descriptor_buffer_offsets(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.layout,
-                    0,
-                    &desc_buffer_indices,
-                    &desc_buffer_offsets,
-                );
+        let is_kept = |e: &vk::ImageMemoryBarrier2| {
+            blit.is_source_written || e.image == blit.destination.image
+        };
+        let mut before_barriers = self.image_barriers.clone();
+        before_barriers.retain(is_kept);
+        if !blit.is_destination_written {
+            // Never was in any layout, the first time around the contents are undefined
+            for barrier in &mut before_barriers {
+                if barrier.image == blit.destination.image {
+                    barrier.old_layout = vk::ImageLayout::UNDEFINED;
+                }
+            }
         }
+        let mut after_barriers = blit.after_barriers.clone();
+        after_barriers.retain(is_kept);
+        ctx.extension.try_begin_label(
+            command_buffer,
+            &format!(
+                "stage: {} / {} {} to {}",
+                self.name,
+                blit.verb(),
+                blit.source.name,
+                blit.destination.name
+            ),
+        );
         unsafe {
-            ctx.device
-                .cmd_pipeline_barrier2(command_buffer, &barrier_dep_info);
-            ctx.device
-                .cmd_begin_rendering(command_buffer, &rendering_info);
-            ctx.device.cmd_bind_pipeline(
+            ctx.device.cmd_pipeline_barrier2(
                 command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before_barriers),
             );
-        }
-        let per_pass_buffers =
-            self.reserve_pass_buffers(&buffer_allocator, shader_resources_by_kind);
-        let tasks = &batches_by_task_type[self.task_kind.to_usize()];
-        for task in tasks {
-            let mesh_buffer = mesh_buffers_by_id.get(&task.mesh_buffer_id).unwrap();
-            let is_indexed = !mesh_buffer.indices.is_empty();
-            // Most of the time it's nowehere near going to be close to 32 addresses
-            let mut push_constants: Vec<u64> = Vec::with_capacity(32);
-            // First appearing, the per-pass data, uploaded once and repeated for all tasks
-            push_constants.extend(&per_pass_buffers);
-            // Second, the addresses pointing to the already uploaded vertex data
-            if self.task_kind != TaskKind::Fullscreen {
-                push_constants.extend(&[
-                    mesh_buffer.vertices.device_addr,
-                    mesh_buffer.normals.device_addr,
-                    mesh_buffer.tex_coords.device_addr,
-                ]);
-            }
-            // Third, the per-instance date for the task, uploaded per task
-            push_constants.extend(&self.reserve_instance_buffers(&buffer_allocator, task));
-            // Now we push the data into the command stream and issue the draws
-            unsafe {
-                if !push_constants.is_empty() {
-                    let push_constants = push_constants.align_to::<u8>().1;
-                    ctx.device.cmd_push_constants(
-                        command_buffer,
-                        self.layout,
-                        ShaderStageFlags::ALL_GRAPHICS,
-                        0u32,
-                        &push_constants,
-                    );
-                }
-                if is_indexed {
-                    ctx.device.cmd_bind_index_buffer(
-                        command_buffer,
-                        mesh_buffer.indices.buffer,
-                        mesh_buffer.indices.offset,
-                        vk::IndexType::UINT32,
-                    );
-                    ctx.device.cmd_draw_indexed(
-                        command_buffer,
-                        mesh_buffer.count,
-                        task.instance_count,
-                        0,
-                        0,
-                        0,
-                    );
-                } else {
-                    ctx.device.cmd_draw(
-                        command_buffer,
-                        mesh_buffer.count,
-                        task.instance_count,
-                        0,
-                        0,
-                    )
-                }
+            if blit.is_source_written {
+                blit.record(ctx, command_buffer);
             }
+            ctx.device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after_barriers),
+            );
         }
-        // End drawing this stage
-        unsafe { ctx.device.cmd_end_rendering(command_buffer) }
-        if !self.is_final {
-            // Nothing else to do
-            return;
+        ctx.extension.try_end_label(command_buffer);
+    }
+
+    ///
+    /// Push constants prepare writes for each draw of this stage.
+    ///
+    pub fn per_draw_layout(&self) -> PerDrawLayout {
+        PerDrawLayout::new(
+            !self.per_pass_updaters.is_empty(),
+            !self.constants.is_empty(),
+            self.task_kind == TaskKind::Fullscreen,
+            &self.per_instance_updaters,
+            &self.vertex_formats,
+            &self.per_draw_fields,
+        )
+    }
+
+    ///
+    /// Checks the host layout of every resource kind this stage consumes against the
+    /// blocks its shaders declare. Kinds the shaders don't declare by name are skipped,
+    /// as is FrameConstants, its block is only known once set on the renderer.
+    ///
+    pub fn resource_layout_mismatches(&self) -> Vec<LayoutMismatch> {
+        self.per_pass_updaters
+            .iter()
+            .chain(&self.per_instance_updaters)
+            .filter(|kind| **kind != ResourceKind::FrameConstants)
+            .filter_map(|kind| {
+                self.check_resource_layout(*kind, kind.resource_size() as u32, &kind.host_layout())
+                    .err()
+            })
+            .collect()
+    }
+
+    pub fn check_resource_layout(
+        &self,
+        kind: ResourceKind,
+        host_size: u32,
+        host_members: &[HostMember],
+    ) -> Result<(), LayoutMismatch> {
+        self.check_block_layout(&kind.to_string(), host_size, host_members)
+    }
+
+    pub fn check_block_layout(
+        &self,
+        block: &str,
+        host_size: u32,
+        host_members: &[HostMember],
+    ) -> Result<(), LayoutMismatch> {
+        match self.reflection.block(block) {
+            Some(block) => block.compare(&self.name, host_size, host_members),
+            None => Ok(()),
         }
-        // Need to transition for presenting
-        let present_image_barriers = vec![Attachment::default_attachment_present_barrier(
-            default_attachment.image,
-        )];
-        let barrier_dep_info = vk::DependencyInfo::builder()
-            .image_memory_barriers(&present_image_barriers)
-            .build();
-        unsafe {
-            ctx.device
-                .cmd_pipeline_barrier2(command_buffer, &barrier_dep_info);
+    }
+
+    fn shading_rate_barrier(image: vk::Image, is_entering: bool) -> vk::ImageMemoryBarrier2 {
+        let texture_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let rate_layout = vk::ImageLayout::FRAGMENT_SHADING_RATE_ATTACHMENT_OPTIMAL_KHR;
+        let rate_stage = vk::PipelineStageFlags2::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR;
+        let rate_access = vk::AccessFlags2::FRAGMENT_SHADING_RATE_ATTACHMENT_READ_KHR;
+        let b = vk::ImageMemoryBarrier2::builder()
+            .image(image)
+            .subresource_range(Attachment::color_subresource_range());
+        if is_entering {
+            // Uploads of the texture happen earlier in the frame
+            b.src_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(rate_stage)
+                .dst_access_mask(rate_access)
+                .old_layout(texture_layout)
+                .new_layout(rate_layout)
+                .build()
+        } else {
+            b.src_stage_mask(rate_stage)
+                .src_access_mask(rate_access)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .old_layout(rate_layout)
+                .new_layout(texture_layout)
+                .build()
         }
     }
 
+    ///
+    /// Variant the pipeline pipeline_for picks was made for, None for the base one.
+    ///
+    pub fn drawn_variant_of(&self, variant: Option<u16>) -> Option<u16> {
+        variant.filter(|e| {
+            self.variant_pipelines
+                .get(*e as usize)
+                .is_some_and(Option::is_some)
+        })
+    }
+
+    pub fn pipeline_for(&self, variant: Option<u16>) -> vk::Pipeline {
+        variant
+            .and_then(|e| self.variant_pipelines.get(e as usize).copied().flatten())
+            .unwrap_or(self.pipeline)
+    }
+
     pub fn wait_for_previous_frame(
         &self,
         device: &ash::Device,
@@ -261,13 +1475,12 @@ impl Stage {
         crate::pipeline::signal_value_for(current_frame, total_stages, self.index)
     }
 
-    fn release_reserved_buffers(&mut self, mem: &DeviceAllocator) {
-        for buffer in self.reserved_buffers.drain(..) {
-            mem.free(buffer);
-        }
-    }
-
-    fn reserve_instance_buffers(&mut self, mem: &DeviceAllocator, task: &RenderTask) -> Vec<u64> {
+    fn reserve_instance_buffers(
+        &self,
+        ring: &mut FrameRing,
+        scene_slots: &HashMap<u32, SceneSlot>,
+        task: &RenderTask,
+    ) -> Vec<u64> {
         if self.per_instance_updaters.is_empty() {
             // Nothing to upload
             return Vec::new();
@@ -275,43 +1488,210 @@ impl Stage {
         // We'll need the addresses to pass them to the shaders later
         let mut device_addrs = Vec::with_capacity(self.per_instance_updaters.len());
         for kind in self.per_instance_updaters.clone() {
-            if let Some(res) = task.resources.get(&kind) {
-                let buffer = updater::alloc_and_fill_multi(mem, res, task.instance_count);
-                device_addrs.push(buffer.device_addr);
-                self.reserved_buffers.push(buffer);
-            } else {
-                panic!("unavailable resource kind {}", kind)
+            match task.resources.get(&kind) {
+                // Already on the GPU, checked against the instance count when queued
+                Some(MultiResource::SceneSlot(id)) => {
+                    let slot = scene_slots
+                        .get(&id.index)
+                        .filter(|e| e.id == *id)
+                        .unwrap_or_else(|| {
+                            panic!("scene slot {} freed while a task was queued with it!", id)
+                        });
+                    device_addrs.push(slot.buffer.device_addr);
+                }
+                Some(res) => {
+                    let buffer = updater::alloc_and_fill_multi(ring, res, task.instance_count);
+                    device_addrs.push(buffer.device_addr);
+                }
+                None => panic!("unavailable resource kind {}", kind),
             }
         }
         device_addrs
     }
 
+    fn write_per_draw_fields(
+        &self,
+        task: &RenderTask,
+        motion: &FrameMotion,
+        ring: &mut FrameRing,
+        dst: &mut Vec<u8>,
+    ) {
+        for field in &self.per_draw_fields {
+            match field {
+                PerDrawField::AlbedoTexture => {
+                    // Default texture if there is no material
+                    let (texture, sampler) = match task.resources.get(&ResourceKind::Material) {
+                        Some(MultiResource::Material(e)) if !e.is_empty() => {
+                            (e[0].diffuse_handle, e[0].diffuse_sampler as u32)
+                        }
+                        _ => (0, 0),
+                    };
+                    dst.extend(texture.to_ne_bytes());
+                    dst.extend(sampler.to_ne_bytes());
+                }
+                PerDrawField::AlphaCutoff => dst.extend(task.alpha_cutoff.to_ne_bytes()),
+                PerDrawField::Flags => dst.extend(task.flags().to_ne_bytes()),
+                PerDrawField::ViewDepth => dst.extend(task.view_depth.to_ne_bytes()),
+                PerDrawField::PreviousTransform => {
+                    let buffer = self.reserve_previous_transforms(motion, ring, task);
+                    dst.extend(buffer.device_addr.to_ne_bytes());
+                }
+                PerDrawField::InterpolatedTransform => {
+                    self.write_interpolated_transform(task, motion, ring, dst)
+                }
+                PerDrawField::TextureFeedback => dst.extend(self.texture_feedback.to_ne_bytes()),
+            }
+        }
+    }
+
+    /*
+     * Last frame mvp of each instance of the task. Tasks without an object id, or not
+     * seen with as many instances before, get their current mvps, so zero velocity.
+     *
+     * Stages interpolating transforms get view projections as the task's mvps, the
+     * model comes from the simulation. Their objects move from the interpolated
+     * transform a simulation step earlier, see SimulationTransforms::velocity_origin,
+     * rather than from where the previous frame interpolated them.
+     */
+    fn reserve_previous_transforms(
+        &self,
+        motion: &FrameMotion,
+        ring: &mut FrameRing,
+        task: &RenderTask,
+    ) -> DeviceSlice {
+        let current = match task.resources.get(&ResourceKind::Transform) {
+            Some(MultiResource::Transform(e)) => e,
+            _ => panic!(
+                "stage {} writes {}, but the task has no {}!",
+                self.name,
+                PerDrawField::PreviousTransform,
+                ResourceKind::Transform
+            ),
+        };
+        let previous = task
+            .object_id
+            .and_then(|e| motion.previous.get(self.task_kind, e))
+            .filter(|e| e.len() >= task.instance_count as usize);
+        let simulation = self.simulation_of(motion, task);
+        let extras = (0..task.instance_count as usize)
+            .map(|i| {
+                let prev_mvp = previous.map_or(current[i].mvp, |e| e[i]);
+                match simulation {
+                    Some((simulation, index)) => TransformExtra {
+                        prev_mvp: prev_mvp
+                            * motion::affine_to_mat4(&simulation.velocity_origin(index + i)),
+                    },
+                    None => TransformExtra { prev_mvp },
+                }
+            })
+            .collect();
+        let resource = MultiResource::TransformExtra(extras);
+        updater::alloc_and_fill_multi(ring, &resource, task.instance_count)
+    }
+
+    /*
+     * Both state addresses, then the index of the task's first instance and the alpha.
+     * The cpu way blends the instances into the ring here, both addresses point at them
+     * and they start at 0 with an alpha of 0.
+     */
+    fn write_interpolated_transform(
+        &self,
+        task: &RenderTask,
+        motion: &FrameMotion,
+        ring: &mut FrameRing,
+        dst: &mut Vec<u8>,
+    ) {
+        let (simulation, index) = self.simulation_of(motion, task).unwrap_or_else(|| {
+            panic!(
+                "stage {} writes {}, but there are no simulation transforms for the task!",
+                self.name,
+                PerDrawField::InterpolatedTransform
+            )
+        });
+        let ([previous, current], index, alpha) = match self.transform_interpolation {
+            TransformInterpolation::Shader => {
+                let uploaded = motion
+                    .uploaded_simulation
+                    .expect("simulation transforms weren't uploaded!");
+                (uploaded, index as u32, simulation.alpha())
+            }
+            TransformInterpolation::Cpu => {
+                let bytes = simulation.interpolated_bytes(index, task.instance_count as usize);
+                let buffer = updater::alloc_and_copy_bytes(ring, &bytes);
+                ([buffer.device_addr; 2], 0, 0.0)
+            }
+            TransformInterpolation::None => unreachable!("checked when loading"),
+        };
+        dst.extend(previous.to_ne_bytes());
+        dst.extend(current.to_ne_bytes());
+        dst.extend(index.to_ne_bytes());
+        dst.extend(alpha.to_ne_bytes());
+    }
+
+    /*
+     * Simulation transforms of the frame and the index of the task's first instance in
+     * them, if the stage interpolates and they have every instance of the task.
+     */
+    fn simulation_of<'a>(
+        &self,
+        motion: &FrameMotion<'a>,
+        task: &RenderTask,
+    ) -> Option<(&'a motion::SimulationTransforms, usize)> {
+        if self.transform_interpolation == TransformInterpolation::None {
+            return None;
+        }
+        let simulation = motion.simulation?;
+        let index = task.object_id? as usize;
+        (index + task.instance_count as usize <= simulation.len()).then_some((simulation, index))
+    }
+
     fn reserve_pass_buffers(
-        &mut self,
-        mem: &DeviceAllocator,
+        &self,
+        ring: &mut FrameRing,
         shader_resources_by_kind: &HashMap<ResourceKind, SingleResource>,
+        overrides: &HashMap<ResourceKind, SingleResource>,
     ) -> Vec<u64> {
-        if self.per_pass_updaters.is_empty() {
-            // Nothing to upload
-            return Vec::new();
+        let mut addresses = Vec::with_capacity(2);
+        if !self.per_pass_updaters.is_empty() {
+            addresses.push(self.reserve_per_pass_buffer(ring, shader_resources_by_kind, overrides));
+        }
+        if !self.constants.is_empty() {
+            // Copied every frame, values set later don't reach frames already prepared
+            let dst = updater::alloc_in(ring, self.constants.size() as u64);
+            let bytes = self.constants.to_bytes();
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst.addr as *mut u8, bytes.len())
+            };
+            addresses.push(dst.device_addr);
         }
+        addresses
+    }
+
+    fn reserve_per_pass_buffer(
+        &self,
+        ring: &mut FrameRing,
+        shader_resources_by_kind: &HashMap<ResourceKind, SingleResource>,
+        overrides: &HashMap<ResourceKind, SingleResource>,
+    ) -> u64 {
         let total_size: usize = self
             .per_pass_updaters
             .iter()
             .map(|e| e.resource_size())
             .sum();
-        let dst = mem.alloc(total_size as u64).unwrap();
+        let dst = updater::alloc_in(ring, total_size as u64);
         let mut offset = 0u64;
         for kind in self.per_pass_updaters.clone() {
-            if let Some(res) = shader_resources_by_kind.get(&kind) {
+            let res = overrides
+                .get(&kind)
+                .or_else(|| shader_resources_by_kind.get(&kind));
+            if let Some(res) = res {
                 offset = updater::fill_single(res, &dst, offset);
             } else {
                 panic!("unavailable resource kind {}", kind)
             }
         }
-        // Will be freed later
-        self.reserved_buffers.push(dst);
+        // Reclaimed once the GPU is done with the frame
         // We'll need 1 address since all the data goes into the same buffer
-        vec![dst.device_addr]
+        dst.device_addr
     }
 }
@@ -0,0 +1,245 @@
+/*
+ * Last state set in the command buffer of the frame, so Stage::render drops commands
+ * setting a value that's already set. Every command it records goes through the cache:
+ * the set_* methods remember the value and tell whether the command still has to be
+ * recorded, counting it as requested either way and as emitted only then.
+ *
+ * What the cache knows gets forgotten at the boundaries of rendering scopes and after
+ * executing secondary command buffers, anything recorded around it that it doesn't see
+ * could have changed the state. Disabled, it still tracks the state and counts, it only
+ * never drops commands, so both ways can be compared.
+ */
+use ash::vk;
+
+///
+/// Kinds of state setting commands the cache sees.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+pub enum StateCommand {
+    Pipeline,
+    CullMode,
+    Viewport,
+    Scissor,
+    DepthBounds,
+    ShadingRate,
+    Descriptors,
+    IndexBuffer,
+    PushConstants,
+}
+
+impl StateCommand {
+    pub const ALL: [Self; 9] = [
+        Self::Pipeline,
+        Self::CullMode,
+        Self::Viewport,
+        Self::Scissor,
+        Self::DepthBounds,
+        Self::ShadingRate,
+        Self::Descriptors,
+        Self::IndexBuffer,
+        Self::PushConstants,
+    ];
+}
+
+///
+/// Commands asked for and actually recorded, by StateCommand.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateCounters {
+    pub requested: [u64; StateCommand::ALL.len()],
+    pub emitted: [u64; StateCommand::ALL.len()],
+}
+
+impl StateCounters {
+    pub fn requested(&self, command: StateCommand) -> u64 {
+        self.requested[command as usize]
+    }
+
+    pub fn emitted(&self, command: StateCommand) -> u64 {
+        self.emitted[command as usize]
+    }
+
+    ///
+    /// Commands dropped as redundant over every kind.
+    ///
+    pub fn dropped(&self) -> u64 {
+        self.requested.iter().sum::<u64>() - self.emitted.iter().sum::<u64>()
+    }
+}
+
+///
+/// See the module docs. Values without an Eq of their own are kept by their bits.
+///
+#[derive(Default)]
+pub struct StateCache {
+    is_enabled: bool,
+    pipeline: Option<vk::Pipeline>,
+    cull_mode: Option<vk::CullModeFlags>,
+    viewport: Option<[u32; 6]>,
+    scissor: Option<vk::Rect2D>,
+    depth_bounds: Option<[u32; 2]>,
+    shading_rate: Option<(vk::Extent2D, [vk::FragmentShadingRateCombinerOpKHR; 2])>,
+    // Layout and the raw handle or address of every table, in set order
+    descriptors: Option<(vk::PipelineLayout, Vec<u64>)>,
+    index_buffer: Option<(vk::Buffer, vk::DeviceSize)>,
+    push_constants: Option<(vk::PipelineLayout, Vec<u8>)>,
+    counters: StateCounters,
+}
+
+impl StateCache {
+    pub fn new(is_enabled: bool) -> Self {
+        Self {
+            is_enabled,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    ///
+    /// Starts over for a new command buffer, forgetting the state and the counts.
+    ///
+    pub fn begin(&mut self, is_enabled: bool) {
+        *self = Self::new(is_enabled);
+    }
+
+    ///
+    /// Forgets the state but keeps counting, for rendering scope boundaries and after
+    /// executing secondary command buffers.
+    ///
+    pub fn invalidate(&mut self) {
+        *self = Self {
+            is_enabled: self.is_enabled,
+            counters: self.counters,
+            ..Default::default()
+        };
+    }
+
+    pub fn counters(&self) -> StateCounters {
+        self.counters
+    }
+
+    pub fn pipeline(&self) -> Option<vk::Pipeline> {
+        self.pipeline
+    }
+
+    pub fn cull_mode(&self) -> Option<vk::CullModeFlags> {
+        self.cull_mode
+    }
+
+    pub fn scissor(&self) -> Option<vk::Rect2D> {
+        self.scissor
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: vk::Pipeline) -> bool {
+        let is_same = self.pipeline == Some(pipeline);
+        self.pipeline = Some(pipeline);
+        self.count(StateCommand::Pipeline, is_same)
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: vk::CullModeFlags) -> bool {
+        let is_same = self.cull_mode == Some(cull_mode);
+        self.cull_mode = Some(cull_mode);
+        self.count(StateCommand::CullMode, is_same)
+    }
+
+    pub fn set_viewport(&mut self, viewport: vk::Viewport) -> bool {
+        let bits = [
+            viewport.x,
+            viewport.y,
+            viewport.width,
+            viewport.height,
+            viewport.min_depth,
+            viewport.max_depth,
+        ]
+        .map(f32::to_bits);
+        let is_same = self.viewport == Some(bits);
+        self.viewport = Some(bits);
+        self.count(StateCommand::Viewport, is_same)
+    }
+
+    pub fn set_scissor(&mut self, scissor: vk::Rect2D) -> bool {
+        let is_same = self.scissor == Some(scissor);
+        self.scissor = Some(scissor);
+        self.count(StateCommand::Scissor, is_same)
+    }
+
+    pub fn set_depth_bounds(&mut self, min: f32, max: f32) -> bool {
+        let bits = [min.to_bits(), max.to_bits()];
+        let is_same = self.depth_bounds == Some(bits);
+        self.depth_bounds = Some(bits);
+        self.count(StateCommand::DepthBounds, is_same)
+    }
+
+    pub fn set_shading_rate(
+        &mut self,
+        fragment_size: vk::Extent2D,
+        combiner_ops: [vk::FragmentShadingRateCombinerOpKHR; 2],
+    ) -> bool {
+        let rate = (fragment_size, combiner_ops);
+        let is_same = self.shading_rate == Some(rate);
+        self.shading_rate = Some(rate);
+        self.count(StateCommand::ShadingRate, is_same)
+    }
+
+    ///
+    /// Tables bound to the sets starting at 0 with the layout, by their raw handles or
+    /// addresses.
+    ///
+    pub fn set_descriptors(&mut self, layout: vk::PipelineLayout, tables: &[u64]) -> bool {
+        let is_same = self
+            .descriptors
+            .as_ref()
+            .is_some_and(|(bound_layout, bound)| *bound_layout == layout && bound == tables);
+        if !is_same {
+            // Binding with another layout can disturb what got pushed with the previous one
+            if self.descriptors.as_ref().map(|e| e.0) != Some(layout) {
+                self.push_constants = None;
+            }
+            self.descriptors = Some((layout, tables.to_vec()));
+        }
+        self.count(StateCommand::Descriptors, is_same)
+    }
+
+    pub fn set_index_buffer(&mut self, buffer: vk::Buffer, offset: vk::DeviceSize) -> bool {
+        let is_same = self.index_buffer == Some((buffer, offset));
+        self.index_buffer = Some((buffer, offset));
+        self.count(StateCommand::IndexBuffer, is_same)
+    }
+
+    ///
+    /// Whole push constant block of the layout, from offset 0.
+    ///
+    pub fn set_push_constants(&mut self, layout: vk::PipelineLayout, bytes: &[u8]) -> bool {
+        let is_same = self
+            .push_constants
+            .as_ref()
+            .is_some_and(|(bound_layout, bound)| *bound_layout == layout && bound == bytes);
+        if !is_same {
+            match &mut self.push_constants {
+                // Keeps the allocation, they get set for almost every draw
+                Some((bound_layout, bound)) => {
+                    *bound_layout = layout;
+                    bound.clear();
+                    bound.extend_from_slice(bytes);
+                }
+                None => self.push_constants = Some((layout, bytes.to_vec())),
+            }
+        }
+        self.count(StateCommand::PushConstants, is_same)
+    }
+
+    /*
+     * Whether the command has to be recorded.
+     */
+    fn count(&mut self, command: StateCommand, is_same: bool) -> bool {
+        self.counters.requested[command as usize] += 1;
+        let is_emitted = !is_same || !self.is_enabled;
+        if is_emitted {
+            self.counters.emitted[command as usize] += 1;
+        }
+        is_emitted
+    }
+}
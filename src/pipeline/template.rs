@@ -0,0 +1,500 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Number, Value};
+
+use super::file::migrate;
+
+/*
+ * Pipeline templates are pipeline files of their own, with "params" holding the
+ * default value of each parameter (null for required ones). A pass entry like
+ *   { "use": "builtin:bloom", "name": "bloom", "params": { "input": "hdr" } }
+ * gets replaced by the template's passes, and its targets and programs get appended
+ * to the pipeline's. Strings starting with @ are local to the template and get the
+ * instance name as prefix, "${expr}" gets replaced by the value of the expression over
+ * the params. Entries like
+ *   { "repeat": { "index": "i", "from": 0, "to": "${mips}" }, "items": [...] }
+ * in the targets or passes of a template get unrolled, "to" excluded and counting down
+ * if it's lower than "from". Templates get migrated from their "version" like pipeline
+ * files, after unrolling. Names the expansion adds have to be new to the pipeline, the
+ * prefix keeps locals of different instances apart but not from a user's names.
+ */
+
+const BUILTIN_PREFIX: &str = "builtin:";
+
+#[cfg(feature = "builtin-passes")]
+const BUILTINS: &[(&str, &str)] = &[
+    ("bloom", include_str!("../../shader/builtin/bloom.json")),
+    ("tonemap", include_str!("../../shader/builtin/tonemap.json")),
+];
+
+///
+/// Replaces every template use in the passes of the pipeline with its expansion, paths
+/// of local templates are relative to base_dir.
+///
+pub fn expand(pipeline: &mut Value, base_dir: &Path) {
+    let passes = match pipeline.get_mut("passes").and_then(|e| e.as_array_mut()) {
+        Some(passes) => std::mem::take(passes),
+        None => return,
+    };
+    let mut expanded_passes = Vec::with_capacity(passes.len());
+    let mut targets = Vec::new();
+    let mut programs = Vec::new();
+    let mut uses_by_template = HashMap::<String, u32>::new();
+    // Names in each section of the pipeline so far
+    let mut taken = HashMap::<&str, HashSet<String>>::new();
+    for name in ["targets", "programs"] {
+        let items = pipeline[name].as_array().map_or(&[][..], |e| e.as_slice());
+        taken.insert(name, HashSet::from_iter(names_of(items)));
+    }
+    // Names of uses are instance names, gone once expanded
+    let user_passes = passes.iter().filter(|e| e.get("use").is_none());
+    taken.insert("passes", HashSet::from_iter(names_of(user_passes)));
+    for pass in passes {
+        let template_name = match pass.get("use").and_then(|e| e.as_str()) {
+            Some(name) => name.to_string(),
+            None => {
+                expanded_passes.push(pass);
+                continue;
+            }
+        };
+        let template = load(&template_name, base_dir);
+        // Instance name is the prefix of everything local to the template
+        let use_count = uses_by_template.entry(template_name.clone()).or_default();
+        let instance = match pass.get("name").and_then(|e| e.as_str()) {
+            Some(name) => name.to_string(),
+            None => format!("{}{}", default_instance_name(&template_name), use_count),
+        };
+        *use_count += 1;
+        let params = params_of(&template_name, &template, pass.get("params"));
+        let mut template = substitute(&prefix_locals(template, &instance), &params);
+        for name in ["targets", "programs", "passes"] {
+            if let Some(items) = template.get_mut(name).and_then(|e| e.as_array_mut()) {
+                let items = std::mem::take(items);
+                template[name] = Value::Array(unroll(items, &params));
+            }
+        }
+        // Unrolled first so migrations see every item
+        migrate(&mut template).unwrap_or_else(|e| panic!("template {}: {}", template_name, e));
+        let mut section = |name: &'static str| -> Vec<Value> {
+            let items = template
+                .get(name)
+                .and_then(|e| e.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let taken = taken.get_mut(name).unwrap();
+            for item in names_of(&items) {
+                if !taken.insert(item.clone()) {
+                    panic!(
+                        "{} as {} adds {} {} but the pipeline already has one by that name!",
+                        template_name,
+                        instance,
+                        match name {
+                            "targets" => "target",
+                            "programs" => "program",
+                            _ => "pass",
+                        },
+                        item
+                    );
+                }
+            }
+            items
+        };
+        targets.extend(section("targets"));
+        programs.extend(section("programs"));
+        for mut expanded in section("passes") {
+            if expanded.get("use").is_some() {
+                panic!("template {} can't use other templates!", template_name);
+            }
+            // Kept around so the expansion shows up in the frame graph
+            expanded["template"] = Value::String(format!("{} as {}", template_name, instance));
+            expanded_passes.push(expanded);
+        }
+    }
+    pipeline["passes"] = Value::Array(expanded_passes);
+    for (name, items) in [("targets", targets), ("programs", programs)] {
+        match pipeline.get_mut(name).and_then(|e| e.as_array_mut()) {
+            Some(existing) => existing.extend(items),
+            None => pipeline[name] = Value::Array(items),
+        }
+    }
+}
+
+fn names_of<'a>(items: impl IntoIterator<Item = &'a Value>) -> Vec<String> {
+    items
+        .into_iter()
+        .filter_map(|e| e.get("name").and_then(|e| e.as_str()))
+        .map(String::from)
+        .collect()
+}
+
+fn load(name: &str, base_dir: &Path) -> Value {
+    let src = match name.strip_prefix(BUILTIN_PREFIX) {
+        Some(builtin) => builtin_source(builtin).to_string(),
+        None => {
+            let path: PathBuf = base_dir.join(name);
+            std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("failed reading template {}: {}", path.display(), e))
+        }
+    };
+    serde_json::from_str(&src).unwrap_or_else(|e| panic!("couldn't parse template {}: {}", name, e))
+}
+
+#[cfg(feature = "builtin-passes")]
+fn builtin_source(name: &str) -> &'static str {
+    BUILTINS
+        .iter()
+        .find(|e| e.0 == name)
+        .map(|e| e.1)
+        .unwrap_or_else(|| panic!("unknown builtin template {}!", name))
+}
+
+#[cfg(not(feature = "builtin-passes"))]
+fn builtin_source(name: &str) -> &'static str {
+    panic!(
+        "builtin template {} requested but the builtin-passes feature is disabled!",
+        name
+    )
+}
+
+fn default_instance_name(template_name: &str) -> String {
+    let name = template_name
+        .strip_prefix(BUILTIN_PREFIX)
+        .unwrap_or(template_name);
+    Path::new(name)
+        .file_stem()
+        .and_then(|e| e.to_str())
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn params_of(
+    template_name: &str,
+    template: &Value,
+    provided: Option<&Value>,
+) -> Map<String, Value> {
+    let mut params = template
+        .get("params")
+        .and_then(|e| e.as_object())
+        .cloned()
+        .unwrap_or_default();
+    if let Some(provided) = provided.and_then(|e| e.as_object()) {
+        for (name, value) in provided {
+            if !params.contains_key(name) {
+                panic!("template {} has no parameter {}!", template_name, name);
+            }
+            params.insert(name.clone(), value.clone());
+        }
+    }
+    if let Some((name, _)) = params.iter().find(|e| e.1.is_null()) {
+        panic!("template {} requires parameter {}!", template_name, name);
+    }
+    params
+}
+
+fn prefix_locals(value: Value, instance: &str) -> Value {
+    match value {
+        Value::String(s) => match s.strip_prefix('@') {
+            Some(local) => Value::String(format!("{}_{}", instance, local)),
+            None => Value::String(s),
+        },
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|e| prefix_locals(e, instance))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                // Parameters come from the user, they aren't local
+                .map(|(k, v)| match k.as_str() {
+                    "params" => (k, v),
+                    _ => (k, prefix_locals(v, instance)),
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn unroll(items: Vec<Value>, vars: &Map<String, Value>) -> Vec<Value> {
+    let mut unrolled = Vec::with_capacity(items.len());
+    for item in items {
+        let repeat = match item.get("repeat") {
+            Some(repeat) => repeat,
+            None => {
+                unrolled.push(item);
+                continue;
+            }
+        };
+        let bound = |name: &str| -> i64 {
+            let v = substitute(&repeat[name], vars);
+            v.as_i64()
+                .unwrap_or_else(|| panic!("repeat {} has to be an integer, got {}", name, v))
+        };
+        let index = repeat["index"]
+            .as_str()
+            .expect("repeat index has to be a name!")
+            .to_string();
+        let (from, to) = (bound("from"), bound("to"));
+        let indices: Vec<i64> = if from <= to {
+            (from..to).collect()
+        } else {
+            ((to + 1)..=from).rev().collect()
+        };
+        let body = item
+            .get("items")
+            .and_then(|e| e.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for i in indices {
+            let mut vars = vars.clone();
+            vars.insert(index.clone(), Value::from(i));
+            let body: Vec<_> = body.iter().map(|e| substitute(e, &vars)).collect();
+            unrolled.extend(unroll(body, &vars));
+        }
+    }
+    unrolled
+}
+
+/*
+ * Expressions of repeat blocks get substituted once their index is known, everything
+ * else right away.
+ */
+fn substitute(value: &Value, vars: &Map<String, Value>) -> Value {
+    match value {
+        Value::String(s) => substitute_str(s, vars),
+        Value::Array(items) => Value::Array(items.iter().map(|e| substitute(e, vars)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| match k.as_str() {
+                    "params" => (k.clone(), v.clone()),
+                    _ => (k.clone(), substitute(v, vars)),
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn substitute_str(s: &str, vars: &Map<String, Value>) -> Value {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => panic!("unclosed expression in {}", s),
+        };
+        let expr = &rest[start + 2..end];
+        let value = match eval(expr, vars) {
+            Some(value) => value,
+            // Unknown names belong to an enclosing repeat, left for later
+            None => Value::String(rest[start..=end].to_string()),
+        };
+        // A lone expression keeps the type of its value
+        if start == 0 && end == rest.len() - 1 && out.is_empty() {
+            return value;
+        }
+        out.push_str(&rest[..start]);
+        match value {
+            Value::String(v) => out.push_str(&v),
+            v => out.push_str(&v.to_string()),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Value::String(out)
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn to_f64(self) -> f64 {
+        match self {
+            Num::Int(v) => v as f64,
+            Num::Float(v) => v,
+        }
+    }
+
+    fn to_value(self) -> Value {
+        match self {
+            Num::Int(v) => Value::from(v),
+            Num::Float(v) => Number::from_f64(v)
+                .map(Value::Number)
+                .unwrap_or_else(|| panic!("expression evaluated to {}", v)),
+        }
+    }
+}
+
+/*
+ * Supports + - * / ^ and parentheses over numbers and params. Integers stay integers
+ * unless divided or mixed with floats. Returns None if the expression uses a name
+ * that isn't known yet.
+ */
+fn eval(expr: &str, vars: &Map<String, Value>) -> Option<Value> {
+    let name = expr.trim();
+    if let Some(value) = vars.get(name) {
+        // Lone names can be of any type
+        return Some(value.clone());
+    }
+    let tokens = tokenize(expr);
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        vars,
+    };
+    let result = parser.sum()?;
+    if parser.pos != tokens.len() {
+        panic!("unexpected {:?} in expression {}", tokens[parser.pos], expr);
+    }
+    Some(result.to_value())
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Num(Num),
+    Name(String),
+    Op(char),
+}
+
+fn tokenize(expr: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let num = if literal.contains('.') {
+                Num::Float(literal.parse().unwrap())
+            } else {
+                Num::Int(literal.parse().unwrap())
+            };
+            tokens.push(Token::Num(num));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else {
+            panic!("unexpected '{}' in expression {}", c, expr);
+        }
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    vars: &'a Map<String, Value>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Option<Num> {
+        let mut lhs = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek_op() {
+            self.pos += 1;
+            let rhs = self.product()?;
+            lhs = apply(op, lhs, rhs);
+        }
+        Some(lhs)
+    }
+
+    fn product(&mut self) -> Option<Num> {
+        let mut lhs = self.power()?;
+        while let Some(op @ ('*' | '/')) = self.peek_op() {
+            self.pos += 1;
+            let rhs = self.power()?;
+            lhs = apply(op, lhs, rhs);
+        }
+        Some(lhs)
+    }
+
+    fn power(&mut self) -> Option<Num> {
+        let base = self.unary()?;
+        if self.peek_op() == Some('^') {
+            self.pos += 1;
+            // Right associative
+            let exp = self.power()?;
+            return Some(apply('^', base, exp));
+        }
+        Some(base)
+    }
+
+    fn unary(&mut self) -> Option<Num> {
+        if self.peek_op() == Some('-') {
+            self.pos += 1;
+            return Some(apply('-', Num::Int(0), self.unary()?));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Option<Num> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .expect("expression ended unexpectedly");
+        self.pos += 1;
+        match token {
+            Token::Num(num) => Some(num),
+            Token::Name(name) => {
+                let value = self.vars.get(&name)?;
+                Some(match (value.as_i64(), value.as_f64()) {
+                    (Some(v), _) => Num::Int(v),
+                    (_, Some(v)) => Num::Float(v),
+                    _ => panic!("{} isn't a number, it's {}", name, value),
+                })
+            }
+            Token::Op('(') => {
+                let inner = self.sum()?;
+                if self.peek_op() != Some(')') {
+                    panic!("missing ) in expression");
+                }
+                self.pos += 1;
+                Some(inner)
+            }
+            Token::Op(op) => panic!("unexpected {} in expression", op),
+        }
+    }
+}
+
+fn apply(op: char, lhs: Num, rhs: Num) -> Num {
+    match (op, lhs, rhs) {
+        ('+', Num::Int(a), Num::Int(b)) => Num::Int(a + b),
+        ('-', Num::Int(a), Num::Int(b)) => Num::Int(a - b),
+        ('*', Num::Int(a), Num::Int(b)) => Num::Int(a * b),
+        ('^', Num::Int(a), Num::Int(b)) if b >= 0 => Num::Int(a.pow(b as u32)),
+        ('+', a, b) => Num::Float(a.to_f64() + b.to_f64()),
+        ('-', a, b) => Num::Float(a.to_f64() - b.to_f64()),
+        ('*', a, b) => Num::Float(a.to_f64() * b.to_f64()),
+        ('/', a, b) => Num::Float(a.to_f64() / b.to_f64()),
+        ('^', a, b) => Num::Float(a.to_f64().powf(b.to_f64())),
+        _ => unreachable!(),
+    }
+}
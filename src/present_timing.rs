@@ -0,0 +1,178 @@
+/*
+ * Presentation timestamps from VK_GOOGLE_display_timing, enabled whenever the device
+ * has it. Every present carries an id and an optional target time, the display reports
+ * back when each one actually reached the screen a few frames later. Times are in
+ * nanoseconds of the display clock, which isn't the one of Instant.
+ */
+use std::collections::VecDeque;
+
+use ash::vk;
+
+// Presents kept in Renderer::present_timing_history
+pub const HISTORY_LEN: usize = 128;
+
+///
+/// When a present was asked for and when it happened. Desired is 0 for presents without
+/// a target time. Earliest is when it could have been shown had it been asked for then,
+/// and margin how long before the latest point it could have been queued and still make
+/// it.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PresentTiming {
+    pub present_id: u32,
+    pub desired_present_time: u64,
+    pub actual_present_time: u64,
+    pub earliest_present_time: u64,
+    pub present_margin: u64,
+    pub is_missed_vsync: bool,
+}
+
+///
+/// Timings of the last presents and the count of those that missed a vsync. A present
+/// misses one when it reaches the screen over a refresh and a half after the one before
+/// it, or over half a refresh after its target time.
+///
+#[derive(Clone, Debug)]
+pub struct DisplayTiming {
+    history: VecDeque<PresentTiming>,
+    history_len: usize,
+    // Asked for again after each swapchain rebuild
+    refresh_duration: Option<u64>,
+    next_present_id: u32,
+    target_present_time: Option<u64>,
+    last_actual_present_time: Option<u64>,
+    missed_vsyncs: u64,
+}
+
+impl DisplayTiming {
+    pub fn new(history_len: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            refresh_duration: None,
+            next_present_id: 1,
+            target_present_time: None,
+            last_actual_present_time: None,
+            missed_vsyncs: 0,
+        }
+    }
+
+    pub fn history(&self) -> &VecDeque<PresentTiming> {
+        &self.history
+    }
+
+    pub fn missed_vsyncs(&self) -> u64 {
+        self.missed_vsyncs
+    }
+
+    pub fn refresh_duration(&self) -> Option<u64> {
+        self.refresh_duration
+    }
+
+    pub fn set_refresh_duration(&mut self, duration: Option<u64>) {
+        self.refresh_duration = duration;
+    }
+
+    ///
+    /// Target time of the next present only, presents after it go out as soon as they
+    /// can again.
+    ///
+    pub fn set_target_present_time(&mut self, time: u64) {
+        self.target_present_time = Some(time);
+    }
+
+    ///
+    /// Id and target time for the present about to be queued.
+    ///
+    pub fn next_present(&mut self) -> vk::PresentTimeGOOGLE {
+        let present_id = self.next_present_id;
+        // 0 is no id at all
+        self.next_present_id = self.next_present_id.checked_add(1).unwrap_or(1);
+        vk::PresentTimeGOOGLE {
+            present_id,
+            desired_present_time: self.target_present_time.take().unwrap_or(0),
+        }
+    }
+
+    ///
+    /// Adds a timing reported by the display, in the order they got reported.
+    ///
+    pub fn record(&mut self, past: vk::PastPresentationTimingGOOGLE) -> PresentTiming {
+        let is_missed_vsync = match self.refresh_duration {
+            Some(refresh) => {
+                let is_late_after_last = self
+                    .last_actual_present_time
+                    .is_some_and(|e| past.actual_present_time > e + refresh + refresh / 2);
+                let is_late_for_target = past.desired_present_time != 0
+                    && past.actual_present_time > past.desired_present_time + refresh / 2;
+                is_late_after_last || is_late_for_target
+            }
+            None => false,
+        };
+        self.missed_vsyncs += is_missed_vsync as u64;
+        self.last_actual_present_time = Some(past.actual_present_time);
+        let timing = PresentTiming {
+            present_id: past.present_id,
+            desired_present_time: past.desired_present_time,
+            actual_present_time: past.actual_present_time,
+            earliest_present_time: past.earliest_present_time,
+            present_margin: past.present_margin,
+            is_missed_vsync,
+        };
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(timing);
+        timing
+    }
+
+    ///
+    /// Forgets the last present, the next swapchain starts presenting from scratch.
+    ///
+    pub fn restart(&mut self) {
+        self.refresh_duration = None;
+        self.last_actual_present_time = None;
+        self.target_present_time = None;
+    }
+}
+
+///
+/// Refresh cycle of the display the swapchain presents to, in nanoseconds.
+///
+pub fn query_refresh_duration(
+    fns: &vk::GoogleDisplayTimingFn,
+    device: &ash::Device,
+    swapchain: vk::SwapchainKHR,
+) -> Option<u64> {
+    let mut properties = vk::RefreshCycleDurationGOOGLE::default();
+    let result = unsafe {
+        (fns.get_refresh_cycle_duration_google)(device.handle(), swapchain, &mut properties)
+    };
+    (result == vk::Result::SUCCESS).then_some(properties.refresh_duration)
+}
+
+///
+/// Timings the display reported since the last call, oldest first.
+///
+pub fn query_past_timings(
+    fns: &vk::GoogleDisplayTimingFn,
+    device: &ash::Device,
+    swapchain: vk::SwapchainKHR,
+) -> Vec<vk::PastPresentationTimingGOOGLE> {
+    let get = fns.get_past_presentation_timing_google;
+    let mut count = 0;
+    let result = unsafe { get(device.handle(), swapchain, &mut count, std::ptr::null_mut()) };
+    if result != vk::Result::SUCCESS || count == 0 {
+        return Vec::new();
+    }
+    let mut timings = vec![vk::PastPresentationTimingGOOGLE::default(); count as usize];
+    let result = unsafe { get(device.handle(), swapchain, &mut count, timings.as_mut_ptr()) };
+    match result {
+        // Incomplete still fills in count of them, the rest come next time
+        vk::Result::SUCCESS | vk::Result::INCOMPLETE => {
+            timings.truncate(count as usize);
+            timings
+        }
+        _ => Vec::new(),
+    }
+}
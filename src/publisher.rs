@@ -0,0 +1,232 @@
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Arc,
+    },
+};
+#[cfg(not(loom))]
+use std::sync::{
+    atomic::{AtomicPtr, Ordering},
+    Arc,
+};
+
+use crate::shader_resource::ResourceKind;
+
+/*
+ * Latest-value publication of shader resources, a triple buffer generalized to any
+ * number of producers. Every buffer is owned by exactly one party at any time: a
+ * publisher (its back buffer), the slot (the latest published value) or the consumer
+ * (its front buffer). Ownership only changes hands through atomic swaps of the slot
+ * pointer, so nobody ever touches a buffer someone else owns, torn reads can't happen
+ * and neither producers nor the consumer ever wait on each other.
+ */
+
+// Low bit of the slot pointer, set while it holds a value the consumer hasn't taken yet
+const FRESH: usize = 1;
+
+/*
+ * Same interface as the loom cell, so loom sees every access to the buffer contents
+ * when the model in tests/publisher_loom.rs runs.
+ */
+#[cfg(not(loom))]
+struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn new(value: T) -> Self {
+        Self(std::cell::UnsafeCell::new(value))
+    }
+
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+struct Contents {
+    len: usize,
+    data: Box<[u8]>,
+}
+
+struct Buffer {
+    contents: UnsafeCell<Contents>,
+}
+
+impl Buffer {
+    fn alloc(max_size: usize) -> *mut Buffer {
+        Box::into_raw(Box::new(Buffer {
+            contents: UnsafeCell::new(Contents {
+                len: 0,
+                data: vec![0u8; max_size].into_boxed_slice(),
+            }),
+        }))
+    }
+
+    /*
+     * SAFETY: Caller must own the buffer, ie, it can't be reachable from anywhere else.
+     */
+    unsafe fn free(ptr: *mut Buffer) {
+        drop(Box::from_raw(untagged(ptr)));
+    }
+}
+
+fn tagged(ptr: *mut Buffer) -> *mut Buffer {
+    ptr.map_addr(|e| e | FRESH)
+}
+
+fn untagged(ptr: *mut Buffer) -> *mut Buffer {
+    ptr.map_addr(|e| e & !FRESH)
+}
+
+fn is_fresh(ptr: *mut Buffer) -> bool {
+    ptr.addr() & FRESH != 0
+}
+
+struct PublicationSlot {
+    kind: ResourceKind,
+    max_size: usize,
+    latest: AtomicPtr<Buffer>,
+}
+
+impl Drop for PublicationSlot {
+    fn drop(&mut self) {
+        #[cfg(loom)]
+        let latest = self.latest.with_mut(|e| *e);
+        #[cfg(not(loom))]
+        let latest = *self.latest.get_mut();
+        // SAFETY: Last reference to the slot, nobody else can reach the latest buffer.
+        unsafe { Buffer::free(latest) }
+    }
+}
+
+///
+/// Producer side of a publication slot. Clone it to hand one to each producing thread,
+/// every clone owns its own back buffer.
+///
+pub struct ResourcePublisher {
+    slot: Arc<PublicationSlot>,
+    back: *mut Buffer,
+}
+
+/*
+ * SAFETY: The back buffer is exclusively owned by the publisher, the shared slot is
+ * only accessed atomically.
+ */
+unsafe impl Send for ResourcePublisher {}
+
+impl ResourcePublisher {
+    pub fn kind(&self) -> ResourceKind {
+        self.slot.kind
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.slot.max_size
+    }
+
+    ///
+    /// Publishes a new value for the resource kind, replacing any previous value the
+    /// renderer didn't take yet. Never blocks.
+    ///
+    pub fn publish(&mut self, bytes: &[u8]) {
+        assert!(
+            bytes.len() <= self.slot.max_size,
+            "resource {} of {} bytes doesn't fit in its slot of {} bytes!",
+            self.slot.kind,
+            bytes.len(),
+            self.slot.max_size
+        );
+        // SAFETY: The back buffer is ours until it gets swapped into the slot.
+        let back = unsafe { &*self.back };
+        back.contents.with_mut(|e| {
+            let contents = unsafe { &mut *e };
+            contents.data[..bytes.len()].copy_from_slice(bytes);
+            contents.len = bytes.len();
+        });
+        let prev = self.slot.latest.swap(tagged(self.back), Ordering::AcqRel);
+        // Whatever was published before is ours now, consumed or not
+        self.back = untagged(prev);
+    }
+}
+
+impl Clone for ResourcePublisher {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+            back: Buffer::alloc(self.slot.max_size),
+        }
+    }
+}
+
+impl Drop for ResourcePublisher {
+    fn drop(&mut self) {
+        // SAFETY: The back buffer is exclusively owned by this publisher.
+        unsafe { Buffer::free(self.back) }
+    }
+}
+
+///
+/// Consumer side of a publication slot, there is only one per slot and it's kept by
+/// the renderer.
+///
+pub struct ResourceConsumer {
+    slot: Arc<PublicationSlot>,
+    front: *mut Buffer,
+}
+
+/*
+ * SAFETY: The front buffer is exclusively owned by the consumer, the shared slot is
+ * only accessed atomically.
+ */
+unsafe impl Send for ResourceConsumer {}
+
+impl ResourceConsumer {
+    pub fn new(kind: ResourceKind, max_size: usize) -> Self {
+        Self {
+            slot: Arc::new(PublicationSlot {
+                kind,
+                max_size,
+                latest: AtomicPtr::new(Buffer::alloc(max_size)),
+            }),
+            front: Buffer::alloc(max_size),
+        }
+    }
+
+    pub fn publisher(&self) -> ResourcePublisher {
+        ResourcePublisher {
+            slot: self.slot.clone(),
+            back: Buffer::alloc(self.slot.max_size),
+        }
+    }
+
+    ///
+    /// Takes the most recently published value, if anything was published since the
+    /// last time this was called.
+    ///
+    pub fn take_latest(&mut self) -> Option<&[u8]> {
+        /*
+         * Only the consumer clears the fresh bit, so once it's seen set it stays set
+         * until the swap below, no matter how many publishes happen in between.
+         */
+        if !is_fresh(self.slot.latest.load(Ordering::Acquire)) {
+            return None;
+        }
+        let prev = self.slot.latest.swap(self.front, Ordering::AcqRel);
+        self.front = untagged(prev);
+        // SAFETY: The front buffer is ours until the next swap.
+        let front = unsafe { &*self.front };
+        let contents = front.contents.with(|e| unsafe { &*e });
+        Some(&contents.data[..contents.len])
+    }
+}
+
+impl Drop for ResourceConsumer {
+    fn drop(&mut self) {
+        // SAFETY: The front buffer is exclusively owned by the consumer.
+        unsafe { Buffer::free(self.front) }
+    }
+}
@@ -0,0 +1,134 @@
+#[derive(Copy, Clone, Debug)]
+struct Range {
+    start: u64,
+    end: u64,
+}
+
+impl Range {
+    fn size(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+///
+/// First fit allocator over a linear span of memory. It only does the bookkeeping of
+/// the free ranges, what the offsets point into is up to the user.
+///
+#[derive(Clone, Debug)]
+pub struct RangeAllocator {
+    size: u64,
+    // Free ranges, sorted by start and never adjacent to each other
+    ranges: Vec<Range>,
+}
+
+impl RangeAllocator {
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            ranges: vec![Range {
+                start: 0,
+                end: size,
+            }],
+        }
+    }
+
+    ///
+    /// Returns the offset of a free range of the given size, alignment has to be a
+    /// power of two.
+    ///
+    pub fn alloc(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        for i in 0..self.ranges.len() {
+            let range = self.ranges[i];
+            let start = align_up(range.start, alignment);
+            let end = start + size;
+            if end > range.end {
+                continue;
+            }
+            match (start == range.start, end == range.end) {
+                // Took the entire range
+                (true, true) => {
+                    self.ranges.remove(i);
+                }
+                // Took the front of the range
+                (true, false) => self.ranges[i].start = end,
+                // Took the back of the range, the alignment gap stays free
+                (false, true) => self.ranges[i].end = start,
+                // Took the middle of the range, split it around
+                (false, false) => {
+                    self.ranges[i].end = start;
+                    self.ranges.insert(
+                        i + 1,
+                        Range {
+                            start: end,
+                            end: range.end,
+                        },
+                    );
+                }
+            }
+            return Some(start);
+        }
+        None
+    }
+
+    pub fn free(&mut self, offset: u64, size: u64) {
+        let end = offset + size;
+        // Index of the first free range after the one being freed
+        let i = self.ranges.partition_point(|e| e.start < offset);
+        let is_prev_adjacent = i > 0 && self.ranges[i - 1].end == offset;
+        let is_next_adjacent = i < self.ranges.len() && self.ranges[i].start == end;
+        match (is_prev_adjacent, is_next_adjacent) {
+            (true, true) => {
+                //    . <- join both
+                // |f|f|f|
+                self.ranges[i - 1].end = self.ranges[i].end;
+                self.ranges.remove(i);
+            }
+            (true, false) => {
+                //    . <- extend forwards
+                // |f|f|o|
+                self.ranges[i - 1].end = end;
+            }
+            (false, true) => {
+                //    . <- extend backwards
+                // |o|f|f|
+                self.ranges[i].start = offset;
+            }
+            (false, false) => {
+                //    . <- insert
+                // |o|f|o|
+                self.ranges.insert(i, Range { start: offset, end });
+            }
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn available(&self) -> u64 {
+        self.ranges.iter().map(|r| r.size()).sum()
+    }
+
+    pub fn largest_free(&self) -> u64 {
+        self.ranges.iter().map(|r| r.size()).max().unwrap_or(0)
+    }
+
+    pub fn free_range_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    ///
+    /// Offset and size of every free range, by offset.
+    ///
+    pub fn free_ranges(&self) -> Vec<[u64; 2]> {
+        self.ranges.iter().map(|r| [r.start, r.size()]).collect()
+    }
+
+    pub fn is_unused(&self) -> bool {
+        self.available() == self.size
+    }
+}
+
+fn align_up(v: u64, alignment: u64) -> u64 {
+    (v + (alignment - 1)) & !(alignment - 1)
+}
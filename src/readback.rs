@@ -0,0 +1,513 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ash::vk;
+
+use crate::{
+    buffer::{BufferKind, DeviceAllocator, DeviceSlice},
+    context::VulkanContext,
+    pipeline::attachment::Attachment,
+    renderer::{Renderer, WaitTimeout},
+    retry::{RetryAfter, RetryError},
+};
+
+///
+/// Returned when the readback buffer has no room left for the copy. Resolve or drop
+/// outstanding requests and try again.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadbackBusy {
+    pub size: u64,
+    pub available: u64,
+}
+
+impl std::fmt::Display for ReadbackBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no room for a readback of {} bytes, {} available",
+            self.size, self.available
+        )
+    }
+}
+
+impl std::error::Error for ReadbackBusy {}
+
+impl From<ReadbackBusy> for RetryError {
+    fn from(_: ReadbackBusy) -> Self {
+        // Requests resolved or dropped by then give their room back when it starts
+        RetryError::Transient(RetryAfter::NextFrame)
+    }
+}
+
+enum PendingCopy {
+    Buffer {
+        src: vk::Buffer,
+        src_offset: u64,
+        // Rounded up to the alignment of the readback buffer
+        dst: DeviceSlice,
+        size: u64,
+    },
+    // Tightly packed rows of the mip of the layer, left in the layout it was found in
+    Image {
+        src: ImageSource,
+        dst: DeviceSlice,
+    },
+    // Same for the swapchain image of the frame it gets recorded in, rows as wide as extent
+    Presented {
+        extent: vk::Extent2D,
+        dst: DeviceSlice,
+    },
+}
+
+///
+/// Mip of a layer of an image read back, and the layout it's in when the copy gets
+/// recorded.
+///
+#[derive(Clone, Copy)]
+pub struct ImageSource {
+    pub image: vk::Image,
+    pub layout: vk::ImageLayout,
+    pub aspect: vk::ImageAspectFlags,
+    pub mip: u32,
+    pub layer: u32,
+    // Of the mip
+    pub extent: vk::Extent2D,
+}
+
+// Slices of requests dropped unresolved, with the frame timeline value they wait for
+type Abandoned = Arc<Mutex<Vec<(u64, DeviceSlice)>>>;
+
+///
+/// Host visible buffer GPU data gets copied into for reading it back, see
+/// Renderer::read_buffer. Copies get recorded after the last stage of the next frame
+/// submitted.
+///
+pub struct Readbacks {
+    allocator: DeviceAllocator,
+    pending: Vec<PendingCopy>,
+    abandoned: Abandoned,
+}
+
+impl Readbacks {
+    pub fn new(ctx: &VulkanContext, size: u64) -> Self {
+        Self {
+            allocator: DeviceAllocator::new(ctx, size, BufferKind::Readback),
+            pending: Vec::new(),
+            abandoned: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    ///
+    /// Reserves room for the range of the slice, copied once the frame that signals
+    /// wait_value gets recorded. Panics if the range doesn't lie within the slice.
+    ///
+    pub fn request(
+        &mut self,
+        slice: &DeviceSlice,
+        range: Range<u64>,
+        wait_value: u64,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        if range.is_empty() || range.end > slice.size {
+            panic!(
+                "readback range {:?} isn't within the slice of {} bytes!",
+                range, slice.size
+            );
+        }
+        let size = range.end - range.start;
+        let dst = self.alloc(size)?;
+        self.pending.push(PendingCopy::Buffer {
+            src: slice.buffer,
+            src_offset: slice.offset + range.start,
+            dst,
+            size,
+        });
+        Ok(self.request_of(dst, size, wait_value))
+    }
+
+    ///
+    /// Same as request for a mip of a layer of an image, size bytes of tightly
+    /// packed rows of the extent of the mip.
+    ///
+    pub fn request_image(
+        &mut self,
+        src: ImageSource,
+        size: u64,
+        wait_value: u64,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        let dst = self.alloc(size)?;
+        self.pending.push(PendingCopy::Image { src, dst });
+        Ok(self.request_of(dst, size, wait_value))
+    }
+
+    ///
+    /// Same as request_image for the swapchain image presented by the frame, whichever
+    /// it turns out to be. Images of a swapchain rebuilt in the meantime get copied as
+    /// far as they overlap the extent.
+    ///
+    pub fn request_presented(
+        &mut self,
+        extent: vk::Extent2D,
+        size: u64,
+        wait_value: u64,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        let dst = self.alloc(size)?;
+        self.pending.push(PendingCopy::Presented { extent, dst });
+        Ok(self.request_of(dst, size, wait_value))
+    }
+
+    fn alloc(&self, size: u64) -> Result<DeviceSlice, ReadbackBusy> {
+        self.allocator.alloc(size).ok_or_else(|| ReadbackBusy {
+            size,
+            available: self.allocator.available(),
+        })
+    }
+
+    fn request_of(&self, dst: DeviceSlice, size: u64, wait_value: u64) -> ReadbackRequest {
+        ReadbackRequest {
+            slice: Some(dst),
+            size,
+            wait_value,
+            abandoned: self.abandoned.clone(),
+        }
+    }
+
+    ///
+    /// Records the pending copies, after whatever the command buffer wrote so far. The
+    /// presented image has to be ready for presenting already.
+    ///
+    pub fn record(&mut self, ctx: &VulkanContext, cmd: vk::CommandBuffer, presented: &Attachment) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let barrier = |src_stage, src_access, dst_stage, dst_access| {
+            let barriers = [vk::MemoryBarrier2::builder()
+                .src_stage_mask(src_stage)
+                .src_access_mask(src_access)
+                .dst_stage_mask(dst_stage)
+                .dst_access_mask(dst_access)
+                .build()];
+            let dep_info = vk::DependencyInfo::builder()
+                .memory_barriers(&barriers)
+                .build();
+            unsafe { ctx.device.cmd_pipeline_barrier2(cmd, &dep_info) };
+        };
+        // Stages write through buffer addresses, which count as any shader write
+        barrier(
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::MEMORY_WRITE,
+            vk::PipelineStageFlags2::COPY,
+            vk::AccessFlags2::TRANSFER_READ,
+        );
+        for copy in self.pending.drain(..) {
+            match copy {
+                PendingCopy::Buffer {
+                    src,
+                    src_offset,
+                    dst,
+                    size,
+                } => {
+                    let region = vk::BufferCopy {
+                        src_offset,
+                        dst_offset: dst.offset,
+                        size,
+                    };
+                    unsafe { ctx.device.cmd_copy_buffer(cmd, src, dst.buffer, &[region]) };
+                }
+                PendingCopy::Image { src, dst } => {
+                    let extent = src.extent;
+                    let region =
+                        Self::region_of(src.aspect, src.mip, src.layer, extent, extent.width, &dst);
+                    Self::record_image_copy(ctx, cmd, src.image, src.layout, region, dst.buffer)
+                }
+                PendingCopy::Presented { extent, dst } => {
+                    let overlap = vk::Extent2D {
+                        width: extent.width.min(presented.extent.width),
+                        height: extent.height.min(presented.extent.height),
+                    };
+                    let aspect = vk::ImageAspectFlags::COLOR;
+                    let region = Self::region_of(aspect, 0, 0, overlap, extent.width, &dst);
+                    let layout = presented.layout_for(vk::ImageLayout::PRESENT_SRC_KHR);
+                    Self::record_image_copy(ctx, cmd, presented.image, layout, region, dst.buffer)
+                }
+            }
+        }
+        barrier(
+            vk::PipelineStageFlags2::COPY,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            vk::PipelineStageFlags2::HOST,
+            vk::AccessFlags2::HOST_READ,
+        );
+    }
+
+    /*
+     * Into the transfer layout and back, the barrier before the copies made the writes
+     * available already. Shared presentable images get copied in their layout.
+     */
+    fn record_image_copy(
+        ctx: &VulkanContext,
+        cmd: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+        region: vk::BufferImageCopy,
+        dst: vk::Buffer,
+    ) {
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: region.image_subresource.aspect_mask,
+            base_mip_level: region.image_subresource.mip_level,
+            level_count: 1,
+            base_array_layer: region.image_subresource.base_array_layer,
+            layer_count: 1,
+        };
+        let transition = |from, to, src_access, dst_access| {
+            let barriers = [vk::ImageMemoryBarrier2::builder()
+                .image(image)
+                .subresource_range(range)
+                .old_layout(from)
+                .new_layout(to)
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .src_access_mask(src_access)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(dst_access)
+                .build()];
+            let dep_info = vk::DependencyInfo::builder()
+                .image_memory_barriers(&barriers)
+                .build();
+            unsafe { ctx.device.cmd_pipeline_barrier2(cmd, &dep_info) };
+        };
+        let copy_layout = if layout == vk::ImageLayout::SHARED_PRESENT_KHR {
+            layout
+        } else {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        };
+        transition(
+            layout,
+            copy_layout,
+            vk::AccessFlags2::MEMORY_WRITE,
+            vk::AccessFlags2::TRANSFER_READ,
+        );
+        unsafe {
+            ctx.device
+                .cmd_copy_image_to_buffer(cmd, image, copy_layout, dst, &[region])
+        };
+        transition(
+            copy_layout,
+            layout,
+            vk::AccessFlags2::TRANSFER_READ,
+            vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+        );
+    }
+
+    /*
+     * Mip of the layer into rows row_length texels apart, which can be more than the
+     * width copied.
+     */
+    fn region_of(
+        aspect: vk::ImageAspectFlags,
+        mip: u32,
+        layer: u32,
+        extent: vk::Extent2D,
+        row_length: u32,
+        dst: &DeviceSlice,
+    ) -> vk::BufferImageCopy {
+        vk::BufferImageCopy {
+            buffer_offset: dst.offset,
+            buffer_row_length: row_length,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: aspect,
+                mip_level: mip,
+                base_array_layer: layer,
+                layer_count: 1,
+            },
+            image_extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Frees the room of dropped requests the GPU is done with.
+    ///
+    pub fn release_abandoned(&self, completed_frames: u64) {
+        let mut abandoned = self.abandoned.lock().unwrap();
+        abandoned.retain(|(wait_value, slice)| {
+            if *wait_value > completed_frames {
+                return true;
+            }
+            self.allocator.free(*slice);
+            false
+        });
+    }
+
+    pub fn allocator(&self) -> &DeviceAllocator {
+        &self.allocator
+    }
+
+    // Copies requested but not recorded yet
+    pub fn pending_copies(&self) -> u32 {
+        self.pending.len() as u32
+    }
+
+    pub fn is_presented_pending(&self) -> bool {
+        self.pending
+            .iter()
+            .any(|e| matches!(e, PendingCopy::Presented { .. }))
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.allocator.destroy(device);
+    }
+
+    fn free(&self, slice: DeviceSlice) {
+        self.allocator.free(slice);
+    }
+}
+
+///
+/// Bytes copied back from the GPU, once the frame that copies them is done. Dropping it
+/// unresolved gives its room back after that frame.
+///
+pub struct ReadbackRequest {
+    // None once resolved
+    slice: Option<DeviceSlice>,
+    size: u64,
+    wait_value: u64,
+    abandoned: Abandoned,
+}
+
+impl ReadbackRequest {
+    ///
+    /// Frame the copy gets recorded in.
+    ///
+    pub fn frame(&self) -> u64 {
+        self.wait_value - 1
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.slice.is_none()
+    }
+
+    ///
+    /// The bytes if the GPU is done with the frame, without blocking. Panics if the
+    /// request was already resolved.
+    ///
+    pub fn try_resolve(&mut self, renderer: &Renderer) -> Option<Vec<u8>> {
+        if renderer.completed_frames() < self.wait_value {
+            return None;
+        }
+        Some(self.take(renderer))
+    }
+
+    ///
+    /// Same as try_resolve, waiting up to the timeout for the GPU to finish the frame.
+    /// A frame that never gets submitted, like while hibernated, runs into the timeout.
+    ///
+    pub fn resolve_wait(
+        &mut self,
+        renderer: &Renderer,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, WaitTimeout> {
+        renderer.wait_frame_value(self.wait_value, timeout)?;
+        Ok(self.take(renderer))
+    }
+
+    fn take(&mut self, renderer: &Renderer) -> Vec<u8> {
+        let slice = self.slice.take().expect("readback was already resolved!");
+        let readbacks = renderer
+            .readbacks()
+            .expect("readback doesn't belong to the renderer!");
+        let mut bytes = slice.read();
+        bytes.truncate(self.size as usize);
+        readbacks.free(slice);
+        bytes
+    }
+}
+
+impl Drop for ReadbackRequest {
+    fn drop(&mut self) {
+        if let Some(slice) = self.slice.take() {
+            // The copy may still be pending or running
+            if let Ok(mut abandoned) = self.abandoned.lock() {
+                abandoned.push((self.wait_value, slice));
+            }
+        }
+    }
+}
+
+///
+/// Makes the request of a queued readback, see Renderer::queue_readback.
+///
+pub type ReadFn = dyn FnMut(&mut Renderer) -> Result<ReadbackRequest, ReadbackBusy> + Send;
+
+// Filled once the readback got room or was given up on
+pub(crate) type QueuedSlot = Arc<Mutex<Option<Result<ReadbackRequest, RetryError>>>>;
+
+///
+/// Readback the renderer makes once the readback buffer has room, see
+/// Renderer::queue_readback.
+///
+pub struct QueuedReadback {
+    id: u64,
+    slot: QueuedSlot,
+    is_taken: bool,
+}
+
+impl QueuedReadback {
+    ///
+    /// Id of the readback in the RetriedWork of the events about it.
+    ///
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    ///
+    /// The request once it got room, RetryError::Transient while it waits for some and
+    /// RetryError::Exhausted once given up on. Panics if the request was taken already.
+    ///
+    pub fn poll(&mut self) -> Result<ReadbackRequest, RetryError> {
+        if self.is_taken {
+            panic!("request of queued readback {} was taken already!", self.id);
+        }
+        let placed = self.slot.lock().unwrap().take();
+        match placed {
+            Some(e) => {
+                self.is_taken = true;
+                e
+            }
+            None => Err(RetryError::Transient(RetryAfter::NextFrame)),
+        }
+    }
+}
+
+pub(crate) struct QueuedRead {
+    pub id: u64,
+    pub read: Box<ReadFn>,
+    pub slot: QueuedSlot,
+}
+
+impl QueuedRead {
+    pub fn new(id: u64, read: Box<ReadFn>) -> (Self, QueuedReadback) {
+        let slot: QueuedSlot = Arc::new(Mutex::new(None));
+        let handle = QueuedReadback {
+            id,
+            slot: slot.clone(),
+            is_taken: false,
+        };
+        (Self { id, read, slot }, handle)
+    }
+
+    // Dropped by the host, the request would go unread
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.slot) == 1
+    }
+}
@@ -0,0 +1,708 @@
+use std::collections::HashMap;
+
+use crate::spirv_patch::{LayoutProperty, LayoutRule, RuleMismatch};
+
+/*
+ * Minimal SPIR-V reflection, only what's needed to check the host side struct layouts
+ * against what the shaders declare and to list the descriptor bindings of a stage.
+ */
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+const SPIRV_HEADER_LEN: usize = 5;
+
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ROW_MAJOR: u32 = 4;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Size of a PhysicalStorageBuffer pointer, ie, a buffer_reference
+const POINTER_SIZE: u32 = 8;
+
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub bindings: Vec<DescriptorBinding>,
+    pub blocks: Vec<BlockLayout>,
+    pub specialization_ids: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub count: u32,
+    pub kind: BindingKind,
+    // Images sampled as arrays of layers, like sampler2DArray
+    pub is_arrayed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+pub enum BindingKind {
+    Sampler,
+    Image,
+    CombinedImageSampler,
+    Buffer,
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockLayout {
+    pub name: String,
+    pub size: u32,
+    pub members: Vec<BlockMember>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMember {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Clone)]
+enum Type {
+    Scalar(u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Array(u32, u32),
+    RuntimeArray(u32),
+    Struct(Vec<u32>),
+    Pointer(u32),
+    // Whether it's arrayed
+    Image(bool),
+    Sampler,
+    // Image type it samples
+    SampledImage(u32),
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    // Variable id to pointer type id
+    variables: Vec<(u32, u32)>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+impl ShaderReflection {
+    pub fn of(words: &[u32]) -> Self {
+        if words.len() < SPIRV_HEADER_LEN || words[0] != SPIRV_MAGIC {
+            log::warn!("can't reflect shader, not a SPIR-V module!");
+            return Self::default();
+        }
+        let module = Module::parse(&words[SPIRV_HEADER_LEN..]);
+        Self {
+            bindings: module.bindings(),
+            blocks: module.blocks(),
+            specialization_ids: module.specialization_ids(),
+        }
+    }
+
+    pub fn block(&self, name: &str) -> Option<&BlockLayout> {
+        self.blocks.iter().find(|e| e.name == name)
+    }
+
+    ///
+    /// True if nothing got reflected, ie, the shader couldn't be parsed.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty() && self.blocks.is_empty() && self.specialization_ids.is_empty()
+    }
+
+    ///
+    /// Merges the reflection data of another shader of the same program. Blocks and bindings
+    /// shared between both are kept only once, blocks declared with different members in
+    /// each, like the push constants, end up with the members of both.
+    ///
+    pub fn merge(&mut self, other: &ShaderReflection) {
+        for binding in &other.bindings {
+            if !self
+                .bindings
+                .iter()
+                .any(|e| e.set == binding.set && e.binding == binding.binding)
+            {
+                self.bindings.push(binding.clone());
+            }
+        }
+        for block in &other.blocks {
+            match self.blocks.iter_mut().find(|e| e.name == block.name) {
+                Some(existing) => {
+                    for member in &block.members {
+                        if !existing.members.iter().any(|e| e.name == member.name) {
+                            existing.members.push(member.clone());
+                        }
+                    }
+                    existing.members.sort_by_key(|e| e.offset);
+                    existing.size = existing.size.max(block.size);
+                }
+                None => self.blocks.push(block.clone()),
+            }
+        }
+        self.specialization_ids
+            .extend_from_slice(&other.specialization_ids);
+        self.bindings.sort_by_key(|e| (e.set, e.binding));
+        self.blocks.sort_by(|a, b| a.name.cmp(&b.name));
+        self.specialization_ids.sort();
+        self.specialization_ids.dedup();
+    }
+}
+
+///
+/// Offsets and strides the blocks of the shader declare other than the rule lays them
+/// out, see SpirvModule::check_block_layouts.
+///
+pub(crate) fn rule_mismatches(words: &[u32], rule: LayoutRule) -> Vec<RuleMismatch> {
+    if words.len() < SPIRV_HEADER_LEN || words[0] != SPIRV_MAGIC {
+        return Vec::new();
+    }
+    let module = Module::parse(&words[SPIRV_HEADER_LEN..]);
+    let mut blocks: Vec<_> = module
+        .types
+        .iter()
+        .filter_map(|(&id, t)| match t {
+            Type::Struct(members)
+                if module.decorations.contains_key(&(id, DECORATION_BLOCK))
+                    || module
+                        .decorations
+                        .contains_key(&(id, DECORATION_BUFFER_BLOCK)) =>
+            {
+                Some((id, members))
+            }
+            _ => None,
+        })
+        .collect();
+    blocks.sort_by_key(|e| e.0);
+    let mut mismatches = Vec::new();
+    for (id, members) in blocks {
+        let block = module.names.get(&id).cloned().unwrap_or_default();
+        module.rule_mismatches_of(id, members, rule, (&block, ""), &mut mismatches);
+    }
+    mismatches
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Self {
+        let mut module = Self::default();
+        let mut i = 0;
+        while i < words.len() {
+            let count = (words[i] >> 16) as usize;
+            let opcode = words[i] & 0xFFFF;
+            if count == 0 || i + count > words.len() {
+                log::warn!("malformed SPIR-V instruction at word {}", i);
+                break;
+            }
+            let ops = &words[i + 1..i + count];
+            module.handle(opcode, ops);
+            i += count;
+        }
+        module
+    }
+
+    fn handle(&mut self, opcode: u32, ops: &[u32]) {
+        match opcode {
+            OP_NAME if ops.len() > 1 => {
+                self.names.insert(ops[0], string_of(&ops[1..]));
+            }
+            OP_MEMBER_NAME if ops.len() > 2 => {
+                self.member_names
+                    .insert((ops[0], ops[1]), string_of(&ops[2..]));
+            }
+            OP_TYPE_BOOL if !ops.is_empty() => {
+                self.types.insert(ops[0], Type::Scalar(4));
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT if ops.len() > 1 => {
+                self.types.insert(ops[0], Type::Scalar(ops[1] / 8));
+            }
+            OP_TYPE_VECTOR if ops.len() > 2 => {
+                self.types.insert(ops[0], Type::Vector(ops[1], ops[2]));
+            }
+            OP_TYPE_MATRIX if ops.len() > 2 => {
+                self.types.insert(ops[0], Type::Matrix(ops[1], ops[2]));
+            }
+            OP_TYPE_IMAGE if ops.len() > 4 => {
+                self.types.insert(ops[0], Type::Image(ops[4] != 0));
+            }
+            OP_TYPE_SAMPLER if !ops.is_empty() => {
+                self.types.insert(ops[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE if ops.len() > 1 => {
+                self.types.insert(ops[0], Type::SampledImage(ops[1]));
+            }
+            OP_TYPE_ARRAY if ops.len() > 2 => {
+                self.types.insert(ops[0], Type::Array(ops[1], ops[2]));
+            }
+            OP_TYPE_RUNTIME_ARRAY if ops.len() > 1 => {
+                self.types.insert(ops[0], Type::RuntimeArray(ops[1]));
+            }
+            OP_TYPE_STRUCT if !ops.is_empty() => {
+                self.types.insert(ops[0], Type::Struct(ops[1..].to_vec()));
+            }
+            OP_TYPE_POINTER if ops.len() > 2 => {
+                self.types.insert(ops[0], Type::Pointer(ops[2]));
+            }
+            OP_CONSTANT if ops.len() > 2 => {
+                self.constants.insert(ops[1], ops[2]);
+            }
+            OP_VARIABLE if ops.len() > 1 => {
+                self.variables.push((ops[1], ops[0]));
+            }
+            OP_DECORATE if ops.len() > 1 => {
+                let value = ops.get(2).copied().unwrap_or(0);
+                self.decorations.insert((ops[0], ops[1]), value);
+            }
+            OP_MEMBER_DECORATE if ops.len() > 2 => {
+                let value = ops.get(3).copied().unwrap_or(0);
+                self.member_decorations
+                    .insert((ops[0], ops[1], ops[2]), value);
+            }
+            _ => {}
+        }
+    }
+
+    fn bindings(&self) -> Vec<DescriptorBinding> {
+        let mut bindings: Vec<_> = self
+            .variables
+            .iter()
+            .filter_map(|&(id, pointer_type)| {
+                let set = *self.decorations.get(&(id, DECORATION_DESCRIPTOR_SET))?;
+                let binding = *self.decorations.get(&(id, DECORATION_BINDING))?;
+                let pointee = match self.types.get(&pointer_type) {
+                    Some(Type::Pointer(t)) => *t,
+                    _ => return None,
+                };
+                let (kind, count) = self.binding_kind_of(pointee);
+                Some(DescriptorBinding {
+                    name: self.names.get(&id).cloned().unwrap_or_default(),
+                    set,
+                    binding,
+                    count,
+                    kind,
+                    is_arrayed: self.is_arrayed(pointee),
+                })
+            })
+            .collect();
+        bindings.sort_by_key(|e| (e.set, e.binding));
+        bindings
+    }
+
+    fn specialization_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<_> = self
+            .decorations
+            .iter()
+            .filter(|(key, _)| key.1 == DECORATION_SPEC_ID)
+            .map(|(_, &id)| id)
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    fn binding_kind_of(&self, type_id: u32) -> (BindingKind, u32) {
+        match self.types.get(&type_id) {
+            Some(Type::Sampler) => (BindingKind::Sampler, 1),
+            Some(Type::Image(_)) => (BindingKind::Image, 1),
+            Some(Type::SampledImage(_)) => (BindingKind::CombinedImageSampler, 1),
+            Some(Type::Struct(_)) => (BindingKind::Buffer, 1),
+            Some(Type::Array(elem, len)) => {
+                let (kind, _) = self.binding_kind_of(*elem);
+                (kind, self.constants.get(len).copied().unwrap_or(1))
+            }
+            // Unbounded, the actual count depends on the descriptor set layout
+            Some(Type::RuntimeArray(elem)) => (self.binding_kind_of(*elem).0, 0),
+            _ => (BindingKind::Unknown, 0),
+        }
+    }
+
+    fn is_arrayed(&self, type_id: u32) -> bool {
+        match self.types.get(&type_id) {
+            Some(Type::Image(is_arrayed)) => *is_arrayed,
+            Some(Type::SampledImage(e) | Type::Array(e, _) | Type::RuntimeArray(e)) => {
+                self.is_arrayed(*e)
+            }
+            _ => false,
+        }
+    }
+
+    fn blocks(&self) -> Vec<BlockLayout> {
+        let mut blocks: Vec<_> = self
+            .types
+            .iter()
+            .filter_map(|(&id, t)| match t {
+                Type::Struct(members) if self.has_explicit_layout(id, members.len()) => {
+                    Some(self.block_of(id, members))
+                }
+                _ => None,
+            })
+            .filter(|e| !e.name.is_empty())
+            .collect();
+        // Same struct might get emitted more than once with different ids
+        blocks.sort_by(|a, b| a.name.cmp(&b.name));
+        blocks.dedup_by(|a, b| a.name == b.name);
+        blocks
+    }
+
+    fn has_explicit_layout(&self, id: u32, member_count: usize) -> bool {
+        (0..member_count as u32).any(|m| {
+            self.member_decorations
+                .contains_key(&(id, m, DECORATION_OFFSET))
+        })
+    }
+
+    fn block_of(&self, id: u32, members: &[u32]) -> BlockLayout {
+        let members: Vec<_> = members
+            .iter()
+            .enumerate()
+            .map(|(m, &member_type)| {
+                let m = m as u32;
+                let offset = self
+                    .member_decorations
+                    .get(&(id, m, DECORATION_OFFSET))
+                    .copied()
+                    .unwrap_or(0);
+                let size = match (
+                    self.types.get(&member_type),
+                    self.member_decorations
+                        .get(&(id, m, DECORATION_MATRIX_STRIDE)),
+                ) {
+                    (Some(Type::Matrix(_, columns)), Some(stride)) => columns * stride,
+                    _ => self.size_of(member_type),
+                };
+                BlockMember {
+                    name: self.member_names.get(&(id, m)).cloned().unwrap_or_default(),
+                    offset,
+                    size,
+                }
+            })
+            .collect();
+        BlockLayout {
+            name: self.names.get(&id).cloned().unwrap_or_default(),
+            size: members.iter().map(|e| e.offset + e.size).max().unwrap_or(0),
+            members,
+        }
+    }
+
+    fn size_of(&self, type_id: u32) -> u32 {
+        match self.types.get(&type_id) {
+            Some(Type::Scalar(size)) => *size,
+            Some(Type::Vector(component, count)) => self.size_of(*component) * count,
+            Some(Type::Matrix(column, count)) => self.size_of(*column) * count,
+            Some(Type::Array(elem, len)) => {
+                let len = self.constants.get(len).copied().unwrap_or(0);
+                let stride = self
+                    .decorations
+                    .get(&(type_id, DECORATION_ARRAY_STRIDE))
+                    .copied()
+                    .unwrap_or_else(|| self.size_of(*elem));
+                stride * len
+            }
+            Some(Type::Struct(members)) => self.block_of(type_id, members).size,
+            Some(Type::Pointer(_)) => POINTER_SIZE,
+            _ => 0,
+        }
+    }
+
+    /*
+     * Checks the members of the struct against the rule, each one right after the end
+     * of the previous one as declared, so one that's off doesn't throw off the rest.
+     */
+    fn rule_mismatches_of(
+        &self,
+        id: u32,
+        members: &[u32],
+        rule: LayoutRule,
+        (block, path): (&str, &str),
+        mismatches: &mut Vec<RuleMismatch>,
+    ) {
+        let mut end = 0u32;
+        for (m, &member_type) in members.iter().enumerate() {
+            let m = m as u32;
+            let name = self
+                .member_names
+                .get(&(id, m))
+                .cloned()
+                .unwrap_or_else(|| m.to_string());
+            let name = if path.is_empty() {
+                name
+            } else {
+                format!("{}.{}", path, name)
+            };
+            let mut check = |property, declared: Option<u32>, expected| match declared {
+                Some(declared) if declared != expected => mismatches.push(RuleMismatch {
+                    block: block.to_string(),
+                    member: name.clone(),
+                    property,
+                    declared,
+                    expected,
+                    rule,
+                }),
+                _ => {}
+            };
+            let is_row_major = self
+                .member_decorations
+                .contains_key(&(id, m, DECORATION_ROW_MAJOR));
+            let (size, align) = self.rule_layout(member_type, rule, is_row_major);
+            let declared = self.member_decorations.get(&(id, m, DECORATION_OFFSET));
+            let expected = end.next_multiple_of(align);
+            check(LayoutProperty::Offset, declared.copied(), expected);
+            end = declared.copied().unwrap_or(expected) + size;
+
+            // Arrays of arrays or of matrices check the strides down to the innermost
+            let mut t = member_type;
+            while let Some(Type::Array(elem, _) | Type::RuntimeArray(elem)) = self.types.get(&t) {
+                let stride = self.rule_stride(*elem, rule, is_row_major);
+                let declared = self.decorations.get(&(t, DECORATION_ARRAY_STRIDE));
+                check(LayoutProperty::ArrayStride, declared.copied(), stride);
+                t = *elem;
+            }
+            if let Some(Type::Matrix(..)) = self.types.get(&t) {
+                let (vector_size, vector_align) = self.matrix_vector(t, rule, is_row_major);
+                let stride = vector_size.next_multiple_of(rule.aggregate_align(vector_align));
+                let declared = self
+                    .member_decorations
+                    .get(&(id, m, DECORATION_MATRIX_STRIDE));
+                check(LayoutProperty::MatrixStride, declared.copied(), stride);
+            }
+            if let Some(Type::Struct(nested)) = self.types.get(&t) {
+                self.rule_mismatches_of(t, nested, rule, (block, &name), mismatches);
+            }
+        }
+    }
+
+    /*
+     * Size and alignment of the type laid out by the rule. Row major matrices are
+     * arrays of rows instead of columns.
+     */
+    fn rule_layout(&self, type_id: u32, rule: LayoutRule, is_row_major: bool) -> (u32, u32) {
+        match self.types.get(&type_id) {
+            Some(Type::Scalar(size)) => (*size, *size),
+            Some(Type::Vector(component, count)) => {
+                let size = self.size_of(*component);
+                (size * count, rule.vector_align(size, *count))
+            }
+            Some(Type::Matrix(_, _)) => {
+                let (vector_size, vector_align) = self.matrix_vector(type_id, rule, is_row_major);
+                let align = rule.aggregate_align(vector_align);
+                let count = self.matrix_vectors(type_id, is_row_major);
+                (vector_size.next_multiple_of(align) * count, align)
+            }
+            Some(Type::Array(elem, len)) => {
+                let len = self.constants.get(len).copied().unwrap_or(0);
+                let (_, align) = self.rule_layout(*elem, rule, is_row_major);
+                let align = rule.aggregate_align(align);
+                (self.rule_stride(*elem, rule, is_row_major) * len, align)
+            }
+            Some(Type::RuntimeArray(elem)) => {
+                let (_, align) = self.rule_layout(*elem, rule, is_row_major);
+                (0, rule.aggregate_align(align))
+            }
+            Some(Type::Struct(members)) => {
+                let mut end = 0u32;
+                let mut align = 1;
+                for (m, &member_type) in members.iter().enumerate() {
+                    let is_row_major = self.member_decorations.contains_key(&(
+                        type_id,
+                        m as u32,
+                        DECORATION_ROW_MAJOR,
+                    ));
+                    let (size, member_align) = self.rule_layout(member_type, rule, is_row_major);
+                    end = end.next_multiple_of(member_align) + size;
+                    align = align.max(member_align);
+                }
+                let align = rule.aggregate_align(align);
+                (end.next_multiple_of(align), align)
+            }
+            Some(Type::Pointer(_)) => (POINTER_SIZE, POINTER_SIZE),
+            _ => (0, 1),
+        }
+    }
+
+    fn rule_stride(&self, elem: u32, rule: LayoutRule, is_row_major: bool) -> u32 {
+        let (size, align) = self.rule_layout(elem, rule, is_row_major);
+        size.next_multiple_of(rule.aggregate_align(align))
+    }
+
+    /*
+     * Size and alignment of the columns of the matrix, or of its rows if it's row major.
+     */
+    fn matrix_vector(&self, type_id: u32, rule: LayoutRule, is_row_major: bool) -> (u32, u32) {
+        let (column, columns) = match self.types.get(&type_id) {
+            Some(Type::Matrix(column, columns)) => (*column, *columns),
+            _ => return (0, 1),
+        };
+        let (component, rows) = match self.types.get(&column) {
+            Some(Type::Vector(component, rows)) => (*component, *rows),
+            _ => return (0, 1),
+        };
+        let component_size = self.size_of(component);
+        let count = if is_row_major { columns } else { rows };
+        (
+            component_size * count,
+            rule.vector_align(component_size, count),
+        )
+    }
+
+    fn matrix_vectors(&self, type_id: u32, is_row_major: bool) -> u32 {
+        let (column, columns) = match self.types.get(&type_id) {
+            Some(Type::Matrix(column, columns)) => (*column, *columns),
+            _ => return 0,
+        };
+        match self.types.get(&column) {
+            Some(Type::Vector(_, rows)) if is_row_major => *rows,
+            _ => columns,
+        }
+    }
+}
+
+fn string_of(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|e| e.to_le_bytes())
+        .take_while(|e| *e != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[derive(Clone, Debug)]
+pub struct HostMember {
+    pub name: &'static str,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct LayoutMismatch {
+    pub stage: String,
+    pub block: String,
+    pub expected_size: u32,
+    pub provided_size: u32,
+    pub members: Vec<MemberMismatch>,
+}
+
+///
+/// Descriptor binding a shader declares that the pipeline doesn't provide as declared.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BindingMismatch {
+    pub stage: String,
+    pub shader: String,
+    pub set: u32,
+    pub binding: u32,
+    pub name: String,
+    pub problem: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct MemberMismatch {
+    pub index: usize,
+    pub shader_name: String,
+    pub host_name: String,
+    pub expected_offset: u32,
+    pub provided_offset: u32,
+    pub expected_size: u32,
+    pub provided_size: u32,
+}
+
+impl BlockLayout {
+    ///
+    /// Compares this shader declared block against the host side layout of the struct
+    /// that gets written into it. Members are matched by declaration order.
+    ///
+    pub fn compare(
+        &self,
+        stage: &str,
+        host_size: u32,
+        host_members: &[HostMember],
+    ) -> Result<(), LayoutMismatch> {
+        let mut members = Vec::new();
+        for index in 0..self.members.len().max(host_members.len()) {
+            let shader = self.members.get(index);
+            let host = host_members.get(index);
+            let (expected_offset, expected_size) = shader.map_or((0, 0), |e| (e.offset, e.size));
+            let (provided_offset, provided_size) = host.map_or((0, 0), |e| (e.offset, e.size));
+            if expected_offset != provided_offset || expected_size != provided_size {
+                members.push(MemberMismatch {
+                    index,
+                    shader_name: shader.map_or(String::new(), |e| e.name.clone()),
+                    host_name: host.map_or(String::new(), |e| e.name.to_string()),
+                    expected_offset,
+                    provided_offset,
+                    expected_size,
+                    provided_size,
+                });
+            }
+        }
+        if members.is_empty() && self.size == host_size {
+            return Ok(());
+        }
+        Err(LayoutMismatch {
+            stage: stage.to_string(),
+            block: self.name.clone(),
+            expected_size: self.size,
+            provided_size: host_size,
+            members,
+        })
+    }
+}
+
+impl std::fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "stage {} declares {} with {} bytes, host provides {} bytes",
+            self.stage, self.block, self.expected_size, self.provided_size
+        )?;
+        for m in &self.members {
+            write!(
+                f,
+                "; member {} ({}/{}) expected offset {} size {}, got offset {} size {}",
+                m.index,
+                m.shader_name,
+                m.host_name,
+                m.expected_offset,
+                m.expected_size,
+                m.provided_offset,
+                m.provided_size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for BindingMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "shader {} of stage {}, set {} binding {} ({}): {}",
+            self.shader, self.stage, self.set, self.binding, self.name, self.problem
+        )
+    }
+}
+
+impl std::error::Error for BindingMismatch {}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use ash::vk;
+use bitvec::vec::BitVec;
+
+use crate::{
+    buffer::DeviceAllocator,
+    context::VulkanContext,
+    debug::DebugContext,
+    handle::Generations,
+    pipeline::{
+        descriptor::DescriptorBackend,
+        file::Pipeline,
+        sampler::{Sampler, SamplerKey},
+    },
+    renderer::{self, MeshBuffer, Renderer},
+    texture::Texture,
+};
+
+///
+/// Instance, device and queues shared by every renderer made with Renderer::with_core.
+/// Renderers only lock the queues while rendering, so they can live on different threads.
+///
+pub struct RenderCore {
+    pub vulkan_context: VulkanContext,
+    pub queue_family_index: u32,
+    pub async_compute_family: Option<u32>,
+    pub is_validation_layer_enabled: bool,
+    debug_context: Option<DebugContext>,
+    // Queue submissions need external synchronization
+    queue_lock: Mutex<()>,
+    // Renderers get made one at a time, the first one makes the tables and the builtins
+    creation_lock: Mutex<()>,
+    tables: OnceLock<Mutex<CoreTables>>,
+}
+
+impl RenderCore {
+    pub fn new(
+        vulkan_context: VulkanContext,
+        queue_family_index: u32,
+        async_compute_family: Option<u32>,
+        is_validation_layer_enabled: bool,
+        debug_context: Option<DebugContext>,
+    ) -> Self {
+        Self {
+            vulkan_context,
+            queue_family_index,
+            async_compute_family,
+            is_validation_layer_enabled,
+            debug_context,
+            queue_lock: Mutex::new(()),
+            creation_lock: Mutex::new(()),
+            tables: OnceLock::new(),
+        }
+    }
+
+    pub fn is_descriptor_buffer_enabled(&self) -> bool {
+        self.vulkan_context.extension.descriptor_buffer.is_some()
+    }
+
+    pub fn lock_queues(&self) -> MutexGuard<'_, ()> {
+        self.queue_lock.lock().expect("queue lock poisoned!")
+    }
+
+    pub(crate) fn lock_creation(&self) -> MutexGuard<'_, ()> {
+        // Guards nothing, a renderer that panicked while made leaves nothing half done
+        self.creation_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    ///
+    /// Tables shared by the renderers made with the core. Panics before the first one
+    /// made them.
+    ///
+    pub fn lock_tables(&self) -> MutexGuard<'_, CoreTables> {
+        self.tables
+            .get()
+            .expect("no renderer made the core tables yet!")
+            .lock()
+            .expect("core tables lock poisoned!")
+    }
+
+    /*
+     * Made by the pipeline of the first renderer, sized by its budget.
+     */
+    pub(crate) fn tables_or_make(
+        &self,
+        make: impl FnOnce() -> CoreTables,
+    ) -> MutexGuard<'_, CoreTables> {
+        self.tables
+            .get_or_init(|| Mutex::new(make()))
+            .lock()
+            .expect("core tables lock poisoned!")
+    }
+
+    ///
+    /// Destroys the device and the instance. Every renderer made with the core has to be
+    /// destroyed before.
+    ///
+    pub fn destroy(core: Arc<RenderCore>) {
+        let views = Arc::strong_count(&core) - 1;
+        let mut core = Arc::try_unwrap(core).unwrap_or_else(|_| {
+            panic!(
+                "render core still used by {} renderers, destroy them first!",
+                views
+            )
+        });
+        core.destroy_owned();
+    }
+
+    pub(crate) fn destroy_owned(&mut self) {
+        log::trace!("destroying render core...");
+        unsafe { self.vulkan_context.device.device_wait_idle().unwrap() };
+        if let Some(tables) = self.tables.take() {
+            let tables = tables.into_inner().unwrap_or_else(|e| e.into_inner());
+            tables.destroy(&self.vulkan_context.device);
+        }
+        unsafe { self.vulkan_context.device.destroy_device(None) };
+        if let Some(debug_context) = &mut self.debug_context {
+            debug_context.destroy();
+        }
+        unsafe { self.vulkan_context.instance.destroy_instance(None) };
+        log::trace!("render core destroyed!");
+    }
+}
+
+/*
+ * Core handed to a renderer that's still being made. Destroyed when dropped as the last
+ * owner, like Renderer::destroy does, so a renderer panicking half way through being made
+ * with a core of its own doesn't leak the device and the instance.
+ */
+pub(crate) struct CoreOwner(pub Option<Arc<RenderCore>>);
+
+impl Drop for CoreOwner {
+    fn drop(&mut self) {
+        if let Some(mut core) = self.0.take().and_then(|e| Arc::try_unwrap(e).ok()) {
+            core.destroy_owned();
+        }
+    }
+}
+
+///
+/// Texture and sampler tables every renderer made with the core binds, along with the
+/// meshes and the ids handed out for both, so all of them can draw the same assets by
+/// handle. A texture or mesh still belongs to the renderer that made it: only that one
+/// uploads, evicts and frees it, and its memory comes from that renderer's allocators.
+///
+pub struct CoreTables {
+    pub image_descriptors: Box<dyn DescriptorBackend>,
+    pub sampler_descriptors: Box<dyn DescriptorBackend>,
+    pub samplers_by_key: HashMap<SamplerKey, Sampler>,
+    // Sampler ids go from 0 up to this, see PipelineBudget::sampler_capacity
+    pub sampler_capacity: u32,
+    // Made by Renderer::get_sampler, the next frame prepared by any renderer writes them
+    pub unpublished_samplers: Vec<u8>,
+    pub meshes: HashMap<u32, MeshBuffer>,
+    pub mesh_ids: BitVec,
+    pub mesh_generations: Generations,
+    pub texture_generations: Generations,
+    // Meshes with uploads begun, cleared by the renderer they belong to once done
+    pub uploading_meshes: HashSet<u32>,
+    // Sampled in place of textures not uploaded yet and by free slots
+    pub default_view: Option<vk::ImageView>,
+    // Whether RendererConfig::noise of the first renderer had the reserved ids taken
+    pub has_noise_textures: bool,
+    // Kept once the renderer that made them is destroyed before the others
+    pub(crate) builtin_textures: Vec<Texture>,
+    // Replaced by sampler policies while other renderers could still sample with them
+    pub(crate) retired_samplers: Vec<Sampler>,
+    // Descriptor memory of both tables, None when they are descriptor sets
+    descriptor_allocator: Option<DeviceAllocator>,
+    // Holds the test triangles
+    mesh_allocator: DeviceAllocator,
+}
+
+impl CoreTables {
+    // Room for the test triangles
+    const MESH_ALLOCATOR_BYTES: u64 = 64 * 1024;
+
+    ///
+    /// Tables with the capacities given, in descriptor memory of its own when there is a
+    /// size for it. Comes with the test triangles at their reserved ids.
+    ///
+    pub fn new(
+        ctx: &VulkanContext,
+        descriptor_bytes: Option<u64>,
+        image_capacity: u32,
+        sampler_capacity: u32,
+    ) -> Self {
+        let mut descriptor_allocator =
+            descriptor_bytes.map(|e| DeviceAllocator::new_descriptor(ctx, e));
+        let image_descriptors =
+            Pipeline::image_desc_buffer(ctx, descriptor_allocator.as_mut(), image_capacity);
+        let sampler_descriptors =
+            Pipeline::sampler_desc_buffer(ctx, descriptor_allocator.as_mut(), sampler_capacity);
+        let mut mesh_allocator = DeviceAllocator::new_general(ctx, Self::MESH_ALLOCATOR_BYTES);
+        let mut meshes = HashMap::new();
+        let mut mesh_ids = BitVec::repeat(false, 1024);
+        meshes.insert(
+            Renderer::ID_TEST_TRIANGLE,
+            renderer::make_test_triangle(&mut mesh_allocator),
+        );
+        meshes.insert(
+            Renderer::ID_TEST_TRIANGLE_INTERLEAVED,
+            renderer::make_test_triangle_interleaved(&mut mesh_allocator),
+        );
+        for id in meshes.keys() {
+            mesh_ids.set(*id as usize, true);
+        }
+        Self {
+            image_descriptors,
+            sampler_descriptors,
+            samplers_by_key: HashMap::new(),
+            sampler_capacity,
+            unpublished_samplers: Vec::new(),
+            meshes,
+            mesh_ids,
+            mesh_generations: Generations::default(),
+            texture_generations: Generations::default(),
+            uploading_meshes: HashSet::new(),
+            default_view: None,
+            has_noise_textures: false,
+            builtin_textures: Vec::new(),
+            retired_samplers: Vec::new(),
+            descriptor_allocator,
+            mesh_allocator,
+        }
+    }
+
+    fn destroy(&self, device: &ash::Device) {
+        for e in [&self.image_descriptors, &self.sampler_descriptors] {
+            e.destroy(device);
+        }
+        for e in self.samplers_by_key.values().chain(&self.retired_samplers) {
+            e.destroy(device);
+        }
+        for e in &self.builtin_textures {
+            e.destroy(device, None);
+        }
+        if let Some(allocator) = &self.descriptor_allocator {
+            allocator.destroy(device);
+        }
+        self.mesh_allocator.destroy(device);
+    }
+}
@@ -1,10 +1,16 @@
 use std::{collections::HashMap, hash::Hash};
 
+use ash::vk;
+use glam::Vec4;
+
+use crate::bounds::{merge_spheres, MeshBounds};
+use crate::handle::MeshHandle;
 use crate::shader_resource::{ResourceKind, MultiResource};
 use crate::UsedAsIndex;
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, serde::Deserialize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, serde::Deserialize, strum_macros::Display)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 #[repr(u8)]
 pub enum TaskKind {
     MeshStatic,
@@ -26,6 +32,8 @@ pub enum TaskKind {
     Sky,
     Fullscreen,
     Nuklear,
+    // Drawn back to front after the opaque kinds, needs a Transform
+    Translucent,
 }
 
 impl TaskKind {
@@ -70,12 +78,170 @@ impl TaskKind {
     }
 }
 
-const MAX_TASK_KIND: u8 = TaskKind::Nuklear.to_u8();
+const MAX_TASK_KIND: u8 = TaskKind::Translucent.to_u8();
 impl crate::UsedAsIndex<MAX_TASK_KIND> for TaskKind {}
 
 pub struct RenderTask {
     pub kind: TaskKind,
-    pub mesh_buffer_id: u32,
+    pub mesh: MeshHandle,
     pub instance_count: u32,
     pub resources: HashMap<ResourceKind, MultiResource>,
+    // Pipeline permutation to draw with, see Renderer::variant_id
+    pub variant: Option<u16>,
+    // Alpha tested stages discard fragments with albedo alpha below it
+    pub alpha_cutoff: f32,
+    // Drawn without culling, whatever the stage culls
+    pub is_two_sided: bool,
+    // View space depth of the first instance, filled in from its Transform when queued
+    pub view_depth: f32,
+    // Stable identity across frames, previousTransform per draw fields are looked up by it
+    pub object_id: Option<u64>,
+    pub bounds: TaskBounds,
+    // Clips the draws of stages declaring dynamicScissor, the whole stage area if None
+    pub scissor: Option<ScissorRect>,
+    // Bit i set draws it in split screen viewport i, see Renderer::set_viewports. u8::MAX
+    // draws it in all of them
+    pub viewport_mask: u8,
+}
+
+///
+/// Region in pixels the draws of a task are clipped to, like the nested clip rectangles
+/// of UI toolkits. It may reach past the render area, only the part within it counts.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ScissorRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ScissorRect {
+    ///
+    /// The part of it within the area, None if nothing is left.
+    ///
+    pub fn clamped_to(&self, area: vk::Rect2D) -> Option<vk::Rect2D> {
+        let start = |offset: i32, area_offset: i32| (offset as i64).max(area_offset as i64);
+        let end = |offset: i32, size: u32, area_offset: i32, area_size: u32| {
+            (offset as i64 + size as i64).min(area_offset as i64 + area_size as i64)
+        };
+        let x = start(self.x, area.offset.x);
+        let y = start(self.y, area.offset.y);
+        let x_end = end(self.x, self.width, area.offset.x, area.extent.width);
+        let y_end = end(self.y, self.height, area.offset.y, area.extent.height);
+        if x_end <= x || y_end <= y {
+            return None;
+        }
+        Some(vk::Rect2D {
+            offset: vk::Offset2D {
+                x: x as i32,
+                y: y as i32,
+            },
+            extent: vk::Extent2D {
+                width: (x_end - x) as u32,
+                height: (y_end - y) as u32,
+            },
+        })
+    }
+}
+
+impl From<vk::Rect2D> for ScissorRect {
+    fn from(rect: vk::Rect2D) -> Self {
+        Self {
+            x: rect.offset.x,
+            y: rect.offset.y,
+            width: rect.extent.width,
+            height: rect.extent.height,
+        }
+    }
+}
+
+///
+/// View space bounding sphere of every instance of a task, for culling on the host.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TaskBounds {
+    #[default]
+    None,
+    // Center in xyz, radius in w
+    Sphere(Vec4),
+    // Sphere out of the mesh bounds and the Transform of each instance, worked out when
+    // queued. None if either is missing.
+    FromMesh,
+}
+
+impl RenderTask {
+    pub const FLAG_TWO_SIDED: u32 = 1;
+
+    ///
+    /// Single instance of the mesh without resources, drawn as the stages of its kind
+    /// draw it in every viewport. Set the rest through struct update syntax:
+    ///
+    /// ```
+    /// # use rend_vk::render_task::{RenderTask, TaskKind};
+    /// # use rend_vk::renderer::Renderer;
+    /// let task = RenderTask {
+    ///     alpha_cutoff: 0.5,
+    ///     ..RenderTask::new(Renderer::TEST_TRIANGLE, TaskKind::MeshStatic)
+    /// };
+    /// assert_eq!(task.viewport_mask, u8::MAX);
+    /// ```
+    ///
+    pub fn new(mesh: MeshHandle, kind: TaskKind) -> Self {
+        Self {
+            kind,
+            mesh,
+            instance_count: 1,
+            resources: HashMap::new(),
+            variant: None,
+            alpha_cutoff: 0.0,
+            is_two_sided: false,
+            view_depth: 0.0,
+            object_id: None,
+            bounds: TaskBounds::None,
+            scissor: None,
+            viewport_mask: u8::MAX,
+        }
+    }
+
+    ///
+    /// Distance along the view direction of the origin of the first instance, None
+    /// without a Transform.
+    ///
+    pub fn view_depth_of(resources: &HashMap<ResourceKind, MultiResource>) -> Option<f32> {
+        match resources.get(&ResourceKind::Transform) {
+            // Views look down -z
+            Some(MultiResource::Transform(e)) if !e.is_empty() => Some(-e[0].mv.w_axis.z),
+            _ => None,
+        }
+    }
+
+    ///
+    /// View space sphere holding the mesh bounds at every instance, None without a
+    /// Transform.
+    ///
+    pub fn bounds_of(
+        bounds: &MeshBounds,
+        resources: &HashMap<ResourceKind, MultiResource>,
+    ) -> TaskBounds {
+        match resources.get(&ResourceKind::Transform) {
+            Some(MultiResource::Transform(e)) => e
+                .iter()
+                .map(|e| bounds.transformed_sphere(&e.mv))
+                .reduce(merge_spheres)
+                .map_or(TaskBounds::None, TaskBounds::Sphere),
+            _ => TaskBounds::None,
+        }
+    }
+
+    ///
+    /// Bits of the flags per draw field.
+    ///
+    pub fn flags(&self) -> u32 {
+        if self.is_two_sided {
+            Self::FLAG_TWO_SIDED
+        } else {
+            0
+        }
+    }
 }
@@ -0,0 +1,360 @@
+/*
+ * Data every frame gets on the device: the frame constants and light clusters the host
+ * set, the motion of the simulation, and the per pass and per instance data of the tasks
+ * in the frame ring region of the frame, see frame_regions. Buffers uploaded for a frame
+ * live until the frame after the next one starts.
+ */
+use std::any::TypeId;
+
+use glam::Vec2;
+
+use crate::{
+    buffer::{DeviceAllocator, DeviceSlice},
+    debug_flags, deterministic,
+    frame_regions::FrameRegions,
+    frame_ring::FrameRing,
+    light_cluster::ClusterData,
+    motion::{Affine, JitterSequence, PreviousTransforms, SimulationTransforms},
+    noise::{self, FrameNoise},
+    pipeline::{file::TransformInterpolation, per_draw, stage::Stage},
+    reflection::HostMember,
+    render_task::RenderTask,
+    shader_block::ShaderBlock,
+    shader_resource::{FrameConstants, MultiResource, ResourceKind, SingleResource},
+    updater,
+};
+
+use super::Renderer;
+
+pub(super) struct FrameData {
+    pub regions: FrameRegions,
+    // Uploaded for the next frame and in use by the previous one respectively
+    pub buffers: Vec<DeviceSlice>,
+    pub in_flight_buffers: Vec<DeviceSlice>,
+    // Block type last set with set_frame_constants, checked against the shaders once
+    pub constants_type: Option<TypeId>,
+    // Bytes and members of the last block set, uploaded again every frame
+    pub constants: Option<(Vec<u8>, Vec<HostMember>)>,
+    // Written into the jitter member of the frame constants
+    pub taa_jitter: Option<JitterSequence>,
+    pub previous_transforms: PreviousTransforms,
+    // Set with set_frame_transforms_interpolated
+    pub simulation_transforms: Option<SimulationTransforms>,
+}
+
+impl FrameData {
+    pub fn new(regions: FrameRegions) -> Self {
+        Self {
+            regions,
+            buffers: Vec::new(),
+            in_flight_buffers: Vec::new(),
+            constants_type: None,
+            constants: None,
+            taa_jitter: None,
+            previous_transforms: PreviousTransforms::new(),
+            simulation_transforms: None,
+        }
+    }
+
+    ///
+    /// Frees the buffers of the frame before the previous one, the GPU is done with it by
+    /// the time the next frame gets prepared. The ones uploaded since are in flight then.
+    ///
+    pub fn begin(&mut self, allocator: &DeviceAllocator) {
+        for buffer in self.in_flight_buffers.drain(..) {
+            allocator.free(buffer);
+        }
+        std::mem::swap(&mut self.buffers, &mut self.in_flight_buffers);
+    }
+
+    ///
+    /// Releases the frame ring regions and frees every buffer, only while the device is
+    /// idle.
+    ///
+    pub fn release(&mut self, allocator: &DeviceAllocator) {
+        self.regions.release_all();
+        for buffer in self
+            .buffers
+            .drain(..)
+            .chain(self.in_flight_buffers.drain(..))
+        {
+            allocator.free(buffer);
+        }
+    }
+}
+
+impl Renderer {
+    ///
+    /// Copies the cluster ranges and light indices to the device and places the
+    /// LightClusters resource pointing to them. The copies live until the frame after
+    /// the next one starts.
+    ///
+    pub fn upload_clusters(&mut self, data: &ClusterData) {
+        let ranges = alloc_and_copy(&self.general_allocator, &data.ranges, "cluster range");
+        let light_indices =
+            alloc_and_copy(&self.general_allocator, &data.light_indices, "light index");
+        self.frame_data.buffers.extend([ranges, light_indices]);
+        self.place_shader_resource(
+            ResourceKind::LightClusters,
+            SingleResource::LightClusters(
+                data.to_resource(ranges.device_addr, light_indices.device_addr),
+            ),
+        );
+    }
+
+    ///
+    /// Keeps the block to copy to the device every frame from now on, the FrameConstants
+    /// resource pointing to the copy of the frame. Whenever the block type changes it
+    /// gets checked against the block of the same name in the stages consuming
+    /// FrameConstants, mismatches are logged. With a TAA jitter sequence set, its clip
+    /// space offset for the frame replaces the jitter member. A uint perDrawLayoutVersion
+    /// member gets per_draw::LAYOUT_VERSION, the noise members the values of the frame,
+    /// see noise.rs. In deterministic mode the time member gets the fixed time of the
+    /// frame, see deterministic.rs.
+    ///
+    pub fn set_frame_constants<T: ShaderBlock>(&mut self, value: &T) {
+        let members = T::members();
+        if self.frame_data.constants_type != Some(TypeId::of::<T>()) {
+            self.frame_data.constants_type = Some(TypeId::of::<T>());
+            for stage in self.stages_consuming(ResourceKind::FrameConstants) {
+                let size = std::mem::size_of::<T>() as u32;
+                if let Err(mismatch) = stage.check_block_layout(T::NAME, size, &members) {
+                    log::warn!("{}", mismatch);
+                }
+            }
+        }
+        let bytes = unsafe {
+            std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+        };
+        self.frame_data.constants = Some((bytes.to_vec(), members));
+    }
+
+    /*
+     * Copy of the frame constants for the frame being prepared, with what the renderer
+     * fills in written over the members of the host's block. The copy lives until the
+     * frame after the next one starts.
+     */
+    pub(super) fn upload_frame_constants(&mut self, frame: u64) {
+        let (mut bytes, members) = match &self.frame_data.constants {
+            Some((bytes, members)) => (bytes.clone(), members),
+            None => return,
+        };
+        if let Some(jitter) = self.taa_jitter() {
+            let extent = self.swapchain_context.surface_extent;
+            let jitter = jitter * 2.0 / Vec2::new(extent.width as f32, extent.height as f32);
+            if !write_member(&mut bytes, members, "jitter", jitter) {
+                log::warn!(
+                    "frame constants have no jitter member of 8 bytes, the TAA jitter is lost"
+                );
+            }
+        }
+        let version = per_draw::LAYOUT_VERSION;
+        write_member(&mut bytes, members, per_draw::VERSION_MEMBER, version);
+        let noise = FrameNoise::of(frame);
+        write_member(
+            &mut bytes,
+            members,
+            noise::FRAME_INDEX_MEMBER,
+            noise.frame_index,
+        );
+        write_member(&mut bytes, members, noise::SEED_MEMBER, noise.seed);
+        write_member(&mut bytes, members, noise::OFFSET_MEMBER, noise.offset);
+        let flags = self.debug_flags.global;
+        write_member(&mut bytes, members, debug_flags::MEMBER, flags);
+        if let Some(deterministic) = &self.config.deterministic {
+            let time = deterministic.time_of(frame);
+            write_member(&mut bytes, members, deterministic::TIME_MEMBER, time);
+        }
+        let block = alloc_and_copy(&self.general_allocator, &bytes, "frame constants");
+        self.frame_data.buffers.push(block);
+        self.place_shader_resource(
+            ResourceKind::FrameConstants,
+            SingleResource::FrameConstants(FrameConstants {
+                addr: block.device_addr,
+            }),
+        );
+    }
+
+    ///
+    /// Sub-pixel offsets cycled through one per frame for TAA, None turns jitter off.
+    /// The offset of each frame goes into the jitter member of the frame constants in
+    /// clip space, shaders add it to the projected xy of the stages using the camera
+    /// projection. The frame constants set last get it every frame, the host doesn't
+    /// have to set them again for it to advance.
+    ///
+    pub fn set_taa_jitter_sequence(&mut self, sequence: Option<JitterSequence>) {
+        self.frame_data.taa_jitter = sequence;
+    }
+
+    ///
+    /// Transforms of every simulation object in the previous and current simulation
+    /// states, and how far from the previous one to the current one the frame tasks
+    /// queued now get rendered at, for simulations ticking at a fixed rate of their own.
+    /// Objects are at the index of their RenderTask::object_id, the instances of a task
+    /// at the indices after it. Stages with a transformInterpolation other than none
+    /// blend them for their interpolatedTransform per draw field, the mvps of their
+    /// tasks are the view projections then. Kept until set again.
+    ///
+    /// Matrices get lerped component wise, see motion::lerp_affine, or blended as dual
+    /// quaternions with RendererConfig::is_dual_quaternion_interpolation and the
+    /// dual-quaternion feature. Velocities of the previousTransform per draw field come
+    /// from the simulation states, see SimulationTransforms::velocity_origin.
+    ///
+    pub fn set_frame_transforms_interpolated(
+        &mut self,
+        prev: &[Affine],
+        curr: &[Affine],
+        alpha: f32,
+    ) {
+        let transforms = SimulationTransforms::new(prev, curr, alpha);
+        #[cfg(feature = "dual-quaternion")]
+        let transforms =
+            transforms.with_dual_quaternions(self.config.is_dual_quaternion_interpolation);
+        self.frame_data.simulation_transforms = Some(transforms);
+    }
+
+    pub fn clear_frame_transforms(&mut self) {
+        self.frame_data.simulation_transforms = None;
+    }
+
+    ///
+    /// Jitter in pixels of the frame tasks queued now get rendered in, None without a
+    /// jitter sequence.
+    ///
+    pub fn taa_jitter(&self) -> Option<Vec2> {
+        let frame = self.queued_frame();
+        self.frame_data.taa_jitter.as_ref().map(|e| e.at(frame))
+    }
+
+    // What the previousTransform per draw fields of the next frame read
+    pub(super) fn record_previous_transforms(&mut self, frame: u64) {
+        for task in self.batches_by_task_type.iter().flatten() {
+            let object_id = match task.object_id {
+                Some(e) => e,
+                None => continue,
+            };
+            if let Some(MultiResource::Transform(e)) = task.resources.get(&ResourceKind::Transform)
+            {
+                let mvps = e.iter().map(|e| e.mvp).collect();
+                self.frame_data
+                    .previous_transforms
+                    .record(task.kind, object_id, frame, mvps);
+            }
+        }
+        self.frame_data
+            .previous_transforms
+            .evict(frame, self.config.previous_transform_lifetime);
+    }
+}
+
+/*
+ * Worst case general buffer bytes taken by the per pass data and the constants of every
+ * stage, allocated even if the stage has nothing to draw.
+ */
+pub(super) fn per_pass_size_of(stages: &[Stage], alignment: u64) -> u64 {
+    let align = |v: u64| v.div_ceil(alignment) * alignment;
+    let per_pass: u64 = stages
+        .iter()
+        .filter(|e| !e.per_pass_updaters.is_empty())
+        .map(|e| {
+            align(
+                e.per_pass_updaters
+                    .iter()
+                    .map(|k| k.resource_size() as u64)
+                    .sum(),
+            )
+        })
+        .sum();
+    let constants: u64 = stages
+        .iter()
+        .filter(|e| !e.constants.is_empty())
+        .map(|e| align(e.constants.size() as u64))
+        .sum();
+    per_pass + constants
+}
+
+/*
+ * Both simulation states into the frame region, once for every stage blending them in
+ * its shaders. None if no stage drawing any task does.
+ */
+pub(super) fn upload_simulation_transforms(
+    simulation: Option<&SimulationTransforms>,
+    stages: &[Stage],
+    batches_by_task_type: &[Vec<RenderTask>],
+    region: &mut FrameRing,
+) -> Option<[u64; 2]> {
+    let simulation = simulation.filter(|e| !e.is_empty())?;
+    let is_read = stages.iter().any(|e| {
+        e.transform_interpolation == TransformInterpolation::Shader
+            && !batches_by_task_type[e.task_kind.to_usize()].is_empty()
+    });
+    if !is_read {
+        return None;
+    }
+    let [previous, current] = simulation.to_bytes();
+    Some([
+        updater::alloc_and_copy_bytes(region, &previous).device_addr,
+        updater::alloc_and_copy_bytes(region, &current).device_addr,
+    ])
+}
+
+// Per pass and per instance data an offscreen pass of the stage takes from the frame region
+pub(super) fn offscreen_size_of(stage: &Stage, alignment: u64, tasks: &[RenderTask]) -> u64 {
+    let align = |v: u64| v.div_ceil(alignment) * alignment;
+    let per_instance: u64 = tasks
+        .iter()
+        .flat_map(|task| {
+            stage
+                .per_instance_updaters
+                .iter()
+                .map(|k| align(k.resource_size() as u64 * task.instance_count as u64))
+        })
+        .sum();
+    per_pass_size_of(std::slice::from_ref(stage), alignment) + per_instance
+}
+
+pub(super) fn task_size_of(stages: &[Stage], alignment: u64, task: &RenderTask) -> u64 {
+    let align = |v: u64| v.div_ceil(alignment) * alignment;
+    stages
+        .iter()
+        .filter(|e| e.task_kind == task.kind)
+        .flat_map(|e| &e.per_instance_updaters)
+        .map(|k| align(k.resource_size() as u64 * task.instance_count as u64))
+        .sum()
+}
+
+/*
+ * Writes the value over the member of the block with that name, if there's one of the
+ * same size. Returns whether there was.
+ */
+fn write_member<V: Copy>(block: &mut [u8], members: &[HostMember], name: &str, value: V) -> bool {
+    let size = std::mem::size_of::<V>();
+    let member = members
+        .iter()
+        .find(|e| e.name == name && e.size as usize == size);
+    match member {
+        Some(member) => {
+            let dst = &mut block[member.offset as usize..member.offset as usize + size];
+            unsafe { std::ptr::write_unaligned(dst.as_mut_ptr() as *mut V, value) };
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg_attr(feature = "alloc-canaries", track_caller)]
+pub(super) fn alloc_and_copy<T>(mem: &DeviceAllocator, items: &[T], purpose: &str) -> DeviceSlice {
+    // Empty arrays still get a valid address
+    let size = (std::mem::size_of_val(items) as u64).max(4);
+    let slice = mem
+        .alloc(size)
+        .unwrap_or_else(|| panic!("couldn't allocate '{}' buffer of size {}", purpose, size));
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            items.as_ptr() as *const u8,
+            slice.addr as *mut u8,
+            std::mem::size_of_val(items),
+        )
+    };
+    slice
+}
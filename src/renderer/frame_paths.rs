@@ -0,0 +1,289 @@
+/*
+ * Paths of frames that skip stages, see stats::FramePath. Idle frames run none of them
+ * and keep or clear the presented image, see RendererConfig::idle_frames. Overlay frames
+ * only run the overlay stage over the region marked dirty, on top of the shared
+ * presentable image or a retained frame, see low_latency.
+ */
+use std::{collections::HashSet, time::Instant};
+
+use ash::vk;
+
+use crate::{
+    config::IdleFrames,
+    low_latency::{self, OverlayState, RetainedFrame},
+    pipeline::{attachment::Attachment, stage::PreparedStage},
+    render_task::{ScissorRect, TaskKind},
+    stats::FramePath,
+};
+
+use super::Renderer;
+
+pub(super) struct FramePaths {
+    // Swapchain images presented since the swapchain was made, idle frames can keep those
+    pub presented_images: HashSet<vk::Image>,
+    pub overlay: OverlayState,
+    // Low latency overlay without shared presentable images, made by the first frame
+    pub retained_frame: Option<RetainedFrame>,
+}
+
+impl FramePaths {
+    pub fn new() -> Self {
+        Self {
+            presented_images: HashSet::new(),
+            overlay: OverlayState::default(),
+            retained_frame: None,
+        }
+    }
+
+    ///
+    /// Forgets what the previous swapchain presented and retained, only while the device
+    /// is idle.
+    ///
+    pub fn reset(&mut self, device: &ash::Device) {
+        self.presented_images.clear();
+        self.overlay.reset();
+        if let Some(retained) = self.retained_frame.take() {
+            retained.destroy(device);
+        }
+    }
+}
+
+impl Renderer {
+    ///
+    /// Whether frames with only overlay tasks queued can skip every stage but the overlay
+    /// one, see RendererConfig::low_latency_overlay. Needs a shared presentable swapchain
+    /// image, or else a swapchain low_latency::is_retainable accepts, no internal
+    /// resolution and a final Nuklear stage that loads the default attachment as its only
+    /// attachment.
+    ///
+    pub fn is_low_latency_overlay_active(&self) -> bool {
+        (self.swapchain_context.is_shared_present() || self.is_overlay_retained())
+            && self.scaled_target.is_none()
+            && self.overlay_stage().is_some()
+    }
+
+    ///
+    /// Adds the region to the one the next overlay frame redraws, in pixels of the
+    /// swapchain. Overlay frames begin rendering over that region alone, with the
+    /// scissors of their tasks intersected with it, or redraw the whole image if nothing
+    /// got marked. Frames going through every stage redraw it anyway.
+    ///
+    pub fn mark_overlay_dirty(&mut self, rect: ScissorRect) {
+        self.frame_paths.overlay.mark_dirty(rect);
+    }
+
+    ///
+    /// Marks when input the next frame responds to came in. FrameStats::input_to_present
+    /// of that frame measures from the oldest input marked.
+    ///
+    pub fn mark_input(&mut self, at: Instant) {
+        self.frame_paths.overlay.mark_input(at);
+    }
+
+    /*
+     * Called with the stats of the frame swapped in.
+     */
+    pub(super) fn mark_presented(&mut self, image: vk::Image) {
+        self.frame_paths.presented_images.insert(image);
+        let path = self.stats.queued.path;
+        self.stats.queued.input_to_present =
+            self.frame_paths.overlay.presented(path, Instant::now());
+    }
+
+    /*
+     * Nothing would be drawn and nothing uploaded, so no stage has to run. The texture
+     * inspector draws on top of the frame, so it needs it rendered, as do offscreen passes
+     * recorded after its stages.
+     */
+    pub(super) fn is_idle_frame(&self) -> bool {
+        self.config.idle_frames != IdleFrames::Render
+            && self.batches_by_task_type.iter().all(|e| e.is_empty())
+            && self.optimal_transition_queue.is_empty()
+            && self.transition_retries.is_empty()
+            && self.texture_region_updates.is_empty()
+            && self.inspected_texture.is_none()
+            && self.offscreen_passes.is_empty()
+    }
+
+    /*
+     * Only overlay tasks queued on top of a whole frame the shared image holds already,
+     * see low_latency. Anything else that changes the scene takes every stage, like it
+     * keeps frames from being idle.
+     */
+    pub(super) fn is_overlay_frame(&self) -> bool {
+        if !self.is_low_latency_overlay_active() {
+            return false;
+        }
+        // Without a shared image, the whole frame to draw on top of is the retained one
+        let is_retained = self
+            .frame_paths
+            .retained_frame
+            .as_ref()
+            .is_some_and(|e| e.is_filled());
+        if !self.swapchain_context.is_shared_present() && !is_retained {
+            return false;
+        }
+        let overlay_kind = TaskKind::Nuklear.to_usize();
+        let is_scene_changed = self
+            .batches_by_task_type
+            .iter()
+            .enumerate()
+            .any(|(i, e)| i != overlay_kind && !e.is_empty())
+            || !self.optimal_transition_queue.is_empty()
+            || !self.transition_retries.is_empty()
+            || !self.texture_region_updates.is_empty()
+            || self.inspected_texture.is_some()
+            || !self.offscreen_passes.is_empty();
+        let has_overlay_tasks = !self.batches_by_task_type[overlay_kind].is_empty();
+        self.frame_paths
+            .overlay
+            .is_overlay_frame(is_scene_changed, has_overlay_tasks)
+    }
+
+    /*
+     * Low latency overlay through a RetainedFrame, for swapchains without a shared
+     * presentable image.
+     */
+    fn is_overlay_retained(&self) -> bool {
+        let swapchain = &self.swapchain_context;
+        self.config.low_latency_overlay
+            && !swapchain.is_shared_present()
+            && swapchain
+                .attachments
+                .first()
+                .is_some_and(|e| low_latency::is_retainable(swapchain.present_mode, e.usage))
+    }
+
+    /*
+     * Index of the stage overlay frames record, the only one drawing Nuklear tasks if it
+     * can draw them on its own.
+     */
+    pub(super) fn overlay_stage(&self) -> Option<usize> {
+        let mut stages = self
+            .pipeline
+            .stages
+            .iter()
+            .enumerate()
+            .filter(|e| e.1.task_kind == TaskKind::Nuklear);
+        match (stages.next(), stages.next()) {
+            (Some((i, stage)), None) if stage.is_overlay_capable() => Some(i),
+            _ => None,
+        }
+    }
+
+    pub(super) fn record_idle_frame(&self, default_attachment: &Attachment) {
+        let color = match self.config.idle_frames {
+            IdleFrames::Keep
+                if self
+                    .frame_paths
+                    .presented_images
+                    .contains(&default_attachment.image) =>
+            {
+                // Still in the present layout since it was last presented
+                return;
+            }
+            IdleFrames::Clear(e) => e,
+            _ => [0.0, 0.0, 0.0, 1.0],
+        };
+        let device = &self.vulkan_context.device;
+        let cmd = self.draw_command_buffer;
+        let image = default_attachment.image;
+        let clear_barriers = [
+            default_attachment.adapt_barrier(Attachment::default_attachment_clear_barrier(image))
+        ];
+        let present_barriers = [default_attachment.adapt_barrier(
+            Attachment::default_attachment_cleared_present_barrier(image),
+        )];
+        let clear_color = vk::ClearColorValue { float32: color };
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().image_memory_barriers(&clear_barriers),
+            );
+            device.cmd_clear_color_image(
+                cmd,
+                image,
+                default_attachment.layout_for(vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+                &clear_color,
+                &[Attachment::color_subresource_range()],
+            );
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().image_memory_barriers(&present_barriers),
+            );
+        }
+    }
+
+    /*
+     * Path the frame being prepared takes, out of what got queued for it. Makes the
+     * retained frame first if overlay frames go through one.
+     */
+    pub(super) fn select_frame_path(&mut self) -> FramePath {
+        if self.frame_paths.retained_frame.is_none() && self.is_overlay_retained() {
+            let extent = self.swapchain_context.surface_extent;
+            let format = self.swapchain_context.surface_format.format;
+            let retained = RetainedFrame::make(&self.vulkan_context, format, extent);
+            self.frame_paths.retained_frame = Some(retained);
+        }
+        if self.is_idle_frame() {
+            FramePath::Idle
+        } else if self.is_overlay_frame() {
+            FramePath::Overlay
+        } else if self.pipeline.is_present_declared {
+            FramePath::PresentStage
+        } else {
+            FramePath::Stages
+        }
+    }
+
+    /*
+     * Limits the overlay stage of an overlay frame to the region marked dirty since the
+     * last one, see mark_overlay_dirty.
+     */
+    pub(super) fn clip_overlay_frame(
+        &mut self,
+        stages: &mut [PreparedStage],
+        default_attachment: &Attachment,
+    ) {
+        let area = default_attachment.render_area_no_offset();
+        // Marked outside of the image, nothing to redraw
+        let region = self
+            .frame_paths
+            .overlay
+            .take_dirty(area)
+            .unwrap_or_default();
+        let index = self.overlay_stage().unwrap();
+        self.pipeline.stages[index].clip_prepared(&mut stages[index], region);
+        if let Some(retained) = &mut self.frame_paths.retained_frame {
+            retained.set_redrawn(region);
+        }
+    }
+
+    // Overlay frames draw on top of the retained frame, copied back before the stages
+    pub(super) fn record_retained_restore(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        default_attachment: &Attachment,
+    ) {
+        if let Some(retained) = &self.frame_paths.retained_frame {
+            if self.stats.queued.path == FramePath::Overlay {
+                let ctx = &self.vulkan_context;
+                retained.record_restore(ctx, command_buffer, default_attachment);
+            }
+        }
+    }
+
+    // Whatever gets presented is retained after the stages, idle frames included
+    pub(super) fn record_retain(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        default_attachment: &Attachment,
+    ) {
+        if let Some(retained) = &mut self.frame_paths.retained_frame {
+            if self.stats.queued.path != FramePath::Flush {
+                let ctx = &self.vulkan_context;
+                retained.record_retain(ctx, command_buffer, default_attachment);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ * Stats of the frames, see FrameStats, and the counters and GPU timings that go into
+ * them. Tasks queued while a frame is prepared count for the one after it, so the stats
+ * of the queued frame are swapped in and out around every prepared one.
+ */
+use std::time::{Duration, Instant};
+
+use crate::{
+    events::{RenderEvent, StageTimer},
+    stats::{FrameStats, SubmissionSummary},
+};
+
+use super::{frame_data, Renderer};
+
+pub(super) struct StatsTracker {
+    // Of the frame tasks are queued for, frame_stats returns the last rendered one
+    pub queued: FrameStats,
+    pub last: FrameStats,
+    // Set by prepare_frame for the FrameEnded event
+    pub frame_started_at: Option<Instant>,
+    // None when the queue can't write timestamps
+    pub stage_timer: Option<StageTimer>,
+    // Acquisitions that timed out since the last one that went through
+    pub acquire_timeouts: u32,
+    // Suboptimal frames in a row since the swapchain was made
+    pub suboptimal_frames: u32,
+    pub texture_evictions: u64,
+    pub texture_restores: u64,
+}
+
+impl StatsTracker {
+    pub fn new(stage_timer: Option<StageTimer>) -> Self {
+        Self {
+            queued: FrameStats::default(),
+            last: FrameStats::default(),
+            frame_started_at: None,
+            stage_timer,
+            acquire_timeouts: 0,
+            suboptimal_frames: 0,
+            texture_evictions: 0,
+            texture_restores: 0,
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        if let Some(timer) = &self.stage_timer {
+            timer.destroy(device);
+        }
+    }
+}
+
+impl Renderer {
+    ///
+    /// Task counts of the last rendered frame.
+    ///
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.stats.last
+    }
+
+    ///
+    /// Acquisitions that timed out in a row so far, 0 once one goes through. The frame
+    /// that goes through reports them in FrameStats::consecutive_acquire_timeouts.
+    ///
+    pub fn consecutive_acquire_timeouts(&self) -> u32 {
+        self.stats.acquire_timeouts
+    }
+
+    ///
+    /// Totals of the tasks queued for the next frame. Only counting the unique meshes
+    /// allocates.
+    ///
+    pub fn frame_submission_summary(&self) -> SubmissionSummary {
+        let alignment = self.general_allocator.alignment();
+        let stages = &self.pipeline.stages;
+        let tables = self.tables();
+        SubmissionSummary::of(
+            self.batches_by_task_type.iter().flatten(),
+            frame_data::per_pass_size_of(stages, alignment),
+            |task| {
+                tables
+                    .meshes
+                    .get(&task.mesh.index)
+                    .map_or(0, |e| e.count as u64)
+            },
+            |kind| {
+                stages
+                    .iter()
+                    .filter(|e| e.blit.is_none() && e.task_kind == kind)
+                    .count() as u64
+            },
+            |task| frame_data::task_size_of(stages, alignment, task),
+        )
+    }
+
+    #[cfg(feature = "bench-metrics")]
+    pub(super) fn take_bench_counters(&mut self) {
+        // Of the whole core, the tables are shared
+        let flushed = {
+            let mut tables = self.tables();
+            tables.image_descriptors.take_flushed_bytes()
+                + tables.sampler_descriptors.take_flushed_bytes()
+        };
+        let bench = &mut self.stats.queued.bench;
+        bench.allocations = self.general_allocator.take_allocation_count();
+        bench.descriptor_flush_bytes = flushed;
+    }
+
+    // GPU time of each stage, if they were timed
+    pub(super) fn emit_stage_timings(&mut self) -> Option<Vec<Duration>> {
+        let timings = self
+            .stats
+            .stage_timer
+            .as_mut()
+            .and_then(|e| e.read(&self.vulkan_context.device));
+        let timings = timings?;
+        let frame = timings.frame;
+        for (stage, gpu_time) in self.pipeline.stages.iter().zip(&timings.stages) {
+            self.event_sink.emit(RenderEvent::StageExecuted {
+                frame,
+                stage: stage.name.clone(),
+                gpu_time: *gpu_time,
+            });
+        }
+        let mut viewport_gpu_times = Vec::new();
+        for (stage, viewport, gpu_time) in timings.viewports {
+            let viewport = viewport as usize;
+            if viewport_gpu_times.len() <= viewport {
+                viewport_gpu_times.resize(viewport + 1, Duration::ZERO);
+            }
+            viewport_gpu_times[viewport] += gpu_time;
+            self.event_sink.emit(RenderEvent::StageViewportExecuted {
+                frame,
+                stage: self.pipeline.stages[stage as usize].name.clone(),
+                viewport: viewport as u32,
+                gpu_time,
+            });
+        }
+        self.stats.queued.viewport_gpu_times = viewport_gpu_times;
+        Some(timings.stages)
+    }
+
+    /*
+     * Makes the stats of the frame just submitted the last frame's, the ones of the next
+     * frame the queued ones, and reports how long the frame took on the host.
+     */
+    pub(super) fn end_frame_stats(&mut self, frame: u64, next: FrameStats) {
+        self.stats.queued.frame = self.incr_current_frame();
+        self.stats.last = std::mem::replace(&mut self.stats.queued, next);
+        if let Some(started_at) = self.stats.frame_started_at.take() {
+            self.event_sink.emit(RenderEvent::FrameEnded {
+                frame,
+                path: self.stats.last.path,
+                cpu_time: started_at.elapsed(),
+            });
+        }
+    }
+}
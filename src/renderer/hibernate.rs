@@ -0,0 +1,97 @@
+/*
+ * Hibernation, releasing what only rendering needs while the app sits in the background
+ * and bringing it back on resume. Everything released here is made again the way the
+ * first frames after creating the renderer or resizing it make it.
+ */
+use crate::events::RenderEvent;
+
+use super::Renderer;
+
+// What resume brings back, kept while hibernated
+pub(super) struct Hibernation {
+    // Evicted by hibernate, restored on resume when there's a texture restorer
+    evicted_textures: Vec<u32>,
+}
+
+impl Renderer {
+    ///
+    /// Releases what only rendering needs while the app sits in the background: the
+    /// swapchain, the frame data regions, the pipeline targets and, with a floor given,
+    /// the least recently used textures until their image memory fits in it. Image pool
+    /// blocks left empty go back to the driver. Handles, texture and mesh metadata, mesh
+    /// buffers and what was set on the pipeline stay, as do the queued tasks and the
+    /// textures they reference.
+    ///
+    /// render returns FrameOutcome::Hibernated until resume is called. Targets declared
+    /// with preserveOnRecreate keep their memory to be carried over by resume, the others
+    /// come back empty. Texture ids of released attachments sample the default texture
+    /// meanwhile, and reading them back panics.
+    ///
+    pub fn hibernate(&mut self, texture_floor: Option<u64>) {
+        if self.is_hibernated() {
+            return;
+        }
+        self.wait_idle_for("hibernate", |renderer| {
+            renderer.swapchain_context.release(&renderer.vulkan_context);
+            renderer.frame_paths.reset(&renderer.vulkan_context.device);
+            renderer.frame_data.release(&renderer.general_allocator);
+            let evicted = match texture_floor {
+                Some(floor) => renderer.evict_textures_down_to(floor),
+                None => Vec::new(),
+            };
+            // Device is idle, no need to wait for the next frame to destroy them
+            renderer.release_freed_textures();
+            let device = &renderer.vulkan_context.device;
+            let released_image_memory = renderer.image_pool.release_unused_blocks(device)
+                + renderer.pipeline.release_attachments(device);
+            renderer.replace_attachment_texture_ids();
+            renderer.event_sink.emit(RenderEvent::Hibernated {
+                evicted_textures: evicted.len() as u32,
+                released_image_memory,
+            });
+            renderer.hibernation = Some(Hibernation {
+                evicted_textures: evicted,
+            });
+        });
+    }
+
+    ///
+    /// Brings back what hibernate released, the swapchain at the current size of the
+    /// surface, width and height being used when the surface leaves it up to the
+    /// swapchain. Textures evicted by hibernate get restored through the texture
+    /// restorer if one is set, otherwise they stay evicted until restore_texture.
+    ///
+    /// The surface may come back at another size than hibernate left, the swapchain and
+    /// what goes with its extent get made at the new one the way resize makes them. The
+    /// pipeline gets loaded again either way, making the targets hibernate released at
+    /// the current extent. Does nothing if not hibernated.
+    ///
+    pub fn resume(&mut self, width: u32, height: u32) {
+        let evicted = match self.hibernation.take() {
+            Some(e) => e.evicted_textures,
+            None => return,
+        };
+        // Released by hibernate already, nothing is in flight
+        self.create_swapchain(width, height);
+        let mut restored_textures = 0;
+        if self.texture_restorer.is_some() {
+            for id in evicted {
+                // Freed while hibernated or already restored by hand
+                if self.textures_by_id.get(&id).is_some_and(|e| e.is_evicted())
+                    && self.restore_or_defer(id)
+                {
+                    restored_textures += 1;
+                }
+            }
+        }
+        self.event_sink.emit(RenderEvent::Resumed {
+            width: self.swapchain_context.surface_extent.width,
+            height: self.swapchain_context.surface_extent.height,
+            restored_textures,
+        });
+    }
+
+    pub fn is_hibernated(&self) -> bool {
+        self.hibernation.is_some()
+    }
+}
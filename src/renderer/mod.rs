@@ -0,0 +1,7250 @@
+use core::panic;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::CStr,
+    mem::align_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use ash::{
+    extensions::{
+        ext::{self, DebugUtils},
+        khr,
+    },
+    util::Align,
+    vk, Entry,
+};
+use glam::Vec3;
+
+#[cfg(feature = "alloc-canaries")]
+use crate::canary::CanaryError;
+#[cfg(feature = "fault-injection")]
+use crate::fault::{Fault, FaultInjector};
+use crate::{
+    adapter::{self, AdapterError},
+    bounds::MeshBounds,
+    buffer::{DeviceAllocator, DeviceSlice},
+    capabilities::{CapabilityError, DeviceCapabilities, DeviceFeature},
+    capture::{CaptureUnavailable, CapturedState, FrameCapture},
+    cleanup::Cleanup,
+    config::{self, DescriptorMode, RendererConfig, TaskRejected, UploadQueue},
+    context::{self, ExtensionContext, VulkanContext},
+    debug::{self, DebugContext},
+    debug_flags::{DebugFlag, DebugFlags},
+    events::{LogSink, RenderEvent, RenderEventSink, StageTimer},
+    format::Format,
+    frame_budget::FrameBudget,
+    frame_regions::FrameRegions,
+    frame_ring::FrameRing,
+    governor::{GovernorState, QualityGovernor, QualityGovernorConfig, QualityOverride},
+    handle::{
+        Generations, IdUnavailable, MeshHandle, ResourceMeta, SceneSlotId, StaleHandle,
+        TextureHandle,
+    },
+    ibl::{self, IblBakeDesc, IblBaker, IblMaps},
+    image_pool::ImagePool,
+    interop::{self, ExternalSemaphoreFns, ExternalSemaphoreHandle},
+    inspector,
+    memory::{
+        AllocatorReport, AttachmentMemoryReport, MemoryReport, MemorySnapshot,
+        MemorySnapshotTracker, TextureMemoryReport, TextureSnapshot,
+    },
+    mesh_opt::{self, MeshData, MeshOptFlags, MeshOptReport},
+    mesh_upload::{MeshAttribute, MeshUploadCursor, MeshUploadOperation, UploadScheduler},
+    motion::FrameMotion,
+    noise::NoiseTexture,
+    offscreen::{
+        self, OffscreenError, OffscreenImage, OffscreenKey, OffscreenPassDesc,
+        PendingOffscreenPass, PreparedOffscreenPass,
+    },
+    operation::{Operation, OperationHandle, Operations},
+    pipeline::{
+        self,
+        attachment::Attachment,
+        budget::{BudgetLimits, PipelineBudget},
+        descriptor::DescriptorSizes,
+        file::{ExtraUsage, ShadingRate},
+        hints::OptimizationHint,
+        per_draw, plan,
+        sampler::{Sampler, SamplerKey, SamplerPolicy, SamplersExhausted},
+        stage::{PreparedStage, StagePreparation, StageRecording},
+        state_cache::StateCache,
+        Pipeline,
+    },
+    present_timing::{self, DisplayTiming, PresentTiming},
+    readback::{ImageSource, QueuedRead, QueuedReadback, ReadbackBusy, ReadbackRequest, Readbacks},
+    render_core::{CoreOwner, CoreTables, RenderCore},
+    render_task::{RenderTask, TaskBounds, TaskKind},
+    retry::{PendingWorkSummary, RetriedWork, RetryError, RetryQueue, RetryReason},
+    scaling::{self, UpscaleFilter},
+    scene_slot::{SceneSlot, SceneSlotRejected},
+    shader_resource::{Material, MultiResource, ResourceKind},
+    sparse::{self, PageBinding, PageMemory, PagePool, PagePoolId, SparseError},
+    split_screen::{ViewportDesc, MAX_VIEWPORTS},
+    stage_constants::{ConstantValue, StageConstant, StageConstants, UnknownConstant},
+    stats::{FramePath, FrameStats},
+    swapchain::{self, SwapchainCapabilities},
+    task_sender::TaskSender,
+    texture::{
+        DualViewTexture, MipMap, NoSrgbPair, ReimportError, Residency, Texture, TextureRegion,
+        TextureRestorer,
+    },
+    texture_feedback::{self, FeedbackEntry, TextureExtent, TextureFeedback},
+    texture_loader::{CreateTextureError, LoadingTexture, TextureLoader},
+    upload::{self, AsyncUploadQueue},
+    vertex,
+    vertex_layout::{self, StreamFormats, VertexAttributeKind, VertexLayout, VertexLayoutKind},
+    UsedAsIndex,
+};
+
+mod frame_data;
+mod frame_paths;
+mod frame_stats;
+mod hibernate;
+mod shader_resources;
+
+use frame_data::FrameData;
+use frame_paths::FramePaths;
+use frame_stats::StatsTracker;
+use hibernate::Hibernation;
+use shader_resources::ShaderResources;
+
+#[derive(Clone)]
+pub struct MeshBuffer {
+    pub vertices: DeviceSlice,
+    pub normals: DeviceSlice,
+    pub tex_coords: DeviceSlice,
+    pub indices: DeviceSlice,
+    pub count: u32,
+    pub bounds: Option<MeshBounds>,
+    // Applied to the bounds when read, see Renderer::set_mesh_bounds_inflation
+    pub bounds_inflation: f32,
+    // Interleaved meshes hold every attribute in vertices, None for separate ones
+    pub layout: Option<VertexLayout>,
+    // Of every attribute, the ones of the layout for interleaved meshes
+    pub formats: StreamFormats,
+}
+
+impl MeshBuffer {
+    pub fn layout_kind(&self) -> VertexLayoutKind {
+        match self.layout {
+            Some(_) => VertexLayoutKind::Interleaved,
+            None => VertexLayoutKind::Separate,
+        }
+    }
+
+    /*
+     * Stride, offset and format of the positions in vertices.
+     */
+    fn position_stride(&self) -> (u32, u32, Format) {
+        let format = self.formats.position;
+        match &self.layout {
+            Some(layout) => (
+                layout.stride(),
+                layout.offset_of(VertexAttributeKind::Position).unwrap(),
+                format,
+            ),
+            None => (vertex_layout::format_size(format), 0, format),
+        }
+    }
+}
+
+/*
+ * Texture work needing staging, retried when the general buffer had no room for it.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StagingWork {
+    // Updates queued by update_texture_region
+    Regions(u32),
+    // Bringing back an evicted texture
+    Restore(u32),
+}
+
+impl StagingWork {
+    fn texture(self) -> u32 {
+        match self {
+            Self::Regions(e) | Self::Restore(e) => e,
+        }
+    }
+}
+
+/*
+ * What the default attachment of a renderer made with the core is, see
+ * Renderer::with_core_headless.
+ */
+enum ViewOutput<F> {
+    Surface {
+        create_surface: F,
+        is_vsync_enabled: bool,
+    },
+    Headless(vk::Extent2D),
+}
+
+/*
+ * Texture gen_texture_at and make_texture_at make, a plain 2D one unless told otherwise.
+ */
+struct NewTexture<'a> {
+    name: String,
+    format: crate::format::Format,
+    mip_maps: &'a [MipMap],
+    is_cube: bool,
+    // Of the sRGB view sharing the image, dual view textures only
+    srgb_id: Option<u32>,
+}
+
+impl<'a> NewTexture<'a> {
+    fn new(name: String, format: crate::format::Format, mip_maps: &'a [MipMap]) -> Self {
+        Self {
+            name,
+            format,
+            mip_maps,
+            is_cube: false,
+            srgb_id: None,
+        }
+    }
+}
+
+type CreateSurface = fn(&ash::Entry, &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>;
+
+///
+/// Renderer is Send but not Sync. It can be created on one thread and moved to a
+/// dedicated render thread afterwards, but every call has to come from the thread
+/// that currently owns it.
+///
+pub struct Renderer {
+    pub vulkan_context: Box<context::VulkanContext>,
+    swapchain_context: Box<swapchain::SwapchainContext>,
+    // Taken on destroy, the last renderer holding it destroys it too
+    core: Option<Arc<RenderCore>>,
+    frame_capture: FrameCapture,
+    is_verbose_labels_enabled: bool,
+    pipeline: Box<Pipeline>,
+    // Loaded again when the swapchain changes size, see rebuild_pipeline
+    pipeline_path: String,
+    // Stages render to it instead of the swapchain images with an internal resolution
+    scaled_target: Option<Attachment>,
+    // Draws the scaled target for the filters that don't blit, made along with it
+    #[cfg(feature = "builtin-passes")]
+    scaling_stage: Option<scaling::ScalingStage>,
+    general_allocator: Box<DeviceAllocator>,
+    // Scope of the general allocator meshes and texture staging get allocated in
+    allocation_scope: Option<DeviceAllocator>,
+    // None when descriptors go into classic descriptor sets
+    descriptor_allocator: Option<Box<DeviceAllocator>>,
+    // Meshes this renderer made, the ones it uploads to and frees, see CoreTables::meshes
+    own_meshes: HashSet<u32>,
+    mesh_uploads: UploadScheduler,
+    textures_by_id: HashMap<u32, Texture>,
+    freed_textures: Vec<Texture>,
+    // Host metadata by id, kept across eviction until the resource is freed
+    texture_meta: HashMap<u32, ResourceMeta>,
+    mesh_meta: HashMap<u32, ResourceMeta>,
+    // Per instance data kept across frames, see create_scene_slot
+    scene_slots_by_id: HashMap<u32, SceneSlot>,
+    scene_slot_generations: Generations,
+    free_scene_slot_ids: Vec<u32>,
+    // Content hash of texture_meta to the id, the latest texture with the hash wins
+    textures_by_hash: HashMap<u64, u32>,
+    // Images of evicted and replaced textures, destroyed along with the freed ones
+    evicted_images: Vec<Texture>,
+    // Texture still sampled by each id until its reimported image is uploaded
+    reimported_textures: HashMap<u32, Texture>,
+    // Frame each texture id was last referenced in by a queued task
+    texture_last_use: HashMap<u32, u64>,
+    // sRGB id of each dual view texture to its linear id, the one it's kept by
+    dual_view_owners: HashMap<u32, u32>,
+    texture_memory_budget: Option<u64>,
+    texture_restorer: Option<Box<TextureRestorer>>,
+    memory_snapshots: MemorySnapshotTracker,
+    // Sampler id to use for each texture id instead of the one in the materials
+    sampler_overrides: HashMap<u32, u8>,
+    sampler_policy: SamplerPolicy,
+    // Applied once the previous frame is done with the current samplers
+    pending_sampler_policy: Option<SamplerPolicy>,
+    is_unpublished_sampler_warned: bool,
+    quality_governor: Option<QualityGovernor>,
+    // While RendererConfig::gpu_frame_budget is set and some stage is optional
+    frame_budget: Option<FrameBudget>,
+    // Split screen, see set_viewports
+    viewports: Vec<ViewportDesc>,
+    quality_override: QualityOverride,
+    // Lod bias of the sampler policy from before the governor took it over
+    ungoverned_lod_bias: f32,
+    inspected_texture: Option<TextureHandle>,
+    image_pool: ImagePool,
+    shader_resources: ShaderResources,
+    frame_data: FrameData,
+    debug_flags: DebugFlags,
+    // Whether RendererConfig::noise had the reserved ids taken on creation
+    has_noise_textures: bool,
+    // Made by the first read_buffer
+    readbacks: Option<Readbacks>,
+    // Only with RendererConfig::is_texture_feedback_enabled
+    texture_feedback: Option<TextureFeedback>,
+    batches_by_task_type: Vec<Vec<RenderTask>>,
+    task_sender: TaskSender,
+    // Made by the first texture_loader
+    texture_loader: Option<TextureLoader>,
+    // Created for loaders that aren't done writing their staging memory yet
+    loading_textures: Vec<LoadingTexture>,
+    // Frame handed out by prepare_frame and not submitted yet
+    prepared_frame: Option<u64>,
+    // None without VK_GOOGLE_display_timing
+    display_timing: Option<DisplayTiming>,
+    // Rebuilt at the start of the next prepared frame
+    is_swapchain_rebuild_pending: bool,
+    // Some while hibernated
+    hibernation: Option<Hibernation>,
+    config: RendererConfig,
+    stats: StatsTracker,
+    frame_paths: FramePaths,
+    event_sink: Box<dyn RenderEventSink>,
+    operations: Operations<Renderer>,
+    // Of the draw command buffer, begun again for every frame
+    state_cache: StateCache,
+    #[cfg(feature = "fault-injection")]
+    faults: Option<FaultInjector>,
+
+    optimal_transition_queue: Vec<u32>,
+    ongoing_optimal_transitions: Vec<(u32, u64)>,
+    // Staged once the texture isn't uploading anymore, see update_texture_region
+    texture_region_updates: HashMap<u32, Vec<(TextureRegion, Vec<u8>)>>,
+    // Slots of the image table handed out by attachment_texture_id
+    attachment_texture_ids: HashMap<String, u32>,
+    // Texture work that found no room for its staging, see retry
+    texture_retries: RetryQueue<StagingWork>,
+    // Transitions put off by the upload budget
+    transition_retries: RetryQueue<u32>,
+    queued_readbacks: RetryQueue<QueuedRead>,
+    next_readback_id: u64,
+    // Retried work given up on since the host last took it
+    failed_work: Vec<(RetriedWork, RetryError)>,
+    // Made by the first bake_ibl
+    ibl_baker: Option<IblBaker>,
+    // Bake timeline value the next frame submission has to wait on
+    pending_ibl_wait: Option<u64>,
+    async_upload: Option<AsyncUploadQueue>,
+    // Upload timeline value the next frame submission has to wait on
+    pending_upload_wait: Option<u64>,
+    // Made by the first export_interop_semaphore, see interop
+    interop_semaphore: Option<vk::Semaphore>,
+    // Value the next submitted frame waits on before signaling one more
+    pending_external_wait: Option<u64>,
+    // Last value a frame signaled on the interop semaphore
+    external_value: u64,
+    // Signaled by sparse binding submissions, made by the first one
+    sparse_semaphore: Option<vk::Semaphore>,
+    // Last value a sparse binding submission signals
+    sparse_value: u64,
+    // Sparse timeline value the next frame submission has to wait on
+    pending_sparse_wait: Option<u64>,
+    page_pools: Vec<PagePool>,
+    // Pages unbound by the sparse timeline value, back to their pools once it's reached
+    unbound_pages: Vec<(u64, PageMemory)>,
+    // Queued by render_to_texture for the next frame
+    offscreen_passes: Vec<PendingOffscreenPass>,
+    // Freed offscreen textures, handed out again to passes of their key
+    offscreen_pool: Vec<Texture>,
+    // Offscreen textures rendered for the first time and the frame rendering them
+    rendering_offscreen: Vec<(u32, u64)>,
+
+    queue_family_index: u32,
+    present_queue: vk::Queue,
+
+    pool: vk::CommandPool,
+    draw_command_buffer: vk::CommandBuffer,
+    setup_command_buffer: vk::CommandBuffer,
+
+    present_complete_semaphore: vk::Semaphore,
+    rendering_complete_semaphore: vk::Semaphore,
+    pass_timeline_semaphore: vk::Semaphore,
+    // Frame N signals N + 1 once the GPU is done with it
+    frame_timeline_semaphore: vk::Semaphore,
+
+    draw_commands_reuse_fence: vk::Fence,
+    setup_commands_reuse_fence: vk::Fence,
+
+    current_frame: AtomicU64,
+}
+
+///
+/// Returned when the GPU didn't finish the frame in time.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaitTimeout {
+    pub frame: u64,
+    pub timeout: std::time::Duration,
+}
+
+impl std::fmt::Display for WaitTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "frame {} still running on the GPU after {:?}",
+            self.frame, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for WaitTimeout {}
+
+///
+/// Frame made by Renderer::prepare_frame, waiting for submit_frame. It can be moved to
+/// the thread that submits.
+///
+#[must_use = "prepared frames have to be passed to submit_frame"]
+pub struct PreparedFrame {
+    frame: u64,
+    present_index: u32,
+    default_attachment: Attachment,
+    stages: Vec<PreparedStage>,
+    offscreen: Vec<PreparedOffscreenPass>,
+    // Stats up to the preparation, the queues count into the next frame from there
+    stats: FrameStats,
+    // Acquired from a swapchain that no longer matches the surface exactly
+    is_suboptimal: bool,
+    // Acquired for this frame, shared presentable images only get acquired once
+    is_acquired: bool,
+}
+
+impl PreparedFrame {
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+///
+/// What render did with the frame. Skipped frames keep their queued tasks unless said
+/// otherwise, the next render picks them up.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOutcome {
+    Submitted,
+    // Submitted, but the swapchain no longer matches the surface exactly, see
+    // RendererConfig::suboptimal_frames_before_rebuild
+    Suboptimal,
+    // Nothing got rendered and the queued tasks were dropped, until the host calls resize
+    SwapchainOutOfDate,
+    // No swapchain image within RendererConfig::acquire_timeout, nothing got submitted
+    AcquireTimedOut,
+    // Nothing got rendered, until the host calls resume
+    Hibernated,
+}
+
+// Fails to compile if Renderer or PreparedFrame stop being movable to another thread.
+const _: () = {
+    fn assert_send<T: Send>() {}
+    let _ = assert_send::<Renderer>;
+    let _ = assert_send::<PreparedFrame>;
+};
+
+impl Renderer {
+    pub const ID_TEST_TRIANGLE: u32 = 0;
+    pub const TEST_TRIANGLE: MeshHandle = MeshHandle {
+        index: Self::ID_TEST_TRIANGLE,
+        generation: 0,
+    };
+    // Same triangle with its attributes interleaved, see VertexLayout::packed
+    pub const ID_TEST_TRIANGLE_INTERLEAVED: u32 = 1;
+    pub const TEST_TRIANGLE_INTERLEAVED: MeshHandle = MeshHandle {
+        index: Self::ID_TEST_TRIANGLE_INTERLEAVED,
+        generation: 0,
+    };
+    // Sampled in place of evicted textures
+    pub const ID_DEFAULT_TEXTURE: u32 = 0;
+    // Blue noise of RendererConfig::noise, see noise.rs
+    pub const ID_BLUE_NOISE: u32 = 1;
+    pub const BLUE_NOISE: TextureHandle = TextureHandle {
+        index: Self::ID_BLUE_NOISE,
+        generation: 0,
+    };
+    pub const ID_BLUE_NOISE_DUAL: u32 = 2;
+    pub const BLUE_NOISE_DUAL: TextureHandle = TextureHandle {
+        index: Self::ID_BLUE_NOISE_DUAL,
+        generation: 0,
+    };
+    // Single draw command buffer, see wait_for_frame_slot
+    pub const FRAMES_IN_FLIGHT: u32 = 1;
+    // Buffer every mesh and frame region comes out of, and the one of the descriptor tables
+    pub const GENERAL_ALLOCATOR_BYTES: u64 = 64 * 1024 * 1024;
+    pub const DESCRIPTOR_ALLOCATOR_BYTES: u64 = 1024 * 1024;
+    // Frames the frame ring watermarks in the frame stats look back over
+    pub const FRAME_RING_HISTORY: usize = 120;
+
+    ///
+    /// Renderer with its own surface, pipeline and frame state on top of a shared core.
+    /// Each one renders and presents on its own, destroying one leaves the others alone.
+    /// Texture and mesh ids are handed out by the core, so every renderer can draw the
+    /// meshes and sample the textures of the others, see CoreTables. They still belong to
+    /// the renderer that made them, freeing or evicting them is up to that one.
+    ///
+    /// The first renderer made with the core makes the tables and the builtin textures,
+    /// with its budget and noise config, and uploads the builtins with its first frame.
+    /// The others shouldn't render before that frame is submitted.
+    ///
+    pub fn with_core<F>(
+        core: Arc<RenderCore>,
+        config: RendererConfig,
+        pipeline_path: &str,
+        is_vsync_enabled: bool,
+        create_surface: F,
+    ) -> Renderer
+    where
+        F: FnOnce(&ash::Entry, &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>,
+    {
+        let output = ViewOutput::Surface {
+            create_surface,
+            is_vsync_enabled,
+        };
+        Self::with_core_output(core, config, pipeline_path, output)
+    }
+
+    ///
+    /// Same as with_core but without a surface, for previews and thumbnails of an editor
+    /// next to its main view. Frames render into an image of the extent standing in for
+    /// the swapchain one, in swapchain::HEADLESS_FORMAT. Nothing gets acquired or
+    /// presented, the frames can be read back or copied out like presented ones. resize
+    /// makes the image again at the new extent.
+    ///
+    pub fn with_core_headless(
+        core: Arc<RenderCore>,
+        config: RendererConfig,
+        pipeline_path: &str,
+        width: u32,
+        height: u32,
+    ) -> Renderer {
+        let output = ViewOutput::<CreateSurface>::Headless(vk::Extent2D { width, height });
+        Self::with_core_output(core, config, pipeline_path, output)
+    }
+
+    fn with_core_output<F>(
+        core: Arc<RenderCore>,
+        config: RendererConfig,
+        pipeline_path: &str,
+        output: ViewOutput<F>,
+    ) -> Renderer
+    where
+        F: FnOnce(&ash::Entry, &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>,
+    {
+        log::trace!("entering Renderer::with_core");
+        let vulkan_context = core.vulkan_context.clone();
+        let device = vulkan_context.device.clone();
+        let queue_family_index = core.queue_family_index;
+        let async_compute_family = core.async_compute_family;
+        let is_validation_layer_enabled = core.is_validation_layer_enabled;
+        let is_descriptor_buffer_enabled = core.is_descriptor_buffer_enabled();
+        // Dropped in reverse, what got made so far goes before the core if making it panics
+        let mut core = CoreOwner(Some(core));
+        // Made one at a time, the first one makes the builtins the others use
+        let creating = core.0.clone().unwrap();
+        let _creation = creating.lock_creation();
+        let mut cleanup = Cleanup::new();
+
+        // Extent only read without a surface
+        let (create_surface, is_vsync_enabled, headless_extent) = match output {
+            ViewOutput::Surface {
+                create_surface,
+                is_vsync_enabled,
+            } => (Some(create_surface), is_vsync_enabled, vk::Extent2D::default()),
+            ViewOutput::Headless(extent) => (None, false, extent),
+        };
+        let surface = create_surface.map(|create_surface| {
+            log::trace!("creating surface...");
+            let surface = create_surface(&vulkan_context.entry, &vulkan_context.instance)
+                .unwrap_or_else(|e| panic!("error creating surface: {}", e));
+            let surface_ext = vulkan_context.extension.surface.clone();
+            cleanup.defer(move || unsafe { surface_ext.destroy_surface(surface, None) });
+            let is_present_supported = unsafe {
+                vulkan_context
+                    .extension
+                    .surface
+                    .get_physical_device_surface_support(
+                        vulkan_context.physical_device,
+                        queue_family_index,
+                        surface,
+                    )
+                    .unwrap()
+            };
+            if !is_present_supported {
+                panic!(
+                    "queue family {} of the render core can't present to this surface!",
+                    queue_family_index
+                );
+            }
+            log::trace!("surface created!");
+            surface
+        });
+
+        log::trace!("creating command buffers...");
+        let present_queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+
+        let pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(queue_family_index);
+
+        let pool = unsafe { device.create_command_pool(&pool_create_info, None).unwrap() };
+        let pool_device = device.clone();
+        cleanup.defer(move || unsafe { pool_device.destroy_command_pool(pool, None) });
+
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_buffer_count(2)
+            .command_pool(pool)
+            .level(vk::CommandBufferLevel::PRIMARY);
+
+        let command_buffers = unsafe {
+            device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .unwrap()
+        };
+        let setup_command_buffer = command_buffers[0];
+        let draw_command_buffer = command_buffers[1];
+        log::trace!("command buffers created!");
+
+        let async_upload = async_compute_family.map(|e| AsyncUploadQueue::new(&device, e));
+        match &async_upload {
+            Some(e) => log::info!("async uploads on queue family {}", e.family_index),
+            None => log::info!("no async compute queue family, uploads on graphics"),
+        }
+        if let Some(e) = &async_upload {
+            let (async_upload, device) = (e.clone(), device.clone());
+            cleanup.defer(move || async_upload.destroy(&device));
+        }
+
+        log::trace!("creating fences...");
+        let fence_create_info =
+            vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+        let draw_commands_reuse_fence = unsafe {
+            device
+                .create_fence(&fence_create_info, None)
+                .expect("Create fence failed.")
+        };
+        let setup_commands_reuse_fence = unsafe {
+            device
+                .create_fence(&fence_create_info, None)
+                .expect("Create fence failed.")
+        };
+        let fence_device = device.clone();
+        cleanup.defer(move || unsafe {
+            fence_device.destroy_fence(draw_commands_reuse_fence, None);
+            fence_device.destroy_fence(setup_commands_reuse_fence, None);
+        });
+        log::trace!("fences created!");
+
+        log::trace!("creating semaphores...");
+        let semaphore_create_info = vk::SemaphoreCreateInfo::default();
+        let present_complete_semaphore = unsafe {
+            device
+                .create_semaphore(&semaphore_create_info, None)
+                .unwrap()
+        };
+        let rendering_complete_semaphore = unsafe {
+            device
+                .create_semaphore(&semaphore_create_info, None)
+                .unwrap()
+        };
+        let mut timeline_semaphore_type_create_info = vk::SemaphoreTypeCreateInfo::builder()
+            .initial_value(0)
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .build();
+        let timeline_semaphore_create_info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut timeline_semaphore_type_create_info)
+            .build();
+        let pass_timeline_semaphore = unsafe {
+            device
+                .create_semaphore(&timeline_semaphore_create_info, None)
+                .unwrap()
+        };
+        let frame_timeline_semaphore = unsafe {
+            device
+                .create_semaphore(&timeline_semaphore_create_info, None)
+                .unwrap()
+        };
+        let semaphore_device = device.clone();
+        cleanup.defer(move || {
+            for e in [
+                present_complete_semaphore,
+                rendering_complete_semaphore,
+                pass_timeline_semaphore,
+                frame_timeline_semaphore,
+            ] {
+                unsafe { semaphore_device.destroy_semaphore(e, None) };
+            }
+        });
+        log::trace!("semaphores created!");
+
+        log::trace!("creating allocators...");
+        let general_allocator =
+            DeviceAllocator::new_general(&vulkan_context, Self::GENERAL_ALLOCATOR_BYTES);
+        let mut descriptor_allocator = is_descriptor_buffer_enabled.then(|| {
+            DeviceAllocator::new_descriptor(&vulkan_context, Self::DESCRIPTOR_ALLOCATOR_BYTES)
+        });
+        for e in std::iter::once(&general_allocator).chain(&descriptor_allocator) {
+            let (allocator, device) = (e.clone(), device.clone());
+            cleanup.defer(move || allocator.destroy(&device));
+        }
+        // Lives as long as the general allocator, nothing to free on cleanup
+        let frame_ring = match general_allocator.alloc(config.frame_ring_bytes) {
+            Some(e) => FrameRing::new(e, Self::FRAMES_IN_FLIGHT, Self::FRAME_RING_HISTORY),
+            None => panic!(
+                "frame ring of {} bytes doesn't fit in the general buffer of {}!",
+                config.frame_ring_bytes,
+                Self::GENERAL_ALLOCATOR_BYTES
+            ),
+        };
+        log::trace!("allocators created!");
+
+        log::trace!("creating swapchain...");
+        let swapchain_context = match surface {
+            Some(surface) => swapchain::SwapchainContext::make(
+                &vulkan_context,
+                surface,
+                is_vsync_enabled,
+                config.low_latency_overlay,
+            ),
+            None => swapchain::SwapchainContext::make_headless(&vulkan_context, headless_extent),
+        };
+        let (swapchain, ctx) = (swapchain_context.clone(), vulkan_context.clone());
+        cleanup.defer(move || swapchain.destroy_swapchain(&ctx));
+        log::trace!("swapchain created!");
+
+        let scaled_target = config.internal_resolution.map(|(width, height)| {
+            scaling::make_target(
+                &vulkan_context,
+                swapchain_context.surface_format.format,
+                swapchain_context.unorm_format,
+                vk::Extent2D { width, height },
+            )
+        });
+        if let Some(e) = &scaled_target {
+            let (target, device) = (e.clone(), device.clone());
+            cleanup.defer(move || scaling::destroy_target(&device, &target));
+        }
+        #[cfg(feature = "builtin-passes")]
+        let scaling_stage = scaled_target.as_ref().map(|target| {
+            let format = swapchain_context.surface_format.format;
+            scaling::ScalingStage::new(&vulkan_context, target, format)
+        });
+        #[cfg(feature = "builtin-passes")]
+        if let Some(e) = &scaling_stage {
+            let (stage, device) = (e.clone(), device.clone());
+            cleanup.defer(move || stage.destroy(&device));
+        }
+
+        let mesh_uploads =
+            UploadScheduler::new(general_allocator.clone(), config.mesh_staging_bytes);
+        mesh_uploads.set_bounds_computed(config.is_mesh_bounds_computed);
+        mesh_uploads.set_retry_limit(config.retry_limit);
+
+        // Last, a pipeline file that's missing or doesn't compile is the likeliest panic
+        log::trace!("creating pipeline...");
+        let budget_limits = BudgetLimits::of(
+            &vulkan_context,
+            &general_allocator,
+            descriptor_allocator.as_ref(),
+            config.frame_ring_bytes,
+            Self::FRAMES_IN_FLIGHT,
+            config.expected_tasks_per_kind,
+        );
+        let mut pip = pipeline::file::Pipeline::load(
+            &creating,
+            descriptor_allocator.as_mut(),
+            scaled_target
+                .clone()
+                .unwrap_or_else(|| swapchain_context.attachments[0].clone()),
+            is_validation_layer_enabled,
+            Some(pipeline_path),
+            scaled_target.is_some(),
+            // No split screen viewports yet
+            1,
+            &budget_limits,
+        );
+        log::info!("{}", pip.budget());
+        pip.resolve_uninitialized_reads(config.uninitialized_reads);
+        log::trace!("pipeline created!");
+
+        let textures_by_id = HashMap::new();
+
+        let mut batches_by_task_type = Vec::with_capacity(TaskKind::MAX_SIZE + 1);
+        (0..TaskKind::MAX_LEN).for_each(|_| {
+            batches_by_task_type.push(Vec::new());
+        });
+
+        let stage_timer = StageTimer::new(&vulkan_context, queue_family_index, pip.total_stages());
+
+        log::trace!("finishing renderer...");
+        cleanup.disarm();
+        let mut renderer = Renderer {
+            pipeline: Box::new(pip),
+            pipeline_path: pipeline_path.to_string(),
+            scaled_target,
+            #[cfg(feature = "builtin-passes")]
+            scaling_stage,
+            batches_by_task_type,
+            task_sender: TaskSender::new(),
+            texture_loader: None,
+            loading_textures: Vec::new(),
+            prepared_frame: None,
+            display_timing: vulkan_context
+                .extension
+                .display_timing
+                .as_ref()
+                .filter(|_| surface.is_some())
+                .map(|_| DisplayTiming::new(present_timing::HISTORY_LEN)),
+            is_swapchain_rebuild_pending: false,
+            hibernation: None,
+            config,
+            stats: StatsTracker::new(stage_timer),
+            frame_paths: FramePaths::new(),
+            event_sink: Box::new(LogSink),
+            operations: Operations::default(),
+            state_cache: StateCache::default(),
+            #[cfg(feature = "fault-injection")]
+            faults: None,
+            core: core.0.take(),
+            frame_capture: FrameCapture::new(),
+            is_verbose_labels_enabled: false,
+            swapchain_context: Box::new(swapchain_context),
+            vulkan_context: Box::new(vulkan_context),
+            general_allocator: Box::new(general_allocator),
+            allocation_scope: None,
+            descriptor_allocator: descriptor_allocator.map(Box::new),
+            own_meshes: HashSet::new(),
+            textures_by_id,
+            freed_textures: Vec::new(),
+            texture_meta: HashMap::new(),
+            mesh_meta: HashMap::new(),
+            scene_slots_by_id: HashMap::new(),
+            scene_slot_generations: Generations::default(),
+            free_scene_slot_ids: Vec::new(),
+            textures_by_hash: HashMap::new(),
+            mesh_uploads,
+            evicted_images: Vec::new(),
+            reimported_textures: HashMap::new(),
+            texture_last_use: HashMap::new(),
+            dual_view_owners: HashMap::new(),
+            texture_memory_budget: None,
+            texture_restorer: None,
+            memory_snapshots: MemorySnapshotTracker::default(),
+            sampler_overrides: HashMap::new(),
+            sampler_policy: SamplerPolicy::default(),
+            pending_sampler_policy: None,
+            is_unpublished_sampler_warned: false,
+            quality_governor: None,
+            frame_budget: None,
+            viewports: Vec::new(),
+            quality_override: QualityOverride::default(),
+            ungoverned_lod_bias: 0.0,
+            inspected_texture: None,
+            image_pool: ImagePool::new(ImagePool::DEFAULT_BLOCK_SIZE),
+            draw_command_buffer,
+            present_queue,
+            setup_command_buffer,
+            rendering_complete_semaphore,
+            pass_timeline_semaphore,
+            frame_timeline_semaphore,
+            present_complete_semaphore,
+            setup_commands_reuse_fence,
+            draw_commands_reuse_fence,
+            pool,
+            optimal_transition_queue: Vec::new(),
+            ongoing_optimal_transitions: Vec::new(),
+            texture_region_updates: HashMap::new(),
+            attachment_texture_ids: HashMap::new(),
+            texture_retries: RetryQueue::new(),
+            transition_retries: RetryQueue::new(),
+            queued_readbacks: RetryQueue::new(),
+            next_readback_id: 0,
+            failed_work: Vec::new(),
+            async_upload,
+            pending_upload_wait: None,
+            interop_semaphore: None,
+            pending_external_wait: None,
+            external_value: 0,
+            sparse_semaphore: None,
+            sparse_value: 0,
+            pending_sparse_wait: None,
+            page_pools: Vec::new(),
+            unbound_pages: Vec::new(),
+            offscreen_passes: Vec::new(),
+            offscreen_pool: Vec::new(),
+            rendering_offscreen: Vec::new(),
+            queue_family_index,
+            ibl_baker: None,
+            pending_ibl_wait: None,
+            shader_resources: ShaderResources::new(),
+            frame_data: FrameData::new(FrameRegions::new(frame_ring)),
+            debug_flags: DebugFlags::default(),
+            has_noise_textures: false,
+            readbacks: None,
+            texture_feedback: None,
+            current_frame: AtomicU64::new(0),
+        };
+        if renderer.config.is_texture_feedback_enabled {
+            // A slot per id of the image table
+            let size = renderer.tables().image_descriptors.capacity() as u64
+                * texture_feedback::ENTRY_SIZE;
+            let buffer = match renderer.general_allocator.alloc(size) {
+                Some(e) => e,
+                None => panic!(
+                    "texture feedback of {} bytes doesn't fit in the general buffer!",
+                    size
+                ),
+            };
+            renderer.texture_feedback = Some(TextureFeedback::new(buffer));
+        }
+        // Builtins of the core, made by the first renderer
+        let has_builtins = renderer.tables().default_view.is_some();
+        if has_builtins {
+            let has_noise_textures = renderer.tables().has_noise_textures;
+            renderer.has_noise_textures = has_noise_textures;
+            log::trace!("renderer finished!");
+            return renderer;
+        }
+        // Reserve the texture ID 0 with a single transparent black texel
+        let default_texture = renderer.gen_texture(
+            "default_texture".to_string(),
+            Format::R8G8B8A8_UNORM,
+            &[MipMap {
+                index: 0,
+                size: 4,
+                offset: 0,
+                width: 1,
+                height: 1,
+            }],
+            4,
+        );
+        let staging = renderer.textures_by_id[&Self::ID_DEFAULT_TEXTURE]
+            .staging
+            .as_ref()
+            .unwrap();
+        unsafe { std::ptr::write_bytes(staging.addr as *mut u8, 0, 4) };
+        // First upload of the first frame, it's laid out before any stage samples it
+        renderer
+            .queue_texture_for_uploading(default_texture)
+            .unwrap();
+        renderer.tables().default_view =
+            Some(renderer.textures_by_id[&Self::ID_DEFAULT_TEXTURE].view);
+        if let Some((single, dual)) = renderer.config.noise.textures() {
+            // Set first, builtins get memory of their own
+            renderer.has_noise_textures = true;
+            renderer.register_noise(Self::ID_BLUE_NOISE, Format::R8_UNORM, &single);
+            renderer.register_noise(Self::ID_BLUE_NOISE_DUAL, Format::R8G8_UNORM, &dual);
+            renderer.tables().has_noise_textures = true;
+        }
+        renderer.reset_free_texture_slots();
+        log::trace!("renderer finished!");
+        renderer
+    }
+
+    /*
+     * Uploaded through the same staging path as any texture, right behind the default
+     * one.
+     */
+    fn register_noise(&mut self, id: u32, format: Format, noise: &NoiseTexture) {
+        noise.check(format);
+        let size = noise.texels.len() as u32;
+        let mip = MipMap {
+            index: 0,
+            size,
+            offset: 0,
+            width: noise.width,
+            height: noise.height,
+        };
+        let name = format!("blue_noise_{}", id);
+        let handle = self
+            .gen_texture_with_id(id, name, format, &[mip], size)
+            .unwrap_or_else(|e| panic!("{}", e));
+        let staging = self.textures_by_id[&id].staging.as_ref().unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                noise.texels.as_ptr(),
+                staging.addr as *mut u8,
+                noise.texels.len(),
+            )
+        };
+        self.queue_texture_for_uploading(handle).unwrap();
+    }
+
+    pub fn destroy(&mut self) {
+        log::trace!("destroying renderer...");
+        unsafe { self.vulkan_context.device.device_wait_idle().unwrap() };
+        let mut operations = std::mem::take(&mut self.operations);
+        operations.cancel_all(self);
+        self.operations = operations;
+        self.emit_operation_events();
+        self.log_alive_resources();
+        // Other renderers of the core sample the builtins, it destroys them
+        let builtin_ids = [
+            Self::ID_DEFAULT_TEXTURE,
+            Self::ID_BLUE_NOISE,
+            Self::ID_BLUE_NOISE_DUAL,
+        ];
+        let builtins: Vec<_> = builtin_ids
+            .into_iter()
+            .filter(|e| self.is_builtin_texture(*e))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|e| self.textures_by_id.remove(&e))
+            .collect();
+        self.tables().builtin_textures.extend(builtins);
+        let textures: Vec<_> = self.textures_by_id.drain().map(|e| e.1).collect();
+        {
+            let mut tables = self.tables();
+            for texture in &textures {
+                tables.texture_generations.bump(texture.id);
+                if let Some(srgb) = &texture.srgb {
+                    tables.texture_generations.bump(srgb.id);
+                }
+            }
+        }
+        self.freed_textures.extend(textures);
+        let replaced: Vec<_> = self.reimported_textures.drain().map(|e| e.1).collect();
+        self.evicted_images.extend(replaced);
+        self.release_freed_textures();
+        self.loading_textures.clear();
+        if let Some(loader) = &self.texture_loader {
+            loader.close(&self.vulkan_context.device);
+        }
+        let fallback = self.default_texture_descriptor();
+        let core = self.shared_core();
+        let mut tables = core.lock_tables();
+        let attachment_ids = self.attachment_texture_ids.drain().map(|e| e.1);
+        let pooled_ids: Vec<_> = self.offscreen_pool.iter().map(|e| e.id).collect();
+        for id in attachment_ids.chain(pooled_ids) {
+            tables
+                .image_descriptors
+                .reset_image_at(&self.vulkan_context, id, fallback);
+        }
+        tables.image_descriptors.flush(&self.vulkan_context);
+        for id in self.own_meshes.drain() {
+            tables.meshes.remove(&id);
+            tables.mesh_ids.set(id as usize, false);
+            tables.uploading_meshes.remove(&id);
+            tables.mesh_generations.bump(id);
+        }
+        drop(tables);
+        for texture in self.offscreen_pool.drain(..) {
+            texture.destroy(&self.vulkan_context.device, Some(&mut self.image_pool));
+        }
+        self.image_pool.destroy(&self.vulkan_context.device);
+        self.unbound_pages.clear();
+        if let Some(baker) = &mut self.ibl_baker {
+            baker.destroy(&self.vulkan_context.device);
+        }
+        for pool in &mut self.page_pools {
+            pool.destroy(&self.vulkan_context.device);
+        }
+        self.pipeline.destroy(&self.vulkan_context.device);
+        if let Some(target) = &self.scaled_target {
+            scaling::destroy_target(&self.vulkan_context.device, target);
+        }
+        #[cfg(feature = "builtin-passes")]
+        if let Some(stage) = &self.scaling_stage {
+            stage.destroy(&self.vulkan_context.device);
+        }
+        self.frame_paths.reset(&self.vulkan_context.device);
+        self.stats.destroy(&self.vulkan_context.device);
+        for e in std::iter::once(&self.general_allocator).chain(&self.descriptor_allocator) {
+            e.destroy(&self.vulkan_context.device);
+        }
+        if let Some(readbacks) = &self.readbacks {
+            readbacks.destroy(&self.vulkan_context.device);
+        }
+        unsafe {
+            let destroy_semaphore = |s| self.vulkan_context.device.destroy_semaphore(s, None);
+            let destroy_fence = |s| self.vulkan_context.device.destroy_fence(s, None);
+            self.vulkan_context.device.device_wait_idle().unwrap();
+            destroy_semaphore(self.present_complete_semaphore);
+            destroy_semaphore(self.rendering_complete_semaphore);
+            destroy_semaphore(self.pass_timeline_semaphore);
+            destroy_semaphore(self.frame_timeline_semaphore);
+            if let Some(semaphore) = self.interop_semaphore {
+                destroy_semaphore(semaphore);
+            }
+            if let Some(semaphore) = self.sparse_semaphore {
+                destroy_semaphore(semaphore);
+            }
+            destroy_fence(self.draw_commands_reuse_fence);
+            destroy_fence(self.setup_commands_reuse_fence);
+            if let Some(async_upload) = &self.async_upload {
+                async_upload.destroy(&self.vulkan_context.device);
+            }
+            self.vulkan_context
+                .device
+                .destroy_command_pool(self.pool, None);
+            self.swapchain_context.destroy(&self.vulkan_context);
+        }
+        log::trace!("renderer destroyed!");
+        // Last one holding the core takes it down too
+        if let Some(mut core) = self.core.take().and_then(|e| Arc::try_unwrap(e).ok()) {
+            core.destroy_owned();
+        }
+    }
+
+    /*
+     * Textures and meshes the host never freed, destroy takes them down anyway. Logged
+     * with their metadata so tooling can trace them back to the assets.
+     */
+    fn log_alive_resources(&self) {
+        if !log::log_enabled!(log::Level::Debug) {
+            return;
+        }
+        let mut texture_ids: Vec<_> = self
+            .textures_by_id
+            .keys()
+            .filter(|e| !self.is_builtin_texture(**e))
+            .collect();
+        texture_ids.sort();
+        for id in texture_ids {
+            log::debug!(
+                "texture {} {} still alive on destroy, {:?}",
+                id,
+                self.textures_by_id[id].name,
+                self.texture_meta.get(id)
+            );
+        }
+        let mut mesh_ids: Vec<_> = self.own_meshes.iter().collect();
+        mesh_ids.sort();
+        for id in mesh_ids {
+            log::debug!(
+                "mesh {} still alive on destroy, {:?}",
+                id,
+                self.mesh_meta.get(id)
+            );
+        }
+    }
+
+    ///
+    /// Queues the task for the next frame, tasks over budget are dropped and only show up
+    /// in the frame stats. See try_add_task_to_queue.
+    ///
+    pub fn add_task_to_queue(&mut self, task: RenderTask) {
+        let _ = self.try_add_task_to_queue(task);
+    }
+
+    pub fn try_add_task_to_queue(&mut self, mut task: RenderTask) -> Result<(), TaskRejected> {
+        let kind = task.kind;
+        if !self.is_mesh_current(task.mesh) {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            return Err(TaskRejected::StaleMesh { mesh: task.mesh });
+        }
+        if self.is_mesh_uploading(task.mesh.index) {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            self.stats.queued.uploading_mesh_tasks += 1;
+            return Err(TaskRejected::MeshUploading { mesh: task.mesh });
+        }
+        if let Err(rejected) = self.check_scene_slots(&task) {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            return Err(rejected);
+        }
+        // Fullscreen stages don't read the vertices
+        let (layout, formats) = {
+            let tables = self.tables();
+            let mesh = &tables.meshes[&task.mesh.index];
+            (mesh.layout_kind(), mesh.formats)
+        };
+        if kind != TaskKind::Fullscreen && !self.pipeline.accepts_vertex_layout(kind, layout) {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            return Err(TaskRejected::VertexLayout {
+                kind,
+                mesh: task.mesh,
+                layout,
+            });
+        }
+        let rejected_format = match kind {
+            TaskKind::Fullscreen => None,
+            _ => self.pipeline.rejected_vertex_format(kind, &formats),
+        };
+        if let Some((attribute, format)) = rejected_format {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            return Err(TaskRejected::VertexFormat {
+                kind,
+                mesh: task.mesh,
+                attribute,
+                format,
+            });
+        }
+        if task.bounds == TaskBounds::FromMesh {
+            task.bounds = match self.mesh_bounds(task.mesh) {
+                Some(bounds) => RenderTask::bounds_of(&bounds, &task.resources),
+                None => TaskBounds::None,
+            };
+        }
+        if let Some(depth) = RenderTask::view_depth_of(&task.resources) {
+            task.view_depth = depth;
+        } else if kind == TaskKind::Translucent {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            return Err(TaskRejected::MissingResource {
+                kind,
+                resource: ResourceKind::Transform,
+            });
+        }
+        let current_frame = self.queued_frame();
+        let queued_total: usize = self.batches_by_task_type.iter().map(|e| e.len()).sum();
+        let queued = self.batches_by_task_type[kind.to_usize()].len();
+        if let Err(rejected) = self.config.check_task_budget(kind, queued, queued_total) {
+            self.stats.queued.rejected_by_kind[kind.to_usize()] += 1;
+            return Err(rejected);
+        }
+        if let Some(MultiResource::Material(materials)) =
+            task.resources.get_mut(&ResourceKind::Material)
+        {
+            for material in materials {
+                if !self.sampler_overrides.is_empty() {
+                    material.override_samplers(&self.sampler_overrides);
+                }
+                self.fall_back_unpublished_samplers(material);
+            }
+        }
+        // Textures referenced this frame are never evicted
+        if let Some(MultiResource::Material(materials)) =
+            task.resources.get(&ResourceKind::Material)
+        {
+            for material in materials {
+                for id in [
+                    material.diffuse_handle,
+                    material.normal_handle,
+                    material.glow_handle,
+                ] {
+                    let id = self.dual_view_owners.get(&id).copied().unwrap_or(id);
+                    self.texture_last_use.insert(id, current_frame);
+                }
+            }
+        }
+        self.batches_by_task_type[kind.to_usize()].push(task);
+        self.stats.queued.accepted_by_kind[kind.to_usize()] += 1;
+        Ok(())
+    }
+
+    /*
+     * Every scene slot of the task has to be current and hold its resource for exactly
+     * as many instances as the task draws.
+     */
+    fn check_scene_slots(&self, task: &RenderTask) -> Result<(), TaskRejected> {
+        for (resource, e) in &task.resources {
+            let slot = match e {
+                MultiResource::SceneSlot(slot) => slot,
+                _ => continue,
+            };
+            let size = self
+                .scene_slot(*slot)
+                .ok_or(TaskRejected::StaleSceneSlot { slot: *slot })?
+                .size;
+            let expected = (resource.resource_size() * task.instance_count as usize) as u64;
+            if size != expected {
+                return Err(TaskRejected::SceneSlotSize {
+                    resource: *resource,
+                    slot: *slot,
+                    expected,
+                    found: size,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> &RendererConfig {
+        &self.config
+    }
+
+    ///
+    /// Filter for scaling the internal resolution to the window from the next frame on,
+    /// does nothing without one set in the config.
+    ///
+    pub fn set_upscale_filter(&mut self, filter: UpscaleFilter) {
+        self.config.upscale_filter = filter;
+    }
+
+    ///
+    /// Size the stages render at, the one of the swapchain without an internal
+    /// resolution.
+    ///
+    pub fn render_extent(&self) -> vk::Extent2D {
+        self.scaled_target
+            .as_ref()
+            .map_or(self.swapchain_context.surface_extent, |e| e.extent)
+    }
+
+    ///
+    /// New budgets apply to tasks queued from now on.
+    ///
+    pub fn set_config(&mut self, config: RendererConfig) {
+        self.mesh_uploads
+            .set_staging_capacity(config.mesh_staging_bytes);
+        self.mesh_uploads
+            .set_bounds_computed(config.is_mesh_bounds_computed);
+        self.mesh_uploads.set_retry_limit(config.retry_limit);
+        self.config = config;
+    }
+
+    ///
+    /// Replaces where the events of the renderer go, LogSink by default.
+    ///
+    pub fn set_event_sink(&mut self, sink: Box<dyn RenderEventSink>) {
+        self.event_sink = sink;
+    }
+
+    ///
+    /// Events kept by the sink since the last call, none unless it keeps them like
+    /// RingBufferSink does.
+    ///
+    pub fn drain_events(&mut self) -> Vec<RenderEvent> {
+        self.event_sink.drain()
+    }
+
+    ///
+    /// Runs the operation a step at a time from now on, within
+    /// RendererConfig::operation_budget at the start of every frame and within the budget
+    /// of every pump_operations call. Its events go to the event sink.
+    ///
+    pub fn start_operation(&mut self, operation: Box<dyn Operation<Renderer>>) -> OperationHandle {
+        let handle = self.operations.start(operation);
+        self.emit_operation_events();
+        handle
+    }
+
+    ///
+    /// Steps the running operations until they are all done or blocked or the budget is
+    /// spent, on top of the stepping every frame does. Takes at least one step.
+    ///
+    pub fn pump_operations(&mut self, budget: Duration) {
+        let mut operations = std::mem::take(&mut self.operations);
+        if self.config.deterministic.is_some() {
+            operations.pump_turn(self);
+        } else {
+            operations.pump(self, budget);
+        }
+        // Started by the steps meanwhile
+        let started = std::mem::replace(&mut self.operations, operations);
+        self.operations.append(started);
+        self.emit_operation_events();
+    }
+
+    fn emit_operation_events(&mut self) {
+        for event in self.operations.take_events() {
+            self.event_sink.emit(event);
+        }
+    }
+
+    /*
+     * Drops the newest tasks until the worst case draw data of the frame fits in the
+     * frame ring, otherwise the allocations fail in the middle of recording.
+     */
+    fn trim_tasks_to_fit(&mut self) {
+        let ring = self.frame_data.regions.ring();
+        let alignment = ring.alignment();
+        let stages = &self.pipeline.stages;
+        // Draw data of the previous frame using this region is already reset
+        let budget = ring.guaranteed_available();
+        let per_pass_size = frame_data::per_pass_size_of(stages, alignment);
+        let task_size = |task: &RenderTask| frame_data::task_size_of(stages, alignment, task);
+        let task_sizes: Vec<Vec<u64>> = self
+            .batches_by_task_type
+            .iter()
+            .map(|e| e.iter().map(task_size).collect())
+            .collect();
+        let required_size = per_pass_size + task_sizes.iter().flatten().sum::<u64>();
+        if required_size <= budget {
+            return;
+        }
+        let trimmed = config::tasks_to_trim(&task_sizes, per_pass_size, budget);
+        for (kind, count) in trimmed.into_iter().enumerate() {
+            let batch = &mut self.batches_by_task_type[kind];
+            batch.truncate(batch.len() - count);
+            self.stats.queued.trimmed_by_kind[kind] += count as u32;
+        }
+        let trimmed: Vec<_> = self
+            .stats
+            .queued
+            .trimmed_by_kind
+            .iter()
+            .enumerate()
+            .filter(|e| *e.1 > 0)
+            .map(|(kind, count)| format!("{} {}", TaskKind::of_usize(kind), count))
+            .collect();
+        log::warn!(
+            "frame {}: draw data needs {} bytes but only {} are available, trimmed tasks: {}",
+            self.get_current_frame(),
+            required_size,
+            budget,
+            trimmed.join(", ")
+        );
+    }
+
+    pub fn queued_task_count(&self, kind: TaskKind) -> usize {
+        self.batches_by_task_type[kind.to_usize()].len()
+    }
+
+    ///
+    /// Tasks of the kind queued for the next frame, empty right after render.
+    ///
+    pub fn queued_tasks(&self, kind: TaskKind) -> impl Iterator<Item = &RenderTask> {
+        self.batches_by_task_type[kind.to_usize()].iter()
+    }
+
+    ///
+    /// Drops the queued tasks of the kind, or of every kind with None, without rendering
+    /// them. They still count as accepted in the frame stats.
+    ///
+    pub fn clear_queued_tasks(&mut self, kind: Option<TaskKind>) {
+        match kind {
+            Some(kind) => self.batches_by_task_type[kind.to_usize()].clear(),
+            None => self.batches_by_task_type.iter_mut().for_each(|e| e.clear()),
+        }
+    }
+
+    pub fn try_get_sampler(&self, key: SamplerKey) -> Option<u8> {
+        self.tables().samplers_by_key.get(&key).map(|s| s.position)
+    }
+
+    ///
+    /// Id of the sampler for the key, made if there is none yet. Fails once every id up
+    /// to the sampler capacity is taken.
+    ///
+    /// Descriptors of new samplers get written when the next frame is prepared, never
+    /// while a frame in flight reads the table. Tasks queued before then sample with
+    /// Pipeline::fallback_sampler instead, the id is valid for tasks of the next frame
+    /// on, see is_sampler_published.
+    ///
+    pub fn get_sampler(&mut self, key: SamplerKey) -> Result<u8, SamplersExhausted> {
+        // Held throughout, other renderers of the core make samplers in the same table
+        let mut tables = self.tables();
+        if let Some(e) = tables.samplers_by_key.get(&key) {
+            return Ok(e.position);
+        }
+        //  Sampler for this key not found, generate one
+        let id = Sampler::next_id(tables.samplers_by_key.len(), tables.sampler_capacity)?;
+        let name = format!("{}", id);
+        let sampler = Sampler::of_key(&self.vulkan_context, name, key, &self.sampler_policy, id);
+        //  store it for later querying
+        tables.samplers_by_key.insert(key, sampler.clone());
+        // Only in host memory until publish_samplers
+        tables
+            .sampler_descriptors
+            .place_sampler_at(&self.vulkan_context, id as u32, sampler.sampler);
+        tables.unpublished_samplers.push(id);
+        // Return the ID for referencing on the client side
+        Ok(id)
+    }
+
+    ///
+    /// Whether tasks queued now can sample with the sampler, false for ids get_sampler
+    /// made since the last frame was prepared and ids it never made.
+    ///
+    pub fn is_sampler_published(&self, id: u8) -> bool {
+        let tables = self.tables();
+        (id as usize) < tables.samplers_by_key.len() && !tables.unpublished_samplers.contains(&id)
+    }
+
+    /*
+     * Called once the previous frame is done, like apply_sampler_policy, so no frame in
+     * flight reads the table while the new descriptors get written.
+     */
+    fn publish_samplers(&mut self) {
+        let mut tables = self.tables();
+        let CoreTables {
+            sampler_descriptors,
+            unpublished_samplers,
+            ..
+        } = &mut *tables;
+        for id in unpublished_samplers.drain(..) {
+            sampler_descriptors.flush_single(&self.vulkan_context, id as u32);
+        }
+    }
+
+    /*
+     * Swaps the samplers of the material the shaders couldn't read yet for the fallback
+     * one. u8::MAX is no sampler at all and stays.
+     */
+    fn fall_back_unpublished_samplers(&mut self, material: &mut Material) {
+        let fallback = self.pipeline.fallback_sampler;
+        for sampler in [
+            &mut material.diffuse_sampler,
+            &mut material.normal_sampler,
+            &mut material.glow_sampler,
+        ] {
+            if *sampler == u8::MAX || self.is_sampler_published(*sampler) {
+                continue;
+            }
+            if !self.is_unpublished_sampler_warned {
+                log::warn!(
+                    "task samples with sampler {} before it's published, sampling with {} instead until the next frame",
+                    sampler,
+                    fallback
+                );
+                self.is_unpublished_sampler_warned = true;
+            }
+            *sampler = fallback;
+        }
+    }
+
+    ///
+    /// Copy of the buffers of the mesh, meshes of every renderer of the core are in
+    /// there.
+    ///
+    pub fn fetch_mesh(&self, handle: MeshHandle) -> Option<MeshBuffer> {
+        let tables = self.tables();
+        if !tables
+            .mesh_generations
+            .is_current(handle.index, handle.generation)
+        {
+            return None;
+        }
+        tables.meshes.get(&handle.index).cloned()
+    }
+
+    pub fn fetch_mesh_or_fail(&self, handle: MeshHandle) -> MeshBuffer {
+        self.fetch_mesh(handle)
+            .unwrap_or_else(|| panic!("couldn't find mesh with handle {}", handle))
+    }
+
+    fn is_mesh_current(&self, handle: MeshHandle) -> bool {
+        self.tables()
+            .mesh_generations
+            .is_current(handle.index, handle.generation)
+    }
+
+    ///
+    /// Frees the buffers of the mesh. Tasks queued with it, offscreen passes included,
+    /// get dropped since the slot may hold another mesh by the time they'd render. Panics
+    /// if another renderer of the core made the mesh, only that one can free it.
+    ///
+    pub fn free_mesh(&mut self, handle: MeshHandle) -> Result<(), StaleHandle> {
+        if !self.is_mesh_current(handle) {
+            return Err(StaleHandle);
+        }
+        let id = handle.index;
+        if !self.own_meshes.contains(&id) {
+            panic!("mesh {} belongs to another renderer of the core!", handle);
+        }
+        // Frames of the others may still draw it
+        self.wait_other_renderers();
+        let mesh = self.tables().meshes.remove(&id).ok_or(StaleHandle)?;
+        for batch in &mut self.batches_by_task_type {
+            batch.retain(|e| e.mesh.index != id);
+        }
+        for pass in &mut self.offscreen_passes {
+            pass.tasks.retain(|e| e.mesh.index != id);
+        }
+        let free_if_not_empty = |v: &DeviceSlice| {
+            if v.size > 0 {
+                self.general_allocator.free(v.clone());
+            }
+        };
+        free_if_not_empty(&mesh.vertices);
+        free_if_not_empty(&mesh.normals);
+        free_if_not_empty(&mesh.tex_coords);
+        free_if_not_empty(&mesh.indices);
+        self.mesh_uploads.remove_mesh(id);
+        self.mesh_meta.remove(&id);
+        self.own_meshes.remove(&id);
+        let mut tables = self.tables();
+        tables.uploading_meshes.remove(&id);
+        tables.mesh_ids.set(id as usize, false);
+        tables.mesh_generations.bump(id);
+        Ok(())
+    }
+
+    ///
+    /// Copies the bytes into a slot that stays on the GPU across frames. Tasks reference
+    /// it with MultiResource::SceneSlot in place of the resource of all their instances,
+    /// so nothing gets copied for them each frame. Panics if the bytes are empty or don't
+    /// fit in the general allocator.
+    ///
+    pub fn create_scene_slot(&mut self, bytes: &[u8]) -> SceneSlotId {
+        if bytes.is_empty() {
+            panic!("scene slots can't be empty!");
+        }
+        let index = self
+            .free_scene_slot_ids
+            .pop()
+            .unwrap_or(self.scene_slots_by_id.len() as u32);
+        let id = SceneSlotId {
+            index,
+            generation: self.scene_slot_generations.of(index),
+        };
+        match SceneSlot::of(id, &self.general_allocator, bytes) {
+            Some(slot) => self.scene_slots_by_id.insert(index, slot),
+            None => {
+                self.free_scene_slot_ids.push(index);
+                let available = self.general_allocator.available();
+                self.alloc_failed(bytes.len() as u64, "scene_slot", available)
+            }
+        };
+        id
+    }
+
+    ///
+    /// Replaces the data of the slot, frames rendered from now on read the new bytes. The
+    /// ones already submitted keep reading the previous bytes, released once they're
+    /// done. Only as many bytes as the slot was made with are accepted.
+    ///
+    pub fn update_scene_slot(
+        &mut self,
+        id: SceneSlotId,
+        bytes: &[u8],
+    ) -> Result<(), SceneSlotRejected> {
+        let size = self
+            .scene_slot(id)
+            .ok_or(SceneSlotRejected::Stale { slot: id })?
+            .size;
+        if size != bytes.len() as u64 {
+            return Err(SceneSlotRejected::SizeMismatch {
+                slot: id,
+                expected: size,
+                found: bytes.len() as u64,
+            });
+        }
+        let slot = match SceneSlot::of(id, &self.general_allocator, bytes) {
+            Some(e) => e,
+            None => {
+                let available = self.general_allocator.available();
+                self.alloc_failed(size, "scene_slot", available)
+            }
+        };
+        let previous = self.scene_slots_by_id.insert(id.index, slot).unwrap();
+        // Freed once the GPU is done with the frames that might read it
+        self.frame_data.buffers.push(previous.buffer);
+        Ok(())
+    }
+
+    ///
+    /// Tasks queued with the slot have to be rendered before freeing it, its memory gets
+    /// released once the frames in flight are done.
+    ///
+    pub fn free_scene_slot(&mut self, id: SceneSlotId) -> Result<(), StaleHandle> {
+        if self.scene_slot(id).is_none() {
+            return Err(StaleHandle);
+        }
+        let slot = self.scene_slots_by_id.remove(&id.index).unwrap();
+        self.frame_data.buffers.push(slot.buffer);
+        self.scene_slot_generations.bump(id.index);
+        self.free_scene_slot_ids.push(id.index);
+        Ok(())
+    }
+
+    pub fn is_scene_slot_current(&self, id: SceneSlotId) -> bool {
+        self.scene_slot(id).is_some()
+    }
+
+    fn scene_slot(&self, id: SceneSlotId) -> Option<&SceneSlot> {
+        self.scene_slots_by_id.get(&id.index).filter(|e| e.id == id)
+    }
+
+    ///
+    /// Writes the attribute of the mesh in chunks copied over the next frames, for data
+    /// too big to write in one go. Tasks with the mesh get rejected until all of its
+    /// uploads are complete.
+    ///
+    pub fn begin_mesh_upload(
+        &mut self,
+        handle: MeshHandle,
+        attribute: MeshAttribute,
+    ) -> Result<MeshUploadCursor, StaleHandle> {
+        let mesh = self.fetch_mesh(handle).ok_or(StaleHandle)?;
+        if !self.own_meshes.contains(&handle.index) {
+            panic!("mesh {} belongs to another renderer of the core!", handle);
+        }
+        let dst = attribute.of(&mesh);
+        let position_stride = mesh.position_stride();
+        self.tables().uploading_meshes.insert(handle.index);
+        Ok(self
+            .mesh_uploads
+            .begin(handle.index, attribute, dst, position_stride))
+    }
+
+    ///
+    /// Same as begin_mesh_upload writing all of the data, as an operation taking a chunk
+    /// of it per step. The data has to fill the attribute.
+    ///
+    pub fn upload_mesh_in_steps(
+        &mut self,
+        handle: MeshHandle,
+        attribute: MeshAttribute,
+        data: Vec<u8>,
+    ) -> Result<OperationHandle, StaleHandle> {
+        let cursor = self.begin_mesh_upload(handle, attribute)?;
+        let operation = MeshUploadOperation::new(handle, cursor, data);
+        Ok(self.start_operation(Box::new(operation)))
+    }
+
+    ///
+    /// Whether every upload begun for the mesh finished and the GPU copied it all.
+    ///
+    pub fn is_upload_complete(&self, handle: MeshHandle) -> Result<bool, StaleHandle> {
+        if !self.is_mesh_current(handle) {
+            return Err(StaleHandle);
+        }
+        Ok(!self.is_mesh_uploading(handle.index))
+    }
+
+    fn is_mesh_uploading(&self, id: u32) -> bool {
+        // Skips the scheduler lock for the meshes written in one go
+        if !self.tables().uploading_meshes.contains(&id) {
+            return false;
+        }
+        // Meshes of other renderers upload until the one they belong to sees them done
+        !self.own_meshes.contains(&id)
+            || self.mesh_uploads.is_uploading(id, self.completed_frames())
+    }
+
+    pub(crate) fn completed_frames(&self) -> u64 {
+        unsafe {
+            self.vulkan_context
+                .device
+                .get_semaphore_counter_value(self.frame_timeline_semaphore)
+                .unwrap()
+        }
+    }
+
+    pub fn gen_mesh(
+        &mut self,
+        vertices_size: u32,
+        normals_size: u32,
+        tex_coords_size: u32,
+        indices_size: u32,
+        count: u32,
+    ) -> MeshHandle {
+        // Reserve mesh id
+        let mesh_id = {
+            let mut tables = self.tables();
+            let id = tables.mesh_ids.first_zero().expect("ran out of mesh ids!");
+            tables.mesh_ids.set(id, true);
+            id as u32
+        };
+        self.gen_mesh_at(
+            mesh_id,
+            vertices_size,
+            normals_size,
+            tex_coords_size,
+            indices_size,
+            count,
+        )
+    }
+
+    ///
+    /// Same as gen_mesh but with the id picked by the caller, so the same sequence of
+    /// calls hands out the same ids regardless of what the renderer allocated internally.
+    ///
+    pub fn gen_mesh_with_id(
+        &mut self,
+        id: u32,
+        vertices_size: u32,
+        normals_size: u32,
+        tex_coords_size: u32,
+        indices_size: u32,
+        count: u32,
+    ) -> Result<MeshHandle, IdUnavailable> {
+        {
+            let mut tables = self.tables();
+            if tables.mesh_ids.get(id as usize).as_deref() != Some(&false) {
+                return Err(IdUnavailable { id });
+            }
+            tables.mesh_ids.set(id as usize, true);
+        }
+        Ok(self.gen_mesh_at(
+            id,
+            vertices_size,
+            normals_size,
+            tex_coords_size,
+            indices_size,
+            count,
+        ))
+    }
+
+    /*
+     * Buffers of a mesh at the id, taken by the caller already.
+     */
+    fn gen_mesh_at(
+        &mut self,
+        mesh_id: u32,
+        vertices_size: u32,
+        normals_size: u32,
+        tex_coords_size: u32,
+        indices_size: u32,
+        count: u32,
+    ) -> MeshHandle {
+        let sizes = [
+            (vertices_size, "vertex"),
+            (normals_size, "normal"),
+            (tex_coords_size, "tex_coord"),
+            (indices_size, "index"),
+        ];
+        let allocator = self.caller_allocator();
+        let mut slices = [DeviceSlice::empty(); 4];
+        for (i, (size, purpose)) in sizes.into_iter().enumerate() {
+            if size == 0 {
+                continue;
+            }
+            match allocator.alloc(size as u64) {
+                Some(e) => slices[i] = e,
+                None => {
+                    // Nothing of the mesh stays allocated if the host catches the panic
+                    for e in slices.into_iter().filter(|e| !e.is_empty()) {
+                        allocator.free(e);
+                    }
+                    self.tables().mesh_ids.set(mesh_id as usize, false);
+                    self.alloc_failed(size as u64, purpose, allocator.available())
+                }
+            }
+        }
+        let [vertices, normals, tex_coords, indices] = slices;
+
+        self.own_meshes.insert(mesh_id);
+        let mut tables = self.tables();
+        tables.meshes.insert(
+            mesh_id,
+            MeshBuffer {
+                vertices,
+                normals,
+                tex_coords,
+                indices,
+                count,
+                bounds: None,
+                bounds_inflation: 1.0,
+                layout: None,
+                formats: StreamFormats::default(),
+            },
+        );
+
+        MeshHandle {
+            index: mesh_id,
+            generation: tables.mesh_generations.of(mesh_id),
+        }
+    }
+
+    ///
+    /// Same as gen_mesh, keeping the metadata until the mesh is freed, see mesh_meta.
+    ///
+    pub fn gen_mesh_with_meta(
+        &mut self,
+        vertices_size: u32,
+        normals_size: u32,
+        tex_coords_size: u32,
+        indices_size: u32,
+        count: u32,
+        meta: ResourceMeta,
+    ) -> MeshHandle {
+        let handle = self.gen_mesh(
+            vertices_size,
+            normals_size,
+            tex_coords_size,
+            indices_size,
+            count,
+        );
+        self.mesh_meta.insert(handle.index, meta);
+        handle
+    }
+
+    ///
+    /// Generates a mesh holding the data, after running the optimizations of the flags on
+    /// a copy of it, see mesh_opt. 16 bit indices get widened, the renderer draws with 32
+    /// bit ones. Quantized meshes can only be drawn by stages reading their formats, see
+    /// TaskRejected::VertexFormat.
+    ///
+    pub fn gen_mesh_with_data(
+        &mut self,
+        data: &MeshData,
+        flags: MeshOptFlags,
+    ) -> (MeshHandle, MeshOptReport) {
+        let mut mesh = data.pack();
+        let report = mesh_opt::optimize(&mut mesh, flags);
+        let indices = mesh.indices.as_deref().unwrap_or_default();
+        let handle = self.gen_mesh(
+            mesh.streams[0].0.len() as u32,
+            mesh.streams[1].0.len() as u32,
+            mesh.streams[2].0.len() as u32,
+            std::mem::size_of_val(indices) as u32,
+            mesh.count(),
+        );
+        let mut tables = self.tables();
+        let buffer = tables.meshes.get_mut(&handle.index).unwrap();
+        buffer.formats = mesh.formats;
+        if self.config.is_mesh_bounds_computed {
+            let (stride, _, format) = buffer.position_stride();
+            let positions = vertex::decode_positions(&mesh.streams[0].0, stride, 0, format);
+            buffer.bounds = MeshBounds::of_positions(&positions);
+        }
+        let copy_into = |src: &[u8], dst: &DeviceSlice| {
+            // Missing attributes have no buffer to copy into
+            if !src.is_empty() {
+                unsafe {
+                    std::ptr::copy_nonoverlapping(src.as_ptr(), dst.addr as *mut u8, src.len())
+                }
+            }
+        };
+        copy_into(&mesh.streams[0].0, &buffer.vertices);
+        copy_into(&mesh.streams[1].0, &buffer.normals);
+        copy_into(&mesh.streams[2].0, &buffer.tex_coords);
+        let (_, index_bytes, _) = unsafe { indices.align_to::<u8>() };
+        copy_into(index_bytes, &buffer.indices);
+        (handle, report)
+    }
+
+    ///
+    /// Generates a mesh with its attributes interleaved in one buffer, vertex after vertex
+    /// as the layout describes them, and 32 bit indices if any. Only stages accepting
+    /// interleaved meshes in pipeline.json can draw it, see TaskRejected::VertexLayout.
+    /// Panics if the data isn't a whole number of vertices.
+    ///
+    pub fn gen_mesh_interleaved(
+        &mut self,
+        layout: VertexLayout,
+        data: &[u8],
+        indices: &[u8],
+        count: u32,
+    ) -> MeshHandle {
+        let stride = layout.stride() as usize;
+        if !data.len().is_multiple_of(stride) || !indices.len().is_multiple_of(4) {
+            panic!(
+                "{} bytes of vertices with a stride of {} or {} bytes of indices aren't whole!",
+                data.len(),
+                stride,
+                indices.len()
+            );
+        }
+        let handle = self.gen_mesh(data.len() as u32, 0, 0, indices.len() as u32, count);
+        let mut tables = self.tables();
+        let buffer = tables.meshes.get_mut(&handle.index).unwrap();
+        buffer.formats = layout.formats();
+        buffer.layout = Some(layout);
+        if self.config.is_mesh_bounds_computed {
+            let (stride, offset, format) = buffer.position_stride();
+            let positions = vertex::decode_positions(data, stride, offset, format);
+            buffer.bounds = MeshBounds::of_positions(&positions);
+        }
+        for (src, dst) in [(data, &buffer.vertices), (indices, &buffer.indices)] {
+            if !src.is_empty() {
+                unsafe {
+                    std::ptr::copy_nonoverlapping(src.as_ptr(), dst.addr as *mut u8, src.len())
+                }
+            }
+        }
+        handle
+    }
+
+    ///
+    /// Object space bounds of the mesh positions, inflated by the factor set for the mesh.
+    /// Only computed for meshes generated with data or whose vertices got uploaded in
+    /// chunks, and only while RendererConfig::is_mesh_bounds_computed. Chunked uploads
+    /// have them as soon as they're finished.
+    ///
+    pub fn mesh_bounds(&self, handle: MeshHandle) -> Option<MeshBounds> {
+        let mesh = self.fetch_mesh(handle)?;
+        // Finished uploads replace the bounds at the start of the next frame
+        let bounds = self.mesh_uploads.bounds_of(handle.index).or(mesh.bounds)?;
+        Some(bounds.inflated(mesh.bounds_inflation))
+    }
+
+    ///
+    /// Factor the bounds of the mesh get scaled by around their centers, for skinned
+    /// meshes whose animations reach past the bind pose. 1 by default.
+    ///
+    pub fn set_mesh_bounds_inflation(
+        &mut self,
+        handle: MeshHandle,
+        factor: f32,
+    ) -> Result<(), StaleHandle> {
+        if !self.is_mesh_current(handle) {
+            return Err(StaleHandle);
+        }
+        self.tables()
+            .meshes
+            .get_mut(&handle.index)
+            .unwrap()
+            .bounds_inflation = factor;
+        Ok(())
+    }
+
+    pub fn mesh_meta(&self, handle: MeshHandle) -> Option<&ResourceMeta> {
+        if !self.is_mesh_current(handle) {
+            return None;
+        }
+        self.mesh_meta.get(&handle.index)
+    }
+
+    // Sampled by id alone, never freed or evicted
+    fn is_builtin_texture(&self, id: u32) -> bool {
+        let is_noise = self.has_noise_textures
+            && (id == Self::ID_BLUE_NOISE || id == Self::ID_BLUE_NOISE_DUAL);
+        id == Self::ID_DEFAULT_TEXTURE || is_noise
+    }
+
+    fn is_texture_current(&self, handle: TextureHandle) -> bool {
+        self.tables()
+            .texture_generations
+            .is_current(handle.index, handle.generation)
+    }
+
+    ///
+    /// Both ids of a dual view texture fetch the same texture, the one of its linear id.
+    ///
+    pub fn fetch_texture(&self, handle: TextureHandle) -> Option<&Texture> {
+        if !self.is_texture_current(handle) {
+            return None;
+        }
+        self.textures_by_id.get(&self.texture_id_of(handle))
+    }
+
+    /*
+     * Id the texture of the handle is kept by, the linear one for either id of a dual
+     * view texture.
+     */
+    fn texture_id_of(&self, handle: TextureHandle) -> u32 {
+        let id = handle.index;
+        self.dual_view_owners.get(&id).copied().unwrap_or(id)
+    }
+
+    pub fn gen_texture(
+        &mut self,
+        name: String,
+        format: crate::format::Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+    ) -> TextureHandle {
+        // Reserve texture id
+        let texture_id = self
+            .next_free_texture_id()
+            .expect("ran out of texture ids!");
+        let desc = NewTexture::new(name, format, mip_maps);
+        self.gen_texture_at(texture_id, desc, staging_size)
+    }
+
+    ///
+    /// Same as gen_texture but with six layers sampled as a cube. Mips give the extent of
+    /// a face, their size and offset cover all six faces, stored one after the other in
+    /// the +X, -X, +Y, -Y, +Z, -Z order. Until uploaded its id samples the default
+    /// texture, which can't be sampled as a cube, so it shouldn't be handed to tasks before.
+    /// Regions of it can't be updated, reimport_texture replaces all faces instead.
+    ///
+    pub fn gen_texture_cube(
+        &mut self,
+        name: String,
+        format: crate::format::Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+    ) -> TextureHandle {
+        let texture_id = self
+            .next_free_texture_id()
+            .expect("ran out of texture ids!");
+        let desc = NewTexture {
+            is_cube: true,
+            ..NewTexture::new(name, format, mip_maps)
+        };
+        self.gen_texture_at(texture_id, desc, staging_size)
+    }
+
+    ///
+    /// Loader creating textures from other threads, see TextureLoader. All loaders share
+    /// one staging buffer of RendererConfig::loader_staging_bytes, made by the first call.
+    ///
+    pub fn texture_loader(&mut self) -> TextureLoader {
+        if self.texture_loader.is_none() {
+            let staging = DeviceAllocator::new_general(
+                &self.vulkan_context,
+                self.config.loader_staging_bytes,
+            );
+            self.texture_loader = Some(TextureLoader::new(staging));
+        }
+        self.texture_loader.clone().unwrap()
+    }
+
+    /*
+     * Creates the textures queued by loaders and uploads the ones their loaders are done
+     * writing. Staging memory of failed or abandoned ones goes back once no loader can
+     * write it anymore.
+     */
+    fn create_loaded_textures(&mut self) {
+        let loader = match &self.texture_loader {
+            Some(e) => e.clone(),
+            None => return,
+        };
+        for mut queued in loader.take() {
+            if queued.is_abandoned() {
+                if let Some(staging) = queued.staging.take() {
+                    loader.free_staging(staging);
+                }
+                continue;
+            }
+            let outcome = if !self.is_texture_format_supported(queued.format) {
+                Err(CreateTextureError::UnsupportedFormat(queued.format))
+            } else {
+                match self.next_free_texture_id() {
+                    Some(id) => Ok(self.make_texture_at(
+                        id,
+                        NewTexture::new(queued.name.clone(), queued.format, &queued.mip_maps),
+                        queued.staging.take().map(Box::new),
+                    )),
+                    None => Err(CreateTextureError::OutOfTextureIds),
+                }
+            };
+            self.loading_textures.push(queued.resolve(outcome));
+        }
+        let mut loading = std::mem::take(&mut self.loading_textures);
+        loading.retain_mut(|e| {
+            let is_filled = e.is_filled();
+            if !is_filled && !e.is_abandoned() {
+                return true;
+            }
+            match e.handle {
+                Some(handle) if is_filled => {
+                    // Freed by the caller meanwhile, its staging memory went with it
+                    let _ = self.queue_texture_for_uploading(handle);
+                }
+                Some(handle) => {
+                    let _ = self.free_texture(handle);
+                }
+                None => {
+                    if let Some(staging) = e.staging.take() {
+                        loader.free_staging(staging);
+                    }
+                }
+            }
+            false
+        });
+        self.loading_textures = loading;
+    }
+
+    /*
+     * Whether images of the format can be sampled and copied into.
+     */
+    fn is_texture_format_supported(&self, format: crate::format::Format) -> bool {
+        let ctx = &self.vulkan_context;
+        let features = unsafe {
+            ctx.instance
+                .get_physical_device_format_properties(ctx.physical_device, format.to_vk())
+                .optimal_tiling_features
+        };
+        features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+            && features.contains(vk::FormatFeatureFlags::TRANSFER_DST)
+    }
+
+    /*
+     * Staging memory goes back to the loader buffer it came from, or the general one.
+     */
+    fn free_staging(
+        general_allocator: &DeviceAllocator,
+        texture_loader: &Option<TextureLoader>,
+        slice: DeviceSlice,
+    ) {
+        match texture_loader {
+            Some(loader) if loader.is_staging_of(&slice) => loader.free_staging(slice),
+            _ => general_allocator.free(slice),
+        }
+    }
+
+    ///
+    /// Same as gen_texture, but with two ids sampling the one image: the linear id views
+    /// it with the UNORM format of the pair, the sRGB id with the sRGB one, decoding on
+    /// sampling. Either format of the pair can be passed, the texture keeps the UNORM one.
+    /// Uploads, eviction and metadata go through either id to the one texture. Freeing
+    /// one id leaves the other working, the image goes once both are freed. Only these
+    /// images get the mutable format viewing them as both needs, which can keep drivers
+    /// from compressing them.
+    ///
+    pub fn gen_texture_dual_view(
+        &mut self,
+        name: String,
+        format: crate::format::Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+    ) -> Result<DualViewTexture, NoSrgbPair> {
+        let linear_format = match format.srgb_sibling() {
+            Some(sibling) if format.is_srgb() => sibling,
+            Some(_) => format,
+            None => return Err(NoSrgbPair { format }),
+        };
+        // Samples the default texture until uploaded
+        let srgb_id = self
+            .next_free_texture_id()
+            .expect("ran out of texture ids!");
+        let linear_id = self
+            .next_free_texture_id()
+            .expect("ran out of texture ids!");
+        let desc = NewTexture {
+            srgb_id: Some(srgb_id),
+            ..NewTexture::new(name, linear_format, mip_maps)
+        };
+        let linear_id = self.gen_texture_at(linear_id, desc, staging_size);
+        self.dual_view_owners.insert(srgb_id, linear_id.index);
+        Ok(DualViewTexture {
+            linear_id,
+            srgb_id: TextureHandle {
+                index: srgb_id,
+                generation: self.tables().texture_generations.of(srgb_id),
+            },
+        })
+    }
+
+    ///
+    /// Same as gen_texture, keeping the metadata until the texture is freed, see
+    /// texture_meta. Evicting and restoring the texture keeps it too.
+    ///
+    pub fn gen_texture_with_meta(
+        &mut self,
+        name: String,
+        format: crate::format::Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+        meta: ResourceMeta,
+    ) -> TextureHandle {
+        let handle = self.gen_texture(name, format, mip_maps, staging_size);
+        if let Some(hash) = meta.content_hash {
+            self.textures_by_hash.insert(hash, handle.index);
+        }
+        self.texture_meta.insert(handle.index, meta);
+        handle
+    }
+
+    ///
+    /// Replaces the contents of the texture with a new image of the format and mips, the
+    /// id stays the same. Fill the staging buffer of the texture fetched afterwards and
+    /// queue it for uploading, same as after gen_texture. Until the new image is uploaded
+    /// tasks keep sampling the old one, which gets destroyed once no frame samples it
+    /// anymore. Reimporting again before that drops the upload in between, as does it
+    /// with pending region updates and restores. The metadata gets replaced if given,
+    /// sampler overrides and when the texture was last used stay. Both ids of dual view
+    /// textures get the new image, the format has to have an sRGB pair then too. Cube
+    /// textures stay cubes, with mips as gen_texture_cube takes them.
+    ///
+    pub fn reimport_texture(
+        &mut self,
+        handle: TextureHandle,
+        format: crate::format::Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+        meta: Option<ResourceMeta>,
+    ) -> Result<(), ReimportError> {
+        let texture = self
+            .fetch_texture(handle)
+            .ok_or(ReimportError::StaleHandle)?;
+        if texture.sparse.is_some() {
+            return Err(ReimportError::Sparse);
+        }
+        if texture.offscreen.is_some() {
+            return Err(ReimportError::Offscreen);
+        }
+        let srgb = texture.srgb.as_ref().map(|e| (e.id, e.refs));
+        let format = match srgb.map(|_| format.srgb_sibling()) {
+            None => format,
+            Some(Some(sibling)) if format.is_srgb() => sibling,
+            Some(Some(_)) => format,
+            Some(None) => return Err(ReimportError::NoSrgbPair(NoSrgbPair { format })),
+        };
+        let (id, name) = (texture.id, texture.name.clone());
+        let (layers, is_cube) = (texture.layers, texture.is_cube);
+        // Whatever was going to be staged or uploaded for it is outdated now
+        self.optimal_transition_queue.retain(|e| *e != id);
+        self.transition_retries.retain(|e| *e != id);
+        self.ongoing_optimal_transitions.retain(|e| e.0 != id);
+        self.texture_region_updates.remove(&id);
+        self.texture_retries.retain(|e| e.texture() != id);
+        let replaced = self.textures_by_id.remove(&id).unwrap();
+        match self.reimported_textures.entry(id) {
+            // Never sampled, gone once the GPU is done with whatever it got of it
+            std::collections::hash_map::Entry::Occupied(_) => self.evicted_images.push(replaced),
+            std::collections::hash_map::Entry::Vacant(e) => {
+                e.insert(replaced);
+            }
+        }
+        let staging = (staging_size > 0).then(|| self.alloc_staging(&name, staging_size));
+        let shared_with = self.texture_queue_families();
+        let mut texture = crate::texture::make(
+            &self.vulkan_context,
+            Some(&mut self.image_pool),
+            crate::texture::TextureDesc {
+                id,
+                name,
+                mip_maps,
+                layers,
+                is_cube,
+                format,
+                attachment_usage: None,
+                is_storage: false,
+                is_transient: false,
+                shared_with: &shared_with,
+                srgb_id: srgb.map(|e| e.0),
+            },
+            staging,
+        );
+        if let (Some(view), Some((_, refs))) = (&mut texture.srgb, srgb) {
+            view.refs = refs;
+        }
+        self.textures_by_id.insert(id, texture);
+        if let Some(meta) = meta {
+            let hash = self.texture_meta.remove(&id).and_then(|e| e.content_hash);
+            if let Some(hash) = hash.filter(|e| self.textures_by_hash.get(e) == Some(&id)) {
+                self.textures_by_hash.remove(&hash);
+            }
+            if let Some(hash) = meta.content_hash {
+                self.textures_by_hash.insert(hash, id);
+            }
+            self.texture_meta.insert(id, meta);
+        }
+        Ok(())
+    }
+
+    pub fn texture_meta(&self, handle: TextureHandle) -> Option<&ResourceMeta> {
+        if !self.is_texture_current(handle) {
+            return None;
+        }
+        self.texture_meta.get(&self.texture_id_of(handle))
+    }
+
+    ///
+    /// Texture made with the content hash in its metadata, for loaders to reuse instead
+    /// of uploading the same data again. The latest one if several share the hash.
+    ///
+    pub fn find_texture_by_hash(&self, hash: u64) -> Option<TextureHandle> {
+        self.textures_by_hash.get(&hash).map(|index| TextureHandle {
+            index: *index,
+            generation: self.tables().texture_generations.of(*index),
+        })
+    }
+
+    ///
+    /// Same as gen_texture but with the id picked by the caller, so the same sequence of
+    /// calls hands out the same ids regardless of what the renderer allocated internally.
+    ///
+    pub fn gen_texture_with_id(
+        &mut self,
+        id: u32,
+        name: String,
+        format: crate::format::Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+    ) -> Result<TextureHandle, IdUnavailable> {
+        if !self.tables().image_descriptors.is_free(id) {
+            return Err(IdUnavailable { id });
+        }
+        let desc = NewTexture::new(name, format, mip_maps);
+        Ok(self.gen_texture_at(id, desc, staging_size))
+    }
+
+    fn gen_texture_at(
+        &mut self,
+        texture_id: u32,
+        desc: NewTexture,
+        staging_size: u32,
+    ) -> TextureHandle {
+        let staging = (staging_size > 0).then(|| self.alloc_staging(&desc.name, staging_size));
+        self.make_texture_at(texture_id, desc, staging)
+    }
+
+    /*
+     * Image and descriptor of a texture with its staging memory allocated already.
+     */
+    fn make_texture_at(
+        &mut self,
+        texture_id: u32,
+        desc: NewTexture,
+        staging: Option<Box<DeviceSlice>>,
+    ) -> TextureHandle {
+        let shared_with = self.texture_queue_families();
+        // Builtins go to the core on destroy, their memory can't come from the pool
+        let pool = (!self.is_builtin_texture(texture_id)).then_some(&mut self.image_pool);
+        let texture = crate::texture::make(
+            &self.vulkan_context,
+            pool,
+            crate::texture::TextureDesc {
+                id: texture_id,
+                name: desc.name,
+                mip_maps: desc.mip_maps,
+                layers: if desc.is_cube { 6 } else { 1 },
+                is_cube: desc.is_cube,
+                format: desc.format,
+                attachment_usage: None,
+                is_storage: false,
+                is_transient: false,
+                shared_with: &shared_with,
+                srgb_id: desc.srgb_id,
+            },
+            staging,
+        );
+        self.place_texture(texture)
+    }
+
+    ///
+    /// Bakes the image based lighting maps of the cube texture, see ibl.rs. The bake gets
+    /// recorded on the setup command buffer and submitted right away, the next frame
+    /// submitted waits for it and IblMaps::is_ready tells when it's done without
+    /// blocking. Panics if the source isn't an uploaded cube texture, or if the desc has
+    /// sizes or samples of zero or more specular mips than its size has.
+    ///
+    pub fn bake_ibl(&mut self, source_cube: u32, desc: IblBakeDesc) -> IblMaps {
+        let source = match self.textures_by_id.get(&source_cube) {
+            Some(e) => e,
+            None => panic!("no texture with id {}!", source_cube),
+        };
+        assert!(source.is_cube, "{} isn't a cube texture!", source.name);
+        assert!(source.is_uploaded(), "{} isn't uploaded yet!", source.name);
+        assert!(
+            desc.irradiance_size > 0 && desc.brdf_lut_size > 0 && desc.sample_count > 0,
+            "IBL bake sizes and sample count can't be zero!"
+        );
+        let name = source.name.clone();
+        let specular_mips = ibl::mip_maps(desc.specular_size, desc.specular_mips, 6);
+        let specular = self.make_baked_texture(format!("{} specular", name), &specular_mips, true);
+        let irradiance_mips = ibl::mip_maps(desc.irradiance_size, 1, 6);
+        let irradiance =
+            self.make_baked_texture(format!("{} irradiance", name), &irradiance_mips, true);
+        let brdf_lut_mips = ibl::mip_maps(desc.brdf_lut_size, 1, 1);
+        let brdf_lut = self.make_baked_texture(format!("{} BRDF LUT", name), &brdf_lut_mips, false);
+
+        // The previous bake may still be running
+        let command_buffer = self.begin_setup_commands();
+        if self.ibl_baker.is_none() {
+            self.ibl_baker = Some(IblBaker::new(&self.vulkan_context));
+        }
+        let baker = self.ibl_baker.as_mut().unwrap();
+        let device = &self.vulkan_context.device;
+        baker.release_finished(device);
+        let maps = [specular, irradiance, brdf_lut].map(|e| &self.textures_by_id[&e.index]);
+        baker.record(
+            &self.vulkan_context,
+            command_buffer,
+            &self.textures_by_id[&source_cube],
+            maps,
+            desc.sample_count,
+        );
+        unsafe { device.end_command_buffer(command_buffer) }.expect("end command buffer failed!");
+        baker.value += 1;
+        let (semaphore, value) = (baker.semaphore, baker.value);
+
+        // The source may have been uploaded on the async queue
+        let mut wait_semaphores = Vec::new();
+        let mut wait_values = Vec::new();
+        if let Some(value) = self.pending_upload_wait {
+            wait_semaphores.push(self.async_upload.as_ref().unwrap().timeline_semaphore);
+            wait_values.push(value);
+        }
+        let wait_mask = vec![vk::PipelineStageFlags::COMPUTE_SHADER; wait_semaphores.len()];
+        let signal_semaphores = [semaphore];
+        let signal_values = [value];
+        let command_buffers = [command_buffer];
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values)
+            .build();
+        let submit_info = vk::SubmitInfo::builder()
+            .push_next(&mut timeline_info)
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_mask)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+        let core = self.core.clone().expect("renderer already destroyed!");
+        let submitted = {
+            let _queues = core.lock_queues();
+            self.queue_submit(
+                self.present_queue,
+                &[submit_info.build()],
+                self.setup_commands_reuse_fence,
+            )
+        };
+        self.expect_device(submitted, "IBL bake submit");
+        self.pending_ibl_wait = Some(value);
+        IblMaps {
+            specular,
+            irradiance,
+            brdf_lut,
+            value,
+        }
+    }
+
+    pub(crate) fn is_ibl_bake_done(&self, value: u64) -> bool {
+        self.ibl_baker
+            .as_ref()
+            .is_some_and(|e| e.completed(&self.vulkan_context.device) >= value)
+    }
+
+    /*
+     * Keeps the texture and puts it in the descriptor of its id.
+     */
+    fn place_texture(&mut self, texture: Texture) -> TextureHandle {
+        let texture_id = texture.id;
+        // Samples the default texture until uploaded, the image is undefined until then
+        let view = self.tables().default_view.unwrap_or(texture.view);
+        self.tables().image_descriptors.place_image_at(
+            &self.vulkan_context,
+            texture_id,
+            vk::DescriptorImageInfo {
+                image_view: view,
+                image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                ..Default::default()
+            },
+        );
+        self.textures_by_id.insert(texture_id, texture);
+        return TextureHandle {
+            index: texture_id,
+            generation: self.tables().texture_generations.of(texture_id),
+        };
+    }
+
+    /*
+     * Texture written by compute dispatches instead of uploads, its descriptor samples
+     * it right away.
+     */
+    fn make_baked_texture(
+        &mut self,
+        name: String,
+        mip_maps: &[MipMap],
+        is_cube: bool,
+    ) -> TextureHandle {
+        let id = self
+            .next_free_texture_id()
+            .expect("ran out of texture ids!");
+        let texture = crate::texture::make(
+            &self.vulkan_context,
+            Some(&mut self.image_pool),
+            crate::texture::TextureDesc {
+                id,
+                name,
+                mip_maps,
+                layers: if is_cube { 6 } else { 1 },
+                is_cube,
+                format: ibl::FORMAT,
+                attachment_usage: None,
+                is_storage: true,
+                is_transient: false,
+                shared_with: &[],
+                srgb_id: None,
+            },
+            None,
+        );
+        self.tables().image_descriptors.place_image_at(
+            &self.vulkan_context,
+            id,
+            vk::DescriptorImageInfo {
+                image_view: texture.view,
+                image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                ..Default::default()
+            },
+        );
+        self.textures_by_id.insert(id, texture);
+        TextureHandle {
+            index: id,
+            generation: self.tables().texture_generations.of(id),
+        }
+    }
+
+    // Same as alloc_staging, None instead of panicking
+    fn try_alloc_staging(&self, staging_size: u32) -> Option<Box<DeviceSlice>> {
+        self.caller_allocator()
+            .alloc(staging_size as u64)
+            .map(Box::new)
+    }
+
+    fn alloc_staging(&mut self, name: &str, staging_size: u32) -> Box<DeviceSlice> {
+        let allocator = self.caller_allocator();
+        let size = staging_size as u64;
+        match allocator.alloc(size) {
+            Some(slice) => Box::new(slice),
+            None => {
+                let purpose = format!("{} staging", name);
+                self.alloc_failed(size, &purpose, allocator.available())
+            }
+        }
+    }
+
+    ///
+    /// Scope of the general allocator that meshes and texture staging buffers get
+    /// allocated in from now on, so they count against its budget. None goes back to
+    /// the general allocator itself. Freeing them later frees them from the scope
+    /// regardless of the scope set by then. Panics if the scope is of another allocator.
+    ///
+    pub fn set_allocation_scope(&mut self, scope: Option<DeviceAllocator>) {
+        if let Some(scope) = &scope {
+            if !scope.shares_buffer_with(&self.general_allocator) {
+                panic!("allocation scope isn't a scope of the general allocator!");
+            }
+        }
+        self.allocation_scope = scope;
+    }
+
+    pub fn allocation_scope(&self) -> Option<&DeviceAllocator> {
+        self.allocation_scope.as_ref()
+    }
+
+    ///
+    /// Scope of the general allocator to pass to set_allocation_scope, see
+    /// DeviceAllocator::create_scope. Its allocations have to be freed before destroying
+    /// it, the renderer still holds the slices of meshes and textures otherwise.
+    ///
+    pub fn create_allocation_scope(&self, name: &str, budget: u64) -> DeviceAllocator {
+        self.general_allocator.create_scope(name, budget)
+    }
+
+    /*
+     * Allocator of what gets allocated on behalf of the caller.
+     */
+    fn caller_allocator(&self) -> DeviceAllocator {
+        self.allocation_scope
+            .clone()
+            .unwrap_or_else(|| self.general_allocator.as_ref().clone())
+    }
+
+    fn alloc_failed(&mut self, size: u64, purpose: &str, available: u64) -> ! {
+        self.event_sink.emit(RenderEvent::AllocationFailed {
+            purpose: purpose.to_string(),
+            size,
+            available,
+        });
+        panic!("couldn't allocate '{}' buffer of size {}", purpose, size)
+    }
+
+    /*
+     * Device loss gets reported before panicking like on any other error.
+     */
+    fn expect_device<T>(&mut self, result: ash::prelude::VkResult<T>, what: &str) -> T {
+        match result {
+            Ok(e) => e,
+            Err(e) => {
+                if e == vk::Result::ERROR_DEVICE_LOST {
+                    let frame = self.get_current_frame();
+                    self.event_sink.emit(RenderEvent::DeviceLost { frame });
+                }
+                panic!("{} failed! {}", what, e)
+            }
+        }
+    }
+
+    ///
+    /// Makes the operations hooked by the injector fail while it says so, None stops
+    /// injecting. Only with the fault-injection feature, see fault.
+    ///
+    #[cfg(feature = "fault-injection")]
+    pub fn set_fault_injector(&mut self, faults: Option<FaultInjector>) {
+        self.general_allocator.set_fault_injector(faults.clone());
+        self.faults = faults;
+    }
+
+    ///
+    /// Checks the guards after every allocation of the general and descriptor buffers,
+    /// and the ranges freed since, logging what it finds as errors. Cheap enough to
+    /// call every frame in debug builds. Only with the alloc-canaries feature, see
+    /// canary.
+    ///
+    #[cfg(feature = "alloc-canaries")]
+    pub fn check_canaries(&self) -> Vec<CanaryError> {
+        let mut errors = self.general_allocator.check_canaries();
+        if let Some(e) = &self.descriptor_allocator {
+            errors.extend(e.check_canaries());
+        }
+        for e in &errors {
+            log::error!("{}", e);
+        }
+        errors
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn is_fault_injected(&self, fault: Fault) -> bool {
+        self.faults.as_ref().is_some_and(|e| e.take(fault))
+    }
+
+    /*
+     * Call sites the fault injector hooks into, plain calls without the feature.
+     */
+    #[inline(always)]
+    fn acquire_next_image(&self) -> ash::prelude::VkResult<(u32, bool)> {
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::AcquireOutOfDate) {
+            return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+        }
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::AcquireTimeout) {
+            return Err(vk::Result::TIMEOUT);
+        }
+        // Deterministic frames get rendered however late the image is
+        let timeout = match self.config.deterministic {
+            Some(_) => u64::MAX,
+            None => u64::try_from(self.config.acquire_timeout.as_nanos()).unwrap_or(u64::MAX),
+        };
+        let acquired = unsafe {
+            self.vulkan_context.extension.swapchain.acquire_next_image(
+                self.swapchain_context.swapchain,
+                timeout,
+                self.present_complete_semaphore,
+                vk::Fence::null(),
+            )
+        };
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::AcquireSuboptimal) {
+            return acquired.map(|(e, _)| (e, true));
+        }
+        acquired
+    }
+
+    #[inline(always)]
+    fn queue_submit(
+        &self,
+        queue: vk::Queue,
+        submits: &[vk::SubmitInfo],
+        fence: vk::Fence,
+    ) -> ash::prelude::VkResult<()> {
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::SubmitDeviceLost) {
+            return Err(vk::Result::ERROR_DEVICE_LOST);
+        }
+        unsafe {
+            self.vulkan_context
+                .device
+                .queue_submit(queue, submits, fence)
+        }
+    }
+
+    #[inline(always)]
+    /*
+     * Tables shared with the other renderers of the core, see CoreTables.
+     */
+    fn tables(&self) -> MutexGuard<'_, CoreTables> {
+        self.core
+            .as_ref()
+            .expect("renderer already destroyed!")
+            .lock_tables()
+    }
+
+    /*
+     * For locking the tables while fields of the renderer are borrowed.
+     */
+    fn shared_core(&self) -> Arc<RenderCore> {
+        self.core.clone().expect("renderer already destroyed!")
+    }
+
+    /*
+     * Other renderers of the core may still have frames in flight sampling what's about
+     * to be destroyed or rewritten. They all submit to the one queue.
+     */
+    fn wait_other_renderers(&mut self) {
+        let waited = {
+            let core = self.core.as_ref().expect("renderer already destroyed!");
+            if Arc::strong_count(core) == 1 {
+                return;
+            }
+            let _queues = core.lock_queues();
+            unsafe { self.vulkan_context.device.queue_wait_idle(self.present_queue) }
+        };
+        self.expect_device(waited, "queue wait");
+    }
+
+    /*
+     * Taken right away, pointing to the default texture, so renderers of the core on
+     * other threads can't take it too before the texture gets placed.
+     */
+    fn next_free_texture_id(&self) -> Option<u32> {
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::DescriptorsFull) {
+            return None;
+        }
+        let mut tables = self.tables();
+        let id = tables.image_descriptors.next_free()? as u32;
+        // Only the builtins come before there is a default texture
+        if let Some(view) = tables.default_view {
+            let fallback = vk::DescriptorImageInfo {
+                image_view: view,
+                image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                ..Default::default()
+            };
+            tables
+                .image_descriptors
+                .place_image_at(&self.vulkan_context, id, fallback);
+        }
+        Some(id)
+    }
+
+    #[inline(always)]
+    fn try_spend_upload(&self, bytes: u64) -> bool {
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::UploadBudget) {
+            return false;
+        }
+        self.mesh_uploads.try_spend(bytes)
+    }
+
+    #[inline(always)]
+    fn readbacks_for(&mut self, size: u64) -> Result<&mut Readbacks, ReadbackBusy> {
+        #[cfg(feature = "fault-injection")]
+        if self.is_fault_injected(Fault::ReadbackBusy) {
+            return Err(ReadbackBusy { size, available: 0 });
+        }
+        #[cfg(not(feature = "fault-injection"))]
+        let _ = size;
+        let readback_bytes = self.config.readback_bytes;
+        let ctx = &self.vulkan_context;
+        Ok(self
+            .readbacks
+            .get_or_insert_with(|| Readbacks::new(ctx, readback_bytes)))
+    }
+
+    fn texture_queue_families(&self) -> Vec<u32> {
+        match &self.async_upload {
+            Some(e) if self.config.upload_queue == UploadQueue::AsyncConcurrent => {
+                vec![self.queue_family_index, e.family_index]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    ///
+    /// Renders the tasks with the pipelines and state of the stage into a texture of the
+    /// size and format of the desc, after the stages of the next frame. The texture
+    /// samples the default texture until that frame is done, so its residency says
+    /// Uploading and a RenderEvent::TextureUploaded comes once it's sampleable, from the
+    /// frame after the pass on. Rendering with the same size and format again, while the
+    /// texture isn't freed, renders into the same texture and returns its handle, it
+    /// stays sampleable with what it held before meanwhile. Freed textures go back to a
+    /// pool the next pass of their size and format takes from.
+    ///
+    /// Only stages drawing into a single color output of their own without reading
+    /// attachments can render offscreen, the format has to be the one of that output.
+    /// Stages with depth get a depth image along with the texture. The attachments of
+    /// the stage aren't touched.
+    ///
+    /// Panics for an empty size.
+    ///
+    pub fn render_to_texture(
+        &mut self,
+        desc: OffscreenPassDesc,
+        tasks: Vec<RenderTask>,
+    ) -> Result<TextureHandle, OffscreenError> {
+        if desc.width == 0 || desc.height == 0 {
+            panic!(
+                "offscreen pass of {} can't render at {}x{}!",
+                desc.stage, desc.width, desc.height
+            );
+        }
+        let stage = self
+            .pipeline
+            .stages
+            .iter()
+            .find(|e| e.name == desc.stage)
+            .ok_or_else(|| OffscreenError::UnknownStage(desc.stage.clone()))?;
+        let key = offscreen::key_of(stage, &desc)?;
+        let max = self.config.max_offscreen_extent;
+        if desc.width > max || desc.height > max {
+            return Err(OffscreenError::TooLarge {
+                width: desc.width,
+                height: desc.height,
+                max,
+            });
+        }
+        for task in &tasks {
+            if !self.is_mesh_current(task.mesh) {
+                return Err(TaskRejected::StaleMesh { mesh: task.mesh }.into());
+            }
+            if self.is_mesh_uploading(task.mesh.index) {
+                return Err(TaskRejected::MeshUploading { mesh: task.mesh }.into());
+            }
+            self.check_scene_slots(task)?;
+        }
+        let held = self
+            .textures_by_id
+            .values()
+            .find(|e| e.offscreen.as_ref().is_some_and(|e| e.key == key))
+            .map(|e| e.id);
+        let id = match held {
+            Some(id) => id,
+            None => self.take_offscreen_texture(&desc, key)?,
+        };
+        // Later passes into the same texture replace the earlier ones
+        self.offscreen_passes.retain(|e| e.texture != id);
+        self.offscreen_passes.push(PendingOffscreenPass {
+            texture: id,
+            stage: desc.stage,
+            tasks,
+        });
+        Ok(TextureHandle {
+            index: id,
+            generation: self.tables().texture_generations.of(id),
+        })
+    }
+
+    /*
+     * Texture out of the offscreen pool if there's one of the key, a new one otherwise.
+     * Either samples the default texture until rendered.
+     */
+    fn take_offscreen_texture(
+        &mut self,
+        desc: &OffscreenPassDesc,
+        key: OffscreenKey,
+    ) -> Result<u32, OffscreenError> {
+        let pooled = self
+            .offscreen_pool
+            .iter()
+            .position(|e| e.offscreen.as_ref().is_some_and(|e| e.key == key));
+        if let Some(index) = pooled {
+            // Its slot was kept pointing to the default texture
+            let mut texture = self.offscreen_pool.swap_remove(index);
+            texture.offscreen.as_mut().unwrap().is_rendered = false;
+            let id = texture.id;
+            self.textures_by_id.insert(id, texture);
+            return Ok(id);
+        }
+        let id = self
+            .next_free_texture_id()
+            .ok_or(OffscreenError::TexturesFull)?;
+        let mip_maps = |format: Format| {
+            [MipMap {
+                index: 0,
+                width: key.width,
+                height: key.height,
+                size: format.texel_size().unwrap_or_default() * key.width * key.height,
+                offset: 0,
+            }]
+        };
+        let name = format!("offscreen {} {}x{}", desc.stage, key.width, key.height);
+        // Sampled, read back and copied into like any other texture
+        let usage = vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST;
+        let depth = key.depth_format.map(|format| {
+            Box::new(crate::texture::make(
+                &self.vulkan_context,
+                Some(&mut self.image_pool),
+                crate::texture::TextureDesc {
+                    id,
+                    name: format!("{} depth", name),
+                    mip_maps: &mip_maps(format),
+                    layers: 1,
+                    is_cube: false,
+                    format,
+                    attachment_usage: Some(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | usage),
+                    is_storage: false,
+                    is_transient: false,
+                    shared_with: &[],
+                    srgb_id: None,
+                },
+                None,
+            ))
+        });
+        let mut texture = crate::texture::make(
+            &self.vulkan_context,
+            Some(&mut self.image_pool),
+            crate::texture::TextureDesc {
+                id,
+                name,
+                mip_maps: &mip_maps(key.format),
+                layers: 1,
+                is_cube: false,
+                format: key.format,
+                attachment_usage: Some(vk::ImageUsageFlags::COLOR_ATTACHMENT | usage),
+                is_storage: false,
+                is_transient: false,
+                shared_with: &[],
+                srgb_id: None,
+            },
+            None,
+        );
+        texture.offscreen = Some(Box::new(OffscreenImage {
+            key,
+            depth,
+            is_rendered: false,
+        }));
+        let fallback = self.default_texture_descriptor();
+        self.tables()
+            .image_descriptors
+            .place_image_at(&self.vulkan_context, id, fallback);
+        self.textures_by_id.insert(id, texture);
+        Ok(id)
+    }
+
+    ///
+    /// Removes the texture. Its memory and descriptor slot are released at the start of
+    /// the next frame, once the previous one isn't sampling from it anymore. Offscreen
+    /// textures go back to their pool instead, see render_to_texture. Dual view textures
+    /// go once both of their ids are freed, until then only the handle goes stale.
+    ///
+    pub fn free_texture(&mut self, handle: TextureHandle) -> Result<(), StaleHandle> {
+        if !self.is_texture_current(handle) {
+            return Err(StaleHandle);
+        }
+        let id = self.texture_id_of(handle);
+        let texture = self.textures_by_id.get_mut(&id).ok_or(StaleHandle)?;
+        if let Some(srgb) = &mut texture.srgb {
+            srgb.refs -= 1;
+            if srgb.refs > 0 {
+                // Its slot keeps the view until the other id is freed too
+                self.sampler_overrides.remove(&handle.index);
+                if self.inspected_texture == Some(handle) {
+                    self.inspected_texture = None;
+                }
+                self.tables().texture_generations.bump(handle.index);
+                return Ok(());
+            }
+            self.dual_view_owners.remove(&srgb.id);
+            self.sampler_overrides.remove(&srgb.id);
+            self.sampler_overrides.remove(&id);
+        }
+        let texture = self.textures_by_id.remove(&id).unwrap();
+        if let Some(replaced) = self.reimported_textures.remove(&id) {
+            self.evicted_images.push(replaced);
+        }
+        self.optimal_transition_queue.retain(|e| *e != id);
+        self.ongoing_optimal_transitions.retain(|e| e.0 != id);
+        self.texture_region_updates.remove(&id);
+        self.texture_retries.retain(|e| e.texture() != id);
+        self.transition_retries.retain(|e| *e != id);
+        self.offscreen_passes.retain(|e| e.texture != id);
+        self.rendering_offscreen.retain(|e| e.0 != id);
+        self.freed_textures.push(texture);
+        self.sampler_overrides.remove(&id);
+        self.texture_last_use.remove(&id);
+        let hash = self.texture_meta.remove(&id).and_then(|e| e.content_hash);
+        if let Some(hash) = hash.filter(|e| self.textures_by_hash.get(e) == Some(&id)) {
+            self.textures_by_hash.remove(&hash);
+        }
+        if self.inspected_texture == Some(handle) {
+            self.inspected_texture = None;
+        }
+        // Slot is reused only once released, the handle goes stale right away
+        self.tables().texture_generations.bump(handle.index);
+        Ok(())
+    }
+
+    fn release_freed_textures(&mut self) {
+        let fallback = self.default_texture_descriptor();
+        let is_releasing = !self.freed_textures.is_empty();
+        if self.freed_textures.iter().any(|e| e.sparse.is_some()) {
+            // Binds submitted after the last frame may still touch their images
+            self.wait_sparse_binds();
+        }
+        if is_releasing || !self.evicted_images.is_empty() {
+            self.wait_other_renderers();
+        }
+        for texture in std::mem::take(&mut self.freed_textures) {
+            if texture.offscreen.is_some() {
+                // Keeps its slot, for the next pass of its key
+                self.tables().image_descriptors.place_image_at(
+                    &self.vulkan_context,
+                    texture.id,
+                    fallback,
+                );
+                self.offscreen_pool.push(texture);
+                continue;
+            }
+            if let Some(staging) = &texture.staging {
+                let loader = &self.texture_loader;
+                Self::free_staging(&self.general_allocator, loader, *staging.as_ref());
+            }
+            texture.destroy(&self.vulkan_context.device, Some(&mut self.image_pool));
+            for page in texture.sparse.iter().flat_map(|e| e.bound.values()) {
+                self.page_pools[page.pool.0 as usize].free(*page);
+            }
+            self.tables().image_descriptors.reset_image_at(
+                &self.vulkan_context,
+                texture.id,
+                fallback,
+            );
+            if let Some(srgb) = &texture.srgb {
+                self.tables().image_descriptors.reset_image_at(
+                    &self.vulkan_context,
+                    srgb.id,
+                    fallback,
+                );
+            }
+        }
+        if is_releasing {
+            self.tables().image_descriptors.flush(&self.vulkan_context);
+        }
+        for texture in self.evicted_images.drain(..) {
+            // Slot stays, it points to the default texture or the image that replaced it
+            if let Some(staging) = &texture.staging {
+                let loader = &self.texture_loader;
+                Self::free_staging(&self.general_allocator, loader, *staging.as_ref());
+            }
+            texture.destroy(&self.vulkan_context.device, Some(&mut self.image_pool));
+        }
+    }
+
+    fn default_texture_descriptor(&self) -> vk::DescriptorImageInfo {
+        let view = self.tables().default_view;
+        vk::DescriptorImageInfo {
+            image_view: view.expect("default texture isn't made yet!"),
+            image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+            ..Default::default()
+        }
+    }
+
+    /*
+     * Free slots point to the default texture, so a stale or made up id in a material
+     * still samples a valid image.
+     */
+    fn reset_free_texture_slots(&mut self) {
+        let fallback = self.default_texture_descriptor();
+        let mut tables = self.tables();
+        let descriptors = &mut tables.image_descriptors;
+        for index in 0..descriptors.capacity() {
+            if descriptors.is_free(index) {
+                descriptors.reset_image_at(&self.vulkan_context, index, fallback);
+            }
+        }
+        descriptors.flush(&self.vulkan_context);
+    }
+
+    ///
+    /// Once the image memory of the resident textures goes over the budget, the least
+    /// recently referenced ones get evicted at the start of the next frame. None turns
+    /// eviction off. With texture feedback on, the ones no shader sampled in the last
+    /// frame read back go before the rest.
+    ///
+    pub fn set_texture_memory_budget(&mut self, bytes: Option<u64>) {
+        self.texture_memory_budget = bytes;
+    }
+
+    ///
+    /// Called to refill evicted textures when they get restored. Without one, restored
+    /// textures wait in the Uploading state for the host to fill their staging buffer
+    /// and queue them for uploading.
+    ///
+    pub fn set_texture_restorer(&mut self, restorer: Option<Box<TextureRestorer>>) {
+        self.texture_restorer = restorer;
+    }
+
+    ///
+    /// Brings an evicted texture back through the usual staging path, does nothing for
+    /// any other texture. Tasks keep sampling the default texture until it's uploaded.
+    /// With a restorer set, evicted textures referenced by queued tasks get restored on
+    /// their own.
+    ///
+    pub fn restore_texture(&mut self, handle: TextureHandle) -> Result<(), StaleHandle> {
+        let texture = self.fetch_texture(handle).ok_or(StaleHandle)?;
+        if texture.is_evicted() {
+            self.restore_or_defer(texture.id);
+        }
+        Ok(())
+    }
+
+    /*
+     * Restores the texture unless a retry of its restore is pending already, putting it
+     * off to a later frame without room for its staging. Returns whether it got restored.
+     */
+    fn restore_or_defer(&mut self, id: u32) -> bool {
+        let work = StagingWork::Restore(id);
+        if self.texture_retries.iter().any(|e| *e.0 == work) {
+            return false;
+        }
+        if self.restore_texture_at(id) {
+            return true;
+        }
+        self.defer_texture_staging(work, 1);
+        false
+    }
+
+    /*
+     * Returns false without room for the staging, the texture stays evicted.
+     */
+    fn restore_texture_at(&mut self, id: u32) -> bool {
+        let size = self.textures_by_id[&id].size();
+        let staging = match self.try_alloc_staging(size) {
+            Some(e) => e,
+            None => return false,
+        };
+        let evicted = self.textures_by_id.remove(&id).unwrap();
+        let shared_with = self.texture_queue_families();
+        let mut texture = crate::texture::make(
+            &self.vulkan_context,
+            Some(&mut self.image_pool),
+            crate::texture::TextureDesc {
+                id,
+                name: evicted.name,
+                mip_maps: &evicted.mip_maps,
+                layers: evicted.layers,
+                is_cube: evicted.is_cube,
+                format: evicted.format,
+                attachment_usage: None,
+                is_storage: false,
+                is_transient: false,
+                shared_with: &shared_with,
+                srgb_id: evicted.srgb.as_ref().map(|e| e.id),
+            },
+            Some(staging),
+        );
+        if let (Some(srgb), Some(evicted)) = (&mut texture.srgb, &evicted.srgb) {
+            // One of its ids may be freed already
+            srgb.refs = evicted.refs;
+        }
+        if let Some(restorer) = &mut self.texture_restorer {
+            let staging = texture.staging.as_ref().unwrap();
+            let data = unsafe {
+                std::slice::from_raw_parts_mut(staging.addr as *mut u8, staging.size as usize)
+            };
+            restorer(&texture, data);
+            self.optimal_transition_queue.push(id);
+        }
+        self.textures_by_id.insert(id, texture);
+        self.stats.texture_restores += 1;
+        true
+    }
+
+    fn defer_texture_staging(&mut self, work: StagingWork, attempts: u32) {
+        let limit = self.config.retry_limit;
+        let deferred = self
+            .texture_retries
+            .defer(work, RetryReason::StagingFull, attempts, limit);
+        if deferred.is_err() {
+            // Evicted ones stay evicted, resident ones keep their contents
+            self.texture_region_updates.remove(&work.texture());
+        }
+        let work = RetriedWork::Texture(work.texture());
+        self.report_retry(work, RetryReason::StagingFull, deferred);
+    }
+
+    /*
+     * Takes the texture work retried this frame out of its queue and tries it again.
+     * Region updates of textures that started uploading or got evicted meanwhile go
+     * back to waiting like fresh ones.
+     */
+    fn retry_texture_staging(&mut self) {
+        let cap = self.config.retried_work_per_frame;
+        for (work, attempts) in self.texture_retries.take(cap) {
+            let texture = match self.textures_by_id.get(&work.texture()) {
+                Some(e) => e,
+                // Freed meanwhile
+                None => continue,
+            };
+            let is_done = match work {
+                StagingWork::Regions(id) => {
+                    if texture.staging.is_some() || texture.is_evicted() {
+                        continue;
+                    }
+                    self.stage_region_updates_of(id)
+                }
+                StagingWork::Restore(id) => !texture.is_evicted() || self.restore_texture_at(id),
+            };
+            if !is_done {
+                self.defer_texture_staging(work, attempts + 1);
+            }
+        }
+    }
+
+    fn restore_referenced_textures(&mut self) {
+        if self.texture_restorer.is_none() {
+            return;
+        }
+        let current_frame = self.get_current_frame();
+        let ids: Vec<u32> = self
+            .textures_by_id
+            .values()
+            .filter(|e| e.is_evicted() && self.texture_last_use.get(&e.id) == Some(&current_frame))
+            .map(|e| e.id)
+            .collect();
+        for id in ids {
+            self.restore_or_defer(id);
+        }
+    }
+
+    fn resolve_texture_feedback(&mut self) {
+        let mut feedback = match self.texture_feedback.take() {
+            Some(e) => e,
+            None => return,
+        };
+        // Attachments and free slots have no texture
+        feedback.resolve(self, |id| {
+            let owner = self.dual_view_owners.get(&id).copied().unwrap_or(id);
+            let texture = self.textures_by_id.get(&owner)?;
+            Some(TextureExtent {
+                width: texture.width(),
+                height: texture.height(),
+                mip_levels: texture.mip_maps.len() as u32,
+            })
+        });
+        self.texture_feedback = Some(feedback);
+    }
+
+    /*
+     * Whether either id of the texture got sampled in the last frame read back, true
+     * without texture feedback.
+     */
+    fn is_texture_sampled(&self, texture: &Texture) -> bool {
+        match &self.texture_feedback {
+            Some(feedback) => {
+                feedback.is_sampled(texture.id)
+                    || texture.srgb.as_ref().is_some_and(|e| feedback.is_sampled(e.id))
+            }
+            None => true,
+        }
+    }
+
+    /*
+     * Evicts the least recently referenced resident textures until the budget is met,
+     * the ones texture feedback says no shader sampled first. The default texture, the
+     * inspected one and the ones referenced this frame stay.
+     */
+    fn evict_textures_over_budget(&mut self) {
+        if let Some(budget) = self.texture_memory_budget {
+            self.evict_textures_down_to(budget);
+        }
+    }
+
+    /*
+     * Returns the ids of the evicted textures.
+     */
+    fn evict_textures_down_to(&mut self, budget: u64) -> Vec<u32> {
+        let mut total = self.resident_texture_bytes();
+        if total <= budget {
+            return Vec::new();
+        }
+        let current_frame = self.get_current_frame();
+        let inspected = self.inspected_texture.map(|e| e.index);
+        let mut candidates: Vec<(bool, Option<u64>, u32)> = self
+            .textures_by_id
+            .values()
+            .filter(|e| e.residency() == Residency::Resident && e.sparse.is_none())
+            // Nothing to restore offscreen textures from
+            .filter(|e| e.offscreen.is_none())
+            .filter(|e| !self.is_builtin_texture(e.id) && Some(e.id) != inspected)
+            .map(|e| {
+                let last_use = self.texture_last_use.get(&e.id).copied();
+                (self.is_texture_sampled(e), last_use, e.id)
+            })
+            .filter(|e| e.1 != Some(current_frame))
+            .collect();
+        // Unsampled ones, then never referenced ones go first
+        candidates.sort_unstable();
+        let fallback_view = self.default_texture_descriptor().image_view;
+        let mut evicted = Vec::new();
+        for (_, _, id) in candidates {
+            if total <= budget {
+                break;
+            }
+            let texture = self.textures_by_id.get_mut(&id).unwrap();
+            total -= texture.allocation.size;
+            self.evicted_images.push(texture.clone());
+            texture.image = vk::Image::null();
+            texture.view = vk::ImageView::null();
+            let mut slots = vec![id];
+            if let Some(srgb) = &mut texture.srgb {
+                srgb.view = vk::ImageView::null();
+                slots.push(srgb.id);
+            }
+            for slot in slots {
+                self.tables().image_descriptors.place_image_at(
+                    &self.vulkan_context,
+                    slot,
+                    vk::DescriptorImageInfo {
+                        image_view: fallback_view,
+                        image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                        ..Default::default()
+                    },
+                );
+            }
+            evicted.push(id);
+        }
+        if !evicted.is_empty() {
+            self.tables().image_descriptors.flush(&self.vulkan_context);
+            self.stats.texture_evictions += evicted.len() as u64;
+            log::info!(
+                "evicted {} textures, {} bytes resident for a budget of {}",
+                evicted.len(),
+                total,
+                budget
+            );
+        }
+        if total > budget {
+            log::warn!(
+                "textures still take {} bytes over a budget of {}, the rest is in use",
+                total,
+                budget
+            );
+        }
+        evicted
+    }
+
+    fn resident_texture_bytes(&self) -> u64 {
+        self.textures_by_id
+            .values()
+            .filter(|e| !e.is_evicted())
+            .map(|e| e.allocation.size)
+            .sum()
+    }
+
+    ///
+    /// Size of the memory blocks textures get suballocated from, only affects blocks
+    /// allocated from now on.
+    ///
+    pub fn set_image_pool_block_size(&mut self, block_size: u64) {
+        self.image_pool.set_block_size(block_size);
+    }
+
+    pub fn queue_texture_for_uploading(
+        &mut self,
+        handle: TextureHandle,
+    ) -> Result<(), StaleHandle> {
+        let texture = self.fetch_texture(handle).ok_or(StaleHandle)?;
+        let id = texture.id;
+        if texture.is_evicted() {
+            // Queued by the restorer if there is one
+            self.restore_texture_at(id);
+            return Ok(());
+        }
+        self.optimal_transition_queue.push(id);
+        Ok(())
+    }
+
+    ///
+    /// Overwrites the texels of the region with the bytes, tightly packed, leaving the
+    /// rest of the texture as it is. Updates wait for the texture to finish uploading and
+    /// go out together in one copy the frame after, in the order they were made. Panics if
+    /// the texture is sparse (see upload_texture_pages), evicted, rendered offscreen or a
+    /// cube, the region is past the edge of its mip or the bytes don't add up to it.
+    ///
+    pub fn update_texture_region(
+        &mut self,
+        handle: TextureHandle,
+        region: TextureRegion,
+        bytes: &[u8],
+    ) -> Result<(), StaleHandle> {
+        let texture = self.fetch_texture(handle).ok_or(StaleHandle)?;
+        if texture.sparse.is_some() {
+            panic!(
+                "texture {} {} is sparse, upload its pages instead!",
+                texture.id, texture.name
+            );
+        }
+        if texture.is_evicted() {
+            panic!(
+                "texture {} {} is evicted, restore it first!",
+                texture.id, texture.name
+            );
+        }
+        if texture.offscreen.is_some() {
+            panic!(
+                "texture {} {} is rendered offscreen, it can't be updated!",
+                texture.id, texture.name
+            );
+        }
+        if texture.is_cube {
+            panic!(
+                "texture {} {} is a cube, reimport it instead!",
+                texture.id, texture.name
+            );
+        }
+        let is_inside = texture.mip_maps.get(region.mip as usize).is_some_and(|e| {
+            region.offset[0] as u64 + region.extent[0] as u64 <= e.width as u64
+                && region.offset[1] as u64 + region.extent[1] as u64 <= e.height as u64
+        });
+        if !is_inside || region.extent.contains(&0) {
+            panic!(
+                "region {:?} is outside of texture {} {}!",
+                region, texture.id, texture.name
+            );
+        }
+        let size = sparse::region_size(texture.format, region);
+        if bytes.len() as u64 != size as u64 {
+            panic!(
+                "{} bytes for a region of {} bytes of texture {}!",
+                bytes.len(),
+                size,
+                texture.name
+            );
+        }
+        self.texture_region_updates
+            .entry(texture.id)
+            .or_default()
+            .push((region, bytes.to_vec()));
+        Ok(())
+    }
+
+    /*
+     * Stages the region updates of every texture that isn't uploading, all of its regions
+     * in one staging buffer, and queues them like a regular upload.
+     */
+    fn stage_texture_region_updates(&mut self) {
+        let ready: Vec<u32> = self
+            .texture_region_updates
+            .keys()
+            .copied()
+            .filter(|e| {
+                let texture = &self.textures_by_id[e];
+                let is_retried = self
+                    .texture_retries
+                    .iter()
+                    .any(|(work, _)| *work == StagingWork::Regions(*e));
+                // Evicted ones get theirs once restored
+                texture.staging.is_none() && !texture.is_evicted() && !is_retried
+            })
+            .collect();
+        for id in ready {
+            if !self.stage_region_updates_of(id) {
+                self.defer_texture_staging(StagingWork::Regions(id), 1);
+            }
+        }
+    }
+
+    /*
+     * Returns false without room for the staging, the updates stay queued.
+     */
+    fn stage_region_updates_of(&mut self, id: u32) -> bool {
+        let size: usize = self.texture_region_updates[&id]
+            .iter()
+            .map(|e| e.1.len())
+            .sum();
+        let staging = match self.try_alloc_staging(size as u32) {
+            Some(e) => e,
+            None => return false,
+        };
+        let updates = self.texture_region_updates.remove(&id).unwrap();
+        {
+            let data = unsafe { std::slice::from_raw_parts_mut(staging.addr as *mut u8, size) };
+            let mut offset = 0;
+            for (_, bytes) in &updates {
+                data[offset..offset + bytes.len()].copy_from_slice(bytes);
+                offset += bytes.len();
+            }
+        }
+        let texture = self.textures_by_id.get_mut(&id).unwrap();
+        texture.staging = Some(staging);
+        texture.staged_regions = updates.into_iter().map(|e| e.0).collect();
+        self.optimal_transition_queue.push(id);
+        true
+    }
+
+    pub fn is_texture_uploaded(&self, handle: TextureHandle) -> Result<bool, StaleHandle> {
+        Ok(self.texture_residency(handle)? == Residency::Resident)
+    }
+
+    pub fn texture_residency(&self, handle: TextureHandle) -> Result<Residency, StaleHandle> {
+        let texture = self.fetch_texture(handle).ok_or(StaleHandle)?;
+        Ok(texture.residency())
+    }
+
+    ///
+    /// Finest mip shaders writing texture feedback sampled of every live texture id, in
+    /// id order, as of the newest frame read back. Both ids of dual view textures have
+    /// their own entry. Empty unless RendererConfig::is_texture_feedback_enabled is set,
+    /// and until the first frame is read back.
+    ///
+    pub fn texture_feedback(&self) -> &[FeedbackEntry] {
+        self.texture_feedback.as_ref().map_or(&[], |e| e.entries())
+    }
+
+    ///
+    /// Sparse texture of the mips, each half the one before, with no memory behind any of
+    /// its pages. Unbound pages read as zeros, see bind_texture_pages. Tasks sample the
+    /// default texture until the first upload_texture_pages is done. Freeing it gives its
+    /// pages back to their pools. Sparse textures never get evicted.
+    ///
+    pub fn gen_texture_sparse(
+        &mut self,
+        name: String,
+        format: Format,
+        width: u32,
+        height: u32,
+        mips: u32,
+    ) -> Result<TextureHandle, SparseError> {
+        if !self.vulkan_context.capabilities.is_sparse_residency_enabled {
+            return Err(SparseError::Unavailable(CapabilityError {
+                feature: DeviceFeature::SparseResidency,
+            }));
+        }
+        let max_mips = 32 - width.max(height).leading_zeros();
+        if mips == 0 || mips > max_mips {
+            panic!("{} mips for a {}x{} texture!", mips, width, height);
+        }
+        let texture_id = self
+            .next_free_texture_id()
+            .expect("ran out of texture ids!");
+        let mip_maps = sparse::mip_maps_of(format, width, height, mips);
+        let texture =
+            match sparse::make_texture(&self.vulkan_context, texture_id, name, format, &mip_maps) {
+                Ok(e) => e,
+                Err(e) => {
+                    let fallback = self.default_texture_descriptor();
+                    self.tables().image_descriptors.reset_image_at(
+                        &self.vulkan_context,
+                        texture_id,
+                        fallback,
+                    );
+                    return Err(e);
+                }
+            };
+        self.textures_by_id.insert(texture_id, texture);
+        Ok(TextureHandle {
+            index: texture_id,
+            generation: self.tables().texture_generations.of(texture_id),
+        })
+    }
+
+    ///
+    /// Pool of memory for the pages of sparse textures, the budget caps the bytes of the
+    /// pages bound to it. Pools live as long as the renderer.
+    ///
+    pub fn create_page_pool(&mut self, name: &str, budget: u64) -> PagePoolId {
+        self.page_pools.push(PagePool::new(name.to_string(), budget));
+        PagePoolId(self.page_pools.len() as u32 - 1)
+    }
+
+    pub fn page_pool(&self, id: PagePoolId) -> Option<&PagePool> {
+        self.page_pools.get(id.0 as usize)
+    }
+
+    ///
+    /// Binds the pages the regions cover to memory of the pool, pages already bound stay
+    /// as they are. Frames submitted from now on see them bound, what they hold is
+    /// undefined until uploaded. Nothing gets bound if a region is off the page grid or
+    /// the pages don't fit in the budget of the pool.
+    ///
+    pub fn bind_texture_pages(
+        &mut self,
+        handle: TextureHandle,
+        regions: &[PageBinding],
+        pool: PagePoolId,
+    ) -> Result<(), SparseError> {
+        let texture = self.fetch_sparse_texture(handle)?;
+        let sparse = texture.sparse.as_ref().unwrap();
+        let page_pool = self
+            .page_pools
+            .get(pool.0 as usize)
+            .ok_or(SparseError::UnknownPool(pool))?;
+        let mut pages = Vec::new();
+        for region in regions {
+            pages.extend(sparse.pages_of(&texture.mip_maps, *region, true)?);
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        pages.retain(|e| !sparse.bound.contains_key(e));
+        page_pool.check_budget(pages.iter().map(|e| sparse.size_of(*e)).sum())?;
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let (page_size, memory_type_bits) = (sparse.page_size, sparse.memory_type_bits);
+        let sizes: Vec<_> = pages.iter().map(|e| sparse.size_of(*e)).collect();
+        let page_pool = &mut self.page_pools[pool.0 as usize];
+        let bound: Vec<_> = pages
+            .into_iter()
+            .zip(sizes)
+            .map(|(page, size)| {
+                let memory = page_pool.alloc(
+                    &self.vulkan_context,
+                    pool,
+                    size,
+                    page_size,
+                    memory_type_bits,
+                );
+                (page, Some(memory))
+            })
+            .collect();
+        let texture = &self.textures_by_id[&handle.index];
+        let binds = texture.sparse.as_ref().unwrap().binds_of(texture, &bound);
+        self.submit_sparse_binds(texture.image, &binds, None);
+        let texture = self.textures_by_id.get_mut(&handle.index).unwrap();
+        let sparse = texture.sparse.as_mut().unwrap();
+        for (page, memory) in bound {
+            sparse.bound.insert(page, memory.unwrap());
+        }
+        Ok(())
+    }
+
+    ///
+    /// Unbinds the pages the regions cover once the GPU is done with the frames submitted
+    /// so far, their memory goes back to its pool after that. Pages that weren't bound get
+    /// skipped. Nothing gets unbound if a region is off the page grid.
+    ///
+    pub fn unbind_texture_pages(
+        &mut self,
+        handle: TextureHandle,
+        regions: &[PageBinding],
+    ) -> Result<(), SparseError> {
+        let texture = self.fetch_sparse_texture(handle)?;
+        let sparse = texture.sparse.as_ref().unwrap();
+        let mut pages = Vec::new();
+        for region in regions {
+            pages.extend(sparse.pages_of(&texture.mip_maps, *region, true)?);
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        pages.retain(|e| sparse.bound.contains_key(e));
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let unbound: Vec<_> = pages.iter().map(|e| (*e, None)).collect();
+        let binds = sparse.binds_of(texture, &unbound);
+        let image = texture.image;
+        let frame_done_value = self.get_current_frame();
+        let value = self.submit_sparse_binds(image, &binds, Some(frame_done_value));
+        let texture = self.textures_by_id.get_mut(&handle.index).unwrap();
+        let sparse = texture.sparse.as_mut().unwrap();
+        for page in pages {
+            let memory = sparse.bound.remove(&page).unwrap();
+            self.unbound_pages.push((value, memory));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Stages the bytes of the regions, tightly packed one after the other, and queues
+    /// them for uploading like queue_texture_for_uploading. Only bound pages keep what
+    /// gets written, regions don't have to stay on the page grid. Panics if the bytes
+    /// don't add up to the regions or pages of the texture are still uploading.
+    ///
+    pub fn upload_texture_pages(
+        &mut self,
+        handle: TextureHandle,
+        regions: &[PageBinding],
+        bytes: &[u8],
+    ) -> Result<(), SparseError> {
+        let texture = self.fetch_sparse_texture(handle)?;
+        let sparse = texture.sparse.as_ref().unwrap();
+        for region in regions {
+            sparse.pages_of(&texture.mip_maps, *region, false)?;
+        }
+        let size: u64 = regions
+            .iter()
+            .map(|e| sparse::region_size(texture.format, *e) as u64)
+            .sum();
+        if bytes.len() as u64 != size {
+            panic!(
+                "{} bytes for regions of {} bytes of texture {}!",
+                bytes.len(),
+                size,
+                texture.name
+            );
+        }
+        if texture.staging.is_some() {
+            panic!(
+                "pages of texture {} {} are still uploading!",
+                texture.id, texture.name
+            );
+        }
+        if regions.is_empty() {
+            return Ok(());
+        }
+        let name = texture.name.clone();
+        let staging = self.alloc_staging(&name, size as u32);
+        let data =
+            unsafe { std::slice::from_raw_parts_mut(staging.addr as *mut u8, size as usize) };
+        data.copy_from_slice(bytes);
+        let texture = self.textures_by_id.get_mut(&handle.index).unwrap();
+        texture.staging = Some(staging);
+        texture.sparse.as_mut().unwrap().staged = regions.to_vec();
+        self.optimal_transition_queue.push(handle.index);
+        Ok(())
+    }
+
+    fn fetch_sparse_texture(&self, handle: TextureHandle) -> Result<&Texture, SparseError> {
+        let texture = self.fetch_texture(handle).ok_or(SparseError::StaleHandle)?;
+        if texture.sparse.is_none() {
+            return Err(SparseError::NotSparse);
+        }
+        Ok(texture)
+    }
+
+    /*
+     * Submits the binds after the ones before, waiting on the frame value if any. Returns
+     * the sparse timeline value they signal, the next frame submitted waits on it.
+     */
+    fn submit_sparse_binds(
+        &mut self,
+        image: vk::Image,
+        binds: &(Vec<vk::SparseImageMemoryBind>, Vec<vk::SparseMemoryBind>),
+        frame_done_value: Option<u64>,
+    ) -> u64 {
+        let ctx = &self.vulkan_context;
+        let semaphore = *self
+            .sparse_semaphore
+            .get_or_insert_with(|| sparse::make_semaphore(ctx));
+        let mut waits = vec![(semaphore, self.sparse_value)];
+        if let Some(value) = frame_done_value {
+            waits.push((self.frame_timeline_semaphore, value));
+        }
+        self.sparse_value += 1;
+        let core = self.core.clone().expect("renderer already destroyed!");
+        let submitted = {
+            let _queues = core.lock_queues();
+            sparse::submit_binds(
+                &self.vulkan_context,
+                self.present_queue,
+                image,
+                binds,
+                &waits,
+                (semaphore, self.sparse_value),
+            )
+        };
+        self.expect_device(submitted, "sparse bind");
+        self.pending_sparse_wait = Some(self.sparse_value);
+        self.sparse_value
+    }
+
+    fn wait_sparse_binds(&self) {
+        if let Some(semaphore) = self.sparse_semaphore {
+            let semaphores = [semaphore];
+            let values = [self.sparse_value];
+            let info = vk::SemaphoreWaitInfo::builder()
+                .semaphores(&semaphores)
+                .values(&values);
+            unsafe { self.vulkan_context.device.wait_semaphores(&info, u64::MAX) }
+                .expect("sparse bind wait failed!");
+        }
+    }
+
+    fn release_unbound_pages(&mut self) {
+        let semaphore = match self.sparse_semaphore {
+            Some(e) if !self.unbound_pages.is_empty() => e,
+            _ => return,
+        };
+        let done = unsafe {
+            self.vulkan_context
+                .device
+                .get_semaphore_counter_value(semaphore)
+                .unwrap()
+        };
+        let pools = &mut self.page_pools;
+        self.unbound_pages.retain(|(value, page)| {
+            if *value > done {
+                return true;
+            }
+            pools[page.pool.0 as usize].free(*page);
+            false
+        });
+    }
+
+    ///
+    /// Re-creates the samplers of every key that isn't exact with the policy applied,
+    /// starting with the next prepared frame. Sampler ids stay the same. Setting the
+    /// policy in effect does nothing. Samplers are shared by every renderer of the core,
+    /// the policy applied last is the one all of them sample with.
+    ///
+    pub fn set_sampler_policy(&mut self, policy: SamplerPolicy) {
+        let current = self.pending_sampler_policy.unwrap_or(self.sampler_policy);
+        if policy != current {
+            self.pending_sampler_policy = Some(policy);
+        }
+    }
+
+    pub fn sampler_policy(&self) -> SamplerPolicy {
+        self.pending_sampler_policy.unwrap_or(self.sampler_policy)
+    }
+
+    ///
+    /// Lets the governor pick the lod bias of the sampler policy, and a render scale
+    /// if enabled, from the GPU time of the last frames and the texture memory left in
+    /// the budget. While enabled the governor owns the lod bias of the sampler policy,
+    /// None hands it back to whatever it was before.
+    ///
+    pub fn set_quality_governor(&mut self, config: Option<QualityGovernorConfig>) {
+        let was_enabled = self.quality_governor.is_some();
+        self.quality_governor = config.map(QualityGovernor::new);
+        let lod_bias = match &self.quality_governor {
+            Some(governor) => {
+                if !was_enabled {
+                    self.ungoverned_lod_bias = self.sampler_policy().lod_bias;
+                }
+                governor.state().lod_bias
+            }
+            None if was_enabled => self.ungoverned_lod_bias,
+            None => return,
+        };
+        self.set_sampler_policy(SamplerPolicy {
+            lod_bias,
+            ..self.sampler_policy()
+        });
+    }
+
+    ///
+    /// What the governor decided for the current frame, None without a governor.
+    ///
+    pub fn governor_state(&self) -> Option<GovernorState> {
+        self.quality_governor.as_ref().map(|e| *e.state())
+    }
+
+    ///
+    /// Replaces the decision of the governor for the next frame only.
+    ///
+    pub fn override_quality(&mut self, overrides: QualityOverride) {
+        self.quality_override = overrides;
+    }
+
+    fn update_quality_governor(&mut self, gpu_time: Option<Duration>) {
+        let overrides = std::mem::take(&mut self.quality_override);
+        let headroom = self.texture_memory_budget.map(|budget| {
+            let used = self.resident_texture_bytes() as f64 / budget.max(1) as f64;
+            (1.0 - used) as f32
+        });
+        let governor = match &mut self.quality_governor {
+            Some(e) => e,
+            None => return,
+        };
+        let lod_bias = governor.update(gpu_time, headroom, overrides).lod_bias;
+        self.set_sampler_policy(SamplerPolicy {
+            lod_bias,
+            ..self.sampler_policy()
+        });
+    }
+
+    /*
+     * Called once the previous frame is done, so nothing in flight still uses the old
+     * samplers or their descriptors.
+     */
+    fn apply_sampler_policy(&mut self) {
+        let policy = match self.pending_sampler_policy.take() {
+            Some(e) if e != self.sampler_policy => e,
+            _ => return,
+        };
+        self.sampler_policy = policy;
+        self.wait_other_renderers();
+        let ctx = &self.vulkan_context;
+        let mut tables = self.tables();
+        let CoreTables {
+            sampler_descriptors: descriptors,
+            samplers_by_key,
+            ..
+        } = &mut *tables;
+        let mut recreated = 0;
+        for (key, sampler) in samplers_by_key {
+            if key.is_exact {
+                continue;
+            }
+            let old = std::mem::replace(
+                sampler,
+                Sampler::of_key(ctx, sampler.name.clone(), *key, &policy, sampler.position),
+            );
+            old.destroy(&ctx.device);
+            descriptors.place_sampler_at(ctx, sampler.position as u32, sampler.sampler);
+            recreated += 1;
+        }
+        if recreated > 0 {
+            descriptors.flush(ctx);
+        }
+        log::debug!(
+            "sampler policy {:?} applied to {} samplers",
+            policy,
+            recreated
+        );
+    }
+
+    ///
+    /// Makes every task queued from now on sample the texture with the given sampler,
+    /// whatever sampler id their materials hold for it. None removes the override.
+    ///
+    pub fn override_texture_sampler(
+        &mut self,
+        handle: TextureHandle,
+        sampler: Option<u8>,
+    ) -> Result<(), StaleHandle> {
+        if self.fetch_texture(handle).is_none() {
+            return Err(StaleHandle);
+        }
+        match sampler {
+            Some(sampler) => {
+                if sampler as usize >= self.tables().samplers_by_key.len() {
+                    panic!("sampler {} doesn't exist!", sampler);
+                }
+                self.sampler_overrides.insert(handle.index, sampler)
+            }
+            None => self.sampler_overrides.remove(&handle.index),
+        };
+        Ok(())
+    }
+
+    ///
+    /// Draws the texture with all its mips at 1:1 pixels on top of the frame, in the
+    /// top left corner. None hides it. Textures in formats that can't be copied into the
+    /// swapchain image, like compressed ones, are refused with a warning.
+    ///
+    pub fn set_texture_inspector(
+        &mut self,
+        handle: Option<TextureHandle>,
+    ) -> Result<(), StaleHandle> {
+        self.inspected_texture = match handle {
+            Some(handle) => {
+                let texture = self.fetch_texture(handle).ok_or(StaleHandle)?;
+                let target = &self.swapchain_context.attachments[0];
+                if inspector::can_inspect(&self.vulkan_context, texture, target) {
+                    Some(handle)
+                } else {
+                    log::warn!(
+                        "can't inspect texture {} {}, its format {} can't be copied into the swapchain",
+                        texture.id,
+                        texture.name,
+                        texture.format
+                    );
+                    None
+                }
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    ///
+    /// Asks RenderDoc to capture the next num_frames frames.
+    ///
+    pub fn trigger_capture(&self, num_frames: u32) -> Result<(), CaptureUnavailable> {
+        self.frame_capture.trigger(num_frames)
+    }
+
+    ///
+    /// State replays of the frames from now on need set again, see CapturedState.
+    /// Sampler overrides go by texture id.
+    ///
+    pub fn capture_state(&self) -> CapturedState {
+        let mut sampler_overrides: Vec<_> = self
+            .sampler_overrides
+            .iter()
+            .map(|(id, sampler)| (*id, *sampler))
+            .collect();
+        sampler_overrides.sort_unstable();
+        CapturedState {
+            version: CapturedState::VERSION,
+            frame: self.get_current_frame(),
+            sampler_overrides,
+            inspected_texture: self.inspected_texture.map(|e| e.index),
+        }
+    }
+
+    ///
+    /// Sets the captured state again, in place of the sampler overrides and the texture
+    /// inspector there were. Fails without changing anything if a texture it names
+    /// doesn't exist.
+    ///
+    pub fn replay_state(&mut self, state: &CapturedState) -> Result<(), StaleHandle> {
+        let handle_of = |renderer: &Self, id: u32| {
+            let handle = TextureHandle {
+                index: id,
+                generation: renderer.tables().texture_generations.of(id),
+            };
+            renderer
+                .fetch_texture(handle)
+                .map(|_| handle)
+                .ok_or(StaleHandle)
+        };
+        let overrides = state
+            .sampler_overrides
+            .iter()
+            .map(|(id, sampler)| Ok((handle_of(self, *id)?, *sampler)))
+            .collect::<Result<Vec<_>, StaleHandle>>()?;
+        let inspected = state
+            .inspected_texture
+            .map(|id| handle_of(self, id))
+            .transpose()?;
+        self.sampler_overrides.clear();
+        for (handle, sampler) in overrides {
+            self.override_texture_sampler(handle, Some(sampler))?;
+        }
+        self.set_texture_inspector(inspected)
+    }
+
+    ///
+    /// Exports the timeline semaphore frames synchronize with an external producer on,
+    /// made on the first call. Every call hands out a new handle to the same semaphore,
+    /// see interop for the handshake.
+    ///
+    pub fn export_interop_semaphore(
+        &mut self,
+    ) -> Result<ExternalSemaphoreHandle, CapabilityError> {
+        let fns = self
+            .vulkan_context
+            .extension
+            .external_semaphore
+            .clone()
+            .ok_or(CapabilityError {
+                feature: DeviceFeature::ExternalSemaphore,
+            })?;
+        let ctx = &self.vulkan_context;
+        let semaphore = *self
+            .interop_semaphore
+            .get_or_insert_with(|| interop::make_semaphore(ctx, &fns));
+        let exported = interop::export(&fns, semaphore);
+        Ok(self.expect_device(exported, "semaphore export"))
+    }
+
+    ///
+    /// Makes the next submitted frame, the prepared one if there is one, wait on the GPU
+    /// for the external producer to signal the value on the interop semaphore. Calling it
+    /// again before the frame gets submitted replaces the value.
+    ///
+    /// Panics without an exported interop semaphore, or if the value isn't greater than
+    /// the last one a frame signaled.
+    ///
+    pub fn wait_external_value_before_frame(&mut self, value: u64) {
+        if self.interop_semaphore.is_none() {
+            panic!("no interop semaphore exported to wait on!");
+        }
+        if value <= self.external_value {
+            panic!(
+                "external value {} isn't past {}, the last one signaled!",
+                value, self.external_value
+            );
+        }
+        self.pending_external_wait = Some(value);
+    }
+
+    ///
+    /// Value the next submitted frame signals on the interop semaphore once it's done
+    /// reading what the producer wrote. Panics without a wait_external_value_before_frame
+    /// for that frame.
+    ///
+    pub fn signal_value_after_frame(&self) -> u64 {
+        self.pending_external_wait
+            .expect("the next frame doesn't wait on an external value!")
+            + 1
+    }
+
+    ///
+    /// Copies the range of the slice, relative to its start, into the readback buffer
+    /// after the last stage of the next submitted frame, the prepared one if there is
+    /// one. Meant for looking at what stages wrote, like culled draw lists or skinned
+    /// vertices. The request resolves to the bytes once the GPU is done with that frame.
+    ///
+    /// Panics on empty ranges or ranges past the end of the slice. Outstanding requests
+    /// share the RendererConfig::readback_bytes of the readback buffer, ReadbackBusy
+    /// means it's full. Freeing the buffer of the slice before the frame is submitted
+    /// has the same effect as freeing it while the frame runs on the GPU.
+    ///
+    pub fn read_buffer(
+        &mut self,
+        slice: &DeviceSlice,
+        range: std::ops::Range<u64>,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        let wait_value = self.get_current_frame() + 1;
+        let size = range.end.saturating_sub(range.start);
+        self.readbacks_for(size)?.request(slice, range, wait_value)
+    }
+
+    ///
+    /// Same as read_buffer for the first layer of the named attachment, after the last
+    /// stage touching it. The bytes are its rows tightly packed, only the depth of depth
+    /// stencil attachments.
+    ///
+    /// Panics for unknown attachments, the default one, memoryless ones, ones no stage
+    /// touches, ones created without TRANSFER_SRC usage and formats without a known
+    /// texel size.
+    ///
+    pub fn read_attachment(&mut self, name: &str) -> Result<ReadbackRequest, ReadbackBusy> {
+        self.read_attachment_mip(name, 0)
+    }
+
+    ///
+    /// Same as read_attachment for a mip of the attachment, rows as wide as the mip. Only
+    /// depth pyramids have more than the first one, see Renderer::attachment_texture_id.
+    ///
+    pub fn read_attachment_mip(
+        &mut self,
+        name: &str,
+        mip: u32,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        self.read_attachment_at(name, mip, 0)
+    }
+
+    ///
+    /// Same as read_attachment for a layer of an attachment with several, like the
+    /// targets of multiview stages.
+    ///
+    pub fn read_attachment_layer(
+        &mut self,
+        name: &str,
+        layer: u32,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        self.read_attachment_at(name, 0, layer)
+    }
+
+    fn read_attachment_at(
+        &mut self,
+        name: &str,
+        mip: u32,
+        layer: u32,
+    ) -> Result<ReadbackRequest, ReadbackBusy> {
+        if self.is_hibernated() {
+            panic!("attachments can't be read back while hibernated!");
+        }
+        let attachment = self
+            .pipeline
+            .attachments
+            .iter()
+            .find(|e| e.name == name)
+            .unwrap_or_else(|| panic!("unknown attachment {}!", name));
+        if attachment.is_default() || attachment.is_memoryless {
+            panic!("attachment {} can't be read back!", name);
+        }
+        attachment.require_usage(ExtraUsage::TransferSrc, "read it back");
+        if mip >= attachment.mips {
+            panic!(
+                "attachment {} has {} mips, there's no mip {}!",
+                name, attachment.mips, mip
+            );
+        }
+        if layer >= attachment.layers {
+            panic!(
+                "attachment {} has {} layers, there's no layer {}!",
+                name, attachment.layers, layer
+            );
+        }
+        let layout = self
+            .pipeline
+            .end_of_frame_layout(attachment)
+            .unwrap_or_else(|| panic!("no stage touches attachment {}!", name));
+        let texel_size = attachment
+            .format
+            .texel_size()
+            .unwrap_or_else(|| panic!("can't read back {} attachments!", attachment.format));
+        let aspect = if attachment.format.has_depth() {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            attachment.format.aspect()
+        };
+        let src = ImageSource {
+            image: attachment.image,
+            layout,
+            aspect,
+            mip,
+            layer,
+            extent: vk::Extent2D {
+                width: (attachment.extent.width >> mip).max(1),
+                height: (attachment.extent.height >> mip).max(1),
+            },
+        };
+        let size = texel_size as u64 * src.extent.width as u64 * src.extent.height as u64;
+        let wait_value = self.get_current_frame() + 1;
+        self.readbacks_for(size)?
+            .request_image(src, size, wait_value)
+    }
+
+    ///
+    /// Same as read_attachment for the swapchain image the next frame presents, after the
+    /// scaling and texture inspector overlay. Four bytes per pixel in the order of the
+    /// swapchain format, see swapchain_capabilities.
+    ///
+    /// Panics when the surface doesn't allow reading its images or while hibernated.
+    ///
+    pub fn read_presented(&mut self) -> Result<ReadbackRequest, ReadbackBusy> {
+        if self.is_hibernated() {
+            panic!("nothing gets presented while hibernated!");
+        }
+        if !self.swapchain_context.is_readable(&self.vulkan_context) {
+            panic!("the surface doesn't allow reading back presented images!");
+        }
+        let extent = self.swapchain_context.surface_extent;
+        let size = 4 * extent.width as u64 * extent.height as u64;
+        let wait_value = self.get_current_frame() + 1;
+        self.readbacks_for(size)?
+            .request_presented(extent, size, wait_value)
+    }
+
+    ///
+    /// Same as read_attachment for the first mip and layer of the texture. Unbound pages
+    /// of sparse textures read as zeros.
+    ///
+    /// Panics for stale handles, textures not resident and formats without a known texel
+    /// size.
+    ///
+    pub fn read_texture(&mut self, handle: TextureHandle) -> Result<ReadbackRequest, ReadbackBusy> {
+        let texture = self
+            .fetch_texture(handle)
+            .unwrap_or_else(|| panic!("stale texture handle {:?}!", handle));
+        if texture.residency() != Residency::Resident {
+            panic!(
+                "texture {} {} isn't resident, it can't be read back!",
+                texture.id, texture.name
+            );
+        }
+        let texel_size = texture
+            .format
+            .texel_size()
+            .unwrap_or_else(|| panic!("can't read back {} textures!", texture.format));
+        let src = ImageSource {
+            image: texture.image,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            aspect: texture.format.aspect(),
+            mip: 0,
+            layer: 0,
+            extent: texture.extent(),
+        };
+        let size = texel_size as u64 * src.extent.width as u64 * src.extent.height as u64;
+        let wait_value = self.get_current_frame() + 1;
+        self.readbacks_for(size)?
+            .request_image(src, size, wait_value)
+    }
+
+    ///
+    /// Runs the read, one of the read functions above, now and again on the frames after
+    /// for as long as it finds the readback buffer busy, up to RendererConfig::retry_limit
+    /// times. The returned QueuedReadback gets the ReadbackRequest once one succeeded.
+    /// Reads retried get a RenderEvent::WorkRetried each, ones given up on a
+    /// RenderEvent::WorkFailed. Dropping the QueuedReadback drops the retries with it.
+    ///
+    pub fn queue_readback(
+        &mut self,
+        read: impl FnMut(&mut Renderer) -> Result<ReadbackRequest, ReadbackBusy> + Send + 'static,
+    ) -> QueuedReadback {
+        let id = self.next_readback_id;
+        self.next_readback_id += 1;
+        let (queued, handle) = QueuedRead::new(id, Box::new(read));
+        self.attempt_readback(queued, 0);
+        handle
+    }
+
+    fn attempt_readback(&mut self, mut queued: QueuedRead, attempts: u32) {
+        let busy = match (queued.read)(self) {
+            Ok(request) => {
+                *queued.slot.lock().unwrap() = Some(Ok(request));
+                return;
+            }
+            Err(e) => e,
+        };
+        log::debug!("readback {} put off, {}", queued.id, busy);
+        let (id, slot) = (queued.id, queued.slot.clone());
+        let limit = self.config.retry_limit;
+        let deferred =
+            self.queued_readbacks
+                .defer(queued, RetryReason::ReadbackBusy, attempts + 1, limit);
+        if let Err(e) = deferred {
+            *slot.lock().unwrap() = Some(Err(e));
+        }
+        let work = RetriedWork::Readback(id);
+        self.report_retry(work, RetryReason::ReadbackBusy, deferred);
+    }
+
+    fn retry_readbacks(&mut self) {
+        let cap = self.config.retried_work_per_frame;
+        for (queued, attempts) in self.queued_readbacks.take(cap) {
+            if !queued.is_abandoned() {
+                self.attempt_readback(queued, attempts);
+            }
+        }
+    }
+
+    fn report_retry(
+        &mut self,
+        work: RetriedWork,
+        reason: RetryReason,
+        deferred: Result<u32, RetryError>,
+    ) {
+        match deferred {
+            Ok(attempt) => self.event_sink.emit(RenderEvent::WorkRetried {
+                work,
+                reason,
+                attempt,
+            }),
+            Err(e) => {
+                if let RetryError::Exhausted { attempts, .. } = e {
+                    self.event_sink.emit(RenderEvent::WorkFailed {
+                        work,
+                        reason,
+                        attempts,
+                    });
+                }
+                self.failed_work.push((work, e));
+            }
+        }
+    }
+
+    ///
+    /// Work the renderer gave up retrying since the last call, with the error it failed
+    /// with. Textures keep their previous contents, evicted ones stay evicted, mesh
+    /// uploads are cancelled and queued readbacks resolve to the error.
+    ///
+    pub fn take_failed_work(&mut self) -> Vec<(RetriedWork, RetryError)> {
+        std::mem::take(&mut self.failed_work)
+    }
+
+    ///
+    /// Depths of the upload and readback queues of the renderer, retried work included.
+    ///
+    pub fn pending_work_summary(&self) -> PendingWorkSummary {
+        let restores = self
+            .texture_retries
+            .iter()
+            .filter(|e| matches!(e.0, StagingWork::Restore(_)))
+            .count();
+        let (mesh_chunks, retried_mesh_chunks) = self.mesh_uploads.pending_chunks();
+        let copies = self.readbacks.as_ref().map_or(0, |e| e.pending_copies());
+        let queued_readbacks = self.queued_readbacks.len() as u32;
+        PendingWorkSummary {
+            texture_uploads: (self.optimal_transition_queue.len()
+                + self.transition_retries.len()
+                + self.texture_region_updates.len()
+                + restores) as u32,
+            retried_texture_uploads: (self.transition_retries.len() + self.texture_retries.len())
+                as u32,
+            mesh_chunks,
+            retried_mesh_chunks,
+            readbacks: copies + queued_readbacks,
+            retried_readbacks: queued_readbacks,
+        }
+    }
+
+    ///
+    /// Id the named attachment can be sampled through in the image table, for shaders
+    /// reaching it by texture id like culling ones reading a depth pyramid. Sampling it
+    /// is only valid where the attachment is in the read only layout: outside of their
+    /// own stage for depth pyramids, in the stages declaring it as input for the rest.
+    /// The same id on every call, it stays taken for as long as the pipeline lives.
+    ///
+    /// None for unknown attachments, the default one, memoryless ones, ones with stencil
+    /// and when the image table is full. Panics for ones created without SAMPLED usage.
+    ///
+    pub fn attachment_texture_id(&mut self, name: &str) -> Option<u32> {
+        if let Some(id) = self.attachment_texture_ids.get(name) {
+            return Some(*id);
+        }
+        let attachment = self
+            .pipeline
+            .attachments
+            .iter()
+            .find(|e| e.name == name)
+            .filter(|e| !e.is_default() && !e.is_memoryless && !e.format.has_stencil())?;
+        attachment.require_usage(ExtraUsage::Sampled, "sample it by texture id");
+        let desc = if attachment.is_released() {
+            // Points to the attachment again on resume
+            self.default_texture_descriptor()
+        } else {
+            vk::DescriptorImageInfo {
+                image_view: attachment.view,
+                image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                ..Default::default()
+            }
+        };
+        let id = self.next_free_texture_id()?;
+        self.tables()
+            .image_descriptors
+            .place_image_at(&self.vulkan_context, id, desc);
+        self.tables().image_descriptors.flush(&self.vulkan_context);
+        self.attachment_texture_ids.insert(name.to_string(), id);
+        Some(id)
+    }
+
+    pub(crate) fn readbacks(&self) -> Option<&Readbacks> {
+        self.readbacks.as_ref()
+    }
+
+    ///
+    /// Inserts a debug label for every draw with the mesh id and task kind. Only has
+    /// effect with debug enabled.
+    ///
+    pub fn set_verbose_labels(&mut self, is_enabled: bool) {
+        self.is_verbose_labels_enabled = is_enabled;
+    }
+
+    ///
+    /// Id of a pipeline variant declared in the pipeline file, to be used in RenderTask.
+    ///
+    pub fn variant_id(&self, name: &str) -> Option<u16> {
+        self.pipeline.variant_id(name)
+    }
+
+    ///
+    /// Changes the depth bounds test range of the stage for the next frames, for example
+    /// to fit the depth span of a light volume. Returns false if the stage doesn't test
+    /// depth bounds, either because it doesn't declare them or the device can't.
+    ///
+    pub fn set_depth_bounds(&mut self, stage: &str, min: f32, max: f32) -> bool {
+        if !(0.0..=1.0).contains(&min) || !(0.0..=1.0).contains(&max) || min > max {
+            panic!(
+                "depth bounds [{}, {}] have to be within 0 and 1, lowest first!",
+                min, max
+            );
+        }
+        let stage = match self.pipeline.stages.iter_mut().find(|e| e.name == stage) {
+            Some(e) => e,
+            None => panic!("stage {} doesn't exist!", stage),
+        };
+        match &mut stage.depth_bounds {
+            Some(bounds) => {
+                *bounds = [min, max];
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///
+    /// Range of a buffer stages declare in writesBuffers or readsBuffers, for the barriers
+    /// between them. Registering again replaces the range. Until a buffer is registered
+    /// its barriers cover all memory. Buffers with an initial state in the pipeline file
+    /// get filled with it by the host right away, each time they're registered.
+    ///
+    pub fn register_named_buffer(&mut self, name: &str, slice: DeviceSlice) {
+        if !self.pipeline.declared_buffers.contains(name) {
+            panic!("no stage reads or writes buffer {}!", name);
+        }
+        if let Some(value) = self.pipeline.buffer_fills.get(name) {
+            // Host coherent, visible to the next submit without a barrier
+            let words = (slice.size / 4) as usize;
+            unsafe { std::slice::from_raw_parts_mut(slice.addr as *mut u32, words).fill(*value) };
+        }
+        self.pipeline.named_buffers.insert(name.to_string(), slice);
+    }
+
+    ///
+    /// Sets the texture a stage with the attachment shading rate takes its rates from, an
+    /// R8_UINT texture with one texel per capabilities().shading_rate_texel_size pixels.
+    /// None unsets it. The stage shades at 1x1 while the texture isn't uploaded. Returns
+    /// false if the stage doesn't use the attachment rate, either because it doesn't
+    /// declare it or the device can't.
+    ///
+    pub fn set_shading_rate_image(
+        &mut self,
+        stage: &str,
+        texture: Option<TextureHandle>,
+    ) -> Result<bool, StaleHandle> {
+        if let Some(handle) = texture {
+            let texture = self.fetch_texture(handle).ok_or(StaleHandle)?;
+            if texture.format != Format::R8_UINT || texture.mip_map_count() != 1 {
+                panic!(
+                    "shading rate image {} has to be R8_UINT without mip maps!",
+                    texture.name
+                );
+            }
+        }
+        let stage = match self.pipeline.stages.iter_mut().find(|e| e.name == stage) {
+            Some(e) => e,
+            None => panic!("stage {} doesn't exist!", stage),
+        };
+        if stage.shading_rate != ShadingRate::Attachment {
+            return Ok(false);
+        }
+        stage.shading_rate_texture = texture;
+        Ok(true)
+    }
+
+    ///
+    /// Optional device features and extensions the renderer runs with, what "requires" in
+    /// pipeline files gets matched against.
+    ///
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        self.vulkan_context.capabilities
+    }
+
+    ///
+    /// Store ops and depth compare ops worth a second look in the loaded pipeline, see
+    /// OptimizationHint.
+    ///
+    pub fn optimization_hints(&self) -> &[OptimizationHint] {
+        self.pipeline.optimization_hints()
+    }
+
+    ///
+    /// Bytes the loaded pipeline takes per attachment and stage, checked against the
+    /// device and the allocators before loading it, see file::Pipeline::budget.
+    ///
+    pub fn pipeline_budget(&self) -> &PipelineBudget {
+        self.pipeline.budget()
+    }
+
+    ///
+    /// Stages of the loaded pipeline whose shaders are made of the file, includes
+    /// included, for file watchers to tell which stages a change touches. Empty for files
+    /// no stage uses. See file::Pipeline::shader_dependencies.
+    ///
+    pub fn stages_depending_on(&self, path: impl AsRef<std::path::Path>) -> Vec<String> {
+        self.pipeline.stages_depending_on(path.as_ref())
+    }
+
+    pub fn dump_frame_graph(&self) -> String {
+        self.pipeline.dump_frame_graph()
+    }
+
+    pub fn dump_frame_graph_to_file<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> std::io::Result<()> {
+        self.pipeline.dump_frame_graph_to_file(path)
+    }
+
+    ///
+    /// Recreates the swapchain for the new size of the window, does nothing while it's
+    /// minimized or hibernated. Without an internal resolution the pipeline gets loaded
+    /// again at the new size: render areas, viewports and scissors of the stages follow
+    /// it, as do targets sized relative to the window. Targets get made again empty
+    /// unless declared with preserveOnRecreate, see file::PreserveMode, every written one
+    /// left empty gets a RenderEvent::TargetReset.
+    ///
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 || self.is_hibernated() {
+            return;
+        }
+        self.wait_idle_for("resize", |renderer| {
+            renderer.swapchain_context.release(&renderer.vulkan_context);
+            renderer.create_swapchain(width, height);
+        });
+    }
+
+    /*
+     * Runs the action with the queues locked and the device idle. Swapchain and frame
+     * resources only ever get released through here.
+     */
+    fn wait_idle_for(&mut self, action: &str, f: impl FnOnce(&mut Self)) {
+        if let Some(frame) = self.prepared_frame {
+            panic!(
+                "can't {} with frame {} prepared, submit it first!",
+                action, frame
+            );
+        }
+        let core = self.core.clone().expect("renderer already destroyed!");
+        let _queues = core.lock_queues();
+        unsafe { self.vulkan_context.device.device_wait_idle().unwrap() };
+        f(self);
+    }
+
+    ///
+    /// Rebuilds the swapchain for the current size and capabilities of the surface,
+    /// with the same present mode and format preference. Happens on its own after
+    /// enough suboptimal frames unless RendererConfig::is_manual_swapchain_rebuild is
+    /// set. Does nothing while hibernated.
+    ///
+    pub fn rebuild_swapchain(&mut self) {
+        if self.is_hibernated() {
+            return;
+        }
+        let extent = self.swapchain_context.surface_extent;
+        self.wait_idle_for("rebuild the swapchain", |renderer| {
+            renderer.swapchain_context.release(&renderer.vulkan_context);
+            renderer.create_swapchain(extent.width, extent.height);
+        });
+    }
+
+    fn create_swapchain(&mut self, width: u32, height: u32) {
+        self.swapchain_context
+            .create(&self.vulkan_context, width, height);
+        self.frame_paths.reset(&self.vulkan_context.device);
+        self.stats.suboptimal_frames = 0;
+        if let Some(timing) = &mut self.display_timing {
+            timing.restart();
+        }
+        self.is_swapchain_rebuild_pending = false;
+        // Scaled targets keep the internal resolution, only the presenting side changes
+        let extent = self.swapchain_context.attachments[0].extent;
+        let is_resized = self.scaled_target.is_none() && self.pipeline_extent() != extent;
+        if is_resized || self.pipeline.has_released_attachments() {
+            self.rebuild_pipeline();
+        }
+        self.event_sink.emit(RenderEvent::SwapchainRecreated {
+            width: self.swapchain_context.surface_extent.width,
+            height: self.swapchain_context.surface_extent.height,
+        });
+    }
+
+    fn pipeline_extent(&self) -> vk::Extent2D {
+        let default = self.pipeline.attachments.iter().find(|e| e.is_default());
+        default.expect("pipeline without a default attachment!").extent
+    }
+
+    /*
+     * Loads the pipeline again at the extent of the swapchain, or of the scaled target
+     * with an internal resolution, for the stages rendering at its size and the targets
+     * sized relative to it. What was set on the stages at runtime carries over, ids
+     * handed out for attachments point to the new images. The device has to be idle.
+     */
+    fn rebuild_pipeline(&mut self) {
+        let core = self.shared_core();
+        let viewport_layers = self.viewport_layers();
+        let limits = BudgetLimits::of(
+            &self.vulkan_context,
+            &self.general_allocator,
+            self.descriptor_allocator.as_deref(),
+            self.config.frame_ring_bytes,
+            Self::FRAMES_IN_FLIGHT,
+            self.config.expected_tasks_per_kind,
+        );
+        let mut pipeline = pipeline::file::Pipeline::load(
+            &core,
+            self.descriptor_allocator.as_deref_mut(),
+            self.scaled_target
+                .clone()
+                .unwrap_or_else(|| self.swapchain_context.attachments[0].clone()),
+            core.is_validation_layer_enabled,
+            Some(&self.pipeline_path),
+            self.scaled_target.is_some(),
+            viewport_layers,
+            &limits,
+        );
+        pipeline.resolve_uninitialized_reads(self.config.uninitialized_reads);
+        pipeline.carry_over(&self.pipeline);
+        let command_buffer = self.begin_setup_commands();
+        let carried =
+            pipeline.record_preserved(&self.vulkan_context, command_buffer, &self.pipeline);
+        self.submit_setup_commands_and_wait(command_buffer, "preserved targets");
+        let reset: Vec<_> = pipeline
+            .reset_attachments(&self.pipeline, &carried)
+            .into_iter()
+            .map(|e| RenderEvent::TargetReset {
+                target: e.name.clone(),
+                width: e.extent.width,
+                height: e.extent.height,
+            })
+            .collect();
+        let old = std::mem::replace(&mut self.pipeline, Box::new(pipeline));
+        if let Some(mem) = &self.descriptor_allocator {
+            old.free_descriptors(mem);
+        }
+        old.destroy(&self.vulkan_context.device);
+        if old.total_stages() != self.pipeline.total_stages() {
+            if let Some(timer) = self.stats.stage_timer.take() {
+                timer.destroy(&self.vulkan_context.device);
+            }
+            let total_stages = self.pipeline.total_stages();
+            self.stats.stage_timer =
+                StageTimer::new(&self.vulkan_context, self.queue_family_index, total_stages);
+        }
+        self.replace_attachment_texture_ids();
+        for event in reset {
+            self.event_sink.emit(event);
+        }
+    }
+
+    /*
+     * Setup command buffer, begun once the commands recorded into it last time are done.
+     */
+    fn begin_setup_commands(&mut self) -> vk::CommandBuffer {
+        let waited = unsafe {
+            self.vulkan_context.device.wait_for_fences(
+                &[self.setup_commands_reuse_fence],
+                true,
+                u64::MAX,
+            )
+        };
+        self.expect_device(waited, "fence wait");
+        let device = &self.vulkan_context.device;
+        let command_buffer = self.setup_command_buffer;
+        unsafe {
+            device
+                .reset_fences(&[self.setup_commands_reuse_fence])
+                .expect("fence reset failed!");
+            device
+                .reset_command_buffer(
+                    command_buffer,
+                    vk::CommandBufferResetFlags::RELEASE_RESOURCES,
+                )
+                .expect("reset command buffer failed!");
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("begin commandbuffer failed!");
+        }
+        command_buffer
+    }
+
+    /*
+     * Submits what got recorded since begin_setup_commands and waits for it to run. The
+     * queues have to be locked already, like in wait_idle_for.
+     */
+    fn submit_setup_commands_and_wait(&mut self, command_buffer: vk::CommandBuffer, purpose: &str) {
+        let ended = unsafe {
+            self.vulkan_context
+                .device
+                .end_command_buffer(command_buffer)
+        };
+        ended.expect("end command buffer failed!");
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        let submitted = self.queue_submit(
+            self.present_queue,
+            &[submit_info.build()],
+            self.setup_commands_reuse_fence,
+        );
+        self.expect_device(submitted, purpose);
+        let waited = unsafe {
+            self.vulkan_context.device.wait_for_fences(
+                &[self.setup_commands_reuse_fence],
+                true,
+                u64::MAX,
+            )
+        };
+        self.expect_device(waited, "fence wait");
+    }
+
+    /*
+     * Points the ids handed out by attachment_texture_id to the attachments of the same
+     * name in the current pipeline, freeing the ones of attachments it doesn't have.
+     * Released ones point to the default texture.
+     */
+    fn replace_attachment_texture_ids(&mut self) {
+        let fallback = self.default_texture_descriptor();
+        let ids: Vec<_> = self.attachment_texture_ids.drain().collect();
+        let core = self.shared_core();
+        let mut tables = core.lock_tables();
+        for (name, id) in ids {
+            let attachment = self.pipeline.attachments.iter().find(|e| e.name == name);
+            match attachment {
+                // Samples the default texture until loaded again
+                Some(e) if e.is_released() => {
+                    tables
+                        .image_descriptors
+                        .place_image_at(&self.vulkan_context, id, fallback);
+                    self.attachment_texture_ids.insert(name, id);
+                }
+                Some(e) => {
+                    let desc = vk::DescriptorImageInfo {
+                        image_view: e.view,
+                        image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                        ..Default::default()
+                    };
+                    tables
+                        .image_descriptors
+                        .place_image_at(&self.vulkan_context, id, desc);
+                    self.attachment_texture_ids.insert(name, id);
+                }
+                None => tables
+                    .image_descriptors
+                    .reset_image_at(&self.vulkan_context, id, fallback),
+            }
+        }
+        tables.image_descriptors.flush(&self.vulkan_context);
+    }
+
+    ///
+    /// Timings of the last present_timing::HISTORY_LEN presents the display reported on,
+    /// oldest first. They lag a few frames behind. None without VK_GOOGLE_display_timing.
+    ///
+    pub fn present_timing_history(&self) -> Option<&VecDeque<PresentTiming>> {
+        self.display_timing.as_ref().map(|e| e.history())
+    }
+
+    ///
+    /// Display clock time in nanoseconds the next presented frame shouldn't reach the
+    /// screen before, for pacing against the display. Only the next frame takes it, and
+    /// it's dropped on a swapchain rebuild. Does nothing without VK_GOOGLE_display_timing.
+    ///
+    pub fn set_target_present_time(&mut self, time: u64) {
+        if let Some(timing) = &mut self.display_timing {
+            timing.set_target_present_time(time);
+        }
+    }
+
+    ///
+    /// Nanoseconds between refreshes of the display, once a frame got presented to the
+    /// current swapchain. None without VK_GOOGLE_display_timing.
+    ///
+    pub fn refresh_duration(&self) -> Option<u64> {
+        self.display_timing
+            .as_ref()
+            .and_then(|e| e.refresh_duration())
+    }
+
+    ///
+    /// Format of the swapchain and whether passes can write to it through a UNORM view.
+    ///
+    pub fn swapchain_capabilities(&self) -> SwapchainCapabilities {
+        self.swapchain_context.capabilities(&self.vulkan_context)
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        let device = &self.vulkan_context.device;
+        let mut attachments: Vec<_> = self
+            .pipeline
+            .attachments
+            .iter()
+            .filter(|e| !e.is_default() && !e.is_released())
+            .map(|e| AttachmentMemoryReport {
+                name: e.name.clone(),
+                is_memoryless: e.is_memoryless,
+                is_lazily_allocated: e.is_lazily_allocated(),
+                committed: if e.is_lazily_allocated() {
+                    unsafe { device.get_device_memory_commitment(e.memory) }
+                } else {
+                    0
+                },
+            })
+            .collect();
+        attachments.sort_by(|a, b| a.name.cmp(&b.name));
+        let report_of = |e: &DeviceAllocator| AllocatorReport {
+            size: e.size(),
+            available: e.available(),
+        };
+        MemoryReport {
+            general: report_of(&self.general_allocator),
+            descriptor: self.descriptor_allocator.as_deref().map(report_of),
+            readback: self.readbacks.as_ref().map(|e| report_of(e.allocator())),
+            attachments,
+            image_pools: self.image_pool.report(),
+            texture_bytes: self.resident_texture_bytes(),
+            texture_memory_budget: self.texture_memory_budget,
+            texture_evictions: self.stats.texture_evictions,
+            texture_restores: self.stats.texture_restores,
+            textures: self.texture_memory_reports(),
+        }
+    }
+
+    ///
+    /// Allocator free ranges, texture residency and pending destroys in one call, cheap
+    /// enough to make every frame. The sequence only changes along with the rest, so an
+    /// overlay can skip rebuilding what it draws when it's the same as last time.
+    ///
+    pub fn memory_snapshot(&mut self) -> MemorySnapshot {
+        let mut textures: Vec<_> = self
+            .textures_by_id
+            .values()
+            .map(|e| TextureSnapshot {
+                id: e.id,
+                size: if e.is_evicted() { 0 } else { e.allocation.size },
+                residency: e.residency(),
+            })
+            .collect();
+        textures.sort_by_key(|e| e.id);
+        let pending_destroys = self.freed_textures.len()
+            + self.evicted_images.len()
+            + self.frame_data.in_flight_buffers.len();
+        let snapshot = MemorySnapshot {
+            sequence: 0,
+            general: self.general_allocator.snapshot(),
+            descriptor: self.descriptor_allocator.as_ref().map(|e| e.snapshot()),
+            readback: self.readbacks.as_ref().map(|e| e.allocator().snapshot()),
+            image_pools: self.image_pool.report(),
+            textures,
+            texture_bytes: self.resident_texture_bytes(),
+            texture_memory_budget: self.texture_memory_budget,
+            pending_destroys: pending_destroys as u32,
+        };
+        self.memory_snapshots.track(snapshot)
+    }
+
+    fn texture_memory_reports(&self) -> Vec<TextureMemoryReport> {
+        let mut textures: Vec<_> = self
+            .textures_by_id
+            .values()
+            .map(|e| TextureMemoryReport {
+                id: e.id,
+                name: e.name.clone(),
+                size: if e.is_evicted() { 0 } else { e.allocation.size },
+                residency: e.residency(),
+                meta: self.texture_meta.get(&e.id).cloned(),
+            })
+            .collect();
+        textures.sort_by_key(|e| e.id);
+        textures
+    }
+
+    ///
+    /// Splits the render area into the viewports for the frames prepared from now on,
+    /// see split_screen. None enabled, or none at all, draws every stage once over the
+    /// whole render area again. Panics with more than MAX_VIEWPORTS.
+    ///
+    /// Targets declared perViewport have a layer per viewport, the pipeline gets loaded
+    /// again for another number of them the way resize loads it, waiting for the device
+    /// to be idle. Can't be called with a frame prepared then. They come back empty with
+    /// a RenderEvent::TargetReset each if written.
+    ///
+    pub fn set_viewports(&mut self, viewports: &[ViewportDesc]) {
+        assert!(
+            viewports.len() <= MAX_VIEWPORTS,
+            "{} viewports, at most {} are supported!",
+            viewports.len(),
+            MAX_VIEWPORTS
+        );
+        self.viewports = viewports.to_vec();
+        let layers = self.pipeline.viewport_layers();
+        if layers.is_some_and(|e| e != self.viewport_layers()) && !self.is_hibernated() {
+            self.wait_idle_for("set the viewports", |renderer| renderer.rebuild_pipeline());
+        }
+    }
+
+    // Of the targets allocated per split screen viewport, at least the one
+    fn viewport_layers(&self) -> u32 {
+        self.viewports.len().max(1) as u32
+    }
+
+    ///
+    /// Enables or disables the viewport of the index, disabled ones don't cost anything
+    /// but the memory of their layers. Panics if there's no such viewport.
+    ///
+    pub fn set_viewport_enabled(&mut self, index: usize, is_enabled: bool) {
+        let count = self.viewports.len();
+        self.viewports
+            .get_mut(index)
+            .unwrap_or_else(|| panic!("no viewport {}, there are {}!", index, count))
+            .is_enabled = is_enabled;
+    }
+
+    pub fn viewports(&self) -> &[ViewportDesc] {
+        &self.viewports
+    }
+
+    ///
+    /// Changes a constant declared by the stage in pipeline.json, see stage_constants.
+    /// Frames prepared from now on read the value, the value has to be of the declared
+    /// type.
+    ///
+    pub fn set_stage_constant(
+        &mut self,
+        stage: &str,
+        name: &str,
+        value: ConstantValue,
+    ) -> Result<(), UnknownConstant> {
+        self.stage_constants_mut(stage)?.set(stage, name, value)
+    }
+
+    ///
+    /// Constants declared by the stage with their current values, in declaration order.
+    ///
+    pub fn list_stage_constants(&self, stage: &str) -> Result<&[StageConstant], UnknownConstant> {
+        self.pipeline
+            .stages
+            .iter()
+            .find(|e| e.name == stage)
+            .map(|e| e.constants.constants())
+            .ok_or_else(|| UnknownConstant::Stage(stage.to_string()))
+    }
+
+    ///
+    /// Replaces the debug flags of the stage, or the global ones with None, see
+    /// debug_flags. Frames prepared from now on read them.
+    ///
+    pub fn set_debug_flags(
+        &mut self,
+        stage: Option<&str>,
+        flags: u64,
+    ) -> Result<(), UnknownConstant> {
+        match stage {
+            None => self.debug_flags.global = flags,
+            Some(stage) => self.stage_constants_mut(stage)?.debug_flags = flags,
+        }
+        Ok(())
+    }
+
+    ///
+    /// Debug flags of the stage, or the global ones with None.
+    ///
+    pub fn debug_flags(&self, stage: Option<&str>) -> Result<u64, UnknownConstant> {
+        match stage {
+            None => Ok(self.debug_flags.global),
+            Some(stage) => self
+                .pipeline
+                .stages
+                .iter()
+                .find(|e| e.name == stage)
+                .map(|e| e.constants.debug_flags)
+                .ok_or_else(|| UnknownConstant::Stage(stage.to_string())),
+        }
+    }
+
+    ///
+    /// Bit of the named debug flag, the next free one the first time the name is
+    /// registered. Panics once all 64 bits have names.
+    ///
+    pub fn register_debug_flag(&mut self, name: &str) -> u32 {
+        self.debug_flags.register(name)
+    }
+
+    ///
+    /// Named debug flags in the order they were registered.
+    ///
+    pub fn list_debug_flags(&self) -> Vec<DebugFlag> {
+        self.debug_flags.list()
+    }
+
+    fn stage_constants_mut(&mut self, stage: &str) -> Result<&mut StageConstants, UnknownConstant> {
+        self.pipeline
+            .stages
+            .iter_mut()
+            .find(|e| e.name == stage)
+            .map(|e| &mut e.constants)
+            .ok_or_else(|| UnknownConstant::Stage(stage.to_string()))
+    }
+
+    /*
+     * Debug flags set this frame, as a label for frame captures to show.
+     */
+    fn label_debug_flags(&self) {
+        let mut flags: Vec<_> = self
+            .pipeline
+            .stages
+            .iter()
+            .filter(|e| e.constants.debug_flags != 0)
+            .map(|e| format!("{} {:#x}", e.name, e.constants.debug_flags))
+            .collect();
+        if self.debug_flags.global != 0 {
+            flags.insert(0, format!("global {:#x}", self.debug_flags.global));
+        }
+        if !flags.is_empty() {
+            self.vulkan_context.extension.try_insert_label(
+                self.draw_command_buffer,
+                &format!("debug flags: {}", flags.join(", ")),
+            );
+        }
+    }
+
+    ///
+    /// Registers block the shaders of the stage have to declare, for including in them
+    /// instead of lining up USING macros by hand. See per_draw for the layout.
+    ///
+    pub fn per_draw_glsl_struct(&self, stage: &str) -> String {
+        self.pipeline
+            .stages
+            .iter()
+            .find(|e| e.name == stage)
+            .unwrap_or_else(|| panic!("no stage named {}!", stage))
+            .per_draw_layout()
+            .glsl_struct(stage)
+    }
+
+    ///
+    /// Per draw layout of every drawing stage as markdown, a table each.
+    ///
+    pub fn per_draw_layout_doc(&self) -> String {
+        let mut doc = format!("# Per draw layout {}\n", per_draw::LAYOUT_VERSION);
+        for stage in self.pipeline.stages.iter().filter(|e| e.blit.is_none()) {
+            doc += &format!(
+                "\n## {}\n\n{}",
+                stage.name,
+                stage.per_draw_layout().markdown_table()
+            );
+        }
+        doc
+    }
+
+    ///
+    /// Prepares and submits the frame in one go, see try_prepare_frame.
+    ///
+    pub fn render(&mut self) -> FrameOutcome {
+        match self.try_prepare_frame() {
+            Ok(frame) => self.submit_frame(frame),
+            Err(outcome) => outcome,
+        }
+    }
+
+    ///
+    /// Submits the uploads, offscreen passes and readbacks queued so far and waits for the
+    /// GPU to finish them, without acquiring or presenting a swapchain image. Meant for
+    /// tools after the resource side of the renderer only, like asset bakers that never
+    /// call render: afterwards textures queued for uploading are resident, meshes
+    /// uploaded, render_to_texture results rendered and readbacks resolvable. Work over
+    /// the per frame upload budget or put off for retrying takes more than one
+    /// submission, each one counts as a frame, so destroy after a flush finds nothing in
+    /// flight whether render was ever called or not.
+    ///
+    /// Queued tasks stay queued for the next render, nothing gets batched. Readbacks
+    /// queued for lack of room only get their turn if resolved requests make some.
+    /// WaitTimeout if the GPU isn't done within the timeout, what got submitted stays
+    /// submitted and the next flush or frame picks up from there. Panics with a frame
+    /// prepared and not submitted yet, and with a read_presented request pending since
+    /// there is nothing presented to read.
+    ///
+    pub fn flush_gpu_work(&mut self, timeout: Duration) -> Result<(), WaitTimeout> {
+        if let Some(frame) = self.prepared_frame {
+            panic!("frame {} was prepared but never submitted!", frame);
+        }
+        if self.readbacks.as_ref().is_some_and(|e| e.is_presented_pending()) {
+            panic!("can't flush with a read of the presented image pending!");
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            if !self.operations.is_empty() {
+                self.pump_operations(self.config.operation_budget);
+            }
+            self.create_loaded_textures();
+            let frame = self.submit_flush_frame();
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            self.wait_frame_value(frame + 1, remaining)
+                .map_err(|_| WaitTimeout { frame, timeout })?;
+            // Finished uploads and passes get marked done by the next flush frame
+            if !self.has_pending_gpu_work() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(WaitTimeout { frame, timeout });
+            }
+        }
+    }
+
+    /*
+     * Idle frame without a swapchain image, see flush_gpu_work. Returns its id.
+     */
+    fn submit_flush_frame(&mut self) -> u64 {
+        let core = self.core.clone().expect("renderer already destroyed!");
+        let waited = unsafe {
+            self.vulkan_context.device.wait_for_fences(
+                &[self.draw_commands_reuse_fence],
+                true,
+                u64::MAX,
+            )
+        };
+        self.expect_device(waited, "fence wait");
+        // Stats of the tasks queued so far count for the next render
+        let queued = std::mem::take(&mut self.stats.queued);
+        self.stats.queued.path = FramePath::Flush;
+        let frame = self.get_current_frame();
+        let stages = self.prepare_stages(true);
+        let offscreen = self.prepare_offscreen_passes();
+        // Flush frames neither record into it nor read presented images back
+        let default_attachment = self.swapchain_context.attachments[0].clone();
+        let _queues = core.lock_queues();
+        self.record_submit_commandbuffer(
+            self.draw_command_buffer,
+            self.draw_commands_reuse_fence,
+            self.present_queue,
+            SubmitSync {
+                wait_mask: &[],
+                wait_semaphores: &[],
+                signal_semaphores: &[self.frame_timeline_semaphore],
+                signal_values: &[frame + 1],
+            },
+            &default_attachment,
+            &stages,
+            &offscreen,
+        );
+        // In submission order after the uploads, which wait for the first one
+        let total_stages = self.pipeline.total_stages();
+        for stage in &self.pipeline.stages {
+            stage.signal_next_frame(
+                &self.vulkan_context.device,
+                frame,
+                total_stages,
+                self.pass_timeline_semaphore,
+                self.present_queue,
+            );
+        }
+        self.stats.queued.frame = self.incr_current_frame();
+        self.stats.last = std::mem::replace(&mut self.stats.queued, queued);
+        frame
+    }
+
+    /*
+     * Anything flush_gpu_work still has to submit or see finished.
+     */
+    fn has_pending_gpu_work(&self) -> bool {
+        let summary = self.pending_work_summary();
+        let copies = self.readbacks.as_ref().map_or(0, |e| e.pending_copies());
+        summary.texture_uploads + summary.retried_texture_uploads > 0
+            || summary.mesh_chunks + summary.retried_mesh_chunks > 0
+            || copies > 0
+            || !self.ongoing_optimal_transitions.is_empty()
+            || self
+                .tables()
+                .uploading_meshes
+                .iter()
+                .any(|e| self.own_meshes.contains(e))
+            || !self.offscreen_passes.is_empty()
+            || !self.rendering_offscreen.is_empty()
+    }
+
+    ///
+    /// First half of render. Acquires the swapchain image, waits for the previous frame,
+    /// takes the queued tasks and writes their per pass and per instance data. None if
+    /// the frame got skipped, see try_prepare_frame for why.
+    ///
+    pub fn prepare_frame(&mut self) -> Option<PreparedFrame> {
+        self.try_prepare_frame().ok()
+    }
+
+    ///
+    /// Same as prepare_frame, telling why the frame got skipped. The queued tasks get
+    /// dropped if the swapchain is out of date. They stay queued while hibernated, or if
+    /// no swapchain image became available within RendererConfig::acquire_timeout, so a
+    /// window that's minimized or occluded doesn't block the host indefinitely.
+    ///
+    /// Tasks queued while the frame is pending go to the next one, so a TaskSender can
+    /// keep feeding the next frame from another thread while this one gets submitted.
+    /// The frame has to be passed to submit_frame of this same renderer before preparing
+    /// another one. Its draw data lives in a frame region that isn't reused until the
+    /// GPU is done with the frame. It references meshes and textures by their buffers,
+    /// freeing them before it's submitted has the same effect as freeing them while the
+    /// frame runs on the GPU.
+    ///
+    pub fn try_prepare_frame(&mut self) -> Result<PreparedFrame, FrameOutcome> {
+        if let Some(frame) = self.prepared_frame {
+            panic!("frame {} was prepared but never submitted!", frame);
+        }
+        if self.is_hibernated() {
+            return Err(FrameOutcome::Hibernated);
+        }
+        if self.is_swapchain_rebuild_pending {
+            self.rebuild_swapchain();
+        }
+        let started_at = Instant::now();
+        if !self.operations.is_empty() {
+            self.pump_operations(self.config.operation_budget);
+        }
+        self.shader_resources.take_published();
+        self.create_loaded_textures();
+        for task in self.task_sender.take() {
+            self.add_task_to_queue(task);
+        }
+        let is_shared_acquired =
+            self.swapchain_context.is_shared_present() && self.frame_paths.overlay.is_acquired();
+        let is_acquired = !self.swapchain_context.is_headless() && !is_shared_acquired;
+        let acquired = if is_acquired {
+            self.acquire_next_image()
+        } else {
+            // The one shared image stays acquired, headless ones have nothing to acquire
+            Ok((0, false))
+        };
+        let (present_index, is_suboptimal) = match acquired {
+            Ok(e) => e,
+            Err(vk::Result::TIMEOUT | vk::Result::NOT_READY) => {
+                /*
+                 * Nothing got acquired so nothing signals present_complete_semaphore, the
+                 * next acquisition can use it again. Tasks and stats carry over.
+                 */
+                self.stats.acquire_timeouts += 1;
+                self.event_sink.emit(RenderEvent::AcquireTimedOut {
+                    frame: self.get_current_frame(),
+                    consecutive: self.stats.acquire_timeouts,
+                });
+                return Err(FrameOutcome::AcquireTimedOut);
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                // Nothing got signaled, drop the frame until the host calls resize
+                let frame = self.get_current_frame();
+                self.event_sink
+                    .emit(RenderEvent::SwapchainOutOfDate { frame });
+                for batch in &mut self.batches_by_task_type {
+                    batch.clear();
+                }
+                return Err(FrameOutcome::SwapchainOutOfDate);
+            }
+            Err(e) => panic!("couldn't acquire the next swapchain image: {}", e),
+        };
+        self.stats.queued.consecutive_acquire_timeouts =
+            std::mem::take(&mut self.stats.acquire_timeouts);
+        if self.swapchain_context.is_shared_present() {
+            self.frame_paths.overlay.set_acquired();
+        }
+        let frame = self.get_current_frame();
+        self.event_sink.emit(RenderEvent::FrameStarted { frame });
+        self.stats.frame_started_at = Some(started_at);
+        let default_attachment = self.swapchain_context.attachments[present_index as usize].clone();
+        // Textures, buffers and descriptors of the previous frame get reused from here on
+        let waited = unsafe {
+            self.vulkan_context.device.wait_for_fences(
+                &[self.draw_commands_reuse_fence],
+                true,
+                u64::MAX,
+            )
+        };
+        self.expect_device(waited, "fence wait");
+        let path = self.select_frame_path();
+        self.stats.queued.path = path;
+        let mut stages = self.prepare_stages(path == FramePath::Idle);
+        if path == FramePath::Overlay {
+            self.clip_overlay_frame(&mut stages, &default_attachment);
+        }
+        let offscreen = self.prepare_offscreen_passes();
+        self.prepared_frame = Some(frame);
+        Ok(PreparedFrame {
+            frame,
+            present_index,
+            default_attachment,
+            stages,
+            offscreen,
+            stats: std::mem::take(&mut self.stats.queued),
+            is_suboptimal,
+            is_acquired,
+        })
+    }
+
+    ///
+    /// Second half of render. Records the prepared frame, submits it and presents.
+    /// Returns whether acquisition or presentation found the swapchain suboptimal.
+    ///
+    pub fn submit_frame(&mut self, frame: PreparedFrame) -> FrameOutcome {
+        match self.prepared_frame.take() {
+            Some(e) if e == frame.frame => (),
+            _ => panic!(
+                "frame {} wasn't prepared by this renderer or was already submitted!",
+                frame.frame
+            ),
+        }
+        // Other renderers on the same core submit to the same queues
+        let core = self.core.clone().expect("renderer already destroyed!");
+        let _queues = core.lock_queues();
+        // Tasks queued while the frame was pending count for the next one
+        let next_stats = std::mem::replace(&mut self.stats.queued, frame.stats);
+        // Nothing to wait on for shared images acquired by an earlier frame
+        let (wait_mask, wait_semaphores): (&[_], &[_]) = if frame.is_acquired {
+            (
+                &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+                &[self.present_complete_semaphore],
+            )
+        } else {
+            (&[], &[])
+        };
+        // Nothing presents headless frames, nothing waits for them to be rendered either
+        let is_headless = self.swapchain_context.is_headless();
+        let signal_semaphores = [
+            self.frame_timeline_semaphore,
+            self.rendering_complete_semaphore,
+        ];
+        let signal_count = if is_headless { 1 } else { 2 };
+        // Binary semaphores ignore their value
+        let frame_done_value = frame.frame + 1;
+        self.record_submit_commandbuffer(
+            self.draw_command_buffer,
+            self.draw_commands_reuse_fence,
+            self.present_queue,
+            SubmitSync {
+                wait_mask,
+                wait_semaphores,
+                signal_semaphores: &signal_semaphores[..signal_count],
+                signal_values: &[frame_done_value, 0][..signal_count],
+            },
+            &frame.default_attachment,
+            &frame.stages,
+            &frame.offscreen,
+        );
+        let image = frame.default_attachment.image;
+        let is_suboptimal_present = if is_headless {
+            // Left in the presentable layout like a presented image
+            self.mark_presented(image);
+            false
+        } else {
+            self.present(frame.frame, frame.present_index, image)
+        };
+        let is_suboptimal = frame.is_suboptimal || is_suboptimal_present;
+        let outcome = self.count_suboptimal(frame.frame, is_suboptimal);
+        self.collect_present_timings();
+        #[cfg(feature = "bench-metrics")]
+        self.take_bench_counters();
+        self.end_frame_stats(frame.frame, next_stats);
+        outcome
+    }
+
+    /*
+     * Presents the rendered image, whether the swapchain turned out suboptimal.
+     */
+    fn present(&mut self, frame: u64, present_index: u32, image: vk::Image) -> bool {
+        let wait_semaphores = [self.rendering_complete_semaphore];
+        let swapchains = [self.swapchain_context.swapchain];
+        let image_indices = [present_index];
+        let present_times: Vec<_> = self
+            .display_timing
+            .as_mut()
+            .map(|e| e.next_present())
+            .into_iter()
+            .collect();
+        let mut present_times_info = vk::PresentTimesInfoGOOGLE::builder().times(&present_times);
+        let mut present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+        if !present_times.is_empty() {
+            present_info = present_info.push_next(&mut present_times_info);
+        }
+        let presented = unsafe {
+            self.vulkan_context
+                .extension
+                .swapchain
+                .queue_present(self.present_queue, &present_info)
+        };
+        match presented {
+            Ok(is_suboptimal) => {
+                self.mark_presented(image);
+                return is_suboptimal;
+            }
+            // Host catches up through resize
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.event_sink
+                    .emit(RenderEvent::SwapchainOutOfDate { frame });
+            }
+            Err(e) => self.expect_device(Err(e), "present"),
+        }
+        false
+    }
+
+    /*
+     * Records the presents the display reported on since the last frame, a few frames
+     * behind this one. The refresh cycle gets asked for once per swapchain.
+     */
+    fn collect_present_timings(&mut self) {
+        let (timing, fns) = match (
+            self.display_timing.as_mut(),
+            self.vulkan_context.extension.display_timing.as_ref(),
+        ) {
+            (Some(timing), Some(fns)) => (timing, fns),
+            _ => return,
+        };
+        let device = &self.vulkan_context.device;
+        let swapchain = self.swapchain_context.swapchain;
+        if timing.refresh_duration().is_none() {
+            let duration = present_timing::query_refresh_duration(fns, device, swapchain);
+            timing.set_refresh_duration(duration);
+        }
+        for past in present_timing::query_past_timings(fns, device, swapchain) {
+            timing.record(past);
+        }
+        self.stats.queued.missed_vsyncs = timing.missed_vsyncs();
+    }
+
+    /*
+     * Counts suboptimal frames in a row and schedules the rebuild once there are enough
+     * of them. Rebuilding right away would wait on the frame just submitted, the next
+     * prepared frame has to wait for it anyway.
+     */
+    fn count_suboptimal(&mut self, frame: u64, is_suboptimal: bool) -> FrameOutcome {
+        if !is_suboptimal {
+            self.stats.suboptimal_frames = 0;
+            self.stats.queued.consecutive_suboptimal_frames = 0;
+            return FrameOutcome::Submitted;
+        }
+        self.stats.suboptimal_frames += 1;
+        self.stats.queued.consecutive_suboptimal_frames = self.stats.suboptimal_frames;
+        self.event_sink.emit(RenderEvent::SwapchainSuboptimal {
+            frame,
+            consecutive: self.stats.suboptimal_frames,
+        });
+        if !self.config.is_manual_swapchain_rebuild
+            && self.stats.suboptimal_frames >= self.config.suboptimal_frames_before_rebuild
+        {
+            self.is_swapchain_rebuild_pending = true;
+        }
+        FrameOutcome::Suboptimal
+    }
+
+    ///
+    /// Sends tasks to the queues of the renderer from any thread, see TaskSender.
+    ///
+    pub fn task_sender(&self) -> TaskSender {
+        self.task_sender.clone()
+    }
+
+    ///
+    /// Blocks until at most max_frames_ahead submitted frames are still running on the
+    /// GPU, meant to be called at the top of the frame loop before sampling input and
+    /// queueing tasks so they are as fresh as possible once the frame reaches the screen.
+    ///
+    /// There is a single draw command buffer, so prepare_frame waits for the previous
+    /// frame before preparing the next one anyway. Waiting here with max_frames_ahead 0
+    /// moves that same wait before input sampling, render then finds the frame done and
+    /// only blocks on swapchain acquisition and submission. Values above 0 return right
+    /// away until more than one frame can be in flight.
+    ///
+    pub fn wait_for_frame_slot(
+        &self,
+        max_frames_ahead: u32,
+        timeout: std::time::Duration,
+    ) -> Result<(), WaitTimeout> {
+        // Frames before this one have to be done
+        let wait_value = match self
+            .get_current_frame()
+            .checked_sub(max_frames_ahead as u64)
+        {
+            Some(v) if v > 0 => v,
+            _ => return Ok(()),
+        };
+        self.wait_frame_value(wait_value, timeout)
+    }
+
+    /*
+     * Blocks until the frame timeline semaphore reaches the value, frame N signals N + 1.
+     */
+    pub(crate) fn wait_frame_value(
+        &self,
+        wait_value: u64,
+        timeout: std::time::Duration,
+    ) -> Result<(), WaitTimeout> {
+        let semaphores = [self.frame_timeline_semaphore];
+        let values = [wait_value];
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(&semaphores)
+            .values(&values)
+            .build();
+        let timeout_ns = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        match unsafe {
+            self.vulkan_context
+                .device
+                .wait_semaphores(&wait_info, timeout_ns)
+        } {
+            Ok(_) => Ok(()),
+            Err(vk::Result::TIMEOUT) => Err(WaitTimeout {
+                frame: wait_value - 1,
+                timeout,
+            }),
+            Err(e) => panic!("frame slot wait failed! {}", e),
+        }
+    }
+
+    fn incr_current_frame(&self) -> u64 {
+        self.current_frame.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn get_current_frame(&self) -> u64 {
+        self.current_frame.load(Ordering::Relaxed)
+    }
+
+    /*
+     * Frame tasks queued now get rendered in, the one after the prepared frame if there
+     * is one pending.
+     */
+    fn queued_frame(&self) -> u64 {
+        self.get_current_frame() + self.prepared_frame.is_some() as u64
+    }
+
+    /*
+     * Stages skipped in the frame being prepared, out of the timings of the last one
+     * timed. The budget starts over whenever the configured one or the stages change.
+     */
+    fn update_frame_budget(&mut self, timings: Option<&[Duration]>) {
+        let priorities: Vec<_> = self
+            .pipeline
+            .stages
+            .iter()
+            .map(|e| e.budget_priority)
+            .collect();
+        let budget = match self.config.gpu_frame_budget {
+            Some(e) if priorities.iter().any(Option::is_some) => e,
+            _ => {
+                self.frame_budget = None;
+                return;
+            }
+        };
+        let is_stale = self
+            .frame_budget
+            .as_ref()
+            .is_none_or(|e| e.budget() != budget || e.priorities() != priorities);
+        if is_stale {
+            self.frame_budget = Some(FrameBudget::new(budget, priorities));
+        }
+        let frame = self.get_current_frame();
+        let frame_budget = self.frame_budget.as_mut().unwrap();
+        if let Some(timings) = timings.filter(|_| !is_stale) {
+            frame_budget.record(timings);
+        }
+        let changes = frame_budget.update();
+        for i in changes.skipped {
+            self.event_sink.emit(RenderEvent::StageSkipped {
+                frame,
+                stage: self.pipeline.stages[i].name.clone(),
+                predicted: frame_budget.estimate(i),
+            });
+        }
+        for i in changes.restored {
+            self.event_sink.emit(RenderEvent::StageRestored {
+                frame,
+                stage: self.pipeline.stages[i].name.clone(),
+            });
+        }
+        self.stats.queued.skipped_stages = frame_budget
+            .skipped()
+            .into_iter()
+            .map(|e| self.pipeline.stages[e].name.clone())
+            .collect();
+    }
+
+    fn is_stage_skipped(&self, stage: usize) -> bool {
+        self.frame_budget
+            .as_ref()
+            .is_some_and(|e| e.is_skipped(stage))
+    }
+
+    fn prepare_stages(&mut self, is_idle: bool) -> Vec<PreparedStage> {
+        // Previous frame is done by now, nothing can be sampling the freed textures
+        self.release_freed_textures();
+        self.release_unbound_pages();
+        // Deterministic frames don't go by how long earlier ones took
+        let timings = self
+            .emit_stage_timings()
+            .filter(|_| self.config.deterministic.is_none());
+        self.update_quality_governor(timings.as_ref().map(|e| e.iter().sum()));
+        self.update_frame_budget(timings.as_deref());
+        self.apply_sampler_policy();
+        self.publish_samplers();
+        self.retry_texture_staging();
+        self.restore_referenced_textures();
+        self.resolve_texture_feedback();
+        self.evict_textures_over_budget();
+        self.frame_data.begin(&self.general_allocator);
+        let current_frame = self.get_current_frame();
+        self.upload_frame_constants(current_frame);
+        let completed_frames = self.completed_frames();
+        self.mesh_uploads.release(completed_frames);
+        self.mesh_uploads
+            .stage_deferred(self.config.retried_work_per_frame);
+        for (mesh_id, deferred) in self.mesh_uploads.take_retries() {
+            let work = RetriedWork::Mesh(mesh_id);
+            self.report_retry(work, RetryReason::StagingFull, deferred);
+        }
+        if let Some(readbacks) = &self.readbacks {
+            readbacks.release_abandoned(completed_frames);
+        }
+        self.retry_readbacks();
+        self.finish_offscreen_renders(completed_frames);
+        let bounds = self.mesh_uploads.take_bounds();
+        let mut tables = self.tables();
+        for (id, bounds) in bounds {
+            if let Some(mesh) = tables.meshes.get_mut(&id) {
+                mesh.bounds = Some(bounds);
+            }
+        }
+        // Other renderers of the core look after their own uploads
+        let (mesh_uploads, own_meshes) = (&self.mesh_uploads, &self.own_meshes);
+        tables.uploading_meshes.retain(|e| {
+            !own_meshes.contains(e) || mesh_uploads.is_uploading(*e, completed_frames)
+        });
+        drop(tables);
+        self.mesh_uploads
+            .begin_frame(self.config.upload_bytes_per_frame);
+        self.frame_data.regions.begin(
+            &self.vulkan_context,
+            self.frame_timeline_semaphore,
+            current_frame,
+        );
+        // Flush frames leave queued tasks alone, they go to the next render
+        if self.stats.queued.path != FramePath::Flush {
+            self.trim_tasks_to_fit();
+        }
+        let core = self.shared_core();
+        let pipeline = &mut self.pipeline;
+        let region = self.frame_data.regions.region_mut(current_frame);
+
+        if !self.ongoing_optimal_transitions.is_empty() {
+            let mut tables = core.lock_tables();
+            let current_timeline_counter = unsafe {
+                self.vulkan_context
+                    .device
+                    .get_semaphore_counter_value(self.pass_timeline_semaphore)
+                    .unwrap()
+            };
+            let prev_len = self.ongoing_optimal_transitions.len();
+            self.ongoing_optimal_transitions.retain(|e| {
+                if e.1 > current_timeline_counter {
+                    return true;
+                }
+                let texture = &mut self.textures_by_id.get_mut(&e.0).unwrap();
+                // Free the staging buffer after it has been used
+                match &texture.staging {
+                    Some(staging) => {
+                        let device = staging.as_ref().clone();
+                        let loader = &self.texture_loader;
+                        Self::free_staging(&self.general_allocator, loader, device);
+                    }
+                    _ => panic!(
+                        "staging buffer for texture {} {} is missing!",
+                        texture.id, texture.name
+                    ),
+                }
+                // Set staging to None to mark the texture as "uploaded"
+                texture.staging = None;
+                self.event_sink.emit(RenderEvent::TextureUploaded {
+                    texture: texture.id,
+                    name: texture.name.clone(),
+                });
+                // Pointed to the default texture until now, both ids of dual view ones
+                let srgb = texture.srgb.as_ref().map(|e| (e.id, e.view));
+                for (id, view) in [(texture.id, texture.view)].into_iter().chain(srgb) {
+                    tables.image_descriptors.place_image_at(
+                        &self.vulkan_context,
+                        id,
+                        vk::DescriptorImageInfo {
+                            image_view: view,
+                            image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                            ..Default::default()
+                        },
+                    );
+                }
+                // Sampled up to the previous frame
+                if let Some(replaced) = self.reimported_textures.remove(&texture.id) {
+                    self.evicted_images.push(replaced);
+                }
+                return false;
+            });
+            if prev_len != self.ongoing_optimal_transitions.len() {
+                // Update the descriptors on the device
+                tables.image_descriptors.flush(&self.vulkan_context);
+            }
+        }
+
+        if is_idle {
+            // No per pass data either, idle frames don't record any stage
+            self.stats.queued.frame_ring = region.watermarks();
+            return Vec::new();
+        }
+        plan::sort_back_to_front(
+            &mut self.batches_by_task_type[TaskKind::Translucent.to_usize()],
+        );
+        let feedback_addr = self.texture_feedback.as_ref().map_or(0, |e| e.device_addr());
+        for stage in &mut pipeline.stages {
+            stage.texture_feedback = feedback_addr;
+        }
+        let frame_budget = self.frame_budget.as_ref();
+        let is_split = self.viewports.iter().any(|e| e.is_enabled);
+        let motion = FrameMotion {
+            previous: &self.frame_data.previous_transforms,
+            simulation: self.frame_data.simulation_transforms.as_ref(),
+            uploaded_simulation: frame_data::upload_simulation_transforms(
+                self.frame_data.simulation_transforms.as_ref(),
+                &pipeline.stages,
+                &self.batches_by_task_type,
+                region,
+            ),
+        };
+        let tables = core.lock_tables();
+        let frame = StagePreparation {
+            mesh_buffers_by_id: &tables.meshes,
+            shader_resources_by_kind: &self.shader_resources.by_kind,
+            motion: &motion,
+            scene_slots: &self.scene_slots_by_id,
+        };
+        let prepared = pipeline
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                // Skipped ones still begin and end rendering, clearing or keeping outputs
+                if frame_budget.is_some_and(|e| e.is_skipped(i)) {
+                    return PreparedStage::default();
+                }
+                let tasks = &self.batches_by_task_type[stage.task_kind.to_usize()];
+                if is_split && stage.is_drawn_per_viewport() {
+                    return stage.prepare_viewports(tasks, &self.viewports, &frame, region);
+                }
+                stage.prepare(tasks, &frame, region)
+            })
+            .collect();
+        drop(tables);
+        self.stats.queued.frame_ring = region.watermarks();
+        self.record_previous_transforms(current_frame);
+        // The prepared stages hold everything the draws need from the tasks
+        for batch in &mut self.batches_by_task_type {
+            batch.clear();
+        }
+        prepared
+    }
+
+    /*
+     * Offscreen passes fitting into what the stages left of the frame region, the rest
+     * wait for the next frame.
+     */
+    fn prepare_offscreen_passes(&mut self) -> Vec<PreparedOffscreenPass> {
+        if self.offscreen_passes.is_empty() {
+            return Vec::new();
+        }
+        let current_frame = self.get_current_frame();
+        let core = self.shared_core();
+        let tables = core.lock_tables();
+        let region = self.frame_data.regions.region_mut(current_frame);
+        let depth_clear_value = self.pipeline.depth_convention.clear_value();
+        let mut prepared = Vec::new();
+        let mut deferred = Vec::new();
+        for pass in self.offscreen_passes.drain(..) {
+            let index = self
+                .pipeline
+                .stages
+                .iter()
+                .position(|e| e.name == pass.stage)
+                .unwrap();
+            let stage = &self.pipeline.stages[index];
+            let size = frame_data::offscreen_size_of(stage, region.alignment(), &pass.tasks);
+            if size > region.available() {
+                log::warn!(
+                    "frame {}: offscreen pass of {} needs {} bytes of draw data but only {} are available, deferred",
+                    current_frame,
+                    pass.stage,
+                    size,
+                    region.available()
+                );
+                deferred.push(pass);
+                continue;
+            }
+            // Cpu blending only, the frame uploads them for the stages
+            let motion = FrameMotion {
+                previous: &self.frame_data.previous_transforms,
+                simulation: self.frame_data.simulation_transforms.as_ref(),
+                uploaded_simulation: None,
+            };
+            prepared.push(PreparedOffscreenPass {
+                stage: index,
+                texture: pass.texture,
+                prepared: stage.prepare_offscreen(
+                    &pass.tasks,
+                    &StagePreparation {
+                        mesh_buffers_by_id: &tables.meshes,
+                        shader_resources_by_kind: &self.shader_resources.by_kind,
+                        motion: &motion,
+                        scene_slots: &self.scene_slots_by_id,
+                    },
+                    region,
+                ),
+                target: offscreen::target_of(
+                    &self.textures_by_id[&pass.texture],
+                    depth_clear_value,
+                ),
+            });
+        }
+        self.offscreen_passes = deferred;
+        self.stats.queued.frame_ring = region.watermarks();
+        prepared
+    }
+
+    /*
+     * Offscreen textures sample the default texture until the frame first rendering them
+     * is done, like uploading ones.
+     */
+    fn finish_offscreen_renders(&mut self, completed_frames: u64) {
+        let prev_len = self.rendering_offscreen.len();
+        let core = self.shared_core();
+        let mut tables = core.lock_tables();
+        let textures_by_id = &mut self.textures_by_id;
+        let descriptors = &mut tables.image_descriptors;
+        self.rendering_offscreen.retain(|(id, frame)| {
+            if *frame >= completed_frames {
+                return true;
+            }
+            let texture = textures_by_id.get_mut(id).unwrap();
+            texture.offscreen.as_mut().unwrap().is_rendered = true;
+            self.event_sink.emit(RenderEvent::TextureUploaded {
+                texture: texture.id,
+                name: texture.name.clone(),
+            });
+            descriptors.place_image_at(
+                &self.vulkan_context,
+                texture.id,
+                vk::DescriptorImageInfo {
+                    image_view: texture.view,
+                    image_layout: vk::ImageLayout::READ_ONLY_OPTIMAL,
+                    ..Default::default()
+                },
+            );
+            false
+        });
+        if prev_len != self.rendering_offscreen.len() {
+            descriptors.flush(&self.vulkan_context);
+        }
+    }
+
+    fn update_shading_rate_images(&mut self) {
+        for i in 0..self.pipeline.stages.len() {
+            let image = self.pipeline.stages[i]
+                .shading_rate_texture
+                .and_then(|e| self.fetch_texture(e))
+                .filter(|e| e.residency() == Residency::Resident)
+                .map(|e| (e.image, e.view));
+            self.pipeline.stages[i].shading_rate_image = image;
+        }
+    }
+
+    /*
+     * Textures texture feedback says got sampled at the finest mips upload first, ones no
+     * shader sampled last. Stable, the order they got queued in stays among equals.
+     */
+    fn prioritize_queued_uploads(&mut self) {
+        let feedback = match &self.texture_feedback {
+            Some(e) => e,
+            None => return,
+        };
+        let textures = &self.textures_by_id;
+        self.optimal_transition_queue.sort_by_key(|e| {
+            let srgb = textures[e].srgb.as_ref().map(|e| feedback.priority_of(e.id));
+            feedback.priority_of(*e).min(srgb.unwrap_or(u32::MAX))
+        });
+    }
+
+    fn record_stages(&mut self, default_attachment: &Attachment, prepared: &[PreparedStage]) {
+        self.update_shading_rate_images();
+        self.stage_texture_region_updates();
+        self.prioritize_queued_uploads();
+        let fallback_view = self.default_texture_descriptor().image_view;
+        let (sampler_descriptors, image_descriptors) = {
+            let tables = self.tables();
+            (
+                tables.sampler_descriptors.binding(),
+                tables.image_descriptors.binding(),
+            )
+        };
+        let total_stages = self.pipeline.total_stages();
+        let current_frame = self.get_current_frame();
+        let pipeline = &mut self.pipeline;
+
+        let async_upload = self
+            .async_upload
+            .as_mut()
+            .filter(|_| self.config.upload_queue != UploadQueue::Graphics);
+        if let Some(async_upload) = async_upload {
+            // Exclusive textures made while uploads were concurrent still can go async,
+            // sparse ones stay on the graphics queue their pages get bound on, as do
+            // region updates of textures already sampled from
+            let (uploads, rest): (Vec<u32>, Vec<u32>) =
+                self.optimal_transition_queue.drain(..).partition(|e| {
+                    let texture = &self.textures_by_id[e];
+                    texture.sparse.is_none()
+                        && texture.staged_regions.is_empty()
+                        && (texture.is_concurrent
+                            || self.config.upload_queue == UploadQueue::AsyncExclusive)
+                });
+            self.optimal_transition_queue = rest;
+            if !uploads.is_empty() {
+                let textures: Vec<_> = uploads.iter().map(|e| &self.textures_by_id[e]).collect();
+                let wait_value =
+                    async_upload.submit(&self.vulkan_context, &textures, self.queue_family_index);
+                self.pending_upload_wait = Some(wait_value);
+                for texture in textures {
+                    if !texture.is_concurrent {
+                        texture.acquire_from(
+                            &self.vulkan_context,
+                            self.draw_command_buffer,
+                            async_upload.family_index,
+                            self.queue_family_index,
+                        );
+                    }
+                    self.stats.queued.async_upload_bytes +=
+                        texture.staging.as_ref().map_or(0, |e| e.size);
+                    self.ongoing_optimal_transitions
+                        .push((texture.id, pipeline.signal_value_for(current_frame + 1, 0)))
+                }
+            }
+        }
+
+        if !self.optimal_transition_queue.is_empty() || !self.transition_retries.is_empty() {
+            self.vulkan_context.extension.try_begin_label(
+                self.draw_command_buffer,
+                &format!(
+                    "texture transitions ({} textures)",
+                    self.optimal_transition_queue.len() + self.transition_retries.len()
+                ),
+            );
+            // Retried ones first, then the ones queued fresh
+            let mut transitions = self
+                .transition_retries
+                .take(self.config.retried_work_per_frame);
+            transitions.extend(self.optimal_transition_queue.drain(..).map(|e| (e, 0)));
+            let signal_value = self.pipeline.signal_value_for(current_frame + 1, 0);
+            for (texture_id, attempts) in transitions {
+                let texture = &self.textures_by_id[&texture_id];
+                // Sparse textures and region updates only copy what got staged
+                let size = if texture.sparse.is_some() || !texture.staged_regions.is_empty() {
+                    texture.staging.as_ref().map_or(0, |e| e.size)
+                } else {
+                    texture.size() as u64
+                };
+                // Textures over the upload budget wait for a later frame, ones put off
+                // retry_limit times already go through regardless
+                if attempts < self.config.retry_limit && !self.try_spend_upload(size) {
+                    let deferred = self.transition_retries.defer(
+                        texture_id,
+                        RetryReason::UploadBudget,
+                        attempts + 1,
+                        self.config.retry_limit,
+                    );
+                    let work = RetriedWork::Texture(texture_id);
+                    self.report_retry(work, RetryReason::UploadBudget, deferred);
+                    continue;
+                }
+                let texture = self.textures_by_id.get_mut(&texture_id).unwrap();
+                texture.transition_to_optimal(&self.vulkan_context, self.draw_command_buffer);
+                if let Some(sparse) = &mut texture.sparse {
+                    sparse.is_initialized = true;
+                    sparse.staged.clear();
+                }
+                texture.staged_regions.clear();
+                self.ongoing_optimal_transitions
+                    .push((texture_id, signal_value))
+            }
+            self.vulkan_context
+                .extension
+                .try_end_label(self.draw_command_buffer);
+        }
+
+        self.stats.queued.mesh_upload_bytes = self.mesh_uploads.record(
+            &self.vulkan_context,
+            self.draw_command_buffer,
+            current_frame,
+        );
+        if self.stats.queued.uploading_mesh_tasks > 0 {
+            log::warn!(
+                "skipped {} tasks of meshes still uploading",
+                self.stats.queued.uploading_mesh_tasks
+            );
+        }
+        // Whichever frame comes first, flush and idle ones included
+        self.pipeline
+            .record_initial_states(&self.vulkan_context, self.draw_command_buffer);
+
+        if self.stats.queued.path == FramePath::Flush {
+            // Signaled by submit_flush_frame once the uploads of the frame are submitted
+            for stage in &self.pipeline.stages {
+                stage.wait_for_previous_frame(
+                    &self.vulkan_context.device,
+                    current_frame,
+                    total_stages,
+                    self.pass_timeline_semaphore,
+                );
+            }
+            return;
+        }
+        if self.stats.queued.path == FramePath::Idle {
+            // Later frames and texture uploads wait on the stage values of this one
+            for stage in &self.pipeline.stages {
+                stage.wait_for_previous_frame(
+                    &self.vulkan_context.device,
+                    current_frame,
+                    total_stages,
+                    self.pass_timeline_semaphore,
+                );
+                stage.signal_next_frame(
+                    &self.vulkan_context.device,
+                    current_frame,
+                    total_stages,
+                    self.pass_timeline_semaphore,
+                    self.present_queue,
+                );
+            }
+            self.record_idle_frame(default_attachment);
+            return;
+        }
+
+        // Overlay frames only record the overlay stage, the rest only keep their values going
+        let overlay_stage =
+            (self.stats.queued.path == FramePath::Overlay).then(|| self.overlay_stage().unwrap());
+        self.label_debug_flags();
+        // Skipped blits don't copy anything
+        let skipped_blits: Vec<_> = (0..self.pipeline.stages.len())
+            .map(|i| self.is_stage_skipped(i) && self.pipeline.stages[i].blit.is_some())
+            .collect();
+        let mut timer = self.stats.stage_timer.as_mut().filter(|_| {
+            self.event_sink.is_stage_timing_enabled()
+                || self.quality_governor.is_some()
+                || self.frame_budget.is_some()
+        });
+        if let Some(timer) = &mut timer {
+            timer.reset(
+                &self.vulkan_context.device,
+                self.draw_command_buffer,
+                current_frame,
+            );
+        }
+        self.state_cache.begin(self.config.is_state_cache_enabled);
+        let pipeline = &mut self.pipeline;
+        for (i, (stage, prepared)) in pipeline.stages.iter_mut().zip(prepared).enumerate() {
+            stage.wait_for_previous_frame(
+                &self.vulkan_context.device,
+                current_frame,
+                total_stages,
+                self.pass_timeline_semaphore,
+            );
+            if overlay_stage.is_some_and(|e| e != i) || skipped_blits[i] {
+                // Zero GPU time, every query has to be written for the timings to be read
+                if let Some(timer) = &timer {
+                    let cmd = self.draw_command_buffer;
+                    timer.write(&self.vulkan_context.device, cmd, stage.index, false);
+                    timer.write(&self.vulkan_context.device, cmd, stage.index, true);
+                }
+                stage.signal_next_frame(
+                    &self.vulkan_context.device,
+                    current_frame,
+                    total_stages,
+                    self.pass_timeline_semaphore,
+                    self.present_queue,
+                );
+                continue;
+            }
+            stage.substitute_unwritten_inputs(
+                &self.vulkan_context,
+                &pipeline.written_attachments,
+                fallback_view,
+            );
+            self.vulkan_context
+                .extension
+                .try_begin_label(self.draw_command_buffer, &format!("stage: {}", stage.name));
+            let started_at = timer.is_some().then(Instant::now);
+            if let Some(timer) = &timer {
+                let cmd = self.draw_command_buffer;
+                timer.write(&self.vulkan_context.device, cmd, stage.index, false);
+            }
+            // Nothing else recorded, the overlay stage has its own rendering scope
+            let scope = (stage.is_scope_continued, stage.is_scope_kept_open);
+            if overlay_stage.is_some() {
+                (stage.is_scope_continued, stage.is_scope_kept_open) = (false, false);
+            }
+            stage.render(
+                &self.vulkan_context,
+                prepared,
+                StageRecording {
+                    command_buffer: self.draw_command_buffer,
+                    sampler_descriptors,
+                    image_descriptors,
+                    default_attachment: self.scaled_target.as_ref().unwrap_or(default_attachment),
+                    named_buffers: &pipeline.named_buffers,
+                    is_verbose_labels_enabled: self.is_verbose_labels_enabled,
+                },
+                timer.as_deref_mut(),
+                &mut self.state_cache,
+            );
+            (stage.is_scope_continued, stage.is_scope_kept_open) = scope;
+            if let Some(timer) = &timer {
+                let cmd = self.draw_command_buffer;
+                timer.write(&self.vulkan_context.device, cmd, stage.index, true);
+            }
+            if let Some(started_at) = started_at {
+                self.event_sink.emit(RenderEvent::StageRecorded {
+                    frame: current_frame,
+                    stage: stage.name.clone(),
+                    cpu_time: started_at.elapsed(),
+                });
+            }
+            stage.mark_written(&mut pipeline.written_attachments);
+            self.vulkan_context
+                .extension
+                .try_end_label(self.draw_command_buffer);
+            stage.signal_next_frame(
+                &self.vulkan_context.device,
+                current_frame,
+                total_stages,
+                self.pass_timeline_semaphore,
+                self.present_queue,
+            );
+        }
+        self.stats.queued.state_commands = self.state_cache.counters();
+        // Offscreen only pipelines leave the swapchain image as is, nothing presents it
+        if self.scaled_target.is_none() && !self.pipeline.stages.iter().any(|e| e.is_final) {
+            self.record_idle_frame(default_attachment);
+        }
+
+        if let Some(scaled_target) = &self.scaled_target {
+            self.vulkan_context
+                .extension
+                .try_begin_label(self.draw_command_buffer, "upscale");
+            self.record_scaling(scaled_target, default_attachment);
+            self.vulkan_context
+                .extension
+                .try_end_label(self.draw_command_buffer);
+        }
+
+        // Textures still being uploaded show up once done
+        let inspected = self
+            .inspected_texture
+            .and_then(|e| self.textures_by_id.get(&e.index))
+            .filter(|e| e.is_uploaded());
+        if let Some(texture) = inspected {
+            self.vulkan_context
+                .extension
+                .try_begin_label(self.draw_command_buffer, "texture inspector");
+            inspector::record(
+                &self.vulkan_context,
+                self.draw_command_buffer,
+                texture,
+                default_attachment,
+            );
+            self.vulkan_context
+                .extension
+                .try_end_label(self.draw_command_buffer);
+        }
+    }
+
+    /*
+     * Scales the internal target into the presented image, with the scaling stage for
+     * the filters that have one and a blit otherwise.
+     */
+    fn record_scaling(&self, scaled_target: &Attachment, default_attachment: &Attachment) {
+        #[cfg(feature = "builtin-passes")]
+        if self.config.upscale_filter == UpscaleFilter::Sharpen {
+            let stage = self.scaling_stage.as_ref().unwrap();
+            stage.record(
+                &self.vulkan_context,
+                self.draw_command_buffer,
+                scaled_target,
+                default_attachment,
+                self.config.letterbox_color,
+            );
+            return;
+        }
+        scaling::record(
+            &self.vulkan_context,
+            self.draw_command_buffer,
+            scaled_target,
+            default_attachment,
+            self.config.upscale_filter,
+            self.config.letterbox_color,
+        );
+    }
+
+    /*
+     * After the stages, so the attachments of their stages went through the barriers of
+     * the frame already. Passes into textures freed since preparing them are skipped.
+     */
+    fn record_offscreen_passes(&mut self, passes: &[PreparedOffscreenPass]) {
+        let sampler_descriptors = self.tables().sampler_descriptors.binding();
+        let image_descriptors = self.tables().image_descriptors.binding();
+        let frame = self.get_current_frame();
+        for pass in passes {
+            let texture = match self.textures_by_id.get(&pass.texture) {
+                Some(e) if e.image == pass.target.color => e,
+                _ => continue,
+            };
+            self.pipeline.stages[pass.stage].render_offscreen(
+                &self.vulkan_context,
+                &pass.prepared,
+                sampler_descriptors,
+                image_descriptors,
+                self.draw_command_buffer,
+                &pass.target,
+            );
+            let is_rendering = texture.offscreen.as_ref().is_some_and(|e| !e.is_rendered);
+            if is_rendering && !self.rendering_offscreen.iter().any(|e| e.0 == pass.texture) {
+                self.rendering_offscreen.push((pass.texture, frame));
+            }
+        }
+    }
+
+    /*
+     * Readback of what the stages of the frame reported. Skipped while the readback
+     * buffer is full, the next frames report about the same.
+     */
+    fn request_texture_feedback(&mut self) {
+        if self.stats.queued.path == FramePath::Idle {
+            return;
+        }
+        let buffer = match &self.texture_feedback {
+            Some(e) => *e.buffer(),
+            None => return,
+        };
+        let wait_value = self.get_current_frame() + 1;
+        let request = self
+            .readbacks_for(buffer.size)
+            .and_then(|e| e.request(&buffer, 0..buffer.size, wait_value));
+        match request {
+            Ok(e) => self.texture_feedback.as_mut().unwrap().push(e),
+            Err(e) => log::debug!("texture feedback skipped, {}", e),
+        }
+    }
+
+    fn record_submit_commandbuffer(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        command_buffer_reuse_fence: vk::Fence,
+        submit_queue: vk::Queue,
+        sync: SubmitSync,
+        default_attachment: &Attachment,
+        prepared: &[PreparedStage],
+        offscreen: &[PreparedOffscreenPass],
+    ) {
+        let SubmitSync {
+            wait_mask,
+            wait_semaphores,
+            signal_semaphores,
+            signal_values,
+        } = sync;
+        unsafe {
+            // Waited on by prepare_frame
+            self.vulkan_context
+                .device
+                .reset_fences(&[command_buffer_reuse_fence])
+                .expect("fence reset failed!");
+
+            self.vulkan_context
+                .device
+                .reset_command_buffer(
+                    command_buffer,
+                    vk::CommandBufferResetFlags::RELEASE_RESOURCES,
+                )
+                .expect("reset command buffer failed!");
+
+            let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+            self.vulkan_context
+                .device
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+                .expect("begin commandbuffer failed!");
+            #[cfg(feature = "bench-metrics")]
+            let record_started = Instant::now();
+
+            // Idle frames run no stage writing it
+            let feedback = self
+                .texture_feedback
+                .as_ref()
+                .filter(|_| self.stats.queued.path != FramePath::Idle);
+            if let Some(feedback) = feedback {
+                feedback.record_clear(&self.vulkan_context, command_buffer);
+            }
+            self.record_retained_restore(command_buffer, default_attachment);
+            self.record_stages(default_attachment, prepared);
+            self.record_retain(command_buffer, default_attachment);
+            self.record_offscreen_passes(offscreen);
+            self.request_texture_feedback();
+            if let Some(readbacks) = &mut self.readbacks {
+                readbacks.record(&self.vulkan_context, command_buffer, default_attachment);
+            }
+
+            self.vulkan_context
+                .device
+                .end_command_buffer(command_buffer)
+                .expect("end command buffer failed!");
+            #[cfg(feature = "bench-metrics")]
+            {
+                self.stats.queued.bench.record_time = record_started.elapsed();
+            }
+
+            let command_buffers = vec![command_buffer];
+
+            // Textures uploaded on the async queue can't be sampled before it's done
+            let mut wait_semaphores = wait_semaphores.to_vec();
+            let mut wait_mask = wait_mask.to_vec();
+            let mut wait_values = Vec::new();
+            if let Some(value) = self.pending_upload_wait.take() {
+                // Values of binary semaphores are ignored
+                wait_values.resize(wait_semaphores.len(), 0);
+                wait_values.push(value);
+                wait_semaphores.push(self.async_upload.as_ref().unwrap().timeline_semaphore);
+                wait_mask.push(vk::PipelineStageFlags::ALL_COMMANDS);
+            }
+            // Nor can pages of sparse textures before they're bound
+            if let Some(value) = self.pending_sparse_wait.take() {
+                wait_values.resize(wait_semaphores.len(), 0);
+                wait_values.push(value);
+                wait_semaphores.push(self.sparse_semaphore.unwrap());
+                wait_mask.push(vk::PipelineStageFlags::ALL_COMMANDS);
+            }
+            // Nor can maps being baked
+            if let Some(value) = self.pending_ibl_wait.take() {
+                wait_values.resize(wait_semaphores.len(), 0);
+                wait_values.push(value);
+                wait_semaphores.push(self.ibl_baker.as_ref().unwrap().semaphore);
+                wait_mask.push(vk::PipelineStageFlags::ALL_COMMANDS);
+            }
+            // Whatever the producer wrote can be read by any stage
+            let mut signal_semaphores = signal_semaphores.to_vec();
+            let mut signal_values = signal_values.to_vec();
+            if let Some(value) = self.pending_external_wait.take() {
+                wait_values.resize(wait_semaphores.len(), 0);
+                wait_values.push(value);
+                wait_semaphores.push(self.interop_semaphore.unwrap());
+                wait_mask.push(vk::PipelineStageFlags::ALL_COMMANDS);
+                signal_values.resize(signal_semaphores.len(), 0);
+                signal_values.push(value + 1);
+                signal_semaphores.push(self.interop_semaphore.unwrap());
+                self.external_value = value + 1;
+            }
+            let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+                .wait_semaphore_values(&wait_values)
+                .signal_semaphore_values(&signal_values)
+                .build();
+            let submit_info = vk::SubmitInfo::builder()
+                .push_next(&mut timeline_info)
+                .wait_semaphores(&wait_semaphores)
+                .wait_dst_stage_mask(&wait_mask)
+                .command_buffers(&command_buffers)
+                .signal_semaphores(&signal_semaphores);
+
+            let submitted = self.queue_submit(
+                submit_queue,
+                &[submit_info.build()],
+                command_buffer_reuse_fence,
+            );
+            self.expect_device(submitted, "queue submit");
+        }
+    }
+}
+
+/*
+ * Semaphores a frame submission waits on and signals. Values only matter for timeline
+ * semaphores, binary ones get 0.
+ */
+struct SubmitSync<'a> {
+    wait_mask: &'a [vk::PipelineStageFlags],
+    wait_semaphores: &'a [vk::Semaphore],
+    signal_semaphores: &'a [vk::Semaphore],
+    signal_values: &'a [u64],
+}
+
+pub fn make_renderer<F>(
+    is_vsync_enabled: bool,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: &[*const i8],
+    create_surface: F,
+) -> Renderer
+where
+    F: FnOnce(&ash::Entry, &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>,
+{
+    make_renderer_with_config(
+        RendererConfig::default(),
+        is_vsync_enabled,
+        is_debug_enabled,
+        is_validation_layer_enabled,
+        instance_extensions,
+        create_surface,
+    )
+}
+
+///
+/// make_renderer with the old surface callback, which writes the surface through the
+/// pointer and returns the result.
+///
+#[deprecated(note = "use make_renderer, its callback returns the surface")]
+pub fn make_renderer_with_surface_ptr<F>(
+    is_vsync_enabled: bool,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: &[*const i8],
+    create_surface: F,
+) -> Renderer
+where
+    F: FnOnce(&ash::Entry, &ash::Instance, *mut vk::SurfaceKHR) -> vk::Result,
+{
+    make_renderer(
+        is_vsync_enabled,
+        is_debug_enabled,
+        is_validation_layer_enabled,
+        instance_extensions,
+        |entry, instance| {
+            let mut surface = vk::SurfaceKHR::null();
+            match create_surface(entry, instance, &mut surface) {
+                vk::Result::SUCCESS => Ok(surface),
+                e => Err(e),
+            }
+        },
+    )
+}
+
+///
+/// Same as make_renderer, with config in place from the start. Needed for the parts
+/// of it only read at creation, like the descriptor mode and the adapter.
+///
+pub fn make_renderer_with_config<F>(
+    config: RendererConfig,
+    is_vsync_enabled: bool,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: &[*const i8],
+    create_surface: F,
+) -> Renderer
+where
+    F: FnOnce(&ash::Entry, &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>,
+{
+    try_make_renderer_with_config(
+        config,
+        is_vsync_enabled,
+        is_debug_enabled,
+        is_validation_layer_enabled,
+        instance_extensions,
+        create_surface,
+    )
+    .unwrap_or_else(|e| panic!("{}!", e))
+}
+
+///
+/// Same as make_renderer_with_config, returning the error if the adapter of the config
+/// can't be used instead of panicking.
+///
+pub fn try_make_renderer_with_config<F>(
+    config: RendererConfig,
+    is_vsync_enabled: bool,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: &[*const i8],
+    create_surface: F,
+) -> Result<Renderer, AdapterError>
+where
+    F: FnOnce(&ash::Entry, &ash::Instance) -> Result<vk::SurfaceKHR, vk::Result>,
+{
+    let core = try_make_render_core(
+        &config,
+        is_debug_enabled,
+        is_validation_layer_enabled,
+        instance_extensions,
+    )?;
+    // Renderer ends up as the only owner, destroying it destroys the core too
+    Ok(Renderer::with_core(
+        core,
+        config,
+        "pipeline.json",
+        is_vsync_enabled,
+        create_surface,
+    ))
+}
+
+///
+/// Instance and device to share between renderers, see Renderer::with_core. Only the
+/// descriptor mode and the adapter of the config are read.
+///
+pub fn make_render_core(
+    config: &RendererConfig,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: &[*const i8],
+) -> Arc<RenderCore> {
+    try_make_render_core(
+        config,
+        is_debug_enabled,
+        is_validation_layer_enabled,
+        instance_extensions,
+    )
+    .unwrap_or_else(|e| panic!("{}!", e))
+}
+
+///
+/// Same as make_render_core, returning the error if the adapter of the config can't be
+/// used instead of panicking. Nothing made on the way is left behind then.
+///
+pub fn try_make_render_core(
+    config: &RendererConfig,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: &[*const i8],
+) -> Result<Arc<RenderCore>, AdapterError> {
+    log::trace!("entering make_render_core");
+
+    log::trace!("creating entry...");
+    let entry = Entry::linked();
+    log::trace!("entry created!");
+    log::trace!("creating instance...");
+    // Shared presentation support of surfaces can only be asked for through it
+    let is_surface_capabilities2_enabled = config.low_latency_overlay
+        && is_instance_extension_supported(&entry, vk::KhrGetSurfaceCapabilities2Fn::name());
+    let mut instance_extensions = instance_extensions.to_vec();
+    if is_surface_capabilities2_enabled {
+        instance_extensions.push(vk::KhrGetSurfaceCapabilities2Fn::name().as_ptr());
+    }
+    let instance = make_instance(
+        &entry,
+        &instance_extensions,
+        is_debug_enabled,
+        is_validation_layer_enabled,
+    );
+    log::trace!("instance created!");
+    let mut cleanup = Cleanup::new();
+    let instance_to_destroy = instance.clone();
+    cleanup.defer(move || unsafe { instance_to_destroy.destroy_instance(None) });
+
+    let debug_context = if is_debug_enabled {
+        Some(DebugContext::new(&entry, &instance))
+    } else {
+        None
+    };
+    if let Some(e) = &debug_context {
+        let mut debug_context = e.clone();
+        cleanup.defer(move || debug_context.destroy());
+    }
+
+    let debug_utils_ext = if is_debug_enabled {
+        Some(DebugUtils::new(&entry, &instance))
+    } else {
+        None
+    };
+    let surface_extension = khr::Surface::new(&entry, &instance);
+    log::trace!("selecting physical device...");
+    // Renderers check presentation support once they have their surface
+    let adapters = adapter::adapters_of(&instance, None);
+    let adapter = config.adapter.select(&adapters, config.descriptor_mode)?;
+    log::info!("adapter {} {}", adapter.index, adapter.name);
+    let physical_device = adapter.physical_device;
+    let queue_family_index = adapter.graphics_family.unwrap();
+    let async_compute_family = upload::find_async_compute_family(&instance, physical_device);
+    let is_descriptor_buffer_supported =
+        is_device_extension_supported(&instance, physical_device, ext::DescriptorBuffer::name());
+    let is_descriptor_buffer_enabled = match config.descriptor_mode {
+        DescriptorMode::Auto => is_descriptor_buffer_supported,
+        DescriptorMode::DescriptorBuffer if !is_descriptor_buffer_supported => {
+            panic!("descriptor buffer mode requested but the device doesn't support it!")
+        }
+        DescriptorMode::DescriptorBuffer => true,
+        DescriptorMode::DescriptorSets => false,
+    };
+    if is_descriptor_buffer_enabled {
+        log::info!("descriptors in descriptor buffers");
+    } else {
+        log::info!("descriptors in classic descriptor sets");
+    }
+    // Lets sRGB swapchain images also get UNORM views
+    let is_swapchain_mutable_format_enabled = is_device_extension_supported(
+        &instance,
+        physical_device,
+        vk::KhrSwapchainMutableFormatFn::name(),
+    );
+    let is_depth_bounds_enabled = unsafe {
+        instance
+            .get_physical_device_features(physical_device)
+            .depth_bounds
+            == vk::TRUE
+    };
+    // Descriptors never point to invalid images by construction, these only back that up
+    let (is_null_descriptor_enabled, is_robust_image_access_enabled) =
+        robustness_support(&instance, physical_device);
+    if is_null_descriptor_enabled || is_robust_image_access_enabled {
+        log::info!(
+            "null descriptors {}, robust image access {}",
+            is_null_descriptor_enabled,
+            is_robust_image_access_enabled
+        );
+    }
+    let (is_shading_rate_enabled, shading_rate_texel_size) =
+        shading_rate_support(&instance, physical_device);
+    let external_semaphore_support = interop::export_support(&instance, physical_device);
+    let is_sparse_residency_enabled =
+        sparse_residency_support(&instance, physical_device, queue_family_index);
+    let is_display_timing_enabled = is_device_extension_supported(
+        &instance,
+        physical_device,
+        vk::GoogleDisplayTimingFn::name(),
+    );
+    let is_shared_presentable_image_enabled = is_surface_capabilities2_enabled
+        && is_device_extension_supported(
+            &instance,
+            physical_device,
+            vk::KhrSharedPresentableImageFn::name(),
+        );
+    if config.low_latency_overlay && !is_shared_presentable_image_enabled {
+        log::warn!(
+            "no shared presentable images, low latency overlay redraws a copy of the last frame"
+        );
+    }
+    if is_shading_rate_enabled {
+        log::info!(
+            "fragment shading rate enabled, attachment texel size {:?}",
+            shading_rate_texel_size.map(|e| (e.width, e.height))
+        );
+    } else {
+        log::info!("no fragment shading rate, every stage shades at 1x1");
+    }
+    log::trace!("physical device selected!");
+    log::trace!("creating device...");
+    let (device, capabilities) = make_device(
+        &instance,
+        physical_device,
+        queue_family_index,
+        async_compute_family,
+        DeviceFeatures {
+            is_descriptor_buffer_enabled,
+            is_swapchain_mutable_format_enabled,
+            is_depth_bounds_enabled,
+            is_null_descriptor_enabled,
+            is_robust_image_access_enabled,
+            is_shading_rate_enabled,
+            shading_rate_texel_size,
+            external_semaphore_extension: external_semaphore_support.map(|e| e.0),
+            is_sparse_residency_enabled,
+            is_display_timing_enabled,
+            is_shared_presentable_image_enabled,
+        },
+        is_debug_enabled,
+    );
+    log::trace!("device created!");
+    let device_to_destroy = device.clone();
+    cleanup.defer(move || unsafe { device_to_destroy.destroy_device(None) });
+
+    let swapchain_extension = ash::extensions::khr::Swapchain::new(&instance, &device);
+    let descriptor_buffer_ext = is_descriptor_buffer_enabled
+        .then(|| ash::extensions::ext::DescriptorBuffer::new(&instance, &device));
+    let descriptor_sizes =
+        is_descriptor_buffer_enabled.then(|| DescriptorSizes::of(&instance, physical_device));
+    // Ash has no wrapper for it, the functions get loaded by hand
+    let fragment_shading_rate = is_shading_rate_enabled.then(|| {
+        vk::KhrFragmentShadingRateFn::load(|name| unsafe {
+            std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+        })
+    });
+
+    let external_semaphore = external_semaphore_support
+        .map(|(_, handle_type)| ExternalSemaphoreFns::load(&instance, &device, handle_type));
+    let display_timing = is_display_timing_enabled.then(|| {
+        vk::GoogleDisplayTimingFn::load(|name| unsafe {
+            std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+        })
+    });
+
+    let surface_capabilities2 = is_surface_capabilities2_enabled.then(|| {
+        vk::KhrGetSurfaceCapabilities2Fn::load(|name| unsafe {
+            std::mem::transmute(entry.get_instance_proc_addr(instance.handle(), name.as_ptr()))
+        })
+    });
+
+    let mem_props = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+    let vulkan_context = VulkanContext {
+        entry,
+        device,
+        instance,
+        physical_device,
+        memory_properties: mem_props,
+        capabilities,
+        extension: ExtensionContext {
+            descriptor_buffer: descriptor_buffer_ext,
+            descriptor_sizes,
+            debug_utils: debug_utils_ext,
+            swapchain: swapchain_extension,
+            surface: surface_extension,
+            is_swapchain_mutable_format_enabled,
+            is_depth_bounds_enabled,
+            fragment_shading_rate,
+            shading_rate_texel_size,
+            external_semaphore,
+            display_timing,
+            surface_capabilities2,
+            is_shared_presentable_image_enabled,
+        },
+    };
+    log::trace!("render core finished!");
+    cleanup.disarm();
+    Ok(Arc::new(RenderCore::new(
+        vulkan_context,
+        queue_family_index,
+        async_compute_family,
+        is_validation_layer_enabled,
+        debug_context,
+    )))
+}
+
+///
+/// Optional device features and extensions make_device enables, checked for support
+/// beforehand.
+///
+pub struct DeviceFeatures<'a> {
+    pub is_descriptor_buffer_enabled: bool,
+    pub is_swapchain_mutable_format_enabled: bool,
+    pub is_depth_bounds_enabled: bool,
+    pub is_null_descriptor_enabled: bool,
+    pub is_robust_image_access_enabled: bool,
+    pub is_shading_rate_enabled: bool,
+    pub shading_rate_texel_size: Option<vk::Extent2D>,
+    pub external_semaphore_extension: Option<&'a CStr>,
+    pub is_sparse_residency_enabled: bool,
+    pub is_display_timing_enabled: bool,
+    pub is_shared_presentable_image_enabled: bool,
+}
+
+pub fn make_device(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    queue_family_index: u32,
+    async_compute_family: Option<u32>,
+    features: DeviceFeatures,
+    is_debug_enabled: bool,
+) -> (ash::Device, DeviceCapabilities) {
+    let DeviceFeatures {
+        is_descriptor_buffer_enabled,
+        is_swapchain_mutable_format_enabled,
+        is_depth_bounds_enabled,
+        is_null_descriptor_enabled,
+        is_robust_image_access_enabled,
+        is_shading_rate_enabled,
+        shading_rate_texel_size,
+        external_semaphore_extension,
+        is_sparse_residency_enabled,
+        is_display_timing_enabled,
+        is_shared_presentable_image_enabled,
+    } = features;
+    let mut device_extension_names_raw = vec![khr::Swapchain::name().as_ptr()];
+    if is_descriptor_buffer_enabled {
+        device_extension_names_raw.push(ext::DescriptorBuffer::name().as_ptr());
+    }
+    if is_swapchain_mutable_format_enabled {
+        device_extension_names_raw.push(vk::KhrSwapchainMutableFormatFn::name().as_ptr());
+    }
+    if is_null_descriptor_enabled {
+        device_extension_names_raw.push(vk::ExtRobustness2Fn::name().as_ptr());
+    }
+    if is_shading_rate_enabled {
+        device_extension_names_raw.push(vk::KhrFragmentShadingRateFn::name().as_ptr());
+    }
+    if let Some(name) = external_semaphore_extension {
+        device_extension_names_raw.push(name.as_ptr());
+    }
+    if is_display_timing_enabled {
+        device_extension_names_raw.push(vk::GoogleDisplayTimingFn::name().as_ptr());
+    }
+    if is_shared_presentable_image_enabled {
+        device_extension_names_raw.push(vk::KhrSharedPresentableImageFn::name().as_ptr());
+    }
+    let non_semantic_info_name =
+        CStr::from_bytes_with_nul(b"VK_KHR_shader_non_semantic_info\0").unwrap();
+    if is_debug_enabled {
+        device_extension_names_raw.push(non_semantic_info_name.as_ptr());
+    }
+    // Enabled whenever supported, pipelines needing them say so in "requires"
+    let supported = unsafe { instance.get_physical_device_features(physical_device) };
+    let features = vk::PhysicalDeviceFeatures {
+        shader_clip_distance: 1,
+        depth_bounds: is_depth_bounds_enabled as u32,
+        depth_clamp: supported.depth_clamp,
+        wide_lines: supported.wide_lines,
+        sampler_anisotropy: supported.sampler_anisotropy,
+        sparse_binding: is_sparse_residency_enabled as u32,
+        sparse_residency_image2_d: is_sparse_residency_enabled as u32,
+        ..Default::default()
+    };
+    // Stages with several views render into all of them in one go
+    let mut features11 = vk::PhysicalDeviceVulkan11Features {
+        multiview: 1,
+        ..Default::default()
+    };
+    let mut features12 = vk::PhysicalDeviceVulkan12Features {
+        descriptor_indexing: 1,
+        timeline_semaphore: 1,
+        buffer_device_address: 1,
+        scalar_block_layout: 1,
+        runtime_descriptor_array: 1,
+        shader_sampled_image_array_non_uniform_indexing: 1,
+        // Descriptor sets need these to be written while bound
+        descriptor_binding_partially_bound: !is_descriptor_buffer_enabled as u32,
+        descriptor_binding_sampled_image_update_after_bind: !is_descriptor_buffer_enabled as u32,
+        ..Default::default()
+    };
+    let mut features13 = vk::PhysicalDeviceVulkan13Features {
+        dynamic_rendering: 1,
+        synchronization2: 1,
+        robust_image_access: is_robust_image_access_enabled as u32,
+        ..Default::default()
+    };
+    let mut robustness2_feature = vk::PhysicalDeviceRobustness2FeaturesEXT {
+        null_descriptor: 1,
+        ..Default::default()
+    };
+    let mut shading_rate_feature = vk::PhysicalDeviceFragmentShadingRateFeaturesKHR {
+        pipeline_fragment_shading_rate: 1,
+        attachment_fragment_shading_rate: shading_rate_texel_size.is_some() as u32,
+        ..Default::default()
+    };
+    let mut descriptor_buffer_feature = vk::PhysicalDeviceDescriptorBufferFeaturesEXT {
+        descriptor_buffer: 1,
+        ..Default::default()
+    };
+    let mut features2_builder = vk::PhysicalDeviceFeatures2::builder()
+        .features(features)
+        .push_next(&mut features11)
+        .push_next(&mut features12)
+        .push_next(&mut features13);
+    if is_descriptor_buffer_enabled {
+        features2_builder = features2_builder.push_next(&mut descriptor_buffer_feature);
+    }
+    if is_null_descriptor_enabled {
+        features2_builder = features2_builder.push_next(&mut robustness2_feature);
+    }
+    if is_shading_rate_enabled {
+        features2_builder = features2_builder.push_next(&mut shading_rate_feature);
+    }
+    let mut features2 = features2_builder.build();
+
+    let priorities = [1.0];
+
+    let mut queue_infos = vec![vk::DeviceQueueCreateInfo::builder()
+        .queue_family_index(queue_family_index)
+        .queue_priorities(&priorities)
+        .build()];
+    if let Some(family) = async_compute_family {
+        queue_infos.push(
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(family)
+                .queue_priorities(&priorities)
+                .build(),
+        );
+    }
+
+    let device_create_info = vk::DeviceCreateInfo::builder()
+        .queue_create_infos(&queue_infos)
+        .enabled_extension_names(&device_extension_names_raw)
+        .push_next(&mut features2)
+        .build();
+
+    log::info!("initializing Device...");
+    let device: ash::Device = unsafe {
+        instance
+            .create_device(physical_device, &device_create_info, None)
+            .expect("couldn't create the device!")
+    };
+    log::info!("device initialized!");
+    let capabilities = DeviceCapabilities {
+        is_multiview_enabled: features11.multiview == vk::TRUE,
+        is_shading_rate_enabled,
+        shading_rate_texel_size: shading_rate_texel_size.filter(|_| is_shading_rate_enabled),
+        is_depth_bounds_enabled,
+        is_depth_clamp_enabled: features.depth_clamp == vk::TRUE,
+        is_wide_lines_enabled: features.wide_lines == vk::TRUE,
+        is_sampler_anisotropy_enabled: features.sampler_anisotropy == vk::TRUE,
+        is_descriptor_buffer_enabled,
+        is_swapchain_mutable_format_enabled,
+        is_null_descriptor_enabled,
+        is_robust_image_access_enabled,
+        is_external_semaphore_enabled: external_semaphore_extension.is_some(),
+        is_sparse_residency_enabled,
+        is_display_timing_enabled,
+    };
+    log::info!("device capabilities {:?}", capabilities);
+    (device, capabilities)
+}
+
+pub fn make_instance(
+    entry: &ash::Entry,
+    extensions: &[*const i8],
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+) -> ash::Instance {
+    let app_name = CStr::from_bytes_with_nul(b"rend-vk\0").unwrap();
+
+    let mut layers_names_raw = vec![];
+
+    let validation_layer_name =
+        CStr::from_bytes_with_nul(b"VK_LAYER_KHRONOS_validation\0").unwrap();
+    if is_debug_enabled && is_validation_layer_enabled {
+        layers_names_raw.push(validation_layer_name.as_ptr());
+    }
+
+    let mut instance_extensions = extensions.to_vec();
+    if is_debug_enabled {
+        instance_extensions.push(DebugUtils::name().as_ptr());
+    }
+
+    let appinfo = vk::ApplicationInfo::builder()
+        .application_name(app_name)
+        .application_version(0)
+        .engine_name(app_name)
+        .engine_version(0)
+        .api_version(vk::make_api_version(0, 1, 3, 0));
+
+    let mut create_info = vk::InstanceCreateInfo::builder()
+        .application_info(&appinfo)
+        .enabled_layer_names(&layers_names_raw)
+        .enabled_extension_names(&instance_extensions);
+
+    let enabled_validation_features = [vk::ValidationFeatureEnableEXT::DEBUG_PRINTF];
+    let mut validation_features_ext = vk::ValidationFeaturesEXT::builder()
+        .enabled_validation_features(&enabled_validation_features)
+        .build();
+
+    let mut messenger_info = debug::messenger_create_info();
+
+    if is_debug_enabled {
+        create_info = create_info
+            .push_next(&mut validation_features_ext)
+            .push_next(&mut messenger_info);
+    }
+
+    log::info!("initializing Instance...");
+    let instance: ash::Instance = unsafe {
+        entry
+            .create_instance(&create_info, None)
+            .expect("instance creation error!")
+    };
+    log::info!("instance initialized!");
+    return instance;
+}
+
+///
+/// Whether the device supports null descriptors from VK_EXT_robustness2 and robust image
+/// access, in that order.
+///
+pub fn robustness_support(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> (bool, bool) {
+    let is_robustness2_supported =
+        is_device_extension_supported(instance, physical_device, vk::ExtRobustness2Fn::name());
+    let mut robustness2 = vk::PhysicalDeviceRobustness2FeaturesEXT::default();
+    let mut features13 = vk::PhysicalDeviceVulkan13Features::default();
+    let mut features2_builder = vk::PhysicalDeviceFeatures2::builder().push_next(&mut features13);
+    if is_robustness2_supported {
+        features2_builder = features2_builder.push_next(&mut robustness2);
+    }
+    let mut features2 = features2_builder.build();
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+    (
+        is_robustness2_supported && robustness2.null_descriptor == vk::TRUE,
+        features13.robust_image_access == vk::TRUE,
+    )
+}
+
+///
+/// Whether the device can bind pages of 2D images that read as zeros while unbound, with
+/// the binding done on the graphics queue.
+///
+pub fn sparse_residency_support(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    queue_family_index: u32,
+) -> bool {
+    let features = unsafe { instance.get_physical_device_features(physical_device) };
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    features.sparse_binding == vk::TRUE
+        && features.sparse_residency_image2_d == vk::TRUE
+        && properties.sparse_properties.residency_non_resident_strict == vk::TRUE
+        && families[queue_family_index as usize]
+            .queue_flags
+            .contains(vk::QueueFlags::SPARSE_BINDING)
+}
+
+///
+/// Whether the device can set the shading rate per pipeline, and the smallest texel size
+/// of shading rate attachments if it can take the rate from those too.
+///
+pub fn shading_rate_support(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> (bool, Option<vk::Extent2D>) {
+    let name = vk::KhrFragmentShadingRateFn::name();
+    if !is_device_extension_supported(instance, physical_device, name) {
+        return (false, None);
+    }
+    let mut rate_features = vk::PhysicalDeviceFragmentShadingRateFeaturesKHR::default();
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut rate_features)
+        .build();
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+    let mut rate_properties = vk::PhysicalDeviceFragmentShadingRatePropertiesKHR::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::builder()
+        .push_next(&mut rate_properties)
+        .build();
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+    if rate_features.pipeline_fragment_shading_rate != vk::TRUE {
+        return (false, None);
+    }
+    let texel_size = (rate_features.attachment_fragment_shading_rate == vk::TRUE)
+        .then_some(rate_properties.min_fragment_shading_rate_attachment_texel_size);
+    (true, texel_size)
+}
+
+pub fn is_instance_extension_supported(entry: &ash::Entry, name: &CStr) -> bool {
+    let extensions = entry
+        .enumerate_instance_extension_properties(None)
+        .expect("couldn't enumerate the instance extensions!");
+    extensions
+        .iter()
+        .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name)
+}
+
+pub fn is_device_extension_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    name: &CStr,
+) -> bool {
+    let extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .expect("couldn't enumerate the device extensions!")
+    };
+    extensions
+        .iter()
+        .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name)
+}
+
+///
+/// First discrete device with a graphics queue family, which also has to present to the
+/// surface if one is passed.
+///
+pub fn select_physical_device(
+    instance: &ash::Instance,
+    surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+) -> (vk::PhysicalDevice, u32) {
+    let devices = unsafe {
+        instance
+            .enumerate_physical_devices()
+            .expect("Physical device error")
+    };
+    devices
+        .iter()
+        .find_map(|pdevice| {
+            let properties = unsafe { instance.get_physical_device_properties(*pdevice) };
+            let is_discrete = vk::PhysicalDeviceType::DISCRETE_GPU == properties.device_type;
+            if !is_discrete {
+                return None;
+            }
+            unsafe {
+                instance
+                    .get_physical_device_queue_family_properties(*pdevice)
+                    .iter()
+                    .enumerate()
+                    .find_map(|(index, info)| {
+                        let supports_graphic_and_surface =
+                            info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                                && surface.is_none_or(|(ext, surface)| {
+                                    ext.get_physical_device_surface_support(
+                                        *pdevice,
+                                        index as u32,
+                                        surface,
+                                    )
+                                    .unwrap()
+                                });
+                        if supports_graphic_and_surface {
+                            Some((*pdevice, index as u32))
+                        } else {
+                            None
+                        }
+                    })
+            }
+        })
+        .expect("Couldn't find a suitable physical device!")
+}
+
+pub(crate) fn make_test_triangle(buffer_allocator: &mut DeviceAllocator) -> MeshBuffer {
+    #[derive(Clone, Debug, Copy)]
+    struct Attrib3f {
+        pub values: [f32; 3],
+    }
+    #[derive(Clone, Debug, Copy)]
+    struct Attrib2f {
+        pub values: [f32; 2],
+    }
+    let vertices = [
+        Attrib3f {
+            values: [-1.0, 1.0, 0.0],
+        },
+        Attrib3f {
+            values: [1.0, 1.0, 0.0],
+        },
+        Attrib3f {
+            values: [0.0, -1.0, 0.0],
+        },
+    ];
+    let tex_coords = [
+        Attrib2f { values: [0.0, 0.0] },
+        Attrib2f { values: [1.0, 0.0] },
+        Attrib2f { values: [1.0, 1.0] },
+    ];
+    let normals = [
+        Attrib3f {
+            values: [0.0, 1.0, 0.0],
+        },
+        Attrib3f {
+            values: [1.0, 1.0, 0.0],
+        },
+        Attrib3f {
+            values: [1.0, 0.0, 0.0],
+        },
+    ];
+
+    fn alloc_and_copy<T: std::marker::Copy>(
+        elements: &[T],
+        buffer_allocator: &mut DeviceAllocator,
+    ) -> DeviceSlice {
+        let buffer = buffer_allocator
+            .alloc(std::mem::size_of_val(&elements) as u64)
+            .expect("couldn't allocate index buffer");
+        let mut slice = unsafe {
+            Align::new(
+                buffer.addr,
+                align_of::<u32>() as u64,
+                buffer_allocator.buffer.alignment,
+            )
+        };
+        slice.copy_from_slice(elements);
+        buffer
+    }
+
+    let vertex_buffer = alloc_and_copy(&vertices, buffer_allocator);
+    let normal_buffer = alloc_and_copy(&normals, buffer_allocator);
+    let tex_coord_buffer = alloc_and_copy(&tex_coords, buffer_allocator);
+
+    MeshBuffer {
+        vertices: vertex_buffer,
+        indices: DeviceSlice::empty(),
+        tex_coords: tex_coord_buffer,
+        normals: normal_buffer,
+        count: vertices.len() as u32,
+        bounds: MeshBounds::of_positions(&vertices.map(|e| Vec3::from(e.values))),
+        bounds_inflation: 1.0,
+        layout: None,
+        formats: StreamFormats::default(),
+    }
+}
+
+/*
+ * The triangle of make_test_triangle with the position, normal and tex coord of each
+ * vertex next to each other.
+ */
+pub(crate) fn make_test_triangle_interleaved(buffer_allocator: &mut DeviceAllocator) -> MeshBuffer {
+    #[rustfmt::skip]
+    let vertices: [f32; 24] = [
+        -1.0, 1.0, 0.0,  0.0, 1.0, 0.0,  0.0, 0.0,
+        1.0, 1.0, 0.0,  1.0, 1.0, 0.0,  1.0, 0.0,
+        0.0, -1.0, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0,
+    ];
+    let layout = VertexLayout::packed();
+    let bytes: Vec<u8> = vertices.iter().flat_map(|e| e.to_ne_bytes()).collect();
+    let buffer = buffer_allocator
+        .alloc(bytes.len() as u64)
+        .expect("couldn't allocate interleaved test triangle");
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.addr as *mut u8, bytes.len()) };
+    MeshBuffer {
+        vertices: buffer,
+        normals: DeviceSlice::empty(),
+        tex_coords: DeviceSlice::empty(),
+        indices: DeviceSlice::empty(),
+        count: 3,
+        bounds: MeshBounds::of_strided_position_bytes(&bytes, layout.stride(), 0),
+        bounds_inflation: 1.0,
+        formats: layout.formats(),
+        layout: Some(layout),
+    }
+}
@@ -0,0 +1,143 @@
+/*
+ * Single shader resources of the frame, the ones placed by the host and the ones
+ * published from other threads, see publisher, and the checks of what the host provides
+ * against the blocks the stages declare.
+ */
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    pipeline::stage::Stage,
+    publisher::{ResourceConsumer, ResourcePublisher},
+    reflection::LayoutMismatch,
+    render_task::TaskKind,
+    shader_resource::{KnownLayout, ResourceKind, SingleResource},
+};
+
+use super::Renderer;
+
+pub(super) struct ShaderResources {
+    pub by_kind: HashMap<ResourceKind, SingleResource>,
+    // Sizes a mismatch got logged for, once per kind and size
+    pub reported_sizes: HashSet<(ResourceKind, usize)>,
+    consumers: HashMap<ResourceKind, ResourceConsumer>,
+}
+
+impl ShaderResources {
+    pub fn new() -> Self {
+        Self {
+            by_kind: HashMap::new(),
+            reported_sizes: HashSet::new(),
+            consumers: HashMap::new(),
+        }
+    }
+
+    pub fn publisher(&mut self, kind: ResourceKind) -> ResourcePublisher {
+        self.consumers
+            .entry(kind)
+            .or_insert_with(|| ResourceConsumer::new(kind, kind.resource_size()))
+            .publisher()
+    }
+
+    ///
+    /// Replaces the placed resources with the latest values published since the last
+    /// frame. Values of the wrong size get discarded.
+    ///
+    pub fn take_published(&mut self) {
+        for (kind, consumer) in &mut self.consumers {
+            if let Some(data) = consumer.take_latest() {
+                if data.len() != kind.resource_size() {
+                    log::warn!(
+                        "discarding published {} of {} bytes, expected {} bytes",
+                        kind,
+                        data.len(),
+                        kind.resource_size()
+                    );
+                    continue;
+                }
+                self.by_kind
+                    .insert(*kind, SingleResource::read(*kind, data));
+            }
+        }
+    }
+}
+
+impl Renderer {
+    ///
+    /// Checks the layout of T against the block the shaders of every stage consuming
+    /// the resource kind declare for it.
+    ///
+    pub fn validate_resource_layout<T: KnownLayout>(
+        &self,
+        kind: ResourceKind,
+    ) -> Result<(), LayoutMismatch> {
+        let members = T::members();
+        for stage in self.stages_consuming(kind) {
+            stage.check_resource_layout(kind, std::mem::size_of::<T>() as u32, &members)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Warns once per kind and size if the shaders declare a different size for the
+    /// resource than what is being provided.
+    ///
+    pub fn check_shader_resource_size(&mut self, kind: ResourceKind, provided_size: usize) {
+        let mismatch = self.stages_consuming(kind).find_map(|stage| {
+            stage
+                .reflection
+                .block(&kind.to_string())
+                .filter(|e| e.size as usize != provided_size)
+                .map(|e| (stage.name.clone(), e.size))
+        });
+        if let Some((stage, expected_size)) = mismatch {
+            if self
+                .shader_resources
+                .reported_sizes
+                .insert((kind, provided_size))
+            {
+                log::warn!(
+                    "stage {} declares {} with {} bytes, but {} bytes were provided",
+                    stage,
+                    kind,
+                    expected_size,
+                    provided_size
+                );
+            }
+        }
+    }
+
+    ///
+    /// Per instance resources tasks of the kind need, each stage drawing them reads its
+    /// own.
+    ///
+    pub fn instance_resources_of(&self, kind: TaskKind) -> Vec<ResourceKind> {
+        let mut resources = Vec::new();
+        for stage in self.pipeline.stages.iter().filter(|e| e.task_kind == kind) {
+            for resource in &stage.per_instance_updaters {
+                if !resources.contains(resource) {
+                    resources.push(*resource);
+                }
+            }
+        }
+        resources
+    }
+
+    pub(super) fn stages_consuming(&self, kind: ResourceKind) -> impl Iterator<Item = &Stage> {
+        self.pipeline.stages.iter().filter(move |e| {
+            e.per_pass_updaters.contains(&kind) || e.per_instance_updaters.contains(&kind)
+        })
+    }
+
+    pub fn place_shader_resource(&mut self, kind: ResourceKind, item: SingleResource) {
+        self.shader_resources.by_kind.insert(kind, item);
+    }
+
+    ///
+    /// Hands out a publisher for the resource kind that can be moved to another thread.
+    /// Values published through it replace the ones placed with place_shader_resource
+    /// at the start of the next frame, only the most recent one is kept.
+    ///
+    pub fn resource_publisher(&mut self, kind: ResourceKind) -> ResourcePublisher {
+        self.shader_resources.publisher(kind)
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/*
+ * Work the renderer queues itself, texture uploads, chunked mesh uploads and queued
+ * readbacks, doesn't fail when a pool is momentarily out of room. It goes back into a
+ * retry queue and gets tried again on later frames, up to RendererConfig::retry_limit
+ * times. Each frame only takes RendererConfig::retried_work_per_frame of it back out,
+ * oldest first, ahead of the work queued fresh that frame.
+ */
+
+///
+/// When work that ran into a full pool is worth trying again.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RetryAfter {
+    // Frames free what the GPU is done with when they start
+    NextFrame,
+}
+
+///
+/// Pool that was out of room.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, strum_macros::Display)]
+#[serde(rename_all = "camelCase")]
+pub enum RetryReason {
+    // Staging out of the general buffer, or mesh staging past RendererConfig::mesh_staging_bytes
+    StagingFull,
+    // RendererConfig::upload_bytes_per_frame spent by earlier uploads of the frame
+    UploadBudget,
+    // Readback buffer taken by outstanding requests
+    ReadbackBusy,
+}
+
+///
+/// Work that couldn't go ahead because a pool ran out of room.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryError {
+    // Worth trying again, the renderer does so itself for the work it queues
+    Transient(RetryAfter),
+    // Given up on after this many attempts, the work was dropped
+    Exhausted { reason: RetryReason, attempts: u32 },
+}
+
+impl std::fmt::Display for RetryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transient(RetryAfter::NextFrame) => {
+                write!(f, "out of room for now, try again next frame")
+            }
+            Self::Exhausted { reason, attempts } => {
+                write!(f, "gave up after {} attempts, {}", attempts, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RetryError {}
+
+///
+/// Work retried by the renderer, in the events reporting it.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", content = "id", rename_all = "camelCase")]
+pub enum RetriedWork {
+    // Staging, region update, restore or transition of the texture id
+    Texture(u32),
+    // Chunks of an upload of the mesh id
+    Mesh(u32),
+    // Id of the QueuedReadback
+    Readback(u64),
+}
+
+///
+/// Depths of the queues of work the renderer owns, for hosts to throttle their loaders
+/// with. Retried work counts in its queue and in the retried count next to it.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingWorkSummary {
+    // Staged textures and region updates waiting for their copy
+    pub texture_uploads: u32,
+    pub retried_texture_uploads: u32,
+    // Staged and deferred chunks of mesh uploads
+    pub mesh_chunks: u32,
+    pub retried_mesh_chunks: u32,
+    // Copies not recorded yet and readbacks queued without room
+    pub readbacks: u32,
+    pub retried_readbacks: u32,
+}
+
+struct Retried<T> {
+    work: T,
+    reason: RetryReason,
+    // Failed ones so far
+    attempts: u32,
+}
+
+///
+/// Work waiting for another attempt, oldest first.
+///
+pub struct RetryQueue<T> {
+    waiting: VecDeque<Retried<T>>,
+}
+
+impl<T> Default for RetryQueue<T> {
+    fn default() -> Self {
+        Self {
+            waiting: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> RetryQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    ///
+    /// Puts the work back after its attempt failed, attempts failed ones so far counting
+    /// this one. Returns the attempt it gets next, or the error it failed with for good
+    /// once it failed more than limit times, dropping it.
+    ///
+    pub fn defer(
+        &mut self,
+        work: T,
+        reason: RetryReason,
+        attempts: u32,
+        limit: u32,
+    ) -> Result<u32, RetryError> {
+        if attempts > limit {
+            return Err(RetryError::Exhausted { reason, attempts });
+        }
+        self.waiting.push_back(Retried {
+            work,
+            reason,
+            attempts,
+        });
+        Ok(attempts + 1)
+    }
+
+    ///
+    /// Takes up to cap of the oldest ones out for another attempt, with the attempts
+    /// they failed so far.
+    ///
+    pub fn take(&mut self, cap: u32) -> Vec<(T, u32)> {
+        let count = self.waiting.len().min(cap as usize);
+        self.waiting
+            .drain(..count)
+            .map(|e| (e.work, e.attempts))
+            .collect()
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.waiting.retain(|e| f(&e.work));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&T, RetryReason)> {
+        self.waiting.iter().map(|e| (&e.work, e.reason))
+    }
+}
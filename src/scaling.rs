@@ -0,0 +1,571 @@
+use ash::vk;
+
+use crate::{context::VulkanContext, image_pool, pipeline::attachment::Attachment};
+
+/*
+ * Rendering at a resolution of its own instead of the one of the swapchain. Stages write
+ * to an internal target in place of the swapchain image, which gets scaled into the
+ * presented image after the last stage, keeping its aspect with bars around it. Resizing
+ * only rebuilds the swapchain, the internal target and every pipeline target sized
+ * relative to it stay as they are.
+ *
+ * Nearest and linear scaling blit the target. The sharpening filter gets drawn by a
+ * scaling stage appended after the last one instead, with the shaders in shader/builtin
+ * compiled by glslangValidator when the renderer gets made.
+ */
+
+///
+/// Filter scaling the internal target into the presented image, see
+/// Renderer::set_upscale_filter.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    // Blocky unless scaling by whole numbers, keeps pixel art sharp
+    Nearest,
+    #[default]
+    Linear,
+    // Bicubic with the contrast adaptive sharpening of FSR1 after it, see ScalingStage
+    #[cfg(feature = "builtin-passes")]
+    Sharpen,
+}
+
+impl UpscaleFilter {
+    ///
+    /// Filter of the blit, Sharpen doesn't blit but would fall back to linear.
+    ///
+    pub fn to_vk(self) -> vk::Filter {
+        match self {
+            Self::Nearest => vk::Filter::NEAREST,
+            Self::Linear => vk::Filter::LINEAR,
+            #[cfg(feature = "builtin-passes")]
+            Self::Sharpen => vk::Filter::LINEAR,
+        }
+    }
+}
+
+///
+/// Largest rect with the aspect of the source that fits centered in the target, the
+/// rest of the target are the bars.
+///
+pub fn letterbox(source: vk::Extent2D, target: vk::Extent2D) -> vk::Rect2D {
+    assert!(
+        source.width > 0 && source.height > 0,
+        "internal resolution can't be empty!"
+    );
+    let (sw, sh) = (source.width as u64, source.height as u64);
+    let (tw, th) = (target.width as u64, target.height as u64);
+    // Rounded to the nearest pixel, whichever side fills the target exactly
+    let (width, height) = if tw * sh <= th * sw {
+        (tw, ((sh * tw + sw / 2) / sw).clamp(1, th))
+    } else {
+        (((sw * th + sh / 2) / sh).clamp(1, tw), th)
+    };
+    vk::Rect2D {
+        offset: vk::Offset2D {
+            x: ((tw - width) / 2) as i32,
+            y: ((th - height) / 2) as i32,
+        },
+        extent: vk::Extent2D {
+            width: width as u32,
+            height: height as u32,
+        },
+    }
+}
+
+///
+/// Internal target in the format of the swapchain, with a UNORM view too when the
+/// swapchain images have one. Named like the default attachment, since stages render to
+/// it in its place.
+///
+pub fn make_target(
+    ctx: &VulkanContext,
+    vk_format: vk::Format,
+    unorm_format: Option<vk::Format>,
+    extent: vk::Extent2D,
+) -> Attachment {
+    let features = unsafe {
+        ctx.instance
+            .get_physical_device_format_properties(ctx.physical_device, vk_format)
+            .optimal_tiling_features
+    };
+    if !features.contains(vk::FormatFeatureFlags::BLIT_SRC) {
+        panic!("can't scale from {:?} images!", vk_format);
+    }
+    let view_formats: Vec<_> = std::iter::once(vk_format).chain(unorm_format).collect();
+    let mut format_list_info = vk::ImageFormatListCreateInfo::builder()
+        .view_formats(&view_formats)
+        .build();
+    let mut create_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(vk_format)
+        .extent(extent.into())
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                // Read by the scaling stage
+                | vk::ImageUsageFlags::SAMPLED,
+        );
+    if unorm_format.is_some() {
+        create_info = create_info
+            .flags(vk::ImageCreateFlags::MUTABLE_FORMAT)
+            .push_next(&mut format_list_info);
+    }
+    let image = unsafe { ctx.device.create_image(&create_info, None) }.unwrap();
+    let allocation =
+        image_pool::alloc_dedicated_for(ctx, image, &[vk::MemoryPropertyFlags::DEVICE_LOCAL]);
+    let make_view = |format: vk::Format| {
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(Attachment::color_subresource_range())
+            .image(image);
+        unsafe { ctx.device.create_image_view(&view_info, None) }.unwrap()
+    };
+    let view = make_view(vk_format);
+    let unorm_view = unorm_format.map(|e| (e, make_view(e)));
+    ctx.try_set_debug_name("internal_image", image);
+    ctx.try_set_debug_name("internal_memory", allocation.memory);
+    ctx.try_set_debug_name("internal_view", view);
+    Attachment {
+        memory: allocation.memory,
+        memory_flags: allocation.memory_flags,
+        usage: create_info.usage,
+        ..Attachment::default_attachment_of(vk_format, image, view, unorm_view, extent)
+    }
+}
+
+pub fn destroy_target(device: &ash::Device, target: &Attachment) {
+    unsafe {
+        device.destroy_image_view(target.view, None);
+        if let Some((_, view)) = target.unorm_view {
+            device.destroy_image_view(view, None);
+        }
+        device.destroy_image(target.image, None);
+        device.free_memory(target.memory, None);
+    }
+}
+
+///
+/// Scales the internal target the stages wrote into the acquired swapchain image and
+/// leaves it ready for presenting. Bars get cleared to the color when the aspects
+/// differ.
+///
+pub fn record(
+    ctx: &VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    source: &Attachment,
+    target: &Attachment,
+    filter: UpscaleFilter,
+    bar_color: [f32; 4],
+) {
+    let barrier =
+        |image: vk::Image,
+         from: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2),
+         to: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2)| {
+            vk::ImageMemoryBarrier2::builder()
+                .image(image)
+                .subresource_range(Attachment::color_subresource_range())
+                .old_layout(from.0)
+                .src_access_mask(from.1)
+                .src_stage_mask(from.2)
+                .new_layout(to.0)
+                .dst_access_mask(to.1)
+                .dst_stage_mask(to.2)
+                .build()
+        };
+    use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+    let rendered = (
+        Il::ATTACHMENT_OPTIMAL,
+        Af::COLOR_ATTACHMENT_WRITE,
+        Ps::COLOR_ATTACHMENT_OUTPUT,
+    );
+    let copy_src = (Il::TRANSFER_SRC_OPTIMAL, Af::TRANSFER_READ, Ps::BLIT);
+    let cleared = (Il::TRANSFER_DST_OPTIMAL, Af::TRANSFER_WRITE, Ps::CLEAR);
+    let copy_dst = (Il::TRANSFER_DST_OPTIMAL, Af::TRANSFER_WRITE, Ps::BLIT);
+    let rect = letterbox(source.extent, target.extent);
+    let is_barred = rect.extent != target.extent;
+    let before = [
+        barrier(source.image, rendered, copy_src),
+        // Chains with the wait on the acquired image
+        target.adapt_barrier(barrier(
+            target.image,
+            (Il::UNDEFINED, Af::NONE, Ps::COLOR_ATTACHMENT_OUTPUT),
+            if is_barred { cleared } else { copy_dst },
+        )),
+    ];
+    let after = [
+        target.adapt_barrier(barrier(
+            target.image,
+            copy_dst,
+            (Il::PRESENT_SRC_KHR, Af::NONE, Ps::BOTTOM_OF_PIPE),
+        )),
+        // Stages of the next frame wait for the blit to be done reading it
+        barrier(source.image, copy_src, rendered),
+    ];
+    let corners = |rect: vk::Rect2D| {
+        [
+            vk::Offset3D {
+                x: rect.offset.x,
+                y: rect.offset.y,
+                z: 0,
+            },
+            vk::Offset3D {
+                x: rect.offset.x + rect.extent.width as i32,
+                y: rect.offset.y + rect.extent.height as i32,
+                z: 1,
+            },
+        ]
+    };
+    let layers = vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let blit = vk::ImageBlit {
+        src_subresource: layers,
+        src_offsets: corners(source.render_area_no_offset()),
+        dst_subresource: layers,
+        dst_offsets: corners(rect),
+    };
+    let device = &ctx.device;
+    unsafe {
+        device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::builder().image_memory_barriers(&before),
+        );
+        if is_barred {
+            device.cmd_clear_color_image(
+                command_buffer,
+                target.image,
+                target.layout_for(Il::TRANSFER_DST_OPTIMAL),
+                &vk::ClearColorValue { float32: bar_color },
+                &[Attachment::color_subresource_range()],
+            );
+            let blitted = [target.adapt_barrier(barrier(target.image, cleared, copy_dst))];
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&blitted),
+            );
+        }
+        device.cmd_blit_image(
+            command_buffer,
+            source.image,
+            Il::TRANSFER_SRC_OPTIMAL,
+            target.image,
+            target.layout_for(Il::TRANSFER_DST_OPTIMAL),
+            &[blit],
+            filter.to_vk(),
+        );
+        device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::builder().image_memory_barriers(&after),
+        );
+    }
+}
+
+// GLSL of the scaling stage, compiled next to it when the stage gets made
+#[cfg(feature = "builtin-passes")]
+const SHADER_DIR: &str = "shader/builtin";
+#[cfg(feature = "builtin-passes")]
+const SHADERS: [(vk::ShaderStageFlags, &str); 2] = [
+    (vk::ShaderStageFlags::VERTEX, "upscale.vert"),
+    (vk::ShaderStageFlags::FRAGMENT, "upscale_sharpen.frag"),
+];
+
+///
+/// SPIR-V of the scaling stage shaders, compiled from the GLSL in shader/builtin. Panics
+/// if glslangValidator fails on them.
+///
+#[cfg(feature = "builtin-passes")]
+pub fn compile_shaders() -> [(&'static str, Vec<u8>); 2] {
+    SHADERS.map(|(_, file)| {
+        let src = format!("{SHADER_DIR}/{file}");
+        let out = format!("{src}.spv");
+        crate::shader::compile_glsl(file, &[&src, "-V", "-o", &out]);
+        let spirv = std::fs::read(&out).unwrap_or_else(|e| panic!("failed reading {}: {}", out, e));
+        (file, spirv)
+    })
+}
+
+///
+/// Stage appended after the last one of the pipeline for the filters that don't blit.
+/// Draws the internal target into the letterboxed rect of the presented image, clearing
+/// the bars. Made with the internal target, it samples the same one for as long as the
+/// renderer lives.
+///
+#[cfg(feature = "builtin-passes")]
+#[derive(Clone)]
+pub struct ScalingStage {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    sampler: vk::Sampler,
+}
+
+#[cfg(feature = "builtin-passes")]
+impl ScalingStage {
+    ///
+    /// Stage sampling the source and drawing into images of the target format, the one
+    /// of the swapchain.
+    ///
+    pub fn new(ctx: &VulkanContext, source: &Attachment, target_format: vk::Format) -> Self {
+        let device = &ctx.device;
+        // Neighbors of the sharpening get read between texels
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None) }.unwrap();
+        let samplers = [sampler];
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .immutable_samplers(&samplers)
+            .build()];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let set_layout =
+            unsafe { device.create_descriptor_set_layout(&set_layout_info, None) }.unwrap();
+        let set_layouts = [set_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None) }.unwrap();
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { device.create_descriptor_pool(&pool_info, None) }.unwrap();
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let set = unsafe { device.allocate_descriptor_sets(&alloc_info) }
+            .expect("couldn't allocate the scaling stage descriptor set!")[0];
+        let read = [vk::DescriptorImageInfo {
+            image_view: source.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ..Default::default()
+        }];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&read)
+            .build();
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        let pipeline = Self::graphics_pipeline(ctx, layout, target_format);
+        ctx.try_set_debug_name("scaling_stage_pipeline", pipeline);
+        Self {
+            pipeline,
+            layout,
+            set_layout,
+            pool,
+            set,
+            sampler,
+        }
+    }
+
+    fn graphics_pipeline(
+        ctx: &VulkanContext,
+        layout: vk::PipelineLayout,
+        target_format: vk::Format,
+    ) -> vk::Pipeline {
+        let device = &ctx.device;
+        let modules = compile_shaders().map(|(name, spirv)| {
+            // Copies into words, the bytes carry no alignment
+            let code = ash::util::read_spv(&mut std::io::Cursor::new(spirv))
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", name, e));
+            let module_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+            unsafe { device.create_shader_module(&module_info, None) }
+                .unwrap_or_else(|e| panic!("{} shader module error: {}", name, e))
+        });
+        let stages: Vec<_> = SHADERS
+            .iter()
+            .zip(modules)
+            .map(|((stage, _), module)| {
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(*stage)
+                    .module(module)
+                    .name(c"main")
+                    .build()
+            })
+            .collect();
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        // Both dynamic, the letterbox changes with the swapchain
+        let viewport = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .build()];
+        let blend =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&blend_attachments);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let color_formats = [target_format];
+        let mut rendering =
+            vk::PipelineRenderingCreateInfo::builder().color_attachment_formats(&color_formats);
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .color_blend_state(&blend)
+            .dynamic_state(&dynamic)
+            .layout(layout)
+            .push_next(&mut rendering)
+            .build();
+        let pipeline = unsafe {
+            device.create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+        }
+        .map_err(|e| e.1)
+        .unwrap_or_else(|e| panic!("failed creating the scaling stage pipeline: {}", e))[0];
+        for module in modules {
+            unsafe { device.destroy_shader_module(module, None) };
+        }
+        pipeline
+    }
+
+    ///
+    /// Same as the record function of this file, drawing with the sharpening filter
+    /// instead of blitting.
+    ///
+    pub fn record(
+        &self,
+        ctx: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+        source: &Attachment,
+        target: &Attachment,
+        bar_color: [f32; 4],
+    ) {
+        let barrier =
+            |image: vk::Image,
+             from: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2),
+             to: (vk::ImageLayout, vk::AccessFlags2, vk::PipelineStageFlags2)| {
+                vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(Attachment::color_subresource_range())
+                    .old_layout(from.0)
+                    .src_access_mask(from.1)
+                    .src_stage_mask(from.2)
+                    .new_layout(to.0)
+                    .dst_access_mask(to.1)
+                    .dst_stage_mask(to.2)
+                    .build()
+            };
+        use vk::{AccessFlags2 as Af, ImageLayout as Il, PipelineStageFlags2 as Ps};
+        let rendered = (
+            Il::ATTACHMENT_OPTIMAL,
+            Af::COLOR_ATTACHMENT_WRITE,
+            Ps::COLOR_ATTACHMENT_OUTPUT,
+        );
+        let sampled = (
+            Il::SHADER_READ_ONLY_OPTIMAL,
+            Af::SHADER_SAMPLED_READ,
+            Ps::FRAGMENT_SHADER,
+        );
+        let before = [
+            barrier(source.image, rendered, sampled),
+            // Chains with the wait on the acquired image
+            target.adapt_barrier(barrier(
+                target.image,
+                (Il::UNDEFINED, Af::NONE, Ps::COLOR_ATTACHMENT_OUTPUT),
+                rendered,
+            )),
+        ];
+        let after = [
+            target.adapt_barrier(barrier(
+                target.image,
+                rendered,
+                (Il::PRESENT_SRC_KHR, Af::NONE, Ps::BOTTOM_OF_PIPE),
+            )),
+            // Stages of the next frame wait for the stage to be done sampling it
+            barrier(source.image, sampled, rendered),
+        ];
+        let rect = letterbox(source.extent, target.extent);
+        let color_attachments = [vk::RenderingAttachmentInfo::builder()
+            .image_view(target.view)
+            .image_layout(target.layout_for(Il::ATTACHMENT_OPTIMAL))
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue { float32: bar_color },
+            })
+            .build()];
+        let rendering_info = vk::RenderingInfo::builder()
+            .render_area(target.render_area_no_offset())
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+        let viewport = vk::Viewport {
+            x: rect.offset.x as f32,
+            y: rect.offset.y as f32,
+            width: rect.extent.width as f32,
+            height: rect.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let device = &ctx.device;
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&before),
+            );
+            device.cmd_begin_rendering(command_buffer, &rendering_info);
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.set],
+                &[],
+            );
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[rect]);
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            device.cmd_end_rendering(command_buffer);
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&after),
+            );
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.pool, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
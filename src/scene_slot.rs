@@ -0,0 +1,72 @@
+/*
+ * Per instance data that stays on the GPU across frames, for tasks of objects that rarely
+ * change to reference instead of carrying it inline and getting it copied every frame.
+ * Slots are never written while a frame may read them, updates fill a new buffer and the
+ * old one gets released with the frame buffers once the frames prepared with it are done.
+ */
+use crate::{
+    buffer::{DeviceAllocator, DeviceSlice},
+    handle::SceneSlotId,
+};
+
+#[derive(Clone, Copy)]
+pub struct SceneSlot {
+    pub id: SceneSlotId,
+    pub buffer: DeviceSlice,
+    // Bytes it was made with, the buffer may be padded past them
+    pub size: u64,
+}
+
+impl SceneSlot {
+    ///
+    /// Allocates a buffer for the bytes and copies them in, None if the allocator has no
+    /// room left for them.
+    ///
+    pub fn of(id: SceneSlotId, mem: &DeviceAllocator, bytes: &[u8]) -> Option<Self> {
+        let buffer = mem.alloc(bytes.len() as u64)?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.addr as *mut u8, bytes.len())
+        };
+        Some(Self {
+            id,
+            buffer,
+            size: bytes.len() as u64,
+        })
+    }
+}
+
+///
+/// Returned by Renderer::update_scene_slot, the slot keeps its data.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneSlotRejected {
+    // Slot was freed since the id was handed out
+    Stale {
+        slot: SceneSlotId,
+    },
+    // Slots keep the size they were made with
+    SizeMismatch {
+        slot: SceneSlotId,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl std::fmt::Display for SceneSlotRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneSlotRejected::Stale { slot } => write!(f, "scene slot {} was freed", slot),
+            SceneSlotRejected::SizeMismatch {
+                slot,
+                expected,
+                found,
+            } => write!(
+                f,
+                "scene slot {} holds {} bytes, got {}",
+                slot, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneSlotRejected {}
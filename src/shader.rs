@@ -2,6 +2,9 @@ use std::ffi::CStr;
 
 use ash::{util::read_spv, vk, Device};
 
+use crate::reflection::ShaderReflection;
+use crate::spirv_patch::{BindingRemap, ShaderPatch, SpirvModule};
+
 pub const ATTRIB_LOC_POSITION: u32 = 0;
 pub const ATTRIB_LOC_NORMAL: u32 = 1;
 pub const ATTRIB_LOC_COLOR: u32 = 2;
@@ -16,6 +19,7 @@ pub struct ShaderProgram {
 pub struct Shader {
     pub name: String,
     pub info: vk::PipelineShaderStageCreateInfo,
+    pub reflection: ShaderReflection,
 }
 impl Shader {
     pub fn type_id(&self) -> vk::ShaderStageFlags {
@@ -23,17 +27,27 @@ impl Shader {
     }
 }
 impl ShaderProgram {
+    pub fn reflection(&self) -> ShaderReflection {
+        let mut reflection = ShaderReflection::default();
+        for shader in &self.shaders {
+            reflection.merge(&shader.reflection);
+        }
+        reflection
+    }
     pub fn destroy(&self, device: &Device) {
         self.shaders
             .iter()
             .for_each(|e| unsafe { device.destroy_shader_module(e.info.module, None) });
     }
+    ///
+    /// Shaders come with the patch declared for them in the pipeline, if any.
+    ///
     pub fn new<R: std::io::Read + std::io::Seek>(
         device: &Device,
         name: String,
-        vertex: Option<(String, R)>,
-        fragment: Option<(String, R)>,
-        geometry: Option<(String, R)>,
+        vertex: Option<(String, R, Option<&ShaderPatch>)>,
+        fragment: Option<(String, R, Option<&ShaderPatch>)>,
+        geometry: Option<(String, R, Option<&ShaderPatch>)>,
     ) -> Self {
         let shader_entry_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
         let stage_infos: Vec<Shader> = vec![vertex, fragment, geometry]
@@ -49,6 +63,10 @@ impl ShaderProgram {
                     };
                     let bin = read_spv(&mut name_cursor.1)
                         .expect(&format!("failed to load shader, type: {}", i));
+                    let (reflection, bin) = match name_cursor.2 {
+                        Some(patch) => patched(&name_cursor.0, bin, patch),
+                        None => (ShaderReflection::of(&bin), bin),
+                    };
                     let info = vk::ShaderModuleCreateInfo::builder().code(&bin);
                     let module = unsafe { device.create_shader_module(&info, None) }
                         .expect(&format!("shader module error, type: {}", i));
@@ -60,6 +78,7 @@ impl ShaderProgram {
                             stage: sh_type,
                             ..Default::default()
                         },
+                        reflection,
                     })
                 }
                 None => None,
@@ -74,3 +93,58 @@ impl ShaderProgram {
         }
     }
 }
+
+///
+/// Runs glslangValidator with the arguments, the way every shader gets compiled to
+/// SPIR-V before loading. Panics if it can't be started or fails.
+///
+pub fn compile_glsl(name: &str, args: &[&str]) {
+    log::info!("compiling shader {} with args {:?}...", name, args);
+    let res = std::process::Command::new("glslangValidator")
+        .args(args)
+        .spawn()
+        .unwrap_or_else(|_| panic!("Failed to start {}", name))
+        // TODO: Could launch all of these these concurrently and wait for them all.
+        .wait();
+    match res {
+        Err(e) => {
+            panic!("Error compiling shader {}, error {}", name, e)
+        }
+        Ok(e) if !e.success() => {
+            panic!("Failed compiling shader {}, error {}", name, e);
+        }
+        _ => {}
+    }
+    log::info!("shader {} compiled!", name);
+}
+
+/*
+ * Remaps the bindings of the shader and checks its block layouts, reflecting it before
+ * its debug info gets stripped so the names still get checked. Panics on shaders that
+ * can't be patched or remap tables that can't be applied.
+ */
+fn patched(name: &str, code: Vec<u32>, patch: &ShaderPatch) -> (ShaderReflection, Vec<u32>) {
+    let mut module =
+        SpirvModule::parse(code).unwrap_or_else(|e| panic!("can't patch shader {}, {}", name, e));
+    let remap = BindingRemap::parse(&patch.binding_remap)
+        .unwrap_or_else(|e| panic!("shader {}, {}", name, e));
+    if !remap.is_empty() {
+        let remapped = module.remap_bindings(&remap);
+        log::info!("remapped {} bindings of shader {}", remapped, name);
+    }
+    if let Some(rule) = patch.block_layout {
+        for mismatch in module.check_block_layouts(rule) {
+            log::warn!("shader {}, {}", name, mismatch);
+        }
+    }
+    let reflection = ShaderReflection::of(module.words());
+    if patch.strip_debug_info {
+        let stripped = module.strip_debug_info();
+        log::debug!(
+            "stripped {} debug instructions of shader {}",
+            stripped,
+            name
+        );
+    }
+    (reflection, module.into_words())
+}
@@ -0,0 +1,228 @@
+/*
+ * Host structs shared with the shaders, declared once in Rust with shader_block!. The
+ * macro checks at compile time that the struct lays out like the GLSL struct would in
+ * the scalar block layout, and generates that GLSL struct for the shaders to include:
+ *
+ *   shader_block! {
+ *       pub struct FrameConstants {
+ *           pub view_proj: Mat4,
+ *           pub resolution: Vec2,
+ *           pub time: f32,
+ *       }
+ *   }
+ *
+ * Fields keep their order, names turn camelCase on the GLSL side. Members the scalar
+ * layout would place elsewhere than repr(C) does, like a Vec4 after a single f32, fail
+ * to compile, add explicit padding members in that case.
+ */
+use glam::{IVec2, IVec3, IVec4, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+
+use crate::shader_resource::KnownLayout;
+
+///
+/// Type with a GLSL counterpart of the same size in the scalar block layout.
+///
+pub trait GlslType {
+    // Alignment in the scalar layout, that of the scalar components
+    const ALIGN: usize;
+
+    fn glsl_type() -> String;
+
+    fn glsl_array_suffix() -> String {
+        String::new()
+    }
+}
+
+macro_rules! glsl_type {
+    ($t:ty, $glsl:literal, $align:literal) => {
+        impl GlslType for $t {
+            const ALIGN: usize = $align;
+
+            fn glsl_type() -> String {
+                $glsl.to_string()
+            }
+        }
+    };
+}
+
+glsl_type!(f32, "float", 4);
+glsl_type!(u32, "uint", 4);
+glsl_type!(i32, "int", 4);
+glsl_type!(u64, "uint64_t", 8);
+glsl_type!(Vec2, "vec2", 4);
+glsl_type!(Vec3, "vec3", 4);
+glsl_type!(Vec4, "vec4", 4);
+glsl_type!(UVec2, "uvec2", 4);
+glsl_type!(UVec3, "uvec3", 4);
+glsl_type!(UVec4, "uvec4", 4);
+glsl_type!(IVec2, "ivec2", 4);
+glsl_type!(IVec3, "ivec3", 4);
+glsl_type!(IVec4, "ivec4", 4);
+glsl_type!(Mat3, "mat3", 4);
+glsl_type!(Mat4, "mat4", 4);
+
+impl<T: GlslType, const N: usize> GlslType for [T; N] {
+    const ALIGN: usize = T::ALIGN;
+
+    fn glsl_type() -> String {
+        T::glsl_type()
+    }
+
+    fn glsl_array_suffix() -> String {
+        format!("[{}]{}", N, T::glsl_array_suffix())
+    }
+}
+
+///
+/// Struct declared with shader_block!, see the top of this file.
+///
+pub trait ShaderBlock: KnownLayout + Copy + 'static {
+    const NAME: &'static str;
+
+    ///
+    /// GLSL type, name and array suffix of each member in declaration order.
+    ///
+    fn glsl_members() -> Vec<(String, String, String)>;
+
+    ///
+    /// GLSL declaration of the struct, meant to be written to a file the shaders
+    /// include.
+    ///
+    fn glsl_source() -> String {
+        let mut src = format!(
+            "// Generated from the Rust struct {}, don't edit\nstruct {}\n{{\n",
+            Self::NAME,
+            Self::NAME
+        );
+        for (ty, name, suffix) in Self::glsl_members() {
+            src += &format!("  {} {}{};\n", ty, name, suffix);
+        }
+        src += "};\n";
+        src
+    }
+}
+
+pub fn camel_case(name: &str) -> String {
+    let mut dst = String::with_capacity(name.len());
+    let mut is_upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            is_upper = !dst.is_empty();
+        } else if is_upper {
+            dst.extend(c.to_uppercase());
+            is_upper = false;
+        } else {
+            dst.push(c);
+        }
+    }
+    dst
+}
+
+pub const fn align_up(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+///
+/// Declares a struct shared with the shaders, see the top of this file. A member the
+/// scalar layout would place elsewhere fails to compile:
+///
+/// ```compile_fail
+/// rend_vk::shader_block! {
+///     pub struct Misplaced {
+///         pub time: f32,
+///         pub color: glam::Vec4,
+///     }
+/// }
+/// ```
+///
+/// So does trailing padding:
+///
+/// ```compile_fail
+/// rend_vk::shader_block! {
+///     pub struct Unpadded {
+///         pub view_proj: glam::Mat4,
+///         pub time: f32,
+///     }
+/// }
+/// ```
+///
+/// Both compile with explicit padding:
+///
+/// ```
+/// rend_vk::shader_block! {
+///     pub struct Padded {
+///         pub time: f32,
+///         pub padding: [u32; 3],
+///         pub color: glam::Vec4,
+///         pub view_proj: glam::Mat4,
+///     }
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! shader_block {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty),*
+        }
+
+        const _: () = {
+            let mut offset = 0usize;
+            $(
+                offset = $crate::shader_block::align_up(
+                    offset,
+                    <$ty as $crate::shader_block::GlslType>::ALIGN,
+                );
+                assert!(
+                    std::mem::offset_of!($name, $field) == offset,
+                    concat!(
+                        "scalar layout places ",
+                        stringify!($name),
+                        "::",
+                        stringify!($field),
+                        " elsewhere, add explicit padding before it"
+                    )
+                );
+                offset += std::mem::size_of::<$ty>();
+            )*
+            assert!(
+                std::mem::size_of::<$name>() == offset,
+                concat!(
+                    stringify!($name),
+                    " has trailing padding, add explicit padding at the end"
+                )
+            );
+        };
+
+        impl $crate::shader_resource::KnownLayout for $name {
+            fn members() -> Vec<$crate::reflection::HostMember> {
+                vec![$($crate::reflection::HostMember {
+                    name: stringify!($field),
+                    offset: std::mem::offset_of!($name, $field) as u32,
+                    size: std::mem::size_of::<$ty>() as u32,
+                }),*]
+            }
+        }
+
+        impl $crate::shader_block::ShaderBlock for $name {
+            const NAME: &'static str = stringify!($name);
+
+            fn glsl_members() -> Vec<(String, String, String)> {
+                use $crate::shader_block::GlslType;
+                vec![$((
+                    <$ty>::glsl_type(),
+                    $crate::shader_block::camel_case(stringify!($field)),
+                    <$ty>::glsl_array_suffix(),
+                )),*]
+            }
+        }
+    };
+}
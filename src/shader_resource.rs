@@ -4,11 +4,11 @@ use std::{
     mem::{align_of, size_of},
 };
 
-use glam::{Mat4, Vec3, Vec4};
+use glam::{Mat4, UVec3, Vec3, Vec4};
 
-use crate::UsedAsIndex;
+use crate::{handle::SceneSlotId, reflection::HostMember, UsedAsIndex};
 
-#[derive(PartialEq, Eq, Clone, Copy, strum_macros::Display, Hash)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, strum_macros::Display, Hash)]
 #[repr(u8)]
 pub enum ResourceKind {
     Transform = 0,
@@ -22,6 +22,9 @@ pub enum ResourceKind {
     Sky = 8,
     StaticShadow = 9,
     TransformExtra = 10,
+    LightClusters = 11,
+    ViewMatrices = 12,
+    FrameConstants = 13,
 }
 
 impl ResourceKind {
@@ -78,6 +81,28 @@ impl ResourceKind {
             ResourceKind::Sky => align_of::<Sky>(),
             ResourceKind::StaticShadow => align_of::<StaticShadow>(),
             ResourceKind::TransformExtra => align_of::<TransformExtra>(),
+            ResourceKind::LightClusters => align_of::<LightClusters>(),
+            ResourceKind::ViewMatrices => align_of::<ViewMatrices>(),
+            ResourceKind::FrameConstants => align_of::<FrameConstants>(),
+        }
+    }
+
+    pub fn host_layout(self) -> Vec<HostMember> {
+        match self {
+            ResourceKind::Transform => Transform::members(),
+            ResourceKind::Material => Material::members(),
+            ResourceKind::DirLight => DirLight::members(),
+            ResourceKind::Frustum => Frustum::members(),
+            ResourceKind::ViewRay => ViewRay::members(),
+            ResourceKind::PointLight => PointLight::members(),
+            ResourceKind::SpotLight => SpotLight::members(),
+            ResourceKind::Joint => Joint::members(),
+            ResourceKind::Sky => Sky::members(),
+            ResourceKind::StaticShadow => StaticShadow::members(),
+            ResourceKind::TransformExtra => TransformExtra::members(),
+            ResourceKind::LightClusters => LightClusters::members(),
+            ResourceKind::ViewMatrices => ViewMatrices::members(),
+            ResourceKind::FrameConstants => FrameConstants::members(),
         }
     }
 
@@ -94,11 +119,14 @@ impl ResourceKind {
             ResourceKind::Sky => size_of::<Sky>(),
             ResourceKind::StaticShadow => size_of::<StaticShadow>(),
             ResourceKind::TransformExtra => size_of::<TransformExtra>(),
+            ResourceKind::LightClusters => size_of::<LightClusters>(),
+            ResourceKind::ViewMatrices => size_of::<ViewMatrices>(),
+            ResourceKind::FrameConstants => size_of::<FrameConstants>(),
         }
     }
 }
 
-const MAX_RESOURCE_KIND: u8 = ResourceKind::TransformExtra.to_u8();
+const MAX_RESOURCE_KIND: u8 = ResourceKind::FrameConstants.to_u8();
 impl UsedAsIndex<MAX_RESOURCE_KIND> for ResourceKind {}
 
 #[derive(Clone)]
@@ -125,6 +153,22 @@ pub struct Material {
     pub glow_sampler: u8,
     pub padding: u8,
 }
+impl Material {
+    ///
+    /// Swaps the sampler of each texture that has an override, keyed by texture id.
+    ///
+    pub fn override_samplers(&mut self, overrides: &HashMap<u32, u8>) {
+        for (handle, sampler) in [
+            (self.diffuse_handle, &mut self.diffuse_sampler),
+            (self.normal_handle, &mut self.normal_sampler),
+            (self.glow_handle, &mut self.glow_sampler),
+        ] {
+            if let Some(v) = overrides.get(&handle) {
+                *sampler = *v;
+            }
+        }
+    }
+}
 #[derive(Clone)]
 #[repr(C)]
 pub struct DirLight {
@@ -182,6 +226,130 @@ pub struct StaticShadow {}
 #[derive(Clone)]
 #[repr(C)]
 pub struct Sky {}
+///
+/// Cluster grid built by light_cluster::ClusterBuilder, the addresses point to the
+/// arrays uploaded with Renderer::upload_clusters. Slice of a view depth d is
+/// d * z_scale - z_bias, with ln(d) instead of d when is_exponential is set.
+///
+#[derive(Clone)]
+#[repr(C)]
+pub struct LightClusters {
+    pub ranges: u64,
+    pub light_indices: u64,
+    pub dims: UVec3,
+    pub is_exponential: u32,
+    pub z_scale: f32,
+    pub z_bias: f32,
+}
+///
+/// Matrix of each view of a multiview stage, indexed by gl_ViewIndex. Applied on top of
+/// the instance transforms, which carry just the model matrix in that case.
+///
+#[derive(Clone)]
+#[repr(C)]
+pub struct ViewMatrices {
+    pub items: [Mat4; ViewMatrices::MAX_VIEWS as usize],
+}
+impl ViewMatrices {
+    // Enough for 4 shadow cascades or the 6 faces of a cube
+    pub const MAX_VIEWS: u32 = 6;
+}
+///
+/// Address of the block set with Renderer::set_frame_constants, declared by the
+/// shaders with shader_block!. Keep it 8 byte aligned within the per pass data.
+///
+#[derive(Clone)]
+#[repr(C)]
+pub struct FrameConstants {
+    pub addr: u64,
+}
+
+///
+/// Host side layout of a resource struct, used to check it against the layout
+/// the shaders declare for it.
+///
+pub trait KnownLayout {
+    fn members() -> Vec<HostMember>;
+}
+
+fn size_of_field<T, F>(_: fn(&T) -> &F) -> u32 {
+    size_of::<F>() as u32
+}
+
+macro_rules! known_layout {
+    ($t:ident { $($field:ident),* }) => {
+        impl KnownLayout for $t {
+            fn members() -> Vec<HostMember> {
+                vec![$(HostMember {
+                    name: stringify!($field),
+                    offset: std::mem::offset_of!($t, $field) as u32,
+                    size: size_of_field(|e: &$t| &e.$field),
+                }),*]
+            }
+        }
+    };
+}
+
+known_layout!(Transform { mvp, mv });
+known_layout!(TransformExtra { prev_mvp });
+known_layout!(Material {
+    shininess,
+    scaling,
+    diffuse_handle,
+    normal_handle,
+    glow_handle,
+    diffuse_sampler,
+    normal_sampler,
+    glow_sampler,
+    padding
+});
+known_layout!(DirLight {
+    view_dir,
+    color,
+    sky_color,
+    ground_color,
+    inv_view_shadow_proj
+});
+known_layout!(PointLight { color, radius });
+known_layout!(SpotLight {
+    cos_cutoff_rad,
+    sin_cutoff_rad,
+    range,
+    inv_range,
+    intensity,
+    color
+});
+known_layout!(Frustum {
+    width,
+    height,
+    inv_width,
+    inv_height,
+    near_plane,
+    far_plane
+});
+known_layout!(ViewRay {
+    bleft,
+    m22,
+    bright,
+    m23,
+    tright,
+    m32,
+    tleft,
+    m33
+});
+known_layout!(Joint {});
+known_layout!(StaticShadow {});
+known_layout!(Sky {});
+known_layout!(ViewMatrices { items });
+known_layout!(FrameConstants { addr });
+known_layout!(LightClusters {
+    ranges,
+    light_indices,
+    dims,
+    is_exponential,
+    z_scale,
+    z_bias
+});
 
 pub enum MultiResource {
     Transform(Vec<Transform>),
@@ -195,8 +363,14 @@ pub enum MultiResource {
     Sky(Vec<Sky>),
     StaticShadow(Vec<StaticShadow>),
     TransformExtra(Vec<TransformExtra>),
+    LightClusters(Vec<LightClusters>),
+    ViewMatrices(Vec<ViewMatrices>),
+    FrameConstants(Vec<FrameConstants>),
+    // Data of every instance already on the GPU, see Renderer::create_scene_slot
+    SceneSlot(SceneSlotId),
 }
 
+#[derive(Clone)]
 pub enum SingleResource {
     Transform(Transform),
     Material(Material),
@@ -209,6 +383,53 @@ pub enum SingleResource {
     Sky(Sky),
     StaticShadow(StaticShadow),
     TransformExtra(TransformExtra),
+    LightClusters(LightClusters),
+    ViewMatrices(ViewMatrices),
+    FrameConstants(FrameConstants),
+}
+
+impl SingleResource {
+    ///
+    /// Reads a resource of the given kind from raw bytes, no alignment required.
+    ///
+    pub fn read(kind: ResourceKind, data: &[u8]) -> Self {
+        match kind {
+            ResourceKind::Transform => read_single::<Transform>(data),
+            ResourceKind::Material => read_single::<Material>(data),
+            ResourceKind::DirLight => read_single::<DirLight>(data),
+            ResourceKind::Frustum => read_single::<Frustum>(data),
+            ResourceKind::ViewRay => read_single::<ViewRay>(data),
+            ResourceKind::PointLight => read_single::<PointLight>(data),
+            ResourceKind::SpotLight => read_single::<SpotLight>(data),
+            ResourceKind::Joint => read_single::<Joint>(data),
+            ResourceKind::Sky => read_single::<Sky>(data),
+            ResourceKind::StaticShadow => read_single::<StaticShadow>(data),
+            ResourceKind::TransformExtra => read_single::<TransformExtra>(data),
+            ResourceKind::LightClusters => read_single::<LightClusters>(data),
+            ResourceKind::ViewMatrices => read_single::<ViewMatrices>(data),
+            ResourceKind::FrameConstants => read_single::<FrameConstants>(data),
+        }
+    }
+}
+
+fn read_single<T: WrapResource<T>>(data: &[u8]) -> SingleResource {
+    assert!(
+        data.len() >= size_of::<T>(),
+        "unexpected resource {} size! expected {}, got only {}",
+        std::any::type_name::<T>(),
+        size_of::<T>(),
+        data.len()
+    );
+    let item = unsafe { data.as_ptr().cast::<T>().read_unaligned() };
+    T::single_wrapper_for(std::slice::from_ref(&item))
+}
+
+///
+/// The resources as laid out on the host, which is how the shaders read them, for filling
+/// scene slots. See Renderer::create_scene_slot.
+///
+pub fn bytes_of<T: WrapResource<T>>(items: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(items.as_ptr().cast::<u8>(), std::mem::size_of_val(items)) }
 }
 
 pub fn resources_by_kind_map() -> HashMap<ResourceKind, MultiResource> {
@@ -312,3 +533,27 @@ impl WrapResource<TransformExtra> for TransformExtra {
         SingleResource::TransformExtra(res[0].clone())
     }
 }
+impl WrapResource<LightClusters> for LightClusters {
+    fn multi_wrapper_for(res: &[LightClusters]) -> MultiResource {
+        MultiResource::LightClusters(res.to_vec())
+    }
+    fn single_wrapper_for(res: &[LightClusters]) -> SingleResource {
+        SingleResource::LightClusters(res[0].clone())
+    }
+}
+impl WrapResource<ViewMatrices> for ViewMatrices {
+    fn multi_wrapper_for(res: &[ViewMatrices]) -> MultiResource {
+        MultiResource::ViewMatrices(res.to_vec())
+    }
+    fn single_wrapper_for(res: &[ViewMatrices]) -> SingleResource {
+        SingleResource::ViewMatrices(res[0].clone())
+    }
+}
+impl WrapResource<FrameConstants> for FrameConstants {
+    fn multi_wrapper_for(res: &[FrameConstants]) -> MultiResource {
+        MultiResource::FrameConstants(res.to_vec())
+    }
+    fn single_wrapper_for(res: &[FrameConstants]) -> SingleResource {
+        SingleResource::FrameConstants(res[0].clone())
+    }
+}
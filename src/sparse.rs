@@ -0,0 +1,667 @@
+/*
+ * Sparse partially resident textures. Their images get no memory when made, pages of
+ * them get bound to memory from page pools and unbound again through sparse binding
+ * submissions on the graphics queue. Those signal a timeline semaphore of their own the
+ * next frame waits on. Unbound pages read as zeros, the device only gets the capability
+ * when it guarantees that.
+ */
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::{
+    capabilities::CapabilityError,
+    context::VulkanContext,
+    format::Format,
+    handle::StaleHandle,
+    image_pool::ImageAllocation,
+    range_allocator::RangeAllocator,
+    texture::{MipMap, Texture},
+};
+
+///
+/// Region of a mip of a sparse texture, in texels. Offset and extent of regions to bind
+/// have to be multiples of the page granularity of the texture, extents can stop at the
+/// edge of the mip instead. Regions of mips in the mip tail stand for the whole tail.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageBinding {
+    pub mip: u32,
+    pub offset: [u32; 2],
+    pub extent: [u32; 2],
+}
+
+///
+/// Page pool made by Renderer::create_page_pool.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PagePoolId(pub(crate) u32);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SparseError {
+    Unavailable(CapabilityError),
+    StaleHandle,
+    // Texture wasn't made with gen_texture_sparse
+    NotSparse,
+    UnsupportedFormat(Format),
+    // Past the edge of the mip, or off the page grid when binding
+    BadRegion(PageBinding),
+    UnknownPool(PagePoolId),
+    // Nothing got bound, the pool would go over its budget
+    PoolExhausted {
+        pool: String,
+        budget: u64,
+        needed: u64,
+    },
+}
+
+impl std::fmt::Display for SparseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unavailable(e) => e.fmt(f),
+            Self::StaleHandle => StaleHandle.fmt(f),
+            Self::NotSparse => write!(f, "the texture isn't sparse"),
+            Self::UnsupportedFormat(format) => {
+                write!(f, "the device has no sparse {} textures", format)
+            }
+            Self::BadRegion(region) => write!(
+                f,
+                "region at {:?} of {:?} texels is off the pages of mip {}",
+                region.offset, region.extent, region.mip
+            ),
+            Self::UnknownPool(id) => write!(f, "no page pool {}", id.0),
+            Self::PoolExhausted {
+                pool,
+                budget,
+                needed,
+            } => write!(
+                f,
+                "page pool {} needs {} bytes for a budget of {}",
+                pool, needed, budget
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SparseError {}
+
+impl From<StaleHandle> for SparseError {
+    fn from(_: StaleHandle) -> Self {
+        Self::StaleHandle
+    }
+}
+
+impl From<CapabilityError> for SparseError {
+    fn from(e: CapabilityError) -> Self {
+        Self::Unavailable(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Page {
+    // In pages from the top left of the mip
+    Texels { mip: u32, x: u32, y: u32 },
+    MipTail,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PageMemory {
+    pub pool: PagePoolId,
+    pub memory: vk::DeviceMemory,
+    pub offset: u64,
+    pub size: u64,
+}
+
+///
+/// Pages of a sparse texture and what they're bound to.
+///
+#[derive(Clone, Debug)]
+pub struct SparsePages {
+    pub granularity: vk::Extent2D,
+    pub page_size: u64,
+    pub memory_type_bits: u32,
+    // Mips from this one on share their memory, bound as a whole
+    pub mip_tail_first: u32,
+    pub mip_tail_size: u64,
+    pub mip_tail_offset: u64,
+    pub bound: HashMap<Page, PageMemory>,
+    // Out of the undefined layout, set once the first upload gets recorded
+    pub is_initialized: bool,
+    // Regions in the staging buffer of the texture, in order
+    pub staged: Vec<PageBinding>,
+}
+
+impl SparsePages {
+    ///
+    /// Pages the region covers, in order. Regions to bind have to stay on the page grid.
+    ///
+    pub fn pages_of(
+        &self,
+        mip_maps: &[MipMap],
+        region: PageBinding,
+        is_on_grid: bool,
+    ) -> Result<Vec<Page>, SparseError> {
+        let mip = match mip_maps.get(region.mip as usize) {
+            Some(e) => e,
+            None => return Err(SparseError::BadRegion(region)),
+        };
+        let ends = [
+            region.offset[0] as u64 + region.extent[0] as u64,
+            region.offset[1] as u64 + region.extent[1] as u64,
+        ];
+        if region.extent.contains(&0) || ends[0] > mip.width as u64 || ends[1] > mip.height as u64 {
+            return Err(SparseError::BadRegion(region));
+        }
+        if region.mip >= self.mip_tail_first {
+            return Ok(vec![Page::MipTail]);
+        }
+        let grid = [self.granularity.width, self.granularity.height];
+        let edges = [mip.width, mip.height];
+        let is_off_grid = (0..2).any(|i| {
+            !region.offset[i].is_multiple_of(grid[i])
+                || (!region.extent[i].is_multiple_of(grid[i]) && ends[i] != edges[i] as u64)
+        });
+        if is_on_grid && is_off_grid {
+            return Err(SparseError::BadRegion(region));
+        }
+        let first = [region.offset[0] / grid[0], region.offset[1] / grid[1]];
+        let last = [
+            (ends[0] as u32).div_ceil(grid[0]),
+            (ends[1] as u32).div_ceil(grid[1]),
+        ];
+        Ok((first[1]..last[1])
+            .flat_map(|y| (first[0]..last[0]).map(move |x| (x, y)))
+            .map(|(x, y)| Page::Texels {
+                mip: region.mip,
+                x,
+                y,
+            })
+            .collect())
+    }
+
+    pub fn size_of(&self, page: Page) -> u64 {
+        match page {
+            Page::Texels { .. } => self.page_size,
+            Page::MipTail => self.mip_tail_size,
+        }
+    }
+
+    pub fn bound_bytes(&self) -> u64 {
+        self.bound.values().map(|e| e.size).sum()
+    }
+
+    ///
+    /// Binds of the pages to their memory, or to none to unbind them. Pages in the mip
+    /// tail go in the opaque binds.
+    ///
+    pub fn binds_of(
+        &self,
+        texture: &Texture,
+        pages: &[(Page, Option<PageMemory>)],
+    ) -> (Vec<vk::SparseImageMemoryBind>, Vec<vk::SparseMemoryBind>) {
+        let mut image_binds = Vec::new();
+        let mut opaque_binds = Vec::new();
+        for (page, memory) in pages {
+            let (memory, memory_offset) =
+                memory.map_or((vk::DeviceMemory::null(), 0), |e| (e.memory, e.offset));
+            match *page {
+                Page::Texels { mip, x, y } => {
+                    let extent = texture.mip_maps[mip as usize].extent();
+                    let offset = [x * self.granularity.width, y * self.granularity.height];
+                    image_binds.push(vk::SparseImageMemoryBind {
+                        subresource: vk::ImageSubresource {
+                            aspect_mask: texture.format.aspect(),
+                            mip_level: mip,
+                            array_layer: 0,
+                        },
+                        offset: vk::Offset3D {
+                            x: offset[0] as i32,
+                            y: offset[1] as i32,
+                            z: 0,
+                        },
+                        extent: vk::Extent3D {
+                            width: self.granularity.width.min(extent.width - offset[0]),
+                            height: self.granularity.height.min(extent.height - offset[1]),
+                            depth: 1,
+                        },
+                        memory,
+                        memory_offset,
+                        flags: vk::SparseMemoryBindFlags::empty(),
+                    });
+                }
+                Page::MipTail => opaque_binds.push(vk::SparseMemoryBind {
+                    resource_offset: self.mip_tail_offset,
+                    size: self.mip_tail_size,
+                    memory,
+                    memory_offset,
+                    flags: vk::SparseMemoryBindFlags::empty(),
+                }),
+            }
+        }
+        (image_binds, opaque_binds)
+    }
+
+    ///
+    /// Copies of the staged regions out of the staging buffer at the offset, tightly
+    /// packed one after the other.
+    ///
+    pub fn staged_copy_regions(&self, format: Format, offset: u64) -> Vec<vk::BufferImageCopy> {
+        copy_regions(format, &self.staged, offset)
+    }
+}
+
+///
+/// Copies of the regions out of a buffer holding their texels tightly packed one after
+/// the other, from the offset on.
+///
+pub fn copy_regions(
+    format: Format,
+    regions: &[PageBinding],
+    offset: u64,
+) -> Vec<vk::BufferImageCopy> {
+    let mut offset = offset;
+    regions
+        .iter()
+        .map(|region| {
+            let copy = vk::BufferImageCopy::builder()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(format.aspect())
+                        .layer_count(1)
+                        .mip_level(region.mip)
+                        .build(),
+                )
+                .image_offset(vk::Offset3D {
+                    x: region.offset[0] as i32,
+                    y: region.offset[1] as i32,
+                    z: 0,
+                })
+                .image_extent(vk::Extent3D {
+                    width: region.extent[0],
+                    height: region.extent[1],
+                    depth: 1,
+                })
+                .buffer_offset(offset)
+                .build();
+            offset += region_size(format, *region) as u64;
+            copy
+        })
+        .collect()
+}
+
+///
+/// Bytes of the region tightly packed, compressed formats by their 4x4 blocks.
+///
+pub fn region_size(format: Format, region: PageBinding) -> u32 {
+    let [width, height] = region.extent;
+    match format.texel_size() {
+        Some(texel_size) => texel_size * width * height,
+        None => format.size_for(width.next_multiple_of(4), height.next_multiple_of(4)),
+    }
+}
+
+///
+/// Sparse texture of a single layer with the image made and nothing bound. The view
+/// covers every mip.
+///
+pub fn make_texture(
+    ctx: &VulkanContext,
+    id: u32,
+    name: String,
+    format: Format,
+    mip_maps: &[MipMap],
+) -> Result<Texture, SparseError> {
+    assert!(!mip_maps.is_empty(), "mip_maps can't be empty!");
+    let usage = vk::ImageUsageFlags::TRANSFER_DST
+        | vk::ImageUsageFlags::TRANSFER_SRC
+        | vk::ImageUsageFlags::SAMPLED;
+    let vk_format = format.to_vk();
+    let format_properties = unsafe {
+        ctx.instance
+            .get_physical_device_sparse_image_format_properties(
+                ctx.physical_device,
+                vk_format,
+                vk::ImageType::TYPE_2D,
+                vk::SampleCountFlags::TYPE_1,
+                usage,
+                vk::ImageTiling::OPTIMAL,
+            )
+    };
+    if format_properties.is_empty() {
+        return Err(SparseError::UnsupportedFormat(format));
+    }
+    let create_info = vk::ImageCreateInfo {
+        flags: vk::ImageCreateFlags::SPARSE_BINDING | vk::ImageCreateFlags::SPARSE_RESIDENCY,
+        image_type: vk::ImageType::TYPE_2D,
+        format: vk_format,
+        extent: mip_maps[0].extent().into(),
+        mip_levels: mip_maps.len() as u32,
+        array_layers: 1,
+        samples: vk::SampleCountFlags::TYPE_1,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        ..Default::default()
+    };
+    let image = unsafe { ctx.device.create_image(&create_info, None) }.unwrap();
+    let requirements = unsafe { ctx.device.get_image_memory_requirements(image) };
+    let sparse_requirements = unsafe { ctx.device.get_image_sparse_memory_requirements(image) };
+    let aspect = format.aspect();
+    let sparse_requirements = match sparse_requirements
+        .iter()
+        .find(|e| e.format_properties.aspect_mask.contains(aspect))
+    {
+        Some(e) => *e,
+        None => {
+            unsafe { ctx.device.destroy_image(image, None) };
+            return Err(SparseError::UnsupportedFormat(format));
+        }
+    };
+    ctx.try_set_debug_name(&name, image);
+    let image_view_info = vk::ImageViewCreateInfo::builder()
+        .subresource_range(
+            vk::ImageSubresourceRange::builder()
+                .aspect_mask(aspect)
+                .level_count(mip_maps.len() as u32)
+                .layer_count(1)
+                .build(),
+        )
+        .image(image)
+        .format(vk_format)
+        .view_type(vk::ImageViewType::TYPE_2D);
+    let view = unsafe {
+        ctx.device
+            .create_image_view(&image_view_info, None)
+            .expect("failed image view")
+    };
+    let granularity = sparse_requirements.format_properties.image_granularity;
+    let sparse = SparsePages {
+        granularity: vk::Extent2D {
+            width: granularity.width,
+            height: granularity.height,
+        },
+        page_size: requirements.alignment,
+        memory_type_bits: requirements.memory_type_bits,
+        mip_tail_first: sparse_requirements.image_mip_tail_first_lod,
+        mip_tail_size: sparse_requirements.image_mip_tail_size,
+        mip_tail_offset: sparse_requirements.image_mip_tail_offset,
+        bound: HashMap::new(),
+        is_initialized: false,
+        staged: Vec::new(),
+    };
+    Ok(Texture {
+        id,
+        format,
+        mip_maps: mip_maps.to_vec(),
+        name,
+        // Pages are bound to memory of page pools instead
+        allocation: ImageAllocation {
+            memory: vk::DeviceMemory::null(),
+            offset: 0,
+            size: 0,
+            memory_type_index: 0,
+            memory_flags: vk::MemoryPropertyFlags::empty(),
+            is_dedicated: false,
+        },
+        image,
+        view,
+        staging: None,
+        staged_regions: Vec::new(),
+        is_concurrent: false,
+        layers: 1,
+        is_cube: false,
+        sparse: Some(Box::new(sparse)),
+        offscreen: None,
+        srgb: None,
+    })
+}
+
+///
+/// Mips of a sparse texture, each half the one before down to 1x1. Offsets are the ones
+/// of the mips tightly packed.
+///
+pub fn mip_maps_of(format: Format, width: u32, height: u32, mips: u32) -> Vec<MipMap> {
+    let mut offset = 0;
+    (0..mips)
+        .map(|index| {
+            let (width, height) = ((width >> index).max(1), (height >> index).max(1));
+            let size = region_size(
+                format,
+                PageBinding {
+                    mip: index,
+                    offset: [0, 0],
+                    extent: [width, height],
+                },
+            );
+            let mip = MipMap {
+                index,
+                width,
+                height,
+                size,
+                offset,
+            };
+            offset += size;
+            mip
+        })
+        .collect()
+}
+
+struct PageBlock {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    ranges: RangeAllocator,
+}
+
+///
+/// Memory pages of sparse textures get bound to, suballocated from blocks of a few
+/// pages each. The budget counts the bytes of bound pages, not the blocks.
+///
+pub struct PagePool {
+    name: String,
+    budget: u64,
+    used: u64,
+    blocks: Vec<PageBlock>,
+}
+
+impl PagePool {
+    const PAGES_PER_BLOCK: u64 = 64;
+
+    pub fn new(name: String, budget: u64) -> Self {
+        Self {
+            name,
+            budget,
+            used: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    ///
+    /// Bytes of the blocks allocated from the device.
+    ///
+    pub fn block_bytes(&self) -> u64 {
+        self.blocks.iter().map(|e| e.ranges.size()).sum()
+    }
+
+    ///
+    /// Errors if the pages don't fit in what's left of the budget.
+    ///
+    pub fn check_budget(&self, bytes: u64) -> Result<(), SparseError> {
+        if self.used + bytes > self.budget {
+            return Err(SparseError::PoolExhausted {
+                pool: self.name.clone(),
+                budget: self.budget,
+                needed: self.used + bytes,
+            });
+        }
+        Ok(())
+    }
+
+    ///
+    /// Memory for a page of the size, the budget has to be checked before.
+    ///
+    pub fn alloc(
+        &mut self,
+        ctx: &VulkanContext,
+        id: PagePoolId,
+        size: u64,
+        page_size: u64,
+        memory_type_bits: u32,
+    ) -> PageMemory {
+        let (memory_type_index, _) = ctx
+            .memory_type_index_for_any(memory_type_bits, &[vk::MemoryPropertyFlags::DEVICE_LOCAL])
+            .expect("no suitable memory type for sparse pages!");
+        self.used += size;
+        let found = self
+            .blocks
+            .iter_mut()
+            .filter(|e| e.memory_type_index == memory_type_index)
+            .find_map(|block| {
+                block
+                    .ranges
+                    .alloc(size, page_size)
+                    .map(|offset| (block.memory, offset))
+            });
+        let (memory, offset) = match found {
+            Some(e) => e,
+            None => {
+                let block_size = (page_size * Self::PAGES_PER_BLOCK).max(size);
+                let allocate_info = vk::MemoryAllocateInfo::builder()
+                    .allocation_size(block_size)
+                    .memory_type_index(memory_type_index)
+                    .build();
+                let memory = unsafe {
+                    ctx.device
+                        .allocate_memory(&allocate_info, None)
+                        .expect("failed page pool block alloc")
+                };
+                ctx.try_set_debug_name(
+                    &format!("{}_pages_{}", self.name, self.blocks.len()),
+                    memory,
+                );
+                let mut ranges = RangeAllocator::new(block_size);
+                let offset = ranges
+                    .alloc(size, page_size)
+                    .expect("page doesn't fit in an empty page block!");
+                self.blocks.push(PageBlock {
+                    memory,
+                    memory_type_index,
+                    ranges,
+                });
+                (memory, offset)
+            }
+        };
+        PageMemory {
+            pool: id,
+            memory,
+            offset,
+            size,
+        }
+    }
+
+    pub fn free(&mut self, page: PageMemory) {
+        let block = self
+            .blocks
+            .iter_mut()
+            .find(|e| e.memory == page.memory)
+            .expect("page doesn't belong to the pool!");
+        block.ranges.free(page.offset, page.size);
+        self.used -= page.size;
+    }
+
+    ///
+    /// Gives the blocks no page is allocated from back to the driver, returns how many
+    /// bytes that freed.
+    ///
+    pub fn release_unused_blocks(&mut self, device: &ash::Device) -> u64 {
+        let mut released = 0;
+        self.blocks.retain(|block| {
+            if !block.ranges.is_unused() {
+                return true;
+            }
+            unsafe { device.free_memory(block.memory, None) };
+            released += block.ranges.size();
+            false
+        });
+        released
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for block in self.blocks.drain(..) {
+            unsafe { device.free_memory(block.memory, None) };
+        }
+        self.used = 0;
+    }
+}
+
+///
+/// Timeline semaphore of the sparse binding submissions, starting at 0.
+///
+pub fn make_semaphore(ctx: &VulkanContext) -> vk::Semaphore {
+    let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+        .semaphore_type(vk::SemaphoreType::TIMELINE)
+        .initial_value(0)
+        .build();
+    let create_info = vk::SemaphoreCreateInfo::builder()
+        .push_next(&mut type_info)
+        .build();
+    let semaphore = unsafe { ctx.device.create_semaphore(&create_info, None) }.unwrap();
+    ctx.try_set_debug_name("sparse_timeline_semaphore", semaphore);
+    semaphore
+}
+
+///
+/// Submits the binds of the image to the queue, waiting on the timeline semaphore values
+/// and signaling the value on the other one. The queue has to support sparse binding and
+/// be locked by the caller.
+///
+pub fn submit_binds(
+    ctx: &VulkanContext,
+    queue: vk::Queue,
+    image: vk::Image,
+    binds: &(Vec<vk::SparseImageMemoryBind>, Vec<vk::SparseMemoryBind>),
+    waits: &[(vk::Semaphore, u64)],
+    signal: (vk::Semaphore, u64),
+) -> ash::prelude::VkResult<()> {
+    let (image_binds, opaque_binds) = binds;
+    let image_infos = [vk::SparseImageMemoryBindInfo::builder()
+        .image(image)
+        .binds(image_binds)
+        .build()];
+    let opaque_infos = [vk::SparseImageOpaqueMemoryBindInfo::builder()
+        .image(image)
+        .binds(opaque_binds)
+        .build()];
+    let wait_semaphores: Vec<_> = waits.iter().map(|e| e.0).collect();
+    let wait_values: Vec<_> = waits.iter().map(|e| e.1).collect();
+    let signal_semaphores = [signal.0];
+    let signal_values = [signal.1];
+    let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+        .wait_semaphore_values(&wait_values)
+        .signal_semaphore_values(&signal_values)
+        .build();
+    let mut bind_info = vk::BindSparseInfo::builder()
+        .push_next(&mut timeline_info)
+        .wait_semaphores(&wait_semaphores)
+        .signal_semaphores(&signal_semaphores);
+    if !image_binds.is_empty() {
+        bind_info = bind_info.image_binds(&image_infos);
+    }
+    if !opaque_binds.is_empty() {
+        bind_info = bind_info.image_opaque_binds(&opaque_infos);
+    }
+    unsafe {
+        ctx.device
+            .queue_bind_sparse(queue, &[bind_info.build()], vk::Fence::null())
+    }
+}
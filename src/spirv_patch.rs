@@ -0,0 +1,482 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+/*
+ * In memory patching of SPIR-V binaries before their shader modules get made, for
+ * shaders written against other conventions, like HLSL ones compiled by DXC. Patches
+ * are declared per shader in pipeline.json, keyed by the shader file name the programs
+ * use:
+ *
+ *   "shaders": {
+ *     "skin.frag": {
+ *       "bindingRemap": { "2:0": "0:5", "2:1": "0:6" },
+ *       "blockLayout": "std140",
+ *       "stripDebugInfo": true
+ *     }
+ *   }
+ *
+ * Binding remaps rewrite the literals of DescriptorSet and Binding decorations in place,
+ * everything else stays as it was word for word, instructions this doesn't know about
+ * included. Block layouts only get checked, mismatches are logged but the offsets stay
+ * as declared. Stripping drops OpSource and the other debug instructions, after the
+ * shader was reflected so block and binding names still get checked.
+ */
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+const SPIRV_HEADER_LEN: usize = 5;
+// Highest minor version of SPIR-V 1
+const MAX_MINOR_VERSION: u32 = 6;
+
+const OP_SOURCE_CONTINUED: u32 = 2;
+const OP_SOURCE: u32 = 3;
+const OP_SOURCE_EXTENSION: u32 = 4;
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_STRING: u32 = 7;
+const OP_LINE: u32 = 8;
+const OP_EXT_INST_IMPORT: u32 = 11;
+const OP_DECORATE: u32 = 71;
+const OP_NO_LINE: u32 = 317;
+const OP_MODULE_PROCESSED: u32 = 330;
+
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+const DEBUG_OPCODES: [u32; 9] = [
+    OP_SOURCE_CONTINUED,
+    OP_SOURCE,
+    OP_SOURCE_EXTENSION,
+    OP_NAME,
+    OP_MEMBER_NAME,
+    OP_STRING,
+    OP_LINE,
+    OP_NO_LINE,
+    OP_MODULE_PROCESSED,
+];
+
+// Names of the instructions errors can point at, the rest go by their opcode
+const INSTRUCTION_NAMES: [(u32, &str); 30] = [
+    (0, "OpNop"),
+    (2, "OpSourceContinued"),
+    (3, "OpSource"),
+    (4, "OpSourceExtension"),
+    (5, "OpName"),
+    (6, "OpMemberName"),
+    (7, "OpString"),
+    (8, "OpLine"),
+    (10, "OpExtension"),
+    (11, "OpExtInstImport"),
+    (14, "OpMemoryModel"),
+    (15, "OpEntryPoint"),
+    (16, "OpExecutionMode"),
+    (17, "OpCapability"),
+    (19, "OpTypeVoid"),
+    (21, "OpTypeInt"),
+    (22, "OpTypeFloat"),
+    (23, "OpTypeVector"),
+    (30, "OpTypeStruct"),
+    (32, "OpTypePointer"),
+    (33, "OpTypeFunction"),
+    (43, "OpConstant"),
+    (54, "OpFunction"),
+    (56, "OpFunctionEnd"),
+    (59, "OpVariable"),
+    (71, "OpDecorate"),
+    (72, "OpMemberDecorate"),
+    (248, "OpLabel"),
+    (253, "OpReturn"),
+    (330, "OpModuleProcessed"),
+];
+
+///
+/// Patches of a shader, see the shaders of pipeline.json.
+///
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ShaderPatch {
+    // "set:binding" the shader declares to the "set:binding" it gets
+    #[serde(default)]
+    pub binding_remap: BTreeMap<String, String>,
+    // Rule the offsets and strides of its blocks get checked against
+    #[serde(default)]
+    pub block_layout: Option<LayoutRule>,
+    #[serde(default)]
+    pub strip_debug_info: bool,
+}
+
+///
+/// Block layout rules of GLSL, the ones blocks get checked against.
+///
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum LayoutRule {
+    Std140,
+    Std430,
+    Scalar,
+}
+
+impl LayoutRule {
+    ///
+    /// Alignment of arrays, matrices and structs whose elements or members are aligned
+    /// to align. Std140 rounds those up to a vec4.
+    ///
+    pub fn aggregate_align(self, align: u32) -> u32 {
+        match self {
+            Self::Std140 => align.next_multiple_of(16),
+            Self::Std430 | Self::Scalar => align,
+        }
+    }
+
+    ///
+    /// Alignment of a vector of count components of component_size bytes each.
+    ///
+    pub fn vector_align(self, component_size: u32, count: u32) -> u32 {
+        match (self, count) {
+            (Self::Scalar, _) => component_size,
+            (_, 2) => component_size * 2,
+            _ => component_size * 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum LayoutProperty {
+    Offset,
+    #[strum(serialize = "array stride")]
+    ArrayStride,
+    #[strum(serialize = "matrix stride")]
+    MatrixStride,
+}
+
+///
+/// Offset or stride of a block member declared other than the rule lays it out.
+/// Members of nested structs go by their path, like "lights.color".
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleMismatch {
+    pub block: String,
+    pub member: String,
+    pub property: LayoutProperty,
+    pub declared: u32,
+    pub expected: u32,
+    pub rule: LayoutRule,
+}
+
+impl std::fmt::Display for RuleMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "block {} member {} has {} {}, {} lays it out at {}",
+            self.block, self.member, self.property, self.declared, self.rule, self.expected
+        )
+    }
+}
+
+///
+/// SPIR-V that can't be patched, offset being the byte it was found at.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchError {
+    pub offset: usize,
+    // Instruction at the offset, "header" for the module header
+    pub instruction: String,
+    pub problem: String,
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "malformed SPIR-V at byte {} ({}): {}",
+            self.offset, self.instruction, self.problem
+        )
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+///
+/// Binding remap table entry that isn't a pair of "set:binding" or that sends two
+/// bindings to the same one.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidRemap {
+    pub entry: String,
+    pub problem: String,
+}
+
+impl std::fmt::Display for InvalidRemap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "binding remap {}: {}", self.entry, self.problem)
+    }
+}
+
+impl std::error::Error for InvalidRemap {}
+
+///
+/// Set and binding pairs to rewrite, parsed out of a bindingRemap table.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BindingRemap {
+    targets: HashMap<(u32, u32), (u32, u32)>,
+}
+
+impl BindingRemap {
+    pub fn parse(table: &BTreeMap<String, String>) -> Result<Self, InvalidRemap> {
+        let mut remap = Self::default();
+        for (from, to) in table {
+            let invalid = |problem: &str| InvalidRemap {
+                entry: format!("{} -> {}", from, to),
+                problem: problem.to_string(),
+            };
+            let (from, to) = match (set_binding_of(from), set_binding_of(to)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => return Err(invalid("expected \"set:binding\" on both sides")),
+            };
+            if remap.targets.values().any(|e| *e == to) {
+                return Err(invalid("another binding is remapped to the same one"));
+            }
+            remap.targets.insert(from, to);
+        }
+        Ok(remap)
+    }
+
+    pub fn insert(&mut self, from: (u32, u32), to: (u32, u32)) {
+        self.targets.insert(from, to);
+    }
+
+    pub fn get(&self, set: u32, binding: u32) -> Option<(u32, u32)> {
+        self.targets.get(&(set, binding)).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+fn set_binding_of(text: &str) -> Option<(u32, u32)> {
+    let (set, binding) = text.split_once(':')?;
+    Some((set.trim().parse().ok()?, binding.trim().parse().ok()?))
+}
+
+///
+/// SPIR-V binary whose header and instruction stream were checked, ready for patching.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpirvModule {
+    words: Vec<u32>,
+}
+
+impl SpirvModule {
+    ///
+    /// Checks the header, the version being SPIR-V 1.0 to 1.6, and that every
+    /// instruction fits in the module.
+    ///
+    pub fn parse(words: Vec<u32>) -> Result<Self, PatchError> {
+        let header = |offset: usize, problem: String| PatchError {
+            offset,
+            instruction: "header".to_string(),
+            problem,
+        };
+        if words.len() < SPIRV_HEADER_LEN {
+            let problem = format!("{} words, a header takes {}", words.len(), SPIRV_HEADER_LEN);
+            return Err(header(0, problem));
+        }
+        if words[0] != SPIRV_MAGIC {
+            let problem = if words[0] == SPIRV_MAGIC.swap_bytes() {
+                "byte swapped modules aren't supported".to_string()
+            } else {
+                format!("magic {:#010x} isn't SPIR-V", words[0])
+            };
+            return Err(header(0, problem));
+        }
+        let version = words[1];
+        let (major, minor) = ((version >> 16) & 0xFF, (version >> 8) & 0xFF);
+        if version & 0xFF0000FF != 0 || major != 1 || minor > MAX_MINOR_VERSION {
+            return Err(header(4, format!("unsupported version {:#010x}", version)));
+        }
+        if words[4] != 0 {
+            return Err(header(16, format!("reserved schema {} isn't 0", words[4])));
+        }
+        let module = Self { words };
+        for e in module.instructions() {
+            let (index, count) = e?;
+            let opcode = module.words[index] & 0xFFFF;
+            if opcode == OP_DECORATE && count > 2 {
+                let decoration = module.words[index + 2];
+                let is_set_binding =
+                    decoration == DECORATION_DESCRIPTOR_SET || decoration == DECORATION_BINDING;
+                if is_set_binding && count != 4 {
+                    return Err(PatchError {
+                        offset: index * 4,
+                        instruction: instruction_name(opcode),
+                        problem: format!(
+                            "decoration {} takes a single literal, got {} words",
+                            decoration,
+                            count - 3
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(module)
+    }
+
+    ///
+    /// Same as parse for the bytes of a .spv file, in the byte order of the host.
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PatchError> {
+        if !bytes.len().is_multiple_of(4) {
+            return Err(PatchError {
+                offset: bytes.len(),
+                instruction: "header".to_string(),
+                problem: format!("{} bytes isn't a whole number of words", bytes.len()),
+            });
+        }
+        let words = bytes
+            .chunks_exact(4)
+            .map(|e| u32::from_ne_bytes(e.try_into().unwrap()))
+            .collect();
+        Self::parse(words)
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    pub fn into_words(self) -> Vec<u32> {
+        self.words
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|e| e.to_ne_bytes()).collect()
+    }
+
+    /*
+     * Word index and word count of each instruction after the header.
+     */
+    fn instructions(&self) -> impl Iterator<Item = Result<(usize, usize), PatchError>> + '_ {
+        let mut index = SPIRV_HEADER_LEN;
+        std::iter::from_fn(move || {
+            if index >= self.words.len() {
+                return None;
+            }
+            let count = (self.words[index] >> 16) as usize;
+            let opcode = self.words[index] & 0xFFFF;
+            let problem = if count == 0 {
+                Some("word count of 0".to_string())
+            } else if index + count > self.words.len() {
+                Some(format!(
+                    "{} words but only {} are left",
+                    count,
+                    self.words.len() - index
+                ))
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                let error = PatchError {
+                    offset: index * 4,
+                    instruction: instruction_name(opcode),
+                    problem,
+                };
+                // Nothing past it can be found
+                index = self.words.len();
+                return Some(Err(error));
+            }
+            let instruction = (index, count);
+            index += count;
+            Some(Ok(instruction))
+        })
+    }
+
+    ///
+    /// Rewrites the set and binding of the variables the remap has an entry for.
+    /// Returns how many got remapped, ones decorated with only one of both are left
+    /// alone.
+    ///
+    pub fn remap_bindings(&mut self, remap: &BindingRemap) -> usize {
+        // Variable id to the word indices of its set and binding literals
+        let mut literals: HashMap<u32, (Option<usize>, Option<usize>)> = HashMap::new();
+        for (index, count) in self.instructions().map(|e| e.unwrap()) {
+            if self.words[index] & 0xFFFF != OP_DECORATE || count != 4 {
+                continue;
+            }
+            let entry = literals.entry(self.words[index + 1]).or_default();
+            match self.words[index + 2] {
+                DECORATION_DESCRIPTOR_SET => entry.0 = Some(index + 3),
+                DECORATION_BINDING => entry.1 = Some(index + 3),
+                _ => {}
+            }
+        }
+        let mut remapped = 0;
+        for (set_index, binding_index) in literals.into_values() {
+            let (set_index, binding_index) = match (set_index, binding_index) {
+                (Some(set), Some(binding)) => (set, binding),
+                _ => continue,
+            };
+            let set = self.words[set_index];
+            let binding = self.words[binding_index];
+            if let Some((new_set, new_binding)) = remap.get(set, binding) {
+                self.words[set_index] = new_set;
+                self.words[binding_index] = new_binding;
+                remapped += 1;
+            }
+        }
+        remapped
+    }
+
+    ///
+    /// Drops OpSource, OpName, OpLine and the rest of the debug instructions. Returns
+    /// how many got dropped.
+    ///
+    pub fn strip_debug_info(&mut self) -> usize {
+        let instructions: Vec<_> = self.instructions().map(|e| e.unwrap()).collect();
+        // Non semantic instructions take their strings from OpString, those have to stay
+        let is_non_semantic_used = instructions.iter().any(|&(index, count)| {
+            self.words[index] & 0xFFFF == OP_EXT_INST_IMPORT
+                && count > 2
+                && string_of(&self.words[index + 2..index + count]).starts_with("NonSemantic.")
+        });
+        let mut words = self.words[..SPIRV_HEADER_LEN].to_vec();
+        let mut stripped = 0;
+        for (index, count) in instructions {
+            let opcode = self.words[index] & 0xFFFF;
+            let is_kept = opcode == OP_STRING && is_non_semantic_used;
+            if DEBUG_OPCODES.contains(&opcode) && !is_kept {
+                stripped += 1;
+            } else {
+                words.extend_from_slice(&self.words[index..index + count]);
+            }
+        }
+        self.words = words;
+        stripped
+    }
+
+    ///
+    /// Offsets and strides the Block and BufferBlock structs declare other than the rule
+    /// lays them out. Only reported, nothing gets rewritten.
+    ///
+    pub fn check_block_layouts(&self, rule: LayoutRule) -> Vec<RuleMismatch> {
+        crate::reflection::rule_mismatches(&self.words, rule)
+    }
+}
+
+fn string_of(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|e| e.to_le_bytes())
+        .take_while(|e| *e != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn instruction_name(opcode: u32) -> String {
+    INSTRUCTION_NAMES
+        .iter()
+        .find(|e| e.0 == opcode)
+        .map_or_else(|| format!("opcode {}", opcode), |e| e.1.to_string())
+}
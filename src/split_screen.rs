@@ -0,0 +1,95 @@
+/*
+ * Split screen viewports, see Renderer::set_viewports. With any enabled, stages draw
+ * once per enabled viewport into its region of their attachments, with the tasks whose
+ * viewport_mask has its bit set and the shader resources of the renderer overridden by
+ * the ones of the viewport, like its own ViewMatrices. Stages of passes marked
+ * "sharedAcrossViewports" draw once over the whole render area with every task whatever
+ * their mask, like shadow maps, post processing or UI. Blits always copy once.
+ *
+ * Attachments are shared full size between the viewports, each one drawing within its
+ * region. Every stage still begins and ends its rendering once, so clears clear the
+ * whole attachment once before any viewport draws. Passes sampling their inputs around
+ * the pixel they shade, like blurs, read across the borders of the regions.
+ *
+ * Targets declared "perViewport" get a full size layer per viewport instead. Stages
+ * rendering into or sampling any begin rendering once per viewport over its region,
+ * clears included, through the layers of that viewport, so blurs only read what their
+ * own viewport drew. Only passes drawing once per viewport can use them.
+ *
+ * Disabled viewports neither prepare nor draw anything, their regions keep whatever
+ * shared stages or clears leave in them.
+ */
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::render_task::RenderTask;
+use crate::shader_resource::{ResourceKind, SingleResource};
+
+// One bit of RenderTask::viewport_mask each, a timestamp pair per stage too
+pub const MAX_VIEWPORTS: usize = 4;
+
+///
+/// Region of the attachments a viewport draws in, in pixels of the render area, and the
+/// shader resources its draws use instead of the ones placed on the renderer.
+///
+#[derive(Clone, Default)]
+pub struct ViewportDesc {
+    pub region: vk::Rect2D,
+    pub is_enabled: bool,
+    pub resources: HashMap<ResourceKind, SingleResource>,
+}
+
+impl ViewportDesc {
+    ///
+    /// Enabled, with the shared resources.
+    ///
+    pub fn new(region: vk::Rect2D) -> Self {
+        Self {
+            region,
+            is_enabled: true,
+            resources: HashMap::new(),
+        }
+    }
+
+    pub fn with_resource(mut self, kind: ResourceKind, resource: SingleResource) -> Self {
+        self.resources.insert(kind, resource);
+        self
+    }
+}
+
+///
+/// Whether the task gets drawn in the viewport of the index.
+///
+pub fn is_drawn_in(task: &RenderTask, viewport: usize) -> bool {
+    task.viewport_mask & (1 << viewport) != 0
+}
+
+///
+/// Regions of count viewports side by side over the area, the last one taking what the
+/// division leaves.
+///
+pub fn side_by_side(area: vk::Rect2D, count: u32) -> Vec<vk::Rect2D> {
+    assert!(count > 0, "no viewports to split into!");
+    let width = area.extent.width / count;
+    (0..count)
+        .map(|i| {
+            let x = area.offset.x + (i * width) as i32;
+            let extent = if i + 1 == count {
+                area.extent.width - i * width
+            } else {
+                width
+            };
+            vk::Rect2D {
+                offset: vk::Offset2D {
+                    x,
+                    y: area.offset.y,
+                },
+                extent: vk::Extent2D {
+                    width: extent,
+                    height: area.extent.height,
+                },
+            }
+        })
+        .collect()
+}
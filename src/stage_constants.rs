@@ -0,0 +1,378 @@
+/*
+ * Constants declared per stage in pipeline.json and changed at runtime, for tweaking
+ * shaders without recompiling them:
+ *
+ *   "constants": [
+ *     { "name": "radius", "type": "float", "default": 0.5, "min": 0.1, "max": 2.0 },
+ *     { "name": "tint", "type": "vec3", "default": [1, 1, 1] }
+ *   ]
+ *
+ * They get packed in declaration order in the scalar block layout, each member right
+ * after the previous one since all of them are 4 byte aligned. The block gets copied to
+ * the general buffer every frame the stage is prepared in, so a frame sees the values
+ * set before it was prepared and none set afterwards. Its address goes into the push
+ * constants right after the per pass data address, first if there is no per pass data.
+ * Shaders declare it as StageConstants::glsl_source generates it:
+ *
+ *   layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer StageConstants
+ *   {
+ *     float radius;
+ *     vec3 tint;
+ *   };
+ *
+ * The stage's debug flags come last, aligned to 8 bytes, see debug_flags. Reflected
+ * shaders declaring a block of that name get checked against the declaration when the
+ * pipeline loads, they can leave the debug flags out.
+ */
+use glam::{Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+use crate::debug_flags;
+use crate::pipeline::file::{ConstantComponents, ConstantDecl, Pass};
+
+pub const BLOCK_NAME: &str = "StageConstants";
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ConstantType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Int,
+    UInt,
+}
+
+impl ConstantType {
+    pub fn components(self) -> usize {
+        match self {
+            Self::Float | Self::Int | Self::UInt => 1,
+            Self::Vec2 => 2,
+            Self::Vec3 => 3,
+            Self::Vec4 => 4,
+        }
+    }
+
+    pub fn size(self) -> u32 {
+        self.components() as u32 * 4
+    }
+}
+
+///
+/// Value of a stage constant, see Renderer::set_stage_constant. Serializes along with
+/// its type, hosts can save the values they tweaked and set them again later.
+///
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum ConstantValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+    UInt(u32),
+}
+
+impl ConstantValue {
+    pub fn kind(&self) -> ConstantType {
+        match self {
+            Self::Float(_) => ConstantType::Float,
+            Self::Vec2(_) => ConstantType::Vec2,
+            Self::Vec3(_) => ConstantType::Vec3,
+            Self::Vec4(_) => ConstantType::Vec4,
+            Self::Int(_) => ConstantType::Int,
+            Self::UInt(_) => ConstantType::UInt,
+        }
+    }
+
+    ///
+    /// Value of the type out of the components written in pipeline.json, None if their
+    /// count doesn't match or an integer type gets a fraction or a value out of range.
+    ///
+    pub fn of(kind: ConstantType, components: &ConstantComponents) -> Option<Self> {
+        let values = match components {
+            ConstantComponents::Scalar(v) => std::slice::from_ref(v),
+            ConstantComponents::Vector(v) => v.as_slice(),
+        };
+        if values.len() != kind.components() {
+            return None;
+        }
+        let float = |i: usize| values[i] as f32;
+        let value = match kind {
+            ConstantType::Float => Self::Float(float(0)),
+            ConstantType::Vec2 => Self::Vec2([float(0), float(1)]),
+            ConstantType::Vec3 => Self::Vec3([float(0), float(1), float(2)]),
+            ConstantType::Vec4 => Self::Vec4([float(0), float(1), float(2), float(3)]),
+            ConstantType::Int => Self::Int(integer_of(values[0])?),
+            ConstantType::UInt => Self::UInt(integer_of(values[0])?),
+        };
+        Some(value)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Float(v) => v.to_ne_bytes().to_vec(),
+            Self::Vec2(v) => v.iter().flat_map(|e| e.to_ne_bytes()).collect(),
+            Self::Vec3(v) => v.iter().flat_map(|e| e.to_ne_bytes()).collect(),
+            Self::Vec4(v) => v.iter().flat_map(|e| e.to_ne_bytes()).collect(),
+            Self::Int(v) => v.to_ne_bytes().to_vec(),
+            Self::UInt(v) => v.to_ne_bytes().to_vec(),
+        }
+    }
+}
+
+fn integer_of<T: TryFrom<i64>>(v: f64) -> Option<T> {
+    if v.fract() != 0.0 {
+        return None;
+    }
+    T::try_from(v as i64).ok()
+}
+
+impl From<f32> for ConstantValue {
+    fn from(v: f32) -> Self {
+        Self::Float(v)
+    }
+}
+
+impl From<Vec2> for ConstantValue {
+    fn from(v: Vec2) -> Self {
+        Self::Vec2(v.to_array())
+    }
+}
+
+impl From<Vec3> for ConstantValue {
+    fn from(v: Vec3) -> Self {
+        Self::Vec3(v.to_array())
+    }
+}
+
+impl From<Vec4> for ConstantValue {
+    fn from(v: Vec4) -> Self {
+        Self::Vec4(v.to_array())
+    }
+}
+
+impl From<i32> for ConstantValue {
+    fn from(v: i32) -> Self {
+        Self::Int(v)
+    }
+}
+
+impl From<u32> for ConstantValue {
+    fn from(v: u32) -> Self {
+        Self::UInt(v)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnknownConstant {
+    // No enabled stage of the name
+    Stage(String),
+    // The stage declares no constant of the name
+    Name {
+        stage: String,
+        name: String,
+    },
+    // The constant is declared with another type
+    Type {
+        stage: String,
+        name: String,
+        declared: ConstantType,
+        provided: ConstantType,
+    },
+}
+
+impl std::fmt::Display for UnknownConstant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnknownConstant::Stage(stage) => write!(f, "no stage {}", stage),
+            UnknownConstant::Name { stage, name } => {
+                write!(f, "stage {} declares no constant {}", stage, name)
+            }
+            UnknownConstant::Type {
+                stage,
+                name,
+                declared,
+                provided,
+            } => write!(
+                f,
+                "constant {} of stage {} is a {}, got a {}",
+                name, stage, declared, provided
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnknownConstant {}
+
+///
+/// Constant as declared and its current value, see Renderer::list_stage_constants.
+///
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StageConstant {
+    pub name: String,
+    pub kind: ConstantType,
+    pub value: ConstantValue,
+    pub default: ConstantValue,
+    // Meant for sliders, nothing keeps the value within them
+    pub min: Option<ConstantValue>,
+    pub max: Option<ConstantValue>,
+    // Bytes from the start of the block
+    pub offset: u32,
+}
+
+///
+/// Every constant of a stage, in declaration order, and its debug flags.
+///
+#[derive(Clone, Debug, Default)]
+pub struct StageConstants {
+    constants: Vec<StageConstant>,
+    // Of the constants, without the debug flags
+    size: u32,
+    has_block: bool,
+    pub debug_flags: u64,
+}
+
+impl StageConstants {
+    ///
+    /// Constants of the stage at their defaults. Panics on duplicate names or on values
+    /// that don't fit the declared types.
+    ///
+    pub fn of(stage: &str, decls: &[ConstantDecl]) -> Self {
+        let mut constants: Vec<StageConstant> = Vec::with_capacity(decls.len());
+        let mut size = 0;
+        for decl in decls {
+            if constants.iter().any(|e| e.name == decl.name) {
+                panic!("stage {} declares constant {} twice!", stage, decl.name);
+            }
+            let value_of = |what: &str, components: &ConstantComponents| {
+                ConstantValue::of(decl.kind, components).unwrap_or_else(|| {
+                    panic!(
+                        "{} of constant {} of stage {} doesn't fit its type {}!",
+                        what, decl.name, stage, decl.kind
+                    )
+                })
+            };
+            // Single numbers apply to every component of vector bounds
+            let bound_of = |what: &str, components: &ConstantComponents| match components {
+                ConstantComponents::Scalar(v) => value_of(
+                    what,
+                    &ConstantComponents::Vector(vec![*v; decl.kind.components()]),
+                ),
+                ConstantComponents::Vector(_) => value_of(what, components),
+            };
+            let default = value_of("default", &decl.default);
+            constants.push(StageConstant {
+                name: decl.name.clone(),
+                kind: decl.kind,
+                value: default,
+                default,
+                min: decl.min.as_ref().map(|e| bound_of("min", e)),
+                max: decl.max.as_ref().map(|e| bound_of("max", e)),
+                offset: size,
+            });
+            size += decl.kind.size();
+        }
+        Self {
+            has_block: !constants.is_empty(),
+            constants,
+            size,
+            debug_flags: 0,
+        }
+    }
+
+    ///
+    /// Constants the pass declares, with a block even without any if it asks for its
+    /// debug flags.
+    ///
+    pub fn of_pass(pass: &Pass) -> Self {
+        let mut constants = Self::of(&pass.name, &pass.constants);
+        constants.has_block |= pass.has_debug_flags;
+        constants
+    }
+
+    ///
+    /// Whether the stage gets no block at all.
+    ///
+    pub fn is_empty(&self) -> bool {
+        !self.has_block
+    }
+
+    ///
+    /// Bytes of the whole block, debug flags included.
+    ///
+    pub fn size(&self) -> u32 {
+        self.debug_flags_offset() + 8
+    }
+
+    pub fn debug_flags_offset(&self) -> u32 {
+        debug_flags::offset_after(self.size)
+    }
+
+    pub fn constants(&self) -> &[StageConstant] {
+        &self.constants
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StageConstant> {
+        self.constants.iter().find(|e| e.name == name)
+    }
+
+    ///
+    /// Replaces the value of the constant, rejecting values of another type.
+    ///
+    pub fn set(
+        &mut self,
+        stage: &str,
+        name: &str,
+        value: ConstantValue,
+    ) -> Result<(), UnknownConstant> {
+        let constant = self
+            .constants
+            .iter_mut()
+            .find(|e| e.name == name)
+            .ok_or_else(|| UnknownConstant::Name {
+                stage: stage.to_string(),
+                name: name.to_string(),
+            })?;
+        if constant.kind != value.kind() {
+            return Err(UnknownConstant::Type {
+                stage: stage.to_string(),
+                name: name.to_string(),
+                declared: constant.kind,
+                provided: value.kind(),
+            });
+        }
+        constant.value = value;
+        Ok(())
+    }
+
+    ///
+    /// Current values as the shaders read them.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size() as usize);
+        for constant in &self.constants {
+            bytes.extend(constant.value.to_bytes());
+        }
+        bytes.resize(self.debug_flags_offset() as usize, 0);
+        bytes.extend(self.debug_flags.to_ne_bytes());
+        bytes
+    }
+
+    ///
+    /// GLSL declaration of the block, see the top of this file.
+    ///
+    pub fn glsl_source(&self) -> String {
+        let mut src = format!(
+            "layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer {}\n{{\n",
+            BLOCK_NAME
+        );
+        for constant in &self.constants {
+            src += &format!("  {} {};\n", constant.kind, constant.name);
+        }
+        // Scalar layout aligns it to 8 by itself
+        src += &format!("  uint64_t {};\n}};\n", debug_flags::MEMBER);
+        src
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{
+    frame_ring::RingWatermarks,
+    pipeline::state_cache::StateCounters,
+    render_task::{RenderTask, TaskKind},
+    UsedAsIndex,
+};
+
+///
+/// Task counts of a single frame. Rejected tasks were refused when queued because of
+/// the task budgets, trimmed ones were accepted but dropped before rendering because
+/// their draw data wouldn't fit in the per-frame buffers.
+///
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    pub frame: u64,
+    pub accepted_by_kind: [u32; TaskKind::MAX_LEN],
+    pub rejected_by_kind: [u32; TaskKind::MAX_LEN],
+    pub trimmed_by_kind: [u32; TaskKind::MAX_LEN],
+    // Staging bytes of the textures uploaded on the async compute queue
+    pub async_upload_bytes: u64,
+    // Mesh chunk bytes copied on the graphics queue
+    pub mesh_upload_bytes: u64,
+    // Rejected because their mesh was still uploading, included in rejected_by_kind
+    pub uploading_mesh_tasks: u32,
+    // Acquisitions that timed out in a row right before the one of this frame
+    pub consecutive_acquire_timeouts: u32,
+    // Suboptimal frames in a row up to this one, 0 if this one wasn't
+    pub consecutive_suboptimal_frames: u32,
+    // Presents reported late so far, see present_timing. 0 without VK_GOOGLE_display_timing
+    pub missed_vsyncs: u64,
+    // Draw data of this frame in the frame ring, and the most of the frames before
+    pub frame_ring: RingWatermarks,
+    pub path: FramePath,
+    // From the oldest input marked with Renderer::mark_input until the frame was handed
+    // to the presentation engine, scanout comes on top. None without any marked
+    pub input_to_present: Option<std::time::Duration>,
+    // Left out to stay within RendererConfig::gpu_frame_budget
+    pub skipped_stages: Vec<String>,
+    // Of each split screen viewport over the stages drawing once per viewport, from the
+    // last frame timed, see split_screen. Empty without viewports or stage timing
+    pub viewport_gpu_times: Vec<std::time::Duration>,
+    // State commands the stages asked for and the ones recorded, see state_cache
+    pub state_commands: StateCounters,
+    #[cfg(feature = "bench-metrics")]
+    pub bench: BenchCounters,
+}
+
+///
+/// What benchmarks compare between versions of the crate, counted only with the
+/// bench-metrics feature. Allocations and descriptor bytes are the ones since the
+/// previous frame got submitted.
+///
+#[cfg(feature = "bench-metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BenchCounters {
+    // Recording the command buffer of the frame, without submitting it
+    pub record_time: std::time::Duration,
+    // Made in the general buffer, staging and per frame buffers included
+    pub allocations: u64,
+    // Flushed to the image and sampler tables, see DescriptorBackend::take_flushed_bytes
+    pub descriptor_flush_bytes: u64,
+}
+
+///
+/// How the frame got to the swapchain image.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum FramePath {
+    // Every stage recorded, the last one writing the default attachment presents
+    #[default]
+    Stages,
+    // Every stage recorded, the single pass declared present writes the swapchain image
+    PresentStage,
+    // Nothing to draw, no stage recorded and the image cleared or kept, see IdleFrames
+    Idle,
+    // Only overlay tasks queued, only the overlay stage recorded into the shared image
+    Overlay,
+    // No swapchain image, only uploads, offscreen passes and readbacks, see
+    // Renderer::flush_gpu_work
+    Flush,
+}
+
+impl FrameStats {
+    pub fn accepted(&self) -> u32 {
+        self.accepted_by_kind.iter().sum()
+    }
+
+    pub fn rejected(&self) -> u32 {
+        self.rejected_by_kind.iter().sum()
+    }
+
+    pub fn trimmed(&self) -> u32 {
+        self.trimmed_by_kind.iter().sum()
+    }
+
+    ///
+    /// Tasks that didn't make it into the frame for any reason.
+    ///
+    pub fn dropped(&self) -> u32 {
+        self.rejected() + self.trimmed()
+    }
+}
+
+///
+/// What is queued for the next frame so far, see Renderer::frame_submission_summary.
+/// Tasks trimmed at render time to fit the per-frame buffers are still counted here.
+///
+#[derive(Clone, Debug, Default)]
+pub struct SubmissionSummary {
+    pub tasks_by_kind: [u32; TaskKind::MAX_LEN],
+    pub unique_meshes: u32,
+    // Mesh vertex or index count times instances, for every stage drawing the task
+    pub estimated_vertices: u64,
+    // General buffer bytes the draw data will take, per pass data included
+    pub draw_data_bytes: u64,
+}
+
+impl SubmissionSummary {
+    ///
+    /// Summary of the queued tasks, with the per pass draw data bytes of the frame, the
+    /// vertex or index count of the mesh of a task, how many stages draw tasks of a
+    /// kind and the draw data bytes of a task.
+    ///
+    pub fn of<'a>(
+        tasks: impl IntoIterator<Item = &'a RenderTask>,
+        per_pass_bytes: u64,
+        vertices_of: impl Fn(&RenderTask) -> u64,
+        stages_drawing: impl Fn(TaskKind) -> u64,
+        draw_data_of: impl Fn(&RenderTask) -> u64,
+    ) -> Self {
+        let stages_by_kind: [u64; TaskKind::MAX_LEN] =
+            std::array::from_fn(|e| stages_drawing(TaskKind::of_usize(e)));
+        let mut summary = Self {
+            draw_data_bytes: per_pass_bytes,
+            ..Default::default()
+        };
+        let mut meshes = HashSet::new();
+        for task in tasks {
+            let kind = task.kind.to_usize();
+            summary.tasks_by_kind[kind] += 1;
+            meshes.insert(task.mesh.index);
+            summary.estimated_vertices +=
+                vertices_of(task) * task.instance_count as u64 * stages_by_kind[kind];
+            summary.draw_data_bytes += draw_data_of(task);
+        }
+        summary.unique_meshes = meshes.len() as u32;
+        summary
+    }
+
+    pub fn tasks(&self) -> u32 {
+        self.tasks_by_kind.iter().sum()
+    }
+}
@@ -0,0 +1,20 @@
+use ash::vk;
+use winit::window::Window;
+
+///
+/// Surface for a winit window, to return from the make_renderer callback.
+///
+pub fn create_for_window(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    window: &Window,
+) -> Result<vk::SurfaceKHR, vk::Result> {
+    unsafe { ash_window::create_surface(entry, instance, window, None) }
+}
+
+///
+/// Instance extensions the window's surface needs, to pass on to make_renderer.
+///
+pub fn required_extensions(window: &Window) -> Result<&'static [*const i8], vk::Result> {
+    ash_window::enumerate_required_extensions(window)
+}
@@ -1,7 +1,20 @@
 use ash::vk;
 
-use crate::{context::VulkanContext, format::Format, pipeline::attachment::Attachment};
+use crate::{
+    context::VulkanContext, format::Format, image_pool, low_latency,
+    pipeline::attachment::Attachment,
+};
 
+// Of the image headless renderers render into, in place of the swapchain ones
+pub const HEADLESS_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+const HEADLESS_UNORM_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+pub const HEADLESS_USAGE: vk::ImageUsageFlags = vk::ImageUsageFlags::from_raw(
+    vk::ImageUsageFlags::COLOR_ATTACHMENT.as_raw()
+        | vk::ImageUsageFlags::TRANSFER_SRC.as_raw()
+        | vk::ImageUsageFlags::TRANSFER_DST.as_raw(),
+);
+
+#[derive(Clone)]
 pub struct SwapchainContext {
     pub surface: vk::SurfaceKHR,
     pub surface_format: vk::SurfaceFormatKHR,
@@ -9,6 +22,22 @@ pub struct SwapchainContext {
     pub swapchain: vk::SwapchainKHR,
     pub present_mode: vk::PresentModeKHR,
     pub attachments: Vec<Attachment>,
+    // Set when the swapchain images also get UNORM views
+    pub unorm_format: Option<vk::Format>,
+}
+
+///
+/// What the swapchain ended up with, see Renderer::swapchain_capabilities.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct SwapchainCapabilities {
+    pub format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
+    // None when passes asking for a UNORM view render through the regular one
+    pub unorm_format: Option<vk::Format>,
+    pub is_mutable_format_supported: bool,
+    // Single shared presentable image, see low_latency
+    pub is_shared_present: bool,
 }
 
 impl SwapchainContext {
@@ -16,13 +45,32 @@ impl SwapchainContext {
         vulkan_context: &VulkanContext,
         surface: vk::SurfaceKHR,
         is_vsync_enabled: bool,
+        is_low_latency_overlay: bool,
     ) -> Self {
-        let present_mode = present_mode(&vulkan_context, surface, is_vsync_enabled);
+        let present_mode = present_mode(
+            vulkan_context,
+            surface,
+            is_vsync_enabled,
+            is_low_latency_overlay,
+        );
         let surface_extent = surface_extent(&vulkan_context, surface, 0, 0);
         let surface_format = surface_format(&vulkan_context, surface);
-        let swapchain = swapchain(&vulkan_context, surface, surface_extent, present_mode);
-        let swapchain_attachments =
-            attachments(&vulkan_context, surface, swapchain, surface_extent);
+        let unorm_format = unorm_format(vulkan_context, surface_format.format);
+        let swapchain = swapchain(
+            vulkan_context,
+            surface,
+            surface_extent,
+            present_mode,
+            unorm_format,
+        );
+        let swapchain_attachments = attachments(
+            vulkan_context,
+            surface,
+            swapchain,
+            surface_extent,
+            present_mode,
+            unorm_format,
+        );
         Self {
             present_mode,
             surface,
@@ -30,29 +78,182 @@ impl SwapchainContext {
             surface_format,
             swapchain,
             attachments: swapchain_attachments,
+            unorm_format,
+        }
+    }
+
+    ///
+    /// Without a surface, a single image of the extent stands in for the swapchain ones.
+    /// It's never presented, frames can be read back or copied out of it.
+    ///
+    pub fn make_headless(vulkan_context: &VulkanContext, extent: vk::Extent2D) -> Self {
+        Self {
+            present_mode: vk::PresentModeKHR::FIFO,
+            surface: vk::SurfaceKHR::null(),
+            surface_extent: extent,
+            surface_format: vk::SurfaceFormatKHR {
+                format: HEADLESS_FORMAT,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+            swapchain: vk::SwapchainKHR::null(),
+            attachments: vec![headless_attachment(vulkan_context, extent)],
+            unorm_format: Some(HEADLESS_UNORM_FORMAT),
         }
     }
 
+    pub fn is_headless(&self) -> bool {
+        self.surface == vk::SurfaceKHR::null()
+    }
+
+    pub fn capabilities(&self, ctx: &VulkanContext) -> SwapchainCapabilities {
+        SwapchainCapabilities {
+            format: self.surface_format.format,
+            color_space: self.surface_format.color_space,
+            unorm_format: self.unorm_format,
+            is_mutable_format_supported: self.is_headless()
+                || ctx.extension.is_swapchain_mutable_format_enabled,
+            is_shared_present: self.is_shared_present(),
+        }
+    }
+
+    pub fn is_shared_present(&self) -> bool {
+        is_shared(self.present_mode)
+    }
+
+    ///
+    /// Destroys the swapchain and the views of its images, keeping the surface. The
+    /// device has to be idle. Nothing can be presented until create is called.
+    ///
+    pub fn release(&mut self, ctx: &VulkanContext) {
+        self.destroy_swapchain(ctx);
+        self.swapchain = vk::SwapchainKHR::null();
+        self.attachments.clear();
+    }
+
+    pub fn is_readable(&self, ctx: &VulkanContext) -> bool {
+        if self.is_headless() {
+            return true;
+        }
+        image_usage(ctx, self.surface, self.present_mode)
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.attachments.is_empty()
+    }
+
+    ///
+    /// New swapchain for the current size of the surface after a release, width and
+    /// height are only used when the surface leaves the size up to the swapchain.
+    ///
+    pub fn create(&mut self, ctx: &VulkanContext, width: u32, height: u32) {
+        assert!(self.is_released(), "swapchain wasn't released!");
+        if self.is_headless() {
+            self.surface_extent = vk::Extent2D { width, height };
+            self.attachments = vec![headless_attachment(ctx, self.surface_extent)];
+            return;
+        }
+        self.surface_extent = surface_extent(ctx, self.surface, width, height);
+        self.swapchain = swapchain(
+            ctx,
+            self.surface,
+            self.surface_extent,
+            self.present_mode,
+            self.unorm_format,
+        );
+        self.attachments = attachments(
+            ctx,
+            self.surface,
+            self.swapchain,
+            self.surface_extent,
+            self.present_mode,
+            self.unorm_format,
+        );
+    }
+
     pub fn destroy(&self, ctx: &VulkanContext) {
+        self.destroy_swapchain(ctx);
+        if !self.is_headless() {
+            unsafe { ctx.extension.surface.destroy_surface(self.surface, None) };
+        }
+    }
+
+    pub(crate) fn destroy_swapchain(&self, ctx: &VulkanContext) {
         for att in self.attachments.iter() {
             unsafe {
                 ctx.device.destroy_image_view(att.view, None);
+                if let Some((_, view)) = att.unorm_view {
+                    ctx.device.destroy_image_view(view, None);
+                }
+            }
+        }
+        if self.is_headless() {
+            for att in self.attachments.iter() {
+                unsafe {
+                    ctx.device.destroy_image(att.image, None);
+                    ctx.device.free_memory(att.memory, None);
+                }
             }
+            return;
         }
         unsafe {
             ctx.extension
                 .swapchain
                 .destroy_swapchain(self.swapchain, None);
-            ctx.extension.surface.destroy_surface(self.surface, None);
         }
     }
 }
 
+/*
+ * Usable everywhere a swapchain image is, read back and blitted from and to.
+ */
+fn headless_attachment(ctx: &VulkanContext, extent: vk::Extent2D) -> Attachment {
+    let view_formats = [HEADLESS_FORMAT, HEADLESS_UNORM_FORMAT];
+    let mut format_list_info = vk::ImageFormatListCreateInfo::builder()
+        .view_formats(&view_formats)
+        .build();
+    let create_info = vk::ImageCreateInfo::builder()
+        .flags(vk::ImageCreateFlags::MUTABLE_FORMAT)
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(HEADLESS_FORMAT)
+        .extent(extent.into())
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(HEADLESS_USAGE)
+        .push_next(&mut format_list_info);
+    let image = unsafe { ctx.device.create_image(&create_info, None) }.unwrap();
+    let allocation =
+        image_pool::alloc_dedicated_for(ctx, image, &[vk::MemoryPropertyFlags::DEVICE_LOCAL]);
+    let make_view = |format: vk::Format| {
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(Attachment::color_subresource_range())
+            .image(image);
+        unsafe { ctx.device.create_image_view(&view_info, None) }.unwrap()
+    };
+    let view = make_view(HEADLESS_FORMAT);
+    let unorm_view = Some((HEADLESS_UNORM_FORMAT, make_view(HEADLESS_UNORM_FORMAT)));
+    ctx.try_set_debug_name("headless_image", image);
+    ctx.try_set_debug_name("headless_memory", allocation.memory);
+    ctx.try_set_debug_name("headless_view", view);
+    Attachment {
+        memory: allocation.memory,
+        memory_flags: allocation.memory_flags,
+        usage: create_info.usage,
+        ..Attachment::default_attachment_of(HEADLESS_FORMAT, image, view, unorm_view, extent)
+    }
+}
+
 pub fn attachments(
     ctx: &VulkanContext,
     surface: vk::SurfaceKHR,
     swapchain: vk::SwapchainKHR,
     surface_extent: vk::Extent2D,
+    present_mode: vk::PresentModeKHR,
+    unorm_format: Option<vk::Format>,
 ) -> Vec<Attachment> {
     let images = unsafe {
         ctx.extension
@@ -61,42 +262,48 @@ pub fn attachments(
             .unwrap()
     };
     let surface_format = surface_format(ctx, surface);
-    let image_views: Vec<vk::ImageView> = images
-        .iter()
-        .map(|&image| {
-            let create_view_info = vk::ImageViewCreateInfo::builder()
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(surface_format.format)
-                .components(vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::R,
-                    g: vk::ComponentSwizzle::G,
-                    b: vk::ComponentSwizzle::B,
-                    a: vk::ComponentSwizzle::A,
-                    ..Default::default()
-                })
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                    ..Default::default()
-                })
-                .image(image)
-                .build();
-            unsafe {
-                ctx.device
-                    .create_image_view(&create_view_info, None)
-                    .unwrap()
-            }
-        })
-        .collect();
+    let usage = image_usage(ctx, surface, present_mode);
+    let make_view = |image: vk::Image, format: vk::Format| {
+        let create_view_info = vk::ImageViewCreateInfo::builder()
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .components(vk::ComponentMapping {
+                r: vk::ComponentSwizzle::R,
+                g: vk::ComponentSwizzle::G,
+                b: vk::ComponentSwizzle::B,
+                a: vk::ComponentSwizzle::A,
+            })
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image(image)
+            .build();
+        unsafe {
+            ctx.device
+                .create_image_view(&create_view_info, None)
+                .unwrap()
+        }
+    };
     let attachments: Vec<Attachment> = images
         .into_iter()
-        .zip(image_views)
-        .map(|e| {
-            let (image, view) = e;
-            Attachment::default_attachment_of(surface_format.format, image, view, surface_extent)
+        .map(|image| {
+            let view = make_view(image, surface_format.format);
+            let unorm_view = unorm_format.map(|e| (e, make_view(image, e)));
+            Attachment {
+                usage,
+                is_shared_present: is_shared(present_mode),
+                ..Attachment::default_attachment_of(
+                    surface_format.format,
+                    image,
+                    view,
+                    unorm_view,
+                    surface_extent,
+                )
+            }
         })
         .collect();
 
@@ -108,21 +315,39 @@ pub fn swapchain(
     surface: vk::SurfaceKHR,
     surface_extent: vk::Extent2D,
     present_mode: vk::PresentModeKHR,
+    unorm_format: Option<vk::Format>,
 ) -> vk::SwapchainKHR {
     let surface_format = surface_format(ctx, surface);
-    let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
+    let view_formats: Vec<_> = std::iter::once(surface_format.format)
+        .chain(unorm_format)
+        .collect();
+    let mut format_list_info = vk::ImageFormatListCreateInfo::builder()
+        .view_formats(&view_formats)
+        .build();
+    let image_count = if is_shared(present_mode) {
+        1
+    } else {
+        desired_image_count(ctx, surface)
+    };
+    let mut swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
         .surface(surface)
-        .min_image_count(desired_image_count(ctx, surface))
+        .min_image_count(image_count)
         .image_color_space(surface_format.color_space)
         .image_format(surface_format.format)
         .image_extent(surface_extent)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage(ctx, surface, present_mode))
         .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
         .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(present_mode)
         .clipped(true)
         .image_array_layers(1);
+    if unorm_format.is_some() {
+        // Views in other formats need the images created mutable
+        swapchain_create_info = swapchain_create_info
+            .flags(vk::SwapchainCreateFlagsKHR::MUTABLE_FORMAT)
+            .push_next(&mut format_list_info);
+    }
 
     unsafe {
         ctx.extension
@@ -132,6 +357,26 @@ pub fn swapchain(
     }
 }
 
+///
+/// Transfer destination for the texture inspector overlay and scaling, source for
+/// reading back presented images where the surface allows it. Shared presentable images
+/// have their own supported usage.
+///
+pub fn image_usage(
+    ctx: &VulkanContext,
+    surface: vk::SurfaceKHR,
+    present_mode: vk::PresentModeKHR,
+) -> vk::ImageUsageFlags {
+    let supported = if is_shared(present_mode) {
+        low_latency::shared_present_usage(ctx, surface).unwrap_or_default()
+    } else {
+        surface_capabilities(ctx, surface).supported_usage_flags
+    };
+    vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | vk::ImageUsageFlags::TRANSFER_DST
+        | (supported & vk::ImageUsageFlags::TRANSFER_SRC)
+}
+
 pub fn surface_extent(
     ctx: &VulkanContext,
     surface: vk::SurfaceKHR,
@@ -165,11 +410,70 @@ pub fn surface_format(ctx: &VulkanContext, surface: vk::SurfaceKHR) -> vk::Surfa
     }
 }
 
+///
+/// UNORM format to view the sRGB swapchain images with, None if the swapchain isn't sRGB
+/// or the device can't do it.
+///
+pub fn unorm_format(ctx: &VulkanContext, format: vk::Format) -> Option<vk::Format> {
+    let unorm = match format {
+        vk::Format::B8G8R8A8_SRGB => vk::Format::B8G8R8A8_UNORM,
+        vk::Format::R8G8B8A8_SRGB => vk::Format::R8G8B8A8_UNORM,
+        vk::Format::A8B8G8R8_SRGB_PACK32 => vk::Format::A8B8G8R8_UNORM_PACK32,
+        _ if Format::of_u32(format.as_raw() as u32).is_srgb() => {
+            log::warn!(
+                "swapchain format {:?} has no UNORM counterpart, single view only",
+                format
+            );
+            return None;
+        }
+        // Already writes raw values
+        _ => return None,
+    };
+    if !ctx.extension.is_swapchain_mutable_format_enabled {
+        log::warn!("VK_KHR_swapchain_mutable_format missing, single swapchain view only");
+        return None;
+    }
+    let props = unsafe {
+        ctx.instance
+            .get_physical_device_format_properties(ctx.physical_device, unorm)
+    };
+    if !props
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT)
+    {
+        log::warn!(
+            "{:?} can't be a color attachment, single swapchain view only",
+            unorm
+        );
+        return None;
+    }
+    Some(unorm)
+}
+
+///
+/// Shared demand refresh for the low latency overlay without vsync, if the shared image
+/// can be rendered and blitted to, see low_latency. The regular modes otherwise, the
+/// overlay retains frames in those, see low_latency::RetainedFrame.
+///
 pub fn present_mode(
     ctx: &VulkanContext,
     surface: vk::SurfaceKHR,
     is_vsync_enabled: bool,
+    is_low_latency_overlay: bool,
 ) -> vk::PresentModeKHR {
+    if is_low_latency_overlay && !is_vsync_enabled {
+        let needed = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST;
+        match low_latency::shared_present_usage(ctx, surface) {
+            Some(e) if e.contains(needed) => return vk::PresentModeKHR::SHARED_DEMAND_REFRESH,
+            Some(e) => log::warn!(
+                "shared presentable images only support {:?}, low latency overlay retains frames",
+                e
+            ),
+            None => {
+                log::warn!("shared presentation unsupported, low latency overlay retains frames")
+            }
+        }
+    }
     let present_modes = unsafe {
         ctx.extension
             .surface
@@ -190,6 +494,10 @@ pub fn present_mode(
         })
 }
 
+fn is_shared(present_mode: vk::PresentModeKHR) -> bool {
+    present_mode == vk::PresentModeKHR::SHARED_DEMAND_REFRESH
+}
+
 pub fn desired_image_count(ctx: &VulkanContext, surface: vk::SurfaceKHR) -> u32 {
     let surface_caps = surface_capabilities(ctx, surface);
     let desired_image_count = surface_caps.min_image_count + 1;
@@ -0,0 +1,40 @@
+use std::sync::{Arc, Mutex};
+
+use crate::render_task::RenderTask;
+
+///
+/// Queues tasks from other threads, made with Renderer::task_sender. Sent tasks go
+/// through the same checks and budgets as add_task_to_queue once the next frame gets
+/// prepared, rejections only show up in the frame stats.
+///
+#[derive(Clone)]
+pub struct TaskSender {
+    tasks: Arc<Mutex<Vec<RenderTask>>>,
+}
+
+impl TaskSender {
+    pub(crate) fn new() -> Self {
+        Self {
+            tasks: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn send(&self, task: RenderTask) {
+        self.lock().push(task);
+    }
+
+    pub fn send_all(&self, tasks: impl IntoIterator<Item = RenderTask>) {
+        self.lock().extend(tasks);
+    }
+
+    ///
+    /// Tasks sent since the last call, in the order they were sent.
+    ///
+    pub(crate) fn take(&self) -> Vec<RenderTask> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<RenderTask>> {
+        self.tasks.lock().expect("task sender lock poisoned!")
+    }
+}
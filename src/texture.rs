@@ -1,6 +1,20 @@
 use ash::vk;
 
-use crate::{buffer::DeviceSlice, context::VulkanContext};
+use crate::{
+    buffer::DeviceSlice,
+    context::VulkanContext,
+    format::Format,
+    handle::{StaleHandle, TextureHandle},
+    image_pool::{self, ImageAllocation, ImagePool},
+    offscreen::OffscreenImage,
+    sparse::{self, PageBinding, SparsePages},
+};
+
+///
+/// Texels of a mip, see Renderer::update_texture_region. Same as the regions sparse
+/// textures get pages bound and uploaded by, without having to be on their page grid.
+///
+pub type TextureRegion = PageBinding;
 
 #[derive(Clone)]
 pub struct Texture {
@@ -8,12 +22,109 @@ pub struct Texture {
     pub format: crate::format::Format,
     pub mip_maps: Vec<MipMap>,
     pub name: String,
-    pub memory: vk::DeviceMemory,
+    pub allocation: ImageAllocation,
     pub image: vk::Image,
     pub view: vk::ImageView,
     pub staging: Option<Box<DeviceSlice>>,
+    // Regions the staging buffer holds, tightly packed, the whole texture when empty
+    pub staged_regions: Vec<TextureRegion>,
+    // Shared by several queue families, ownership never changes
+    pub is_concurrent: bool,
+    // Array layers, viewed as a 2D array when more than one
+    pub layers: u32,
+    // Six layers viewed as a cube, see Renderer::gen_texture_cube
+    pub is_cube: bool,
+    // Pages and their memory of textures made by gen_texture_sparse
+    pub sparse: Option<Box<SparsePages>>,
+    // Pool key and depth image of textures made by render_to_texture
+    pub offscreen: Option<Box<OffscreenImage>>,
+    // Second slot and view of textures made by gen_texture_dual_view
+    pub srgb: Option<Box<SrgbView>>,
+}
+
+///
+/// sRGB side of a texture made by Renderer::gen_texture_dual_view, its own descriptor
+/// slot sampling the same image through a view with the sRGB format of the pair.
+///
+#[derive(Clone)]
+pub struct SrgbView {
+    pub id: u32,
+    pub view: vk::ImageView,
+    // Ids of the pair not freed yet, the image goes once none is left
+    pub refs: u32,
+}
+
+///
+/// Ids of a texture made by Renderer::gen_texture_dual_view. Materials holding the
+/// linear one sample the texels as they are, the sRGB one decodes them to linear.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DualViewTexture {
+    pub linear_id: TextureHandle,
+    pub srgb_id: TextureHandle,
+}
+
+///
+/// Returned when a dual view texture is asked for with a format that has no sRGB
+/// sibling, see Format::srgb_sibling.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoSrgbPair {
+    pub format: Format,
+}
+
+impl std::fmt::Display for NoSrgbPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} has no sRGB pair to view it as both", self.format)
+    }
+}
+
+impl std::error::Error for NoSrgbPair {}
+
+///
+/// Why Renderer::reimport_texture didn't replace the contents of a texture.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReimportError {
+    StaleHandle,
+    // Sparse and offscreen textures get their contents some other way
+    Sparse,
+    Offscreen,
+    // The texture is a dual view one, see gen_texture_dual_view
+    NoSrgbPair(NoSrgbPair),
+}
+
+impl std::fmt::Display for ReimportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StaleHandle => StaleHandle.fmt(f),
+            Self::Sparse => write!(f, "sparse textures get their pages uploaded instead"),
+            Self::Offscreen => write!(f, "offscreen textures get rendered to instead"),
+            Self::NoSrgbPair(e) => e.fmt(f),
+        }
+    }
 }
 
+impl std::error::Error for ReimportError {}
+
+///
+/// Where the image data of a texture is, see Renderer::texture_residency.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum Residency {
+    Resident,
+    // Image memory released, shaders sample the default texture in its place
+    Evicted,
+    // Staging data not on the image yet
+    Uploading,
+}
+
+///
+/// Fills the staging buffer of an evicted texture being brought back, in the layout
+/// passed to gen_texture.
+///
+pub type TextureRestorer = dyn FnMut(&Texture, &mut [u8]) + Send;
+
 #[derive(Clone, Debug)]
 pub struct MipMap {
     pub index: u32,
@@ -45,7 +156,27 @@ impl Default for MipMap {
 
 impl Texture {
     pub fn is_uploaded(&self) -> bool {
-        self.staging.is_none()
+        self.residency() == Residency::Resident
+    }
+
+    ///
+    /// Evicted textures keep their metadata but have no image.
+    ///
+    pub fn is_evicted(&self) -> bool {
+        self.image == vk::Image::null()
+    }
+
+    pub fn residency(&self) -> Residency {
+        if self.is_evicted() {
+            Residency::Evicted
+        } else if self.staging.is_some()
+            || self.sparse.as_ref().is_some_and(|e| !e.is_initialized)
+            || self.offscreen.as_ref().is_some_and(|e| !e.is_rendered)
+        {
+            Residency::Uploading
+        } else {
+            Residency::Resident
+        }
     }
 
     pub fn mip_map_count(&self) -> u32 {
@@ -71,17 +202,19 @@ impl Texture {
         self.mip_maps.iter().map(|e| e.size).sum()
     }
 
-    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
         vk::ImageSubresourceRange {
             base_mip_level: 0,
             aspect_mask: self.format.aspect(),
             level_count: self.mip_map_count(),
-            layer_count: 1,
+            layer_count: self.layers,
             ..Default::default()
         }
     }
 
     pub fn buffer_copy_regions(&self, offset: u64) -> Vec<vk::BufferImageCopy> {
+        // Faces of a mip follow each other, the size of the mip covers all of them
+        let layer_count = if self.is_cube { self.layers } else { 1 };
         self.mip_maps
             .iter()
             .map(|mm| {
@@ -89,7 +222,7 @@ impl Texture {
                     .image_subresource(
                         vk::ImageSubresourceLayers::builder()
                             .aspect_mask(self.format.aspect())
-                            .layer_count(1)
+                            .layer_count(layer_count)
                             .mip_level(mm.index)
                             .build(),
                     )
@@ -118,13 +251,42 @@ impl Texture {
         };
     }
 
+    ///
+    /// Copies the staging buffer into the image and leaves it ready for sampling. Sparse
+    /// textures and textures with staged regions only get those copied, keeping what
+    /// earlier uploads wrote.
+    ///
     pub fn transition_to_optimal(&self, ctx: &VulkanContext, cmd_buffer: vk::CommandBuffer) {
+        let image_slice = self.staging.as_ref().unwrap();
+        let buffer_copy_regions = match &self.sparse {
+            Some(sparse) => sparse.staged_copy_regions(self.format, image_slice.offset),
+            None if !self.staged_regions.is_empty() => {
+                sparse::copy_regions(self.format, &self.staged_regions, image_slice.offset)
+            }
+            None => self.buffer_copy_regions(image_slice.offset),
+        };
+        let is_initialized = self
+            .sparse
+            .as_ref()
+            .map_or(!self.staged_regions.is_empty(), |e| e.is_initialized);
+        // Earlier frames may still be sampling what the copy overwrites
+        let (old_layout, src_stage) = if is_initialized {
+            (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            )
+        } else {
+            (
+                vk::ImageLayout::UNDEFINED,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+            )
+        };
         let barrier_initial = vk::ImageMemoryBarrier {
             image: self.image,
             subresource_range: self.subresource_range(),
             src_access_mask: vk::AccessFlags::empty(),
             dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
-            old_layout: vk::ImageLayout::UNDEFINED,
+            old_layout,
             new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
             ..Default::default()
         };
@@ -137,12 +299,10 @@ impl Texture {
             new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             ..Default::default()
         };
-        let image_slice = self.staging.as_ref().unwrap();
-        let buffer_copy_regions = self.buffer_copy_regions(image_slice.offset);
         unsafe {
             ctx.device.cmd_pipeline_barrier(
                 cmd_buffer,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
+                src_stage,
                 vk::PipelineStageFlags::TRANSFER,
                 vk::DependencyFlags::empty(),
                 &[],
@@ -172,6 +332,135 @@ impl Texture {
         };
     }
 
+    ///
+    /// Same as transition_to_optimal but meant for a queue without graphics support.
+    /// With ownership, (src, dst) queue families, the final barrier releases the image to
+    /// dst, which then has to record acquire_from with the same families.
+    ///
+    pub fn upload_on_async_queue(
+        &self,
+        ctx: &VulkanContext,
+        cmd_buffer: vk::CommandBuffer,
+        ownership: Option<(u32, u32)>,
+    ) {
+        let (src_family, dst_family) =
+            ownership.unwrap_or((vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED));
+        let barrier_initial = vk::ImageMemoryBarrier {
+            image: self.image,
+            subresource_range: self.subresource_range(),
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ..Default::default()
+        };
+        // Fragment stages don't exist on this queue, the semaphore makes the writes visible
+        let barrier_end = vk::ImageMemoryBarrier {
+            image: self.image,
+            subresource_range: self.subresource_range(),
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_queue_family_index: src_family,
+            dst_queue_family_index: dst_family,
+            ..Default::default()
+        };
+        let image_slice = self.staging.as_ref().unwrap();
+        let buffer_copy_regions = self.buffer_copy_regions(image_slice.offset);
+        unsafe {
+            ctx.device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier_initial],
+            );
+            ctx.device.cmd_copy_buffer_to_image(
+                cmd_buffer,
+                image_slice.buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &buffer_copy_regions,
+            );
+            ctx.device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier_end],
+            );
+        }
+    }
+
+    ///
+    /// Takes ownership of an image released by upload_on_async_queue, has to execute
+    /// after the release, ie, wait on a semaphore the upload signals.
+    ///
+    pub fn acquire_from(
+        &self,
+        ctx: &VulkanContext,
+        cmd_buffer: vk::CommandBuffer,
+        src_family: u32,
+        dst_family: u32,
+    ) {
+        let barrier = vk::ImageMemoryBarrier {
+            image: self.image,
+            subresource_range: self.subresource_range(),
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_queue_family_index: src_family,
+            dst_queue_family_index: dst_family,
+            ..Default::default()
+        };
+        unsafe {
+            ctx.device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        };
+    }
+
+    ///
+    /// Destroys the image and its view and returns the memory. Staging buffer, if any,
+    /// isn't freed here, nor are the pages of sparse textures. Depth images of offscreen
+    /// textures go along with them.
+    ///
+    pub fn destroy(&self, device: &ash::Device, mut pool: Option<&mut ImagePool>) {
+        if self.is_evicted() {
+            // Image went away on eviction
+            return;
+        }
+        if let Some(depth) = self.offscreen.as_ref().and_then(|e| e.depth.as_ref()) {
+            depth.destroy(device, pool.as_deref_mut());
+        }
+        unsafe {
+            if let Some(srgb) = &self.srgb {
+                device.destroy_image_view(srgb.view, None);
+            }
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+        }
+        if self.sparse.is_some() {
+            return;
+        }
+        match pool {
+            Some(pool) => pool.free(device, self.allocation),
+            None => unsafe { device.free_memory(self.allocation.memory, None) },
+        }
+    }
+
     pub fn read_staging(&self) -> Vec<u8> {
         if let Some(device) = &self.staging {
             let slice = unsafe {
@@ -184,18 +473,85 @@ impl Texture {
     }
 }
 
+///
+/// What make creates a texture from.
+///
+pub struct TextureDesc<'a> {
+    pub id: u32,
+    pub name: String,
+    pub mip_maps: &'a [MipMap],
+    pub layers: u32,
+    // Viewed as a cube, layers has to be 6
+    pub is_cube: bool,
+    pub format: crate::format::Format,
+    // None for sampled textures
+    pub attachment_usage: Option<vk::ImageUsageFlags>,
+    // Written by compute shaders on top of being sampled
+    pub is_storage: bool,
+    pub is_transient: bool,
+    pub shared_with: &'a [u32],
+    pub srgb_id: Option<u32>,
+}
+
+///
+/// Texture memory gets suballocated from the pool when there is one, otherwise the
+/// image gets a dedicated allocation. The image is shared concurrently by the queue
+/// families in shared_with if there is more than one. Cube textures get one view of
+/// their six layers as a cube, their copies cover every face. Storage textures can be
+/// written by compute shaders on top of being sampled. Attachments come with the usage
+/// their image gets, see Pipeline::attachment_usages. With an sRGB id, the image gets
+/// a mutable format and a second view with the sRGB sibling of the format, which has to
+/// be the UNORM one of its pair.
+///
 pub fn make(
     ctx: &VulkanContext,
-    id: u32,
-    name: String,
-    mip_maps: &[MipMap],
-    format: crate::format::Format,
-    is_attachment: bool,
+    pool: Option<&mut ImagePool>,
+    desc: TextureDesc,
     staging: Option<Box<DeviceSlice>>,
 ) -> Texture {
+    let TextureDesc {
+        id,
+        name,
+        mip_maps,
+        layers,
+        is_cube,
+        format,
+        attachment_usage,
+        is_storage,
+        is_transient,
+        shared_with,
+        srgb_id,
+    } = desc;
     assert!(!mip_maps.is_empty(), "mip_maps can't be empty!");
+    assert!(
+        !is_cube || layers == 6,
+        "cube textures need 6 layers, {} has {}!",
+        name,
+        layers
+    );
     let vk_format = format.to_vk();
+    let storage_usage = if is_storage {
+        vk::ImageUsageFlags::STORAGE
+    } else {
+        vk::ImageUsageFlags::empty()
+    };
+    let srgb_format = srgb_id.map(|_| match format.srgb_sibling() {
+        Some(e) if !format.is_srgb() => e,
+        _ => panic!("{} isn't the UNORM format of an sRGB pair!", format),
+    });
+    // Only ever viewed as the formats of the pair, which keeps compression possible
+    let view_formats = [vk_format, srgb_format.map_or(vk_format, |e| e.to_vk())];
+    let format_list = vk::ImageFormatListCreateInfo {
+        view_format_count: view_formats.len() as u32,
+        p_view_formats: view_formats.as_ptr(),
+        ..Default::default()
+    };
     let create_info = vk::ImageCreateInfo {
+        flags: if is_cube {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        },
         image_type: vk::ImageType::TYPE_2D,
         format: vk_format,
         extent: vk::Extent2D {
@@ -204,76 +560,83 @@ pub fn make(
         }
         .into(),
         mip_levels: mip_maps.len() as u32,
-        array_layers: 1,
+        array_layers: layers,
         samples: vk::SampleCountFlags::TYPE_1,
         tiling: vk::ImageTiling::OPTIMAL,
-        usage: if is_attachment {
-            (if format.has_depth() {
-                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
-            } else {
-                vk::ImageUsageFlags::COLOR_ATTACHMENT
-            }) | vk::ImageUsageFlags::SAMPLED
+        usage: if let Some(usage) = attachment_usage {
+            usage
         } else {
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED
-        },
-        sharing_mode: vk::SharingMode::EXCLUSIVE,
+            // Source of the texture inspector copies too
+            let usage = vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::SAMPLED;
+            // Any of these can be set as shading rate image of a stage
+            if format == crate::format::Format::R8_UINT
+                && ctx.extension.shading_rate_texel_size.is_some()
+            {
+                usage | vk::ImageUsageFlags::FRAGMENT_SHADING_RATE_ATTACHMENT_KHR
+            } else {
+                usage
+            }
+        } | storage_usage,
         ..Default::default()
     };
-    let image = unsafe { ctx.device.create_image(&create_info, None) }.unwrap();
-    let mut dedicated_req = vk::MemoryDedicatedRequirements {
-        ..Default::default()
+    let create_info = if srgb_format.is_some() {
+        vk::ImageCreateInfo {
+            flags: create_info.flags | vk::ImageCreateFlags::MUTABLE_FORMAT,
+            p_next: &format_list as *const _ as *const std::ffi::c_void,
+            ..create_info
+        }
+    } else {
+        create_info
     };
-    let mut memory_req = vk::MemoryRequirements2::builder()
-        .push_next(&mut dedicated_req)
-        .build();
-
-    let requirements_info = vk::ImageMemoryRequirementsInfo2::builder()
-        .image(image)
-        .build();
-    unsafe {
-        ctx.device
-            .get_image_memory_requirements2(&requirements_info, &mut memory_req)
+    let is_concurrent = shared_with.len() > 1;
+    let create_info = if is_concurrent {
+        vk::ImageCreateInfo {
+            sharing_mode: vk::SharingMode::CONCURRENT,
+            queue_family_index_count: shared_with.len() as u32,
+            p_queue_family_indices: shared_with.as_ptr(),
+            ..create_info
+        }
+    } else {
+        // Default sharing mode is exclusive
+        create_info
     };
-
-    let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::builder()
-        .image(image)
-        .build();
-
-    let memory_allocate_info = vk::MemoryAllocateInfo::builder()
-        .push_next(&mut dedicated_info)
-        .allocation_size(memory_req.memory_requirements.size)
-        .memory_type_index(
-            ctx.memory_type_index_for(
-                memory_req.memory_requirements.memory_type_bits,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            )
-            .unwrap(),
-        )
-        .build();
-
-    let memory = unsafe {
-        ctx.device
-            .allocate_memory(&memory_allocate_info, None)
-            .expect("failed image memory alloc")
+    let image = unsafe { ctx.device.create_image(&create_info, None) }.unwrap();
+    use vk::MemoryPropertyFlags as Mpf;
+    let preferred_memory_flags: &[Mpf] = if is_transient {
+        &[Mpf::LAZILY_ALLOCATED | Mpf::DEVICE_LOCAL, Mpf::DEVICE_LOCAL]
+    } else {
+        &[Mpf::DEVICE_LOCAL]
     };
-
-    unsafe {
-        ctx.device
-            .bind_image_memory(image, memory, 0)
-            .expect("failed image memory bind")
+    let allocation = match pool {
+        Some(pool) => pool.alloc(ctx, image, preferred_memory_flags),
+        None => image_pool::alloc_dedicated_for(ctx, image, preferred_memory_flags),
     };
+    if is_transient && !allocation.memory_flags.contains(Mpf::LAZILY_ALLOCATED) {
+        log::info!(
+            "no lazily allocated memory type available for {}, falling back to device local memory",
+            name
+        );
+    }
 
     let image_view_info = vk::ImageViewCreateInfo::builder()
         .subresource_range(
             vk::ImageSubresourceRange::builder()
                 .aspect_mask(format.aspect())
                 .level_count(mip_maps.len() as u32)
-                .layer_count(1)
+                .layer_count(layers)
                 .build(),
         )
         .image(image)
         .format(vk_format)
-        .view_type(vk::ImageViewType::TYPE_2D);
+        .view_type(if is_cube {
+            vk::ImageViewType::CUBE
+        } else if layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        });
 
     ctx.try_set_debug_name(&name, image);
 
@@ -282,14 +645,30 @@ pub fn make(
             .create_image_view(&image_view_info, None)
             .expect("failed image view")
     };
+    let srgb = srgb_id.zip(srgb_format).map(|(id, srgb_format)| {
+        let image_view_info = image_view_info.format(srgb_format.to_vk());
+        let view = unsafe {
+            ctx.device
+                .create_image_view(&image_view_info, None)
+                .expect("failed image view")
+        };
+        Box::new(SrgbView { id, view, refs: 2 })
+    });
     Texture {
         name,
         id,
         mip_maps: mip_maps.to_vec(),
-        memory,
+        allocation,
         format,
         image,
         view,
         staging,
+        staged_regions: Vec::new(),
+        is_concurrent,
+        layers,
+        is_cube,
+        sparse: None,
+        offscreen: None,
+        srgb,
     }
 }
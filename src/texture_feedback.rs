@@ -0,0 +1,217 @@
+/*
+ * Coarse visibility feedback for texture streaming, kept when
+ * RendererConfig::is_texture_feedback_enabled is set. Stages listing the textureFeedback
+ * per draw field get the address of a buffer with a u32 per slot of the image table, their
+ * shaders atomicMin how many texels a pixel covers where they sample into the slot of the
+ * texture id through WRITE_TEXTURE_FEEDBACK:
+ *
+ *   WRITE_TEXTURE_FEEDBACK(mat.diffuseId, texCoord);
+ *
+ * The buffer gets filled with NOT_SAMPLED before the first stage of every frame and read
+ * back through the readback ring after the last one, so what the renderer goes by lags
+ * the frames in flight behind.
+ *
+ * Shaders write the floor of the log2 of the footprint over a 1x1 image, plus
+ * FOOTPRINT_BIAS. It doesn't depend on the image bound to the id, textures still
+ * uploading or evicted report what they'll need rather than the default texture they
+ * show meanwhile. decode turns it into a mip with the extent of the texture.
+ */
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use crate::buffer::DeviceSlice;
+use crate::context::VulkanContext;
+use crate::readback::ReadbackRequest;
+use crate::renderer::Renderer;
+
+///
+/// Min mip of texture ids no shader sampled in the frame.
+///
+pub const NOT_SAMPLED: u32 = u32::MAX;
+// Bytes of the slot of each texture id
+pub const ENTRY_SIZE: u64 = 4;
+// TEXTURE_FEEDBACK_BIAS of the shaders
+pub const FOOTPRINT_BIAS: u32 = 32;
+
+///
+/// What the last read back frame sampled of a live texture id. min_mip is NOT_SAMPLED
+/// unless is_sampled.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeedbackEntry {
+    pub texture: u32,
+    pub min_mip: u32,
+    pub is_sampled: bool,
+}
+
+pub struct TextureFeedback {
+    buffer: DeviceSlice,
+    // Oldest first, one per frame that cleared the buffer
+    pending: VecDeque<ReadbackRequest>,
+    // By texture id
+    entries: Vec<FeedbackEntry>,
+}
+
+impl TextureFeedback {
+    pub fn new(buffer: DeviceSlice) -> Self {
+        Self {
+            buffer,
+            pending: VecDeque::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn buffer(&self) -> &DeviceSlice {
+        &self.buffer
+    }
+
+    pub fn device_addr(&self) -> u64 {
+        self.buffer.device_addr
+    }
+
+    ///
+    /// Fills the buffer with NOT_SAMPLED, ordered after whatever the previous frame read
+    /// or wrote of it and before the stages of this one.
+    ///
+    pub fn record_clear(&self, ctx: &VulkanContext, cmd: vk::CommandBuffer) {
+        let barrier = |src_stage, src_access, dst_stage, dst_access| {
+            let barriers = [vk::MemoryBarrier2::builder()
+                .src_stage_mask(src_stage)
+                .src_access_mask(src_access)
+                .dst_stage_mask(dst_stage)
+                .dst_access_mask(dst_access)
+                .build()];
+            let dep_info = vk::DependencyInfo::builder()
+                .memory_barriers(&barriers)
+                .build();
+            unsafe { ctx.device.cmd_pipeline_barrier2(cmd, &dep_info) };
+        };
+        barrier(
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+            vk::PipelineStageFlags2::CLEAR,
+            vk::AccessFlags2::TRANSFER_WRITE,
+        );
+        unsafe {
+            ctx.device.cmd_fill_buffer(
+                cmd,
+                self.buffer.buffer,
+                self.buffer.offset,
+                self.buffer.size,
+                NOT_SAMPLED,
+            )
+        };
+        // Stages write through the buffer address, which counts as any shader access
+        barrier(
+            vk::PipelineStageFlags2::CLEAR,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        );
+    }
+
+    ///
+    /// Readback of the buffer in the frame that cleared it.
+    ///
+    pub fn push(&mut self, request: ReadbackRequest) {
+        self.pending.push_back(request);
+    }
+
+    ///
+    /// Takes the newest frame the GPU is done with, if any got done since the last call.
+    /// See decode for extent_of.
+    ///
+    pub fn resolve(
+        &mut self,
+        renderer: &Renderer,
+        extent_of: impl Fn(u32) -> Option<TextureExtent>,
+    ) {
+        let mut newest = None;
+        while let Some(bytes) = self
+            .pending
+            .front_mut()
+            .and_then(|e| e.try_resolve(renderer))
+        {
+            self.pending.pop_front();
+            newest = Some(bytes);
+        }
+        if let Some(bytes) = newest {
+            self.entries = decode(&bytes, extent_of);
+        }
+    }
+
+    pub fn entries(&self) -> &[FeedbackEntry] {
+        &self.entries
+    }
+
+    ///
+    /// None if the id wasn't live in the last read back frame.
+    ///
+    pub fn entry(&self, texture: u32) -> Option<&FeedbackEntry> {
+        self.entries
+            .binary_search_by_key(&texture, |e| e.texture)
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    ///
+    /// Whether shaders sampled the id in the last read back frame. Ids nothing was read
+    /// back for yet count as sampled, so they aren't the first to go.
+    ///
+    pub fn is_sampled(&self, texture: u32) -> bool {
+        self.entry(texture).is_none_or(|e| e.is_sampled)
+    }
+
+    ///
+    /// Sort key putting the ids sampled at the finest mips first, unsampled ones last.
+    ///
+    pub fn priority_of(&self, texture: u32) -> u32 {
+        self.entry(texture).map_or(NOT_SAMPLED, |e| e.min_mip)
+    }
+}
+
+///
+/// What decode needs of the texture behind an id.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureExtent {
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+}
+
+///
+/// Entries of the ids in the bytes of a read back buffer, a native endian u32 per id.
+/// Ids extent_of has no texture for, like free slots or attachments, are left out. The
+/// mips are clamped to the ones the texture has.
+///
+pub fn decode(
+    bytes: &[u8],
+    extent_of: impl Fn(u32) -> Option<TextureExtent>,
+) -> Vec<FeedbackEntry> {
+    bytes
+        .chunks_exact(ENTRY_SIZE as usize)
+        .enumerate()
+        .filter_map(|(i, e)| {
+            let texture = i as u32;
+            let footprint = u32::from_ne_bytes(e.try_into().unwrap());
+            let extent = extent_of(texture)?;
+            let is_sampled = footprint != NOT_SAMPLED;
+            let min_mip = if is_sampled {
+                // Texels per pixel over the whole image, along its largest side
+                let side = extent.width.max(extent.height).max(1).ilog2();
+                (footprint + side)
+                    .saturating_sub(FOOTPRINT_BIAS)
+                    .min(extent.mip_levels.max(1) - 1)
+            } else {
+                NOT_SAMPLED
+            };
+            Some(FeedbackEntry {
+                texture,
+                min_mip,
+                is_sampled,
+            })
+        })
+        .collect()
+}
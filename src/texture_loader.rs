@@ -0,0 +1,357 @@
+/*
+ * Texture creation from threads other than the one owning the renderer. Loaders allocate
+ * staging memory out of a buffer of their own, never touching the allocators of the
+ * renderer, and queue the rest. The renderer creates the images and places their
+ * descriptors when it prepares the next frame or flushes, then uploads each texture
+ * once its loader is done filling the staging memory.
+ *
+ * Closing the loader when the renderer gets destroyed takes the write side of the
+ * RwLock, staging writes hold the read side, so no loader writes into the buffer while
+ * it goes away nor after.
+ */
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex, MutexGuard, RwLock,
+};
+use std::time::Duration;
+
+use ash::vk;
+
+use crate::buffer::{DeviceAllocator, DeviceSlice};
+use crate::format::Format;
+use crate::handle::TextureHandle;
+use crate::texture::MipMap;
+
+///
+/// Why a PendingTexture resolved without a texture.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CreateTextureError {
+    // The staging buffer of the loaders has no room for it right now
+    OutOfStaging { size: u64, available: u64 },
+    OutOfTextureIds,
+    // The device can't sample images of the format or copy into them
+    UnsupportedFormat(Format),
+    // Renderer destroyed before creating it
+    Destroyed,
+}
+
+impl std::fmt::Display for CreateTextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfStaging { size, available } => write!(
+                f,
+                "loader staging can't fit {} bytes, {} are available",
+                size, available
+            ),
+            Self::OutOfTextureIds => write!(f, "ran out of texture ids"),
+            Self::UnsupportedFormat(format) => {
+                write!(f, "textures of format {} aren't supported", format)
+            }
+            Self::Destroyed => write!(f, "the renderer was destroyed"),
+        }
+    }
+}
+
+impl std::error::Error for CreateTextureError {}
+
+type Outcome = Result<TextureHandle, CreateTextureError>;
+
+struct Slot {
+    outcome: Mutex<Option<Outcome>>,
+    resolved: Condvar,
+    // Set by the loader once it's done writing the staging memory
+    is_filled: AtomicBool,
+}
+
+impl Slot {
+    fn new(outcome: Option<Outcome>) -> Arc<Self> {
+        Arc::new(Self {
+            outcome: Mutex::new(outcome),
+            resolved: Condvar::new(),
+            is_filled: AtomicBool::new(false),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Outcome>> {
+        self.outcome.lock().expect("pending texture lock poisoned!")
+    }
+}
+
+pub(crate) struct QueuedTexture {
+    pub name: String,
+    pub format: Format,
+    pub mip_maps: Vec<MipMap>,
+    pub staging: Option<DeviceSlice>,
+    slot: Arc<Slot>,
+}
+
+impl QueuedTexture {
+    // Dropped by the loader, nobody would ever see the texture
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.slot) == 1
+    }
+
+    ///
+    /// Staging memory still in it goes with the LoadingTexture, to be freed once the
+    /// loader is done writing it.
+    ///
+    pub fn resolve(self, outcome: Outcome) -> LoadingTexture {
+        *self.slot.lock() = Some(outcome);
+        self.slot.resolved.notify_all();
+        LoadingTexture {
+            handle: outcome.ok(),
+            staging: self.staging,
+            slot: self.slot,
+        }
+    }
+}
+
+///
+/// Resolved texture of a loader, waiting for it to be done writing the staging memory.
+///
+pub(crate) struct LoadingTexture {
+    // None if it failed
+    pub handle: Option<TextureHandle>,
+    // Of failed ones, no texture took it
+    pub staging: Option<DeviceSlice>,
+    slot: Arc<Slot>,
+}
+
+impl LoadingTexture {
+    pub fn is_filled(&self) -> bool {
+        self.slot.is_filled.load(Ordering::Acquire)
+    }
+
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.slot) == 1
+    }
+}
+
+struct Shared {
+    // None once the renderer is destroyed
+    staging: Mutex<Option<DeviceAllocator>>,
+    buffer: vk::Buffer,
+    queue: Mutex<Vec<QueuedTexture>>,
+    // Whether the staging buffer is still there, see the comment at the top
+    is_open: RwLock<bool>,
+}
+
+///
+/// Creates textures from any thread, made with Renderer::texture_loader. Staging memory
+/// comes out of a buffer only loaders allocate from, of RendererConfig::
+/// loader_staging_bytes, freed once the texture is uploaded. Creating the image and
+/// placing its descriptor waits for the renderer to prepare the next frame or to flush,
+/// see PendingTexture.
+///
+#[derive(Clone)]
+pub struct TextureLoader {
+    shared: Arc<Shared>,
+}
+
+impl TextureLoader {
+    pub(crate) fn new(staging: DeviceAllocator) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                buffer: staging.buffer.buffer,
+                staging: Mutex::new(Some(staging)),
+                queue: Mutex::new(Vec::new()),
+                is_open: RwLock::new(true),
+            }),
+        }
+    }
+
+    ///
+    /// Same as Renderer::gen_texture, with the staging memory ready to be written right
+    /// away through the PendingTexture. The texture gets created by the renderer and
+    /// uploaded once queue_for_uploading was called on the PendingTexture. Failures
+    /// resolve it with the error instead, running out of staging memory right away.
+    ///
+    pub fn create_texture(
+        &self,
+        name: String,
+        format: Format,
+        mip_maps: &[MipMap],
+        staging_size: u32,
+    ) -> PendingTexture {
+        let staging = if staging_size > 0 {
+            let size = staging_size as u64;
+            match &*self.lock_staging() {
+                Some(allocator) => match allocator.alloc(size) {
+                    Some(e) => Some(e),
+                    None => {
+                        let available = allocator.available();
+                        return self.failed(CreateTextureError::OutOfStaging { size, available });
+                    }
+                },
+                None => return self.failed(CreateTextureError::Destroyed),
+            }
+        } else {
+            None
+        };
+        let slot = Slot::new(None);
+        let mut queue = self.lock_queue();
+        // Closed meanwhile, the queue was failed already
+        if self.lock_staging().is_none() {
+            return self.failed(CreateTextureError::Destroyed);
+        }
+        queue.push(QueuedTexture {
+            name,
+            format,
+            mip_maps: mip_maps.to_vec(),
+            staging,
+            slot: slot.clone(),
+        });
+        PendingTexture {
+            slot,
+            staging,
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn failed(&self, error: CreateTextureError) -> PendingTexture {
+        PendingTexture {
+            slot: Slot::new(Some(Err(error))),
+            staging: None,
+            shared: self.shared.clone(),
+        }
+    }
+
+    ///
+    /// Textures queued since the last call, in the order they were queued.
+    ///
+    pub(crate) fn take(&self) -> Vec<QueuedTexture> {
+        std::mem::take(&mut *self.lock_queue())
+    }
+
+    pub(crate) fn is_staging_of(&self, slice: &DeviceSlice) -> bool {
+        slice.buffer == self.shared.buffer
+    }
+
+    pub(crate) fn free_staging(&self, slice: DeviceSlice) {
+        if let Some(allocator) = &*self.lock_staging() {
+            allocator.free(slice);
+        }
+    }
+
+    ///
+    /// Fails whatever is still queued and destroys the staging buffer, once no loader
+    /// writes into it anymore.
+    ///
+    pub(crate) fn close(&self, device: &ash::Device) {
+        let mut is_open = self
+            .shared
+            .is_open
+            .write()
+            .expect("texture loader lock poisoned!");
+        *is_open = false;
+        let staging = self.lock_staging().take();
+        for e in self.take() {
+            // Its staging memory goes along with the buffer
+            e.resolve(Err(CreateTextureError::Destroyed));
+        }
+        if let Some(staging) = staging {
+            staging.destroy(device);
+        }
+    }
+
+    fn lock_staging(&self) -> MutexGuard<'_, Option<DeviceAllocator>> {
+        self.shared
+            .staging
+            .lock()
+            .expect("loader staging lock poisoned!")
+    }
+
+    fn lock_queue(&self) -> MutexGuard<'_, Vec<QueuedTexture>> {
+        self.shared
+            .queue
+            .lock()
+            .expect("texture loader lock poisoned!")
+    }
+}
+
+///
+/// Texture queued by a TextureLoader. Write the staging memory, from any thread, then
+/// call queue_for_uploading. Dropping it before the texture got created drops the
+/// texture, dropping it before queue_for_uploading frees the texture. Failed textures
+/// keep their staging memory until dropped or queued for uploading, writing it does no
+/// harm. Don't free the texture before queue_for_uploading, the staging memory goes
+/// with it.
+///
+pub struct PendingTexture {
+    slot: Arc<Slot>,
+    staging: Option<DeviceSlice>,
+    shared: Arc<Shared>,
+}
+
+impl PendingTexture {
+    ///
+    /// The texture once the renderer created it, or why it didn't. None until then.
+    ///
+    pub fn id(&self) -> Option<Result<TextureHandle, CreateTextureError>> {
+        *self.slot.lock()
+    }
+
+    ///
+    /// Same as id, waiting up to the timeout for the renderer to get to it. None if it
+    /// didn't in time.
+    ///
+    pub fn wait(&self, timeout: Duration) -> Option<Result<TextureHandle, CreateTextureError>> {
+        let outcome = self.slot.lock();
+        let (outcome, _) = self
+            .slot
+            .resolved
+            .wait_timeout_while(outcome, timeout, |e| e.is_none())
+            .expect("pending texture lock poisoned!");
+        *outcome
+    }
+
+    pub fn staging_size(&self) -> u64 {
+        self.staging.map_or(0, |e| e.size)
+    }
+
+    ///
+    /// Copies the bytes into the staging memory at the offset, laid out as for
+    /// gen_texture. False if the renderer was destroyed meanwhile or the texture failed
+    /// before getting any, nothing gets written then. Panics past the end of the staging
+    /// memory, and after queue_for_uploading.
+    ///
+    pub fn write_staging(&self, offset: u64, bytes: &[u8]) -> bool {
+        let staging = match &self.staging {
+            Some(e) => e,
+            None if matches!(self.id(), Some(Err(_))) => return false,
+            None => panic!("texture has no staging memory to write!"),
+        };
+        if offset + bytes.len() as u64 > staging.size {
+            panic!(
+                "{} bytes at {} don't fit the staging memory of {} bytes!",
+                bytes.len(),
+                offset,
+                staging.size
+            );
+        }
+        if self.slot.is_filled.load(Ordering::Acquire) {
+            panic!("texture was queued for uploading already!");
+        }
+        let is_open = self
+            .shared
+            .is_open
+            .read()
+            .expect("texture loader lock poisoned!");
+        if !*is_open {
+            return false;
+        }
+        unsafe {
+            let dst = (staging.addr as *mut u8).add(offset as usize);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst, bytes.len());
+        }
+        true
+    }
+
+    ///
+    /// Done writing, the renderer uploads the texture once it created it.
+    ///
+    pub fn queue_for_uploading(&self) {
+        self.slot.is_filled.store(true, Ordering::Release);
+    }
+}
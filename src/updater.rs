@@ -1,9 +1,10 @@
 use crate::{
-    buffer::{DeviceAllocator, DeviceSlice},
+    buffer::DeviceSlice,
+    frame_ring::FrameRing,
     shader_resource::{MultiResource, SingleResource},
 };
 
-fn alloc_and_copy_into<T>(mem: &DeviceAllocator, src: &[T], count: u32) -> DeviceSlice {
+fn alloc_and_copy_into<T>(ring: &mut FrameRing, src: &[T], count: u32) -> DeviceSlice {
     if count as usize != src.len() {
         panic!(
             "expected {} resources of type {}, found {}",
@@ -14,7 +15,7 @@ fn alloc_and_copy_into<T>(mem: &DeviceAllocator, src: &[T], count: u32) -> Devic
     }
     let per_item_size = std::mem::size_of::<T>() as u64;
     let total_size = per_item_size * count as u64;
-    let device = mem.alloc(total_size).unwrap();
+    let device = alloc_in(ring, total_size);
     let src = src.as_ptr() as *const u8;
     let dst = device.addr as *mut u8;
     unsafe {
@@ -33,23 +34,47 @@ fn copy_into<T>(src: *const T, dst: &DeviceSlice, offset: u64) -> u64 {
     offset + per_item_size as u64
 }
 
+///
+/// Frame data allocated with the alignment of the ring. Running out of it panics, the
+/// renderer trims the tasks of a frame to what the ring can take beforehand.
+///
+pub fn alloc_in(ring: &mut FrameRing, size: u64) -> DeviceSlice {
+    let alignment = ring.alignment();
+    ring.alloc(size, alignment).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn alloc_and_copy_bytes(ring: &mut FrameRing, bytes: &[u8]) -> DeviceSlice {
+    let dst = alloc_in(ring, bytes.len() as u64);
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst.addr as *mut u8, bytes.len()) };
+    dst
+}
+
+///
+/// Copies the per instance resources of a task, the shaders get the address in the push
+/// constants and read them as buffer_reference blocks. No descriptor is involved, so
+/// maxUniformBufferRange doesn't limit how large they can be.
+///
 pub fn alloc_and_fill_multi(
-    mem: &DeviceAllocator,
+    ring: &mut FrameRing,
     resource: &MultiResource,
     instance_count: u32,
 ) -> DeviceSlice {
     match resource {
-        MultiResource::Transform(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::Material(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::DirLight(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::Frustum(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::ViewRay(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::PointLight(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::SpotLight(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::Joint(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::Sky(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::StaticShadow(e) => alloc_and_copy_into(mem, e, instance_count),
-        MultiResource::TransformExtra(e) => alloc_and_copy_into(mem, e, instance_count),
+        MultiResource::Transform(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::Material(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::DirLight(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::Frustum(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::ViewRay(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::PointLight(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::SpotLight(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::Joint(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::Sky(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::StaticShadow(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::TransformExtra(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::LightClusters(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::ViewMatrices(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::FrameConstants(e) => alloc_and_copy_into(ring, e, instance_count),
+        MultiResource::SceneSlot(e) => panic!("scene slot {} is already on the GPU!", e),
     }
 }
 
@@ -66,5 +91,8 @@ pub fn fill_single(src: &SingleResource, dst: &DeviceSlice, offset: u64) -> u64
         SingleResource::Sky(e) => copy_into(e, dst, offset),
         SingleResource::StaticShadow(e) => copy_into(e, dst, offset),
         SingleResource::TransformExtra(e) => copy_into(e, dst, offset),
+        SingleResource::LightClusters(e) => copy_into(e, dst, offset),
+        SingleResource::ViewMatrices(e) => copy_into(e, dst, offset),
+        SingleResource::FrameConstants(e) => copy_into(e, dst, offset),
     }
 }
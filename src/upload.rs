@@ -0,0 +1,147 @@
+use ash::vk;
+
+use crate::{context::VulkanContext, texture::Texture};
+
+///
+/// Queue of a compute capable family other than the graphics one, texture uploads get
+/// recorded here so they don't compete with the graphics work of the frame.
+///
+#[derive(Clone)]
+pub struct AsyncUploadQueue {
+    pub family_index: u32,
+    pub queue: vk::Queue,
+    pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    // Signaled with the next value on every submission
+    pub timeline_semaphore: vk::Semaphore,
+    pub last_value: u64,
+}
+
+impl AsyncUploadQueue {
+    pub fn new(device: &ash::Device, family_index: u32) -> Self {
+        let queue = unsafe { device.get_device_queue(family_index, 0) };
+        let pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(family_index);
+        let pool = unsafe { device.create_command_pool(&pool_create_info, None).unwrap() };
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_buffer_count(1)
+            .command_pool(pool)
+            .level(vk::CommandBufferLevel::PRIMARY);
+        let command_buffer = unsafe {
+            device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .unwrap()[0]
+        };
+        let mut timeline_semaphore_type_create_info = vk::SemaphoreTypeCreateInfo::builder()
+            .initial_value(0)
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .build();
+        let timeline_semaphore_create_info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut timeline_semaphore_type_create_info)
+            .build();
+        let timeline_semaphore = unsafe {
+            device
+                .create_semaphore(&timeline_semaphore_create_info, None)
+                .unwrap()
+        };
+        Self {
+            family_index,
+            queue,
+            pool,
+            command_buffer,
+            timeline_semaphore,
+            last_value: 0,
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_semaphore(self.timeline_semaphore, None);
+            device.destroy_command_pool(self.pool, None);
+        }
+    }
+
+    ///
+    /// Records the uploads of the textures and submits them right away. Returns the value
+    /// of the timeline semaphore the graphics queue has to wait on before sampling them.
+    /// Exclusive textures get released to graphics_family, which has to acquire them.
+    ///
+    pub fn submit(
+        &mut self,
+        ctx: &VulkanContext,
+        textures: &[&Texture],
+        graphics_family: u32,
+    ) -> u64 {
+        let device = &ctx.device;
+        // Previous submission is done long ago, the frame that waited on it finished
+        let semaphores = [self.timeline_semaphore];
+        let values = [self.last_value];
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(&semaphores)
+            .values(&values)
+            .build();
+        unsafe {
+            device
+                .wait_semaphores(&wait_info, u64::MAX)
+                .expect("async upload wait failed!");
+            device
+                .reset_command_buffer(
+                    self.command_buffer,
+                    vk::CommandBufferResetFlags::RELEASE_RESOURCES,
+                )
+                .expect("reset command buffer failed!");
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device
+                .begin_command_buffer(self.command_buffer, &begin_info)
+                .expect("begin commandbuffer failed!");
+        }
+        ctx.extension.try_begin_label(
+            self.command_buffer,
+            &format!("async texture uploads ({} textures)", textures.len()),
+        );
+        for texture in textures {
+            let ownership =
+                (!texture.is_concurrent).then_some((self.family_index, graphics_family));
+            texture.upload_on_async_queue(ctx, self.command_buffer, ownership);
+        }
+        ctx.extension.try_end_label(self.command_buffer);
+        self.last_value += 1;
+        let command_buffers = [self.command_buffer];
+        let signal_values = [self.last_value];
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .signal_semaphore_values(&signal_values)
+            .build();
+        let submit_info = vk::SubmitInfo::builder()
+            .push_next(&mut timeline_info)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&semaphores);
+        unsafe {
+            device
+                .end_command_buffer(self.command_buffer)
+                .expect("end command buffer failed!");
+            device
+                .queue_submit(self.queue, &[submit_info.build()], vk::Fence::null())
+                .expect("async upload submit failed!");
+        }
+        self.last_value
+    }
+}
+
+///
+/// Compute capable queue family without graphics support, if the device has one.
+///
+pub fn find_async_compute_family(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Option<u32> {
+    let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    families
+        .iter()
+        .position(|e| {
+            e.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !e.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|e| e as u32)
+}
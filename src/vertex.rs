@@ -0,0 +1,223 @@
+/*
+ * Quantized vertex attributes. The encoders take the f32 attributes meshes are made of
+ * and write them in the smaller formats of StreamFormats::QUANTIZED, the decoders read
+ * them back the way the shaders do, for bounds and to measure the error:
+ *
+ *   - Positions as R16G16B16A16_SFLOAT, with a w of 1. Half floats keep 11 significant
+ *     bits, so the error grows with the distance from the origin of the mesh.
+ *   - Normals as A2B10G10R10_SNORM_PACK32, clamped to [-1, 1], with an alpha of 0.
+ *   - Tex coords as R16G16_UNORM, clamped to [0, 1], tiling ones don't survive it.
+ *
+ * Components round to the nearest value of the format, ties to even for half floats.
+ */
+use glam::{Vec2, Vec3};
+
+use crate::{
+    format::Format,
+    mesh_opt::PackedMesh,
+    vertex_layout::{StreamFormats, VertexAttributeKind},
+};
+
+///
+/// Largest distance between an attribute before and after quantizing, over every vertex
+/// of a mesh. 0 for attributes left as they were.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuantizationError {
+    pub position: f32,
+    pub normal: f32,
+    pub tex_coord: f32,
+}
+
+pub fn f32_to_f16(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN keeps a mantissa bit
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Too small for a normal half float, subnormal or zero
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let rest = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let is_rounded_up = rest > halfway || (rest == halfway && half & 1 == 1);
+        return sign | (half + is_rounded_up as u32) as u16;
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let is_rounded_up = rest > 0x1000 || (rest == 0x1000 && half & 1 == 1);
+    // Carrying out of the mantissa bumps the exponent, up to infinity
+    sign | (half + is_rounded_up as u32) as u16
+}
+
+pub fn f16_to_f32(v: u16) -> f32 {
+    let sign = ((v & 0x8000) as u32) << 16;
+    let exponent = ((v >> 10) & 0x1f) as u32;
+    let mantissa = (v & 0x3ff) as u32;
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | mantissa << 13),
+        _ => f32::from_bits(sign | (exponent + 127 - 15) << 23 | mantissa << 13),
+    }
+}
+
+pub fn encode_positions_f16(positions: &[Vec3]) -> Vec<u8> {
+    positions
+        .iter()
+        .flat_map(|e| [e.x, e.y, e.z, 1.0])
+        .flat_map(|e| f32_to_f16(e).to_ne_bytes())
+        .collect()
+}
+
+pub fn decode_positions_f16(bytes: &[u8]) -> Vec<Vec3> {
+    decode_positions(bytes, 8, 0, Format::R16G16B16A16_SFLOAT)
+}
+
+pub fn encode_normals_snorm10(normals: &[Vec3]) -> Vec<u8> {
+    let component = |v: f32| (v.clamp(-1.0, 1.0) * 511.0).round() as i32 as u32 & 0x3ff;
+    normals
+        .iter()
+        .map(|e| component(e.x) | component(e.y) << 10 | component(e.z) << 20)
+        .flat_map(|e| e.to_ne_bytes())
+        .collect()
+}
+
+pub fn decode_normals_snorm10(bytes: &[u8]) -> Vec<Vec3> {
+    // Sign extended from the top of the word, -512 reads as -1 like -511
+    let component = |v: u32, at: u32| ((((v << (22 - at)) as i32) >> 22) as f32 / 511.0).max(-1.0);
+    bytes
+        .chunks_exact(4)
+        .map(|e| u32::from_ne_bytes(e.try_into().unwrap()))
+        .map(|e| Vec3::new(component(e, 0), component(e, 10), component(e, 20)))
+        .collect()
+}
+
+pub fn encode_tex_coords_unorm16(tex_coords: &[Vec2]) -> Vec<u8> {
+    let component = |v: f32| (v.clamp(0.0, 1.0) * 65535.0).round() as u16;
+    tex_coords
+        .iter()
+        .flat_map(|e| [component(e.x), component(e.y)])
+        .flat_map(|e| e.to_ne_bytes())
+        .collect()
+}
+
+pub fn decode_tex_coords_unorm16(bytes: &[u8]) -> Vec<Vec2> {
+    let component = |e: &[u8]| u16::from_ne_bytes(e.try_into().unwrap()) as f32 / 65535.0;
+    bytes
+        .chunks_exact(4)
+        .map(|e| Vec2::new(component(&e[..2]), component(&e[2..])))
+        .collect()
+}
+
+///
+/// Positions at the offset of each vertex of the stride, in either format positions can
+/// be held in.
+///
+pub fn decode_positions(bytes: &[u8], stride: u32, offset: u32, format: Format) -> Vec<Vec3> {
+    let vertices = bytes
+        .chunks_exact(stride as usize)
+        .map(|e| &e[offset as usize..]);
+    match format {
+        Format::R32G32B32_SFLOAT => vertices
+            .map(|e| {
+                let component =
+                    |i: usize| f32::from_ne_bytes(e[i * 4..i * 4 + 4].try_into().unwrap());
+                Vec3::new(component(0), component(1), component(2))
+            })
+            .collect(),
+        Format::R16G16B16A16_SFLOAT => vertices
+            .map(|e| {
+                let component = |i: usize| {
+                    f16_to_f32(u16::from_ne_bytes(e[i * 2..i * 2 + 2].try_into().unwrap()))
+                };
+                Vec3::new(component(0), component(1), component(2))
+            })
+            .collect(),
+        _ => panic!("positions can't be held as {}!", format),
+    }
+}
+
+fn floats_of(bytes: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    bytes
+        .chunks_exact(4)
+        .map(|e| f32::from_ne_bytes(e.try_into().unwrap()))
+}
+
+fn max_distance<T: Copy>(a: &[T], b: &[T], distance: impl Fn(T, T) -> f32) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| distance(*a, *b))
+        .fold(0.0, f32::max)
+}
+
+///
+/// Rewrites the f32 attributes of the mesh in the formats, returning how far off they
+/// ended up. Attributes the mesh is missing stay in their f32 format. Panics if the mesh
+/// was quantized already or a format isn't one the attribute can be held in.
+///
+pub fn quantize(mesh: &mut PackedMesh, formats: StreamFormats) -> QuantizationError {
+    if mesh.formats != StreamFormats::default() {
+        panic!("mesh was quantized already!");
+    }
+    formats.validate();
+    let mut error = QuantizationError::default();
+    let mut applied = StreamFormats::default();
+    for (i, kind) in VertexAttributeKind::ALL.into_iter().enumerate() {
+        let (data, size) = &mut mesh.streams[i];
+        let format = formats.of(kind);
+        if data.is_empty() || format == kind.format() {
+            continue;
+        }
+        let floats: Vec<_> = floats_of(data).collect();
+        *data = match kind {
+            VertexAttributeKind::Position => {
+                let input: Vec<_> = floats.chunks_exact(3).map(Vec3::from_slice).collect();
+                let quantized = encode_positions_f16(&input);
+                let output = decode_positions_f16(&quantized);
+                error.position = max_distance(&input, &output, Vec3::distance);
+                quantized
+            }
+            VertexAttributeKind::Normal => {
+                let input: Vec<_> = floats.chunks_exact(3).map(Vec3::from_slice).collect();
+                let quantized = encode_normals_snorm10(&input);
+                let output = decode_normals_snorm10(&quantized);
+                error.normal = max_distance(&input, &output, Vec3::distance);
+                quantized
+            }
+            VertexAttributeKind::TexCoord => {
+                let input: Vec<_> = floats.chunks_exact(2).map(Vec2::from_slice).collect();
+                let quantized = encode_tex_coords_unorm16(&input);
+                let output = decode_tex_coords_unorm16(&quantized);
+                error.tex_coord = max_distance(&input, &output, Vec2::distance);
+                quantized
+            }
+        };
+        *size = crate::vertex_layout::format_size(format) as usize;
+        match kind {
+            VertexAttributeKind::Position => applied.position = format,
+            VertexAttributeKind::Normal => applied.normal = format,
+            VertexAttributeKind::TexCoord => applied.tex_coord = format,
+        }
+    }
+    mesh.formats = applied;
+    error
+}
@@ -0,0 +1,306 @@
+/*
+ * Interleaved meshes hold all their attributes in a single buffer, one vertex after the
+ * other. There are no vertex input bindings to describe them with, the shaders pull the
+ * vertices through buffer addresses, so each stage declares in pipeline.json which layouts
+ * its shaders read and tasks with meshes of another layout get rejected when queued. The
+ * same goes for the formats of the attributes, shaders decode quantized ones themselves,
+ * so stages declare which formats they read and meshes in others get rejected.
+ */
+use serde::Deserialize;
+
+use crate::format::Format;
+
+///
+/// How the attributes of a mesh are laid out. Separate keeps one buffer per attribute,
+/// which suits passes only reading positions like shadows. Interleaved keeps them in one
+/// buffer, for passes reading every attribute of each vertex.
+///
+#[derive(Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, strum_macros::Display)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VertexLayoutKind {
+    #[default]
+    Separate,
+    Interleaved,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, strum_macros::Display)]
+pub enum VertexAttributeKind {
+    Position,
+    Normal,
+    TexCoord,
+}
+
+impl VertexAttributeKind {
+    pub const ALL: [Self; 3] = [Self::Position, Self::Normal, Self::TexCoord];
+
+    ///
+    /// Format the attribute is in unless quantized, the one stages read by default.
+    ///
+    pub const fn format(self) -> Format {
+        match self {
+            Self::Position | Self::Normal => Format::R32G32B32_SFLOAT,
+            Self::TexCoord => Format::R32G32_SFLOAT,
+        }
+    }
+
+    pub const fn size(self) -> u32 {
+        format_size(self.format())
+    }
+
+    ///
+    /// Formats meshes can hold the attribute in, the unquantized one first. See vertex
+    /// for the encoders of the others.
+    ///
+    pub const fn formats(self) -> &'static [Format] {
+        match self {
+            Self::Position => &[Format::R32G32B32_SFLOAT, Format::R16G16B16A16_SFLOAT],
+            Self::Normal => &[Format::R32G32B32_SFLOAT, Format::A2B10G10R10_SNORM_PACK32],
+            Self::TexCoord => &[Format::R32G32_SFLOAT, Format::R16G16_UNORM],
+        }
+    }
+
+    pub fn is_format_supported(self, format: Format) -> bool {
+        self.formats().contains(&format)
+    }
+}
+
+///
+/// Bytes of one attribute in a vertex format, 0 for formats attributes can't be held in.
+///
+pub const fn format_size(format: Format) -> u32 {
+    match format {
+        Format::R32G32B32_SFLOAT => 12,
+        Format::R32G32_SFLOAT | Format::R16G16B16A16_SFLOAT => 8,
+        Format::A2B10G10R10_SNORM_PACK32 | Format::R16G16_UNORM => 4,
+        _ => 0,
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct VertexAttribute {
+    pub kind: VertexAttributeKind,
+    pub format: Format,
+    // Bytes from the start of the vertex
+    pub offset: u32,
+}
+
+impl VertexAttribute {
+    pub const fn new(kind: VertexAttributeKind, offset: u32) -> Self {
+        Self::with_format(kind, kind.format(), offset)
+    }
+
+    pub const fn with_format(kind: VertexAttributeKind, format: Format, offset: u32) -> Self {
+        Self {
+            kind,
+            format,
+            offset,
+        }
+    }
+
+    pub const fn size(&self) -> u32 {
+        format_size(self.format)
+    }
+}
+
+///
+/// Attribute order, formats and offsets within one vertex of an interleaved mesh, see
+/// Renderer::gen_mesh_interleaved.
+///
+#[derive(Clone, Eq, PartialEq)]
+pub struct VertexLayout {
+    stride: u32,
+    attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    ///
+    /// Panics if there is no position, an attribute appears twice, is in a format it
+    /// can't be held in, or doesn't fit 4 byte aligned within the stride.
+    ///
+    pub fn new(stride: u32, attributes: &[VertexAttribute]) -> Self {
+        if stride == 0 || !stride.is_multiple_of(4) {
+            panic!("vertex stride of {} isn't a multiple of 4!", stride);
+        }
+        for (i, attribute) in attributes.iter().enumerate() {
+            if attributes[..i].iter().any(|e| e.kind == attribute.kind) {
+                panic!(
+                    "{} appears more than once in the vertex layout!",
+                    attribute.kind
+                );
+            }
+            if !attribute.kind.is_format_supported(attribute.format) {
+                panic!("{} can't be held as {}!", attribute.kind, attribute.format);
+            }
+            let end = attribute.offset + attribute.size();
+            if !attribute.offset.is_multiple_of(4) || end > stride {
+                panic!(
+                    "{} at offset {} doesn't fit 4 byte aligned in a stride of {}!",
+                    attribute.kind, attribute.offset, stride
+                );
+            }
+        }
+        if !attributes
+            .iter()
+            .any(|e| e.kind == VertexAttributeKind::Position)
+        {
+            panic!("vertex layout without a position!");
+        }
+        Self {
+            stride,
+            attributes: attributes.to_vec(),
+        }
+    }
+
+    ///
+    /// Position, normal and tex coord right after each other, 32 bytes per vertex.
+    ///
+    pub fn packed() -> Self {
+        Self::new(
+            32,
+            &[
+                VertexAttribute::new(VertexAttributeKind::Position, 0),
+                VertexAttribute::new(VertexAttributeKind::Normal, 12),
+                VertexAttribute::new(VertexAttributeKind::TexCoord, 24),
+            ],
+        )
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn offset_of(&self, kind: VertexAttributeKind) -> Option<u32> {
+        self.attributes
+            .iter()
+            .find(|e| e.kind == kind)
+            .map(|e| e.offset)
+    }
+
+    ///
+    /// Formats of the attributes, the unquantized one for those missing.
+    ///
+    pub fn formats(&self) -> StreamFormats {
+        let format_of = |kind: VertexAttributeKind| {
+            self.attributes
+                .iter()
+                .find(|e| e.kind == kind)
+                .map_or(kind.format(), |e| e.format)
+        };
+        StreamFormats {
+            position: format_of(VertexAttributeKind::Position),
+            normal: format_of(VertexAttributeKind::Normal),
+            tex_coord: format_of(VertexAttributeKind::TexCoord),
+        }
+    }
+
+    ///
+    /// Stride and the position, normal and tex coord offsets, two per address sized push
+    /// constant. Missing attributes read whatever is at the start of the vertex.
+    ///
+    pub(crate) fn push_constants(&self) -> [u64; 2] {
+        let offset = |kind| self.offset_of(kind).unwrap_or(0) as u64;
+        [
+            self.stride as u64 | offset(VertexAttributeKind::Position) << 32,
+            offset(VertexAttributeKind::Normal) | offset(VertexAttributeKind::TexCoord) << 32,
+        ]
+    }
+}
+
+///
+/// Format of each attribute of a mesh, the layout of interleaved ones holds them too.
+/// Quantized formats take a half to a third of the memory of the default ones, see
+/// vertex::quantize.
+///
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StreamFormats {
+    pub position: Format,
+    pub normal: Format,
+    pub tex_coord: Format,
+}
+
+impl Default for StreamFormats {
+    fn default() -> Self {
+        Self {
+            position: VertexAttributeKind::Position.format(),
+            normal: VertexAttributeKind::Normal.format(),
+            tex_coord: VertexAttributeKind::TexCoord.format(),
+        }
+    }
+}
+
+impl StreamFormats {
+    ///
+    /// Positions in half floats, normals in 10 bit signed normalized and tex coords in
+    /// 16 bit unsigned normalized components, 16 bytes per vertex instead of 32.
+    ///
+    pub const QUANTIZED: Self = Self {
+        position: Format::R16G16B16A16_SFLOAT,
+        normal: Format::A2B10G10R10_SNORM_PACK32,
+        tex_coord: Format::R16G16_UNORM,
+    };
+
+    pub fn of(&self, kind: VertexAttributeKind) -> Format {
+        match kind {
+            VertexAttributeKind::Position => self.position,
+            VertexAttributeKind::Normal => self.normal,
+            VertexAttributeKind::TexCoord => self.tex_coord,
+        }
+    }
+
+    ///
+    /// Panics if an attribute is in a format it can't be held in.
+    ///
+    pub fn validate(&self) {
+        for kind in VertexAttributeKind::ALL {
+            if !kind.is_format_supported(self.of(kind)) {
+                panic!("{} can't be held as {}!", kind, self.of(kind));
+            }
+        }
+    }
+}
+
+///
+/// Formats the shaders of a stage read each attribute in, declared in pipeline.json as
+/// "vertexFormats". Attributes left out are only read unquantized.
+///
+#[derive(Deserialize, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct AcceptedFormats {
+    pub position: Vec<Format>,
+    pub normal: Vec<Format>,
+    pub tex_coord: Vec<Format>,
+}
+
+impl Default for AcceptedFormats {
+    fn default() -> Self {
+        Self {
+            position: vec![VertexAttributeKind::Position.format()],
+            normal: vec![VertexAttributeKind::Normal.format()],
+            tex_coord: vec![VertexAttributeKind::TexCoord.format()],
+        }
+    }
+}
+
+impl AcceptedFormats {
+    pub fn of(&self, kind: VertexAttributeKind) -> &[Format] {
+        match kind {
+            VertexAttributeKind::Position => &self.position,
+            VertexAttributeKind::Normal => &self.normal,
+            VertexAttributeKind::TexCoord => &self.tex_coord,
+        }
+    }
+
+    ///
+    /// First attribute whose format isn't accepted, along with that format.
+    ///
+    pub fn rejected_of(&self, formats: &StreamFormats) -> Option<(VertexAttributeKind, Format)> {
+        VertexAttributeKind::ALL
+            .into_iter()
+            .map(|e| (e, formats.of(e)))
+            .find(|(kind, format)| !self.of(*kind).contains(format))
+    }
+}
@@ -0,0 +1,145 @@
+/*
+ * Adapter selection against made up enumerations, no GPU involved.
+ */
+use ash::vk;
+
+use rend_vk::adapter::{AdapterError, AdapterInfo, AdapterSelector, MissingFeature};
+use rend_vk::config::DescriptorMode;
+
+fn adapter(index: usize, name: &str, device_type: vk::PhysicalDeviceType) -> AdapterInfo {
+    AdapterInfo {
+        index,
+        physical_device: vk::PhysicalDevice::null(),
+        name: name.to_string(),
+        device_type,
+        vendor_id: 0,
+        device_id: index as u32,
+        driver_version: 0,
+        api_version: vk::API_VERSION_1_3,
+        uuid: [index as u8; 16],
+        luid: Some([index as u8; 8]),
+        vram_heap_sizes: vec![1 << 30],
+        graphics_family: Some(0),
+        is_descriptor_buffer_supported: true,
+        is_descriptor_set_fallback_supported: true,
+        missing: Vec::new(),
+    }
+}
+
+fn machine() -> Vec<AdapterInfo> {
+    let mut old = adapter(0, "old discrete", vk::PhysicalDeviceType::DISCRETE_GPU);
+    old.missing = vec![MissingFeature::ApiVersion];
+    let mut integrated = adapter(1, "integrated", vk::PhysicalDeviceType::INTEGRATED_GPU);
+    integrated.is_descriptor_buffer_supported = false;
+    let discrete = adapter(2, "discrete", vk::PhysicalDeviceType::DISCRETE_GPU);
+    vec![old, integrated, discrete]
+}
+
+#[test]
+fn auto_prefers_usable_discrete_adapters() {
+    let adapters = machine();
+    let selected = AdapterSelector::Auto.select(&adapters, DescriptorMode::Auto);
+    assert_eq!(selected.unwrap().name, "discrete");
+    // Integrated ones do when no discrete one is usable
+    let selected = AdapterSelector::Auto.select(&adapters[..2], DescriptorMode::Auto);
+    assert_eq!(selected.unwrap().name, "integrated");
+}
+
+#[test]
+fn auto_reports_what_every_adapter_lacks() {
+    let adapters = machine();
+    assert_eq!(
+        AdapterSelector::Auto.select(&adapters[..2], DescriptorMode::DescriptorBuffer),
+        Err(AdapterError::NoneSuitable {
+            rejected: vec![
+                ("old discrete".to_string(), vec![MissingFeature::ApiVersion]),
+                (
+                    "integrated".to_string(),
+                    vec![MissingFeature::DescriptorBuffer]
+                ),
+            ]
+        })
+    );
+    assert_eq!(
+        AdapterSelector::Auto.select(&[], DescriptorMode::Auto),
+        Err(AdapterError::NoneSuitable {
+            rejected: Vec::new()
+        })
+    );
+}
+
+#[test]
+fn selectors_find_their_adapter() {
+    let adapters = machine();
+    let mode = DescriptorMode::Auto;
+    let by_index = AdapterSelector::ByIndex(1).select(&adapters, mode);
+    assert_eq!(by_index.unwrap().name, "integrated");
+    let by_uuid = AdapterSelector::ByUuid([2; 16]).select(&adapters, mode);
+    assert_eq!(by_uuid.unwrap().name, "discrete");
+    let by_luid = AdapterSelector::ByLuid([1; 8]).select(&adapters, mode);
+    assert_eq!(by_luid.unwrap().name, "integrated");
+    assert_eq!(
+        AdapterSelector::ByUuid([7; 16]).select(&adapters, mode),
+        Err(AdapterError::NotFound(AdapterSelector::ByUuid([7; 16])))
+    );
+    assert_eq!(
+        AdapterSelector::ByIndex(3).select(&adapters, mode),
+        Err(AdapterError::NotFound(AdapterSelector::ByIndex(3)))
+    );
+}
+
+#[test]
+fn selected_adapters_missing_features_are_errors() {
+    let adapters = machine();
+    assert_eq!(
+        AdapterSelector::ByIndex(0).select(&adapters, DescriptorMode::Auto),
+        Err(AdapterError::Unsupported {
+            name: "old discrete".to_string(),
+            missing: vec![MissingFeature::ApiVersion],
+        })
+    );
+    // Descriptor sets are the fallback, unless descriptor buffers are asked for
+    assert!(AdapterSelector::ByIndex(1)
+        .select(&adapters, DescriptorMode::Auto)
+        .is_ok());
+    let error = AdapterSelector::ByIndex(1)
+        .select(&adapters, DescriptorMode::DescriptorBuffer)
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "adapter integrated lacks VK_EXT_descriptor_buffer"
+    );
+}
+
+#[test]
+fn descriptors_are_missing_only_without_either_mode() {
+    let mut adapter = adapter(0, "bare", vk::PhysicalDeviceType::CPU);
+    adapter.is_descriptor_set_fallback_supported = false;
+    assert!(adapter.is_usable(DescriptorMode::Auto));
+    assert_eq!(
+        adapter.missing_features(DescriptorMode::DescriptorSets),
+        [MissingFeature::DescriptorSetUpdateAfterBind]
+    );
+    adapter.is_descriptor_buffer_supported = false;
+    assert_eq!(
+        adapter.missing_features(DescriptorMode::Auto),
+        [
+            MissingFeature::DescriptorBuffer,
+            MissingFeature::DescriptorSetUpdateAfterBind
+        ]
+    );
+}
+
+#[test]
+fn selectors_round_trip_through_json() {
+    let selector = AdapterSelector::ByUuid([0xab; 16]);
+    let json = serde_json::to_string(&selector).unwrap();
+    assert_eq!(
+        serde_json::from_str::<AdapterSelector>(&json).unwrap(),
+        selector
+    );
+    assert_eq!(
+        serde_json::from_str::<AdapterSelector>(r#""auto""#).unwrap(),
+        AdapterSelector::Auto
+    );
+}
@@ -0,0 +1,253 @@
+/*
+ * Budgets of allocator scopes. The bookkeeping gets checked on its own against a range
+ * allocator the way DeviceAllocator drives it, then once through the general allocator
+ * of a renderer on a headless surface with validation on.
+ */
+
+mod common;
+
+use rend_vk::alloc_scope::{ScopeBudgetExceeded, ScopeTable};
+use rend_vk::buffer::AllocError;
+use rend_vk::range_allocator::RangeAllocator;
+use rend_vk::renderer::Renderer;
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+/*
+ * Buffer of 1024 bytes with scopes, allocating and freeing like DeviceAllocator does.
+ */
+struct Buffer {
+    ranges: RangeAllocator,
+    scopes: ScopeTable,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Self {
+            ranges: RangeAllocator::new(1024),
+            scopes: ScopeTable::new(),
+        }
+    }
+
+    fn alloc(&mut self, scope: Option<usize>, size: u64) -> Result<u64, AllocError> {
+        if let Some(scope) = scope {
+            self.scopes
+                .check(scope, size)
+                .map_err(AllocError::ScopeBudgetExceeded)?;
+        }
+        let offset = self.ranges.alloc(size, 16).ok_or(AllocError::OutOfMemory)?;
+        if let Some(scope) = scope {
+            self.scopes.record(scope, offset, size);
+        }
+        Ok(offset)
+    }
+
+    fn free(&mut self, offset: u64, size: u64) {
+        self.scopes.release(offset);
+        self.ranges.free(offset, size);
+    }
+
+    fn destroy_scope(&mut self, id: usize) -> usize {
+        let outstanding = self.scopes.destroy(id);
+        for (offset, size) in &outstanding {
+            self.ranges.free(*offset, *size);
+        }
+        outstanding.len()
+    }
+
+    fn used(&self, id: usize) -> u64 {
+        self.scopes.snapshot_of(id).used
+    }
+}
+
+#[test]
+fn budgets_fit_exactly() {
+    let mut buffer = Buffer::new();
+    let ui = buffer.scopes.create("ui", 256, None);
+    buffer.alloc(Some(ui), 128).unwrap();
+    buffer.alloc(Some(ui), 128).unwrap();
+    assert_eq!(buffer.scopes.remaining(ui), 0);
+    // A single byte over fails while the buffer has plenty of room
+    assert_eq!(
+        buffer.alloc(Some(ui), 16),
+        Err(AllocError::ScopeBudgetExceeded(ScopeBudgetExceeded {
+            scope: "ui".to_string(),
+            budget: 256,
+            used: 256,
+            size: 16,
+        }))
+    );
+    assert_eq!(buffer.ranges.available(), 768);
+    buffer.alloc(None, 768).unwrap();
+
+    let snapshot = buffer.scopes.snapshot_of(ui);
+    assert_eq!((snapshot.used, snapshot.peak), (256, 256));
+    assert_eq!(snapshot.allocation_count, 2);
+    assert_eq!(snapshot.failed_count, 1);
+    assert_eq!(snapshot.usage(), 1.0);
+}
+
+#[test]
+fn running_out_of_ranges_isnt_a_budget_failure() {
+    let mut buffer = Buffer::new();
+    let terrain = buffer.scopes.create("terrain", 4096, None);
+    buffer.alloc(None, 1000).unwrap();
+    assert_eq!(
+        buffer.alloc(Some(terrain), 64),
+        Err(AllocError::OutOfMemory)
+    );
+    let snapshot = buffer.scopes.snapshot_of(terrain);
+    assert_eq!((snapshot.used, snapshot.failed_count), (0, 0));
+}
+
+#[test]
+fn nested_scopes_count_against_every_budget() {
+    let mut buffer = Buffer::new();
+    let characters = buffer.scopes.create("characters", 512, None);
+    let hair = buffer.scopes.create("hair", 1024, Some(characters));
+    let cloth = buffer.scopes.create("cloth", 128, Some(characters));
+    buffer.alloc(Some(characters), 128).unwrap();
+    buffer.alloc(Some(hair), 256).unwrap();
+    // Hair has room left, the characters it's nested in don't
+    let error = buffer.alloc(Some(hair), 256).unwrap_err();
+    assert_eq!(
+        error,
+        AllocError::ScopeBudgetExceeded(ScopeBudgetExceeded {
+            scope: "characters".to_string(),
+            budget: 512,
+            used: 384,
+            size: 256,
+        })
+    );
+    // The innermost budget gets reported first
+    let error = buffer.alloc(Some(cloth), 144).unwrap_err();
+    assert!(matches!(error, AllocError::ScopeBudgetExceeded(e) if e.scope == "cloth"));
+    buffer.alloc(Some(cloth), 128).unwrap();
+
+    assert_eq!(buffer.used(characters), 512);
+    assert_eq!(buffer.used(hair), 256);
+    assert_eq!(buffer.used(cloth), 128);
+    assert_eq!(buffer.scopes.remaining(hair), 0);
+    assert_eq!(buffer.scopes.snapshot_of(characters).allocation_count, 1);
+    assert_eq!(buffer.scopes.snapshot_of(hair).failed_count, 1);
+    assert_eq!(
+        buffer.scopes.snapshot_of(hair).parent,
+        Some(characters as u32)
+    );
+}
+
+#[test]
+fn frees_interleave_between_scopes() {
+    let mut buffer = Buffer::new();
+    let particles = buffer.scopes.create("particles", 512, None);
+    let sparks = buffer.scopes.create("sparks", 256, Some(particles));
+    let a = buffer.alloc(None, 128).unwrap();
+    let b = buffer.alloc(Some(particles), 128).unwrap();
+    let c = buffer.alloc(Some(sparks), 128).unwrap();
+    let d = buffer.alloc(None, 128).unwrap();
+    let e = buffer.alloc(Some(sparks), 128).unwrap();
+    assert_eq!(buffer.used(particles), 384);
+
+    // Whichever handle frees them, they're uncounted from the scope they were made in
+    buffer.free(c, 128);
+    buffer.free(a, 128);
+    assert_eq!(buffer.used(sparks), 128);
+    assert_eq!(buffer.used(particles), 256);
+    buffer.free(b, 128);
+    assert_eq!(buffer.used(particles), 128);
+    // The freed ranges get reused by whoever comes first
+    let f = buffer.alloc(Some(sparks), 128).unwrap();
+    assert_eq!(f, a);
+    buffer.free(d, 128);
+    assert_eq!(buffer.used(particles), 256);
+    buffer.free(e, 128);
+    buffer.free(f, 128);
+    assert_eq!(buffer.used(particles), 0);
+    assert_eq!(buffer.scopes.snapshot_of(particles).peak, 384);
+    assert_eq!(buffer.ranges.free_ranges(), [[0, 1024]]);
+}
+
+#[test]
+fn destroying_frees_nested_scopes_too() {
+    let mut buffer = Buffer::new();
+    let ui = buffer.scopes.create("ui", 1024, None);
+    let fonts = buffer.scopes.create("fonts", 512, Some(ui));
+    let other = buffer.scopes.create("other", 512, None);
+    let kept = buffer.alloc(None, 64).unwrap();
+    buffer.alloc(Some(ui), 64).unwrap();
+    buffer.alloc(Some(fonts), 64).unwrap();
+    buffer.alloc(Some(fonts), 64).unwrap();
+    let elsewhere = buffer.alloc(Some(other), 64).unwrap();
+
+    assert_eq!(buffer.destroy_scope(ui), 3);
+    assert!(!buffer.scopes.is_live(ui));
+    assert!(!buffer.scopes.is_live(fonts));
+    let live: Vec<_> = buffer
+        .scopes
+        .snapshots()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(live, ["other"]);
+    assert_eq!(buffer.used(other), 64);
+    assert_eq!(buffer.ranges.available(), 1024 - 128);
+
+    buffer.free(kept, 64);
+    buffer.free(elsewhere, 64);
+    assert_eq!(buffer.ranges.free_ranges(), [[0, 1024]]);
+}
+
+#[test]
+#[should_panic]
+fn destroyed_scopes_cant_allocate() {
+    let mut buffer = Buffer::new();
+    let ui = buffer.scopes.create("ui", 1024, None);
+    buffer.destroy_scope(ui);
+    let _ = buffer.alloc(Some(ui), 64);
+}
+
+#[test]
+fn meshes_count_against_the_allocation_scope() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let meshes = renderer.create_allocation_scope("meshes", 1 << 20);
+    let alignment = meshes.alignment();
+    let budget_of = |renderer: &mut Renderer, name: &str| {
+        let snapshot = renderer.memory_snapshot();
+        let scope = snapshot.general.scopes.iter().find(|e| e.name == name);
+        scope.map(|e| (e.used, e.allocation_count))
+    };
+
+    renderer.set_allocation_scope(Some(meshes.clone()));
+    let mesh = renderer.gen_mesh(alignment as u32, 0, 0, alignment as u32, 3);
+    renderer.set_allocation_scope(None);
+    let unscoped = renderer.gen_mesh(alignment as u32, 0, 0, 0, 3);
+    assert_eq!(budget_of(&mut renderer, "meshes"), Some((alignment * 2, 2)));
+    // Freed from the scope it was made in, whatever scope is set by now
+    renderer.free_mesh(mesh).unwrap();
+    renderer.free_mesh(unscoped).unwrap();
+    assert_eq!(budget_of(&mut renderer, "meshes"), Some((0, 0)));
+
+    // Up to the byte, then the scope is full with the general allocator far from it
+    let lods = meshes.create_scope("lods", alignment * 2);
+    let slices = [lods.try_alloc(alignment), lods.try_alloc(alignment - 1)];
+    assert_eq!(lods.available(), 0);
+    assert!(matches!(
+        lods.try_alloc(1),
+        Err(AllocError::ScopeBudgetExceeded(e)) if e.scope == "lods"
+    ));
+    assert!(renderer.memory_snapshot().general.available > alignment);
+    meshes.free(slices[0].clone().unwrap());
+    assert_eq!(lods.scope_snapshot().unwrap().used, alignment);
+
+    // The rest gets freed along with the scope, nested ones too
+    let decals = meshes.create_scope("decals", alignment);
+    decals.try_alloc(alignment).unwrap();
+    assert_eq!(meshes.destroy_scope(), 2);
+    assert_eq!(budget_of(&mut renderer, "lods"), None);
+    drop(decals);
+    drop(lods);
+    common::finish(renderer);
+}
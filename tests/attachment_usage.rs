@@ -0,0 +1,125 @@
+/*
+ * Usage inferred for the attachments of a few pipelines from the passes touching them,
+ * without loading anything.
+ */
+use ash::vk::ImageUsageFlags as Iu;
+
+use rend_vk::pipeline::file::{ExtraUsage, Pipeline};
+
+fn usage_of(pip: &Pipeline, name: &str) -> Iu {
+    pip.attachment_usages()
+        .into_iter()
+        .find(|e| e.0 == name)
+        .unwrap_or_else(|| panic!("no target {}", name))
+        .1
+}
+
+#[test]
+fn gbuffer_targets_get_rendered_and_sampled() {
+    let pip = Pipeline::read(None);
+    let usages = pip.attachment_usages();
+    let names: Vec<_> = usages.iter().map(|e| e.0.as_str()).collect();
+    assert_eq!(
+        names,
+        ["albedo", "normal", "velocity", "misc", "lightAcc", "depth"]
+    );
+    assert_eq!(
+        usage_of(&pip, "albedo"),
+        Iu::COLOR_ATTACHMENT | Iu::SAMPLED | Iu::TRANSFER_SRC
+    );
+    assert_eq!(usage_of(&pip, "normal"), Iu::COLOR_ATTACHMENT | Iu::SAMPLED);
+    assert_eq!(
+        usage_of(&pip, "depth"),
+        Iu::DEPTH_STENCIL_ATTACHMENT | Iu::SAMPLED
+    );
+    // Only the disabled point light pass would sample it
+    assert_eq!(usage_of(&pip, "velocity"), Iu::COLOR_ATTACHMENT);
+}
+
+#[test]
+fn blits_copy_and_pyramids_write_storage() {
+    let pip = Pipeline::read(Some("tests/depth_pyramid.json"));
+    assert_eq!(
+        usage_of(&pip, "depth"),
+        Iu::DEPTH_STENCIL_ATTACHMENT | Iu::SAMPLED | Iu::TRANSFER_SRC
+    );
+    assert_eq!(
+        usage_of(&pip, "hiZ"),
+        Iu::STORAGE | Iu::SAMPLED | Iu::TRANSFER_SRC
+    );
+
+    let pip = Pipeline::read(Some("tests/downsample_chain.json"));
+    assert_eq!(
+        usage_of(&pip, "hdr"),
+        Iu::COLOR_ATTACHMENT | Iu::SAMPLED | Iu::TRANSFER_SRC
+    );
+    assert_eq!(
+        usage_of(&pip, "naive_1"),
+        Iu::TRANSFER_SRC | Iu::TRANSFER_DST
+    );
+    assert_eq!(
+        usage_of(&pip, "naive_2"),
+        Iu::TRANSFER_SRC | Iu::TRANSFER_DST
+    );
+    // Levels of the chain get its extra usage
+    for level in ["bloom_0", "bloom_2"] {
+        assert_eq!(
+            usage_of(&pip, level),
+            Iu::COLOR_ATTACHMENT | Iu::SAMPLED | Iu::TRANSFER_SRC
+        );
+    }
+}
+
+#[test]
+fn extra_usage_goes_on_top() {
+    let mut pip = Pipeline::read(Some("tests/offscreen.json"));
+    assert_eq!(
+        usage_of(&pip, "preview"),
+        Iu::COLOR_ATTACHMENT | Iu::SAMPLED | Iu::TRANSFER_SRC
+    );
+    pip.targets[0].extra_usage = vec![ExtraUsage::Storage, ExtraUsage::TransferDst];
+    assert_eq!(
+        usage_of(&pip, "preview"),
+        Iu::COLOR_ATTACHMENT | Iu::SAMPLED | Iu::STORAGE | Iu::TRANSFER_DST
+    );
+}
+
+#[test]
+fn untouched_targets_get_the_attachment_usage_of_their_format() {
+    let mut pip = Pipeline::read(None);
+    pip.passes.iter_mut().for_each(|e| e.is_disabled = true);
+    assert_eq!(
+        usage_of(&pip, "albedo"),
+        Iu::COLOR_ATTACHMENT | Iu::TRANSFER_SRC
+    );
+    assert_eq!(usage_of(&pip, "depth"), Iu::DEPTH_STENCIL_ATTACHMENT);
+}
+
+#[test]
+fn memoryless_targets_stay_transient() {
+    let mut pip = Pipeline::read(None);
+    let velocity = pip
+        .targets
+        .iter_mut()
+        .find(|e| e.name == "velocity")
+        .unwrap();
+    velocity.is_memoryless = true;
+    assert_eq!(
+        usage_of(&pip, "velocity"),
+        Iu::COLOR_ATTACHMENT | Iu::TRANSIENT_ATTACHMENT
+    );
+}
+
+#[test]
+#[should_panic(expected = "memoryless attachment velocity can't have storage usage!")]
+fn memoryless_targets_refuse_extra_usage() {
+    let mut pip = Pipeline::read(None);
+    let velocity = pip
+        .targets
+        .iter_mut()
+        .find(|e| e.name == "velocity")
+        .unwrap();
+    velocity.is_memoryless = true;
+    velocity.extra_usage = vec![ExtraUsage::Storage];
+    pip.attachment_usages();
+}
@@ -0,0 +1,74 @@
+/*
+ * Synthetic scene plans and report statistics, run with cargo test --features
+ * bench-metrics. Plans are pure, so none of this needs a GPU.
+ */
+#![cfg(feature = "bench-metrics")]
+
+use rend_vk::bench::{ScenePlan, Summary, SyntheticSceneParams};
+use rend_vk::render_task::TaskKind;
+
+fn small(seed: u64) -> SyntheticSceneParams {
+    SyntheticSceneParams {
+        seed,
+        meshes: 4,
+        triangles_per_mesh: 8,
+        textures: 3,
+        texture_size: 4,
+        tasks_per_kind: 16,
+        kinds: vec![TaskKind::MeshStatic, TaskKind::LightDir],
+    }
+}
+
+#[test]
+fn same_seed_plans_the_same_scene() {
+    let first = ScenePlan::of(&small(7));
+    let again = ScenePlan::of(&small(7));
+    assert_eq!(first, again);
+    assert_eq!(first.hash(), again.hash());
+    let other = ScenePlan::of(&small(8));
+    assert_ne!(first.hash(), other.hash());
+}
+
+#[test]
+fn plans_follow_the_params() {
+    let plan = ScenePlan::of(&small(1));
+    assert_eq!(plan.meshes.len(), 4);
+    for mesh in &plan.meshes {
+        assert_eq!(mesh.indices.len(), 8 * 3);
+        assert_eq!(mesh.positions.len(), 8 * 3 * 3);
+        assert_eq!(mesh.normals.len(), 8 * 3 * 3);
+        assert_eq!(mesh.tex_coords.len(), 8 * 3 * 2);
+    }
+    assert_eq!(plan.textures.len(), 3);
+    assert!(plan.textures.iter().all(|e| e.len() == 4 * 4 * 4));
+    assert_eq!(plan.tasks.len(), 32);
+    let (meshes, fullscreen): (Vec<_>, Vec<_>) = plan
+        .tasks
+        .iter()
+        .partition(|e| e.kind == TaskKind::MeshStatic);
+    assert!(meshes.iter().all(|e| e.mesh.is_some_and(|e| e < 4)));
+    assert!(fullscreen.iter().all(|e| e.mesh.is_none()));
+    assert!(plan.tasks.iter().all(|e| e.texture < 3));
+}
+
+#[test]
+#[should_panic(expected = "need meshes and textures")]
+fn mesh_tasks_without_meshes_panic() {
+    ScenePlan::of(&SyntheticSceneParams {
+        meshes: 0,
+        ..small(1)
+    });
+}
+
+#[test]
+fn summaries_take_the_nearest_rank() {
+    let samples: Vec<f64> = (1..=200).rev().map(f64::from).collect();
+    let summary = Summary::of(&samples);
+    assert_eq!(summary.count, 200);
+    assert_eq!(summary.mean, 100.5);
+    assert_eq!(summary.p95, 190.0);
+    assert_eq!(summary.p99, 198.0);
+    assert_eq!(summary.max, 200.0);
+    assert_eq!(Summary::of(&[3.0]).p99, 3.0);
+    assert_eq!(Summary::of(&[]), Summary::default());
+}
@@ -0,0 +1,82 @@
+/*
+ * What blit passes can't do on any device, checked against the formats of the
+ * attachments in tests/blit.json. No GPU involved.
+ */
+use rend_vk::format::Format;
+use rend_vk::pipeline::file::{Filtering, Pass, Pipeline};
+
+// Color into history, depth into a copy of it
+fn blits() -> Pipeline {
+    Pipeline::read(Some("tests/blit.json"))
+}
+
+fn format_of(pip: &Pipeline, target: &str) -> Format {
+    pip.targets
+        .iter()
+        .find(|e| e.name == target)
+        .unwrap()
+        .format
+}
+
+fn blit<'a>(pip: &'a Pipeline, name: &str) -> &'a Pass {
+    pip.passes.iter().find(|e| e.name == name).unwrap()
+}
+
+#[test]
+fn blits_between_matching_aspects_pass() {
+    let pip = blits();
+    for pass in pip.passes.iter().filter(|e| e.destination.is_some()) {
+        let source = format_of(&pip, &pass.source.as_ref().unwrap().name);
+        let destination = format_of(&pip, pass.destination.as_ref().unwrap());
+        Pipeline::validate_blit_aspects(pass, source, destination);
+    }
+    // Converting color is fine
+    let history = blit(&pip, "history");
+    Pipeline::validate_blit_aspects(history, Format::R8G8B8A8_UNORM, Format::R16G16B16A16_SFLOAT);
+}
+
+#[test]
+#[should_panic(expected = "pass history blits color into history, their aspects")]
+fn color_cant_be_blitted_into_depth() {
+    let pip = blits();
+    Pipeline::validate_blit_aspects(
+        blit(&pip, "history"),
+        Format::R8G8B8A8_UNORM,
+        Format::D32_SFLOAT,
+    );
+}
+
+#[test]
+#[should_panic(expected = "pass depthCopy blits depth into depthCopy, their aspects")]
+fn depth_cant_be_blitted_into_depth_stencil() {
+    let pip = blits();
+    Pipeline::validate_blit_aspects(
+        blit(&pip, "depthCopy"),
+        Format::D32_SFLOAT,
+        Format::D32_SFLOAT_S8_UINT,
+    );
+}
+
+#[test]
+#[should_panic(expected = "blits depth stencil attachment depth with linear filtering")]
+fn depth_blits_cant_filter_linearly() {
+    let mut pip = blits();
+    let pass = pip
+        .passes
+        .iter_mut()
+        .find(|e| e.name == "depthCopy")
+        .unwrap();
+    pass.filter = Filtering::Linear;
+    Pipeline::validate_blit_aspects(pass, Format::D32_SFLOAT, Format::D32_SFLOAT);
+}
+
+#[test]
+#[should_panic(expected = "depth stencil blits can't convert between formats!")]
+fn depth_blits_cant_convert() {
+    let pip = blits();
+    Pipeline::validate_blit_aspects(
+        blit(&pip, "depthCopy"),
+        Format::D32_SFLOAT,
+        Format::D16_UNORM,
+    );
+}
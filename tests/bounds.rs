@@ -0,0 +1,165 @@
+/*
+ * Mesh bounds out of known vertex sets and task spheres derived from them, no GPU
+ * involved.
+ */
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3, Vec4};
+
+use rend_vk::bounds::{merge_spheres, MeshBounds};
+use rend_vk::render_task::{RenderTask, TaskBounds};
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+
+const TOLERANCE: f32 = 1e-5;
+
+fn assert_contains_all(bounds: &MeshBounds, positions: &[Vec3]) {
+    for e in positions {
+        assert!(
+            e.distance(bounds.center) <= bounds.radius,
+            "{} outside of {:?}",
+            e,
+            bounds
+        );
+    }
+}
+
+#[test]
+fn cube_corners() {
+    let mut positions = Vec::new();
+    for i in 0..8 {
+        let bit = |b: i32| if i & (1 << b) != 0 { 1.0 } else { -1.0 };
+        positions.push(Vec3::new(bit(0), bit(1), bit(2)) * Vec3::new(1.0, 2.0, 3.0));
+    }
+    let bounds = MeshBounds::of_positions(&positions).unwrap();
+    assert_eq!(bounds.min, Vec3::new(-1.0, -2.0, -3.0));
+    assert_eq!(bounds.max, Vec3::new(1.0, 2.0, 3.0));
+    assert!(bounds.center.length() < TOLERANCE);
+    assert!((bounds.radius - 14f32.sqrt()).abs() < TOLERANCE);
+    assert_contains_all(&bounds, &positions);
+}
+
+#[test]
+fn interior_points_dont_grow_the_sphere() {
+    // Regular tetrahedron around the origin, circumradius 3
+    let tetrahedron = [
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(1.0, -1.0, -1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+    ]
+    .map(|e| e * 3f32.sqrt());
+    let mut positions = tetrahedron.to_vec();
+    for i in 0..100 {
+        let t = i as f32 * 0.37;
+        positions.push(Vec3::new(t.sin(), t.cos(), (t * 0.5).sin()) * 0.5);
+    }
+    let bounds = MeshBounds::of_positions(&positions).unwrap();
+    assert!(bounds.center.length() < TOLERANCE);
+    assert!((bounds.radius - 3.0).abs() < TOLERANCE);
+    assert_contains_all(&bounds, &positions);
+}
+
+#[test]
+fn two_far_points_span_the_diameter() {
+    let positions = [
+        Vec3::new(-5.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(5.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -2.0),
+    ];
+    let bounds = MeshBounds::of_positions(&positions).unwrap();
+    assert!(bounds.center.length() < TOLERANCE);
+    assert!((bounds.radius - 5.0).abs() < TOLERANCE);
+}
+
+#[test]
+fn obtuse_triangle_uses_its_longest_side() {
+    // The circumscribed circle would be bigger than needed
+    let positions = [
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(1.0, 0.5, 0.0),
+    ];
+    let bounds = MeshBounds::of_positions(&positions).unwrap();
+    assert!(bounds.center.distance(Vec3::new(2.0, 0.0, 0.0)) < TOLERANCE);
+    assert!((bounds.radius - 2.0).abs() < TOLERANCE);
+}
+
+#[test]
+fn collinear_and_repeated_points() {
+    let positions = [
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(2.0, 2.0, 2.0),
+        Vec3::new(2.0, 2.0, 2.0),
+        Vec3::new(3.0, 3.0, 3.0),
+    ];
+    let bounds = MeshBounds::of_positions(&positions).unwrap();
+    assert!(bounds.center.distance(Vec3::splat(2.0)) < TOLERANCE);
+    assert!((bounds.radius - 3f32.sqrt()).abs() < TOLERANCE);
+    let single = MeshBounds::of_positions(&[Vec3::ONE]).unwrap();
+    assert_eq!(single.radius, 0.0);
+    assert!(MeshBounds::of_positions(&[]).is_none());
+}
+
+#[test]
+fn reads_packed_positions() {
+    let positions = [Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 4.0, 2.0)];
+    let bytes: Vec<u8> = positions
+        .iter()
+        .flat_map(|e| e.to_array())
+        .flat_map(|e| e.to_ne_bytes())
+        .collect();
+    let bounds = MeshBounds::of_position_bytes(&bytes).unwrap();
+    assert_eq!(bounds, MeshBounds::of_positions(&positions).unwrap());
+    assert_eq!(bounds.min, Vec3::new(-1.0, 0.0, 2.0));
+    assert_eq!(bounds.max, Vec3::new(1.0, 4.0, 2.0));
+}
+
+#[test]
+fn inflation_scales_around_the_centers() {
+    let bounds = MeshBounds::of_positions(&[Vec3::ZERO, Vec3::new(2.0, 2.0, 0.0)]).unwrap();
+    let inflated = bounds.inflated(1.5);
+    assert_eq!(inflated.min, Vec3::new(-0.5, -0.5, 0.0));
+    assert_eq!(inflated.max, Vec3::new(2.5, 2.5, 0.0));
+    assert_eq!(inflated.center, bounds.center);
+    assert!((inflated.radius - bounds.radius * 1.5).abs() < TOLERANCE);
+}
+
+#[test]
+fn task_sphere_holds_every_instance() {
+    let bounds = MeshBounds::of_positions(&[Vec3::new(-1.0, 0.0, 0.0), Vec3::X]).unwrap();
+    let transform = |mv: Mat4| Transform {
+        mvp: Mat4::IDENTITY,
+        mv,
+    };
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![
+            transform(Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0))),
+            transform(Mat4::from_scale_rotation_translation(
+                Vec3::splat(2.0),
+                Default::default(),
+                Vec3::new(4.0, 0.0, -10.0),
+            )),
+        ]),
+    );
+    let sphere = match RenderTask::bounds_of(&bounds, &resources) {
+        TaskBounds::Sphere(e) => e,
+        e => panic!("no sphere, got {:?}", e),
+    };
+    // From x -1 of the first instance to x 6 of the scaled one
+    assert!(sphere.distance(Vec4::new(2.5, 0.0, -10.0, 3.5)) < TOLERANCE);
+    assert_eq!(
+        RenderTask::bounds_of(&bounds, &HashMap::new()),
+        TaskBounds::None
+    );
+}
+
+#[test]
+fn merged_spheres_keep_the_bigger_one_around_the_smaller() {
+    let big = Vec4::new(0.0, 0.0, 0.0, 5.0);
+    let small = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    assert_eq!(merge_spheres(big, small), big);
+    assert_eq!(merge_spheres(small, big), big);
+}
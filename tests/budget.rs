@@ -0,0 +1,282 @@
+/*
+ * Budgets of pipeline files against made up device limits, no GPU involved.
+ */
+use ash::vk;
+
+use rend_vk::capabilities::DeviceCapabilities;
+use rend_vk::pipeline::budget::{
+    self, AllocatorLimits, BudgetLimits, Overage, MIN_IMAGE_CAPACITY, MIN_SAMPLER_CAPACITY,
+};
+use rend_vk::pipeline::descriptor::DescriptorSizes;
+use rend_vk::pipeline::dry_run::DryRun;
+use rend_vk::pipeline::file::{Pipeline, U32OrF32};
+use rend_vk::render_task::TaskKind;
+use rend_vk::shader_resource::ResourceKind;
+use rend_vk::UsedAsIndex;
+
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 640,
+    height: 480,
+};
+
+fn limits() -> BudgetLimits {
+    BudgetLimits {
+        descriptor_sizes: DescriptorSizes {
+            sampled_image: 32,
+            combined_image_sampler: 48,
+            sampler: 16,
+            uniform_buffer: 64,
+            storage_buffer: 16,
+        },
+        descriptor_allocator: Some(AllocatorLimits {
+            size: 1024 * 1024,
+            alignment: 64,
+        }),
+        general_allocator: AllocatorLimits {
+            size: 64 * 1024 * 1024,
+            alignment: 64,
+        },
+        frame_ring_bytes: 16 * 1024 * 1024,
+        device_local_bytes: 1024 * 1024 * 1024,
+        max_per_stage_descriptor_samplers: 16,
+        max_descriptor_set_samplers: 1024,
+        frames_alive: 1,
+        expected_tasks_per_kind: [10; TaskKind::MAX_LEN],
+    }
+}
+
+fn aligned(bytes: usize) -> u64 {
+    (bytes as u64).next_multiple_of(64)
+}
+
+#[test]
+fn shipped_pipeline_adds_up() {
+    let budget = Pipeline::read(None).budget(EXTENT, &limits()).unwrap();
+    let texels = 640 * 480;
+    let attachments: Vec<_> = budget
+        .attachments
+        .iter()
+        .map(|e| (e.name.as_str(), e.bytes))
+        .collect();
+    assert_eq!(
+        attachments,
+        [
+            ("albedo", texels * 4),
+            ("normal", texels * 4),
+            ("velocity", texels * 4),
+            ("misc", texels * 4),
+            ("lightAcc", texels * 4),
+            ("depth", texels * 4),
+        ]
+    );
+    assert_eq!(budget.attachment_bytes, texels * 24);
+
+    let stages: Vec<_> = budget.stages.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(stages, ["gbuffer", "dirlight", "translucent", "copy"]);
+    // Four inputs of 48 bytes, the only descriptors of a stage
+    assert_eq!(budget.stages[1].descriptor_bytes, 192);
+    assert_eq!(budget.stages[3].descriptor_bytes, 192);
+    assert_eq!(budget.stages[0].descriptor_bytes, 0);
+    assert_eq!(budget.stages[2].descriptor_bytes, 0);
+    // Image table and 12 samplers, 16 per stage minus the 4 attachment inputs
+    assert_eq!(budget.table_descriptor_bytes, 1024 * 32 + 12 * 16);
+    assert_eq!((budget.image_capacity, budget.sampler_capacity), (1024, 12));
+    assert_eq!(budget.image_table_bytes, 1024 * 32);
+    assert_eq!(budget.sampler_table_bytes, aligned(12 * 16));
+    assert_eq!(
+        budget.descriptor_bytes,
+        budget.table_descriptor_bytes + 2 * 192
+    );
+
+    let per_task = aligned(ResourceKind::Transform.resource_size())
+        + aligned(ResourceKind::Material.resource_size())
+        + aligned(ResourceKind::TransformExtra.resource_size());
+    assert_eq!(budget.stages[0].frame_bytes, per_task * 10);
+    let per_pass = ResourceKind::ViewRay.resource_size() + ResourceKind::Frustum.resource_size();
+    // Fullscreen stages reserve per pass once and per task for each of their tasks
+    assert_eq!(budget.stages[3].frame_bytes, aligned(per_pass));
+    assert_eq!(
+        budget.frame_region_bytes,
+        budget.stages.iter().map(|e| e.frame_bytes).sum::<u64>()
+    );
+    assert_eq!(
+        budget.device_bytes,
+        budget.attachment_bytes + 64 * 1024 * 1024 + 1024 * 1024
+    );
+}
+
+#[test]
+fn descriptor_sets_take_no_descriptor_bytes() {
+    let limits = BudgetLimits {
+        descriptor_allocator: None,
+        ..limits()
+    };
+    let budget = Pipeline::read(None).budget(EXTENT, &limits).unwrap();
+    assert_eq!(budget.descriptor_bytes, 0);
+    assert_eq!(budget.descriptor_allocator_bytes, None);
+}
+
+#[test]
+fn huge_descriptors_shrink_the_tables_to_fit() {
+    let limits = BudgetLimits {
+        descriptor_sizes: DescriptorSizes {
+            sampled_image: 2048,
+            combined_image_sampler: 2048,
+            sampler: 4096,
+            uniform_buffer: 2048,
+            storage_buffer: 2048,
+        },
+        max_per_stage_descriptor_samplers: 1024,
+        ..limits()
+    };
+    let budget = Pipeline::read(None).budget(EXTENT, &limits).unwrap();
+    // Half of the allocator for images, an eighth for samplers
+    assert_eq!(budget.image_capacity, 256);
+    assert_eq!(budget.image_table_bytes, 512 * 1024);
+    assert_eq!(budget.sampler_capacity, 32);
+    assert_eq!(budget.sampler_table_bytes, 128 * 1024);
+    assert_eq!(
+        budget.table_descriptor_bytes,
+        budget.image_table_bytes + budget.sampler_table_bytes
+    );
+    assert!(budget.descriptor_bytes <= 1024 * 1024);
+}
+
+#[test]
+fn tables_past_their_floor_are_over_budget() {
+    let limits = BudgetLimits {
+        descriptor_sizes: DescriptorSizes {
+            sampled_image: 4096,
+            ..limits().descriptor_sizes
+        },
+        ..limits()
+    };
+    let error = Pipeline::read(None).budget(EXTENT, &limits).unwrap_err();
+    assert_eq!(error.budget.image_capacity, MIN_IMAGE_CAPACITY);
+    assert_eq!(
+        error.overages,
+        [Overage::Descriptors {
+            bytes: error.budget.descriptor_bytes,
+            available: 1024 * 1024,
+            largest: "the image table".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn table_capacities_fit_the_bytes_they_get() {
+    // Aligned down first, 1000 bytes only hold 960 of 64 byte alignment
+    assert_eq!(budget::table_capacity(1024, 1, 64, 1000, 64), 15);
+    assert_eq!(budget::table_capacity(8, 1, 64, 1000, 64), 8);
+    assert_eq!(budget::table_capacity(1024, 16, 64, 100, 64), 16);
+    // Floors past the capacity don't raise it
+    assert_eq!(
+        budget::table_capacity(4, MIN_SAMPLER_CAPACITY, 64, 0, 64),
+        4
+    );
+    assert_eq!(budget::table_capacity(1024, 1, 0, 0, 64), 1024);
+}
+
+#[test]
+fn memoryless_targets_take_no_memory() {
+    let mut pip = Pipeline::read(None);
+    pip.targets[0].is_memoryless = true;
+    let budget = pip.budget(EXTENT, &limits()).unwrap();
+    assert_eq!(budget.attachments[0].bytes, 0);
+}
+
+#[test]
+fn every_overage_names_what_takes_the_most() {
+    let mut pip = Pipeline::read(None);
+    let normal = pip.targets.iter_mut().find(|e| e.name == "normal").unwrap();
+    normal.width = U32OrF32::U32(16384);
+    normal.height = U32OrF32::U32(16384);
+    let mut tasks = [10; TaskKind::MAX_LEN];
+    tasks[TaskKind::Translucent.to_usize()] = 1024 * 1024;
+    let limits = BudgetLimits {
+        descriptor_allocator: Some(AllocatorLimits {
+            size: 4096,
+            alignment: 64,
+        }),
+        expected_tasks_per_kind: tasks,
+        ..limits()
+    };
+    let error = pip.budget(EXTENT, &limits).unwrap_err();
+    assert_eq!(
+        error.overages,
+        [
+            Overage::Descriptors {
+                bytes: error.budget.descriptor_bytes,
+                available: 4096,
+                largest: "the image table".to_string(),
+            },
+            Overage::FrameRegions {
+                bytes: error.budget.frame_region_bytes,
+                available: 16 * 1024 * 1024,
+                largest: "translucent".to_string(),
+            },
+            Overage::DeviceMemory {
+                bytes: error.budget.device_bytes,
+                available: 1024 * 1024 * 1024,
+                largest: "normal".to_string(),
+            },
+        ]
+    );
+    let message = error.to_string();
+    assert!(message.starts_with("the pipeline doesn't fit the device:"));
+    assert!(message.contains("most of them normal"));
+}
+
+#[test]
+fn disabled_passes_take_nothing() {
+    let mut pip = Pipeline::read(None);
+    for pass in pip.passes.iter_mut().filter(|e| e.name != "gbuffer") {
+        pass.is_disabled = true;
+    }
+    let budget = pip.budget(EXTENT, &limits()).unwrap();
+    let stages: Vec<_> = budget.stages.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(stages, ["gbuffer"]);
+}
+
+#[test]
+fn dry_runs_check_the_same_budget() {
+    let run = DryRun::with_limits(
+        Pipeline::read(None),
+        &DeviceCapabilities::default(),
+        EXTENT,
+        false,
+        &limits(),
+    );
+    assert_eq!(
+        *run.budget(),
+        Pipeline::read(None).budget(EXTENT, &limits()).unwrap()
+    );
+}
+
+#[test]
+#[should_panic(expected = "the pipeline doesn't fit the device")]
+fn dry_runs_panic_over_budget() {
+    let limits = BudgetLimits {
+        device_local_bytes: 1024,
+        ..limits()
+    };
+    DryRun::with_limits(
+        Pipeline::read(None),
+        &DeviceCapabilities::default(),
+        EXTENT,
+        false,
+        &limits,
+    );
+}
+
+#[test]
+fn budgets_serialize_in_camel_case() {
+    let budget = Pipeline::read(None).budget(EXTENT, &limits()).unwrap();
+    let json = serde_json::to_value(&budget).unwrap();
+    assert_eq!(
+        json["attachments"][0]["extent"],
+        serde_json::json!([640, 480])
+    );
+    assert!(json["tableDescriptorBytes"].is_u64());
+    assert!(json["stages"][0]["frameBytes"].is_u64());
+}
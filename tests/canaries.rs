@@ -0,0 +1,192 @@
+/*
+ * Guards and poisoned ranges of the alloc-canaries feature. The table gets checked on
+ * its own against host memory and a range allocator the way DeviceAllocator drives it,
+ * then once through an allocation scope of a renderer on a headless surface.
+ */
+#![cfg(feature = "alloc-canaries")]
+
+use ash::{extensions::ext::HeadlessSurface, vk};
+
+use rend_vk::canary::{CanaryError, CanaryTable, FREED, FRESH, GUARD, GUARD_SIZE};
+use rend_vk::range_allocator::RangeAllocator;
+use rend_vk::renderer;
+
+const ALIGNMENT: u64 = 16;
+
+/*
+ * Buffer of 1024 bytes in host memory, allocating and freeing like DeviceAllocator does.
+ */
+struct Buffer {
+    memory: Vec<u8>,
+    ranges: RangeAllocator,
+    canaries: CanaryTable,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Self {
+            memory: vec![0; 1024],
+            ranges: RangeAllocator::new(1024),
+            canaries: CanaryTable::default(),
+        }
+    }
+
+    fn alloc(&mut self, size: u64, owner: &str) -> u64 {
+        let offset = self
+            .ranges
+            .alloc(CanaryTable::reserved_size(size), ALIGNMENT)
+            .unwrap();
+        self.canaries
+            .allocated(&mut self.memory, offset, size, owner.to_string());
+        offset
+    }
+
+    fn free(&mut self, offset: u64, size: u64) -> Result<(), CanaryError> {
+        let checked = self.canaries.freed(&mut self.memory, offset);
+        self.ranges.free(offset, CanaryTable::reserved_size(size));
+        checked
+    }
+
+    fn check(&mut self) -> Vec<CanaryError> {
+        self.canaries.check(&mut self.memory)
+    }
+}
+
+fn floats_of(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|e| f32::from_ne_bytes(e.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn guards_sit_right_after_the_slice() {
+    let mut buffer = Buffer::new();
+    let a = buffer.alloc(32, "a");
+    let b = buffer.alloc(32, "b");
+    // The slices keep their size and alignment, the guard only pushes the next one
+    assert_eq!(a % ALIGNMENT, 0);
+    assert_eq!(b % ALIGNMENT, 0);
+    assert!(b >= a + 32 + GUARD_SIZE);
+    let end = (a + 32) as usize;
+    assert_eq!(buffer.memory[end..end + GUARD_SIZE as usize], GUARD);
+    assert!(buffer.check().is_empty());
+}
+
+#[test]
+fn overruns_name_the_slice_and_its_neighbour() {
+    let mut buffer = Buffer::new();
+    let lights = buffer.alloc(48, "lights");
+    buffer.alloc(64, "materials");
+    // Stride math one vec4 short, the last write lands past the end
+    let end = (lights + 48) as usize;
+    buffer.memory[end + 4..end + 8].copy_from_slice(&1.0f32.to_ne_bytes());
+    let expected = CanaryError::Trampled {
+        owner: "lights".to_string(),
+        offset: lights,
+        size: 48,
+        byte: 4,
+        next: Some("materials".to_string()),
+    };
+    assert_eq!(buffer.check(), [expected.clone()]);
+    assert_eq!(
+        expected.to_string(),
+        "guard of the 48 bytes at 0 from lights overwritten at byte 4, written past the \
+        end of it and maybe into the one from materials"
+    );
+    // Reported once, the guard is armed again
+    assert!(buffer.check().is_empty());
+    buffer.memory[end] = 0;
+    assert_eq!(
+        buffer.free(lights, 48),
+        Err(CanaryError::Trampled {
+            owner: "lights".to_string(),
+            offset: lights,
+            size: 48,
+            byte: 0,
+            next: Some("materials".to_string()),
+        })
+    );
+}
+
+#[test]
+fn writes_within_the_slice_are_fine() {
+    let mut buffer = Buffer::new();
+    let a = buffer.alloc(32, "a");
+    buffer.memory[a as usize..(a + 32) as usize].fill(0xff);
+    assert!(buffer.check().is_empty());
+    assert_eq!(buffer.free(a, 32), Ok(()));
+}
+
+#[test]
+fn fresh_and_freed_ranges_are_poisoned() {
+    let mut buffer = Buffer::new();
+    let a = buffer.alloc(32, "a");
+    let range = a as usize..(a + 32) as usize;
+    assert_eq!(
+        floats_of(&buffer.memory[range.clone()]),
+        [FRESH, FRESH].concat()
+    );
+    buffer.free(a, 32).unwrap();
+    // Guard included
+    let reserved = a as usize..(a + 32 + GUARD_SIZE) as usize;
+    assert_eq!(floats_of(&buffer.memory[reserved]), [FREED; 3].concat());
+    assert_ne!(FRESH, FREED);
+}
+
+#[test]
+fn writes_after_free_get_caught() {
+    let mut buffer = Buffer::new();
+    let a = buffer.alloc(32, "particles");
+    buffer.free(a, 32).unwrap();
+    buffer.memory[a as usize + 20] = 7;
+    assert_eq!(
+        buffer.check(),
+        [CanaryError::WrittenAfterFree {
+            owner: "particles".to_string(),
+            offset: a,
+            size: 32 + GUARD_SIZE,
+            byte: 20,
+        }]
+    );
+    assert!(buffer.check().is_empty());
+    // Allocated over, the range is no one's to watch anymore
+    let b = buffer.alloc(16, "sparks");
+    assert_eq!(a, b);
+    buffer.memory[b as usize] = 7;
+    assert!(buffer.check().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "no allocation at 512 to free")]
+fn freeing_unknown_offsets_panics() {
+    Buffer::new().free(512, 16).unwrap();
+}
+
+#[test]
+fn renderers_report_where_the_slice_was_allocated() {
+    let extensions = [
+        vk::KhrSurfaceFn::name().as_ptr(),
+        HeadlessSurface::name().as_ptr(),
+    ];
+    let mut renderer = renderer::make_renderer(false, true, true, &extensions, |entry, e| {
+        let info = vk::HeadlessSurfaceCreateInfoEXT::default();
+        unsafe { HeadlessSurface::new(entry, e).create_headless_surface(&info, None) }
+    });
+    let scope = renderer.create_allocation_scope("overrun", 4096);
+    let slice = scope.alloc(100).unwrap();
+    assert_eq!(slice.size % slice.alignment, 0);
+    assert!(renderer.check_canaries().is_empty());
+    unsafe { *(slice.addr as *mut u8).add(slice.size as usize) = 0 };
+    match renderer.check_canaries().as_slice() {
+        [CanaryError::Trampled { owner, size, .. }] => {
+            assert!(owner.starts_with("tests/canaries.rs:"), "{}", owner);
+            assert!(owner.ends_with(" in scope 'overrun'"), "{}", owner);
+            assert_eq!(*size, slice.size);
+        }
+        errors => panic!("{:?}", errors),
+    }
+    scope.free(slice);
+    scope.destroy_scope();
+    renderer.destroy();
+}
@@ -0,0 +1,129 @@
+/*
+ * Matching what pipeline files require against synthetic device capabilities, no GPU
+ * involved.
+ */
+use rend_vk::capabilities::{DeviceCapabilities, DeviceFeature, MissingFeatures};
+use rend_vk::pipeline::{file::Pipeline, hints::OptimizationHint};
+
+fn pipeline_of(json: &str) -> Pipeline {
+    serde_json::from_str(json).unwrap()
+}
+
+fn sample_pipeline() -> Pipeline {
+    pipeline_of(
+        r#"{
+            "targets": [],
+            "programs": [],
+            "requires": ["multiview"],
+            "passes": [
+                { "name": "gbuffer", "requires": ["depthClamp"] },
+                { "name": "vrs", "requires": ["fragmentShadingRate", "shadingRateAttachment"] },
+                { "name": "outline", "requires": ["wideLines"], "optional": true },
+                { "name": "shadow", "requires": ["depthClamp", "depthBounds"] },
+                { "name": "debug", "requires": ["wideLines"], "isDisabled": true },
+                { "name": "present" }
+            ]
+        }"#,
+    )
+}
+
+fn disabled(pipeline: &Pipeline) -> Vec<&str> {
+    pipeline
+        .passes
+        .iter()
+        .filter(|e| e.is_disabled)
+        .map(|e| e.name.as_str())
+        .collect()
+}
+
+#[test]
+fn every_missing_feature_is_listed_once() {
+    let capabilities = DeviceCapabilities {
+        is_shading_rate_enabled: true,
+        is_depth_bounds_enabled: true,
+        ..Default::default()
+    };
+    let mut pipeline = sample_pipeline();
+    let error = pipeline.match_requirements(&capabilities).unwrap_err();
+    assert_eq!(
+        error,
+        MissingFeatures {
+            missing: vec![
+                (DeviceFeature::Multiview, vec!["the pipeline".to_string()]),
+                (
+                    DeviceFeature::ShadingRateAttachment,
+                    vec!["vrs".to_string()]
+                ),
+                (
+                    DeviceFeature::DepthClamp,
+                    vec!["gbuffer".to_string(), "shadow".to_string()]
+                ),
+            ]
+        }
+    );
+    let message = error.to_string();
+    assert!(
+        message.contains("depthClamp for gbuffer, shadow"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn optional_stages_get_disabled() {
+    let capabilities = DeviceCapabilities {
+        is_multiview_enabled: true,
+        is_shading_rate_enabled: true,
+        shading_rate_texel_size: Some(ash::vk::Extent2D {
+            width: 16,
+            height: 16,
+        }),
+        is_depth_bounds_enabled: true,
+        is_depth_clamp_enabled: true,
+        ..Default::default()
+    };
+    let mut pipeline = sample_pipeline();
+    let hints = pipeline.match_requirements(&capabilities).unwrap();
+    assert_eq!(
+        hints,
+        [OptimizationHint::MissingFeatures {
+            stage: "outline".to_string(),
+            features: vec!["wideLines".to_string()],
+        }]
+    );
+    assert_eq!(disabled(&pipeline), ["outline", "debug"]);
+
+    // With everything there nothing changes
+    let mut pipeline = sample_pipeline();
+    let capabilities = DeviceCapabilities {
+        is_wide_lines_enabled: true,
+        ..capabilities
+    };
+    assert_eq!(pipeline.match_requirements(&capabilities), Ok(Vec::new()));
+    assert_eq!(disabled(&pipeline), ["debug"]);
+}
+
+#[test]
+fn unknown_features_fail_to_parse() {
+    let json = r#"{ "targets": [], "programs": [], "passes": [], "requires": ["rayTracing"] }"#;
+    assert!(serde_json::from_str::<Pipeline>(json).is_err());
+}
+
+#[test]
+fn capabilities_serialize_under_the_required_names() {
+    let capabilities = DeviceCapabilities {
+        is_depth_clamp_enabled: true,
+        shading_rate_texel_size: Some(ash::vk::Extent2D {
+            width: 8,
+            height: 16,
+        }),
+        ..Default::default()
+    };
+    let value = serde_json::to_value(capabilities).unwrap();
+    assert_eq!(value["depthClamp"], true);
+    assert_eq!(value["wideLines"], false);
+    assert_eq!(value["shadingRateTexelSize"], serde_json::json!([8, 16]));
+    let json = format!(r#"["{}"]"#, DeviceFeature::DepthClamp);
+    let features: Vec<DeviceFeature> = serde_json::from_str(&json).unwrap();
+    assert!(capabilities.has(features[0]));
+}
@@ -0,0 +1,134 @@
+/*
+ * Frame capture and debug labels. Nothing injects RenderDoc into the test process, so
+ * captures are unavailable whether the renderdoc feature is on or not. The labeled
+ * frames render on a headless surface with debug and validation on, so does the state
+ * captured by one renderer and replayed by another.
+ */
+
+mod common;
+
+use rend_vk::capture::{CaptureFormatError, CaptureUnavailable, CapturedState, FrameCapture};
+use rend_vk::format::Format;
+use rend_vk::handle::{StaleHandle, TextureHandle};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::texture::MipMap;
+
+fn fill() -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 1.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn captures_without_renderdoc_are_unavailable() {
+    let capture = FrameCapture::new();
+    assert!(!capture.is_available());
+    assert_eq!(capture.trigger(1), Err(CaptureUnavailable));
+    assert_eq!(
+        CaptureUnavailable.to_string(),
+        "frame capture unavailable, RenderDoc isn't loaded"
+    );
+}
+
+#[test]
+fn labeled_frames_render_cleanly() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    assert_eq!(renderer.trigger_capture(1), Err(CaptureUnavailable));
+    for is_verbose in [false, true] {
+        renderer.set_verbose_labels(is_verbose);
+        renderer.add_task_to_queue(fill());
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    }
+    common::finish(renderer);
+}
+
+#[test]
+fn captured_state_goes_through_json() {
+    let state = CapturedState {
+        version: CapturedState::VERSION,
+        frame: 12,
+        sampler_overrides: vec![(40, 0), (45, 1)],
+        inspected_texture: Some(45),
+    };
+    assert_eq!(
+        CapturedState::from_json(&state.to_json()),
+        Ok(state.clone())
+    );
+    let other = CapturedState {
+        version: CapturedState::VERSION + 1,
+        ..state
+    };
+    assert_eq!(
+        CapturedState::from_json(&other.to_json()),
+        Err(CaptureFormatError::Version(CapturedState::VERSION + 1))
+    );
+    assert!(matches!(
+        CapturedState::from_json("{"),
+        Err(CaptureFormatError::Syntax(_))
+    ));
+}
+
+const TEXTURE_IDS: [u32; 2] = [40, 45];
+
+// Renderer with textures at the same ids every time, and their handles
+fn make_renderer_with_textures() -> (Renderer, Vec<TextureHandle>) {
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 4 * 4 * 4,
+        offset: 0,
+    }];
+    let handles = TEXTURE_IDS
+        .iter()
+        .map(|id| {
+            let name = format!("texture {}", id);
+            let format = Format::R8G8B8A8_UNORM;
+            renderer
+                .gen_texture_with_id(*id, name, format, &mip_maps, 0)
+                .unwrap()
+        })
+        .collect();
+    (renderer, handles)
+}
+
+#[test]
+fn replays_take_the_sampler_overrides_of_the_capture() {
+    let _serial = common::serial();
+    let (mut captured, handles) = make_renderer_with_textures();
+    captured
+        .override_texture_sampler(handles[1], Some(1))
+        .unwrap();
+    captured.set_texture_inspector(Some(handles[1])).unwrap();
+    assert_eq!(captured.render(), FrameOutcome::Submitted);
+    let state = captured.capture_state();
+    assert_eq!(state.sampler_overrides, [(TEXTURE_IDS[1], 1)]);
+    assert_eq!(state.inspected_texture, Some(TEXTURE_IDS[1]));
+    common::finish(captured);
+
+    let state = CapturedState::from_json(&state.to_json()).unwrap();
+    let (mut replayed, handles) = make_renderer_with_textures();
+    // Replaced by the captured ones
+    replayed
+        .override_texture_sampler(handles[0], Some(0))
+        .unwrap();
+    replayed.replay_state(&state).unwrap();
+    let replayed_state = replayed.capture_state();
+    assert_eq!(replayed_state.sampler_overrides, state.sampler_overrides);
+    assert_eq!(replayed_state.inspected_texture, state.inspected_texture);
+    assert_eq!(replayed.render(), FrameOutcome::Submitted);
+
+    // Naming a texture the replaying renderer doesn't have changes nothing
+    let missing = CapturedState {
+        sampler_overrides: vec![(TEXTURE_IDS[1] + 1, 0)],
+        inspected_texture: None,
+        ..state.clone()
+    };
+    assert_eq!(replayed.replay_state(&missing), Err(StaleHandle));
+    assert_eq!(replayed.capture_state(), replayed_state);
+    common::finish(replayed);
+}
@@ -0,0 +1,133 @@
+/*
+ * Fixtures of the tests that need a device: a logger counting the errors the validation
+ * layer reports and headless renderers with validation on. Included with mod common,
+ * not every test uses all of it.
+ */
+#![allow(dead_code)]
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+
+use ash::{extensions::ext::HeadlessSurface, vk};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::render_core::RenderCore;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{self, Renderer};
+
+// One renderer at a time, the validation counter is global
+static SERIAL: Mutex<()> = Mutex::new(());
+static VALIDATION_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+struct ValidationCounter;
+
+impl log::Log for ValidationCounter {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        // The debug callback logs the severity first
+        if record.args().to_string().starts_with("ERROR") {
+            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ValidationCounter = ValidationCounter;
+
+///
+/// Held for the whole test, a test that panicked holding it doesn't fail the others.
+///
+pub fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn validation_errors() -> u32 {
+    VALIDATION_ERRORS.load(Ordering::Relaxed)
+}
+
+pub fn reset_validation_errors() {
+    VALIDATION_ERRORS.store(0, Ordering::Relaxed);
+}
+
+///
+/// Installs the validation counter, the first call in the process wins.
+///
+pub fn install_logger() {
+    let _ = log::set_logger(&LOGGER).map(|_| log::set_max_level(log::LevelFilter::Debug));
+}
+
+pub fn headless_extensions() -> [*const i8; 2] {
+    [
+        vk::KhrSurfaceFn::name().as_ptr(),
+        HeadlessSurface::name().as_ptr(),
+    ]
+}
+
+///
+/// Surface callback of Renderer::with_core.
+///
+pub fn headless_surface(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+) -> Result<vk::SurfaceKHR, vk::Result> {
+    let info = vk::HeadlessSurfaceCreateInfoEXT::default();
+    unsafe { HeadlessSurface::new(entry, instance).create_headless_surface(&info, None) }
+}
+
+///
+/// Core with debug and validation on that can present to headless surfaces.
+///
+pub fn make_core(config: &RendererConfig) -> Arc<RenderCore> {
+    install_logger();
+    renderer::make_render_core(config, true, true, &headless_extensions())
+}
+
+///
+/// Headless renderer owning its core, with the pipeline file and an output image of the
+/// size given. Validation errors are counted from before it's made, making it counts.
+///
+pub fn make_renderer_with(
+    config: RendererConfig,
+    pipeline: &str,
+    width: u32,
+    height: u32,
+) -> Renderer {
+    let core = make_core(&config);
+    reset_validation_errors();
+    Renderer::with_core_headless(core, config, pipeline, width, height)
+}
+
+///
+/// Single instance of the test triangle, what most tests draw. Anything else goes through
+/// struct update syntax.
+///
+pub fn task(kind: TaskKind) -> RenderTask {
+    RenderTask::new(Renderer::TEST_TRIANGLE, kind)
+}
+
+pub fn make_renderer(pipeline: &str, width: u32, height: u32) -> Renderer {
+    make_renderer_with(RendererConfig::default(), pipeline, width, height)
+}
+
+///
+/// Destroys the renderer, nothing may have been reported since it was made.
+///
+pub fn finish(mut renderer: Renderer) {
+    renderer.destroy();
+    assert_eq!(validation_errors(), 0);
+}
+
+///
+/// Destroys the renderer, the object tracker reports what it leaked.
+///
+pub fn finish_without_leaks(mut renderer: Renderer) {
+    reset_validation_errors();
+    renderer.destroy();
+    assert_eq!(validation_errors(), 0, "leaked objects");
+}
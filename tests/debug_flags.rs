@@ -0,0 +1,170 @@
+/*
+ * Debug flag words, their names and where they land in the StageConstants block. The
+ * stage of tests/debug_flags.json writes the low 32 bits of its debug flags into every
+ * pixel, read back on a headless surface to see which frame picked up a change.
+ */
+use std::{sync::Mutex, time::Duration};
+
+use ash::{extensions::ext::HeadlessSurface, vk};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::debug_flags::{DebugFlag, DebugFlags, MAX_FLAGS};
+use rend_vk::pipeline::file::Pass;
+use rend_vk::pipeline::per_draw::PerDrawLayout;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{self, FrameOutcome, Renderer};
+use rend_vk::stage_constants::{StageConstants, UnknownConstant};
+
+// One renderer at a time
+static SERIAL: Mutex<()> = Mutex::new(());
+
+const SIZE: u32 = 16;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn pass_of(json: &str) -> Pass {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn names_keep_their_bits() {
+    let mut flags = DebugFlags::default();
+    assert_eq!(flags.register("showNormals"), 0);
+    assert_eq!(flags.register("noShadows"), 1);
+    assert_eq!(flags.register("showNormals"), 0);
+    assert_eq!(flags.bit("noShadows"), Some(1));
+    assert_eq!(flags.bit("overdraw"), None);
+    flags.global = 0b10;
+    assert_eq!(
+        flags.list(),
+        [
+            DebugFlag {
+                name: "showNormals".to_string(),
+                bit: 0,
+                is_set_globally: false,
+            },
+            DebugFlag {
+                name: "noShadows".to_string(),
+                bit: 1,
+                is_set_globally: true,
+            },
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "all 64 are taken")]
+fn names_run_out_after_64() {
+    let mut flags = DebugFlags::default();
+    for i in 0..MAX_FLAGS {
+        assert_eq!(flags.register(&format!("flag{}", i)), i);
+    }
+    flags.register("oneTooMany");
+}
+
+#[test]
+fn passes_can_ask_for_a_block_of_just_the_flags() {
+    let pass = pass_of(r#"{ "name": "debug", "debugFlags": true }"#);
+    let mut constants = StageConstants::of_pass(&pass);
+    assert!(!constants.is_empty());
+    assert_eq!(constants.debug_flags_offset(), 0);
+    assert_eq!(constants.size(), 8);
+    constants.debug_flags = 0x1234_5678_9abc_def0;
+    assert_eq!(constants.to_bytes(), 0x1234_5678_9abc_def0u64.to_ne_bytes());
+    assert!(PerDrawLayout::of_pass(&pass).member("constants").is_some());
+    // No block otherwise, the registers stay as they were
+    let pass = pass_of(r#"{ "name": "plain" }"#);
+    assert!(StageConstants::of_pass(&pass).is_empty());
+    assert!(PerDrawLayout::of_pass(&pass).member("constants").is_none());
+}
+
+#[test]
+fn flags_follow_the_constants_aligned() {
+    let pass = pass_of(
+        r#"{
+            "name": "ssao",
+            "constants": [{ "name": "radius", "type": "float", "default": 0.5 }]
+        }"#,
+    );
+    let mut constants = StageConstants::of_pass(&pass);
+    constants.debug_flags = 1 << 40;
+    assert_eq!(constants.debug_flags_offset(), 8);
+    let bytes = constants.to_bytes();
+    assert_eq!(bytes.len(), 16);
+    assert_eq!(bytes[0..4], 0.5f32.to_ne_bytes());
+    assert_eq!(bytes[4..8], [0; 4]);
+    assert_eq!(bytes[8..16], (1u64 << 40).to_ne_bytes());
+}
+
+fn make_renderer() -> Renderer {
+    let extensions = [
+        vk::KhrSurfaceFn::name().as_ptr(),
+        HeadlessSurface::name().as_ptr(),
+    ];
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, true, true, &extensions);
+    let mut renderer =
+        Renderer::with_core(core, config, "tests/debug_flags.json", false, |entry, e| {
+            let info = vk::HeadlessSurfaceCreateInfoEXT::default();
+            unsafe { HeadlessSurface::new(entry, e).create_headless_surface(&info, None) }
+        });
+    renderer.resize(SIZE, SIZE);
+    renderer
+}
+
+fn assert_every_pixel(bytes: &[u8], flags: u32) {
+    assert_eq!(bytes.len() as u32, SIZE * SIZE * 4);
+    for (i, pixel) in bytes.chunks_exact(4).enumerate() {
+        assert_eq!(pixel, flags.to_le_bytes(), "pixel {}", i);
+    }
+}
+
+#[test]
+fn shaders_see_changes_from_the_next_frame_on() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let mut renderer = make_renderer();
+    assert_eq!(
+        renderer.set_debug_flags(Some("missing"), 1),
+        Err(UnknownConstant::Stage("missing".to_string()))
+    );
+    let bit = renderer.register_debug_flag("showOverdraw");
+    let flags = 0x0a0b_0c00 | 1 << bit;
+    renderer
+        .set_debug_flags(Some("debug"), flags as u64)
+        .unwrap();
+    renderer.add_task_to_queue(RenderTask::new(
+        Renderer::TEST_TRIANGLE,
+        TaskKind::Fullscreen,
+    ));
+    let mut first = renderer.read_attachment("flags").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    // Toggled after the frame was prepared, only the next one sees it
+    renderer
+        .set_debug_flags(Some("debug"), (flags ^ 1 << bit) as u64)
+        .unwrap();
+    assert_eq!(renderer.debug_flags(Some("debug")), Ok(0x0a0b_0c00));
+    assert_every_pixel(&first.resolve_wait(&renderer, TIMEOUT).unwrap(), flags);
+    renderer.add_task_to_queue(RenderTask::new(
+        Renderer::TEST_TRIANGLE,
+        TaskKind::Fullscreen,
+    ));
+    let mut second = renderer.read_attachment("flags").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_every_pixel(
+        &second.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        0x0a0b_0c00,
+    );
+    // The global word doesn't reach the stage's
+    renderer.set_debug_flags(None, u64::MAX).unwrap();
+    assert_eq!(renderer.debug_flags(None), Ok(u64::MAX));
+    renderer.add_task_to_queue(RenderTask::new(
+        Renderer::TEST_TRIANGLE,
+        TaskKind::Fullscreen,
+    ));
+    let mut third = renderer.read_attachment("flags").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_every_pixel(
+        &third.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        0x0a0b_0c00,
+    );
+    renderer.destroy();
+}
@@ -0,0 +1,83 @@
+/*
+ * Reverse-Z on a headless surface with validation on. The scene stage of
+ * tests/depth_convention.json draws two overlapping triangles at different depths, the
+ * reverse file is the same with "depthConvention": "reverse". Given transforms that flip
+ * the depth, both conventions have to produce the same image.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const NEAR_GRAY: f32 = 0.25;
+const FAR_GRAY: f32 = 0.75;
+
+// Half sized test triangle at x and depth z, filled with the gray
+fn triangle(x: f32, z: f32, gray: f32, projection: Mat4) -> RenderTask {
+    let mvp = projection
+        * Mat4::from_translation(Vec3::new(x, 0.0, z))
+        * Mat4::from_scale(Vec3::new(0.5, 0.5, 1.0));
+    RenderTask {
+        resources: [(
+            ResourceKind::Transform,
+            MultiResource::Transform(vec![Transform { mvp, mv: mvp }]),
+        )]
+        .into(),
+        alpha_cutoff: gray,
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+/*
+ * Color and depth of the scene drawn with the pipeline, the far triangle first so only
+ * the depth test keeps it from covering the near one.
+ */
+fn render(pipeline: &str, projection: Mat4) -> (Vec<u8>, Vec<f32>) {
+    let mut renderer = common::make_renderer(pipeline, SIZE, SIZE);
+    renderer.add_task_to_queue(triangle(0.25, 0.75, FAR_GRAY, projection));
+    renderer.add_task_to_queue(triangle(-0.25, 0.25, NEAR_GRAY, projection));
+    let mut color = renderer.read_attachment("color").unwrap();
+    let mut depth = renderer.read_attachment("depth").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let color = color.resolve_wait(&renderer, TIMEOUT).unwrap();
+    let depth = depth
+        .resolve_wait(&renderer, TIMEOUT)
+        .unwrap()
+        .chunks_exact(4)
+        .map(|e| f32::from_le_bytes([e[0], e[1], e[2], e[3]]))
+        .collect();
+    common::finish(renderer);
+    (color, depth)
+}
+
+#[test]
+fn both_conventions_render_the_same_image() {
+    let _serial = common::serial();
+    let (standard, standard_depth) = render("tests/depth_convention.json", Mat4::IDENTITY);
+    // Depth goes from z to 1 - z
+    let flip = Mat4::from_translation(Vec3::Z) * Mat4::from_scale(Vec3::new(1.0, 1.0, -1.0));
+    let (reverse, reverse_depth) = render("tests/depth_convention_reverse.json", flip);
+    assert_eq!(standard.len() as u32, SIZE * SIZE * 4);
+    assert_eq!(standard, reverse);
+    for (i, (standard, reverse)) in standard_depth.iter().zip(&reverse_depth).enumerate() {
+        assert!(
+            (1.0 - standard - reverse).abs() < 1e-6,
+            "depth {} and {} at pixel {}",
+            standard,
+            reverse,
+            i
+        );
+    }
+    // Along the wide edge, left of both, over both and right of both
+    let gray_at = |x: u32| standard[((44 * SIZE + x) * 4) as usize];
+    assert_ne!(gray_at(10), gray_at(54));
+    assert_eq!(gray_at(32), gray_at(10));
+}
@@ -0,0 +1,174 @@
+/*
+ * Depth pyramids of tests/depth_pyramid.json, traced without a device and built on a
+ * headless surface with validation on. The pattern stage hashes every pixel into its
+ * depth, the mips read back have to match reducing the depth read back on the CPU.
+ * Validation errors logged by the object tracker on destroy count as leaks.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::capabilities::DeviceCapabilities;
+use rend_vk::handle::MeshHandle;
+use rend_vk::pipeline::depth_pyramid::mip_extents;
+use rend_vk::pipeline::dry_run::{DryMesh, DryRun};
+use rend_vk::pipeline::file::{Pipeline, Reduction};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::Renderer;
+
+const PIPELINE: &str = "tests/depth_pyramid.json";
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 64,
+    height: 64,
+};
+const QUAD: MeshHandle = MeshHandle {
+    index: 0,
+    generation: 0,
+};
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer(PIPELINE, EXTENT.width, EXTENT.height)
+}
+
+/*
+ * Next mip of the texels, each one keeping the min or max of the 2x2 it covers. The
+ * last row and column take the one left over of odd sizes along.
+ */
+fn reduce(texels: &[f32], extent: [u32; 2], reduction: Reduction) -> Vec<f32> {
+    let [width, height] = extent;
+    let next = [(width / 2).max(1), (height / 2).max(1)];
+    let span = |texel: u32, size: u32, next_size: u32| {
+        let first = texel * 2;
+        let is_last = texel == next_size - 1;
+        let last = first + 1 + (is_last && size % 2 == 1) as u32;
+        first..=last.min(size - 1)
+    };
+    let mut reduced = Vec::with_capacity((next[0] * next[1]) as usize);
+    for y in 0..next[1] {
+        for x in 0..next[0] {
+            let values = span(y, height, next[1]).flat_map(|sy| {
+                span(x, width, next[0]).map(move |sx| texels[(sy * width + sx) as usize])
+            });
+            reduced.push(match reduction {
+                Reduction::Min => values.fold(f32::INFINITY, f32::min),
+                Reduction::Max => values.fold(f32::NEG_INFINITY, f32::max),
+            });
+        }
+    }
+    reduced
+}
+
+fn floats_of(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|e| f32::from_ne_bytes(e.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn mips_halve_down_to_a_single_texel() {
+    assert_eq!(
+        mip_extents(61, 37),
+        [[61, 37], [30, 18], [15, 9], [7, 4], [3, 2], [1, 1]]
+    );
+    assert_eq!(mip_extents(1, 8), [[1, 8], [1, 4], [1, 2], [1, 1]]);
+    assert_eq!(mip_extents(1, 1), [[1, 1]]);
+}
+
+#[test]
+fn odd_sizes_fold_the_leftover_texels_into_the_last_ones() {
+    let texels: Vec<f32> = (0..15).map(|e| e as f32).collect();
+    // 5x3 into 2x1, the last texel covering the three right columns
+    assert_eq!(reduce(&texels, [5, 3], Reduction::Max), [11.0, 14.0]);
+    assert_eq!(reduce(&texels, [5, 3], Reduction::Min), [0.0, 2.0]);
+    assert_eq!(reduce(&[4.0, 2.0, 3.0], [3, 1], Reduction::Min), [2.0]);
+}
+
+#[test]
+fn frames_trace_the_pyramid_between_its_transitions() {
+    let pip = Pipeline::read(Some(PIPELINE));
+    let mut run = DryRun::new(pip, &DeviceCapabilities::default(), EXTENT, false);
+    let meshes = HashMap::from([(
+        QUAD.index,
+        DryMesh {
+            count: 3,
+            is_indexed: false,
+        },
+    )]);
+    let tasks = || vec![RenderTask::new(QUAD, TaskKind::Fullscreen)];
+    let first = run.frame(tasks(), &meshes);
+    assert!(first.warnings.is_empty(), "{:?}", first.warnings);
+    let lines = first.lines();
+    let pyramid: Vec<_> = lines.iter().filter(|e| e.starts_with("hiZ:")).collect();
+    assert_eq!(
+        pyramid,
+        [
+            "hiZ: wait 1",
+            "hiZ: transition depth ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL",
+            "hiZ: transition hiZ UNDEFINED -> GENERAL",
+            "hiZ: depth pyramid depth -> hiZ 6 mips",
+            "hiZ: transition depth READ_ONLY_OPTIMAL -> READ_ONLY_OPTIMAL",
+            "hiZ: transition hiZ GENERAL -> READ_ONLY_OPTIMAL",
+            "hiZ: signal 4",
+        ]
+    );
+    // Left sampleable, the present stage only waits on it
+    assert!(!lines
+        .iter()
+        .any(|e| e.starts_with("present: transition hiZ")));
+    let second = run.frame(tasks(), &meshes);
+    assert!(second
+        .lines()
+        .contains(&"hiZ: transition hiZ UNDEFINED -> GENERAL".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "only depth pyramids can write it")]
+fn mip_chained_targets_only_take_pyramid_writes() {
+    let mut pip = Pipeline::read(Some(PIPELINE));
+    let present = pip.passes.iter_mut().find(|e| e.name == "present").unwrap();
+    present.inputs.clear();
+    present.outputs[0].name = "hiZ".to_string();
+    DryRun::new(pip, &DeviceCapabilities::default(), EXTENT, false);
+}
+
+#[test]
+fn pyramid_mips_match_a_cpu_reduction() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let texture_id = renderer.attachment_texture_id("hiZ");
+    assert!(texture_id.is_some());
+    assert_eq!(renderer.attachment_texture_id("hiZ"), texture_id);
+    assert_eq!(renderer.attachment_texture_id("default"), None);
+
+    for _ in 0..2 {
+        renderer.add_task_to_queue(common::task(TaskKind::Fullscreen));
+        renderer.render();
+    }
+    renderer.add_task_to_queue(common::task(TaskKind::Fullscreen));
+    let extents = mip_extents(61, 37);
+    let mut depth = renderer.read_attachment("depth").unwrap();
+    let mut mips: Vec<_> = (0..extents.len() as u32)
+        .map(|mip| renderer.read_attachment_mip("hiZ", mip).unwrap())
+        .collect();
+    renderer.render();
+    let depth = floats_of(&depth.resolve_wait(&renderer, TIMEOUT).unwrap());
+    let mips: Vec<_> = mips
+        .iter_mut()
+        .map(|e| floats_of(&e.resolve_wait(&renderer, TIMEOUT).unwrap()))
+        .collect();
+
+    // Not all the same, or any reduction would do
+    assert!(depth.iter().any(|e| *e != depth[0]));
+    assert_eq!(mips[0], depth);
+    for mip in 1..extents.len() {
+        let expected = reduce(&mips[mip - 1], extents[mip - 1], Reduction::Max);
+        assert_eq!(mips[mip], expected, "mip {}", mip);
+    }
+    common::finish(renderer);
+}
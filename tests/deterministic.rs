@@ -0,0 +1,74 @@
+/*
+ * Deterministic mode. The frame times on their own, then two renderers in deterministic
+ * mode filling tests/scissor.json with the same overlapping tasks on a headless surface,
+ * whose targets have to come out byte for byte the same.
+ */
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::deterministic::Deterministic;
+use rend_vk::render_task::{RenderTask, ScissorRect, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn frames_advance_time_by_the_fixed_frame_time() {
+    let deterministic = Deterministic {
+        frame_time: Duration::from_millis(10),
+    };
+    assert_eq!(deterministic.time_of(0), 0.0);
+    assert_eq!(deterministic.time_of(3), 0.03);
+    let default = Deterministic::default();
+    assert!((default.time_of(60) - 1.0).abs() < 1e-6);
+}
+
+fn fill(gray: u8, x: i32) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: gray as f32 / 255.0,
+        scissor: Some(ScissorRect {
+            x,
+            y: 0,
+            width: 24,
+            height: SIZE,
+        }),
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+// Frames of the same tasks, the target of each one read back
+fn render_frames() -> Vec<Vec<u8>> {
+    let config = RendererConfig {
+        deterministic: Some(Deterministic::default()),
+        // Would skip every frame if acquiring went by it
+        acquire_timeout: Duration::ZERO,
+        ..Default::default()
+    };
+    let mut renderer = common::make_renderer_with(config, "tests/scissor.json", SIZE, SIZE);
+    let frames = (0..3)
+        .map(|frame| {
+            for (i, x) in [0, 16, 8, 32].into_iter().enumerate() {
+                renderer.add_task_to_queue(fill(40 * (i as u8 + frame), x));
+            }
+            let mut request = renderer.read_attachment("ui").unwrap();
+            assert_eq!(renderer.render(), FrameOutcome::Submitted);
+            request.resolve_wait(&renderer, TIMEOUT).unwrap()
+        })
+        .collect();
+    common::finish(renderer);
+    frames
+}
+
+#[test]
+fn deterministic_renderers_render_the_same_frames() {
+    let _serial = common::serial();
+    let first = render_frames();
+    let second = render_frames();
+    assert_eq!(first.len(), second.len());
+    for (i, (a, b)) in first.iter().zip(&second).enumerate() {
+        assert!(a == b, "frame {} differs", i);
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Downsample chains of tests/downsample_chain.json, expanded and traced without a
+ * device, then built on a headless surface with validation on. The spot stage lights a
+ * single pixel the chain and the plain blit chain next to it both reduce. Validation
+ * errors logged by the object tracker on destroy count as leaks.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ash::vk;
+use glam::Vec2;
+
+use rend_vk::capabilities::DeviceCapabilities;
+use rend_vk::handle::MeshHandle;
+use rend_vk::pipeline::dry_run::{DryMesh, DryRun};
+use rend_vk::pipeline::file::{Pipeline, U32OrF32};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::Renderer;
+
+const PIPELINE: &str = "tests/downsample_chain.json";
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 64,
+    height: 64,
+};
+const QUAD: MeshHandle = MeshHandle {
+    index: 0,
+    generation: 0,
+};
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Side of the last level of both chains
+const LAST_SIDE: usize = 8;
+
+fn make_renderer() -> Renderer {
+    common::make_renderer(PIPELINE, EXTENT.width, EXTENT.height)
+}
+
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits >> 15 == 1 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1F) as i32;
+    let mantissa = (bits & 0x3FF) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 => f32::INFINITY,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Red of each texel of a R16G16B16A16_SFLOAT readback, the spot is grey
+fn reds_of(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(8)
+        .map(|e| half_to_f32(u16::from_ne_bytes([e[0], e[1]])))
+        .collect()
+}
+
+fn centroid_x(texels: &[f32]) -> f32 {
+    let total: f32 = texels.iter().sum();
+    let weighted: f32 = texels
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (i % LAST_SIDE) as f32 * e)
+        .sum();
+    weighted / total
+}
+
+// Last level of the chain and of the blits, for the spot at the pixel given
+fn last_levels(renderer: &mut Renderer, spot: Vec2) -> (Vec<f32>, Vec<f32>) {
+    renderer
+        .set_stage_constant("spot", "spot", spot.into())
+        .unwrap();
+    renderer.add_task_to_queue(common::task(TaskKind::Fullscreen));
+    let mut chain = renderer.read_attachment("bloom_2").unwrap();
+    let mut blits = renderer.read_attachment("naive_2").unwrap();
+    renderer.render();
+    (
+        reds_of(&chain.resolve_wait(renderer, TIMEOUT).unwrap()),
+        reds_of(&blits.resolve_wait(renderer, TIMEOUT).unwrap()),
+    )
+}
+
+#[test]
+fn chains_expand_into_a_pass_per_level() {
+    let pip = Pipeline::read(Some(PIPELINE));
+    let levels: Vec<_> = pip
+        .targets
+        .iter()
+        .filter(|e| e.name.starts_with("bloom_"))
+        .map(|e| match (&e.width, &e.height) {
+            (U32OrF32::U32(w), U32OrF32::U32(h)) => (e.name.as_str(), *w, *h),
+            _ => panic!("level {} sized relative to the window", e.name),
+        })
+        .collect();
+    assert_eq!(
+        levels,
+        [("bloom_0", 32, 32), ("bloom_1", 16, 16), ("bloom_2", 8, 8)]
+    );
+
+    let passes: Vec<_> = pip
+        .passes
+        .iter()
+        .filter(|e| e.name.starts_with("bloomDown"))
+        .collect();
+    let inputs: Vec<_> = passes.iter().map(|e| e.inputs[0].name.as_str()).collect();
+    assert_eq!(inputs, ["hdr", "bloom_0", "bloom_1"]);
+    let constants: Vec<Vec<_>> = passes
+        .iter()
+        .map(|e| e.constants.iter().map(|e| e.name.as_str()).collect())
+        .collect();
+    assert_eq!(
+        constants,
+        [
+            vec!["threshold", "knee", "maxLuminance"],
+            vec!["maxLuminance"],
+            vec!["maxLuminance"],
+        ]
+    );
+    assert!(passes
+        .iter()
+        .all(|e| e.template.as_deref() == Some("downsampleChain as bloomDown")));
+}
+
+#[test]
+fn levels_get_sampled_by_the_next_one() {
+    let pip = Pipeline::read(Some(PIPELINE));
+    let mut run = DryRun::new(pip, &DeviceCapabilities::default(), EXTENT, false);
+    let meshes = HashMap::from([(
+        QUAD.index,
+        DryMesh {
+            count: 3,
+            is_indexed: false,
+        },
+    )]);
+    let frame = run.frame(vec![RenderTask::new(QUAD, TaskKind::Fullscreen)], &meshes);
+    assert!(frame.warnings.is_empty(), "{:?}", frame.warnings);
+    let lines = frame.lines();
+    let transitions: Vec<_> = lines
+        .iter()
+        .filter(|e| e.starts_with("bloomDown_") && e.contains("transition"))
+        .collect();
+    assert_eq!(
+        transitions,
+        [
+            "bloomDown_0: transition hdr ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL",
+            "bloomDown_0: transition bloom_0 UNDEFINED -> ATTACHMENT_OPTIMAL",
+            "bloomDown_1: transition bloom_0 ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL",
+            "bloomDown_1: transition bloom_1 UNDEFINED -> ATTACHMENT_OPTIMAL",
+            "bloomDown_2: transition bloom_1 ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL",
+            "bloomDown_2: transition bloom_2 UNDEFINED -> ATTACHMENT_OPTIMAL",
+        ]
+    );
+    assert!(lines.contains(&"bloomDown_2: begin rendering 8x8 bloom_2 LOAD/STORE".to_string()));
+    assert!(lines.contains(
+        &"present: transition bloom_2 ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string()
+    ));
+}
+
+#[test]
+fn chain_spreads_a_bright_pixel_the_blits_snap_to_a_texel() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    // Plain filtering for the comparison, nothing cut by the threshold
+    renderer
+        .set_stage_constant("bloomDown_0", "threshold", 0.0.into())
+        .unwrap();
+    renderer
+        .set_stage_constant("bloomDown_0", "knee", 0.0.into())
+        .unwrap();
+    // Twice for the constants to reach a prepared frame
+    last_levels(&mut renderer, Vec2::new(16.0, 21.0));
+    let (first_chain, first_blits) = last_levels(&mut renderer, Vec2::new(16.0, 21.0));
+
+    // Blits box filter the spot into a single texel
+    assert_eq!(first_blits.iter().filter(|e| **e > 0.0).count(), 1);
+    assert!(first_chain.iter().filter(|e| **e > 0.0).count() > 4);
+
+    let mut previous = centroid_x(&first_chain);
+    for x in 17..24 {
+        let (chain, blits) = last_levels(&mut renderer, Vec2::new(x as f32, 21.0));
+        // Every pixel of the same 8x8 block lands on the same texel
+        assert_eq!(blits, first_blits, "spot at {}", x);
+        let centroid = centroid_x(&chain);
+        assert!(
+            centroid > previous,
+            "spot at {}: {} after {}",
+            x,
+            centroid,
+            previous
+        );
+        previous = centroid;
+    }
+    common::finish(renderer);
+}
+
+#[test]
+fn max_luminance_clamps_fireflies() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    renderer
+        .set_stage_constant("bloomDown_0", "maxLuminance", 2.0.into())
+        .unwrap();
+    last_levels(&mut renderer, Vec2::new(21.0, 21.0));
+    let (chain, _) = last_levels(&mut renderer, Vec2::new(21.0, 21.0));
+    // Filtering only averages, the threshold only takes away
+    assert!(chain.iter().any(|e| *e > 0.0));
+    assert!(chain.iter().all(|e| *e <= 2.0), "{:?}", chain);
+    common::finish(renderer);
+}
@@ -0,0 +1,888 @@
+/*
+ * Frames of pipeline files traced without a device. The trace of the shipped pipeline
+ * is spelled out in full, so any change to barriers, scopes or draw order has to show up
+ * here along with it.
+ */
+use std::collections::HashMap;
+
+use ash::vk;
+
+use rend_vk::capabilities::DeviceCapabilities;
+use rend_vk::format::Format;
+use rend_vk::handle::MeshHandle;
+use rend_vk::pipeline::dry_run::{DryMesh, DryRun};
+use rend_vk::pipeline::file::{
+    Filtering, InitialState, Pass, Pipeline, PreserveMode, ShadingRate, SpecValue,
+};
+use rend_vk::pipeline::specialization::Specialization;
+use rend_vk::reflection::ShaderReflection;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::shader_resource::{MultiResource, ResourceKind};
+
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 64,
+    height: 64,
+};
+const QUAD: MeshHandle = MeshHandle {
+    index: 0,
+    generation: 0,
+};
+const CUBE: MeshHandle = MeshHandle {
+    index: 1,
+    generation: 0,
+};
+const SPHERE: MeshHandle = MeshHandle {
+    index: 2,
+    generation: 0,
+};
+
+fn meshes() -> HashMap<u32, DryMesh> {
+    let mesh = |count, is_indexed| DryMesh { count, is_indexed };
+    HashMap::from([
+        (QUAD.index, mesh(3, false)),
+        (CUBE.index, mesh(36, true)),
+        (SPHERE.index, mesh(240, true)),
+    ])
+}
+
+/*
+ * The trace only looks at which resources a task has, not at what's in them.
+ */
+fn task(mesh: MeshHandle, kind: TaskKind, resources: &[ResourceKind]) -> RenderTask {
+    let resources = resources
+        .iter()
+        .map(|e| {
+            let resource = match e {
+                ResourceKind::Transform => MultiResource::Transform(Vec::new()),
+                ResourceKind::Material => MultiResource::Material(Vec::new()),
+                ResourceKind::TransformExtra => MultiResource::TransformExtra(Vec::new()),
+                ResourceKind::DirLight => MultiResource::DirLight(Vec::new()),
+                _ => unreachable!(),
+            };
+            (*e, resource)
+        })
+        .collect();
+    RenderTask {
+        resources,
+        ..RenderTask::new(mesh, kind)
+    }
+}
+
+fn scene() -> Vec<RenderTask> {
+    let static_resources = [
+        ResourceKind::Transform,
+        ResourceKind::Material,
+        ResourceKind::TransformExtra,
+    ];
+    let two_sided = RenderTask {
+        is_two_sided: true,
+        ..task(SPHERE, TaskKind::MeshStatic, &static_resources)
+    };
+    let instanced = RenderTask {
+        instance_count: 2,
+        ..task(CUBE, TaskKind::MeshStatic, &static_resources)
+    };
+    let near = RenderTask {
+        view_depth: 2.0,
+        ..task(CUBE, TaskKind::Translucent, &[ResourceKind::Transform])
+    };
+    let far = RenderTask {
+        view_depth: 5.0,
+        ..task(SPHERE, TaskKind::Translucent, &[ResourceKind::Transform])
+    };
+    vec![
+        two_sided,
+        instanced,
+        task(QUAD, TaskKind::LightDir, &[ResourceKind::DirLight]),
+        near,
+        far,
+        task(QUAD, TaskKind::Fullscreen, &[]),
+    ]
+}
+
+fn dry_run(pip: Pipeline, is_scaled: bool) -> DryRun {
+    DryRun::new(pip, &DeviceCapabilities::default(), EXTENT, is_scaled)
+}
+
+fn size_of(kind: ResourceKind, instance_count: usize) -> usize {
+    kind.resource_size() * instance_count
+}
+
+#[test]
+fn shipped_pipeline_traces_in_full() {
+    let mut run = dry_run(Pipeline::read(None), false);
+    let trace = run.frame(scene(), &meshes());
+    let per_pass = size_of(ResourceKind::ViewRay, 1) + size_of(ResourceKind::Frustum, 1);
+    let expected = [
+        // Culled tasks first, whatever order they were sent in
+        format!("gbuffer: reserve Transform {}", size_of(ResourceKind::Transform, 2)),
+        format!("gbuffer: reserve Material {}", size_of(ResourceKind::Material, 2)),
+        format!(
+            "gbuffer: reserve TransformExtra {}",
+            size_of(ResourceKind::TransformExtra, 2)
+        ),
+        format!("gbuffer: reserve Transform {}", size_of(ResourceKind::Transform, 1)),
+        format!("gbuffer: reserve Material {}", size_of(ResourceKind::Material, 1)),
+        format!(
+            "gbuffer: reserve TransformExtra {}",
+            size_of(ResourceKind::TransformExtra, 1)
+        ),
+        format!("dirlight: reserve PerPass {per_pass}"),
+        format!("dirlight: reserve DirLight {}", size_of(ResourceKind::DirLight, 1)),
+        format!("translucent: reserve Transform {}", size_of(ResourceKind::Transform, 1)),
+        format!("translucent: reserve Transform {}", size_of(ResourceKind::Transform, 1)),
+        format!("copy: reserve PerPass {per_pass}"),
+        "gbuffer: wait 0".to_string(),
+        "gbuffer: transition albedo UNDEFINED -> ATTACHMENT_OPTIMAL".to_string(),
+        "gbuffer: transition normal UNDEFINED -> ATTACHMENT_OPTIMAL".to_string(),
+        "gbuffer: transition misc UNDEFINED -> ATTACHMENT_OPTIMAL".to_string(),
+        "gbuffer: transition depth UNDEFINED -> ATTACHMENT_OPTIMAL".to_string(),
+        "gbuffer: begin rendering 64x64 albedo CLEAR/STORE normal CLEAR/STORE misc CLEAR/STORE velocity CLEAR/STORE depth CLEAR/STORE".to_string(),
+        "gbuffer: bind descriptors".to_string(),
+        "  bind pipeline gbuffer".to_string(),
+        "  cull mode BACK".to_string(),
+        format!("  draw mesh {CUBE} indices 36 x2"),
+        "  cull mode NONE".to_string(),
+        format!("  draw mesh {SPHERE} indices 240 x1"),
+        "gbuffer: end rendering".to_string(),
+        "gbuffer: signal 4".to_string(),
+        "dirlight: wait 1".to_string(),
+        "dirlight: transition albedo ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string(),
+        "dirlight: transition normal ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string(),
+        "dirlight: transition misc ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string(),
+        "dirlight: transition lightAcc UNDEFINED -> ATTACHMENT_OPTIMAL".to_string(),
+        "dirlight: begin rendering 64x64 lightAcc CLEAR/STORE depth LOAD/STORE".to_string(),
+        "dirlight: bind descriptors albedo normal misc depth".to_string(),
+        "  bind pipeline dirlight".to_string(),
+        "  cull mode BACK".to_string(),
+        format!("  draw mesh {QUAD} vertices 3 x1"),
+        "dirlight: signal 5".to_string(),
+        // Same scope as dirlight, blended back to front
+        "translucent: wait 2".to_string(),
+        "translucent: bind descriptors".to_string(),
+        "  bind pipeline translucent".to_string(),
+        "  cull mode NONE".to_string(),
+        format!("  draw mesh {SPHERE} indices 240 x1"),
+        format!("  draw mesh {CUBE} indices 36 x1"),
+        "translucent: end rendering".to_string(),
+        "translucent: signal 6".to_string(),
+        "copy: wait 3".to_string(),
+        "copy: transition lightAcc ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string(),
+        "copy: transition default UNDEFINED -> ATTACHMENT_OPTIMAL".to_string(),
+        "copy: begin rendering 64x64 default CLEAR/STORE".to_string(),
+        "copy: bind descriptors lightAcc normal albedo misc".to_string(),
+        "  bind pipeline copy".to_string(),
+        "  cull mode NONE".to_string(),
+        format!("  draw mesh {QUAD} vertices 3 x1"),
+        "copy: end rendering".to_string(),
+        "copy: present".to_string(),
+        "copy: signal 7".to_string(),
+    ];
+    assert_eq!(trace.lines(), expected);
+    assert!(trace.warnings.is_empty());
+}
+
+#[test]
+fn later_frames_only_move_the_timeline() {
+    let mut run = dry_run(Pipeline::read(None), false);
+    let first = run.frame(scene(), &meshes());
+    let second = run.frame(scene(), &meshes());
+    let timeline = |e: &String| e.contains(": wait ") || e.contains(": signal ");
+    let without_timeline = |lines: Vec<String>| -> Vec<String> {
+        lines.into_iter().filter(|e| !timeline(e)).collect()
+    };
+    assert_eq!(
+        without_timeline(first.lines()),
+        without_timeline(second.lines())
+    );
+    let values: Vec<_> = second.lines().into_iter().filter(timeline).collect();
+    assert_eq!(
+        values,
+        [
+            "gbuffer: wait 4",
+            "gbuffer: signal 8",
+            "dirlight: wait 5",
+            "dirlight: signal 9",
+            "translucent: wait 6",
+            "translucent: signal 10",
+            "copy: wait 7",
+            "copy: signal 11",
+        ]
+    );
+}
+
+#[test]
+fn scaled_frames_upscale_instead_of_presenting() {
+    let mut run = dry_run(Pipeline::read(None), true);
+    let lines = run.frame(scene(), &meshes()).lines();
+    assert_eq!(lines.last().unwrap(), "upscale");
+    assert!(!lines.iter().any(|e| e.ends_with(": present")));
+}
+
+#[test]
+fn instance_resources_past_the_uniform_range_take_a_single_address() {
+    let pip = Pipeline::read(Some("tests/large_resources.json"));
+    let mut run = dry_run(pip, false);
+    let grid = RenderTask {
+        instance_count: 4096,
+        ..task(QUAD, TaskKind::Fullscreen, &[ResourceKind::Transform])
+    };
+    let trace = run.frame(vec![grid], &meshes());
+    let size = size_of(ResourceKind::Transform, 4096);
+    // Way past the 64 KiB uniform buffers are limited to on most devices
+    assert_eq!(size, 512 * 1024);
+    let lines = trace.lines();
+    assert!(lines.contains(&format!("grid: reserve Transform {size}")));
+    assert!(lines.contains(&format!("  draw mesh {QUAD} vertices 3 x4096")));
+    assert!(trace.warnings.is_empty());
+}
+
+#[test]
+fn sampling_before_writing_warns_on_the_first_frame() {
+    let mut pip = Pipeline::read(None);
+    let copy = pip.passes.iter().position(|e| e.name == "copy").unwrap();
+    let copy = pip.passes.remove(copy);
+    pip.passes.insert(0, copy);
+    let mut run = dry_run(pip, false);
+
+    let first = run.frame(scene(), &meshes());
+    assert_eq!(
+        first.warnings,
+        ["lightAcc", "normal", "albedo", "misc"].map(|e| format!(
+            "stage copy samples {e} before anything wrote it, it gets the default texture until then"
+        ))
+    );
+    let lines = first.lines();
+    assert!(lines.contains(
+        &"copy: bind descriptors (default texture) (default texture) (default texture) (default texture)"
+            .to_string()
+    ));
+    assert!(!lines
+        .iter()
+        .any(|e| e.starts_with("copy: transition lightAcc")));
+
+    // Written by the previous frame from then on
+    let second = run.frame(scene(), &meshes());
+    assert!(second.warnings.is_empty());
+    assert!(second
+        .lines()
+        .contains(&"copy: bind descriptors lightAcc normal albedo misc".to_string()));
+}
+
+#[test]
+#[should_panic]
+fn tasks_need_their_meshes() {
+    let mut run = dry_run(Pipeline::read(None), false);
+    run.frame(scene(), &HashMap::new());
+}
+
+#[test]
+fn reads_before_writes_need_initial_states() {
+    let pip = Pipeline::read(Some("tests/uninitialized_read.json"));
+    let reads: Vec<_> = pip
+        .uninitialized_reads()
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(
+        reads,
+        [
+            "pass copy reads attachment history before anything wrote it, it needs an initialState",
+            "pass copy reads buffer counters before anything wrote it, it needs an initialState",
+        ]
+    );
+    let mut run = dry_run(pip, false);
+    let first = run.frame(vec![task(QUAD, TaskKind::Fullscreen, &[])], &meshes());
+    assert_eq!(
+        first.warnings,
+        ["stage copy samples history before anything wrote it, it gets the default texture until then"]
+    );
+}
+
+#[test]
+fn initial_states_count_as_written_on_the_first_frame() {
+    let pip = Pipeline::read(Some("tests/initial_state.json"));
+    assert!(pip.uninitialized_reads().is_empty());
+    let mut run = dry_run(pip, false);
+
+    let first = run.frame(vec![task(QUAD, TaskKind::Fullscreen, &[])], &meshes());
+    assert!(first.warnings.is_empty(), "{:?}", first.warnings);
+    let lines = first.lines();
+    // From the layout the store stage leaves it in, like on any later frame
+    assert!(lines
+        .contains(&"copy: transition history ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string()));
+    assert!(lines.contains(&"copy: bind descriptors history".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "can't start out as")]
+fn color_attachments_cant_start_out_as_depth() {
+    let mut pip = Pipeline::read(Some("tests/initial_state.json"));
+    let history = pip
+        .targets
+        .iter_mut()
+        .find(|e| e.name == "history")
+        .unwrap();
+    history.initial_state = Some(InitialState::ClearDepth(1.0));
+    dry_run(pip, false);
+}
+
+// Color into history, depth into a copy of it
+fn blits_with(edit: impl FnOnce(&mut Pipeline)) -> DryRun {
+    let mut pip = Pipeline::read(Some("tests/blit.json"));
+    edit(&mut pip);
+    dry_run(pip, false)
+}
+
+fn set_format(pip: &mut Pipeline, target: &str, format: Format) {
+    pip.targets
+        .iter_mut()
+        .find(|e| e.name == target)
+        .unwrap()
+        .format = format;
+}
+
+#[test]
+fn blits_of_the_same_format_copy() {
+    let mut run = blits_with(|_| {});
+    let lines = run
+        .frame(vec![task(QUAD, TaskKind::Fullscreen, &[])], &meshes())
+        .lines();
+    assert!(lines.contains(&"history: copy color -> history".to_string()));
+    assert!(lines.contains(&"depthCopy: copy depth -> depthCopy".to_string()));
+
+    // Converting color is fine
+    let mut run = blits_with(|pip| set_format(pip, "history", Format::R16G16B16A16_SFLOAT));
+    let lines = run
+        .frame(vec![task(QUAD, TaskKind::Fullscreen, &[])], &meshes())
+        .lines();
+    assert!(lines.contains(&"history: blit color -> history".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "pass history blits color into history, their aspects")]
+fn dry_runs_reject_mismatched_blits() {
+    blits_with(|pip| set_format(pip, "history", Format::D32_SFLOAT));
+}
+
+// Gbuffer stage specializing constant 0, flipping it in an alphaTested variant
+fn with_variant(edit: impl FnOnce(&mut Pipeline)) -> Pipeline {
+    let mut pip = Pipeline::read(None);
+    let gbuffer = pip.passes.iter_mut().find(|e| e.name == "gbuffer").unwrap();
+    gbuffer
+        .specialization
+        .insert("0".to_string(), SpecValue::Bool(false));
+    let overrides = [("0".to_string(), SpecValue::Bool(true))].into();
+    gbuffer
+        .variants
+        .insert("alphaTested".to_string(), overrides);
+    edit(&mut pip);
+    pip
+}
+
+fn declaring(ids: &[u32]) -> ShaderReflection {
+    ShaderReflection {
+        specialization_ids: ids.to_vec(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn tasks_of_unknown_variants_draw_with_the_base_pipeline() {
+    let mut run = dry_run(with_variant(|_| {}), false);
+    let alpha_tested = run.variant_id("alphaTested").unwrap();
+    assert_eq!(run.variant_id("alpha_tested"), None);
+    let resources = [
+        ResourceKind::Transform,
+        ResourceKind::Material,
+        ResourceKind::TransformExtra,
+    ];
+    let variant_task = |variant| RenderTask {
+        variant,
+        ..task(CUBE, TaskKind::MeshStatic, &resources)
+    };
+    let tasks = vec![
+        variant_task(Some(alpha_tested)),
+        // Past the variants of the pipeline
+        variant_task(Some(alpha_tested + 1)),
+        variant_task(None),
+    ];
+    let binds: Vec<_> = run
+        .frame(tasks, &meshes())
+        .lines()
+        .into_iter()
+        .filter(|e| e.starts_with("  bind pipeline gbuffer"))
+        .collect();
+    assert_eq!(
+        binds,
+        [
+            "  bind pipeline gbuffer",
+            "  bind pipeline gbuffer alphaTested"
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "stage gbuffer has an invalid specialization constant id alpha!")]
+fn specialization_ids_have_to_be_numbers() {
+    dry_run(
+        with_variant(|pip| {
+            let gbuffer = &mut pip.passes[0];
+            let variant = gbuffer.variants.get_mut("alphaTested").unwrap();
+            variant.insert("alpha".to_string(), SpecValue::Int(1));
+        }),
+        false,
+    );
+}
+
+#[test]
+fn specialization_ids_declared_by_the_shaders_pass() {
+    let pip = with_variant(|_| {});
+    for pass in &pip.passes {
+        Specialization::validate_ids(pass, Some(&declaring(&[0, 3])));
+    }
+}
+
+#[test]
+#[should_panic(
+    expected = "stage gbuffer specializes constant 3, but its shaders don't declare it!"
+)]
+fn variant_ids_the_shaders_lack_are_rejected() {
+    let pip = with_variant(|pip| {
+        let variant = pip.passes[0].variants.get_mut("alphaTested").unwrap();
+        variant.insert("3".to_string(), SpecValue::Float(0.5));
+    });
+    Specialization::validate_ids(&pip.passes[0], Some(&declaring(&[0])));
+}
+
+#[test]
+fn history_sampled_on_frame_zero_gets_the_default_texture() {
+    let pip = Pipeline::read(Some("tests/uninitialized_read.json"));
+    let mut run = dry_run(pip, false);
+    let mut frame = || {
+        run.frame(vec![task(QUAD, TaskKind::Fullscreen, &[])], &meshes())
+            .lines()
+    };
+    let first = frame();
+    // Never made read only out of UNDEFINED, what validation would flag
+    assert!(first.contains(&"copy: bind descriptors (default texture)".to_string()));
+    let copy_lines: Vec<_> = first
+        .iter()
+        .take_while(|e| !e.starts_with("store"))
+        .collect();
+    assert!(
+        copy_lines.iter().all(|e| !e.contains("transition history")),
+        "{:?}",
+        copy_lines
+    );
+    let second = frame();
+    assert!(second
+        .contains(&"copy: transition history ATTACHMENT_OPTIMAL -> READ_ONLY_OPTIMAL".to_string()));
+    assert!(second.contains(&"copy: bind descriptors history".to_string()));
+}
+
+#[test]
+fn frame_graph_of_the_shipped_pipeline_matches_its_snapshot() {
+    let run = dry_run(Pipeline::read(None), false);
+    let expected = std::fs::read_to_string("tests/pipeline_files/frame_graph.dot").unwrap();
+    assert_eq!(run.dump_frame_graph(), expected);
+}
+
+#[test]
+fn depth_pyramids_are_compute_stages_in_the_frame_graph() {
+    let run = dry_run(Pipeline::read(Some("tests/depth_pyramid.json")), false);
+    let graph = run.dump_frame_graph();
+    assert!(graph.contains("label=\"1: hiZ\\ncompute\\n"), "{}", graph);
+    assert!(graph.contains("label=\"depth pyramid from\""), "{}", graph);
+}
+
+#[test]
+fn buffers_read_after_being_written_get_a_barrier_between_the_stages() {
+    let mut pip = Pipeline::read(None);
+    for (name, is_writer) in [("gbuffer", true), ("copy", false)] {
+        let pass = pip.passes.iter_mut().find(|e| e.name == name).unwrap();
+        let buffers = if is_writer {
+            &mut pass.writes_buffers
+        } else {
+            &mut pass.reads_buffers
+        };
+        buffers.push("culled".to_string());
+    }
+    let mut run = dry_run(pip, false);
+    let lines = run.frame(scene(), &meshes()).lines();
+    let position = |line: &str| lines.iter().position(|e| e == line).unwrap();
+    let barriers: Vec<_> = lines
+        .iter()
+        .filter(|e| e.contains("buffer barrier"))
+        .collect();
+    // The write waits on the read of the previous frame
+    assert_eq!(
+        barriers,
+        [
+            "gbuffer: buffer barrier culled read -> write",
+            "copy: buffer barrier culled write -> read",
+        ]
+    );
+    assert!(position("gbuffer: buffer barrier culled read -> write") < position("gbuffer: begin rendering 64x64 albedo CLEAR/STORE normal CLEAR/STORE misc CLEAR/STORE velocity CLEAR/STORE depth CLEAR/STORE"));
+    assert!(
+        position("gbuffer: end rendering") < position("copy: buffer barrier culled write -> read")
+    );
+    assert!(
+        position("copy: buffer barrier culled write -> read")
+            < position("copy: begin rendering 64x64 default CLEAR/STORE")
+    );
+}
+
+#[test]
+fn buffers_only_read_need_no_barrier() {
+    let mut pip = Pipeline::read(None);
+    for pass in pip.passes.iter_mut() {
+        pass.reads_buffers.push("lights".to_string());
+    }
+    let mut run = dry_run(pip, false);
+    let lines = run.frame(scene(), &meshes()).lines();
+    assert!(
+        lines.iter().all(|e| !e.contains("buffer barrier")),
+        "{:?}",
+        lines
+    );
+}
+
+fn set_memoryless(pip: &mut Pipeline, target: &str) {
+    pip.targets
+        .iter_mut()
+        .find(|e| e.name == target)
+        .unwrap()
+        .is_memoryless = true;
+}
+
+#[test]
+fn memoryless_attachments_are_never_stored() {
+    let mut pip = Pipeline::read(None);
+    set_memoryless(&mut pip, "velocity");
+    let lines = dry_run(pip, false).frame(scene(), &meshes()).lines();
+    assert!(lines.contains(&"gbuffer: begin rendering 64x64 albedo CLEAR/STORE normal CLEAR/STORE misc CLEAR/STORE velocity CLEAR/DONT_CARE depth CLEAR/STORE".to_string()));
+}
+
+#[test]
+#[should_panic(
+    expected = "memoryless attachment depth can't be sampled, but pass dirlight reads from it!"
+)]
+fn memoryless_attachments_cant_be_sampled() {
+    let mut pip = Pipeline::read(None);
+    set_memoryless(&mut pip, "depth");
+    dry_run(pip, false);
+}
+
+fn preserving(pip: &mut Pipeline, target: &str, mode: PreserveMode) -> vk::ImageUsageFlags {
+    let target = pip.targets.iter_mut().find(|e| e.name == target).unwrap();
+    target.is_preserved_on_recreate = true;
+    target.preserve_mode = mode;
+    let name = target.name.clone();
+    let usages = pip.attachment_usages();
+    usages.into_iter().find(|e| e.0 == name).unwrap().1
+}
+
+#[test]
+fn preserved_attachments_get_copied_by_transfers() {
+    let transfer = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+    let mut pip = Pipeline::read(None);
+    assert!(preserving(&mut pip, "albedo", PreserveMode::Blit).contains(transfer));
+    assert!(preserving(&mut pip, "depth", PreserveMode::CopyCentered).contains(transfer));
+    // Made empty like the others
+    assert!(!preserving(&mut pip, "normal", PreserveMode::Discard).contains(transfer));
+    dry_run(pip, false);
+}
+
+#[test]
+#[should_panic(
+    expected = "depth stencil attachment depth is preserved with linear filtering, only nearest is supported!"
+)]
+fn preserved_depth_attachments_cant_be_filtered() {
+    let mut pip = Pipeline::read(None);
+    preserving(&mut pip, "depth", PreserveMode::Blit);
+    pip.targets
+        .iter_mut()
+        .find(|e| e.name == "depth")
+        .unwrap()
+        .preserve_filter = Filtering::Linear;
+    dry_run(pip, false);
+}
+
+#[test]
+#[should_panic(expected = "attachment velocity is preserved on recreate, it can't be memoryless")]
+fn memoryless_attachments_cant_be_preserved() {
+    let mut pip = Pipeline::read(None);
+    set_memoryless(&mut pip, "velocity");
+    preserving(&mut pip, "velocity", PreserveMode::Blit);
+    dry_run(pip, false);
+}
+
+fn set_per_viewport(pip: &mut Pipeline, target: &str) {
+    pip.targets
+        .iter_mut()
+        .find(|e| e.name == target)
+        .unwrap()
+        .is_per_viewport = true;
+}
+
+#[test]
+fn per_viewport_attachments_trace_like_shared_ones() {
+    let mut pip = Pipeline::read(None);
+    set_per_viewport(&mut pip, "albedo");
+    set_per_viewport(&mut pip, "lightAcc");
+    dry_run(pip, false).frame(scene(), &meshes());
+}
+
+#[test]
+#[should_panic(
+    expected = "attachment velocity is allocated per viewport, it has to be a single layer one with memory and without full mips!"
+)]
+fn memoryless_attachments_cant_be_per_viewport() {
+    let mut pip = Pipeline::read(None);
+    set_memoryless(&mut pip, "velocity");
+    set_per_viewport(&mut pip, "velocity");
+    dry_run(pip, false);
+}
+
+#[test]
+#[should_panic(
+    expected = "pass dirlight uses attachment albedo allocated per viewport, it has to be a draw pass drawing once per viewport!"
+)]
+fn passes_shared_across_viewports_cant_use_per_viewport_attachments() {
+    let mut pip = Pipeline::read(None);
+    set_per_viewport(&mut pip, "albedo");
+    pip.passes
+        .iter_mut()
+        .find(|e| e.name == "dirlight")
+        .unwrap()
+        .is_shared_across_viewports = true;
+    dry_run(pip, false);
+}
+
+#[test]
+fn two_sided_tasks_get_grouped_to_switch_culling_once() {
+    let resources = [
+        ResourceKind::Transform,
+        ResourceKind::Material,
+        ResourceKind::TransformExtra,
+    ];
+    let tasks: Vec<_> = (0..4)
+        .map(|i| RenderTask {
+            is_two_sided: i % 2 == 0,
+            ..task(CUBE, TaskKind::MeshStatic, &resources)
+        })
+        .collect();
+    assert_eq!(
+        tasks.iter().map(|e| e.flags()).collect::<Vec<_>>(),
+        [RenderTask::FLAG_TWO_SIDED, 0, RenderTask::FLAG_TWO_SIDED, 0]
+    );
+    let lines = dry_run(Pipeline::read(None), false)
+        .frame(tasks, &meshes())
+        .lines();
+    let gbuffer: Vec<_> = lines
+        .iter()
+        .skip_while(|e| *e != "gbuffer: bind descriptors")
+        .take_while(|e| *e != "gbuffer: end rendering")
+        .filter(|e| e.starts_with("  cull mode") || e.starts_with("  draw"))
+        .collect();
+    let draw = format!("  draw mesh {CUBE} indices 36 x1");
+    assert_eq!(
+        gbuffer,
+        [
+            "  cull mode BACK",
+            &draw,
+            &draw,
+            "  cull mode NONE",
+            &draw,
+            &draw
+        ]
+    );
+}
+
+// Fullscreen pass after copy loading what it draws over
+fn ui_pass(output: serde_json::Value) -> Pass {
+    let ui = serde_json::json!({
+        "name": "ui",
+        "program": "copy",
+        "batch": "FULLSCREEN",
+        "outputs": [output],
+        "state": {
+            "writing": "COLOR",
+            "depth": "NO",
+            "scissor": "DEFAULT",
+            "viewport": "DEFAULT",
+            "stencil": "NO",
+            "triangle": {
+                "frontFace": "CCW",
+                "cullFace": "NONE",
+                "polygonMode": "FILL"
+            },
+            "blending": "NO",
+            "clearing": "NO"
+        }
+    });
+    serde_json::from_value(ui).unwrap()
+}
+
+#[test]
+fn later_passes_writing_the_default_attachment_take_over_presenting() {
+    let mut pip = Pipeline::read(None);
+    pip.passes.push(ui_pass(
+        serde_json::json!({ "name": "default", "viewFormat": "unorm" }),
+    ));
+    let lines = dry_run(pip, false)
+        .frame(vec![task(QUAD, TaskKind::Fullscreen, &[])], &meshes())
+        .lines();
+    let tail: Vec<_> = lines
+        .iter()
+        .skip_while(|e| *e != "copy: end rendering")
+        .filter(|e| !e.starts_with("  "))
+        .collect();
+    // Through another view of the image, the UI can't continue the scope of copy
+    assert_eq!(
+        tail,
+        [
+            "copy: end rendering",
+            "copy: signal 8",
+            "ui: wait 4",
+            "ui: transition default ATTACHMENT_OPTIMAL -> ATTACHMENT_OPTIMAL",
+            "ui: begin rendering 64x64 default LOAD/STORE",
+            "ui: bind descriptors",
+            "ui: end rendering",
+            "ui: present",
+            "ui: signal 9",
+        ]
+    );
+}
+
+#[test]
+fn stages_shade_at_the_rates_the_device_has() {
+    let rates = |capabilities: &DeviceCapabilities| {
+        let mut pip = Pipeline::read(None);
+        for pass in &mut pip.passes {
+            pass.shading_rate = match pass.name.as_str() {
+                "dirlight" => ShadingRate::Quarter,
+                "copy" => ShadingRate::Attachment,
+                _ => ShadingRate::Full,
+            };
+        }
+        let mut run = DryRun::new(pip, capabilities, EXTENT, false);
+        let lines = run.frame(scene(), &meshes()).lines();
+        let mut stage = String::new();
+        let mut rates = Vec::new();
+        for line in lines {
+            if let Some((name, _)) = line.split_once(": ") {
+                stage = name.to_string();
+            } else if let Some(rate) = line.strip_prefix("  shading rate ") {
+                rates.push(format!("{stage} {rate}"));
+            }
+        }
+        rates
+    };
+    // Without attachment support the copy stage falls back to 1x1
+    let capabilities = DeviceCapabilities {
+        is_shading_rate_enabled: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        rates(&capabilities),
+        ["gbuffer 1x1", "dirlight 2x2", "translucent 1x1", "copy 1x1"]
+    );
+    let capabilities = DeviceCapabilities {
+        shading_rate_texel_size: Some(vk::Extent2D {
+            width: 16,
+            height: 16,
+        }),
+        ..capabilities
+    };
+    assert_eq!(
+        rates(&capabilities),
+        [
+            "gbuffer 1x1",
+            "dirlight 2x2",
+            "translucent 1x1",
+            "copy attachment"
+        ]
+    );
+    // No shading rate state to set at all without the extension
+    assert!(rates(&DeviceCapabilities::default()).is_empty());
+}
+
+#[test]
+#[should_panic(
+    expected = "pass copy is declared present, but ui also write to the default attachment!"
+)]
+fn present_passes_write_the_swapchain_image_alone() {
+    let mut pip = Pipeline::read(None);
+    let copy = pip.passes.iter_mut().find(|e| e.name == "copy").unwrap();
+    copy.is_present = true;
+    pip.passes.push(ui_pass(serde_json::json!("default")));
+    dry_run(pip, false);
+}
+
+#[test]
+fn offscreen_pipelines_present_nothing_of_their_own() {
+    let mut pip = Pipeline::read(None);
+    pip.passes.retain(|e| e.name != "copy");
+    let mut run = dry_run(pip, false);
+    let lines = run.frame(scene(), &meshes()).lines();
+    let is_presenting = |e: &String| e.contains("default") || e.ends_with(": present");
+    assert!(!lines.iter().any(is_presenting), "{:#?}", lines);
+}
+
+#[test]
+#[should_panic(
+    expected = "pass translucent is declared present but doesn't write to the default attachment!"
+)]
+fn present_passes_have_to_write_the_swapchain_image() {
+    let mut pip = Pipeline::read(None);
+    pip.passes.retain(|e| e.name != "copy");
+    let translucent = pip.passes.iter_mut().find(|e| e.name == "translucent");
+    translucent.unwrap().is_present = true;
+    dry_run(pip, false);
+}
+
+fn scope_lines(edit: impl FnOnce(&mut Pass)) -> Vec<String> {
+    let mut pip = Pipeline::read(None);
+    edit(
+        pip.passes
+            .iter_mut()
+            .find(|e| e.name == "translucent")
+            .unwrap(),
+    );
+    let lines = dry_run(pip, false).frame(scene(), &meshes()).lines();
+    lines
+        .into_iter()
+        .filter(|e| e.contains(" rendering"))
+        .filter(|e| e.starts_with("dirlight") || e.starts_with("translucent"))
+        .collect()
+}
+
+#[test]
+fn continued_scopes_store_what_their_last_stage_stores() {
+    let lines = scope_lines(|translucent| {
+        translucent.depth_stencil.as_mut().unwrap().is_stored = false;
+    });
+    assert_eq!(
+        lines,
+        [
+            "dirlight: begin rendering 64x64 lightAcc CLEAR/STORE depth LOAD/DONT_CARE",
+            "translucent: end rendering",
+        ]
+    );
+}
+
+#[test]
+fn isolated_passes_begin_their_own_scope() {
+    let lines = scope_lines(|translucent| translucent.is_isolated = true);
+    assert_eq!(
+        lines,
+        [
+            "dirlight: begin rendering 64x64 lightAcc CLEAR/STORE depth LOAD/STORE",
+            "dirlight: end rendering",
+            "translucent: begin rendering 64x64 lightAcc LOAD/STORE depth LOAD/STORE",
+            "translucent: end rendering",
+        ]
+    );
+}
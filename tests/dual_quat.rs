@@ -0,0 +1,49 @@
+/*
+ * Dual quaternion blending of simulation transforms, no GPU involved.
+ */
+#![cfg(feature = "dual-quaternion")]
+
+use glam::{Mat4, Quat, Vec3};
+
+use rend_vk::dual_quat::{self, DualQuat};
+use rend_vk::motion::{self, Affine, SimulationTransforms};
+
+fn rigid(angle: f32, x: f32) -> Affine {
+    let m = Mat4::from_rotation_translation(Quat::from_rotation_y(angle), Vec3::new(x, 0.0, 0.0));
+    motion::mat4_to_affine(&m)
+}
+
+fn assert_close(a: &Affine, b: &Affine) {
+    for (a, b) in a.iter().zip(b) {
+        assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn rigid_transforms_round_trip() {
+    let m = rigid(1.0, 3.0);
+    assert_close(&DualQuat::from_affine(&m).to_affine(), &m);
+    assert_eq!(dual_quat::pack_all(&[m, m]).len(), 2);
+}
+
+#[test]
+fn blending_keeps_the_scale_where_lerping_shrinks() {
+    // Most of a half turn, a whole one would be ambiguous
+    let (a, b) = (rigid(0.0, 0.0), rigid(3.0, 0.0));
+    let blended = DualQuat::blend(&DualQuat::from_affine(&a), &DualQuat::from_affine(&b), 0.5);
+    assert_close(&blended.to_affine(), &rigid(1.5, 0.0));
+    // The lerp scales the x and z axes down by cos(1.5)
+    let lerped = motion::lerp_affine(&a, &b, 0.5);
+    assert!(Vec3::new(lerped[0], lerped[4], lerped[8]).length() < 0.1);
+}
+
+#[test]
+fn simulation_transforms_blend_as_dual_quaternions() {
+    let (a, b) = (rigid(0.0, 0.0), rigid(1.0, 4.0));
+    let transforms = SimulationTransforms::new(&[a], &[b], 0.5).with_dual_quaternions(true);
+    assert!(transforms.is_dual_quaternion());
+    let expected = DualQuat::blend(&DualQuat::from_affine(&a), &DualQuat::from_affine(&b), 0.5);
+    assert_close(&transforms.interpolated(0), &expected.to_affine());
+    // Packed for the shaders, 8 floats each
+    assert_eq!(transforms.to_bytes()[0].len(), 8 * 4);
+}
@@ -0,0 +1,185 @@
+/*
+ * Dual view textures, one image sampled through a UNORM and an sRGB id. The pure tests
+ * check the format pairs, the one on a headless surface with validation on samples a
+ * gray texel through both ids and reads back the albedo the gbuffer stage writes.
+ */
+
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use glam::Mat4;
+
+use rend_vk::format::Format;
+use rend_vk::handle::{StaleHandle, TextureHandle};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::shader_resource::{Material, MultiResource, ResourceKind, Transform, TransformExtra};
+use rend_vk::texture::{MipMap, NoSrgbPair};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const GRAY: u8 = 0x80;
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+fn mip_maps() -> [MipMap; 1] {
+    [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 64,
+        offset: 0,
+    }]
+}
+
+fn textured_triangle(texture: TextureHandle) -> RenderTask {
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform {
+            mvp: Mat4::IDENTITY,
+            mv: Mat4::IDENTITY,
+        }]),
+    );
+    // The gbuffer stage reads it, even if nothing moves
+    resources.insert(
+        ResourceKind::TransformExtra,
+        MultiResource::TransformExtra(vec![TransformExtra {
+            prev_mvp: Mat4::IDENTITY,
+        }]),
+    );
+    resources.insert(
+        ResourceKind::Material,
+        MultiResource::Material(vec![Material {
+            shininess: 0.0,
+            scaling: 1.0,
+            diffuse_handle: texture.index,
+            normal_handle: texture.index,
+            glow_handle: texture.index,
+            diffuse_sampler: 0,
+            normal_sampler: 0,
+            glow_sampler: 0,
+            padding: 0,
+        }]),
+    );
+    RenderTask {
+        resources,
+        is_two_sided: true,
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+// Red of the albedo the triangle sampling the texture leaves, the cleared rest is darker
+fn sampled_red(renderer: &mut Renderer, texture: TextureHandle) -> u8 {
+    renderer.add_task_to_queue(textured_triangle(texture));
+    let mut request = renderer.read_attachment("albedo").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let albedo = request.resolve_wait(renderer, TIMEOUT).unwrap();
+    albedo.chunks_exact(4).map(|e| e[0]).max().unwrap()
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// What the gbuffer stage writes to the sRGB albedo for a sampled diffuse
+fn albedo_of(sampled: f32) -> u8 {
+    (linear_to_srgb(sampled.powf(2.2)) * 255.0).round() as u8
+}
+
+fn assert_near(actual: u8, expected: u8) {
+    assert!(
+        actual.abs_diff(expected) <= 2,
+        "{} isn't near {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn srgb_siblings_pair_up() {
+    assert_eq!(
+        Format::R8G8B8A8_UNORM.srgb_sibling(),
+        Some(Format::R8G8B8A8_SRGB)
+    );
+    assert_eq!(
+        Format::BC7_SRGB_BLOCK.srgb_sibling(),
+        Some(Format::BC7_UNORM_BLOCK)
+    );
+    assert_eq!(Format::R32_SFLOAT.srgb_sibling(), None);
+    assert_eq!(Format::R8_UINT.srgb_sibling(), None);
+    for v in 0..=Format::X8_D24_UNORM_PACK32.to_u8() {
+        let format = Format::of_u8(v);
+        if let Some(sibling) = format.srgb_sibling() {
+            assert_eq!(sibling.srgb_sibling(), Some(format));
+            assert_ne!(format.is_srgb(), sibling.is_srgb(), "{}", format);
+        } else {
+            assert!(!format.is_srgb(), "{} has no sibling", format);
+        }
+    }
+}
+
+#[test]
+fn both_ids_sample_the_one_image() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let rejected =
+        renderer.gen_texture_dual_view("float".to_string(), Format::R32_SFLOAT, &mip_maps(), 64);
+    assert_eq!(
+        rejected,
+        Err(NoSrgbPair {
+            format: Format::R32_SFLOAT
+        })
+    );
+
+    // Either format of the pair works, the texture keeps the UNORM one
+    let ids = renderer
+        .gen_texture_dual_view("gray".to_string(), Format::R8G8B8A8_SRGB, &mip_maps(), 64)
+        .unwrap();
+    assert_ne!(ids.linear_id.index, ids.srgb_id.index);
+    let texture = renderer.fetch_texture(ids.srgb_id).unwrap();
+    assert_eq!(texture.id, ids.linear_id.index);
+    assert_eq!(texture.format, Format::R8G8B8A8_UNORM);
+    let staging = texture.staging.as_ref().unwrap();
+    unsafe { std::ptr::write_bytes(staging.addr as *mut u8, GRAY, 64) };
+    renderer.queue_texture_for_uploading(ids.srgb_id).unwrap();
+    renderer.render();
+    renderer.render();
+    assert!(renderer.fetch_texture(ids.linear_id).unwrap().is_uploaded());
+
+    // Raw through the linear id, decoded to linear through the sRGB one
+    let gray = GRAY as f32 / 255.0;
+    let linear = sampled_red(&mut renderer, ids.linear_id);
+    let srgb = sampled_red(&mut renderer, ids.srgb_id);
+    assert_near(linear, albedo_of(gray));
+    assert_near(srgb, albedo_of(srgb_to_linear(gray)));
+    assert!(linear > srgb + 40);
+
+    // The image stays for the id left
+    renderer.free_texture(ids.linear_id).unwrap();
+    assert_eq!(renderer.free_texture(ids.linear_id), Err(StaleHandle));
+    assert!(renderer.fetch_texture(ids.linear_id).is_none());
+    renderer.render();
+    assert_near(sampled_red(&mut renderer, ids.srgb_id), srgb);
+
+    renderer.free_texture(ids.srgb_id).unwrap();
+    assert!(renderer.fetch_texture(ids.srgb_id).is_none());
+    renderer.render();
+    renderer.render();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
@@ -0,0 +1,52 @@
+/*
+ * Guards the cost of the default event sink, which sees a couple of events every frame
+ * even when nobody reads them. No logger is installed, so everything it forwards is off.
+ */
+use std::time::{Duration, Instant};
+
+use rend_vk::events::{LogSink, RenderEvent, RenderEventSink, RingBufferSink};
+use rend_vk::stats::FramePath;
+
+const FRAMES: u64 = 1_000_000;
+
+#[test]
+fn log_sink_is_cheap_without_logger() {
+    let mut sink = LogSink;
+    assert!(!sink.is_stage_timing_enabled());
+    let started_at = Instant::now();
+    for frame in 0..FRAMES {
+        sink.emit(RenderEvent::FrameStarted { frame });
+        sink.emit(RenderEvent::FrameEnded {
+            frame,
+            path: FramePath::Stages,
+            cpu_time: Duration::ZERO,
+        });
+    }
+    // Generous even for debug builds, a frame's worth of events should cost nanoseconds
+    let elapsed = started_at.elapsed();
+    assert!(
+        elapsed < Duration::from_secs(1),
+        "{} frames of events took {:?}",
+        FRAMES,
+        elapsed
+    );
+    assert!(sink.drain().is_empty());
+}
+
+#[test]
+fn ring_buffer_sink_keeps_the_latest() {
+    let mut sink = RingBufferSink::new(3);
+    for frame in 0..5 {
+        sink.emit(RenderEvent::FrameStarted { frame });
+    }
+    let frames: Vec<_> = sink
+        .drain()
+        .into_iter()
+        .map(|e| match e {
+            RenderEvent::FrameStarted { frame } => frame,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(frames, [2, 3, 4]);
+    assert!(sink.drain().is_empty());
+}
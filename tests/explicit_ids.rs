@@ -0,0 +1,126 @@
+/*
+ * Meshes and textures created with ids picked by the caller. The same sequence of calls
+ * runs twice through a renderer on a headless surface, once with internal and automatic
+ * allocations mixed in, and has to come out with the same ids both times.
+ */
+
+mod common;
+
+use rend_vk::format::Format;
+use rend_vk::handle::{IdUnavailable, MeshHandle, TextureHandle};
+use rend_vk::offscreen::OffscreenPassDesc;
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::texture::MipMap;
+
+const SIZE: u32 = 64;
+const MESH_IDS: [u32; 3] = [30, 31, 35];
+const TEXTURE_IDS: [u32; 3] = [40, 41, 45];
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("tests/offscreen.json", SIZE, SIZE)
+}
+
+fn mip_maps() -> [MipMap; 1] {
+    [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 4 * 4 * 4,
+        offset: 0,
+    }]
+}
+
+// Takes texture and mesh ids the way the renderer and automatic ids do
+fn allocate_internally(renderer: &mut Renderer) {
+    let desc = OffscreenPassDesc {
+        stage: "preview".to_string(),
+        width: SIZE,
+        height: SIZE,
+        format: Format::R8G8B8A8_UNORM,
+    };
+    renderer.render_to_texture(desc, Vec::new()).unwrap();
+    renderer
+        .gen_texture_dual_view("dual".to_string(), Format::R8G8B8A8_SRGB, &mip_maps(), 0)
+        .unwrap();
+    renderer.gen_texture("auto".to_string(), Format::R8G8B8A8_UNORM, &mip_maps(), 0);
+    renderer.gen_mesh(1024, 0, 0, 0, 3);
+    renderer.gen_mesh(1024, 0, 0, 0, 3);
+}
+
+fn create_in_sequence(is_perturbed: bool) -> (Vec<MeshHandle>, Vec<TextureHandle>) {
+    let mut renderer = make_renderer();
+    if is_perturbed {
+        allocate_internally(&mut renderer);
+    }
+    let mut meshes = Vec::new();
+    let mut textures = Vec::new();
+    for (i, (mesh_id, texture_id)) in MESH_IDS.into_iter().zip(TEXTURE_IDS).enumerate() {
+        meshes.push(
+            renderer
+                .gen_mesh_with_id(mesh_id, 1024, 0, 0, 0, 3)
+                .unwrap(),
+        );
+        let name = format!("texture {}", i);
+        let format = Format::R8G8B8A8_UNORM;
+        let texture = renderer.gen_texture_with_id(texture_id, name, format, &mip_maps(), 0);
+        textures.push(texture.unwrap());
+        if is_perturbed && i == 0 {
+            // Automatic ids handed out between explicit ones skip them
+            let auto = renderer.gen_mesh(1024, 0, 0, 0, 3);
+            assert!(!MESH_IDS.contains(&auto.index));
+        }
+    }
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+    (meshes, textures)
+}
+
+#[test]
+fn explicit_ids_ignore_internal_allocations() {
+    let _serial = common::serial();
+    let (meshes, textures) = create_in_sequence(false);
+    let indices: Vec<_> = meshes.iter().map(|e| e.index).collect();
+    assert_eq!(indices, MESH_IDS);
+    let indices: Vec<_> = textures.iter().map(|e| e.index).collect();
+    assert_eq!(indices, TEXTURE_IDS);
+    // Generations included, the handles are what a replay compares
+    assert_eq!(create_in_sequence(true), (meshes, textures));
+}
+
+#[test]
+fn ids_in_use_are_refused() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let format = Format::R8G8B8A8_UNORM;
+    let mesh = renderer.gen_mesh_with_id(30, 1024, 0, 0, 0, 3).unwrap();
+    assert_eq!(
+        renderer.gen_mesh_with_id(30, 1024, 0, 0, 0, 3),
+        Err(IdUnavailable { id: 30 })
+    );
+    let auto = renderer.gen_texture("auto".to_string(), format, &mip_maps(), 0);
+    let taken =
+        renderer.gen_texture_with_id(auto.index, "taken".to_string(), format, &mip_maps(), 0);
+    assert_eq!(taken, Err(IdUnavailable { id: auto.index }));
+    let huge = renderer.gen_texture_with_id(u32::MAX, "huge".to_string(), format, &mip_maps(), 0);
+    assert_eq!(huge, Err(IdUnavailable { id: u32::MAX }));
+    assert_eq!(
+        renderer.gen_mesh_with_id(u32::MAX, 1024, 0, 0, 0, 3),
+        Err(IdUnavailable { id: u32::MAX })
+    );
+
+    // Mesh ids are free again right away, texture slots once the next frame starts
+    renderer.free_mesh(mesh).unwrap();
+    let regenerated = renderer.gen_mesh_with_id(30, 1024, 0, 0, 0, 3).unwrap();
+    assert_ne!(regenerated.generation, mesh.generation);
+    renderer.free_texture(auto).unwrap();
+    let early =
+        renderer.gen_texture_with_id(auto.index, "early".to_string(), format, &mip_maps(), 0);
+    assert_eq!(early, Err(IdUnavailable { id: auto.index }));
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    renderer
+        .gen_texture_with_id(auto.index, "later".to_string(), format, &mip_maps(), 0)
+        .unwrap();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
@@ -0,0 +1,472 @@
+/*
+ * Drives the error paths of the renderer through the fault injector, on a headless
+ * surface with validation on. Every path has to report the failure, release whatever it
+ * had taken and, where the renderer defines it, render the next frame as usual.
+ * Validation errors logged by the object tracker on destroy count as leaks.
+ */
+#![cfg(feature = "fault-injection")]
+
+mod common;
+
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Mutex,
+};
+
+use rend_vk::events::{RenderEvent, RenderEventSink, RingBufferSink};
+use rend_vk::fault::{Fault, FaultInjector};
+use rend_vk::format::Format;
+use rend_vk::mesh_upload::MeshAttribute;
+use rend_vk::render_task::{RenderTask, TaskBounds, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::retry::{RetriedWork, RetryAfter, RetryError, RetryReason};
+use rend_vk::texture::MipMap;
+
+/*
+ * Sink shared with the test, the renderer owns the one it's given.
+ */
+#[derive(Clone)]
+struct SharedSink(std::sync::Arc<Mutex<RingBufferSink>>);
+
+impl RenderEventSink for SharedSink {
+    fn emit(&mut self, event: RenderEvent) {
+        self.0.lock().unwrap().emit(event);
+    }
+}
+
+impl SharedSink {
+    fn drain(&self) -> Vec<RenderEvent> {
+        self.0.lock().unwrap().drain()
+    }
+}
+
+struct Harness {
+    renderer: Renderer,
+    faults: FaultInjector,
+    events: SharedSink,
+}
+
+fn harness() -> Harness {
+    let mut renderer = common::make_renderer("pipeline.json", 64, 64);
+    let events = SharedSink(std::sync::Arc::new(Mutex::new(RingBufferSink::new(256))));
+    renderer.set_event_sink(Box::new(events.clone()));
+    let faults = FaultInjector::new();
+    renderer.set_fault_injector(Some(faults.clone()));
+    Harness {
+        renderer,
+        faults,
+        events,
+    }
+}
+
+impl Harness {
+    fn available(&self) -> u64 {
+        self.renderer.memory_report().general.available
+    }
+
+    fn finish(mut self) {
+        common::reset_validation_errors();
+        self.renderer.destroy();
+        assert_eq!(common::validation_errors(), 0, "leaked objects");
+    }
+}
+
+fn has_event(events: &[RenderEvent], f: impl Fn(&RenderEvent) -> bool) -> bool {
+    events.iter().any(f)
+}
+
+#[test]
+fn injector_counts_down() {
+    let faults = FaultInjector::new();
+    faults.fail_next(Fault::AllocFailed, 2);
+    assert_eq!(faults.pending(Fault::AllocFailed), 2);
+    assert!(!faults.take(Fault::SubmitDeviceLost));
+    assert!(faults.take(Fault::AllocFailed));
+    assert!(faults.take(Fault::AllocFailed));
+    assert!(!faults.take(Fault::AllocFailed));
+    assert_eq!(faults.injected(Fault::AllocFailed), 2);
+    faults.fail_next(Fault::DescriptorsFull, 3);
+    faults.clear();
+    assert!(!faults.take(Fault::DescriptorsFull));
+}
+
+#[test]
+fn passing_calls_go_first() {
+    let faults = FaultInjector::new();
+    faults.fail_after(Fault::AllocFailed, 2, 1);
+    assert!(!faults.take(Fault::AllocFailed));
+    assert!(!faults.take(Fault::AllocFailed));
+    assert!(faults.take(Fault::AllocFailed));
+    assert!(!faults.take(Fault::AllocFailed));
+}
+
+#[test]
+fn clones_share_counts() {
+    let faults = FaultInjector::new();
+    let other = faults.clone();
+    other.fail_next(Fault::AcquireOutOfDate, 1);
+    assert!(faults.take(Fault::AcquireOutOfDate));
+    assert_eq!(other.injected(Fault::AcquireOutOfDate), 1);
+}
+
+#[test]
+fn out_of_date_skips_the_frame() {
+    let _serial = common::serial();
+    let mut h = harness();
+    h.renderer.render();
+    h.faults.fail_next(Fault::AcquireOutOfDate, 1);
+    assert!(h.renderer.prepare_frame().is_none());
+    assert!(has_event(&h.events.drain(), |e| matches!(
+        e,
+        RenderEvent::SwapchainOutOfDate { .. }
+    )));
+    // The swapchain gets recreated and the next frame goes through
+    let frame = h.renderer.prepare_frame().expect("frame after out of date");
+    h.renderer.submit_frame(frame);
+    assert_eq!(h.faults.injected(Fault::AcquireOutOfDate), 1);
+    h.finish();
+}
+
+#[test]
+fn acquire_timeout_keeps_the_tasks() {
+    let _serial = common::serial();
+    let mut h = harness();
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    h.renderer
+        .add_task_to_queue(common::task(TaskKind::Fullscreen));
+    h.faults.fail_next(Fault::AcquireTimeout, 3);
+    for consecutive in 1..=3 {
+        assert_eq!(h.renderer.render(), FrameOutcome::AcquireTimedOut);
+        assert_eq!(h.renderer.consecutive_acquire_timeouts(), consecutive);
+    }
+    assert_eq!(h.renderer.queued_tasks(TaskKind::Fullscreen).count(), 1);
+    let events = h.events.drain();
+    assert!(has_event(&events, |e| matches!(
+        e,
+        RenderEvent::AcquireTimedOut { consecutive: 3, .. }
+    )));
+    // Acquisition goes through again with the tasks of the skipped frames
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(h.renderer.consecutive_acquire_timeouts(), 0);
+    let stats = h.renderer.frame_stats();
+    assert_eq!(stats.consecutive_acquire_timeouts, 3);
+    assert_eq!(stats.accepted(), 1);
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(h.renderer.frame_stats().consecutive_acquire_timeouts, 0);
+    h.finish();
+}
+
+#[test]
+fn suboptimal_still_renders() {
+    let _serial = common::serial();
+    let mut h = harness();
+    h.faults.fail_next(Fault::AcquireSuboptimal, 1);
+    let frame = h.renderer.prepare_frame().expect("suboptimal frame");
+    assert_eq!(h.renderer.submit_frame(frame), FrameOutcome::Suboptimal);
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(h.faults.injected(Fault::AcquireSuboptimal), 1);
+    h.finish();
+}
+
+fn count_events(events: &[RenderEvent], f: impl Fn(&RenderEvent) -> bool) -> usize {
+    events.iter().filter(|e| f(e)).count()
+}
+
+#[test]
+fn suboptimal_frames_rebuild_the_swapchain_once() {
+    let _serial = common::serial();
+    let mut h = harness();
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    let threshold = h.renderer.config().suboptimal_frames_before_rebuild;
+    h.faults.fail_next(Fault::AcquireSuboptimal, threshold + 2);
+    for consecutive in 1..=threshold {
+        assert_eq!(h.renderer.render(), FrameOutcome::Suboptimal);
+        assert_eq!(
+            h.renderer.frame_stats().consecutive_suboptimal_frames,
+            consecutive
+        );
+    }
+    // Rebuilt before the next frame, which starts counting again
+    for consecutive in 1..=2 {
+        assert_eq!(h.renderer.render(), FrameOutcome::Suboptimal);
+        assert_eq!(
+            h.renderer.frame_stats().consecutive_suboptimal_frames,
+            consecutive
+        );
+    }
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(h.renderer.frame_stats().consecutive_suboptimal_frames, 0);
+    let events = h.events.drain();
+    assert_eq!(
+        count_events(&events, |e| matches!(
+            e,
+            RenderEvent::SwapchainRecreated { .. }
+        )),
+        1
+    );
+    assert_eq!(
+        count_events(&events, |e| matches!(
+            e,
+            RenderEvent::SwapchainSuboptimal { .. }
+        )),
+        threshold as usize + 2
+    );
+    h.finish();
+}
+
+#[test]
+fn manual_rebuild_only_reports() {
+    let _serial = common::serial();
+    let mut h = harness();
+    let mut config = h.renderer.config().clone();
+    config.is_manual_swapchain_rebuild = true;
+    h.renderer.set_config(config);
+    let frames = h.renderer.config().suboptimal_frames_before_rebuild + 2;
+    h.faults.fail_next(Fault::AcquireSuboptimal, frames);
+    for _ in 0..frames {
+        assert_eq!(h.renderer.render(), FrameOutcome::Suboptimal);
+    }
+    assert_eq!(
+        h.renderer.frame_stats().consecutive_suboptimal_frames,
+        frames
+    );
+    let is_recreated = |e: &RenderEvent| matches!(e, RenderEvent::SwapchainRecreated { .. });
+    assert!(!has_event(&h.events.drain(), is_recreated));
+    h.renderer.rebuild_swapchain();
+    assert!(has_event(&h.events.drain(), is_recreated));
+    assert_eq!(h.renderer.render(), FrameOutcome::Submitted);
+    h.finish();
+}
+
+#[test]
+fn failed_mesh_allocation_frees_the_rest() {
+    let _serial = common::serial();
+    let mut h = harness();
+    h.renderer.render();
+    let available = h.available();
+    // Vertices go through, normals fail
+    h.faults.fail_after(Fault::AllocFailed, 1, 1);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        h.renderer.gen_mesh(1024, 1024, 0, 0, 3)
+    }));
+    assert!(result.is_err());
+    assert!(has_event(&h.events.drain(), |e| matches!(
+        e,
+        RenderEvent::AllocationFailed { .. }
+    )));
+    assert_eq!(h.available(), available);
+    let mesh = h.renderer.gen_mesh(1024, 1024, 0, 0, 3);
+    h.renderer.free_mesh(mesh).unwrap();
+    h.renderer.render();
+    h.finish();
+}
+
+#[test]
+fn staging_failure_retries_next_frame() {
+    let _serial = common::serial();
+    let mut h = harness();
+    let mesh = h.renderer.gen_mesh(4096, 0, 0, 0, 3);
+    let mut cursor = h
+        .renderer
+        .begin_mesh_upload(mesh, MeshAttribute::Vertices)
+        .unwrap();
+    let data = vec![0u8; 4096];
+    h.faults.fail_next(Fault::AllocFailed, 1);
+    assert_eq!(cursor.write(&data), 0);
+    h.renderer.render();
+    assert_eq!(cursor.write(&data), data.len());
+    cursor.finish();
+    for _ in 0..4 {
+        h.renderer.render();
+    }
+    assert!(h.renderer.is_upload_complete(mesh).unwrap());
+    h.renderer.free_mesh(mesh).unwrap();
+    h.renderer.render();
+    h.finish();
+}
+
+#[test]
+fn full_descriptors_reject_the_texture() {
+    let _serial = common::serial();
+    let mut h = harness();
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 64,
+        offset: 0,
+    }];
+    let available = h.available();
+    h.faults.fail_next(Fault::DescriptorsFull, 1);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        h.renderer
+            .gen_texture("full".to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 64)
+    }));
+    assert!(result.is_err());
+    assert_eq!(h.available(), available);
+    let texture = h
+        .renderer
+        .gen_texture("next".to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 64);
+    h.renderer.free_texture(texture).unwrap();
+    h.renderer.render();
+    h.renderer.render();
+    h.finish();
+}
+
+#[test]
+fn lost_device_is_reported() {
+    let _serial = common::serial();
+    let mut h = harness();
+    h.renderer.render();
+    h.faults.fail_next(Fault::SubmitDeviceLost, 1);
+    let result = catch_unwind(AssertUnwindSafe(|| h.renderer.render()));
+    assert!(result.is_err());
+    assert!(has_event(&h.events.drain(), |e| matches!(
+        e,
+        RenderEvent::DeviceLost { .. }
+    )));
+    // Nothing defines rendering after a lost device, only tearing down
+    h.renderer.destroy();
+}
+
+#[test]
+fn chunks_without_room_retry_until_staged() {
+    let _serial = common::serial();
+    let mut h = harness();
+    let mesh = h.renderer.gen_mesh(4096, 0, 0, 0, 3);
+    let mut cursor = h
+        .renderer
+        .begin_mesh_upload(mesh, MeshAttribute::Vertices)
+        .unwrap();
+    // The write and the first retry find no room
+    h.faults.fail_next(Fault::AllocFailed, 2);
+    cursor.write_all(&vec![0u8; 4096]);
+    cursor.finish();
+    assert_eq!(h.renderer.pending_work_summary().retried_mesh_chunks, 1);
+    for _ in 0..4 {
+        h.renderer.render();
+    }
+    assert!(h.renderer.is_upload_complete(mesh).unwrap());
+    assert!(has_event(&h.events.drain(), |e| matches!(
+        e,
+        RenderEvent::WorkRetried {
+            work: RetriedWork::Mesh(_),
+            reason: RetryReason::StagingFull,
+            attempt: 2,
+        }
+    )));
+    assert!(h.renderer.take_failed_work().is_empty());
+    h.renderer.free_mesh(mesh).unwrap();
+    h.renderer.render();
+    h.finish();
+}
+
+#[test]
+fn transitions_over_the_budget_wait_their_turn() {
+    let _serial = common::serial();
+    let mut h = harness();
+    // Built in textures out of the way first
+    h.renderer.render();
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 64,
+        offset: 0,
+    }];
+    let texture = h
+        .renderer
+        .gen_texture("late".to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 64);
+    h.faults.fail_next(Fault::UploadBudget, 2);
+    h.renderer.render();
+    let summary = h.renderer.pending_work_summary();
+    assert_eq!(summary.texture_uploads, 1);
+    assert_eq!(summary.retried_texture_uploads, 1);
+    assert!(!h.renderer.is_texture_uploaded(texture).unwrap());
+    for _ in 0..4 {
+        h.renderer.render();
+    }
+    assert!(h.renderer.is_texture_uploaded(texture).unwrap());
+    let retried = count_events(&h.events.drain(), |e| {
+        matches!(
+            e,
+            RenderEvent::WorkRetried {
+                reason: RetryReason::UploadBudget,
+                ..
+            }
+        )
+    });
+    assert_eq!(retried, 2);
+    h.renderer.free_texture(texture).unwrap();
+    h.renderer.render();
+    h.finish();
+}
+
+#[test]
+fn queued_readbacks_wait_for_room() {
+    let _serial = common::serial();
+    let mut h = harness();
+    let mesh = h.renderer.gen_mesh(1024, 0, 0, 0, 3);
+    let vertices = h.renderer.fetch_mesh(mesh).unwrap().vertices;
+    h.faults.fail_next(Fault::ReadbackBusy, 2);
+    let mut queued = h
+        .renderer
+        .queue_readback(move |e| e.read_buffer(&vertices, 0..256));
+    assert_eq!(
+        queued.poll().err(),
+        Some(RetryError::Transient(RetryAfter::NextFrame))
+    );
+    assert_eq!(h.renderer.pending_work_summary().retried_readbacks, 1);
+    h.renderer.render();
+    assert!(queued.poll().is_err());
+    h.renderer.render();
+    let mut request = queued.poll().unwrap();
+    h.renderer.render();
+    let bytes = request
+        .resolve_wait(&h.renderer, std::time::Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(bytes.len(), 256);
+    let work = RetriedWork::Readback(queued.id());
+    let retried = count_events(
+        &h.events.drain(),
+        |e| matches!(e, RenderEvent::WorkRetried { work: retried, .. } if *retried == work),
+    );
+    assert_eq!(retried, 2);
+    h.renderer.free_mesh(mesh).unwrap();
+    h.renderer.render();
+    h.finish();
+}
+
+#[test]
+fn work_past_the_retry_limit_fails_for_good() {
+    let _serial = common::serial();
+    let mut h = harness();
+    let mut config = h.renderer.config().clone();
+    config.retry_limit = 1;
+    h.renderer.set_config(config);
+    let mesh = h.renderer.gen_mesh(1024, 0, 0, 0, 3);
+    let vertices = h.renderer.fetch_mesh(mesh).unwrap().vertices;
+    h.faults.fail_next(Fault::ReadbackBusy, 8);
+    let mut queued = h
+        .renderer
+        .queue_readback(move |e| e.read_buffer(&vertices, 0..256));
+    h.renderer.render();
+    let exhausted = RetryError::Exhausted {
+        reason: RetryReason::ReadbackBusy,
+        attempts: 2,
+    };
+    assert_eq!(queued.poll().err(), Some(exhausted));
+    assert_eq!(
+        h.renderer.take_failed_work(),
+        [(RetriedWork::Readback(queued.id()), exhausted)]
+    );
+    assert!(has_event(&h.events.drain(), |e| matches!(
+        e,
+        RenderEvent::WorkFailed { attempts: 2, .. }
+    )));
+    assert_eq!(h.renderer.pending_work_summary().readbacks, 0);
+    h.faults.clear();
+    h.renderer.free_mesh(mesh).unwrap();
+    h.renderer.render();
+    h.finish();
+}
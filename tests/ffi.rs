@@ -0,0 +1,218 @@
+/*
+ * Smoke test of the C ABI, run with cargo test --features ffi. Loads the cdylib the way
+ * a C program would and only goes through the exported symbols, so it runs without a
+ * window or a GPU.
+ */
+#![cfg(feature = "ffi")]
+
+use std::ffi::{c_char, c_void, CStr};
+use std::path::{Path, PathBuf};
+
+use libloading::{Library, Symbol};
+
+// Same values as RendVkResult
+const OK: u32 = 0;
+const NULL_ARGUMENT: u32 = 1;
+const PANIC: u32 = 8;
+
+// Mirrors RendVkConfig
+#[repr(C)]
+struct Config {
+    is_vsync_enabled: bool,
+    is_debug_enabled: bool,
+    is_validation_layer_enabled: bool,
+    instance_extensions: *const *const c_char,
+    instance_extensions_len: u32,
+}
+
+type SurfaceCallback = extern "C" fn(*mut c_void, *mut c_void, *mut u64) -> i32;
+
+/*
+ * cargo test builds the cdylib next to the test binary in target/<profile>/deps, only
+ * cargo build copies it one level up. Falls back to the debug build in CARGO_TARGET_DIR.
+ */
+fn library_path() -> Option<PathBuf> {
+    let name = libloading::library_filename("rend_vk");
+    let exe = std::env::current_exe().unwrap();
+    let mut candidates: Vec<PathBuf> = exe
+        .ancestors()
+        .skip(1)
+        .take(2)
+        .map(|e| e.join(&name))
+        .collect();
+    if let Some(dir) = std::env::var_os("CARGO_TARGET_DIR") {
+        candidates.push(Path::new(&dir).join("debug").join(&name));
+    }
+    candidates.into_iter().find(|e| e.is_file())
+}
+
+/*
+ * None when the cdylib couldn't be found, the tests then get skipped with a message.
+ */
+fn load() -> Option<Library> {
+    let path = match library_path() {
+        Some(e) => e,
+        None => {
+            eprintln!(
+                "skipped, no rend_vk cdylib next to the test binary or in CARGO_TARGET_DIR, \
+                 build it with cargo build --features ffi"
+            );
+            return None;
+        }
+    };
+    let lib = unsafe { Library::new(&path) }
+        .unwrap_or_else(|e| panic!("couldn't load {}: {}", path.display(), e));
+    Some(lib)
+}
+
+fn last_error(lib: &Library) -> Option<String> {
+    let message: Symbol<extern "C" fn() -> *const c_char> =
+        unsafe { lib.get(b"rendvk_last_error_message\0") }.unwrap();
+    let ptr = message();
+    (!ptr.is_null()).then(|| {
+        unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+#[test]
+fn null_renderer_is_an_error() {
+    let lib = match load() {
+        Some(e) => e,
+        None => return,
+    };
+    let render: Symbol<extern "C" fn(*mut c_void) -> u32> =
+        unsafe { lib.get(b"rendvk_render\0") }.unwrap();
+    let destroy: Symbol<extern "C" fn(*mut c_void) -> u32> =
+        unsafe { lib.get(b"rendvk_destroy\0") }.unwrap();
+    assert_eq!(render(std::ptr::null_mut()), NULL_ARGUMENT);
+    assert_eq!(last_error(&lib).as_deref(), Some("renderer is null"));
+    assert_eq!(destroy(std::ptr::null_mut()), OK);
+    assert_eq!(last_error(&lib), None);
+}
+
+#[test]
+fn bad_arguments_are_rejected() {
+    let lib = match load() {
+        Some(e) => e,
+        None => return,
+    };
+    let make: Symbol<
+        extern "C" fn(*const Config, Option<SurfaceCallback>, *mut c_void, *mut *mut c_void) -> u32,
+    > = unsafe { lib.get(b"rendvk_make_renderer\0") }.unwrap();
+    let size_of: Symbol<extern "C" fn(u32) -> u32> =
+        unsafe { lib.get(b"rendvk_resource_size_of\0") }.unwrap();
+    let config = Config {
+        is_vsync_enabled: true,
+        is_debug_enabled: false,
+        is_validation_layer_enabled: false,
+        instance_extensions: std::ptr::null(),
+        instance_extensions_len: 0,
+    };
+    let mut out = std::ptr::null_mut();
+    assert_eq!(
+        make(&config, None, std::ptr::null_mut(), &mut out),
+        NULL_ARGUMENT
+    );
+    assert!(out.is_null());
+    assert_ne!(size_of(0), 0);
+    assert_eq!(size_of(u32::MAX), 0);
+}
+
+extern "C" fn failing_surface(_: *mut c_void, _: *mut c_void, _: *mut u64) -> i32 {
+    // VK_ERROR_INITIALIZATION_FAILED
+    -3
+}
+
+#[test]
+fn panics_stay_on_the_rust_side() {
+    let lib = match load() {
+        Some(e) => e,
+        None => return,
+    };
+    let make: Symbol<
+        extern "C" fn(*const Config, Option<SurfaceCallback>, *mut c_void, *mut *mut c_void) -> u32,
+    > = unsafe { lib.get(b"rendvk_make_renderer\0") }.unwrap();
+    let config = Config {
+        is_vsync_enabled: true,
+        is_debug_enabled: false,
+        is_validation_layer_enabled: false,
+        instance_extensions: std::ptr::null(),
+        instance_extensions_len: 0,
+    };
+    let mut out = std::ptr::null_mut();
+    // Fails on the surface at the latest, or earlier without a device
+    let result = make(
+        &config,
+        Some(failing_surface),
+        std::ptr::null_mut(),
+        &mut out,
+    );
+    assert_eq!(result, PANIC);
+    assert!(out.is_null());
+    assert!(last_error(&lib).unwrap().starts_with("renderer panicked"));
+}
+
+// Mirrors RendVkTask
+#[repr(C)]
+struct Task {
+    kind: u32,
+    mesh: u64,
+    instance_count: u32,
+    variant: u32,
+    alpha_cutoff: f32,
+    is_two_sided: bool,
+    resource_bits: u32,
+    resources: *const u8,
+    resources_len: u32,
+}
+
+// Same values as RendVkResult, ResourceKind and TaskKind
+const INVALID_ARGUMENT: u32 = 2;
+const TRANSFORM_BIT: u32 = 1;
+const FRUSTUM_BIT: u32 = 1 << 3;
+const MESH_STATIC: u32 = 0;
+
+#[test]
+fn task_resources_are_checked_before_unpacking() {
+    let lib = match load() {
+        Some(e) => e,
+        None => return,
+    };
+    let add_task: Symbol<extern "C" fn(*mut c_void, *const Task) -> u32> =
+        unsafe { lib.get(b"rendvk_add_task\0") }.unwrap();
+    let size_of: Symbol<extern "C" fn(u32) -> u32> =
+        unsafe { lib.get(b"rendvk_resource_size_of\0") }.unwrap();
+    let transforms_size = size_of(0) as usize * 2;
+    // One byte in, so the transforms sit at an odd address
+    let storage = vec![0u8; transforms_size + 1];
+    let task = |resource_bits: u32, resources_len: usize| Task {
+        kind: MESH_STATIC,
+        mesh: 0,
+        instance_count: 2,
+        variant: u32::MAX,
+        alpha_cutoff: 0.0,
+        is_two_sided: false,
+        resource_bits,
+        resources: storage[1..].as_ptr(),
+        resources_len: resources_len as u32,
+    };
+
+    // Kinds tasks can't carry are refused, not unpacked into a panic
+    let frustums = task(TRANSFORM_BIT | FRUSTUM_BIT, transforms_size);
+    assert_eq!(add_task(std::ptr::null_mut(), &frustums), INVALID_ARGUMENT);
+    assert_eq!(
+        last_error(&lib).as_deref(),
+        Some("unsupported resource kinds in bits 0x8")
+    );
+
+    let short = task(TRANSFORM_BIT, transforms_size - 1);
+    assert_eq!(add_task(std::ptr::null_mut(), &short), INVALID_ARGUMENT);
+    assert!(last_error(&lib).unwrap().starts_with("resources take"));
+
+    // Unpacked from the unaligned bytes, then stopped at the missing renderer
+    let unaligned = task(TRANSFORM_BIT, transforms_size);
+    assert_eq!(add_task(std::ptr::null_mut(), &unaligned), NULL_ARGUMENT);
+    assert_eq!(last_error(&lib).as_deref(), Some("renderer is null"));
+}
@@ -0,0 +1,96 @@
+/*
+ * Resource only use of the renderer, render never gets called. Uploads, offscreen passes
+ * of the preview stage of tests/offscreen.json and readbacks go through flush_gpu_work on
+ * a headless surface with validation on, objects destroy finds alive count as errors.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::format::Format;
+use rend_vk::offscreen::OffscreenPassDesc;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::stats::FramePath;
+use rend_vk::texture::{MipMap, Residency};
+
+const SIZE: u32 = 64;
+const TEXTURE_SIZE: u32 = 32;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("tests/offscreen.json", SIZE, SIZE)
+}
+
+fn fill(gray: u8) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: gray as f32 / 255.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn uploads_passes_and_readbacks_finish_without_rendering() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+    let mip = MipMap {
+        index: 0,
+        width: TEXTURE_SIZE,
+        height: TEXTURE_SIZE,
+        size,
+        offset: 0,
+    };
+    let texture = renderer.gen_texture("gray".to_string(), Format::R8G8B8A8_UNORM, &[mip], size);
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::write_bytes(staging.addr as *mut u8, 0x40, size as usize) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert_eq!(
+        renderer.texture_residency(texture).unwrap(),
+        Residency::Resident
+    );
+    assert_eq!(renderer.frame_stats().path, FramePath::Flush);
+
+    let desc = OffscreenPassDesc {
+        stage: "preview".to_string(),
+        width: TEXTURE_SIZE,
+        height: TEXTURE_SIZE,
+        format: Format::R8G8B8A8_UNORM,
+    };
+    let preview = renderer.render_to_texture(desc, vec![fill(120)]).unwrap();
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert_eq!(
+        renderer.texture_residency(preview).unwrap(),
+        Residency::Resident
+    );
+
+    let mut uploaded = renderer.read_texture(texture).unwrap();
+    let mut rendered = renderer.read_texture(preview).unwrap();
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    let uploaded = uploaded.try_resolve(&renderer).unwrap();
+    assert!(uploaded.iter().all(|e| *e == 0x40));
+    let rendered = rendered.try_resolve(&renderer).unwrap();
+    assert!(rendered.chunks(4).all(|e| e == [120, 120, 120, 255]));
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn queued_tasks_wait_for_the_next_render() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    renderer.add_task_to_queue(fill(80));
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert_eq!(renderer.frame_stats().path, FramePath::Flush);
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_ne!(renderer.frame_stats().path, FramePath::Idle);
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
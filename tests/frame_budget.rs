@@ -0,0 +1,116 @@
+/*
+ * Which stages a FrameBudget skips and brings back, fed synthetic GPU times. Nothing
+ * touches the GPU.
+ */
+use std::time::Duration;
+
+use rend_vk::frame_budget::{BudgetChanges, FrameBudget, MIN_SKIPPED, WINDOW};
+use rend_vk::pipeline::file::Pass;
+
+fn ms(v: u64) -> Duration {
+    Duration::from_millis(v)
+}
+
+// Fills the windows with the same times and updates once per frame
+fn run(budget: &mut FrameBudget, timings: &[Duration], frames: usize) -> Vec<BudgetChanges> {
+    (0..frames)
+        .map(|_| {
+            budget.record(timings);
+            budget.update()
+        })
+        .collect()
+}
+
+#[test]
+fn lowest_priorities_get_skipped_first() {
+    // Required, then priorities 2, 1 and 1
+    let mut budget = FrameBudget::new(ms(12), vec![None, Some(2), Some(1), Some(1)]);
+    let changes = run(&mut budget, &[ms(6), ms(3), ms(2), ms(2)], 1);
+    // 13ms over 12ms, the later one of the lowest priority is enough
+    assert_eq!(changes[0].skipped, [3]);
+    assert_eq!(budget.skipped(), [3]);
+    assert_eq!(budget.predicted(), ms(11));
+    // Averaging 4ms now
+    let changes = run(&mut budget, &[ms(6), ms(3), ms(6), ms(0)], 1);
+    assert_eq!(changes[0].skipped, [2]);
+    assert_eq!(budget.skipped(), [2, 3]);
+    assert_eq!(budget.predicted(), ms(9));
+}
+
+#[test]
+fn required_stages_never_get_skipped() {
+    let mut budget = FrameBudget::new(ms(5), vec![None, Some(0), None]);
+    let changes = run(&mut budget, &[ms(4), ms(1), ms(4)], 1);
+    assert_eq!(changes[0].skipped, [1]);
+    // Still over, nothing left to skip
+    assert!(run(&mut budget, &[ms(4), ms(0), ms(4)], 1)[0].is_empty());
+    assert_eq!(budget.skipped(), [1]);
+    assert_eq!(budget.predicted(), ms(8));
+}
+
+#[test]
+fn estimates_average_the_window() {
+    let mut budget = FrameBudget::new(ms(100), vec![Some(0)]);
+    assert_eq!(budget.estimate(0), Duration::ZERO);
+    run(&mut budget, &[ms(2)], WINDOW);
+    assert_eq!(budget.estimate(0), ms(2));
+    // A single spike moves it by its share
+    run(&mut budget, &[ms(2 + WINDOW as u64)], 1);
+    assert_eq!(budget.estimate(0), ms(3));
+    // Unrecorded stages time nothing and get left out
+    run(&mut budget, &[Duration::ZERO], WINDOW);
+    assert_eq!(budget.estimate(0), ms(3));
+    run(&mut budget, &[ms(2)], WINDOW);
+    assert_eq!(budget.estimate(0), ms(2));
+}
+
+#[test]
+fn skipped_stages_wait_before_coming_back() {
+    let mut budget = FrameBudget::new(ms(10), vec![None, Some(0)]);
+    run(&mut budget, &[ms(8), ms(4)], 1);
+    assert_eq!(budget.skipped(), [1]);
+    // Well under the budget without it, but not skipped for long enough
+    let changes = run(&mut budget, &[ms(4), ms(0)], WINDOW);
+    assert!(changes.iter().all(BudgetChanges::is_empty));
+    let changes = run(&mut budget, &[ms(4), ms(0)], MIN_SKIPPED as usize);
+    let restored: Vec<_> = changes.iter().flat_map(|e| e.restored.clone()).collect();
+    assert_eq!(restored, [1]);
+    assert!(changes.last().unwrap().restored.is_empty());
+    assert!(budget.skipped().is_empty());
+}
+
+#[test]
+fn stages_stay_skipped_right_under_the_budget() {
+    let mut budget = FrameBudget::new(ms(10), vec![None, Some(0)]);
+    run(&mut budget, &[ms(8), ms(3)], 1);
+    assert_eq!(budget.skipped(), [1]);
+    // 9ms with it back fits the budget but not the restore ratio, no flapping
+    let changes = run(&mut budget, &[ms(6), ms(0)], 4 * MIN_SKIPPED as usize);
+    assert!(changes.iter().all(BudgetChanges::is_empty));
+    assert_eq!(budget.skipped(), [1]);
+}
+
+#[test]
+fn highest_priorities_come_back_first() {
+    let mut budget = FrameBudget::new(ms(10), vec![Some(5), Some(1), None]);
+    run(&mut budget, &[ms(3), ms(3), ms(9)], 1);
+    assert_eq!(budget.skipped(), [0, 1]);
+    let changes = run(
+        &mut budget,
+        &[ms(0), ms(0), ms(1)],
+        MIN_SKIPPED as usize + 2,
+    );
+    let restored: Vec<_> = changes.iter().flat_map(|e| e.restored.clone()).collect();
+    assert_eq!(restored, [0, 1]);
+    // One a frame
+    assert!(changes.iter().all(|e| e.restored.len() <= 1));
+    assert!(budget.skipped().is_empty());
+}
+
+#[test]
+fn priorities_come_from_the_pass() {
+    let pass: Pass = serde_json::from_str(r#"{ "name": "bloom", "budgetPriority": 3 }"#).unwrap();
+    assert_eq!(pass.budget_priority, Some(3));
+    let pass: Pass = serde_json::from_str(r#"{ "name": "gbuffer" }"#).unwrap();
+    assert_eq!(pass.budget_priority, None);
+}
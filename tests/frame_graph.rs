@@ -0,0 +1,34 @@
+/*
+ * Frame graph of the shipped pipeline loaded on a headless surface with validation on,
+ * against the snapshot the dry run gets compared with in tests/dry_run.rs.
+ */
+
+mod common;
+
+/*
+ * The dry run has no reflection, the sizes of the blocks the shaders declare aren't in
+ * the snapshot.
+ */
+fn without_blocks(graph: &str) -> String {
+    graph
+        .lines()
+        .map(|line| {
+            line.split("\\n")
+                .filter(|e| !e.ends_with(" bytes"))
+                .collect::<Vec<_>>()
+                .join("\\n")
+        })
+        .map(|e| e + "\n")
+        .collect()
+}
+
+#[test]
+fn loaded_pipeline_matches_the_dry_run_snapshot() {
+    let _serial = common::serial();
+    let renderer = common::make_renderer("pipeline.json", 64, 64);
+    let graph = renderer.dump_frame_graph();
+    assert!(graph.contains(" bytes"), "{}", graph);
+    let expected = std::fs::read_to_string("tests/pipeline_files/frame_graph.dot").unwrap();
+    assert_eq!(without_blocks(&graph), expected);
+    common::finish(renderer);
+}
@@ -0,0 +1,200 @@
+/*
+ * Frame ring bookkeeping over a made up slice, nothing in it is ever written.
+ */
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use rend_vk::buffer::DeviceSlice;
+use rend_vk::frame_ring::{FrameRing, RingError};
+
+const BASE_OFFSET: u64 = 4096;
+const BASE_ADDR: u64 = 1 << 32;
+
+fn ring(size: u64, frames_in_flight: u32) -> FrameRing {
+    let slice = DeviceSlice {
+        size,
+        offset: BASE_OFFSET,
+        alignment: 256,
+        device_addr: BASE_ADDR + BASE_OFFSET,
+        ..DeviceSlice::empty()
+    };
+    FrameRing::new(slice, frames_in_flight, 4)
+}
+
+// Offset in the ring, checking the slice agrees with itself
+fn offset_of(slice: &DeviceSlice) -> u64 {
+    let offset = slice.offset - BASE_OFFSET;
+    assert_eq!(slice.device_addr, BASE_ADDR + slice.offset);
+    assert_eq!(slice.addr as u64, offset);
+    offset
+}
+
+#[test]
+fn allocations_skip_the_tail_instead_of_straddling_it() {
+    let mut ring = ring(1024, 2);
+    ring.reset(0);
+    assert_eq!(offset_of(&ring.alloc(400, 16).unwrap()), 0);
+    ring.reset(1);
+    assert_eq!(offset_of(&ring.alloc(400, 16).unwrap()), 400);
+    ring.reset(0);
+    // 224 bytes left before the end, not enough
+    assert_eq!(ring.available(), 624);
+    assert_eq!(offset_of(&ring.alloc(300, 16).unwrap()), 0);
+    // The skipped tail stays taken
+    assert_eq!(ring.available(), 100);
+    let marks = ring.watermarks();
+    assert_eq!(marks.frame_bytes, 524);
+    assert_eq!(marks.tail_skips, 1);
+    assert_eq!(marks.tail_waste_bytes, 224);
+    assert_eq!(
+        ring.alloc(200, 16).err(),
+        Some(RingError::Full {
+            size: 200,
+            available: 100
+        })
+    );
+    ring.reset(1);
+    // Frame 0 keeps the tail it skipped along with what it allocated
+    assert_eq!(ring.available(), 500);
+    ring.reset(0);
+    assert_eq!(ring.available(), 1024);
+}
+
+#[test]
+fn allocations_larger_than_a_region_fail() {
+    let mut ring = ring(1024, 2);
+    ring.reset(0);
+    let error = ring.alloc(513, 1).err().unwrap();
+    assert_eq!(
+        error,
+        RingError::TooLarge {
+            size: 513,
+            region_size: 512
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "frame data of 513 bytes is larger than the per-frame region of 512 bytes"
+    );
+    // Nothing taken by the failed one
+    assert_eq!(ring.available(), 1024);
+    assert!(ring.alloc(512, 1).is_ok());
+}
+
+#[test]
+fn allocations_are_aligned() {
+    let mut ring = ring(1024, 1);
+    ring.reset(0);
+    ring.alloc(3, 1).unwrap();
+    assert_eq!(offset_of(&ring.alloc(8, 64).unwrap()), 64);
+    assert_eq!(offset_of(&ring.alloc(1, 4).unwrap()), 72);
+    assert_eq!(ring.watermarks().frame_bytes, 73);
+}
+
+#[test]
+#[should_panic(expected = "before resetting any frame")]
+fn allocating_before_a_reset_panics() {
+    ring(1024, 1).alloc(16, 16).unwrap();
+}
+
+#[test]
+fn watermarks_keep_the_peak_of_the_last_frames() {
+    let mut ring = ring(4096, 1);
+    for size in [100, 900, 300, 200, 100, 100, 100] {
+        ring.reset(0);
+        ring.alloc(size, 1).unwrap();
+    }
+    let marks = ring.watermarks();
+    assert_eq!(marks.frame_bytes, 100);
+    // The current frame and the 4 before it, the 900 one fell out
+    assert_eq!(marks.peak_frame_bytes, 300);
+    assert_eq!(marks.size, 4096);
+    assert_eq!(marks.region_size, 4096);
+}
+
+#[test]
+fn clearing_reclaims_every_frame() {
+    let mut ring = ring(1000, 3);
+    for i in 0..3 {
+        ring.reset(i);
+        ring.alloc(300, 4).unwrap();
+    }
+    assert_eq!(ring.available(), 100);
+    ring.clear();
+    assert_eq!(ring.available(), 1000);
+    ring.reset(1);
+    assert_eq!(ring.guaranteed_available(), 1000);
+    assert_eq!(offset_of(&ring.alloc(300, 4).unwrap()), 0);
+}
+
+/*
+ * Random sizes, alignments and frame counts, checking every allocation against the
+ * ones of the frames still alive.
+ */
+#[test]
+fn random_allocations_never_overlap() {
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    for _ in 0..200 {
+        let size = rng.gen_range(256..8192);
+        let frames_in_flight = rng.gen_range(1..4u32);
+        let mut ring = ring(size, frames_in_flight);
+        let region_size = ring.region_size();
+        let mut live: Vec<Vec<(u64, u64)>> = vec![Vec::new(); frames_in_flight as usize];
+        for frame in 0..rng.gen_range(1..64usize) {
+            let index = frame % frames_in_flight as usize;
+            ring.reset(index);
+            live[index].clear();
+            let guaranteed = ring.guaranteed_available();
+            let mut allocated = 0;
+            for _ in 0..rng.gen_range(0..16) {
+                let bytes = rng.gen_range(0..region_size + region_size / 4);
+                let alignment = 1 << rng.gen_range(0..9);
+                let available = ring.available();
+                let slice = match ring.alloc(bytes, alignment) {
+                    Ok(e) => e,
+                    Err(RingError::TooLarge {
+                        size: requested,
+                        region_size: region,
+                    }) => {
+                        assert!(requested > region && region == region_size);
+                        assert_eq!(ring.available(), available);
+                        continue;
+                    }
+                    Err(RingError::Full {
+                        size: requested,
+                        available: left,
+                    }) => {
+                        // Only past what was guaranteed, padding included
+                        assert_eq!(requested, bytes);
+                        assert_eq!(left, available);
+                        assert!(allocated + bytes + alignment - 1 > guaranteed);
+                        assert_eq!(ring.available(), available);
+                        continue;
+                    }
+                };
+                let offset = offset_of(&slice);
+                assert_eq!(offset % alignment, 0);
+                assert!(offset + bytes <= size, "{} straddles the end", offset);
+                for &(start, len) in live.iter().flatten() {
+                    assert!(
+                        offset + bytes <= start || start + len <= offset || bytes == 0 || len == 0,
+                        "{}+{} overlaps {}+{}",
+                        offset,
+                        bytes,
+                        start,
+                        len
+                    );
+                }
+                live[index].push((offset, bytes));
+                allocated += bytes + alignment - 1;
+                let taken: u64 = live.iter().flatten().map(|e| e.1).sum();
+                assert!(taken + ring.available() <= size);
+            }
+        }
+        // Resetting every frame in turn leaves the whole ring to the last one
+        for index in 0..frames_in_flight as usize {
+            ring.reset(index);
+        }
+        assert_eq!(ring.available(), size);
+        assert_eq!(ring.guaranteed_available(), size);
+    }
+}
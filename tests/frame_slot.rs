@@ -0,0 +1,44 @@
+/*
+ * Waiting for a frame slot before queueing the next frame, on a headless surface with
+ * validation on. A readback of the frame tells whether the GPU is done with it.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn fill() -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 1.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn waiting_for_no_frames_ahead_finishes_the_previous_frame() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    for _ in 0..3 {
+        renderer.add_task_to_queue(fill());
+        let mut request = renderer.read_attachment("ui").unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        renderer.wait_for_frame_slot(0, TIMEOUT).unwrap();
+        assert!(request.try_resolve(&renderer).is_some());
+    }
+    common::finish(renderer);
+}
+
+#[test]
+fn frames_ahead_past_the_submitted_ones_dont_wait() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    renderer.add_task_to_queue(fill());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert!(renderer.wait_for_frame_slot(8, Duration::ZERO).is_ok());
+    common::finish(renderer);
+}
@@ -0,0 +1,195 @@
+/*
+ * The skyline packer on synthetic glyph runs, no GPU involved, and glyph atlases on a
+ * headless surface with validation on, read back after their region updates landed.
+ * Validation errors logged by the object tracker on destroy count as leaks.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use rend_vk::format::Format;
+use rend_vk::glyph_atlas::{AtlasFull, GlyphAtlas, SkylinePacker};
+use rend_vk::renderer::Renderer;
+use rend_vk::texture::Residency;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+use common::finish_without_leaks as finish;
+
+/*
+ * Packs glyph sized rectangles until the first one that doesn't fit, returning where
+ * they went.
+ */
+fn pack_until_full(packer: &mut SkylinePacker, sizes: &[[u32; 2]]) -> Vec<[u32; 4]> {
+    let mut packed = Vec::new();
+    for &[width, height] in sizes {
+        match packer.pack(width, height) {
+            Some([x, y]) => packed.push([x, y, width, height]),
+            None => break,
+        }
+    }
+    packed
+}
+
+fn glyph_sizes(seed: u64, count: usize) -> Vec<[u32; 2]> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            // Mostly lowercase heights, some capitals and descenders
+            let height = match rng.gen_range(0..10) {
+                0..=5 => rng.gen_range(10..14),
+                6..=8 => rng.gen_range(14..20),
+                _ => rng.gen_range(20..28),
+            };
+            [rng.gen_range(4..20), height]
+        })
+        .collect()
+}
+
+#[test]
+fn packed_rectangles_never_overlap() {
+    for seed in 0..20 {
+        let mut packer = SkylinePacker::new(256, 256);
+        let packed = pack_until_full(&mut packer, &glyph_sizes(seed, 2000));
+        for (i, a) in packed.iter().enumerate() {
+            assert!(
+                a[0] + a[2] <= 256 && a[1] + a[3] <= 256,
+                "{:?} is outside",
+                a
+            );
+            for b in &packed[i + 1..] {
+                let is_apart = a[0] + a[2] <= b[0]
+                    || b[0] + b[2] <= a[0]
+                    || a[1] + a[3] <= b[1]
+                    || b[1] + b[3] <= a[1];
+                assert!(is_apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+}
+
+#[test]
+fn glyph_runs_fill_most_of_the_atlas() {
+    for seed in 0..20 {
+        let mut packer = SkylinePacker::new(512, 512);
+        pack_until_full(&mut packer, &glyph_sizes(seed, 5000));
+        assert!(packer.occupancy() > 0.8, "{}", packer.occupancy());
+        // Tallest first leaves less below the skyline
+        let mut sizes = glyph_sizes(seed, 5000);
+        sizes.sort_by_key(|e| std::cmp::Reverse(e[1]));
+        let mut packer = SkylinePacker::new(512, 512);
+        pack_until_full(&mut packer, &sizes);
+        assert!(packer.occupancy() > 0.9, "{}", packer.occupancy());
+    }
+}
+
+#[test]
+fn rectangles_too_large_do_not_fit() {
+    let mut packer = SkylinePacker::new(64, 64);
+    assert_eq!(packer.pack(65, 1), None);
+    assert_eq!(packer.pack(64, 64), Some([0, 0]));
+    assert_eq!(packer.pack(1, 1), None);
+    packer.clear();
+    assert_eq!(packer.pack(32, 16), Some([0, 0]));
+    assert_eq!(packer.pack(32, 8), Some([32, 0]));
+    // Lowest top wins over the leftmost spot
+    assert_eq!(packer.pack(16, 8), Some([32, 8]));
+}
+
+fn read_atlas(renderer: &mut Renderer, atlas: &GlyphAtlas<char>) -> Vec<u8> {
+    for _ in 0..8 {
+        renderer.render();
+    }
+    assert_eq!(
+        renderer.texture_residency(atlas.texture()).unwrap(),
+        Residency::Resident
+    );
+    let mut request = renderer.read_texture(atlas.texture()).unwrap();
+    renderer.render();
+    request.resolve_wait(renderer, TIMEOUT).unwrap()
+}
+
+#[test]
+fn inserted_glyphs_read_back_at_their_slots() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let mut atlas = GlyphAtlas::new(&mut renderer, 64, Format::R8_UNORM);
+    let a = atlas
+        .insert(&mut renderer, 'a', 8, 12, &[0x40; 8 * 12])
+        .unwrap();
+    let b = atlas
+        .insert(&mut renderer, 'b', 10, 16, &[0xc0; 10 * 16])
+        .unwrap();
+    assert_eq!(atlas.get(&'a'), Some(a));
+    assert_eq!(a.uv_max - a.uv_min, glam::Vec2::new(8.0, 12.0) / 64.0);
+
+    let texels = read_atlas(&mut renderer, &atlas);
+    let texel = |x: u32, y: u32| texels[(y * 64 + x) as usize];
+    assert_eq!(texel(a.x, a.y), 0x40);
+    assert_eq!(texel(a.x + 7, a.y + 11), 0x40);
+    assert_eq!(texel(b.x + 9, b.y + 15), 0xc0);
+    // Padding stays empty
+    assert_eq!(texel(a.x + 8, a.y), 0);
+    assert_eq!(texel(b.x, b.y + 16), 0);
+
+    atlas.destroy(&mut renderer);
+    finish(renderer);
+}
+
+#[test]
+fn full_atlases_grow_then_evict() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let mut atlas = GlyphAtlas::new(&mut renderer, 32, Format::R8_UNORM);
+    atlas.set_max_size(64);
+    let glyph = |i: u8| vec![i + 1; 15 * 15];
+    for i in 0..4 {
+        atlas
+            .insert(&mut renderer, (b'a' + i) as char, 15, 15, &glyph(i))
+            .unwrap();
+    }
+    let first = atlas.texture();
+    atlas.insert(&mut renderer, 'e', 15, 15, &glyph(4)).unwrap();
+    assert_eq!(atlas.size(), 64);
+    assert_eq!(atlas.revision(), 1);
+    assert_ne!(atlas.texture(), first);
+    let texels = read_atlas(&mut renderer, &atlas);
+    for i in 0..5 {
+        let slot = atlas.get(&((b'a' + i) as char)).unwrap();
+        assert_eq!(texels[(slot.y * 64 + slot.x) as usize], i + 1);
+    }
+
+    for i in 5..16 {
+        atlas
+            .insert(&mut renderer, (b'a' + i) as char, 15, 15, &glyph(i))
+            .unwrap();
+    }
+    assert_eq!(
+        atlas.insert(&mut renderer, 'q', 15, 15, &glyph(16)),
+        Err(AtlasFull {
+            width: 15,
+            height: 15
+        })
+    );
+    atlas.set_lru_eviction(true);
+    // Recently looked up ones survive
+    atlas.get(&'a');
+    atlas
+        .insert(&mut renderer, 'q', 15, 15, &glyph(16))
+        .unwrap();
+    assert!(atlas.contains(&'a') && !atlas.contains(&'b'));
+    assert_eq!(atlas.len(), 13);
+    let texels = read_atlas(&mut renderer, &atlas);
+    let slot = atlas.get(&'a').unwrap();
+    assert_eq!(texels[(slot.y * 64 + slot.x) as usize], 1);
+
+    atlas.destroy(&mut renderer);
+    finish(renderer);
+}
@@ -0,0 +1,111 @@
+/*
+ * Control loop of the quality governor fed with made up frame times, no GPU involved.
+ */
+use std::time::Duration;
+
+use rend_vk::governor::{Pressure, QualityGovernor, QualityGovernorConfig, QualityOverride};
+
+const TARGET: Duration = Duration::from_millis(10);
+
+fn config() -> QualityGovernorConfig {
+    QualityGovernorConfig {
+        target_gpu_time: TARGET,
+        window: 4,
+        degrade_ratio: 1.2,
+        recover_ratio: 0.8,
+        cooldown_frames: 8,
+        is_render_scale_enabled: true,
+        ..Default::default()
+    }
+}
+
+fn run(governor: &mut QualityGovernor, gpu_time: Duration, frames: u32) {
+    for _ in 0..frames {
+        governor.update(Some(gpu_time), None, QualityOverride::default());
+    }
+}
+
+#[test]
+fn degrades_lod_bias_first_then_render_scale() {
+    let mut governor = QualityGovernor::new(config());
+    let slow = TARGET * 2;
+    // Not a full window yet
+    run(&mut governor, slow, 3);
+    assert_eq!(governor.state().lod_bias, 0.0);
+    run(&mut governor, slow, 1);
+    assert_eq!(governor.state().lod_bias, 0.5);
+    assert_eq!(governor.state().pressure, Pressure::GpuTime);
+    // One step per cooldown
+    run(&mut governor, slow, 7);
+    assert_eq!(governor.state().lod_bias, 0.5);
+    run(&mut governor, slow, 1);
+    assert_eq!(governor.state().lod_bias, 1.0);
+    run(&mut governor, slow, 8 * 2);
+    assert_eq!(governor.state().lod_bias, 2.0);
+    assert_eq!(governor.state().render_scale, 1.0);
+    run(&mut governor, slow, 8);
+    assert_eq!(governor.state().render_scale, 0.875);
+    // Stays within the bounds no matter how long it's slow
+    run(&mut governor, slow, 8 * 20);
+    assert_eq!(governor.state().lod_bias, 2.0);
+    assert_eq!(governor.state().render_scale, 0.5);
+}
+
+#[test]
+fn holds_between_the_thresholds() {
+    let mut governor = QualityGovernor::new(config());
+    run(&mut governor, TARGET * 2, 4);
+    assert_eq!(governor.state().lod_bias, 0.5);
+    // Alternating around the target without leaving the band
+    for frame in 0..1000 {
+        let gpu_time = if frame % 2 == 0 {
+            TARGET * 115 / 100
+        } else {
+            TARGET * 85 / 100
+        };
+        governor.update(Some(gpu_time), None, QualityOverride::default());
+        assert_eq!(governor.state().lod_bias, 0.5);
+    }
+    assert_eq!(governor.state().pressure, Pressure::None);
+}
+
+#[test]
+fn recovers_in_reverse() {
+    let mut governor = QualityGovernor::new(config());
+    run(&mut governor, TARGET * 2, 8 * 6);
+    assert_eq!(governor.state().lod_bias, 2.0);
+    assert_eq!(governor.state().render_scale, 0.75);
+    let fast = TARGET / 2;
+    run(&mut governor, fast, 8);
+    assert_eq!(governor.state().pressure, Pressure::Relaxed);
+    assert_eq!(governor.state().render_scale, 0.875);
+    assert_eq!(governor.state().lod_bias, 2.0);
+    run(&mut governor, fast, 8 * 10);
+    assert_eq!(governor.state().render_scale, 1.0);
+    assert_eq!(governor.state().lod_bias, 0.0);
+}
+
+#[test]
+fn memory_pressure_degrades_without_timings() {
+    let mut governor = QualityGovernor::new(config());
+    governor.update(None, Some(0.5), QualityOverride::default());
+    assert_eq!(governor.state().lod_bias, 0.0);
+    governor.update(None, Some(0.01), QualityOverride::default());
+    assert_eq!(governor.state().pressure, Pressure::Memory);
+    assert_eq!(governor.state().lod_bias, 0.5);
+}
+
+#[test]
+fn overrides_last_one_frame() {
+    let mut governor = QualityGovernor::new(config());
+    let overrides = QualityOverride {
+        lod_bias: Some(1.5),
+        render_scale: None,
+    };
+    governor.update(Some(TARGET), None, overrides);
+    assert_eq!(governor.state().lod_bias, 1.5);
+    assert!(governor.state().is_overridden);
+    governor.update(Some(TARGET), None, QualityOverride::default());
+    assert_eq!(governor.state().lod_bias, 0.0);
+    assert!(!governor.state().is_overridden);
+}
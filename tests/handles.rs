@@ -0,0 +1,118 @@
+/*
+ * Generation checked mesh handles. The packing and the generation bookkeeping get
+ * checked on their own, freeing and regenerating meshes with tasks queued once through a
+ * renderer on a headless surface with validation on.
+ */
+
+mod common;
+
+use rend_vk::config::TaskRejected;
+use rend_vk::format::Format;
+use rend_vk::handle::{Generations, MeshHandle, StaleHandle};
+use rend_vk::offscreen::OffscreenPassDesc;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+const SIZE: u32 = 64;
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("tests/offscreen.json", SIZE, SIZE)
+}
+
+// Fullscreen stages don't read the vertices, any mesh draws
+fn fill(mesh: MeshHandle) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 0.5,
+        ..RenderTask::new(mesh, TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn raw_handles_keep_the_index_in_the_low_bits() {
+    let handle = MeshHandle {
+        index: 7,
+        generation: 3,
+    };
+    assert_eq!(handle.to_raw(), (3 << 32) | 7);
+    assert_eq!(handle.to_raw() as u32, 7);
+    assert_eq!(MeshHandle::from_raw(handle.to_raw()), handle);
+    let last = MeshHandle {
+        index: u32::MAX,
+        generation: u32::MAX,
+    };
+    assert_eq!(MeshHandle::from_raw(last.to_raw()), last);
+}
+
+#[test]
+fn freeing_a_slot_outdates_its_handles() {
+    let mut generations = Generations::default();
+    // Slots never freed are at generation 0, also past the ones tracked
+    assert!(generations.is_current(5, 0));
+    generations.bump(5);
+    assert!(!generations.is_current(5, 0));
+    assert!(generations.is_current(5, 1));
+    assert!(generations.is_current(4, 0));
+    assert!(generations.is_current(6, 0));
+    generations.bump(5);
+    assert_eq!(generations.of(5), 2);
+}
+
+#[test]
+fn stale_handles_are_rejected_after_the_slot_is_regenerated() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let freed = renderer.gen_mesh(1024, 0, 0, 0, 3);
+    renderer.free_mesh(freed).unwrap();
+    let regenerated = renderer.gen_mesh(1024, 0, 0, 0, 3);
+    assert_eq!(regenerated.index, freed.index);
+    assert_ne!(regenerated.generation, freed.generation);
+
+    assert!(renderer.fetch_mesh(freed).is_none());
+    assert!(renderer.fetch_mesh(regenerated).is_some());
+    assert_eq!(renderer.free_mesh(freed), Err(StaleHandle));
+    assert_eq!(
+        renderer.try_add_task_to_queue(fill(freed)),
+        Err(TaskRejected::StaleMesh { mesh: freed })
+    );
+    // The regenerated mesh is untouched by the stale free
+    renderer.try_add_task_to_queue(fill(regenerated)).unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    renderer.free_mesh(regenerated).unwrap();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn freeing_a_mesh_drops_its_queued_tasks() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let kept = renderer.gen_mesh(1024, 0, 0, 0, 3);
+    let freed = renderer.gen_mesh(1024, 0, 0, 0, 3);
+    renderer.try_add_task_to_queue(fill(kept)).unwrap();
+    renderer.try_add_task_to_queue(fill(freed)).unwrap();
+    renderer.try_add_task_to_queue(fill(freed)).unwrap();
+    let desc = OffscreenPassDesc {
+        stage: "preview".to_string(),
+        width: SIZE,
+        height: SIZE,
+        format: Format::R8G8B8A8_UNORM,
+    };
+    renderer
+        .render_to_texture(desc, vec![fill(freed), fill(kept)])
+        .unwrap();
+
+    renderer.free_mesh(freed).unwrap();
+    let queued: Vec<_> = renderer
+        .queued_tasks(TaskKind::Fullscreen)
+        .map(|e| e.mesh)
+        .collect();
+    assert_eq!(queued, [kept]);
+    // Same slot as the freed one, the tasks queued before mustn't draw it
+    let regenerated = renderer.gen_mesh(1024, 0, 0, 0, 3);
+    assert_eq!(regenerated.index, freed.index);
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    renderer.free_mesh(kept).unwrap();
+    renderer.free_mesh(regenerated).unwrap();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
@@ -0,0 +1,216 @@
+/*
+ * Hibernates and resumes a renderer on a headless surface with validation on, coming
+ * back at another extent every cycle. Validation errors logged by the object tracker on
+ * destroy count as leaks. Pipeline targets get released and made again at the extent
+ * resume comes back at, tests/resize_contents.json has one of each preserve mode.
+ */
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+use rend_vk::events::{RenderEvent, RenderEventSink, RingBufferSink};
+use rend_vk::format::Format;
+use rend_vk::render_task::TaskKind;
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::texture::{MipMap, Residency};
+
+#[derive(Clone)]
+struct SharedSink(Arc<Mutex<RingBufferSink>>);
+
+impl RenderEventSink for SharedSink {
+    fn emit(&mut self, event: RenderEvent) {
+        self.0.lock().unwrap().emit(event);
+    }
+}
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+#[test]
+fn hibernate_and_resume_leak_nothing() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let events = SharedSink(Arc::new(Mutex::new(RingBufferSink::new(256))));
+    renderer.set_event_sink(Box::new(events.clone()));
+    let restored = Arc::new(AtomicU32::new(0));
+    let restored_in = restored.clone();
+    renderer.set_texture_restorer(Some(Box::new(move |_, data: &mut [u8]| {
+        data.fill(0xff);
+        restored_in.fetch_add(1, Ordering::Relaxed);
+    })));
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 64,
+        offset: 0,
+    }];
+    let texture =
+        renderer.gen_texture("texture".to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 64);
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    renderer.render();
+    renderer.render();
+    assert_eq!(
+        renderer.texture_residency(texture).unwrap(),
+        Residency::Resident
+    );
+
+    for cycle in 0..3 {
+        renderer.hibernate(Some(0));
+        assert!(renderer.is_hibernated());
+        assert_eq!(
+            renderer.texture_residency(texture).unwrap(),
+            Residency::Evicted
+        );
+        assert_eq!(renderer.render(), FrameOutcome::Hibernated);
+        // Ignored until resumed
+        renderer.resize(32, 32);
+
+        // The swapchain and the pipeline come back at the new size, as across resizes
+        let (width, height) = (96 + 16 * cycle, 48);
+        renderer.resume(width, height);
+        assert!(!renderer.is_hibernated());
+        assert_eq!(restored.load(Ordering::Relaxed), cycle + 1);
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        renderer.render();
+        assert_eq!(
+            renderer.texture_residency(texture).unwrap(),
+            Residency::Resident
+        );
+    }
+
+    let events = events.0.lock().unwrap().drain();
+    let count = |f: fn(&RenderEvent) -> bool| events.iter().filter(|e| f(e)).count();
+    assert_eq!(
+        count(|e| matches!(
+            e,
+            RenderEvent::Hibernated {
+                evicted_textures: 1,
+                ..
+            }
+        )),
+        3
+    );
+    assert_eq!(
+        count(|e| matches!(
+            e,
+            RenderEvent::Resumed {
+                width: 96..=128,
+                height: 48,
+                restored_textures: 1,
+            }
+        )),
+        3
+    );
+
+    renderer.free_texture(texture).unwrap();
+    renderer.render();
+    renderer.render();
+    common::reset_validation_errors();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0, "leaked objects");
+}
+
+#[test]
+fn destroy_while_hibernated() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    renderer.render();
+    renderer.hibernate(None);
+    renderer.hibernate(None);
+    assert_eq!(renderer.render(), FrameOutcome::Hibernated);
+    common::reset_validation_errors();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0, "leaked objects");
+}
+
+const WRITTEN: u32 = 0x4433_2211;
+const TARGETS: [&str; 3] = ["accumulated", "centered", "discarded"];
+
+// Renders a frame, drawing only with is_drawn, and reads the targets back
+fn render_targets(renderer: &mut Renderer, is_drawn: bool) -> Vec<Vec<u8>> {
+    if is_drawn {
+        renderer.add_task_to_queue(common::task(TaskKind::Fullscreen));
+    }
+    let mut readbacks = TARGETS.map(|e| renderer.read_attachment(e).unwrap());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let timeout = Duration::from_secs(5);
+    let bytes = readbacks
+        .iter_mut()
+        .map(|e| e.resolve_wait(renderer, timeout).unwrap())
+        .collect();
+    assert_eq!(common::validation_errors(), 0);
+    bytes
+}
+
+fn is_every_pixel(bytes: &[u8], value: u32) -> bool {
+    bytes.chunks_exact(4).all(|e| e == value.to_le_bytes())
+}
+
+#[test]
+fn targets_are_released_and_made_again_at_the_resumed_extent() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/resize_contents.json", 16, 16);
+    renderer.set_event_sink(Box::new(RingBufferSink::new(64)));
+    for stage in ["accumulate", "center", "discard"] {
+        renderer
+            .set_debug_flags(Some(stage), WRITTEN as u64)
+            .unwrap();
+    }
+
+    for (width, height) in [(24, 20), (24, 20)] {
+        // Written, so the released one loses contents it had
+        render_targets(&mut renderer, true);
+        renderer.drain_events();
+        renderer.hibernate(None);
+        let released = renderer.drain_events().into_iter().find_map(|e| match e {
+            RenderEvent::Hibernated {
+                released_image_memory,
+                ..
+            } => Some(released_image_memory),
+            _ => None,
+        });
+        // Only the one without preserveOnRecreate
+        assert!(released.unwrap() >= 16 * 16 * 4);
+        let kept: Vec<_> = renderer
+            .memory_report()
+            .attachments
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(kept, ["accumulated", "centered"]);
+
+        // Loaded again even at the extent it was hibernated at
+        renderer.resume(width, height);
+        let reset: Vec<_> = renderer
+            .drain_events()
+            .into_iter()
+            .filter(|e| matches!(e, RenderEvent::TargetReset { .. }))
+            .collect();
+        assert!(matches!(
+            &reset[..],
+            [RenderEvent::TargetReset { target, width: 24, height: 20 }] if target == "discarded"
+        ));
+        let targets = render_targets(&mut renderer, false);
+        for bytes in &targets {
+            assert_eq!(bytes.len() as u32, width * height * 4);
+        }
+        assert!(is_every_pixel(&targets[0], WRITTEN));
+        // A 16x16 square the first time around, the whole target after
+        let written = targets[1]
+            .chunks_exact(4)
+            .filter(|e| *e == WRITTEN.to_le_bytes());
+        assert!(written.count() >= 16 * 16);
+    }
+
+    render_targets(&mut renderer, true)
+        .iter()
+        .for_each(|e| assert!(is_every_pixel(e, WRITTEN)));
+    common::finish(renderer);
+}
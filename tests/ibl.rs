@@ -0,0 +1,258 @@
+/*
+ * IBL bake of a cube texture on a headless surface with validation on. The source is a
+ * constant environment, so the prefiltered and irradiance cubes have to come out as the
+ * same constant whatever the roughness, and the BRDF table has to stay between 0 and 1.
+ * The same bake runs on a renderer without a surface through flush_gpu_work alone. The
+ * compiled SPIR-V gets checked for its entry points without a device.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::format::Format;
+use rend_vk::handle::TextureHandle;
+use rend_vk::ibl::{self, IblBakeDesc};
+use rend_vk::renderer::{self, Renderer};
+use rend_vk::texture::{MipMap, TextureRegion};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const FACE_SIZE: u32 = 8;
+// 0.5 as a half float
+const HALF_GRAY: u16 = 0x3800;
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+fn desc() -> IblBakeDesc {
+    IblBakeDesc {
+        specular_size: 16,
+        specular_mips: 3,
+        irradiance_size: 8,
+        brdf_lut_size: 16,
+        sample_count: 64,
+    }
+}
+
+// Gray cube, queued for uploading
+fn queue_gray_cube(renderer: &mut Renderer) -> TextureHandle {
+    let size = 8 * FACE_SIZE * FACE_SIZE * 6;
+    let mip_maps = [MipMap {
+        index: 0,
+        width: FACE_SIZE,
+        height: FACE_SIZE,
+        size,
+        offset: 0,
+    }];
+    let format = Format::R16G16B16A16_SFLOAT;
+    let cube = renderer.gen_texture_cube("sky".to_string(), format, &mip_maps, size);
+    let texture = renderer.fetch_texture(cube).unwrap();
+    assert!(texture.is_cube);
+    assert_eq!(texture.layers, 6);
+    let staging = texture.staging.as_ref().unwrap();
+    let texels = staging.addr as *mut u16;
+    for i in 0..(size / 2) as usize {
+        unsafe { texels.add(i).write(HALF_GRAY) };
+    }
+    renderer.queue_texture_for_uploading(cube).unwrap();
+    cube
+}
+
+// Gray cube, uploaded
+fn gray_cube(renderer: &mut Renderer) -> TextureHandle {
+    let cube = queue_gray_cube(renderer);
+    renderer.render();
+    renderer.render();
+    assert!(renderer.fetch_texture(cube).unwrap().is_uploaded());
+    cube
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32 / 1024.0;
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-14),
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Red and green of every texel of the first mip and face
+fn read_rg(renderer: &mut Renderer, texture: TextureHandle) -> Vec<(f32, f32)> {
+    let mut request = renderer.read_texture(texture).unwrap();
+    renderer.render();
+    let bytes = request.resolve_wait(renderer, TIMEOUT).unwrap();
+    bytes
+        .chunks_exact(8)
+        .map(|e| {
+            let red = u16::from_le_bytes([e[0], e[1]]);
+            let green = u16::from_le_bytes([e[2], e[3]]);
+            (half_to_f32(red), half_to_f32(green))
+        })
+        .collect()
+}
+
+#[test]
+fn constant_environments_bake_to_constant_maps() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let cube = gray_cube(&mut renderer);
+    let maps = renderer.bake_ibl(cube.index, desc());
+
+    let specular = renderer.fetch_texture(maps.specular).unwrap();
+    assert!(specular.is_cube);
+    assert_eq!((specular.width(), specular.mip_map_count()), (16, 3));
+    let irradiance = renderer.fetch_texture(maps.irradiance).unwrap();
+    assert!(irradiance.is_cube);
+    assert_eq!((irradiance.width(), irradiance.mip_map_count()), (8, 1));
+    let brdf_lut = renderer.fetch_texture(maps.brdf_lut).unwrap();
+    assert!(!brdf_lut.is_cube);
+    assert_eq!((brdf_lut.width(), brdf_lut.layers), (16, 1));
+
+    // Frames after the bake wait for it, it's done once they are
+    renderer.render();
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert!(maps.is_ready(&renderer));
+
+    for texture in [maps.specular, maps.irradiance] {
+        for (red, _) in read_rg(&mut renderer, texture) {
+            assert!((red - 0.5).abs() < 0.01, "{:?} texel of {}", texture, red);
+        }
+    }
+    let table = read_rg(&mut renderer, maps.brdf_lut);
+    assert_eq!(table.len(), 16 * 16);
+    for (scale, bias) in &table {
+        assert!(*scale >= 0.0 && *bias >= 0.0 && scale + bias <= 1.01);
+    }
+    // Smooth surfaces seen head on reflect F0, the scale is close to 1 there
+    let (scale, bias) = table[15];
+    assert!(scale > 0.9 && bias < 0.05, "{} {}", scale, bias);
+
+    // Baking again makes new maps, the old ones stay until freed
+    let again = renderer.bake_ibl(cube.index, desc());
+    assert_ne!(again.specular.index, maps.specular.index);
+    for texture in [maps.specular, maps.irradiance, maps.brdf_lut] {
+        renderer.free_texture(texture).unwrap();
+    }
+    renderer.render();
+    renderer.render();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn bakes_without_a_surface() {
+    let _serial = common::serial();
+    common::install_logger();
+    // Not even the surface instance extensions
+    let config = RendererConfig::default();
+    let core = renderer::make_render_core(&config, true, true, &[]);
+    let mut renderer = Renderer::with_core_headless(core, config, "tests/scissor.json", 1, 1);
+    common::reset_validation_errors();
+
+    let cube = queue_gray_cube(&mut renderer);
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert!(renderer.fetch_texture(cube).unwrap().is_uploaded());
+    let maps = renderer.bake_ibl(cube.index, desc());
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    assert!(maps.is_ready(&renderer));
+    let mut request = renderer.read_texture(maps.irradiance).unwrap();
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    let bytes = request.try_resolve(&renderer).unwrap();
+    for texel in bytes.chunks_exact(8) {
+        let red = half_to_f32(u16::from_le_bytes([texel[0], texel[1]]));
+        assert!((red - 0.5).abs() < 0.01, "irradiance texel of {}", red);
+    }
+
+    for texture in [cube, maps.specular, maps.irradiance, maps.brdf_lut] {
+        renderer.free_texture(texture).unwrap();
+    }
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+#[should_panic(expected = "isn't a cube texture!")]
+fn flat_textures_are_refused() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 64,
+        offset: 0,
+    }];
+    let flat = renderer.gen_texture("flat".to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 0);
+    renderer.bake_ibl(flat.index, desc());
+}
+
+#[test]
+#[should_panic(expected = "is a cube, reimport it instead!")]
+fn cube_region_updates_are_refused() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let cube = gray_cube(&mut renderer);
+    let region = TextureRegion {
+        mip: 0,
+        offset: [0, 0],
+        extent: [1, 1],
+    };
+    let _ = renderer.update_texture_region(cube, region, &[0; 8]);
+}
+
+// Words of the module, panics on a bad header
+fn spirv_words(spirv: &[u8]) -> Vec<u32> {
+    assert_eq!(spirv.len() % 4, 0);
+    let words: Vec<u32> = spirv
+        .chunks_exact(4)
+        .map(|e| u32::from_le_bytes([e[0], e[1], e[2], e[3]]))
+        .collect();
+    assert_eq!(words[0], 0x0723_0203, "bad magic number");
+    assert!(
+        words[1] <= 0x0001_0300,
+        "newer SPIR-V than Vulkan 1.2 takes"
+    );
+    words
+}
+
+#[test]
+fn compiled_shaders_are_8x8_compute_kernels() {
+    for (_, spirv) in ibl::compile_shaders() {
+        let words = spirv_words(&spirv);
+        let (mut entry, mut local_size) = (None, None);
+        let mut i = 5;
+        while i < words.len() {
+            let count = (words[i] >> 16) as usize;
+            assert!(count > 0 && i + count <= words.len(), "bad instruction");
+            let operands = &words[i + 1..i + count];
+            match words[i] & 0xffff {
+                // OpEntryPoint
+                15 => {
+                    let name: Vec<u8> = operands[2..]
+                        .iter()
+                        .flat_map(|e| e.to_le_bytes())
+                        .take_while(|e| *e != 0)
+                        .collect();
+                    entry = Some((operands[0], operands[1], name));
+                }
+                // OpExecutionMode LocalSize
+                16 if operands[1] == 17 => {
+                    local_size = Some([operands[2], operands[3], operands[4]])
+                }
+                _ => {}
+            }
+            i += count;
+        }
+        let (model, function, name) = entry.expect("no entry point");
+        // GLCompute
+        assert_eq!(model, 5);
+        assert_eq!(name, b"main");
+        assert!(function > 0);
+        assert_eq!(local_size, Some([8, 8, 1]));
+    }
+}
@@ -0,0 +1,53 @@
+/*
+ * Frames without anything queued under RendererConfig::idle_frames, on a headless
+ * surface with validation on. Green reads back the same in RGBA and BGRA swapchains.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::{IdleFrames, RendererConfig};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+use rend_vk::stats::FramePath;
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn fill() -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 0.5,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn idle_frames_clear_the_swapchain_image_without_the_stages() {
+    let _serial = common::serial();
+    let config = RendererConfig {
+        idle_frames: IdleFrames::Clear([0.0, 1.0, 0.0, 1.0]),
+        ..Default::default()
+    };
+    let mut renderer = common::make_renderer_with(config, "tests/scissor.json", SIZE, SIZE);
+    renderer.add_task_to_queue(fill());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.frame_stats().path, FramePath::Stages);
+
+    let mut request = renderer.read_presented().unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.frame_stats().path, FramePath::Idle);
+    let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    assert_eq!(bytes.len() as u32, SIZE * SIZE * 4);
+    assert!(bytes.chunks_exact(4).all(|e| e == [0, 255, 0, 255]));
+    common::finish(renderer);
+}
+
+#[test]
+fn idle_frames_render_by_default() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", SIZE, SIZE);
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.frame_stats().path, FramePath::Stages);
+    common::finish(renderer);
+}
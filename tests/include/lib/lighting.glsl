@@ -0,0 +1,4 @@
+#ifndef LIGHTING_GLSL
+#define LIGHTING_GLSL
+float light;
+#endif
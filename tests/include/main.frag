@@ -0,0 +1,5 @@
+#version 460
+#include "nested/material.glsl"
+#include "common.glsl" // again, pasted once
+#include <lighting.glsl>
+void main() {}
@@ -0,0 +1,2 @@
+#version 460
+#include common.glsl
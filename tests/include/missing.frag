@@ -0,0 +1 @@
+#include "nested/broken.glsl"
@@ -0,0 +1 @@
+#include "nowhere.glsl"
@@ -0,0 +1,6 @@
+// Classic guard
+#ifndef GUARDED_GLSL
+#define GUARDED_GLSL
+#include "guarded.glsl"
+float guarded;
+#endif
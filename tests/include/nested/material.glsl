@@ -0,0 +1,4 @@
+#include "../common.glsl"
+#include "guarded.glsl"
+#include "guarded.glsl"
+float roughness;
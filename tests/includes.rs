@@ -0,0 +1,138 @@
+/*
+ * Include pasting over the shaders in tests/include, and the stages of the default
+ * pipeline each shader file ends up in. Nothing gets compiled.
+ */
+use std::path::{Path, PathBuf};
+
+use rend_vk::pipeline::file::Pipeline;
+use rend_vk::pipeline::include::{self, IncludeError};
+
+const DIR: &str = "tests/include";
+
+fn path(name: &str) -> PathBuf {
+    Path::new(DIR).join(name)
+}
+
+fn search_paths() -> Vec<PathBuf> {
+    vec![path("lib")]
+}
+
+#[test]
+fn pastes_nested_includes_in_place() {
+    let flat = include::flatten(&path("main.frag"), &search_paths()).unwrap();
+    assert_eq!(
+        flat.files,
+        [
+            path("main.frag"),
+            path("nested/material.glsl"),
+            path("common.glsl"),
+            path("nested/guarded.glsl"),
+            path("lib/lighting.glsl"),
+        ]
+    );
+    assert!(!flat.source.contains("#include"));
+    assert!(!flat.source.contains("#pragma once"));
+    let first = |e: &str| flat.source.find(e).unwrap();
+    assert!(first("#version 460") < first("const float PI"));
+    assert!(first("const float PI") < first("float roughness"));
+    assert!(first("float light") < first("void main"));
+}
+
+#[test]
+fn guards_keep_files_from_repeating() {
+    let flat = include::flatten(&path("main.frag"), &search_paths()).unwrap();
+    // Included twice, pasted once
+    assert_eq!(flat.source.matches("const float PI").count(), 1);
+    // Pasted again, the compiler skips the second one through its guard
+    assert_eq!(flat.source.matches("float guarded;").count(), 2);
+}
+
+#[test]
+fn angle_brackets_only_look_in_the_search_paths() {
+    let err = include::flatten(&path("main.frag"), &[]).unwrap_err();
+    assert_eq!(
+        err,
+        IncludeError::Missing {
+            include: "lighting.glsl".to_string(),
+            chain: vec![path("main.frag")],
+        }
+    );
+}
+
+#[test]
+fn missing_includes_report_the_chain() {
+    let err = include::flatten(&path("missing.frag"), &search_paths()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "couldn't find include \"nowhere.glsl\" of \
+         tests/include/missing.frag -> tests/include/nested/broken.glsl"
+    );
+    assert!(matches!(
+        include::flatten(&path("nowhere.frag"), &[]),
+        Err(IncludeError::Unreadable { .. })
+    ));
+}
+
+#[test]
+fn cycles_report_the_chain() {
+    let err = include::flatten(&path("cycle_a.glsl"), &[]).unwrap_err();
+    assert_eq!(
+        err,
+        IncludeError::Cycle {
+            chain: vec![
+                path("cycle_a.glsl"),
+                path("cycle_b.glsl"),
+                path("cycle_a.glsl")
+            ],
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "include cycle tests/include/cycle_a.glsl -> tests/include/cycle_b.glsl \
+         -> tests/include/cycle_a.glsl"
+    );
+}
+
+#[test]
+fn malformed_includes_report_the_line() {
+    let err = include::flatten(&path("malformed.frag"), &[]).unwrap_err();
+    assert_eq!(
+        err,
+        IncludeError::Malformed {
+            path: path("malformed.frag"),
+            line: 2,
+        }
+    );
+}
+
+#[test]
+fn paths_get_normalized() {
+    assert_eq!(
+        include::normalize(Path::new("shader/builtin/../shared.glsl.frag")),
+        Path::new("shader/shared.glsl.frag")
+    );
+    assert_eq!(
+        include::normalize(Path::new("./a/../../b")),
+        Path::new("../b")
+    );
+}
+
+#[test]
+fn shared_files_map_to_every_stage_including_them() {
+    let pip = Pipeline::read(None);
+    let dependencies = pip.shader_dependencies().unwrap();
+    let stages_of = |e: &str| -> Vec<_> {
+        dependencies[Path::new(e)]
+            .iter()
+            .map(String::as_str)
+            .collect()
+    };
+    assert_eq!(stages_of("shader/gbuffer.frag"), ["gbuffer"]);
+    assert_eq!(stages_of("shader/fullscreen.vert"), ["copy", "dirlight"]);
+    // Only the disabled point light pass uses it
+    assert!(!dependencies.contains_key(Path::new("shader/point_light.frag")));
+    assert_eq!(
+        stages_of("shader/shared.glsl.frag"),
+        ["copy", "dirlight", "gbuffer", "translucent"]
+    );
+}
@@ -0,0 +1,94 @@
+/*
+ * Attachments and named buffers of tests/initial_state.json start out as their initial
+ * states declare. The copy stage samples history before the store stage writes it, so on
+ * frame zero it copies the cleared contents and the frame after whatever store wrote.
+ * tests/uninitialized_read.json is the same without the initial states.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::{RendererConfig, UninitializedReads};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+const SIZE: u32 = 32;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer(path: &str, uninitialized_reads: UninitializedReads) -> Renderer {
+    let config = RendererConfig {
+        uninitialized_reads,
+        ..Default::default()
+    };
+    common::make_renderer_with(config, path, SIZE, SIZE)
+}
+
+fn fill(gray: u8) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: gray as f32 / 255.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+// Renders a frame filling history with the gray, returns what copy copied of it before
+fn copied_in_frame(renderer: &mut Renderer, gray: u8) -> Vec<u8> {
+    renderer.add_task_to_queue(fill(gray));
+    let mut request = renderer.read_attachment("copied").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    request.resolve_wait(renderer, TIMEOUT).unwrap()
+}
+
+fn is_near(texel: &[u8], expected: [u8; 4]) -> bool {
+    texel.iter().zip(expected).all(|(a, b)| a.abs_diff(b) <= 1)
+}
+
+#[test]
+fn history_starts_out_cleared_on_frame_zero() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer("tests/initial_state.json", UninitializedReads::Error);
+    let counters = renderer
+        .create_allocation_scope("counters", 1024)
+        .alloc(64)
+        .unwrap();
+    renderer.register_named_buffer("counters", counters);
+    let words = unsafe { std::slice::from_raw_parts(counters.addr as *const u32, 16) };
+    assert!(words.iter().all(|e| *e == 7));
+
+    let first = copied_in_frame(&mut renderer, 120);
+    assert!(first.chunks(4).all(|e| is_near(e, [64, 128, 191, 255])));
+    let second = copied_in_frame(&mut renderer, 80);
+    assert!(second.chunks(4).all(|e| e == [120, 120, 120, 255]));
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn uninitialized_reads_start_out_zeroed_when_asked() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer("tests/uninitialized_read.json", UninitializedReads::Zero);
+    let counters = renderer
+        .create_allocation_scope("counters", 1024)
+        .alloc(64)
+        .unwrap();
+    unsafe { std::ptr::write_bytes(counters.addr as *mut u8, 0xff, 64) };
+    renderer.register_named_buffer("counters", counters);
+    let words = unsafe { std::slice::from_raw_parts(counters.addr as *const u32, 16) };
+    assert!(words.iter().all(|e| *e == 0));
+
+    // Copy writes alpha 1 whatever it samples
+    let first = copied_in_frame(&mut renderer, 120);
+    assert!(first.chunks(4).all(|e| e == [0, 0, 0, 255]));
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+#[should_panic(expected = "pass copy reads attachment history before anything wrote it")]
+fn uninitialized_reads_fail_creation_by_default() {
+    let _serial = common::serial();
+    make_renderer(
+        "tests/uninitialized_read.json",
+        UninitializedReads::default(),
+    );
+}
@@ -0,0 +1,138 @@
+/*
+ * Bindings of the attachment inputs of the shipped passes against made up shader
+ * reflections. No GPU involved.
+ */
+use rend_vk::pipeline::descriptor::SET_ATTACHMENTS;
+use rend_vk::pipeline::file::{Pass, Pipeline};
+use rend_vk::pipeline::plan;
+use rend_vk::reflection::{BindingKind, BindingMismatch, DescriptorBinding, ShaderReflection};
+
+fn sampler(name: &str, binding: u32) -> DescriptorBinding {
+    DescriptorBinding {
+        name: name.to_string(),
+        set: SET_ATTACHMENTS,
+        binding,
+        count: 1,
+        kind: BindingKind::CombinedImageSampler,
+        is_arrayed: false,
+    }
+}
+
+fn declaring(bindings: Vec<DescriptorBinding>) -> ShaderReflection {
+    ShaderReflection {
+        bindings,
+        ..Default::default()
+    }
+}
+
+fn pass(name: &str) -> Pass {
+    let pip = Pipeline::read(None);
+    pip.passes.into_iter().find(|e| e.name == name).unwrap()
+}
+
+/*
+ * Dirlight names the samplers of albedo, normal, misc and depth in that order.
+ */
+fn dirlight_bindings(shaders: &[(&str, &ShaderReflection)]) -> Result<Vec<u32>, BindingMismatch> {
+    plan::input_bindings(&pass("dirlight"), "dirlight", shaders, &[1; 4])
+}
+
+#[test]
+fn named_inputs_go_where_the_shaders_declare_them() {
+    let frag = declaring(vec![
+        sampler("gbDepth", 0),
+        sampler("gbAlbedo", 1),
+        sampler("gbNormal", 2),
+        sampler("gbMisc", 3),
+    ]);
+    assert_eq!(
+        dirlight_bindings(&[("dirlight.frag", &frag)]),
+        Ok(vec![1, 2, 3, 0])
+    );
+
+    // Same sampler in both shaders is fine as long as they agree
+    let vert = declaring(vec![sampler("gbDepth", 0)]);
+    let shaders = [("dirlight.vert", &vert), ("dirlight.frag", &frag)];
+    assert_eq!(dirlight_bindings(&shaders), Ok(vec![1, 2, 3, 0]));
+}
+
+#[test]
+fn unnamed_inputs_fall_back_to_their_position() {
+    // Copy names none of its lightAcc, normal, albedo and misc inputs
+    let frag = declaring(vec![sampler("albedo", 0), sampler("source", 1)]);
+    let bindings = plan::input_bindings(&pass("copy"), "copy", &[("copy.frag", &frag)], &[1; 4]);
+    // Matched by name, then normal by its position, the unsampled ones anywhere free
+    assert_eq!(bindings, Ok(vec![2, 1, 0, 3]));
+}
+
+#[test]
+fn samplers_without_an_input_are_a_mismatch() {
+    let frag = declaring(vec![
+        sampler("gbAlbedo", 0),
+        sampler("gbNormal", 1),
+        sampler("gbMisc", 2),
+        sampler("gbDepth", 3),
+        sampler("gbShadow", 4),
+    ]);
+    let mismatch = dirlight_bindings(&[("dirlight.frag", &frag)]).unwrap_err();
+    assert_eq!(
+        mismatch,
+        BindingMismatch {
+            stage: "dirlight".to_string(),
+            shader: "dirlight.frag".to_string(),
+            set: SET_ATTACHMENTS,
+            binding: 4,
+            name: "gbShadow".to_string(),
+            problem: "no input goes there".to_string(),
+        }
+    );
+    assert_eq!(
+        mismatch.to_string(),
+        "shader dirlight.frag of stage dirlight, set 2 binding 4 (gbShadow): no input goes there"
+    );
+}
+
+#[test]
+fn samplers_have_to_match_their_input() {
+    let arrayed = DescriptorBinding {
+        is_arrayed: true,
+        ..sampler("gbDepth", 3)
+    };
+    let frag = declaring(vec![
+        sampler("gbAlbedo", 0),
+        sampler("gbNormal", 1),
+        sampler("gbMisc", 2),
+        arrayed.clone(),
+    ]);
+    let mismatch = dirlight_bindings(&[("dirlight.frag", &frag)]).unwrap_err();
+    assert_eq!(mismatch.binding, 3);
+    assert_eq!(
+        mismatch.problem,
+        "input depth has 1 layers, sampled as an array"
+    );
+
+    // Shaders of a program can't disagree on a binding either
+    let vert = declaring(vec![arrayed]);
+    let frag = declaring(vec![
+        sampler("gbAlbedo", 0),
+        sampler("gbNormal", 1),
+        sampler("gbMisc", 2),
+        sampler("gbDepth", 3),
+    ]);
+    let shaders = [("dirlight.vert", &vert), ("dirlight.frag", &frag)];
+    let mismatch = dirlight_bindings(&shaders).unwrap_err();
+    assert_eq!(mismatch.shader, "dirlight.frag");
+    assert_eq!(
+        mismatch.problem,
+        "declared differently as gbDepth in dirlight.vert"
+    );
+}
+
+#[test]
+#[should_panic(
+    expected = "input albedo of pass dirlight is sampled as gbAlbedo, program dirlight declares no such attachment sampler!"
+)]
+fn named_samplers_have_to_exist() {
+    let frag = declaring(vec![sampler("albedo", 0)]);
+    let _ = dirlight_bindings(&[("dirlight.frag", &frag)]);
+}
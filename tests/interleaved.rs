@@ -0,0 +1,108 @@
+/*
+ * Draws the test triangle in both vertex layouts on a headless surface with validation
+ * on, with the default pipeline, whose stages only read separate attributes, and with the
+ * one of examples/vertex_fetch.json, which draws interleaved meshes as MeshAnimated tasks.
+ */
+
+mod common;
+
+use rend_vk::bounds::MeshBounds;
+use rend_vk::config::TaskRejected;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::vertex_layout::{
+    VertexAttribute, VertexAttributeKind, VertexLayout, VertexLayoutKind,
+};
+
+fn make_renderer(pipeline_path: &str) -> Renderer {
+    common::make_renderer(pipeline_path, 64, 64)
+}
+
+#[test]
+fn default_stages_reject_interleaved_meshes() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer("pipeline.json");
+    let interleaved = Renderer::TEST_TRIANGLE_INTERLEAVED;
+    assert_eq!(
+        renderer.try_add_task_to_queue(RenderTask::new(interleaved, TaskKind::MeshStatic)),
+        Err(TaskRejected::VertexLayout {
+            kind: TaskKind::MeshStatic,
+            mesh: interleaved,
+            layout: VertexLayoutKind::Interleaved,
+        })
+    );
+    renderer
+        .try_add_task_to_queue(common::task(TaskKind::MeshStatic))
+        .unwrap();
+    // Fullscreen stages don't read the vertices
+    renderer
+        .try_add_task_to_queue(RenderTask::new(interleaved, TaskKind::Fullscreen))
+        .unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(
+        renderer.mesh_bounds(interleaved),
+        renderer.mesh_bounds(Renderer::TEST_TRIANGLE)
+    );
+    common::finish(renderer);
+}
+
+#[test]
+fn both_layouts_draw() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer("examples/vertex_fetch.json");
+    let separate = Renderer::TEST_TRIANGLE;
+    let interleaved = Renderer::TEST_TRIANGLE_INTERLEAVED;
+    for (mesh, kind) in [
+        (separate, TaskKind::MeshAnimated),
+        (interleaved, TaskKind::MeshStatic),
+    ] {
+        assert!(matches!(
+            renderer.try_add_task_to_queue(RenderTask::new(mesh, kind)),
+            Err(TaskRejected::VertexLayout { .. })
+        ));
+    }
+
+    // Tex coord first and a gap before the position, read at the offsets of the layout
+    let layout = VertexLayout::new(
+        28,
+        &[
+            VertexAttribute::new(VertexAttributeKind::TexCoord, 0),
+            VertexAttribute::new(VertexAttributeKind::Position, 12),
+        ],
+    );
+    let positions = [[-1.0f32, -1.0, 0.5], [1.0, -1.0, 0.5], [0.0, 1.0, 0.5]];
+    let data: Vec<u8> = positions
+        .iter()
+        .flat_map(|e| [0.0, 0.0, 0.0].iter().chain(e).chain(&[0.0]))
+        .flat_map(|e| e.to_ne_bytes())
+        .collect();
+    let indices: Vec<u8> = [0u32, 1, 2].iter().flat_map(|e| e.to_ne_bytes()).collect();
+    let mesh = renderer.gen_mesh_interleaved(layout, &data, &indices, 3);
+    assert_eq!(
+        renderer.mesh_bounds(mesh),
+        MeshBounds::of_positions(&positions.map(glam::Vec3::from))
+    );
+
+    for _ in 0..3 {
+        for (mesh, kind) in [
+            (separate, TaskKind::MeshStatic),
+            (interleaved, TaskKind::MeshAnimated),
+            (mesh, TaskKind::MeshAnimated),
+            (separate, TaskKind::Fullscreen),
+        ] {
+            renderer
+                .try_add_task_to_queue(RenderTask::new(mesh, kind))
+                .unwrap();
+        }
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    }
+    renderer.free_mesh(mesh).unwrap();
+    renderer.render();
+    common::finish(renderer);
+}
+
+#[test]
+#[should_panic]
+fn layouts_without_a_position_panic() {
+    VertexLayout::new(8, &[VertexAttribute::new(VertexAttributeKind::TexCoord, 0)]);
+}
@@ -0,0 +1,169 @@
+/*
+ * Renders at a fixed internal resolution on a headless surface with validation on, and
+ * reads back what gets presented at a couple of window sizes. The ui stage of
+ * tests/scissor.json fills with white, so the presented image is white where the scaled
+ * image lands and the bar color around it. Sharpening a flat image leaves it flat, so the
+ * sharpening filter of builtin-passes presents the same.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::scaling::{self, UpscaleFilter};
+
+const INTERNAL: (u32, u32) = (32, 32);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn filters() -> Vec<UpscaleFilter> {
+    #[allow(unused_mut)]
+    let mut filters = vec![UpscaleFilter::Nearest, UpscaleFilter::Linear];
+    #[cfg(feature = "builtin-passes")]
+    filters.push(UpscaleFilter::Sharpen);
+    filters
+}
+
+fn make_renderer() -> Renderer {
+    let config = RendererConfig {
+        internal_resolution: Some(INTERNAL),
+        upscale_filter: UpscaleFilter::Nearest,
+        letterbox_color: [1.0, 0.0, 0.0, 1.0],
+        ..Default::default()
+    };
+    common::make_renderer_with(config, "tests/scissor.json", INTERNAL.0, INTERNAL.1)
+}
+
+fn white_fill() -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 1.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+fn extent(width: u32, height: u32) -> vk::Extent2D {
+    vk::Extent2D { width, height }
+}
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: extent(width, height),
+    }
+}
+
+#[test]
+fn letterbox_keeps_the_aspect_centered() {
+    let letterbox = scaling::letterbox;
+    // Same aspect fills the target
+    assert_eq!(
+        letterbox(extent(32, 32), extent(64, 64)),
+        rect(0, 0, 64, 64)
+    );
+    assert_eq!(
+        letterbox(extent(320, 180), extent(1280, 720)),
+        rect(0, 0, 1280, 720)
+    );
+    // Bars left and right, then top and bottom
+    assert_eq!(
+        letterbox(extent(32, 32), extent(64, 32)),
+        rect(16, 0, 32, 32)
+    );
+    assert_eq!(
+        letterbox(extent(32, 32), extent(40, 80)),
+        rect(0, 20, 40, 40)
+    );
+    assert_eq!(
+        letterbox(extent(16, 9), extent(100, 100)),
+        rect(0, 22, 100, 56)
+    );
+    // Downscaling too, sizes rounded to the nearest pixel
+    assert_eq!(
+        letterbox(extent(1920, 1080), extent(101, 101)),
+        rect(0, 22, 101, 57)
+    );
+    assert_eq!(letterbox(extent(3, 1), extent(1, 1)), rect(0, 0, 1, 1));
+}
+
+#[test]
+fn presented_images_get_letterboxed() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    // Red bars in the byte order of the swapchain
+    let bar = match renderer.swapchain_capabilities().format {
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => [0, 0, 255, 255],
+        _ => [255, 0, 0, 255],
+    };
+    for (width, height) in [(64, 32), (40, 80), (32, 32)] {
+        renderer.resize(width, height);
+        assert_eq!(renderer.render_extent(), extent(INTERNAL.0, INTERNAL.1));
+        let area = scaling::letterbox(renderer.render_extent(), extent(width, height));
+        for filter in filters() {
+            renderer.set_upscale_filter(filter);
+            renderer.add_task_to_queue(white_fill());
+            let mut request = renderer.read_presented().unwrap();
+            assert_eq!(renderer.render(), FrameOutcome::Submitted);
+            let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+            assert_eq!(bytes.len() as u32, width * height * 4);
+            for y in 0..height as i32 {
+                for x in 0..width as i32 {
+                    let i = ((y * width as i32 + x) * 4) as usize;
+                    let is_inside = x >= area.offset.x
+                        && y >= area.offset.y
+                        && x < area.offset.x + area.extent.width as i32
+                        && y < area.offset.y + area.extent.height as i32;
+                    let expected = if is_inside { [255; 4] } else { bar };
+                    assert_eq!(
+                        bytes[i..i + 4],
+                        expected,
+                        "pixel {}, {} at {}x{} with {:?}",
+                        x,
+                        y,
+                        width,
+                        height,
+                        filter
+                    );
+                }
+            }
+        }
+    }
+    common::finish(renderer);
+}
+
+#[cfg(feature = "builtin-passes")]
+#[test]
+fn scaling_shaders_compile_to_one_entry_point_each() {
+    let models: Vec<u32> = scaling::compile_shaders()
+        .iter()
+        .map(|(name, spirv)| {
+            let words: Vec<u32> = spirv
+                .chunks_exact(4)
+                .map(|e| u32::from_le_bytes([e[0], e[1], e[2], e[3]]))
+                .collect();
+            assert_eq!(words[0], 0x0723_0203, "{}: bad magic number", name);
+            let mut models = Vec::new();
+            let mut i = 5;
+            while i < words.len() {
+                let count = (words[i] >> 16) as usize;
+                assert!(
+                    count > 0 && i + count <= words.len(),
+                    "{}: bad instruction",
+                    name
+                );
+                // OpEntryPoint
+                if words[i] & 0xffff == 15 {
+                    models.push(words[i + 1]);
+                }
+                i += count;
+            }
+            assert_eq!(models.len(), 1, "{}", name);
+            models[0]
+        })
+        .collect();
+    // Vertex, Fragment
+    assert_eq!(models, [0, 4]);
+}
@@ -0,0 +1,148 @@
+/*
+ * Skeleton of the interop handshake with a second render core standing in for the
+ * external producer, on its own device. The producer imports the exported semaphore and
+ * signals from the host, where a real one would signal from its queue after writing its
+ * buffers. Devices without exportable timeline semaphores only check the error.
+ */
+
+mod common;
+
+use ash::vk;
+
+use rend_vk::capabilities::{CapabilityError, DeviceFeature};
+use rend_vk::config::RendererConfig;
+use rend_vk::interop::{ExternalSemaphoreFns, ExternalSemaphoreHandle};
+use rend_vk::render_core::RenderCore;
+use rend_vk::renderer::{self, FrameOutcome, Renderer};
+
+const TIMEOUT_NS: u64 = 5_000_000_000;
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+/*
+ * Timeline semaphore of the producer device sharing the payload of the handle.
+ */
+fn import(producer: &RenderCore, handle: ExternalSemaphoreHandle) -> vk::Semaphore {
+    let ctx = &producer.vulkan_context;
+    let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+        .semaphore_type(vk::SemaphoreType::TIMELINE)
+        .build();
+    let create_info = vk::SemaphoreCreateInfo::builder()
+        .push_next(&mut type_info)
+        .build();
+    let semaphore = unsafe { ctx.device.create_semaphore(&create_info, None) }.unwrap();
+    match (&ctx.extension.external_semaphore, handle) {
+        (Some(ExternalSemaphoreFns::Fd(fns)), ExternalSemaphoreHandle::Fd(fd)) => {
+            let info = vk::ImportSemaphoreFdInfoKHR::builder()
+                .semaphore(semaphore)
+                .handle_type(vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD)
+                .fd(fd);
+            unsafe { fns.import_semaphore_fd(&info) }.unwrap();
+        }
+        (Some(ExternalSemaphoreFns::Win32(fns)), ExternalSemaphoreHandle::Win32(handle)) => {
+            let info = vk::ImportSemaphoreWin32HandleInfoKHR::builder()
+                .semaphore(semaphore)
+                .handle_type(vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_WIN32)
+                .handle(handle);
+            unsafe { fns.import_semaphore_win32_handle(&info) }.unwrap();
+        }
+        _ => panic!("producer can't import {:?}", handle),
+    }
+    semaphore
+}
+
+fn signal(producer: &RenderCore, semaphore: vk::Semaphore, value: u64) {
+    let info = vk::SemaphoreSignalInfo::builder()
+        .semaphore(semaphore)
+        .value(value);
+    unsafe { producer.vulkan_context.device.signal_semaphore(&info) }.unwrap();
+}
+
+fn wait(producer: &RenderCore, semaphore: vk::Semaphore, value: u64) {
+    let semaphores = [semaphore];
+    let values = [value];
+    let info = vk::SemaphoreWaitInfo::builder()
+        .semaphores(&semaphores)
+        .values(&values);
+    unsafe {
+        producer
+            .vulkan_context
+            .device
+            .wait_semaphores(&info, TIMEOUT_NS)
+    }
+    .unwrap();
+}
+
+#[test]
+fn missing_features_name_themselves() {
+    let error = CapabilityError {
+        feature: DeviceFeature::ExternalSemaphore,
+    };
+    assert_eq!(
+        error.to_string(),
+        "the device was created without externalSemaphore"
+    );
+}
+
+#[test]
+fn frames_wait_on_the_producer_and_signal_back() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let handle = match renderer.export_interop_semaphore() {
+        Ok(e) => e,
+        Err(e) => {
+            assert!(!renderer.capabilities().is_external_semaphore_enabled);
+            assert_eq!(e.feature, DeviceFeature::ExternalSemaphore);
+            renderer.destroy();
+            return;
+        }
+    };
+    let producer = renderer::make_render_core(&RendererConfig::default(), false, true, &[]);
+    let semaphore = import(&producer, handle);
+
+    for frame in 0..4u64 {
+        // The producer writes its buffers, then lets the frame read them
+        let ready = frame * 2 + 1;
+        signal(&producer, semaphore, ready);
+        renderer.wait_external_value_before_frame(ready);
+        let done = renderer.signal_value_after_frame();
+        assert_eq!(done, ready + 1);
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        // Safe to overwrite them from here on
+        wait(&producer, semaphore, done);
+    }
+    // Frames without a wait leave the value alone
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let value = unsafe {
+        producer
+            .vulkan_context
+            .device
+            .get_semaphore_counter_value(semaphore)
+    };
+    assert_eq!(value.unwrap(), 8);
+
+    unsafe {
+        producer
+            .vulkan_context
+            .device
+            .destroy_semaphore(semaphore, None)
+    };
+    RenderCore::destroy(producer);
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+#[should_panic]
+fn values_have_to_move_forward() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    // Without external semaphores the first wait panics already
+    let _ = renderer.export_interop_semaphore();
+    renderer.wait_external_value_before_frame(2);
+    renderer.render();
+    // The frame signaled 3 already
+    renderer.wait_external_value_before_frame(3);
+}
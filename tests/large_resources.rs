@@ -0,0 +1,64 @@
+/*
+ * Per instance resources past maxUniformBufferRange, read through their buffer address
+ * on a headless surface with validation on. The grid stage of tests/large_resources.json
+ * colors each pixel with the transform of its own instance, so every pixel read back
+ * checks a different part of the buffer.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use glam::{Mat4, Vec4};
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Most devices limit uniform buffers to this
+const UNIFORM_RANGE: usize = 64 * 1024;
+
+fn color_of(x: u32, y: u32) -> [u8; 4] {
+    [(x * 4) as u8, (y * 4) as u8, ((x ^ y) * 4) as u8, 255]
+}
+
+fn grid() -> RenderTask {
+    let transforms = (0..SIZE * SIZE)
+        .map(|i| {
+            let color = Vec4::from(color_of(i % SIZE, i / SIZE).map(|e| e as f32 / 255.0));
+            let mvp = Mat4::from_cols(Vec4::X, Vec4::Y, Vec4::Z, color);
+            Transform { mvp, mv: mvp }
+        })
+        .collect();
+    RenderTask {
+        instance_count: SIZE * SIZE,
+        resources: [(
+            ResourceKind::Transform,
+            MultiResource::Transform(transforms),
+        )]
+        .into(),
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn transforms_past_the_uniform_range_read_back() {
+    let size = ResourceKind::Transform.resource_size() * (SIZE * SIZE) as usize;
+    assert!(size > 4 * UNIFORM_RANGE, "{} bytes", size);
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/large_resources.json", SIZE, SIZE);
+    renderer.add_task_to_queue(grid());
+    let mut request = renderer.read_attachment("grid").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    assert_eq!(bytes.len() as u32, SIZE * SIZE * 4);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let i = ((y * SIZE + x) * 4) as usize;
+            assert_eq!(bytes[i..i + 4], color_of(x, y), "pixel {}, {}", x, y);
+        }
+    }
+    common::finish(renderer);
+}
@@ -0,0 +1,231 @@
+/*
+ * Light binning into the cluster grid, with lights placed through the inverse of the
+ * projection so they land in known tiles and slices. No GPU involved.
+ */
+use glam::{Mat4, Vec3};
+
+use rend_vk::light_cluster::{
+    ClusterBuilder, ClusterData, ClusterGridConfig, GpuLight, ZDistribution,
+};
+
+const DIMS: (u32, u32, u32) = (4, 4, 8);
+
+fn config(z_distribution: ZDistribution) -> ClusterGridConfig {
+    ClusterGridConfig {
+        dims: DIMS,
+        z_near: 1.0,
+        z_far: 101.0,
+        z_distribution,
+    }
+}
+
+fn proj() -> Mat4 {
+    Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 200.0)
+}
+
+// View space point projecting to the NDC position, at the view depth
+fn at_ndc(x: f32, y: f32, depth: f32) -> Vec3 {
+    let p = proj().inverse().project_point3(Vec3::new(x, y, 0.5));
+    p / -p.z * depth
+}
+
+// NDC of the center of the tile
+fn tile_center(x: u32, y: u32) -> (f32, f32) {
+    let (dx, dy, _) = DIMS;
+    (
+        -1.0 + (2 * x + 1) as f32 / dx as f32,
+        -1.0 + (2 * y + 1) as f32 / dy as f32,
+    )
+}
+
+fn build(z_distribution: ZDistribution, lights: &[GpuLight]) -> ClusterData {
+    ClusterBuilder::new(config(z_distribution)).build(lights, Mat4::IDENTITY, proj())
+}
+
+// Clusters holding the light, as x, y, z
+fn clusters_of(data: &ClusterData, light: u32) -> Vec<(u32, u32, u32)> {
+    let (dx, dy, dz) = DIMS;
+    let mut clusters = Vec::new();
+    for z in 0..dz {
+        for y in 0..dy {
+            for x in 0..dx {
+                if data.lights_in(x, y, z).contains(&light) {
+                    clusters.push((x, y, z));
+                }
+            }
+        }
+    }
+    clusters
+}
+
+#[test]
+fn linear_slices_split_the_range_evenly() {
+    let config = config(ZDistribution::Linear);
+    assert_eq!(config.slice_bounds(0), (1.0, 13.5));
+    assert_eq!(config.slice_bounds(7), (88.5, 101.0));
+    let (scale, bias) = config.depth_scale_and_bias();
+    for z in 0..DIMS.2 {
+        let (near, far) = config.slice_bounds(z);
+        let middle = (near + far) / 2.0;
+        assert_eq!((middle * scale - bias).floor() as u32, z);
+    }
+}
+
+#[test]
+fn exponential_slices_grow_with_depth() {
+    let config = ClusterGridConfig {
+        z_far: 256.0,
+        ..config(ZDistribution::Exponential)
+    };
+    let bounds: Vec<_> = (0..DIMS.2).map(|z| config.slice_bounds(z)).collect();
+    // Each slice reaches twice as far as the previous one
+    for (z, (near, far)) in bounds.iter().enumerate() {
+        assert!((near - 2f32.powi(z as i32)).abs() < 1e-3, "{} {}", z, near);
+        assert!((far / near - 2.0).abs() < 1e-4);
+    }
+    let (scale, bias) = config.depth_scale_and_bias();
+    for (z, (near, far)) in bounds.into_iter().enumerate() {
+        let middle = (near + far) / 2.0;
+        assert_eq!((middle.ln() * scale - bias).floor() as usize, z);
+    }
+}
+
+#[test]
+fn small_lights_land_in_their_cluster_only() {
+    let (x, y) = tile_center(0, 3);
+    // Slice 2 covers depths 26 to 38.5
+    let light = GpuLight {
+        position: at_ndc(x, y, 32.0),
+        radius: 0.5,
+    };
+    let data = build(ZDistribution::Linear, &[light]);
+    assert_eq!(clusters_of(&data, 0), [(0, 3, 2)]);
+}
+
+#[test]
+fn lights_on_boundaries_land_in_every_neighbor() {
+    // Center of the view, where four tiles meet, at the boundary of slices 1 and 2
+    let light = GpuLight {
+        position: at_ndc(0.0, 0.0, 26.0),
+        radius: 0.1,
+    };
+    let data = build(ZDistribution::Linear, &[light]);
+    let mut expected = Vec::new();
+    for z in 1..=2 {
+        for y in 1..=2 {
+            for x in 1..=2 {
+                expected.push((x, y, z));
+            }
+        }
+    }
+    assert_eq!(clusters_of(&data, 0), expected);
+}
+
+#[test]
+fn lights_outside_the_range_land_nowhere() {
+    let lights = [
+        // Behind the camera, not reaching past z_near
+        GpuLight {
+            position: Vec3::new(0.0, 0.0, 2.0),
+            radius: 1.5,
+        },
+        // Past z_far
+        GpuLight {
+            position: Vec3::new(0.0, 0.0, -120.0),
+            radius: 10.0,
+        },
+        // Off to the side of the frustum
+        GpuLight {
+            position: Vec3::new(100.0, 0.0, -20.0),
+            radius: 5.0,
+        },
+    ];
+    let data = build(ZDistribution::Linear, &lights);
+    assert!(data.light_indices.is_empty());
+    assert!(data.ranges.iter().all(|e| e.count == 0));
+}
+
+#[test]
+fn huge_lights_land_everywhere() {
+    let light = GpuLight {
+        position: Vec3::new(0.0, 0.0, -50.0),
+        radius: 1000.0,
+    };
+    let data = build(ZDistribution::Exponential, &[light]);
+    assert_eq!(clusters_of(&data, 0).len(), data.config.cluster_count());
+}
+
+#[test]
+fn ranges_cover_the_index_list_in_cluster_order() {
+    // A grid of lights over the whole frustum, most clusters get several
+    let mut lights = Vec::new();
+    for i in 0..64 {
+        let x = -0.9 + 1.8 * (i % 8) as f32 / 7.0;
+        let y = -0.9 + 1.8 * (i / 8) as f32 / 7.0;
+        lights.push(GpuLight {
+            position: at_ndc(x, y, 1.0 + i as f32 * 1.5),
+            radius: 4.0,
+        });
+    }
+    let data = build(ZDistribution::Exponential, &lights);
+    assert_eq!(data.ranges.len(), data.config.cluster_count());
+    let mut offset = 0;
+    for range in &data.ranges {
+        assert_eq!(range.offset, offset);
+        offset += range.count;
+    }
+    assert_eq!(offset as usize, data.light_indices.len());
+    // Lights of a cluster keep the order they were passed in
+    for range in &data.ranges {
+        let start = range.offset as usize;
+        let indices = &data.light_indices[start..start + range.count as usize];
+        assert!(indices.windows(2).all(|e| e[0] < e[1]));
+    }
+    // Every light is somewhere, they're all inside the frustum
+    for light in 0..lights.len() as u32 {
+        assert!(data.light_indices.contains(&light), "light {}", light);
+    }
+}
+
+#[test]
+fn the_view_matrix_moves_the_lights() {
+    let (x, y) = tile_center(2, 1);
+    let position = at_ndc(x, y, 32.0);
+    let offset = Vec3::new(10.0, -4.0, 3.0);
+    let light = GpuLight {
+        position: position + offset,
+        radius: 0.5,
+    };
+    let view = Mat4::from_translation(-offset);
+    let data = ClusterBuilder::new(config(ZDistribution::Linear)).build(&[light], view, proj());
+    assert_eq!(clusters_of(&data, 0), [(2, 1, 2)]);
+}
+
+#[test]
+fn resources_describe_the_grid() {
+    let data = build(ZDistribution::Exponential, &[]);
+    let resource = data.to_resource(0x1000, 0x2000);
+    assert_eq!((resource.ranges, resource.light_indices), (0x1000, 0x2000));
+    assert_eq!(resource.dims.to_array(), [4, 4, 8]);
+    assert_eq!(resource.is_exponential, 1);
+    let (scale, bias) = data.config.depth_scale_and_bias();
+    assert_eq!((resource.z_scale, resource.z_bias), (scale, bias));
+}
+
+#[test]
+#[should_panic(expected = "cluster grid dims can't be zero!")]
+fn empty_grids_are_rejected() {
+    ClusterBuilder::new(ClusterGridConfig {
+        dims: (4, 0, 8),
+        ..config(ZDistribution::Linear)
+    });
+}
+
+#[test]
+#[should_panic(expected = "cluster grid needs 0 < z_near < z_far!")]
+fn grids_starting_at_the_eye_are_rejected() {
+    ClusterBuilder::new(ClusterGridConfig {
+        z_near: 0.0,
+        ..config(ZDistribution::Exponential)
+    });
+}
@@ -0,0 +1,131 @@
+/*
+ * Bookkeeping of the low latency overlay on its own. Shared presentable images, retained
+ * frames and the overlay frames recorded into them need a device and a surface
+ * supporting them.
+ */
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use rend_vk::low_latency::{self, OverlayState};
+use rend_vk::render_task::ScissorRect;
+use rend_vk::stats::FramePath;
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D { width, height },
+    }
+}
+
+fn scissor(x: i32, y: i32, width: u32, height: u32) -> ScissorRect {
+    ScissorRect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+const AREA: vk::Rect2D = vk::Rect2D {
+    offset: vk::Offset2D { x: 0, y: 0 },
+    extent: vk::Extent2D {
+        width: 800,
+        height: 600,
+    },
+};
+
+#[test]
+fn dirty_regions_get_unioned() {
+    let mut overlay = OverlayState::default();
+    overlay.mark_dirty(scissor(10, 20, 30, 40));
+    overlay.mark_dirty(scissor(100, 5, 10, 10));
+    // No area, nothing marked
+    overlay.mark_dirty(scissor(500, 500, 0, 10));
+    assert_eq!(overlay.take_dirty(AREA), Some(rect(10, 5, 100, 55)));
+    // Starts over
+    assert_eq!(overlay.take_dirty(AREA), Some(AREA));
+}
+
+#[test]
+fn dirty_regions_get_clamped_to_the_area() {
+    let mut overlay = OverlayState::default();
+    overlay.mark_dirty(scissor(-10, 590, 20, 20));
+    assert_eq!(overlay.take_dirty(AREA), Some(rect(0, 590, 10, 10)));
+    overlay.mark_dirty(scissor(900, 0, 20, 20));
+    assert_eq!(overlay.take_dirty(AREA), None);
+}
+
+#[test]
+fn overlay_frames_need_a_whole_frame_first() {
+    let mut overlay = OverlayState::default();
+    assert!(!overlay.is_overlay_frame(false, true));
+    overlay.presented(FramePath::Stages, Instant::now());
+    assert!(overlay.is_frame_complete());
+    assert!(overlay.is_overlay_frame(false, true));
+    // Scene changed, or nothing for the overlay to draw
+    assert!(!overlay.is_overlay_frame(true, true));
+    assert!(!overlay.is_overlay_frame(false, false));
+    // Overlay frames leave it complete
+    overlay.presented(FramePath::Overlay, Instant::now());
+    assert!(overlay.is_overlay_frame(false, true));
+}
+
+#[test]
+fn whole_frames_drop_the_dirty_region() {
+    let mut overlay = OverlayState::default();
+    overlay.mark_dirty(scissor(10, 10, 10, 10));
+    overlay.presented(FramePath::Overlay, Instant::now());
+    assert_eq!(overlay.take_dirty(AREA), Some(rect(10, 10, 10, 10)));
+    overlay.mark_dirty(scissor(10, 10, 10, 10));
+    overlay.presented(FramePath::Stages, Instant::now());
+    assert_eq!(overlay.take_dirty(AREA), Some(AREA));
+}
+
+#[test]
+fn input_to_present_measures_from_the_oldest_input() {
+    let mut overlay = OverlayState::default();
+    let start = Instant::now();
+    assert_eq!(overlay.presented(FramePath::Idle, start), None);
+    overlay.mark_input(start + Duration::from_millis(5));
+    overlay.mark_input(start + Duration::from_millis(2));
+    overlay.mark_input(start + Duration::from_millis(8));
+    let presented = start + Duration::from_millis(10);
+    assert_eq!(
+        overlay.presented(FramePath::Overlay, presented),
+        Some(Duration::from_millis(8))
+    );
+    // Taken by the frame presenting it
+    assert_eq!(overlay.presented(FramePath::Overlay, presented), None);
+}
+
+#[test]
+fn new_swapchains_start_over() {
+    let mut overlay = OverlayState::default();
+    overlay.set_acquired();
+    overlay.presented(FramePath::Stages, Instant::now());
+    overlay.mark_dirty(scissor(1, 1, 1, 1));
+    overlay.reset();
+    assert!(!overlay.is_acquired());
+    assert!(!overlay.is_frame_complete());
+    assert_eq!(overlay.take_dirty(AREA), Some(AREA));
+}
+
+#[test]
+fn frames_get_retained_without_vsync_and_with_copyable_images() {
+    let copyable = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | vk::ImageUsageFlags::TRANSFER_SRC
+        | vk::ImageUsageFlags::TRANSFER_DST;
+    for mode in [vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX] {
+        assert!(low_latency::is_retainable(mode, copyable));
+    }
+    // Waits for vertical blanks, the overlay wouldn't show any sooner
+    for mode in [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::FIFO_RELAXED] {
+        assert!(!low_latency::is_retainable(mode, copyable));
+    }
+    // Surfaces that can't copy from their images
+    let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST;
+    assert!(!low_latency::is_retainable(
+        vk::PresentModeKHR::IMMEDIATE,
+        usage
+    ));
+}
@@ -0,0 +1,142 @@
+/*
+ * Memory snapshots of synthetic allocator states, no GPU involved.
+ */
+use rend_vk::memory::{
+    AllocatorSnapshot, MemorySnapshot, MemorySnapshotTracker, PoolMemoryReport, TextureSnapshot,
+};
+use rend_vk::range_allocator::RangeAllocator;
+use rend_vk::texture::Residency;
+
+fn snapshot_of(general: &RangeAllocator, textures: Vec<TextureSnapshot>) -> MemorySnapshot {
+    MemorySnapshot {
+        sequence: 0,
+        general: AllocatorSnapshot::of(general),
+        descriptor: None,
+        readback: None,
+        image_pools: vec![PoolMemoryReport {
+            memory_type_index: 1,
+            block_count: 1,
+            size: 1024,
+            available: 512,
+            largest_free: 256,
+            free_range_count: 2,
+            dedicated_count: 0,
+            dedicated_size: 0,
+        }],
+        texture_bytes: textures.iter().map(|e| e.size).sum(),
+        textures,
+        texture_memory_budget: Some(4096),
+        pending_destroys: 0,
+    }
+}
+
+fn texture(id: u32, size: u64, residency: Residency) -> TextureSnapshot {
+    TextureSnapshot {
+        id,
+        size,
+        residency,
+    }
+}
+
+#[test]
+fn free_and_used_ranges_cover_the_allocator() {
+    let mut ranges = RangeAllocator::new(1024);
+    let offsets: Vec<_> = (0..8).map(|_| ranges.alloc(128, 64).unwrap()).collect();
+    let allocator = AllocatorSnapshot::of(&ranges);
+    assert_eq!(allocator.free_ranges, Vec::<[u64; 2]>::new());
+    assert_eq!(allocator.used_ranges(), [[0, 1024]]);
+    assert_eq!(allocator.fragmentation(), 0.0);
+
+    // Every other one freed, plus the last two so the tail joins up
+    for i in [0, 2, 4, 6, 7] {
+        ranges.free(offsets[i], 128);
+    }
+    let allocator = AllocatorSnapshot::of(&ranges);
+    assert_eq!(allocator.available, 640);
+    assert_eq!(
+        allocator.free_ranges,
+        [[0, 128], [256, 128], [512, 128], [768, 256]]
+    );
+    assert_eq!(
+        allocator.used_ranges(),
+        [[128, 128], [384, 128], [640, 128]]
+    );
+    assert_eq!(allocator.largest_free(), 256);
+    assert_eq!(allocator.fragmentation(), 1.0 - 256.0 / 640.0);
+
+    let used: u64 = allocator.used_ranges().iter().map(|e| e[1]).sum();
+    assert_eq!(used + allocator.available, allocator.size);
+}
+
+#[test]
+fn empty_allocators_are_one_free_range() {
+    let allocator = AllocatorSnapshot::of(&RangeAllocator::new(256));
+    assert_eq!(allocator.free_ranges, [[0, 256]]);
+    assert!(allocator.used_ranges().is_empty());
+    assert_eq!(allocator.fragmentation(), 0.0);
+
+    let full = AllocatorSnapshot {
+        size: 256,
+        available: 0,
+        free_ranges: Vec::new(),
+        scopes: Vec::new(),
+    };
+    assert_eq!(full.largest_free(), 0);
+    assert_eq!(full.fragmentation(), 0.0);
+}
+
+#[test]
+fn sequence_only_moves_with_changes() {
+    let mut tracker = MemorySnapshotTracker::default();
+    let mut ranges = RangeAllocator::new(1024);
+    let textures = vec![texture(0, 64, Residency::Resident)];
+    let first = tracker.track(snapshot_of(&ranges, textures.clone()));
+    assert_eq!(first.sequence, 1);
+    for _ in 0..3 {
+        let same = tracker.track(snapshot_of(&ranges, textures.clone()));
+        assert_eq!(same, first);
+    }
+
+    let offset = ranges.alloc(256, 64).unwrap();
+    let allocated = tracker.track(snapshot_of(&ranges, textures.clone()));
+    assert_eq!(allocated.sequence, 2);
+
+    // Back to the first state is still a change
+    ranges.free(offset, 256);
+    let freed = tracker.track(snapshot_of(&ranges, textures));
+    assert_eq!(freed.sequence, 3);
+
+    let evicted = vec![texture(0, 0, Residency::Evicted)];
+    assert_eq!(
+        tracker
+            .track(snapshot_of(&ranges, evicted.clone()))
+            .sequence,
+        4
+    );
+    let mut destroying = snapshot_of(&ranges, evicted);
+    destroying.pending_destroys = 1;
+    assert_eq!(tracker.track(destroying).sequence, 5);
+}
+
+#[test]
+fn snapshots_serialize_under_stable_names() {
+    let mut ranges = RangeAllocator::new(1024);
+    ranges.alloc(256, 64).unwrap();
+    let textures = vec![
+        texture(0, 64, Residency::Resident),
+        texture(1, 0, Residency::Evicted),
+    ];
+    let snapshot = MemorySnapshotTracker::default().track(snapshot_of(&ranges, textures));
+    let value = serde_json::to_value(&snapshot).unwrap();
+    assert_eq!(value["sequence"], 1);
+    assert_eq!(
+        value["general"]["freeRanges"],
+        serde_json::json!([[256, 768]])
+    );
+    assert_eq!(value["descriptor"], serde_json::Value::Null);
+    assert_eq!(value["imagePools"][0]["largestFree"], 256);
+    assert_eq!(value["textures"][1]["residency"], "Evicted");
+    assert_eq!(value["textureBytes"], 64);
+    assert_eq!(value["textureMemoryBudget"], 4096);
+    assert_eq!(value["pendingDestroys"], 0);
+}
@@ -0,0 +1,177 @@
+/*
+ * Mesh optimizations on small made up meshes, no GPU involved.
+ */
+use rend_vk::mesh_opt::{
+    self, MeshData, MeshIndices, MeshOptFlags, PackedMesh, VertexStream, ACMR_CACHE_SIZE,
+};
+
+const GRID: u32 = 12;
+// Position, normal and tex coord interleaved
+const STRIDE: u32 = 32;
+
+fn grid_vertices() -> Vec<u8> {
+    let mut floats = Vec::new();
+    for y in 0..=GRID {
+        for x in 0..=GRID {
+            let (u, v) = (x as f32 / GRID as f32, y as f32 / GRID as f32);
+            floats.extend([u, v, (u * 7.0).sin() * 0.1]);
+            floats.extend([0.0, 0.0, 1.0]);
+            floats.extend([u, v]);
+        }
+    }
+    floats.iter().flat_map(|e| e.to_ne_bytes()).collect()
+}
+
+// Two triangles per quad, shuffled so the order is bad for any cache
+fn grid_indices() -> Vec<u32> {
+    let mut triangles = Vec::new();
+    for y in 0..GRID {
+        for x in 0..GRID {
+            let i = y * (GRID + 1) + x;
+            triangles.push([i, i + 1, i + GRID + 1]);
+            triangles.push([i + 1, i + GRID + 2, i + GRID + 1]);
+        }
+    }
+    let mut seed = 12345u32;
+    for i in (1..triangles.len()).rev() {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        triangles.swap(i, (seed >> 8) as usize % (i + 1));
+    }
+    triangles.concat()
+}
+
+fn mesh_data<'a>(vertices: &'a [u8], vertex_count: u32, indices: MeshIndices<'a>) -> MeshData<'a> {
+    MeshData {
+        vertex_count,
+        positions: VertexStream {
+            data: vertices,
+            stride: STRIDE,
+        },
+        normals: Some(VertexStream {
+            data: &vertices[12..],
+            stride: STRIDE,
+        }),
+        tex_coords: Some(VertexStream {
+            data: &vertices[24..],
+            stride: STRIDE,
+        }),
+        indices,
+    }
+}
+
+// Attribute bytes of the corners of every triangle, sorted to compare meshes
+fn triangles_of(mesh: &PackedMesh) -> Vec<Vec<u8>> {
+    let indices: Vec<u32> = match &mesh.indices {
+        Some(e) => e.clone(),
+        None => (0..mesh.vertex_count).collect(),
+    };
+    let mut triangles: Vec<Vec<u8>> = indices
+        .chunks_exact(3)
+        .map(|e| {
+            let mut bytes = Vec::new();
+            for v in e {
+                for (data, size) in &mesh.streams {
+                    let at = *v as usize * size;
+                    bytes.extend_from_slice(&data[at..at + size]);
+                }
+            }
+            bytes
+        })
+        .collect();
+    triangles.sort();
+    triangles
+}
+
+#[test]
+fn vertex_cache_reorder_improves_acmr() {
+    let vertices = grid_vertices();
+    let indices = grid_indices();
+    let vertex_count = (GRID + 1) * (GRID + 1);
+    let mut mesh = mesh_data(&vertices, vertex_count, MeshIndices::U32(&indices)).pack();
+    let flags = MeshOptFlags {
+        vertex_cache_reorder: true,
+        ..Default::default()
+    };
+    let report = mesh_opt::optimize(&mut mesh, flags);
+    assert!(report.acmr_before > 1.5, "{:?}", report);
+    assert!(report.acmr_after < 1.0, "{:?}", report);
+    let after = mesh_opt::acmr(mesh.indices.as_deref(), vertex_count, ACMR_CACHE_SIZE);
+    assert_eq!(after, report.acmr_after);
+}
+
+#[test]
+fn all_optimizations_keep_attributes_bit_exact() {
+    let vertices = grid_vertices();
+    let indices: Vec<u16> = grid_indices().iter().map(|e| *e as u16).collect();
+    let vertex_count = (GRID + 1) * (GRID + 1);
+    let data = mesh_data(&vertices, vertex_count, MeshIndices::U16(&indices));
+    let original = data.pack();
+    let mut mesh = data.pack();
+    let report = mesh_opt::optimize(&mut mesh, MeshOptFlags::ALL);
+    assert_eq!(report.vertices_deduplicated, 0);
+    assert!(report.acmr_after < report.acmr_before);
+    assert_eq!(mesh.vertex_count, vertex_count);
+    assert_eq!(mesh.count(), indices.len() as u32);
+    assert_eq!(triangles_of(&mesh), triangles_of(&original));
+}
+
+#[test]
+fn deduplicate_generates_indices() {
+    let vertices = grid_vertices();
+    let indices = grid_indices();
+    // Every corner written out, like a non indexed OBJ
+    let unindexed: Vec<u8> = indices
+        .iter()
+        .flat_map(|e| {
+            let at = (*e * STRIDE) as usize;
+            vertices[at..at + STRIDE as usize].to_vec()
+        })
+        .collect();
+    let corners = indices.len() as u32;
+    let data = mesh_data(&unindexed, corners, MeshIndices::None);
+    let original = data.pack();
+    let mut mesh = data.pack();
+    let flags = MeshOptFlags {
+        deduplicate_vertices: true,
+        ..Default::default()
+    };
+    let report = mesh_opt::optimize(&mut mesh, flags);
+    let vertex_count = (GRID + 1) * (GRID + 1);
+    assert_eq!(report.acmr_before, 3.0);
+    assert_eq!(report.vertices_deduplicated, corners - vertex_count);
+    assert_eq!(mesh.vertex_count, vertex_count);
+    assert_eq!(mesh.indices.as_ref().unwrap().len(), corners as usize);
+    assert_eq!(triangles_of(&mesh), triangles_of(&original));
+}
+
+#[test]
+fn fetch_reorder_numbers_vertices_by_first_use() {
+    let vertices = grid_vertices();
+    let indices = grid_indices();
+    let vertex_count = (GRID + 1) * (GRID + 1);
+    let data = mesh_data(&vertices, vertex_count, MeshIndices::U32(&indices));
+    let original = data.pack();
+    let mut mesh = data.pack();
+    let flags = MeshOptFlags {
+        fetch_reorder: true,
+        ..Default::default()
+    };
+    mesh_opt::optimize(&mut mesh, flags);
+    let mut next = 0;
+    for e in mesh.indices.as_ref().unwrap() {
+        assert!(*e <= next);
+        next = next.max(*e + 1);
+    }
+    assert_eq!(triangles_of(&mesh), triangles_of(&original));
+}
+
+#[test]
+fn no_flags_leave_the_mesh_alone() {
+    let vertices = grid_vertices();
+    let vertex_count = (GRID + 1) * (GRID + 1);
+    let data = mesh_data(&vertices, vertex_count, MeshIndices::None);
+    let mut mesh = data.pack();
+    let report = mesh_opt::optimize(&mut mesh, MeshOptFlags::default());
+    assert_eq!(mesh, data.pack());
+    assert_eq!(report.acmr_before, report.acmr_after);
+}
@@ -0,0 +1,66 @@
+/*
+ * Chunked uploads of a mesh bigger than the staging capacity, on a headless surface with
+ * validation on. Writes take what fits, the rest goes in once frames copied some out.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::{RendererConfig, TaskRejected};
+use rend_vk::mesh_upload::{MeshAttribute, MeshUploadCursor};
+use rend_vk::render_task::{RenderTask, TaskKind};
+
+const STAGING: u64 = 2 * MeshUploadCursor::CHUNK_SIZE;
+const MESH_SIZE: u64 = STAGING * 3 + 100;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn meshes_past_the_staging_capacity_upload_over_several_frames() {
+    let _serial = common::serial();
+    let config = RendererConfig {
+        mesh_staging_bytes: STAGING,
+        readback_bytes: MESH_SIZE,
+        ..Default::default()
+    };
+    let mut renderer = common::make_renderer_with(config, "pipeline.json", 64, 64);
+    let data: Vec<u8> = (0..MESH_SIZE as u32).map(|e| (e % 251) as u8).collect();
+    let mesh = renderer.gen_mesh(data.len() as u32, 0, 0, 0, 3);
+    let task = || RenderTask::new(mesh, TaskKind::Fullscreen);
+
+    let mut cursor = renderer
+        .begin_mesh_upload(mesh, MeshAttribute::Vertices)
+        .unwrap();
+    let mut written = 0;
+    let mut frames = 0;
+    while written < data.len() {
+        let taken = cursor.write(&data[written..]);
+        assert!(taken as u64 <= STAGING, "{}", taken);
+        written += taken;
+        if taken == 0 {
+            assert_eq!(
+                renderer.try_add_task_to_queue(task()),
+                Err(TaskRejected::MeshUploading { mesh })
+            );
+            renderer.render();
+            frames += 1;
+            assert!(frames < 16, "staging never freed up");
+        }
+    }
+    cursor.finish();
+    assert!(frames >= 3, "{} frames", frames);
+    while !renderer.is_upload_complete(mesh).unwrap() {
+        renderer.render();
+        frames += 1;
+        assert!(frames < 32, "upload never completed");
+    }
+    assert_eq!(renderer.try_add_task_to_queue(task()), Ok(()));
+
+    let vertices = renderer.fetch_mesh(mesh).unwrap().vertices;
+    let mut request = renderer
+        .read_buffer(&vertices, 0..data.len() as u64)
+        .unwrap();
+    renderer.render();
+    assert!(request.resolve_wait(&renderer, TIMEOUT).unwrap() == data);
+    common::finish(renderer);
+}
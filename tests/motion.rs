@@ -0,0 +1,160 @@
+/*
+ * TAA jitter sequences, the previous transform cache and interpolated simulation
+ * transforms, no GPU involved.
+ */
+use glam::{Mat4, Quat, Vec2, Vec3};
+
+use rend_vk::motion::{
+    self, Affine, JitterSequence, PreviousTransforms, SimulationTransforms, IDENTITY_AFFINE,
+};
+use rend_vk::pipeline::file::{Pass, PerDrawField, TransformInterpolation};
+use rend_vk::render_task::TaskKind;
+
+#[test]
+fn halton_2_3_starts_at_index_one() {
+    let sequence = JitterSequence::halton_2_3(8);
+    assert_eq!(sequence.len(), 8);
+    assert_eq!(sequence.at(0), Vec2::new(0.0, 1.0 / 3.0 - 0.5));
+    assert_eq!(sequence.at(1), Vec2::new(-0.25, 2.0 / 3.0 - 0.5));
+    assert_eq!(sequence.at(2), Vec2::new(0.25, 1.0 / 9.0 - 0.5));
+    // Wraps around
+    assert_eq!(sequence.at(8), sequence.at(0));
+    for e in sequence.samples() {
+        assert!(e.abs().max_element() < 0.5);
+    }
+}
+
+#[test]
+fn clip_space_jitter_spans_two_units_per_extent() {
+    let sequence = JitterSequence::new(vec![Vec2::new(0.5, -0.5)]);
+    let jitter = sequence.clip_space_at(3, 100, 50);
+    assert_eq!(jitter, Vec2::new(0.01, -0.02));
+}
+
+#[test]
+#[should_panic]
+fn empty_jitter_sequence_panics() {
+    JitterSequence::new(Vec::new());
+}
+
+#[test]
+fn previous_transforms_are_keyed_by_kind_and_id() {
+    let mut cache = PreviousTransforms::new();
+    let mvp = Mat4::from_translation(Vec3::X);
+    cache.record(TaskKind::MeshStatic, 7, 0, vec![mvp]);
+    assert_eq!(cache.get(TaskKind::MeshStatic, 7), Some(&[mvp][..]));
+    assert_eq!(cache.get(TaskKind::ShadowDir, 7), None);
+    assert_eq!(cache.get(TaskKind::MeshStatic, 8), None);
+}
+
+#[test]
+fn previous_transforms_evict_unseen_objects() {
+    let mut cache = PreviousTransforms::new();
+    cache.record(TaskKind::MeshStatic, 1, 0, vec![Mat4::IDENTITY]);
+    cache.record(TaskKind::MeshStatic, 2, 0, vec![Mat4::IDENTITY]);
+    for frame in 1..=4 {
+        // Only object 2 keeps showing up
+        cache.record(TaskKind::MeshStatic, 2, frame, vec![Mat4::IDENTITY]);
+        cache.evict(frame, 4);
+    }
+    assert_eq!(cache.len(), 2);
+    cache.evict(5, 4);
+    assert_eq!(cache.len(), 1);
+    assert!(cache.get(TaskKind::MeshStatic, 1).is_none());
+    assert!(cache.get(TaskKind::MeshStatic, 2).is_some());
+}
+
+fn translation(x: f32) -> Affine {
+    motion::mat4_to_affine(&Mat4::from_translation(Vec3::new(x, 0.0, 0.0)))
+}
+
+// Where the origin of the object ends up
+fn origin_x(m: &Affine) -> f32 {
+    motion::affine_to_mat4(m).transform_point3(Vec3::ZERO).x
+}
+
+#[test]
+fn affine_transforms_round_trip_through_mat4() {
+    let m = Mat4::from_scale_rotation_translation(
+        Vec3::new(1.0, 2.0, 3.0),
+        Quat::from_rotation_y(0.5),
+        Vec3::new(4.0, 5.0, 6.0),
+    );
+    let affine = motion::mat4_to_affine(&m);
+    assert_eq!(affine[3], 4.0);
+    assert_eq!(affine[7], 5.0);
+    assert_eq!(affine[11], 6.0);
+    assert_eq!(motion::affine_to_mat4(&affine), m);
+    assert_eq!(motion::affine_to_mat4(&IDENTITY_AFFINE), Mat4::IDENTITY);
+}
+
+#[test]
+fn simulation_transforms_interpolate_by_alpha() {
+    let transforms = SimulationTransforms::new(
+        &[translation(0.0), translation(10.0)],
+        &[translation(4.0), translation(10.0)],
+        0.25,
+    );
+    assert_eq!(origin_x(&transforms.interpolated(0)), 1.0);
+    assert_eq!(origin_x(&transforms.interpolated(1)), 10.0);
+    let bytes = transforms.to_bytes();
+    assert_eq!(bytes[0].len(), 2 * 12 * 4);
+    assert_eq!(transforms.interpolated_bytes(1, 1).len(), 12 * 4);
+}
+
+#[test]
+fn velocity_comes_from_the_simulation_states() {
+    let (previous, current) = ([translation(0.0)], [translation(4.0)]);
+    // Frames interpolated at irregular alphas within the same simulation step
+    let mut last_rendered = None;
+    for alpha in [0.1, 0.25, 0.9] {
+        let transforms = SimulationTransforms::new(&previous, &current, alpha);
+        let rendered = origin_x(&transforms.interpolated(0));
+        let motion = rendered - origin_x(&transforms.velocity_origin(0));
+        // A whole step every frame, whatever the alpha
+        assert!((motion - 4.0).abs() < 1e-5, "{} at alpha {}", motion, alpha);
+        if let Some(last) = last_rendered {
+            // Unlike the difference of what consecutive frames render
+            assert_ne!(rendered - last, 4.0);
+        }
+        last_rendered = Some(rendered);
+    }
+}
+
+#[test]
+fn static_objects_have_no_velocity() {
+    let transforms = SimulationTransforms::new(&[translation(3.0)], &[translation(3.0)], 0.5);
+    assert_eq!(transforms.velocity_origin(0), transforms.interpolated(0));
+}
+
+#[test]
+#[should_panic(expected = "1 previous simulation transforms but 2 current ones")]
+fn simulation_states_have_the_same_objects() {
+    SimulationTransforms::new(&[IDENTITY_AFFINE], &[IDENTITY_AFFINE; 2], 0.5);
+}
+
+#[test]
+#[should_panic(expected = "isn't within 0 and 1")]
+fn interpolation_alpha_is_within_the_step() {
+    SimulationTransforms::new(&[IDENTITY_AFFINE], &[IDENTITY_AFFINE], 1.5);
+}
+
+#[test]
+fn passes_pick_where_transforms_get_blended() {
+    let pass: Pass = serde_json::from_str(
+        r#"{
+            "name": "gbuffer",
+            "transformInterpolation": "cpu",
+            "perDrawFields": ["interpolatedTransform"]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(pass.transform_interpolation, TransformInterpolation::Cpu);
+    assert_eq!(
+        PerDrawField::InterpolatedTransform.member_names(),
+        ["previousSimulation", "currentSimulation", "simulationBlend"]
+    );
+    assert_eq!(PerDrawField::InterpolatedTransform.size(), 24);
+    let pass: Pass = serde_json::from_str(r#"{ "name": "gbuffer" }"#).unwrap();
+    assert_eq!(pass.transform_interpolation, TransformInterpolation::None);
+}
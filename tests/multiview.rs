@@ -0,0 +1,100 @@
+/*
+ * Multiview on a headless surface with validation on. The views stage of
+ * tests/multiview.json draws the test triangle once per layer of its target, moved by
+ * the matrix of that view and colored by its view index, both layers get read back.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+use rend_vk::shader_resource::{
+    MultiResource, ResourceKind, SingleResource, Transform, ViewMatrices,
+};
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+fn triangle() -> RenderTask {
+    RenderTask {
+        resources: [(
+            ResourceKind::Transform,
+            MultiResource::Transform(vec![Transform {
+                mvp: Mat4::IDENTITY,
+                mv: Mat4::IDENTITY,
+            }]),
+        )]
+        .into(),
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+// Half the size, into the left half of the first view and the right half of the second
+fn view_matrices() -> ViewMatrices {
+    let half =
+        |x| Mat4::from_translation(Vec3::new(x, 0.0, 0.0)) * Mat4::from_scale(Vec3::splat(0.5));
+    let mut items = [Mat4::IDENTITY; ViewMatrices::MAX_VIEWS as usize];
+    items[0] = half(-0.5);
+    items[1] = half(0.5);
+    ViewMatrices { items }
+}
+
+// Pixels of the color in the left and right halves of the layer
+fn count_halves(bytes: &[u8], color: [u8; 4]) -> (u32, u32) {
+    let (mut left, mut right) = (0, 0);
+    for (i, pixel) in bytes.chunks_exact(4).enumerate() {
+        if pixel == color {
+            if (i as u32 % SIZE) < SIZE / 2 {
+                left += 1;
+            } else {
+                right += 1;
+            }
+        }
+    }
+    (left, right)
+}
+
+#[test]
+fn each_view_renders_into_its_own_layer() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/multiview.json", SIZE, SIZE);
+    renderer.place_shader_resource(
+        ResourceKind::ViewMatrices,
+        SingleResource::ViewMatrices(view_matrices()),
+    );
+    renderer.add_task_to_queue(triangle());
+    let mut requests = [0, 1].map(|layer| renderer.read_attachment_layer("views", layer).unwrap());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let [first, second] = requests
+        .each_mut()
+        .map(|e| e.resolve_wait(&renderer, TIMEOUT).unwrap());
+    for bytes in [&first, &second] {
+        assert_eq!(bytes.len() as u32, SIZE * SIZE * 4);
+    }
+    let (left, right) = count_halves(&first, RED);
+    assert!(left > 0 && right == 0, "{} red left, {} right", left, right);
+    assert_eq!(count_halves(&first, GREEN), (0, 0));
+    let (left, right) = count_halves(&second, GREEN);
+    assert!(
+        left == 0 && right > 0,
+        "{} green left, {} right",
+        left,
+        right
+    );
+    assert_eq!(count_halves(&second, RED), (0, 0));
+    common::finish(renderer);
+}
+
+#[test]
+#[should_panic(expected = "attachment views has 2 layers, there's no layer 2!")]
+fn reading_a_layer_past_the_last_panics() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/multiview.json", SIZE, SIZE);
+    let _ = renderer.read_attachment_layer("views", 2);
+}
@@ -0,0 +1,186 @@
+/*
+ * Frame noise on its own without a device, and the noise textures of a renderer on a
+ * headless surface with validation on, read back once uploaded. Validation errors logged
+ * by the object tracker on destroy count as leaks.
+ */
+
+mod common;
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use glam::UVec2;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::events::{RenderEvent, RingBufferSink};
+use rend_vk::format::Format;
+use rend_vk::noise::{FrameNoise, NoiseConfig, NoiseTexture, NOISE_PERIOD, NOISE_SIZE};
+use rend_vk::renderer::Renderer;
+use rend_vk::texture::MipMap;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer(noise: NoiseConfig) -> Renderer {
+    let config = RendererConfig {
+        noise,
+        ..Default::default()
+    };
+    let mut renderer = common::make_renderer_with(config, "pipeline.json", 64, 64);
+    renderer.set_event_sink(Box::new(RingBufferSink::new(256)));
+    renderer
+}
+
+use common::finish_without_leaks as finish;
+
+fn uploaded_names(renderer: &mut Renderer) -> Vec<String> {
+    renderer
+        .drain_events()
+        .into_iter()
+        .filter_map(|e| match e {
+            RenderEvent::TextureUploaded { name, .. } => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn seeds_only_depend_on_the_frame() {
+    let from = |start: u64| (start..start + 8).map(FrameNoise::of).collect::<Vec<_>>();
+    assert_eq!(from(1000), from(1000));
+    let seeds: Vec<_> = (0..4).map(|e| FrameNoise::of(e).seed).collect();
+    assert_eq!(seeds, [0x92CA_2F0E, 0x36DE_B503, 0xB442_1BBB, 0x96A0_F96B]);
+    // Consecutive frames never share a seed
+    let unique: HashSet<_> = (0..4096).map(|e| FrameNoise::of(e).seed).collect();
+    assert_eq!(unique.len(), 4096);
+    // Past the 32 bits of the index the seed still changes
+    let wrapped = FrameNoise::of(1 << 32);
+    assert_eq!(wrapped.frame_index, 0);
+    assert_ne!(wrapped.seed, FrameNoise::of(0).seed);
+}
+
+#[test]
+fn offsets_cycle_through_the_texture() {
+    for frame in 0..NOISE_PERIOD {
+        let noise = FrameNoise::of(frame);
+        assert_eq!(noise.offset, FrameNoise::of(frame + NOISE_PERIOD).offset);
+        assert!(noise.offset.cmplt(UVec2::splat(NOISE_SIZE)).all());
+    }
+    let unique: HashSet<_> = (0..NOISE_PERIOD)
+        .map(|e| FrameNoise::of(e).offset.to_array())
+        .collect();
+    assert_eq!(unique.len(), NOISE_PERIOD as usize);
+}
+
+#[test]
+fn embedded_textures_spread_every_value_evenly() {
+    for (texture, channels) in [
+        (NoiseTexture::embedded_single(), 1),
+        (NoiseTexture::embedded_dual(), 2),
+    ] {
+        assert_eq!([texture.width, texture.height], [NOISE_SIZE, NOISE_SIZE]);
+        for channel in 0..channels {
+            let mut counts = [0u32; 256];
+            for texel in texture.texels.chunks_exact(channels).map(|e| e[channel]) {
+                counts[texel as usize] += 1;
+            }
+            // 4096 texels over 256 values
+            assert!(counts.iter().all(|e| *e == 16), "channel {}", channel);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "4x4 R8G8_UNORM noise texture needs 32 bytes, got 16")]
+fn custom_textures_need_every_texel() {
+    let texture = NoiseTexture {
+        width: 4,
+        height: 4,
+        texels: vec![0; 16],
+    };
+    texture.check(Format::R8G8_UNORM);
+}
+
+#[test]
+fn embedded_textures_read_back_as_embedded() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer(NoiseConfig::Embedded);
+    for _ in 0..4 {
+        renderer.render();
+    }
+    let uploaded = uploaded_names(&mut renderer);
+    assert!(
+        uploaded.contains(&"blue_noise_1".to_string()),
+        "{:?}",
+        uploaded
+    );
+    assert!(
+        uploaded.contains(&"blue_noise_2".to_string()),
+        "{:?}",
+        uploaded
+    );
+    let mut single = renderer.read_texture(Renderer::BLUE_NOISE).unwrap();
+    let mut dual = renderer.read_texture(Renderer::BLUE_NOISE_DUAL).unwrap();
+    renderer.render();
+    assert_eq!(
+        single.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        NoiseTexture::embedded_single().texels
+    );
+    assert_eq!(
+        dual.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        NoiseTexture::embedded_dual().texels
+    );
+    finish(renderer);
+}
+
+#[test]
+fn custom_textures_take_the_reserved_ids() {
+    let _serial = common::serial();
+    let single = NoiseTexture {
+        width: 4,
+        height: 2,
+        texels: (0..8).collect(),
+    };
+    let dual = NoiseTexture {
+        width: 2,
+        height: 2,
+        texels: (100..108).collect(),
+    };
+    let mut renderer = make_renderer(NoiseConfig::Custom {
+        single: single.clone(),
+        dual: dual.clone(),
+    });
+    for _ in 0..4 {
+        renderer.render();
+    }
+    let mut request = renderer.read_texture(Renderer::BLUE_NOISE_DUAL).unwrap();
+    renderer.render();
+    assert_eq!(
+        request.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        dual.texels
+    );
+    let mut request = renderer.read_texture(Renderer::BLUE_NOISE).unwrap();
+    renderer.render();
+    assert_eq!(
+        request.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        single.texels
+    );
+    finish(renderer);
+}
+
+#[test]
+fn disabled_noise_leaves_the_ids_to_the_host() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer(NoiseConfig::Disabled);
+    assert!(renderer.fetch_texture(Renderer::BLUE_NOISE).is_none());
+    let mip = MipMap {
+        index: 0,
+        width: 1,
+        height: 1,
+        size: 4,
+        offset: 0,
+    };
+    let texture = renderer.gen_texture("host".to_string(), Format::R8G8B8A8_UNORM, &[mip], 4);
+    assert_eq!(texture.index, Renderer::ID_BLUE_NOISE);
+    renderer.free_texture(texture).unwrap();
+    finish(renderer);
+}
@@ -0,0 +1,246 @@
+/*
+ * Offscreen passes of the preview stage of tests/offscreen.json on a headless surface
+ * with validation on. The stage fills whatever it renders into with the alpha cutoff of
+ * the test triangle task as gray, so texels read back tell which pass wrote them.
+ * Validation errors count as failures, layouts the passes get wrong included.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::TaskRejected;
+use rend_vk::format::Format;
+use rend_vk::handle::MeshHandle;
+use rend_vk::offscreen::{OffscreenError, OffscreenPassDesc};
+use rend_vk::pipeline::file::Pipeline;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::texture::Residency;
+
+const PIPELINE: &str = "tests/offscreen.json";
+const SIZE: u32 = 64;
+const PREVIEW_SIZE: u32 = 256;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer(PIPELINE, SIZE, SIZE)
+}
+
+fn fill(gray: u8) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: gray as f32 / 255.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+fn preview(width: u32, height: u32) -> OffscreenPassDesc {
+    OffscreenPassDesc {
+        stage: "preview".to_string(),
+        width,
+        height,
+        format: Format::R8G8B8A8_UNORM,
+    }
+}
+
+fn assert_filled(bytes: &[u8], size: u32, gray: u8) {
+    assert_eq!(bytes.len() as u32, size * size * 4);
+    for (i, texel) in bytes.chunks(4).enumerate() {
+        assert_eq!(
+            texel,
+            [gray, gray, gray, 255],
+            "texel {}, {}",
+            i as u32 % size,
+            i as u32 / size
+        );
+    }
+}
+
+#[test]
+fn errors_name_the_stage_and_the_limit() {
+    let too_large = OffscreenError::TooLarge {
+        width: 8192,
+        height: 16,
+        max: 4096,
+    };
+    assert_eq!(
+        too_large.to_string(),
+        "offscreen passes render at up to 4096x4096, not 8192x16"
+    );
+    let unsupported = OffscreenError::UnsupportedStage {
+        stage: "present".to_string(),
+        reason: "samples attachments",
+    };
+    assert_eq!(
+        unsupported.to_string(),
+        "stage present can't render offscreen, it samples attachments"
+    );
+    let mesh = MeshHandle {
+        index: 3,
+        generation: 1,
+    };
+    assert_eq!(
+        OffscreenError::from(TaskRejected::StaleMesh { mesh }),
+        OffscreenError::Rejected(TaskRejected::StaleMesh { mesh })
+    );
+}
+
+#[test]
+fn preview_stage_draws_into_a_single_output() {
+    let file = std::fs::read_to_string(PIPELINE).unwrap();
+    let pipeline: Pipeline = serde_json::from_str(&file).unwrap();
+    let stage = pipeline
+        .passes
+        .iter()
+        .find(|e| e.name == "preview")
+        .unwrap();
+    let outputs: Vec<_> = stage.outputs.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(outputs, ["preview"]);
+    assert!(stage.inputs.is_empty());
+    assert!(stage.depth_stencil.is_none());
+}
+
+#[test]
+fn renders_the_triangle_into_a_texture() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let texture = renderer
+        .render_to_texture(preview(PREVIEW_SIZE, PREVIEW_SIZE), vec![fill(120)])
+        .unwrap();
+    // Samples the default texture until the frame rendering it is done
+    assert_eq!(
+        renderer.texture_residency(texture).unwrap(),
+        Residency::Uploading
+    );
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(
+        renderer.texture_residency(texture).unwrap(),
+        Residency::Resident
+    );
+    let mut request = renderer.read_texture(texture).unwrap();
+    renderer.render();
+    let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    assert_filled(&bytes, PREVIEW_SIZE, 120);
+    common::finish(renderer);
+}
+
+#[test]
+fn passes_of_the_same_key_reuse_the_texture() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let first = renderer
+        .render_to_texture(preview(PREVIEW_SIZE, PREVIEW_SIZE), vec![fill(40)])
+        .unwrap();
+    renderer.render();
+    renderer.render();
+    let again = renderer
+        .render_to_texture(preview(PREVIEW_SIZE, PREVIEW_SIZE), vec![fill(80)])
+        .unwrap();
+    assert_eq!(again, first);
+    // Keeps what it held until the next pass is done
+    assert_eq!(
+        renderer.texture_residency(again).unwrap(),
+        Residency::Resident
+    );
+    let other = renderer
+        .render_to_texture(preview(128, 128), vec![fill(80)])
+        .unwrap();
+    assert_ne!(other.index, first.index);
+    renderer.render();
+    let mut request = renderer.read_texture(again).unwrap();
+    renderer.render();
+    assert_filled(
+        &request.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        PREVIEW_SIZE,
+        80,
+    );
+
+    // Freed ones go back to the pool, the next pass of their key takes them
+    renderer.free_texture(first).unwrap();
+    assert!(renderer.texture_residency(first).is_err());
+    renderer.render();
+    let pooled = renderer
+        .render_to_texture(preview(PREVIEW_SIZE, PREVIEW_SIZE), vec![fill(160)])
+        .unwrap();
+    assert_eq!(pooled.index, first.index);
+    assert_ne!(pooled.generation, first.generation);
+    assert_eq!(
+        renderer.texture_residency(pooled).unwrap(),
+        Residency::Uploading
+    );
+    renderer.render();
+    renderer.render();
+    let mut request = renderer.read_texture(pooled).unwrap();
+    renderer.render();
+    assert_filled(
+        &request.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        PREVIEW_SIZE,
+        160,
+    );
+    common::finish(renderer);
+}
+
+#[test]
+fn passes_past_the_cap_or_of_other_stages_get_rejected() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let max = renderer.config().max_offscreen_extent;
+    assert_eq!(
+        renderer.render_to_texture(preview(max + 1, 16), vec![fill(40)]),
+        Err(OffscreenError::TooLarge {
+            width: max + 1,
+            height: 16,
+            max
+        })
+    );
+    let unknown = OffscreenPassDesc {
+        stage: "nope".to_string(),
+        ..preview(16, 16)
+    };
+    assert_eq!(
+        renderer.render_to_texture(unknown, vec![fill(40)]),
+        Err(OffscreenError::UnknownStage("nope".to_string()))
+    );
+    let present = OffscreenPassDesc {
+        stage: "present".to_string(),
+        ..preview(16, 16)
+    };
+    assert!(matches!(
+        renderer.render_to_texture(present, vec![fill(40)]),
+        Err(OffscreenError::UnsupportedStage { .. })
+    ));
+    let float = OffscreenPassDesc {
+        format: Format::R16G16B16A16_SFLOAT,
+        ..preview(16, 16)
+    };
+    assert!(matches!(
+        renderer.render_to_texture(float, vec![fill(40)]),
+        Err(OffscreenError::FormatMismatch { .. })
+    ));
+    common::finish(renderer);
+}
+
+#[test]
+fn stage_attachments_stay_untouched() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    for _ in 0..3 {
+        renderer.add_task_to_queue(fill(40));
+        let texture = renderer
+            .render_to_texture(preview(PREVIEW_SIZE, PREVIEW_SIZE), vec![fill(200)])
+            .unwrap();
+        let mut request = renderer.read_attachment("preview").unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        assert_filled(&request.resolve_wait(&renderer, TIMEOUT).unwrap(), SIZE, 40);
+        renderer.render();
+        let mut request = renderer.read_texture(texture).unwrap();
+        renderer.render();
+        assert_filled(
+            &request.resolve_wait(&renderer, TIMEOUT).unwrap(),
+            PREVIEW_SIZE,
+            200,
+        );
+    }
+    common::finish(renderer);
+}
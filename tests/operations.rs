@@ -0,0 +1,259 @@
+/*
+ * Operations on their own, stepped over a fake context instead of a renderer. What the
+ * renderer runs through them needs a device.
+ */
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use rend_vk::events::RenderEvent;
+use rend_vk::operation::{Operation, OperationState, Operations, Step};
+
+// Resources alive, as ids
+#[derive(Default)]
+struct Ctx {
+    alive: HashSet<u32>,
+    next: u32,
+    is_gone: bool,
+}
+
+// Makes a resource per step, done after the given number of them
+struct Synthetic {
+    steps: u32,
+    made: Vec<u32>,
+    is_blocking: bool,
+}
+
+impl Synthetic {
+    fn new(steps: u32) -> Box<Self> {
+        Box::new(Self {
+            steps,
+            made: Vec::new(),
+            is_blocking: true,
+        })
+    }
+}
+
+impl Operation<Ctx> for Synthetic {
+    fn name(&self) -> &str {
+        "synthetic"
+    }
+
+    fn progress(&self) -> f32 {
+        self.made.len() as f32 / self.steps as f32
+    }
+
+    fn step(&mut self, ctx: &mut Ctx) -> Step {
+        if ctx.is_gone {
+            return Step::Abandoned;
+        }
+        if self.made.len() as u32 == self.steps {
+            return Step::Done;
+        }
+        ctx.next += 1;
+        ctx.alive.insert(ctx.next);
+        self.made.push(ctx.next);
+        if self.is_blocking {
+            Step::Blocked
+        } else {
+            Step::Continue
+        }
+    }
+
+    fn unwind(&mut self, ctx: &mut Ctx) {
+        for e in self.made.drain(..) {
+            assert!(ctx.alive.remove(&e), "resource {} released twice", e);
+        }
+    }
+}
+
+const STEPS: u32 = 5;
+
+fn pump(ops: &mut Operations<Ctx>, ctx: &mut Ctx) {
+    ops.pump(ctx, Duration::from_secs(1));
+}
+
+#[test]
+fn completes_with_events_for_every_change() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let handle = ops.start(Synthetic::new(STEPS));
+    assert_eq!(handle.state(), OperationState::Running);
+    // One step per pump since every step blocks, plus one to say it's done
+    for i in 1..=STEPS {
+        pump(&mut ops, &mut ctx);
+        assert_eq!(handle.progress(), i as f32 / STEPS as f32);
+    }
+    assert!(!handle.is_complete());
+    pump(&mut ops, &mut ctx);
+    assert!(handle.is_complete());
+    assert_eq!(handle.progress(), 1.0);
+    assert!(ops.is_empty());
+    // Completed operations keep what they made
+    assert_eq!(ctx.alive.len(), STEPS as usize);
+
+    let events = ops.take_events();
+    let id = handle.id();
+    assert!(matches!(
+        &events[0],
+        RenderEvent::OperationStarted { operation, name } if *operation == id && name == "synthetic"
+    ));
+    let progressed = events
+        .iter()
+        .filter(
+            |e| matches!(e, RenderEvent::OperationProgressed { operation, .. } if *operation == id),
+        )
+        .count();
+    assert_eq!(progressed, STEPS as usize);
+    assert!(matches!(
+        events.last(),
+        Some(RenderEvent::OperationCompleted { operation }) if *operation == id
+    ));
+    assert!(ops.take_events().is_empty());
+}
+
+#[test]
+fn cancelling_at_every_step_leaks_nothing() {
+    for k in 0..=STEPS {
+        let mut ops = Operations::default();
+        let mut ctx = Ctx::default();
+        let handle = ops.start(Synthetic::new(STEPS));
+        for _ in 0..k {
+            pump(&mut ops, &mut ctx);
+        }
+        assert_eq!(ctx.alive.len(), k as usize);
+        handle.cancel();
+        // Only a flag until the next pump
+        assert_eq!(handle.state(), OperationState::Running);
+        pump(&mut ops, &mut ctx);
+        assert_eq!(
+            handle.state(),
+            OperationState::Cancelled,
+            "after {} steps",
+            k
+        );
+        assert!(ctx.alive.is_empty(), "leaked after {} steps", k);
+        assert!(ops.is_empty());
+        let events = ops.take_events();
+        assert!(matches!(
+            events.last(),
+            Some(RenderEvent::OperationCancelled { operation }) if *operation == handle.id()
+        ));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, RenderEvent::OperationCompleted { .. })));
+        // Never stepped again
+        pump(&mut ops, &mut ctx);
+        assert_eq!(ctx.next, k);
+        assert_eq!(handle.state(), OperationState::Cancelled);
+    }
+}
+
+#[test]
+fn cancelling_after_completion_does_nothing() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let mut op = Synthetic::new(STEPS);
+    op.is_blocking = false;
+    let handle = ops.start(op);
+    pump(&mut ops, &mut ctx);
+    assert!(handle.is_complete());
+    handle.cancel();
+    pump(&mut ops, &mut ctx);
+    assert!(handle.is_complete());
+    assert_eq!(ctx.alive.len(), STEPS as usize);
+}
+
+#[test]
+fn abandoned_operations_get_unwound() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let handle = ops.start(Synthetic::new(STEPS));
+    pump(&mut ops, &mut ctx);
+    pump(&mut ops, &mut ctx);
+    ctx.is_gone = true;
+    pump(&mut ops, &mut ctx);
+    assert_eq!(handle.state(), OperationState::Cancelled);
+    assert!(ctx.alive.is_empty());
+}
+
+#[test]
+fn cancel_all_unwinds_everything_running() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let handles: Vec<_> = (0..3).map(|_| ops.start(Synthetic::new(STEPS))).collect();
+    pump(&mut ops, &mut ctx);
+    assert_eq!(ctx.alive.len(), 3);
+    ops.cancel_all(&mut ctx);
+    assert!(ctx.alive.is_empty());
+    assert!(handles
+        .iter()
+        .all(|e| e.state() == OperationState::Cancelled));
+}
+
+#[test]
+fn a_zero_budget_still_takes_a_step() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let mut op = Synthetic::new(STEPS);
+    op.is_blocking = false;
+    let handle = ops.start(op);
+    ops.pump(&mut ctx, Duration::ZERO);
+    assert_eq!(ctx.next, 1);
+    assert_eq!(handle.state(), OperationState::Running);
+    // Enough budget runs it to the end in one go
+    pump(&mut ops, &mut ctx);
+    assert!(handle.is_complete());
+}
+
+#[test]
+fn turns_step_every_operation_once_whatever_the_time() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let mut op = Synthetic::new(2);
+            op.is_blocking = false;
+            ops.start(op)
+        })
+        .collect();
+    for made in [3, 6] {
+        ops.pump_turn(&mut ctx);
+        assert_eq!(ctx.next, made);
+    }
+    // Done on the turn after their last step
+    ops.pump_turn(&mut ctx);
+    assert!(handles.iter().all(|e| e.is_complete()));
+    assert!(ops.is_empty());
+}
+
+#[test]
+fn ids_are_unique() {
+    let mut ops = Operations::<Ctx>::default();
+    let mut others = Operations::<Ctx>::default();
+    let a = ops.start(Synthetic::new(1));
+    let b = others.start(Synthetic::new(1));
+    assert_ne!(a.id(), b.id());
+    ops.append(others);
+    assert_eq!(ops.len(), 2);
+    assert_eq!(ops.take_events().len(), 2);
+}
+
+#[test]
+fn waiting_times_out_or_sees_the_end() {
+    let mut ops = Operations::default();
+    let mut ctx = Ctx::default();
+    let handle = ops.start(Synthetic::new(STEPS));
+    assert_eq!(
+        handle.wait(Duration::from_millis(10)),
+        OperationState::Running
+    );
+    let waiter = {
+        let handle = handle.clone();
+        thread::spawn(move || handle.wait(Duration::from_secs(30)))
+    };
+    while !handle.state().is_terminal() {
+        pump(&mut ops, &mut ctx);
+    }
+    assert_eq!(waiter.join().unwrap(), OperationState::Completed);
+}
@@ -0,0 +1,53 @@
+/*
+ * Renderers whose pipeline fails to load, on a headless surface with validation on. The
+ * object tracker of the validation layer reports every object still alive when the device
+ * and the instance get destroyed, so no errors means nothing made before the panic leaked.
+ */
+
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::render_core::RenderCore;
+use rend_vk::renderer::Renderer;
+
+fn make_core() -> Arc<RenderCore> {
+    let core = common::make_core(&RendererConfig::default());
+    common::reset_validation_errors();
+    core
+}
+
+fn make_renderer(core: Arc<RenderCore>) -> std::thread::Result<Renderer> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        Renderer::with_core(
+            core,
+            RendererConfig::default(),
+            "tests/missing_pipeline.json",
+            false,
+            common::headless_surface,
+        )
+    }))
+}
+
+#[test]
+fn failed_renderers_leave_shared_cores_clean() {
+    let _serial = common::serial();
+    let core = make_core();
+    assert!(make_renderer(core.clone()).is_err());
+    // The one handed over is gone, the core is still up
+    assert_eq!(Arc::strong_count(&core), 1);
+    RenderCore::destroy(core);
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn failed_renderers_take_their_own_core_down() {
+    let _serial = common::serial();
+    let core = make_core();
+    let weak = Arc::downgrade(&core);
+    assert!(make_renderer(core).is_err());
+    assert!(weak.upgrade().is_none());
+    assert_eq!(common::validation_errors(), 0);
+}
@@ -0,0 +1,144 @@
+/*
+ * Per draw layouts of made up passes, locking the generated GLSL so layout changes show
+ * up here before they show up as garbage on screen. No GPU involved.
+ */
+use rend_vk::pipeline::file::{PerDrawField, Pipeline};
+use rend_vk::pipeline::per_draw::{Condition, PerDrawLayout, LAYOUT_VERSION};
+use rend_vk::shader_resource::ResourceKind;
+
+fn layouts_of(passes: &str) -> Vec<PerDrawLayout> {
+    let json = format!(
+        r#"{{ "targets": [], "programs": [], "passes": {} }}"#,
+        passes
+    );
+    let pipeline: Pipeline = serde_json::from_str(&json).unwrap();
+    pipeline.passes.iter().map(PerDrawLayout::of_pass).collect()
+}
+
+fn header(stage: &str) -> String {
+    format!(
+        "// Per draw layout of stage {}, generated by Renderer::per_draw_glsl_struct, don't edit\n\
+         #define PER_DRAW_LAYOUT_VERSION {}\n\
+         layout(scalar, push_constant) uniform Registers\n{{\n",
+        stage, LAYOUT_VERSION
+    )
+}
+
+#[test]
+fn mesh_stage_without_fields() {
+    let layouts = layouts_of(
+        r#"[{
+            "name": "gbuffer",
+            "batch": "MESH_STATIC",
+            "perPassUpdaters": [],
+            "perInstanceUpdaters": ["TRANSFORM", "MATERIAL", "TRANSFORM_EXTRA"]
+        }]"#,
+    );
+    assert_eq!(
+        layouts[0].glsl_struct("gbuffer"),
+        header("gbuffer")
+            + "  Positions positions;\n  Normals normals;\n  TexCoords texCoords;\n  \
+               Transforms transforms;\n  Materials materials;\n  \
+               TransformExtras transformExtras;\n}\nregisters;\n"
+    );
+    assert_eq!(layouts[0].size(), 48);
+}
+
+#[test]
+fn fields_follow_every_address() {
+    let layouts = layouts_of(
+        r#"[{
+            "name": "shadow",
+            "batch": "MESH_STATIC",
+            "perPassUpdaters": ["VIEW_RAY"],
+            "perInstanceUpdaters": ["TRANSFORM"],
+            "perDrawFields": ["previousTransform", "albedoTexture", "alphaCutoff", "flags"],
+            "constants": [{ "name": "bias", "type": "float", "default": 0.01 }]
+        }]"#,
+    );
+    let layout = &layouts[0];
+    assert_eq!(
+        layout.glsl_struct("shadow"),
+        header("shadow")
+            + "  PassData pass;\n  StageConstants constants;\n  Positions positions;\n  \
+               Normals normals;\n  TexCoords texCoords;\n  Transforms transforms;\n  \
+               TransformExtras previousTransform;\n  uint albedoTexture;\n  \
+               uint albedoSampler;\n  float alphaCutoff;\n  uint flags;\n}\nregisters;\n"
+    );
+    let sampler = layout.member("albedoSampler").unwrap();
+    assert_eq!(sampler.offset, 60);
+    assert_eq!(
+        sampler.condition,
+        Condition::Field(PerDrawField::AlbedoTexture)
+    );
+    assert_eq!(layout.size(), 72);
+}
+
+#[test]
+fn fullscreen_stages_skip_the_vertex_addresses() {
+    let layouts = layouts_of(
+        r#"[{
+            "name": "copy",
+            "batch": "FULLSCREEN",
+            "perPassUpdaters": ["VIEW_RAY", "FRUSTUM"],
+            "perInstanceUpdaters": ["DIR_LIGHT"],
+            "perDrawFields": ["viewDepth"]
+        }]"#,
+    );
+    assert_eq!(
+        layouts[0].glsl_struct("copy"),
+        header("copy")
+            + "  PassData pass;\n  DirLights dirLights;\n  float viewDepth;\n}\nregisters;\n"
+    );
+}
+
+#[test]
+fn quantized_stages_read_quantized_addresses() {
+    let layouts = layouts_of(
+        r#"[{
+            "name": "gbuffer",
+            "batch": "MESH_STATIC",
+            "perPassUpdaters": [],
+            "perInstanceUpdaters": ["SKY"],
+            "vertexFormats": {
+                "position": ["R16G16B16A16_SFLOAT"],
+                "normal": ["A2B10G10R10_SNORM_PACK32", "R32G32B32_SFLOAT"],
+                "texCoord": ["R32G32_SFLOAT", "R16G16_UNORM"]
+            }
+        }]"#,
+    );
+    assert_eq!(
+        layouts[0].glsl_struct("gbuffer"),
+        header("gbuffer")
+            + "  PositionsF16 positions;\n  NormalsSnorm10 normals;\n  TexCoords texCoords;\n  \
+               uint64_t skies;\n}\nregisters;\n"
+    );
+    assert_eq!(
+        layouts[0].members()[3].condition,
+        Condition::PerInstance(ResourceKind::Sky)
+    );
+}
+
+#[test]
+fn tables_list_every_member() {
+    let layouts = layouts_of(
+        r#"[{
+            "name": "translucent",
+            "batch": "TRANSLUCENT",
+            "perPassUpdaters": [],
+            "perInstanceUpdaters": ["TRANSFORM"],
+            "perDrawFields": ["viewDepth"]
+        }]"#,
+    );
+    let table = layouts[0].markdown_table();
+    let rows: Vec<_> = table.lines().collect();
+    assert_eq!(rows.len(), 2 + 5);
+    assert_eq!(
+        rows[2],
+        "| 0 | 8 | `positions` | `Positions` | batch isn't FULLSCREEN |"
+    );
+    assert_eq!(
+        rows[6],
+        "| 32 | 4 | `viewDepth` | `float` | perDrawFields lists viewDepth |"
+    );
+}
@@ -0,0 +1,167 @@
+/*
+ * Pipeline files over the corpus in tests/pipeline_files: migrations from older versions,
+ * the errors files not matching the schema get and the optimization hints of a file,
+ * snapshotted next to it. Nothing gets compiled or loaded.
+ */
+use std::path::Path;
+
+use rend_vk::pipeline::file::{
+    migrate_to_latest, FileError, MigrationError, Pipeline, SchemaError, PIPELINE_VERSION,
+};
+
+const DIR: &str = "tests/pipeline_files";
+
+fn read(name: &str) -> String {
+    std::fs::read_to_string(Path::new(DIR).join(name)).unwrap()
+}
+
+fn parse(name: &str) -> Result<Pipeline, FileError> {
+    Pipeline::parse(&read(name), Path::new(DIR))
+}
+
+fn schema_error(name: &str) -> SchemaError {
+    match parse(name) {
+        Err(FileError::Schema(e)) => e,
+        Err(e) => panic!("{} failed on something else: {}", name, e),
+        Ok(_) => panic!("{} parsed", name),
+    }
+}
+
+#[test]
+fn older_files_get_migrated() {
+    let pipeline = parse("v1.json").unwrap();
+    assert_eq!(pipeline.version, PIPELINE_VERSION);
+    let usage: Vec<_> = pipeline
+        .targets
+        .iter()
+        .map(|e| {
+            e.extra_usage
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    // Memoryless targets never had the usage to begin with
+    assert_eq!(
+        usage,
+        [vec!["transferSrc", "sampled", "transferDst"], vec![]]
+    );
+}
+
+#[test]
+fn migrated_files_keep_their_layout() {
+    assert_eq!(
+        migrate_to_latest(&read("v1.json")).unwrap(),
+        read("v1_migrated.json")
+    );
+    // Already there
+    assert_eq!(
+        migrate_to_latest(&read("v2.json")).unwrap(),
+        read("v2.json")
+    );
+}
+
+#[test]
+fn newer_files_are_rejected() {
+    assert_eq!(
+        migrate_to_latest(&read("newer.json")),
+        Err(MigrationError::Unsupported {
+            version: 3,
+            latest: PIPELINE_VERSION
+        })
+    );
+    assert_eq!(
+        migrate_to_latest(&read("invalid_version.json")),
+        Err(MigrationError::InvalidVersion("\"2\"".to_string()))
+    );
+    assert_eq!(migrate_to_latest("[]"), Err(MigrationError::NotAnObject));
+}
+
+#[test]
+fn syntax_errors_have_a_position() {
+    match parse("syntax.json") {
+        Err(FileError::Migration(MigrationError::Syntax(e))) => {
+            assert!(e.contains("line"), "{}", e)
+        }
+        _ => panic!("syntax.json parsed"),
+    }
+}
+
+#[test]
+fn misspelled_pass_fields_name_the_pass() {
+    let e = schema_error("pass_typo.json");
+    assert_eq!(e.path, "passes[1].depthWite");
+    assert_eq!(e.name.as_deref(), Some("present"));
+    assert!(e.message.starts_with("unknown field `depthWite`"), "{}", e);
+    assert!(e.to_string().starts_with("passes[1].depthWite (present): "));
+}
+
+#[test]
+fn misspelled_nested_fields_lead_to_them() {
+    let e = schema_error("state_typo.json");
+    assert_eq!(e.path, "passes[0].state.triangle.cullFase");
+    assert_eq!(e.name.as_deref(), Some("ui"));
+}
+
+#[test]
+fn wrong_types_lead_to_the_field() {
+    let e = schema_error("target_type.json");
+    assert_eq!(e.path, "targets[0].width");
+    assert_eq!(e.name.as_deref(), Some("ui"));
+}
+
+#[test]
+fn unknown_top_level_keys_are_rejected() {
+    let e = schema_error("unknown_key.json");
+    assert_eq!(e.path, "passess");
+    assert_eq!(e.name, None);
+    assert!(e.message.starts_with("unknown field `passess`"), "{}", e);
+}
+
+#[test]
+fn misspelled_output_fields_are_rejected() {
+    let e = schema_error("output_typo.json");
+    assert_eq!(e.path, "passes[0].outputs[0].clearColour");
+    assert_eq!(e.name.as_deref(), Some("ui"));
+}
+
+// One hint per line, as printed
+fn hints_of(name: &str) -> String {
+    parse(name)
+        .unwrap()
+        .optimization_hints()
+        .iter()
+        .map(|e| format!("{}\n", e))
+        .collect()
+}
+
+#[test]
+fn optimization_hints_match_their_snapshot() {
+    assert_eq!(hints_of("store_hints.json"), read("store_hints.txt"));
+}
+
+#[test]
+fn stored_attachments_read_afterwards_give_no_hints() {
+    assert_eq!(hints_of("v2.json"), "");
+}
+
+#[test]
+fn repo_pipelines_are_at_the_latest_version() {
+    let files = ["pipeline.json"].into_iter().map(String::from).chain(
+        ["examples", "tests"]
+            .into_iter()
+            .flat_map(|dir| std::fs::read_dir(dir).unwrap())
+            .map(|e| e.unwrap().path())
+            .filter(|e| e.extension().is_some_and(|e| e == "json"))
+            .map(|e| e.to_string_lossy().into_owned()),
+    );
+    for file in files {
+        let json = std::fs::read_to_string(&file).unwrap();
+        let migrated = migrate_to_latest(&json).unwrap();
+        assert_eq!(migrated.trim_end(), json.trim_end(), "{}", file);
+        let base_dir = Path::new(&file).parent().unwrap();
+        if let Err(e) = Pipeline::parse(&json, base_dir) {
+            panic!("{}: {}", file, e);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * Frames rendered in two halves with prepare_frame and submit_frame, on a headless
+ * surface with validation on. Tasks queued between the halves count into the next
+ * frame, wherever they come from.
+ */
+
+mod common;
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+fn fill() -> RenderTask {
+    common::task(TaskKind::Fullscreen)
+}
+
+fn accepted(renderer: &Renderer) -> u32 {
+    renderer.frame_stats().accepted_by_kind[TaskKind::Fullscreen.to_usize()]
+}
+
+#[test]
+fn tasks_queued_while_a_frame_is_pending_go_to_the_next_one() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    renderer.add_task_to_queue(fill());
+    let frame = renderer.prepare_frame().unwrap();
+
+    let sender = renderer.task_sender();
+    std::thread::spawn(move || sender.send_all([fill(), fill()]))
+        .join()
+        .unwrap();
+    renderer.add_task_to_queue(fill());
+    // Prepared frames can be submitted from another thread
+    let frame = std::thread::spawn(move || frame).join().unwrap();
+    assert_eq!(renderer.submit_frame(frame), FrameOutcome::Submitted);
+    assert_eq!(accepted(&renderer), 1);
+
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(accepted(&renderer), 3);
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(accepted(&renderer), 0);
+    common::finish(renderer);
+}
+
+#[test]
+#[should_panic(expected = "was prepared but never submitted!")]
+fn frames_cant_be_prepared_twice() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    let _frame = renderer.prepare_frame().unwrap();
+    let _ = renderer.prepare_frame();
+}
@@ -0,0 +1,75 @@
+/*
+ * Missed vsyncs out of made up display timings, no GPU involved.
+ */
+use ash::vk;
+
+use rend_vk::present_timing::DisplayTiming;
+
+const REFRESH: u64 = 16_000_000;
+
+fn past(present_id: u32, desired: u64, actual: u64) -> vk::PastPresentationTimingGOOGLE {
+    vk::PastPresentationTimingGOOGLE {
+        present_id,
+        desired_present_time: desired,
+        actual_present_time: actual,
+        earliest_present_time: actual,
+        present_margin: 0,
+    }
+}
+
+#[test]
+fn frames_late_after_the_last_one_miss_a_vsync() {
+    let mut timing = DisplayTiming::new(4);
+    timing.set_refresh_duration(Some(REFRESH));
+    let actual = [0, 1, 2, 4, 5].map(|e| 1_000_000_000 + e * REFRESH);
+    for (i, &time) in actual.iter().enumerate() {
+        timing.record(past(i as u32 + 1, 0, time));
+    }
+    assert_eq!(timing.missed_vsyncs(), 1);
+    let missed: Vec<_> = timing.history().iter().map(|e| e.is_missed_vsync).collect();
+    // The first one fell out of the window
+    assert_eq!(missed, [false, false, true, false]);
+    assert_eq!(timing.history()[0].present_id, 2);
+}
+
+#[test]
+fn frames_late_for_their_target_miss_a_vsync() {
+    let mut timing = DisplayTiming::new(8);
+    timing.set_refresh_duration(Some(REFRESH));
+    timing.record(past(1, 0, REFRESH));
+    assert!(
+        timing
+            .record(past(2, 2 * REFRESH, 3 * REFRESH))
+            .is_missed_vsync
+    );
+    // Within half a refresh of the target is on time
+    assert!(
+        !timing
+            .record(past(3, 4 * REFRESH, 4 * REFRESH))
+            .is_missed_vsync
+    );
+    assert_eq!(timing.missed_vsyncs(), 1);
+}
+
+#[test]
+fn nothing_is_missed_before_the_refresh_cycle_is_known() {
+    let mut timing = DisplayTiming::new(8);
+    timing.record(past(1, 0, 0));
+    timing.record(past(2, 0, 10 * REFRESH));
+    assert_eq!(timing.missed_vsyncs(), 0);
+    // Nor across a swapchain rebuild, the frame before was presented to the old one
+    timing.restart();
+    timing.set_refresh_duration(Some(REFRESH));
+    timing.record(past(3, 0, 20 * REFRESH));
+    assert_eq!(timing.missed_vsyncs(), 0);
+}
+
+#[test]
+fn target_times_go_with_the_next_present_only() {
+    let mut timing = DisplayTiming::new(8);
+    timing.set_target_present_time(123);
+    let first = timing.next_present();
+    let second = timing.next_present();
+    assert_eq!((first.present_id, first.desired_present_time), (1, 123));
+    assert_eq!((second.present_id, second.desired_present_time), (2, 0));
+}
@@ -0,0 +1,92 @@
+/*
+ * Publication slots hammered from several threads at once, three producers and the
+ * consumer. Every published value is a producer id and a counter repeated over the
+ * whole buffer, so a torn read shows up as a value that isn't uniform. The exhaustive
+ * interleavings are in publisher_loom.rs.
+ */
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use rend_vk::publisher::ResourceConsumer;
+use rend_vk::shader_resource::ResourceKind;
+
+const PRODUCERS: usize = 3;
+const PUBLISHES: u32 = 100_000;
+// Words per value, big enough for a copy to be preempted halfway through
+const WORDS: usize = 256;
+
+fn value_of(producer: u32, counter: u32) -> Vec<u8> {
+    let word = (producer << 24 | counter).to_ne_bytes();
+    word.repeat(WORDS)
+}
+
+#[test]
+fn values_never_tear_and_stay_in_order() {
+    let mut consumer = ResourceConsumer::new(ResourceKind::Transform, WORDS * 4);
+    let finished = Arc::new(AtomicUsize::new(0));
+    let producers: Vec<_> = (0..PRODUCERS as u32)
+        .map(|producer| {
+            let mut publisher = consumer.publisher();
+            let finished = finished.clone();
+            std::thread::spawn(move || {
+                for counter in 1..=PUBLISHES {
+                    publisher.publish(&value_of(producer, counter));
+                }
+                finished.fetch_add(1, Ordering::Release);
+            })
+        })
+        .collect();
+
+    // Last counter taken of each producer, publishes of one producer are ordered
+    let mut last = [0u32; PRODUCERS];
+    let mut taken = 0;
+    let mut check = |bytes: &[u8]| {
+        assert_eq!(bytes.len(), WORDS * 4);
+        let first = &bytes[..4];
+        assert!(bytes.chunks(4).all(|e| e == first), "torn value");
+        let word = u32::from_ne_bytes(first.try_into().unwrap());
+        let (producer, counter) = ((word >> 24) as usize, word & 0xff_ffff);
+        assert!(
+            counter > last[producer],
+            "value older than one already taken"
+        );
+        last[producer] = counter;
+    };
+    while finished.load(Ordering::Acquire) < PRODUCERS {
+        if let Some(bytes) = consumer.take_latest() {
+            check(bytes);
+            taken += 1;
+        }
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    // Whatever got published last is still there to take, then nothing is
+    if let Some(bytes) = consumer.take_latest() {
+        check(bytes);
+        taken += 1;
+    }
+    assert!(consumer.take_latest().is_none());
+    assert!(taken > 0);
+    // The value left in the slot is the last one of some producer
+    assert!(last.contains(&PUBLISHES));
+}
+
+#[test]
+fn publishers_dropped_before_the_consumer_leave_the_value() {
+    let mut consumer = ResourceConsumer::new(ResourceKind::Transform, 8);
+    let threads: Vec<_> = (0..4u8)
+        .map(|i| {
+            let mut publisher = consumer.publisher();
+            std::thread::spawn(move || publisher.publish(&[i; 8]))
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let bytes = consumer.take_latest().unwrap();
+    assert!(bytes.iter().all(|e| *e == bytes[0]) && bytes[0] < 4);
+    assert!(consumer.take_latest().is_none());
+}
@@ -0,0 +1,68 @@
+/*
+ * Every interleaving of the slot swaps, two producers and the consumer. Only built with
+ * loom, which stands in for the std atomics in the publisher:
+ *
+ *     RUSTFLAGS="--cfg loom" cargo test --release --test publisher_loom
+ */
+#![cfg(loom)]
+
+use rend_vk::publisher::ResourceConsumer;
+use rend_vk::shader_resource::ResourceKind;
+
+const SIZE: usize = 4;
+
+#[test]
+fn swaps_never_hand_out_a_buffer_someone_else_owns() {
+    loom::model(|| {
+        let mut consumer = ResourceConsumer::new(ResourceKind::Transform, SIZE);
+        let producers: Vec<_> = (1..=2u8)
+            .map(|producer| {
+                let mut publisher = consumer.publisher();
+                loom::thread::spawn(move || {
+                    publisher.publish(&[producer; SIZE]);
+                    publisher.publish(&[producer | 0x10; SIZE]);
+                })
+            })
+            .collect();
+
+        // Last value taken of each producer, its second value never precedes its first
+        let mut last = [0u8; 3];
+        let mut check = |bytes: &[u8]| {
+            assert!(bytes.iter().all(|e| *e == bytes[0]), "torn value");
+            let producer = (bytes[0] & 0xf) as usize;
+            assert!(
+                bytes[0] > last[producer],
+                "value older than one already taken"
+            );
+            last[producer] = bytes[0];
+        };
+        if let Some(bytes) = consumer.take_latest() {
+            check(bytes);
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let bytes = consumer.take_latest().map(|e| e.to_vec());
+        match bytes {
+            Some(bytes) => {
+                check(&bytes);
+                assert_eq!(bytes[0] & 0x10, 0x10, "the last value isn't a second one");
+            }
+            // Taken before the producers finished, only possible if it was the last value
+            None => assert!(last.iter().any(|e| e & 0x10 != 0)),
+        }
+        assert!(consumer.take_latest().is_none());
+    });
+}
+
+#[test]
+fn dropping_in_any_order_frees_every_buffer() {
+    loom::model(|| {
+        let mut consumer = ResourceConsumer::new(ResourceKind::Transform, SIZE);
+        let mut publisher = consumer.publisher();
+        let producer = loom::thread::spawn(move || publisher.publish(&[1; SIZE]));
+        let _ = consumer.take_latest();
+        drop(consumer);
+        producer.join().unwrap();
+    });
+}
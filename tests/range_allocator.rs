@@ -0,0 +1,33 @@
+/*
+ * Free range bookkeeping shared by the device allocator and the image pool.
+ */
+use rend_vk::range_allocator::RangeAllocator;
+
+#[test]
+fn allocations_are_first_fit_and_aligned() {
+    let mut ranges = RangeAllocator::new(1024);
+    assert_eq!(ranges.alloc(10, 1), Some(0));
+    assert_eq!(ranges.alloc(100, 64), Some(64));
+    // The alignment gap stays free for later allocations that fit
+    assert_eq!(ranges.free_ranges(), [[10, 54], [164, 860]]);
+    assert_eq!(ranges.alloc(50, 2), Some(10));
+    assert_eq!(ranges.alloc(2000, 1), None);
+    assert_eq!(ranges.available(), 1024 - 160);
+}
+
+#[test]
+fn freed_ranges_coalesce_with_both_neighbours() {
+    let mut ranges = RangeAllocator::new(300);
+    let offsets: Vec<_> = (0..3).map(|_| ranges.alloc(100, 1).unwrap()).collect();
+    assert_eq!(offsets, [0, 100, 200]);
+    assert_eq!(ranges.free_range_count(), 0);
+    ranges.free(0, 100);
+    ranges.free(200, 100);
+    assert_eq!(ranges.free_ranges(), [[0, 100], [200, 100]]);
+    // Doesn't fit in either of the two apart
+    assert_eq!(ranges.alloc(150, 1), None);
+    ranges.free(100, 100);
+    assert_eq!(ranges.free_ranges(), [[0, 300]]);
+    assert!(ranges.is_unused());
+    assert_eq!(ranges.largest_free(), 300);
+}
@@ -0,0 +1,151 @@
+/*
+ * Reads buffers back from the GPU on a headless surface with validation on. No stage
+ * writes storage buffers yet, so the data comes from chunked mesh uploads: the GPU
+ * copies them into the vertex buffers the stages pull their vertices from. Validation
+ * errors logged by the object tracker on destroy count as leaks.
+ */
+
+mod common;
+
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::Duration,
+};
+
+use rend_vk::handle::MeshHandle;
+use rend_vk::mesh_upload::MeshAttribute;
+use rend_vk::readback::ReadbackBusy;
+use rend_vk::renderer::Renderer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer(readback_bytes: u64) -> Renderer {
+    let mut renderer = common::make_renderer("pipeline.json", 64, 64);
+    let mut config = renderer.config().clone();
+    config.readback_bytes = readback_bytes;
+    renderer.set_config(config);
+    renderer
+}
+
+use common::finish_without_leaks as finish;
+
+/*
+ * Mesh with its vertices written by the GPU, returns what they hold.
+ */
+fn uploaded_mesh(renderer: &mut Renderer) -> (MeshHandle, Vec<u8>) {
+    let data: Vec<u8> = (0..1024)
+        .map(|e| e as f32)
+        .flat_map(|e| e.to_ne_bytes())
+        .collect();
+    let mesh = renderer.gen_mesh(data.len() as u32, 0, 0, 0, 3);
+    let mut cursor = renderer
+        .begin_mesh_upload(mesh, MeshAttribute::Vertices)
+        .unwrap();
+    assert_eq!(cursor.write(&data), data.len());
+    cursor.finish();
+    for _ in 0..4 {
+        renderer.render();
+    }
+    assert!(renderer.is_upload_complete(mesh).unwrap());
+    (mesh, data)
+}
+
+#[test]
+fn reads_back_what_the_gpu_wrote() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer(64 * 1024);
+    let (mesh, data) = uploaded_mesh(&mut renderer);
+    let vertices = renderer.fetch_mesh(mesh).unwrap().vertices;
+
+    let mut whole = renderer
+        .read_buffer(&vertices, 0..data.len() as u64)
+        .unwrap();
+    let mut middle = renderer.read_buffer(&vertices, 100..300).unwrap();
+    assert_eq!(whole.frame(), middle.frame());
+    // Nothing recorded the copies yet
+    assert_eq!(whole.try_resolve(&renderer), None);
+    renderer.render();
+
+    assert_eq!(
+        middle.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        data[100..300]
+    );
+    assert!(middle.is_resolved());
+    let bytes = loop {
+        if let Some(e) = whole.try_resolve(&renderer) {
+            break e;
+        }
+        std::thread::yield_now();
+    };
+    assert_eq!(bytes, data);
+
+    renderer.free_mesh(mesh).unwrap();
+    renderer.render();
+    finish(renderer);
+}
+
+#[test]
+fn outstanding_requests_share_the_buffer() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer(4096);
+    let (mesh, data) = uploaded_mesh(&mut renderer);
+    let vertices = renderer.fetch_mesh(mesh).unwrap().vertices;
+
+    let mut requests = Vec::new();
+    let busy = loop {
+        match renderer.read_buffer(&vertices, 0..1024) {
+            Ok(e) => requests.push(e),
+            Err(e) => break e,
+        }
+    };
+    assert!(!requests.is_empty());
+    assert_eq!(
+        busy,
+        ReadbackBusy {
+            size: 1024,
+            available: busy.available
+        }
+    );
+    assert!(busy.available < 1024);
+    renderer.render();
+    let mut last = requests.pop().unwrap();
+    assert_eq!(last.resolve_wait(&renderer, TIMEOUT).unwrap(), data[..1024]);
+    // Its room is free again
+    let mut next = renderer.read_buffer(&vertices, 1024..2048).unwrap();
+
+    // Dropped unresolved, freed once their frame is done
+    drop(requests);
+    renderer.render();
+    assert_eq!(
+        next.resolve_wait(&renderer, TIMEOUT).unwrap(),
+        data[1024..2048]
+    );
+    renderer.render();
+    assert_eq!(
+        renderer.memory_report().readback.unwrap().available,
+        renderer.memory_report().readback.unwrap().size
+    );
+
+    renderer.free_mesh(mesh).unwrap();
+    renderer.render();
+    finish(renderer);
+}
+
+#[test]
+fn ranges_past_the_slice_are_rejected() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer(4096);
+    let mesh = renderer.gen_mesh(256, 0, 0, 0, 3);
+    let vertices = renderer.fetch_mesh(mesh).unwrap().vertices;
+    for range in [0..257, 256..256, 300..400] {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            renderer.read_buffer(&vertices, range.clone())
+        }));
+        assert!(result.is_err(), "{:?} accepted", range);
+    }
+    let request = renderer.read_buffer(&vertices, 0..256).unwrap();
+    drop(request);
+    renderer.free_mesh(mesh).unwrap();
+    renderer.render();
+    finish(renderer);
+}
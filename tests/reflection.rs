@@ -0,0 +1,208 @@
+/*
+ * Host structs checked against the blocks reflected from spirv_layouts.spv, the std140
+ * Lights block described in spirv_layouts.spvasm. Runs without a device.
+ */
+use std::mem::{offset_of, size_of};
+
+use rend_vk::reflection::{BlockLayout, HostMember, LayoutMismatch, ShaderReflection};
+use rend_vk::shader_resource::KnownLayout;
+
+const LAYOUTS: &[u8] = include_bytes!("spirv_layouts.spv");
+
+// Same as the block, padding spelled out
+#[repr(C)]
+struct Lights {
+    exposure: f32,
+    _pad0: [f32; 3],
+    direction: [f32; 3],
+    _pad1: f32,
+    weights: [[f32; 4]; 2],
+    view: [[f32; 4]; 4],
+    light: [f32; 4],
+}
+
+// Tightly packed, what forgetting the std140 padding gives
+#[repr(C)]
+struct PackedLights {
+    exposure: f32,
+    direction: [f32; 3],
+    weights: [f32; 2],
+    view: [[f32; 4]; 4],
+    light: [f32; 4],
+}
+
+// First two members swapped, both take a vec4 slot so nothing else moves
+#[repr(C)]
+struct ReorderedLights {
+    direction: [f32; 3],
+    _pad1: f32,
+    exposure: f32,
+    _pad0: [f32; 3],
+    weights: [[f32; 4]; 2],
+    view: [[f32; 4]; 4],
+    light: [f32; 4],
+}
+
+// Members as the block, one vec4 of trailing padding too many
+#[repr(C)]
+struct PaddedLights {
+    lights: Lights,
+    _pad: [f32; 4],
+}
+
+macro_rules! member {
+    ($t:ty, $field:ident, $size:expr) => {
+        HostMember {
+            name: stringify!($field),
+            offset: offset_of!($t, $field) as u32,
+            size: $size as u32,
+        }
+    };
+}
+
+impl KnownLayout for Lights {
+    fn members() -> Vec<HostMember> {
+        vec![
+            member!(Lights, exposure, 4),
+            member!(Lights, direction, 12),
+            member!(Lights, weights, 32),
+            member!(Lights, view, 64),
+            member!(Lights, light, 16),
+        ]
+    }
+}
+
+impl KnownLayout for PackedLights {
+    fn members() -> Vec<HostMember> {
+        vec![
+            member!(PackedLights, exposure, 4),
+            member!(PackedLights, direction, 12),
+            member!(PackedLights, weights, 8),
+            member!(PackedLights, view, 64),
+            member!(PackedLights, light, 16),
+        ]
+    }
+}
+
+impl KnownLayout for ReorderedLights {
+    fn members() -> Vec<HostMember> {
+        vec![
+            member!(ReorderedLights, direction, 12),
+            member!(ReorderedLights, exposure, 4),
+            member!(ReorderedLights, weights, 32),
+            member!(ReorderedLights, view, 64),
+            member!(ReorderedLights, light, 16),
+        ]
+    }
+}
+
+impl KnownLayout for PaddedLights {
+    fn members() -> Vec<HostMember> {
+        Lights::members()
+    }
+}
+
+fn lights_block() -> BlockLayout {
+    let words: Vec<u32> = LAYOUTS
+        .chunks_exact(4)
+        .map(|e| u32::from_ne_bytes(e.try_into().unwrap()))
+        .collect();
+    ShaderReflection::of(&words)
+        .block("Lights")
+        .unwrap()
+        .clone()
+}
+
+fn check<T: KnownLayout>(block: &BlockLayout) -> Result<(), LayoutMismatch> {
+    block.compare("lighting", size_of::<T>() as u32, &T::members())
+}
+
+// Index, shader and host member names, expected and provided offset and size
+fn members_of(mismatch: &LayoutMismatch) -> Vec<(usize, &str, &str, u32, u32, u32, u32)> {
+    mismatch
+        .members
+        .iter()
+        .map(|e| {
+            (
+                e.index,
+                e.shader_name.as_str(),
+                e.host_name.as_str(),
+                e.expected_offset,
+                e.provided_offset,
+                e.expected_size,
+                e.provided_size,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn matching_structs_pass() {
+    let block = lights_block();
+    assert_eq!(block.size, 144);
+    assert_eq!(size_of::<Lights>(), 144);
+    check::<Lights>(&block).unwrap();
+}
+
+#[test]
+fn packed_structs_report_every_shifted_member() {
+    let mismatch = check::<PackedLights>(&lights_block()).unwrap_err();
+    assert_eq!(mismatch.stage, "lighting");
+    assert_eq!(mismatch.block, "Lights");
+    assert_eq!((mismatch.expected_size, mismatch.provided_size), (144, 104));
+    assert_eq!(
+        members_of(&mismatch),
+        [
+            (1, "direction", "direction", 16, 4, 12, 12),
+            (2, "weights", "weights", 32, 16, 32, 8),
+            (3, "view", "view", 64, 24, 64, 64),
+            (4, "light", "light", 128, 88, 16, 16),
+        ]
+    );
+    assert_eq!(
+        mismatch.to_string(),
+        "stage lighting declares Lights with 144 bytes, host provides 104 bytes\
+         ; member 1 (direction/direction) expected offset 16 size 12, got offset 4 size 12\
+         ; member 2 (weights/weights) expected offset 32 size 32, got offset 16 size 8\
+         ; member 3 (view/view) expected offset 64 size 64, got offset 24 size 64\
+         ; member 4 (light/light) expected offset 128 size 16, got offset 88 size 16"
+    );
+}
+
+#[test]
+fn reordered_structs_are_matched_by_declaration_order() {
+    let mismatch = check::<ReorderedLights>(&lights_block()).unwrap_err();
+    // Same size, only the order gives it away
+    assert_eq!((mismatch.expected_size, mismatch.provided_size), (144, 144));
+    assert_eq!(
+        members_of(&mismatch),
+        [
+            (0, "exposure", "direction", 0, 0, 4, 12),
+            (1, "direction", "exposure", 16, 16, 12, 4),
+        ]
+    );
+}
+
+#[test]
+fn trailing_padding_is_a_size_mismatch() {
+    let mismatch = check::<PaddedLights>(&lights_block()).unwrap_err();
+    assert!(mismatch.members.is_empty());
+    assert_eq!((mismatch.expected_size, mismatch.provided_size), (144, 160));
+}
+
+#[test]
+fn members_missing_on_either_side_show_up_empty() {
+    let block = lights_block();
+    let members = Lights::members();
+    let mismatch = block.compare("lighting", 128, &members[..4]).unwrap_err();
+    assert_eq!(members_of(&mismatch), [(4, "light", "", 128, 0, 16, 0)]);
+
+    let mut extra = members.clone();
+    extra.push(HostMember {
+        name: "intensity",
+        offset: 144,
+        size: 4,
+    });
+    let mismatch = block.compare("lighting", 148, &extra).unwrap_err();
+    assert_eq!(members_of(&mismatch), [(5, "", "intensity", 0, 144, 0, 4)]);
+}
@@ -0,0 +1,174 @@
+/*
+ * Reimporting a texture under the same id on a headless surface with validation on.
+ * Tasks sample it through the gbuffer stage every frame, the albedo read back must show
+ * either the contents it had or the final ones, never the default texture or a reimport
+ * dropped in between.
+ */
+
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use glam::Mat4;
+
+use rend_vk::format::Format;
+use rend_vk::handle::{ResourceMeta, TextureHandle};
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::shader_resource::{Material, MultiResource, ResourceKind, Transform, TransformExtra};
+use rend_vk::texture::{MipMap, ReimportError, Residency};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+fn mip_maps(size: u32) -> [MipMap; 1] {
+    [MipMap {
+        index: 0,
+        width: size,
+        height: size,
+        size: size * size * 4,
+        offset: 0,
+    }]
+}
+
+fn meta(path: &str, hash: u64) -> ResourceMeta {
+    ResourceMeta {
+        source_path: Some(path.to_string()),
+        content_hash: Some(hash),
+        user_tag: 0,
+    }
+}
+
+// Fills the staging of the texture as it is now with the gray and queues it
+fn fill(renderer: &mut Renderer, texture: TextureHandle, gray: u8) {
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::write_bytes(staging.addr as *mut u8, gray, staging.size as usize) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+}
+
+fn textured_triangle(texture: TextureHandle) -> RenderTask {
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform {
+            mvp: Mat4::IDENTITY,
+            mv: Mat4::IDENTITY,
+        }]),
+    );
+    // The gbuffer stage reads it, even if nothing moves
+    resources.insert(
+        ResourceKind::TransformExtra,
+        MultiResource::TransformExtra(vec![TransformExtra {
+            prev_mvp: Mat4::IDENTITY,
+        }]),
+    );
+    resources.insert(
+        ResourceKind::Material,
+        MultiResource::Material(vec![Material {
+            shininess: 0.0,
+            scaling: 1.0,
+            diffuse_handle: texture.index,
+            normal_handle: texture.index,
+            glow_handle: texture.index,
+            diffuse_sampler: 0,
+            normal_sampler: 0,
+            glow_sampler: 0,
+            padding: 0,
+        }]),
+    );
+    RenderTask {
+        resources,
+        is_two_sided: true,
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+// Red of the albedo the triangle sampling the texture leaves in a frame
+fn sampled_red(renderer: &mut Renderer, texture: TextureHandle) -> u8 {
+    renderer.add_task_to_queue(textured_triangle(texture));
+    let mut request = renderer.read_attachment("albedo").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let albedo = request.resolve_wait(renderer, TIMEOUT).unwrap();
+    albedo.chunks_exact(4).map(|e| e[0]).max().unwrap()
+}
+
+#[test]
+fn reimports_in_consecutive_frames_end_up_with_the_last() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let texture = renderer.gen_texture_with_meta(
+        "asset".to_string(),
+        Format::R8G8B8A8_UNORM,
+        &mip_maps(4),
+        64,
+        meta("asset.png", 1),
+    );
+    fill(&mut renderer, texture, 0x40);
+    renderer.override_texture_sampler(texture, Some(0)).unwrap();
+    renderer.render();
+    renderer.render();
+    let old = sampled_red(&mut renderer, texture);
+
+    renderer
+        .reimport_texture(
+            texture,
+            Format::R8G8B8A8_UNORM,
+            &mip_maps(8),
+            256,
+            Some(meta("asset.png", 2)),
+        )
+        .unwrap();
+    fill(&mut renderer, texture, 0x80);
+    assert_eq!(sampled_red(&mut renderer, texture), old);
+    // The one above never gets sampled
+    renderer
+        .reimport_texture(
+            texture,
+            Format::R8G8B8A8_UNORM,
+            &mip_maps(2),
+            16,
+            Some(meta("asset.png", 3)),
+        )
+        .unwrap();
+    fill(&mut renderer, texture, 0xc0);
+    let mut reds = Vec::new();
+    for _ in 0..4 {
+        reds.push(sampled_red(&mut renderer, texture));
+    }
+    let new = *reds.last().unwrap();
+    assert!(new > old);
+    assert!(reds.iter().all(|e| *e == old || *e == new), "{:?}", reds);
+
+    // Same id, new contents and metadata
+    assert_eq!(
+        renderer.texture_residency(texture).unwrap(),
+        Residency::Resident
+    );
+    assert_eq!(renderer.fetch_texture(texture).unwrap().width(), 2);
+    let meta = renderer.texture_meta(texture).unwrap();
+    assert_eq!(meta.content_hash, Some(3));
+    assert_eq!(renderer.find_texture_by_hash(3), Some(texture));
+    assert_eq!(renderer.find_texture_by_hash(1), None);
+    let mut request = renderer.read_texture(texture).unwrap();
+    renderer.render();
+    let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    assert_eq!(bytes, [0xc0; 16]);
+
+    renderer.free_texture(texture).unwrap();
+    assert_eq!(
+        renderer.reimport_texture(texture, Format::R8G8B8A8_UNORM, &mip_maps(2), 16, None),
+        Err(ReimportError::StaleHandle)
+    );
+    renderer.render();
+    renderer.render();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
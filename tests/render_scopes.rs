@@ -0,0 +1,57 @@
+/*
+ * Which stages continue the rendering scope of the previous one, with attachments named
+ * by strings. No GPU involved.
+ */
+use rend_vk::pipeline::plan::{self, ScopeShape};
+
+fn rendering(views: &[&'static str]) -> ScopeShape<Vec<&'static str>, &'static str> {
+    ScopeShape {
+        is_blit: false,
+        is_final: false,
+        is_isolated: false,
+        view_mask: 0,
+        is_rate_attachment: false,
+        is_waiting: false,
+        is_clearing: false,
+        views: views.to_vec(),
+        rendered: views.to_vec(),
+        inputs: Vec::new(),
+    }
+}
+
+#[test]
+fn stages_rendering_the_same_views_share_a_scope() {
+    let opaque = ScopeShape {
+        is_clearing: true,
+        ..rendering(&["lightAcc", "depth"])
+    };
+    let decals = rendering(&["lightAcc", "depth"]);
+    let bloom = rendering(&["bloom"]);
+    assert_eq!(
+        plan::scope_starts(&[opaque.clone(), decals.clone(), decals, bloom]),
+        [0, 0, 0, 3]
+    );
+}
+
+#[test]
+fn clearing_waiting_or_sampling_the_scope_ends_it() {
+    let first = rendering(&["lightAcc", "depth"]);
+    let next = |edit: fn(&mut ScopeShape<_, _>)| {
+        let mut shape = rendering(&["lightAcc", "depth"]);
+        edit(&mut shape);
+        plan::continues_scope(&first, &first, &shape)
+    };
+    assert!(next(|_| ()));
+    assert!(!next(|e| e.is_clearing = true));
+    assert!(!next(|e| e.is_waiting = true));
+    assert!(!next(|e| e.is_isolated = true));
+    assert!(!next(|e| e.view_mask = 0b11));
+    assert!(!next(|e| e.inputs = vec!["depth"]));
+    // Sampling what came before the scope is fine
+    assert!(next(|e| e.inputs = vec!["albedo"]));
+    let last = ScopeShape {
+        is_final: true,
+        ..first.clone()
+    };
+    assert!(!plan::continues_scope(&first, &last, &first));
+}
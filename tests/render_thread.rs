@@ -0,0 +1,41 @@
+/*
+ * Renderer made on one thread and rendering on another, on a headless surface with
+ * validation on.
+ */
+
+mod common;
+
+use std::thread;
+
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::FrameOutcome;
+
+fn fill() -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 1.0,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+#[test]
+fn renderer_renders_on_the_thread_it_was_moved_to() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    renderer.add_task_to_queue(fill());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let mut renderer = thread::spawn(move || {
+        for _ in 0..3 {
+            // Meshes allocate from the device allocator the main thread used
+            let mesh = renderer.gen_mesh(1024, 0, 0, 0, 3);
+            renderer.add_task_to_queue(fill());
+            assert_eq!(renderer.render(), FrameOutcome::Submitted);
+            renderer.free_mesh(mesh).unwrap();
+        }
+        renderer
+    })
+    .join()
+    .unwrap();
+    renderer.add_task_to_queue(fill());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    common::finish_without_leaks(renderer);
+}
@@ -0,0 +1,71 @@
+/*
+ * Pipelines following the size of the output. The stage of tests/resize_contents.json
+ * writes its debug flags into every pixel of a target sized relative to the output,
+ * another one copies it to the output, both need their render area, viewport and
+ * scissor to cover the new size for every pixel to be written.
+ */
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::render_task::TaskKind;
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+const SIZE: u32 = 16;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const WRITTEN: u32 = 0x4433_2211;
+
+// Renders a frame drawing the stage, returns the target and the presented image
+fn render_and_read(renderer: &mut Renderer) -> (Vec<u8>, Vec<u8>) {
+    renderer.add_task_to_queue(common::task(TaskKind::Fullscreen));
+    let mut target = renderer.read_attachment("accumulated").unwrap();
+    let mut presented = renderer.read_presented().unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let target = target.resolve_wait(renderer, TIMEOUT).unwrap();
+    let presented = presented.resolve_wait(renderer, TIMEOUT).unwrap();
+    assert_eq!(common::validation_errors(), 0);
+    (target, presented)
+}
+
+fn assert_rendered_at(renderer: &mut Renderer, width: u32, height: u32) {
+    let (target, presented) = render_and_read(renderer);
+    assert_eq!(target.len() as u32, width * height * 4);
+    for (i, pixel) in target.chunks_exact(4).enumerate() {
+        assert_eq!(pixel, WRITTEN.to_le_bytes(), "target pixel {}", i);
+    }
+    // Copied over in sRGB, alpha is the only channel left as it was
+    assert_eq!(presented.len() as u32, width * height * 4);
+    for (i, pixel) in presented.chunks_exact(4).enumerate() {
+        assert_eq!(pixel[3], WRITTEN.to_le_bytes()[3], "presented pixel {}", i);
+    }
+}
+
+#[test]
+fn stages_and_relative_targets_follow_resizes() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/resize_contents.json", SIZE, SIZE);
+    renderer
+        .set_debug_flags(Some("accumulate"), WRITTEN as u64)
+        .unwrap();
+    assert_rendered_at(&mut renderer, SIZE, SIZE);
+    renderer.resize(SIZE * 2, SIZE + 3);
+    assert_rendered_at(&mut renderer, SIZE * 2, SIZE + 3);
+    renderer.resize(SIZE / 2, SIZE / 2);
+    assert_rendered_at(&mut renderer, SIZE / 2, SIZE / 2);
+    // Back to the size it was made with
+    renderer.resize(SIZE, SIZE);
+    assert_rendered_at(&mut renderer, SIZE, SIZE);
+    common::finish(renderer);
+}
+
+#[test]
+fn debug_flags_set_before_a_resize_stay_set() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/resize_contents.json", SIZE, SIZE);
+    renderer
+        .set_debug_flags(Some("accumulate"), WRITTEN as u64)
+        .unwrap();
+    renderer.resize(SIZE + 5, SIZE);
+    assert_rendered_at(&mut renderer, SIZE + 5, SIZE);
+    common::finish(renderer);
+}
@@ -0,0 +1,155 @@
+/*
+ * Pipeline targets across frames and resizes. Each drawing stage of
+ * tests/resize_contents.json writes its debug flags into every pixel of a target it
+ * never clears, frames without tasks leave them as they were. The accumulated target is
+ * preserved by blitting it, the centered one by copyCentered, the discarded one starts
+ * out empty at every new size.
+ */
+mod common;
+
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::events::{RenderEvent, RingBufferSink};
+use rend_vk::pipeline::preserve;
+use rend_vk::render_task::TaskKind;
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+const SIZE: u32 = 16;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const WRITTEN: u32 = 0x4433_2211;
+const STAGES: [&str; 3] = ["accumulate", "center", "discard"];
+
+fn make_renderer() -> Renderer {
+    let mut renderer = common::make_renderer("tests/resize_contents.json", SIZE, SIZE);
+    renderer.set_event_sink(Box::new(RingBufferSink::new(64)));
+    renderer
+}
+
+fn set_written(renderer: &mut Renderer, value: u32) {
+    for stage in STAGES {
+        renderer.set_debug_flags(Some(stage), value as u64).unwrap();
+    }
+}
+
+// Renders a frame, drawing the stages only with is_drawn, and reads the targets back
+fn render_and_read(renderer: &mut Renderer, is_drawn: bool) -> [Vec<u8>; 3] {
+    if is_drawn {
+        renderer.add_task_to_queue(common::task(TaskKind::Fullscreen));
+    }
+    let mut readbacks =
+        ["accumulated", "centered", "discarded"].map(|e| renderer.read_attachment(e).unwrap());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let bytes = readbacks
+        .each_mut()
+        .map(|e| e.resolve_wait(renderer, TIMEOUT).unwrap());
+    assert_eq!(common::validation_errors(), 0);
+    bytes
+}
+
+fn assert_every_pixel(bytes: &[u8], extent: (u32, u32), value: u32) {
+    assert_eq!(bytes.len() as u32, extent.0 * extent.1 * 4);
+    for (i, pixel) in bytes.chunks_exact(4).enumerate() {
+        assert_eq!(pixel, value.to_le_bytes(), "pixel {}", i);
+    }
+}
+
+// Written inside the region copyCentered kept, zero around it
+fn assert_centered(bytes: &[u8], from: (u32, u32), to: (u32, u32), value: u32) {
+    let extent = |e: (u32, u32)| vk::Extent2D {
+        width: e.0,
+        height: e.1,
+    };
+    let (_, offset, kept) = preserve::centered_region(extent(from), extent(to));
+    assert_eq!(bytes.len() as u32, to.0 * to.1 * 4);
+    for (i, pixel) in bytes.chunks_exact(4).enumerate() {
+        let (x, y) = ((i as u32 % to.0) as i32, (i as u32 / to.0) as i32);
+        let is_kept = (offset.x..offset.x + kept.width as i32).contains(&x)
+            && (offset.y..offset.y + kept.height as i32).contains(&y);
+        let expected = if is_kept { value } else { 0 };
+        assert_eq!(pixel, expected.to_le_bytes(), "pixel {}x{}", x, y);
+    }
+}
+
+fn reset_targets(renderer: &mut Renderer) -> Vec<(String, u32, u32)> {
+    renderer
+        .drain_events()
+        .into_iter()
+        .filter_map(|e| match e {
+            RenderEvent::TargetReset {
+                target,
+                width,
+                height,
+            } => Some((target, width, height)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn targets_keep_their_contents_between_frames() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    set_written(&mut renderer, WRITTEN);
+    for is_drawn in [true, false, false] {
+        // Loaded as they were and not drawn over
+        for bytes in render_and_read(&mut renderer, is_drawn) {
+            assert_every_pixel(&bytes, (SIZE, SIZE), WRITTEN);
+        }
+    }
+    common::finish(renderer);
+}
+
+#[test]
+fn preserved_targets_survive_resizes_mid_accumulation() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    set_written(&mut renderer, WRITTEN);
+    render_and_read(&mut renderer, true);
+    reset_targets(&mut renderer);
+
+    let larger = (SIZE * 2, SIZE + 3);
+    renderer.resize(larger.0, larger.1);
+    assert_eq!(
+        reset_targets(&mut renderer),
+        [("discarded".to_string(), larger.0, larger.1)]
+    );
+    let [accumulated, centered, discarded] = render_and_read(&mut renderer, false);
+    assert_every_pixel(&accumulated, larger, WRITTEN);
+    assert_centered(&centered, (SIZE, SIZE), larger, WRITTEN);
+    assert_eq!(discarded.len() as u32, larger.0 * larger.1 * 4);
+
+    // Carried over again from what the previous resize left
+    let smaller = (SIZE / 2, SIZE / 2);
+    renderer.resize(smaller.0, smaller.1);
+    assert_eq!(
+        reset_targets(&mut renderer),
+        [("discarded".to_string(), smaller.0, smaller.1)]
+    );
+    let [accumulated, centered, _] = render_and_read(&mut renderer, false);
+    assert_every_pixel(&accumulated, smaller, WRITTEN);
+    assert_centered(&centered, larger, smaller, WRITTEN);
+
+    // Drawn at the new size
+    set_written(&mut renderer, 0x0101_0101);
+    for bytes in render_and_read(&mut renderer, true) {
+        assert_every_pixel(&bytes, smaller, 0x0101_0101);
+    }
+    common::finish(renderer);
+}
+
+#[test]
+fn targets_keep_their_contents_across_resizes_to_the_same_size() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    set_written(&mut renderer, WRITTEN);
+    render_and_read(&mut renderer, true);
+    // Nothing to load again, the pipeline stays
+    renderer.resize(SIZE, SIZE);
+    assert!(reset_targets(&mut renderer).is_empty());
+    for bytes in render_and_read(&mut renderer, false) {
+        assert_every_pixel(&bytes, (SIZE, SIZE), WRITTEN);
+    }
+    common::finish(renderer);
+}
@@ -0,0 +1,62 @@
+/*
+ * Host metadata of textures and meshes, on a headless surface with validation on. It
+ * lives as long as the resource and nothing finds a freed resource through it.
+ */
+
+mod common;
+
+use rend_vk::format::Format;
+use rend_vk::handle::ResourceMeta;
+use rend_vk::texture::MipMap;
+
+const TEXTURE_SIZE: u32 = 16;
+
+fn meta(path: &str, hash: u64) -> ResourceMeta {
+    ResourceMeta {
+        source_path: Some(path.to_string()),
+        content_hash: Some(hash),
+        user_tag: 7,
+    }
+}
+
+#[test]
+fn metadata_lives_as_long_as_its_resource() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+    let mips = [MipMap {
+        index: 0,
+        width: TEXTURE_SIZE,
+        height: TEXTURE_SIZE,
+        size,
+        offset: 0,
+    }];
+    let castle = meta("env/castle_albedo.ktx2", 0xc457);
+    let texture = renderer.gen_texture_with_meta(
+        "castle".to_string(),
+        Format::R8G8B8A8_UNORM,
+        &mips,
+        size,
+        castle.clone(),
+    );
+    let plain = renderer.gen_texture("plain".to_string(), Format::R8G8B8A8_UNORM, &mips, 0);
+    assert_eq!(renderer.texture_meta(texture), Some(&castle));
+    assert_eq!(renderer.texture_meta(plain), None);
+    assert_eq!(renderer.find_texture_by_hash(0xc457), Some(texture));
+    assert_eq!(renderer.find_texture_by_hash(0xdead), None);
+    let report = renderer.memory_report();
+    let entry = report.textures.iter().find(|e| e.id == texture.index);
+    assert_eq!(entry.unwrap().meta, Some(castle));
+
+    let wall = meta("env/wall.glb", 0x3a11);
+    let mesh = renderer.gen_mesh_with_meta(36, 0, 0, 0, 3, wall.clone());
+    assert_eq!(renderer.mesh_meta(mesh), Some(&wall));
+
+    renderer.free_texture(texture).unwrap();
+    renderer.free_mesh(mesh).unwrap();
+    assert_eq!(renderer.texture_meta(texture), None);
+    assert_eq!(renderer.find_texture_by_hash(0xc457), None);
+    assert_eq!(renderer.mesh_meta(mesh), None);
+    renderer.render();
+    common::finish(renderer);
+}
@@ -0,0 +1,82 @@
+/*
+ * Retry queues on their own, without a device. What goes through them inside the
+ * renderer is driven by the fault injection tests.
+ */
+use rend_vk::readback::ReadbackBusy;
+use rend_vk::retry::{RetriedWork, RetryAfter, RetryError, RetryQueue, RetryReason};
+
+#[test]
+fn deferred_work_gets_its_next_attempt() {
+    let mut queue = RetryQueue::new();
+    assert!(queue.is_empty());
+    assert_eq!(queue.defer("a", RetryReason::StagingFull, 1, 2), Ok(2));
+    assert_eq!(queue.defer("a", RetryReason::StagingFull, 2, 2), Ok(3));
+    assert_eq!(queue.len(), 2);
+}
+
+#[test]
+fn work_past_the_limit_is_dropped() {
+    let mut queue = RetryQueue::new();
+    assert_eq!(
+        queue.defer(7u32, RetryReason::UploadBudget, 3, 2),
+        Err(RetryError::Exhausted {
+            reason: RetryReason::UploadBudget,
+            attempts: 3
+        })
+    );
+    // A limit of zero gives up on the first failure
+    assert!(queue.defer(7u32, RetryReason::ReadbackBusy, 1, 0).is_err());
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn takes_the_oldest_up_to_the_cap() {
+    let mut queue = RetryQueue::new();
+    for (work, attempts) in [(1u32, 1), (2, 3), (3, 2)] {
+        queue
+            .defer(work, RetryReason::StagingFull, attempts, 8)
+            .unwrap();
+    }
+    assert_eq!(queue.take(2), [(1, 1), (2, 3)]);
+    assert_eq!(queue.take(0), []);
+    assert_eq!(queue.take(4), [(3, 2)]);
+    assert!(queue.take(4).is_empty());
+}
+
+#[test]
+fn retain_drops_work_and_iter_keeps_reasons() {
+    let mut queue = RetryQueue::new();
+    queue.defer(1u32, RetryReason::StagingFull, 1, 8).unwrap();
+    queue.defer(2u32, RetryReason::UploadBudget, 1, 8).unwrap();
+    queue.retain(|e| *e != 1);
+    let left: Vec<_> = queue.iter().map(|(work, reason)| (*work, reason)).collect();
+    assert_eq!(left, [(2, RetryReason::UploadBudget)]);
+}
+
+#[test]
+fn busy_readbacks_are_transient() {
+    let busy = ReadbackBusy {
+        size: 256,
+        available: 0,
+    };
+    assert_eq!(
+        RetryError::from(busy),
+        RetryError::Transient(RetryAfter::NextFrame)
+    );
+}
+
+#[test]
+fn errors_and_work_read_well() {
+    let exhausted = RetryError::Exhausted {
+        reason: RetryReason::ReadbackBusy,
+        attempts: 4,
+    };
+    assert_eq!(
+        exhausted.to_string(),
+        "gave up after 4 attempts, ReadbackBusy"
+    );
+    assert_eq!(
+        serde_json::to_string(&RetriedWork::Mesh(3)).unwrap(),
+        r#"{"kind":"mesh","id":3}"#
+    );
+}
@@ -0,0 +1,153 @@
+/*
+ * Makes a new sampler every frame while drawing with it on a headless surface with
+ * validation on. The texture is a single color, so whatever sampler ends up used the
+ * albedo the gbuffer stage writes stays the same, and any frame reading a descriptor
+ * before it's written shows up as a difference in the readback. Needs a device with
+ * VK_EXT_headless_surface and the validation layers installed. The renderer only
+ * enables debug printf, synchronization validation has to be switched on in the layer
+ * settings for its errors to count.
+ */
+
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use glam::Mat4;
+
+use rend_vk::format::Format;
+use rend_vk::handle::TextureHandle;
+use rend_vk::pipeline::file::{Filtering, WrapMode};
+use rend_vk::pipeline::sampler::SamplerKey;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::shader_resource::{Material, MultiResource, ResourceKind, Transform, TransformExtra};
+use rend_vk::texture::MipMap;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+fn gray_texture(renderer: &mut Renderer) -> TextureHandle {
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 4,
+        height: 4,
+        size: 64,
+        offset: 0,
+    }];
+    let texture = renderer.gen_texture("gray".to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 64);
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::write_bytes(staging.addr as *mut u8, 0x80, 64) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    texture
+}
+
+fn textured_triangle(texture: TextureHandle, sampler: u8) -> RenderTask {
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform {
+            mvp: Mat4::IDENTITY,
+            mv: Mat4::IDENTITY,
+        }]),
+    );
+    // The gbuffer stage reads it, even if nothing moves
+    resources.insert(
+        ResourceKind::TransformExtra,
+        MultiResource::TransformExtra(vec![TransformExtra {
+            prev_mvp: Mat4::IDENTITY,
+        }]),
+    );
+    resources.insert(
+        ResourceKind::Material,
+        MultiResource::Material(vec![Material {
+            shininess: 0.0,
+            scaling: 1.0,
+            diffuse_handle: texture.index,
+            normal_handle: texture.index,
+            glow_handle: texture.index,
+            diffuse_sampler: sampler,
+            normal_sampler: sampler,
+            glow_sampler: sampler,
+            padding: 0,
+        }]),
+    );
+    RenderTask {
+        resources,
+        is_two_sided: true,
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+/*
+ * Every key without anisotropy the pipeline didn't make itself already.
+ */
+fn new_keys(renderer: &Renderer) -> Vec<SamplerKey> {
+    let mut keys = Vec::new();
+    for filter in [Filtering::Nearest, Filtering::Linear] {
+        for wrap_mode in [
+            WrapMode::Repeat,
+            WrapMode::MirroredRepeat,
+            WrapMode::ClampToEdge,
+        ] {
+            for is_exact in [false, true] {
+                keys.push(SamplerKey {
+                    filter,
+                    wrap_mode,
+                    anisotropy: 1,
+                    compare: None,
+                    is_exact,
+                });
+            }
+        }
+    }
+    keys.retain(|e| renderer.try_get_sampler(*e).is_none());
+    keys
+}
+
+#[test]
+fn new_samplers_every_frame_sample_the_same() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let texture = gray_texture(&mut renderer);
+    renderer.render();
+    renderer.render();
+
+    let mut expected = None;
+    let mut previous = None;
+    let keys = new_keys(&renderer);
+    assert!(keys.len() > 4);
+    for (frame, key) in keys.into_iter().enumerate() {
+        let sampler = renderer.get_sampler(key).unwrap();
+        assert!(!renderer.is_sampler_published(sampler));
+        // Falls back until the next frame
+        renderer.add_task_to_queue(textured_triangle(texture, sampler));
+        if let Some(previous) = previous {
+            assert!(renderer.is_sampler_published(previous));
+            renderer.add_task_to_queue(textured_triangle(texture, previous));
+        }
+        let mut request = renderer.read_attachment("albedo").unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        assert!(renderer.is_sampler_published(sampler));
+        let albedo = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+        match &expected {
+            None => expected = Some(albedo),
+            Some(e) => assert!(*e == albedo, "albedo of frame {} differs", frame),
+        }
+        previous = Some(sampler);
+    }
+    // Ids never made fall back too
+    assert!(!renderer.is_sampler_published(u8::MAX - 1));
+
+    renderer.free_texture(texture).unwrap();
+    renderer.render();
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
@@ -0,0 +1,135 @@
+/*
+ * Sampler ids and capacities against made up device limits, what the sampler policy
+ * makes of the keys and what texture sampler overrides make of materials. No GPU
+ * involved.
+ */
+use std::collections::HashMap;
+
+use ash::vk;
+
+use rend_vk::pipeline::file::{Filtering, WrapMode};
+use rend_vk::pipeline::sampler::{Sampler, SamplerKey, SamplerPolicy, SamplersExhausted};
+use rend_vk::shader_resource::Material;
+
+const TRILINEAR: SamplerKey = SamplerKey {
+    filter: Filtering::Linear,
+    wrap_mode: WrapMode::Repeat,
+    anisotropy: 16,
+    compare: None,
+    is_exact: false,
+};
+
+const LOW: SamplerPolicy = SamplerPolicy {
+    max_anisotropy: 2,
+    lod_bias: 1.5,
+    force_nearest_min_filter: true,
+};
+
+#[test]
+fn capacity_never_goes_past_the_ids() {
+    assert_eq!(Sampler::capacity_within(4000, 4000, 0), Sampler::MAX_IDS);
+    // The attachment inputs of the passes take their share of the stage
+    assert_eq!(Sampler::capacity_within(16, 4000, 4), 12);
+    assert_eq!(Sampler::capacity_within(4000, 96, 4), 96);
+    assert_eq!(Sampler::capacity_within(4, 4000, 8), 0);
+}
+
+#[test]
+fn one_sampler_past_the_capacity_is_an_error() {
+    let capacity = Sampler::capacity_within(4000, 4000, 0);
+    let ids: Vec<_> = (0..capacity as usize)
+        .map(|e| Sampler::next_id(e, capacity).unwrap())
+        .collect();
+    assert_eq!(ids, (0..=254).collect::<Vec<u8>>());
+    assert_eq!(
+        Sampler::next_id(capacity as usize, capacity),
+        Err(SamplersExhausted { capacity: 255 })
+    );
+}
+
+#[test]
+fn ids_never_wrap_around() {
+    // No aliasing of id 0 or the "no sampler" id, whatever the capacity says
+    for taken in [255, 256, 511] {
+        assert_eq!(
+            Sampler::next_id(taken, u32::MAX),
+            Err(SamplersExhausted {
+                capacity: Sampler::MAX_IDS
+            })
+        );
+    }
+    assert_eq!(
+        Sampler::next_id(12, 12),
+        Err(SamplersExhausted { capacity: 12 })
+    );
+    assert_eq!(Sampler::next_id(11, 12), Ok(11));
+}
+
+#[test]
+fn the_default_policy_keeps_the_keys() {
+    let info = Sampler::info_of_key(TRILINEAR, &SamplerPolicy::default());
+    assert_eq!(info.anisotropy_enable, vk::TRUE);
+    assert_eq!(info.max_anisotropy, 16.0);
+    assert_eq!(info.mip_lod_bias, 0.0);
+    assert_eq!(info.min_filter, vk::Filter::LINEAR);
+    assert_eq!(info.mag_filter, vk::Filter::LINEAR);
+}
+
+#[test]
+fn policies_apply_on_top_of_the_keys() {
+    let info = Sampler::info_of_key(TRILINEAR, &LOW);
+    assert_eq!(info.max_anisotropy, 2.0);
+    assert_eq!(info.mip_lod_bias, 1.5);
+    assert_eq!(info.min_filter, vk::Filter::NEAREST);
+    // Only minification is forced
+    assert_eq!(info.mag_filter, vk::Filter::LINEAR);
+    assert_eq!(info.address_mode_u, vk::SamplerAddressMode::REPEAT);
+
+    // Caps, never raises
+    let key = SamplerKey {
+        anisotropy: 1,
+        ..TRILINEAR
+    };
+    let info = Sampler::info_of_key(key, &LOW);
+    assert_eq!(info.anisotropy_enable, vk::FALSE);
+    assert_eq!(info.max_anisotropy, 1.0);
+}
+
+// What the policy changes
+fn resolved(key: SamplerKey, policy: &SamplerPolicy) -> (f32, f32, vk::Filter) {
+    let info = Sampler::info_of_key(key, policy);
+    (info.max_anisotropy, info.mip_lod_bias, info.min_filter)
+}
+
+#[test]
+fn exact_keys_ignore_the_policy() {
+    assert_eq!(
+        resolved(TRILINEAR.exact(), &LOW),
+        resolved(TRILINEAR, &SamplerPolicy::default())
+    );
+    assert_eq!(resolved(TRILINEAR, &LOW), (2.0, 1.5, vk::Filter::NEAREST));
+}
+
+#[test]
+fn overrides_swap_the_samplers_of_their_textures_only() {
+    let mut material = Material {
+        shininess: 0.0,
+        scaling: 1.0,
+        diffuse_handle: 3,
+        normal_handle: 4,
+        glow_handle: 3,
+        diffuse_sampler: 1,
+        normal_sampler: 1,
+        glow_sampler: 2,
+        padding: 0,
+    };
+    material.override_samplers(&HashMap::from([(3, 7), (9, 8)]));
+    assert_eq!(
+        [
+            material.diffuse_sampler,
+            material.normal_sampler,
+            material.glow_sampler
+        ],
+        [7, 1, 7]
+    );
+}
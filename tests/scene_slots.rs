@@ -0,0 +1,171 @@
+/*
+ * Draws the test triangle with its Transform in a scene slot on a headless surface with
+ * validation on, against the same triangle with the Transform inline. The albedo the
+ * gbuffer stage writes has to match, and follow updates of the slot.
+ */
+
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::config::TaskRejected;
+use rend_vk::handle::StaleHandle;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::scene_slot::SceneSlotRejected;
+use rend_vk::shader_resource::{
+    bytes_of, Material, MultiResource, ResourceKind, Transform, TransformExtra,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("pipeline.json", 64, 64)
+}
+
+fn transform(x: f32) -> Transform {
+    let mv = Mat4::from_translation(Vec3::new(x, 0.0, 0.0));
+    Transform { mvp: mv, mv }
+}
+
+/*
+ * Triangle with the Material and TransformExtra the gbuffer stage reads, the Transform
+ * is up to the caller.
+ */
+fn triangle(transform: MultiResource, instance_count: u32) -> RenderTask {
+    let count = instance_count as usize;
+    let mut resources = HashMap::new();
+    resources.insert(ResourceKind::Transform, transform);
+    resources.insert(
+        ResourceKind::Material,
+        MultiResource::Material(vec![
+            Material {
+                shininess: 0.0,
+                scaling: 1.0,
+                diffuse_handle: 0,
+                normal_handle: 0,
+                glow_handle: 0,
+                diffuse_sampler: 0,
+                normal_sampler: 0,
+                glow_sampler: 0,
+                padding: 0,
+            };
+            count
+        ]),
+    );
+    resources.insert(
+        ResourceKind::TransformExtra,
+        MultiResource::TransformExtra(vec![
+            TransformExtra {
+                prev_mvp: Mat4::IDENTITY,
+            };
+            count
+        ]),
+    );
+    RenderTask {
+        instance_count,
+        resources,
+        is_two_sided: true,
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+fn render_albedo(renderer: &mut Renderer, tasks: Vec<RenderTask>) -> Vec<u8> {
+    for task in tasks {
+        renderer.try_add_task_to_queue(task).unwrap();
+    }
+    let mut request = renderer.read_attachment("albedo").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    request.resolve_wait(renderer, TIMEOUT).unwrap()
+}
+
+#[test]
+fn slots_draw_like_inline_data() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let empty = render_albedo(&mut renderer, Vec::new());
+    let inline = MultiResource::Transform(vec![transform(0.0)]);
+    let drawn = render_albedo(&mut renderer, vec![triangle(inline, 1)]);
+    assert!(drawn != empty);
+
+    let slot = renderer.create_scene_slot(bytes_of(&[transform(0.0)]));
+    for _ in 0..3 {
+        let task = triangle(MultiResource::SceneSlot(slot), 1);
+        assert!(render_albedo(&mut renderer, vec![task]) == drawn);
+    }
+    // Off to the side, out of view from the next frame on
+    renderer
+        .update_scene_slot(slot, bytes_of(&[transform(10.0)]))
+        .unwrap();
+    for _ in 0..3 {
+        let task = triangle(MultiResource::SceneSlot(slot), 1);
+        assert!(render_albedo(&mut renderer, vec![task]) == empty);
+    }
+    renderer
+        .update_scene_slot(slot, bytes_of(&[transform(0.0)]))
+        .unwrap();
+    let task = triangle(MultiResource::SceneSlot(slot), 1);
+    assert!(render_albedo(&mut renderer, vec![task]) == drawn);
+
+    renderer.free_scene_slot(slot).unwrap();
+    renderer.render();
+    common::finish(renderer);
+}
+
+#[test]
+fn mismatched_and_stale_slots_are_rejected() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let pair = [transform(0.0), transform(0.5)];
+    let slot = renderer.create_scene_slot(bytes_of(&pair));
+    assert!(renderer.is_scene_slot_current(slot));
+
+    assert_eq!(
+        renderer.update_scene_slot(slot, bytes_of(&pair[..1])),
+        Err(SceneSlotRejected::SizeMismatch {
+            slot,
+            expected: 256,
+            found: 128,
+        })
+    );
+    // Two instances worth of Transform, drawn as one
+    assert_eq!(
+        renderer.try_add_task_to_queue(triangle(MultiResource::SceneSlot(slot), 1)),
+        Err(TaskRejected::SceneSlotSize {
+            resource: ResourceKind::Transform,
+            slot,
+            expected: 128,
+            found: 256,
+        })
+    );
+    renderer
+        .try_add_task_to_queue(triangle(MultiResource::SceneSlot(slot), 2))
+        .unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+
+    renderer.free_scene_slot(slot).unwrap();
+    assert!(!renderer.is_scene_slot_current(slot));
+    assert_eq!(renderer.free_scene_slot(slot), Err(StaleHandle));
+    assert_eq!(
+        renderer.update_scene_slot(slot, bytes_of(&pair)),
+        Err(SceneSlotRejected::Stale { slot })
+    );
+    // Same index again, the old id stays stale
+    let reused = renderer.create_scene_slot(bytes_of(&pair));
+    assert_eq!(reused.index, slot.index);
+    assert_ne!(reused.generation, slot.generation);
+    assert_eq!(
+        renderer.try_add_task_to_queue(triangle(MultiResource::SceneSlot(slot), 2)),
+        Err(TaskRejected::StaleSceneSlot { slot })
+    );
+    renderer
+        .try_add_task_to_queue(triangle(MultiResource::SceneSlot(reused), 2))
+        .unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+
+    renderer.free_scene_slot(reused).unwrap();
+    renderer.render();
+    common::finish(renderer);
+}
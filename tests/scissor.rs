@@ -0,0 +1,170 @@
+/*
+ * Clips UI style fills with per task scissors on a headless surface with validation on.
+ * The ui stage of tests/scissor.json fills the whole target with the alpha cutoff of each
+ * task as gray, so every pixel read back tells which clip it ended up in.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::pipeline::plan;
+use rend_vk::render_task::{RenderTask, ScissorRect, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("tests/scissor.json", SIZE, SIZE)
+}
+
+fn fill(gray: u8, scissor: Option<ScissorRect>) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: gray as f32 / 255.0,
+        scissor,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> Option<ScissorRect> {
+    Some(ScissorRect {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+fn rect_2d(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D { width, height },
+    }
+}
+
+fn area() -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D::default(),
+        extent: vk::Extent2D {
+            width: SIZE,
+            height: SIZE,
+        },
+    }
+}
+
+/*
+ * Gray of the last fill whose clip holds the pixel, 0 where the clear is untouched.
+ */
+fn expected_at(fills: &[(u8, Option<ScissorRect>)], x: u32, y: u32) -> u8 {
+    let within = |scissor: &Option<ScissorRect>| match scissor {
+        None => true,
+        Some(e) => e.clamped_to(area()).is_some_and(|r| {
+            let (x, y) = (x as i32, y as i32);
+            x >= r.offset.x
+                && y >= r.offset.y
+                && x < r.offset.x + r.extent.width as i32
+                && y < r.offset.y + r.extent.height as i32
+        }),
+    };
+    fills
+        .iter()
+        .rev()
+        .find(|(_, scissor)| within(scissor))
+        .map_or(0, |(gray, _)| *gray)
+}
+
+#[test]
+fn scissors_are_clamped_to_the_area() {
+    let area = area();
+    let clamped = |scissor: Option<ScissorRect>| scissor.unwrap().clamped_to(area);
+    assert_eq!(clamped(rect(8, 8, 16, 16)), Some(rect_2d(8, 8, 16, 16)));
+    assert_eq!(clamped(rect(-10, -4, 20, 8)), Some(rect_2d(0, 0, 10, 4)));
+    assert_eq!(
+        clamped(rect(56, 40, 100, 100)),
+        Some(rect_2d(56, 40, 8, 24))
+    );
+    assert_eq!(
+        clamped(rect(i32::MIN, i32::MIN, u32::MAX, u32::MAX)),
+        Some(rect_2d(0, 0, SIZE, SIZE))
+    );
+    for scissor in [
+        rect(64, 0, 8, 8),
+        rect(-8, 0, 8, 8),
+        rect(8, 8, 0, 8),
+        rect(8, 8, 8, 0),
+    ] {
+        assert_eq!(clamped(scissor), None, "{:?}", scissor);
+    }
+}
+
+#[test]
+fn scissors_get_intersected_with_the_dirty_region() {
+    let region = rect_2d(16, 16, 32, 8);
+    let clipped = |scissor: Option<ScissorRect>| {
+        plan::clip_scissor(
+            scissor.map(|e| e.clamped_to(area()).unwrap()),
+            area(),
+            region,
+        )
+    };
+    // Draws without a scissor of their own redraw the whole region
+    assert_eq!(clipped(None), Some(region));
+    assert_eq!(clipped(rect(8, 8, 16, 16)), Some(rect_2d(16, 16, 8, 8)));
+    assert_eq!(clipped(rect(20, 18, 4, 4)), Some(rect_2d(20, 18, 4, 4)));
+    // Outside of it, skipped
+    assert_eq!(clipped(rect(0, 0, 16, 64)), None);
+    assert_eq!(clipped(rect(16, 24, 32, 8)), None);
+}
+
+#[test]
+fn nested_clips_leave_the_rest_untouched() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let fills = [
+        // Window, a panel in it, a button in the panel and a tooltip over both
+        (40, rect(4, 4, 48, 48)),
+        (80, rect(8, 16, 32, 24)),
+        (120, rect(12, 20, 8, 8)),
+        (160, rect(16, 24, 24, 4)),
+        // Past the edges, only the part within the target gets filled
+        (200, rect(-8, -8, 12, 12)),
+        (240, rect(56, 40, 100, 100)),
+        // Nothing left after clamping, skipped
+        (255, rect(64, 64, 8, 8)),
+        (255, rect(20, 20, 0, 8)),
+    ];
+    // Same scissor twice in a row, and the previous one again after another
+    let fills: Vec<_> = fills
+        .iter()
+        .chain(&[(80, rect(8, 16, 32, 24)), (80, rect(8, 16, 32, 24))])
+        .chain(&[(120, rect(12, 20, 8, 8))])
+        .copied()
+        .collect();
+    for _ in 0..2 {
+        for (gray, scissor) in &fills {
+            renderer.add_task_to_queue(fill(*gray, *scissor));
+        }
+        let mut request = renderer.read_attachment("ui").unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+        assert_eq!(bytes.len() as u32, SIZE * SIZE * 4);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let i = ((y * SIZE + x) * 4) as usize;
+                let gray = expected_at(&fills, x, y);
+                let alpha = if gray == 0 { 0 } else { 255 };
+                assert_eq!(
+                    bytes[i..i + 4],
+                    [gray, gray, gray, alpha],
+                    "pixel {}, {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+    common::finish(renderer);
+}
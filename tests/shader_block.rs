@@ -0,0 +1,80 @@
+/*
+ * Host structs declared with shader_block!, checked without a device. The misplaced
+ * member and trailing padding checks fail to compile, see the examples on the macro.
+ */
+#[path = "../examples/blocks/frame_constants.rs"]
+mod frame_constants;
+
+use glam::{Mat4, UVec2, Vec3};
+
+use rend_vk::shader_block::{camel_case, ShaderBlock};
+use rend_vk::shader_resource::KnownLayout;
+
+use frame_constants::{FrameConstants, GLSL_PATH};
+
+rend_vk::shader_block! {
+    pub struct Probe {
+        pub world_to_probe: Mat4,
+        pub center: Vec3,
+        pub ray_count: u32,
+        pub sh_weights: [[f32; 3]; 2],
+        pub grid_cell: UVec2,
+    }
+}
+
+#[test]
+fn checked_in_frame_constants_glsl_is_up_to_date() {
+    let checked_in = std::fs::read_to_string(GLSL_PATH).unwrap();
+    assert_eq!(
+        FrameConstants::glsl_source(),
+        checked_in,
+        "run the frame_constants example with --glsl"
+    );
+}
+
+#[test]
+fn field_names_turn_camel_case() {
+    assert_eq!(camel_case("view_proj"), "viewProj");
+    assert_eq!(
+        camel_case("per_draw_layout_version"),
+        "perDrawLayoutVersion"
+    );
+    assert_eq!(camel_case("time"), "time");
+    assert_eq!(camel_case("_unused"), "unused");
+    assert_eq!(camel_case("grid__cell"), "gridCell");
+    assert_eq!(camel_case("trailing_"), "trailing");
+}
+
+#[test]
+fn glsl_source_keeps_the_member_order_and_array_sizes() {
+    let expected = "// Generated from the Rust struct Probe, don't edit\n\
+                    struct Probe\n\
+                    {\n  \
+                    mat4 worldToProbe;\n  \
+                    vec3 center;\n  \
+                    uint rayCount;\n  \
+                    float shWeights[2][3];\n  \
+                    uvec2 gridCell;\n\
+                    };\n";
+    assert_eq!(Probe::NAME, "Probe");
+    assert_eq!(Probe::glsl_source(), expected);
+}
+
+#[test]
+fn members_have_the_scalar_layout_offsets() {
+    let members: Vec<_> = Probe::members()
+        .into_iter()
+        .map(|e| (e.name, e.offset, e.size))
+        .collect();
+    assert_eq!(
+        members,
+        [
+            ("world_to_probe", 0, 64),
+            ("center", 64, 12),
+            ("ray_count", 76, 4),
+            ("sh_weights", 80, 24),
+            ("grid_cell", 104, 8),
+        ]
+    );
+    assert_eq!(std::mem::size_of::<Probe>(), 112);
+}
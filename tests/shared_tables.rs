@@ -0,0 +1,157 @@
+/*
+ * Two renderers on one core sharing the texture and sampler tables and the ids. Both
+ * render to headless surfaces with validation on, the second one outliving the first.
+ * Previews made without a surface render into an image of their own, tests/scissor.json
+ * fills it with white.
+ */
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rend_vk::config::{RendererConfig, TaskRejected};
+use rend_vk::format::Format;
+use rend_vk::handle::{MeshHandle, TextureHandle};
+use rend_vk::render_core::RenderCore;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::swapchain;
+use rend_vk::texture::MipMap;
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// One at a time, the validation counter is global
+
+fn make_core() -> Arc<RenderCore> {
+    common::make_core(&RendererConfig::default())
+}
+
+fn make_view(core: &Arc<RenderCore>) -> Renderer {
+    let config = RendererConfig::default();
+    Renderer::with_core_headless(core.clone(), config, "tests/offscreen.json", SIZE, SIZE)
+}
+
+// Fullscreen stages don't read the vertices, any mesh draws
+fn fill(mesh: MeshHandle) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: 0.5,
+        ..RenderTask::new(mesh, TaskKind::Fullscreen)
+    }
+}
+
+fn gen_texel(view: &mut Renderer, name: &str) -> TextureHandle {
+    let mip_maps = [MipMap {
+        index: 0,
+        width: 1,
+        height: 1,
+        size: 4,
+        offset: 0,
+    }];
+    view.gen_texture(name.to_string(), Format::R8G8B8A8_UNORM, &mip_maps, 4)
+}
+
+#[test]
+fn views_draw_each_others_meshes_and_never_share_ids() {
+    let _serial = common::serial();
+    let core = make_core();
+    let mut main_view = make_view(&core);
+    // Uploads the builtins
+    assert_eq!(main_view.render(), FrameOutcome::Submitted);
+    let mut preview_view = make_view(&core);
+    common::reset_validation_errors();
+
+    let main_texture = gen_texel(&mut main_view, "main");
+    let preview_texture = gen_texel(&mut preview_view, "preview");
+    assert_ne!(main_texture.index, preview_texture.index);
+    let main_mesh = main_view.gen_mesh(1024, 0, 0, 0, 3);
+    let preview_mesh = preview_view.gen_mesh(1024, 0, 0, 0, 3);
+    assert_ne!(main_mesh.index, preview_mesh.index);
+
+    preview_view.try_add_task_to_queue(fill(main_mesh)).unwrap();
+    assert_eq!(preview_view.render(), FrameOutcome::Submitted);
+    main_view.try_add_task_to_queue(fill(preview_mesh)).unwrap();
+    assert_eq!(main_view.render(), FrameOutcome::Submitted);
+
+    // Freed by its maker, stale for both
+    main_view.free_mesh(main_mesh).unwrap();
+    assert_eq!(
+        preview_view.try_add_task_to_queue(fill(main_mesh)),
+        Err(TaskRejected::StaleMesh { mesh: main_mesh })
+    );
+    main_view.free_texture(main_texture).unwrap();
+    main_view.render();
+
+    // The builtins the main view made stay with the core
+    main_view.destroy();
+    preview_view
+        .try_add_task_to_queue(fill(Renderer::TEST_TRIANGLE))
+        .unwrap();
+    assert_eq!(preview_view.render(), FrameOutcome::Submitted);
+    preview_view.free_mesh(preview_mesh).unwrap();
+    preview_view.free_texture(preview_texture).unwrap();
+    preview_view.render();
+    preview_view.destroy();
+    RenderCore::destroy(core);
+    assert_eq!(
+        common::validation_errors(),
+        0,
+        "validation errors or leaked objects"
+    );
+}
+
+#[test]
+#[should_panic(expected = "belongs to another renderer of the core")]
+fn only_the_maker_frees_a_mesh() {
+    let _serial = common::serial();
+    let core = make_core();
+    let mut main_view = make_view(&core);
+    let mut preview_view = make_view(&core);
+    let mesh = main_view.gen_mesh(1024, 0, 0, 0, 3);
+    let _ = preview_view.free_mesh(mesh);
+}
+
+#[test]
+fn previews_render_without_a_surface() {
+    let _serial = common::serial();
+    let core = make_core();
+    let mut main_view = make_view(&core);
+    assert_eq!(main_view.render(), FrameOutcome::Submitted);
+    let config = RendererConfig::default();
+    let mut preview =
+        Renderer::with_core_headless(core.clone(), config, "tests/scissor.json", 32, 16);
+    common::reset_validation_errors();
+    assert_eq!(
+        preview.swapchain_capabilities().format,
+        swapchain::HEADLESS_FORMAT
+    );
+
+    preview.add_task_to_queue(fill(Renderer::TEST_TRIANGLE));
+    let mut request = preview.read_presented().unwrap();
+    assert_eq!(preview.render(), FrameOutcome::Submitted);
+    let bytes = request.resolve_wait(&preview, TIMEOUT).unwrap();
+    assert_eq!(bytes.len(), 32 * 16 * 4);
+    assert!(bytes.iter().all(|e| *e == 255), "preview isn't white");
+    // The main view keeps presenting in between
+    assert_eq!(main_view.render(), FrameOutcome::Submitted);
+
+    // Made again at the new extent
+    preview.resize(8, 8);
+    preview.add_task_to_queue(fill(Renderer::TEST_TRIANGLE));
+    let mut request = preview.read_presented().unwrap();
+    assert_eq!(preview.render(), FrameOutcome::Submitted);
+    assert_eq!(
+        request.resolve_wait(&preview, TIMEOUT).unwrap().len(),
+        8 * 8 * 4
+    );
+
+    preview.destroy();
+    main_view.destroy();
+    RenderCore::destroy(core);
+    assert_eq!(
+        common::validation_errors(),
+        0,
+        "validation errors or leaked objects"
+    );
+}
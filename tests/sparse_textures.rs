@@ -0,0 +1,268 @@
+/*
+ * Sparse textures on a headless surface with validation on, where the device has them,
+ * and the page math on its own. Validation errors logged by the object tracker on
+ * destroy count as leaks.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::capabilities::{CapabilityError, DeviceFeature};
+use rend_vk::format::Format;
+use rend_vk::renderer::Renderer;
+use rend_vk::sparse::{self, Page, PageBinding, SparseError, SparsePages};
+use rend_vk::texture::{MipMap, Residency};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    let mut renderer = common::make_renderer("pipeline.json", 64, 64);
+    let mut config = renderer.config().clone();
+    config.readback_bytes = 8 * 1024 * 1024;
+    renderer.set_config(config);
+    renderer
+}
+
+use common::finish_without_leaks as finish;
+
+#[test]
+fn bound_pages_read_back_what_got_uploaded() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let size = 1024;
+    let name = "terrain".to_string();
+    let texture = match renderer.gen_texture_sparse(name, Format::R8G8B8A8_UNORM, size, size, 1) {
+        Ok(e) => e,
+        Err(e) => {
+            assert!(!renderer.capabilities().is_sparse_residency_enabled);
+            assert_eq!(
+                e,
+                SparseError::Unavailable(CapabilityError {
+                    feature: DeviceFeature::SparseResidency
+                })
+            );
+            finish(renderer);
+            return;
+        }
+    };
+    let sparse = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .sparse
+        .clone()
+        .unwrap();
+    let [width, height] = [sparse.granularity.width, sparse.granularity.height];
+    assert!(size >= width * 3 && size >= height * 3);
+    let page = |x: u32, y: u32| PageBinding {
+        mip: 0,
+        offset: [x * width, y * height],
+        extent: [width, height],
+    };
+    let pool = renderer.create_page_pool("terrain", 4 * sparse.page_size);
+    renderer
+        .bind_texture_pages(texture, &[page(0, 0), page(1, 1)], pool)
+        .unwrap();
+    assert_eq!(
+        renderer.page_pool(pool).unwrap().used(),
+        2 * sparse.page_size
+    );
+    // Staged for a page left unbound too, its writes go nowhere
+    let regions = [page(0, 0), page(1, 0), page(1, 1)];
+    let bytes = vec![0xab; 3 * (width * height * 4) as usize];
+    renderer
+        .upload_texture_pages(texture, &regions, &bytes)
+        .unwrap();
+    for _ in 0..4 {
+        renderer.render();
+    }
+    assert_eq!(
+        renderer.texture_residency(texture).unwrap(),
+        Residency::Resident
+    );
+
+    let mut request = renderer.read_texture(texture).unwrap();
+    renderer.render();
+    let texels = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    let texel = |x: u32, y: u32| texels[((y * size + x) * 4) as usize..][..4].to_vec();
+    assert_eq!(texel(0, 0), [0xab; 4]);
+    assert_eq!(texel(width * 2 - 1, height * 2 - 1), [0xab; 4]);
+    assert_eq!(texel(width, 0), [0; 4]);
+    assert_eq!(texel(width * 2, height * 2), [0; 4]);
+
+    // Pages go back to the pool once the frames before the unbind are done
+    renderer
+        .unbind_texture_pages(texture, &[page(0, 0)])
+        .unwrap();
+    for _ in 0..4 {
+        renderer.render();
+    }
+    assert_eq!(renderer.page_pool(pool).unwrap().used(), sparse.page_size);
+    let mut request = renderer.read_texture(texture).unwrap();
+    renderer.render();
+    let texels = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    let texel = |x: u32, y: u32| texels[((y * size + x) * 4) as usize..][..4].to_vec();
+    assert_eq!(texel(0, 0), [0; 4]);
+    assert_eq!(texel(width, height), [0xab; 4]);
+
+    let tiny = renderer.create_page_pool("tiny", 0);
+    assert_eq!(
+        renderer.bind_texture_pages(texture, &[page(2, 2)], tiny),
+        Err(SparseError::PoolExhausted {
+            pool: "tiny".to_string(),
+            budget: 0,
+            needed: sparse.page_size,
+        })
+    );
+    let off_grid = PageBinding {
+        offset: [1, 0],
+        ..page(2, 2)
+    };
+    assert_eq!(
+        renderer.bind_texture_pages(texture, &[off_grid], pool),
+        Err(SparseError::BadRegion(off_grid))
+    );
+    renderer.free_texture(texture).unwrap();
+    renderer.render();
+    assert_eq!(renderer.page_pool(pool).unwrap().used(), 0);
+    assert_eq!(
+        renderer.bind_texture_pages(texture, &[page(0, 0)], pool),
+        Err(SparseError::StaleHandle)
+    );
+    finish(renderer);
+}
+
+/*
+ * Page math of a made up 300x200 texture with 64x32 pages and the mips from 3 on in the
+ * tail, no device involved.
+ */
+fn made_up_pages() -> (SparsePages, Vec<MipMap>) {
+    let pages = SparsePages {
+        granularity: vk::Extent2D {
+            width: 64,
+            height: 32,
+        },
+        page_size: 65536,
+        memory_type_bits: 1,
+        mip_tail_first: 3,
+        mip_tail_size: 65536,
+        mip_tail_offset: 1 << 20,
+        bound: HashMap::new(),
+        is_initialized: false,
+        staged: Vec::new(),
+    };
+    (
+        pages,
+        sparse::mip_maps_of(Format::R8G8B8A8_UNORM, 300, 200, 5),
+    )
+}
+
+#[test]
+fn regions_cover_the_pages_they_touch() {
+    let (pages, mip_maps) = made_up_pages();
+    let region = PageBinding {
+        mip: 0,
+        offset: [64, 32],
+        extent: [128, 32],
+    };
+    assert_eq!(
+        pages.pages_of(&mip_maps, region, true).unwrap(),
+        [
+            Page::Texels { mip: 0, x: 1, y: 1 },
+            Page::Texels { mip: 0, x: 2, y: 1 },
+        ]
+    );
+    // Stopping at the edge of the mip is on the grid too
+    let edge = PageBinding {
+        mip: 0,
+        offset: [256, 192],
+        extent: [44, 8],
+    };
+    assert_eq!(
+        pages.pages_of(&mip_maps, edge, true).unwrap(),
+        [Page::Texels { mip: 0, x: 4, y: 6 }]
+    );
+    let tail = PageBinding {
+        mip: 4,
+        offset: [0, 0],
+        extent: [18, 12],
+    };
+    assert_eq!(
+        pages.pages_of(&mip_maps, tail, true).unwrap(),
+        [Page::MipTail]
+    );
+}
+
+#[test]
+fn regions_off_the_grid_only_upload() {
+    let (pages, mip_maps) = made_up_pages();
+    let region = PageBinding {
+        mip: 1,
+        offset: [10, 10],
+        extent: [60, 10],
+    };
+    assert_eq!(
+        pages.pages_of(&mip_maps, region, true),
+        Err(SparseError::BadRegion(region))
+    );
+    assert_eq!(
+        pages.pages_of(&mip_maps, region, false).unwrap(),
+        [
+            Page::Texels { mip: 1, x: 0, y: 0 },
+            Page::Texels { mip: 1, x: 1, y: 0 },
+        ]
+    );
+    for region in [
+        PageBinding {
+            mip: 0,
+            offset: [256, 0],
+            extent: [64, 32],
+        },
+        PageBinding {
+            mip: 5,
+            offset: [0, 0],
+            extent: [1, 1],
+        },
+        PageBinding {
+            mip: 0,
+            offset: [0, 0],
+            extent: [0, 32],
+        },
+    ] {
+        assert_eq!(
+            pages.pages_of(&mip_maps, region, false),
+            Err(SparseError::BadRegion(region))
+        );
+    }
+}
+
+#[test]
+fn staged_regions_pack_tightly() {
+    let (mut pages, _) = made_up_pages();
+    pages.staged = vec![
+        PageBinding {
+            mip: 0,
+            offset: [64, 0],
+            extent: [64, 32],
+        },
+        PageBinding {
+            mip: 2,
+            offset: [0, 0],
+            extent: [75, 50],
+        },
+    ];
+    let copies = pages.staged_copy_regions(Format::R8G8B8A8_UNORM, 4096);
+    assert_eq!(copies[0].buffer_offset, 4096);
+    assert_eq!(copies[0].image_offset.x, 64);
+    assert_eq!(copies[1].buffer_offset, 4096 + 64 * 32 * 4);
+    assert_eq!(copies[1].image_subresource.mip_level, 2);
+    assert_eq!(copies[1].image_extent.width, 75);
+    let error = SparseError::BadRegion(pages.staged[1]);
+    assert_eq!(
+        error.to_string(),
+        "region at [0, 0] of [75, 50] texels is off the pages of mip 2"
+    );
+}
@@ -0,0 +1,248 @@
+/*
+ * SPIR-V patching of the hand assembled modules next to this file, their .spvasm being
+ * what got assembled. Runs without a device.
+ */
+use std::collections::BTreeMap;
+
+use rend_vk::reflection::ShaderReflection;
+use rend_vk::spirv_patch::{
+    BindingRemap, LayoutProperty, LayoutRule, RuleMismatch, ShaderPatch, SpirvModule,
+};
+
+const BINDINGS: &[u8] = include_bytes!("spirv_bindings.spv");
+const LAYOUTS: &[u8] = include_bytes!("spirv_layouts.spv");
+
+const OP_DECORATE: u32 = 71;
+const OP_DECORATE_STRING: u32 = 5632;
+const DEBUG_OPCODES: [u32; 9] = [2, 3, 4, 5, 6, 7, 8, 317, 330];
+
+fn words_of(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|e| u32::from_ne_bytes(e.try_into().unwrap()))
+        .collect()
+}
+
+// Word index and opcode of each instruction
+fn instructions(words: &[u32]) -> Vec<(usize, u32)> {
+    let mut instructions = Vec::new();
+    let mut index = 5;
+    while index < words.len() {
+        instructions.push((index, words[index] & 0xFFFF));
+        index += (words[index] >> 16) as usize;
+    }
+    instructions
+}
+
+fn first_of(words: &[u32], opcode: u32) -> usize {
+    instructions(words)
+        .into_iter()
+        .find(|e| e.1 == opcode)
+        .unwrap()
+        .0
+}
+
+fn remap_of(entries: &[(&str, &str)]) -> BindingRemap {
+    let table: BTreeMap<_, _> = entries
+        .iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect();
+    BindingRemap::parse(&table).unwrap()
+}
+
+fn bindings_of(words: &[u32]) -> Vec<(String, u32, u32)> {
+    ShaderReflection::of(words)
+        .bindings
+        .into_iter()
+        .map(|e| (e.name, e.set, e.binding))
+        .collect()
+}
+
+#[test]
+fn identity_remaps_keep_the_binary_as_it_was() {
+    let mut module = SpirvModule::from_bytes(BINDINGS).unwrap();
+    let remap = remap_of(&[("2:0", "2:0"), ("2:1", "2:1")]);
+    assert_eq!(module.remap_bindings(&remap), 2);
+    assert_eq!(module.to_bytes(), BINDINGS);
+    assert_eq!(module.remap_bindings(&BindingRemap::default()), 0);
+    assert_eq!(module.to_bytes(), BINDINGS);
+}
+
+#[test]
+fn remaps_rewrite_only_the_set_and_binding_literals() {
+    let original = words_of(BINDINGS);
+    let mut module = SpirvModule::parse(original.clone()).unwrap();
+    let remap = remap_of(&[("2:0", "0:5"), ("2:1", "0:6"), ("3:0", "0:7")]);
+    assert_eq!(module.remap_bindings(&remap), 2);
+
+    let changed: Vec<_> = (0..original.len())
+        .filter(|e| module.words()[*e] != original[*e])
+        .collect();
+    let decorations: Vec<_> = instructions(&original)
+        .into_iter()
+        .filter(|e| e.1 == OP_DECORATE && original[e.0] >> 16 == 4)
+        .map(|e| e.0 + 3)
+        .collect();
+    // Both literals of both variables, the block decoration has none
+    assert_eq!(changed, decorations);
+    assert_eq!(
+        bindings_of(module.words()),
+        [("params".to_string(), 0, 5), ("albedo".to_string(), 0, 6)]
+    );
+    // Instructions it doesn't know about included
+    let unknown = first_of(&original, OP_DECORATE_STRING);
+    let len = (original[unknown] >> 16) as usize;
+    assert_eq!(
+        module.words()[unknown..unknown + len],
+        original[unknown..unknown + len]
+    );
+}
+
+#[test]
+fn remap_tables_take_pairs_of_set_and_binding() {
+    let table = |from: &str, to: &str| BTreeMap::from([(from.to_string(), to.to_string())]);
+    assert!(BindingRemap::parse(&table("2:0", "0")).is_err());
+    assert!(BindingRemap::parse(&table("a:0", "0:1")).is_err());
+    let remap = BindingRemap::parse(&table(" 2 : 0", "0:1")).unwrap();
+    assert_eq!(remap.get(2, 0), Some((0, 1)));
+
+    let mut both = table("2:0", "0:1");
+    both.insert("2:1".to_string(), "0:1".to_string());
+    let error = BindingRemap::parse(&both).unwrap_err();
+    assert_eq!(error.entry, "2:1 -> 0:1");
+}
+
+#[test]
+fn stripping_drops_the_debug_instructions_only() {
+    let original = words_of(BINDINGS);
+    let mut module = SpirvModule::parse(original.clone()).unwrap();
+    // OpString, OpSource, 6 names, OpModuleProcessed and OpLine
+    assert_eq!(module.strip_debug_info(), 10);
+
+    let kept: Vec<u32> = original[..5]
+        .iter()
+        .copied()
+        .chain(
+            instructions(&original)
+                .into_iter()
+                .filter(|e| !DEBUG_OPCODES.contains(&e.1))
+                .flat_map(|(index, _)| {
+                    let len = (original[index] >> 16) as usize;
+                    original[index..index + len].to_vec()
+                }),
+        )
+        .collect();
+    assert_eq!(module.words(), kept);
+    assert!(module.words().len() < original.len());
+    let stripped = SpirvModule::parse(module.into_words()).unwrap();
+    let bindings: Vec<_> = bindings_of(stripped.words())
+        .into_iter()
+        .map(|e| (e.1, e.2))
+        .collect();
+    assert_eq!(bindings, [(2, 0), (2, 1)]);
+}
+
+#[test]
+fn malformed_modules_point_at_the_problem() {
+    let original = words_of(BINDINGS);
+    let error = |words: Vec<u32>| SpirvModule::parse(words).unwrap_err();
+
+    let mut magic = original.clone();
+    magic[0] = 0x12345678;
+    assert_eq!(error(magic).instruction, "header");
+    let mut swapped = original.clone();
+    swapped[0] = swapped[0].swap_bytes();
+    assert!(error(swapped).problem.contains("byte swapped"));
+    let mut version = original.clone();
+    version[1] = 0x00020000;
+    assert_eq!(error(version).offset, 4);
+    assert!(error(original[..3].to_vec()).problem.contains("header"));
+    let uneven = SpirvModule::from_bytes(&BINDINGS[..BINDINGS.len() - 2]).unwrap_err();
+    assert_eq!(uneven.offset, BINDINGS.len() - 2);
+
+    // Cut in the middle of the instruction
+    let unknown = first_of(&original, OP_DECORATE_STRING);
+    let cut = error(original[..unknown + 2].to_vec());
+    assert_eq!(cut.offset, unknown * 4);
+    assert_eq!(cut.instruction, "opcode 5632");
+    assert!(cut.problem.contains("only 2 are left"), "{}", cut);
+
+    let mut empty = original.clone();
+    empty[unknown] &= 0xFFFF;
+    let empty = error(empty);
+    assert_eq!(empty.offset, unknown * 4);
+    assert!(empty.problem.contains("word count of 0"));
+
+    // Binding decoration without its literal
+    let decoration = instructions(&original)
+        .into_iter()
+        .find(|e| e.1 == OP_DECORATE && original[e.0 + 2] == 33)
+        .unwrap()
+        .0;
+    let mut short = original.clone();
+    short[decoration] = (3 << 16) | OP_DECORATE;
+    let short = error(short);
+    assert_eq!(short.offset, decoration * 4);
+    assert_eq!(short.instruction, "OpDecorate");
+    assert_eq!(
+        short.to_string(),
+        format!(
+            "malformed SPIR-V at byte {} (OpDecorate): decoration 33 takes a single literal, got 0 words",
+            decoration * 4
+        )
+    );
+}
+
+#[test]
+fn blocks_get_checked_against_the_layout_rules() {
+    let module = SpirvModule::from_bytes(LAYOUTS).unwrap();
+    assert_eq!(module.check_block_layouts(LayoutRule::Std140), []);
+
+    let mismatch = |member: &str, property, declared, expected, rule| RuleMismatch {
+        block: "Lights".to_string(),
+        member: member.to_string(),
+        property,
+        declared,
+        expected,
+        rule,
+    };
+    let scalar = LayoutRule::Scalar;
+    assert_eq!(
+        module.check_block_layouts(scalar),
+        [
+            mismatch("direction", LayoutProperty::Offset, 16, 4, scalar),
+            mismatch("weights", LayoutProperty::Offset, 32, 28, scalar),
+            mismatch("weights", LayoutProperty::ArrayStride, 16, 4, scalar),
+            mismatch("view", LayoutProperty::Offset, 64, 40, scalar),
+        ]
+    );
+    let std430 = LayoutRule::Std430;
+    let mismatches = module.check_block_layouts(std430);
+    assert_eq!(
+        mismatches,
+        [
+            mismatch("weights", LayoutProperty::Offset, 32, 28, std430),
+            mismatch("weights", LayoutProperty::ArrayStride, 16, 4, std430),
+            mismatch("view", LayoutProperty::Offset, 64, 48, std430),
+        ]
+    );
+    assert_eq!(
+        mismatches[1].to_string(),
+        "block Lights member weights has array stride 16, std430 lays it out at 4"
+    );
+    // Checking doesn't rewrite anything
+    assert_eq!(module.to_bytes(), LAYOUTS);
+}
+
+#[test]
+fn patches_read_from_the_pipeline() {
+    let patch: ShaderPatch = serde_json::from_str(
+        r#"{ "bindingRemap": { "2:0": "0:5" }, "blockLayout": "std140", "stripDebugInfo": true }"#,
+    )
+    .unwrap();
+    assert_eq!(patch.binding_remap["2:0"], "0:5");
+    assert_eq!(patch.block_layout, Some(LayoutRule::Std140));
+    assert!(patch.strip_debug_info);
+    let empty: ShaderPatch = serde_json::from_str("{}").unwrap();
+    assert!(empty.binding_remap.is_empty() && empty.block_layout.is_none());
+}
@@ -0,0 +1,235 @@
+/*
+ * Split screen viewports. The ui stage of tests/split_screen.json fills the target with
+ * the alpha cutoff of each task as gray, once per viewport with the tasks routed to it,
+ * the present stage copies it once over the whole target. Pixels read back tell which
+ * viewport drew what, on a headless surface with validation on. In
+ * tests/split_screen_layers.json the target is allocated per viewport instead, each
+ * viewport fills and presents a layer of its own.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::events::{RenderEvent, RingBufferSink};
+use rend_vk::pipeline::file::Pass;
+use rend_vk::render_task::{RenderTask, ScissorRect, TaskBounds, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::split_screen::{self, ViewportDesc, MAX_VIEWPORTS};
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    let mut renderer = common::make_renderer("tests/split_screen.json", SIZE, SIZE);
+    renderer.set_event_sink(Box::new(RingBufferSink::new(256)));
+    renderer
+}
+
+fn fill(gray: u8, viewport_mask: u8, scissor: Option<ScissorRect>) -> RenderTask {
+    RenderTask {
+        mesh: Renderer::TEST_TRIANGLE,
+        instance_count: 1,
+        kind: TaskKind::Fullscreen,
+        resources: Default::default(),
+        variant: None,
+        alpha_cutoff: gray as f32 / 255.0,
+        is_two_sided: false,
+        view_depth: 0.0,
+        object_id: None,
+        bounds: TaskBounds::None,
+        scissor,
+        viewport_mask,
+    }
+}
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D { width, height },
+    }
+}
+
+// Left and right halves of the target
+fn halves() -> Vec<ViewportDesc> {
+    split_screen::side_by_side(rect(0, 0, SIZE, SIZE), 2)
+        .into_iter()
+        .map(ViewportDesc::new)
+        .collect()
+}
+
+// Renders the tasks and reads the gray of every pixel back, row by row
+fn render_grays(renderer: &mut Renderer, tasks: Vec<RenderTask>) -> Vec<u8> {
+    for task in tasks {
+        renderer.add_task_to_queue(task);
+    }
+    let mut request = renderer.read_attachment("ui").unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let bytes = request.resolve_wait(renderer, TIMEOUT).unwrap();
+    assert_eq!(bytes.len() as u32, SIZE * SIZE * 4);
+    bytes.chunks_exact(4).map(|e| e[0]).collect()
+}
+
+fn assert_grays(grays: &[u8], expected_at: impl Fn(u32, u32) -> u8) {
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let gray = grays[(y * SIZE + x) as usize];
+            assert_eq!(gray, expected_at(x, y), "pixel {}, {}", x, y);
+        }
+    }
+}
+
+// Viewports drawn in the frames whose stage times came back since the last call
+fn timed_viewports(renderer: &mut Renderer) -> Vec<u32> {
+    let mut viewports: Vec<_> = renderer
+        .drain_events()
+        .into_iter()
+        .filter_map(|e| match e {
+            RenderEvent::StageViewportExecuted {
+                stage, viewport, ..
+            } => {
+                assert_eq!(stage, "ui");
+                Some(viewport)
+            }
+            _ => None,
+        })
+        .collect();
+    viewports.sort_unstable();
+    viewports.dedup();
+    viewports
+}
+
+fn tasks() -> Vec<RenderTask> {
+    vec![
+        fill(80, 0b01, None),
+        fill(160, 0b10, None),
+        // In both, straddling the border of the halves
+        fill(
+            200,
+            u8::MAX,
+            Some(ScissorRect {
+                x: 28,
+                y: 0,
+                width: 8,
+                height: 8,
+            }),
+        ),
+    ]
+}
+
+fn is_straddling(x: u32, y: u32) -> bool {
+    (28..36).contains(&x) && y < 8
+}
+
+#[test]
+fn tasks_draw_in_the_viewports_of_their_mask() {
+    let task = fill(0, 0b0101, None);
+    assert!(split_screen::is_drawn_in(&task, 0));
+    assert!(!split_screen::is_drawn_in(&task, 1));
+    assert!(split_screen::is_drawn_in(&task, 2));
+    let task = fill(0, u8::MAX, None);
+    assert!((0..MAX_VIEWPORTS).all(|e| split_screen::is_drawn_in(&task, e)));
+}
+
+#[test]
+fn side_by_side_regions_cover_the_area() {
+    let regions = split_screen::side_by_side(rect(10, 20, 100, 50), 3);
+    assert_eq!(
+        regions,
+        [
+            rect(10, 20, 33, 50),
+            rect(43, 20, 33, 50),
+            rect(76, 20, 34, 50)
+        ]
+    );
+    assert_eq!(
+        split_screen::side_by_side(rect(0, 0, 64, 64), 1),
+        [rect(0, 0, 64, 64)]
+    );
+}
+
+#[test]
+fn passes_draw_per_viewport_unless_shared() {
+    let pass: Pass =
+        serde_json::from_str(r#"{ "name": "ui", "sharedAcrossViewports": true }"#).unwrap();
+    assert!(pass.is_shared_across_viewports);
+    let pass: Pass = serde_json::from_str(r#"{ "name": "gbuffer" }"#).unwrap();
+    assert!(!pass.is_shared_across_viewports);
+}
+
+#[test]
+fn viewports_draw_their_tasks_in_their_regions() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    renderer.set_viewports(&halves());
+    let grays = render_grays(&mut renderer, tasks());
+    assert_grays(&grays, |x, y| match (is_straddling(x, y), x < SIZE / 2) {
+        (true, _) => 200,
+        (false, true) => 80,
+        (false, false) => 160,
+    });
+    // Timed once the frame is done, read when the next one gets prepared
+    render_grays(&mut renderer, tasks());
+    assert_eq!(timed_viewports(&mut renderer), [0, 1]);
+
+    // The right one draws nothing anymore, its half stays cleared
+    renderer.set_viewport_enabled(1, false);
+    let grays = render_grays(&mut renderer, tasks());
+    assert_grays(&grays, |x, y| match (is_straddling(x, y), x < SIZE / 2) {
+        (true, true) => 200,
+        (false, true) => 80,
+        (_, false) => 0,
+    });
+    // Still the times of the last frame with both
+    timed_viewports(&mut renderer);
+    render_grays(&mut renderer, tasks());
+    assert_eq!(timed_viewports(&mut renderer), [0]);
+    assert_eq!(renderer.frame_stats().viewport_gpu_times.len(), 1);
+
+    // Back to a single view over the whole target, masks don't matter then
+    renderer.set_viewports(&[]);
+    let grays = render_grays(&mut renderer, tasks());
+    assert_grays(&grays, |x, y| if is_straddling(x, y) { 200 } else { 160 });
+    common::finish(renderer);
+}
+
+#[test]
+fn per_viewport_targets_get_a_layer_each() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/split_screen_layers.json", SIZE, SIZE);
+    renderer.set_viewports(&halves());
+    for task in [fill(80, 0b01, None), fill(160, 0b10, None)] {
+        renderer.add_task_to_queue(task);
+    }
+    let mut layers = [0, 1].map(|e| renderer.read_attachment_layer("ui", e).unwrap());
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let [left, right] = layers
+        .each_mut()
+        .map(|e| e.resolve_wait(&renderer, TIMEOUT).unwrap());
+    // Only the region of its viewport is drawn into each
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let i = ((y * SIZE + x) * 4) as usize;
+            if x < SIZE / 2 {
+                assert_eq!(left[i], 80, "left pixel {}, {}", x, y);
+            } else {
+                assert_eq!(right[i], 160, "right pixel {}, {}", x, y);
+            }
+        }
+    }
+    assert_eq!(common::validation_errors(), 0);
+    common::finish(renderer);
+}
+
+#[test]
+#[should_panic(expected = "at most 4 are supported")]
+fn too_many_viewports_panic() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    renderer.set_viewports(&vec![
+        ViewportDesc::new(rect(0, 0, 8, 8));
+        MAX_VIEWPORTS + 1
+    ]);
+}
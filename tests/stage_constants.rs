@@ -0,0 +1,113 @@
+/*
+ * Packing, typing and the GLSL declaration of stage constants, no GPU involved.
+ */
+use glam::Vec3;
+
+use rend_vk::pipeline::file::ConstantDecl;
+use rend_vk::stage_constants::{ConstantType, ConstantValue, StageConstants, UnknownConstant};
+
+fn constants_of(json: &str) -> StageConstants {
+    let decls: Vec<ConstantDecl> = serde_json::from_str(json).unwrap();
+    StageConstants::of("ssao", &decls)
+}
+
+#[test]
+fn packs_in_declaration_order() {
+    let constants = constants_of(
+        r#"[
+            { "name": "radius", "type": "float", "default": 0.5, "min": 0.1, "max": 2 },
+            { "name": "tint", "type": "vec3", "default": [1, 0.5, 0], "min": 0, "max": 1 },
+            { "name": "samples", "type": "uint", "default": 16 },
+            { "name": "offset", "type": "vec2", "default": [-1, 1] }
+        ]"#,
+    );
+    let offsets: Vec<_> = constants.constants().iter().map(|e| e.offset).collect();
+    assert_eq!(offsets, [0, 4, 16, 20]);
+    // Debug flags last, 8 byte aligned
+    assert_eq!(constants.debug_flags_offset(), 32);
+    assert_eq!(constants.size(), 40);
+    let tint = constants.get("tint").unwrap();
+    assert_eq!(tint.default, ConstantValue::Vec3([1.0, 0.5, 0.0]));
+    assert_eq!(tint.min, Some(ConstantValue::Vec3([0.0; 3])));
+    assert_eq!(tint.max, Some(ConstantValue::Vec3([1.0; 3])));
+    assert_eq!(constants.get("samples").unwrap().max, None);
+    let bytes = constants.to_bytes();
+    assert_eq!(bytes.len(), 40);
+    assert_eq!(bytes[0..4], 0.5f32.to_ne_bytes());
+    assert_eq!(bytes[16..20], 16u32.to_ne_bytes());
+    assert_eq!(bytes[32..40], 0u64.to_ne_bytes());
+}
+
+#[test]
+fn rejects_other_types() {
+    let mut constants =
+        constants_of(r#"[{ "name": "tint", "type": "vec3", "default": [1, 1, 1] }]"#);
+    assert_eq!(
+        constants.set("ssao", "tint", 1.0.into()),
+        Err(UnknownConstant::Type {
+            stage: "ssao".to_string(),
+            name: "tint".to_string(),
+            declared: ConstantType::Vec3,
+            provided: ConstantType::Float,
+        })
+    );
+    assert_eq!(
+        constants.set("ssao", "radius", 1.0.into()),
+        Err(UnknownConstant::Name {
+            stage: "ssao".to_string(),
+            name: "radius".to_string(),
+        })
+    );
+    constants
+        .set("ssao", "tint", Vec3::new(0.0, 0.25, 1.0).into())
+        .unwrap();
+    let tint = constants.get("tint").unwrap();
+    assert_eq!(tint.value, ConstantValue::Vec3([0.0, 0.25, 1.0]));
+    assert_eq!(tint.default, ConstantValue::Vec3([1.0; 3]));
+}
+
+#[test]
+#[should_panic(expected = "default of constant count of stage ssao doesn't fit its type int")]
+fn integers_take_no_fractions() {
+    constants_of(r#"[{ "name": "count", "type": "int", "default": 1.5 }]"#);
+}
+
+#[test]
+#[should_panic(expected = "default of constant tint of stage ssao doesn't fit its type vec3")]
+fn vectors_take_every_component() {
+    constants_of(r#"[{ "name": "tint", "type": "vec3", "default": [1, 1] }]"#);
+}
+
+#[test]
+#[should_panic(expected = "stage ssao declares constant radius twice")]
+fn names_are_unique() {
+    constants_of(
+        r#"[
+            { "name": "radius", "type": "float", "default": 1 },
+            { "name": "radius", "type": "float", "default": 2 }
+        ]"#,
+    );
+}
+
+#[test]
+fn glsl_follows_the_declaration() {
+    let constants = constants_of(
+        r#"[
+            { "name": "threshold", "type": "float", "default": 1 },
+            { "name": "levels", "type": "int", "default": 5 }
+        ]"#,
+    );
+    assert_eq!(
+        constants.glsl_source(),
+        "layout(scalar, buffer_reference, buffer_reference_align = 8) readonly buffer StageConstants\n\
+         {\n  float threshold;\n  int levels;\n  uint64_t debugFlags;\n};\n"
+    );
+}
+
+#[test]
+fn values_serialize_with_their_type() {
+    let value = ConstantValue::Vec2([0.5, 1.0]);
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(json, r#"{"type":"vec2","value":[0.5,1.0]}"#);
+    assert_eq!(serde_json::from_str::<ConstantValue>(&json).unwrap(), value);
+}
@@ -0,0 +1,169 @@
+/*
+ * State cache in front of the commands Stage::render records. The pure tests drive the
+ * cache itself, the one on a headless surface with validation on renders the same fills
+ * of tests/scissor.json with the cache on and forced off and compares what it reads back.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use ash::vk;
+
+use rend_vk::config::RendererConfig;
+use rend_vk::pipeline::state_cache::{StateCache, StateCommand};
+use rend_vk::render_task::{RenderTask, ScissorRect, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+
+const SIZE: u32 = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer(is_state_cache_enabled: bool) -> Renderer {
+    let config = RendererConfig {
+        is_state_cache_enabled,
+        ..Default::default()
+    };
+    common::make_renderer_with(config, "tests/scissor.json", SIZE, SIZE)
+}
+
+fn fill(gray: u8, scissor: Option<ScissorRect>) -> RenderTask {
+    RenderTask {
+        alpha_cutoff: gray as f32 / 255.0,
+        scissor,
+        ..common::task(TaskKind::Fullscreen)
+    }
+}
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> Option<ScissorRect> {
+    Some(ScissorRect {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+// Runs of the same scissor and gray, so there is state set twice in a row to drop
+fn fills() -> Vec<RenderTask> {
+    vec![
+        fill(40, None),
+        fill(40, None),
+        fill(90, rect(8, 8, 32, 32)),
+        fill(120, rect(8, 8, 32, 32)),
+        fill(120, rect(8, 8, 32, 32)),
+        fill(200, rect(16, 40, 40, 8)),
+        fill(250, None),
+    ]
+}
+
+// Reads the gray of every pixel back, row by row, after two frames of the same fills
+fn render_grays(renderer: &mut Renderer) -> Vec<u8> {
+    let mut grays = Vec::new();
+    for _ in 0..2 {
+        for task in fills() {
+            renderer.add_task_to_queue(task);
+        }
+        let mut request = renderer.read_attachment("ui").unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        let bytes = request.resolve_wait(renderer, TIMEOUT).unwrap();
+        grays = bytes.chunks_exact(4).map(|e| e[0]).collect();
+    }
+    grays
+}
+
+#[test]
+fn repeated_state_is_dropped() {
+    let mut cache = StateCache::new(true);
+    let pipeline = vk::Pipeline::null();
+    assert!(cache.set_pipeline(pipeline));
+    assert!(!cache.set_pipeline(pipeline));
+    assert!(cache.set_cull_mode(vk::CullModeFlags::BACK));
+    assert!(cache.set_cull_mode(vk::CullModeFlags::NONE));
+    assert!(!cache.set_cull_mode(vk::CullModeFlags::NONE));
+    let counters = cache.counters();
+    assert_eq!(counters.requested(StateCommand::Pipeline), 2);
+    assert_eq!(counters.emitted(StateCommand::Pipeline), 1);
+    assert_eq!(counters.requested(StateCommand::CullMode), 3);
+    assert_eq!(counters.emitted(StateCommand::CullMode), 2);
+    assert_eq!(counters.dropped(), 2);
+}
+
+#[test]
+fn invalidating_forgets_the_state_but_keeps_counting() {
+    let mut cache = StateCache::new(true);
+    let viewport = vk::Viewport {
+        width: 64.0,
+        height: 64.0,
+        max_depth: 1.0,
+        ..Default::default()
+    };
+    assert!(cache.set_viewport(viewport));
+    assert!(!cache.set_viewport(viewport));
+    cache.invalidate();
+    assert_eq!(cache.scissor(), None);
+    assert!(cache.set_viewport(viewport));
+    assert_eq!(cache.counters().requested(StateCommand::Viewport), 3);
+    assert_eq!(cache.counters().emitted(StateCommand::Viewport), 2);
+    assert!(cache.is_enabled());
+
+    // A new command buffer starts counting over
+    cache.begin(false);
+    assert!(!cache.is_enabled());
+    assert_eq!(cache.counters().requested(StateCommand::Viewport), 0);
+}
+
+#[test]
+fn disabled_cache_emits_everything_but_still_counts() {
+    let mut cache = StateCache::new(false);
+    for _ in 0..3 {
+        assert!(cache.set_depth_bounds(0.0, 1.0));
+        assert!(cache.set_index_buffer(vk::Buffer::null(), 256));
+    }
+    let counters = cache.counters();
+    assert_eq!(counters.requested(StateCommand::DepthBounds), 3);
+    assert_eq!(counters.emitted(StateCommand::DepthBounds), 3);
+    assert_eq!(counters.requested(StateCommand::IndexBuffer), 3);
+    assert_eq!(counters.dropped(), 0);
+}
+
+#[test]
+fn descriptors_and_push_constants_depend_on_the_layout() {
+    let mut cache = StateCache::new(true);
+    let layout = vk::PipelineLayout::null();
+    let other: vk::PipelineLayout = vk::Handle::from_raw(1);
+    assert!(cache.set_descriptors(layout, &[1, 2]));
+    assert!(!cache.set_descriptors(layout, &[1, 2]));
+    assert!(cache.set_descriptors(layout, &[1, 3]));
+    assert!(cache.set_descriptors(other, &[1, 3]));
+    assert!(cache.set_push_constants(layout, &[1, 2, 3, 4]));
+    assert!(!cache.set_push_constants(layout, &[1, 2, 3, 4]));
+    assert!(cache.set_push_constants(other, &[1, 2, 3, 4]));
+    assert!(cache.set_push_constants(other, &[1, 2, 3, 5]));
+    // Pushed again once the tables of another layout got bound in between
+    assert!(cache.set_descriptors(layout, &[1, 3]));
+    assert!(cache.set_descriptors(other, &[1, 3]));
+    assert!(cache.set_push_constants(other, &[1, 2, 3, 5]));
+    assert_eq!(cache.counters().emitted(StateCommand::Descriptors), 5);
+    assert_eq!(cache.counters().emitted(StateCommand::PushConstants), 4);
+}
+
+#[test]
+fn cache_renders_the_same_as_without() {
+    let _serial = common::serial();
+    let mut results = Vec::new();
+    for is_state_cache_enabled in [true, false] {
+        let mut renderer = make_renderer(is_state_cache_enabled);
+        let grays = render_grays(&mut renderer);
+        results.push((grays, renderer.frame_stats().state_commands));
+        renderer.destroy();
+        assert_eq!(common::validation_errors(), 0);
+    }
+    let (cached_grays, cached) = &results[0];
+    let (uncached_grays, uncached) = &results[1];
+    assert_eq!(cached_grays, uncached_grays);
+    // Same commands asked for, only the cache drops any
+    assert_eq!(cached.requested, uncached.requested);
+    assert_eq!(uncached.dropped(), 0);
+    assert!(cached.dropped() > 0);
+    assert!(cached.emitted(StateCommand::Scissor) < cached.requested(StateCommand::Scissor));
+}
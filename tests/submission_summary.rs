@@ -0,0 +1,101 @@
+/*
+ * Summaries of queued tasks, as Renderer::frame_submission_summary makes them out of
+ * its batches, with made up meshes and stages. No GPU involved.
+ */
+use rend_vk::handle::MeshHandle;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::stats::SubmissionSummary;
+
+const PER_PASS_BYTES: u64 = 256;
+
+fn task(kind: TaskKind, mesh: u32, instance_count: u32) -> RenderTask {
+    RenderTask {
+        instance_count,
+        alpha_cutoff: 0.5,
+        ..RenderTask::new(
+            MeshHandle {
+                index: mesh,
+                generation: 0,
+            },
+            kind,
+        )
+    }
+}
+
+// Mesh n has n * 100 vertices, static meshes get drawn by two stages and lights by one,
+// draw data is 64 bytes per instance
+fn summary_of(tasks: &[RenderTask]) -> SubmissionSummary {
+    SubmissionSummary::of(
+        tasks,
+        PER_PASS_BYTES,
+        |task| task.mesh.index as u64 * 100,
+        |kind| match kind {
+            TaskKind::MeshStatic => 2,
+            TaskKind::LightPoint => 1,
+            _ => 0,
+        },
+        |task| 64 * task.instance_count as u64,
+    )
+}
+
+#[test]
+fn empty_queues_only_have_per_pass_data() {
+    let summary = summary_of(&[]);
+    assert_eq!(summary.tasks(), 0);
+    assert_eq!(summary.unique_meshes, 0);
+    assert_eq!(summary.estimated_vertices, 0);
+    assert_eq!(summary.draw_data_bytes, PER_PASS_BYTES);
+}
+
+#[test]
+fn tasks_get_counted_per_kind() {
+    let summary = summary_of(&[
+        task(TaskKind::MeshStatic, 1, 1),
+        task(TaskKind::MeshStatic, 2, 1),
+        task(TaskKind::LightPoint, 1, 1),
+    ]);
+    assert_eq!(summary.tasks(), 3);
+    assert_eq!(summary.tasks_by_kind[TaskKind::MeshStatic.to_usize()], 2);
+    assert_eq!(summary.tasks_by_kind[TaskKind::LightPoint.to_usize()], 1);
+    assert_eq!(summary.tasks_by_kind[TaskKind::Fullscreen.to_usize()], 0);
+}
+
+#[test]
+fn meshes_count_once_across_kinds() {
+    let summary = summary_of(&[
+        task(TaskKind::MeshStatic, 3, 1),
+        task(TaskKind::MeshStatic, 3, 4),
+        task(TaskKind::LightPoint, 3, 1),
+        task(TaskKind::LightPoint, 5, 1),
+    ]);
+    assert_eq!(summary.unique_meshes, 2);
+}
+
+#[test]
+fn vertices_scale_with_instances_and_stages() {
+    let summary = summary_of(&[
+        // 200 vertices, 3 instances, 2 stages
+        task(TaskKind::MeshStatic, 2, 3),
+        // 100 vertices, 1 instance, 1 stage
+        task(TaskKind::LightPoint, 1, 1),
+        // No stage draws it
+        task(TaskKind::Fullscreen, 4, 1),
+    ]);
+    assert_eq!(summary.estimated_vertices, 200 * 3 * 2 + 100);
+    assert_eq!(summary.draw_data_bytes, PER_PASS_BYTES + 64 * 5);
+}
+
+#[test]
+fn summaries_follow_the_iterator() {
+    let batches = [
+        vec![task(TaskKind::MeshStatic, 1, 1)],
+        vec![],
+        vec![task(TaskKind::LightPoint, 2, 2)],
+    ];
+    // Same as the renderer does over its batches
+    let summary = SubmissionSummary::of(batches.iter().flatten(), 0, |_| 1, |_| 1, |_| 0);
+    assert_eq!(summary.tasks(), 2);
+    assert_eq!(summary.estimated_vertices, 3);
+    let cleared = SubmissionSummary::of(batches[1].iter(), 0, |_| 1, |_| 1, |_| 0);
+    assert_eq!(cleared.tasks(), 0);
+}
@@ -0,0 +1,104 @@
+/*
+ * Task budgets and the trimming of draw data that doesn't fit the frame, the
+ * bookkeeping Renderer::try_add_task_to_queue and render go by. No GPU involved.
+ */
+use rend_vk::config::{self, RendererConfig, TaskRejected};
+use rend_vk::render_task::TaskKind;
+use rend_vk::stats::FrameStats;
+
+fn config() -> RendererConfig {
+    let mut config = RendererConfig {
+        max_tasks_total: 10,
+        ..Default::default()
+    };
+    config.set_max_tasks_for(TaskKind::MeshStatic, 4);
+    config
+}
+
+#[test]
+fn tasks_fit_until_their_kind_is_full() {
+    let config = config();
+    assert_eq!(config.check_task_budget(TaskKind::MeshStatic, 3, 3), Ok(()));
+    assert_eq!(
+        config.check_task_budget(TaskKind::MeshStatic, 4, 4),
+        Err(TaskRejected::KindBudget {
+            kind: TaskKind::MeshStatic,
+            limit: 4
+        })
+    );
+    // Other kinds keep their own budget
+    assert_eq!(config.check_task_budget(TaskKind::LightDir, 0, 4), Ok(()));
+}
+
+#[test]
+fn tasks_fit_until_the_frame_is_full() {
+    let config = config();
+    assert_eq!(config.check_task_budget(TaskKind::LightDir, 5, 9), Ok(()));
+    assert_eq!(
+        config.check_task_budget(TaskKind::LightDir, 5, 10),
+        Err(TaskRejected::TotalBudget { limit: 10 })
+    );
+    // The kind budget is the one reported when both are exceeded
+    assert_eq!(
+        config.check_task_budget(TaskKind::MeshStatic, 4, 10),
+        Err(TaskRejected::KindBudget {
+            kind: TaskKind::MeshStatic,
+            limit: 4
+        })
+    );
+}
+
+#[test]
+fn zero_budgets_reject_everything() {
+    let mut config = config();
+    config.set_max_tasks_for(TaskKind::Fullscreen, 0);
+    assert!(config
+        .check_task_budget(TaskKind::Fullscreen, 0, 0)
+        .is_err());
+    config.max_tasks_total = 0;
+    assert!(config.check_task_budget(TaskKind::LightDir, 0, 0).is_err());
+}
+
+#[test]
+fn frames_that_fit_keep_every_task() {
+    let sizes = vec![vec![64, 64], vec![], vec![128]];
+    assert_eq!(config::tasks_to_trim(&sizes, 256, 512), [0, 0, 0]);
+}
+
+#[test]
+fn the_largest_kind_loses_its_newest_tasks_first() {
+    // Kind 0 takes 300 bytes, kind 1 takes 200
+    let sizes = vec![vec![100, 100, 100], vec![50, 150]];
+    // 100 over, one task of kind 0 does it
+    assert_eq!(config::tasks_to_trim(&sizes, 0, 400), [1, 0]);
+    // 250 over: kind 0 drops to 200, ties go to the later kind, which gives up its 150
+    assert_eq!(config::tasks_to_trim(&sizes, 0, 250), [1, 1]);
+    assert_eq!(config::tasks_to_trim(&sizes, 0, 200), [2, 1]);
+}
+
+#[test]
+fn fixed_draw_data_counts_against_the_budget() {
+    let sizes = vec![vec![100, 100]];
+    assert_eq!(config::tasks_to_trim(&sizes, 0, 200), [0]);
+    assert_eq!(config::tasks_to_trim(&sizes, 50, 200), [1]);
+}
+
+#[test]
+fn trimming_stops_once_no_task_takes_bytes() {
+    // Tasks without per instance data are free, and fixed data alone doesn't fit
+    let sizes = vec![vec![0, 0], vec![64, 0]];
+    assert_eq!(config::tasks_to_trim(&sizes, 1024, 512), [0, 2]);
+}
+
+#[test]
+fn frame_stats_add_up_the_kinds() {
+    let mut stats = FrameStats::default();
+    stats.accepted_by_kind[TaskKind::MeshStatic.to_usize()] = 4;
+    stats.accepted_by_kind[TaskKind::LightDir.to_usize()] = 2;
+    stats.rejected_by_kind[TaskKind::MeshStatic.to_usize()] = 3;
+    stats.trimmed_by_kind[TaskKind::LightDir.to_usize()] = 1;
+    assert_eq!(stats.accepted(), 6);
+    assert_eq!(stats.rejected(), 3);
+    assert_eq!(stats.trimmed(), 1);
+    assert_eq!(stats.dropped(), 4);
+}
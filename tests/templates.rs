@@ -0,0 +1,249 @@
+/*
+ * Template expansion over the local templates in tests/templates, on the raw pipeline
+ * JSON before it gets parsed into a pipeline. Nothing gets compiled or loaded.
+ */
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use rend_vk::pipeline::template;
+
+const DIR: &str = "tests/templates";
+
+fn expand(mut pipeline: Value) -> Value {
+    template::expand(&mut pipeline, Path::new(DIR));
+    pipeline
+}
+
+fn names(pipeline: &Value, section: &str) -> Vec<String> {
+    pipeline[section]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+fn user_pipeline(passes: Value) -> Value {
+    json!({
+        "targets": [{ "name": "color" }],
+        "programs": [{ "name": "copy" }],
+        "passes": passes
+    })
+}
+
+#[test]
+fn locals_get_the_instance_name_as_prefix() {
+    let pipeline = expand(user_pipeline(json!([
+        { "name": "draw" },
+        { "use": "blur.json", "name": "soft", "params": { "input": "color" } },
+        { "name": "present" }
+    ])));
+    assert_eq!(names(&pipeline, "targets"), ["color", "soft_temp"]);
+    assert_eq!(names(&pipeline, "programs"), ["copy", "soft_blur"]);
+    // Expanded in place of the use
+    assert_eq!(
+        names(&pipeline, "passes"),
+        ["draw", "soft_horizontal", "soft_vertical", "present"]
+    );
+    let horizontal = &pipeline["passes"][1];
+    assert_eq!(horizontal["program"], "soft_blur");
+    assert_eq!(horizontal["outputs"], json!(["soft_temp"]));
+    // Params aren't local, they name the user's attachments
+    assert_eq!(horizontal["inputs"][0]["name"], "color");
+    assert_eq!(horizontal["template"], "blur.json as soft");
+    assert_eq!(pipeline["targets"][1]["group"], "soft_blur");
+    assert!(pipeline["passes"][0].get("template").is_none());
+}
+
+#[test]
+fn unnamed_uses_are_numbered_per_template() {
+    let pipeline = expand(user_pipeline(json!([
+        { "use": "blur.json", "params": { "input": "color" } },
+        { "use": "pyramid.json", "params": { "levels": 2 } },
+        { "use": "blur.json", "params": { "input": "color" } }
+    ])));
+    assert_eq!(
+        names(&pipeline, "targets"),
+        [
+            "color",
+            "blur0_temp",
+            "pyramid0_level_0",
+            "pyramid0_level_1",
+            "blur1_temp"
+        ]
+    );
+}
+
+#[test]
+fn lone_expressions_keep_their_type() {
+    let pipeline = expand(user_pipeline(json!([
+        { "use": "blur.json", "params": { "input": "color", "radius": 3 } }
+    ])));
+    let constants = &pipeline["passes"][0]["constants"];
+    assert_eq!(constants["radius"], json!(3));
+    // Mixing in a float makes it a float
+    assert_eq!(constants["step"], json!(1.5));
+    let pipeline = expand(user_pipeline(json!([{ "use": "pyramid.json" }])));
+    let widths: Vec<_> = pipeline["targets"].as_array().unwrap()[1..]
+        .iter()
+        .map(|e| e["width"].as_f64().unwrap())
+        .collect();
+    assert_eq!(widths, [1.0, 0.5, 0.25]);
+}
+
+#[test]
+fn nested_repeats_see_the_outer_index() {
+    let pipeline = expand(user_pipeline(json!([
+        { "use": "pyramid.json", "name": "p", "params": { "levels": 4 } }
+    ])));
+    // Inner repeats count down from the outer index, "to" excluded
+    assert_eq!(
+        names(&pipeline, "passes"),
+        [
+            "p_reduce_1_from_0",
+            "p_reduce_2_from_1",
+            "p_reduce_2_from_0",
+            "p_reduce_3_from_2",
+            "p_reduce_3_from_1",
+            "p_reduce_3_from_0",
+        ]
+    );
+    let last = &pipeline["passes"][5];
+    assert_eq!(last["outputs"], json!(["p_level_3"]));
+    assert_eq!(last["inputs"][0]["name"], "p_level_0");
+}
+
+#[test]
+fn empty_repeats_expand_to_nothing() {
+    let pipeline = expand(user_pipeline(json!([
+        { "use": "pyramid.json", "name": "p", "params": { "levels": 1 } }
+    ])));
+    assert_eq!(names(&pipeline, "targets"), ["color", "p_level_0"]);
+    assert_eq!(names(&pipeline, "passes"), Vec::<String>::new());
+}
+
+#[test]
+fn pipelines_without_uses_stay_as_they_are() {
+    let pipeline = user_pipeline(json!([{ "name": "draw" }]));
+    assert_eq!(expand(pipeline.clone()), pipeline);
+}
+
+#[test]
+fn unversioned_templates_get_migrated() {
+    let pipeline = expand(user_pipeline(json!([
+        { "use": "blur.json", "name": "soft", "params": { "input": "color" } },
+        { "use": "pyramid.json", "name": "p", "params": { "levels": 2 } }
+    ])));
+    // Version 1 targets kept the usage they used to get regardless of the passes
+    let targets = pipeline["targets"].as_array().unwrap();
+    assert_eq!(
+        targets[1]["extraUsage"],
+        json!(["sampled", "transferSrc", "transferDst"])
+    );
+    // pyramid.json declares the latest version
+    assert!(targets[2..].iter().all(|e| e.get("extraUsage").is_none()));
+    // The user's pipeline gets migrated on its own when read
+    assert!(targets[0].get("extraUsage").is_none());
+}
+
+#[test]
+#[should_panic(expected = "template future.json: pipeline version 99 is newer than")]
+fn templates_for_later_versions_are_rejected() {
+    expand(user_pipeline(json!([{ "use": "future.json" }])));
+}
+
+#[test]
+#[should_panic(
+    expected = "blur.json as soft adds target soft_temp but the pipeline already has one"
+)]
+fn prefixed_locals_colliding_with_user_names_are_rejected() {
+    let mut pipeline = user_pipeline(json!([
+        { "use": "blur.json", "name": "soft", "params": { "input": "color" } }
+    ]));
+    pipeline["targets"] = json!([{ "name": "color" }, { "name": "soft_temp" }]);
+    expand(pipeline);
+}
+
+#[test]
+#[should_panic(expected = "blur.json as soft adds pass soft_horizontal but the pipeline already")]
+fn prefixed_locals_colliding_with_user_passes_are_rejected() {
+    expand(user_pipeline(json!([
+        { "name": "soft_horizontal" },
+        { "use": "blur.json", "name": "soft", "params": { "input": "color" } }
+    ])));
+}
+
+#[test]
+#[should_panic(
+    expected = "blur.json as soft adds target soft_temp but the pipeline already has one"
+)]
+fn instances_sharing_a_name_are_rejected() {
+    expand(user_pipeline(json!([
+        { "use": "blur.json", "name": "soft", "params": { "input": "color" } },
+        { "use": "blur.json", "name": "soft", "params": { "input": "color" } }
+    ])));
+}
+
+#[test]
+#[should_panic(expected = "template blur.json requires parameter input!")]
+fn missing_required_params_are_rejected() {
+    expand(user_pipeline(json!([{ "use": "blur.json" }])));
+}
+
+#[test]
+#[should_panic(expected = "template blur.json has no parameter raduis!")]
+fn unknown_params_are_rejected() {
+    expand(user_pipeline(json!([
+        { "use": "blur.json", "params": { "input": "color", "raduis": 1 } }
+    ])));
+}
+
+#[test]
+#[should_panic(expected = "template nested_use.json can't use other templates!")]
+fn templates_using_templates_are_rejected() {
+    expand(user_pipeline(json!([{ "use": "nested_use.json" }])));
+}
+
+#[test]
+#[should_panic(expected = "repeat to has to be an integer, got 2.5")]
+fn fractional_repeat_bounds_are_rejected() {
+    expand(user_pipeline(json!([{ "use": "bad_repeat.json" }])));
+}
+
+#[test]
+#[should_panic(expected = "unclosed expression in ${input")]
+fn unclosed_expressions_are_rejected() {
+    expand(user_pipeline(json!([
+        { "use": "unclosed.json", "params": { "input": "color" } }
+    ])));
+}
+
+#[test]
+#[should_panic(expected = "failed reading template tests/templates/missing.json")]
+fn missing_templates_are_rejected() {
+    expand(user_pipeline(json!([{ "use": "missing.json" }])));
+}
+
+#[cfg(not(feature = "builtin-passes"))]
+#[test]
+#[should_panic(expected = "the builtin-passes feature is disabled")]
+fn builtins_need_their_feature() {
+    expand(user_pipeline(json!([
+        { "use": "builtin:bloom", "params": { "input": "color", "output": "color" } }
+    ])));
+}
+
+#[cfg(feature = "builtin-passes")]
+#[test]
+fn builtins_expand_side_by_side() {
+    let pipeline = expand(user_pipeline(json!([
+        { "use": "builtin:bloom", "params": { "input": "color", "output": "color" } },
+        { "use": "builtin:tonemap", "params": { "input": "color", "output": "default" } }
+    ])));
+    let passes = names(&pipeline, "passes");
+    assert!(passes
+        .iter()
+        .all(|e| e.starts_with("bloom0_") || e.starts_with("tonemap0_")));
+    assert_eq!(names(&pipeline, "targets")[0], "color");
+}
@@ -0,0 +1,81 @@
+/*
+ * Textures going over a memory budget, on a headless surface with validation on. The
+ * least recently referenced ones lose their image, and come back with their texels
+ * through the restorer.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::format::Format;
+use rend_vk::handle::TextureHandle;
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::texture::{MipMap, Residency};
+
+const TEXTURE_SIZE: u32 = 32;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn texels(seed: u32) -> Vec<u8> {
+    let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+    (0..size).map(|e| (e * seed) as u8).collect()
+}
+
+fn upload(renderer: &mut Renderer, name: &str, seed: u32) -> TextureHandle {
+    let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+    let mip = MipMap {
+        index: 0,
+        width: TEXTURE_SIZE,
+        height: TEXTURE_SIZE,
+        size,
+        offset: 0,
+    };
+    let texture = renderer.gen_texture(name.to_string(), Format::R8G8B8A8_UNORM, &[mip], size);
+    let data = texels(seed);
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), staging.addr as *mut u8, data.len()) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    texture
+}
+
+#[test]
+fn evicted_textures_come_back_with_their_texels() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("tests/scissor.json", 64, 64);
+    let first = upload(&mut renderer, "first", 7);
+    let second = upload(&mut renderer, "second", 13);
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.texture_residency(first), Ok(Residency::Resident));
+    let resident = renderer.memory_report().texture_bytes;
+
+    // Room for everything but one, neither was ever referenced so the older one goes
+    renderer.set_texture_memory_budget(Some(resident - 1));
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.texture_residency(first), Ok(Residency::Evicted));
+    assert_eq!(renderer.texture_residency(second), Ok(Residency::Resident));
+    assert_eq!(renderer.is_texture_uploaded(first), Ok(false));
+    let report = renderer.memory_report();
+    assert_eq!(report.texture_evictions, 1);
+    assert!(report.texture_bytes < resident, "{}", report.texture_bytes);
+
+    renderer.set_texture_memory_budget(None);
+    renderer.set_texture_restorer(Some(Box::new(|texture, data| {
+        assert_eq!(texture.name, "first");
+        data.copy_from_slice(&texels(7));
+    })));
+    renderer.restore_texture(first).unwrap();
+    assert_eq!(renderer.texture_residency(first), Ok(Residency::Uploading));
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    assert_eq!(renderer.texture_residency(first), Ok(Residency::Resident));
+    assert_eq!(renderer.memory_report().texture_restores, 1);
+    let mut request = renderer.read_texture(first).unwrap();
+    assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+    assert!(bytes == texels(7));
+    common::finish(renderer);
+}
@@ -0,0 +1,214 @@
+/*
+ * Texture feedback, the mips shaders report sampling at. The pure tests decode read back
+ * bytes, the one on a headless surface with validation on draws one mipped texture up
+ * close and another far away with tests/texture_feedback.json and reads what they report.
+ */
+
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::config::RendererConfig;
+use rend_vk::format::Format;
+use rend_vk::handle::TextureHandle;
+use rend_vk::pipeline::file::PerDrawField;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::shader_resource::{Material, MultiResource, ResourceKind, Transform, TransformExtra};
+use rend_vk::texture::MipMap;
+use rend_vk::texture_feedback::{self, FeedbackEntry, TextureExtent, FOOTPRINT_BIAS, NOT_SAMPLED};
+
+const SIZE: u32 = 64;
+const TEXTURE_SIZE: u32 = 256;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    let config = RendererConfig {
+        is_texture_feedback_enabled: true,
+        ..Default::default()
+    };
+    common::make_renderer_with(config, "tests/texture_feedback.json", SIZE, SIZE)
+}
+
+fn extent(side: u32, mip_levels: u32) -> Option<TextureExtent> {
+    Some(TextureExtent {
+        width: side,
+        height: side,
+        mip_levels,
+    })
+}
+
+fn bytes_of(footprints: &[u32]) -> Vec<u8> {
+    footprints.iter().flat_map(|e| e.to_ne_bytes()).collect()
+}
+
+// Full chain down to 1x1, tightly packed
+fn mip_chain(size: u32) -> Vec<MipMap> {
+    let mut offset = 0;
+    (0..=size.ilog2())
+        .map(|index| {
+            let side = size >> index;
+            let mip = MipMap {
+                index,
+                width: side,
+                height: side,
+                size: side * side * 4,
+                offset,
+            };
+            offset += mip.size;
+            mip
+        })
+        .collect()
+}
+
+fn gen_gray_texture(renderer: &mut Renderer, name: &str) -> TextureHandle {
+    let mips = mip_chain(TEXTURE_SIZE);
+    let size = mips.iter().map(|e| e.size).sum();
+    let texture = renderer.gen_texture(name.to_string(), Format::R8G8B8A8_UNORM, &mips, size);
+    let staging = renderer
+        .fetch_texture(texture)
+        .unwrap()
+        .staging
+        .as_ref()
+        .unwrap();
+    unsafe { std::ptr::write_bytes(staging.addr as *mut u8, 0x80, size as usize) };
+    renderer.queue_texture_for_uploading(texture).unwrap();
+    texture
+}
+
+// The test triangle over half the target, shrunk by the scale, its coordinates too
+fn textured_triangle(texture: TextureHandle, scale: f32, uv_scaling: f32) -> RenderTask {
+    let mvp = Mat4::from_scale(Vec3::new(scale, scale, 1.0));
+    let mut resources = HashMap::new();
+    resources.insert(
+        ResourceKind::Transform,
+        MultiResource::Transform(vec![Transform {
+            mvp,
+            mv: Mat4::IDENTITY,
+        }]),
+    );
+    resources.insert(
+        ResourceKind::TransformExtra,
+        MultiResource::TransformExtra(vec![TransformExtra { prev_mvp: mvp }]),
+    );
+    resources.insert(
+        ResourceKind::Material,
+        MultiResource::Material(vec![Material {
+            shininess: 0.0,
+            scaling: uv_scaling,
+            diffuse_handle: texture.index,
+            normal_handle: texture.index,
+            glow_handle: texture.index,
+            diffuse_sampler: 0,
+            normal_sampler: 0,
+            glow_sampler: 0,
+            padding: 0,
+        }]),
+    );
+    RenderTask {
+        resources,
+        is_two_sided: true,
+        ..common::task(TaskKind::MeshStatic)
+    }
+}
+
+fn entry_of(renderer: &Renderer, texture: TextureHandle) -> Option<FeedbackEntry> {
+    renderer
+        .texture_feedback()
+        .iter()
+        .find(|e| e.texture == texture.index)
+        .copied()
+}
+
+#[test]
+fn feedback_field_is_an_address() {
+    assert_eq!(
+        PerDrawField::TextureFeedback.member_names(),
+        &["textureFeedback"]
+    );
+    assert_eq!(PerDrawField::TextureFeedback.size(), 8);
+}
+
+#[test]
+fn footprints_decode_to_mips_of_the_texture() {
+    // A quarter texel per pixel, two texels and 2^20 texels per pixel of a 1x1 image
+    let bytes = bytes_of(&[
+        FOOTPRINT_BIAS - 2,
+        FOOTPRINT_BIAS + 1,
+        FOOTPRINT_BIAS - 20,
+        NOT_SAMPLED,
+    ]);
+    let entries = texture_feedback::decode(&bytes, |_| extent(256, 9));
+    let mips: Vec<u32> = entries.iter().map(|e| e.min_mip).collect();
+    assert_eq!(mips, [6, 8, 0, NOT_SAMPLED]);
+    assert!(entries[..3].iter().all(|e| e.is_sampled));
+    assert!(!entries[3].is_sampled);
+
+    // Clamped to the mips the texture has, the largest side counts
+    let entries = texture_feedback::decode(&bytes[..4], |_| extent(256, 4));
+    assert_eq!(entries[0].min_mip, 3);
+    let wide = texture_feedback::decode(&bytes[..4], |_| {
+        Some(TextureExtent {
+            width: 1024,
+            height: 16,
+            mip_levels: 11,
+        })
+    });
+    assert_eq!(wide[0].min_mip, 8);
+}
+
+#[test]
+fn ids_without_texture_are_left_out() {
+    let bytes = bytes_of(&[FOOTPRINT_BIAS, NOT_SAMPLED, FOOTPRINT_BIAS, NOT_SAMPLED]);
+    let entries = texture_feedback::decode(&bytes, |id| extent(1, 1).filter(|_| id % 2 == 0));
+    let ids: Vec<u32> = entries.iter().map(|e| e.texture).collect();
+    assert_eq!(ids, [0, 2]);
+    // Trailing bytes short of an entry too
+    assert!(texture_feedback::decode(&bytes[..3], |_| extent(1, 1)).is_empty());
+}
+
+#[test]
+fn close_textures_report_finer_mips_than_distant_ones() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let near = gen_gray_texture(&mut renderer, "near");
+    let far = gen_gray_texture(&mut renderer, "far");
+    let unused = gen_gray_texture(&mut renderer, "unused");
+    renderer.render();
+    renderer.render();
+
+    // Magnified over half the target, and the whole texture over a few pixels
+    let mut entries = None;
+    for _ in 0..8 {
+        renderer.add_task_to_queue(textured_triangle(near, 1.0, 1.0 / SIZE as f32));
+        renderer.add_task_to_queue(textured_triangle(far, 1.0 / 16.0, 1.0));
+        let mut request = renderer.read_attachment("color").unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        request.resolve_wait(&renderer, TIMEOUT).unwrap();
+        let found = (entry_of(&renderer, near), entry_of(&renderer, far));
+        if let (Some(n), Some(f)) = found {
+            if n.is_sampled && f.is_sampled {
+                entries = Some((n, f));
+                break;
+            }
+        }
+    }
+    let (near_entry, far_entry) = entries.expect("no frame read back reported both textures");
+    assert_eq!(near_entry.min_mip, 0);
+    assert!(far_entry.min_mip >= 5, "{:?}", far_entry);
+
+    let unused_entry = entry_of(&renderer, unused).unwrap();
+    assert!(!unused_entry.is_sampled);
+    assert_eq!(unused_entry.min_mip, NOT_SAMPLED);
+    let ids: Vec<u32> = renderer
+        .texture_feedback()
+        .iter()
+        .map(|e| e.texture)
+        .collect();
+    assert!(ids.windows(2).all(|e| e[0] < e[1]));
+
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
@@ -0,0 +1,119 @@
+/*
+ * Textures created from loader threads while the owning thread keeps rendering, on a
+ * headless surface with validation on. Each loader fills its textures with a byte of its
+ * own, the contents get read back once all of them are resident.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::format::Format;
+use rend_vk::handle::TextureHandle;
+use rend_vk::renderer::Renderer;
+use rend_vk::texture::{MipMap, Residency};
+use rend_vk::texture_loader::CreateTextureError;
+
+const SIZE: u32 = 64;
+const TEXTURE_SIZE: u32 = 16;
+const THREADS: u8 = 4;
+const TEXTURES_PER_THREAD: u8 = 12;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_renderer() -> Renderer {
+    common::make_renderer("tests/offscreen.json", SIZE, SIZE)
+}
+
+fn mip() -> MipMap {
+    MipMap {
+        index: 0,
+        width: TEXTURE_SIZE,
+        height: TEXTURE_SIZE,
+        size: TEXTURE_SIZE * TEXTURE_SIZE * 4,
+        offset: 0,
+    }
+}
+
+#[test]
+fn threads_create_textures_while_rendering() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let loader = renderer.texture_loader();
+            std::thread::spawn(move || {
+                let mut created: Vec<(TextureHandle, u8)> = Vec::new();
+                for i in 0..TEXTURES_PER_THREAD {
+                    let fill = thread * TEXTURES_PER_THREAD + i + 1;
+                    let name = format!("loaded {} {}", thread, i);
+                    let pending =
+                        loader.create_texture(name, Format::R8G8B8A8_UNORM, &[mip()], size);
+                    assert!(pending.write_staging(0, &vec![fill; size as usize]));
+                    pending.queue_for_uploading();
+                    let texture = pending
+                        .wait(TIMEOUT)
+                        .expect("texture not created in time!")
+                        .unwrap();
+                    created.push((texture, fill));
+                }
+                created
+            })
+        })
+        .collect();
+    while !threads.iter().all(|e| e.is_finished()) {
+        renderer.render();
+    }
+    let created: Vec<_> = threads
+        .into_iter()
+        .flat_map(|e| e.join().unwrap())
+        .collect();
+    assert_eq!(created.len(), (THREADS * TEXTURES_PER_THREAD) as usize);
+    renderer.flush_gpu_work(TIMEOUT).unwrap();
+    for (texture, fill) in &created {
+        assert_eq!(
+            renderer.texture_residency(*texture).unwrap(),
+            Residency::Resident
+        );
+        let mut request = renderer.read_texture(*texture).unwrap();
+        renderer.flush_gpu_work(TIMEOUT).unwrap();
+        let bytes = request.try_resolve(&renderer).unwrap();
+        assert!(bytes.iter().all(|e| e == fill));
+    }
+    for (texture, _) in created {
+        renderer.free_texture(texture).unwrap();
+    }
+    renderer.destroy();
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn pending_textures_fail_once_the_renderer_is_destroyed() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer();
+    let loader = renderer.texture_loader();
+    let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+    let pending = loader.create_texture("late".to_string(), Format::R8G8B8A8_UNORM, &[mip()], size);
+    renderer.destroy();
+    assert_eq!(pending.id(), Some(Err(CreateTextureError::Destroyed)));
+    assert!(!pending.write_staging(0, &[1, 2, 3, 4]));
+    let after = loader.create_texture("after".to_string(), Format::R8G8B8A8_UNORM, &[mip()], size);
+    assert_eq!(after.id(), Some(Err(CreateTextureError::Destroyed)));
+    assert_eq!(common::validation_errors(), 0);
+}
+
+#[test]
+fn errors_describe_what_went_wrong() {
+    let error = CreateTextureError::OutOfStaging {
+        size: 4096,
+        available: 1024,
+    };
+    assert_eq!(
+        error.to_string(),
+        "loader staging can't fit 4096 bytes, 1024 are available"
+    );
+    assert_eq!(
+        CreateTextureError::OutOfTextureIds.to_string(),
+        "ran out of texture ids"
+    );
+}
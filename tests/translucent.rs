@@ -0,0 +1,87 @@
+/*
+ * Translucent tasks: the view depth they get from their Transform, the back to front
+ * order they draw in and, on a headless surface with validation on, the rejection of
+ * ones without a Transform.
+ */
+
+mod common;
+
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec3};
+
+use rend_vk::config::TaskRejected;
+use rend_vk::pipeline::plan;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::shader_resource::{MultiResource, ResourceKind, Transform};
+
+fn at_depth(depth: f32) -> HashMap<ResourceKind, MultiResource> {
+    let mv = Mat4::from_translation(Vec3::new(1.0, 2.0, -depth));
+    let transforms = vec![Transform { mvp: mv, mv }];
+    [(
+        ResourceKind::Transform,
+        MultiResource::Transform(transforms),
+    )]
+    .into()
+}
+
+fn translucent(resources: HashMap<ResourceKind, MultiResource>) -> RenderTask {
+    RenderTask {
+        view_depth: RenderTask::view_depth_of(&resources).unwrap_or_default(),
+        resources,
+        ..common::task(TaskKind::Translucent)
+    }
+}
+
+#[test]
+fn view_depth_comes_from_the_first_transform() {
+    assert_eq!(RenderTask::view_depth_of(&at_depth(5.0)), Some(5.0));
+    assert_eq!(RenderTask::view_depth_of(&HashMap::new()), None);
+    let empty = [(
+        ResourceKind::Transform,
+        MultiResource::Transform(Vec::new()),
+    )]
+    .into();
+    assert_eq!(RenderTask::view_depth_of(&empty), None);
+}
+
+#[test]
+fn translucent_tasks_sort_back_to_front() {
+    let mut tasks: Vec<_> = [2.0, 8.0, 0.5, 8.0, 4.0]
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| RenderTask {
+            instance_count: i as u32 + 1,
+            ..translucent(at_depth(e))
+        })
+        .collect();
+    plan::sort_back_to_front(&mut tasks);
+    let order: Vec<_> = tasks
+        .iter()
+        .map(|e| (e.view_depth, e.instance_count))
+        .collect();
+    // Ties keep the order they were queued in
+    assert_eq!(order, [(8.0, 2), (8.0, 4), (4.0, 5), (2.0, 1), (0.5, 3)]);
+}
+
+#[test]
+fn translucent_tasks_need_a_transform() {
+    let _serial = common::serial();
+    let mut renderer = common::make_renderer("pipeline.json", 64, 64);
+    assert_eq!(
+        renderer.try_add_task_to_queue(translucent(HashMap::new())),
+        Err(TaskRejected::MissingResource {
+            kind: TaskKind::Translucent,
+            resource: ResourceKind::Transform,
+        })
+    );
+    assert_eq!(
+        renderer.try_add_task_to_queue(translucent(at_depth(3.0))),
+        Ok(())
+    );
+    renderer.render();
+    let stats = renderer.frame_stats();
+    assert_eq!(stats.rejected_by_kind[TaskKind::Translucent.to_usize()], 1);
+    assert_eq!(stats.accepted_by_kind[TaskKind::Translucent.to_usize()], 1);
+    common::finish(renderer);
+}
@@ -0,0 +1,63 @@
+/*
+ * Texture uploads through each RendererConfig::upload_queue, on a headless surface with
+ * validation on. Devices without a compute family apart from graphics upload the async
+ * ones on the graphics queue, the uploaded texels read back the same either way.
+ */
+
+mod common;
+
+use std::time::Duration;
+
+use rend_vk::config::{RendererConfig, UploadQueue};
+use rend_vk::format::Format;
+use rend_vk::renderer::FrameOutcome;
+use rend_vk::texture::MipMap;
+
+const TEXTURE_SIZE: u32 = 32;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn uploaded_texels_read_back_through_every_queue() {
+    let _serial = common::serial();
+    for upload_queue in [
+        UploadQueue::Graphics,
+        UploadQueue::AsyncExclusive,
+        UploadQueue::AsyncConcurrent,
+    ] {
+        let config = RendererConfig {
+            upload_queue,
+            ..Default::default()
+        };
+        let mut renderer = common::make_renderer_with(config, "tests/scissor.json", 64, 64);
+        let size = TEXTURE_SIZE * TEXTURE_SIZE * 4;
+        let mip = MipMap {
+            index: 0,
+            width: TEXTURE_SIZE,
+            height: TEXTURE_SIZE,
+            size,
+            offset: 0,
+        };
+        let texture =
+            renderer.gen_texture("pattern".to_string(), Format::R8G8B8A8_UNORM, &[mip], size);
+        let texels: Vec<u8> = (0..size).map(|e| (e * 7) as u8).collect();
+        let staging = renderer
+            .fetch_texture(texture)
+            .unwrap()
+            .staging
+            .as_ref()
+            .unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(texels.as_ptr(), staging.addr as *mut u8, texels.len())
+        };
+        renderer.queue_texture_for_uploading(texture).unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        if upload_queue == UploadQueue::Graphics {
+            assert_eq!(renderer.frame_stats().async_upload_bytes, 0);
+        }
+        let mut request = renderer.read_texture(texture).unwrap();
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+        let bytes = request.resolve_wait(&renderer, TIMEOUT).unwrap();
+        assert!(bytes == texels, "{:?}", upload_queue);
+        common::finish(renderer);
+    }
+}
@@ -0,0 +1,315 @@
+/*
+ * Quantized vertex formats: the encoders on their own and through mesh_opt with no GPU
+ * involved, then quantized meshes drawn on a headless surface with validation on, with
+ * the pipeline of tests/vertex_formats.json, whose MeshAnimated stage reads quantized
+ * attributes.
+ */
+
+mod common;
+
+use ash::vk;
+use glam::{Vec2, Vec3};
+
+use rend_vk::capabilities::DeviceCapabilities;
+use rend_vk::config::TaskRejected;
+use rend_vk::format::Format;
+use rend_vk::mesh_opt::{self, MeshData, MeshIndices, MeshOptFlags, PackedMesh, VertexStream};
+use rend_vk::pipeline::dry_run::DryRun;
+use rend_vk::pipeline::file::Pipeline;
+use rend_vk::render_task::{RenderTask, TaskKind};
+use rend_vk::renderer::{FrameOutcome, Renderer};
+use rend_vk::vertex::{self, QuantizationError};
+use rend_vk::vertex_layout::{
+    AcceptedFormats, StreamFormats, VertexAttribute, VertexAttributeKind, VertexLayout,
+};
+
+// 128 triangles, every stream a multiple of 512 bytes in either format
+const VERTEX_COUNT: u32 = 384;
+// Position, normal and tex coord interleaved
+const STRIDE: u32 = 32;
+
+fn vertices() -> Vec<u8> {
+    let mut floats = Vec::new();
+    for i in 0..VERTEX_COUNT {
+        let t = i as f32 * 0.37;
+        floats.extend(Vec3::new(t.sin() * 40.0, t.cos() * 3.0, t * 0.1).to_array());
+        floats.extend(Vec3::new(t.cos(), t.sin(), 0.5).normalize().to_array());
+        floats.extend([i as f32 / VERTEX_COUNT as f32, (t.sin() + 1.0) * 0.5]);
+    }
+    floats.iter().flat_map(|e| e.to_ne_bytes()).collect()
+}
+
+fn mesh_data(vertices: &[u8]) -> MeshData<'_> {
+    MeshData {
+        vertex_count: VERTEX_COUNT,
+        positions: VertexStream {
+            data: vertices,
+            stride: STRIDE,
+        },
+        normals: Some(VertexStream {
+            data: &vertices[12..],
+            stride: STRIDE,
+        }),
+        tex_coords: Some(VertexStream {
+            data: &vertices[24..],
+            stride: STRIDE,
+        }),
+        indices: MeshIndices::None,
+    }
+}
+
+const QUANTIZE: MeshOptFlags = MeshOptFlags {
+    vertex_cache_reorder: false,
+    overdraw_reorder: false,
+    fetch_reorder: false,
+    deduplicate_vertices: false,
+    quantize: Some(StreamFormats::QUANTIZED),
+};
+
+// Half a step of each format, over every component of the attribute
+const NORMAL_ERROR: f32 = 0.5 / 511.0 * 1.7321;
+const TEX_COORD_ERROR: f32 = 0.5 / 65535.0 * 1.4143;
+
+#[test]
+fn half_floats_round_to_nearest_even() {
+    for (value, half) in [
+        (0.0, 0x0000),
+        (-0.0, 0x8000),
+        (1.0, 0x3c00),
+        (-2.0, 0xc000),
+        (65504.0, 0x7bff),
+        // Smallest subnormal
+        (2f32.powi(-24), 0x0001),
+        (1e-9, 0x0000),
+        (65520.0, 0x7c00),
+        (f32::INFINITY, 0x7c00),
+        // Ties go to the even mantissa
+        (1.0 + 2f32.powi(-11), 0x3c00),
+        (1.0 + 3.0 * 2f32.powi(-11), 0x3c02),
+    ] {
+        assert_eq!(vertex::f32_to_f16(value), half, "{value}");
+    }
+    assert!(vertex::f16_to_f32(vertex::f32_to_f16(f32::NAN)).is_nan());
+    assert!(vertex::f16_to_f32(0x8000).is_sign_negative());
+    assert_eq!(vertex::f16_to_f32(0x0001), 2f32.powi(-24));
+}
+
+#[test]
+fn encoders_round_trip_within_half_a_step() {
+    let positions: Vec<_> = (0..1000)
+        .map(|i| {
+            Vec3::new(
+                i as f32 * 0.731 - 300.0,
+                (i as f32).sin(),
+                1.0 / (i + 1) as f32,
+            )
+        })
+        .collect();
+    let decoded = vertex::decode_positions_f16(&vertex::encode_positions_f16(&positions));
+    for (a, b) in positions.iter().zip(&decoded) {
+        assert!(a.distance(*b) <= a.length() * 2f32.powi(-11), "{a} {b}");
+    }
+
+    let normals: Vec<_> = (0..1000)
+        .map(|i| Vec3::new((i as f32).sin(), (i as f32).cos(), i as f32 / 500.0 - 1.0))
+        .map(Vec3::normalize)
+        .chain([Vec3::X, -Vec3::Y, Vec3::Z])
+        .collect();
+    let decoded = vertex::decode_normals_snorm10(&vertex::encode_normals_snorm10(&normals));
+    for (a, b) in normals.iter().zip(&decoded) {
+        assert!(a.distance(*b) <= NORMAL_ERROR, "{a} {b}");
+    }
+
+    let tex_coords: Vec<_> = (0..1000)
+        .map(|i| Vec2::new(i as f32 / 999.0, ((i as f32).sin() + 1.0) * 0.5))
+        .collect();
+    let decoded =
+        vertex::decode_tex_coords_unorm16(&vertex::encode_tex_coords_unorm16(&tex_coords));
+    for (a, b) in tex_coords.iter().zip(&decoded) {
+        assert!(a.distance(*b) <= TEX_COORD_ERROR, "{a} {b}");
+    }
+}
+
+#[test]
+fn quantizing_halves_the_streams() {
+    let vertices = vertices();
+    let data = mesh_data(&vertices);
+    let mut mesh = data.pack();
+    let sizes_of =
+        |mesh: &PackedMesh| -> Vec<_> { mesh.streams.iter().map(|e| e.0.len()).collect() };
+    let f32_sizes = sizes_of(&mesh);
+    let report = mesh_opt::optimize(&mut mesh, QUANTIZE);
+
+    assert_eq!(mesh.formats, StreamFormats::QUANTIZED);
+    let element_sizes: Vec<_> = mesh.streams.iter().map(|e| e.1).collect();
+    assert_eq!(element_sizes, [8, 4, 4]);
+    let sizes = sizes_of(&mesh);
+    assert_eq!(sizes, [8, 4, 4].map(|e| e * VERTEX_COUNT as usize));
+    assert_eq!(
+        sizes.iter().sum::<usize>() * 2,
+        f32_sizes.iter().sum::<usize>()
+    );
+    let error = report.quantization_error.unwrap();
+    assert!(error.position > 0.0 && error.position <= 40.0 * 2f32.powi(-11) * 1.7321);
+    assert!(error.normal > 0.0 && error.normal <= NORMAL_ERROR);
+    assert!(error.tex_coord <= TEX_COORD_ERROR);
+    // Nothing else ran, the order is the one sent
+    assert!(mesh.indices.is_none());
+    assert_eq!(
+        mesh_opt::optimize(&mut data.pack(), MeshOptFlags::default()).quantization_error,
+        None
+    );
+}
+
+#[test]
+#[should_panic]
+fn quantizing_twice_panics() {
+    let vertices = vertices();
+    let mut mesh = mesh_data(&vertices).pack();
+    vertex::quantize(&mut mesh, StreamFormats::QUANTIZED);
+    vertex::quantize(&mut mesh, StreamFormats::QUANTIZED);
+}
+
+#[test]
+fn missing_attributes_stay_as_they_were() {
+    let vertices = vertices();
+    let data = MeshData {
+        normals: None,
+        tex_coords: None,
+        ..mesh_data(&vertices)
+    };
+    let mut mesh = data.pack();
+    let error = vertex::quantize(&mut mesh, StreamFormats::QUANTIZED);
+    assert_eq!(
+        mesh.formats,
+        StreamFormats {
+            position: Format::R16G16B16A16_SFLOAT,
+            ..Default::default()
+        }
+    );
+    assert_eq!(error.normal, 0.0);
+    assert_eq!(error.tex_coord, 0.0);
+    assert_ne!(error, QuantizationError::default());
+}
+
+#[test]
+fn layouts_hold_quantized_attributes() {
+    let layout = VertexLayout::new(
+        16,
+        &[
+            VertexAttribute::with_format(
+                VertexAttributeKind::Position,
+                Format::R16G16B16A16_SFLOAT,
+                0,
+            ),
+            VertexAttribute::with_format(
+                VertexAttributeKind::Normal,
+                Format::A2B10G10R10_SNORM_PACK32,
+                8,
+            ),
+            VertexAttribute::with_format(VertexAttributeKind::TexCoord, Format::R16G16_UNORM, 12),
+        ],
+    );
+    assert_eq!(layout.formats(), StreamFormats::QUANTIZED);
+    assert_eq!(
+        AcceptedFormats::default().rejected_of(&layout.formats()),
+        Some((VertexAttributeKind::Position, Format::R16G16B16A16_SFLOAT))
+    );
+}
+
+#[test]
+#[should_panic]
+fn layouts_with_unsupported_formats_panic() {
+    VertexLayout::new(
+        8,
+        &[VertexAttribute::with_format(
+            VertexAttributeKind::Position,
+            Format::R16G16_UNORM,
+            0,
+        )],
+    );
+}
+
+#[test]
+#[should_panic]
+fn passes_accepting_unsupported_formats_panic() {
+    let mut pip = Pipeline::read(Some("tests/vertex_formats.json"));
+    let pass = pip
+        .passes
+        .iter_mut()
+        .find(|e| e.name == "gbufferQuantized")
+        .unwrap();
+    pass.vertex_formats.normal = vec![Format::R16G16_UNORM];
+    DryRun::new(
+        pip,
+        &DeviceCapabilities::default(),
+        vk::Extent2D {
+            width: 64,
+            height: 64,
+        },
+        false,
+    );
+}
+
+fn make_renderer(pipeline_path: &str) -> Renderer {
+    common::make_renderer(pipeline_path, 64, 64)
+}
+
+#[test]
+fn quantized_meshes_draw_where_their_formats_are_read() {
+    let _serial = common::serial();
+    let mut renderer = make_renderer("tests/vertex_formats.json");
+    let vertices = vertices();
+    let data = mesh_data(&vertices);
+
+    let available = |renderer: &mut Renderer| renderer.memory_snapshot().general.available;
+    let before = available(&mut renderer);
+    let (full, _) = renderer.gen_mesh_with_data(&data, MeshOptFlags::default());
+    let after_full = available(&mut renderer);
+    let (quantized, report) = renderer.gen_mesh_with_data(&data, QUANTIZE);
+    let after_quantized = available(&mut renderer);
+    assert_eq!((before - after_full) / 2, after_full - after_quantized);
+
+    // Bounds of the positions the shaders read, off by no more than the error
+    let full_bounds = renderer.mesh_bounds(full).unwrap();
+    let quantized_bounds = renderer.mesh_bounds(quantized).unwrap();
+    let error = report.quantization_error.unwrap().position;
+    assert!(full_bounds.min.distance(quantized_bounds.min) <= error * 1.7321);
+    assert!(full_bounds.max.distance(quantized_bounds.max) <= error * 1.7321);
+
+    assert_eq!(
+        renderer.try_add_task_to_queue(RenderTask::new(quantized, TaskKind::MeshStatic)),
+        Err(TaskRejected::VertexFormat {
+            kind: TaskKind::MeshStatic,
+            mesh: quantized,
+            attribute: VertexAttributeKind::Position,
+            format: Format::R16G16B16A16_SFLOAT,
+        })
+    );
+    assert_eq!(
+        renderer.try_add_task_to_queue(RenderTask::new(full, TaskKind::MeshAnimated)),
+        Err(TaskRejected::VertexFormat {
+            kind: TaskKind::MeshAnimated,
+            mesh: full,
+            attribute: VertexAttributeKind::Position,
+            format: Format::R32G32B32_SFLOAT,
+        })
+    );
+    for _ in 0..3 {
+        for (mesh, kind) in [
+            (full, TaskKind::MeshStatic),
+            (quantized, TaskKind::MeshAnimated),
+            // Fullscreen stages don't read the vertices
+            (quantized, TaskKind::Fullscreen),
+        ] {
+            renderer
+                .try_add_task_to_queue(RenderTask::new(mesh, kind))
+                .unwrap();
+        }
+        assert_eq!(renderer.render(), FrameOutcome::Submitted);
+    }
+    renderer.free_mesh(quantized).unwrap();
+    renderer.free_mesh(full).unwrap();
+    renderer.render();
+    common::finish(renderer);
+}